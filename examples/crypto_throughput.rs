@@ -0,0 +1,83 @@
+//! Crypto Throughput
+//!
+//! Measures, on this machine, how fast received signatures are checked one
+//! at a time and on the crypto worker pool at each level of parallelism up
+//! to one worker per CPU, and how fast large payloads are hashed with
+//! SHA-256 and BLAKE3 and sealed with AES-256-GCM.
+//!
+//! Run with `cargo run --release --example crypto_throughput [signatures]`.
+
+use std::sync::Arc;
+use std::time::Instant;
+use synapse::{
+    config::ResourceLimitsConfig,
+    crypto::{CryptoManager, StorageKey, VerifyJob},
+    crypto_pool::CryptoWorkerPool,
+    error::Result,
+    hashing::{HardwareAcceleration, HashAlgorithm},
+    limits::ResourceGovernor,
+};
+
+/// Payload hashed and sealed in the bulk measurements
+const PAYLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let count: usize = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(2_000);
+
+    let mut signer = CryptoManager::new();
+    let mut receiver = CryptoManager::new();
+    let (_, public_key) = signer.generate_keypair()?;
+    receiver.import_public_key("sender", &public_key)?;
+    let verifier = receiver.signature_verifier("sender").expect("key was just imported");
+
+    println!("🔏 Signing {} messages...", count);
+    let mut jobs = Vec::with_capacity(count);
+    for i in 0..count {
+        let data = format!("benchmark message {}", i).into_bytes();
+        let signature = signer.sign_bytes(&data)?;
+        jobs.push(VerifyJob::new(verifier.clone(), data, signature));
+    }
+
+    let started = Instant::now();
+    let valid = jobs.iter().filter(|job| job.run()).count();
+    let serial = count as f64 / started.elapsed().as_secs_f64();
+    assert_eq!(valid, count);
+    println!("   serial:     {:>10.0} verifications/s", serial);
+
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut workers = 1;
+    while workers <= cpus {
+        let limits = Arc::new(ResourceGovernor::new(&ResourceLimitsConfig {
+            max_concurrent_crypto: workers,
+            ..Default::default()
+        }));
+        let pool = CryptoWorkerPool::new(limits);
+        let started = Instant::now();
+        let valid = pool.verify_batch(jobs.clone()).await.into_iter().filter(|valid| *valid).count();
+        let pooled = count as f64 / started.elapsed().as_secs_f64();
+        assert_eq!(valid, count);
+        println!("   {:>2} workers: {:>10.0} verifications/s ({:.1}x)", workers, pooled, pooled / serial);
+        workers = if workers == cpus { cpus + 1 } else { (workers * 2).min(cpus) };
+    }
+
+    let hardware = HardwareAcceleration::current();
+    println!("⚡ CPU acceleration: {}", hardware.features().join(", "));
+    let payload = vec![0x5a; PAYLOAD_BYTES];
+    let throughput = |started: Instant| PAYLOAD_BYTES as f64 / started.elapsed().as_secs_f64() / (1024.0 * 1024.0);
+    for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+        let started = Instant::now();
+        algorithm.hash(&payload);
+        println!("   {:<11} {:>10.0} MiB/s", format!("{}:", algorithm), throughput(started));
+    }
+    let key = StorageKey::from_bytes([7; 32]);
+    let started = Instant::now();
+    key.seal(&payload)?;
+    println!(
+        "   {:<11} {:>10.0} MiB/s ({})",
+        "aes-gcm:",
+        throughput(started),
+        if hardware.hardware_aes() { "hardware" } else { "software" }
+    );
+    Ok(())
+}
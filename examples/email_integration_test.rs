@@ -17,13 +17,14 @@ fn create_test_config() -> Config {
             domain: "test.local".to_string(),
             capabilities: vec!["messaging".to_string()],
             display_name: Some("Test Router".to_string()),
+            region: None,
         },
         email: EmailConfig {
             smtp: SmtpConfig {
                 host: "localhost".to_string(),
                 port: 2525,
                 username: "test@test.local".to_string(),
-                password: "test".to_string(),
+                password: "test".into(),
                 use_tls: false,
                 use_ssl: false,
             },
@@ -31,7 +32,7 @@ fn create_test_config() -> Config {
                 host: "localhost".to_string(),
                 port: 1143,
                 username: "test@test.local".to_string(),
-                password: "test".to_string(),
+                password: "test".into(),
                 use_ssl: false,
             },
         },
@@ -57,6 +58,34 @@ fn create_test_config() -> Config {
             file: None,
             log_message_content: false,
         },
+        proxy: Default::default(),
+        resumption: Default::default(),
+        relay: Default::default(),
+        admission: Default::default(),
+        trust_gate: Default::default(),
+        storage: Default::default(),
+        probing: Default::default(),
+        listeners: Default::default(),
+        endpoint_updates: Default::default(),
+        cluster: Default::default(),
+        mail_store: Default::default(),
+        election: Default::default(),
+        metering: Default::default(),
+        payload_streams: Default::default(),
+        time_sync: Default::default(),
+        digest: Default::default(),
+        diagnostics: Default::default(),
+        echo: Default::default(),
+        nat: Default::default(),
+        fast_relay: Default::default(),
+        email_sending: Default::default(),
+        mailing_list: Default::default(),
+        limits: Default::default(),
+        runtime: Default::default(),
+        // Set up the email server with the router, to show what it detects
+        startup: synapse::config::StartupConfig {
+            email_server: synapse::config::EmailServerStartup::Eager,
+        },
     }
 }
 
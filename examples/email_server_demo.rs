@@ -37,7 +37,7 @@ async fn main() -> Result<()> {
 
     // Step 2: Test authentication system
     info!("🔐 Testing authentication system...");
-    let _auth_handler = create_test_auth_handler();
+    let _auth_handler = create_test_auth_handler().await;
     
     info!("✅ Authentication handler created successfully!");
 
@@ -83,6 +83,7 @@ async fn main() -> Result<()> {
         security_level: SecurityLevel::Public,
         routing_path: vec![],
         metadata: HashMap::new(),
+        attachments: Vec::new(),
     };
 
     info!("✅ Test message created: {}", test_message.message_id);
@@ -155,7 +155,7 @@ async fn main() -> Result<()> {
                 },
             };
             
-            if let Err(e) = email_server.add_user(test_user) {
+            if let Err(e) = email_server.add_user(test_user).await {
                 warn!("⚠️  Failed to add test user: {}", e);
             } else {
                 info!("👤 Test user added successfully");
@@ -201,13 +201,14 @@ fn create_demo_config() -> Config {
             domain: "synapse.local".to_string(),
             capabilities: vec!["messaging".to_string(), "email-server".to_string()],
             display_name: Some("Enhanced Demo Router".to_string()),
+            region: None,
         },
         email: EmailConfig {
             smtp: SmtpConfig {
                 host: "localhost".to_string(),
                 port: 2525,
                 username: "demo@synapse.local".to_string(),
-                password: "demo_password".to_string(),
+                password: "demo_password".into(),
                 use_tls: false,
                 use_ssl: false,
             },
@@ -215,7 +216,7 @@ fn create_demo_config() -> Config {
                 host: "localhost".to_string(),
                 port: 1143,
                 username: "demo@synapse.local".to_string(),
-                password: "demo_password".to_string(),
+                password: "demo_password".into(),
                 use_ssl: false,
             },
         },
@@ -241,5 +242,33 @@ fn create_demo_config() -> Config {
             file: None,
             log_message_content: false,
         },
+        proxy: Default::default(),
+        resumption: Default::default(),
+        relay: Default::default(),
+        admission: Default::default(),
+        trust_gate: Default::default(),
+        storage: Default::default(),
+        probing: Default::default(),
+        listeners: Default::default(),
+        endpoint_updates: Default::default(),
+        cluster: Default::default(),
+        mail_store: Default::default(),
+        election: Default::default(),
+        metering: Default::default(),
+        payload_streams: Default::default(),
+        time_sync: Default::default(),
+        digest: Default::default(),
+        diagnostics: Default::default(),
+        echo: Default::default(),
+        nat: Default::default(),
+        fast_relay: Default::default(),
+        email_sending: Default::default(),
+        mailing_list: Default::default(),
+        limits: Default::default(),
+        runtime: Default::default(),
+        // Set up the email server with the router, to show what it detects
+        startup: synapse::config::StartupConfig {
+            email_server: synapse::config::EmailServerStartup::Eager,
+        },
     }
 }
@@ -44,7 +44,8 @@ async fn run_auth_demo() -> Result<(), Box<dyn std::error::Error>> {
                 client_id: std::env::var("GITHUB_CLIENT_ID")
                     .unwrap_or_else(|_| "your-github-client-id".to_string()),
                 client_secret: std::env::var("GITHUB_CLIENT_SECRET")
-                    .unwrap_or_else(|_| "your-github-client-secret".to_string()),
+                    .unwrap_or_else(|_| "your-github-client-secret".to_string())
+                    .into(),
                 redirect_uri: "http://localhost:8080/auth/callback".to_string(),
                 scopes: vec!["user:email".to_string()],
                 enabled: true,
@@ -54,7 +55,8 @@ async fn run_auth_demo() -> Result<(), Box<dyn std::error::Error>> {
                 client_id: std::env::var("GOOGLE_CLIENT_ID")
                     .unwrap_or_else(|_| "your-google-client-id".to_string()),
                 client_secret: std::env::var("GOOGLE_CLIENT_SECRET")
-                    .unwrap_or_else(|_| "your-google-client-secret".to_string()),
+                    .unwrap_or_else(|_| "your-google-client-secret".to_string())
+                    .into(),
                 redirect_uri: "http://localhost:8080/auth/callback".to_string(),
                 scopes: vec!["profile".to_string(), "email".to_string()],
                 enabled: true,
@@ -200,5 +200,6 @@ fn create_sample_message(id: &str, content: &str) -> SecureMessage {
             metadata.insert("demo_id".to_string(), id.to_string());
             metadata
         },
+        attachments: Vec::new(),
     }
 }
\ No newline at end of file
@@ -36,7 +36,7 @@ async fn main() -> Result<()> {
             host: "localhost".to_string(),
             port: 587,
             username: "demo@example.com".to_string(),
-            password: "demo".to_string(),
+            password: "demo".into(),
             use_ssl: false,
             use_tls: false,
         },
@@ -44,7 +44,7 @@ async fn main() -> Result<()> {
             host: "localhost".to_string(),
             port: 993,
             username: "demo@example.com".to_string(),
-            password: "demo".to_string(),
+            password: "demo".into(),
             use_ssl: true,
         },
     };
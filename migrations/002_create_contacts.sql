@@ -0,0 +1,14 @@
+-- Synapse Contact Book Schema
+-- Migration: 002_create_contacts
+
+CREATE TABLE contacts (
+    owner_id VARCHAR(255) NOT NULL,
+    global_id VARCHAR(255) NOT NULL,
+    display_name VARCHAR(255) NOT NULL,
+    contact JSONB NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (owner_id, global_id)
+);
+
+CREATE INDEX idx_contacts_owner ON contacts(owner_id);
+CREATE INDEX idx_contacts_display_name ON contacts(owner_id, display_name);
@@ -0,0 +1,21 @@
+-- Synapse Profile Attachments Schema
+-- Migration: 003_create_profile_attachments
+
+CREATE TABLE attachment_blobs (
+    content_hash CHAR(64) PRIMARY KEY,
+    media_type VARCHAR(255) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    data BYTEA NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE profile_attachments (
+    participant_id VARCHAR(255) NOT NULL REFERENCES participants(global_id) ON DELETE CASCADE,
+    kind VARCHAR(255) NOT NULL,
+    content_hash CHAR(64) NOT NULL,
+    attachment JSONB NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (participant_id, kind)
+);
+
+CREATE INDEX idx_profile_attachments_hash ON profile_attachments(content_hash);
@@ -0,0 +1,13 @@
+-- Synapse Link Probe History Schema
+-- Migration: 004_create_probe_samples
+
+CREATE TABLE probe_samples (
+    id BIGSERIAL PRIMARY KEY,
+    peer VARCHAR(255) NOT NULL,
+    transport VARCHAR(64) NOT NULL,
+    measured_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    sample JSONB NOT NULL
+);
+
+CREATE INDEX idx_probe_samples_series ON probe_samples(peer, transport, measured_at);
+CREATE INDEX idx_probe_samples_measured_at ON probe_samples(measured_at);
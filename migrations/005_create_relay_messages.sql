@@ -0,0 +1,15 @@
+-- Synapse Relay Store-and-Forward Schema
+-- Migration: 005_create_relay_messages
+
+CREATE TABLE relay_messages (
+    id UUID PRIMARY KEY,
+    recipient VARCHAR(255) NOT NULL,
+    sender VARCHAR(255) NOT NULL,
+    stored_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    size BIGINT NOT NULL,
+    sealed BYTEA NOT NULL
+);
+
+CREATE INDEX idx_relay_messages_recipient ON relay_messages(recipient, stored_at);
+CREATE INDEX idx_relay_messages_expires_at ON relay_messages(expires_at);
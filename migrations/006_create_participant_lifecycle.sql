@@ -0,0 +1,13 @@
+-- Synapse Participant Lifecycle Schema
+-- Migration: 006_create_participant_lifecycle
+-- Latest signed lifecycle notice per participant. Rows for deleted
+-- participants are tombstones and outlive the participant row.
+
+CREATE TABLE participant_lifecycle (
+    global_id VARCHAR(255) PRIMARY KEY,
+    state VARCHAR(32) NOT NULL,
+    notice JSONB NOT NULL,
+    issued_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX idx_participant_lifecycle_state ON participant_lifecycle(state, issued_at);
@@ -0,0 +1,16 @@
+-- Synapse Data Purge Audit Schema
+-- Migration: 007_create_purge_audits
+-- One row per right-to-be-forgotten purge. The subject is a pseudonym, so
+-- the audit can be kept after the participant's data is gone.
+
+CREATE TABLE purge_audits (
+    id UUID PRIMARY KEY,
+    subject VARCHAR(64) NOT NULL,
+    requested_by VARCHAR(255) NOT NULL,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    completed_at TIMESTAMP WITH TIME ZONE,
+    items JSONB NOT NULL DEFAULT '[]'
+);
+
+CREATE INDEX idx_purge_audits_subject ON purge_audits(subject);
+CREATE INDEX idx_purge_audits_started_at ON purge_audits(started_at);
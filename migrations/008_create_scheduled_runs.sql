@@ -0,0 +1,11 @@
+-- Synapse Scheduled Job State Schema
+-- Migration: 008_create_scheduled_runs
+-- Last and next run of periodic jobs such as trust decay, so a restarted
+-- node can catch up on runs it missed.
+
+CREATE TABLE scheduled_runs (
+    job VARCHAR(64) PRIMARY KEY,
+    last_run_at TIMESTAMP WITH TIME ZONE,
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    last_processed BIGINT NOT NULL DEFAULT 0
+);
@@ -0,0 +1,10 @@
+-- Synapse Participant Nonce Schema
+-- Migration: 009_create_participant_nonces
+-- Highest committed transaction nonce per participant, shared by every node
+-- on the database so replay protection survives restarts.
+
+CREATE TABLE participant_nonces (
+    participant_id VARCHAR(255) PRIMARY KEY,
+    nonce BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,8 @@
+-- Synapse Participant Region Schema
+-- Migration: 010_add_participant_regions
+-- Region a participant runs in (e.g. "eu-west"), used for geo-aware peer
+-- selection and data-residency filters on discovery.
+
+ALTER TABLE participants ADD COLUMN region VARCHAR(64);
+
+CREATE INDEX idx_participants_region ON participants (region);
@@ -0,0 +1,4 @@
+-- Synapse Contact Book Schema
+-- Rollback: 002_create_contacts
+
+DROP TABLE IF EXISTS contacts;
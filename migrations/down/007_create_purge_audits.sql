@@ -0,0 +1,4 @@
+-- Synapse Data Purge Audit Schema
+-- Rollback: 007_create_purge_audits
+
+DROP TABLE IF EXISTS purge_audits;
@@ -0,0 +1,4 @@
+-- Synapse Participant Nonce Schema
+-- Rollback: 009_create_participant_nonces
+
+DROP TABLE IF EXISTS participant_nonces;
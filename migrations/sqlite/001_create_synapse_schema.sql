@@ -0,0 +1,120 @@
+-- Synapse Participant Registry Schema (embedded SQLite)
+-- Migration: 001_create_participants_table
+-- JSON columns are stored as TEXT; timestamps as ISO-8601 TEXT.
+
+CREATE TABLE participants (
+    global_id TEXT PRIMARY KEY,
+    display_name TEXT NOT NULL,
+    entity_type TEXT NOT NULL,
+    identities TEXT NOT NULL DEFAULT '[]',
+    discovery_permissions TEXT NOT NULL,
+    availability TEXT NOT NULL,
+    contact_preferences TEXT NOT NULL DEFAULT '{}',
+    trust_ratings TEXT NOT NULL DEFAULT '{}',
+    topic_subscriptions TEXT NOT NULL DEFAULT '[]',
+    organizational_context TEXT,
+    public_key BLOB,
+    supported_protocols TEXT NOT NULL DEFAULT '[]',
+    last_seen TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE trust_balances (
+    participant_id TEXT PRIMARY KEY REFERENCES participants(global_id) ON DELETE CASCADE,
+    total_points INTEGER NOT NULL DEFAULT 0,
+    available_points INTEGER NOT NULL DEFAULT 0,
+    staked_points INTEGER NOT NULL DEFAULT 0,
+    earned_lifetime INTEGER NOT NULL DEFAULT 0,
+    last_activity TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    decay_rate REAL NOT NULL DEFAULT 0.02
+);
+
+CREATE TABLE blockchain_blocks (
+    block_number INTEGER PRIMARY KEY,
+    timestamp TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    previous_hash TEXT NOT NULL,
+    hash TEXT NOT NULL UNIQUE,
+    transactions TEXT NOT NULL DEFAULT '[]',
+    nonce INTEGER NOT NULL DEFAULT 0,
+    validator TEXT NOT NULL
+);
+
+CREATE TABLE blockchain_transactions (
+    id TEXT PRIMARY KEY,
+    block_number INTEGER NOT NULL REFERENCES blockchain_blocks(block_number) ON DELETE CASCADE,
+    transaction_type TEXT NOT NULL,
+    transaction_data TEXT NOT NULL,
+    timestamp TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    hash BLOB NOT NULL
+);
+
+CREATE TABLE participant_relationships (
+    id TEXT PRIMARY KEY,
+    from_participant TEXT NOT NULL REFERENCES participants(global_id) ON DELETE CASCADE,
+    to_participant TEXT NOT NULL REFERENCES participants(global_id) ON DELETE CASCADE,
+    relationship_type TEXT NOT NULL,
+    trust_score INTEGER CHECK (trust_score >= 0 AND trust_score <= 100),
+    relationship_context TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(from_participant, to_participant)
+);
+
+CREATE TABLE trust_ratings (
+    id TEXT PRIMARY KEY,
+    rater_id TEXT NOT NULL REFERENCES participants(global_id) ON DELETE CASCADE,
+    subject_id TEXT NOT NULL REFERENCES participants(global_id) ON DELETE CASCADE,
+    category TEXT NOT NULL,
+    score INTEGER NOT NULL CHECK (score >= 0 AND score <= 100),
+    comment TEXT,
+    relationship_context TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(rater_id, subject_id, category)
+);
+
+CREATE INDEX idx_participants_last_seen ON participants (last_seen);
+CREATE INDEX idx_participants_updated_at ON participants (updated_at);
+
+CREATE INDEX idx_trust_balances_total_points ON trust_balances (total_points);
+CREATE INDEX idx_trust_balances_last_activity ON trust_balances (last_activity);
+
+CREATE INDEX idx_blockchain_blocks_timestamp ON blockchain_blocks (timestamp);
+CREATE INDEX idx_blockchain_blocks_validator ON blockchain_blocks (validator);
+
+CREATE INDEX idx_blockchain_transactions_type ON blockchain_transactions (transaction_type);
+CREATE INDEX idx_blockchain_transactions_timestamp ON blockchain_transactions (timestamp);
+
+CREATE INDEX idx_relationships_from ON participant_relationships (from_participant);
+CREATE INDEX idx_relationships_to ON participant_relationships (to_participant);
+CREATE INDEX idx_relationships_type ON participant_relationships (relationship_type);
+
+CREATE INDEX idx_trust_ratings_subject ON trust_ratings (subject_id);
+CREATE INDEX idx_trust_ratings_rater ON trust_ratings (rater_id);
+CREATE INDEX idx_trust_ratings_category ON trust_ratings (category);
+CREATE INDEX idx_trust_ratings_score ON trust_ratings (score);
+
+CREATE TRIGGER update_participants_updated_at AFTER UPDATE ON participants
+FOR EACH ROW BEGIN
+    UPDATE participants SET updated_at = CURRENT_TIMESTAMP WHERE global_id = NEW.global_id;
+END;
+
+CREATE TRIGGER update_relationships_updated_at AFTER UPDATE ON participant_relationships
+FOR EACH ROW BEGIN
+    UPDATE participant_relationships SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE VIEW participant_trust_summary AS
+SELECT
+    p.global_id,
+    p.display_name,
+    p.entity_type,
+    tb.total_points,
+    tb.available_points,
+    tb.staked_points,
+    COALESCE(AVG(tr.score), 0) as avg_trust_rating,
+    COUNT(tr.score) as total_ratings
+FROM participants p
+LEFT JOIN trust_balances tb ON p.global_id = tb.participant_id
+LEFT JOIN trust_ratings tr ON p.global_id = tr.subject_id
+GROUP BY p.global_id, p.display_name, p.entity_type, tb.total_points, tb.available_points, tb.staked_points;
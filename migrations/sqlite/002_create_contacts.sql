@@ -0,0 +1,14 @@
+-- Synapse Contact Book Schema (embedded SQLite)
+-- Migration: 002_create_contacts
+
+CREATE TABLE contacts (
+    owner_id TEXT NOT NULL,
+    global_id TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    contact TEXT NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (owner_id, global_id)
+);
+
+CREATE INDEX idx_contacts_owner ON contacts(owner_id);
+CREATE INDEX idx_contacts_display_name ON contacts(owner_id, display_name);
@@ -0,0 +1,21 @@
+-- Synapse Profile Attachments Schema (embedded SQLite)
+-- Migration: 003_create_profile_attachments
+
+CREATE TABLE attachment_blobs (
+    content_hash TEXT PRIMARY KEY,
+    media_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE profile_attachments (
+    participant_id TEXT NOT NULL REFERENCES participants(global_id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    attachment TEXT NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (participant_id, kind)
+);
+
+CREATE INDEX idx_profile_attachments_hash ON profile_attachments(content_hash);
@@ -0,0 +1,13 @@
+-- Synapse Link Probe History Schema (embedded SQLite)
+-- Migration: 004_create_probe_samples
+
+CREATE TABLE probe_samples (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    peer TEXT NOT NULL,
+    transport TEXT NOT NULL,
+    measured_at TEXT NOT NULL,
+    sample TEXT NOT NULL
+);
+
+CREATE INDEX idx_probe_samples_series ON probe_samples(peer, transport, measured_at);
+CREATE INDEX idx_probe_samples_measured_at ON probe_samples(measured_at);
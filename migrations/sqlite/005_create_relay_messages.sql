@@ -0,0 +1,15 @@
+-- Synapse Relay Store-and-Forward Schema (embedded SQLite)
+-- Migration: 005_create_relay_messages
+
+CREATE TABLE relay_messages (
+    id TEXT PRIMARY KEY,
+    recipient TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    stored_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    sealed BLOB NOT NULL
+);
+
+CREATE INDEX idx_relay_messages_recipient ON relay_messages(recipient, stored_at);
+CREATE INDEX idx_relay_messages_expires_at ON relay_messages(expires_at);
@@ -0,0 +1,11 @@
+-- Synapse Participant Lifecycle Schema (embedded SQLite)
+-- Migration: 006_create_participant_lifecycle
+
+CREATE TABLE participant_lifecycle (
+    global_id TEXT PRIMARY KEY,
+    state TEXT NOT NULL,
+    notice TEXT NOT NULL,
+    issued_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_participant_lifecycle_state ON participant_lifecycle(state, issued_at);
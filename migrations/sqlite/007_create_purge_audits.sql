@@ -0,0 +1,14 @@
+-- Synapse Data Purge Audit Schema (embedded SQLite)
+-- Migration: 007_create_purge_audits
+
+CREATE TABLE purge_audits (
+    id TEXT PRIMARY KEY,
+    subject TEXT NOT NULL,
+    requested_by TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    completed_at TEXT,
+    items TEXT NOT NULL DEFAULT '[]'
+);
+
+CREATE INDEX idx_purge_audits_subject ON purge_audits(subject);
+CREATE INDEX idx_purge_audits_started_at ON purge_audits(started_at);
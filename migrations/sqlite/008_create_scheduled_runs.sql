@@ -0,0 +1,9 @@
+-- Synapse Scheduled Job State Schema (embedded SQLite)
+-- Migration: 008_create_scheduled_runs
+
+CREATE TABLE scheduled_runs (
+    job TEXT PRIMARY KEY,
+    last_run_at TEXT,
+    next_run_at TEXT NOT NULL,
+    last_processed INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,8 @@
+-- Synapse Participant Nonce Schema (embedded SQLite)
+-- Migration: 009_create_participant_nonces
+
+CREATE TABLE participant_nonces (
+    participant_id TEXT PRIMARY KEY,
+    nonce INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
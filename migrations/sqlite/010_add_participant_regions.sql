@@ -0,0 +1,6 @@
+-- Synapse Participant Region Schema (embedded SQLite)
+-- Migration: 010_add_participant_regions
+
+ALTER TABLE participants ADD COLUMN region TEXT;
+
+CREATE INDEX idx_participants_region ON participants (region);
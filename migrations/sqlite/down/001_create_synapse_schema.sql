@@ -0,0 +1,13 @@
+-- Synapse Participant Registry Schema (embedded SQLite)
+-- Rollback: 001_create_participants_table
+
+DROP VIEW IF EXISTS participant_trust_summary;
+DROP TRIGGER IF EXISTS update_relationships_updated_at;
+DROP TRIGGER IF EXISTS update_participants_updated_at;
+
+DROP TABLE IF EXISTS trust_ratings;
+DROP TABLE IF EXISTS participant_relationships;
+DROP TABLE IF EXISTS blockchain_transactions;
+DROP TABLE IF EXISTS blockchain_blocks;
+DROP TABLE IF EXISTS trust_balances;
+DROP TABLE IF EXISTS participants;
@@ -0,0 +1,5 @@
+-- Synapse Profile Attachments Schema (embedded SQLite)
+-- Rollback: 003_create_profile_attachments
+
+DROP TABLE IF EXISTS profile_attachments;
+DROP TABLE IF EXISTS attachment_blobs;
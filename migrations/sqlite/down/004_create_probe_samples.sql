@@ -0,0 +1,4 @@
+-- Synapse Link Probe History Schema (embedded SQLite)
+-- Rollback: 004_create_probe_samples
+
+DROP TABLE IF EXISTS probe_samples;
@@ -0,0 +1,4 @@
+-- Synapse Relay Store-and-Forward Schema (embedded SQLite)
+-- Rollback: 005_create_relay_messages
+
+DROP TABLE IF EXISTS relay_messages;
@@ -0,0 +1,4 @@
+-- Synapse Participant Lifecycle Schema (embedded SQLite)
+-- Rollback: 006_create_participant_lifecycle
+
+DROP TABLE IF EXISTS participant_lifecycle;
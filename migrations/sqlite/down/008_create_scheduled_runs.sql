@@ -0,0 +1,4 @@
+-- Synapse Scheduled Job State Schema (embedded SQLite)
+-- Rollback: 008_create_scheduled_runs
+
+DROP TABLE IF EXISTS scheduled_runs;
@@ -0,0 +1,5 @@
+-- Synapse Participant Region Schema (embedded SQLite)
+-- Rollback: 010_add_participant_regions
+
+DROP INDEX IF EXISTS idx_participants_region;
+ALTER TABLE participants DROP COLUMN region;
@@ -0,0 +1,118 @@
+// Dashboard for one AI agent running as a browser Synapse node.
+//
+// Sealed messages travel over a WebSocket relay; the node signs, encrypts,
+// queues and verifies them, and this component only renders its events.
+
+import { useEffect, useState } from "react";
+import { useSynapse } from "@synapse/web/react";
+
+const RELAY_URL = "wss://relay.example.com/synapse";
+
+export function AgentDashboard({ agent, passphrase }: { agent: string; passphrase: string }) {
+  const { client, error, connectionState, messages, presence, receipts } = useSynapse(agent, {
+    entityType: "ai_model",
+    passphrase,
+  });
+  const [draft, setDraft] = useState("");
+  const [peer, setPeer] = useState("");
+
+  // Carry sealed bytes to and from the relay and report what happens
+  useEffect(() => {
+    if (!client) {
+      return;
+    }
+    client.setConnectionState("connecting");
+    const socket = new WebSocket(RELAY_URL);
+    socket.binaryType = "arraybuffer";
+
+    const flush = async () => {
+      for (const queued of await client.pendingMessages()) {
+        try {
+          socket.send(JSON.stringify({ to: queued.to, sealed: queued.sealed }));
+          await client.markDelivered(queued.id);
+        } catch {
+          await client.markFailed(queued.id);
+        }
+      }
+    };
+    socket.onopen = () => {
+      client.setConnectionState("connected");
+      void flush();
+    };
+    socket.onclose = () => client.setConnectionState("disconnected");
+    socket.onmessage = async (event) => {
+      const frame = JSON.parse(event.data);
+      if (frame.presence) {
+        client.setPeerPresence(frame.from, frame.presence);
+      } else {
+        await client.receive(frame.from, new Uint8Array(frame.sealed));
+      }
+    };
+    const unsubscribe = client.onDeliveryReceipt((receipt) => {
+      if (receipt.status === "queued" && socket.readyState === WebSocket.OPEN) {
+        void flush();
+      }
+    });
+
+    return () => {
+      unsubscribe();
+      socket.close();
+    };
+  }, [client]);
+
+  if (error) {
+    return <p role="alert">Could not start {agent}: {error.message}</p>;
+  }
+  if (!client) {
+    return <p>Starting {agent}…</p>;
+  }
+
+  return (
+    <section>
+      <header>
+        <h2>{client.entityId}</h2>
+        <span>Connection: {connectionState}</span>
+      </header>
+
+      <h3>Peers</h3>
+      <ul>
+        {Object.entries(presence).map(([id, status]) => (
+          <li key={id}>
+            {id}: {status}
+          </li>
+        ))}
+      </ul>
+
+      <h3>Inbox</h3>
+      <ul>
+        {messages.map((message) => (
+          <li key={message.id}>
+            <strong>{message.from}</strong> {new Date(message.timestamp).toLocaleTimeString()}: {message.text}
+          </li>
+        ))}
+      </ul>
+
+      <h3>Sent</h3>
+      <ul>
+        {Object.values(receipts).map((receipt) => (
+          <li key={receipt.message_id}>
+            {receipt.peer}: {receipt.status}
+          </li>
+        ))}
+      </ul>
+
+      <form
+        onSubmit={(event) => {
+          event.preventDefault();
+          void client.send(peer, draft).then(() => setDraft(""));
+        }}
+      >
+        <input placeholder="peer@wasm.synapse.local" value={peer} onChange={(e) => setPeer(e.target.value)} />
+        <input placeholder="Message" value={draft} onChange={(e) => setDraft(e.target.value)} />
+        <button type="submit" disabled={!peer || !draft}>
+          Send
+        </button>
+      </form>
+    </section>
+  );
+}
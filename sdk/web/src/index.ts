@@ -0,0 +1,233 @@
+// Synapse web SDK
+//
+// A small typed wrapper over the wasm-bindgen `WasmSynapseNode`: incoming
+// messages as an async iterator, typed event subscriptions for presence and
+// delivery receipts, and connection-state callbacks.
+
+import init, { WasmSynapseNode } from "synapse";
+import type {
+  ConnectionState,
+  ConnectionStateEvent,
+  DeliveryReceiptEvent,
+  SynapseMessageEvent,
+  PresenceEvent,
+  PresenceStatus,
+  SynapseEvent,
+} from "synapse";
+
+export type {
+  ConnectionState,
+  ConnectionStateEvent,
+  DeliveryReceiptEvent,
+  DeliveryStatus,
+  SynapseMessageEvent,
+  PresenceEvent,
+  PresenceStatus,
+  SynapseEvent,
+} from "synapse";
+
+export type SynapseEventType = SynapseEvent["type"];
+export type EventOfType<T extends SynapseEventType> = Extract<SynapseEvent, { type: T }>;
+
+export interface SynapseClientOptions {
+  /** Entity type reported to peers, e.g. "ai_model" */
+  entityType?: string;
+  /** Unlocks (or creates) the IndexedDB store; without it nothing persists */
+  passphrase?: string;
+  /** Location of the `.wasm` file when it is not served next to the bindings */
+  wasmUrl?: string | URL;
+}
+
+let initialized: Promise<unknown> | undefined;
+
+/** Load the WebAssembly module once per page */
+export function loadSynapse(wasmUrl?: string | URL): Promise<unknown> {
+  initialized ??= init(wasmUrl);
+  return initialized;
+}
+
+/** A browser Synapse node */
+export class SynapseClient {
+  readonly node: WasmSynapseNode;
+  /** Whether a saved identity was restored from IndexedDB */
+  readonly restored: boolean;
+  private readonly listenerIds: number[] = [];
+  private readonly finishers: Array<() => void> = [];
+  private closed = false;
+
+  private constructor(node: WasmSynapseNode, restored: boolean) {
+    this.node = node;
+    this.restored = restored;
+  }
+
+  /** Load the module, create a node and give it keys */
+  static async create(name: string, options: SynapseClientOptions = {}): Promise<SynapseClient> {
+    await loadSynapse(options.wasmUrl);
+    const node = new WasmSynapseNode(name, options.entityType ?? "human");
+    let restored = false;
+    if (options.passphrase !== undefined) {
+      restored = await node.open_storage(options.passphrase);
+    } else {
+      await node.generate_keys();
+    }
+    return new SynapseClient(node, restored);
+  }
+
+  get entityId(): string {
+    return this.node.entity_id;
+  }
+
+  get connectionState(): ConnectionState {
+    return this.node.connection_state as ConnectionState;
+  }
+
+  /** Subscribe to one kind of event; returns the unsubscribe function */
+  on<T extends SynapseEventType>(type: T, handler: (event: EventOfType<T>) => void): () => void {
+    return this.subscribe((event) => {
+      if (event.type === type) {
+        handler(event as EventOfType<T>);
+      }
+    });
+  }
+
+  /** Subscribe to every event; returns the unsubscribe function */
+  onAny(handler: (event: SynapseEvent) => void): () => void {
+    return this.subscribe(handler);
+  }
+
+  onConnectionStateChange(handler: (state: ConnectionState) => void): () => void {
+    return this.on("connection_state", (event: ConnectionStateEvent) => handler(event.state));
+  }
+
+  onPresence(handler: (event: PresenceEvent) => void): () => void {
+    return this.on("presence", handler);
+  }
+
+  onDeliveryReceipt(handler: (event: DeliveryReceiptEvent) => void): () => void {
+    return this.on("delivery_receipt", handler);
+  }
+
+  /**
+   * Verified incoming messages, for `for await (const message of client.messages())`.
+   *
+   * Messages arriving while the consumer is busy are buffered; the iterator
+   * ends when `return()` is called (e.g. by `break`) or the client closes.
+   */
+  messages(): AsyncIterableIterator<SynapseMessageEvent> {
+    const buffered: SynapseMessageEvent[] = [];
+    const waiting: Array<(result: IteratorResult<SynapseMessageEvent>) => void> = [];
+    let done = false;
+
+    const finish = () => {
+      done = true;
+      unsubscribe();
+      for (const resolve of waiting.splice(0)) {
+        resolve({ value: undefined, done: true });
+      }
+    };
+    const unsubscribe = this.on("message", (message) => {
+      const resolve = waiting.shift();
+      if (resolve) {
+        resolve({ value: message, done: false });
+      } else {
+        buffered.push(message);
+      }
+    });
+    this.finishers.push(finish);
+
+    return {
+      next: () => {
+        const message = buffered.shift();
+        if (message) {
+          return Promise.resolve({ value: message, done: false });
+        }
+        if (done) {
+          return Promise.resolve({ value: undefined, done: true });
+        }
+        return new Promise((resolve) => waiting.push(resolve));
+      },
+      return: () => {
+        finish();
+        return Promise.resolve({ value: undefined, done: true });
+      },
+      [Symbol.asyncIterator]() {
+        return this;
+      },
+    };
+  }
+
+  /** Our public keys as JSON, to hand to peers out of band */
+  publicKeys(): Promise<string> {
+    return this.node.public_keys();
+  }
+
+  addPeer(peerId: string, publicKeys: string): Promise<void> {
+    return this.node.add_peer(peerId, publicKeys);
+  }
+
+  /** Sign and encrypt a message; resolves to the bytes to transmit */
+  send(to: string, text: string): Promise<Uint8Array> {
+    return this.node.send_message(to, text);
+  }
+
+  /** Open sealed bytes from a peer; the text is also delivered to `messages()` */
+  receive(from: string, sealed: Uint8Array): Promise<string> {
+    return this.node.receive_message(from, sealed);
+  }
+
+  markDelivered(messageId: string): Promise<boolean> {
+    return this.node.mark_delivered(messageId);
+  }
+
+  markFailed(messageId: string): Promise<boolean> {
+    return this.node.mark_failed(messageId);
+  }
+
+  setConnectionState(state: ConnectionState): void {
+    this.node.set_connection_state(state);
+  }
+
+  setPeerPresence(peerId: string, status: PresenceStatus): void {
+    this.node.set_peer_presence(peerId, status);
+  }
+
+  peerPresence(peerId: string): PresenceStatus {
+    return this.node.peer_presence(peerId) as PresenceStatus;
+  }
+
+  /** Undelivered messages, oldest first */
+  async pendingMessages(): Promise<Array<{ id: string; to: string; sealed: number[]; queued_at: number; attempts: number }>> {
+    return JSON.parse(await this.node.pending_messages());
+  }
+
+  async history(peerId: string): Promise<Array<{ timestamp: number; outgoing: boolean; text: string }>> {
+    return JSON.parse(await this.node.history(peerId));
+  }
+
+  /** Remove every listener and end open message iterators */
+  close(): void {
+    if (this.closed) {
+      return;
+    }
+    this.closed = true;
+    for (const finish of this.finishers.splice(0)) {
+      finish();
+    }
+    for (const id of this.listenerIds.splice(0)) {
+      this.node.off_event(id);
+    }
+    this.node.free();
+  }
+
+  private subscribe(handler: (event: SynapseEvent) => void): () => void {
+    const id = this.node.on_event(handler);
+    this.listenerIds.push(id);
+    return () => {
+      const index = this.listenerIds.indexOf(id);
+      if (index >= 0) {
+        this.listenerIds.splice(index, 1);
+        this.node.off_event(id);
+      }
+    };
+  }
+}
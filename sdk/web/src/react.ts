@@ -0,0 +1,81 @@
+// React bindings for the Synapse web SDK
+
+import { useEffect, useState } from "react";
+import {
+  SynapseClient,
+  type ConnectionState,
+  type DeliveryReceiptEvent,
+  type PresenceStatus,
+  type SynapseClientOptions,
+  type SynapseMessageEvent,
+} from "./index";
+
+export interface SynapseState {
+  /** `undefined` until the node is ready */
+  client?: SynapseClient;
+  error?: Error;
+  connectionState: ConnectionState;
+  /** Received messages, oldest first */
+  messages: SynapseMessageEvent[];
+  /** Latest presence reported for each peer */
+  presence: Record<string, PresenceStatus>;
+  /** Latest receipt for each sent message, by message ID */
+  receipts: Record<string, DeliveryReceiptEvent>;
+}
+
+/**
+ * Create a node for the lifetime of the component and keep its events in state.
+ *
+ * The node is recreated when `name` or the passphrase changes and closed on unmount.
+ */
+export function useSynapse(name: string, options: SynapseClientOptions = {}): SynapseState {
+  const [state, setState] = useState<SynapseState>({
+    connectionState: "disconnected",
+    messages: [],
+    presence: {},
+    receipts: {},
+  });
+
+  useEffect(() => {
+    let client: SynapseClient | undefined;
+    let cancelled = false;
+
+    SynapseClient.create(name, options)
+      .then((created) => {
+        if (cancelled) {
+          created.close();
+          return;
+        }
+        client = created;
+        setState((current) => ({ ...current, client: created, connectionState: created.connectionState }));
+
+        created.onAny((event) => {
+          setState((current) => {
+            switch (event.type) {
+              case "message":
+                return { ...current, messages: [...current.messages, event] };
+              case "presence":
+                return { ...current, presence: { ...current.presence, [event.peer]: event.status } };
+              case "delivery_receipt":
+                return { ...current, receipts: { ...current.receipts, [event.message_id]: event } };
+              case "connection_state":
+                return { ...current, connectionState: event.state };
+            }
+          });
+        });
+      })
+      .catch((error: unknown) => {
+        if (!cancelled) {
+          setState((current) => ({ ...current, error: error instanceof Error ? error : new Error(String(error)) }));
+        }
+      });
+
+    return () => {
+      cancelled = true;
+      client?.close();
+    };
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [name, options.passphrase]);
+
+  return state;
+}
@@ -0,0 +1,417 @@
+//! # Inbound Admission Control
+//!
+//! Popular AI endpoints attract floods from senders nobody has vouched for.
+//! The [`AdmissionController`] screens every inbound message before it is
+//! delivered:
+//!
+//! 1. Contacts, and senders whose network trust meets the threshold, are
+//!    always admitted.
+//! 2. Strangers share a per-sender and a global token bucket.
+//! 3. A stranger over budget is admitted only with a valid proof-of-work
+//!    stamp in the `pow_stamp` metadata entry (see [`mint_stamp`]).
+//! 4. Otherwise the message is held as a contact request, and the sender is
+//!    told how much work a stamp needs. The owner can approve or reject held
+//!    requests later.
+
+use crate::config::AdmissionConfig;
+use crate::types::{MessageType, SimpleMessage};
+use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Metadata key carrying a proof-of-work stamp
+pub const POW_STAMP_KEY: &str = "pow_stamp";
+/// Metadata key marking an admission challenge sent back to a stranger
+pub const ADMISSION_CHALLENGE_KEY: &str = "admission_challenge";
+
+/// Source of network trust scores (0-100)
+#[async_trait]
+pub trait TrustSource: Send + Sync {
+    /// Network trust of `global_id`, if known
+    async fn network_trust(&self, global_id: &str) -> Option<f64>;
+
+    /// Trust `requester` has in `global_id` directly, if known
+    async fn entity_trust(&self, _global_id: &str, _requester: &str) -> Option<f64> {
+        None
+    }
+}
+
+#[async_trait]
+impl TrustSource for crate::synapse::blockchain::SynapseBlockchain {
+    /// Score from committed trust reports; unknown if there are none
+    async fn network_trust(&self, global_id: &str) -> Option<f64> {
+        let score = self.get_trust_score_with_provenance(global_id).await.ok()?;
+        (!score.sources.is_empty()).then_some(score.score)
+    }
+}
+
+#[async_trait]
+impl TrustSource for crate::synapse::services::TrustManager {
+    async fn network_trust(&self, global_id: &str) -> Option<f64> {
+        self.get_network_trust_score(global_id).await.ok()
+    }
+
+    #[cfg(feature = "database")]
+    async fn entity_trust(&self, global_id: &str, requester: &str) -> Option<f64> {
+        self.get_entity_trust_score(global_id, requester).await.ok()
+    }
+}
+
+/// What to do with an inbound message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmissionDecision {
+    /// Deliver it
+    Admit,
+    /// Hold it as a contact request; challenge the sender if a challenge is given
+    Hold { challenge: Option<PowChallenge> },
+    /// Drop it: the sender already has too many held requests
+    Drop,
+}
+
+/// Proof-of-work a stranger must attach to get past the throttle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowChallenge {
+    /// Leading zero bits the stamp's SHA-256 must have
+    pub difficulty_bits: u8,
+    /// Seconds until a stamp expires
+    pub max_age_secs: u64,
+}
+
+impl PowChallenge {
+    /// System message telling `to_entity` how to get through
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> SimpleMessage {
+        let mut metadata = HashMap::new();
+        metadata.insert(ADMISSION_CHALLENGE_KEY.to_string(), self.difficulty_bits.to_string());
+        SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: format!(
+                "Message held as a contact request. Attach a {}-bit proof-of-work stamp in '{}' to be delivered.",
+                self.difficulty_bits, POW_STAMP_KEY
+            ),
+            message_type: MessageType::System,
+            metadata,
+        }
+    }
+}
+
+/// A stranger's messages waiting for the owner's approval
+#[derive(Debug, Clone)]
+pub struct ContactRequest {
+    pub sender: String,
+    pub messages: Vec<SimpleMessage>,
+    pub first_seen: chrono::DateTime<Utc>,
+}
+
+/// Mint a stamp proving `difficulty_bits` of work for a message from `from` to `to`
+///
+/// Stamps look like `1:<bits>:<unix time>:<from>:<to>:<counter>`.
+pub fn mint_stamp(from: &str, to: &str, difficulty_bits: u8) -> String {
+    let prefix = format!("1:{}:{}:{}:{}", difficulty_bits, Utc::now().timestamp(), from, to);
+    (0u64..)
+        .map(|counter| format!("{}:{}", prefix, counter))
+        .find(|stamp| leading_zero_bits(&Sha256::digest(stamp.as_bytes())) >= u32::from(difficulty_bits))
+        .expect("counter space is unbounded")
+}
+
+/// Check a stamp for a message from `from` to `to`
+pub fn verify_stamp(stamp: &str, from: &str, to: &str, difficulty_bits: u8, max_age_secs: u64) -> bool {
+    let parts: Vec<&str> = stamp.splitn(6, ':').collect();
+    let [version, bits, minted_at, stamp_from, stamp_to, _counter] = parts[..] else {
+        return false;
+    };
+    let (Ok(bits), Ok(minted_at)) = (bits.parse::<u8>(), minted_at.parse::<i64>()) else {
+        return false;
+    };
+    let age = Utc::now().timestamp() - minted_at;
+    version == "1"
+        && bits >= difficulty_bits
+        && stamp_from == from
+        && stamp_to == to
+        && (-60..=max_age_secs as i64).contains(&age)
+        && leading_zero_bits(&Sha256::digest(stamp.as_bytes())) >= u32::from(difficulty_bits)
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            tokens: f64::from(burst),
+            capacity: f64::from(burst),
+            per_second: f64::from(per_minute) / 60.0,
+            updated: Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * self.per_second).min(self.capacity);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Screens inbound messages from senders without enough trust
+pub struct AdmissionController {
+    config: AdmissionConfig,
+    trust: Option<Arc<dyn TrustSource>>,
+    trust_cache: DashMap<String, (Option<f64>, Instant)>,
+    buckets: DashMap<String, TokenBucket>,
+    global_bucket: Mutex<TokenBucket>,
+    used_stamps: DashMap<String, Instant>,
+    pending: DashMap<String, ContactRequest>,
+    last_challenge: DashMap<String, Instant>,
+}
+
+impl AdmissionController {
+    /// Create a controller; without a trust source only contacts bypass the throttle
+    pub fn new(config: AdmissionConfig) -> Self {
+        let global_rate = config.global_stranger_rate_per_minute;
+        Self {
+            config,
+            trust: None,
+            trust_cache: DashMap::new(),
+            buckets: DashMap::new(),
+            global_bucket: Mutex::new(TokenBucket::new(global_rate, global_rate)),
+            used_stamps: DashMap::new(),
+            pending: DashMap::new(),
+            last_challenge: DashMap::new(),
+        }
+    }
+
+    /// Consult `trust` for network trust scores
+    pub fn with_trust_source(mut self, trust: Arc<dyn TrustSource>) -> Self {
+        self.trust = Some(trust);
+        self
+    }
+
+    /// Whether admission control is switched on
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Settings in use
+    pub fn config(&self) -> &AdmissionConfig {
+        &self.config
+    }
+
+    /// Look up the sender's trust if the cached score is missing or stale
+    pub async fn refresh_trust(&self, sender: &str) {
+        let Some(trust) = &self.trust else {
+            return;
+        };
+        let ttl = Duration::from_secs(self.config.trust_cache_secs);
+        if self.trust_cache.get(sender).is_some_and(|entry| entry.1.elapsed() < ttl) {
+            return;
+        }
+        let score = trust.network_trust(sender).await;
+        self.trust_cache.insert(sender.to_string(), (score, Instant::now()));
+    }
+
+    /// Record a trust score directly, e.g. from a trust report
+    pub fn set_trust(&self, sender: &str, score: f64) {
+        self.trust_cache.insert(sender.to_string(), (Some(score), Instant::now()));
+    }
+
+    /// Decide what happens to a message; `is_contact` says whether the sender is known
+    ///
+    /// Held messages are stored as contact requests before returning.
+    pub fn admit(&self, message: &SimpleMessage, is_contact: bool) -> AdmissionDecision {
+        if !self.config.enabled || is_contact || self.is_trusted(&message.from_entity) {
+            return AdmissionDecision::Admit;
+        }
+        if self.has_valid_stamp(message) {
+            return AdmissionDecision::Admit;
+        }
+
+        let sender_ok = self
+            .buckets
+            .entry(message.from_entity.clone())
+            .or_insert_with(|| TokenBucket::new(self.config.stranger_rate_per_minute, self.config.stranger_burst))
+            .try_take();
+        if sender_ok && self.global_bucket.lock().unwrap().try_take() {
+            return AdmissionDecision::Admit;
+        }
+
+        if !self.hold(message) {
+            warn!("Dropped message from {}: too many held contact requests", message.from_entity);
+            return AdmissionDecision::Drop;
+        }
+        debug!("Held message from stranger {} as a contact request", message.from_entity);
+        AdmissionDecision::Hold {
+            challenge: self.challenge_for(&message.from_entity),
+        }
+    }
+
+    /// Senders with held contact requests
+    pub fn pending_requests(&self) -> Vec<ContactRequest> {
+        self.pending.iter().map(|entry| entry.value().clone()).collect()
+    }
+
+    /// Release a sender's held messages; the caller should add them as a contact
+    pub fn approve(&self, sender: &str) -> Vec<SimpleMessage> {
+        self.pending.remove(sender).map(|(_, request)| request.messages).unwrap_or_default()
+    }
+
+    /// Discard a sender's held messages
+    pub fn reject(&self, sender: &str) -> bool {
+        self.pending.remove(sender).is_some()
+    }
+
+    /// Drop everything held about a sender: queued messages, trust and
+    /// rate state. Returns how many held messages were discarded.
+    pub fn forget(&self, sender: &str) -> usize {
+        self.trust_cache.remove(sender);
+        self.buckets.remove(sender);
+        self.last_challenge.remove(sender);
+        self.pending.remove(sender).map(|(_, request)| request.messages.len()).unwrap_or(0)
+    }
+
+    /// Forget expired stamps and idle buckets
+    pub fn prune(&self) {
+        let stamp_ttl = Duration::from_secs(self.config.stamp_max_age_secs + 60);
+        self.used_stamps.retain(|_, used| used.elapsed() < stamp_ttl);
+        self.buckets.retain(|_, bucket| bucket.updated.elapsed() < Duration::from_secs(600));
+        self.last_challenge.retain(|_, sent| sent.elapsed() < Duration::from_secs(600));
+    }
+
+    fn is_trusted(&self, sender: &str) -> bool {
+        self.trust_cache
+            .get(sender)
+            .and_then(|entry| entry.0)
+            .is_some_and(|score| score >= self.config.trust_threshold)
+    }
+
+    fn has_valid_stamp(&self, message: &SimpleMessage) -> bool {
+        let bits = self.config.pow_difficulty_bits;
+        let Some(stamp) = message.metadata.get(POW_STAMP_KEY) else {
+            return false;
+        };
+        if bits == 0
+            || !verify_stamp(stamp, &message.from_entity, &message.to, bits, self.config.stamp_max_age_secs)
+        {
+            return false;
+        }
+        // Each stamp buys exactly one message
+        self.used_stamps.insert(stamp.clone(), Instant::now()).is_none()
+    }
+
+    fn hold(&self, message: &SimpleMessage) -> bool {
+        if !self.pending.contains_key(&message.from_entity) && self.pending.len() >= self.config.max_pending_senders {
+            return false;
+        }
+        let mut request = self.pending.entry(message.from_entity.clone()).or_insert_with(|| ContactRequest {
+            sender: message.from_entity.clone(),
+            messages: Vec::new(),
+            first_seen: Utc::now(),
+        });
+        if request.messages.len() >= self.config.max_held_per_sender {
+            return false;
+        }
+        request.messages.push(message.clone());
+        true
+    }
+
+    /// Challenge a sender at most once a minute so floods are not amplified
+    fn challenge_for(&self, sender: &str) -> Option<PowChallenge> {
+        if self.config.pow_difficulty_bits == 0 {
+            return None;
+        }
+        if self.last_challenge.get(sender).is_some_and(|sent| sent.elapsed() < Duration::from_secs(60)) {
+            return None;
+        }
+        self.last_challenge.insert(sender.to_string(), Instant::now());
+        Some(PowChallenge {
+            difficulty_bits: self.config.pow_difficulty_bits,
+            max_age_secs: self.config.stamp_max_age_secs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AdmissionConfig {
+        AdmissionConfig {
+            enabled: true,
+            stranger_rate_per_minute: 1,
+            stranger_burst: 2,
+            pow_difficulty_bits: 8,
+            ..AdmissionConfig::default()
+        }
+    }
+
+    fn message(from: &str) -> SimpleMessage {
+        SimpleMessage::new("bot@example.com", from, "hello")
+    }
+
+    #[test]
+    fn strangers_are_throttled_and_held() {
+        let controller = AdmissionController::new(config());
+        let stranger = message("stranger@example.com");
+        assert_eq!(controller.admit(&stranger, false), AdmissionDecision::Admit);
+        assert_eq!(controller.admit(&stranger, false), AdmissionDecision::Admit);
+
+        let AdmissionDecision::Hold { challenge } = controller.admit(&stranger, false) else {
+            panic!("third message should be held");
+        };
+        assert_eq!(challenge.unwrap().difficulty_bits, 8);
+        assert!(matches!(controller.admit(&stranger, false), AdmissionDecision::Hold { challenge: None }));
+
+        assert_eq!(controller.pending_requests().len(), 1);
+        assert_eq!(controller.approve("stranger@example.com").len(), 2);
+        assert!(controller.pending_requests().is_empty());
+    }
+
+    #[test]
+    fn contacts_and_trusted_senders_bypass_throttle() {
+        let controller = AdmissionController::new(AdmissionConfig { stranger_burst: 0, ..config() });
+        assert_eq!(controller.admit(&message("friend@example.com"), true), AdmissionDecision::Admit);
+
+        controller.set_trust("known@example.com", 80.0);
+        assert_eq!(controller.admit(&message("known@example.com"), false), AdmissionDecision::Admit);
+    }
+
+    #[test]
+    fn stamps_admit_once() {
+        let controller = AdmissionController::new(AdmissionConfig { stranger_burst: 0, ..config() });
+        let mut stamped = message("stranger@example.com");
+        let stamp = mint_stamp("stranger@example.com", "bot@example.com", 8);
+        assert!(verify_stamp(&stamp, "stranger@example.com", "bot@example.com", 8, 60));
+        assert!(!verify_stamp(&stamp, "other@example.com", "bot@example.com", 8, 60));
+
+        stamped.metadata.insert(POW_STAMP_KEY.to_string(), stamp);
+        assert_eq!(controller.admit(&stamped, false), AdmissionDecision::Admit);
+        assert!(matches!(controller.admit(&stamped, false), AdmissionDecision::Hold { .. }));
+    }
+}
@@ -0,0 +1,409 @@
+//! # Participant Analytics
+//!
+//! Research on multi-agent systems asks who talks to whom, how much, how
+//! quickly participants answer one another and whether any of it tracks
+//! trust. [`InteractionGraph::build`] derives that from the conversation
+//! history on this node: a directed edge per pair of participants with its
+//! message and byte counts and response latencies, per-participant totals,
+//! and the correlation of trust scores with volume and responsiveness.
+//!
+//! Nothing leaves the node unless exported, and message content is never
+//! read beyond its length. [`AnalyticsOptions`] replaces participant IDs
+//! with pseudonyms by default, leaves out opted-out participants and drops
+//! pairs that exchanged too few messages to hide in the crowd. Graphs export
+//! as JSON ([`InteractionGraph::to_json`]) or GraphML
+//! ([`InteractionGraph::to_graphml`]) for tools such as Gephi and NetworkX.
+
+use crate::error::Result;
+use crate::hashing::HashAlgorithm;
+use crate::history::HistoryEntry;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// What goes into an interaction graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyticsOptions {
+    /// Only messages recorded from then on
+    pub since: Option<DateTime<Utc>>,
+    /// Leave out pairs that exchanged fewer messages one way
+    pub min_messages: u64,
+    /// Replace participant IDs with pseudonyms
+    pub pseudonymize: bool,
+    /// Keys the pseudonyms, so they match across graphs built with the same
+    /// salt; a random one per graph when unset
+    pub pseudonym_salt: Option<String>,
+    /// Participants left out entirely, e.g. those who opted out
+    pub exclude: BTreeSet<String>,
+    /// Longest wait counted as a response, in seconds
+    pub reply_window_secs: u64,
+}
+
+impl Default for AnalyticsOptions {
+    fn default() -> Self {
+        Self {
+            since: None,
+            min_messages: 1,
+            pseudonymize: true,
+            pseudonym_salt: None,
+            exclude: BTreeSet::new(),
+            reply_window_secs: 86_400,
+        }
+    }
+}
+
+/// Messages one participant sent another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InteractionEdge {
+    pub from: String,
+    pub to: String,
+    pub messages: u64,
+    /// Content bytes
+    pub bytes: u64,
+    pub first_at: DateTime<Utc>,
+    pub last_at: DateTime<Utc>,
+    /// Messages `to` answered within the reply window
+    pub responses: u64,
+    /// Median time `to` took to answer
+    pub median_response_ms: Option<u64>,
+}
+
+/// One participant's share of the traffic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParticipantStats {
+    pub id: String,
+    pub sent: u64,
+    pub received: u64,
+    pub bytes_sent: u64,
+    /// Participants it exchanged messages with
+    pub peers: usize,
+    /// Median time it took to answer others
+    pub median_response_ms: Option<u64>,
+    /// Network trust score (0-100), when known
+    pub trust: Option<f64>,
+}
+
+/// Pearson correlation of participants' trust with their traffic
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrustCorrelation {
+    /// Participants with a trust score
+    pub participants: usize,
+    /// Trust against messages sent and received
+    pub volume: Option<f64>,
+    /// Trust against median response time
+    pub response_time: Option<f64>,
+}
+
+/// Who talked to whom, and how
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InteractionGraph {
+    pub generated_at: DateTime<Utc>,
+    pub since: Option<DateTime<Utc>>,
+    /// Whether IDs are pseudonyms
+    pub pseudonymized: bool,
+    pub participants: Vec<ParticipantStats>,
+    pub edges: Vec<InteractionEdge>,
+    pub trust_correlation: TrustCorrelation,
+}
+
+#[derive(Default)]
+struct EdgeTally {
+    messages: u64,
+    bytes: u64,
+    first_at: Option<DateTime<Utc>>,
+    last_at: Option<DateTime<Utc>>,
+    latencies: Vec<u64>,
+}
+
+#[derive(Default)]
+struct ParticipantTally {
+    sent: u64,
+    received: u64,
+    bytes_sent: u64,
+    peers: BTreeSet<String>,
+    latencies: Vec<u64>,
+}
+
+impl InteractionGraph {
+    /// Build the graph of `entries`, with `trust` scores by participant ID
+    pub fn build(entries: &[HistoryEntry], trust: &HashMap<String, f64>, options: &AnalyticsOptions) -> Self {
+        let mut included: Vec<&HistoryEntry> = entries
+            .iter()
+            .filter(|entry| entry.from != entry.to)
+            .filter(|entry| !options.exclude.contains(&entry.from) && !options.exclude.contains(&entry.to))
+            .filter(|entry| options.since.is_none_or(|since| entry.recorded_at.0 >= since))
+            .collect();
+        included.sort_by_key(|entry| entry.recorded_at.0);
+
+        // A message answers the oldest one its recipient sent it since the
+        // last answer
+        let window = Duration::seconds(options.reply_window_secs as i64);
+        let mut tallies: BTreeMap<(String, String), EdgeTally> = BTreeMap::new();
+        let mut unanswered: HashMap<(&str, &str), DateTime<Utc>> = HashMap::new();
+        for entry in included {
+            let at = entry.recorded_at.0;
+            if let Some(asked) = unanswered.remove(&(entry.to.as_str(), entry.from.as_str())) {
+                if at - asked <= window {
+                    let latency = (at - asked).num_milliseconds().max(0) as u64;
+                    tallies.entry((entry.to.clone(), entry.from.clone())).or_default().latencies.push(latency);
+                }
+            }
+            unanswered.entry((entry.from.as_str(), entry.to.as_str())).or_insert(at);
+
+            let tally = tallies.entry((entry.from.clone(), entry.to.clone())).or_default();
+            tally.messages += 1;
+            tally.bytes += entry.content.len() as u64;
+            tally.first_at.get_or_insert(at);
+            tally.last_at = Some(at);
+        }
+        tallies.retain(|_, tally| tally.messages >= options.min_messages.max(1));
+
+        let mut participants: BTreeMap<&str, ParticipantTally> = BTreeMap::new();
+        for ((from, to), tally) in &tallies {
+            let sender = participants.entry(from.as_str()).or_default();
+            sender.sent += tally.messages;
+            sender.bytes_sent += tally.bytes;
+            sender.peers.insert(to.clone());
+            let recipient = participants.entry(to.as_str()).or_default();
+            recipient.received += tally.messages;
+            recipient.peers.insert(from.clone());
+            recipient.latencies.extend_from_slice(&tally.latencies);
+        }
+
+        let salt = match options.pseudonym_salt {
+            Some(ref salt) => salt.clone(),
+            None => Uuid::new_v4().to_string(),
+        };
+        let name = |id: &str| {
+            if options.pseudonymize {
+                format!("p-{}", &HashAlgorithm::Sha256.hash(format!("{}:{}", salt, id).as_bytes())[..12])
+            } else {
+                id.to_string()
+            }
+        };
+
+        let participants: Vec<ParticipantStats> = participants
+            .into_iter()
+            .map(|(id, mut tally)| ParticipantStats {
+                id: name(id),
+                sent: tally.sent,
+                received: tally.received,
+                bytes_sent: tally.bytes_sent,
+                peers: tally.peers.len(),
+                median_response_ms: median(&mut tally.latencies),
+                trust: trust.get(id).copied(),
+            })
+            .collect();
+        let edges = tallies
+            .into_iter()
+            .filter_map(|((from, to), mut tally)| {
+                Some(InteractionEdge {
+                    from: name(&from),
+                    to: name(&to),
+                    messages: tally.messages,
+                    bytes: tally.bytes,
+                    first_at: tally.first_at?,
+                    last_at: tally.last_at?,
+                    responses: tally.latencies.len() as u64,
+                    median_response_ms: median(&mut tally.latencies),
+                })
+            })
+            .collect();
+
+        let trusted: Vec<&ParticipantStats> = participants.iter().filter(|p| p.trust.is_some()).collect();
+        let trust_correlation = TrustCorrelation {
+            participants: trusted.len(),
+            volume: pearson(trusted.iter().map(|p| (p.trust.unwrap_or_default(), (p.sent + p.received) as f64))),
+            response_time: pearson(
+                trusted
+                    .iter()
+                    .filter_map(|p| Some((p.trust.unwrap_or_default(), p.median_response_ms? as f64))),
+            ),
+        };
+
+        Self {
+            generated_at: Utc::now(),
+            since: options.since,
+            pseudonymized: options.pseudonymize,
+            participants,
+            edges,
+            trust_correlation,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// The graph as GraphML, with the statistics as node and edge data
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"sent\" for=\"node\" attr.name=\"sent\" attr.type=\"long\"/>\n",
+            "  <key id=\"received\" for=\"node\" attr.name=\"received\" attr.type=\"long\"/>\n",
+            "  <key id=\"bytes_sent\" for=\"node\" attr.name=\"bytes_sent\" attr.type=\"long\"/>\n",
+            "  <key id=\"node_response_ms\" for=\"node\" attr.name=\"median_response_ms\" attr.type=\"long\"/>\n",
+            "  <key id=\"trust\" for=\"node\" attr.name=\"trust\" attr.type=\"double\"/>\n",
+            "  <key id=\"messages\" for=\"edge\" attr.name=\"messages\" attr.type=\"long\"/>\n",
+            "  <key id=\"bytes\" for=\"edge\" attr.name=\"bytes\" attr.type=\"long\"/>\n",
+            "  <key id=\"responses\" for=\"edge\" attr.name=\"responses\" attr.type=\"long\"/>\n",
+            "  <key id=\"edge_response_ms\" for=\"edge\" attr.name=\"median_response_ms\" attr.type=\"long\"/>\n",
+            "  <graph id=\"interactions\" edgedefault=\"directed\">\n",
+        ));
+        let data = |out: &mut String, key: &str, value: &dyn std::fmt::Display| {
+            let _ = writeln!(out, "      <data key=\"{}\">{}</data>", key, value);
+        };
+        for participant in &self.participants {
+            let _ = writeln!(out, "    <node id=\"{}\">", escape_xml(&participant.id));
+            data(&mut out, "sent", &participant.sent);
+            data(&mut out, "received", &participant.received);
+            data(&mut out, "bytes_sent", &participant.bytes_sent);
+            if let Some(ms) = participant.median_response_ms {
+                data(&mut out, "node_response_ms", &ms);
+            }
+            if let Some(trust) = participant.trust {
+                data(&mut out, "trust", &trust);
+            }
+            out.push_str("    </node>\n");
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                out,
+                "    <edge source=\"{}\" target=\"{}\">",
+                escape_xml(&edge.from),
+                escape_xml(&edge.to)
+            );
+            data(&mut out, "messages", &edge.messages);
+            data(&mut out, "bytes", &edge.bytes);
+            data(&mut out, "responses", &edge.responses);
+            if let Some(ms) = edge.median_response_ms {
+                data(&mut out, "edge_response_ms", &ms);
+            }
+            out.push_str("    </edge>\n");
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+}
+
+fn median(values: &mut [u64]) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    Some(match values.len() % 2 {
+        0 => (values[middle - 1] + values[middle]) / 2,
+        _ => values[middle],
+    })
+}
+
+/// Pearson correlation coefficient; none for fewer than three points or
+/// when either side does not vary
+fn pearson(points: impl Iterator<Item = (f64, f64)>) -> Option<f64> {
+    let points: Vec<(f64, f64)> = points.collect();
+    if points.len() < 3 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in &points {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return None;
+    }
+    Some(covariance / (variance_x * variance_y).sqrt())
+}
+
+/// Escape text or an attribute value for XML output
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synapse::blockchain::serialization::DateTimeWrapper;
+    use crate::types::MessageType;
+
+    #[test]
+    fn graph_counts_pairs_and_response_latency() {
+        let start = Utc::now() - Duration::hours(1);
+        let entry = |from: &str, to: &str, secs: i64, content: &str| HistoryEntry {
+            message_id: Uuid::new_v4().to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            content: content.to_string(),
+            message_type: MessageType::Direct,
+            metadata: HashMap::new(),
+            recorded_at: DateTimeWrapper::new(start + Duration::seconds(secs)),
+            edited_at: None,
+            revisions: Vec::new(),
+            retracted_at: None,
+        };
+        let entries = vec![
+            entry("alice", "bob", 0, "hi"),
+            entry("alice", "bob", 10, "are you there?"),
+            entry("bob", "alice", 30, "yes"),
+            entry("carol", "bob", 40, "ping"),
+            entry("bob", "carol", 100, "pong"),
+            entry("alice", "<dave & co>", 200, "hello"),
+            entry("mallory", "bob", 300, "opted out"),
+        ];
+        let trust = HashMap::from([
+            ("alice".to_string(), 90.0),
+            ("bob".to_string(), 60.0),
+            ("carol".to_string(), 20.0),
+        ]);
+        let options = AnalyticsOptions {
+            pseudonymize: false,
+            exclude: BTreeSet::from(["mallory".to_string()]),
+            ..AnalyticsOptions::default()
+        };
+
+        let graph = InteractionGraph::build(&entries, &trust, &options);
+        let edge = |from: &str, to: &str| graph.edges.iter().find(|e| e.from == from && e.to == to).unwrap();
+        let alice_bob = edge("alice", "bob");
+        assert_eq!((alice_bob.messages, alice_bob.bytes, alice_bob.responses), (2, 16, 1));
+        // Bob answered the first of Alice's two messages 30 seconds later
+        assert_eq!(alice_bob.median_response_ms, Some(30_000));
+        assert_eq!(edge("carol", "bob").median_response_ms, Some(60_000));
+        assert_eq!(edge("bob", "alice").responses, 0);
+        assert!(graph.participants.iter().all(|p| p.id != "mallory"));
+
+        let bob = graph.participants.iter().find(|p| p.id == "bob").unwrap();
+        assert_eq!((bob.sent, bob.received, bob.peers), (2, 3, 2));
+        assert_eq!(bob.median_response_ms, Some(45_000));
+        assert_eq!(graph.trust_correlation.participants, 3);
+        assert!(graph.trust_correlation.volume.is_some_and(|r| (-1.0..=1.0).contains(&r)));
+
+        let graphml = graph.to_graphml();
+        assert!(graphml.contains("<edge source=\"alice\" target=\"&lt;dave &amp; co&gt;\">"));
+        assert_eq!(graphml.matches("<node ").count(), graph.participants.len());
+
+        // Pseudonyms are stable for a salt, and pairs below the minimum are dropped
+        let options = AnalyticsOptions {
+            min_messages: 2,
+            pseudonym_salt: Some("study-1".to_string()),
+            ..AnalyticsOptions::default()
+        };
+        let private = InteractionGraph::build(&entries, &trust, &options);
+        assert_eq!(private.edges.len(), 1);
+        assert!(private.participants.iter().all(|p| p.id.starts_with("p-") && p.id.len() == 14));
+        assert_eq!(InteractionGraph::build(&entries, &trust, &options).edges, private.edges);
+        assert!(!private.to_json().unwrap().contains("alice"));
+    }
+}
@@ -36,6 +36,7 @@ use uuid::Uuid;
 
 use crate::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
 use crate::error::SynapseError;
+use crate::redaction::Secret;
 use crate::types::SecureMessage;
 
 // Define local types to represent auth-framework functionality
@@ -167,7 +168,7 @@ pub struct SynapseAuthConfig {
 pub struct OAuthProviderConfig {
     pub provider: String,
     pub client_id: String,
-    pub client_secret: String,
+    pub client_secret: Secret<String>,
     pub redirect_uri: String,
     pub scopes: Vec<String>,
     pub enabled: bool,
@@ -416,7 +417,7 @@ impl SynapseAuthManager {
         let oauth_method = OAuth2Method::new()
             .provider(provider)
             .client_id(&config.client_id)
-            .client_secret(&config.client_secret)
+            .client_secret(config.client_secret.expose())
             .redirect_uri(&config.redirect_uri);
         
         Ok(oauth_method)
@@ -502,6 +503,7 @@ impl SynapseAuthManager {
             security_level: crate::types::SecurityLevel::Secure,
             routing_path: vec![],
             metadata: HashMap::new(),
+            attachments: Vec::new(),
         };
         
         Ok(secure_message)
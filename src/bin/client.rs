@@ -88,6 +88,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Command::new("status")
                 .about("Show client status and configuration"),
         )
+        .subcommand(
+            Command::new("capabilities")
+                .about("Show the features, transports and storage this binary was built with")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the report as JSON")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
         .subcommand(
             Command::new("add-entity")
                 .about("Add a new entity to the registry")
@@ -138,6 +148,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .get_matches();
 
+    // Needs no router or configuration
+    if let Some(("capabilities", capabilities_matches)) = matches.subcommand() {
+        return handle_capabilities_command(capabilities_matches);
+    }
+
     let global_id = matches.get_one::<String>("global-id").unwrap().clone();
 
     // Load or create configuration
@@ -337,6 +352,19 @@ async fn handle_status_command(router: &SynapseRouter) -> Result<(), Box<dyn std
     println!("\nEncryption Status:");
     println!("Crypto Support: {}", if status.crypto_available { "✓" } else { "✗" });
 
+    println!("\n{}", status.capabilities);
+
+    Ok(())
+}
+
+/// Handle the capabilities command
+fn handle_capabilities_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let report = synapse::capabilities();
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report);
+    }
     Ok(())
 }
 
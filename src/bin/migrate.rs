@@ -0,0 +1,113 @@
+//! Synapse Migrate - Inspect, upgrade and roll back the Synapse database schema
+//!
+//! The router upgrades the schema on startup; this tool is for checking what
+//! a release would change before deploying it and for rolling back afterwards.
+
+use clap::{Arg, ArgAction, Command};
+use sqlx::PgPool;
+use synapse::{
+    init_logging,
+    storage::migrations::{MigrationManager, MigrationPlan, PostgresExecutor},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    init_logging();
+
+    let dry_run = Arg::new("dry-run")
+        .long("dry-run")
+        .help("Print the plan without changing the schema")
+        .action(ArgAction::SetTrue);
+
+    let matches = Command::new("synapse-migrate")
+        .version("1.0.0")
+        .about("Synapse database schema migrations")
+        .arg(
+            Arg::new("database-url")
+                .long("database-url")
+                .value_name("URL")
+                .help("PostgreSQL connection string (defaults to $DATABASE_URL)")
+                .num_args(1),
+        )
+        .subcommand(Command::new("status").about("Show applied and pending migrations"))
+        .subcommand(
+            Command::new("up")
+                .about("Apply pending migrations")
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .value_name("VERSION")
+                        .help("Stop after this version")
+                        .value_parser(clap::value_parser!(i64))
+                        .num_args(1),
+                )
+                .arg(dry_run.clone()),
+        )
+        .subcommand(
+            Command::new("down")
+                .about("Roll back applied migrations newer than a version")
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .value_name("VERSION")
+                        .help("Version to roll back to (0 removes the whole schema)")
+                        .value_parser(clap::value_parser!(i64))
+                        .required(true)
+                        .num_args(1),
+                )
+                .arg(dry_run),
+        )
+        .get_matches();
+
+    let url = match matches.get_one::<String>("database-url") {
+        Some(url) => url.clone(),
+        None => std::env::var("DATABASE_URL").map_err(|_| "Pass --database-url or set DATABASE_URL")?,
+    };
+    let manager = MigrationManager::new(PgPool::connect(&url).await?);
+
+    match matches.subcommand() {
+        Some(("up", up)) => {
+            let plan = manager.migrate_to(up.get_one::<i64>("to").copied(), up.get_flag("dry-run")).await?;
+            print_plan(&plan);
+        }
+        Some(("down", down)) => {
+            let target = *down.get_one::<i64>("to").unwrap();
+            let plan = manager.rollback_to(target, down.get_flag("dry-run")).await?;
+            print_plan(&plan);
+        }
+        _ => print_status(&manager).await?,
+    }
+
+    Ok(())
+}
+
+async fn print_status(manager: &MigrationManager<PostgresExecutor>) -> Result<(), Box<dyn std::error::Error>> {
+    let status = manager.status().await?;
+    println!("Schema version {} (latest {})", status.current_version, status.latest_version);
+    for applied in &status.applied {
+        let flag = if status.modified.contains(&applied.version) {
+            "  MODIFIED"
+        } else if status.unknown.contains(&applied.version) {
+            "  UNKNOWN"
+        } else {
+            ""
+        };
+        println!("  [x] {:03} {} ({}){}", applied.version, applied.name, applied.applied_at, flag);
+    }
+    for pending in &status.pending {
+        println!("  [ ] {:03} {}", pending.version, pending.name);
+    }
+    Ok(())
+}
+
+fn print_plan(plan: &MigrationPlan) {
+    if plan.is_empty() {
+        println!("Nothing to do; schema is at version {}", plan.from_version);
+        return;
+    }
+    let verb = if plan.dry_run { "Would run" } else { "Ran" };
+    println!("{} {:?} {} -> {}:", verb, plan.direction, plan.from_version, plan.to_version);
+    for step in &plan.steps {
+        println!("  {:03} {}", step.version, step.name);
+    }
+}
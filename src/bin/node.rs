@@ -0,0 +1,72 @@
+//! Synapse Node - Container and service entrypoint configured from the environment
+//!
+//! Runs one standalone node whose settings all come from `SYNAPSE_*`
+//! environment variables (see `Config::apply_env`), so a container needs no
+//! configuration file. State is kept in `SYNAPSE_DATA_DIR`; mount a volume
+//! there to keep the node's key and history across restarts. Secrets can be
+//! passed as files, e.g. `SYNAPSE_SMTP_PASSWORD_FILE=/run/secrets/smtp`.
+//!
+//! Under systemd (`Type=notify`) the node reports readiness and pings the
+//! watchdog; with the `windows-service` feature, `synapse-node
+//! --windows-service` runs it under the Windows service control manager.
+//!
+//! `SYNAPSE_WORKER_THREADS`, `SYNAPSE_CONSENSUS_THREADS` and related
+//! variables lay out the runtimes transports, consensus and storage run on.
+
+use std::error::Error;
+use synapse::{
+    config::Config, init_logging, router_enhanced::EnhancedSynapseRouter, runtime::RuntimeTopology,
+    service::Supervisor,
+};
+use tracing::info;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    init_logging();
+
+    #[cfg(all(windows, feature = "windows-service"))]
+    if std::env::args().any(|arg| arg == "--windows-service") {
+        synapse::service::run_windows_service("synapse-node", |shutdown| {
+            serve(move || Supervisor::with_shutdown(shutdown))
+                .map_err(|e| synapse::error::SynapseError::ConfigurationError(e.to_string()))
+        })?;
+        return Ok(());
+    }
+
+    serve(Supervisor::detect)
+}
+
+/// Load the configuration and run the node on the runtimes it asks for
+fn serve(supervisor: impl FnOnce() -> Supervisor) -> Result<(), Box<dyn Error>> {
+    let config = Config::from_env()?;
+    config.validate()?;
+    RuntimeTopology::new(&config.runtime)?.install();
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if config.runtime.main_worker_threads > 0 {
+        runtime.worker_threads(config.runtime.main_worker_threads);
+    }
+    runtime.enable_all().build()?.block_on(async { run(config, supervisor()).await })
+}
+
+async fn run(config: Config, mut supervisor: Supervisor) -> Result<(), Box<dyn Error>> {
+    let data_dir = Config::env_data_dir();
+
+    #[cfg(feature = "database")]
+    if let Some(ref url) = config.storage.database_url {
+        use synapse::storage::{Database, StartupMigration};
+        supervisor.status("Migrating database");
+        Database::connect(url, StartupMigration::Upgrade).await?;
+        info!("Database schema is up to date");
+    }
+
+    let router = EnhancedSynapseRouter::standalone_with_config(config, &data_dir).await?;
+    router.start().await?;
+    info!("Node running with state in {}", data_dir.display());
+    supervisor.ready("Accepting messages");
+
+    supervisor.shutdown().wait().await;
+    supervisor.stopping();
+    router.stop().await?;
+    info!("Node stopped cleanly");
+    Ok(())
+}
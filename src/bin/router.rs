@@ -6,7 +6,7 @@
 use synapse::{
     router::SynapseRouter, config::Config, init_logging,
     types::{EmailConfig, SmtpConfig, ImapConfig},
-    config::{EntityConfig, RouterConfig, SecurityConfig, LoggingConfig},
+    config::{EntityConfig, RouterConfig, SecurityConfig, LoggingConfig, ListenerConfig, ListenerBinding},
 };
 use tokio::signal;
 use tracing::{info, error};
@@ -45,13 +45,14 @@ async fn main() -> Result<(), Box<dyn Error>> {
             domain: "local".to_string(),
             capabilities: vec!["routing".to_string()],
             display_name: Some(format!("Router {}", port)),
+            region: None,
         },
         email: EmailConfig {
             smtp: SmtpConfig {
                 host: "smtp.example.com".to_string(),
                 port: 587,
                 username: "test@example.com".to_string(),
-                password: "password".to_string(),
+                password: "password".into(),
                 use_tls: true,
                 use_ssl: false,
             },
@@ -59,7 +60,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 host: "imap.example.com".to_string(),
                 port: 993,
                 username: "test@example.com".to_string(),
-                password: "password".to_string(),
+                password: "password".into(),
                 use_ssl: true,
             },
         },
@@ -85,6 +86,34 @@ async fn main() -> Result<(), Box<dyn Error>> {
             file: None,
             log_message_content: false,
         },
+        proxy: Default::default(),
+        resumption: Default::default(),
+        relay: Default::default(),
+        admission: Default::default(),
+        trust_gate: Default::default(),
+        storage: Default::default(),
+        probing: Default::default(),
+        listeners: ListenerConfig {
+            tcp: ListenerBinding::any(port),
+            ..Default::default()
+        },
+        endpoint_updates: Default::default(),
+        cluster: Default::default(),
+        mail_store: Default::default(),
+        election: Default::default(),
+        metering: Default::default(),
+        payload_streams: Default::default(),
+        time_sync: Default::default(),
+        digest: Default::default(),
+        diagnostics: Default::default(),
+        echo: Default::default(),
+        nat: Default::default(),
+        fast_relay: Default::default(),
+        email_sending: Default::default(),
+        mailing_list: Default::default(),
+        limits: Default::default(),
+        runtime: Default::default(),
+        startup: Default::default(),
     };
 
     info!("Starting EMRP router on port {}", port);
@@ -121,6 +150,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     info!("  Known Peers: {}", health.known_peers);
     info!("  Known Keys: {}", health.known_keys);
     info!("  Email Configured: {}", if health.email_available { "✓" } else { "✗" });
+    info!("  Features: {}", health.capabilities.enabled_features().join(", "));
+    info!("  Transports: {}", health.capabilities.transports.join(", "));
 
     info!("Starting message processing loop...");
     loop {
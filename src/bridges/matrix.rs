@@ -0,0 +1,677 @@
+//! # Matrix Bridge
+//!
+//! Maps Synapse conversations to Matrix rooms so people on Element (or any
+//! Matrix client) can talk to AI participants without a custom client.
+//!
+//! The bridge logs in as a single Matrix bot account and hosts a Synapse
+//! identity on the local router. For each bridged room:
+//!
+//! - **Messages** from Matrix users are delivered to every Synapse participant
+//!   of the conversation, tagged with `conversation_id`, `bridge_sender` and
+//!   `bridge_event_id`. Messages from participants to the bridge identity are
+//!   posted into the room, prefixed with the sender's name.
+//! - **Membership** changes in the room are announced to participants as system
+//!   messages; participants joining or leaving on the Synapse side are
+//!   announced in the room with a notice.
+//! - **Read receipts** from Matrix users on messages a participant sent are
+//!   forwarded to that participant; a participant's `read_receipt` system
+//!   message marks the Matrix event as read by the bot.
+//!
+//! Participants reply by sending to the bridge's global ID with the
+//! `conversation_id` metadata key; it may be omitted when the participant is
+//! only in one bridged conversation.
+
+use super::{
+    BRIDGE_EVENT_ID_KEY, BRIDGE_EVENT_KEY, BRIDGE_KEY, BRIDGE_SENDER_KEY, CONVERSATION_ID_KEY, MEMBERSHIP_KEY,
+    SynapseEndpoint,
+};
+use crate::{
+    error::{Result, SynapseError},
+    redaction::Secret,
+    types::{MessageType, SimpleMessage},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Value of the `bridge` metadata key for Matrix traffic
+pub const MATRIX_BRIDGE: &str = "matrix";
+
+/// Matrix events remembered for read-receipt routing
+const MAX_TRACKED_EVENTS: usize = 4096;
+
+/// Matrix bridge settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixBridgeConfig {
+    /// Homeserver base URL, e.g. `https://matrix.example.org`
+    pub homeserver_url: String,
+    /// Access token of the bridge's bot account
+    pub access_token: Secret<String>,
+    /// Long-poll timeout for `/sync`
+    #[serde(default = "default_sync_timeout_ms")]
+    pub sync_timeout_ms: u64,
+    /// How often messages addressed to the bridge identity are collected
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Join rooms the bot is invited to (they are only bridged once mapped)
+    #[serde(default = "default_true")]
+    pub auto_join_invites: bool,
+}
+
+fn default_sync_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1_000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl MatrixBridgeConfig {
+    pub fn new(homeserver_url: impl Into<String>, access_token: impl Into<String>) -> Self {
+        Self {
+            homeserver_url: homeserver_url.into(),
+            access_token: Secret::new(access_token.into()),
+            sync_timeout_ms: default_sync_timeout_ms(),
+            poll_interval_ms: default_poll_interval_ms(),
+            auto_join_invites: true,
+        }
+    }
+}
+
+/// A Synapse conversation bridged to a Matrix room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomMapping {
+    pub conversation_id: String,
+    pub room_id: String,
+    /// Global IDs of the Synapse participants
+    pub participants: HashSet<String>,
+}
+
+/// Minimal Matrix client-server API client
+struct MatrixClient {
+    http: reqwest::Client,
+    base: String,
+    access_token: Secret<String>,
+}
+
+impl MatrixClient {
+    fn new(config: &MatrixBridgeConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.sync_timeout_ms) + Duration::from_secs(30))
+            .build()
+            .map_err(|e| SynapseError::ConfigurationError(format!("Failed to build Matrix client: {}", e)))?;
+        Ok(Self {
+            http,
+            base: format!("{}/_matrix/client/v3", config.homeserver_url.trim_end_matches('/')),
+            access_token: config.access_token.clone(),
+        })
+    }
+
+    async fn request(&self, method: reqwest::Method, path: &str, body: Option<&Value>) -> Result<Value> {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.base, path))
+            .bearer_auth(self.access_token.expose());
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SynapseError::NetworkError(format!("Matrix request {} failed: {}", path, e)))?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            return Err(SynapseError::TransportError(format!(
+                "Matrix homeserver rejected {} ({}): {}",
+                path,
+                status,
+                body.get("error").and_then(Value::as_str).unwrap_or("no error message")
+            )));
+        }
+        Ok(body)
+    }
+
+    async fn whoami(&self) -> Result<String> {
+        let body = self.request(reqwest::Method::GET, "/account/whoami", None).await?;
+        body.get("user_id")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| SynapseError::InvalidMessageFormat("whoami response has no user_id".to_string()))
+    }
+
+    async fn sync(&self, since: Option<&str>, timeout_ms: u64) -> Result<Value> {
+        let mut path = format!("/sync?timeout={}", timeout_ms);
+        if let Some(since) = since {
+            path.push_str(&format!("&since={}", encode(since)));
+        }
+        self.request(reqwest::Method::GET, &path, None).await
+    }
+
+    async fn send_event(&self, room_id: &str, txn_id: &str, content: &Value) -> Result<String> {
+        let path = format!("/rooms/{}/send/m.room.message/{}", encode(room_id), encode(txn_id));
+        let body = self.request(reqwest::Method::PUT, &path, Some(content)).await?;
+        Ok(body.get("event_id").and_then(Value::as_str).unwrap_or_default().to_string())
+    }
+
+    async fn read_receipt(&self, room_id: &str, event_id: &str) -> Result<()> {
+        let path = format!("/rooms/{}/receipt/m.read/{}", encode(room_id), encode(event_id));
+        self.request(reqwest::Method::POST, &path, Some(&json!({}))).await.map(|_| ())
+    }
+
+    async fn join(&self, room_id: &str) -> Result<()> {
+        let path = format!("/join/{}", encode(room_id));
+        self.request(reqwest::Method::POST, &path, Some(&json!({}))).await.map(|_| ())
+    }
+
+    async fn invite(&self, room_id: &str, user_id: &str) -> Result<()> {
+        let path = format!("/rooms/{}/invite", encode(room_id));
+        self.request(reqwest::Method::POST, &path, Some(&json!({ "user_id": user_id }))).await.map(|_| ())
+    }
+}
+
+/// Percent-encode a path segment or query value
+fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+/// Escape text for Matrix `formatted_body`
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Display name for a participant: the local part of its global ID
+fn display_name(global_id: &str) -> &str {
+    global_id.split('@').next().unwrap_or(global_id)
+}
+
+/// Room message content for a participant's message
+fn message_content(sender: &str, text: &str, notice: bool) -> Value {
+    let name = display_name(sender);
+    json!({
+        "msgtype": if notice { "m.notice" } else { "m.text" },
+        "body": format!("{}: {}", name, text),
+        "format": "org.matrix.custom.html",
+        "formatted_body": format!("<b>{}</b>: {}", escape_html(name), escape_html(text)),
+    })
+}
+
+/// Which participant a bridged Matrix event came from
+#[derive(Debug, Clone)]
+struct BridgedEvent {
+    event_id: String,
+    participant: String,
+    conversation_id: String,
+}
+
+/// Bidirectional bridge between Synapse conversations and Matrix rooms
+pub struct MatrixBridge {
+    config: MatrixBridgeConfig,
+    client: MatrixClient,
+    endpoint: Arc<dyn SynapseEndpoint>,
+    /// Bot account's Matrix user ID, learned on start
+    user_id: RwLock<Option<String>>,
+    /// Mappings keyed by room ID
+    rooms: RwLock<HashMap<String, RoomMapping>>,
+    /// Events the bridge posted for participants, oldest first
+    events: Mutex<VecDeque<BridgedEvent>>,
+    since: Mutex<Option<String>>,
+    txn_counter: AtomicU64,
+}
+
+impl MatrixBridge {
+    pub fn new(config: MatrixBridgeConfig, endpoint: Arc<dyn SynapseEndpoint>) -> Result<Self> {
+        if url::Url::parse(&config.homeserver_url).is_err() {
+            return Err(SynapseError::ConfigurationError(format!(
+                "Invalid Matrix homeserver URL: {}",
+                config.homeserver_url
+            )));
+        }
+        Ok(Self {
+            client: MatrixClient::new(&config)?,
+            config,
+            endpoint,
+            user_id: RwLock::new(None),
+            rooms: RwLock::new(HashMap::new()),
+            events: Mutex::new(VecDeque::new()),
+            since: Mutex::new(None),
+            txn_counter: AtomicU64::new(0),
+        })
+    }
+
+    /// Bridge a conversation to a room
+    pub fn bridge_room(
+        &self,
+        conversation_id: impl Into<String>,
+        room_id: impl Into<String>,
+        participants: impl IntoIterator<Item = String>,
+    ) {
+        let mapping = RoomMapping {
+            conversation_id: conversation_id.into(),
+            room_id: room_id.into(),
+            participants: participants.into_iter().collect(),
+        };
+        info!("Bridging conversation {} to Matrix room {}", mapping.conversation_id, mapping.room_id);
+        self.rooms.write().unwrap().insert(mapping.room_id.clone(), mapping);
+    }
+
+    /// Stop bridging a room
+    pub fn unbridge_room(&self, room_id: &str) -> Option<RoomMapping> {
+        self.rooms.write().unwrap().remove(room_id)
+    }
+
+    /// Current room mappings, e.g. for persisting across restarts
+    pub fn mappings(&self) -> Vec<RoomMapping> {
+        self.rooms.read().unwrap().values().cloned().collect()
+    }
+
+    /// Add a Synapse participant to a conversation and announce it in the room
+    pub async fn add_participant(&self, conversation_id: &str, global_id: &str) -> Result<()> {
+        let room_id = self.update_participants(conversation_id, |p| p.insert(global_id.to_string()))?;
+        self.post(&room_id, global_id, "joined the conversation", true).await.map(|_| ())
+    }
+
+    /// Remove a Synapse participant from a conversation and announce it in the room
+    pub async fn remove_participant(&self, conversation_id: &str, global_id: &str) -> Result<()> {
+        let room_id = self.update_participants(conversation_id, |p| p.remove(global_id))?;
+        self.post(&room_id, global_id, "left the conversation", true).await.map(|_| ())
+    }
+
+    /// Invite a Matrix user into a bridged conversation's room
+    pub async fn invite(&self, conversation_id: &str, matrix_user_id: &str) -> Result<()> {
+        let room_id = self.room_for_conversation(conversation_id)?;
+        self.client.invite(&room_id, matrix_user_id).await
+    }
+
+    /// Run the Matrix sync loop and the Synapse polling loop until aborted
+    pub async fn start(self: Arc<Self>) -> Result<JoinHandle<()>> {
+        let user_id = self.client.whoami().await?;
+        info!("Matrix bridge logged in as {}", user_id);
+        *self.user_id.write().unwrap() = Some(user_id);
+
+        Ok(tokio::spawn(async move {
+            tokio::join!(self.sync_loop(), self.poll_loop());
+        }))
+    }
+
+    async fn sync_loop(&self) {
+        loop {
+            if let Err(e) = self.sync_once().await {
+                warn!("Matrix sync failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+
+    async fn poll_loop(&self) {
+        let mut interval = tokio::time::interval(Duration::from_millis(self.config.poll_interval_ms));
+        loop {
+            interval.tick().await;
+            let messages = match self.endpoint.receive().await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    warn!("Failed to collect messages for the Matrix bridge: {}", e);
+                    continue;
+                }
+            };
+            for message in messages {
+                if let Err(e) = self.handle_synapse_message(&message).await {
+                    warn!("Failed to bridge message from {} to Matrix: {}", message.from_entity, e);
+                }
+            }
+        }
+    }
+
+    /// Run one `/sync` round and deliver the results to Synapse
+    pub async fn sync_once(&self) -> Result<()> {
+        let since = self.since.lock().unwrap().clone();
+        // The first sync only establishes a position; history is not replayed
+        let timeout = if since.is_some() { self.config.sync_timeout_ms } else { 0 };
+        let response = self.client.sync(since.as_deref(), timeout).await?;
+
+        if self.config.auto_join_invites {
+            if let Some(invites) = response.pointer("/rooms/invite").and_then(Value::as_object) {
+                for room_id in invites.keys() {
+                    match self.client.join(room_id).await {
+                        Ok(()) => info!("Matrix bridge joined {}", room_id),
+                        Err(e) => warn!("Failed to join Matrix room {}: {}", room_id, e),
+                    }
+                }
+            }
+        }
+
+        if since.is_some() {
+            for message in self.translate_sync(&response) {
+                self.endpoint.send(message).await?;
+            }
+        }
+
+        if let Some(next) = response.get("next_batch").and_then(Value::as_str) {
+            *self.since.lock().unwrap() = Some(next.to_string());
+        }
+        Ok(())
+    }
+
+    /// Turn a `/sync` response into Synapse messages for the mapped rooms
+    pub fn translate_sync(&self, response: &Value) -> Vec<SimpleMessage> {
+        let Some(joined) = response.pointer("/rooms/join").and_then(Value::as_object) else {
+            return Vec::new();
+        };
+
+        let mut messages = Vec::new();
+        for (room_id, room) in joined {
+            let Some(mapping) = self.rooms.read().unwrap().get(room_id).cloned() else {
+                continue;
+            };
+            let timeline = room.pointer("/timeline/events").and_then(Value::as_array);
+            for event in timeline.into_iter().flatten() {
+                messages.extend(self.translate_timeline_event(&mapping, event));
+            }
+            let ephemeral = room.pointer("/ephemeral/events").and_then(Value::as_array);
+            for event in ephemeral.into_iter().flatten() {
+                messages.extend(self.translate_receipts(event));
+            }
+        }
+        messages
+    }
+
+    fn translate_timeline_event(&self, mapping: &RoomMapping, event: &Value) -> Vec<SimpleMessage> {
+        let sender = event.get("sender").and_then(Value::as_str).unwrap_or_default();
+        if self.user_id.read().unwrap().as_deref() == Some(sender) {
+            return Vec::new();
+        }
+        let event_id = event.get("event_id").and_then(Value::as_str).unwrap_or_default();
+        let content = event.get("content").cloned().unwrap_or(Value::Null);
+
+        let (text, message_type, extra) = match event.get("type").and_then(Value::as_str) {
+            Some("m.room.message") => {
+                let Some(body) = content.get("body").and_then(Value::as_str) else {
+                    return Vec::new();
+                };
+                (body.to_string(), MessageType::Direct, None)
+            }
+            Some("m.room.member") => {
+                let Some(membership) = content.get("membership").and_then(Value::as_str) else {
+                    return Vec::new();
+                };
+                let subject = event.get("state_key").and_then(Value::as_str).unwrap_or(sender);
+                (
+                    format!("{} membership in the conversation is now {}", subject, membership),
+                    MessageType::System,
+                    Some(("membership", membership.to_string(), subject.to_string())),
+                )
+            }
+            _ => return Vec::new(),
+        };
+
+        mapping
+            .participants
+            .iter()
+            .map(|participant| {
+                let mut message = SimpleMessage {
+                    to: participant.clone(),
+                    from_entity: self.endpoint.global_id(),
+                    content: text.clone(),
+                    message_type: message_type.clone(),
+                    metadata: HashMap::new(),
+                };
+                let metadata = &mut message.metadata;
+                metadata.insert(BRIDGE_KEY.to_string(), MATRIX_BRIDGE.to_string());
+                metadata.insert(CONVERSATION_ID_KEY.to_string(), mapping.conversation_id.clone());
+                metadata.insert(BRIDGE_EVENT_ID_KEY.to_string(), event_id.to_string());
+                match &extra {
+                    Some((kind, membership, subject)) => {
+                        metadata.insert(BRIDGE_EVENT_KEY.to_string(), kind.to_string());
+                        metadata.insert(MEMBERSHIP_KEY.to_string(), membership.clone());
+                        metadata.insert(BRIDGE_SENDER_KEY.to_string(), subject.clone());
+                    }
+                    None => {
+                        metadata.insert(BRIDGE_SENDER_KEY.to_string(), sender.to_string());
+                    }
+                }
+                message
+            })
+            .collect()
+    }
+
+    /// Forward `m.read` receipts on participants' messages to those participants
+    fn translate_receipts(&self, event: &Value) -> Vec<SimpleMessage> {
+        if event.get("type").and_then(Value::as_str) != Some("m.receipt") {
+            return Vec::new();
+        }
+        let Some(content) = event.get("content").and_then(Value::as_object) else {
+            return Vec::new();
+        };
+        let bot = self.user_id.read().unwrap().clone();
+        let events = self.events.lock().unwrap();
+
+        let mut messages = Vec::new();
+        for (event_id, receipts) in content {
+            let Some(bridged) = events.iter().find(|e| &e.event_id == event_id) else {
+                continue;
+            };
+            let readers = receipts.get("m.read").and_then(Value::as_object);
+            for reader in readers.into_iter().flat_map(|r| r.keys()) {
+                if bot.as_deref() == Some(reader.as_str()) {
+                    continue;
+                }
+                let mut metadata = HashMap::new();
+                metadata.insert(BRIDGE_KEY.to_string(), MATRIX_BRIDGE.to_string());
+                metadata.insert(BRIDGE_EVENT_KEY.to_string(), "read_receipt".to_string());
+                metadata.insert(BRIDGE_EVENT_ID_KEY.to_string(), event_id.clone());
+                metadata.insert(BRIDGE_SENDER_KEY.to_string(), reader.clone());
+                metadata.insert(CONVERSATION_ID_KEY.to_string(), bridged.conversation_id.clone());
+                messages.push(SimpleMessage {
+                    to: bridged.participant.clone(),
+                    from_entity: self.endpoint.global_id(),
+                    content: format!("{} read your message", reader),
+                    message_type: MessageType::System,
+                    metadata,
+                });
+            }
+        }
+        messages
+    }
+
+    /// Post a participant's message to Matrix, or apply a receipt or membership event
+    pub async fn handle_synapse_message(&self, message: &SimpleMessage) -> Result<()> {
+        let mapping = self.mapping_for(message)?;
+        if !mapping.participants.contains(&message.from_entity) {
+            return Err(SynapseError::AuthorizationError(format!(
+                "{} is not a participant in conversation {}",
+                message.from_entity, mapping.conversation_id
+            )));
+        }
+
+        match message.metadata.get(BRIDGE_EVENT_KEY).map(String::as_str) {
+            Some("read_receipt") => {
+                let event_id = message.metadata.get(BRIDGE_EVENT_ID_KEY).ok_or_else(|| {
+                    SynapseError::InvalidMessageFormat(format!("Read receipt without {}", BRIDGE_EVENT_ID_KEY))
+                })?;
+                self.client.read_receipt(&mapping.room_id, event_id).await
+            }
+            Some("membership") => match message.metadata.get(MEMBERSHIP_KEY).map(String::as_str) {
+                Some("leave") => self.remove_participant(&mapping.conversation_id, &message.from_entity).await,
+                other => Err(SynapseError::InvalidMessageFormat(format!(
+                    "Unsupported membership change from Synapse: {:?}",
+                    other
+                ))),
+            },
+            _ => {
+                let notice = message.message_type == MessageType::System;
+                let event_id = self.post(&mapping.room_id, &message.from_entity, &message.content, notice).await?;
+                self.track_event(BridgedEvent {
+                    event_id,
+                    participant: message.from_entity.clone(),
+                    conversation_id: mapping.conversation_id,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    async fn post(&self, room_id: &str, sender: &str, text: &str, notice: bool) -> Result<String> {
+        let txn_id = format!(
+            "synapse-{}-{}",
+            std::process::id(),
+            self.txn_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        let event_id = self.client.send_event(room_id, &txn_id, &message_content(sender, text, notice)).await?;
+        debug!("Posted {} to {} as {}", sender, room_id, event_id);
+        Ok(event_id)
+    }
+
+    fn track_event(&self, event: BridgedEvent) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() >= MAX_TRACKED_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// Mapping a Synapse message belongs to, by `conversation_id` or by its sender's only conversation
+    fn mapping_for(&self, message: &SimpleMessage) -> Result<RoomMapping> {
+        let rooms = self.rooms.read().unwrap();
+        if let Some(conversation_id) = message.metadata.get(CONVERSATION_ID_KEY) {
+            return rooms
+                .values()
+                .find(|m| &m.conversation_id == conversation_id)
+                .cloned()
+                .ok_or_else(|| SynapseError::NotFound(format!("No Matrix room bridged for {}", conversation_id)));
+        }
+
+        let mut candidates = rooms.values().filter(|m| m.participants.contains(&message.from_entity));
+        match (candidates.next(), candidates.next()) {
+            (Some(mapping), None) => Ok(mapping.clone()),
+            (None, _) => Err(SynapseError::NotFound(format!(
+                "{} is not in any bridged conversation",
+                message.from_entity
+            ))),
+            (Some(_), Some(_)) => Err(SynapseError::InvalidMessageFormat(format!(
+                "{} is in several bridged conversations; set {}",
+                message.from_entity, CONVERSATION_ID_KEY
+            ))),
+        }
+    }
+
+    fn room_for_conversation(&self, conversation_id: &str) -> Result<String> {
+        self.rooms
+            .read()
+            .unwrap()
+            .values()
+            .find(|m| m.conversation_id == conversation_id)
+            .map(|m| m.room_id.clone())
+            .ok_or_else(|| SynapseError::NotFound(format!("No Matrix room bridged for {}", conversation_id)))
+    }
+
+    fn update_participants(&self, conversation_id: &str, update: impl FnOnce(&mut HashSet<String>) -> bool) -> Result<String> {
+        let mut rooms = self.rooms.write().unwrap();
+        let mapping = rooms
+            .values_mut()
+            .find(|m| m.conversation_id == conversation_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("No Matrix room bridged for {}", conversation_id)))?;
+        update(&mut mapping.participants);
+        Ok(mapping.room_id.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct NullEndpoint;
+
+    #[async_trait]
+    impl SynapseEndpoint for NullEndpoint {
+        fn global_id(&self) -> String {
+            "matrix-bridge@example.com".to_string()
+        }
+
+        async fn send(&self, _message: SimpleMessage) -> Result<()> {
+            Ok(())
+        }
+
+        async fn receive(&self) -> Result<Vec<SimpleMessage>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn bridge() -> MatrixBridge {
+        let bridge = MatrixBridge::new(
+            MatrixBridgeConfig::new("https://matrix.example.org", "token"),
+            Arc::new(NullEndpoint),
+        )
+        .unwrap();
+        *bridge.user_id.write().unwrap() = Some("@bridge:example.org".to_string());
+        bridge.bridge_room("support", "!room:example.org", ["claude@ai.example.com".to_string()]);
+        bridge
+    }
+
+    #[test]
+    fn room_messages_reach_every_participant() {
+        let bridge = bridge();
+        let sync = json!({
+            "rooms": { "join": { "!room:example.org": { "timeline": { "events": [
+                { "type": "m.room.message", "sender": "@alice:example.org", "event_id": "$1",
+                  "content": { "msgtype": "m.text", "body": "hello" } },
+                { "type": "m.room.message", "sender": "@bridge:example.org", "event_id": "$2",
+                  "content": { "msgtype": "m.text", "body": "echo" } },
+                { "type": "m.room.member", "sender": "@bob:example.org", "state_key": "@bob:example.org",
+                  "event_id": "$3", "content": { "membership": "join" } }
+            ] } } } }
+        });
+
+        let messages = bridge.translate_sync(&sync);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].to, "claude@ai.example.com");
+        assert_eq!(messages[0].content, "hello");
+        assert_eq!(messages[0].metadata[BRIDGE_SENDER_KEY], "@alice:example.org");
+        assert_eq!(messages[0].metadata[CONVERSATION_ID_KEY], "support");
+        assert_eq!(messages[1].message_type, MessageType::System);
+        assert_eq!(messages[1].metadata[MEMBERSHIP_KEY], "join");
+    }
+
+    #[test]
+    fn receipts_are_routed_to_the_original_sender() {
+        let bridge = bridge();
+        bridge.track_event(BridgedEvent {
+            event_id: "$mine".to_string(),
+            participant: "claude@ai.example.com".to_string(),
+            conversation_id: "support".to_string(),
+        });
+        let sync = json!({
+            "rooms": { "join": { "!room:example.org": { "ephemeral": { "events": [
+                { "type": "m.receipt", "content": {
+                    "$mine": { "m.read": { "@alice:example.org": { "ts": 1 } } },
+                    "$other": { "m.read": { "@alice:example.org": { "ts": 1 } } }
+                } }
+            ] } } } }
+        });
+
+        let messages = bridge.translate_sync(&sync);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].to, "claude@ai.example.com");
+        assert_eq!(messages[0].metadata[BRIDGE_EVENT_KEY], "read_receipt");
+    }
+
+    #[test]
+    fn formatted_body_is_escaped() {
+        let content = message_content("claude@ai.example.com", "<script>", false);
+        assert_eq!(content["body"], "claude: <script>");
+        assert_eq!(content["formatted_body"], "<b>claude</b>: &lt;script&gt;");
+    }
+}
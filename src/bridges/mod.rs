@@ -0,0 +1,95 @@
+//! Bridges between Synapse and other chat networks
+//!
+//! A bridge hosts a Synapse identity on the local router and relays traffic
+//! between that identity and rooms or contacts on a foreign network. Messages
+//! coming from the foreign side carry the metadata keys below, so Synapse
+//! participants can tell who actually spoke and reply into the right
+//! conversation.
+
+use crate::{
+    error::Result,
+    router_enhanced::EnhancedSynapseRouter,
+    transport::abstraction::MessageUrgency,
+    types::{SecurityLevel, SimpleMessage},
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+#[cfg(feature = "matrix")]
+pub mod matrix;
+#[cfg(feature = "xmpp")]
+pub mod xmpp;
+
+/// Conversation a bridged message belongs to
+pub const CONVERSATION_ID_KEY: &str = "conversation_id";
+/// Which bridge a message came through (e.g. `matrix`)
+pub const BRIDGE_KEY: &str = "bridge";
+/// Foreign user who sent the message
+pub const BRIDGE_SENDER_KEY: &str = "bridge_sender";
+/// Foreign user a Synapse participant's message should go to
+pub const BRIDGE_RECIPIENT_KEY: &str = "bridge_recipient";
+/// Foreign event or stanza ID, used to address read receipts
+pub const BRIDGE_EVENT_ID_KEY: &str = "bridge_event_id";
+/// Non-message events: `membership`, `read_receipt` or `presence`
+pub const BRIDGE_EVENT_KEY: &str = "bridge_event";
+/// For membership events: `join`, `leave`, `invite` or `ban`
+pub const MEMBERSHIP_KEY: &str = "membership";
+/// For presence events: `available`, `away`, `chat`, `dnd`, `xa` or `unavailable`
+pub const PRESENCE_KEY: &str = "presence";
+
+/// The Synapse side of a bridge
+#[async_trait]
+pub trait SynapseEndpoint: Send + Sync {
+    /// Global ID the bridge sends and receives as
+    fn global_id(&self) -> String;
+
+    /// Send a message into the Synapse network, keeping its metadata
+    async fn send(&self, message: SimpleMessage) -> Result<()>;
+
+    /// Take messages addressed to the bridge
+    async fn receive(&self) -> Result<Vec<SimpleMessage>>;
+}
+
+/// Bridge endpoint backed by one of the router's local identities
+pub struct LocalIdentityEndpoint {
+    router: Arc<EnhancedSynapseRouter>,
+    identity: String,
+    security_level: SecurityLevel,
+}
+
+impl LocalIdentityEndpoint {
+    /// Use the local identity `identity`, which must already be hosted on the router
+    pub fn new(router: Arc<EnhancedSynapseRouter>, identity: impl Into<String>) -> Self {
+        Self {
+            router,
+            identity: identity.into(),
+            security_level: SecurityLevel::Authenticated,
+        }
+    }
+
+    /// Security level for messages sent into Synapse (default: authenticated)
+    pub fn with_security_level(mut self, security_level: SecurityLevel) -> Self {
+        self.security_level = security_level;
+        self
+    }
+}
+
+#[async_trait]
+impl SynapseEndpoint for LocalIdentityEndpoint {
+    fn global_id(&self) -> String {
+        self.router
+            .local_identity(&self.identity)
+            .map_or_else(|| self.identity.clone(), |identity| identity.global_id.clone())
+    }
+
+    async fn send(&self, message: SimpleMessage) -> Result<()> {
+        self.router
+            .send_prepared_as(&self.identity, message, self.security_level.clone(), MessageUrgency::Interactive)
+            .await
+            .map(|_| ())
+    }
+
+    async fn receive(&self) -> Result<Vec<SimpleMessage>> {
+        self.router.receive_as(&self.identity)
+    }
+}
@@ -0,0 +1,685 @@
+//! # XMPP Gateway
+//!
+//! Connects to an existing XMPP server as an external component (XEP-0114)
+//! and serves a domain such as `synapse.example.com`. Every Synapse identity
+//! gets a JID on that domain, so XMPP users add `claude@synapse.example.com`
+//! to their roster like any other contact:
+//!
+//! - Identities in the gateway's own Synapse domain keep their local part
+//!   (`claude@example.com` becomes `claude@synapse.example.com`).
+//! - Identities elsewhere are escaped per XEP-0106
+//!   (`bot@ai.other.org` becomes `bot\40ai.other.org@synapse.example.com`).
+//!
+//! Chat messages become direct Synapse messages from the gateway identity
+//! with the XMPP sender in `bridge_sender` and the `<thread>` in
+//! `conversation_id`. Synapse identities reply by sending to the gateway with
+//! `bridge_recipient` (or the received `bridge_sender`) set to the bare JID.
+//! Presence flows both ways: XMPP presence arrives as `presence` system
+//! messages, and identities publish theirs by sending a system message with
+//! `bridge_event = presence`. Subscription requests are approved
+//! automatically.
+
+use super::{
+    BRIDGE_EVENT_ID_KEY, BRIDGE_EVENT_KEY, BRIDGE_KEY, BRIDGE_RECIPIENT_KEY, BRIDGE_SENDER_KEY, CONVERSATION_ID_KEY,
+    PRESENCE_KEY, SynapseEndpoint,
+};
+use crate::{
+    error::{Result, SynapseError},
+    redaction::Secret,
+    types::{MessageType, SimpleMessage},
+};
+use quick_xml::{Reader, events::Event};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn};
+
+/// Value of the `bridge` metadata key for XMPP traffic
+pub const XMPP_BRIDGE: &str = "xmpp";
+
+/// XMPP gateway settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XmppGatewayConfig {
+    /// XMPP server accepting component connections
+    pub server_host: String,
+    /// Component port (commonly 5347)
+    #[serde(default = "default_component_port")]
+    pub server_port: u16,
+    /// Domain the component serves, e.g. `synapse.example.com`
+    pub component_domain: String,
+    /// Shared component secret configured on the XMPP server
+    pub secret: Secret<String>,
+    /// Synapse domain whose identities keep their plain local part
+    pub synapse_domain: String,
+    /// Delay before reconnecting after the stream drops
+    #[serde(default = "default_reconnect_delay_secs")]
+    pub reconnect_delay_secs: u64,
+    /// How often messages addressed to the gateway identity are collected
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+fn default_component_port() -> u16 {
+    5347
+}
+
+fn default_reconnect_delay_secs() -> u64 {
+    10
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1_000
+}
+
+/// A parsed XMPP stanza
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stanza {
+    pub name: String,
+    pub attrs: HashMap<String, String>,
+    pub children: Vec<Stanza>,
+    pub text: String,
+}
+
+impl Stanza {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.get(name).map(String::as_str)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Stanza> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|c| c.text.as_str())
+    }
+
+    /// Parse a single stanza from a string
+    pub fn parse(xml: &str) -> Result<Stanza> {
+        let mut reader = Reader::from_str(xml);
+        let mut builder = StanzaBuilder::default();
+        loop {
+            let event = reader.read_event().map_err(xml_error)?;
+            if matches!(event, Event::Eof) {
+                return Err(SynapseError::InvalidMessageFormat("Incomplete XMPP stanza".to_string()));
+            }
+            if let Some(Frame::Stanza(stanza)) = builder.feed(event)? {
+                return Ok(stanza);
+            }
+        }
+    }
+}
+
+fn xml_error(e: impl std::fmt::Display) -> SynapseError {
+    SynapseError::InvalidMessageFormat(format!("Malformed XMPP stream: {}", e))
+}
+
+/// Escape text or an attribute value for XML output
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// What the stanza builder produced from an event
+enum Frame {
+    /// Opening `<stream:stream>` with its attributes
+    StreamOpen(HashMap<String, String>),
+    Stanza(Stanza),
+    StreamClose,
+}
+
+/// Assembles top-level stanzas from XML events
+#[derive(Default)]
+struct StanzaBuilder {
+    stack: Vec<Stanza>,
+}
+
+impl StanzaBuilder {
+    fn feed(&mut self, event: Event<'_>) -> Result<Option<Frame>> {
+        match event {
+            Event::Start(start) => {
+                let element = Self::element(&start)?;
+                if element.name == "stream:stream" && self.stack.is_empty() {
+                    return Ok(Some(Frame::StreamOpen(element.attrs)));
+                }
+                self.stack.push(element);
+                Ok(None)
+            }
+            Event::Empty(start) => {
+                let element = Self::element(&start)?;
+                Ok(self.finish(element))
+            }
+            Event::Text(text) => {
+                if let Some(top) = self.stack.last_mut() {
+                    top.text.push_str(&text.unescape().map_err(xml_error)?);
+                }
+                Ok(None)
+            }
+            Event::CData(data) => {
+                if let Some(top) = self.stack.last_mut() {
+                    top.text.push_str(&String::from_utf8_lossy(&data));
+                }
+                Ok(None)
+            }
+            Event::End(end) => match self.stack.pop() {
+                Some(element) => Ok(self.finish(element)),
+                None if end.name().as_ref() == b"stream:stream" => Ok(Some(Frame::StreamClose)),
+                None => Err(xml_error("unexpected closing tag")),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn element(start: &quick_xml::events::BytesStart<'_>) -> Result<Stanza> {
+        let mut element = Stanza {
+            name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+            ..Default::default()
+        };
+        for attr in start.attributes() {
+            let attr = attr.map_err(xml_error)?;
+            element.attrs.insert(
+                String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                attr.unescape_value().map_err(xml_error)?.into_owned(),
+            );
+        }
+        Ok(element)
+    }
+
+    fn finish(&mut self, element: Stanza) -> Option<Frame> {
+        match self.stack.last_mut() {
+            Some(parent) => {
+                parent.children.push(element);
+                None
+            }
+            None => Some(Frame::Stanza(element)),
+        }
+    }
+}
+
+async fn read_frame<R: AsyncBufRead + Unpin>(
+    reader: &mut Reader<R>,
+    builder: &mut StanzaBuilder,
+    buf: &mut Vec<u8>,
+) -> Result<Option<Frame>> {
+    loop {
+        buf.clear();
+        let event = reader.read_event_into_async(buf).await.map_err(xml_error)?;
+        if matches!(event, Event::Eof) {
+            return Ok(None);
+        }
+        if let Some(frame) = builder.feed(event)? {
+            return Ok(Some(frame));
+        }
+    }
+}
+
+/// Escape a Synapse global ID for use as a JID local part (XEP-0106)
+pub fn escape_node(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ' ' => escaped.push_str("\\20"),
+            '"' => escaped.push_str("\\22"),
+            '&' => escaped.push_str("\\26"),
+            '\'' => escaped.push_str("\\27"),
+            '/' => escaped.push_str("\\2f"),
+            ':' => escaped.push_str("\\3a"),
+            '<' => escaped.push_str("\\3c"),
+            '>' => escaped.push_str("\\3e"),
+            '@' => escaped.push_str("\\40"),
+            '\\' => escaped.push_str("\\5c"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverse [`escape_node`]
+pub fn unescape_node(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find('\\') {
+        unescaped.push_str(&rest[..pos]);
+        let code = rest.get(pos + 1..pos + 3);
+        match code.and_then(|c| u8::from_str_radix(c, 16).ok()).map(char::from) {
+            Some(c @ (' ' | '"' | '&' | '\'' | '/' | ':' | '<' | '>' | '@' | '\\')) => {
+                unescaped.push(c);
+                rest = &rest[pos + 3..];
+            }
+            _ => {
+                unescaped.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// JID without its resource
+fn bare_jid(jid: &str) -> &str {
+    jid.split('/').next().unwrap_or(jid)
+}
+
+/// Bidirectional gateway between Synapse identities and XMPP users
+pub struct XmppGateway {
+    config: XmppGatewayConfig,
+    endpoint: Arc<dyn SynapseEndpoint>,
+    /// Writer for the current stream, when connected
+    outbound: RwLock<Option<mpsc::UnboundedSender<String>>>,
+    /// XMPP users subscribed to each Synapse identity's presence
+    subscribers: RwLock<HashMap<String, HashSet<String>>>,
+    /// Presence last published by each Synapse identity
+    presence: RwLock<HashMap<String, String>>,
+}
+
+impl XmppGateway {
+    pub fn new(config: XmppGatewayConfig, endpoint: Arc<dyn SynapseEndpoint>) -> Self {
+        Self {
+            config,
+            endpoint,
+            outbound: RwLock::new(None),
+            subscribers: RwLock::new(HashMap::new()),
+            presence: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// JID representing a Synapse identity
+    pub fn jid_for(&self, global_id: &str) -> String {
+        match global_id.rsplit_once('@') {
+            Some((local, domain)) if domain.eq_ignore_ascii_case(&self.config.synapse_domain) => {
+                format!("{}@{}", escape_node(local), self.config.component_domain)
+            }
+            _ => format!("{}@{}", escape_node(global_id), self.config.component_domain),
+        }
+    }
+
+    /// Synapse identity behind a JID on the gateway's domain
+    pub fn global_id_for(&self, jid: &str) -> Option<String> {
+        let (node, domain) = bare_jid(jid).split_once('@')?;
+        if !domain.eq_ignore_ascii_case(&self.config.component_domain) {
+            return None;
+        }
+        let node = unescape_node(node);
+        if node.contains('@') {
+            Some(node)
+        } else {
+            Some(format!("{}@{}", node, self.config.synapse_domain))
+        }
+    }
+
+    /// Connect and keep the component stream up until aborted
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let sessions = async {
+                loop {
+                    match self.run_session().await {
+                        Ok(()) => info!("XMPP server closed the component stream"),
+                        Err(e) => warn!("XMPP component stream failed: {}", e),
+                    }
+                    *self.outbound.write().unwrap() = None;
+                    tokio::time::sleep(Duration::from_secs(self.config.reconnect_delay_secs)).await;
+                }
+            };
+            tokio::join!(sessions, self.poll_loop());
+        })
+    }
+
+    async fn run_session(&self) -> Result<()> {
+        let address = format!("{}:{}", self.config.server_host, self.config.server_port);
+        let stream = TcpStream::connect(&address)
+            .await
+            .map_err(|e| SynapseError::ConnectionError(format!("Failed to reach XMPP server {}: {}", address, e)))?;
+        let (read_half, mut write_half) = stream.into_split();
+        let mut reader = Reader::from_reader(BufReader::new(read_half));
+        let mut builder = StanzaBuilder::default();
+        let mut buf = Vec::new();
+
+        // XEP-0114 handshake: open the stream, then prove knowledge of the secret
+        let header = format!(
+            "<stream:stream xmlns='jabber:component:accept' xmlns:stream='http://etherx.jabber.org/streams' to='{}'>",
+            escape_xml(&self.config.component_domain)
+        );
+        write_half.write_all(header.as_bytes()).await?;
+        let stream_id = match read_frame(&mut reader, &mut builder, &mut buf).await? {
+            Some(Frame::StreamOpen(attrs)) => attrs
+                .get("id")
+                .cloned()
+                .ok_or_else(|| SynapseError::AuthenticationError("XMPP stream header has no id".to_string()))?,
+            _ => return Err(SynapseError::AuthenticationError("XMPP server did not open a stream".to_string())),
+        };
+        let digest = ring::digest::digest(
+            &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+            format!("{}{}", stream_id, self.config.secret.expose()).as_bytes(),
+        );
+        let digest: String = digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        write_half.write_all(format!("<handshake>{}</handshake>", digest).as_bytes()).await?;
+        match read_frame(&mut reader, &mut builder, &mut buf).await? {
+            Some(Frame::Stanza(stanza)) if stanza.name == "handshake" => {}
+            _ => {
+                return Err(SynapseError::AuthenticationError(
+                    "XMPP server rejected the component secret".to_string(),
+                ));
+            }
+        }
+        info!("XMPP gateway serving {} via {}", self.config.component_domain, address);
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        *self.outbound.write().unwrap() = Some(tx);
+        let writer = tokio::spawn(async move {
+            while let Some(stanza) = rx.recv().await {
+                if write_half.write_all(stanza.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let result = loop {
+            match read_frame(&mut reader, &mut builder, &mut buf).await {
+                Ok(Some(Frame::Stanza(stanza))) => {
+                    let (messages, replies) = self.translate_stanza(&stanza);
+                    for reply in replies {
+                        self.write(reply);
+                    }
+                    for message in messages {
+                        if let Err(e) = self.endpoint.send(message).await {
+                            warn!("Failed to deliver XMPP message into Synapse: {}", e);
+                        }
+                    }
+                }
+                Ok(Some(Frame::StreamClose)) | Ok(None) => break Ok(()),
+                Ok(Some(Frame::StreamOpen(_))) => break Err(xml_error("nested stream header")),
+                Err(e) => break Err(e),
+            }
+        };
+        writer.abort();
+        result
+    }
+
+    async fn poll_loop(&self) {
+        let mut interval = tokio::time::interval(Duration::from_millis(self.config.poll_interval_ms));
+        loop {
+            interval.tick().await;
+            let messages = match self.endpoint.receive().await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    warn!("Failed to collect messages for the XMPP gateway: {}", e);
+                    continue;
+                }
+            };
+            for message in messages {
+                if let Err(e) = self.handle_synapse_message(&message) {
+                    warn!("Failed to send message from {} over XMPP: {}", message.from_entity, e);
+                }
+            }
+        }
+    }
+
+    fn write(&self, stanza: String) -> bool {
+        match self.outbound.read().unwrap().as_ref() {
+            Some(tx) => tx.send(stanza).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Translate an incoming stanza into Synapse messages and immediate XMPP replies
+    pub fn translate_stanza(&self, stanza: &Stanza) -> (Vec<SimpleMessage>, Vec<String>) {
+        let from = stanza.attr("from").unwrap_or_default();
+        let to = stanza.attr("to").unwrap_or_default();
+        let Some(global_id) = self.global_id_for(to) else {
+            debug!("Ignoring {} stanza addressed to {}", stanza.name, to);
+            return (Vec::new(), Vec::new());
+        };
+        let sender = bare_jid(from).to_string();
+
+        let mut metadata = HashMap::new();
+        metadata.insert(BRIDGE_KEY.to_string(), XMPP_BRIDGE.to_string());
+        metadata.insert(BRIDGE_SENDER_KEY.to_string(), sender.clone());
+
+        match stanza.name.as_str() {
+            "message" => {
+                let kind = stanza.attr("type").unwrap_or("normal");
+                let Some(body) = stanza.child_text("body").filter(|_| matches!(kind, "chat" | "normal")) else {
+                    return (Vec::new(), Vec::new());
+                };
+                if let Some(id) = stanza.attr("id") {
+                    metadata.insert(BRIDGE_EVENT_ID_KEY.to_string(), id.to_string());
+                }
+                if let Some(thread) = stanza.child_text("thread") {
+                    metadata.insert(CONVERSATION_ID_KEY.to_string(), thread.to_string());
+                }
+                let message = SimpleMessage {
+                    to: global_id,
+                    from_entity: self.endpoint.global_id(),
+                    content: body.to_string(),
+                    message_type: MessageType::Direct,
+                    metadata,
+                };
+                (vec![message], Vec::new())
+            }
+            "presence" => {
+                let our_jid = bare_jid(to);
+                match stanza.attr("type") {
+                    Some("subscribe") => {
+                        self.subscribers.write().unwrap().entry(global_id.clone()).or_default().insert(sender.clone());
+                        let mut replies = vec![presence_stanza(our_jid, &sender, Some("subscribed"), None)];
+                        replies.push(self.presence_for(&global_id, our_jid, &sender));
+                        (Vec::new(), replies)
+                    }
+                    Some("unsubscribe") => {
+                        if let Some(subscribers) = self.subscribers.write().unwrap().get_mut(&global_id) {
+                            subscribers.remove(&sender);
+                        }
+                        (Vec::new(), vec![presence_stanza(our_jid, &sender, Some("unsubscribed"), None)])
+                    }
+                    Some("probe") => (Vec::new(), vec![self.presence_for(&global_id, our_jid, &sender)]),
+                    kind @ (None | Some("unavailable")) => {
+                        let status = match kind {
+                            Some(_) => "unavailable",
+                            None => stanza.child_text("show").unwrap_or("available"),
+                        };
+                        metadata.insert(BRIDGE_EVENT_KEY.to_string(), PRESENCE_KEY.to_string());
+                        metadata.insert(PRESENCE_KEY.to_string(), status.to_string());
+                        let message = SimpleMessage {
+                            to: global_id,
+                            from_entity: self.endpoint.global_id(),
+                            content: format!("{} is {}", sender, status),
+                            message_type: MessageType::System,
+                            metadata,
+                        };
+                        (vec![message], Vec::new())
+                    }
+                    _ => (Vec::new(), Vec::new()),
+                }
+            }
+            "iq" if matches!(stanza.attr("type"), Some("get" | "set")) => {
+                let reply = format!(
+                    "<iq type='error' from='{}' to='{}' id='{}'><error type='cancel'>\
+                     <service-unavailable xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></iq>",
+                    escape_xml(to),
+                    escape_xml(from),
+                    escape_xml(stanza.attr("id").unwrap_or_default())
+                );
+                (Vec::new(), vec![reply])
+            }
+            _ => (Vec::new(), Vec::new()),
+        }
+    }
+
+    /// Presence stanza describing a Synapse identity's last published status
+    fn presence_for(&self, global_id: &str, from: &str, to: &str) -> String {
+        match self.presence.read().unwrap().get(global_id).map(String::as_str) {
+            Some("unavailable") | None => presence_stanza(from, to, Some("unavailable"), None),
+            Some("available") => presence_stanza(from, to, None, None),
+            Some(show) => presence_stanza(from, to, None, Some(show)),
+        }
+    }
+
+    /// Send a Synapse identity's message or presence update over XMPP
+    pub fn handle_synapse_message(&self, message: &SimpleMessage) -> Result<()> {
+        let from_jid = self.jid_for(&message.from_entity);
+
+        if message.metadata.get(BRIDGE_EVENT_KEY).map(String::as_str) == Some(PRESENCE_KEY) {
+            let status = message.metadata.get(PRESENCE_KEY).cloned().unwrap_or_else(|| "available".to_string());
+            self.presence.write().unwrap().insert(message.from_entity.clone(), status);
+            let subscribers = self.subscribers.read().unwrap().get(&message.from_entity).cloned().unwrap_or_default();
+            for subscriber in subscribers {
+                self.write(self.presence_for(&message.from_entity, &from_jid, &subscriber));
+            }
+            return Ok(());
+        }
+
+        let stanza = self.render_message(message)?;
+        if self.write(stanza) {
+            Ok(())
+        } else {
+            Err(SynapseError::NotRunning)
+        }
+    }
+
+    /// `<message>` stanza for a Synapse identity's reply
+    pub fn render_message(&self, message: &SimpleMessage) -> Result<String> {
+        let recipient = message
+            .metadata
+            .get(BRIDGE_RECIPIENT_KEY)
+            .or_else(|| message.metadata.get(BRIDGE_SENDER_KEY))
+            .ok_or_else(|| {
+                SynapseError::InvalidMessageFormat(format!(
+                    "Message for the XMPP gateway needs {} or {}",
+                    BRIDGE_RECIPIENT_KEY, BRIDGE_SENDER_KEY
+                ))
+            })?;
+        let thread = message
+            .metadata
+            .get(CONVERSATION_ID_KEY)
+            .map(|thread| format!("<thread>{}</thread>", escape_xml(thread)))
+            .unwrap_or_default();
+        Ok(format!(
+            "<message type='chat' from='{}' to='{}' id='{}'><body>{}</body>{}</message>",
+            escape_xml(&self.jid_for(&message.from_entity)),
+            escape_xml(recipient),
+            uuid::Uuid::new_v4(),
+            escape_xml(&message.content),
+            thread
+        ))
+    }
+}
+
+fn presence_stanza(from: &str, to: &str, kind: Option<&str>, show: Option<&str>) -> String {
+    let kind = kind.map(|k| format!(" type='{}'", k)).unwrap_or_default();
+    match show {
+        Some(show) => format!(
+            "<presence from='{}' to='{}'{}><show>{}</show></presence>",
+            escape_xml(from),
+            escape_xml(to),
+            kind,
+            escape_xml(show)
+        ),
+        None => format!("<presence from='{}' to='{}'{}/>", escape_xml(from), escape_xml(to), kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct NullEndpoint;
+
+    #[async_trait]
+    impl SynapseEndpoint for NullEndpoint {
+        fn global_id(&self) -> String {
+            "xmpp-gateway@example.com".to_string()
+        }
+
+        async fn send(&self, _message: SimpleMessage) -> Result<()> {
+            Ok(())
+        }
+
+        async fn receive(&self) -> Result<Vec<SimpleMessage>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn gateway() -> XmppGateway {
+        XmppGateway::new(
+            XmppGatewayConfig {
+                server_host: "localhost".to_string(),
+                server_port: 5347,
+                component_domain: "synapse.example.com".to_string(),
+                secret: "secret".into(),
+                synapse_domain: "example.com".to_string(),
+                reconnect_delay_secs: 10,
+                poll_interval_ms: 1_000,
+            },
+            Arc::new(NullEndpoint),
+        )
+    }
+
+    #[test]
+    fn identities_map_to_jids_and_back() {
+        let gateway = gateway();
+        assert_eq!(gateway.jid_for("claude@example.com"), "claude@synapse.example.com");
+        assert_eq!(gateway.jid_for("bot@ai.other.org"), "bot\\40ai.other.org@synapse.example.com");
+        assert_eq!(gateway.global_id_for("claude@synapse.example.com/phone").unwrap(), "claude@example.com");
+        assert_eq!(gateway.global_id_for("bot\\40ai.other.org@synapse.example.com").unwrap(), "bot@ai.other.org");
+        assert!(gateway.global_id_for("alice@jabber.org").is_none());
+        assert_eq!(unescape_node("a\\5cb\\zz"), "a\\b\\zz");
+    }
+
+    #[test]
+    fn chat_message_becomes_direct_message() {
+        let gateway = gateway();
+        let stanza = Stanza::parse(
+            "<message type='chat' from='alice@jabber.org/laptop' to='claude@synapse.example.com' id='m1'>\
+             <body>Is the build green &amp; deployed?</body><thread>release-42</thread></message>",
+        )
+        .unwrap();
+
+        let (messages, replies) = gateway.translate_stanza(&stanza);
+        assert!(replies.is_empty());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].to, "claude@example.com");
+        assert_eq!(messages[0].content, "Is the build green & deployed?");
+        assert_eq!(messages[0].metadata[BRIDGE_SENDER_KEY], "alice@jabber.org");
+        assert_eq!(messages[0].metadata[CONVERSATION_ID_KEY], "release-42");
+
+        let mut reply = SimpleMessage::new("xmpp-gateway@example.com", "claude@example.com", "Yes <3");
+        reply.metadata = messages[0].metadata.clone();
+        let xml = gateway.render_message(&reply).unwrap();
+        let parsed = Stanza::parse(&xml).unwrap();
+        assert_eq!(parsed.attr("to"), Some("alice@jabber.org"));
+        assert_eq!(parsed.attr("from"), Some("claude@synapse.example.com"));
+        assert_eq!(parsed.child_text("body"), Some("Yes <3"));
+    }
+
+    #[test]
+    fn subscriptions_are_approved_and_presence_relayed() {
+        let gateway = gateway();
+        let subscribe =
+            Stanza::parse("<presence type='subscribe' from='alice@jabber.org' to='claude@synapse.example.com'/>")
+                .unwrap();
+        let (messages, replies) = gateway.translate_stanza(&subscribe);
+        assert!(messages.is_empty());
+        assert!(replies[0].contains("type='subscribed'"));
+
+        let away = Stanza::parse(
+            "<presence from='alice@jabber.org/x' to='claude@synapse.example.com'><show>away</show></presence>",
+        )
+        .unwrap();
+        let (messages, _) = gateway.translate_stanza(&away);
+        assert_eq!(messages[0].message_type, MessageType::System);
+        assert_eq!(messages[0].metadata[PRESENCE_KEY], "away");
+    }
+}
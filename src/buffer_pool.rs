@@ -0,0 +1,222 @@
+//! # Buffer Pool
+//!
+//! At tens of thousands of messages a second, allocating a fresh receive
+//! buffer per connection and a fresh `Vec` per serialized message shows up in
+//! profiles as allocator churn. Transports instead take a [`PooledBuffer`]
+//! from a [`BufferPool`] and give it back when dropped, already sized for the
+//! next use.
+//!
+//! Buffers that grew past [`BufferPoolConfig::max_buffer_bytes`], e.g. for
+//! one very large message, are freed rather than kept, and at most
+//! [`BufferPoolConfig::max_pooled`] idle buffers are held, so the pool never
+//! pins more than their product. [`BufferPool::stats`] reports how well the
+//! pool is doing; it is part of the router's health report.
+
+use serde::{Deserialize, Serialize};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Sizing of a [`BufferPool`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferPoolConfig {
+    /// Capacity new buffers are allocated with
+    pub buffer_bytes: usize,
+    /// Buffers above this capacity are freed instead of returned
+    pub max_buffer_bytes: usize,
+    /// Idle buffers kept for reuse
+    pub max_pooled: usize,
+}
+
+impl Default for BufferPoolConfig {
+    fn default() -> Self {
+        Self {
+            buffer_bytes: 64 * 1024,
+            max_buffer_bytes: 1024 * 1024,
+            max_pooled: 256,
+        }
+    }
+}
+
+/// Counters of a [`BufferPool`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BufferPoolStats {
+    /// Idle buffers held now
+    pub pooled: usize,
+    /// Bytes of capacity those buffers hold
+    pub pooled_bytes: usize,
+    /// Buffers handed out since startup
+    pub taken: u64,
+    /// Of those, how many had to be allocated
+    pub allocated: u64,
+    /// Buffers freed on return because they were too large or the pool full
+    pub discarded: u64,
+}
+
+impl BufferPoolStats {
+    /// Share of buffers served from the pool rather than the allocator
+    pub fn hit_rate(&self) -> f64 {
+        if self.taken == 0 {
+            return 0.0;
+        }
+        (self.taken - self.allocated) as f64 / self.taken as f64
+    }
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    config: BufferPoolConfig,
+    free: Mutex<Vec<Vec<u8>>>,
+    taken: AtomicU64,
+    allocated: AtomicU64,
+    discarded: AtomicU64,
+}
+
+/// Recycles byte buffers between uses; clones share the same pool
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+}
+
+impl BufferPool {
+    pub fn new(config: BufferPoolConfig) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                config,
+                free: Mutex::new(Vec::new()),
+                taken: AtomicU64::new(0),
+                allocated: AtomicU64::new(0),
+                discarded: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Pool shared by the transports of this process
+    pub fn shared() -> &'static BufferPool {
+        static SHARED: OnceLock<BufferPool> = OnceLock::new();
+        SHARED.get_or_init(|| BufferPool::new(BufferPoolConfig::default()))
+    }
+
+    /// An empty buffer with at least the configured capacity
+    pub fn take(&self) -> PooledBuffer {
+        self.inner.taken.fetch_add(1, Ordering::Relaxed);
+        let recycled = self.inner.free.lock().unwrap().pop();
+        let buffer = recycled.unwrap_or_else(|| {
+            self.inner.allocated.fetch_add(1, Ordering::Relaxed);
+            Vec::with_capacity(self.inner.config.buffer_bytes)
+        });
+        PooledBuffer { buffer, pool: Arc::clone(&self.inner) }
+    }
+
+    /// A buffer of exactly `len` zeroed bytes, e.g. to read into
+    pub fn take_zeroed(&self, len: usize) -> PooledBuffer {
+        let mut buffer = self.take();
+        buffer.resize(len, 0);
+        buffer
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        let free = self.inner.free.lock().unwrap();
+        BufferPoolStats {
+            pooled: free.len(),
+            pooled_bytes: free.iter().map(Vec::capacity).sum(),
+            taken: self.inner.taken.load(Ordering::Relaxed),
+            allocated: self.inner.allocated.load(Ordering::Relaxed),
+            discarded: self.inner.discarded.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A buffer on loan from a [`BufferPool`], returned when dropped
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buffer: Vec<u8>,
+    pool: Arc<PoolInner>,
+}
+
+impl PooledBuffer {
+    /// Keep the bytes, taking the buffer out of the pool for good
+    pub fn into_vec(mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl std::io::Write for PooledBuffer {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        if buffer.capacity() == 0 {
+            return; // taken with into_vec
+        }
+        let config = &self.pool.config;
+        if buffer.capacity() <= config.max_buffer_bytes {
+            let mut free = self.pool.free.lock().unwrap();
+            if free.len() < config.max_pooled {
+                buffer.clear();
+                free.push(buffer);
+                return;
+            }
+        }
+        self.pool.discarded.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn buffers_are_recycled_within_bounds() {
+        let pool = BufferPool::new(BufferPoolConfig { buffer_bytes: 16, max_buffer_bytes: 64, max_pooled: 2 });
+
+        let mut first = pool.take();
+        write!(first, "hello").unwrap();
+        let address = first.as_ptr();
+        drop(first);
+        let second = pool.take();
+        assert!(second.is_empty());
+        assert_eq!(second.as_ptr(), address);
+
+        // Oversized buffers and those beyond `max_pooled` are freed
+        let mut large = pool.take();
+        large.resize(1024, 0);
+        let extra = [pool.take(), pool.take(), pool.take()];
+        drop(large);
+        drop(extra);
+        drop(second);
+        assert_eq!(pool.take_zeroed(8).as_slice(), &[0; 8]);
+        assert_eq!(pool.take().into_vec().capacity(), 16);
+
+        let stats = pool.stats();
+        assert_eq!(stats.pooled, 1);
+        assert_eq!(stats.taken, 8);
+        assert_eq!(stats.allocated, 5);
+        assert_eq!(stats.discarded, 3);
+        assert!((stats.hit_rate() - 3.0 / 8.0).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,446 @@
+//! # Call Signaling
+//!
+//! Synapse does not carry audio or video itself; applications run their own
+//! WebRTC (or similar) media stack. What they need from Synapse is a way to
+//! reach the other participant to set the call up. This module standardizes
+//! that signaling: SDP offers and answers and ICE candidates travel as small
+//! system messages carrying a [`CallSignal`], and a [`CallManager`] tracks the
+//! state of each call and emits [`CallEvent`]s to the application.
+//!
+//! | Signal          | Sent by  | State afterwards        |
+//! |-----------------|----------|-------------------------|
+//! | `invite`        | caller   | ringing                 |
+//! | `ringing`       | callee   | ringing (callee alerted)|
+//! | `accept`        | callee   | accepted                |
+//! | `reject`        | callee   | ended                   |
+//! | `ice_candidate` | either   | unchanged               |
+//! | `hangup`        | either   | ended                   |
+//!
+//! Signals from anyone but the call's peer, and signals that make no sense in
+//! the call's current state, are dropped. Signaling is real-time only and is
+//! never sent by email.
+
+use crate::error::{Result, SynapseError};
+use crate::types::{MessageType, SimpleMessage};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Metadata key marking a message as call signaling
+pub const CALL_SIGNAL_KEY: &str = "call_signal";
+
+/// Kind of media a call carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Audio,
+    Video,
+    /// Screen sharing
+    Screen,
+}
+
+/// An ICE candidate, as produced by the media stack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IceCandidate {
+    pub candidate: String,
+    pub sdp_mid: Option<String>,
+    pub sdp_m_line_index: Option<u16>,
+}
+
+/// A call signaling message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CallSignal {
+    /// Start a call with an SDP offer
+    Invite { call_id: String, media: Vec<MediaKind>, sdp_offer: String },
+    /// The callee is being alerted
+    Ringing { call_id: String },
+    /// The callee answered with an SDP answer
+    Accept { call_id: String, sdp_answer: String },
+    /// The callee declined the call
+    Reject { call_id: String, reason: String },
+    /// A trickled ICE candidate
+    IceCandidate { call_id: String, candidate: IceCandidate },
+    /// Either side ended the call
+    Hangup { call_id: String, reason: String },
+}
+
+impl CallSignal {
+    /// Call the signal belongs to
+    pub fn call_id(&self) -> &str {
+        match self {
+            CallSignal::Invite { call_id, .. }
+            | CallSignal::Ringing { call_id }
+            | CallSignal::Accept { call_id, .. }
+            | CallSignal::Reject { call_id, .. }
+            | CallSignal::IceCandidate { call_id, .. }
+            | CallSignal::Hangup { call_id, .. } => call_id,
+        }
+    }
+
+    /// Wrap the signal in a system message
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> SimpleMessage {
+        let mut metadata = HashMap::new();
+        metadata.insert(CALL_SIGNAL_KEY.to_string(), self.call_id().to_string());
+        SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: serde_json::to_string(self).unwrap_or_default(),
+            message_type: MessageType::System,
+            metadata,
+        }
+    }
+
+    /// Extract the signal from a message, if it carries one
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        message.metadata.get(CALL_SIGNAL_KEY)?;
+        serde_json::from_str(&message.content).ok()
+    }
+}
+
+/// Which side started the call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallDirection {
+    Outgoing,
+    Incoming,
+}
+
+/// Where a call stands
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum CallState {
+    /// Invited but not answered yet
+    Ringing,
+    /// Answered; media may flow
+    Accepted,
+    /// Rejected, hung up or failed
+    Ended { reason: String },
+}
+
+/// A call tracked by the [`CallManager`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Call {
+    pub id: String,
+    /// The other participant
+    pub peer: String,
+    pub direction: CallDirection,
+    pub media: Vec<MediaKind>,
+    pub state: CallState,
+    pub started_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+/// What happened to a call, as seen from the peer's signals
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CallEventKind {
+    /// A peer is calling; answer with the offer fed to the media stack
+    Incoming { media: Vec<MediaKind>, sdp_offer: String },
+    /// The callee is being alerted
+    Ringing,
+    /// The callee answered
+    Accepted { sdp_answer: String },
+    /// The peer sent an ICE candidate
+    IceCandidate { candidate: IceCandidate },
+    /// The call is over
+    Ended { reason: String },
+}
+
+/// A call signal received from a peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallEvent {
+    pub call_id: String,
+    pub peer: String,
+    pub kind: CallEventKind,
+    pub received_at: DateTime<Utc>,
+}
+
+/// Tracks calls and turns signals from peers into [`CallEvent`]s
+#[derive(Debug)]
+pub struct CallManager {
+    calls: DashMap<String, Call>,
+    sender: broadcast::Sender<CallEvent>,
+}
+
+impl Default for CallManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CallManager {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self {
+            calls: DashMap::new(),
+            sender,
+        }
+    }
+
+    /// Subscribe to signals from peers
+    pub fn subscribe(&self) -> broadcast::Receiver<CallEvent> {
+        self.sender.subscribe()
+    }
+
+    /// A call by ID
+    pub fn get(&self, call_id: &str) -> Option<Call> {
+        self.calls.get(call_id).map(|c| c.clone())
+    }
+
+    /// Calls that are ringing or accepted
+    pub fn active(&self) -> Vec<Call> {
+        self.calls
+            .iter()
+            .filter(|c| !matches!(c.state, CallState::Ended { .. }))
+            .map(|c| c.clone())
+            .collect()
+    }
+
+    /// Forget ended calls, returning how many were removed
+    pub fn clear_ended(&self) -> usize {
+        let before = self.calls.len();
+        self.calls.retain(|_, c| !matches!(c.state, CallState::Ended { .. }));
+        before - self.calls.len()
+    }
+
+    /// Start a call to `peer`, returning the invite to send
+    pub fn invite(&self, peer: &str, media: Vec<MediaKind>, sdp_offer: impl Into<String>) -> CallSignal {
+        let call_id = Uuid::new_v4().to_string();
+        self.calls.insert(
+            call_id.clone(),
+            Call {
+                id: call_id.clone(),
+                peer: peer.to_string(),
+                direction: CallDirection::Outgoing,
+                media: media.clone(),
+                state: CallState::Ringing,
+                started_at: Utc::now(),
+                accepted_at: None,
+                ended_at: None,
+            },
+        );
+        CallSignal::Invite { call_id, media, sdp_offer: sdp_offer.into() }
+    }
+
+    /// Answer an incoming call, returning the signal to send
+    pub fn accept(&self, call_id: &str, sdp_answer: impl Into<String>) -> Result<(Call, CallSignal)> {
+        let call = self.transition(call_id, |call| {
+            if call.direction != CallDirection::Incoming || call.state != CallState::Ringing {
+                return false;
+            }
+            call.state = CallState::Accepted;
+            call.accepted_at = Some(Utc::now());
+            true
+        })?;
+        let signal = CallSignal::Accept { call_id: call_id.to_string(), sdp_answer: sdp_answer.into() };
+        Ok((call, signal))
+    }
+
+    /// Decline an incoming call, returning the signal to send
+    pub fn reject(&self, call_id: &str, reason: impl Into<String>) -> Result<(Call, CallSignal)> {
+        let reason = reason.into();
+        let call = self.transition(call_id, |call| {
+            if call.direction != CallDirection::Incoming || call.state != CallState::Ringing {
+                return false;
+            }
+            Self::end(call, &reason);
+            true
+        })?;
+        Ok((call, CallSignal::Reject { call_id: call_id.to_string(), reason }))
+    }
+
+    /// End a ringing or accepted call, returning the signal to send
+    pub fn hangup(&self, call_id: &str, reason: impl Into<String>) -> Result<(Call, CallSignal)> {
+        let reason = reason.into();
+        let call = self.transition(call_id, |call| {
+            if matches!(call.state, CallState::Ended { .. }) {
+                return false;
+            }
+            Self::end(call, &reason);
+            true
+        })?;
+        Ok((call, CallSignal::Hangup { call_id: call_id.to_string(), reason }))
+    }
+
+    /// Wrap a local ICE candidate for a call that is not over
+    pub fn ice_candidate(&self, call_id: &str, candidate: IceCandidate) -> Result<(Call, CallSignal)> {
+        let call = self.transition(call_id, |call| !matches!(call.state, CallState::Ended { .. }))?;
+        Ok((call, CallSignal::IceCandidate { call_id: call_id.to_string(), candidate }))
+    }
+
+    /// Mark a call ended locally, e.g. because its invite could not be delivered
+    pub fn fail(&self, call_id: &str, reason: &str) {
+        if let Some(mut call) = self.calls.get_mut(call_id) {
+            Self::end(&mut *call, reason);
+        }
+    }
+
+    /// Apply a signal from `from`, publishing the resulting event
+    ///
+    /// Returns the signal to send back, if any: `ringing` for a new invite.
+    pub fn receive(&self, from: &str, signal: CallSignal) -> Option<CallSignal> {
+        let call_id = signal.call_id().to_string();
+        let (kind, reply) = match signal {
+            CallSignal::Invite { media, sdp_offer, .. } => {
+                // A repeated invite must not reset a call in progress, or be hijacked
+                if self.calls.contains_key(&call_id) {
+                    return None;
+                }
+                self.calls.insert(
+                    call_id.clone(),
+                    Call {
+                        id: call_id.clone(),
+                        peer: from.to_string(),
+                        direction: CallDirection::Incoming,
+                        media: media.clone(),
+                        state: CallState::Ringing,
+                        started_at: Utc::now(),
+                        accepted_at: None,
+                        ended_at: None,
+                    },
+                );
+                let reply = CallSignal::Ringing { call_id: call_id.clone() };
+                (CallEventKind::Incoming { media, sdp_offer }, Some(reply))
+            }
+            signal => {
+                let mut call = self.calls.get_mut(&call_id)?;
+                if call.peer != from {
+                    return None;
+                }
+                let outgoing_ringing = call.direction == CallDirection::Outgoing && call.state == CallState::Ringing;
+                let kind = match signal {
+                    CallSignal::Ringing { .. } if outgoing_ringing => CallEventKind::Ringing,
+                    CallSignal::Accept { sdp_answer, .. } if outgoing_ringing => {
+                        call.state = CallState::Accepted;
+                        call.accepted_at = Some(Utc::now());
+                        CallEventKind::Accepted { sdp_answer }
+                    }
+                    CallSignal::Reject { reason, .. } if outgoing_ringing => {
+                        Self::end(&mut *call, &reason);
+                        CallEventKind::Ended { reason }
+                    }
+                    CallSignal::IceCandidate { candidate, .. } if !matches!(call.state, CallState::Ended { .. }) => {
+                        CallEventKind::IceCandidate { candidate }
+                    }
+                    CallSignal::Hangup { reason, .. } if !matches!(call.state, CallState::Ended { .. }) => {
+                        Self::end(&mut *call, &reason);
+                        CallEventKind::Ended { reason }
+                    }
+                    _ => return None,
+                };
+                (kind, None)
+            }
+        };
+
+        // No subscribers is fine; the application simply isn't listening
+        let _ = self.sender.send(CallEvent {
+            call_id,
+            peer: from.to_string(),
+            kind,
+            received_at: Utc::now(),
+        });
+        reply
+    }
+
+    fn transition(&self, call_id: &str, apply: impl FnOnce(&mut Call) -> bool) -> Result<Call> {
+        let mut call = self
+            .calls
+            .get_mut(call_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Unknown call: {}", call_id)))?;
+        if !apply(&mut *call) {
+            return Err(SynapseError::ValidationFailed(format!(
+                "Call {} cannot do that while {:?}",
+                call_id, call.state
+            )));
+        }
+        Ok(call.clone())
+    }
+
+    fn end(call: &mut Call, reason: &str) {
+        call.state = CallState::Ended { reason: reason.to_string() };
+        call.ended_at = Some(Utc::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signals_round_trip_through_messages() {
+        let signal = CallSignal::IceCandidate {
+            call_id: "c1".to_string(),
+            candidate: IceCandidate {
+                candidate: "candidate:1 1 UDP 2122252543 192.0.2.4 49203 typ host".to_string(),
+                sdp_mid: Some("0".to_string()),
+                sdp_m_line_index: Some(0),
+            },
+        };
+        let message = signal.to_message("alice@example.com", "bob@example.com");
+        assert_eq!(CallSignal::from_message(&message), Some(signal));
+
+        let ordinary = SimpleMessage::new("bob@example.com", "alice@example.com", "hello");
+        assert_eq!(CallSignal::from_message(&ordinary), None);
+    }
+
+    #[test]
+    fn call_is_set_up_and_torn_down() {
+        let alice = CallManager::new();
+        let bob = CallManager::new();
+        let mut alice_events = alice.subscribe();
+        let mut bob_events = bob.subscribe();
+
+        let invite = alice.invite("bob", vec![MediaKind::Audio], "v=0 offer");
+        let ringing = bob.receive("alice", invite.clone()).unwrap();
+        assert!(matches!(bob_events.try_recv().unwrap().kind, CallEventKind::Incoming { .. }));
+        // A duplicate invite neither resets the call nor rings again
+        assert!(bob.receive("alice", invite.clone()).is_none());
+
+        assert!(alice.receive("bob", ringing).is_none());
+        assert_eq!(alice_events.try_recv().unwrap().kind, CallEventKind::Ringing);
+
+        let (call, accept) = bob.accept(invite.call_id(), "v=0 answer").unwrap();
+        assert_eq!(call.state, CallState::Accepted);
+        alice.receive("bob", accept);
+        assert_eq!(
+            alice_events.try_recv().unwrap().kind,
+            CallEventKind::Accepted { sdp_answer: "v=0 answer".to_string() }
+        );
+        assert_eq!(alice.get(invite.call_id()).unwrap().state, CallState::Accepted);
+
+        let (_, hangup) = alice.hangup(invite.call_id(), "done").unwrap();
+        bob.receive("alice", hangup);
+        assert_eq!(bob_events.try_recv().unwrap().kind, CallEventKind::Ended { reason: "done".to_string() });
+        assert!(alice.active().is_empty() && bob.active().is_empty());
+        assert_eq!(bob.clear_ended(), 1);
+    }
+
+    #[test]
+    fn out_of_place_signals_are_dropped() {
+        let alice = CallManager::new();
+        let mut events = alice.subscribe();
+        let invite = alice.invite("bob", vec![MediaKind::Video], "offer");
+        let call_id = invite.call_id().to_string();
+
+        // Only the peer may answer
+        let accept = CallSignal::Accept { call_id: call_id.clone(), sdp_answer: "x".to_string() };
+        alice.receive("mallory", accept.clone());
+        assert!(events.try_recv().is_err());
+        assert_eq!(alice.get(&call_id).unwrap().state, CallState::Ringing);
+
+        // The caller cannot accept its own call
+        assert!(alice.accept(&call_id, "x").is_err());
+
+        alice.receive("bob", CallSignal::Reject { call_id: call_id.clone(), reason: "busy".to_string() });
+        assert!(matches!(events.try_recv().unwrap().kind, CallEventKind::Ended { .. }));
+        // A late answer to an ended call changes nothing
+        alice.receive("bob", accept);
+        assert!(events.try_recv().is_err());
+        assert!(alice.hangup(&call_id, "bye").is_err());
+    }
+}
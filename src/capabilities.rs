@@ -0,0 +1,161 @@
+//! What this build of Synapse can do
+//!
+//! Most subsystems sit behind cargo features, so two binaries of the same
+//! version can differ widely: one without `email` cannot fall back to email
+//! delivery, one without `database` keeps trust data in memory only. The
+//! [`CapabilityReport`] returned by [`crate::capabilities()`] lists the
+//! compiled features, the transports, storage backends and bridges they make
+//! available, the protocol version spoken and the CPU's crypto acceleration.
+//! It is included in the router's health report and printed by
+//! `synapse-client capabilities`.
+
+use crate::hashing::{HardwareAcceleration, HashAlgorithm};
+use crate::protocol::{ProtocolVersion, PROTOCOL_VERSION};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+macro_rules! compiled_features {
+    ($($name:literal),* $(,)?) => {
+        [$(($name, cfg!(feature = $name))),*]
+    };
+}
+
+/// Compiled features, transports and storage of this binary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityReport {
+    /// Crate version
+    pub version: String,
+    /// Wire protocol version spoken
+    pub protocol_version: ProtocolVersion,
+    /// `native` or `wasm32`
+    pub platform: String,
+    /// Every cargo feature and whether it was compiled in
+    pub features: BTreeMap<String, bool>,
+    /// Transports messages can travel over
+    pub transports: Vec<String>,
+    /// Where state can be persisted
+    pub storage_backends: Vec<String>,
+    /// Bridges to other messaging systems
+    pub bridges: Vec<String>,
+    /// Content hash algorithms, most preferred first
+    #[serde(default)]
+    pub hash_algorithms: Vec<HashAlgorithm>,
+    /// Crypto and SIMD instructions the CPU offers
+    #[serde(default)]
+    pub hardware: HardwareAcceleration,
+}
+
+impl CapabilityReport {
+    /// Report for the running binary
+    pub fn current() -> Self {
+        let native = cfg!(not(target_arch = "wasm32"));
+        let features = compiled_features![
+            "minimal", "core", "crypto", "http", "mdns", "auto_discovery", "cache", "networking",
+            "email", "secure-email", "database", "embedded-db", "auth", "enhanced-auth", "telemetry",
+            "search", "tor", "i2p", "matrix", "xmpp", "windows-service", "native", "wasm",
+        ];
+
+        let mut transports = Vec::new();
+        let mut add = |name: &str, available: bool| {
+            if available {
+                transports.push(name.to_string());
+            }
+        };
+        add("tcp", native);
+        add("udp", native);
+        add("nat_traversal", native);
+        add("http", native && cfg!(feature = "http"));
+        add("mdns", native && cfg!(feature = "mdns"));
+        add("email", native && cfg!(feature = "email"));
+        add("fast_relay", native && cfg!(feature = "http"));
+        add("tor", native && cfg!(feature = "tor"));
+        add("i2p", native && cfg!(feature = "i2p"));
+        add("webrtc", !native && cfg!(feature = "wasm"));
+        add("websocket", !native && cfg!(feature = "wasm"));
+
+        let mut storage_backends = vec!["memory".to_string()];
+        if native {
+            storage_backends.push("filesystem".to_string());
+        }
+        if cfg!(feature = "database") {
+            storage_backends.push("postgres".to_string());
+        }
+        if cfg!(feature = "embedded-db") {
+            storage_backends.push("sqlite".to_string());
+        }
+        if cfg!(feature = "cache") {
+            storage_backends.push("redis".to_string());
+        }
+        if cfg!(feature = "wasm") {
+            storage_backends.push("browser".to_string());
+        }
+
+        let bridges = [("matrix", cfg!(feature = "matrix")), ("xmpp", cfg!(feature = "xmpp"))]
+            .into_iter()
+            .filter(|(_, compiled)| *compiled)
+            .map(|(name, _)| name.to_string())
+            .collect();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            platform: if native { "native" } else { "wasm32" }.to_string(),
+            features: features.into_iter().map(|(name, on)| (name.to_string(), on)).collect(),
+            transports,
+            storage_backends,
+            bridges,
+            hash_algorithms: crate::hashing::PREFERENCE.to_vec(),
+            hardware: HardwareAcceleration::current().clone(),
+        }
+    }
+
+    /// Whether a cargo feature was compiled in
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.get(feature).copied().unwrap_or(false)
+    }
+
+    /// Whether a transport is available, by the names in [`transports`](Self::transports)
+    pub fn supports_transport(&self, transport: &str) -> bool {
+        self.transports.iter().any(|t| t == transport)
+    }
+
+    /// Names of the compiled features
+    pub fn enabled_features(&self) -> Vec<&str> {
+        self.features.iter().filter(|(_, on)| **on).map(|(name, _)| name.as_str()).collect()
+    }
+}
+
+impl std::fmt::Display for CapabilityReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |items: &[String]| if items.is_empty() { "none".to_string() } else { items.join(", ") };
+        writeln!(f, "Synapse {} ({}), protocol v{}", self.version, self.platform, self.protocol_version)?;
+        writeln!(f, "Features: {}", self.enabled_features().join(", "))?;
+        writeln!(f, "Transports: {}", list(&self.transports))?;
+        writeln!(f, "Storage: {}", list(&self.storage_backends))?;
+        writeln!(f, "Bridges: {}", list(&self.bridges))?;
+        let hashes: Vec<String> = self.hash_algorithms.iter().map(HashAlgorithm::to_string).collect();
+        writeln!(f, "Hashes: {}", list(&hashes))?;
+        let hardware: Vec<String> = self.hardware.features().into_iter().map(String::from).collect();
+        write!(f, "Acceleration: {}", list(&hardware))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_matches_the_build() {
+        let report = crate::capabilities();
+        assert_eq!(report.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(report.has_feature("email"), cfg!(feature = "email"));
+        assert_eq!(report.supports_transport("email"), cfg!(feature = "email"));
+        assert_eq!(report.has_feature("tor"), report.supports_transport("tor"));
+        assert!(!report.has_feature("no-such-feature"));
+        assert!(report.storage_backends.contains(&"memory".to_string()));
+        assert!(report.to_string().contains("Transports: "));
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<CapabilityReport>(&json).unwrap(), report);
+    }
+}
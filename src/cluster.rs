@@ -0,0 +1,493 @@
+//! # Router Clustering
+//!
+//! A high-throughput service can run several router processes under one
+//! identity behind a load balancer. Any instance may accept a connection on
+//! any transport, but each peer is *owned* by exactly one instance, chosen by
+//! consistent hashing of the peer's global ID over the configured members
+//! ([`HashRing`]). Messages that arrive at an instance that does not own the
+//! sender are forwarded to the owner, so a conversation's history, indicators
+//! and amendments are always applied in one place.
+//!
+//! Instances coordinate through a [`ClusterStore`] that all of them share:
+//!
+//! - **Nonces**: a message ID is claimed once cluster-wide, so a retry that the
+//!   load balancer sends to a different instance is not delivered twice.
+//! - **Queues**: forwarded messages wait in the owner's queue until it drains it.
+//! - **Sessions**: resumption tickets are exchanged so an instance can resume a
+//!   session another instance established.
+//!
+//! [`MemoryClusterStore`] serves instances within one process (and tests);
+//! [`RedisClusterStore`] (feature `cache`) serves separate processes.
+
+use crate::config::ClusterConfig;
+use crate::error::{Result, SynapseError};
+use crate::history::MESSAGE_ID_KEY;
+use crate::transport::resumption::{SessionCache, SessionTicket};
+use crate::types::SimpleMessage;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Metadata key naming the instance that forwarded a message to its owner
+pub const FORWARDED_BY_KEY: &str = "cluster_forwarded_by";
+
+/// Consistent-hash assignment of peers to cluster members
+///
+/// Each member is placed on the ring at `virtual_nodes` points, so adding or
+/// removing a member only moves the peers adjacent to its points.
+#[derive(Debug, Clone)]
+pub struct HashRing {
+    points: BTreeMap<u64, String>,
+}
+
+impl HashRing {
+    /// Place `members` on the ring
+    pub fn new(members: &[String], virtual_nodes: u32) -> Self {
+        let mut points = BTreeMap::new();
+        for member in members {
+            for replica in 0..virtual_nodes.max(1) {
+                points.insert(Self::hash(&format!("{}#{}", member, replica)), member.clone());
+            }
+        }
+        Self { points }
+    }
+
+    // Stable across processes and builds, unlike the std hasher
+    fn hash(key: &str) -> u64 {
+        let digest = Sha256::digest(key.as_bytes());
+        u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"))
+    }
+
+    /// Member that owns a peer, or `None` for an empty ring
+    pub fn owner(&self, peer: &str) -> Option<&str> {
+        let point = Self::hash(peer);
+        self.points
+            .range(point..)
+            .next()
+            .or_else(|| self.points.iter().next())
+            .map(|(_, member)| member.as_str())
+    }
+
+    /// Whether no members are on the ring
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+}
+
+/// Storage shared by every instance of a cluster
+#[async_trait]
+pub trait ClusterStore: Send + Sync {
+    /// Claim a nonce for `ttl`; false if any instance already claimed it
+    async fn claim_nonce(&self, nonce: &str, ttl: Duration) -> Result<bool>;
+
+    /// Queue a message for the instance that owns its sender
+    async fn forward(&self, instance: &str, message: &SimpleMessage) -> Result<()>;
+
+    /// Take every message queued for an instance, oldest first
+    async fn take_forwarded(&self, instance: &str) -> Result<Vec<SimpleMessage>>;
+
+    /// Share a session ticket, replacing an older one for the same peer
+    async fn put_session(&self, ticket: &SessionTicket) -> Result<()>;
+
+    /// Every shared session ticket
+    async fn sessions(&self) -> Result<Vec<SessionTicket>>;
+}
+
+/// In-memory cluster store, for instances within one process
+#[derive(Default)]
+pub struct MemoryClusterStore {
+    nonces: DashMap<String, Instant>,
+    queues: DashMap<String, Vec<SimpleMessage>>,
+    sessions: DashMap<String, SessionTicket>,
+}
+
+impl MemoryClusterStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ClusterStore for MemoryClusterStore {
+    async fn claim_nonce(&self, nonce: &str, ttl: Duration) -> Result<bool> {
+        let now = Instant::now();
+        self.nonces.retain(|_, expires| *expires > now);
+        match self.nonces.entry(nonce.to_string()) {
+            dashmap::mapref::entry::Entry::Occupied(_) => Ok(false),
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                entry.insert(now + ttl);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn forward(&self, instance: &str, message: &SimpleMessage) -> Result<()> {
+        self.queues.entry(instance.to_string()).or_default().push(message.clone());
+        Ok(())
+    }
+
+    async fn take_forwarded(&self, instance: &str) -> Result<Vec<SimpleMessage>> {
+        Ok(self.queues.remove(instance).map(|(_, queue)| queue).unwrap_or_default())
+    }
+
+    async fn put_session(&self, ticket: &SessionTicket) -> Result<()> {
+        let mut entry = self.sessions.entry(ticket.peer.clone()).or_insert_with(|| ticket.clone());
+        if entry.issued_at < ticket.issued_at {
+            *entry = ticket.clone();
+        }
+        Ok(())
+    }
+
+    async fn sessions(&self) -> Result<Vec<SessionTicket>> {
+        Ok(self.sessions.iter().map(|entry| entry.value().clone()).collect())
+    }
+}
+
+/// Redis-backed cluster store, for instances in separate processes
+#[cfg(feature = "cache")]
+pub struct RedisClusterStore {
+    client: redis::Client,
+    prefix: String,
+}
+
+#[cfg(feature = "cache")]
+impl RedisClusterStore {
+    /// Connect to Redis; keys are namespaced under `prefix`
+    pub fn new(redis_url: &str, prefix: impl Into<String>) -> Result<Self> {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| SynapseError::ConfigurationError(format!("Invalid cluster Redis URL: {}", e)))?;
+        Ok(Self { client, prefix: prefix.into() })
+    }
+
+    fn key(&self, parts: &[&str]) -> String {
+        format!("{}:{}", self.prefix, parts.join(":"))
+    }
+
+    async fn connection(&self) -> Result<redis::aio::MultiplexedConnection> {
+        self.client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(Self::error)
+    }
+
+    fn error(e: redis::RedisError) -> SynapseError {
+        SynapseError::DatabaseError(format!("Cluster store: {}", e))
+    }
+}
+
+#[cfg(feature = "cache")]
+#[async_trait]
+impl ClusterStore for RedisClusterStore {
+    async fn claim_nonce(&self, nonce: &str, ttl: Duration) -> Result<bool> {
+        let mut conn = self.connection().await?;
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(self.key(&["nonce", nonce]))
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        Ok(claimed.is_some())
+    }
+
+    async fn forward(&self, instance: &str, message: &SimpleMessage) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let _: i64 = redis::cmd("RPUSH")
+            .arg(self.key(&["queue", instance]))
+            .arg(serde_json::to_string(message)?)
+            .query_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        Ok(())
+    }
+
+    async fn take_forwarded(&self, instance: &str) -> Result<Vec<SimpleMessage>> {
+        let key = self.key(&["queue", instance]);
+        let mut conn = self.connection().await?;
+        // Read and clear in one transaction so a concurrent forward is not lost
+        let (queued, _): (Vec<String>, i64) = redis::pipe()
+            .atomic()
+            .cmd("LRANGE")
+            .arg(&key)
+            .arg(0)
+            .arg(-1)
+            .cmd("DEL")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        let mut messages = Vec::with_capacity(queued.len());
+        for entry in queued {
+            match serde_json::from_str(&entry) {
+                Ok(message) => messages.push(message),
+                Err(e) => warn!("Dropping unreadable forwarded message: {}", e),
+            }
+        }
+        Ok(messages)
+    }
+
+    async fn put_session(&self, ticket: &SessionTicket) -> Result<()> {
+        let key = self.key(&["sessions"]);
+        let mut conn = self.connection().await?;
+        let existing: Option<String> = redis::cmd("HGET")
+            .arg(&key)
+            .arg(&ticket.peer)
+            .query_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        let newer = existing
+            .and_then(|json| serde_json::from_str::<SessionTicket>(&json).ok())
+            .is_none_or(|existing| existing.issued_at < ticket.issued_at);
+        if newer {
+            let _: i64 = redis::cmd("HSET")
+                .arg(&key)
+                .arg(&ticket.peer)
+                .arg(serde_json::to_string(ticket)?)
+                .query_async(&mut conn)
+                .await
+                .map_err(Self::error)?;
+        }
+        Ok(())
+    }
+
+    async fn sessions(&self) -> Result<Vec<SessionTicket>> {
+        let mut conn = self.connection().await?;
+        let stored: Vec<String> = redis::cmd("HVALS")
+            .arg(self.key(&["sessions"]))
+            .query_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        Ok(stored.iter().filter_map(|json| serde_json::from_str(json).ok()).collect())
+    }
+}
+
+/// This instance's view of the cluster
+pub struct Cluster {
+    instance_id: String,
+    ring: HashRing,
+    store: Arc<dyn ClusterStore>,
+    nonce_ttl: Duration,
+    sync_interval: Duration,
+    /// Tickets already shared or adopted, so a resumed ticket is not adopted again
+    seen_tickets: DashMap<Uuid, DateTime<Utc>>,
+}
+
+impl Cluster {
+    /// Join a cluster through a shared store
+    pub fn new(config: &ClusterConfig, store: Arc<dyn ClusterStore>) -> Result<Self> {
+        if !config.members.contains(&config.instance_id) {
+            return Err(SynapseError::ConfigurationError(format!(
+                "Cluster instance {:?} is not one of the members",
+                config.instance_id
+            )));
+        }
+        Ok(Self {
+            instance_id: config.instance_id.clone(),
+            ring: HashRing::new(&config.members, config.virtual_nodes),
+            store,
+            nonce_ttl: Duration::from_secs(config.nonce_ttl_secs),
+            sync_interval: Duration::from_millis(config.sync_interval_ms.max(1)),
+            seen_tickets: DashMap::new(),
+        })
+    }
+
+    /// Join the cluster described by the configuration
+    ///
+    /// Uses Redis when `redis_url` is set, which requires the `cache` feature.
+    pub fn from_config(config: &ClusterConfig) -> Result<Self> {
+        let store: Arc<dyn ClusterStore> = match &config.redis_url {
+            #[cfg(feature = "cache")]
+            Some(url) => Arc::new(RedisClusterStore::new(url, format!("synapse:cluster:{}", config.name))?),
+            #[cfg(not(feature = "cache"))]
+            Some(_) => {
+                return Err(SynapseError::ConfigurationError(
+                    "A Redis cluster store requires the `cache` feature".to_string(),
+                ))
+            }
+            None => Arc::new(MemoryClusterStore::new()),
+        };
+        Self::new(config, store)
+    }
+
+    /// Member ID of this instance
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Instance that owns a peer
+    pub fn owner(&self, peer: &str) -> &str {
+        self.ring.owner(peer).unwrap_or(&self.instance_id)
+    }
+
+    /// Whether this instance owns a peer
+    pub fn owns(&self, peer: &str) -> bool {
+        self.owner(peer) == self.instance_id
+    }
+
+    /// Store shared with the other instances
+    pub fn store(&self) -> Arc<dyn ClusterStore> {
+        Arc::clone(&self.store)
+    }
+
+    /// Decide where a received message is handled
+    ///
+    /// Returns the message if this instance handles it. Messages already
+    /// handled by any instance are dropped, and messages from peers owned by
+    /// another instance are forwarded to that instance.
+    pub async fn route_inbound(&self, message: SimpleMessage) -> Result<Option<SimpleMessage>> {
+        if let Some(id) = message.metadata.get(MESSAGE_ID_KEY) {
+            if !self.store.claim_nonce(&format!("message:{}", id), self.nonce_ttl).await? {
+                debug!("Dropping message {} already received by another instance", id);
+                return Ok(None);
+            }
+        }
+        let owner = self.owner(&message.from_entity);
+        if owner == self.instance_id || message.metadata.contains_key(FORWARDED_BY_KEY) {
+            return Ok(Some(message));
+        }
+
+        let mut forwarded = message;
+        forwarded.metadata.insert(FORWARDED_BY_KEY.to_string(), self.instance_id.clone());
+        self.store.forward(owner, &forwarded).await?;
+        debug!("Forwarded message from {} to instance {}", forwarded.from_entity, owner);
+        Ok(None)
+    }
+
+    /// Take messages other instances forwarded to this one
+    pub async fn take_forwarded(&self) -> Result<Vec<SimpleMessage>> {
+        let mut messages = self.store.take_forwarded(&self.instance_id).await?;
+        for message in &mut messages {
+            message.metadata.remove(FORWARDED_BY_KEY);
+        }
+        Ok(messages)
+    }
+
+    /// Exchange session tickets with the other instances
+    ///
+    /// Shares this instance's new tickets and adopts newer ones shared by
+    /// others. Each ticket is adopted at most once, so one this instance has
+    /// resumed is not taken back; another instance that adopted it before then
+    /// may still resume it once. Returns how many tickets were adopted.
+    pub async fn sync_sessions(&self, sessions: &SessionCache) -> Result<usize> {
+        let now = Utc::now();
+        self.seen_tickets.retain(|_, expires_at| *expires_at > now);
+        for ticket in sessions.tickets() {
+            if self.seen_tickets.insert(ticket.id, ticket.expires_at).is_none() {
+                self.store.put_session(&ticket).await?;
+            }
+        }
+        let mut adopted = 0;
+        for ticket in self.store.sessions().await? {
+            if !ticket.is_valid_at(now) || self.seen_tickets.insert(ticket.id, ticket.expires_at).is_some() {
+                continue;
+            }
+            if sessions.adopt(ticket) {
+                adopted += 1;
+            }
+        }
+        Ok(adopted)
+    }
+
+    /// Keep session tickets in sync in the background
+    pub fn start(self: Arc<Self>, sessions: Arc<SessionCache>) -> tokio::task::JoinHandle<()> {
+        info!("Cluster instance {} started", self.instance_id);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.sync_interval);
+            loop {
+                interval.tick().await;
+                match self.sync_sessions(&sessions).await {
+                    Ok(0) => {}
+                    Ok(adopted) => debug!("Adopted {} session tickets from the cluster", adopted),
+                    Err(e) => warn!("Could not sync cluster sessions: {}", e),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MessageType;
+    use std::collections::HashMap;
+
+    fn config(instance: &str) -> ClusterConfig {
+        ClusterConfig {
+            enabled: true,
+            instance_id: instance.to_string(),
+            members: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn message(from: &str, id: &str) -> SimpleMessage {
+        SimpleMessage {
+            to: "service@example.com".to_string(),
+            from_entity: from.to_string(),
+            content: "hello".to_string(),
+            message_type: MessageType::Direct,
+            metadata: HashMap::from([(MESSAGE_ID_KEY.to_string(), id.to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_ring_moves_few_peers_when_member_leaves() {
+        let members: Vec<String> = ["a", "b", "c"].iter().map(|m| m.to_string()).collect();
+        let full = HashRing::new(&members, 64);
+        let reduced = HashRing::new(&members[..2], 64);
+
+        let peers: Vec<String> = (0..300).map(|i| format!("peer{}@example.com", i)).collect();
+        for member in &members {
+            let owned = peers.iter().filter(|p| full.owner(p) == Some(member.as_str())).count();
+            assert!(owned > 50, "{} owns only {} of 300 peers", member, owned);
+        }
+        // Only peers owned by the departed member change owner
+        for peer in &peers {
+            if full.owner(peer) != Some("c") {
+                assert_eq!(full.owner(peer), reduced.owner(peer));
+            }
+        }
+        assert!(HashRing::new(&[], 64).owner("peer@example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_messages_reach_owner_once() {
+        let store: Arc<dyn ClusterStore> = Arc::new(MemoryClusterStore::new());
+        let a = Cluster::new(&config("a"), Arc::clone(&store)).unwrap();
+        let b = Cluster::new(&config("b"), Arc::clone(&store)).unwrap();
+        let c = Cluster::new(&config("c"), Arc::clone(&store)).unwrap();
+
+        let peer = (0..100)
+            .map(|i| format!("peer{}@example.com", i))
+            .find(|p| b.owns(p))
+            .unwrap();
+
+        // Received by a non-owner: forwarded, and a retry elsewhere is dropped
+        assert!(a.route_inbound(message(&peer, "m1")).await.unwrap().is_none());
+        assert!(c.route_inbound(message(&peer, "m1")).await.unwrap().is_none());
+        assert!(a.take_forwarded().await.unwrap().is_empty());
+
+        let forwarded = b.take_forwarded().await.unwrap();
+        assert_eq!(forwarded.len(), 1);
+        assert!(!forwarded[0].metadata.contains_key(FORWARDED_BY_KEY));
+        assert!(b.take_forwarded().await.unwrap().is_empty());
+
+        // Received by the owner: handled in place
+        assert!(b.route_inbound(message(&peer, "m2")).await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_instance_must_be_member() {
+        assert!(Cluster::new(&config("d"), Arc::new(MemoryClusterStore::new())).is_err());
+    }
+}
@@ -1,8 +1,11 @@
 //! Configuration management for EMRP
 
+use crate::redaction::Secret;
 use crate::types::{EmailConfig, SmtpConfig, ImapConfig};
 use crate::error::{ConfigError, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
 #[cfg(not(target_arch = "wasm32"))]
 use std::path::Path;
 
@@ -19,6 +22,81 @@ pub struct Config {
     pub security: SecurityConfig,
     /// Logging configuration
     pub logging: LoggingConfig,
+    /// Outbound proxy configuration
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Session resumption for reconnecting to recently used peers
+    #[serde(default)]
+    pub resumption: ResumptionConfig,
+    /// Relay server role (store-and-forward for offline peers)
+    #[serde(default)]
+    pub relay: RelayConfig,
+    /// Throttling of inbound messages from untrusted strangers
+    #[serde(default)]
+    pub admission: AdmissionConfig,
+    /// Minimum sender trust for particular kinds of inbound messages
+    #[serde(default)]
+    pub trust_gate: TrustGateConfig,
+    /// Encryption of data the router keeps on disk
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// Capability probes sent to peers before large sends
+    #[serde(default)]
+    pub probing: CapabilityProbeConfig,
+    /// Addresses and ports the node's listeners bind to
+    #[serde(default)]
+    pub listeners: ListenerConfig,
+    /// Announcements of our new addresses when they change
+    #[serde(default)]
+    pub endpoint_updates: EndpointUpdateConfig,
+    /// Several router instances sharing this identity
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+    /// Mailbox and account storage of the local email server
+    #[serde(default)]
+    pub mail_store: MailStoreConfig,
+    /// Which of several nodes sharing an identity is the active one
+    #[serde(default)]
+    pub election: ElectionConfig,
+    /// Usage metering and quotas for hosted providers
+    #[serde(default)]
+    pub metering: MeteringConfig,
+    /// Byte streams between participants
+    #[serde(default)]
+    pub payload_streams: PayloadStreamConfig,
+    /// Sampling of peer clocks for skew-tolerant timestamp checks
+    #[serde(default)]
+    pub time_sync: TimeSyncConfig,
+    /// Periodic delivery report sent to operators
+    #[serde(default)]
+    pub digest: DigestConfig,
+    /// Connectivity self-test
+    #[serde(default)]
+    pub diagnostics: DiagnosticsConfig,
+    /// Echo service answering other nodes' reachability pings
+    #[serde(default)]
+    pub echo: EchoConfig,
+    /// NAT classification and traversal servers
+    #[serde(default)]
+    pub nat: NatConfig,
+    /// Fast email relays: serving handoffs and collecting our own mail
+    #[serde(default)]
+    pub fast_relay: FastRelayConfig,
+    /// Pacing of outgoing email within the SMTP provider's send quotas
+    #[serde(default)]
+    pub email_sending: EmailSendingConfig,
+    /// Mailing-list style email broadcasts and their suppression list
+    #[serde(default)]
+    pub mailing_list: MailingListConfig,
+    /// Caps on peers, queued bytes, crypto work and connections
+    #[serde(default)]
+    pub limits: ResourceLimitsConfig,
+    /// Worker threads and task budgets of transports, consensus and storage
+    #[serde(default)]
+    pub runtime: RuntimeConfig,
+    /// What the router sets up before it starts and what it leaves until needed
+    #[serde(default)]
+    pub startup: StartupConfig,
 }
 
 /// Entity-specific configuration
@@ -34,6 +112,10 @@ pub struct EntityConfig {
     pub capabilities: Vec<String>,
     /// Display name
     pub display_name: Option<String>,
+    /// Region this node runs in (e.g. "eu-west"), used to prefer nearby
+    /// relays and providers
+    #[serde(default)]
+    pub region: Option<String>,
 }
 
 /// Router configuration
@@ -83,6 +165,1045 @@ pub struct LoggingConfig {
     pub log_message_content: bool,
 }
 
+/// Outbound proxy configuration
+///
+/// Proxy URLs use the `socks5://`, `socks5h://` (DNS resolved by the proxy)
+/// or `http://` (CONNECT) schemes and may carry `user:pass@` credentials.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    /// Proxy used by every outbound transport without its own entry
+    pub global: Option<String>,
+    /// Per-transport overrides keyed by transport name (tcp, http, websocket, email)
+    #[serde(default)]
+    pub transports: HashMap<String, String>,
+    /// Hosts that are always reached directly (a leading `.` matches subdomains)
+    #[serde(default)]
+    pub bypass: Vec<String>,
+}
+
+/// Session resumption configuration
+///
+/// Resumed sessions reuse the last working route to a peer (and any TLS/QUIC
+/// session ticket the transport stored) instead of rediscovering it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResumptionConfig {
+    /// Reuse recently negotiated sessions when reconnecting
+    pub enabled: bool,
+    /// How long a session ticket stays valid, in seconds
+    pub lifetime_secs: u64,
+    /// Send the first message of a resumed session immediately (0-RTT)
+    pub early_data: bool,
+    /// How long early-data message IDs are remembered to reject replays, in seconds
+    pub replay_window_secs: u64,
+    /// File session tickets are persisted to, so they survive restarts
+    pub ticket_store: Option<String>,
+    /// Maximum number of peers to keep tickets for
+    pub max_tickets: usize,
+}
+
+impl Default for ResumptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            lifetime_secs: 600,
+            early_data: true,
+            replay_window_secs: 30,
+            ticket_store: None,
+            max_tickets: 256,
+        }
+    }
+}
+
+/// Relay server configuration
+///
+/// A relay accepts messages for registered peers that are offline, keeps them
+/// encrypted at rest and hands them over once the peer reconnects and proves
+/// its identity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayConfig {
+    /// Run this node as a relay
+    pub enabled: bool,
+    /// Largest single message accepted, in bytes
+    pub max_message_bytes: u64,
+    /// Default number of messages held per peer
+    pub default_quota_messages: u64,
+    /// Default number of bytes held per peer
+    pub default_quota_bytes: u64,
+    /// Default time messages are kept before being discarded, in seconds
+    pub retention_secs: u64,
+    /// Time a peer has to answer an authentication challenge, in seconds
+    pub challenge_ttl_secs: u64,
+    /// Lifetime of an authenticated delivery session, in seconds
+    pub session_ttl_secs: u64,
+    /// Interval between sweeps for expired messages, in seconds
+    pub prune_interval_secs: u64,
+    /// Minimum time between push wake-ups sent to the same peer, in seconds
+    pub wake_interval_secs: u64,
+    /// Region held messages are stored in, checked against data-residency
+    /// rules; defaults to the entity's region
+    pub region: Option<String>,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_message_bytes: 1024 * 1024,
+            default_quota_messages: 1000,
+            default_quota_bytes: 64 * 1024 * 1024,
+            retention_secs: 7 * 24 * 3600,
+            challenge_ttl_secs: 60,
+            session_ttl_secs: 3600,
+            prune_interval_secs: 300,
+            wake_interval_secs: 30,
+            region: None,
+        }
+    }
+}
+
+/// Inbound admission control for messages from strangers
+///
+/// Senders who are not contacts and whose network trust is below the
+/// threshold share a small per-sender and global message budget. Messages
+/// beyond it must carry a proof-of-work stamp; otherwise they are held as
+/// contact requests for the owner to approve.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdmissionConfig {
+    /// Apply admission control to inbound messages
+    pub enabled: bool,
+    /// Network trust score (0-100) at or above which senders are never throttled
+    pub trust_threshold: f64,
+    /// Messages per minute allowed from each stranger
+    pub stranger_rate_per_minute: u32,
+    /// Messages a stranger may send in a burst
+    pub stranger_burst: u32,
+    /// Messages per minute allowed from all strangers together
+    pub global_stranger_rate_per_minute: u32,
+    /// Leading zero bits required in a proof-of-work stamp (0 disables stamps)
+    pub pow_difficulty_bits: u8,
+    /// How long a stamp stays valid after it was minted, in seconds
+    pub stamp_max_age_secs: u64,
+    /// Held contact requests kept per sender
+    pub max_held_per_sender: usize,
+    /// Senders with held contact requests kept at once
+    pub max_pending_senders: usize,
+    /// How long cached trust scores are reused, in seconds
+    pub trust_cache_secs: u64,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            trust_threshold: 20.0,
+            stranger_rate_per_minute: 5,
+            stranger_burst: 10,
+            global_stranger_rate_per_minute: 600,
+            pow_difficulty_bits: 20,
+            stamp_max_age_secs: 3600,
+            max_held_per_sender: 3,
+            max_pending_senders: 1000,
+            trust_cache_secs: 300,
+        }
+    }
+}
+
+/// Minimum sender trust for particular kinds of inbound messages
+///
+/// A message matching a requirement is only delivered if its sender's trust
+/// meets every threshold the requirement sets; otherwise it is dropped and
+/// the sender told which requirement it missed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrustGateConfig {
+    /// Enforce the requirements on inbound messages
+    pub enabled: bool,
+    pub requirements: Vec<TrustRequirement>,
+    /// Tell rejected senders why, at most once a minute per sender
+    pub notify_senders: bool,
+    /// How long looked-up trust scores are reused, in seconds
+    pub trust_cache_secs: u64,
+}
+
+impl Default for TrustGateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            requirements: Vec::new(),
+            notify_senders: true,
+            trust_cache_secs: 300,
+        }
+    }
+}
+
+/// Trust a sender needs for messages of one kind
+///
+/// A requirement applies to messages matching both its message type and its
+/// action (the `action` metadata entry, e.g. `llm_task` or `file_transfer`);
+/// leave either unset to match any.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrustRequirement {
+    pub message_type: Option<crate::types::MessageType>,
+    pub action: Option<String>,
+    /// Network trust score (0-100) the sender needs
+    pub min_network_trust: Option<f64>,
+    /// Trust the recipient has in the sender directly (0-100)
+    pub min_entity_trust: Option<f64>,
+}
+
+/// Local storage configuration
+///
+/// Stores are encrypted with a key derived from the node's private key.
+/// Files written before encryption was enabled are read once and rewritten
+/// encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Encrypt the conversation history and session ticket stores
+    pub encrypt_at_rest: bool,
+    /// File the conversation history is kept in across restarts
+    pub history_store: Option<String>,
+    /// Region the history store is in, checked against data-residency rules;
+    /// defaults to the entity's region
+    pub region: Option<String>,
+    /// Directory of the full-text index over the history; requires the
+    /// `search` feature. The index is not encrypted.
+    pub search_index: Option<String>,
+    /// PostgreSQL database of the participant registry and trust services;
+    /// requires the `database` feature
+    pub database_url: Option<String>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            encrypt_at_rest: true,
+            history_store: None,
+            region: None,
+            search_index: None,
+            database_url: None,
+        }
+    }
+}
+
+/// Capability probe configuration
+///
+/// Before a send of at least `large_message_bytes` to a peer whose transport
+/// limits are unknown, the router asks the peer what it accepts, so the payload
+/// is not committed to a transport that will refuse it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CapabilityProbeConfig {
+    /// Probe peers before large sends
+    pub enabled: bool,
+    /// Payload size, in bytes, from which a send first probes the peer
+    pub large_message_bytes: usize,
+    /// How long to wait for the peer's answer, in milliseconds
+    pub timeout_ms: u64,
+    /// How long an answer is trusted, in seconds
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for CapabilityProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            large_message_bytes: 64 * 1024,
+            timeout_ms: 2_000,
+            cache_ttl_secs: 600,
+        }
+    }
+}
+
+/// Peer clock sampling
+///
+/// The router can ask a peer for its clock and measure the offset from ours.
+/// Known offsets are removed from that peer's timestamps before replay and
+/// early-data windows are applied; offsets beyond `warn_skew_ms` are logged
+/// and published as `RouterEvent::ClockSkewDetected`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeSyncConfig {
+    /// Answer and send clock samples
+    pub enabled: bool,
+    /// How long to wait for a peer's answer, in milliseconds
+    pub timeout_ms: u64,
+    /// Samples kept per peer; the one with the shortest round trip is used
+    pub max_samples: usize,
+    /// Offset, in milliseconds, from which a peer's clock is reported as skewed
+    pub warn_skew_ms: u64,
+}
+
+impl Default for TimeSyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_ms: 2_000,
+            max_samples: 8,
+            warn_skew_ms: 2_000,
+        }
+    }
+}
+
+/// NAT behavior discovery
+///
+/// Our NAT is classified against `stun_servers` (RFC 5780 tests need a server
+/// that reports an alternate address), cached for `cache_ttl_secs`, and
+/// advertised to peers so traversal can pick hole punching, TURN or a relay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NatConfig {
+    /// STUN servers tried in order, as "host:port"
+    pub stun_servers: Vec<String>,
+    /// TURN servers for pairs of NATs hole punching cannot open
+    pub turn_servers: Vec<TurnServer>,
+    /// How long to wait for each STUN answer, in milliseconds
+    pub timeout_ms: u64,
+    /// How long a classification is reused
+    pub cache_ttl_secs: u64,
+    /// Quiet time after which a NAT-traversed route gets a keepalive
+    pub keepalive_interval_secs: u64,
+    /// Time without traffic after which a route stops getting keepalives
+    pub keepalive_idle_secs: u64,
+}
+
+impl Default for NatConfig {
+    fn default() -> Self {
+        Self {
+            stun_servers: vec![
+                "stun.stunprotocol.org:3478".to_string(),
+                "stun.l.google.com:19302".to_string(),
+            ],
+            turn_servers: Vec::new(),
+            timeout_ms: 2_000,
+            cache_ttl_secs: 3_600,
+            keepalive_interval_secs: 25,
+            keepalive_idle_secs: 300,
+        }
+    }
+}
+
+/// TURN server configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnServer {
+    pub server: String,
+    pub username: String,
+    pub password: Secret<String>,
+    /// Region the server runs in, e.g. "eu-west"
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// Echo service role
+///
+/// An echo node reflects signed pings with the address and transport they
+/// arrived from, for other nodes' diagnostics and NAT detection. Pinging an
+/// echo node works whether or not this node runs the service itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EchoConfig {
+    /// Answer other nodes' pings
+    pub enabled: bool,
+    /// Pings answered per peer per minute; more are dropped
+    pub max_pings_per_minute: u32,
+    /// How long to wait for a reflection, in milliseconds
+    pub timeout_ms: u64,
+}
+
+impl Default for EchoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_pings_per_minute: 60,
+            timeout_ms: 5_000,
+        }
+    }
+}
+
+/// Fast email relay
+///
+/// A fast relay takes messages meant for its registered peers over an HTTP
+/// handoff or SMTP submission, holds them in the relay store and wakes the
+/// recipient straight away, instead of waiting for the recipient's next mail
+/// poll. Nodes list the relays holding their own mail in `home_relays`; these
+/// are advertised in capability probes so senders hand off to them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FastRelayConfig {
+    /// Accept handoffs for this node's relay peers (needs `relay.enabled`)
+    pub serve: bool,
+    /// Address the HTTP handoff listener binds to
+    pub http_listen: String,
+    /// Relays holding mail for this node
+    pub home_relays: Vec<EmailRelayEndpoint>,
+    /// How long a handoff may take before plain email is used, in milliseconds
+    pub handoff_timeout_ms: u64,
+    /// Minimum time between collections from the home relays, in seconds
+    pub collect_interval_secs: u64,
+}
+
+impl Default for FastRelayConfig {
+    fn default() -> Self {
+        Self {
+            serve: false,
+            http_listen: "0.0.0.0:8825".to_string(),
+            home_relays: Vec::new(),
+            handoff_timeout_ms: 5_000,
+            collect_interval_secs: 5,
+        }
+    }
+}
+
+/// How to reach a fast email relay, as advertised to senders
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailRelayEndpoint {
+    /// The relay's global ID
+    pub relay: String,
+    /// Base URL of its HTTP handoff API, e.g. "https://relay.example.com:8825"
+    #[serde(default)]
+    pub handoff_url: Option<String>,
+    /// Its SMTP submission endpoint as "host:port", used when HTTP fails
+    #[serde(default)]
+    pub smtp_endpoint: Option<String>,
+    /// Delivery latency the relay aims for, in milliseconds
+    #[serde(default = "default_relay_latency_ms")]
+    pub latency_target_ms: u32,
+    /// Largest message the relay accepts, in bytes
+    #[serde(default = "default_relay_max_message_bytes")]
+    pub max_message_bytes: u64,
+}
+
+fn default_relay_latency_ms() -> u32 {
+    2_000
+}
+
+fn default_relay_max_message_bytes() -> u64 {
+    RelayConfig::default().max_message_bytes
+}
+
+/// Mail provider whose send quotas apply to the SMTP account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailProvider {
+    /// Consumer Gmail account
+    Gmail,
+    /// Google Workspace account or its SMTP relay
+    GoogleWorkspace,
+    /// Amazon SES, production access
+    AmazonSes,
+    /// Outlook.com or Microsoft 365
+    Outlook,
+    /// Any other server; only the concurrency cap applies
+    Custom,
+}
+
+/// Pacing of outgoing email
+///
+/// Providers suspend accounts that exceed their send quotas, so sends are
+/// queued and released no faster than the provider's limits allow. When
+/// `provider` is unset it is detected from the SMTP host; the `per_minute`,
+/// `per_day` and `max_concurrent` overrides replace the provider's values.
+/// Sends the server defers with a 4xx reply are retried with exponential
+/// backoff, pausing the whole queue meanwhile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailSendingConfig {
+    /// Provider whose limits apply; detected from the SMTP host when unset
+    pub provider: Option<EmailProvider>,
+    /// Messages per minute
+    pub per_minute: Option<u32>,
+    /// Messages per rolling 24 hours
+    pub per_day: Option<u32>,
+    /// Sends in flight at once
+    pub max_concurrent: Option<usize>,
+    /// Retries of a deferred send before it fails
+    pub max_retries: u32,
+    /// First backoff after a deferral, in milliseconds
+    pub backoff_initial_ms: u64,
+    /// Longest backoff, in milliseconds
+    pub backoff_max_ms: u64,
+}
+
+impl Default for EmailSendingConfig {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            per_minute: None,
+            per_day: None,
+            max_concurrent: None,
+            max_retries: 4,
+            backoff_initial_ms: 2_000,
+            backoff_max_ms: 120_000,
+        }
+    }
+}
+
+/// Email broadcasts to many recipients
+///
+/// Recipients without a known key are sent one message per `batch_size`
+/// addresses in Bcc. Mail to `unsubscribe_address` (an email gateway address)
+/// unsubscribes its sender; unsubscribed, bounced and complaining addresses
+/// are kept in `suppression_file` and skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MailingListConfig {
+    /// Recipients per broadcast message
+    pub batch_size: usize,
+    /// `List-Id` header value, e.g. "announcements.example.com"
+    pub list_id: Option<String>,
+    /// Address receiving unsubscribe mail, advertised in `List-Unsubscribe`
+    pub unsubscribe_address: Option<String>,
+    /// One-click unsubscribe URL, advertised in `List-Unsubscribe`
+    pub unsubscribe_url: Option<String>,
+    /// JSON file the suppression list is kept in; in memory when unset
+    pub suppression_file: Option<String>,
+}
+
+impl Default for MailingListConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 50,
+            list_id: None,
+            unsubscribe_address: None,
+            unsubscribe_url: None,
+            suppression_file: None,
+        }
+    }
+}
+
+/// What happens when a new peer arrives with the peer table full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerEviction {
+    /// Forget the peer heard from least recently to make room
+    #[default]
+    LeastRecentlyUsed,
+    /// Turn the new peer away; known peers are never forgotten
+    RejectNew,
+}
+
+/// Resource limits protecting small hosts
+///
+/// Peers are the participants the node keeps routing, capability and session
+/// state for; past `max_peers` one is evicted or the newcomer refused.
+/// Received messages waiting to be read count against `max_queued_bytes`,
+/// and messages that would exceed it are dropped. Every limit hit is counted
+/// and published as `RouterEvent::ResourceLimitHit`. A limit of 0 means
+/// unlimited, except `max_concurrent_crypto`, where it means one per CPU.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimitsConfig {
+    /// Peers tracked at once
+    pub max_peers: usize,
+    /// What to do with a new peer when `max_peers` are tracked
+    pub peer_eviction: PeerEviction,
+    /// Bytes of received messages waiting to be read
+    pub max_queued_bytes: u64,
+    /// Encryptions, decryptions, signatures and verifications run at once
+    pub max_concurrent_crypto: usize,
+    /// Inbound connections open at once
+    pub max_connections: usize,
+}
+
+impl ResourceLimitsConfig {
+    /// Limits for a Raspberry Pi class device with about 1 GB of memory
+    pub fn small_host() -> Self {
+        Self {
+            max_peers: 500,
+            peer_eviction: PeerEviction::LeastRecentlyUsed,
+            max_queued_bytes: 16 * 1024 * 1024,
+            max_concurrent_crypto: 2,
+            max_connections: 64,
+        }
+    }
+}
+
+impl Default for ResourceLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_peers: 10_000,
+            peer_eviction: PeerEviction::LeastRecentlyUsed,
+            max_queued_bytes: 256 * 1024 * 1024,
+            max_concurrent_crypto: 0,
+            max_connections: 1024,
+        }
+    }
+}
+
+/// Threads and task budget of one subsystem
+///
+/// With `worker_threads` 0 the subsystem's tasks run on the main runtime;
+/// otherwise it gets a runtime of its own with that many threads, so its work
+/// cannot starve the others. `max_tasks` caps how many of its tasks run at
+/// once; further tasks wait for a turn. 0 means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubsystemRuntimeConfig {
+    /// Threads of a dedicated runtime, or 0 to share the main runtime
+    pub worker_threads: usize,
+    /// Tasks of the subsystem run at once
+    pub max_tasks: usize,
+}
+
+/// How the node's async work is spread over runtimes
+///
+/// Everything shares one runtime by default. High-throughput deployments
+/// give transports, consensus and storage their own, so that producing
+/// blocks or a slow database cannot hold up message delivery.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// Worker threads of the main runtime, or 0 for one per CPU
+    pub main_worker_threads: usize,
+    /// Listeners, connections and message delivery
+    pub transports: SubsystemRuntimeConfig,
+    /// Blockchain block production and snapshots
+    pub consensus: SubsystemRuntimeConfig,
+    /// Trust decay and other database maintenance
+    pub storage: SubsystemRuntimeConfig,
+}
+
+/// When the local SMTP/IMAP server is set up
+///
+/// Setting it up assesses connectivity first, binding the mail ports and
+/// looking up the external address, which can take seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailServerStartup {
+    /// While the router is constructed, alongside the transports
+    Eager,
+    /// On first use: `EnhancedSynapseRouter::ensure_email_server`, or at
+    /// start when serving fast relay handoffs
+    #[default]
+    Lazy,
+    /// Never; email goes through the configured provider only
+    Disabled,
+}
+
+impl std::str::FromStr for EmailServerStartup {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "eager" => Ok(Self::Eager),
+            "lazy" => Ok(Self::Lazy),
+            "disabled" => Ok(Self::Disabled),
+            other => Err(format!("unknown email server startup {:?}", other)),
+        }
+    }
+}
+
+/// Router startup
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartupConfig {
+    /// When the local email server is set up
+    pub email_server: EmailServerStartup,
+}
+
+/// Delivery digest for operators
+///
+/// Summarizes, per period, what was delivered to and dead-lettered for each
+/// peer, transport flaps and notable trust-score changes. Operators receive
+/// it as a Synapse message (`recipients`) or as plain email
+/// (`email_recipients`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DigestConfig {
+    /// Send digests from `EnhancedSynapseRouter::spawn_delivery_digest`
+    pub enabled: bool,
+    /// Length of a digest period in seconds (3600 hourly, 86400 daily)
+    pub interval_secs: u64,
+    /// Participants the digest is sent to as a Synapse message
+    pub recipients: Vec<String>,
+    /// Addresses the digest is mailed to as plain text
+    pub email_recipients: Vec<String>,
+    /// Smallest trust-score movement reported
+    pub trust_change_threshold: f64,
+    /// Peers listed, busiest first
+    pub max_peers: usize,
+    /// Skip periods with no traffic, flaps or trust changes
+    pub skip_empty: bool,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 86_400,
+            recipients: Vec::new(),
+            email_recipients: Vec::new(),
+            trust_change_threshold: 5.0,
+            max_peers: 20,
+            skip_empty: true,
+        }
+    }
+}
+
+/// Connectivity self-test run by `EnhancedSynapseRouter::run_diagnostics`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticsConfig {
+    /// Public participant that answers probes, used to test a full round trip
+    pub echo_peer: Option<String>,
+    /// How long each network check may take, in milliseconds
+    pub timeout_ms: u64,
+    /// DKIM selector of the sending domain, to check its key is published
+    pub dkim_selector: Option<String>,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            echo_peer: None,
+            timeout_ms: 5_000,
+            dkim_selector: None,
+        }
+    }
+}
+
+/// Ports tried on peers whose address carries no port
+pub const DEFAULT_PEER_PORTS: &[u16] = &[8080, 8443, 9090, 7777];
+
+/// Where a single listener binds
+///
+/// `port` is tried first, then every port in `port_range`. Port 0 lets the
+/// operating system pick an ephemeral port; the port actually bound is what
+/// gets advertised to peers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListenerBinding {
+    /// Interface address to bind (`0.0.0.0` for all IPv4, `::` for all IPv6)
+    pub address: IpAddr,
+    /// Preferred port (0 for an ephemeral port)
+    pub port: u16,
+    /// Inclusive range of ports to fall back to when `port` is taken
+    pub port_range: Option<(u16, u16)>,
+    /// Also accept IPv4 connections on an IPv6 wildcard address
+    pub dual_stack: bool,
+}
+
+impl ListenerBinding {
+    /// Bind all IPv4 interfaces on `port`
+    pub fn any(port: u16) -> Self {
+        Self {
+            port,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for ListenerBinding {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 0,
+            port_range: None,
+            dual_stack: true,
+        }
+    }
+}
+
+/// Listener configuration for every transport and server that accepts connections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListenerConfig {
+    /// Direct TCP transport
+    pub tcp: ListenerBinding,
+    /// UDP transport
+    pub udp: ListenerBinding,
+    /// SMTP server
+    pub smtp: ListenerBinding,
+    /// IMAP server
+    pub imap: ListenerBinding,
+    /// Ports tried, in order, when a peer's address carries no port
+    pub peer_ports: Vec<u16>,
+    /// Host advertised to peers for wildcard bindings (detected when unset)
+    pub advertise_host: Option<String>,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            tcp: ListenerBinding::any(8080),
+            udp: ListenerBinding::any(8765),
+            smtp: ListenerBinding::any(2525),
+            imap: ListenerBinding::any(1143),
+            peer_ports: DEFAULT_PEER_PORTS.to_vec(),
+            advertise_host: None,
+        }
+    }
+}
+
+/// Endpoint update configuration
+///
+/// When the addresses our listeners are reachable at change, a signed update
+/// is sent to everyone we exchanged messages with recently and published to
+/// the registered discovery backends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointUpdateConfig {
+    /// Announce address changes
+    pub enabled: bool,
+    /// How often the endpoint monitor checks our addresses, in seconds
+    pub check_interval_secs: u64,
+    /// Peers exchanged messages with this recently are told directly, in days
+    pub recent_peer_days: u32,
+}
+
+impl Default for EndpointUpdateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_interval_secs: 60,
+            recent_peer_days: 30,
+        }
+    }
+}
+
+/// Clustering of router instances that share one identity
+///
+/// Every instance lists the same `members` and its own `instance_id`; peers
+/// are assigned to members by consistent hashing, and instances coordinate
+/// through Redis at `redis_url` (or in memory when unset, for instances
+/// within one process).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    /// Run as one instance of a cluster
+    pub enabled: bool,
+    /// Cluster name, namespacing the shared store
+    pub name: String,
+    /// This instance's member ID
+    pub instance_id: String,
+    /// Member IDs of every instance, including this one
+    pub members: Vec<String>,
+    /// Points per member on the hash ring; more spreads peers more evenly
+    pub virtual_nodes: u32,
+    /// Redis server shared by the instances
+    pub redis_url: Option<String>,
+    /// How long a received message ID is remembered cluster-wide, in seconds
+    pub nonce_ttl_secs: u64,
+    /// How often session tickets are exchanged, in milliseconds
+    pub sync_interval_ms: u64,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: "synapse".to_string(),
+            instance_id: String::new(),
+            members: Vec::new(),
+            virtual_nodes: 64,
+            redis_url: None,
+            nonce_ttl_secs: 600,
+            sync_interval_ms: 1000,
+        }
+    }
+}
+
+/// Lease-based leader election between active and standby relay nodes
+///
+/// Nodes sharing an identity campaign for the lease `name` in a store they
+/// share: Redis at `redis_url`, or memory when unset, for nodes within one
+/// process. Only the holder serves relay handoffs and deliveries; a standby
+/// takes over once the holder has not renewed for `lease_ttl_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ElectionConfig {
+    /// Campaign for the lease instead of serving unconditionally
+    pub enabled: bool,
+    /// Lease the nodes compete for; nodes serving one identity share it
+    pub name: String,
+    /// This node's candidate ID; a random one when empty
+    pub instance_id: String,
+    /// Redis server shared by the candidates
+    pub redis_url: Option<String>,
+    /// How long a lease lasts without renewal, in milliseconds
+    pub lease_ttl_ms: u64,
+    /// How often the holder renews and standbys try to take over, in
+    /// milliseconds; well below `lease_ttl_ms`
+    pub renew_interval_ms: u64,
+}
+
+impl Default for ElectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: "synapse-relay".to_string(),
+            instance_id: String::new(),
+            redis_url: None,
+            lease_ttl_ms: 10_000,
+            renew_interval_ms: 3_000,
+        }
+    }
+}
+
+/// Per-participant usage limits within a metering period; `None` is unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageQuota {
+    /// Messages sent or relayed
+    pub messages: Option<u64>,
+    /// Bytes of the messages sent or relayed
+    pub bytes: Option<u64>,
+    /// Bytes the relay holds for the participant at once
+    pub storage_bytes: Option<u64>,
+    /// Minutes spent in relay delivery sessions
+    pub relay_minutes: Option<u64>,
+}
+
+/// Usage metering for hosted Synapse providers
+///
+/// Counts, per participant, the messages and bytes it sends or has relayed,
+/// the bytes the relay holds for it and its relay delivery-session minutes.
+/// Every `period_secs` the counts are exported to `csv_path` and
+/// `webhook_url`, then start again from zero.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeteringConfig {
+    /// Meter usage and enforce quotas
+    pub enabled: bool,
+    /// Length of a metering period, in seconds
+    pub period_secs: u64,
+    /// Quota of participants without one of their own
+    pub default_quota: UsageQuota,
+    /// File each period's usage is appended to as CSV
+    pub csv_path: Option<String>,
+    /// URL each period's usage is POSTed to as JSON
+    pub webhook_url: Option<String>,
+    /// Shared secret signing webhook exports like message webhooks; unsigned
+    /// when unset
+    pub webhook_secret: Option<Secret<String>>,
+}
+
+impl Default for MeteringConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            period_secs: 3600,
+            default_quota: UsageQuota::default(),
+            csv_path: None,
+            webhook_url: None,
+            webhook_secret: None,
+        }
+    }
+}
+
+/// Mailbox and account storage of the local email server
+///
+/// With `redis_url` set, several email server instances share mailboxes,
+/// accounts and IMAP IDLE leases, so SMTP and IMAP can run active-active
+/// behind a load balancer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MailStoreConfig {
+    /// Redis server shared by the instances; in memory when unset
+    pub redis_url: Option<String>,
+    /// Store name, namespacing the shared keys
+    pub name: String,
+    /// This server instance, named in the IDLE leases it holds
+    pub instance_id: String,
+    /// How long an IDLE lease lasts without renewal, in seconds
+    pub idle_lease_secs: u64,
+    /// IDLE sessions a user may hold open across all instances
+    pub max_idle_sessions_per_user: usize,
+    /// How often IDLE sessions check their mailbox for new mail, in milliseconds
+    pub idle_poll_ms: u64,
+}
+
+impl Default for MailStoreConfig {
+    fn default() -> Self {
+        Self {
+            redis_url: None,
+            name: "synapse".to_string(),
+            instance_id: "local".to_string(),
+            idle_lease_secs: 60,
+            max_idle_sessions_per_user: 10,
+            idle_poll_ms: 1000,
+        }
+    }
+}
+
+/// Limits of the byte streams opened with `open_stream`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PayloadStreamConfig {
+    /// Largest payload carried by one chunk, in bytes
+    pub chunk_size: usize,
+    /// Bytes a writer may send ahead of what the peer has read
+    pub window: usize,
+    /// Streams that may be open at once, in both directions
+    pub max_streams: usize,
+}
+
+impl Default for PayloadStreamConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 16 * 1024,
+            window: 256 * 1024,
+            max_streams: 256,
+        }
+    }
+}
+
+/// Prefix of the environment variables read by [`Config::apply_env`]
+pub const ENV_PREFIX: &str = "SYNAPSE_";
+
+/// `SYNAPSE_*` variables, each also readable from the file `SYNAPSE_*_FILE` names
+#[cfg(not(target_arch = "wasm32"))]
+struct EnvVars<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl EnvVars<'_> {
+    fn string(&self, name: &str) -> Result<Option<String>> {
+        let var = format!("{}{}", ENV_PREFIX, name);
+        if let Some(value) = (self.lookup)(&var) {
+            return Ok(Some(value));
+        }
+        let Some(path) = (self.lookup)(&format!("{}_FILE", var)) else {
+            return Ok(None);
+        };
+        let value = std::fs::read_to_string(&path)
+            .map_err(|e| ConfigError::FileNotFound(format!("{}_FILE ({}): {}", var, path, e)))?;
+        Ok(Some(value.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    fn parse<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>> {
+        let Some(value) = self.string(name)? else {
+            return Ok(None);
+        };
+        value.trim().parse().map(Some).map_err(|_| {
+            ConfigError::InvalidFormat(format!("{}{}: cannot parse {:?}", ENV_PREFIX, name, value)).into()
+        })
+    }
+
+    fn flag(&self, name: &str) -> Result<Option<bool>> {
+        let Some(value) = self.string(name)? else {
+            return Ok(None);
+        };
+        match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Some(true)),
+            "0" | "false" | "no" | "off" => Ok(Some(false)),
+            _ => Err(ConfigError::InvalidFormat(format!("{}{}: expected true or false, got {:?}", ENV_PREFIX, name, value)).into()),
+        }
+    }
+
+    /// Comma-separated list; empty entries are dropped
+    fn list(&self, name: &str) -> Result<Option<Vec<String>>> {
+        Ok(self.string(name)?.map(|value| {
+            value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect()
+        }))
+    }
+}
+
 impl Config {
     /// Load configuration from a TOML file (not available on WASM)
     #[cfg(not(target_arch = "wasm32"))]
@@ -116,13 +1237,14 @@ impl Config {
                 domain: "synapse.local".to_string(),
                 capabilities: Self::default_capabilities_for_type(&entity_type),
                 display_name: Some(format!("{} ({})", local_name, entity_type)),
+                region: None,
             },
             email: EmailConfig {
                 smtp: SmtpConfig {
                     host: "localhost".to_string(),
                     port: 587,
                     username: format!("{}@synapse.local", local_name.to_lowercase()),
-                    password: "changeme".to_string(),
+                    password: "changeme".into(),
                     use_tls: true,
                     use_ssl: false,
                 },
@@ -130,7 +1252,7 @@ impl Config {
                     host: "localhost".to_string(),
                     port: 993,
                     username: format!("{}@synapse.local", local_name.to_lowercase()),
-                    password: "changeme".to_string(),
+                    password: "changeme".into(),
                     use_ssl: true,
                 },
             },
@@ -156,9 +1278,292 @@ impl Config {
                 file: None,
                 log_message_content: false,
             },
+            proxy: ProxyConfig::default(),
+            resumption: ResumptionConfig::default(),
+            relay: RelayConfig::default(),
+            admission: AdmissionConfig::default(),
+            trust_gate: TrustGateConfig::default(),
+            storage: StorageConfig::default(),
+            probing: CapabilityProbeConfig::default(),
+            listeners: ListenerConfig::default(),
+            endpoint_updates: EndpointUpdateConfig::default(),
+            cluster: ClusterConfig::default(),
+            mail_store: MailStoreConfig::default(),
+            election: ElectionConfig::default(),
+            metering: MeteringConfig::default(),
+            payload_streams: PayloadStreamConfig::default(),
+            time_sync: TimeSyncConfig::default(),
+            digest: DigestConfig::default(),
+            diagnostics: DiagnosticsConfig::default(),
+            echo: EchoConfig::default(),
+            nat: NatConfig::default(),
+            fast_relay: FastRelayConfig::default(),
+            email_sending: EmailSendingConfig::default(),
+            mailing_list: MailingListConfig::default(),
+            limits: ResourceLimitsConfig::default(),
+            runtime: RuntimeConfig::default(),
+            startup: StartupConfig::default(),
         }
     }
 
+    /// Configuration for a self-contained node keeping its state under `data_dir`
+    ///
+    /// Nothing outside the process is needed: email goes through the node's
+    /// own SMTP and IMAP listeners, the mail store is in memory, and history,
+    /// session tickets, suppressions and keys are files in `data_dir`. The
+    /// node is named after the host.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn standalone(data_dir: &Path) -> Self {
+        let local_name = std::env::var("HOSTNAME")
+            .or_else(|_| std::env::var("COMPUTERNAME"))
+            .map(|host| host.split('.').next().unwrap_or_default().to_lowercase())
+            .ok()
+            .filter(|host| !host.is_empty())
+            .unwrap_or_else(|| "node".to_string());
+        let file = |name: &str| Some(data_dir.join(name).to_string_lossy().into_owned());
+
+        let mut config = Self::default_for_entity(local_name, "service");
+        config.email.smtp.host = "127.0.0.1".to_string();
+        config.email.smtp.port = config.listeners.smtp.port;
+        config.email.smtp.use_tls = false;
+        config.email.imap.host = "127.0.0.1".to_string();
+        config.email.imap.port = config.listeners.imap.port;
+        config.email.imap.use_ssl = false;
+        config.security.private_key_path = file("node_private.pem");
+        config.security.public_key_path = file("node_public.pem");
+        config.storage.encrypt_at_rest = cfg!(feature = "crypto");
+        config.storage.history_store = file("history.json");
+        config.resumption.ticket_store = file("sessions.json");
+        config.mailing_list.suppression_file = file("suppressions.json");
+        config
+    }
+
+    /// Configuration from `SYNAPSE_*` environment variables alone
+    ///
+    /// Starts from the file named by `SYNAPSE_CONFIG` when set, otherwise
+    /// from [`Config::standalone`] in [`Config::env_data_dir`], then applies
+    /// [`Config::apply_env`]. Containers need no configuration file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_env() -> Result<Self> {
+        let mut config = match std::env::var(format!("{}CONFIG", ENV_PREFIX)) {
+            Ok(path) => Self::from_file(path)?,
+            Err(_) => Self::standalone(&Self::env_data_dir()),
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// State directory named by `SYNAPSE_DATA_DIR`, `./synapse-data` by default
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn env_data_dir() -> std::path::PathBuf {
+        std::env::var_os(format!("{}DATA_DIR", ENV_PREFIX))
+            .map(Into::into)
+            .unwrap_or_else(|| "synapse-data".into())
+    }
+
+    /// Override settings from `SYNAPSE_*` environment variables
+    ///
+    /// Every variable can instead be given as `<NAME>_FILE`, the path of a
+    /// file holding the value, so passwords can come from mounted secrets.
+    /// Unset variables leave the setting alone; malformed ones are an error
+    /// naming the variable.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn apply_env(&mut self) -> Result<()> {
+        self.apply_vars(|name| std::env::var(name).ok())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn apply_vars(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let env = EnvVars { lookup: &lookup };
+
+        if let Some(name) = env.string("NAME")? {
+            self.entity.local_name = name;
+        }
+        if let Some(entity_type) = env.string("ENTITY_TYPE")? {
+            self.entity.capabilities = Self::default_capabilities_for_type(&entity_type);
+            self.entity.entity_type = entity_type;
+        }
+        if let Some(domain) = env.string("DOMAIN")? {
+            self.entity.domain = domain;
+        }
+        if let Some(display_name) = env.string("DISPLAY_NAME")? {
+            self.entity.display_name = Some(display_name);
+        }
+        if let Some(region) = env.string("REGION")? {
+            self.entity.region = Some(region);
+        }
+        if let Some(capabilities) = env.list("CAPABILITIES")? {
+            self.entity.capabilities = capabilities;
+        }
+
+        if let Some(address) = env.parse::<IpAddr>("BIND_ADDRESS")? {
+            for binding in [
+                &mut self.listeners.tcp,
+                &mut self.listeners.udp,
+                &mut self.listeners.smtp,
+                &mut self.listeners.imap,
+            ] {
+                binding.address = address;
+            }
+        }
+        for (name, binding) in [
+            ("TCP_PORT", &mut self.listeners.tcp),
+            ("UDP_PORT", &mut self.listeners.udp),
+            ("SMTP_LISTEN_PORT", &mut self.listeners.smtp),
+            ("IMAP_LISTEN_PORT", &mut self.listeners.imap),
+        ] {
+            if let Some(port) = env.parse(name)? {
+                binding.port = port;
+            }
+        }
+        if let Some(host) = env.string("ADVERTISE_HOST")? {
+            self.listeners.advertise_host = Some(host);
+        }
+
+        let smtp = &mut self.email.smtp;
+        if let Some(host) = env.string("SMTP_HOST")? {
+            smtp.host = host;
+        }
+        if let Some(port) = env.parse("SMTP_PORT")? {
+            smtp.port = port;
+        }
+        if let Some(username) = env.string("SMTP_USERNAME")? {
+            smtp.username = username;
+        }
+        if let Some(password) = env.string("SMTP_PASSWORD")? {
+            smtp.password = password.into();
+        }
+        if let Some(use_tls) = env.flag("SMTP_TLS")? {
+            smtp.use_tls = use_tls;
+        }
+        let imap = &mut self.email.imap;
+        if let Some(host) = env.string("IMAP_HOST")? {
+            imap.host = host;
+        }
+        if let Some(port) = env.parse("IMAP_PORT")? {
+            imap.port = port;
+        }
+        if let Some(username) = env.string("IMAP_USERNAME")? {
+            imap.username = username;
+        }
+        if let Some(password) = env.string("IMAP_PASSWORD")? {
+            imap.password = password.into();
+        }
+        if let Some(use_ssl) = env.flag("IMAP_SSL")? {
+            imap.use_ssl = use_ssl;
+        }
+
+        if let Some(path) = env.string("PRIVATE_KEY_PATH")? {
+            self.security.private_key_path = Some(path);
+        }
+        if let Some(path) = env.string("PUBLIC_KEY_PATH")? {
+            self.security.public_key_path = Some(path);
+        }
+        if let Some(auto_generate) = env.flag("AUTO_GENERATE_KEYS")? {
+            self.security.auto_generate_keys = auto_generate;
+        }
+        if let Some(level) = env.string("SECURITY_LEVEL")? {
+            self.security.default_security_level = level;
+        }
+        if let Some(domains) = env.list("TRUSTED_DOMAINS")? {
+            self.security.trusted_domains = domains;
+        }
+
+        if let Some(url) = env.string("DATABASE_URL")? {
+            self.storage.database_url = Some(url);
+        }
+        if let Some(url) = env.string("REDIS_URL")? {
+            self.cluster.redis_url = Some(url.clone());
+            self.election.redis_url = Some(url.clone());
+            self.mail_store.redis_url = Some(url);
+        }
+        if let Some(encrypt) = env.flag("ENCRYPT_AT_REST")? {
+            self.storage.encrypt_at_rest = encrypt;
+        }
+        if let Some(path) = env.string("HISTORY_STORE")? {
+            self.storage.history_store = Some(path);
+        }
+
+        if let Some(name) = env.string("CLUSTER_NAME")? {
+            self.cluster.name = name.clone();
+            self.mail_store.name = name;
+        }
+        if let Some(instance_id) = env.string("INSTANCE_ID")? {
+            self.cluster.instance_id = instance_id.clone();
+            self.election.instance_id = instance_id.clone();
+            self.mail_store.instance_id = instance_id;
+        }
+        if let Some(members) = env.list("CLUSTER_MEMBERS")? {
+            self.cluster.enabled = !members.is_empty();
+            self.cluster.members = members;
+        }
+        if let Some(enabled) = env.flag("LEADER_ELECTION")? {
+            self.election.enabled = enabled;
+        }
+        if let Some(name) = env.string("LEADER_LEASE")? {
+            self.election.name = name;
+        }
+        if let Some(ttl) = env.parse("LEADER_LEASE_TTL_MS")? {
+            self.election.lease_ttl_ms = ttl;
+        }
+
+        if let Some(enabled) = env.flag("METERING")? {
+            self.metering.enabled = enabled;
+        }
+        if let Some(secs) = env.parse("METERING_PERIOD_SECS")? {
+            self.metering.period_secs = secs;
+        }
+        if let Some(path) = env.string("METERING_CSV")? {
+            self.metering.csv_path = Some(path);
+        }
+        if let Some(url) = env.string("METERING_WEBHOOK_URL")? {
+            self.metering.webhook_url = Some(url);
+        }
+        if let Some(secret) = env.string("METERING_WEBHOOK_SECRET")? {
+            self.metering.webhook_secret = Some(secret.into());
+        }
+
+        if let Some(level) = env.string("LOG_LEVEL")? {
+            self.logging.level = level;
+        }
+
+        if env.flag("SMALL_HOST")? == Some(true) {
+            self.limits = ResourceLimitsConfig::small_host();
+        }
+        if let Some(max) = env.parse("MAX_PEERS")? {
+            self.limits.max_peers = max;
+        }
+        if let Some(max) = env.parse("MAX_QUEUED_BYTES")? {
+            self.limits.max_queued_bytes = max;
+        }
+        if let Some(max) = env.parse("MAX_CONCURRENT_CRYPTO")? {
+            self.limits.max_concurrent_crypto = max;
+        }
+        if let Some(max) = env.parse("MAX_CONNECTIONS")? {
+            self.limits.max_connections = max;
+        }
+
+        if let Some(threads) = env.parse("WORKER_THREADS")? {
+            self.runtime.main_worker_threads = threads;
+        }
+        if let Some(startup) = env.parse("EMAIL_SERVER")? {
+            self.startup.email_server = startup;
+        }
+        for (name, subsystem) in [
+            ("TRANSPORT", &mut self.runtime.transports),
+            ("CONSENSUS", &mut self.runtime.consensus),
+            ("STORAGE", &mut self.runtime.storage),
+        ] {
+            if let Some(threads) = env.parse(&format!("{}_THREADS", name))? {
+                subsystem.worker_threads = threads;
+            }
+            if let Some(max) = env.parse(&format!("MAX_{}_TASKS", name))? {
+                subsystem.max_tasks = max;
+            }
+        }
+        Ok(())
+    }
+
     /// Get default capabilities for an entity type
     fn default_capabilities_for_type(entity_type: &str) -> Vec<String> {
         match entity_type {
@@ -217,6 +1622,37 @@ impl Config {
             return Err(ConfigError::ValidationFailed("Invalid IMAP port".to_string()).into());
         }
 
+        for (name, binding) in [
+            ("tcp", &self.listeners.tcp),
+            ("udp", &self.listeners.udp),
+            ("smtp", &self.listeners.smtp),
+            ("imap", &self.listeners.imap),
+        ] {
+            if let Some((first, last)) = binding.port_range {
+                if first == 0 || first > last {
+                    return Err(ConfigError::ValidationFailed(format!("Invalid {} listener port range", name)).into());
+                }
+            }
+        }
+
+        if self.cluster.enabled && !self.cluster.members.contains(&self.cluster.instance_id) {
+            return Err(ConfigError::ValidationFailed(
+                "Cluster instance_id must be one of the cluster members".to_string(),
+            )
+            .into());
+        }
+
+        if self.election.enabled && self.election.renew_interval_ms >= self.election.lease_ttl_ms {
+            return Err(ConfigError::ValidationFailed(
+                "Leader election must renew its lease more often than it expires".to_string(),
+            )
+            .into());
+        }
+
+        if self.metering.enabled && self.metering.period_secs == 0 {
+            return Err(ConfigError::ValidationFailed("Metering period must be at least one second".to_string()).into());
+        }
+
         // Check security configuration
         if !["public", "private", "authenticated", "secure"].contains(&self.security.default_security_level.as_str()) {
             return Err(ConfigError::ValidationFailed("Invalid default security level".to_string()).into());
@@ -239,7 +1675,7 @@ impl Config {
         let local_name = local_name.into();
         let entity_type = entity_type.into();
         let email = email.into();
-        let password = password.into();
+        let password = Secret::new(password.into());
 
         let mut config = Self::default_for_entity(&local_name, &entity_type);
         
@@ -268,7 +1704,7 @@ impl Config {
         let local_name = local_name.into();
         let entity_type = entity_type.into();
         let email = email.into();
-        let password = password.into();
+        let password = Secret::new(password.into());
 
         let mut config = Self::default_for_entity(&local_name, &entity_type);
         
@@ -418,6 +1854,41 @@ mod tests {
         assert!(invalid_config.validate().is_err());
     }
 
+    #[test]
+    fn test_environment_overrides() {
+        let secret = NamedTempFile::new().unwrap();
+        std::fs::write(secret.path(), "s3cret\n").unwrap();
+        let vars: HashMap<String, String> = [
+            ("SYNAPSE_NAME", "relay-7"),
+            ("SYNAPSE_ENTITY_TYPE", "router"),
+            ("SYNAPSE_TCP_PORT", "9000"),
+            ("SYNAPSE_BIND_ADDRESS", "::"),
+            ("SYNAPSE_SMTP_TLS", "no"),
+            ("SYNAPSE_TRUSTED_DOMAINS", "a.example, b.example,"),
+            ("SYNAPSE_REDIS_URL", "redis://cache:6379"),
+            ("SYNAPSE_SMTP_PASSWORD_FILE", secret.path().to_str().unwrap()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let mut config = Config::default_for_entity("Test", "tool");
+        config.apply_vars(|name| vars.get(name).cloned()).unwrap();
+        assert_eq!(config.entity.local_name, "relay-7");
+        assert_eq!(config.entity.capabilities, Config::default_capabilities_for_type("router"));
+        assert_eq!(config.listeners.tcp.port, 9000);
+        assert_eq!(config.listeners.udp.address, "::".parse::<IpAddr>().unwrap());
+        assert!(!config.email.smtp.use_tls);
+        assert_eq!(config.email.smtp.password.expose(), "s3cret");
+        assert_eq!(config.security.trusted_domains, vec!["a.example", "b.example"]);
+        assert_eq!(config.mail_store.redis_url.as_deref(), Some("redis://cache:6379"));
+        // Untouched settings keep their values
+        assert_eq!(config.email.imap.port, Config::default_for_entity("Test", "tool").email.imap.port);
+
+        let err = config.apply_vars(|name| (name == "SYNAPSE_UDP_PORT").then(|| "many".to_string()));
+        assert!(err.unwrap_err().to_string().contains("SYNAPSE_UDP_PORT"));
+    }
+
     #[test]
     fn test_provider_templates() {
         let providers = ConfigTemplates::available_providers();
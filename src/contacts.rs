@@ -0,0 +1,554 @@
+//! # Contact Book
+//!
+//! A richer contact primitive than `register_peer`: each contact carries aliases,
+//! tags, groups, free-form notes, preferred transports and a default security
+//! level. The contact book is consulted by smart name resolution so that
+//! `"Alice"`, `"al"` or `"alice@ai-lab.example.com"` all reach the same peer.
+//!
+//! Contacts can be exchanged as JSON or vCard 4.0 and persisted through the
+//! storage backend when the `database` feature is enabled.
+
+use crate::error::{IdentityError, Result};
+use crate::synapse::blockchain::serialization::DateTimeWrapper;
+use crate::types::SecurityLevel;
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+/// A single entry in the contact book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contact {
+    /// Global ID of the contact (e.g. "alice@ai-lab.example.com")
+    pub global_id: String,
+    /// Name shown to the user
+    pub display_name: String,
+    /// Additional names this contact can be addressed by
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Free-form labels (e.g. "research", "on-call")
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Named groups this contact belongs to
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Private notes about the contact
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Transports to prefer, most preferred first (e.g. "tcp", "email")
+    #[serde(default)]
+    pub preferred_transports: Vec<String>,
+    /// Minimum security level for messages to this contact
+    #[serde(default)]
+    pub default_security: Option<SecurityLevel>,
+    /// Withhold read receipts and typing indicators from this contact, and ignore theirs
+    #[serde(default)]
+    pub suppress_indicators: bool,
+    /// When the contact was added
+    pub created_at: DateTimeWrapper,
+    /// When the contact was last changed
+    pub updated_at: DateTimeWrapper,
+}
+
+impl Contact {
+    /// Create a new contact with no aliases, tags or preferences
+    pub fn new(global_id: impl Into<String>, display_name: impl Into<String>) -> Self {
+        let now = DateTimeWrapper::new(Utc::now());
+        Self {
+            global_id: global_id.into(),
+            display_name: display_name.into(),
+            aliases: Vec::new(),
+            tags: Vec::new(),
+            groups: Vec::new(),
+            notes: None,
+            preferred_transports: Vec::new(),
+            default_security: None,
+            suppress_indicators: false,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// Add an alias
+    pub fn with_alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    /// Add a tag
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Add the contact to a group
+    pub fn with_group(mut self, group: impl Into<String>) -> Self {
+        self.groups.push(group.into());
+        self
+    }
+
+    /// Set the preferred transports
+    pub fn with_preferred_transports(mut self, transports: Vec<String>) -> Self {
+        self.preferred_transports = transports;
+        self
+    }
+
+    /// Set the default security level
+    pub fn with_default_security(mut self, level: SecurityLevel) -> Self {
+        self.default_security = Some(level);
+        self
+    }
+
+    /// Stop exchanging read receipts and typing indicators with this contact
+    pub fn with_indicators_suppressed(mut self) -> Self {
+        self.suppress_indicators = true;
+        self
+    }
+
+    /// All names this contact answers to, including the display name
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.display_name.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+
+    /// Check if the contact has a tag (case-insensitive)
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Check if the contact is in a group (case-insensitive)
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g.eq_ignore_ascii_case(group))
+    }
+
+    /// Apply the contact's security default, never weakening the requested level
+    pub fn effective_security(&self, requested: SecurityLevel) -> SecurityLevel {
+        match &self.default_security {
+            Some(default) if security_rank(default) > security_rank(&requested) => default.clone(),
+            _ => requested,
+        }
+    }
+
+    /// Whether the contact wants email delivery before any real-time transport
+    pub fn prefers_email(&self) -> bool {
+        self.preferred_transports
+            .first()
+            .is_some_and(|t| t.eq_ignore_ascii_case("email"))
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.global_id.to_lowercase().contains(&query)
+            || self.names().any(|n| n.to_lowercase().contains(&query))
+            || self.tags.iter().any(|t| t.to_lowercase().contains(&query))
+            || self.groups.iter().any(|g| g.to_lowercase().contains(&query))
+            || self.notes.as_ref().is_some_and(|n| n.to_lowercase().contains(&query))
+    }
+}
+
+/// Relative strength of a security level, higher is stronger
+pub(crate) fn security_rank(level: &SecurityLevel) -> u8 {
+    match level {
+        SecurityLevel::Public => 0,
+        SecurityLevel::Authenticated => 1,
+        SecurityLevel::Private => 2,
+        SecurityLevel::Secure => 3,
+    }
+}
+
+/// In-memory contact book with alias index
+#[derive(Debug, Default)]
+pub struct ContactBook {
+    /// Global ID -> contact
+    contacts: DashMap<String, Contact>,
+    /// Lowercased display name or alias -> global ID
+    names: DashMap<String, String>,
+}
+
+impl ContactBook {
+    /// Create an empty contact book
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace a contact
+    ///
+    /// Fails if one of the contact's names is already used by a different contact.
+    pub fn upsert(&self, mut contact: Contact) -> Result<()> {
+        for name in contact.names() {
+            if let Some(owner) = self.names.get(&name.to_lowercase()) {
+                if *owner != contact.global_id {
+                    return Err(IdentityError::AlreadyExists(format!(
+                        "Name '{}' already refers to {}",
+                        name,
+                        owner.as_str()
+                    )));
+                }
+            }
+        }
+
+        if let Some(existing) = self.contacts.get(&contact.global_id) {
+            contact.created_at = existing.created_at.clone();
+        }
+        contact.updated_at = DateTimeWrapper::new(Utc::now());
+
+        self.unindex(&contact.global_id);
+        for name in contact.names() {
+            self.names.insert(name.to_lowercase(), contact.global_id.clone());
+        }
+        self.contacts.insert(contact.global_id.clone(), contact);
+        Ok(())
+    }
+
+    /// Remove a contact by global ID
+    pub fn remove(&self, global_id: &str) -> Result<Contact> {
+        self.unindex(global_id);
+        self.contacts
+            .remove(global_id)
+            .map(|(_, contact)| contact)
+            .ok_or_else(|| IdentityError::NotFound(format!("Contact not found: {}", global_id)))
+    }
+
+    fn unindex(&self, global_id: &str) {
+        self.names.retain(|_, owner| owner != global_id);
+    }
+
+    /// Get a contact by global ID
+    pub fn get(&self, global_id: &str) -> Option<Contact> {
+        self.contacts.get(global_id).map(|entry| entry.value().clone())
+    }
+
+    /// Resolve a display name, alias or global ID to a contact
+    pub fn resolve(&self, name: &str) -> Option<Contact> {
+        if let Some(contact) = self.get(name) {
+            return Some(contact);
+        }
+        let global_id = self.names.get(&name.to_lowercase())?;
+        self.get(global_id.as_str())
+    }
+
+    /// Search contacts by name, alias, tag, group or notes
+    pub fn search(&self, query: &str) -> Vec<Contact> {
+        let mut results: Vec<Contact> = self
+            .contacts
+            .iter()
+            .filter(|entry| entry.value().matches(query))
+            .map(|entry| entry.value().clone())
+            .collect();
+        results.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+        results
+    }
+
+    /// All contacts with a tag
+    pub fn with_tag(&self, tag: &str) -> Vec<Contact> {
+        self.filter(|c| c.has_tag(tag))
+    }
+
+    /// All members of a group
+    pub fn group_members(&self, group: &str) -> Vec<Contact> {
+        self.filter(|c| c.in_group(group))
+    }
+
+    /// Names of all groups in use
+    pub fn groups(&self) -> Vec<String> {
+        let mut groups: Vec<String> = self
+            .contacts
+            .iter()
+            .flat_map(|entry| entry.value().groups.clone())
+            .collect();
+        groups.sort();
+        groups.dedup();
+        groups
+    }
+
+    fn filter(&self, predicate: impl Fn(&Contact) -> bool) -> Vec<Contact> {
+        self.contacts
+            .iter()
+            .filter(|entry| predicate(entry.value()))
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    /// All contacts
+    pub fn list(&self) -> Vec<Contact> {
+        self.contacts.iter().map(|entry| entry.value().clone()).collect()
+    }
+
+    /// Number of contacts
+    pub fn len(&self) -> usize {
+        self.contacts.len()
+    }
+
+    /// Check if the contact book is empty
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+
+    /// Export all contacts as a JSON array
+    pub fn export_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.list())?)
+    }
+
+    /// Import contacts from a JSON array, returning the number imported
+    pub fn import_json(&self, json: &str) -> Result<usize> {
+        let contacts: Vec<Contact> = serde_json::from_str(json)?;
+        self.import_all(contacts)
+    }
+
+    /// Export all contacts as concatenated vCard 4.0 entries
+    pub fn export_vcard(&self) -> String {
+        self.list().iter().map(to_vcard).collect()
+    }
+
+    /// Import contacts from vCard text, returning the number imported
+    pub fn import_vcard(&self, vcard: &str) -> Result<usize> {
+        self.import_all(parse_vcards(vcard)?)
+    }
+
+    /// Add or update `contacts`, skipping any whose names clash with another
+    /// contact, returning the number imported
+    pub fn import_all(&self, contacts: Vec<Contact>) -> Result<usize> {
+        let mut imported = 0;
+        for contact in contacts {
+            match self.upsert(contact) {
+                Ok(()) => imported += 1,
+                Err(e) => tracing::warn!("Skipping contact during import: {}", e),
+            }
+        }
+        Ok(imported)
+    }
+
+    /// Load contacts for an owner from the database
+    #[cfg(feature = "database")]
+    pub async fn load_from(&self, db: &crate::synapse::storage::Database, owner_id: &str) -> Result<usize> {
+        let contacts = db
+            .list_contacts(owner_id)
+            .await
+            .map_err(|e| crate::error::SynapseError::DatabaseError(e.to_string()))?;
+        self.import_all(contacts)
+    }
+
+    /// Persist all contacts for an owner to the database
+    #[cfg(feature = "database")]
+    pub async fn save_to(&self, db: &crate::synapse::storage::Database, owner_id: &str) -> Result<()> {
+        for contact in self.list() {
+            db.upsert_contact(owner_id, &contact)
+                .await
+                .map_err(|e| crate::error::SynapseError::DatabaseError(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+fn escape_vcard(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+}
+
+fn unescape_vcard(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => {}
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Split a vCard list value on unescaped commas
+fn split_vcard_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        if escaped {
+            current.push('\\');
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ',' {
+            items.push(unescape_vcard(&current));
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        items.push(unescape_vcard(&current));
+    }
+    items.into_iter().filter(|s| !s.is_empty()).collect()
+}
+
+fn join_vcard_list(values: &[String]) -> String {
+    values.iter().map(|v| escape_vcard(v)).collect::<Vec<_>>().join(",")
+}
+
+fn to_vcard(contact: &Contact) -> String {
+    let mut card = String::from("BEGIN:VCARD\r\nVERSION:4.0\r\n");
+    card.push_str(&format!("FN:{}\r\n", escape_vcard(&contact.display_name)));
+    card.push_str(&format!("EMAIL:{}\r\n", escape_vcard(&contact.global_id)));
+    card.push_str(&format!("X-SYNAPSE-ID:{}\r\n", escape_vcard(&contact.global_id)));
+    if !contact.aliases.is_empty() {
+        card.push_str(&format!("NICKNAME:{}\r\n", join_vcard_list(&contact.aliases)));
+    }
+    if !contact.tags.is_empty() {
+        card.push_str(&format!("CATEGORIES:{}\r\n", join_vcard_list(&contact.tags)));
+    }
+    if !contact.groups.is_empty() {
+        card.push_str(&format!("X-SYNAPSE-GROUPS:{}\r\n", join_vcard_list(&contact.groups)));
+    }
+    if let Some(notes) = &contact.notes {
+        card.push_str(&format!("NOTE:{}\r\n", escape_vcard(notes)));
+    }
+    if !contact.preferred_transports.is_empty() {
+        card.push_str(&format!(
+            "X-SYNAPSE-TRANSPORTS:{}\r\n",
+            join_vcard_list(&contact.preferred_transports)
+        ));
+    }
+    if let Some(level) = &contact.default_security {
+        card.push_str(&format!("X-SYNAPSE-SECURITY:{}\r\n", level));
+    }
+    card.push_str("END:VCARD\r\n");
+    card
+}
+
+fn parse_security_level(value: &str) -> Option<SecurityLevel> {
+    match value.trim().to_lowercase().as_str() {
+        "public" => Some(SecurityLevel::Public),
+        "private" => Some(SecurityLevel::Private),
+        "authenticated" => Some(SecurityLevel::Authenticated),
+        "secure" => Some(SecurityLevel::Secure),
+        _ => None,
+    }
+}
+
+fn parse_vcards(input: &str) -> Result<Vec<Contact>> {
+    let mut contacts = Vec::new();
+    let mut current: Option<(Option<String>, Option<String>, Contact)> = None;
+
+    for line in input.lines().map(str::trim_end).filter(|l| !l.is_empty()) {
+        let (key, value) = match line.split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        // Drop parameters such as "EMAIL;TYPE=work"
+        let key = key.split(';').next().unwrap_or(key).to_uppercase();
+
+        match key.as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VCARD") => {
+                current = Some((None, None, Contact::new("", "")));
+            }
+            "END" if value.eq_ignore_ascii_case("VCARD") => {
+                let (synapse_id, email, mut contact) = current.take().ok_or_else(|| {
+                    IdentityError::InvalidFormat("END:VCARD without BEGIN:VCARD".to_string())
+                })?;
+                contact.global_id = synapse_id.or(email).ok_or_else(|| {
+                    IdentityError::InvalidFormat("vCard has no EMAIL or X-SYNAPSE-ID".to_string())
+                })?;
+                if contact.display_name.is_empty() {
+                    contact.display_name = contact.global_id.clone();
+                }
+                contacts.push(contact);
+            }
+            _ => {
+                let Some((synapse_id, email, contact)) = current.as_mut() else {
+                    continue;
+                };
+                match key.as_str() {
+                    "FN" => contact.display_name = unescape_vcard(value),
+                    "EMAIL" => {
+                        if email.is_none() {
+                            *email = Some(unescape_vcard(value));
+                        }
+                    }
+                    "X-SYNAPSE-ID" => *synapse_id = Some(unescape_vcard(value)),
+                    "NICKNAME" => contact.aliases.extend(split_vcard_list(value)),
+                    "CATEGORIES" => contact.tags.extend(split_vcard_list(value)),
+                    "X-SYNAPSE-GROUPS" => contact.groups.extend(split_vcard_list(value)),
+                    "NOTE" => contact.notes = Some(unescape_vcard(value)),
+                    "X-SYNAPSE-TRANSPORTS" => contact.preferred_transports = split_vcard_list(value),
+                    "X-SYNAPSE-SECURITY" => contact.default_security = parse_security_level(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if current.is_some() {
+        return Err(IdentityError::InvalidFormat("Unterminated vCard".to_string()));
+    }
+
+    Ok(contacts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_book() -> ContactBook {
+        let book = ContactBook::new();
+        book.upsert(
+            Contact::new("alice@ai-lab.example.com", "Alice")
+                .with_alias("al")
+                .with_tag("research")
+                .with_group("lab")
+                .with_default_security(SecurityLevel::Secure),
+        )
+        .unwrap();
+        book.upsert(
+            Contact::new("bob@ops.example.com", "Bob")
+                .with_tag("on-call")
+                .with_preferred_transports(vec!["email".to_string()]),
+        )
+        .unwrap();
+        book
+    }
+
+    #[test]
+    fn test_resolve_by_alias_and_name() {
+        let book = sample_book();
+        assert_eq!(book.resolve("AL").unwrap().global_id, "alice@ai-lab.example.com");
+        assert_eq!(book.resolve("alice").unwrap().global_id, "alice@ai-lab.example.com");
+        assert_eq!(book.resolve("bob@ops.example.com").unwrap().display_name, "Bob");
+        assert!(book.resolve("carol").is_none());
+    }
+
+    #[test]
+    fn test_alias_conflicts_are_rejected() {
+        let book = sample_book();
+        let clash = Contact::new("alice@elsewhere.example.com", "Other").with_alias("al");
+        assert!(book.upsert(clash).is_err());
+    }
+
+    #[test]
+    fn test_security_default_never_weakens() {
+        let book = sample_book();
+        let alice = book.resolve("Alice").unwrap();
+        assert_eq!(alice.effective_security(SecurityLevel::Public), SecurityLevel::Secure);
+        assert!(book.resolve("Bob").unwrap().prefers_email());
+    }
+
+    #[test]
+    fn test_vcard_roundtrip() {
+        let book = sample_book();
+        let exported = book.export_vcard();
+
+        let restored = ContactBook::new();
+        assert_eq!(restored.import_vcard(&exported).unwrap(), 2);
+        let alice = restored.resolve("al").unwrap();
+        assert!(alice.has_tag("research"));
+        assert!(alice.in_group("lab"));
+        assert_eq!(alice.default_security, Some(SecurityLevel::Secure));
+        assert_eq!(restored.search("on-call").len(), 1);
+    }
+}
@@ -0,0 +1,152 @@
+//! # Crypto Worker Pool
+//!
+//! RSA signature checks are the bulk of the CPU a busy node spends on its
+//! receive path, and run one after another on the async runtime they stall
+//! every other task. The [`CryptoWorkerPool`] checks a batch of signatures
+//! at once on tokio's blocking threads: the batch is split into chunks, one
+//! per worker, and each chunk holds a [`CryptoSlot`](crate::limits::CryptoSlot)
+//! while it runs.
+//!
+//! Parallelism is the node's `limits.max_concurrent_crypto`, so pooled
+//! verification shares its budget with signing and decryption and a small
+//! host is never asked to run more than it allows. Jobs are
+//! [`VerifyJob`]s, which carry their own copy of the signer's public key;
+//! [`IncomingMessage::verification_job`](crate::transport::abstraction::IncomingMessage::verification_job)
+//! builds them for received messages.
+//!
+//! `cargo run --release --example crypto_throughput` compares serial and
+//! pooled verification on the local machine.
+
+use crate::crypto::VerifyJob;
+use crate::limits::ResourceGovernor;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::warn;
+
+/// Most signatures one worker checks before another chunk is started
+pub const DEFAULT_BATCH_SIZE: usize = 64;
+
+/// Batches with fewer jobs than this are checked on the calling task
+const INLINE_THRESHOLD: usize = 2;
+
+/// Checks batches of signatures in parallel on blocking worker threads
+#[derive(Debug)]
+pub struct CryptoWorkerPool {
+    limits: Arc<ResourceGovernor>,
+    batch_size: usize,
+    verified: AtomicU64,
+    batches: AtomicU64,
+}
+
+impl CryptoWorkerPool {
+    /// Pool whose parallelism is `limits`' crypto budget
+    pub fn new(limits: Arc<ResourceGovernor>) -> Self {
+        Self {
+            limits,
+            batch_size: DEFAULT_BATCH_SIZE,
+            verified: AtomicU64::new(0),
+            batches: AtomicU64::new(0),
+        }
+    }
+
+    /// Cap the signatures one worker checks per chunk
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Workers that may run at once
+    pub fn parallelism(&self) -> usize {
+        self.limits.crypto_parallelism()
+    }
+
+    /// Signatures checked and batches run since startup
+    pub fn stats(&self) -> (u64, u64) {
+        (self.verified.load(Ordering::Relaxed), self.batches.load(Ordering::Relaxed))
+    }
+
+    /// Check every job, returning whether each signature is valid, in order
+    pub async fn verify_batch(&self, jobs: Vec<VerifyJob>) -> Vec<bool> {
+        if jobs.is_empty() {
+            return Vec::new();
+        }
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.verified.fetch_add(jobs.len() as u64, Ordering::Relaxed);
+
+        if jobs.len() < INLINE_THRESHOLD {
+            let _slot = self.limits.crypto_slot().await;
+            return jobs.iter().map(VerifyJob::run).collect();
+        }
+
+        // Spread the batch evenly over the workers, but keep chunks small
+        // enough that one slow chunk does not hold up the rest
+        let chunk_size = jobs.len().div_ceil(self.parallelism().max(1)).clamp(1, self.batch_size);
+        let mut jobs = jobs.into_iter();
+        let mut workers = Vec::new();
+        loop {
+            let chunk: Vec<VerifyJob> = jobs.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            let slot = self.limits.crypto_slot().await;
+            let len = chunk.len();
+            let worker = tokio::task::spawn_blocking(move || {
+                let _slot = slot;
+                chunk.iter().map(VerifyJob::run).collect::<Vec<bool>>()
+            });
+            workers.push((worker, len));
+        }
+
+        let mut results = Vec::new();
+        for (worker, len) in workers {
+            match worker.await {
+                Ok(valid) => results.extend(valid),
+                Err(e) => {
+                    warn!("Signature worker failed, treating {} signatures as invalid: {}", len, e);
+                    results.extend(std::iter::repeat_n(false, len));
+                }
+            }
+        }
+        results
+    }
+}
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use super::*;
+    use crate::config::ResourceLimitsConfig;
+    use crate::crypto::CryptoManager;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn batch_results_match_serial_checks() {
+        let mut signer = CryptoManager::new();
+        let mut verifier = CryptoManager::new();
+        let (_, public_key) = signer.generate_keypair().unwrap();
+        verifier.import_public_key("alice", &public_key).unwrap();
+        let key = verifier.signature_verifier("alice").unwrap();
+        assert!(verifier.signature_verifier("bob").is_none());
+
+        let jobs: Vec<VerifyJob> = (0..20)
+            .map(|i| {
+                let data = format!("message {}", i).into_bytes();
+                let mut signature = signer.sign_bytes(&data).unwrap();
+                if i % 3 == 0 {
+                    signature[0] ^= 0xff;
+                }
+                VerifyJob::new(key.clone(), data, signature)
+            })
+            .collect();
+        let serial: Vec<bool> = jobs.iter().map(VerifyJob::run).collect();
+        assert_eq!(serial.iter().filter(|valid| !**valid).count(), 7);
+
+        let limits = Arc::new(ResourceGovernor::new(&ResourceLimitsConfig {
+            max_concurrent_crypto: 3,
+            ..Default::default()
+        }));
+        let pool = CryptoWorkerPool::new(limits).with_batch_size(4);
+        assert_eq!(pool.parallelism(), 3);
+        assert_eq!(pool.verify_batch(jobs).await, serial);
+        assert_eq!(pool.verify_batch(Vec::new()).await, Vec::<bool>::new());
+        assert_eq!(pool.stats(), (20, 1));
+    }
+}
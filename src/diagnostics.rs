@@ -0,0 +1,499 @@
+//! # Connectivity Diagnostics
+//!
+//! `EnhancedSynapseRouter::run_diagnostics` checks the things that most often
+//! keep a node from being reachable and reports each as a [`DiagnosticCheck`]:
+//!
+//! - **Listeners**: which transports bound, and where
+//! - **Reachability**: whether advertised endpoints accept connections and
+//!   whether a round trip through a public echo peer succeeds
+//! - **NAT**: whether the addresses we advertise are reachable from outside
+//!   our network at all, and which type of NAT we are behind
+//! - **DNS**: whether our domain resolves and publishes a `_synapse._tcp` SRV
+//!   record matching our TCP endpoint
+//! - **Email**: whether the SMTP server answers and the sending domain
+//!   publishes SPF, DMARC and (with a selector configured) DKIM records
+//!
+//! Failed and warning checks carry a remediation hint. The pure record and
+//! address checks live here; the router gathers what they need.
+
+use crate::error::Result;
+use crate::transport::nat_behavior::{NatClassification, NatType};
+use crate::types::SmtpConfig;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Name of the SRV record advertising a participant's TCP endpoint, under its domain
+pub const SRV_SERVICE: &str = "_synapse._tcp";
+
+/// Outcome of a check, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    /// Could not be checked, e.g. nothing configured to check against
+    Skipped,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Skipped => "SKIP",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        })
+    }
+}
+
+/// Area a check belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckCategory {
+    Listeners,
+    Reachability,
+    Nat,
+    Dns,
+    Email,
+}
+
+/// One diagnostic check and what to do about it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    pub category: CheckCategory,
+    /// Short name, e.g. `listener.tcp`
+    pub name: String,
+    pub status: CheckStatus,
+    /// What was found
+    pub detail: String,
+    /// How to fix a warning or failure
+    #[serde(default)]
+    pub remediation: Option<String>,
+}
+
+impl DiagnosticCheck {
+    pub fn pass(category: CheckCategory, name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(category, name, CheckStatus::Pass, detail)
+    }
+
+    pub fn skipped(category: CheckCategory, name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(category, name, CheckStatus::Skipped, detail)
+    }
+
+    pub fn warn(category: CheckCategory, name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(category, name, CheckStatus::Warn, detail)
+    }
+
+    pub fn fail(category: CheckCategory, name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(category, name, CheckStatus::Fail, detail)
+    }
+
+    fn new(category: CheckCategory, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            category,
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            remediation: None,
+        }
+    }
+
+    /// Attach a remediation hint
+    pub fn fix(mut self, hint: impl Into<String>) -> Self {
+        self.remediation = Some(hint.into());
+        self
+    }
+}
+
+/// Result of [`EnhancedSynapseRouter::run_diagnostics`](crate::router_enhanced::EnhancedSynapseRouter::run_diagnostics)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    pub participant: String,
+    pub generated_at: DateTime<Utc>,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl DiagnosticsReport {
+    pub fn new(participant: impl Into<String>) -> Self {
+        Self {
+            participant: participant.into(),
+            generated_at: Utc::now(),
+            checks: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, check: DiagnosticCheck) {
+        self.checks.push(check);
+    }
+
+    /// Worst status across all checks
+    pub fn status(&self) -> CheckStatus {
+        self.checks.iter().map(|check| check.status).max().unwrap_or(CheckStatus::Pass)
+    }
+
+    /// Whether no check failed
+    pub fn is_healthy(&self) -> bool {
+        self.status() < CheckStatus::Fail
+    }
+
+    /// Checks in one category
+    pub fn category(&self, category: CheckCategory) -> impl Iterator<Item = &DiagnosticCheck> {
+        self.checks.iter().filter(move |check| check.category == category)
+    }
+
+    /// Remediation hints of failed checks first, then warnings
+    pub fn remediations(&self) -> Vec<&str> {
+        let mut checks: Vec<&DiagnosticCheck> = self
+            .checks
+            .iter()
+            .filter(|check| check.status > CheckStatus::Skipped && check.remediation.is_some())
+            .collect();
+        checks.sort_by_key(|check| std::cmp::Reverse(check.status));
+        checks.into_iter().filter_map(|check| check.remediation.as_deref()).collect()
+    }
+
+    /// Plain-text rendering, one line per check
+    pub fn render_text(&self) -> String {
+        let mut lines = vec![format!("Diagnostics for {} ({})", self.participant, self.status())];
+        for check in &self.checks {
+            lines.push(format!("[{}] {}: {}", check.status, check.name, check.detail));
+            if check.status > CheckStatus::Skipped {
+                if let Some(hint) = &check.remediation {
+                    lines.push(format!("       -> {}", hint));
+                }
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// A service record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// DNS lookups used by the DNS and email checks; provided by the application
+#[async_trait]
+pub trait DnsResolver: Send + Sync {
+    /// All TXT strings published at `name`
+    async fn lookup_txt(&self, name: &str) -> Result<Vec<String>>;
+
+    /// All SRV records published at `name`
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>>;
+}
+
+/// Whether peers on other networks could route to `ip`
+///
+/// Loopback, link-local, private (RFC 1918, unique local) and carrier-grade
+/// NAT addresses are not.
+pub fn is_publicly_routable(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            let shared = a == 100 && (64..128).contains(&b);
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || shared)
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            let unique_local = first & 0xfe00 == 0xfc00;
+            let link_local = first & 0xffc0 == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+        }
+    }
+}
+
+/// The SPF policy among a domain's TXT records
+pub fn spf_record(records: &[String]) -> Option<&str> {
+    records.iter().map(String::as_str).find(|record| record.to_lowercase().starts_with("v=spf1"))
+}
+
+/// The DMARC policy (`p=`) among the TXT records at `_dmarc.<domain>`
+pub fn dmarc_policy(records: &[String]) -> Option<String> {
+    let record = records.iter().find(|record| record.to_lowercase().starts_with("v=dmarc1"))?;
+    let policy = record
+        .split(';')
+        .filter_map(|tag| tag.trim().split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("p"))
+        .map(|(_, value)| value.trim().to_lowercase());
+    Some(policy.unwrap_or_else(|| "none".to_string()))
+}
+
+/// Whether the TXT records at `<selector>._domainkey.<domain>` hold a DKIM key
+pub fn has_dkim_key(records: &[String]) -> bool {
+    records.iter().any(|record| {
+        let record = record.to_lowercase();
+        record.contains("p=") && (record.starts_with("v=dkim1") || record.contains("k=rsa") || record.contains("k=ed25519"))
+    })
+}
+
+/// SRV records pointing at `port`
+pub fn srv_matches<'a>(records: &'a [SrvRecord], port: u16) -> impl Iterator<Item = &'a SrvRecord> {
+    records.iter().filter(move |record| record.port == port)
+}
+
+/// Host and port of an advertised endpoint such as `203.0.113.7:8080` or `[2001:db8::1]:8080`
+pub fn split_endpoint(endpoint: &str) -> Option<(&str, u16)> {
+    let (host, port) = endpoint.rsplit_once(':')?;
+    Some((host.trim_start_matches('[').trim_end_matches(']'), port.parse().ok()?))
+}
+
+/// Whether `endpoint` accepts a TCP connection from this host
+pub(crate) async fn check_tcp_endpoint(report: &mut DiagnosticsReport, transport: &str, endpoint: &str, timeout: Duration) {
+    let name = format!("reachability.{}", transport);
+    match tokio::time::timeout(timeout, tokio::net::TcpStream::connect(endpoint)).await {
+        Ok(Ok(_)) => report.push(DiagnosticCheck::pass(
+            CheckCategory::Reachability,
+            name,
+            format!("{} accepts connections", endpoint),
+        )),
+        Ok(Err(e)) => report.push(
+            DiagnosticCheck::warn(CheckCategory::Reachability, name, format!("{} refused a connection: {}", endpoint, e))
+                .fix(format!("Allow inbound connections to {} in the firewall, or forward the port", endpoint)),
+        ),
+        Err(_) => report.push(
+            DiagnosticCheck::warn(CheckCategory::Reachability, name, format!("No answer from {} within {:?}", endpoint, timeout))
+                .fix(format!("Check that {} is the address peers should dial, and that the port is forwarded", endpoint)),
+        ),
+    }
+}
+
+/// Whether the advertised endpoints can be reached from outside our network
+pub(crate) async fn check_nat(report: &mut DiagnosticsReport, endpoints: &BTreeMap<String, String>) {
+    let mut public = Vec::new();
+    let mut private = Vec::new();
+    for endpoint in endpoints.values() {
+        let Some((host, port)) = split_endpoint(endpoint) else {
+            continue;
+        };
+        let addresses: Vec<IpAddr> = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => match tokio::net::lookup_host((host, port)).await {
+                Ok(resolved) => resolved.map(|addr| addr.ip()).collect(),
+                Err(_) => Vec::new(),
+            },
+        };
+        for ip in addresses {
+            if is_publicly_routable(&ip) { public.push(ip) } else { private.push(ip) }
+        }
+    }
+    public.dedup();
+    private.dedup();
+
+    let check = if !public.is_empty() {
+        DiagnosticCheck::pass(CheckCategory::Nat, "nat", format!("Advertising public address {}", public[0]))
+    } else if !private.is_empty() {
+        DiagnosticCheck::warn(
+            CheckCategory::Nat,
+            "nat",
+            format!("Only private addresses are advertised ({}); peers on other networks cannot connect directly", private[0]),
+        )
+        .fix("Set listeners.advertise_host to the public address and forward the listener ports; until then peers fall back to email or relays")
+    } else {
+        DiagnosticCheck::skipped(CheckCategory::Nat, "nat", "No endpoints are advertised")
+    };
+    report.push(check);
+}
+
+/// What the measured NAT type means for peers trying to reach us
+pub(crate) fn check_nat_type(report: &mut DiagnosticsReport, classification: Result<NatClassification>) {
+    let classification = match classification {
+        Ok(classification) => classification,
+        Err(e) => {
+            report.push(
+                DiagnosticCheck::warn(CheckCategory::Nat, "nat.type", format!("Could not classify the NAT: {}", e))
+                    .fix("Check nat.stun_servers and that outbound UDP is allowed"),
+            );
+            return;
+        }
+    };
+    let seen_as = classification.mapped_address.map(|a| format!(", seen as {}", a)).unwrap_or_default();
+    let detail = format!("{:?} via {}{}", classification.nat_type, classification.server, seen_as);
+    let check = match classification.nat_type {
+        NatType::OpenInternet | NatType::FullCone | NatType::RestrictedCone => {
+            DiagnosticCheck::pass(CheckCategory::Nat, "nat.type", detail)
+        }
+        NatType::PortRestrictedCone | NatType::Symmetric => DiagnosticCheck::warn(CheckCategory::Nat, "nat.type", detail)
+            .fix("Hole punching fails with some peers; configure nat.turn_servers or a relay for them"),
+        NatType::UdpBlocked => DiagnosticCheck::fail(CheckCategory::Nat, "nat.type", detail)
+            .fix("Allow outbound UDP, or rely on TCP, relays and email"),
+        NatType::Unknown => DiagnosticCheck::skipped(CheckCategory::Nat, "nat.type", detail)
+            .fix("List a STUN server that reports OTHER-ADDRESS (RFC 5780) first in nat.stun_servers"),
+    };
+    report.push(check);
+}
+
+/// Whether `domain` resolves and publishes an SRV record for our TCP endpoint
+pub(crate) async fn check_dns(
+    report: &mut DiagnosticsReport,
+    domain: &str,
+    tcp_endpoint: Option<&str>,
+    resolver: Option<&dyn DnsResolver>,
+) {
+    match tokio::net::lookup_host((domain, 0)).await {
+        Ok(mut addresses) => match addresses.next() {
+            Some(address) => report.push(DiagnosticCheck::pass(
+                CheckCategory::Dns,
+                "dns.domain",
+                format!("{} resolves to {}", domain, address.ip()),
+            )),
+            None => report.push(DiagnosticCheck::warn(CheckCategory::Dns, "dns.domain", format!("{} has no addresses", domain))),
+        },
+        Err(e) => report.push(
+            DiagnosticCheck::warn(CheckCategory::Dns, "dns.domain", format!("{} does not resolve: {}", domain, e))
+                .fix(format!("Publish an A or AAAA record for {}", domain)),
+        ),
+    }
+
+    let Some(resolver) = resolver else {
+        report.push(
+            DiagnosticCheck::skipped(CheckCategory::Dns, "dns.srv", "No DNS resolver to look up SRV records")
+                .fix("Pass a DnsResolver to set_dns_resolver to check SRV and email records"),
+        );
+        return;
+    };
+    let name = format!("{}.{}", SRV_SERVICE, domain);
+    let (host, port) = tcp_endpoint.and_then(split_endpoint).unwrap_or(("<host>", 0));
+    let publish = format!("Publish `{}. SRV 10 0 {} {}.`", name, port, host);
+    let check = match resolver.lookup_srv(&name).await {
+        Ok(records) if records.is_empty() => {
+            DiagnosticCheck::warn(CheckCategory::Dns, "dns.srv", format!("No SRV record at {}", name)).fix(publish)
+        }
+        Ok(records) if port == 0 || srv_matches(&records, port).next().is_some() => {
+            DiagnosticCheck::pass(CheckCategory::Dns, "dns.srv", format!("{} points at {}:{}", name, records[0].target, records[0].port))
+        }
+        Ok(records) => DiagnosticCheck::warn(
+            CheckCategory::Dns,
+            "dns.srv",
+            format!("{} points at port {}, but TCP listens on {}", name, records[0].port, port),
+        )
+        .fix(publish),
+        Err(e) => DiagnosticCheck::warn(CheckCategory::Dns, "dns.srv", format!("SRV lookup for {} failed: {}", name, e)),
+    };
+    report.push(check);
+}
+
+/// Whether the SMTP server answers and `domain` publishes SPF, DMARC and DKIM records
+pub(crate) async fn check_email(
+    report: &mut DiagnosticsReport,
+    smtp: &SmtpConfig,
+    domain: &str,
+    dkim_selector: Option<&str>,
+    resolver: Option<&dyn DnsResolver>,
+    timeout: Duration,
+) {
+    if smtp.host.is_empty() {
+        report.push(
+            DiagnosticCheck::skipped(CheckCategory::Email, "email.smtp", "No SMTP server configured")
+                .fix("Set email.smtp so messages can fall back to email"),
+        );
+    } else {
+        let server = format!("{}:{}", smtp.host, smtp.port);
+        let check = match tokio::time::timeout(timeout, tokio::net::TcpStream::connect(&server)).await {
+            Ok(Ok(_)) if smtp.username.is_empty() => {
+                DiagnosticCheck::warn(CheckCategory::Email, "email.smtp", format!("{} answers, but no username is set", server))
+                    .fix("Set email.smtp.username and password; most relays refuse unauthenticated mail")
+            }
+            Ok(Ok(_)) => DiagnosticCheck::pass(CheckCategory::Email, "email.smtp", format!("{} answers", server)),
+            Ok(Err(e)) => DiagnosticCheck::fail(CheckCategory::Email, "email.smtp", format!("Cannot connect to {}: {}", server, e))
+                .fix("Check email.smtp.host and port, and that outbound SMTP is not blocked"),
+            Err(_) => DiagnosticCheck::fail(CheckCategory::Email, "email.smtp", format!("No answer from {} within {:?}", server, timeout))
+                .fix("Check email.smtp.host and port, and that outbound SMTP is not blocked"),
+        };
+        report.push(check);
+    }
+
+    let Some(resolver) = resolver else {
+        report.push(DiagnosticCheck::skipped(CheckCategory::Email, "email.auth", "No DNS resolver to look up SPF, DMARC and DKIM"));
+        return;
+    };
+    let spf = match resolver.lookup_txt(domain).await {
+        Ok(records) => match spf_record(&records) {
+            Some(record) => DiagnosticCheck::pass(CheckCategory::Email, "email.spf", record.to_string()),
+            None => DiagnosticCheck::warn(CheckCategory::Email, "email.spf", format!("{} publishes no SPF record", domain))
+                .fix(format!("Publish `{} TXT \"v=spf1 a:{} -all\"`", domain, smtp.host)),
+        },
+        Err(e) => DiagnosticCheck::warn(CheckCategory::Email, "email.spf", format!("TXT lookup for {} failed: {}", domain, e)),
+    };
+    report.push(spf);
+
+    let dmarc_name = format!("_dmarc.{}", domain);
+    let dmarc = match resolver.lookup_txt(&dmarc_name).await {
+        Ok(records) => match dmarc_policy(&records).as_deref() {
+            Some("none") => DiagnosticCheck::warn(CheckCategory::Email, "email.dmarc", "DMARC policy is p=none (monitoring only)")
+                .fix("Move the DMARC policy to p=quarantine once reports look clean"),
+            Some(policy) => DiagnosticCheck::pass(CheckCategory::Email, "email.dmarc", format!("DMARC policy is p={}", policy)),
+            None => DiagnosticCheck::warn(CheckCategory::Email, "email.dmarc", format!("No DMARC record at {}", dmarc_name))
+                .fix(format!("Publish `{} TXT \"v=DMARC1; p=quarantine\"`", dmarc_name)),
+        },
+        Err(e) => DiagnosticCheck::warn(CheckCategory::Email, "email.dmarc", format!("TXT lookup for {} failed: {}", dmarc_name, e)),
+    };
+    report.push(dmarc);
+
+    let Some(selector) = dkim_selector else {
+        report.push(
+            DiagnosticCheck::skipped(CheckCategory::Email, "email.dkim", "No DKIM selector configured")
+                .fix("Set diagnostics.dkim_selector to check the DKIM key"),
+        );
+        return;
+    };
+    let dkim_name = format!("{}._domainkey.{}", selector, domain);
+    let dkim = match resolver.lookup_txt(&dkim_name).await {
+        Ok(records) if has_dkim_key(&records) => {
+            DiagnosticCheck::pass(CheckCategory::Email, "email.dkim", format!("DKIM key published at {}", dkim_name))
+        }
+        Ok(_) => DiagnosticCheck::fail(CheckCategory::Email, "email.dkim", format!("No DKIM key at {}", dkim_name))
+            .fix(format!("Publish the public key of selector {} at {}", selector, dkim_name)),
+        Err(e) => DiagnosticCheck::warn(CheckCategory::Email, "email.dkim", format!("TXT lookup for {} failed: {}", dkim_name, e)),
+    };
+    report.push(dkim);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_orders_by_severity() {
+        let mut report = DiagnosticsReport::new("alice@example.com");
+        report.push(DiagnosticCheck::pass(CheckCategory::Listeners, "listener.tcp", "bound to 0.0.0.0:8080"));
+        report.push(DiagnosticCheck::warn(CheckCategory::Nat, "nat", "private address").fix("set advertise_host"));
+        assert_eq!(report.status(), CheckStatus::Warn);
+        assert!(report.is_healthy());
+
+        report.push(DiagnosticCheck::fail(CheckCategory::Email, "email.smtp", "refused").fix("check smtp.host"));
+        assert!(!report.is_healthy());
+        assert_eq!(report.remediations(), vec!["check smtp.host", "set advertise_host"]);
+        assert_eq!(report.category(CheckCategory::Nat).count(), 1);
+        assert!(report.render_text().contains("[FAIL] email.smtp: refused\n       -> check smtp.host"));
+    }
+
+    #[test]
+    fn classifies_addresses_and_records() {
+        for private in ["10.1.2.3", "192.168.0.5", "172.20.0.1", "100.64.1.1", "127.0.0.1", "fd00::1", "fe80::1"] {
+            assert!(!is_publicly_routable(&private.parse().unwrap()), "{}", private);
+        }
+        for public in ["203.0.113.7", "8.8.8.8", "2001:db8::1"] {
+            assert!(is_publicly_routable(&public.parse().unwrap()), "{}", public);
+        }
+
+        let txt = vec!["google-site-verification=x".to_string(), "v=spf1 mx -all".to_string()];
+        assert_eq!(spf_record(&txt), Some("v=spf1 mx -all"));
+        assert_eq!(dmarc_policy(&["v=DMARC1; p=Reject; rua=mailto:d@example.com".to_string()]).as_deref(), Some("reject"));
+        assert_eq!(dmarc_policy(&txt), None);
+        assert!(has_dkim_key(&["v=DKIM1; k=rsa; p=MIIB".to_string()]));
+
+        let srv = vec![SrvRecord { priority: 10, weight: 0, port: 8080, target: "node.example.com.".into() }];
+        assert_eq!(srv_matches(&srv, 8080).count(), 1);
+        assert_eq!(srv_matches(&srv, 9090).count(), 0);
+    }
+}
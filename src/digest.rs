@@ -0,0 +1,374 @@
+//! # Delivery Digests
+//!
+//! Operators want to know how delivery went without reading logs. A
+//! [`DigestCollector`] follows the router's [`RouterEvent`] stream and tallies,
+//! per period, the messages delivered to and dead-lettered for each peer and
+//! the transports that went down or tripped their circuit breaker. Taking a
+//! [`DeliveryDigest`] closes the period, compares peers' trust scores with the
+//! previous period and starts a new one.
+//!
+//! The router sends digests on a timer (see `DigestConfig`), as a Synapse
+//! message whose `delivery_digest` metadata entry holds the structured report
+//! and whose content is a plain-text rendering, or as plain email.
+
+use crate::admission::TrustSource;
+use crate::config::DigestConfig;
+use crate::error::Result;
+use crate::events::RouterEvent;
+use crate::types::{MessageType, SimpleMessage};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Metadata key holding the JSON form of a delivery digest
+pub const DIGEST_KEY: &str = "delivery_digest";
+
+/// Delivery to one peer during a period
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerDeliveryStats {
+    pub peer: String,
+    pub delivered: u64,
+    pub dead_lettered: u64,
+    /// Messages delivered per transport
+    pub transports: BTreeMap<String, u64>,
+}
+
+impl PeerDeliveryStats {
+    /// Share of messages delivered, 1.0 when nothing was sent
+    pub fn success_rate(&self) -> f64 {
+        let total = self.delivered + self.dead_lettered;
+        if total == 0 { 1.0 } else { self.delivered as f64 / total as f64 }
+    }
+}
+
+/// How often a transport went down during a period
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportFlaps {
+    pub transport: String,
+    pub downs: u32,
+    pub ups: u32,
+    pub circuit_opens: u32,
+}
+
+/// A trust score that moved by at least the configured threshold
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustChange {
+    pub peer: String,
+    pub previous: f64,
+    pub current: f64,
+}
+
+/// Delivery report for one period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryDigest {
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    /// Busiest peers first
+    pub peers: Vec<PeerDeliveryStats>,
+    /// Peers with traffic left out of `peers`
+    pub omitted_peers: usize,
+    pub delivered: u64,
+    /// Dead letters during the period
+    pub dead_lettered: u64,
+    /// Dead letters since the collector started
+    pub dead_lettered_total: u64,
+    /// Transports that went down or tripped their circuit breaker
+    pub flaps: Vec<TransportFlaps>,
+    pub trust_changes: Vec<TrustChange>,
+}
+
+impl DeliveryDigest {
+    /// Whether nothing worth reporting happened
+    pub fn is_empty(&self) -> bool {
+        self.delivered == 0 && self.dead_lettered == 0 && self.flaps.is_empty() && self.trust_changes.is_empty()
+    }
+
+    /// One-line summary, used as the email subject
+    pub fn subject(&self) -> String {
+        format!(
+            "Synapse delivery digest: {} delivered, {} dead-lettered",
+            self.delivered, self.dead_lettered
+        )
+    }
+
+    /// Plain-text rendering for operators
+    pub fn render_text(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "Delivery digest {} to {}",
+                self.period_start.format("%Y-%m-%d %H:%M"),
+                self.period_end.format("%Y-%m-%d %H:%M UTC")
+            ),
+            format!(
+                "{} delivered, {} dead-lettered ({} since start)",
+                self.delivered, self.dead_lettered, self.dead_lettered_total
+            ),
+        ];
+        if !self.peers.is_empty() {
+            lines.push(String::new());
+            lines.push("Peers:".to_string());
+            for peer in &self.peers {
+                let transports: Vec<String> = peer.transports.iter().map(|(t, n)| format!("{} {}", t, n)).collect();
+                let mut line = format!(
+                    "  {}: {} delivered, {} dead-lettered ({:.1}%)",
+                    peer.peer,
+                    peer.delivered,
+                    peer.dead_lettered,
+                    peer.success_rate() * 100.0
+                );
+                if !transports.is_empty() {
+                    line.push_str(&format!(" via {}", transports.join(", ")));
+                }
+                lines.push(line);
+            }
+            if self.omitted_peers > 0 {
+                lines.push(format!("  ...and {} more", self.omitted_peers));
+            }
+        }
+        if !self.flaps.is_empty() {
+            lines.push(String::new());
+            lines.push("Transport flaps:".to_string());
+            for flap in &self.flaps {
+                lines.push(format!(
+                    "  {}: down {}, up {}, circuit opened {}",
+                    flap.transport, flap.downs, flap.ups, flap.circuit_opens
+                ));
+            }
+        }
+        if !self.trust_changes.is_empty() {
+            lines.push(String::new());
+            lines.push("Trust changes:".to_string());
+            for change in &self.trust_changes {
+                lines.push(format!(
+                    "  {}: {:.1} -> {:.1} ({:+.1})",
+                    change.peer,
+                    change.previous,
+                    change.current,
+                    change.current - change.previous
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Wrap the digest in a direct message from `from_entity` to `to_entity`
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(DIGEST_KEY.to_string(), serde_json::to_string(self)?);
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: self.render_text(),
+            message_type: MessageType::Direct,
+            metadata,
+        })
+    }
+
+    /// Extract the digest carried by `message`, if any
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        serde_json::from_str(message.metadata.get(DIGEST_KEY)?).ok()
+    }
+}
+
+struct Period {
+    started_at: DateTime<Utc>,
+    peers: HashMap<String, PeerDeliveryStats>,
+    flaps: HashMap<String, TransportFlaps>,
+    dead_lettered: u64,
+}
+
+impl Period {
+    fn new() -> Self {
+        Self {
+            started_at: Utc::now(),
+            peers: HashMap::new(),
+            flaps: HashMap::new(),
+            dead_lettered: 0,
+        }
+    }
+
+    fn peer(&mut self, peer: &str) -> &mut PeerDeliveryStats {
+        self.peers.entry(peer.to_string()).or_insert_with(|| PeerDeliveryStats {
+            peer: peer.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn transport(&mut self, transport: &str) -> &mut TransportFlaps {
+        self.flaps.entry(transport.to_string()).or_insert_with(|| TransportFlaps {
+            transport: transport.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Tallies router events into periodic delivery digests
+pub struct DigestCollector {
+    config: DigestConfig,
+    period: Mutex<Period>,
+    dead_lettered_total: AtomicU64,
+    /// Score per peer at the last digest, the baseline for trust changes
+    trust_scores: DashMap<String, f64>,
+    trust: RwLock<Option<Arc<dyn TrustSource>>>,
+}
+
+impl DigestCollector {
+    pub fn new(config: DigestConfig) -> Self {
+        Self {
+            config,
+            period: Mutex::new(Period::new()),
+            dead_lettered_total: AtomicU64::new(0),
+            trust_scores: DashMap::new(),
+            trust: RwLock::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &DigestConfig {
+        &self.config
+    }
+
+    /// Report trust-score changes looked up in `trust`
+    pub fn set_trust_source(&self, trust: Arc<dyn TrustSource>) {
+        *self.trust.write().unwrap() = Some(trust);
+    }
+
+    /// Count an event towards the current period
+    pub fn observe(&self, event: &RouterEvent) {
+        let mut period = self.period.lock().unwrap();
+        match event {
+            RouterEvent::MessageDelivered { to, transport, .. } => {
+                let peer = period.peer(to);
+                peer.delivered += 1;
+                *peer.transports.entry(transport.clone()).or_default() += 1;
+            }
+            RouterEvent::MessageDeadLettered { to, .. } => {
+                period.peer(to).dead_lettered += 1;
+                period.dead_lettered += 1;
+                self.dead_lettered_total.fetch_add(1, Ordering::Relaxed);
+            }
+            RouterEvent::TransportDown { transport, .. } => period.transport(transport).downs += 1,
+            RouterEvent::TransportUp { transport } => period.transport(transport).ups += 1,
+            RouterEvent::CircuitOpened { transport, .. } => period.transport(transport).circuit_opens += 1,
+            _ => {}
+        }
+    }
+
+    /// Close the current period and report on it
+    ///
+    /// Trust scores are looked up for every peer with traffic this period or
+    /// a known earlier score; a peer's first score only sets its baseline.
+    pub async fn take(&self) -> DeliveryDigest {
+        let period = std::mem::replace(&mut *self.period.lock().unwrap(), Period::new());
+        let trust_changes = self.trust_changes(period.peers.keys()).await;
+
+        let mut peers: Vec<PeerDeliveryStats> = period.peers.into_values().collect();
+        peers.sort_by(|a, b| {
+            (b.delivered + b.dead_lettered)
+                .cmp(&(a.delivered + a.dead_lettered))
+                .then_with(|| a.peer.cmp(&b.peer))
+        });
+        let delivered = peers.iter().map(|peer| peer.delivered).sum();
+        let omitted_peers = peers.len().saturating_sub(self.config.max_peers);
+        peers.truncate(self.config.max_peers);
+
+        // A transport that only came up is not flapping
+        let mut flaps: Vec<TransportFlaps> = period
+            .flaps
+            .into_values()
+            .filter(|flap| flap.downs > 0 || flap.circuit_opens > 0)
+            .collect();
+        flaps.sort_by(|a, b| a.transport.cmp(&b.transport));
+
+        DeliveryDigest {
+            period_start: period.started_at,
+            period_end: Utc::now(),
+            peers,
+            omitted_peers,
+            delivered,
+            dead_lettered: period.dead_lettered,
+            dead_lettered_total: self.dead_lettered_total.load(Ordering::Relaxed),
+            flaps,
+            trust_changes,
+        }
+    }
+
+    async fn trust_changes<'a>(&self, active: impl Iterator<Item = &'a String>) -> Vec<TrustChange> {
+        let Some(trust) = self.trust.read().unwrap().clone() else {
+            return Vec::new();
+        };
+        let mut peers: BTreeSet<String> = active.cloned().collect();
+        peers.extend(self.trust_scores.iter().map(|entry| entry.key().clone()));
+
+        let mut changes = Vec::new();
+        for peer in peers {
+            let Some(current) = trust.network_trust(&peer).await else {
+                continue;
+            };
+            if let Some(previous) = self.trust_scores.insert(peer.clone(), current) {
+                if (current - previous).abs() >= self.config.trust_change_threshold {
+                    changes.push(TrustChange { peer, previous, current });
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct Scores(DashMap<String, f64>);
+
+    #[async_trait]
+    impl TrustSource for Scores {
+        async fn network_trust(&self, global_id: &str) -> Option<f64> {
+            self.0.get(global_id).map(|score| *score)
+        }
+    }
+
+    fn delivered(to: &str, transport: &str) -> RouterEvent {
+        RouterEvent::MessageDelivered { message_id: "m".into(), to: to.into(), transport: transport.into() }
+    }
+
+    #[tokio::test]
+    async fn digest_tallies_a_period_and_starts_the_next() {
+        let collector = DigestCollector::new(DigestConfig { max_peers: 1, ..Default::default() });
+        let scores = Arc::new(Scores(DashMap::new()));
+        scores.0.insert("bob".into(), 70.0);
+        collector.set_trust_source(scores.clone());
+
+        collector.observe(&delivered("alice", "TCP"));
+        collector.observe(&delivered("alice", "email"));
+        collector.observe(&delivered("bob", "TCP"));
+        collector.observe(&RouterEvent::MessageDeadLettered { message_id: "x".into(), to: "alice".into(), reason: "offline".into() });
+        collector.observe(&RouterEvent::TransportUp { transport: "QUIC".into() });
+        collector.observe(&RouterEvent::TransportDown { transport: "TCP".into(), reason: "reset".into() });
+
+        let digest = collector.take().await;
+        assert_eq!((digest.delivered, digest.dead_lettered, digest.omitted_peers), (3, 1, 1));
+        assert_eq!(digest.peers[0].peer, "alice");
+        assert_eq!(digest.peers[0].transports["TCP"], 1);
+        assert!((digest.peers[0].success_rate() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(digest.flaps.len(), 1);
+        assert_eq!(digest.flaps[0].transport, "TCP");
+        // First score only sets the baseline
+        assert!(digest.trust_changes.is_empty());
+
+        let message = digest.to_message("router", "ops").unwrap();
+        assert!(message.content.contains("alice: 2 delivered, 1 dead-lettered (66.7%)"));
+        assert_eq!(DeliveryDigest::from_message(&message).unwrap().delivered, 3);
+
+        scores.0.insert("bob".into(), 52.5);
+        let digest = collector.take().await;
+        assert_eq!(digest.dead_lettered_total, 1);
+        assert_eq!(digest.trust_changes, vec![TrustChange { peer: "bob".into(), previous: 70.0, current: 52.5 }]);
+        assert!(digest.render_text().contains("bob: 70.0 -> 52.5 (-17.5)"));
+        assert!(!digest.is_empty());
+        assert!(collector.take().await.is_empty());
+    }
+}
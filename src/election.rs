@@ -0,0 +1,539 @@
+//! # Leader Election
+//!
+//! Two relay nodes can serve one identity for availability, but only one may
+//! act on it at a time. Each node campaigns for a lease in a [`LeaseStore`]
+//! they share; the holder is the active node and renews the lease every
+//! `renew_interval_ms`, and a standby takes it over once it has gone
+//! `lease_ttl_ms` without renewal (see
+//! [`ElectionConfig`](crate::config::ElectionConfig)). A node that cannot
+//! reach the store steps down when its lease may have lapsed, whether or not
+//! another node has taken it.
+//!
+//! Every change of holder hands out a higher fencing token. A leader that
+//! was paused past its lease and wakes up still carries its old token, so a
+//! resource shared by the nodes that keeps a [`Fence`] refuses its writes
+//! once the successor's have been admitted.
+//!
+//! Changes of leadership are published as `RouterEvent::LeadershipAcquired`
+//! and `RouterEvent::LeadershipLost`, and can be watched with
+//! [`LeaderElection::subscribe`].
+//!
+//! [`MemoryLeaseStore`] serves nodes within one process (and tests);
+//! [`RedisLeaseStore`] (feature `cache`) serves separate processes.
+
+use crate::config::ElectionConfig;
+use crate::error::{Result, SynapseError};
+use crate::events::{EventBus, RouterEvent};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// A node's claim to be the active one
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaderLease {
+    /// Lease the nodes compete for
+    pub name: String,
+    /// Candidate holding it
+    pub holder: String,
+    /// Raised each time the lease changes hands or lapses
+    pub token: u64,
+    /// When the lease lapses unless renewed, in Unix milliseconds
+    pub expires_at_ms: i64,
+}
+
+impl LeaderLease {
+    pub fn is_live_at(&self, now_ms: i64) -> bool {
+        self.expires_at_ms > now_ms
+    }
+}
+
+/// Storage the candidates for a lease share
+#[async_trait]
+pub trait LeaseStore: Send + Sync {
+    /// Take or renew `name` for `holder` until `now_ms + ttl_ms`, unless
+    /// another holder's lease is live; returns the lease in force afterwards
+    async fn acquire_lease(&self, name: &str, holder: &str, ttl_ms: u64, now_ms: i64) -> Result<LeaderLease>;
+
+    /// Give up `name` if `holder` still holds it under `token`
+    async fn release_lease(&self, name: &str, holder: &str, token: u64) -> Result<bool>;
+
+    /// The live lease on `name`, if any
+    async fn lease(&self, name: &str, now_ms: i64) -> Result<Option<LeaderLease>>;
+}
+
+#[derive(Default)]
+struct LeaseSlot {
+    lease: Option<LeaderLease>,
+    last_token: u64,
+}
+
+/// In-memory lease store, for candidates within one process
+#[derive(Default)]
+pub struct MemoryLeaseStore {
+    slots: Mutex<HashMap<String, LeaseSlot>>,
+}
+
+impl MemoryLeaseStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl LeaseStore for MemoryLeaseStore {
+    async fn acquire_lease(&self, name: &str, holder: &str, ttl_ms: u64, now_ms: i64) -> Result<LeaderLease> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots.entry(name.to_string()).or_default();
+        let token = match slot.lease {
+            Some(ref lease) if lease.is_live_at(now_ms) && lease.holder != holder => return Ok(lease.clone()),
+            Some(ref lease) if lease.is_live_at(now_ms) => lease.token,
+            _ => {
+                slot.last_token += 1;
+                slot.last_token
+            }
+        };
+        let lease = LeaderLease {
+            name: name.to_string(),
+            holder: holder.to_string(),
+            token,
+            expires_at_ms: now_ms + ttl_ms as i64,
+        };
+        slot.lease = Some(lease.clone());
+        Ok(lease)
+    }
+
+    async fn release_lease(&self, name: &str, holder: &str, token: u64) -> Result<bool> {
+        let mut slots = self.slots.lock().unwrap();
+        let Some(slot) = slots.get_mut(name) else {
+            return Ok(false);
+        };
+        if slot.lease.as_ref().is_some_and(|lease| lease.holder == holder && lease.token == token) {
+            slot.lease = None;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn lease(&self, name: &str, now_ms: i64) -> Result<Option<LeaderLease>> {
+        let slots = self.slots.lock().unwrap();
+        Ok(slots
+            .get(name)
+            .and_then(|slot| slot.lease.clone())
+            .filter(|lease| lease.is_live_at(now_ms)))
+    }
+}
+
+// KEYS[1] lease, KEYS[2] last token; ARGV holder, ttl_ms, now_ms, name
+#[cfg(feature = "cache")]
+const ACQUIRE_LEASE: &str = r#"
+local now = tonumber(ARGV[3])
+local token
+local current = redis.call('GET', KEYS[1])
+if current then
+    local lease = cjson.decode(current)
+    if lease.expires_at_ms > now then
+        if lease.holder ~= ARGV[1] then
+            return current
+        end
+        token = lease.token
+    end
+end
+if not token then
+    token = redis.call('INCR', KEYS[2])
+end
+local lease = cjson.encode({name = ARGV[4], holder = ARGV[1], token = token, expires_at_ms = now + tonumber(ARGV[2])})
+redis.call('SET', KEYS[1], lease, 'PX', ARGV[2])
+return lease
+"#;
+
+// KEYS[1] lease; ARGV holder, token
+#[cfg(feature = "cache")]
+const RELEASE_LEASE: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if current then
+    local lease = cjson.decode(current)
+    if lease.holder == ARGV[1] and lease.token == tonumber(ARGV[2]) then
+        redis.call('DEL', KEYS[1])
+        return 1
+    end
+end
+return 0
+"#;
+
+/// Redis-backed lease store, for candidates in separate processes
+#[cfg(feature = "cache")]
+pub struct RedisLeaseStore {
+    client: redis::Client,
+    prefix: String,
+}
+
+#[cfg(feature = "cache")]
+impl RedisLeaseStore {
+    /// Connect to Redis; keys are namespaced under `prefix`
+    pub fn new(redis_url: &str, prefix: impl Into<String>) -> Result<Self> {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| SynapseError::ConfigurationError(format!("Invalid election Redis URL: {}", e)))?;
+        Ok(Self { client, prefix: prefix.into() })
+    }
+
+    fn key(&self, parts: &[&str]) -> String {
+        format!("{}:{}", self.prefix, parts.join(":"))
+    }
+
+    async fn connection(&self) -> Result<redis::aio::MultiplexedConnection> {
+        self.client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(Self::error)
+    }
+
+    fn error(e: redis::RedisError) -> SynapseError {
+        SynapseError::DatabaseError(format!("Lease store: {}", e))
+    }
+}
+
+#[cfg(feature = "cache")]
+#[async_trait]
+impl LeaseStore for RedisLeaseStore {
+    async fn acquire_lease(&self, name: &str, holder: &str, ttl_ms: u64, now_ms: i64) -> Result<LeaderLease> {
+        let mut conn = self.connection().await?;
+        let lease: String = redis::Script::new(ACQUIRE_LEASE)
+            .key(self.key(&["lease", name]))
+            .key(self.key(&["token", name]))
+            .arg(holder)
+            .arg(ttl_ms.max(1))
+            .arg(now_ms)
+            .arg(name)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        Ok(serde_json::from_str(&lease)?)
+    }
+
+    async fn release_lease(&self, name: &str, holder: &str, token: u64) -> Result<bool> {
+        let mut conn = self.connection().await?;
+        let released: i64 = redis::Script::new(RELEASE_LEASE)
+            .key(self.key(&["lease", name]))
+            .arg(holder)
+            .arg(token)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        Ok(released == 1)
+    }
+
+    async fn lease(&self, name: &str, now_ms: i64) -> Result<Option<LeaderLease>> {
+        let mut conn = self.connection().await?;
+        let stored: Option<String> = redis::cmd("GET")
+            .arg(self.key(&["lease", name]))
+            .query_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        Ok(stored
+            .and_then(|json| serde_json::from_str::<LeaderLease>(&json).ok())
+            .filter(|lease| lease.is_live_at(now_ms)))
+    }
+}
+
+/// This node's role for a lease
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum Leadership {
+    /// We hold the lease under `token`
+    Leader { token: u64 },
+    /// Another node, or none we know of, holds it
+    Follower { leader: Option<String> },
+}
+
+/// This node's candidacy for a leader lease
+pub struct LeaderElection {
+    name: String,
+    instance_id: String,
+    store: Arc<dyn LeaseStore>,
+    ttl: Duration,
+    renew_interval: Duration,
+    events: Option<EventBus>,
+    /// Our token and when, by our clock, our lease may have lapsed
+    held: Mutex<Option<(u64, Instant)>>,
+    state: watch::Sender<Leadership>,
+    resigned: AtomicBool,
+}
+
+impl LeaderElection {
+    /// Campaign for the configured lease in `store`
+    pub fn new(config: &ElectionConfig, store: Arc<dyn LeaseStore>) -> Self {
+        let instance_id = match config.instance_id.as_str() {
+            "" => Uuid::new_v4().to_string(),
+            id => id.to_string(),
+        };
+        Self {
+            name: config.name.clone(),
+            instance_id,
+            store,
+            ttl: Duration::from_millis(config.lease_ttl_ms.max(1)),
+            renew_interval: Duration::from_millis(config.renew_interval_ms.max(1)),
+            events: None,
+            held: Mutex::new(None),
+            state: watch::channel(Leadership::Follower { leader: None }).0,
+            resigned: AtomicBool::new(false),
+        }
+    }
+
+    /// Campaign in the store the configuration names
+    ///
+    /// Uses Redis when `redis_url` is set, which requires the `cache` feature.
+    pub fn from_config(config: &ElectionConfig) -> Result<Self> {
+        let store: Arc<dyn LeaseStore> = match &config.redis_url {
+            #[cfg(feature = "cache")]
+            Some(url) => Arc::new(RedisLeaseStore::new(url, "synapse:election")?),
+            #[cfg(not(feature = "cache"))]
+            Some(_) => {
+                return Err(SynapseError::ConfigurationError(
+                    "A Redis lease store requires the `cache` feature".to_string(),
+                ))
+            }
+            None => Arc::new(MemoryLeaseStore::new()),
+        };
+        Ok(Self::new(config, store))
+    }
+
+    /// Publish leadership changes on `events`
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Lease campaigned for
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Our candidate ID
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Take or renew the lease once
+    ///
+    /// On a store error we remain leader until our lease may have lapsed.
+    pub async fn campaign(&self) -> Result<Leadership> {
+        if self.resigned.load(Ordering::Acquire) {
+            return Ok(Leadership::Follower { leader: None });
+        }
+        let started = Instant::now();
+        let ttl_ms = self.ttl.as_millis() as u64;
+        let outcome = self
+            .store
+            .acquire_lease(&self.name, &self.instance_id, ttl_ms, Utc::now().timestamp_millis())
+            .await;
+        let leadership = match outcome {
+            Ok(ref lease) if lease.holder == self.instance_id => {
+                *self.held.lock().unwrap() = Some((lease.token, started + self.ttl));
+                Leadership::Leader { token: lease.token }
+            }
+            Ok(ref lease) => {
+                *self.held.lock().unwrap() = None;
+                Leadership::Follower { leader: Some(lease.holder.clone()) }
+            }
+            Err(_) => match self.fencing_token() {
+                Some(token) => Leadership::Leader { token },
+                None => {
+                    *self.held.lock().unwrap() = None;
+                    Leadership::Follower { leader: None }
+                }
+            },
+        };
+        self.transition(leadership.clone());
+        outcome.map(|_| leadership)
+    }
+
+    /// Whether we hold the lease and it cannot have lapsed yet
+    pub fn is_leader(&self) -> bool {
+        self.fencing_token().is_some()
+    }
+
+    /// Token of the lease we hold, to present to shared resources
+    pub fn fencing_token(&self) -> Option<u64> {
+        match *self.held.lock().unwrap() {
+            Some((token, until)) if until > Instant::now() => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Fail with [`SynapseError::NotLeader`] unless we are the active node
+    pub fn ensure_leader(&self) -> Result<()> {
+        if self.is_leader() {
+            return Ok(());
+        }
+        let leader = match *self.state.borrow() {
+            Leadership::Follower { leader: Some(ref leader) } => leader.clone(),
+            _ => "unknown".to_string(),
+        };
+        Err(SynapseError::NotLeader {
+            lease: self.name.clone(),
+            leader,
+        })
+    }
+
+    /// Our role as of the last campaign
+    pub fn leadership(&self) -> Leadership {
+        self.state.borrow().clone()
+    }
+
+    /// Watch our role change
+    pub fn subscribe(&self) -> watch::Receiver<Leadership> {
+        self.state.subscribe()
+    }
+
+    /// Campaign every `renew_interval_ms` until we resign
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        info!("Campaigning for lease {} as {}", self.name, self.instance_id);
+        tokio::spawn(async move {
+            while !self.resigned.load(Ordering::Acquire) {
+                if let Err(e) = self.campaign().await {
+                    warn!("Could not renew lease {}: {}", self.name, e);
+                }
+                // Wake by the time our lease may lapse, to step down if it
+                // could not be renewed
+                let held_until = self.held.lock().unwrap().map(|(_, until)| until);
+                let wait = match held_until {
+                    Some(until) => self.renew_interval.min(until.saturating_duration_since(Instant::now())),
+                    None => self.renew_interval,
+                };
+                tokio::time::sleep(wait).await;
+            }
+        })
+    }
+
+    /// Stop campaigning and give up the lease, so a standby takes over at once
+    pub async fn resign(&self) -> Result<()> {
+        self.resigned.store(true, Ordering::Release);
+        let held = self.held.lock().unwrap().take();
+        self.transition(Leadership::Follower { leader: None });
+        if let Some((token, _)) = held {
+            self.store.release_lease(&self.name, &self.instance_id, token).await?;
+            info!("Gave up lease {}", self.name);
+        }
+        Ok(())
+    }
+
+    fn transition(&self, leadership: Leadership) {
+        let previous = self.state.send_replace(leadership.clone());
+        let event = match (previous, leadership) {
+            (Leadership::Leader { token: before }, Leadership::Leader { token }) if before == token => return,
+            (_, Leadership::Leader { token }) => {
+                info!("Now the active node for {} (fencing token {})", self.name, token);
+                RouterEvent::LeadershipAcquired {
+                    lease: self.name.clone(),
+                    instance: self.instance_id.clone(),
+                    fencing_token: token,
+                }
+            }
+            (Leadership::Leader { .. }, Leadership::Follower { leader }) => {
+                warn!("No longer the active node for {}; leader is {:?}", self.name, leader);
+                RouterEvent::LeadershipLost {
+                    lease: self.name.clone(),
+                    instance: self.instance_id.clone(),
+                    leader,
+                }
+            }
+            (Leadership::Follower { .. }, Leadership::Follower { .. }) => return,
+        };
+        if let Some(ref events) = self.events {
+            events.publish(event);
+        }
+    }
+}
+
+/// Refuses work from leaders older than the newest one seen
+///
+/// A resource shared by active and standby nodes keeps a fence and admits
+/// each write with the writer's fencing token.
+#[derive(Debug, Default)]
+pub struct Fence {
+    highest: AtomicU64,
+}
+
+impl Fence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Admit work carrying `token`, unless a newer leader's was admitted
+    pub fn admit(&self, token: u64) -> Result<()> {
+        let current = self.highest.fetch_max(token, Ordering::AcqRel);
+        if token < current {
+            return Err(SynapseError::Fenced { token, current });
+        }
+        Ok(())
+    }
+
+    /// Highest token admitted so far
+    pub fn highest(&self) -> u64 {
+        self.highest.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn standby_takes_over_a_lapsed_lease_with_a_higher_token() {
+        let store: Arc<dyn LeaseStore> = Arc::new(MemoryLeaseStore::new());
+        let candidate = |id: &str| {
+            let config = ElectionConfig {
+                enabled: true,
+                instance_id: id.to_string(),
+                lease_ttl_ms: 100,
+                renew_interval_ms: 20,
+                ..ElectionConfig::default()
+            };
+            LeaderElection::new(&config, Arc::clone(&store))
+        };
+        let events = EventBus::default();
+        let mut published = events.subscribe();
+        let primary = candidate("primary").with_event_bus(events);
+        let standby = candidate("standby");
+
+        assert_eq!(primary.campaign().await.unwrap(), Leadership::Leader { token: 1 });
+        assert_eq!(primary.campaign().await.unwrap(), Leadership::Leader { token: 1 });
+        assert_eq!(
+            standby.campaign().await.unwrap(),
+            Leadership::Follower { leader: Some("primary".to_string()) }
+        );
+        assert!(matches!(standby.ensure_leader(), Err(SynapseError::NotLeader { ref leader, .. }) if leader == "primary"));
+        let fence = Fence::new();
+        fence.admit(primary.fencing_token().unwrap()).unwrap();
+
+        // The primary stops renewing; once its lease lapses the standby takes over
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert!(!primary.is_leader());
+        assert_eq!(standby.campaign().await.unwrap(), Leadership::Leader { token: 2 });
+        fence.admit(2).unwrap();
+        assert!(matches!(fence.admit(1), Err(SynapseError::Fenced { token: 1, current: 2 })));
+
+        assert_eq!(
+            primary.campaign().await.unwrap(),
+            Leadership::Follower { leader: Some("standby".to_string()) }
+        );
+        assert_eq!(published.recv().await.unwrap().event.name(), "leadership_acquired");
+        assert!(matches!(
+            published.recv().await.unwrap().event,
+            RouterEvent::LeadershipLost { leader: Some(ref leader), .. } if leader == "standby"
+        ));
+
+        // Resigning hands the lease over without waiting for it to lapse
+        standby.resign().await.unwrap();
+        assert_eq!(primary.campaign().await.unwrap(), Leadership::Leader { token: 3 });
+        assert_eq!(standby.campaign().await.unwrap(), Leadership::Follower { leader: None });
+    }
+}
@@ -7,9 +7,17 @@ use crate::{
 use std::string::ToString;
 use std::collections::HashMap;
 
+#[cfg(feature = "email")]
+use crate::types::{Attachment, AttachmentContent};
+#[cfg(feature = "email")]
+use crate::notifications::Notification;
+#[cfg(feature = "email")]
+use crate::{config::EmailSendingConfig, email_throttle::SendPacer};
+#[cfg(feature = "email")]
+use std::sync::Arc;
 #[cfg(feature = "email")]
 use lettre::{
-    message::{header, Mailbox, Message, SinglePart},
+    message::{header, Attachment as MimeAttachment, Mailbox, Message, MultiPart, SinglePart},
     transport::smtp::authentication::Credentials,
     SmtpTransport, Transport,
 };
@@ -22,6 +30,10 @@ use base64::Engine as _;
 pub struct EmailTransport {
     config: EmailConfig,
     smtp_transport: SmtpTransport,
+    /// Proxy that SMTP traffic is required to use, if any
+    required_proxy: Option<String>,
+    /// Keeps sends within the provider's quotas
+    pacer: Arc<SendPacer>,
 }
 
 /// Dummy email transport for when email feature is disabled
@@ -41,7 +53,7 @@ impl EmailTransport {
                 .port(config.smtp.port)
                 .credentials(Credentials::new(
                     config.smtp.username.clone(),
-                    config.smtp.password.clone(),
+                    config.smtp.password.expose().clone(),
                 ))
                 .build()
         } else {
@@ -50,7 +62,7 @@ impl EmailTransport {
                 .port(config.smtp.port)
                 .credentials(Credentials::new(
                     config.smtp.username.clone(),
-                    config.smtp.password.clone(),
+                    config.smtp.password.expose().clone(),
                 ));
 
             if config.smtp.use_tls {
@@ -60,23 +72,75 @@ impl EmailTransport {
             }
         };
 
+        let pacer = Arc::new(SendPacer::from_config(&EmailSendingConfig::default(), &config.smtp.host));
         Ok(Self {
             config,
             smtp_transport,
+            required_proxy: None,
+            pacer,
         })
     }
 
+    /// Require SMTP traffic to go through the given proxy
+    ///
+    /// The SMTP client cannot tunnel through a proxy, so once a proxy is
+    /// required direct sends are refused instead of leaking around it.
+    pub fn require_proxy(&mut self, proxy_url: String) {
+        self.required_proxy = Some(proxy_url);
+    }
+
+    /// Pace sends by `settings` instead of the detected provider's defaults
+    pub fn set_sending_limits(&mut self, settings: &EmailSendingConfig) {
+        self.pacer = Arc::new(SendPacer::from_config(settings, &self.config.smtp.host));
+    }
+
     /// Send an EMRP message via email
     pub async fn send_message(&self, secure_msg: &SecureMessage, from_email: &str, to_email: &str, simple_msg: &SimpleMessage) -> Result<()> {
+        if let Some(proxy) = &self.required_proxy {
+            return Err(EmailError::SendFailed(format!(
+                "SMTP to {} must use proxy {}, but SMTP proxying is not supported",
+                self.config.smtp.host, proxy
+            )));
+        }
+        
         let email_message = self.create_email_message(secure_msg, from_email, to_email, simple_msg)?;
         
-        let _response = self.smtp_transport.send(&email_message)
-            .map_err(|e| EmailError::SendFailed(e.to_string()))?;
+        self.deliver(&email_message).await?;
 
         tracing::debug!("Email sent successfully");
         Ok(())
     }
 
+    /// Send an already-built email, such as a gateway reply to a plain email correspondent
+    pub async fn send_email(&self, email: &Message) -> Result<()> {
+        if let Some(proxy) = &self.required_proxy {
+            return Err(EmailError::SendFailed(format!(
+                "SMTP to {} must use proxy {}, but SMTP proxying is not supported",
+                self.config.smtp.host, proxy
+            )));
+        }
+
+        self.deliver(email).await
+    }
+
+    /// Send through the pacer, retrying with backoff while the server defers
+    async fn deliver(&self, email: &Message) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            let _permit = self.pacer.acquire().await?;
+            match self.smtp_transport.send(email) {
+                Ok(_) => return Ok(()),
+                // 4xx replies (421, 450, 451, 454) are how providers throttle
+                Err(e) if e.is_transient() && attempt < self.pacer.backoff().max_retries => {
+                    tracing::debug!("SMTP send deferred ({}), retrying", e);
+                    self.pacer.deferred(attempt);
+                    attempt += 1;
+                }
+                Err(e) => return Err(EmailError::SendFailed(e.to_string())),
+            }
+        }
+    }
+
     /// Create an email message with EMRP headers
     fn create_email_message(&self, secure_msg: &SecureMessage, from_email: &str, to_email: &str, simple_msg: &SimpleMessage) -> Result<Message> {
         let from_mailbox = from_email.parse::<Mailbox>()
@@ -85,7 +149,12 @@ impl EmailTransport {
         let to_mailbox = to_email.parse::<Mailbox>()
             .map_err(|e| EmailError::InvalidFormat(format!("Invalid to address: {}", e)))?;
 
-        let subject = self.generate_subject(simple_msg);
+        // A notification's rendering would leak what encryption is hiding
+        let notification = Notification::from_message(simple_msg).filter(|_| secure_msg.encrypted_content.is_empty());
+        let subject = match &notification {
+            Some(notification) => notification.subject(),
+            None => self.generate_subject(simple_msg),
+        };
         
         // Create message without custom headers first (lettre doesn't support arbitrary headers well)
         let body_content = if secure_msg.encrypted_content.is_empty() {
@@ -95,14 +164,51 @@ impl EmailTransport {
             base64::engine::general_purpose::STANDARD.encode(&secure_msg.encrypted_content)
         };
 
-        let message = Message::builder()
+        let builder = Message::builder()
             .from(from_mailbox)
             .to(to_mailbox)
-            .subject(subject)
-            .singlepart(SinglePart::builder()
-                .header(header::ContentType::TEXT_PLAIN)
-                .body(body_content))
-            .map_err(|e| EmailError::InvalidFormat(e.to_string()))?;
+            .subject(subject);
+        let text = SinglePart::builder()
+            .header(header::ContentType::TEXT_PLAIN)
+            .body(body_content.clone());
+        // Mail clients show the rendered notification; Synapse nodes read the envelope
+        let alternative = notification.map(|notification| {
+            MultiPart::alternative()
+                .singlepart(
+                    SinglePart::builder()
+                        .header(header::ContentType::TEXT_PLAIN)
+                        .body(notification.render_text()),
+                )
+                .singlepart(
+                    SinglePart::builder()
+                        .header(header::ContentType::parse("application/json").unwrap())
+                        .body(body_content),
+                )
+        });
+
+        let message = if secure_msg.attachments.is_empty() {
+            match alternative {
+                Some(alternative) => builder.multipart(alternative),
+                None => builder.singlepart(text),
+            }
+        } else {
+            // Inline attachments become MIME parts; streamed ones are already on their way
+            let mut parts = match alternative {
+                Some(alternative) => MultiPart::mixed().multipart(alternative),
+                None => MultiPart::mixed().singlepart(text),
+            };
+            for attachment in &secure_msg.attachments {
+                if let AttachmentContent::Inline { data } = &attachment.content {
+                    let content_type = header::ContentType::parse(&attachment.mime_type)
+                        .unwrap_or_else(|_| header::ContentType::parse("application/octet-stream").unwrap());
+                    parts = parts.singlepart(
+                        MimeAttachment::new(attachment.name.clone()).body(data.clone(), content_type),
+                    );
+                }
+            }
+            builder.multipart(parts)
+        }
+        .map_err(|e| EmailError::InvalidFormat(e.to_string()))?;
 
         Ok(message)
     }
@@ -185,7 +291,7 @@ impl EmailTransport {
     /// Check if SMTP is properly configured
     pub fn is_smtp_configured(&self) -> bool {
         !self.config.smtp.username.is_empty() 
-            && !self.config.smtp.password.is_empty()
+            && !self.config.smtp.password.expose().is_empty()
             && !self.config.smtp.host.is_empty()
             && self.config.smtp.port > 0
     }
@@ -193,7 +299,7 @@ impl EmailTransport {
     /// Check if IMAP is properly configured
     pub fn is_imap_configured(&self) -> bool {
         !self.config.imap.username.is_empty() 
-            && !self.config.imap.password.is_empty()
+            && !self.config.imap.password.expose().is_empty()
             && !self.config.imap.host.is_empty()
             && self.config.imap.port > 0
     }
@@ -239,6 +345,29 @@ impl EmailTransport {
     }
 }
 
+/// Rebuild inline attachments from the MIME parts of a received email
+#[cfg(feature = "email")]
+pub fn attachments_from_mime(raw: &[u8]) -> Vec<Attachment> {
+    use mail_parser::MimeHeaders;
+
+    let Some(parsed) = mail_parser::MessageParser::default().parse(raw) else {
+        return Vec::new();
+    };
+    parsed
+        .attachments()
+        .map(|part| {
+            let mime_type = part
+                .content_type()
+                .map(|ct| match ct.subtype() {
+                    Some(subtype) => format!("{}/{}", ct.ctype(), subtype),
+                    None => ct.ctype().to_string(),
+                })
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            Attachment::new(part.attachment_name().unwrap_or("attachment"), mime_type, part.contents().to_vec())
+        })
+        .collect()
+}
+
 /// Parsed Synapse email message
 #[derive(Debug, Clone)]
 pub struct SynapseEmailMessage {
@@ -308,13 +437,40 @@ mod tests {
         assert_eq!(subject, "[Synapse] Hello! How can I help...");
     }
 
+    #[test]
+    fn test_notifications_render_for_mail_clients() {
+        use crate::notifications::Severity;
+
+        let transport = create_test_transport();
+        let message = Notification::alert(Severity::Warning, "Queue backing up")
+            .with_details("1,200 messages waiting")
+            .to_message("monitor@synapse.local", "ops@example.com")
+            .unwrap();
+        let secure = SecureMessage::new(
+            "ops@example.com",
+            "monitor@synapse.local",
+            Vec::new(),
+            Vec::new(),
+            crate::types::SecurityLevel::Authenticated,
+        );
+        let email = transport
+            .create_email_message(&secure, "monitor@synapse.local", "ops@example.com", &message)
+            .unwrap();
+
+        let raw = String::from_utf8(email.formatted()).unwrap();
+        assert!(raw.contains("Subject: [WARNING] Queue backing up"));
+        assert!(raw.contains("multipart/alternative"));
+        assert!(raw.contains("1,200 messages waiting"));
+        assert!(raw.contains("application/json"));
+    }
+
     fn create_test_transport() -> EmailTransport {
         let config = EmailConfig {
             smtp: crate::types::SmtpConfig {
                 host: "localhost".to_string(),
                 port: 587,
                 username: "test@localhost".to_string(),
-                password: "test".to_string(),
+                password: "test".into(),
                 use_tls: false,
                 use_ssl: false,
             },
@@ -322,7 +478,7 @@ mod tests {
                 host: "localhost".to_string(),
                 port: 993,
                 username: "test@localhost".to_string(),
-                password: "test".to_string(),
+                password: "test".into(),
                 use_ssl: false,
             },
         };
@@ -332,6 +488,8 @@ mod tests {
         EmailTransport {
             config,
             smtp_transport: SmtpTransport::unencrypted_localhost(),
+            pacer: Arc::new(SendPacer::from_config(&EmailSendingConfig::default(), "localhost")),
+            required_proxy: None,
         }
     }
 }
@@ -2,14 +2,16 @@
 
 use crate::error::Result;
 use crate::email_server::smtp_server::AuthHandler;
+use crate::email_server::store::{MailboxStore, MemoryMailboxStore};
+use async_trait::async_trait;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
 
 /// Default authorization handler for EMRP email server
 pub struct SynapseAuthHandler {
-    /// User credentials
-    users: Arc<Mutex<HashMap<String, UserAccount>>>,
+    /// User accounts, shared with the other server instances when the store is
+    store: Arc<dyn MailboxStore>,
     /// Email routing permissions
     routing_permissions: Arc<Mutex<RoutingPermissions>>,
     /// Server configuration
@@ -93,13 +95,10 @@ impl Default for RoutingPermissions {
     }
 }
 
-impl SynapseAuthHandler {
-    /// Create a new auth handler with default configuration
-    pub fn new() -> Self {
-        let mut users = HashMap::new();
-        
-        // Add default admin user
-        users.insert("admin".to_string(), UserAccount {
+/// Accounts an in-memory store starts with
+pub fn default_accounts() -> Vec<UserAccount> {
+    vec![
+        UserAccount {
             username: "admin".to_string(),
             password_hash: "admin".to_string(), // In production: hash this!
             email: "admin@localhost".to_string(),
@@ -110,10 +109,8 @@ impl SynapseAuthHandler {
                 is_admin: true,
             },
             active: true,
-        });
-        
-        // Add default emrp user
-        users.insert("emrp".to_string(), UserAccount {
+        },
+        UserAccount {
             username: "emrp".to_string(),
             password_hash: "emrp123".to_string(), // In production: hash this!
             email: "emrp@localhost".to_string(),
@@ -124,10 +121,20 @@ impl SynapseAuthHandler {
                 is_admin: false,
             },
             active: true,
-        });
+        },
+    ]
+}
 
+impl SynapseAuthHandler {
+    /// Create a new auth handler with default configuration
+    pub fn new() -> Self {
+        Self::with_store(Arc::new(MemoryMailboxStore::with_accounts(default_accounts())))
+    }
+
+    /// Create an auth handler keeping accounts in `store`
+    pub fn with_store(store: Arc<dyn MailboxStore>) -> Self {
         Self {
-            users: Arc::new(Mutex::new(users)),
+            store,
             routing_permissions: Arc::new(Mutex::new(RoutingPermissions::default())),
             config: AuthConfig::default(),
         }
@@ -140,17 +147,19 @@ impl SynapseAuthHandler {
         handler
     }
 
+    /// Store the accounts are kept in
+    pub fn store(&self) -> Arc<dyn MailboxStore> {
+        Arc::clone(&self.store)
+    }
+
     /// Add a new user account
-    pub fn add_user(&self, user: UserAccount) -> Result<()> {
-        let mut users = self.users.lock().unwrap();
-        users.insert(user.username.clone(), user);
-        Ok(())
+    pub async fn add_user(&self, user: UserAccount) -> Result<()> {
+        self.store.put_account(&user).await
     }
 
     /// Remove a user account
-    pub fn remove_user(&self, username: &str) -> Result<bool> {
-        let mut users = self.users.lock().unwrap();
-        Ok(users.remove(username).is_some())
+    pub async fn remove_user(&self, username: &str) -> Result<bool> {
+        self.store.remove_account(username).await
     }
 
     /// Update routing permissions
@@ -204,23 +213,13 @@ impl SynapseAuthHandler {
         }
     }
 
-    /// Get user by username
-    fn get_user(&self, username: &str) -> Option<UserAccount> {
-        let users = self.users.lock().unwrap();
-        users.get(username).cloned()
-    }
-
-    /// Get user by email address
-    fn get_user_by_email(&self, email: &str) -> Option<UserAccount> {
-        let users = self.users.lock().unwrap();
-        users.values().find(|user| user.email == email).cloned()
-    }
 }
 
+#[async_trait]
 impl AuthHandler for SynapseAuthHandler {
     /// Authenticate user credentials
-    fn authenticate(&self, username: &str, password: &str) -> Result<bool> {
-        if let Some(user) = self.get_user(username) {
+    async fn authenticate(&self, username: &str, password: &str) -> Result<bool> {
+        if let Some(user) = self.store.account(username).await? {
             if user.active && user.password_hash == password {
                 // In production: use proper password hashing (bcrypt, argon2, etc.)
                 return Ok(true);
@@ -230,14 +229,14 @@ impl AuthHandler for SynapseAuthHandler {
     }
 
     /// Check if sender is authorized
-    fn is_authorized_sender(&self, email: &str) -> Result<bool> {
+    async fn is_authorized_sender(&self, email: &str) -> Result<bool> {
         // If authentication is not required for sending, allow any local user
         if !self.config.require_auth_for_send {
             return Ok(self.is_local_domain(email));
         }
 
         // Check if user exists and has send permissions
-        if let Some(user) = self.get_user_by_email(email) {
+        if let Some(user) = self.store.account_by_email(email).await? {
             Ok(user.active && user.permissions.can_send)
         } else {
             // Allow sending from local domains even without explicit user account
@@ -246,7 +245,7 @@ impl AuthHandler for SynapseAuthHandler {
     }
 
     /// Check if recipient is authorized
-    fn is_authorized_recipient(&self, email: &str) -> Result<bool> {
+    async fn is_authorized_recipient(&self, email: &str) -> Result<bool> {
         // Always accept mail for local domains
         if self.is_local_domain(email) {
             return Ok(true);
@@ -258,7 +257,7 @@ impl AuthHandler for SynapseAuthHandler {
         }
 
         // Check if we have specific user permissions for relay
-        if let Some(user) = self.get_user_by_email(email) {
+        if let Some(user) = self.store.account_by_email(email).await? {
             return Ok(user.active && user.permissions.can_relay);
         }
 
@@ -268,7 +267,7 @@ impl AuthHandler for SynapseAuthHandler {
 }
 
 /// Create a pre-configured auth handler for testing
-pub fn create_test_auth_handler() -> SynapseAuthHandler {
+pub async fn create_test_auth_handler() -> SynapseAuthHandler {
     let handler = SynapseAuthHandler::new();
     
     // Add test domains
@@ -288,7 +287,7 @@ pub fn create_test_auth_handler() -> SynapseAuthHandler {
             is_admin: false,
         },
         active: true,
-    }).unwrap();
+    }).await.unwrap();
     
     handler
 }
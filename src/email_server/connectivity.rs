@@ -76,10 +76,14 @@ impl ConnectivityDetector {
     pub async fn assess_connectivity(&self) -> Result<ConnectivityAssessment> {
         info!("Starting connectivity assessment for email server...");
 
-        let can_bind_smtp = self.test_port_binding(&self.smtp_ports).await?;
-        let can_bind_imap = self.test_port_binding(&self.imap_ports).await?;
-        
-        let external_ip = self.detect_external_ip().await;
+        // The probes are independent, so a slow external address lookup does
+        // not add to the port checks
+        let (can_bind_smtp, can_bind_imap, external_ip) = tokio::join!(
+            self.test_port_binding(&self.smtp_ports),
+            self.test_port_binding(&self.imap_ports),
+            self.detect_external_ip(),
+        );
+        let (can_bind_smtp, can_bind_imap) = (can_bind_smtp?, can_bind_imap?);
         let has_external_ip = external_ip.is_some();
         
         let firewall_status = if has_external_ip && (can_bind_smtp || can_bind_imap) {
@@ -0,0 +1,508 @@
+//! Gateway between plain email correspondents and the Synapse router
+//!
+//! Mail to a gateway address (e.g. `bot@mydomain.com`) that was not sent by a
+//! Synapse node is turned into a direct message for that address, with the
+//! sender, subject, Message-ID and attachments in the metadata. Replies sent
+//! through [`EmailGateway::compose_reply`] go back as ordinary plain-text email
+//! threaded under the correspondent's original message.
+
+use crate::{
+    bridges::{BRIDGE_EVENT_ID_KEY, BRIDGE_KEY, BRIDGE_RECIPIENT_KEY, BRIDGE_SENDER_KEY, CONVERSATION_ID_KEY},
+    error::{Result, SynapseError},
+    headers,
+    types::{MessageType, SimpleMessage},
+};
+use base64::Engine as _;
+#[cfg(feature = "secure-email")]
+use super::mail_crypto::{MailKeyring, EMAIL_ENCRYPTION_KEY, PGP_MIME};
+use lettre::message::{Mailbox, Message, MessageBuilder, header::ContentType};
+use mail_parser::{HeaderValue, MessageParser, MimeHeaders};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+#[cfg(feature = "secure-email")]
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info};
+
+/// Value of the `bridge` metadata key for gatewayed email
+pub const EMAIL_BRIDGE: &str = "email";
+/// Subject line of the correspondent's mail
+pub const EMAIL_SUBJECT_KEY: &str = "email_subject";
+/// Display name of the correspondent, when given
+pub const EMAIL_SENDER_NAME_KEY: &str = "email_sender_name";
+/// JSON array of [`EmailAttachment`]s
+pub const EMAIL_ATTACHMENTS_KEY: &str = "email_attachments";
+
+/// Which mail the gateway accepts and how replies look
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GatewayPolicy {
+    /// Addresses that accept mail from plain email correspondents
+    pub gateway_addresses: Vec<String>,
+    /// Allowed senders: exact addresses or `*@domain`; empty allows anyone
+    #[serde(default)]
+    pub allowed_senders: Vec<String>,
+    /// Attachments larger than this are dropped (and listed as omitted)
+    #[serde(default = "default_max_attachment_bytes")]
+    pub max_attachment_bytes: usize,
+    /// Text appended to every outbound reply
+    #[serde(default)]
+    pub reply_footer: Option<String>,
+    /// Conversations remembered for threading
+    #[serde(default = "default_max_threads")]
+    pub max_threads: usize,
+}
+
+fn default_max_attachment_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_max_threads() -> usize {
+    10_000
+}
+
+impl GatewayPolicy {
+    pub fn new(gateway_addresses: Vec<String>) -> Self {
+        Self {
+            gateway_addresses,
+            allowed_senders: Vec::new(),
+            max_attachment_bytes: default_max_attachment_bytes(),
+            reply_footer: None,
+            max_threads: default_max_threads(),
+        }
+    }
+
+    fn is_gateway_address(&self, address: &str) -> bool {
+        self.gateway_addresses.iter().any(|a| a.eq_ignore_ascii_case(address))
+    }
+
+    fn allows_sender(&self, address: &str) -> bool {
+        self.allowed_senders.is_empty()
+            || self.allowed_senders.iter().any(|pattern| match pattern.strip_prefix("*@") {
+                Some(domain) => address.rsplit_once('@').is_some_and(|(_, d)| d.eq_ignore_ascii_case(domain)),
+                None => pattern.eq_ignore_ascii_case(address),
+            })
+    }
+}
+
+/// A file attached to a correspondent's mail
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub size: usize,
+    /// Base64 content; absent when the attachment exceeded the size limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+impl EmailAttachment {
+    /// Decoded content
+    pub fn bytes(&self) -> Option<Vec<u8>> {
+        self.data
+            .as_ref()
+            .and_then(|data| base64::engine::general_purpose::STANDARD.decode(data).ok())
+    }
+}
+
+/// Attachments carried by a gatewayed message
+pub fn attachments(message: &SimpleMessage) -> Vec<EmailAttachment> {
+    message
+        .metadata
+        .get(EMAIL_ATTACHMENTS_KEY)
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or_default()
+}
+
+/// An email conversation with one correspondent
+#[derive(Debug, Clone)]
+struct EmailThread {
+    gateway_address: String,
+    correspondent: String,
+    subject: String,
+    /// Message-IDs in the thread, oldest first
+    references: Vec<String>,
+}
+
+#[derive(Default)]
+struct ThreadIndex {
+    threads: HashMap<String, EmailThread>,
+    /// Message-ID to conversation ID
+    messages: HashMap<String, String>,
+    /// Conversations in creation order, for eviction
+    order: VecDeque<String>,
+}
+
+/// Converts plain email to Synapse messages and replies back to email
+pub struct EmailGateway {
+    policy: GatewayPolicy,
+    threads: Mutex<ThreadIndex>,
+    inbound: Mutex<VecDeque<SimpleMessage>>,
+    /// Keys for PGP/MIME and S/MIME with correspondents
+    #[cfg(feature = "secure-email")]
+    keyring: RwLock<Option<Arc<MailKeyring>>>,
+}
+
+impl EmailGateway {
+    pub fn new(policy: GatewayPolicy) -> Self {
+        Self {
+            policy,
+            threads: Mutex::new(ThreadIndex::default()),
+            inbound: Mutex::new(VecDeque::new()),
+            #[cfg(feature = "secure-email")]
+            keyring: RwLock::new(None),
+        }
+    }
+
+    pub fn policy(&self) -> &GatewayPolicy {
+        &self.policy
+    }
+
+    /// Encrypt replies to correspondents whose keys `keyring` holds, and
+    /// decrypt their PGP/MIME mail with its secret key
+    #[cfg(feature = "secure-email")]
+    pub fn set_keyring(&self, keyring: Arc<MailKeyring>) {
+        *self.keyring.write().unwrap() = Some(keyring);
+    }
+
+    #[cfg(feature = "secure-email")]
+    pub fn keyring(&self) -> Option<Arc<MailKeyring>> {
+        self.keyring.read().unwrap().clone()
+    }
+
+    /// Whether the gateway should take a message for these recipients
+    pub fn handles_any(&self, recipients: &[String]) -> bool {
+        recipients.iter().any(|r| self.policy.is_gateway_address(r))
+    }
+
+    /// Convert a received mail into messages for the gateway addresses among `recipients`
+    ///
+    /// Returns no messages for mail produced by Synapse nodes (which carry the
+    /// `X-Synapse-Version` header), auto-generated mail and bounces, or senders
+    /// the policy does not allow. Converted messages are also queued for
+    /// [`EmailGateway::take_inbound`].
+    pub fn ingest(&self, raw: &[u8], envelope_from: &str, recipients: &[String]) -> Result<Vec<SimpleMessage>> {
+        let parsed = MessageParser::default()
+            .parse(raw)
+            .ok_or_else(|| SynapseError::InvalidMessageFormat("Unparseable email".to_string()))?;
+
+        if parsed.header(headers::VERSION).is_some() {
+            return Ok(Vec::new());
+        }
+        let auto_submitted = parsed.header("Auto-Submitted").and_then(header_text);
+        if envelope_from.is_empty() || auto_submitted.is_some_and(|v| !v.eq_ignore_ascii_case("no")) {
+            debug!("Ignoring automatic mail from '{}'", envelope_from);
+            return Ok(Vec::new());
+        }
+
+        let from = parsed.from().and_then(|a| a.first());
+        let sender = from
+            .and_then(|a| a.address())
+            .unwrap_or(envelope_from)
+            .to_lowercase();
+        if !self.policy.allows_sender(&sender) {
+            info!("Email gateway rejected mail from {}", sender);
+            return Err(SynapseError::AuthorizationError(format!("{} may not mail the gateway", sender)));
+        }
+
+        // PGP/MIME hides the body and attachments in an encrypted MIME entity
+        #[cfg(feature = "secure-email")]
+        let decrypted = match self.keyring() {
+            Some(keyring) => keyring.decrypt_pgp_mime(&parsed)?,
+            None => None,
+        };
+        #[cfg(feature = "secure-email")]
+        let inner = decrypted.as_deref().and_then(|entity| MessageParser::default().parse(entity));
+        #[cfg(feature = "secure-email")]
+        let content = inner.as_ref().unwrap_or(&parsed);
+        #[cfg(not(feature = "secure-email"))]
+        let content = &parsed;
+
+        let subject = parsed.subject().unwrap_or_default().to_string();
+        let message_id = parsed
+            .message_id()
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| format!("{}@gateway.synapse", uuid::Uuid::new_v4()));
+        let mut parents = header_list(parsed.references());
+        parents.extend(header_list(parsed.in_reply_to()));
+
+        let body = content.body_text(0).map(|b| b.trim_end().to_string()).unwrap_or_default();
+        let attachments: Vec<EmailAttachment> = content
+            .attachments()
+            .map(|part| {
+                let contents = part.contents();
+                let content_type = part
+                    .content_type()
+                    .map(|ct| match ct.subtype() {
+                        Some(subtype) => format!("{}/{}", ct.ctype(), subtype),
+                        None => ct.ctype().to_string(),
+                    })
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+                EmailAttachment {
+                    filename: part.attachment_name().unwrap_or("attachment").to_string(),
+                    content_type,
+                    size: contents.len(),
+                    data: (contents.len() <= self.policy.max_attachment_bytes)
+                        .then(|| base64::engine::general_purpose::STANDARD.encode(contents)),
+                }
+            })
+            .collect();
+
+        let mut messages = Vec::new();
+        for gateway_address in recipients.iter().filter(|r| self.policy.is_gateway_address(r)) {
+            let conversation_id = self.thread_message(gateway_address, &sender, &subject, &message_id, &parents);
+
+            let mut metadata = HashMap::new();
+            metadata.insert(BRIDGE_KEY.to_string(), EMAIL_BRIDGE.to_string());
+            metadata.insert(BRIDGE_SENDER_KEY.to_string(), sender.clone());
+            metadata.insert(BRIDGE_EVENT_ID_KEY.to_string(), message_id.clone());
+            metadata.insert(CONVERSATION_ID_KEY.to_string(), conversation_id);
+            metadata.insert(EMAIL_SUBJECT_KEY.to_string(), subject.clone());
+            if let Some(name) = from.and_then(|a| a.name()) {
+                metadata.insert(EMAIL_SENDER_NAME_KEY.to_string(), name.to_string());
+            }
+            if !attachments.is_empty() {
+                metadata.insert(EMAIL_ATTACHMENTS_KEY.to_string(), serde_json::to_string(&attachments)?);
+            }
+            #[cfg(feature = "secure-email")]
+            if inner.is_some() {
+                metadata.insert(EMAIL_ENCRYPTION_KEY.to_string(), PGP_MIME.to_string());
+            }
+
+            messages.push(SimpleMessage {
+                to: gateway_address.to_lowercase(),
+                from_entity: sender.clone(),
+                content: body.clone(),
+                message_type: MessageType::Direct,
+                metadata,
+            });
+        }
+
+        info!("Email gateway accepted mail {} from {} ({} attachments)", message_id, sender, attachments.len());
+        self.inbound.lock().unwrap().extend(messages.iter().cloned());
+        Ok(messages)
+    }
+
+    /// Take messages converted from email since the last call
+    pub fn take_inbound(&self) -> Vec<SimpleMessage> {
+        self.inbound.lock().unwrap().drain(..).collect()
+    }
+
+    /// Build a human-readable email for a reply to a correspondent
+    ///
+    /// The reply goes to `bridge_recipient`, or the correspondent of the
+    /// message's `conversation_id`, or `message.to`. When the conversation is
+    /// known the mail is threaded with `In-Reply-To` and `References`. With
+    /// the `secure-email` feature, replies to correspondents whose key the
+    /// keyring holds are sent as PGP/MIME or S/MIME.
+    pub fn compose_reply(&self, message: &SimpleMessage) -> Result<Message> {
+        let mut index = self.threads.lock().unwrap();
+        let thread = message
+            .metadata
+            .get(CONVERSATION_ID_KEY)
+            .and_then(|id| index.threads.get(id).cloned());
+
+        let from = match &thread {
+            Some(thread) => thread.gateway_address.clone(),
+            None => message.from_entity.clone(),
+        };
+        let to = message
+            .metadata
+            .get(BRIDGE_RECIPIENT_KEY)
+            .cloned()
+            .or_else(|| thread.as_ref().map(|t| t.correspondent.clone()))
+            .unwrap_or_else(|| message.to.clone());
+        let subject = match &thread {
+            Some(thread) if thread.subject.to_lowercase().starts_with("re:") => thread.subject.clone(),
+            Some(thread) if !thread.subject.is_empty() => format!("Re: {}", thread.subject),
+            _ => message.metadata.get(EMAIL_SUBJECT_KEY).cloned().unwrap_or_else(|| "Message".to_string()),
+        };
+
+        let mut body = message.content.clone();
+        if let Some(footer) = &self.policy.reply_footer {
+            body.push_str("\n\n-- \n");
+            body.push_str(footer);
+        }
+
+        let domain = from.rsplit_once('@').map_or("localhost", |(_, d)| d);
+        let message_id = format!("<{}@{}>", uuid::Uuid::new_v4(), domain);
+
+        let mut builder = Message::builder()
+            .from(parse_mailbox(&from)?)
+            .to(parse_mailbox(&to)?)
+            .subject(subject)
+            .message_id(Some(message_id.clone()));
+        if let Some(thread) = &thread {
+            if let Some(parent) = thread.references.last() {
+                builder = builder.in_reply_to(format!("<{}>", parent));
+            }
+            let references: Vec<String> = thread.references.iter().map(|r| format!("<{}>", r)).collect();
+            if !references.is_empty() {
+                builder = builder.references(references.join(" "));
+            }
+        }
+        let email = self.finish_reply(builder, &to, body)?;
+
+        if let Some(conversation_id) = message.metadata.get(CONVERSATION_ID_KEY) {
+            let bare_id = message_id.trim_matches(|c| c == '<' || c == '>').to_string();
+            if let Some(thread) = index.threads.get_mut(conversation_id) {
+                thread.references.push(bare_id.clone());
+                index.messages.insert(bare_id, conversation_id.clone());
+            }
+        }
+        Ok(email)
+    }
+
+    /// Add the body to a reply, encrypted when we hold the recipient's key
+    #[cfg_attr(not(feature = "secure-email"), allow(unused_variables))]
+    fn finish_reply(&self, builder: MessageBuilder, to: &str, body: String) -> Result<Message> {
+        #[cfg(feature = "secure-email")]
+        if let Some(encrypted) = self.keyring().map(|keyring| keyring.encrypt(to, &body)).transpose()?.flatten() {
+            return encrypted.into_message(builder);
+        }
+        builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| SynapseError::InvalidFormat(format!("Failed to build reply email: {}", e)))
+    }
+
+    /// Conversation a mail belongs to, recording it in the thread
+    fn thread_message(
+        &self,
+        gateway_address: &str,
+        sender: &str,
+        subject: &str,
+        message_id: &str,
+        parents: &[String],
+    ) -> String {
+        let mut index = self.threads.lock().unwrap();
+        let existing = parents.iter().rev().find_map(|p| index.messages.get(p).cloned());
+        let conversation_id = existing.unwrap_or_else(|| {
+            let conversation_id = message_id.to_string();
+            index.threads.insert(
+                conversation_id.clone(),
+                EmailThread {
+                    gateway_address: gateway_address.to_lowercase(),
+                    correspondent: sender.to_string(),
+                    subject: subject.to_string(),
+                    references: Vec::new(),
+                },
+            );
+            index.order.push_back(conversation_id.clone());
+            conversation_id
+        });
+
+        if let Some(thread) = index.threads.get_mut(&conversation_id) {
+            thread.references.push(message_id.to_string());
+        }
+        index.messages.insert(message_id.to_string(), conversation_id.clone());
+
+        while index.order.len() > self.policy.max_threads {
+            if let Some(evicted) = index.order.pop_front() {
+                index.threads.remove(&evicted);
+                index.messages.retain(|_, conversation| conversation != &evicted);
+            }
+        }
+        conversation_id
+    }
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse::<Mailbox>()
+        .map_err(|e| SynapseError::InvalidFormat(format!("Invalid email address {}: {}", address, e)))
+}
+
+fn header_text<'a>(value: &'a HeaderValue<'_>) -> Option<&'a str> {
+    match value {
+        HeaderValue::Text(text) => Some(text.as_ref()),
+        HeaderValue::TextList(list) => list.first().map(|t| t.as_ref()),
+        _ => None,
+    }
+}
+
+/// Message-IDs from `References` or `In-Reply-To`, without angle brackets
+fn header_list(value: &HeaderValue<'_>) -> Vec<String> {
+    match value {
+        HeaderValue::Text(text) => vec![text.to_string()],
+        HeaderValue::TextList(list) => list.iter().map(|t| t.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gateway() -> EmailGateway {
+        EmailGateway::new(GatewayPolicy::new(vec!["bot@mydomain.com".to_string()]))
+    }
+
+    const FIRST: &str = "From: Ada Lovelace <ada@example.org>\r\n\
+To: bot@mydomain.com\r\n\
+Subject: Printer on fire\r\n\
+Message-ID: <first@example.org>\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed; boundary=\"b\"\r\n\
+\r\n\
+--b\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+\r\n\
+It is on fire again.\r\n\
+--b\r\n\
+Content-Type: text/plain; name=\"log.txt\"\r\n\
+Content-Disposition: attachment; filename=\"log.txt\"\r\n\
+\r\n\
+smoke detected\r\n\
+--b--\r\n";
+
+    #[test]
+    fn plain_mail_becomes_a_threaded_message() {
+        let gateway = gateway();
+        let recipients = vec!["bot@mydomain.com".to_string()];
+        let messages = gateway.ingest(FIRST.as_bytes(), "ada@example.org", &recipients).unwrap();
+
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert_eq!(message.from_entity, "ada@example.org");
+        assert_eq!(message.content, "It is on fire again.");
+        assert_eq!(message.metadata[EMAIL_SUBJECT_KEY], "Printer on fire");
+        assert_eq!(message.metadata[CONVERSATION_ID_KEY], "first@example.org");
+        let files = attachments(message);
+        assert_eq!(files[0].filename, "log.txt");
+        assert_eq!(files[0].bytes().unwrap(), b"smoke detected");
+
+        let second = "From: ada@example.org\r\nTo: bot@mydomain.com\r\nSubject: Re: Printer on fire\r\n\
+Message-ID: <second@example.org>\r\nIn-Reply-To: <first@example.org>\r\n\r\nStill burning.\r\n";
+        let follow_up = gateway.ingest(second.as_bytes(), "ada@example.org", &recipients).unwrap();
+        assert_eq!(follow_up[0].metadata[CONVERSATION_ID_KEY], "first@example.org");
+        assert_eq!(gateway.take_inbound().len(), 2);
+    }
+
+    #[test]
+    fn replies_are_threaded_plain_text() {
+        let gateway = gateway();
+        let recipients = vec!["bot@mydomain.com".to_string()];
+        let inbound = gateway.ingest(FIRST.as_bytes(), "ada@example.org", &recipients).unwrap();
+
+        let mut reply = SimpleMessage::new("ada@example.org", "bot@mydomain.com", "Fire crew dispatched.");
+        reply.metadata.insert(CONVERSATION_ID_KEY.to_string(), inbound[0].metadata[CONVERSATION_ID_KEY].clone());
+        let email = String::from_utf8(gateway.compose_reply(&reply).unwrap().formatted()).unwrap();
+
+        assert!(email.contains("Subject: Re: Printer on fire"));
+        assert!(email.contains("In-Reply-To: <first@example.org>"));
+        assert!(email.contains("Fire crew dispatched."));
+        assert!(!email.contains(headers::VERSION));
+    }
+
+    #[test]
+    fn synapse_and_automatic_mail_is_left_alone() {
+        let gateway = gateway();
+        let recipients = vec!["bot@mydomain.com".to_string()];
+        let synapse = "From: a@b.c\r\nTo: bot@mydomain.com\r\nX-Synapse-Version: 1.0.0\r\n\r\n{}\r\n";
+        assert!(gateway.ingest(synapse.as_bytes(), "a@b.c", &recipients).unwrap().is_empty());
+        let bounce = "From: MAILER-DAEMON@b.c\r\nTo: bot@mydomain.com\r\nAuto-Submitted: auto-replied\r\n\r\nx\r\n";
+        assert!(gateway.ingest(bounce.as_bytes(), "", &recipients).unwrap().is_empty());
+    }
+}
@@ -2,19 +2,25 @@
 
 use crate::error::{SynapseError, Result};
 use crate::types::SecureMessage;
+use crate::config::{ListenerBinding, MailStoreConfig};
+use crate::transport::binding::bind_tcp;
+use super::store::{IdleLease, MailboxStore};
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
-use tokio::net::{TcpListener, TcpStream};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+use tokio::net::TcpStream;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{info, error, debug};
+use chrono::Utc;
+use uuid::Uuid;
+use tracing::{info, error, debug, warn};
 
 /// High-performance IMAP server optimized for EMRP
 pub struct SynapseImapServer {
     /// Server configuration
     config: ImapServerConfig,
-    /// Message store (shared with SMTP server)
-    message_store: Arc<Mutex<HashMap<String, Vec<SecureMessage>>>>,
+    /// Mailboxes, shared with the SMTP server and possibly other instances
+    message_store: Arc<dyn MailboxStore>,
     /// Connected clients
     clients: Arc<Mutex<HashMap<String, ImapSession>>>,
     /// Authorization handler
@@ -25,12 +31,24 @@ pub struct SynapseImapServer {
 pub struct ImapServerConfig {
     /// IMAP port (usually 143 or 993)
     pub port: u16,
+    /// Interface address to bind (an IPv6 wildcard also accepts IPv4)
+    pub bind_address: IpAddr,
+    /// Inclusive range of ports to fall back to when `port` is taken
+    pub port_range: Option<(u16, u16)>,
     /// TLS configuration
     pub tls_config: Option<super::smtp_server::TlsConfig>,
     /// Enable IDLE extension for push notifications
     pub enable_idle: bool,
     /// Performance optimization
     pub performance: ImapPerformanceConfig,
+    /// Instance named in the IDLE leases this server holds
+    pub instance_id: String,
+    /// How long an IDLE lease lasts without renewal
+    pub idle_lease: Duration,
+    /// IDLE sessions a user may hold open across all instances
+    pub max_idle_sessions_per_user: usize,
+    /// How often IDLE sessions check their mailbox for new mail
+    pub idle_poll: Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -45,7 +63,6 @@ pub struct ImapPerformanceConfig {
 
 #[derive(Debug)]
 struct ImapSession {
-    #[allow(dead_code)]
     id: String,
     state: ImapState,
     authenticated_user: Option<String>,
@@ -53,6 +70,12 @@ struct ImapSession {
     #[allow(dead_code)]
     tag: u32,
     idle_mode: bool,
+    /// Tag of the IDLE command, echoed when the client sends DONE
+    idle_tag: Option<String>,
+    /// Message count last reported to the client
+    exists: usize,
+    /// When the IDLE lease was last renewed
+    lease_renewed_at: Option<tokio::time::Instant>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -63,10 +86,33 @@ enum ImapState {
     Logout,
 }
 
+impl ImapServerConfig {
+    /// Configuration listening where `binding` says
+    pub fn from_binding(binding: &ListenerBinding) -> Self {
+        Self {
+            port: binding.port,
+            bind_address: binding.address,
+            port_range: binding.port_range,
+            ..Self::default()
+        }
+    }
+
+    /// Take IDLE lease settings from the mail store configuration
+    pub fn with_mail_store(mut self, store: &MailStoreConfig) -> Self {
+        self.instance_id = store.instance_id.clone();
+        self.idle_lease = Duration::from_secs(store.idle_lease_secs.max(1));
+        self.max_idle_sessions_per_user = store.max_idle_sessions_per_user;
+        self.idle_poll = Duration::from_millis(store.idle_poll_ms.max(1));
+        self
+    }
+}
+
 impl Default for ImapServerConfig {
     fn default() -> Self {
         Self {
             port: 1143, // Non-privileged port for development
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port_range: None,
             tls_config: None,
             enable_idle: true,
             performance: ImapPerformanceConfig {
@@ -74,6 +120,10 @@ impl Default for ImapServerConfig {
                 idle_timeout: 1740, // 29 minutes (RFC requirement)
                 enable_compression: true,
             },
+            instance_id: "local".to_string(),
+            idle_lease: Duration::from_secs(60),
+            max_idle_sessions_per_user: 10,
+            idle_poll: Duration::from_secs(1),
         }
     }
 }
@@ -82,7 +132,7 @@ impl SynapseImapServer {
     /// Create a new IMAP server
     pub fn new(
         config: ImapServerConfig,
-        message_store: Arc<Mutex<HashMap<String, Vec<SecureMessage>>>>,
+        message_store: Arc<dyn MailboxStore>,
         auth_handler: Arc<dyn super::smtp_server::AuthHandler + Send + Sync>,
     ) -> Self {
         Self {
@@ -95,9 +145,14 @@ impl SynapseImapServer {
 
     /// Start the IMAP server
     pub async fn start(&self) -> Result<()> {
-        let addr = format!("0.0.0.0:{}", self.config.port);
-        let listener = TcpListener::bind(&addr).await
-            .map_err(|e| SynapseError::NetworkError(format!("Failed to bind IMAP server to {}: {}", addr, e)))?;
+        let listener = bind_tcp(&ListenerBinding {
+            address: self.config.bind_address,
+            port: self.config.port,
+            port_range: self.config.port_range,
+            dual_stack: true,
+        })?;
+        let addr = listener.local_addr()
+            .map_err(|e| SynapseError::NetworkError(format!("IMAP listener has no local address: {}", e)))?;
 
         info!("EMRP IMAP Server listening on {}", addr);
 
@@ -124,47 +179,131 @@ impl SynapseImapServer {
     /// Handle individual IMAP connection
     async fn handle_connection(&self, stream: TcpStream) -> Result<()> {
         let (read_half, write_half) = stream.into_split();
-        let mut reader = BufReader::new(read_half);
+        let mut lines = BufReader::new(read_half).lines();
         let mut writer = write_half;
         
         let mut session = ImapSession {
-            id: format!("imap_{}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()),
+            id: format!("imap_{}", Uuid::new_v4()),
             state: ImapState::NotAuthenticated,
             authenticated_user: None,
             selected_mailbox: None,
             tag: 0,
             idle_mode: false,
+            idle_tag: None,
+            exists: 0,
+            lease_renewed_at: None,
         };
 
         // Send greeting
         writer.write_all(b"* OK EMRP IMAP Server Ready\r\n").await?;
         writer.flush().await?;
 
-        let mut line = String::new();
-        while reader.read_line(&mut line).await? > 0 {
-            let command = line.trim();
-            debug!("IMAP command: {}", command);
+        let mut poll = tokio::time::interval(self.config.idle_poll);
+        let result: Result<()> = async {
+            loop {
+                let responses = tokio::select! {
+                    line = lines.next_line() => {
+                        let Some(line) = line? else {
+                            return Ok(());
+                        };
+                        let command = line.trim();
+                        debug!("IMAP command: {}", command);
+                        self.process_imap_command(command, &mut session).await?
+                    }
+                    _ = poll.tick(), if session.idle_mode => self.idle_updates(&mut session).await,
+                };
+
+                for response in responses {
+                    writer.write_all(response.as_bytes()).await?;
+                }
+                writer.flush().await?;
 
-            let responses = self.process_imap_command(command, &mut session).await?;
-            
-            for response in responses {
-                writer.write_all(response.as_bytes()).await?;
+                // Check if connection should close
+                if session.state == ImapState::Logout {
+                    return Ok(());
+                }
             }
-            writer.flush().await?;
+        }
+        .await;
 
-            // Check if connection should close
-            if session.state == ImapState::Logout {
-                break;
+        // A dropped connection must not keep holding an IDLE slot
+        self.end_idle(&mut session).await;
+        result
+    }
+
+    /// Report new mail to an idling client and keep its lease alive
+    async fn idle_updates(&self, session: &mut ImapSession) -> Vec<String> {
+        let Some(user) = session.authenticated_user.clone() else {
+            return Vec::new();
+        };
+        let mut responses = Vec::new();
+        let renew_after = self.config.idle_lease / 2;
+        if session.lease_renewed_at.is_none_or(|at| at.elapsed() >= renew_after) && !self.renew_idle_lease(&user, session).await {
+            warn!("IDLE lease for {} session {} was lost", user, session.id);
+        }
+        match self.message_store.count(&user).await {
+            Ok(count) if count != session.exists => {
+                session.exists = count;
+                responses.push(format!("* {} EXISTS\r\n", count));
             }
+            Ok(_) => {}
+            Err(e) => debug!("Could not check mailbox of {}: {}", user, e),
+        }
+        responses
+    }
 
-            line.clear();
+    /// Take or renew the session's IDLE lease; false if the user holds too many
+    async fn renew_idle_lease(&self, user: &str, session: &mut ImapSession) -> bool {
+        let now_ms = Utc::now().timestamp_millis();
+        let lease = IdleLease {
+            user: user.to_string(),
+            session_id: session.id.clone(),
+            instance: self.config.instance_id.clone(),
+            expires_at_ms: now_ms + self.config.idle_lease.as_millis() as i64,
+        };
+        match self
+            .message_store
+            .acquire_idle_lease(&lease, self.config.max_idle_sessions_per_user, now_ms)
+            .await
+        {
+            Ok(acquired) => {
+                if acquired {
+                    session.lease_renewed_at = Some(tokio::time::Instant::now());
+                }
+                acquired
+            }
+            Err(e) => {
+                warn!("Could not take IDLE lease for {}: {}", user, e);
+                false
+            }
         }
+    }
 
-        Ok(())
+    /// Leave IDLE, giving up the session's lease
+    async fn end_idle(&self, session: &mut ImapSession) {
+        session.idle_mode = false;
+        if session.lease_renewed_at.take().is_none() {
+            return;
+        }
+        if let Some(user) = &session.authenticated_user {
+            if let Err(e) = self.message_store.release_idle_lease(user, &session.id).await {
+                debug!("Could not release IDLE lease for {}: {}", user, e);
+            }
+        }
     }
 
     /// Process IMAP command
     async fn process_imap_command(&self, command: &str, session: &mut ImapSession) -> Result<Vec<String>> {
+        // DONE is untagged and ends the IDLE command it answers
+        if session.idle_mode {
+            if !command.eq_ignore_ascii_case("DONE") {
+                return Ok(Vec::new());
+            }
+            self.end_idle(session).await;
+            let tag = session.idle_tag.take().unwrap_or_else(|| "*".to_string());
+            return Ok(vec![format!("{} OK IDLE terminated\r\n", tag)]);
+        }
+
         let parts: Vec<&str> = command.split_whitespace().collect();
         if parts.len() < 2 {
             return Ok(vec!["* BAD Syntax error\r\n".to_string()]);
@@ -189,7 +328,7 @@ impl SynapseImapServer {
                 let username = parts[2].trim_matches('"');
                 let password = parts[3].trim_matches('"');
                 
-                match self.auth_handler.authenticate(username, password) {
+                match self.auth_handler.authenticate(username, password).await {
                     Ok(true) => {
                         session.state = ImapState::Authenticated;
                         session.authenticated_user = Some(username.to_string());
@@ -226,14 +365,11 @@ impl SynapseImapServer {
                 let mailbox = parts[2].trim_matches('"');
                 
                 // Get message count for this user
-                let message_count = {
-                    let store = self.message_store.lock().unwrap();
-                    if let Some(user) = &session.authenticated_user {
-                        store.get(user).map(|msgs| msgs.len()).unwrap_or(0)
-                    } else {
-                        0
-                    }
+                let message_count = match &session.authenticated_user {
+                    Some(user) => self.message_store.count(user).await?,
+                    None => 0,
                 };
+                session.exists = message_count;
                 
                 session.state = ImapState::Selected;
                 session.selected_mailbox = Some(mailbox.to_string());
@@ -262,13 +398,9 @@ impl SynapseImapServer {
                 let items = parts[3..].join(" ");
                 
                 // Get messages for this user
-                let messages = {
-                    let store = self.message_store.lock().unwrap();
-                    if let Some(user) = &session.authenticated_user {
-                        store.get(user).cloned().unwrap_or_default()
-                    } else {
-                        Vec::new()
-                    }
+                let messages = match &session.authenticated_user {
+                    Some(user) => self.message_store.messages(user).await?,
+                    None => Vec::new(),
                 };
                 
                 let mut responses = Vec::new();
@@ -311,17 +443,17 @@ impl SynapseImapServer {
                     return Ok(vec![format!("{} NO IDLE not supported\r\n", tag)]);
                 }
                 
+                let Some(user) = session.authenticated_user.clone() else {
+                    return Ok(vec![format!("{} NO Not authenticated\r\n", tag)]);
+                };
+                if !self.renew_idle_lease(&user, session).await {
+                    return Ok(vec![format!("{} NO Too many IDLE sessions\r\n", tag)]);
+                }
+                
                 session.idle_mode = true;
+                session.idle_tag = Some(tag.to_string());
                 Ok(vec!["+ idling\r\n".to_string()])
             }
-            "DONE" => {
-                if session.idle_mode {
-                    session.idle_mode = false;
-                    Ok(vec![format!("{} OK IDLE terminated\r\n", tag)])
-                } else {
-                    Ok(vec![format!("{} BAD Not in IDLE\r\n", tag)])
-                }
-            }
             "LOGOUT" => {
                 session.state = ImapState::Logout;
                 let mut responses = vec![
@@ -0,0 +1,318 @@
+//! PGP/MIME and S/MIME for plain email correspondents
+//!
+//! Correspondents who are not Synapse nodes cannot read the Synapse envelope,
+//! but many already use encrypted email. When we hold a correspondent's
+//! OpenPGP key or S/MIME certificate, gateway replies to them are encrypted
+//! as standard PGP/MIME (RFC 3156) or S/MIME enveloped data (RFC 8551)
+//! instead of going out in the clear. Inbound PGP/MIME mail is decrypted with
+//! our own OpenPGP key before the gateway reads it.
+
+use crate::error::{Result, SynapseError};
+use aes_gcm::aead::OsRng;
+use cms::{
+    builder::{ContentEncryptionAlgorithm, EnvelopedDataBuilder, KeyEncryptionInfo, KeyTransRecipientInfoBuilder},
+    cert::IssuerAndSerialNumber,
+    content_info::ContentInfo,
+    enveloped_data::RecipientIdentifier,
+};
+use lettre::message::{
+    header::{ContentDisposition, ContentType},
+    Message, MessageBuilder, MultiPart, SinglePart,
+};
+use mail_parser::MimeHeaders;
+use pgp::{crypto::sym::SymmetricKeyAlgorithm, types::PublicKeyTrait, ArmorOptions, Deserializable, SignedPublicKey, SignedSecretKey};
+use rsa::{pkcs8::DecodePublicKey, RsaPublicKey};
+use std::{collections::HashMap, sync::RwLock};
+use tracing::{debug, info};
+use x509_cert::{
+    der::{asn1::ObjectIdentifier, Any, DecodePem, Encode},
+    Certificate,
+};
+
+/// Metadata key naming the encryption an inbound mail arrived with
+pub const EMAIL_ENCRYPTION_KEY: &str = "email_encryption";
+/// Value of [`EMAIL_ENCRYPTION_KEY`] for decrypted PGP/MIME mail
+pub const PGP_MIME: &str = "pgp/mime";
+
+const PGP_ENCRYPTED: &str = "application/pgp-encrypted";
+/// id-envelopedData (RFC 5652)
+const ID_ENVELOPED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.3");
+
+fn encryption_error(context: &str, e: impl std::fmt::Display) -> SynapseError {
+    SynapseError::EncryptionError(format!("{}: {}", context, e))
+}
+
+/// A correspondent's encryption key
+#[derive(Debug, Clone)]
+pub enum CorrespondentKey {
+    Pgp(Box<SignedPublicKey>),
+    Smime(Box<SmimeRecipient>),
+}
+
+/// What S/MIME encryption needs from a recipient certificate
+#[derive(Debug, Clone)]
+pub struct SmimeRecipient {
+    issuer_and_serial: IssuerAndSerialNumber,
+    key: RsaPublicKey,
+}
+
+/// A mail body encrypted for one correspondent
+pub enum EncryptedBody {
+    PgpMime(MultiPart),
+    Smime(SinglePart),
+}
+
+impl EncryptedBody {
+    /// Finish `builder` with the encrypted body
+    pub fn into_message(self, builder: MessageBuilder) -> Result<Message> {
+        match self {
+            EncryptedBody::PgpMime(parts) => builder.multipart(parts),
+            EncryptedBody::Smime(part) => builder.singlepart(part),
+        }
+        .map_err(|e| SynapseError::InvalidFormat(format!("Failed to build encrypted email: {}", e)))
+    }
+}
+
+/// Correspondents' keys, and our own OpenPGP key for inbound mail
+#[derive(Default)]
+pub struct MailKeyring {
+    keys: RwLock<HashMap<String, CorrespondentKey>>,
+    secret: RwLock<Option<(SignedSecretKey, String)>>,
+}
+
+impl MailKeyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an ASCII-armored OpenPGP public key for every address in its user IDs
+    ///
+    /// Returns the addresses the key was added for.
+    pub fn add_pgp_key(&self, armored: &str) -> Result<Vec<String>> {
+        let (key, _) = SignedPublicKey::from_string(armored).map_err(|e| encryption_error("Invalid OpenPGP key", e))?;
+        key.verify().map_err(|e| encryption_error("OpenPGP key self-signature", e))?;
+        if encryption_subkey(&key).is_none() && !key.is_encryption_key() {
+            return Err(SynapseError::EncryptionError("OpenPGP key has no encryption key".to_string()));
+        }
+        let addresses: Vec<String> = key
+            .details
+            .users
+            .iter()
+            .filter_map(|user| user_id_address(user.id.id()))
+            .collect();
+        let mut keys = self.keys.write().unwrap();
+        for address in &addresses {
+            keys.insert(address.clone(), CorrespondentKey::Pgp(Box::new(key.clone())));
+        }
+        info!("Added OpenPGP key {:?} for {:?}", key.fingerprint(), addresses);
+        Ok(addresses)
+    }
+
+    /// Add a PEM S/MIME certificate with an RSA key for `address`
+    pub fn add_smime_certificate(&self, address: &str, pem: &str) -> Result<()> {
+        let certificate = Certificate::from_pem(pem).map_err(|e| encryption_error("Invalid certificate", e))?;
+        let spki = certificate
+            .tbs_certificate
+            .subject_public_key_info
+            .to_der()
+            .map_err(|e| encryption_error("Invalid certificate key", e))?;
+        let key = RsaPublicKey::from_public_key_der(&spki)
+            .map_err(|e| encryption_error("S/MIME certificates need an RSA key", e))?;
+        let recipient = SmimeRecipient {
+            issuer_and_serial: IssuerAndSerialNumber {
+                issuer: certificate.tbs_certificate.issuer.clone(),
+                serial_number: certificate.tbs_certificate.serial_number.clone(),
+            },
+            key,
+        };
+        self.keys
+            .write()
+            .unwrap()
+            .insert(address.to_lowercase(), CorrespondentKey::Smime(Box::new(recipient)));
+        info!("Added S/MIME certificate for {}", address);
+        Ok(())
+    }
+
+    /// Use an ASCII-armored OpenPGP secret key to decrypt inbound mail
+    pub fn set_pgp_secret_key(&self, armored: &str, passphrase: &str) -> Result<()> {
+        let (key, _) = SignedSecretKey::from_string(armored).map_err(|e| encryption_error("Invalid OpenPGP secret key", e))?;
+        *self.secret.write().unwrap() = Some((key, passphrase.to_string()));
+        Ok(())
+    }
+
+    pub fn has_key(&self, address: &str) -> bool {
+        self.keys.read().unwrap().contains_key(&address.to_lowercase())
+    }
+
+    /// Drop the key held for `address`
+    pub fn forget(&self, address: &str) -> bool {
+        self.keys.write().unwrap().remove(&address.to_lowercase()).is_some()
+    }
+
+    /// Encrypt a plain-text body for `address`, or `None` without a key for it
+    pub fn encrypt(&self, address: &str, body: &str) -> Result<Option<EncryptedBody>> {
+        let Some(key) = self.keys.read().unwrap().get(&address.to_lowercase()).cloned() else {
+            return Ok(None);
+        };
+        // Both formats encrypt a whole MIME entity, not just the text
+        let entity = format!(
+            "Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}",
+            body
+        );
+        let encrypted = match key {
+            CorrespondentKey::Pgp(key) => EncryptedBody::PgpMime(pgp_mime(&key, &entity)?),
+            CorrespondentKey::Smime(recipient) => EncryptedBody::Smime(smime(&recipient, &entity)?),
+        };
+        debug!("Encrypted mail body for {}", address);
+        Ok(Some(encrypted))
+    }
+
+    /// The decrypted MIME entity of a PGP/MIME mail, or `None` if `mail` is not one
+    ///
+    /// Fails when the mail is PGP/MIME but cannot be decrypted with our key.
+    pub fn decrypt_pgp_mime(&self, mail: &mail_parser::Message<'_>) -> Result<Option<Vec<u8>>> {
+        if !is_pgp_mime(mail) {
+            return Ok(None);
+        }
+        let armored = mail
+            .attachments()
+            .find(|part| !part.is_content_type("application", "pgp-encrypted"))
+            .map(|part| String::from_utf8_lossy(part.contents()).into_owned())
+            .ok_or_else(|| SynapseError::InvalidMessageFormat("PGP/MIME mail without encrypted part".to_string()))?;
+        let secret = self.secret.read().unwrap();
+        let (key, passphrase) = secret
+            .as_ref()
+            .ok_or_else(|| SynapseError::EncryptionError("No OpenPGP secret key to decrypt mail".to_string()))?;
+        let (message, _) = pgp::Message::from_string(&armored).map_err(|e| encryption_error("Invalid PGP message", e))?;
+        let passphrase = passphrase.clone();
+        let (decrypted, _) = message
+            .decrypt(|| passphrase, &[key])
+            .map_err(|e| encryption_error("PGP decryption failed", e))?;
+        let content = decrypted
+            .decompress()
+            .and_then(|message| message.get_content())
+            .map_err(|e| encryption_error("PGP decryption failed", e))?
+            .ok_or_else(|| SynapseError::EncryptionError("PGP message has no content".to_string()))?;
+        Ok(Some(content))
+    }
+}
+
+/// Whether `mail` is `multipart/encrypted` with the PGP/MIME protocol
+pub fn is_pgp_mime(mail: &mail_parser::Message<'_>) -> bool {
+    mail.content_type().is_some_and(|content_type| {
+        content_type.ctype().eq_ignore_ascii_case("multipart")
+            && content_type.subtype().is_some_and(|subtype| subtype.eq_ignore_ascii_case("encrypted"))
+            && content_type
+                .attribute("protocol")
+                .is_some_and(|protocol| protocol.eq_ignore_ascii_case(PGP_ENCRYPTED))
+    })
+}
+
+/// The address in a user ID such as `Ada Lovelace <ada@example.org>`
+fn user_id_address(user_id: &str) -> Option<String> {
+    let address = match (user_id.rfind('<'), user_id.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &user_id[start + 1..end],
+        _ => user_id.trim(),
+    };
+    address.contains('@').then(|| address.to_lowercase())
+}
+
+fn encryption_subkey(key: &SignedPublicKey) -> Option<&pgp::SignedPublicSubKey> {
+    key.public_subkeys.iter().find(|subkey| subkey.is_encryption_key())
+}
+
+fn pgp_mime(key: &SignedPublicKey, entity: &str) -> Result<MultiPart> {
+    let message = pgp::Message::new_literal_bytes("", entity.as_bytes());
+    let mut rng = OsRng;
+    let encrypted = match encryption_subkey(key) {
+        Some(subkey) => message.encrypt_to_keys_seipdv1(&mut rng, SymmetricKeyAlgorithm::AES256, &[subkey]),
+        None => message.encrypt_to_keys_seipdv1(&mut rng, SymmetricKeyAlgorithm::AES256, &[key]),
+    }
+    .map_err(|e| encryption_error("PGP encryption failed", e))?;
+    let armored = encrypted
+        .to_armored_string(ArmorOptions::default())
+        .map_err(|e| encryption_error("PGP armoring failed", e))?;
+
+    Ok(MultiPart::encrypted(PGP_ENCRYPTED.to_string())
+        .singlepart(
+            SinglePart::builder()
+                .header(ContentType::parse(PGP_ENCRYPTED).unwrap())
+                .body("Version: 1\r\n".to_string()),
+        )
+        .singlepart(
+            SinglePart::builder()
+                .header(ContentType::parse("application/octet-stream; name=\"encrypted.asc\"").unwrap())
+                .header(ContentDisposition::inline_with_name("encrypted.asc"))
+                .body(armored),
+        ))
+}
+
+fn smime(recipient: &SmimeRecipient, entity: &str) -> Result<SinglePart> {
+    // The recipient builder holds its RNG until the envelope is built
+    let mut key_rng = OsRng;
+    let mut content_rng = OsRng;
+    let recipient_info = KeyTransRecipientInfoBuilder::new(
+        RecipientIdentifier::IssuerAndSerialNumber(recipient.issuer_and_serial.clone()),
+        KeyEncryptionInfo::Rsa(recipient.key.clone()),
+        &mut key_rng,
+    )
+    .map_err(|e| encryption_error("S/MIME recipient", e))?;
+    let mut builder = EnvelopedDataBuilder::new(None, entity.as_bytes(), ContentEncryptionAlgorithm::Aes256Cbc, None)
+        .map_err(|e| encryption_error("S/MIME encryption failed", e))?;
+    builder
+        .add_recipient_info(recipient_info)
+        .map_err(|e| encryption_error("S/MIME recipient", e))?;
+    let enveloped = builder
+        .build_with_rng(&mut content_rng)
+        .map_err(|e| encryption_error("S/MIME encryption failed", e))?;
+    let der = ContentInfo {
+        content_type: ID_ENVELOPED_DATA,
+        content: Any::encode_from(&enveloped).map_err(|e| encryption_error("S/MIME encoding failed", e))?,
+    }
+    .to_der()
+    .map_err(|e| encryption_error("S/MIME encoding failed", e))?;
+
+    Ok(SinglePart::builder()
+        .header(ContentType::parse("application/pkcs7-mime; smime-type=enveloped-data; name=\"smime.p7m\"").unwrap())
+        .header(ContentDisposition::attachment("smime.p7m"))
+        .body(der))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pgp_mime_is_recognized_and_needs_our_key() {
+        let raw = b"From: ada@example.org\r\n\
+To: bot@mydomain.com\r\n\
+Subject: ...\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/encrypted; protocol=\"application/pgp-encrypted\"; boundary=\"b\"\r\n\
+\r\n\
+--b\r\n\
+Content-Type: application/pgp-encrypted\r\n\
+\r\n\
+Version: 1\r\n\
+--b\r\n\
+Content-Type: application/octet-stream\r\n\
+\r\n\
+-----BEGIN PGP MESSAGE-----\r\n\
+-----END PGP MESSAGE-----\r\n\
+--b--\r\n";
+        let mail = mail_parser::MessageParser::default().parse(&raw[..]).unwrap();
+        assert!(is_pgp_mime(&mail));
+        let plain = mail_parser::MessageParser::default()
+            .parse(&b"From: ada@example.org\r\nContent-Type: text/plain\r\n\r\nhello\r\n"[..])
+            .unwrap();
+        assert!(!is_pgp_mime(&plain));
+
+        let keyring = MailKeyring::new();
+        assert!(keyring.decrypt_pgp_mime(&plain).unwrap().is_none());
+        assert!(keyring.decrypt_pgp_mime(&mail).is_err());
+        assert!(keyring.encrypt("ada@example.org", "hi").unwrap().is_none());
+
+        assert_eq!(user_id_address("Ada Lovelace <Ada@Example.org>"), Some("ada@example.org".to_string()));
+        assert_eq!(user_id_address("no address here"), None);
+    }
+}
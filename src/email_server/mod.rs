@@ -6,15 +6,27 @@ pub mod smtp_server;
 pub mod imap_server;
 pub mod connectivity;
 pub mod auth;
+pub mod store;
+#[cfg(feature = "email")]
+pub mod gateway;
+#[cfg(feature = "secure-email")]
+pub mod mail_crypto;
 
 pub use smtp_server::{SynapseSmtpServer, SmtpServerConfig, AuthHandler};
 pub use imap_server::{SynapseImapServer, ImapServerConfig};
 pub use connectivity::{ConnectivityDetector, ConnectivityAssessment, ServerRecommendation};
-pub use auth::{SynapseAuthHandler, UserAccount, UserPermissions, create_test_auth_handler};
+pub use auth::{SynapseAuthHandler, UserAccount, UserPermissions, create_test_auth_handler, default_accounts};
+pub use store::{IdleLease, MailboxStore, MemoryMailboxStore, open_mailbox_store};
+#[cfg(feature = "cache")]
+pub use store::RedisMailboxStore;
+#[cfg(feature = "email")]
+pub use gateway::{EmailAttachment, EmailGateway, GatewayPolicy};
+#[cfg(feature = "secure-email")]
+pub use mail_crypto::MailKeyring;
 
+use crate::config::MailStoreConfig;
 use crate::error::Result;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::sync::Arc;
 use tracing::{info, warn};
 
 /// Complete Synapse email server with both SMTP and IMAP
@@ -28,14 +40,23 @@ pub struct SynapseEmailServer {
 impl SynapseEmailServer {
     /// Create a new email server with automatic configuration
     pub async fn new() -> Result<Self> {
+        Self::with_mail_store(&MailStoreConfig::default()).await
+    }
+
+    /// Create an email server keeping mailboxes and accounts where `mail_store` says
+    ///
+    /// Instances configured with the same shared store can all accept SMTP
+    /// and serve IMAP for the same users.
+    pub async fn with_mail_store(mail_store: &MailStoreConfig) -> Result<Self> {
         // Assess connectivity first
         let detector = ConnectivityDetector::default();
         let connectivity = detector.assess_connectivity().await?;
         
         info!("Email server connectivity assessment: {:?}", connectivity.recommended_config);
         
-        // Create auth handler
-        let auth_handler = Arc::new(SynapseAuthHandler::new());
+        // Accounts and mailboxes share one store
+        let message_store = open_mailbox_store(mail_store, default_accounts())?;
+        let auth_handler = Arc::new(SynapseAuthHandler::with_store(Arc::clone(&message_store)));
         
         // Configure SMTP server
         let smtp_config = match &connectivity.recommended_config {
@@ -57,14 +78,12 @@ impl SynapseEmailServer {
                 }
             }
             _ => ImapServerConfig::default(),
-        };
-        
-        // Shared message store
-        let message_store = Arc::new(Mutex::new(HashMap::new()));
+        }
+        .with_mail_store(mail_store);
         
         // Create servers
-        let smtp_server = SynapseSmtpServer::new(smtp_config, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
-        let imap_server = SynapseImapServer::new(imap_config, Arc::clone(&message_store), Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
+        let smtp_server = SynapseSmtpServer::new(smtp_config, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>, Arc::clone(&message_store));
+        let imap_server = SynapseImapServer::new(imap_config, message_store, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
         
         Ok(Self {
             smtp_server,
@@ -81,10 +100,10 @@ impl SynapseEmailServer {
         connectivity: ConnectivityAssessment,
     ) -> Result<Self> {
         let auth_handler = Arc::new(SynapseAuthHandler::new());
-        let message_store = Arc::new(Mutex::new(HashMap::new()));
+        let message_store = auth_handler.store();
         
-        let smtp_server = SynapseSmtpServer::new(smtp_config, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
-        let imap_server = SynapseImapServer::new(imap_config, Arc::clone(&message_store), Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
+        let smtp_server = SynapseSmtpServer::new(smtp_config, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>, Arc::clone(&message_store));
+        let imap_server = SynapseImapServer::new(imap_config, message_store, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
         
         Ok(Self {
             smtp_server,
@@ -151,8 +170,8 @@ impl SynapseEmailServer {
     }
 
     /// Add user account
-    pub fn add_user(&self, user: UserAccount) -> Result<()> {
-        self.auth_handler.add_user(user)
+    pub async fn add_user(&self, user: UserAccount) -> Result<()> {
+        self.auth_handler.add_user(user).await
     }
 
     /// Add local domain for receiving email
@@ -165,6 +184,28 @@ impl SynapseEmailServer {
         self.auth_handler.add_relay_domain(domain)
     }
 
+    /// Route mail from plain email correspondents to the given addresses into Synapse
+    #[cfg(feature = "email")]
+    pub fn enable_gateway(&self, policy: GatewayPolicy) -> Arc<EmailGateway> {
+        let gateway = Arc::new(EmailGateway::new(policy));
+        self.smtp_server.set_gateway(Arc::clone(&gateway));
+        info!("Email gateway enabled for {:?}", gateway.policy().gateway_addresses);
+        gateway
+    }
+
+    /// Gateway for plain email correspondents, if enabled
+    #[cfg(feature = "email")]
+    pub fn gateway(&self) -> Option<Arc<EmailGateway>> {
+        self.smtp_server.gateway()
+    }
+
+    /// Take fast relay handoff submissions for the relay's peers over SMTP
+    #[cfg(feature = "crypto")]
+    pub fn enable_fast_relay(&self, relay: Arc<crate::transport::fast_relay::FastRelayEndpoint>) {
+        self.smtp_server.set_fast_relay(relay);
+        info!("SMTP server accepting fast relay handoffs");
+    }
+
     /// Check if server should be used based on connectivity
     pub fn should_use_local_server(&self) -> bool {
         matches!(
@@ -184,7 +225,7 @@ impl SynapseEmailServer {
 
 /// Create a test email server for development
 pub async fn create_test_email_server() -> Result<SynapseEmailServer> {
-    let auth_handler = Arc::new(create_test_auth_handler());
+    let auth_handler = Arc::new(create_test_auth_handler().await);
     
     // Use test configuration
     let smtp_config = SmtpServerConfig {
@@ -212,9 +253,9 @@ pub async fn create_test_email_server() -> Result<SynapseEmailServer> {
         },
     };
     
-    let message_store = Arc::new(Mutex::new(HashMap::new()));
-    let smtp_server = SynapseSmtpServer::new(smtp_config, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
-    let imap_server = SynapseImapServer::new(imap_config, Arc::clone(&message_store), Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
+    let message_store = auth_handler.store();
+    let smtp_server = SynapseSmtpServer::new(smtp_config, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>, Arc::clone(&message_store));
+    let imap_server = SynapseImapServer::new(imap_config, message_store, Arc::clone(&auth_handler) as Arc<dyn AuthHandler + Send + Sync>);
     
     Ok(SynapseEmailServer {
         smtp_server,
@@ -2,34 +2,53 @@
 
 use crate::error::{SynapseError, Result};
 use crate::types::SecureMessage;
+use crate::config::ListenerBinding;
+use crate::transport::binding::bind_tcp;
 use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
+use super::store::MailboxStore;
+use async_trait::async_trait;
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
-use tokio::net::{TcpListener, TcpStream};
+use std::net::{IpAddr, Ipv4Addr};
+use tokio::net::TcpStream;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{info, error, debug};
 use uuid::Uuid;
 use chrono::Utc;
+#[cfg(feature = "email")]
+use super::gateway::EmailGateway;
+#[cfg(feature = "crypto")]
+use crate::transport::fast_relay::FastRelayEndpoint;
 
 /// High-performance SMTP server optimized for EMRP
 pub struct SynapseSmtpServer {
     /// Server configuration
     config: SmtpServerConfig,
-    /// Message storage
-    message_store: Arc<Mutex<HashMap<String, Vec<SecureMessage>>>>,
+    /// Mailboxes accepted mail is delivered to, possibly shared with other instances
+    message_store: Arc<dyn MailboxStore>,
     /// Connected clients
     clients: Arc<Mutex<HashMap<String, ClientSession>>>,
     /// Authorization handler
     auth_handler: Arc<dyn AuthHandler + Send + Sync>,
     /// Performance metrics
     metrics: Arc<Mutex<ServerMetrics>>,
+    /// Gateway for mail from plain email correspondents, shared by clones
+    #[cfg(feature = "email")]
+    gateway: Arc<std::sync::RwLock<Option<Arc<EmailGateway>>>>,
+    /// Fast relay taking handoff submissions for its peers, shared by clones
+    #[cfg(feature = "crypto")]
+    fast_relay: Arc<std::sync::RwLock<Option<Arc<FastRelayEndpoint>>>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct SmtpServerConfig {
     /// SMTP port (usually 25, 587, or 2525)
     pub port: u16,
+    /// Interface address to bind (an IPv6 wildcard also accepts IPv4)
+    pub bind_address: IpAddr,
+    /// Inclusive range of ports to fall back to when `port` is taken
+    pub port_range: Option<(u16, u16)>,
     /// Maximum message size in bytes
     pub max_message_size: usize,
     /// Authentication required
@@ -64,6 +83,8 @@ struct ClientSession {
     #[allow(dead_code)]
     id: String,
     authenticated: bool,
+    /// Sender is not authorized, so only gateway recipients are accepted
+    gateway_only: bool,
     current_message: Option<SmtpMessage>,
     #[allow(dead_code)]
     connected_at: SystemTime,
@@ -71,10 +92,8 @@ struct ClientSession {
 
 #[derive(Debug)]
 struct SmtpMessage {
-    #[allow(dead_code)]
     from: String,
     to: Vec<String>,
-    #[allow(dead_code)]
     data: Vec<u8>,
 }
 
@@ -103,16 +122,31 @@ impl Default for ServerMetrics {
     }
 }
 
-pub trait AuthHandler {
-    fn authenticate(&self, username: &str, password: &str) -> Result<bool>;
-    fn is_authorized_sender(&self, email: &str) -> Result<bool>;
-    fn is_authorized_recipient(&self, email: &str) -> Result<bool>;
+#[async_trait]
+pub trait AuthHandler: Send + Sync {
+    async fn authenticate(&self, username: &str, password: &str) -> Result<bool>;
+    async fn is_authorized_sender(&self, email: &str) -> Result<bool>;
+    async fn is_authorized_recipient(&self, email: &str) -> Result<bool>;
+}
+
+impl SmtpServerConfig {
+    /// Configuration listening where `binding` says
+    pub fn from_binding(binding: &ListenerBinding) -> Self {
+        Self {
+            port: binding.port,
+            bind_address: binding.address,
+            port_range: binding.port_range,
+            ..Self::default()
+        }
+    }
 }
 
 impl Default for SmtpServerConfig {
     fn default() -> Self {
         Self {
             port: 2525, // Non-privileged port for development
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port_range: None,
             max_message_size: 25 * 1024 * 1024, // 25MB
             require_auth: true,
             tls_config: None,
@@ -127,25 +161,59 @@ impl Default for SmtpServerConfig {
 }
 
 impl SynapseSmtpServer {
-    /// Create a new SMTP server
+    /// Create a new SMTP server delivering into `message_store`
     pub fn new(
         config: SmtpServerConfig,
         auth_handler: Arc<dyn AuthHandler + Send + Sync>,
+        message_store: Arc<dyn MailboxStore>,
     ) -> Self {
         Self {
             config,
-            message_store: Arc::new(Mutex::new(HashMap::new())),
+            message_store,
             clients: Arc::new(Mutex::new(HashMap::new())),
             auth_handler,
             metrics: Arc::new(Mutex::new(ServerMetrics::default())),
+            #[cfg(feature = "email")]
+            gateway: Arc::new(std::sync::RwLock::new(None)),
+            #[cfg(feature = "crypto")]
+            fast_relay: Arc::new(std::sync::RwLock::new(None)),
         }
     }
 
+    /// Hand mail from plain email correspondents to a gateway
+    #[cfg(feature = "email")]
+    pub fn set_gateway(&self, gateway: Arc<EmailGateway>) {
+        *self.gateway.write().unwrap() = Some(gateway);
+    }
+
+    /// Gateway receiving mail from plain email correspondents, if any
+    #[cfg(feature = "email")]
+    pub fn gateway(&self) -> Option<Arc<EmailGateway>> {
+        self.gateway.read().unwrap().clone()
+    }
+
+    /// Hand relay handoff submissions for the relay's peers to `relay`
+    #[cfg(feature = "crypto")]
+    pub fn set_fast_relay(&self, relay: Arc<FastRelayEndpoint>) {
+        *self.fast_relay.write().unwrap() = Some(relay);
+    }
+
+    /// Fast relay taking handoff submissions, if any
+    #[cfg(feature = "crypto")]
+    pub fn fast_relay(&self) -> Option<Arc<FastRelayEndpoint>> {
+        self.fast_relay.read().unwrap().clone()
+    }
+
     /// Start the SMTP server
     pub async fn start(&self) -> Result<()> {
-        let addr = format!("0.0.0.0:{}", self.config.port);
-        let listener = TcpListener::bind(&addr).await
-            .map_err(|e| SynapseError::NetworkError(format!("Failed to bind SMTP server to {}: {}", addr, e)))?;
+        let listener = bind_tcp(&ListenerBinding {
+            address: self.config.bind_address,
+            port: self.config.port,
+            port_range: self.config.port_range,
+            dual_stack: true,
+        })?;
+        let addr = listener.local_addr()
+            .map_err(|e| SynapseError::NetworkError(format!("SMTP listener has no local address: {}", e)))?;
 
         info!("EMRP SMTP Server listening on {}", addr);
 
@@ -184,6 +252,7 @@ impl SynapseSmtpServer {
         let mut session = ClientSession {
             id: format!("smtp_{}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()),
             authenticated: false,
+            gateway_only: false,
             current_message: None,
             connected_at: SystemTime::now(),
         };
@@ -197,8 +266,29 @@ impl SynapseSmtpServer {
             let command = line.trim();
             debug!("SMTP command: {}", command);
 
-            let response = self.process_smtp_command(command, &mut session).await?;
-            
+            let mut response = self.process_smtp_command(command, &mut session).await?;
+
+            if response.starts_with("354") {
+                writer.write_all(response.as_bytes()).await?;
+                writer.flush().await?;
+                let data = self.read_data(&mut reader).await?;
+                response = match (data, session.current_message.take()) {
+                    (None, _) => "552 Message exceeds maximum size\r\n".to_string(),
+                    (Some(data), Some(mut message)) => {
+                        message.data = data;
+                        match self.complete_message(message).await {
+                            Ok(()) => "250 Ok: queued\r\n".to_string(),
+                            Err(SynapseError::AuthorizationError(_)) => "550 Sender not accepted\r\n".to_string(),
+                            Err(e) => {
+                                error!("Failed to process SMTP message: {}", e);
+                                "451 Local error in processing\r\n".to_string()
+                            }
+                        }
+                    }
+                    (Some(_), None) => "503 Bad sequence of commands\r\n".to_string(),
+                };
+            }
+
             writer.write_all(response.as_bytes()).await?;
             writer.flush().await?;
 
@@ -255,7 +345,7 @@ impl SynapseSmtpServer {
                                         let username = auth_parts[1];
                                         let password = auth_parts[2];
                                         
-                                        match self.auth_handler.authenticate(username, password) {
+                                        match self.auth_handler.authenticate(username, password).await {
                                             Ok(true) => {
                                                 session.authenticated = true;
                                                 return Ok("235 Authentication successful\r\n".to_string());
@@ -277,24 +367,28 @@ impl SynapseSmtpServer {
                 }
             }
             "MAIL" => {
-                if self.config.require_auth && !session.authenticated {
+                // Outside correspondents may only write to gateway addresses and relay peers
+                let gateway_available = self.has_gateway() || self.has_fast_relay();
+                if self.config.require_auth && !session.authenticated && !gateway_available {
                     return Ok("530 Authentication required\r\n".to_string());
                 }
                 
                 if let Some(from_addr) = self.extract_email_from_mail_from(command) {
                     // Verify sender authorization
-                    match self.auth_handler.is_authorized_sender(&from_addr) {
-                        Ok(true) => {
-                            session.current_message = Some(SmtpMessage {
-                                from: from_addr,
-                                to: Vec::new(),
-                                data: Vec::new(),
-                            });
-                            Ok("250 Ok\r\n".to_string())
-                        }
-                        Ok(false) => Ok("550 Sender not authorized\r\n".to_string()),
-                        Err(_) => Ok("451 Temporary failure\r\n".to_string()),
+                    let authorized = match self.auth_handler.is_authorized_sender(&from_addr).await {
+                        Ok(authorized) => authorized && (session.authenticated || !self.config.require_auth),
+                        Err(_) => return Ok("451 Temporary failure\r\n".to_string()),
+                    };
+                    if !authorized && !gateway_available {
+                        return Ok("550 Sender not authorized\r\n".to_string());
                     }
+                    session.gateway_only = !authorized;
+                    session.current_message = Some(SmtpMessage {
+                        from: from_addr,
+                        to: Vec::new(),
+                        data: Vec::new(),
+                    });
+                    Ok("250 Ok\r\n".to_string())
                 } else {
                     Ok("501 Syntax error in MAIL command\r\n".to_string())
                 }
@@ -305,8 +399,17 @@ impl SynapseSmtpServer {
                 }
                 
                 if let Some(to_addr) = self.extract_email_from_rcpt_to(command) {
+                    if self.is_gateway_address(&to_addr) || self.is_relay_peer(&to_addr) {
+                        if let Some(ref mut msg) = session.current_message {
+                            msg.to.push(to_addr);
+                        }
+                        return Ok("250 Ok\r\n".to_string());
+                    }
+                    if session.gateway_only {
+                        return Ok("550 Recipient not accepted from unauthenticated senders\r\n".to_string());
+                    }
                     // Verify recipient authorization
-                    match self.auth_handler.is_authorized_recipient(&to_addr) {
+                    match self.auth_handler.is_authorized_recipient(&to_addr).await {
                         Ok(true) => {
                             if let Some(ref mut msg) = session.current_message {
                                 msg.to.push(to_addr);
@@ -342,6 +445,120 @@ impl SynapseSmtpServer {
         }
     }
 
+    fn has_gateway(&self) -> bool {
+        #[cfg(feature = "email")]
+        {
+            self.gateway().is_some()
+        }
+        #[cfg(not(feature = "email"))]
+        {
+            false
+        }
+    }
+
+    fn has_fast_relay(&self) -> bool {
+        #[cfg(feature = "crypto")]
+        {
+            self.fast_relay().is_some()
+        }
+        #[cfg(not(feature = "crypto"))]
+        {
+            false
+        }
+    }
+
+    #[cfg_attr(not(feature = "crypto"), allow(unused_variables))]
+    fn is_relay_peer(&self, address: &str) -> bool {
+        #[cfg(feature = "crypto")]
+        {
+            self.fast_relay().is_some_and(|relay| relay.serves(address))
+        }
+        #[cfg(not(feature = "crypto"))]
+        {
+            false
+        }
+    }
+
+    #[cfg_attr(not(feature = "email"), allow(unused_variables))]
+    fn is_gateway_address(&self, address: &str) -> bool {
+        #[cfg(feature = "email")]
+        {
+            self.gateway().is_some_and(|g| g.handles_any(&[address.to_string()]))
+        }
+        #[cfg(not(feature = "email"))]
+        {
+            false
+        }
+    }
+
+    /// Read message content after DATA up to the terminating "." line
+    ///
+    /// Returns `None` when the content exceeds the maximum message size; the
+    /// rest of the content is still consumed so the session stays in sync.
+    async fn read_data<R: AsyncBufReadExt + Unpin>(&self, reader: &mut R) -> Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        let mut oversized = false;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                return Err(SynapseError::ConnectionError("Connection closed during DATA".to_string()));
+            }
+            let content = line.trim_end_matches(['\r', '\n']);
+            if content == "." {
+                break;
+            }
+            // Undo dot-stuffing (RFC 5321 section 4.5.2)
+            let content = content.strip_prefix('.').unwrap_or(content);
+            if data.len() + content.len() + 2 > self.config.max_message_size {
+                oversized = true;
+            }
+            if !oversized {
+                data.extend_from_slice(content.as_bytes());
+                data.extend_from_slice(b"\r\n");
+            }
+        }
+        Ok((!oversized).then_some(data))
+    }
+
+    /// Route a fully received message to the fast relay, the gateway or the message store
+    #[cfg_attr(not(any(feature = "email", feature = "crypto")), allow(unused_mut))]
+    async fn complete_message(&self, mut message: SmtpMessage) -> Result<()> {
+        #[cfg(feature = "crypto")]
+        if let Some(relay) = self.fast_relay() {
+            if relay.accept_submission(&message.data).await?.is_some() {
+                self.metrics.lock().unwrap().messages_received += 1;
+                return Ok(());
+            }
+            // Relay peers were only let through for handoffs
+            let mut kept = Vec::new();
+            for to in std::mem::take(&mut message.to) {
+                if !relay.serves(&to) || self.auth_handler.is_authorized_recipient(&to).await.unwrap_or(false) {
+                    kept.push(to);
+                }
+            }
+            if kept.is_empty() {
+                return Err(SynapseError::AuthorizationError("Only relay handoffs are accepted for relay peers".to_string()));
+            }
+            message.to = kept;
+        }
+        #[cfg(feature = "email")]
+        if let Some(gateway) = self.gateway() {
+            if gateway.handles_any(&message.to) {
+                let converted = gateway.ingest(&message.data, &message.from, &message.to)?;
+                if !converted.is_empty() {
+                    self.metrics.lock().unwrap().messages_received += 1;
+                    // Any other recipients still get the mail as usual
+                    message.to.retain(|to| !gateway.handles_any(std::slice::from_ref(to)));
+                    if message.to.is_empty() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        self.store_message(message).await
+    }
+
     /// Extract email address from MAIL FROM command
     fn extract_email_from_mail_from(&self, command: &str) -> Option<String> {
         // Parse "MAIL FROM:<email@domain.com>"
@@ -369,7 +586,6 @@ impl SynapseSmtpServer {
     }
 
     /// Store message in the server
-    #[allow(dead_code)]
     async fn store_message(&self, message: SmtpMessage) -> Result<()> {
         let start_time = SystemTime::now();
         
@@ -384,15 +600,12 @@ impl SynapseSmtpServer {
             security_level: crate::types::SecurityLevel::Private,
             routing_path: Vec::new(),
             metadata: std::collections::HashMap::new(),
+            attachments: Vec::new(),
         };
 
         // Store message for each recipient
-        {
-            let mut store = self.message_store.lock().unwrap();
-            for recipient in &message.to {
-                let messages = store.entry(recipient.clone()).or_insert_with(Vec::new);
-                messages.push(secure_message.clone());
-            }
+        for recipient in &message.to {
+            self.message_store.deliver(recipient, &secure_message).await?;
         }
 
         // Update metrics
@@ -412,9 +625,8 @@ impl SynapseSmtpServer {
     }
 
     /// Get messages for a recipient
-    pub fn get_messages(&self, recipient: &str) -> Result<Vec<SecureMessage>> {
-        let store = self.message_store.lock().unwrap();
-        Ok(store.get(recipient).cloned().unwrap_or_default())
+    pub async fn get_messages(&self, recipient: &str) -> Result<Vec<SecureMessage>> {
+        self.message_store.messages(recipient).await
     }
 
     /// Get server metrics
@@ -431,6 +643,8 @@ impl Clone for SynapseSmtpServer {
             clients: Arc::clone(&self.clients),
             auth_handler: Arc::clone(&self.auth_handler),
             metrics: Arc::clone(&self.metrics),
+            #[cfg(feature = "email")]
+            gateway: Arc::clone(&self.gateway),
         }
     }
 }
@@ -0,0 +1,352 @@
+//! Mailbox and account storage for the email servers
+//!
+//! SMTP delivers into a [`MailboxStore`], IMAP reads from it and the auth
+//! handler keeps accounts in it. With a store shared between processes
+//! ([`RedisMailboxStore`], feature `cache`) any number of server instances can
+//! accept SMTP and serve IMAP behind a load balancer: mail accepted by one
+//! instance is visible to IMAP clients of every other.
+//!
+//! IMAP IDLE sessions hold an [`IdleLease`] naming the instance serving them.
+//! Instances renew their leases while the client is connected, so the number
+//! of IDLE sessions per user can be capped cluster-wide and sessions of an
+//! instance that went away expire on their own.
+
+use super::auth::UserAccount;
+use crate::config::MailStoreConfig;
+use crate::error::{Result, SynapseError};
+use crate::types::SecureMessage;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// An IMAP IDLE session held open by one server instance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleLease {
+    /// User whose mailbox is being watched
+    pub user: String,
+    /// IMAP session holding the lease
+    pub session_id: String,
+    /// Server instance the client is connected to
+    pub instance: String,
+    /// When the lease lapses unless renewed, in Unix milliseconds
+    pub expires_at_ms: i64,
+}
+
+impl IdleLease {
+    /// Whether the lease is still held at `now_ms`
+    pub fn is_live_at(&self, now_ms: i64) -> bool {
+        self.expires_at_ms > now_ms
+    }
+}
+
+/// Storage for mailboxes, accounts and IDLE leases
+#[async_trait]
+pub trait MailboxStore: Send + Sync {
+    /// Append a message to a mailbox
+    async fn deliver(&self, mailbox: &str, message: &SecureMessage) -> Result<()>;
+
+    /// Messages in a mailbox, oldest first
+    async fn messages(&self, mailbox: &str) -> Result<Vec<SecureMessage>>;
+
+    /// Number of messages in a mailbox
+    async fn count(&self, mailbox: &str) -> Result<usize>;
+
+    /// Account by username
+    async fn account(&self, username: &str) -> Result<Option<UserAccount>>;
+
+    /// Account by email address
+    async fn account_by_email(&self, email: &str) -> Result<Option<UserAccount>>;
+
+    /// Create or replace an account
+    async fn put_account(&self, account: &UserAccount) -> Result<()>;
+
+    /// Delete an account, returning whether it existed
+    async fn remove_account(&self, username: &str) -> Result<bool>;
+
+    /// Take or renew an IDLE lease
+    ///
+    /// Fails (returns false) when the user already holds `max_per_user` other
+    /// live leases. Expired leases are dropped first.
+    async fn acquire_idle_lease(&self, lease: &IdleLease, max_per_user: usize, now_ms: i64) -> Result<bool>;
+
+    /// Give up an IDLE lease
+    async fn release_idle_lease(&self, user: &str, session_id: &str) -> Result<()>;
+
+    /// Live IDLE leases for a user
+    async fn idle_leases(&self, user: &str, now_ms: i64) -> Result<Vec<IdleLease>>;
+}
+
+/// In-memory mailbox store, for a single server instance
+#[derive(Default)]
+pub struct MemoryMailboxStore {
+    mailboxes: DashMap<String, Vec<SecureMessage>>,
+    accounts: DashMap<String, UserAccount>,
+    leases: DashMap<String, Vec<IdleLease>>,
+}
+
+impl MemoryMailboxStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a store holding the given accounts
+    pub fn with_accounts(accounts: impl IntoIterator<Item = UserAccount>) -> Self {
+        let store = Self::new();
+        for account in accounts {
+            store.accounts.insert(account.username.clone(), account);
+        }
+        store
+    }
+}
+
+#[async_trait]
+impl MailboxStore for MemoryMailboxStore {
+    async fn deliver(&self, mailbox: &str, message: &SecureMessage) -> Result<()> {
+        self.mailboxes.entry(mailbox.to_string()).or_default().push(message.clone());
+        Ok(())
+    }
+
+    async fn messages(&self, mailbox: &str) -> Result<Vec<SecureMessage>> {
+        Ok(self.mailboxes.get(mailbox).map(|m| m.clone()).unwrap_or_default())
+    }
+
+    async fn count(&self, mailbox: &str) -> Result<usize> {
+        Ok(self.mailboxes.get(mailbox).map_or(0, |m| m.len()))
+    }
+
+    async fn account(&self, username: &str) -> Result<Option<UserAccount>> {
+        Ok(self.accounts.get(username).map(|a| a.clone()))
+    }
+
+    async fn account_by_email(&self, email: &str) -> Result<Option<UserAccount>> {
+        Ok(self.accounts.iter().find(|a| a.email == email).map(|a| a.value().clone()))
+    }
+
+    async fn put_account(&self, account: &UserAccount) -> Result<()> {
+        self.accounts.insert(account.username.clone(), account.clone());
+        Ok(())
+    }
+
+    async fn remove_account(&self, username: &str) -> Result<bool> {
+        Ok(self.accounts.remove(username).is_some())
+    }
+
+    async fn acquire_idle_lease(&self, lease: &IdleLease, max_per_user: usize, now_ms: i64) -> Result<bool> {
+        let mut leases = self.leases.entry(lease.user.clone()).or_default();
+        leases.retain(|held| held.is_live_at(now_ms) && held.session_id != lease.session_id);
+        if leases.len() >= max_per_user {
+            return Ok(false);
+        }
+        leases.push(lease.clone());
+        Ok(true)
+    }
+
+    async fn release_idle_lease(&self, user: &str, session_id: &str) -> Result<()> {
+        if let Some(mut leases) = self.leases.get_mut(user) {
+            leases.retain(|held| held.session_id != session_id);
+        }
+        Ok(())
+    }
+
+    async fn idle_leases(&self, user: &str, now_ms: i64) -> Result<Vec<IdleLease>> {
+        Ok(self
+            .leases
+            .get(user)
+            .map(|leases| leases.iter().filter(|l| l.is_live_at(now_ms)).cloned().collect())
+            .unwrap_or_default())
+    }
+}
+
+/// Drops expired leases and stores `ARGV[2]` under session `ARGV[1]` unless
+/// `ARGV[4]` other live leases exist; `ARGV[3]` is now in Unix milliseconds
+#[cfg(feature = "cache")]
+const ACQUIRE_IDLE_LEASE: &str = r#"
+local leases = redis.call('HGETALL', KEYS[1])
+local live = 0
+for i = 1, #leases, 2 do
+    local lease = cjson.decode(leases[i + 1])
+    if lease.expires_at_ms <= tonumber(ARGV[3]) then
+        redis.call('HDEL', KEYS[1], leases[i])
+    elseif leases[i] ~= ARGV[1] then
+        live = live + 1
+    end
+end
+if live >= tonumber(ARGV[4]) then
+    return 0
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+return 1
+"#;
+
+/// Redis-backed mailbox store, shared by every server instance
+#[cfg(feature = "cache")]
+pub struct RedisMailboxStore {
+    client: redis::Client,
+    prefix: String,
+}
+
+#[cfg(feature = "cache")]
+impl RedisMailboxStore {
+    /// Connect to Redis; keys are namespaced under `prefix`
+    pub fn new(redis_url: &str, prefix: impl Into<String>) -> Result<Self> {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| SynapseError::ConfigurationError(format!("Invalid mail store Redis URL: {}", e)))?;
+        Ok(Self { client, prefix: prefix.into() })
+    }
+
+    fn key(&self, parts: &[&str]) -> String {
+        format!("{}:{}", self.prefix, parts.join(":"))
+    }
+
+    async fn connection(&self) -> Result<redis::aio::MultiplexedConnection> {
+        self.client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(Self::error)
+    }
+
+    fn error(e: redis::RedisError) -> SynapseError {
+        SynapseError::DatabaseError(format!("Mail store: {}", e))
+    }
+
+    async fn query<T: redis::FromRedisValue>(&self, cmd: &redis::Cmd) -> Result<T> {
+        let mut conn = self.connection().await?;
+        cmd.query_async(&mut conn).await.map_err(Self::error)
+    }
+}
+
+#[cfg(feature = "cache")]
+#[async_trait]
+impl MailboxStore for RedisMailboxStore {
+    async fn deliver(&self, mailbox: &str, message: &SecureMessage) -> Result<()> {
+        let _: i64 = self
+            .query(redis::cmd("RPUSH").arg(self.key(&["mailbox", mailbox])).arg(serde_json::to_string(message)?))
+            .await?;
+        Ok(())
+    }
+
+    async fn messages(&self, mailbox: &str) -> Result<Vec<SecureMessage>> {
+        let stored: Vec<String> = self
+            .query(redis::cmd("LRANGE").arg(self.key(&["mailbox", mailbox])).arg(0).arg(-1))
+            .await?;
+        stored
+            .iter()
+            .map(|json| serde_json::from_str(json).map_err(Into::into))
+            .collect()
+    }
+
+    async fn count(&self, mailbox: &str) -> Result<usize> {
+        self.query(redis::cmd("LLEN").arg(self.key(&["mailbox", mailbox]))).await
+    }
+
+    async fn account(&self, username: &str) -> Result<Option<UserAccount>> {
+        let stored: Option<String> = self
+            .query(redis::cmd("HGET").arg(self.key(&["accounts"])).arg(username))
+            .await?;
+        Ok(stored.map(|json| serde_json::from_str(&json)).transpose()?)
+    }
+
+    async fn account_by_email(&self, email: &str) -> Result<Option<UserAccount>> {
+        let stored: Vec<String> = self.query(redis::cmd("HVALS").arg(self.key(&["accounts"]))).await?;
+        Ok(stored
+            .iter()
+            .filter_map(|json| serde_json::from_str::<UserAccount>(json).ok())
+            .find(|account| account.email == email))
+    }
+
+    async fn put_account(&self, account: &UserAccount) -> Result<()> {
+        let _: i64 = self
+            .query(
+                redis::cmd("HSET")
+                    .arg(self.key(&["accounts"]))
+                    .arg(&account.username)
+                    .arg(serde_json::to_string(account)?),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_account(&self, username: &str) -> Result<bool> {
+        let removed: i64 = self.query(redis::cmd("HDEL").arg(self.key(&["accounts"])).arg(username)).await?;
+        Ok(removed > 0)
+    }
+
+    async fn acquire_idle_lease(&self, lease: &IdleLease, max_per_user: usize, now_ms: i64) -> Result<bool> {
+        let mut conn = self.connection().await?;
+        let acquired: i64 = redis::Script::new(ACQUIRE_IDLE_LEASE)
+            .key(self.key(&["idle", &lease.user]))
+            .arg(&lease.session_id)
+            .arg(serde_json::to_string(lease)?)
+            .arg(now_ms)
+            .arg(max_per_user)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(Self::error)?;
+        Ok(acquired == 1)
+    }
+
+    async fn release_idle_lease(&self, user: &str, session_id: &str) -> Result<()> {
+        let _: i64 = self.query(redis::cmd("HDEL").arg(self.key(&["idle", user])).arg(session_id)).await?;
+        Ok(())
+    }
+
+    async fn idle_leases(&self, user: &str, now_ms: i64) -> Result<Vec<IdleLease>> {
+        let stored: Vec<String> = self.query(redis::cmd("HVALS").arg(self.key(&["idle", user]))).await?;
+        Ok(stored
+            .iter()
+            .filter_map(|json| serde_json::from_str::<IdleLease>(json).ok())
+            .filter(|lease| lease.is_live_at(now_ms))
+            .collect())
+    }
+}
+
+/// Open the store the configuration describes
+///
+/// Without a `redis_url` this is an in-memory store holding `accounts`; a
+/// shared store keeps whatever accounts were provisioned in it.
+pub fn open_mailbox_store(
+    config: &MailStoreConfig,
+    accounts: impl IntoIterator<Item = UserAccount>,
+) -> Result<Arc<dyn MailboxStore>> {
+    match &config.redis_url {
+        #[cfg(feature = "cache")]
+        Some(url) => Ok(Arc::new(RedisMailboxStore::new(url, format!("synapse:mail:{}", config.name))?)),
+        #[cfg(not(feature = "cache"))]
+        Some(_) => Err(SynapseError::ConfigurationError(
+            "A Redis mail store requires the `cache` feature".to_string(),
+        )),
+        None => Ok(Arc::new(MemoryMailboxStore::with_accounts(accounts))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lease(session_id: &str, expires_at_ms: i64) -> IdleLease {
+        IdleLease {
+            user: "alice".to_string(),
+            session_id: session_id.to_string(),
+            instance: "mail-a".to_string(),
+            expires_at_ms,
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_leases_are_capped_per_user() {
+        let store = MemoryMailboxStore::new();
+        assert!(store.acquire_idle_lease(&lease("s1", 1_000), 2, 0).await.unwrap());
+        assert!(store.acquire_idle_lease(&lease("s2", 1_000), 2, 0).await.unwrap());
+        assert!(!store.acquire_idle_lease(&lease("s3", 1_000), 2, 0).await.unwrap());
+
+        // Renewing a held lease does not count against the cap
+        assert!(store.acquire_idle_lease(&lease("s2", 2_000), 2, 500).await.unwrap());
+        assert_eq!(store.idle_leases("alice", 500).await.unwrap().len(), 2);
+
+        // Expired and released leases free their slot
+        assert!(store.acquire_idle_lease(&lease("s3", 3_000), 2, 1_500).await.unwrap());
+        store.release_idle_lease("alice", "s3").await.unwrap();
+        assert_eq!(store.idle_leases("alice", 1_500).await.unwrap(), vec![lease("s2", 2_000)]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Pacing of outgoing email within provider send quotas
+//!
+//! Gmail, SES and friends count messages per minute and per day and suspend
+//! accounts that go over, so a broadcast to a few hundred peers over email
+//! must not be sent as fast as the SMTP connection allows. Every send waits
+//! its turn in a FIFO queue for a free slot in the rolling minute and day
+//! windows, then for a concurrency permit. A 4xx deferral from the server
+//! pauses the whole queue with exponential backoff, since the provider is
+//! throttling the account rather than the message.
+
+use crate::{
+    config::{EmailProvider, EmailSendingConfig},
+    error::{EmailError, Result},
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{Mutex as QueueLock, OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+const MINUTE: Duration = Duration::from_secs(60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Send quotas of an SMTP account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendLimits {
+    /// Messages per minute, if limited
+    pub per_minute: Option<u32>,
+    /// Messages per rolling 24 hours, if limited
+    pub per_day: Option<u32>,
+    /// Sends in flight at once
+    pub max_concurrent: usize,
+}
+
+impl EmailProvider {
+    /// Guess the provider from the SMTP host name
+    pub fn detect(smtp_host: &str) -> Self {
+        let host = smtp_host.trim_end_matches('.').to_ascii_lowercase();
+        if host == "smtp-relay.gmail.com" {
+            EmailProvider::GoogleWorkspace
+        } else if host == "smtp.gmail.com" || host == "smtp.googlemail.com" {
+            EmailProvider::Gmail
+        } else if host.starts_with("email-smtp.") && host.ends_with(".amazonaws.com") {
+            EmailProvider::AmazonSes
+        } else if host == "smtp.office365.com" || host == "smtp-mail.outlook.com" {
+            EmailProvider::Outlook
+        } else {
+            EmailProvider::Custom
+        }
+    }
+
+    /// Published quotas, rounded down to leave headroom
+    pub fn limits(&self) -> SendLimits {
+        match self {
+            EmailProvider::Gmail => SendLimits {
+                per_minute: Some(20),
+                per_day: Some(500),
+                max_concurrent: 2,
+            },
+            EmailProvider::GoogleWorkspace => SendLimits {
+                per_minute: Some(60),
+                per_day: Some(2_000),
+                max_concurrent: 4,
+            },
+            EmailProvider::AmazonSes => SendLimits {
+                per_minute: Some(840),
+                per_day: Some(50_000),
+                max_concurrent: 10,
+            },
+            EmailProvider::Outlook => SendLimits {
+                per_minute: Some(30),
+                per_day: Some(10_000),
+                max_concurrent: 3,
+            },
+            EmailProvider::Custom => SendLimits {
+                per_minute: None,
+                per_day: None,
+                max_concurrent: 4,
+            },
+        }
+    }
+}
+
+/// Retry timing for deferred sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackoffPolicy {
+    pub max_retries: u32,
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl BackoffPolicy {
+    /// Delay before retry number `attempt`, counting from zero
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max)
+    }
+}
+
+enum Slot {
+    Ready,
+    Wait(Duration),
+    /// The daily quota is used up; a slot frees after the duration
+    Exhausted(Duration),
+}
+
+#[derive(Default)]
+struct Windows {
+    minute: VecDeque<Instant>,
+    day: VecDeque<Instant>,
+    paused_until: Option<Instant>,
+}
+
+/// Queue releasing email sends within an account's limits
+pub struct SendPacer {
+    limits: SendLimits,
+    backoff: BackoffPolicy,
+    queue: QueueLock<()>,
+    permits: Arc<Semaphore>,
+    windows: Mutex<Windows>,
+}
+
+impl SendPacer {
+    pub fn new(limits: SendLimits, backoff: BackoffPolicy) -> Self {
+        Self {
+            limits,
+            backoff,
+            queue: QueueLock::new(()),
+            permits: Arc::new(Semaphore::new(limits.max_concurrent.max(1))),
+            windows: Mutex::new(Windows::default()),
+        }
+    }
+
+    /// Pacer for an account on `smtp_host`, applying the configured overrides
+    pub fn from_config(config: &EmailSendingConfig, smtp_host: &str) -> Self {
+        let provider = config.provider.unwrap_or_else(|| EmailProvider::detect(smtp_host));
+        let defaults = provider.limits();
+        let limits = SendLimits {
+            per_minute: config.per_minute.or(defaults.per_minute),
+            per_day: config.per_day.or(defaults.per_day),
+            max_concurrent: config.max_concurrent.unwrap_or(defaults.max_concurrent),
+        };
+        let backoff = BackoffPolicy {
+            max_retries: config.max_retries,
+            initial: Duration::from_millis(config.backoff_initial_ms),
+            max: Duration::from_millis(config.backoff_max_ms),
+        };
+        Self::new(limits, backoff)
+    }
+
+    pub fn limits(&self) -> SendLimits {
+        self.limits
+    }
+
+    pub fn backoff(&self) -> BackoffPolicy {
+        self.backoff
+    }
+
+    /// Wait for this send's turn; the permit is held while the message is sent
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit> {
+        {
+            let _turn = self.queue.lock().await;
+            loop {
+                match self.reserve(Instant::now()) {
+                    Slot::Ready => break,
+                    Slot::Wait(delay) => tokio::time::sleep(delay).await,
+                    Slot::Exhausted(delay) => {
+                        return Err(EmailError::SendFailed(format!(
+                            "Daily send quota of {} messages reached; next slot in {}s",
+                            self.limits.per_day.unwrap_or_default(),
+                            delay.as_secs()
+                        )));
+                    }
+                }
+            }
+        }
+        Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(|e| EmailError::SendFailed(e.to_string()))
+    }
+
+    /// Record that the server deferred retry number `attempt` and pause the
+    /// queue; returns the pause
+    pub fn deferred(&self, attempt: u32) -> Duration {
+        let delay = self.backoff.delay(attempt);
+        let until = Instant::now() + delay;
+        let mut windows = self.windows.lock().unwrap();
+        if windows.paused_until.is_none_or(|paused| paused < until) {
+            windows.paused_until = Some(until);
+        }
+        warn!("SMTP server deferred a send; pausing email for {}ms", delay.as_millis());
+        delay
+    }
+
+    /// Take a send slot at `now` if the windows allow one
+    fn reserve(&self, now: Instant) -> Slot {
+        let mut windows = self.windows.lock().unwrap();
+        if let Some(until) = windows.paused_until {
+            if until > now {
+                return Slot::Wait(until - now);
+            }
+            windows.paused_until = None;
+        }
+        while windows.minute.front().is_some_and(|sent| now.saturating_duration_since(*sent) >= MINUTE) {
+            windows.minute.pop_front();
+        }
+        while windows.day.front().is_some_and(|sent| now.saturating_duration_since(*sent) >= DAY) {
+            windows.day.pop_front();
+        }
+        if let (Some(limit), Some(oldest)) = (self.limits.per_day, windows.day.front()) {
+            if windows.day.len() >= limit as usize {
+                return Slot::Exhausted(DAY - now.saturating_duration_since(*oldest));
+            }
+        }
+        if let (Some(limit), Some(oldest)) = (self.limits.per_minute, windows.minute.front()) {
+            if windows.minute.len() >= limit as usize {
+                return Slot::Wait(MINUTE - now.saturating_duration_since(*oldest));
+            }
+        }
+        windows.minute.push_back(now);
+        if self.limits.per_day.is_some() {
+            windows.day.push_back(now);
+        }
+        Slot::Ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_are_paced_within_provider_limits() {
+        assert_eq!(EmailProvider::detect("smtp.gmail.com"), EmailProvider::Gmail);
+        assert_eq!(EmailProvider::detect("email-smtp.eu-west-1.amazonaws.com"), EmailProvider::AmazonSes);
+        assert_eq!(EmailProvider::detect("mail.example.com"), EmailProvider::Custom);
+
+        let config = EmailSendingConfig {
+            per_minute: Some(2),
+            per_day: Some(3),
+            ..Default::default()
+        };
+        let pacer = SendPacer::from_config(&config, "smtp.gmail.com");
+        assert_eq!(pacer.limits().max_concurrent, 2);
+
+        let start = Instant::now();
+        assert!(matches!(pacer.reserve(start), Slot::Ready));
+        assert!(matches!(pacer.reserve(start + Duration::from_secs(1)), Slot::Ready));
+        // Third send in the same minute waits for the first to age out
+        match pacer.reserve(start + Duration::from_secs(10)) {
+            Slot::Wait(delay) => assert_eq!(delay, Duration::from_secs(50)),
+            _ => panic!("expected to wait"),
+        }
+        assert!(matches!(pacer.reserve(start + Duration::from_secs(61)), Slot::Ready));
+        // Daily quota of three is used up
+        assert!(matches!(pacer.reserve(start + Duration::from_secs(200)), Slot::Exhausted(_)));
+
+        let backoff = pacer.backoff();
+        assert_eq!(backoff.delay(0), Duration::from_secs(2));
+        assert_eq!(backoff.delay(2), Duration::from_secs(8));
+        assert_eq!(backoff.delay(10), Duration::from_secs(120));
+    }
+}
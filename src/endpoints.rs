@@ -0,0 +1,199 @@
+//! # Endpoint Updates
+//!
+//! A node's address is not fixed: its external IP changes when the network
+//! reconnects, and a listener on an ephemeral port gets a new port after a
+//! restart. Peers that cached the old address would keep failing until they
+//! rediscovered the node, so the node announces the change instead.
+//!
+//! An [`EndpointUpdate`] lists where each of the node's transports can be
+//! reached now, signed with the node's own key. It is sent to recent contacts
+//! as a system message and published to every registered discovery backend
+//! ([`EndpointPublisher`]: a DHT, DNS zone, or participant registry).
+//! Receivers check the signature against the participant's key and keep the
+//! newest update per participant in an [`EndpointCache`], which the
+//! multi-transport router consults before dialing.
+
+use crate::crypto::CryptoManager;
+use crate::error::Result;
+use crate::identity::LocalIdentity;
+use crate::synapse::blockchain::serialization::DateTimeWrapper;
+use crate::types::{MessageType, SimpleMessage};
+use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Metadata key marking a message as an endpoint update
+pub const ENDPOINT_UPDATE_KEY: &str = "endpoint_update";
+
+/// Signed statement of where a participant's transports can be reached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointUpdate {
+    /// Participant the endpoints belong to, and whose key signs the update
+    pub global_id: String,
+    /// `host:port` per transport name (`tcp`, `udp`, ...)
+    pub endpoints: BTreeMap<String, String>,
+    /// When the update was issued; later updates supersede earlier ones
+    pub issued_at: DateTimeWrapper,
+    /// Signature over [`EndpointUpdate::signing_payload`] with the participant's key
+    pub signature: Vec<u8>,
+}
+
+impl EndpointUpdate {
+    /// Create an unsigned update; set `signature` before publishing
+    pub fn new(global_id: impl Into<String>, endpoints: BTreeMap<String, String>) -> Self {
+        Self {
+            global_id: global_id.into(),
+            endpoints,
+            issued_at: DateTimeWrapper::new(Utc::now()),
+            signature: Vec::new(),
+        }
+    }
+
+    /// Create and sign an update for a local identity
+    pub fn sign(identity: &LocalIdentity, endpoints: BTreeMap<String, String>) -> Result<Self> {
+        let mut update = Self::new(identity.global_id.clone(), endpoints);
+        update.signature = identity.sign(&update.signing_payload())?;
+        Ok(update)
+    }
+
+    /// Canonical string covered by the signature
+    pub fn signing_payload(&self) -> String {
+        let endpoints: Vec<String> = self
+            .endpoints
+            .iter()
+            .map(|(transport, endpoint)| format!("{}={}", transport, endpoint))
+            .collect();
+        format!(
+            "synapse-endpoints:{}:{}:{}",
+            self.global_id,
+            self.issued_at.0.timestamp_millis(),
+            endpoints.join(",")
+        )
+    }
+
+    /// Verify the update against the participant's known public key
+    pub fn verify(&self, public_key_pem: &str) -> Result<bool> {
+        let mut crypto = CryptoManager::new();
+        crypto.import_public_key(&self.global_id, public_key_pem)?;
+        crypto.verify_signature(&self.signing_payload(), &self.signature, &self.global_id)
+    }
+
+    /// Wrap the update in a system message to `to_entity`
+    pub fn to_message(&self, to_entity: &str) -> Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(ENDPOINT_UPDATE_KEY.to_string(), self.endpoints.len().to_string());
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: self.global_id.clone(),
+            content: serde_json::to_string(self)?,
+            message_type: MessageType::System,
+            metadata,
+        })
+    }
+
+    /// Extract the update from a message, if it carries one
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        if message.message_type != MessageType::System || !message.metadata.contains_key(ENDPOINT_UPDATE_KEY) {
+            return None;
+        }
+        serde_json::from_str(&message.content).ok()
+    }
+}
+
+/// Discovery backend that peers look participants up in
+///
+/// Implemented for DHTs, DNS zones and registries so a signed update reaches
+/// peers that are not recent contacts. Publishers receive the update as
+/// signed; lookups should hand it back unchanged for receivers to verify.
+#[async_trait]
+pub trait EndpointPublisher: Send + Sync {
+    /// Name used in logs (e.g. "dht", "dns", "registry")
+    fn name(&self) -> &str;
+
+    /// Publish the update, replacing any earlier one for the participant
+    async fn publish(&self, update: &EndpointUpdate) -> Result<()>;
+}
+
+/// Newest verified endpoint update per participant
+#[derive(Debug, Default)]
+pub struct EndpointCache {
+    updates: DashMap<String, EndpointUpdate>,
+}
+
+impl EndpointCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a verified update
+    ///
+    /// Returns false if it was ignored because a newer update is already known.
+    pub fn apply(&self, update: EndpointUpdate) -> bool {
+        if let Some(existing) = self.updates.get(&update.global_id) {
+            if existing.issued_at.0 >= update.issued_at.0 {
+                return false;
+            }
+        }
+        self.updates.insert(update.global_id.clone(), update);
+        true
+    }
+
+    /// Latest update from a participant
+    pub fn get(&self, global_id: &str) -> Option<EndpointUpdate> {
+        self.updates.get(global_id).map(|update| update.clone())
+    }
+
+    /// Where a participant's transport can be reached, if it told us
+    pub fn endpoint(&self, global_id: &str, transport: &str) -> Option<String> {
+        self.updates.get(global_id)?.endpoints.get(transport).cloned()
+    }
+
+    /// Drop what we know about a participant
+    pub fn forget(&self, global_id: &str) -> bool {
+        self.updates.remove(global_id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::TransportBindings;
+    use chrono::Duration;
+
+    fn endpoints(tcp: &str) -> BTreeMap<String, String> {
+        BTreeMap::from([("tcp".to_string(), tcp.to_string())])
+    }
+
+    #[test]
+    fn test_signed_update_round_trip() {
+        let alice = LocalIdentity::new("alice", "alice@example.com", TransportBindings::default());
+        let public_key = alice.generate_keypair().unwrap();
+        let update = EndpointUpdate::sign(&alice, endpoints("203.0.113.7:8080")).unwrap();
+
+        let received = EndpointUpdate::from_message(&update.to_message("bob@example.com").unwrap()).unwrap();
+        assert!(received.verify(&public_key).unwrap());
+
+        let mut redirected = received.clone();
+        redirected.endpoints = endpoints("198.51.100.66:8080");
+        assert!(!redirected.verify(&public_key).unwrap_or(false));
+    }
+
+    #[test]
+    fn test_cache_keeps_newest_update() {
+        let cache = EndpointCache::new();
+        let newer = EndpointUpdate::new("alice@example.com", endpoints("203.0.113.7:8080"));
+        let mut older = EndpointUpdate::new("alice@example.com", endpoints("192.0.2.1:8080"));
+        older.issued_at = DateTimeWrapper::new(newer.issued_at.0 - Duration::minutes(5));
+
+        assert!(cache.apply(newer.clone()));
+        assert!(!cache.apply(older));
+        assert!(!cache.apply(newer));
+        assert_eq!(cache.endpoint("alice@example.com", "tcp").as_deref(), Some("203.0.113.7:8080"));
+        assert!(cache.endpoint("alice@example.com", "udp").is_none());
+
+        assert!(cache.forget("alice@example.com"));
+        assert!(cache.get("alice@example.com").is_none());
+    }
+}
@@ -110,6 +110,61 @@ pub enum SynapseError {
     
     #[error("Signing failed: {0}")]
     Signing(String),
+
+    #[error("Participant {participant} is suspended: {reason}")]
+    ParticipantSuspended {
+        participant: String,
+        reason: String,
+    },
+
+    #[error("Participant {0} has been deleted")]
+    ParticipantDeleted(String),
+
+    #[error("Policy violation ({rule}) for {destination}: {reason}")]
+    PolicyViolation {
+        rule: String,
+        destination: String,
+        reason: String,
+    },
+
+    #[error("Peer {peer} speaks incompatible protocol version {version}: {reason}")]
+    IncompatiblePeer {
+        peer: String,
+        version: String,
+        reason: String,
+    },
+
+    #[error("Node state conflict ({consistency}): {reason}")]
+    StateConflict {
+        consistency: String,
+        reason: String,
+    },
+
+    #[error("Not the active node for {lease} (leader: {leader})")]
+    NotLeader {
+        lease: String,
+        leader: String,
+    },
+
+    #[error("Fencing token {token} is older than {current}")]
+    Fenced {
+        token: u64,
+        current: u64,
+    },
+
+    #[error("Resource limit reached: {resource} (limit {limit})")]
+    ResourceLimit {
+        resource: String,
+        limit: u64,
+    },
+
+    #[error("Quota exceeded for {participant}: {resource} at {used} of {limit}")]
+    QuotaExceeded {
+        participant: String,
+        resource: String,
+        used: u64,
+        limit: u64,
+    },
 }
 
 impl From<auth_framework::AuthError> for SynapseError {
@@ -160,6 +215,13 @@ impl From<auto_discovery::error::DiscoveryError> for SynapseError {
     }
 }
 
+#[cfg(feature = "search")]
+impl From<tantivy::TantivyError> for SynapseError {
+    fn from(e: tantivy::TantivyError) -> Self {
+        SynapseError::DatabaseError(e.to_string())
+    }
+}
+
 pub type Result<T> = std::result::Result<T, SynapseError>;
 
 // Type aliases for specific error types
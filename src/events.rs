@@ -0,0 +1,159 @@
+//! # Router Lifecycle Events
+//!
+//! Applications, the CLI and dashboards can follow what the router is doing
+//! through a typed [`RouterEvent`] stream rather than scraping log output.
+//! Components publish to a shared [`EventBus`]; cloning the bus shares the
+//! same channel, so one subscription sees transports, discovery, circuit
+//! breakers, key changes and the blockchain together.
+//!
+//! Events are best effort: publishing never blocks, and a subscriber that
+//! falls behind the channel capacity skips the oldest events
+//! ([`broadcast::error::RecvError::Lagged`]).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::trace;
+
+/// Default number of events buffered for slow subscribers
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+/// Something that happened inside the router
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RouterEvent {
+    /// A transport started and can carry messages
+    TransportUp { transport: String },
+    /// A transport stopped or failed to start
+    TransportDown { transport: String, reason: String },
+    /// A peer was found reachable through a discovery mechanism
+    PeerDiscovered { peer: String, transport: String },
+    /// A transport's circuit breaker tripped; it is skipped until it recovers
+    CircuitOpened { transport: String, reason: String, failure_count: u32 },
+    /// A message was handed to a transport for delivery
+    MessageDelivered { message_id: String, to: String, transport: String },
+    /// A message could not be delivered by any route and was given up on
+    MessageDeadLettered { message_id: String, to: String, reason: String },
+    /// Key material changed, e.g. a group moved to a new epoch
+    KeyRotated { identity: String, scope: String, epoch: u64 },
+    /// A block was appended to the trust blockchain
+    BlockCommitted { number: u64, hash: String, transactions: usize, validator: String },
+    /// A participant was suspended, deprecated, deleted or reinstated
+    ParticipantLifecycleChanged { participant: String, state: String, issuer: String },
+    /// A received message was dropped as a replay or outside the timestamp window
+    ReplayRejected { sender: String, message_id: String, source: String, verdict: String },
+    /// A peer announced new addresses for its transports
+    PeerEndpointsChanged { peer: String, transports: Vec<String> },
+    /// A peer's clock was measured further from ours than the configured allowance
+    ClockSkewDetected { peer: String, offset_ms: i64, round_trip_ms: u64 },
+    /// A resource limit was reached; `action` says what gave way (e.g. a peer evicted)
+    ResourceLimitHit { resource: String, limit: u64, action: String },
+    /// Imported node state was exported by another node in the term we hold,
+    /// so two nodes acted as primary at once
+    SplitBrainDetected { lineage: String, term: u64, holders: Vec<String> },
+    /// This node took a leader lease and is now the active node
+    LeadershipAcquired { lease: String, instance: String, fencing_token: u64 },
+    /// This node's leader lease lapsed, was taken over or was given up
+    LeadershipLost { lease: String, instance: String, leader: Option<String> },
+}
+
+impl RouterEvent {
+    /// Short snake_case name of the event kind
+    pub fn name(&self) -> &'static str {
+        match self {
+            RouterEvent::TransportUp { .. } => "transport_up",
+            RouterEvent::TransportDown { .. } => "transport_down",
+            RouterEvent::PeerDiscovered { .. } => "peer_discovered",
+            RouterEvent::CircuitOpened { .. } => "circuit_opened",
+            RouterEvent::MessageDelivered { .. } => "message_delivered",
+            RouterEvent::MessageDeadLettered { .. } => "message_dead_lettered",
+            RouterEvent::KeyRotated { .. } => "key_rotated",
+            RouterEvent::BlockCommitted { .. } => "block_committed",
+            RouterEvent::ParticipantLifecycleChanged { .. } => "participant_lifecycle_changed",
+            RouterEvent::ReplayRejected { .. } => "replay_rejected",
+            RouterEvent::PeerEndpointsChanged { .. } => "peer_endpoints_changed",
+            RouterEvent::ClockSkewDetected { .. } => "clock_skew_detected",
+            RouterEvent::ResourceLimitHit { .. } => "resource_limit_hit",
+            RouterEvent::SplitBrainDetected { .. } => "split_brain_detected",
+            RouterEvent::LeadershipAcquired { .. } => "leadership_acquired",
+            RouterEvent::LeadershipLost { .. } => "leadership_lost",
+        }
+    }
+}
+
+/// A published event with the time it was raised
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimedEvent {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: RouterEvent,
+}
+
+/// Shared channel that router components publish [`RouterEvent`]s to
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<TimedEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Publish an event to current subscribers
+    pub fn publish(&self, event: RouterEvent) {
+        trace!("Router event: {:?}", event);
+        // No subscribers is not an error
+        let _ = self.sender.send(TimedEvent { at: Utc::now(), event });
+    }
+
+    /// Receive events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<TimedEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Number of live subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clones_share_one_stream() {
+        let bus = EventBus::default();
+        let mut rx = bus.subscribe();
+        let publisher = bus.clone();
+
+        publisher.publish(RouterEvent::TransportUp { transport: "tcp".to_string() });
+        bus.publish(RouterEvent::PeerDiscovered { peer: "bob".to_string(), transport: "mdns".to_string() });
+
+        assert_eq!(rx.recv().await.unwrap().event.name(), "transport_up");
+        assert_eq!(
+            rx.recv().await.unwrap().event,
+            RouterEvent::PeerDiscovered { peer: "bob".to_string(), transport: "mdns".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_event_json_is_tagged() {
+        let event = TimedEvent {
+            at: Utc::now(),
+            event: RouterEvent::CircuitOpened { transport: "udp".to_string(), reason: "timeouts".to_string(), failure_count: 5 },
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "circuit_opened");
+        assert_eq!(json["failure_count"], 5);
+        assert!(json.get("at").is_some());
+    }
+}
@@ -0,0 +1,104 @@
+//! # Regions
+//!
+//! Participants, relays and providers may carry a region tag such as
+//! `eu-west` or `us-east-1`. Tags are lowercase, `-`-separated and ordered
+//! from broad to narrow, so `eu` covers both `eu-west` and `eu-central`.
+//! Transport selection prefers endpoints close to us, and discovery can be
+//! restricted to regions for data-residency reasons.
+
+use serde::{Deserialize, Serialize};
+
+/// Normalize a region tag: trimmed, lowercase, `_` and spaces as `-`
+pub fn normalize(region: &str) -> String {
+    region
+        .trim()
+        .to_lowercase()
+        .split(|c: char| c == '-' || c == '_' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Whether `region` lies within `scope`, e.g. `eu-west-1` within `eu` or `eu-west`
+pub fn within(region: &str, scope: &str) -> bool {
+    let (region, scope) = (normalize(region), normalize(scope));
+    if scope.is_empty() {
+        return false;
+    }
+    let mut parts = region.split('-');
+    scope.split('-').all(|wanted| parts.next() == Some(wanted))
+}
+
+/// Whether `region` lies within any of `scopes`
+pub fn within_any(region: Option<&str>, scopes: &[String]) -> bool {
+    region.is_some_and(|region| scopes.iter().any(|scope| within(region, scope)))
+}
+
+/// How close two regions are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionAffinity {
+    /// Different areas, e.g. `eu-west` and `us-east`
+    Distant,
+    /// One or both regions are not known
+    Unknown,
+    /// Same area, e.g. `eu-west` and `eu-central`
+    SameArea,
+    /// Same region, or one lies within the other
+    Same,
+}
+
+impl RegionAffinity {
+    /// Affinity between our region and an endpoint's
+    pub fn between(local: Option<&str>, remote: Option<&str>) -> Self {
+        let (Some(local), Some(remote)) = (local, remote) else {
+            return RegionAffinity::Unknown;
+        };
+        if within(local, remote) || within(remote, local) {
+            RegionAffinity::Same
+        } else if normalize(local).split('-').next() == normalize(remote).split('-').next() {
+            RegionAffinity::SameArea
+        } else {
+            RegionAffinity::Distant
+        }
+    }
+
+    /// Score in 0.0-1.0 used when ranking transports
+    pub fn score(self) -> f64 {
+        match self {
+            RegionAffinity::Same => 1.0,
+            RegionAffinity::SameArea => 0.75,
+            RegionAffinity::Unknown => 0.5,
+            RegionAffinity::Distant => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_nest_from_broad_to_narrow() {
+        assert_eq!(normalize(" EU_West 1 "), "eu-west-1");
+        assert!(within("eu-west-1", "eu"));
+        assert!(within("eu-west-1", "EU-West"));
+        assert!(!within("eu-westish", "eu-west"));
+        assert!(!within("eu", "eu-west"));
+        assert!(!within("eu", ""));
+        assert!(within_any(Some("us-east"), &["eu".to_string(), "us".to_string()]));
+        assert!(!within_any(None, &["eu".to_string()]));
+    }
+
+    #[test]
+    fn affinity_ranks_closer_regions_higher() {
+        let local = Some("eu-west");
+        let same = RegionAffinity::between(local, Some("eu-west-2"));
+        let area = RegionAffinity::between(local, Some("eu-central"));
+        let unknown = RegionAffinity::between(local, None);
+        let distant = RegionAffinity::between(local, Some("us-east"));
+        assert_eq!(same, RegionAffinity::Same);
+        assert!(same > area && area > unknown && unknown > distant);
+        assert!(same.score() > area.score() && unknown.score() > distant.score());
+    }
+}
@@ -0,0 +1,565 @@
+//! # End-to-End Encrypted Groups
+//!
+//! A [`GroupManager`] tracks the groups an identity belongs to and the shared
+//! secret each group encrypts with. The scheme is a small sender-keys design:
+//!
+//! - The group admin generates a random 32-byte secret for each **epoch** and
+//!   hands it to every member in a [`GroupKeyPackage`], encrypted pairwise
+//!   with that member's public key.
+//! - Adding or removing a member always starts a new epoch with a fresh,
+//!   unrelated secret: removed members cannot read later traffic and new
+//!   members cannot read earlier traffic.
+//! - Every message is sealed with AES-256-GCM under a key derived from the
+//!   epoch secret, the sender and a per-sender counter. Secrets of old epochs
+//!   are erased once they fall out of the retention window, so a later key
+//!   compromise does not expose them.
+//!
+//! Key packages are only accepted from the group's admin; the router's
+//! message signatures authenticate who sent them.
+
+use crate::error::{Result, SynapseError};
+use crate::identity::LocalIdentity;
+use crate::types::{MessageType, SimpleMessage};
+use aes_gcm::{
+    Aes256Gcm, Key, Nonce,
+    aead::{Aead, KeyInit, OsRng, Payload},
+};
+use base64::Engine as _;
+use dashmap::DashMap;
+use rsa::rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// Metadata key carrying the group a message belongs to
+pub const GROUP_ID_KEY: &str = "group_id";
+/// Metadata key saying what a group message carries: `key_package` or `message`
+pub const GROUP_PAYLOAD_KEY: &str = "group_payload";
+
+/// Epoch secrets kept by default, including the current one
+pub const DEFAULT_RETAINED_EPOCHS: usize = 2;
+
+/// Public-key operations used to hand group secrets to members
+pub trait PairwiseCipher: Send + Sync {
+    /// Global ID the cipher decrypts for
+    fn global_id(&self) -> String;
+
+    /// Encrypt `secret` so only `recipient` can read it
+    fn seal_for(&self, recipient: &str, secret: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decrypt a secret sealed for us
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>>;
+}
+
+impl PairwiseCipher for LocalIdentity {
+    fn global_id(&self) -> String {
+        self.global_id.clone()
+    }
+
+    fn seal_for(&self, recipient: &str, secret: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_for(recipient, &base64::engine::general_purpose::STANDARD.encode(secret))
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        base64::engine::general_purpose::STANDARD
+            .decode(self.decrypt(sealed)?)
+            .map_err(|e| SynapseError::Decryption(e.to_string()))
+    }
+}
+
+/// Public view of a group
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    /// Member allowed to change membership and distribute keys
+    pub admin: String,
+    pub members: BTreeSet<String>,
+    /// Current key epoch
+    pub epoch: u64,
+}
+
+/// A group secret for one epoch, encrypted for a single member
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupKeyPackage {
+    pub group_id: String,
+    pub name: String,
+    pub admin: String,
+    pub epoch: u64,
+    /// Members as of this epoch
+    pub members: BTreeSet<String>,
+    /// Member the secret is sealed for
+    pub recipient: String,
+    /// Epoch secret encrypted with the recipient's public key
+    pub sealed_secret: Vec<u8>,
+}
+
+impl GroupKeyPackage {
+    /// Wrap the package in a system message from the admin to its recipient
+    pub fn to_message(&self) -> Result<SimpleMessage> {
+        Ok(group_message(
+            &self.recipient,
+            &self.admin,
+            &self.group_id,
+            "key_package",
+            MessageType::System,
+            serde_json::to_string(self)?,
+        ))
+    }
+
+    /// Extract a key package from a message, if it carries one
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        (message.metadata.get(GROUP_PAYLOAD_KEY).map(String::as_str) == Some("key_package"))
+            .then(|| serde_json::from_str(&message.content).ok())
+            .flatten()
+    }
+}
+
+/// A message sealed with a group's epoch secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupCiphertext {
+    pub group_id: String,
+    pub epoch: u64,
+    pub sender: String,
+    /// Per-sender message counter within the epoch
+    pub counter: u64,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl GroupCiphertext {
+    /// Wrap the ciphertext in a broadcast message to one member
+    pub fn to_message(&self, to_entity: &str) -> Result<SimpleMessage> {
+        Ok(group_message(
+            to_entity,
+            &self.sender,
+            &self.group_id,
+            "message",
+            MessageType::Broadcast,
+            serde_json::to_string(self)?,
+        ))
+    }
+
+    /// Extract group ciphertext from a message, if it carries some
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        (message.metadata.get(GROUP_PAYLOAD_KEY).map(String::as_str) == Some("message"))
+            .then(|| serde_json::from_str(&message.content).ok())
+            .flatten()
+    }
+
+    fn associated_data(&self) -> Vec<u8> {
+        format!("{}:{}:{}:{}", self.group_id, self.epoch, self.sender, self.counter).into_bytes()
+    }
+}
+
+fn group_message(
+    to: &str,
+    from: &str,
+    group_id: &str,
+    payload: &str,
+    message_type: MessageType,
+    content: String,
+) -> SimpleMessage {
+    let mut metadata = HashMap::new();
+    metadata.insert(GROUP_ID_KEY.to_string(), group_id.to_string());
+    metadata.insert(GROUP_PAYLOAD_KEY.to_string(), payload.to_string());
+    SimpleMessage {
+        to: to.to_string(),
+        from_entity: from.to_string(),
+        content,
+        message_type,
+        metadata,
+    }
+}
+
+struct GroupState {
+    group: Group,
+    /// Epoch -> secret, newest last
+    secrets: BTreeMap<u64, [u8; 32]>,
+    /// Next counter for messages we send in the current epoch
+    send_counter: u64,
+    /// (epoch, sender, counter) already opened, to reject replays
+    seen: HashSet<(u64, String, u64)>,
+}
+
+impl GroupState {
+    fn install(&mut self, epoch: u64, secret: [u8; 32], retained: usize) {
+        self.secrets.insert(epoch, secret);
+        self.group.epoch = epoch;
+        self.send_counter = 0;
+        while self.secrets.len() > retained {
+            self.secrets.pop_first();
+        }
+        let oldest = self.secrets.keys().next().copied().unwrap_or(epoch);
+        self.seen.retain(|(seen_epoch, _, _)| *seen_epoch >= oldest);
+    }
+}
+
+/// Groups an identity belongs to, with their epoch secrets
+pub struct GroupManager {
+    cipher: Arc<dyn PairwiseCipher>,
+    groups: DashMap<String, GroupState>,
+    retained_epochs: usize,
+}
+
+impl GroupManager {
+    /// Create a manager for the identity behind `cipher`
+    pub fn new(cipher: Arc<dyn PairwiseCipher>) -> Self {
+        Self {
+            cipher,
+            groups: DashMap::new(),
+            retained_epochs: DEFAULT_RETAINED_EPOCHS,
+        }
+    }
+
+    /// Keep secrets for this many epochs (at least one) so late messages still open
+    pub fn with_retained_epochs(mut self, epochs: usize) -> Self {
+        self.retained_epochs = epochs.max(1);
+        self
+    }
+
+    /// Global ID of the identity this manager acts for
+    pub fn global_id(&self) -> String {
+        self.cipher.global_id()
+    }
+
+    /// Create a group administered by us, returning key packages for the other members
+    pub fn create_group(&self, name: &str, members: impl IntoIterator<Item = String>) -> Result<(Group, Vec<GroupKeyPackage>)> {
+        let admin = self.global_id();
+        let mut members: BTreeSet<String> = members.into_iter().collect();
+        members.insert(admin.clone());
+
+        let group = Group {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            admin,
+            members,
+            epoch: 0,
+        };
+        let group_id = group.id.clone();
+        self.groups.insert(group_id.clone(), GroupState {
+            group,
+            secrets: BTreeMap::new(),
+            send_counter: 0,
+            seen: HashSet::new(),
+        });
+        let packages = self.rekey(&group_id)?;
+        info!("Created group {} ({})", name, group_id);
+        Ok((self.group(&group_id).expect("group was just created"), packages))
+    }
+
+    /// Add a member and start a new epoch
+    pub fn add_member(&self, group_id: &str, member: &str) -> Result<Vec<GroupKeyPackage>> {
+        self.change_membership(group_id, |members| {
+            members.insert(member.to_string());
+        })
+    }
+
+    /// Remove a member and start a new epoch they cannot read
+    pub fn remove_member(&self, group_id: &str, member: &str) -> Result<Vec<GroupKeyPackage>> {
+        if member == self.global_id() {
+            return Err(SynapseError::ValidationFailed(
+                "The admin cannot remove themselves; use leave_group".to_string(),
+            ));
+        }
+        self.change_membership(group_id, |members| {
+            members.remove(member);
+        })
+    }
+
+    fn change_membership(&self, group_id: &str, change: impl FnOnce(&mut BTreeSet<String>)) -> Result<Vec<GroupKeyPackage>> {
+        {
+            let mut state = self.admin_state(group_id)?;
+            change(&mut state.group.members);
+        }
+        self.rekey(group_id)
+    }
+
+    /// Start a new epoch with a fresh secret, returning a key package per other member
+    pub fn rekey(&self, group_id: &str) -> Result<Vec<GroupKeyPackage>> {
+        let mut state = self.admin_state(group_id)?;
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        let epoch = state.group.epoch + 1;
+
+        let me = self.global_id();
+        let packages = state
+            .group
+            .members
+            .iter()
+            .filter(|member| **member != me)
+            .map(|member| {
+                Ok(GroupKeyPackage {
+                    group_id: state.group.id.clone(),
+                    name: state.group.name.clone(),
+                    admin: state.group.admin.clone(),
+                    epoch,
+                    members: state.group.members.clone(),
+                    recipient: member.clone(),
+                    sealed_secret: self.cipher.seal_for(member, &secret)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        state.install(epoch, secret, self.retained_epochs);
+        debug!("Group {} moved to epoch {}", group_id, epoch);
+        Ok(packages)
+    }
+
+    /// Accept a key package received from `sender`
+    pub fn accept_package(&self, package: &GroupKeyPackage, sender: &str) -> Result<Group> {
+        if package.recipient != self.global_id() {
+            return Err(SynapseError::ValidationFailed(format!(
+                "Key package for group {} is addressed to {}",
+                package.group_id, package.recipient
+            )));
+        }
+        if let Some(state) = self.groups.get(&package.group_id) {
+            if state.group.admin != sender {
+                return Err(SynapseError::AuthorizationError(format!(
+                    "{} is not the admin of group {}",
+                    sender, package.group_id
+                )));
+            }
+            if package.epoch <= state.group.epoch {
+                return Err(SynapseError::ValidationFailed(format!(
+                    "Stale key package for group {} (epoch {} <= {})",
+                    package.group_id, package.epoch, state.group.epoch
+                )));
+            }
+        } else if package.admin != sender {
+            return Err(SynapseError::AuthorizationError(format!(
+                "Key package for new group {} was not sent by its admin",
+                package.group_id
+            )));
+        }
+
+        let secret: [u8; 32] = self
+            .cipher
+            .open(&package.sealed_secret)?
+            .try_into()
+            .map_err(|_| SynapseError::Decryption("Group secret has the wrong length".to_string()))?;
+
+        let mut state = self.groups.entry(package.group_id.clone()).or_insert_with(|| GroupState {
+            group: Group {
+                id: package.group_id.clone(),
+                name: package.name.clone(),
+                admin: package.admin.clone(),
+                members: BTreeSet::new(),
+                epoch: 0,
+            },
+            secrets: BTreeMap::new(),
+            send_counter: 0,
+            seen: HashSet::new(),
+        });
+        state.group.members = package.members.clone();
+        state.install(package.epoch, secret, self.retained_epochs);
+        debug!("Joined epoch {} of group {}", package.epoch, package.group_id);
+        Ok(state.group.clone())
+    }
+
+    /// Seal a message for the group with the current epoch secret
+    pub fn encrypt(&self, group_id: &str, plaintext: &[u8]) -> Result<GroupCiphertext> {
+        let sender = self.global_id();
+        let mut state = self
+            .groups
+            .get_mut(group_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Group {}", group_id)))?;
+        let epoch = state.group.epoch;
+        let secret = *state
+            .secrets
+            .get(&epoch)
+            .ok_or_else(|| SynapseError::Encryption(format!("No key for group {}", group_id)))?;
+        let counter = state.send_counter;
+        state.send_counter += 1;
+
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let mut sealed = GroupCiphertext {
+            group_id: group_id.to_string(),
+            epoch,
+            sender,
+            counter,
+            nonce: nonce.to_vec(),
+            ciphertext: Vec::new(),
+        };
+        let key = message_key(&secret, &sealed);
+        sealed.ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .encrypt(Nonce::from_slice(&nonce), Payload {
+                msg: plaintext,
+                aad: &sealed.associated_data(),
+            })
+            .map_err(|e| SynapseError::Encryption(e.to_string()))?;
+        Ok(sealed)
+    }
+
+    /// Open a group message from another member
+    pub fn decrypt(&self, sealed: &GroupCiphertext) -> Result<Vec<u8>> {
+        let mut state = self
+            .groups
+            .get_mut(&sealed.group_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Group {}", sealed.group_id)))?;
+        // Members removed since may still have sent messages in an earlier epoch
+        if sealed.epoch == state.group.epoch && !state.group.members.contains(&sealed.sender) {
+            return Err(SynapseError::AuthorizationError(format!(
+                "{} is not a member of group {}",
+                sealed.sender, sealed.group_id
+            )));
+        }
+        let secret = *state.secrets.get(&sealed.epoch).ok_or_else(|| {
+            SynapseError::Decryption(format!("No key for epoch {} of group {}", sealed.epoch, sealed.group_id))
+        })?;
+        let replay_key = (sealed.epoch, sealed.sender.clone(), sealed.counter);
+        if state.seen.contains(&replay_key) {
+            return Err(SynapseError::ValidationFailed(format!(
+                "Replayed group message {} from {}",
+                sealed.counter, sealed.sender
+            )));
+        }
+        if sealed.nonce.len() != 12 {
+            return Err(SynapseError::Decryption("Invalid group message nonce".to_string()));
+        }
+
+        let key = message_key(&secret, sealed);
+        let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .decrypt(Nonce::from_slice(&sealed.nonce), Payload {
+                msg: &sealed.ciphertext,
+                aad: &sealed.associated_data(),
+            })
+            .map_err(|_| SynapseError::Decryption(format!("Cannot open message for group {}", sealed.group_id)))?;
+        state.seen.insert(replay_key);
+        Ok(plaintext)
+    }
+
+    /// Forget a group and its secrets
+    pub fn leave_group(&self, group_id: &str) -> Option<Group> {
+        self.groups.remove(group_id).map(|(_, state)| state.group)
+    }
+
+    /// Look up a group
+    pub fn group(&self, group_id: &str) -> Option<Group> {
+        self.groups.get(group_id).map(|state| state.group.clone())
+    }
+
+    /// All groups we belong to
+    pub fn groups(&self) -> Vec<Group> {
+        self.groups.iter().map(|state| state.group.clone()).collect()
+    }
+
+    fn admin_state(&self, group_id: &str) -> Result<dashmap::mapref::one::RefMut<'_, String, GroupState>> {
+        let state = self
+            .groups
+            .get_mut(group_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Group {}", group_id)))?;
+        if state.group.admin != self.global_id() {
+            return Err(SynapseError::AuthorizationError(format!(
+                "Only the admin can change group {}",
+                group_id
+            )));
+        }
+        Ok(state)
+    }
+}
+
+/// Per-message key bound to the epoch secret, sender and counter
+fn message_key(secret: &[u8; 32], sealed: &GroupCiphertext) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"synapse-group-message");
+    hasher.update(secret);
+    hasher.update(sealed.associated_data());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stand-in for public-key sealing: only the named recipient can open
+    struct TestCipher(String);
+
+    impl PairwiseCipher for TestCipher {
+        fn global_id(&self) -> String {
+            self.0.clone()
+        }
+
+        fn seal_for(&self, recipient: &str, secret: &[u8]) -> Result<Vec<u8>> {
+            let mut sealed = format!("{}|", recipient).into_bytes();
+            sealed.extend_from_slice(secret);
+            Ok(sealed)
+        }
+
+        fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+            let prefix = format!("{}|", self.0);
+            sealed
+                .strip_prefix(prefix.as_bytes())
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| SynapseError::Decryption("Not sealed for us".to_string()))
+        }
+    }
+
+    fn manager(id: &str) -> GroupManager {
+        GroupManager::new(Arc::new(TestCipher(id.to_string())))
+    }
+
+    fn deliver(packages: &[GroupKeyPackage], admin: &str, to: &GroupManager) {
+        let me = to.global_id();
+        for package in packages.iter().filter(|p| p.recipient == me) {
+            to.accept_package(package, admin).unwrap();
+        }
+    }
+
+    #[test]
+    fn members_exchange_messages() {
+        let alice = manager("alice");
+        let bob = manager("bob");
+        let (group, packages) = alice.create_group("team", vec!["bob".to_string()]).unwrap();
+        deliver(&packages, "alice", &bob);
+
+        let sealed = alice.encrypt(&group.id, b"hello team").unwrap();
+        assert_eq!(bob.decrypt(&sealed).unwrap(), b"hello team");
+        assert!(bob.decrypt(&sealed).is_err(), "replays are rejected");
+
+        let reply = bob.encrypt(&group.id, b"hi alice").unwrap();
+        assert_eq!(alice.decrypt(&reply).unwrap(), b"hi alice");
+    }
+
+    #[test]
+    fn removed_member_cannot_read_new_epoch() {
+        let alice = manager("alice");
+        let bob = manager("bob");
+        let carol = manager("carol");
+        let (group, packages) = alice
+            .create_group("team", vec!["bob".to_string(), "carol".to_string()])
+            .unwrap();
+        deliver(&packages, "alice", &bob);
+        deliver(&packages, "alice", &carol);
+
+        let packages = alice.remove_member(&group.id, "carol").unwrap();
+        assert!(packages.iter().all(|p| p.recipient != "carol"));
+        deliver(&packages, "alice", &bob);
+
+        let sealed = alice.encrypt(&group.id, b"carol is gone").unwrap();
+        assert_eq!(sealed.epoch, 2);
+        assert_eq!(bob.decrypt(&sealed).unwrap(), b"carol is gone");
+        assert!(carol.decrypt(&sealed).is_err());
+    }
+
+    #[test]
+    fn only_admin_may_distribute_keys_and_old_epochs_expire() {
+        let alice = manager("alice");
+        let bob = manager("bob").with_retained_epochs(1);
+        let (group, packages) = alice.create_group("team", vec!["bob".to_string()]).unwrap();
+        deliver(&packages, "alice", &bob);
+        let old = alice.encrypt(&group.id, b"epoch one").unwrap();
+
+        let packages = alice.rekey(&group.id).unwrap();
+        assert!(bob.accept_package(&packages[0], "mallory").is_err());
+        deliver(&packages, "alice", &bob);
+
+        assert!(bob.decrypt(&old).is_err(), "epoch 1 secret was erased");
+        assert!(bob.add_member(&group.id, "mallory").is_err());
+    }
+}
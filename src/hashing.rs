@@ -0,0 +1,228 @@
+//! # Content Hashing and Hardware Acceleration
+//!
+//! Large transfers spend most of their CPU hashing payloads and running
+//! AES-GCM. Both have hardware-accelerated paths that the underlying crates
+//! pick at runtime: AES-GCM uses AES-NI and PCLMULQDQ (x86) or the ARMv8
+//! AES and PMULL instructions when the CPU has them, SHA-256 uses SHA-NI or
+//! the ARMv8 SHA2 extension, and BLAKE3 runs on AVX-512, AVX2, SSE4.1 or NEON.
+//! [`HardwareAcceleration::current`] reports what this machine offers; it is
+//! part of the [`CapabilityReport`](crate::capabilities::CapabilityReport).
+//!
+//! Content hashes name their [`HashAlgorithm`]. SHA-256 hashes are bare hex,
+//! as they always were; BLAKE3 hashes, several times faster on large
+//! payloads, are prefixed `blake3:`. Nodes list the algorithms they support
+//! in capability probes and use the first of [`PREFERENCE`] both sides
+//! support ([`negotiate`]); peers that predate this only know SHA-256.
+//!
+//! `cargo run --release --example crypto_throughput` includes hashing and
+//! AES-GCM throughput on the local machine.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// Hash algorithms in the order this node prefers them
+pub const PREFERENCE: [HashAlgorithm; 2] = [HashAlgorithm::Blake3, HashAlgorithm::Sha256];
+
+/// Algorithm a content hash was computed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// SHA-256; every node supports it
+    #[default]
+    Sha256,
+    /// BLAKE3, SIMD-parallel
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Algorithm a content hash was made with, from its prefix
+    pub fn of(hash: &str) -> Option<Self> {
+        match hash.split_once(':') {
+            None => Some(HashAlgorithm::Sha256),
+            Some(("blake3", _)) => Some(HashAlgorithm::Blake3),
+            Some(_) => None,
+        }
+    }
+
+    /// Content hash of `data`
+    pub fn hash(&self, data: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// Incremental hasher, for content that arrives in pieces
+    pub fn hasher(&self) -> ContentHasher {
+        match self {
+            HashAlgorithm::Sha256 => ContentHasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => ContentHasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Hash state for content fed in pieces
+pub enum ContentHasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl ContentHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ContentHasher::Sha256(hasher) => hasher.update(data),
+            ContentHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// The content hash, prefixed with the algorithm unless it is SHA-256
+    pub fn finish(self) -> String {
+        match self {
+            ContentHasher::Sha256(hasher) => hex(&hasher.finalize()),
+            ContentHasher::Blake3(hasher) => format!("blake3:{}", hex(hasher.finalize().as_bytes())),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Whether `data` has content hash `hash`, whichever algorithm made it
+pub fn verify(hash: &str, data: &[u8]) -> bool {
+    HashAlgorithm::of(hash).is_some_and(|algorithm| algorithm.hash(data).eq_ignore_ascii_case(hash))
+}
+
+/// Algorithm to hash content for a peer that supports `theirs`
+///
+/// An empty list comes from a node that predates negotiation, which only
+/// knows SHA-256.
+pub fn negotiate(theirs: &[HashAlgorithm]) -> HashAlgorithm {
+    PREFERENCE
+        .into_iter()
+        .find(|algorithm| theirs.contains(algorithm))
+        .unwrap_or(HashAlgorithm::Sha256)
+}
+
+/// CPU features the crypto and hashing code can use on this machine
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareAcceleration {
+    /// AES instructions (AES-NI, ARMv8 AES), used by AES-GCM
+    pub aes: bool,
+    /// Carry-less multiplication (PCLMULQDQ, PMULL), used by GHASH in AES-GCM
+    pub clmul: bool,
+    /// SHA-256 instructions (SHA-NI, ARMv8 SHA2)
+    pub sha: bool,
+    /// SIMD extensions BLAKE3 can use, widest first
+    pub simd: Vec<String>,
+}
+
+impl HardwareAcceleration {
+    /// Features of the running CPU, detected once
+    pub fn current() -> &'static Self {
+        static DETECTED: OnceLock<HardwareAcceleration> = OnceLock::new();
+        DETECTED.get_or_init(Self::detect)
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn detect() -> Self {
+        let mut simd = Vec::new();
+        if std::arch::is_x86_feature_detected!("avx512f") {
+            simd.push("avx512".to_string());
+        }
+        if std::arch::is_x86_feature_detected!("avx2") {
+            simd.push("avx2".to_string());
+        }
+        if std::arch::is_x86_feature_detected!("sse4.1") {
+            simd.push("sse4.1".to_string());
+        }
+        Self {
+            aes: std::arch::is_x86_feature_detected!("aes"),
+            clmul: std::arch::is_x86_feature_detected!("pclmulqdq"),
+            sha: std::arch::is_x86_feature_detected!("sha"),
+            simd,
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn detect() -> Self {
+        let mut simd = Vec::new();
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            simd.push("neon".to_string());
+        }
+        Self {
+            aes: std::arch::is_aarch64_feature_detected!("aes"),
+            clmul: std::arch::is_aarch64_feature_detected!("pmull"),
+            sha: std::arch::is_aarch64_feature_detected!("sha2"),
+            simd,
+        }
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    fn detect() -> Self {
+        Self::default()
+    }
+
+    /// Whether AES-GCM runs on dedicated instructions rather than in software
+    pub fn hardware_aes(&self) -> bool {
+        self.aes && self.clmul
+    }
+
+    /// Names of the detected features, e.g. `aes, clmul, avx2`
+    pub fn features(&self) -> Vec<&str> {
+        [("aes", self.aes), ("clmul", self.clmul), ("sha", self.sha)]
+            .into_iter()
+            .filter(|(_, present)| *present)
+            .map(|(name, _)| name)
+            .chain(self.simd.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_name_their_algorithm_and_negotiate() {
+        let data = b"large transfer";
+        let sha = HashAlgorithm::Sha256.hash(data);
+        let blake = HashAlgorithm::Blake3.hash(data);
+        assert_eq!(sha, crate::types::Attachment::digest(data));
+        assert_eq!(blake, format!("blake3:{}", blake3::hash(data).to_hex()));
+        assert_eq!(HashAlgorithm::of(&sha), Some(HashAlgorithm::Sha256));
+        assert_eq!(HashAlgorithm::of(&blake), Some(HashAlgorithm::Blake3));
+        assert_eq!(HashAlgorithm::of("md5:abc"), None);
+
+        assert!(verify(&sha, data) && verify(&blake, data));
+        assert!(!verify(&blake, b"tampered"));
+        assert!(!verify("md5:abc", data));
+
+        let mut hasher = HashAlgorithm::Blake3.hasher();
+        hasher.update(b"large ");
+        hasher.update(b"transfer");
+        assert_eq!(hasher.finish(), blake);
+
+        assert_eq!(negotiate(&[]), HashAlgorithm::Sha256);
+        assert_eq!(negotiate(&[HashAlgorithm::Sha256, HashAlgorithm::Blake3]), HashAlgorithm::Blake3);
+        assert_eq!(serde_json::to_string(&HashAlgorithm::Blake3).unwrap(), "\"blake3\"");
+
+        let hardware = HardwareAcceleration::current();
+        assert_eq!(hardware.hardware_aes(), hardware.features().contains(&"aes") && hardware.clmul);
+    }
+}
@@ -0,0 +1,633 @@
+//! # Conversation History, Edits and Retractions
+//!
+//! The router keeps the messages it exchanges in a bounded history keyed by
+//! message ID (the `message_id` metadata entry, stamped on every outgoing
+//! message). A sender can later change or withdraw a message by sending a
+//! signed [`MessageAmendment`] that references the ID:
+//!
+//! - **Edit** replaces the content; earlier versions stay available through
+//!   [`ConversationHistory::edit_history`].
+//! - **Retract** leaves a tombstone: the entry remains so the conversation
+//!   keeps its shape, but its content and earlier versions are dropped.
+//!
+//! Only the original author may amend a message. Deployments restrict
+//! amendments with an [`AmendmentPolicy`], e.g. to disable retraction or to
+//! limit edits to a short window after sending.
+//!
+//! The history lives in memory. [`ConversationHistory::save`] writes it to
+//! disk, encrypted with a storage key derived from the node keystore, and
+//! [`ConversationHistory::load`] reads it back, encrypting a file saved
+//! before encryption was enabled. Rolling summaries produced by a
+//! [`crate::summary::Summarizer`] are kept and saved alongside the messages.
+
+use crate::crypto::{CryptoManager, StorageKey, read_sealed_file, write_sealed_file};
+use crate::error::{Result, SynapseError};
+use crate::identity::LocalIdentity;
+use crate::summary::ConversationSummary;
+use crate::synapse::blockchain::serialization::DateTimeWrapper;
+use crate::types::{MessageType, SimpleMessage};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Metadata key carrying a message's ID
+pub const MESSAGE_ID_KEY: &str = "message_id";
+/// Metadata key marking a message as an amendment (`edit` or `retract`)
+pub const AMENDMENT_KEY: &str = "amendment";
+
+/// What an amendment does to the referenced message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AmendmentAction {
+    /// Replace the message content
+    Edit { content: String },
+    /// Withdraw the message, leaving a tombstone
+    Retract,
+}
+
+impl AmendmentAction {
+    /// Name used on the wire and in policy errors
+    pub fn name(&self) -> &'static str {
+        match self {
+            AmendmentAction::Edit { .. } => "edit",
+            AmendmentAction::Retract => "retract",
+        }
+    }
+}
+
+/// Signed edit or retraction of an earlier message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageAmendment {
+    /// ID of the message being amended
+    pub message_id: String,
+    /// Global ID of the author, who must have sent the original message
+    pub author: String,
+    /// The change
+    pub action: AmendmentAction,
+    /// When the amendment was made
+    pub amended_at: DateTimeWrapper,
+    /// Signature over [`MessageAmendment::signing_payload`] with the author's key
+    pub signature: Vec<u8>,
+}
+
+impl MessageAmendment {
+    /// Create an unsigned amendment; set `signature` before sending
+    pub fn new(author: impl Into<String>, message_id: impl Into<String>, action: AmendmentAction) -> Self {
+        Self {
+            message_id: message_id.into(),
+            author: author.into(),
+            action,
+            amended_at: DateTimeWrapper::new(Utc::now()),
+            signature: Vec::new(),
+        }
+    }
+
+    /// Create and sign an amendment on behalf of a local identity
+    pub fn sign(identity: &LocalIdentity, message_id: impl Into<String>, action: AmendmentAction) -> Result<Self> {
+        let mut amendment = Self::new(identity.global_id.clone(), message_id, action);
+        amendment.signature = identity.sign(&amendment.signing_payload())?;
+        Ok(amendment)
+    }
+
+    /// Canonical string covered by the signature
+    pub fn signing_payload(&self) -> String {
+        let content = match &self.action {
+            AmendmentAction::Edit { content } => content.as_str(),
+            AmendmentAction::Retract => "",
+        };
+        format!(
+            "synapse-amendment:{}:{}:{}:{}:{}",
+            self.author,
+            self.message_id,
+            self.action.name(),
+            self.amended_at.0.timestamp(),
+            content
+        )
+    }
+
+    /// Verify the amendment against the author's known public key
+    pub fn verify(&self, public_key_pem: &str) -> Result<bool> {
+        let mut crypto = CryptoManager::new();
+        crypto.import_public_key(&self.author, public_key_pem)?;
+        crypto.verify_signature(&self.signing_payload(), &self.signature, &self.author)
+    }
+
+    /// Wrap the amendment in a system message to `to_entity`
+    pub fn to_message(&self, to_entity: &str) -> Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(AMENDMENT_KEY.to_string(), self.action.name().to_string());
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: self.author.clone(),
+            content: serde_json::to_string(self)?,
+            message_type: MessageType::System,
+            metadata,
+        })
+    }
+
+    /// Extract the amendment from a message, if it carries one
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        if message.message_type != MessageType::System || !message.metadata.contains_key(AMENDMENT_KEY) {
+            return None;
+        }
+        serde_json::from_str(&message.content).ok()
+    }
+}
+
+/// Deployment rules for edits and retractions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmendmentPolicy {
+    /// Whether messages may be edited
+    pub allow_edits: bool,
+    /// Whether messages may be retracted
+    pub allow_retraction: bool,
+    /// How long after a message was recorded it may still be amended
+    #[serde(default)]
+    pub window_secs: Option<u64>,
+}
+
+impl Default for AmendmentPolicy {
+    fn default() -> Self {
+        Self {
+            allow_edits: true,
+            allow_retraction: true,
+            window_secs: None,
+        }
+    }
+}
+
+impl AmendmentPolicy {
+    /// Check whether `action` is allowed on a message recorded at `recorded_at`
+    pub fn check(&self, action: &AmendmentAction, recorded_at: &DateTimeWrapper) -> Result<()> {
+        let allowed = match action {
+            AmendmentAction::Edit { .. } => self.allow_edits,
+            AmendmentAction::Retract => self.allow_retraction,
+        };
+        if !allowed {
+            return Err(SynapseError::AuthorizationError(format!(
+                "Message {}s are disabled by policy",
+                action.name()
+            )));
+        }
+        match self.window_secs {
+            Some(window) if Utc::now() - recorded_at.0 > Duration::seconds(window as i64) => {
+                Err(SynapseError::AuthorizationError(format!(
+                    "Messages can only be amended within {} seconds",
+                    window
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// One version of a message's content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageRevision {
+    /// Content of this version
+    pub content: String,
+    /// When this version became current
+    pub since: DateTimeWrapper,
+}
+
+/// A message in the conversation history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub message_id: String,
+    pub from: String,
+    pub to: String,
+    /// Current content; empty once retracted
+    pub content: String,
+    pub message_type: MessageType,
+    pub metadata: HashMap<String, String>,
+    pub recorded_at: DateTimeWrapper,
+    /// When the content was last edited
+    #[serde(default)]
+    pub edited_at: Option<DateTimeWrapper>,
+    /// Earlier versions, oldest first
+    #[serde(default)]
+    pub revisions: Vec<MessageRevision>,
+    /// When the message was retracted, if it was
+    #[serde(default)]
+    pub retracted_at: Option<DateTimeWrapper>,
+}
+
+impl HistoryEntry {
+    /// Whether the message has been edited
+    pub fn is_edited(&self) -> bool {
+        !self.revisions.is_empty()
+    }
+
+    /// Whether the message has been retracted
+    pub fn is_retracted(&self) -> bool {
+        self.retracted_at.is_some()
+    }
+}
+
+#[derive(Debug, Default)]
+struct HistoryState {
+    entries: HashMap<String, HistoryEntry>,
+    order: VecDeque<String>,
+    /// Latest summary per peer
+    summaries: HashMap<String, ConversationSummary>,
+}
+
+/// On-disk form of the history
+#[derive(Serialize, Deserialize)]
+struct StoredHistory {
+    entries: Vec<HistoryEntry>,
+    #[serde(default)]
+    summaries: Vec<ConversationSummary>,
+}
+
+/// Bounded history of exchanged messages that applies edits and retractions
+#[derive(Debug)]
+pub struct ConversationHistory {
+    state: RwLock<HistoryState>,
+    capacity: usize,
+    policy: RwLock<AmendmentPolicy>,
+}
+
+impl Default for ConversationHistory {
+    fn default() -> Self {
+        Self::new(10_000)
+    }
+}
+
+impl ConversationHistory {
+    /// Create a history keeping at most `capacity` messages
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: RwLock::new(HistoryState::default()),
+            capacity,
+            policy: RwLock::new(AmendmentPolicy::default()),
+        }
+    }
+
+    /// Replace the amendment policy
+    pub fn set_policy(&self, policy: AmendmentPolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
+
+    /// Current amendment policy
+    pub fn policy(&self) -> AmendmentPolicy {
+        self.policy.read().unwrap().clone()
+    }
+
+    /// Record a message, stamping a message ID into its metadata if it has none
+    pub fn record(&self, message: &mut SimpleMessage) -> String {
+        let message_id = message
+            .metadata
+            .entry(MESSAGE_ID_KEY.to_string())
+            .or_insert_with(|| Uuid::new_v4().to_string())
+            .clone();
+
+        let mut state = self.state.write().unwrap();
+        if state.entries.contains_key(&message_id) {
+            return message_id;
+        }
+        state.entries.insert(message_id.clone(), HistoryEntry {
+            message_id: message_id.clone(),
+            from: message.from_entity.clone(),
+            to: message.to.clone(),
+            content: message.content.clone(),
+            message_type: message.message_type.clone(),
+            metadata: message.metadata.clone(),
+            recorded_at: DateTimeWrapper::new(Utc::now()),
+            edited_at: None,
+            revisions: Vec::new(),
+            retracted_at: None,
+        });
+        state.order.push_back(message_id.clone());
+        while state.order.len() > self.capacity {
+            if let Some(oldest) = state.order.pop_front() {
+                state.entries.remove(&oldest);
+            }
+        }
+        message_id
+    }
+
+    /// Look up a message by ID
+    pub fn get(&self, message_id: &str) -> Option<HistoryEntry> {
+        self.state.read().unwrap().entries.get(message_id).cloned()
+    }
+
+    /// Every version of a message, oldest first, ending with the current content
+    pub fn edit_history(&self, message_id: &str) -> Vec<MessageRevision> {
+        let state = self.state.read().unwrap();
+        let Some(entry) = state.entries.get(message_id) else {
+            return Vec::new();
+        };
+        let mut versions = entry.revisions.clone();
+        if !entry.is_retracted() {
+            versions.push(MessageRevision {
+                content: entry.content.clone(),
+                since: entry.edited_at.clone().unwrap_or_else(|| entry.recorded_at.clone()),
+            });
+        }
+        versions
+    }
+
+    /// Every recorded message, oldest first
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        let state = self.state.read().unwrap();
+        state.order.iter().filter_map(|id| state.entries.get(id)).cloned().collect()
+    }
+
+    /// Messages exchanged with `peer`, oldest first
+    pub fn conversation(&self, peer: &str) -> Vec<HistoryEntry> {
+        let state = self.state.read().unwrap();
+        state
+            .order
+            .iter()
+            .filter_map(|id| state.entries.get(id))
+            .filter(|entry| entry.from == peer || entry.to == peer)
+            .cloned()
+            .collect()
+    }
+
+    /// Latest summary of the conversation with `peer`
+    pub fn summary(&self, peer: &str) -> Option<ConversationSummary> {
+        self.state.read().unwrap().summaries.get(peer).cloned()
+    }
+
+    /// Store a summary, replacing the peer's previous one
+    pub fn set_summary(&self, summary: ConversationSummary) {
+        self.state.write().unwrap().summaries.insert(summary.peer.clone(), summary);
+    }
+
+    /// The peer's summary and the messages recorded since it, oldest first
+    ///
+    /// Retracted messages are left out. If the last summarized message has
+    /// dropped out of the history, messages recorded after it are returned.
+    pub fn unsummarized(&self, peer: &str) -> (Option<ConversationSummary>, Vec<HistoryEntry>) {
+        let state = self.state.read().unwrap();
+        let summary = state.summaries.get(peer).cloned();
+        let conversation: Vec<&HistoryEntry> = state
+            .order
+            .iter()
+            .filter_map(|id| state.entries.get(id))
+            .filter(|entry| entry.from == peer || entry.to == peer)
+            .collect();
+        let start = summary.as_ref().map_or(0, |summary| {
+            match conversation.iter().position(|entry| entry.message_id == summary.last_message_id) {
+                Some(last) => last + 1,
+                None => conversation
+                    .iter()
+                    .position(|entry| entry.recorded_at.0 > summary.covers_until.0)
+                    .unwrap_or(conversation.len()),
+            }
+        });
+        let messages = conversation[start..]
+            .iter()
+            .filter(|entry| !entry.is_retracted())
+            .map(|entry| (*entry).clone())
+            .collect();
+        (summary, messages)
+    }
+
+    /// Everyone who sent or received a message recorded since `since`
+    pub fn peers_since(&self, since: DateTime<Utc>) -> Vec<String> {
+        let state = self.state.read().unwrap();
+        let mut peers: Vec<String> = state
+            .entries
+            .values()
+            .filter(|entry| entry.recorded_at.0 >= since)
+            .flat_map(|entry| [entry.from.clone(), entry.to.clone()])
+            .collect();
+        peers.sort();
+        peers.dedup();
+        peers
+    }
+
+    /// Delete every message exchanged with `peer` and its summary, returning
+    /// how many messages were removed
+    pub fn purge_peer(&self, peer: &str) -> usize {
+        let mut state = self.state.write().unwrap();
+        let before = state.entries.len();
+        state.entries.retain(|_, entry| entry.from != peer && entry.to != peer);
+        let HistoryState { entries, order, summaries } = &mut *state;
+        order.retain(|id| entries.contains_key(id));
+        summaries.remove(peer);
+        before - state.entries.len()
+    }
+
+    /// Write the history to `path`, encrypted when `key` is given
+    pub fn save(&self, path: &Path, key: Option<&StorageKey>) -> Result<()> {
+        let stored = {
+            let state = self.state.read().unwrap();
+            StoredHistory {
+                entries: state.order.iter().filter_map(|id| state.entries.get(id)).cloned().collect(),
+                summaries: state.summaries.values().cloned().collect(),
+            }
+        };
+        write_sealed_file(path, key, &serde_json::to_vec(&stored)?)
+    }
+
+    /// Restore messages saved with [`ConversationHistory::save`]
+    ///
+    /// A plaintext file is rewritten encrypted when `key` is given. Returns
+    /// the number of messages restored.
+    pub fn load(&self, path: &Path, key: Option<&StorageKey>) -> Result<usize> {
+        if !path.exists() {
+            return Ok(0);
+        }
+        let (content, plaintext) = read_sealed_file(path, key)?;
+        let stored = match serde_json::from_slice::<StoredHistory>(&content) {
+            Ok(stored) => stored,
+            // Files written before summaries were kept hold only the entries
+            Err(_) => StoredHistory {
+                entries: serde_json::from_slice(&content)?,
+                summaries: Vec::new(),
+            },
+        };
+        let restored = {
+            let mut state = self.state.write().unwrap();
+            for summary in stored.summaries {
+                state.summaries.entry(summary.peer.clone()).or_insert(summary);
+            }
+            let mut restored = 0;
+            for entry in stored.entries {
+                if state.entries.contains_key(&entry.message_id) {
+                    continue;
+                }
+                state.order.push_back(entry.message_id.clone());
+                state.entries.insert(entry.message_id.clone(), entry);
+                restored += 1;
+            }
+            while state.order.len() > self.capacity {
+                if let Some(oldest) = state.order.pop_front() {
+                    state.entries.remove(&oldest);
+                }
+            }
+            restored
+        };
+        if plaintext && key.is_some() {
+            self.save(path, key)?;
+        }
+        Ok(restored)
+    }
+
+    /// Check an amendment against the history and policy without applying it
+    pub fn check(&self, amendment: &MessageAmendment) -> Result<()> {
+        let state = self.state.read().unwrap();
+        let entry = state
+            .entries
+            .get(&amendment.message_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Message {}", amendment.message_id)))?;
+        Self::check_entry(entry, amendment, &self.policy())
+    }
+
+    /// Apply an edit or retraction, returning the updated entry
+    ///
+    /// The caller is responsible for verifying the amendment's signature.
+    pub fn apply(&self, amendment: &MessageAmendment) -> Result<HistoryEntry> {
+        let policy = self.policy();
+        let mut state = self.state.write().unwrap();
+        let entry = state
+            .entries
+            .get_mut(&amendment.message_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Message {}", amendment.message_id)))?;
+        Self::check_entry(entry, amendment, &policy)?;
+
+        match &amendment.action {
+            AmendmentAction::Edit { content } => {
+                let previous = std::mem::replace(&mut entry.content, content.clone());
+                let since = entry.edited_at.replace(amendment.amended_at.clone());
+                entry.revisions.push(MessageRevision {
+                    content: previous,
+                    since: since.unwrap_or_else(|| entry.recorded_at.clone()),
+                });
+            }
+            AmendmentAction::Retract => {
+                entry.content.clear();
+                entry.revisions.clear();
+                entry.retracted_at = Some(amendment.amended_at.clone());
+            }
+        }
+        Ok(entry.clone())
+    }
+
+    fn check_entry(entry: &HistoryEntry, amendment: &MessageAmendment, policy: &AmendmentPolicy) -> Result<()> {
+        if entry.from != amendment.author {
+            return Err(SynapseError::AuthorizationError(format!(
+                "{} cannot amend message {} sent by {}",
+                amendment.author, entry.message_id, entry.from
+            )));
+        }
+        if entry.is_retracted() {
+            return Err(SynapseError::ValidationFailed(format!(
+                "Message {} has been retracted",
+                entry.message_id
+            )));
+        }
+        policy.check(&amendment.action, &entry.recorded_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(history: &ConversationHistory, from: &str) -> String {
+        let mut message = SimpleMessage::new("bob@example.com", from, "first draft");
+        history.record(&mut message)
+    }
+
+    #[test]
+    fn edits_keep_earlier_versions() {
+        let history = ConversationHistory::default();
+        let id = recorded(&history, "alice@example.com");
+
+        let edit = MessageAmendment::new(
+            "alice@example.com",
+            &id,
+            AmendmentAction::Edit { content: "final text".to_string() },
+        );
+        let entry = history.apply(&edit).unwrap();
+        assert_eq!(entry.content, "final text");
+        assert!(entry.is_edited());
+
+        let versions: Vec<_> = history.edit_history(&id).into_iter().map(|v| v.content).collect();
+        assert_eq!(versions, vec!["first draft", "final text"]);
+    }
+
+    #[test]
+    fn retraction_leaves_tombstone_and_blocks_further_changes() {
+        let history = ConversationHistory::default();
+        let id = recorded(&history, "alice@example.com");
+
+        let entry = history
+            .apply(&MessageAmendment::new("alice@example.com", &id, AmendmentAction::Retract))
+            .unwrap();
+        assert!(entry.is_retracted());
+        assert!(entry.content.is_empty());
+        assert!(history.edit_history(&id).is_empty());
+
+        let edit = MessageAmendment::new("alice@example.com", &id, AmendmentAction::Edit { content: "x".to_string() });
+        assert!(history.apply(&edit).is_err());
+    }
+
+    #[test]
+    fn only_author_may_amend_and_policy_applies() {
+        let history = ConversationHistory::default();
+        let id = recorded(&history, "alice@example.com");
+
+        let forged = MessageAmendment::new("mallory@example.com", &id, AmendmentAction::Retract);
+        assert!(matches!(history.apply(&forged), Err(SynapseError::AuthorizationError(_))));
+
+        history.set_policy(AmendmentPolicy {
+            allow_retraction: false,
+            ..AmendmentPolicy::default()
+        });
+        let retract = MessageAmendment::new("alice@example.com", &id, AmendmentAction::Retract);
+        assert!(history.apply(&retract).is_err());
+    }
+
+    #[test]
+    fn amendment_round_trips_through_message() {
+        let amendment = MessageAmendment::new("alice@example.com", "42", AmendmentAction::Retract);
+        let message = amendment.to_message("bob@example.com").unwrap();
+        let decoded = MessageAmendment::from_message(&message).unwrap();
+        assert_eq!(decoded.message_id, "42");
+        assert_eq!(decoded.action, AmendmentAction::Retract);
+        assert_eq!(decoded.signing_payload(), amendment.signing_payload());
+    }
+    #[test]
+    fn purge_peer_removes_both_directions() {
+        let history = ConversationHistory::default();
+        recorded(&history, "alice@example.com");
+        recorded(&history, "carol@example.com");
+        let mut reply = SimpleMessage::new("alice@example.com", "bob@example.com", "reply");
+        history.record(&mut reply);
+
+        assert_eq!(history.purge_peer("alice@example.com"), 2);
+        assert!(history.conversation("alice@example.com").is_empty());
+        assert_eq!(history.conversation("carol@example.com").len(), 1);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn saved_history_is_encrypted() {
+        let path = std::env::temp_dir().join(format!("synapse-history-{}.json", Uuid::new_v4()));
+        let history = ConversationHistory::default();
+        let id = recorded(&history, "alice@example.com");
+        history.save(&path, None).unwrap();
+
+        // Loading a plaintext file with a key encrypts it in place
+        let key = StorageKey::from_bytes([3; 32]);
+        let restored = ConversationHistory::default();
+        assert_eq!(restored.load(&path, Some(&key)).unwrap(), 1);
+        assert_eq!(restored.get(&id).unwrap().content, "first draft");
+        let on_disk = std::fs::read(&path).unwrap();
+        assert!(StorageKey::is_sealed(&on_disk));
+        assert!(!String::from_utf8_lossy(&on_disk).contains("first draft"));
+
+        assert!(ConversationHistory::default().load(&path, None).is_err());
+        assert_eq!(ConversationHistory::default().load(&path, Some(&key)).unwrap(), 1);
+        std::fs::remove_file(&path).ok();
+    }
+}
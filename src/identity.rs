@@ -245,11 +245,16 @@
 //! handling all the complex networking, security, and protocol details automatically.
 
 use crate::blockchain::serialization::UuidWrapper;
-use crate::types::{EntityType, GlobalIdentity};
+use crate::types::{EntityType, GlobalIdentity, SimpleMessage};
+use crate::crypto::CryptoManager;
 use crate::error::{IdentityError, Result};
+use crate::limits::{QueueReservation, ResourceGovernor};
 use crate::synapse::blockchain::serialization::DateTimeWrapper;
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
 use uuid::Uuid;
 
 /// Registry for managing entity identities
@@ -259,6 +264,10 @@ pub struct IdentityRegistry {
     identities: DashMap<String, GlobalIdentity>,
     /// Local name -> Global ID mapping
     local_names: DashMap<String, String>,
+    /// Global ID -> time of last conversation, used to rank candidates
+    recent_interactions: DashMap<String, DateTime<Utc>>,
+    /// Lowercased query -> (Global ID -> times chosen), learned from disambiguation
+    resolution_choices: DashMap<String, HashMap<String, u32>>,
 }
 
 impl IdentityRegistry {
@@ -267,6 +276,8 @@ impl IdentityRegistry {
         Self {
             identities: DashMap::new(),
             local_names: DashMap::new(),
+            recent_interactions: DashMap::new(),
+            resolution_choices: DashMap::new(),
         }
     }
 
@@ -423,6 +434,8 @@ impl IdentityRegistry {
     pub fn clear(&self) {
         self.identities.clear();
         self.local_names.clear();
+        self.recent_interactions.clear();
+        self.resolution_choices.clear();
         tracing::info!("🧹 Cleared all identities");
     }
 
@@ -463,6 +476,183 @@ impl IdentityRegistry {
     }
 }
 
+/// Score gap below which the top two candidates are considered ambiguous
+const AMBIGUITY_MARGIN: f64 = 0.15;
+
+/// Conversations within this window count as recent
+const RECENT_INTERACTION_WINDOW_DAYS: i64 = 14;
+
+/// Context used to rank candidates when a name matches more than one identity
+#[derive(Debug, Clone, Default)]
+pub struct ResolutionContext {
+    /// Organization domain of the caller (e.g. "ai-lab.example.com")
+    pub organization: Option<String>,
+    /// Global IDs the caller is currently talking with
+    pub active_conversations: Vec<String>,
+}
+
+/// A ranked candidate for a contextual name lookup
+#[derive(Debug, Clone)]
+pub struct ResolutionCandidate {
+    /// The candidate identity
+    pub identity: GlobalIdentity,
+    /// Ranking score, higher is better
+    pub score: f64,
+    /// Human-readable reasons that contributed to the score
+    pub reasons: Vec<String>,
+}
+
+/// Result of a contextual name lookup
+#[derive(Debug, Clone)]
+pub enum NameResolution {
+    /// One candidate clearly won
+    Resolved(GlobalIdentity),
+    /// Several candidates scored too closely; ask the caller to choose
+    Ambiguous(Vec<ResolutionCandidate>),
+    /// Nothing matched the name
+    NotFound,
+}
+
+/// Levenshtein edit distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b_chars.len() + 1];
+        for (j, cb) in b_chars.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current[j + 1] = (previous[j + 1] + 1).min(current[j] + 1).min(previous[j] + cost);
+        }
+        previous = current;
+    }
+    previous[b_chars.len()]
+}
+
+/// How well a query matches a name, from 0.0 (no match) to 1.0 (exact)
+fn name_match_score(query: &str, name: &str) -> f64 {
+    let name = name.to_lowercase();
+    if name == query {
+        1.0
+    } else if name.starts_with(query) || name.split(|c: char| !c.is_alphanumeric()).any(|w| w == query) {
+        0.7
+    } else if name.contains(query) {
+        0.5
+    } else if query.len() >= 4 && edit_distance(query, &name) <= 2 {
+        0.4
+    } else {
+        0.0
+    }
+}
+
+impl IdentityRegistry {
+    /// Record that we just exchanged messages with an identity
+    pub fn record_interaction(&self, global_id: &str) {
+        self.recent_interactions.insert(global_id.to_string(), Utc::now());
+    }
+
+    /// Remember which candidate the caller picked for an ambiguous name
+    pub fn record_resolution_choice(&self, query: &str, chosen_global_id: &str) {
+        *self
+            .resolution_choices
+            .entry(query.to_lowercase())
+            .or_default()
+            .entry(chosen_global_id.to_string())
+            .or_insert(0) += 1;
+        self.record_interaction(chosen_global_id);
+    }
+
+    /// Remove an identity along with everything learned about it while
+    /// resolving names, returning how many entries were dropped
+    pub fn forget(&self, global_id: &str) -> usize {
+        let mut removed = self.remove_identity(global_id).is_ok() as usize;
+        removed += self.recent_interactions.remove(global_id).is_some() as usize;
+        self.resolution_choices.retain(|_, chosen| {
+            removed += chosen.remove(global_id).is_some() as usize;
+            !chosen.is_empty()
+        });
+        removed
+    }
+
+    /// Rank every identity that plausibly matches `query`
+    pub fn rank_candidates(&self, query: &str, context: &ResolutionContext) -> Vec<ResolutionCandidate> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let choices = self.resolution_choices.get(&query).map(|c| c.value().clone()).unwrap_or_default();
+        let total_choices: u32 = choices.values().sum();
+        let recent_cutoff = Utc::now() - Duration::days(RECENT_INTERACTION_WINDOW_DAYS);
+
+        let mut candidates: Vec<ResolutionCandidate> = self
+            .identities
+            .iter()
+            .filter_map(|entry| {
+                let identity = entry.value();
+                let local_part = identity.global_id.split('@').next().unwrap_or("");
+                let match_score = name_match_score(&query, &identity.local_name)
+                    .max(name_match_score(&query, local_part))
+                    .max(if identity.global_id.to_lowercase() == query { 1.0 } else { 0.0 });
+                if match_score == 0.0 {
+                    return None;
+                }
+
+                let mut score = match_score;
+                let mut reasons = vec![format!("name match {:.1}", match_score)];
+
+                if context.active_conversations.iter().any(|id| id == &identity.global_id) {
+                    score += 0.4;
+                    reasons.push("in an active conversation".to_string());
+                } else if let Some(last) = self.recent_interactions.get(&identity.global_id) {
+                    if *last > recent_cutoff {
+                        score += 0.25;
+                        reasons.push("talked recently".to_string());
+                    }
+                }
+
+                if let Some(org) = &context.organization {
+                    let domain = identity.global_id.rsplit('@').next().unwrap_or("");
+                    if domain.eq_ignore_ascii_case(org) || domain.ends_with(&format!(".{}", org.to_lowercase())) {
+                        score += 0.3;
+                        reasons.push(format!("same organization ({})", org));
+                    }
+                }
+
+                score += f64::from(identity.trust_level) / 100.0 * 0.2;
+
+                if let Some(times) = choices.get(&identity.global_id) {
+                    score += 0.5 * f64::from(*times) / f64::from(total_choices.max(1));
+                    reasons.push(format!("chosen {} time(s) before", times));
+                }
+
+                Some(ResolutionCandidate { identity: identity.clone(), score, reasons })
+            })
+            .collect();
+
+        candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        candidates
+    }
+
+    /// Resolve a name using conversation, organization, trust and past choices
+    ///
+    /// Returns [`NameResolution::Ambiguous`] when the best candidates are too close to call;
+    /// feed the caller's pick back through [`IdentityRegistry::record_resolution_choice`].
+    pub fn resolve_contextual(&self, query: &str, context: &ResolutionContext) -> NameResolution {
+        let candidates = self.rank_candidates(query, context);
+        match candidates.as_slice() {
+            [] => NameResolution::NotFound,
+            [only] => NameResolution::Resolved(only.identity.clone()),
+            [best, runner_up, ..] if best.score - runner_up.score >= AMBIGUITY_MARGIN => {
+                NameResolution::Resolved(best.identity.clone())
+            }
+            [best, ..] => {
+                let cutoff = best.score - AMBIGUITY_MARGIN;
+                NameResolution::Ambiguous(candidates.into_iter().filter(|c| c.score >= cutoff).collect())
+            }
+        }
+    }
+}
+
 impl Default for IdentityRegistry {
     fn default() -> Self {
         Self::new()
@@ -531,6 +721,425 @@ impl IdentityRegistry {
     }
 }
 
+/// Transport bindings owned by a single local identity
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportBindings {
+    /// TCP listener port for this identity
+    pub tcp_port: Option<u16>,
+    /// UDP listener port for this identity
+    pub udp_port: Option<u16>,
+    /// HTTP/WebSocket listener port for this identity
+    pub http_port: Option<u16>,
+}
+
+impl TransportBindings {
+    /// All ports claimed by these bindings
+    pub fn ports(&self) -> Vec<u16> {
+        [self.tcp_port, self.udp_port, self.http_port]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+/// A persona hosted by this node, with its own keys, resolution cache and inbox
+#[derive(Debug)]
+pub struct LocalIdentity {
+    /// Short name used to select this identity (e.g. "support-bot")
+    pub name: String,
+    /// Global ID messages are sent from
+    pub global_id: String,
+    /// Transport ports reserved for this identity
+    pub bindings: TransportBindings,
+    /// Isolated key pair for signing and decryption
+    crypto: RwLock<CryptoManager>,
+    /// Identity resolution cache, not shared with other personas
+    registry: IdentityRegistry,
+    /// Messages received for this identity, oldest first, with the queue
+    /// space each holds
+    inbox: Mutex<VecDeque<(SimpleMessage, Option<QueueReservation>)>>,
+}
+
+impl LocalIdentity {
+    /// Create a new local identity with an empty key store
+    pub fn new(name: impl Into<String>, global_id: impl Into<String>, bindings: TransportBindings) -> Self {
+        Self {
+            name: name.into(),
+            global_id: global_id.into(),
+            bindings,
+            crypto: RwLock::new(CryptoManager::new()),
+            registry: IdentityRegistry::new(),
+            inbox: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Generate a fresh key pair for this identity, returning the public key PEM
+    pub fn generate_keypair(&self) -> Result<String> {
+        let mut crypto = self.crypto.write()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        let (_, public_pem) = crypto.generate_keypair()?;
+        Ok(public_pem)
+    }
+
+    /// Sign content with this identity's private key
+    pub fn sign(&self, content: &str) -> Result<Vec<u8>> {
+        let crypto = self.crypto.read()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.sign_message(content)
+    }
+
+    /// Sign raw bytes, such as a message's canonical signing payload
+    pub fn sign_bytes(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let crypto = self.crypto.read()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.sign_bytes(data)
+    }
+
+    /// Check a peer's signature over content using this identity's key store
+    pub fn verify_signature(&self, content: &str, signature: &[u8], sender_global_id: &str) -> Result<bool> {
+        let crypto = self.crypto.read()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.verify_signature(content, signature, sender_global_id)
+    }
+
+    /// Encrypt content for a peer whose public key is in this identity's key store
+    #[cfg(feature = "crypto")]
+    pub fn encrypt_for(&self, recipient_global_id: &str, content: &str) -> Result<Vec<u8>> {
+        let crypto = self.crypto.read()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.encrypt_message(content, recipient_global_id)
+    }
+
+    /// Decrypt content sent to this identity
+    pub fn decrypt(&self, encrypted: &[u8]) -> Result<String> {
+        let crypto = self.crypto.read()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.decrypt_message(encrypted)
+    }
+
+    /// This identity's public key in PEM format
+    pub fn public_key_pem(&self) -> Result<String> {
+        let crypto = self.crypto.read()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.get_public_key_pem()
+    }
+
+    /// Replace this identity's key pair with one loaded from a private key PEM
+    pub fn load_private_key(&self, private_key_pem: &str) -> Result<()> {
+        let mut crypto = self.crypto.write()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.load_private_key(private_key_pem)
+    }
+
+    /// Import a peer's public key into this identity's key store
+    pub fn import_peer_key(&self, global_id: &str, public_key_pem: &str) -> Result<()> {
+        let mut crypto = self.crypto.write()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.import_public_key(global_id, public_key_pem)
+    }
+
+    /// Identity resolution cache for this persona
+    pub fn registry(&self) -> &IdentityRegistry {
+        &self.registry
+    }
+
+    /// Queue an incoming message for this identity
+    pub fn deliver(&self, message: SimpleMessage) {
+        self.deliver_reserved(message, None);
+    }
+
+    fn deliver_reserved(&self, message: SimpleMessage, reservation: Option<QueueReservation>) {
+        if let Ok(mut inbox) = self.inbox.lock() {
+            inbox.push_back((message, reservation));
+        }
+    }
+
+    /// Drain all pending messages from this identity's inbox
+    pub fn take_inbox(&self) -> Vec<SimpleMessage> {
+        self.inbox
+            .lock()
+            .map(|mut inbox| inbox.drain(..).map(|(message, _)| message).collect())
+            .unwrap_or_default()
+    }
+
+    /// Copies of the pending messages, oldest first, leaving them queued
+    pub fn peek_inbox(&self) -> Vec<SimpleMessage> {
+        self.inbox
+            .lock()
+            .map(|inbox| inbox.iter().map(|(message, _)| message.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Number of messages waiting in the inbox
+    pub fn inbox_len(&self) -> usize {
+        self.inbox.lock().map(|inbox| inbox.len()).unwrap_or(0)
+    }
+}
+
+/// Manages the set of local identities hosted by one node
+#[derive(Debug, Default)]
+pub struct LocalIdentityManager {
+    /// Identity name -> identity
+    identities: DashMap<String, Arc<LocalIdentity>>,
+    /// Global ID -> identity name
+    by_global_id: DashMap<String, String>,
+    /// Bounds the bytes waiting in inboxes
+    limits: Option<Arc<ResourceGovernor>>,
+}
+
+impl LocalIdentityManager {
+    /// Create an empty identity manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count inbox contents against `limits`' queued bytes
+    pub fn with_resource_limits(mut self, limits: Arc<ResourceGovernor>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Add a local identity, rejecting duplicate names, global IDs or ports
+    pub fn add(&self, identity: LocalIdentity) -> Result<Arc<LocalIdentity>> {
+        if self.identities.contains_key(&identity.name) {
+            return Err(IdentityError::AlreadyExists(format!(
+                "Local identity '{}' already exists",
+                identity.name
+            )));
+        }
+
+        if self.by_global_id.contains_key(&identity.global_id) {
+            return Err(IdentityError::AlreadyExists(format!(
+                "Global ID '{}' is already hosted by this node",
+                identity.global_id
+            )));
+        }
+
+        let requested = identity.bindings.ports();
+        for existing in self.identities.iter() {
+            if let Some(port) = existing.bindings.ports().into_iter().find(|p| requested.contains(p)) {
+                return Err(IdentityError::AlreadyExists(format!(
+                    "Port {} is already bound by identity '{}'",
+                    port,
+                    existing.name
+                )));
+            }
+        }
+
+        let identity = Arc::new(identity);
+        self.by_global_id.insert(identity.global_id.clone(), identity.name.clone());
+        self.identities.insert(identity.name.clone(), identity.clone());
+
+        tracing::info!("🎭 Added local identity '{}' ({})", identity.name, identity.global_id);
+        Ok(identity)
+    }
+
+    /// Remove a local identity by name
+    pub fn remove(&self, name: &str) -> Result<Arc<LocalIdentity>> {
+        let (_, identity) = self.identities.remove(name).ok_or_else(|| {
+            IdentityError::NotFound(format!("Local identity not found: {}", name))
+        })?;
+        self.by_global_id.remove(&identity.global_id);
+        Ok(identity)
+    }
+
+    /// Look up a local identity by name or global ID
+    pub fn get(&self, name_or_global_id: &str) -> Option<Arc<LocalIdentity>> {
+        if let Some(identity) = self.identities.get(name_or_global_id) {
+            return Some(identity.value().clone());
+        }
+        let name = self.by_global_id.get(name_or_global_id)?;
+        self.identities.get(name.as_str()).map(|entry| entry.value().clone())
+    }
+
+    /// Route an incoming message to the inbox of the identity it is addressed to
+    ///
+    /// Fails with [`IdentityError::ResourceLimit`] when the inboxes hold the
+    /// most bytes the resource limits allow.
+    pub fn deliver(&self, message: SimpleMessage) -> Result<()> {
+        let identity = self.get(&message.to).ok_or_else(|| {
+            IdentityError::NotFound(format!("No local identity for recipient: {}", message.to))
+        })?;
+        let reservation = match self.limits {
+            Some(ref limits) => {
+                let bytes = message.content.len()
+                    + message.metadata.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>();
+                Some(limits.try_reserve_bytes(bytes as u64).ok_or_else(|| IdentityError::ResourceLimit {
+                    resource: "queued_bytes".to_string(),
+                    limit: limits.config().max_queued_bytes,
+                })?)
+            }
+            None => None,
+        };
+        identity.deliver_reserved(message, reservation);
+        Ok(())
+    }
+
+    /// Names of all local identities
+    pub fn names(&self) -> Vec<String> {
+        self.identities.iter().map(|entry| entry.key().clone()).collect()
+    }
+
+    /// Number of local identities
+    pub fn count(&self) -> usize {
+        self.identities.len()
+    }
+}
+
+/// Current identity bundle format version
+pub const IDENTITY_BUNDLE_VERSION: u8 = 1;
+
+/// Plaintext contents of an identity bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdentityBundle {
+    /// Bundle format version
+    pub version: u8,
+    /// Name of the local identity
+    pub name: String,
+    /// Global ID of the local identity
+    pub global_id: String,
+    /// Private key (PKCS#8 PEM)
+    pub private_key_pem: String,
+    /// Public key (SPKI PEM)
+    pub public_key_pem: String,
+    /// Contacts known to this identity
+    pub contacts: Vec<GlobalIdentity>,
+    /// Pointers into trust state (e.g. blockchain participant IDs, staking records)
+    #[serde(default)]
+    pub trust_state_pointers: HashMap<String, String>,
+    /// When the bundle was created
+    pub exported_at: DateTimeWrapper,
+}
+
+/// Passphrase-encrypted identity bundle, safe to move between machines
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedIdentityBundle {
+    /// Bundle format version
+    pub version: u8,
+    /// Global ID, left in clear so operators can tell bundles apart
+    pub global_id: String,
+    /// salt + nonce + AES-256-GCM ciphertext of the JSON-encoded [`IdentityBundle`]
+    pub ciphertext: Vec<u8>,
+}
+
+/// Signed statement that an identity has moved to a new endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationProof {
+    /// Identity that moved
+    pub global_id: String,
+    /// Endpoint the identity was previously reachable at, if known
+    pub previous_endpoint: Option<String>,
+    /// Endpoint the identity is now reachable at
+    pub new_endpoint: String,
+    /// When the migration happened
+    pub migrated_at: DateTimeWrapper,
+    /// Signature over [`MigrationProof::signing_payload`] with the identity's key
+    pub signature: Vec<u8>,
+}
+
+impl MigrationProof {
+    /// Create and sign a migration proof for a local identity
+    pub fn sign(identity: &LocalIdentity, previous_endpoint: Option<String>, new_endpoint: impl Into<String>) -> Result<Self> {
+        let mut proof = Self {
+            global_id: identity.global_id.clone(),
+            previous_endpoint,
+            new_endpoint: new_endpoint.into(),
+            migrated_at: DateTimeWrapper::new(Utc::now()),
+            signature: Vec::new(),
+        };
+        proof.signature = identity.sign(&proof.signing_payload())?;
+        Ok(proof)
+    }
+
+    /// Canonical string covered by the signature
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "synapse-migration:{}:{}:{}:{}",
+            self.global_id,
+            self.previous_endpoint.as_deref().unwrap_or(""),
+            self.new_endpoint,
+            self.migrated_at.0.timestamp()
+        )
+    }
+
+    /// Verify the proof against the identity's known public key
+    pub fn verify(&self, public_key_pem: &str) -> Result<bool> {
+        let mut crypto = CryptoManager::new();
+        crypto.import_public_key(&self.global_id, public_key_pem)?;
+        crypto.verify_signature(&self.signing_payload(), &self.signature, &self.global_id)
+    }
+}
+
+/// Export a local identity as a passphrase-encrypted bundle
+pub fn export_bundle(
+    identity: &LocalIdentity,
+    trust_state_pointers: HashMap<String, String>,
+    passphrase: &str,
+) -> Result<EncryptedIdentityBundle> {
+    let private_key_pem = {
+        let crypto = identity.crypto.read()
+            .map_err(|_| IdentityError::Identity("Identity key store poisoned".to_string()))?;
+        crypto.export_private_key_pem()?
+    };
+
+    let bundle = IdentityBundle {
+        version: IDENTITY_BUNDLE_VERSION,
+        name: identity.name.clone(),
+        global_id: identity.global_id.clone(),
+        private_key_pem,
+        public_key_pem: identity.public_key_pem()?,
+        contacts: identity.registry().list_entities(),
+        trust_state_pointers,
+        exported_at: DateTimeWrapper::new(Utc::now()),
+    };
+
+    let plaintext = serde_json::to_vec(&bundle)?;
+    let ciphertext = crate::crypto::encrypt_with_passphrase(&plaintext, passphrase)?;
+
+    tracing::info!("📦 Exported identity bundle for {}", identity.global_id);
+    Ok(EncryptedIdentityBundle {
+        version: IDENTITY_BUNDLE_VERSION,
+        global_id: identity.global_id.clone(),
+        ciphertext,
+    })
+}
+
+/// Decrypt an identity bundle and rebuild the local identity it describes
+///
+/// Returns the restored identity along with the trust state pointers carried in the bundle.
+pub fn import_bundle(
+    encrypted: &EncryptedIdentityBundle,
+    passphrase: &str,
+    bindings: TransportBindings,
+) -> Result<(LocalIdentity, HashMap<String, String>)> {
+    if encrypted.version > IDENTITY_BUNDLE_VERSION {
+        return Err(IdentityError::InvalidFormat(format!(
+            "Unsupported identity bundle version {}",
+            encrypted.version
+        )));
+    }
+
+    let plaintext = crate::crypto::decrypt_with_passphrase(&encrypted.ciphertext, passphrase)?;
+    let bundle: IdentityBundle = serde_json::from_slice(&plaintext)?;
+
+    if bundle.global_id != encrypted.global_id {
+        return Err(IdentityError::ValidationFailed(
+            "Bundle global ID does not match its envelope".to_string(),
+        ));
+    }
+
+    let identity = LocalIdentity::new(bundle.name, bundle.global_id, bindings);
+    identity.load_private_key(&bundle.private_key_pem)?;
+    for contact in bundle.contacts {
+        if let Err(e) = identity.registry().register_identity(contact) {
+            tracing::warn!("Skipping contact while importing identity bundle: {}", e);
+        }
+    }
+
+    tracing::info!("📥 Imported identity bundle for {}", identity.global_id);
+    Ok((identity, bundle.trust_state_pointers))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -630,4 +1239,71 @@ mod tests {
         let ai_entities = registry.list_by_type(EntityType::AiModel);
         assert_eq!(ai_entities.len(), 2);
     }
+
+    #[test]
+    fn test_local_identities_are_isolated() {
+        let manager = LocalIdentityManager::new();
+
+        let support = LocalIdentity::new(
+            "support",
+            "support@bots.example.com",
+            TransportBindings { tcp_port: Some(9100), ..Default::default() },
+        );
+        let sales = LocalIdentity::new(
+            "sales",
+            "sales@bots.example.com",
+            TransportBindings { tcp_port: Some(9200), ..Default::default() },
+        );
+        manager.add(support).unwrap();
+        manager.add(sales).unwrap();
+
+        // Port collisions are rejected
+        let clash = LocalIdentity::new(
+            "billing",
+            "billing@bots.example.com",
+            TransportBindings { tcp_port: Some(9100), ..Default::default() },
+        );
+        assert!(manager.add(clash).is_err());
+
+        manager
+            .deliver(SimpleMessage::new("sales@bots.example.com", "alice@example.com", "hi"))
+            .unwrap();
+        assert_eq!(manager.get("sales").unwrap().inbox_len(), 1);
+        assert_eq!(manager.get("support").unwrap().inbox_len(), 0);
+
+        let support = manager.get("support").unwrap();
+        support
+            .registry()
+            .register_identity(IdentityRegistry::create_human("Alice", "example.com", "key"))
+            .unwrap();
+        assert!(manager.get("sales").unwrap().registry().resolve_local_name("Alice").is_none());
+    }
+
+    #[test]
+    fn test_contextual_resolution_disambiguates_and_learns() {
+        let registry = IdentityRegistry::new();
+        registry.register_identity(GlobalIdentity::new("Alice", "alice@ai-lab.example.com", EntityType::AiModel, "k1")).unwrap();
+        registry.register_identity(GlobalIdentity::new("Alice B", "alice@robotics.example.com", EntityType::Human, "k2")).unwrap();
+        registry.register_identity(GlobalIdentity::new("Alicia", "alicia@ops.example.com", EntityType::Human, "k3")).unwrap();
+
+        // Organization context picks the lab Alice
+        let lab = ResolutionContext { organization: Some("ai-lab.example.com".to_string()), ..Default::default() };
+        match registry.resolve_contextual("alice", &lab) {
+            NameResolution::Resolved(identity) => assert_eq!(identity.global_id, "alice@ai-lab.example.com"),
+            other => panic!("expected resolution, got {:?}", other),
+        }
+
+        // Without context the two Alices are too close to call
+        let ambiguous = registry.resolve_contextual("alice", &ResolutionContext::default());
+        assert!(matches!(ambiguous, NameResolution::Ambiguous(ref c) if c.len() >= 2));
+
+        // After the caller picks one, it wins next time
+        registry.record_resolution_choice("alice", "alice@robotics.example.com");
+        match registry.resolve_contextual("alice", &ResolutionContext::default()) {
+            NameResolution::Resolved(identity) => assert_eq!(identity.global_id, "alice@robotics.example.com"),
+            other => panic!("expected resolution, got {:?}", other),
+        }
+
+        assert!(matches!(registry.resolve_contextual("zed", &ResolutionContext::default()), NameResolution::NotFound));
+    }
 }
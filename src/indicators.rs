@@ -0,0 +1,206 @@
+//! # Read Receipts and Activity Indicators
+//!
+//! Interactive chats between people and AI models feel better when each side
+//! can see that a message was read, or that the other side is typing or busy
+//! producing an answer. These signals travel as tiny system messages whose
+//! content is a compact code:
+//!
+//! | Code      | Meaning                                  |
+//! |-----------|------------------------------------------|
+//! | `r:<id>`  | Message `<id>` was read                  |
+//! | `t`       | Typing                                   |
+//! | `p`       | Processing (e.g. a model is generating)  |
+//! | `i`       | Idle, clears a typing/processing state   |
+//!
+//! Incoming indicators are not delivered as ordinary messages; subscribers to
+//! [`IndicatorHub::subscribe`] receive them as [`IndicatorEvent`]s instead.
+//! Typing, processing and idle are ephemeral and are never sent by email.
+//! Contacts marked with [`Contact::suppress_indicators`](crate::contacts::Contact)
+//! neither receive our indicators nor have theirs surfaced.
+
+use crate::error::{Result, SynapseError};
+use crate::types::{MessageType, SimpleMessage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::broadcast;
+
+/// Metadata key marking a message as an indicator
+pub const INDICATOR_KEY: &str = "indicator";
+
+/// A read receipt or activity indicator
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Indicator {
+    /// The recipient has read a message
+    Read { message_id: String },
+    /// The sender is typing a reply
+    Typing,
+    /// The sender is working on a reply
+    Processing,
+    /// The sender stopped typing or processing
+    Idle,
+}
+
+impl Indicator {
+    /// Compact wire form
+    pub fn encode(&self) -> String {
+        match self {
+            Indicator::Read { message_id } => format!("r:{}", message_id),
+            Indicator::Typing => "t".to_string(),
+            Indicator::Processing => "p".to_string(),
+            Indicator::Idle => "i".to_string(),
+        }
+    }
+
+    /// Parse the compact wire form
+    pub fn decode(wire: &str) -> Result<Self> {
+        match wire {
+            "t" => Ok(Indicator::Typing),
+            "p" => Ok(Indicator::Processing),
+            "i" => Ok(Indicator::Idle),
+            _ => match wire.strip_prefix("r:") {
+                Some(message_id) if !message_id.is_empty() => Ok(Indicator::Read {
+                    message_id: message_id.to_string(),
+                }),
+                _ => Err(SynapseError::InvalidMessageFormat(format!("Unknown indicator: {}", wire))),
+            },
+        }
+    }
+
+    /// Whether the indicator only matters while the conversation is live
+    pub fn is_ephemeral(&self) -> bool {
+        !matches!(self, Indicator::Read { .. })
+    }
+
+    /// Wrap the indicator in a system message
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> SimpleMessage {
+        let mut metadata = HashMap::new();
+        metadata.insert(INDICATOR_KEY.to_string(), "1".to_string());
+        SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: self.encode(),
+            message_type: MessageType::System,
+            metadata,
+        }
+    }
+
+    /// Extract the indicator from a message, if it carries one
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        if message.message_type != MessageType::System || !message.metadata.contains_key(INDICATOR_KEY) {
+            return None;
+        }
+        Self::decode(&message.content).ok()
+    }
+}
+
+/// An indicator received from a peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndicatorEvent {
+    /// Who sent the indicator
+    pub from: String,
+    /// Which of our identities it was addressed to
+    pub to: String,
+    /// The indicator itself
+    pub indicator: Indicator,
+    /// When it arrived
+    pub received_at: DateTime<Utc>,
+}
+
+/// Fans incoming indicators out to subscribers
+#[derive(Debug)]
+pub struct IndicatorHub {
+    enabled: AtomicBool,
+    sender: broadcast::Sender<IndicatorEvent>,
+}
+
+impl Default for IndicatorHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IndicatorHub {
+    /// Create a hub with indicators enabled
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self {
+            enabled: AtomicBool::new(true),
+            sender,
+        }
+    }
+
+    /// Turn indicators on or off for every contact
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether indicators are sent and surfaced at all
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Subscribe to indicators from peers
+    pub fn subscribe(&self) -> broadcast::Receiver<IndicatorEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Consume `message` if it is an indicator, publishing it unless `suppressed`
+    ///
+    /// Returns `true` when the message was an indicator and should not be
+    /// delivered as an ordinary message.
+    pub fn handle(&self, message: &SimpleMessage, suppressed: bool) -> bool {
+        let Some(indicator) = Indicator::from_message(message) else {
+            return false;
+        };
+        if self.is_enabled() && !suppressed {
+            // No subscribers is fine; the indicator is simply dropped
+            let _ = self.sender.send(IndicatorEvent {
+                from: message.from_entity.clone(),
+                to: message.to.clone(),
+                indicator,
+                received_at: Utc::now(),
+            });
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wire_format_round_trips() {
+        for indicator in [
+            Indicator::Read { message_id: "42".to_string() },
+            Indicator::Typing,
+            Indicator::Processing,
+            Indicator::Idle,
+        ] {
+            assert_eq!(Indicator::decode(&indicator.encode()).unwrap(), indicator);
+        }
+        assert!(Indicator::decode("r:").is_err());
+        assert!(Indicator::decode("x").is_err());
+    }
+
+    #[test]
+    fn hub_surfaces_indicators_and_skips_suppressed() {
+        let hub = IndicatorHub::new();
+        let mut events = hub.subscribe();
+
+        let typing = Indicator::Typing.to_message("alice@example.com", "bot@example.com");
+        assert!(hub.handle(&typing, false));
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.from, "alice@example.com");
+        assert_eq!(event.indicator, Indicator::Typing);
+
+        assert!(hub.handle(&typing, true));
+        assert!(events.try_recv().is_err());
+
+        let ordinary = SimpleMessage::new("bot@example.com", "alice@example.com", "t");
+        assert!(!hub.handle(&ordinary, false));
+    }
+}
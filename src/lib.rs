@@ -124,6 +124,7 @@
 //!
 //! ```rust
 //! let router = EnhancedSynapseRouter::new(config, "bot@mydomain.com".to_string()).await?;
+//! router.ensure_email_server().await;
 //! 
 //! if router.is_running_email_server() {
 //!     // You can receive emails directly at bot@mydomain.com
@@ -148,6 +149,37 @@
 //!
 //! - [`router_enhanced`]: Main interface - start here for most use cases
 //! - [`identity`]: Name resolution and identity management  
+//! - [`contacts`]: Contact book with aliases, tags and groups
+//! - [`lifecycle`]: Suspension, deprecation and signed deletion tombstones for participants
+//! - [`purge`]: Right-to-be-forgotten purges across subsystems, with an audit record
+//! - [`organization`]: Organizations with membership, domain proof and shared policy
+//! - [`policy`]: Declarative per-destination transport and security rules
+//! - [`relay`]: Store-and-forward relay server for offline peers
+//! - [`push`]: FCM/APNs/webhook wake-ups for dormant mobile peers
+//! - [`webhooks`]: Signed HTTP delivery of selected incoming messages
+//! - [`admission`]: Trust-weighted throttling of inbound messages from strangers
+//! - [`indicators`]: Read receipts and typing/processing indicators
+//! - [`history`]: Conversation history with signed edits and retractions
+//! - [`notifications`]: Alert, status and approval-request templates for humans and agents
+//! - [`summary`]: Rolling conversation summaries from an application-provided summarizer
+//! - [`groups`]: End-to-end encrypted groups with rekeying on membership change
+//! - [`events`]: Typed stream of router lifecycle events
+//! - [`digest`]: Periodic delivery reports for operators
+//! - [`diagnostics`]: Connectivity self-test with remediation hints
+//! - [`logging`]: JSON/text logging with per-message correlation and sampled traces
+//! - [`service`]: systemd notify/watchdog and Windows service integration
+//! - [`limits`]: Caps on peers, queued bytes, crypto work and connections for small hosts
+//! - [`crypto_pool`]: Batched signature verification on blocking worker threads
+//! - [`hashing`]: BLAKE3/SHA-256 content hashes and detected hardware acceleration
+//! - [`buffer_pool`]: Recycled receive and serialization buffers for transports
+//! - [`runtime`]: Dedicated runtimes and task budgets for transports, consensus and storage
+//! - [`startup`]: Per-subsystem startup timings
+//! - [`node_state`]: Exported node state and split-brain markers for standby failover
+//! - [`election`]: Lease-based leader election with fencing tokens for active/standby nodes
+//! - [`analytics`]: Interaction graphs and communication statistics, exportable as JSON or GraphML
+//! - [`metering`]: Per-participant usage metering, quotas and usage exports for hosted providers
+//! - [`trust`]: Offline what-if replays of trust scoring, slashing and decay
+//! - [`bridges`]: Bridges to other chat networks (`matrix` and `xmpp` features)
 //! - [`transport`]: Multi-transport layer and intelligent routing
 //! - [`email_server`]: SMTP/IMAP server implementation
 //! - [`types`]: Core message types and data structures
@@ -189,6 +221,13 @@
 pub mod config;
 pub mod error;
 pub mod types;
+pub mod signing;
+pub mod protocol;
+pub mod capabilities;
+pub mod hashing;
+pub mod redaction;
+pub mod geo;
+pub mod notifications;
 
 // Platform-specific modules - not available in WASM
 #[cfg(not(target_arch = "wasm32"))]
@@ -200,10 +239,84 @@ pub mod router_enhanced;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod identity;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod contacts;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lifecycle;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod endpoints;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cluster;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod purge;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod organization;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod policy;
+#[cfg(all(feature = "crypto", not(target_arch = "wasm32")))]
+pub mod relay;
+#[cfg(all(feature = "crypto", not(target_arch = "wasm32")))]
+pub mod push;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod webhooks;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod admission;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod trust_gate;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod indicators;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod history;
+#[cfg(all(feature = "search", not(target_arch = "wasm32")))]
+pub mod search;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod summary;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod events;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod digest;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod diagnostics;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod logging;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod service;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod limits;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod crypto_pool;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod buffer_pool;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod runtime;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod startup;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod node_state;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod election;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod analytics;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod metering;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod trust;
+#[cfg(all(feature = "crypto", not(target_arch = "wasm32")))]
+pub mod groups;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bridges;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod email;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod email_throttle;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mailing_list;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod streaming;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod payload_stream;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod calls;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod connectivity;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod email_server;
@@ -215,7 +328,7 @@ pub mod monitoring;
 pub mod transport;
 
 // Re-export commonly used types
-pub use crypto::CryptoManager;
+pub use crypto::{CryptoManager, StorageKey};
 pub use email::EmailTransport;
 
 // Re-export transport types needed for tests
@@ -263,18 +376,30 @@ pub use synapse::storage;
 #[cfg(feature = "auth")]
 pub mod auth_integration;
 
-#[cfg(not(target_arch = "wasm32"))]
-use tracing_subscriber;
-
 /// Initialize the Synapse system with logging (not available on WASM)
+///
+/// Configured from the environment: `RUST_LOG` for filtering,
+/// `SYNAPSE_LOG_FORMAT=json` for JSON lines and `SYNAPSE_TRACE_SAMPLE_RATE`
+/// for sampled message traces. Does nothing if logging is already set up.
 #[cfg(not(target_arch = "wasm32"))]
 pub fn init_logging() {
-    tracing_subscriber::fmt::init();
+    let _ = logging::init(logging::LoggingOptions::from_env());
 }
 
-/// Current protocol version
+/// Initialize logging with explicit options, returning the sampled message traces
+#[cfg(not(target_arch = "wasm32"))]
+pub fn init_logging_with(options: logging::LoggingOptions) -> Result<logging::MessageTraces, SynapseError> {
+    logging::init(options)
+}
+
+/// Current protocol version; see [`protocol`] for negotiation
 pub const PROTOCOL_VERSION: &str = "1.0.0";
 
+/// Features, transports and storage backends compiled into this binary
+pub fn capabilities() -> capabilities::CapabilityReport {
+    capabilities::CapabilityReport::current()
+}
+
 /// Standard email headers for Synapse
 pub mod headers {
     pub const VERSION: &str = "X-Synapse-Version";
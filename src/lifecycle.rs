@@ -0,0 +1,304 @@
+//! # Participant Lifecycle
+//!
+//! A participant is not always simply present or absent. Registries and the
+//! participants themselves publish signed [`LifecycleNotice`]s that move an
+//! identity between states:
+//!
+//! - **Suspended**: temporarily undeliverable; sends fail with
+//!   [`SynapseError::ParticipantSuspended`] until the suspension ends or the
+//!   participant is reinstated.
+//! - **Deprecated**: replaced by a successor identity; sends are forwarded to
+//!   the successor.
+//! - **Deleted**: erased on request. The notice is a tombstone that peers
+//!   keep after dropping their cached contact so the identity is not
+//!   resolved or re-registered again; sends fail with
+//!   [`SynapseError::ParticipantDeleted`].
+//!
+//! Notices travel between routers as system messages; a router only applies
+//! one whose signature checks out against a trusted issuer or the
+//! participant's own key.
+
+use crate::crypto::CryptoManager;
+use crate::error::{Result, SynapseError};
+use crate::identity::LocalIdentity;
+use crate::synapse::blockchain::serialization::DateTimeWrapper;
+use crate::types::{MessageType, SimpleMessage};
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Metadata key marking a message as a lifecycle notice (the state name)
+pub const LIFECYCLE_KEY: &str = "lifecycle";
+
+/// Longest chain of deprecations followed before giving up
+pub const MAX_FORWARDS: usize = 8;
+
+/// Where a participant is in its lifecycle
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum LifecycleState {
+    #[default]
+    Active,
+    /// Temporarily undeliverable, until `until` if set
+    Suspended {
+        reason: String,
+        #[serde(default)]
+        until: Option<DateTimeWrapper>,
+    },
+    /// Replaced by `successor`, which receives forwarded messages
+    Deprecated { successor: String },
+    /// Erased; the notice is kept as a tombstone
+    Deleted { reason: String },
+}
+
+impl LifecycleState {
+    /// Name used on the wire and in events
+    pub fn name(&self) -> &'static str {
+        match self {
+            LifecycleState::Active => "active",
+            LifecycleState::Suspended { .. } => "suspended",
+            LifecycleState::Deprecated { .. } => "deprecated",
+            LifecycleState::Deleted { .. } => "deleted",
+        }
+    }
+
+    /// Whether a suspension has run out
+    fn has_lapsed(&self) -> bool {
+        matches!(self, LifecycleState::Suspended { until: Some(until), .. } if until.0 <= Utc::now())
+    }
+}
+
+/// Signed statement that a participant changed lifecycle state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifecycleNotice {
+    /// Participant the notice is about
+    pub global_id: String,
+    /// The new state
+    pub state: LifecycleState,
+    /// Global ID of the registry or participant that issued the notice
+    pub issuer: String,
+    /// When the notice was issued; later notices supersede earlier ones
+    pub issued_at: DateTimeWrapper,
+    /// Signature over [`LifecycleNotice::signing_payload`] with the issuer's key
+    pub signature: Vec<u8>,
+}
+
+/// A deletion notice
+pub type Tombstone = LifecycleNotice;
+
+impl LifecycleNotice {
+    /// Create an unsigned notice; set `signature` before publishing
+    pub fn new(issuer: impl Into<String>, global_id: impl Into<String>, state: LifecycleState) -> Self {
+        Self {
+            global_id: global_id.into(),
+            state,
+            issuer: issuer.into(),
+            issued_at: DateTimeWrapper::new(Utc::now()),
+            signature: Vec::new(),
+        }
+    }
+
+    /// Create and sign a notice on behalf of a local identity
+    pub fn sign(issuer: &LocalIdentity, global_id: impl Into<String>, state: LifecycleState) -> Result<Self> {
+        let mut notice = Self::new(issuer.global_id.clone(), global_id, state);
+        notice.signature = issuer.sign(&notice.signing_payload())?;
+        Ok(notice)
+    }
+
+    /// Canonical string covered by the signature
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "synapse-lifecycle:{}:{}:{}:{}",
+            self.issuer,
+            self.global_id,
+            self.issued_at.0.timestamp(),
+            serde_json::to_string(&self.state).unwrap_or_default()
+        )
+    }
+
+    /// Verify the notice against the issuer's known public key
+    pub fn verify(&self, public_key_pem: &str) -> Result<bool> {
+        let mut crypto = CryptoManager::new();
+        crypto.import_public_key(&self.issuer, public_key_pem)?;
+        crypto.verify_signature(&self.signing_payload(), &self.signature, &self.issuer)
+    }
+
+    /// Whether this notice records a deletion
+    pub fn is_tombstone(&self) -> bool {
+        matches!(self.state, LifecycleState::Deleted { .. })
+    }
+
+    /// Wrap the notice in a system message to `to_entity`
+    pub fn to_message(&self, to_entity: &str) -> Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(LIFECYCLE_KEY.to_string(), self.state.name().to_string());
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: self.issuer.clone(),
+            content: serde_json::to_string(self)?,
+            message_type: MessageType::System,
+            metadata,
+        })
+    }
+
+    /// Extract the notice from a message, if it carries one
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        if message.message_type != MessageType::System || !message.metadata.contains_key(LIFECYCLE_KEY) {
+            return None;
+        }
+        serde_json::from_str(&message.content).ok()
+    }
+}
+
+/// Lifecycle states known to this router, with the issuers it trusts
+#[derive(Debug, Default)]
+pub struct LifecycleRegistry {
+    /// Global ID -> latest notice
+    notices: DashMap<String, LifecycleNotice>,
+    /// Issuer global ID -> public key PEM
+    issuers: DashMap<String, String>,
+}
+
+impl LifecycleRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept notices signed by `issuer` (typically a registry) for any participant
+    pub fn trust_issuer(&self, issuer: impl Into<String>, public_key_pem: impl Into<String>) {
+        self.issuers.insert(issuer.into(), public_key_pem.into());
+    }
+
+    /// Public key of a trusted issuer
+    pub fn issuer_key(&self, issuer: &str) -> Option<String> {
+        self.issuers.get(issuer).map(|key| key.clone())
+    }
+
+    /// Record a verified notice. Returns false if it was ignored because a
+    /// newer notice is already known or the participant is tombstoned.
+    pub fn apply(&self, notice: LifecycleNotice) -> bool {
+        if let Some(existing) = self.notices.get(&notice.global_id) {
+            if existing.is_tombstone() {
+                debug!("Ignoring {} notice for tombstoned {}", notice.state.name(), notice.global_id);
+                return false;
+            }
+            if existing.issued_at.0 > notice.issued_at.0 {
+                return false;
+            }
+        }
+        if notice.state == LifecycleState::Active {
+            self.notices.remove(&notice.global_id);
+        } else {
+            self.notices.insert(notice.global_id.clone(), notice);
+        }
+        true
+    }
+
+    /// Current state of a participant; unknown participants are active
+    pub fn state(&self, global_id: &str) -> LifecycleState {
+        match self.notices.get(global_id) {
+            Some(notice) if !notice.state.has_lapsed() => notice.state.clone(),
+            _ => LifecycleState::Active,
+        }
+    }
+
+    /// Latest notice about a participant
+    pub fn notice(&self, global_id: &str) -> Option<LifecycleNotice> {
+        self.notices.get(global_id).map(|notice| notice.clone())
+    }
+
+    /// All known tombstones, e.g. to pass on to a peer
+    pub fn tombstones(&self) -> Vec<Tombstone> {
+        self.notices.iter().filter(|n| n.is_tombstone()).map(|n| n.clone()).collect()
+    }
+
+    /// Where a message addressed to `global_id` should go
+    ///
+    /// Follows deprecations to the newest successor and fails for suspended
+    /// or deleted participants.
+    pub fn check_recipient(&self, global_id: &str) -> Result<String> {
+        let mut current = global_id.to_string();
+        for _ in 0..=MAX_FORWARDS {
+            match self.state(&current) {
+                LifecycleState::Active => return Ok(current),
+                LifecycleState::Suspended { reason, .. } => {
+                    return Err(SynapseError::ParticipantSuspended { participant: current, reason });
+                }
+                LifecycleState::Deleted { .. } => return Err(SynapseError::ParticipantDeleted(current)),
+                LifecycleState::Deprecated { successor } => {
+                    debug!("{} is deprecated, forwarding to {}", current, successor);
+                    current = successor;
+                }
+            }
+        }
+        Err(SynapseError::RoutingError(format!(
+            "More than {} successors chained from {}",
+            MAX_FORWARDS, global_id
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::TransportBindings;
+    use chrono::Duration;
+
+    fn notice(global_id: &str, state: LifecycleState) -> LifecycleNotice {
+        LifecycleNotice::new("registry@example.com", global_id, state)
+    }
+
+    #[test]
+    fn test_check_recipient_follows_and_rejects() {
+        let lifecycle = LifecycleRegistry::new();
+        lifecycle.apply(notice("old@example.com", LifecycleState::Deprecated { successor: "mid@example.com".into() }));
+        lifecycle.apply(notice("mid@example.com", LifecycleState::Deprecated { successor: "new@example.com".into() }));
+        assert_eq!(lifecycle.check_recipient("old@example.com").unwrap(), "new@example.com");
+
+        lifecycle.apply(notice("new@example.com", LifecycleState::Suspended { reason: "abuse".into(), until: None }));
+        assert!(matches!(
+            lifecycle.check_recipient("old@example.com"),
+            Err(SynapseError::ParticipantSuspended { ref participant, .. }) if participant == "new@example.com"
+        ));
+
+        lifecycle.apply(notice("gone@example.com", LifecycleState::Deleted { reason: "erasure request".into() }));
+        assert!(matches!(lifecycle.check_recipient("gone@example.com"), Err(SynapseError::ParticipantDeleted(_))));
+
+        lifecycle.apply(notice("loop@example.com", LifecycleState::Deprecated { successor: "loop@example.com".into() }));
+        assert!(lifecycle.check_recipient("loop@example.com").is_err());
+    }
+
+    #[test]
+    fn test_lapsed_suspension_and_tombstones_are_final() {
+        let lifecycle = LifecycleRegistry::new();
+        let until = DateTimeWrapper::new(Utc::now() - Duration::minutes(1));
+        lifecycle.apply(notice("bob@example.com", LifecycleState::Suspended { reason: "cool-off".into(), until: Some(until) }));
+        assert_eq!(lifecycle.state("bob@example.com"), LifecycleState::Active);
+
+        lifecycle.apply(notice("bob@example.com", LifecycleState::Deleted { reason: "erasure request".into() }));
+        assert!(!lifecycle.apply(notice("bob@example.com", LifecycleState::Active)));
+        assert_eq!(lifecycle.tombstones().len(), 1);
+    }
+
+    #[test]
+    fn test_signed_notice_round_trip() {
+        let registry = LocalIdentity::new("registry", "registry@example.com", TransportBindings::default());
+        let public_key = registry.generate_keypair().unwrap();
+        let tombstone = LifecycleNotice::sign(
+            &registry,
+            "alice@example.com",
+            LifecycleState::Deleted { reason: "erasure request".into() },
+        )
+        .unwrap();
+
+        let received = LifecycleNotice::from_message(&tombstone.to_message("bob@example.com").unwrap()).unwrap();
+        assert!(received.is_tombstone());
+        assert!(received.verify(&public_key).unwrap());
+
+        let mut forged = received.clone();
+        forged.global_id = "carol@example.com".to_string();
+        assert!(!forged.verify(&public_key).unwrap_or(false));
+    }
+}
@@ -0,0 +1,382 @@
+//! # Resource Limits
+//!
+//! A node on a Raspberry Pi runs out of memory long before a busy network
+//! runs out of peers. The [`ResourceGovernor`] caps what the router holds on
+//! behalf of others, as set in [`ResourceLimitsConfig`]:
+//!
+//! - **Peers**: [`ResourceGovernor::admit_peer`] tracks who the node keeps
+//!   routing, session and capability state for. When the table is full the
+//!   least recently heard-from peer is evicted, or the newcomer refused with
+//!   [`PeerEviction::RejectNew`].
+//! - **Queued bytes**: received messages waiting to be read hold a
+//!   [`QueueReservation`]; messages that do not fit are dropped.
+//! - **Crypto**: encryption, decryption, signing and verification wait for a
+//!   [`CryptoSlot`], so a flood cannot pin every core.
+//! - **Connections**: inbound connections hold a [`ConnectionSlot`] and are
+//!   refused beyond the cap.
+//!
+//! Every limit hit is counted in [`ResourceUsage`] and published as
+//! [`RouterEvent::ResourceLimitHit`].
+
+use crate::config::{PeerEviction, ResourceLimitsConfig};
+use crate::events::{EventBus, RouterEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, warn};
+
+/// A resource the governor caps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Peers,
+    QueuedBytes,
+    Crypto,
+    Connections,
+}
+
+impl Resource {
+    const ALL: [Resource; 4] = [Resource::Peers, Resource::QueuedBytes, Resource::Crypto, Resource::Connections];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resource::Peers => "peers",
+            Resource::QueuedBytes => "queued_bytes",
+            Resource::Crypto => "crypto",
+            Resource::Connections => "connections",
+        }
+    }
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Outcome of [`ResourceGovernor::admit_peer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAdmission {
+    /// The peer was already tracked
+    Known,
+    /// The peer is now tracked; `evicted` was forgotten to make room
+    Admitted { evicted: Option<String> },
+    /// The table is full and the policy keeps the peers it has
+    Rejected,
+}
+
+/// Current use of each resource, and how often each limit was hit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub peers: usize,
+    pub queued_bytes: u64,
+    pub crypto_in_use: usize,
+    pub connections: usize,
+    /// Limit hits since startup, by resource name
+    pub limit_hits: BTreeMap<String, u64>,
+}
+
+/// Peers by last activity, for least-recently-used eviction
+#[derive(Debug, Default)]
+struct PeerTable {
+    last_seen: HashMap<String, u64>,
+    by_age: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl PeerTable {
+    fn touch(&mut self, peer: &str) -> bool {
+        self.clock += 1;
+        let known = match self.last_seen.insert(peer.to_string(), self.clock) {
+            Some(previous) => {
+                self.by_age.remove(&previous);
+                true
+            }
+            None => false,
+        };
+        self.by_age.insert(self.clock, peer.to_string());
+        known
+    }
+
+    fn evict_oldest(&mut self) -> Option<String> {
+        let (_, peer) = self.by_age.pop_first()?;
+        self.last_seen.remove(&peer);
+        Some(peer)
+    }
+
+    fn remove(&mut self, peer: &str) -> bool {
+        match self.last_seen.remove(peer) {
+            Some(tick) => self.by_age.remove(&tick).is_some(),
+            None => false,
+        }
+    }
+}
+
+/// Enforces [`ResourceLimitsConfig`] for one node
+///
+/// Shared between the router and its transports; see the module docs.
+#[derive(Debug)]
+pub struct ResourceGovernor {
+    config: ResourceLimitsConfig,
+    peers: Mutex<PeerTable>,
+    queued_bytes: Arc<AtomicU64>,
+    crypto: Arc<Semaphore>,
+    crypto_slots: usize,
+    connections: Arc<AtomicUsize>,
+    hits: [AtomicU64; 4],
+    events: EventBus,
+}
+
+impl ResourceGovernor {
+    pub fn new(config: &ResourceLimitsConfig) -> Self {
+        let crypto_slots = match config.max_concurrent_crypto {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            slots => slots,
+        };
+        Self {
+            config: config.clone(),
+            peers: Mutex::new(PeerTable::default()),
+            queued_bytes: Arc::new(AtomicU64::new(0)),
+            crypto: Arc::new(Semaphore::new(crypto_slots)),
+            crypto_slots,
+            connections: Arc::new(AtomicUsize::new(0)),
+            hits: Default::default(),
+            events: EventBus::default(),
+        }
+    }
+
+    /// Governor without limits, for components created without a configuration
+    pub fn unlimited() -> Self {
+        Self::new(&ResourceLimitsConfig {
+            max_peers: 0,
+            peer_eviction: PeerEviction::LeastRecentlyUsed,
+            max_queued_bytes: 0,
+            max_concurrent_crypto: 0,
+            max_connections: 0,
+        })
+    }
+
+    /// Publish limit hits to `events`, typically the router's bus
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn config(&self) -> &ResourceLimitsConfig {
+        &self.config
+    }
+
+    /// Note activity from `peer`, making room for it if it is new
+    pub fn admit_peer(&self, peer: &str) -> PeerAdmission {
+        let mut peers = self.peers.lock().unwrap();
+        let max = self.config.max_peers;
+        if max == 0 || peers.last_seen.contains_key(peer) || peers.last_seen.len() < max {
+            return match peers.touch(peer) {
+                true => PeerAdmission::Known,
+                false => PeerAdmission::Admitted { evicted: None },
+            };
+        }
+        match self.config.peer_eviction {
+            PeerEviction::RejectNew => {
+                drop(peers);
+                self.hit(Resource::Peers, max as u64, format!("refused {}", peer));
+                PeerAdmission::Rejected
+            }
+            PeerEviction::LeastRecentlyUsed => {
+                let evicted = peers.evict_oldest();
+                peers.touch(peer);
+                drop(peers);
+                if let Some(ref evicted) = evicted {
+                    self.hit(Resource::Peers, max as u64, format!("evicted {}", evicted));
+                }
+                PeerAdmission::Admitted { evicted }
+            }
+        }
+    }
+
+    /// Stop tracking `peer`, e.g. after it was purged
+    pub fn forget_peer(&self, peer: &str) -> bool {
+        self.peers.lock().unwrap().remove(peer)
+    }
+
+    /// Reserve room for `bytes` of queued messages, or `None` if it would
+    /// exceed the limit
+    pub fn try_reserve_bytes(&self, bytes: u64) -> Option<QueueReservation> {
+        let max = self.config.max_queued_bytes;
+        let reserved = self.queued_bytes.fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| {
+            let total = queued.saturating_add(bytes);
+            (max == 0 || total <= max).then_some(total)
+        });
+        match reserved {
+            Ok(_) => Some(QueueReservation {
+                bytes,
+                queued: Arc::clone(&self.queued_bytes),
+            }),
+            Err(_) => {
+                self.hit(Resource::QueuedBytes, max, format!("dropped {} bytes", bytes));
+                None
+            }
+        }
+    }
+
+    /// Wait for a turn to run a cryptographic operation
+    pub async fn crypto_slot(&self) -> CryptoSlot {
+        let permit = match Arc::clone(&self.crypto).try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.hit(Resource::Crypto, self.crypto_slots as u64, "waited".to_string());
+                Arc::clone(&self.crypto)
+                    .acquire_owned()
+                    .await
+                    .expect("crypto semaphore is never closed")
+            }
+        };
+        CryptoSlot { _permit: permit }
+    }
+
+    /// Cryptographic operations allowed at once
+    pub fn crypto_parallelism(&self) -> usize {
+        self.crypto_slots
+    }
+
+    /// Take a connection slot, or `None` if the limit is reached
+    pub fn try_connection(&self) -> Option<ConnectionSlot> {
+        let max = self.config.max_connections;
+        let taken = self.connections.fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+            (max == 0 || open < max).then_some(open + 1)
+        });
+        match taken {
+            Ok(_) => Some(ConnectionSlot {
+                connections: Arc::clone(&self.connections),
+            }),
+            Err(_) => {
+                self.hit(Resource::Connections, max as u64, "refused connection".to_string());
+                None
+            }
+        }
+    }
+
+    pub fn usage(&self) -> ResourceUsage {
+        ResourceUsage {
+            peers: self.peers.lock().unwrap().last_seen.len(),
+            queued_bytes: self.queued_bytes.load(Ordering::Acquire),
+            crypto_in_use: self.crypto_slots.saturating_sub(self.crypto.available_permits()),
+            connections: self.connections.load(Ordering::Acquire),
+            limit_hits: Resource::ALL
+                .iter()
+                .map(|resource| (resource.name().to_string(), self.hits[*resource as usize].load(Ordering::Relaxed)))
+                .collect(),
+        }
+    }
+
+    fn hit(&self, resource: Resource, limit: u64, action: String) {
+        if self.hits[resource as usize].fetch_add(1, Ordering::Relaxed) == 0 {
+            warn!("Resource limit reached: {} (limit {}), {}", resource, limit, action);
+        } else {
+            debug!("Resource limit reached: {} (limit {}), {}", resource, limit, action);
+        }
+        self.events.publish(RouterEvent::ResourceLimitHit {
+            resource: resource.name().to_string(),
+            limit,
+            action,
+        });
+    }
+}
+
+/// Bytes of a queued message counted against `max_queued_bytes` until dropped
+#[derive(Debug)]
+pub struct QueueReservation {
+    bytes: u64,
+    queued: Arc<AtomicU64>,
+}
+
+impl QueueReservation {
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl Drop for QueueReservation {
+    fn drop(&mut self) {
+        self.queued.fetch_sub(self.bytes, Ordering::AcqRel);
+    }
+}
+
+/// Turn to run a cryptographic operation, returned when dropped
+#[derive(Debug)]
+pub struct CryptoSlot {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// An open inbound connection, counted until dropped
+#[derive(Debug)]
+pub struct ConnectionSlot {
+    connections: Arc<AtomicUsize>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn limits_are_enforced_and_reported() {
+        let events = EventBus::default();
+        let mut hits = events.subscribe();
+        let governor = ResourceGovernor::new(&ResourceLimitsConfig {
+            max_peers: 2,
+            peer_eviction: PeerEviction::LeastRecentlyUsed,
+            max_queued_bytes: 100,
+            max_concurrent_crypto: 1,
+            max_connections: 1,
+        })
+        .with_event_bus(events);
+
+        // The least recently heard-from peer makes room
+        assert_eq!(governor.admit_peer("alice"), PeerAdmission::Admitted { evicted: None });
+        governor.admit_peer("bob");
+        assert_eq!(governor.admit_peer("alice"), PeerAdmission::Known);
+        assert_eq!(governor.admit_peer("carol"), PeerAdmission::Admitted { evicted: Some("bob".to_string()) });
+        let event = hits.recv().await.unwrap().event;
+        assert!(matches!(event, RouterEvent::ResourceLimitHit { ref resource, limit: 2, .. } if resource == "peers"));
+
+        // Queued bytes are returned when the message is dropped
+        let first = governor.try_reserve_bytes(60).unwrap();
+        assert!(governor.try_reserve_bytes(60).is_none());
+        drop(first);
+        assert!(governor.try_reserve_bytes(60).is_some());
+
+        let connection = governor.try_connection().unwrap();
+        assert!(governor.try_connection().is_none());
+        drop(connection);
+        assert!(governor.try_connection().is_some());
+
+        let slot = governor.crypto_slot().await;
+        assert_eq!(governor.usage().crypto_in_use, 1);
+        drop(slot);
+
+        let usage = governor.usage();
+        assert_eq!(usage.peers, 2);
+        assert_eq!(usage.queued_bytes, 0);
+        assert_eq!(usage.limit_hits["peers"], 1);
+        assert_eq!(usage.limit_hits["queued_bytes"], 1);
+        assert_eq!(usage.limit_hits["connections"], 1);
+        assert_eq!(usage.limit_hits["crypto"], 0);
+
+        let strict = ResourceGovernor::new(&ResourceLimitsConfig {
+            max_peers: 1,
+            peer_eviction: PeerEviction::RejectNew,
+            ..ResourceLimitsConfig::default()
+        });
+        strict.admit_peer("alice");
+        assert_eq!(strict.admit_peer("bob"), PeerAdmission::Rejected);
+        assert_eq!(strict.admit_peer("alice"), PeerAdmission::Known);
+    }
+}
@@ -0,0 +1,430 @@
+//! # Structured Logging and Message Traces
+//!
+//! Work done for a single message (sending, receiving, delivering to a local
+//! identity) runs inside a `message` span carrying the message ID, the peer and
+//! the direction. Every log line emitted along the way is tagged with those
+//! fields, so one `grep` or log query follows a message end to end.
+//!
+//! [`init`] installs the subscriber, either human-readable or as JSON lines for
+//! log shippers, and can add a [`MessageTraceLayer`]. The layer samples message
+//! spans and keeps their timeline (the log lines, their levels and timings) in
+//! memory for inspection. Sampling is decided by hashing the message ID, so with
+//! the same rate both ends of a conversation trace the same messages.
+//!
+//! Traces never contain message content: only the text of log lines is kept,
+//! and the send/receive path does not log payloads. Log output and recorded
+//! lines are scrubbed of credentials with [`crate::redaction::scrub`].
+
+use tracing::Span;
+
+/// Name of the span wrapping the handling of one message
+pub const MESSAGE_SPAN: &str = "message";
+
+/// Span for handling one message; log lines inside it carry its ID and peer
+pub fn message_span(message_id: &str, peer: &str, direction: &'static str) -> Span {
+    tracing::info_span!("message", message_id = %message_id, peer = %peer, direction = direction)
+}
+
+/// The current span if it already handles a message, otherwise a new [`message_span`]
+///
+/// Lets inner layers (e.g. the email router under the enhanced router) join
+/// the caller's span instead of nesting a second one for the same message.
+pub fn current_or_message_span(message_id: &str, peer: &str, direction: &'static str) -> Span {
+    let current = Span::current();
+    if current.metadata().is_some_and(|m| m.name() == MESSAGE_SPAN) {
+        current
+    } else {
+        message_span(message_id, peer, direction)
+    }
+}
+
+/// Stable sampling decision for a message ID (FNV-1a, independent of Rust version)
+pub fn is_sampled(message_id: &str, sample_rate: f64) -> bool {
+    if sample_rate >= 1.0 {
+        return true;
+    }
+    if sample_rate <= 0.0 {
+        return false;
+    }
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in message_id.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    (hash % 10_000) < (sample_rate * 10_000.0) as u64
+}
+
+#[cfg(feature = "telemetry")]
+pub use subscriber::*;
+
+#[cfg(feature = "telemetry")]
+mod subscriber {
+    use super::{is_sampled, MESSAGE_SPAN};
+    use crate::error::{Result, SynapseError};
+    use crate::redaction::{scrub, RedactingWriter};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::collections::VecDeque;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::EnvFilter;
+
+    /// Output format of log lines
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum LogFormat {
+        /// Human-readable lines with span context
+        #[default]
+        Text,
+        /// One JSON object per line, with the current span's fields
+        Json,
+    }
+
+    /// Options for [`init`]
+    #[derive(Debug, Clone)]
+    pub struct LoggingOptions {
+        pub format: LogFormat,
+        /// `EnvFilter` directives, e.g. `info,synapse::transport=debug`
+        pub filter: String,
+        /// Fraction of messages traced (0.0 disables tracing)
+        pub trace_sample_rate: f64,
+        /// Completed traces kept in memory
+        pub trace_capacity: usize,
+        /// Log lines kept per trace
+        pub max_trace_steps: usize,
+    }
+
+    impl Default for LoggingOptions {
+        fn default() -> Self {
+            Self {
+                format: LogFormat::Text,
+                filter: "info".to_string(),
+                trace_sample_rate: 0.0,
+                trace_capacity: 256,
+                max_trace_steps: 64,
+            }
+        }
+    }
+
+    impl LoggingOptions {
+        /// Defaults overridden by `RUST_LOG`, `SYNAPSE_LOG_FORMAT` (`text`/`json`)
+        /// and `SYNAPSE_TRACE_SAMPLE_RATE`
+        pub fn from_env() -> Self {
+            let mut options = Self::default();
+            if let Ok(filter) = std::env::var("RUST_LOG") {
+                options.filter = filter;
+            }
+            if let Ok(format) = std::env::var("SYNAPSE_LOG_FORMAT") {
+                options.format = match format.to_ascii_lowercase().as_str() {
+                    "json" => LogFormat::Json,
+                    _ => LogFormat::Text,
+                };
+            }
+            if let Some(rate) = std::env::var("SYNAPSE_TRACE_SAMPLE_RATE").ok().and_then(|r| r.parse().ok()) {
+                options.trace_sample_rate = rate;
+            }
+            options
+        }
+    }
+
+    /// Install the global subscriber, returning the store sampled traces go to
+    pub fn init(options: LoggingOptions) -> Result<MessageTraces> {
+        let filter = EnvFilter::try_new(&options.filter)
+            .map_err(|e| SynapseError::ConfigurationError(format!("Invalid log filter {}: {}", options.filter, e)))?;
+        let traces = MessageTraces::new(options.trace_capacity);
+        let trace_layer = (options.trace_sample_rate > 0.0).then(|| {
+            MessageTraceLayer::new(options.trace_sample_rate, traces.clone()).with_max_steps(options.max_trace_steps)
+        });
+
+        let registry = tracing_subscriber::registry().with(filter).with(trace_layer);
+        let installed = match options.format {
+            LogFormat::Text => registry
+                .with(tracing_subscriber::fmt::layer().with_writer(|| RedactingWriter(std::io::stdout())))
+                .try_init(),
+            LogFormat::Json => registry
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .json()
+                        .with_current_span(true)
+                        .with_span_list(false)
+                        .with_writer(|| RedactingWriter(std::io::stdout())),
+                )
+                .try_init(),
+        };
+        installed.map_err(|e| SynapseError::ConfigurationError(format!("Logging already initialized: {}", e)))?;
+        Ok(traces)
+    }
+
+    /// One log line inside a traced message span
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct TraceStep {
+        /// Time since the span was opened
+        pub offset: Duration,
+        pub level: String,
+        pub target: String,
+        pub message: String,
+    }
+
+    /// Payload-free timeline of one message's handling
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct MessageTrace {
+        pub message_id: String,
+        pub peer: String,
+        pub direction: String,
+        pub started_at: DateTime<Utc>,
+        pub duration: Duration,
+        pub steps: Vec<TraceStep>,
+        /// Steps beyond the per-trace limit were dropped
+        pub truncated: bool,
+    }
+
+    /// Bounded store of completed traces, shared with the layer
+    #[derive(Debug, Clone)]
+    pub struct MessageTraces {
+        inner: Arc<Mutex<VecDeque<MessageTrace>>>,
+        capacity: usize,
+    }
+
+    impl MessageTraces {
+        pub fn new(capacity: usize) -> Self {
+            Self {
+                inner: Arc::new(Mutex::new(VecDeque::with_capacity(capacity.min(1024)))),
+                capacity: capacity.max(1),
+            }
+        }
+
+        fn push(&self, trace: MessageTrace) {
+            let mut traces = self.inner.lock().unwrap();
+            traces.push_back(trace);
+            while traces.len() > self.capacity {
+                traces.pop_front();
+            }
+        }
+
+        /// Completed traces, oldest first
+        pub fn recent(&self) -> Vec<MessageTrace> {
+            self.inner.lock().unwrap().iter().cloned().collect()
+        }
+
+        /// Traces for a message ID (a message can be traced once per direction)
+        pub fn find(&self, message_id: &str) -> Vec<MessageTrace> {
+            self.inner
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|t| t.message_id == message_id)
+                .cloned()
+                .collect()
+        }
+
+        pub fn clear(&self) {
+            self.inner.lock().unwrap().clear();
+        }
+    }
+
+    /// `tracing` layer recording sampled message spans into [`MessageTraces`]
+    pub struct MessageTraceLayer {
+        sample_rate: f64,
+        max_steps: usize,
+        traces: MessageTraces,
+    }
+
+    impl MessageTraceLayer {
+        pub fn new(sample_rate: f64, traces: MessageTraces) -> Self {
+            Self { sample_rate, max_steps: 64, traces }
+        }
+
+        pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+            self.max_steps = max_steps;
+            self
+        }
+    }
+
+    /// Trace under construction, stored in the span's extensions
+    struct ActiveTrace {
+        started: Instant,
+        trace: MessageTrace,
+    }
+
+    #[derive(Default)]
+    struct SpanFields {
+        message_id: Option<String>,
+        peer: Option<String>,
+        direction: Option<String>,
+    }
+
+    impl Visit for SpanFields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.set(field, value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.set(field, format!("{:?}", value));
+        }
+    }
+
+    impl SpanFields {
+        fn set(&mut self, field: &Field, value: String) {
+            match field.name() {
+                "message_id" => self.message_id = Some(value),
+                "peer" => self.peer = Some(value),
+                "direction" => self.direction = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    /// Extracts only the formatted log message; other event fields are ignored
+    #[derive(Default)]
+    struct EventMessage(String);
+
+    impl Visit for EventMessage {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "message" {
+                self.0 = value.to_string();
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                self.0 = format!("{:?}", value);
+            }
+        }
+    }
+
+    impl<S> Layer<S> for MessageTraceLayer
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            if attrs.metadata().name() != MESSAGE_SPAN {
+                return;
+            }
+            let mut fields = SpanFields::default();
+            attrs.record(&mut fields);
+            let message_id = fields.message_id.unwrap_or_default();
+            if !is_sampled(&message_id, self.sample_rate) {
+                return;
+            }
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(ActiveTrace {
+                    started: Instant::now(),
+                    trace: MessageTrace {
+                        message_id,
+                        peer: fields.peer.unwrap_or_default(),
+                        direction: fields.direction.unwrap_or_default(),
+                        started_at: Utc::now(),
+                        duration: Duration::ZERO,
+                        steps: Vec::new(),
+                        truncated: false,
+                    },
+                });
+            }
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            let Some(span) = ctx.span(id) else { return };
+            let mut extensions = span.extensions_mut();
+            let Some(active) = extensions.get_mut::<ActiveTrace>() else { return };
+            let mut fields = SpanFields::default();
+            values.record(&mut fields);
+            if let Some(peer) = fields.peer {
+                active.trace.peer = peer;
+            }
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            let Some(scope) = ctx.event_scope(event) else { return };
+            for span in scope {
+                if span.name() != MESSAGE_SPAN {
+                    continue;
+                }
+                let mut extensions = span.extensions_mut();
+                if let Some(active) = extensions.get_mut::<ActiveTrace>() {
+                    if active.trace.steps.len() >= self.max_steps {
+                        active.trace.truncated = true;
+                    } else {
+                        let mut message = EventMessage::default();
+                        event.record(&mut message);
+                        let metadata = event.metadata();
+                        active.trace.steps.push(TraceStep {
+                            offset: active.started.elapsed(),
+                            level: metadata.level().to_string(),
+                            target: metadata.target().to_string(),
+                            message: scrub(&message.0).into_owned(),
+                        });
+                    }
+                }
+                // Only the innermost message span records the event
+                break;
+            }
+        }
+
+        fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+            let Some(span) = ctx.span(&id) else { return };
+            let active = span.extensions_mut().remove::<ActiveTrace>();
+            if let Some(mut active) = active {
+                active.trace.duration = active.started.elapsed();
+                self.traces.push(active.trace);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::message_span;
+        use super::*;
+
+        #[test]
+        fn test_traces_sampled_spans_without_payload_fields() {
+            let traces = MessageTraces::new(8);
+            let subscriber = tracing_subscriber::registry().with(MessageTraceLayer::new(1.0, traces.clone()));
+            tracing::subscriber::with_default(subscriber, || {
+                let _span = message_span("msg-1", "bob@example.com", "send").entered();
+                tracing::info!(content = "secret payload", "Routing via {}", "tcp");
+                tracing::debug!("Delivered");
+            });
+
+            let found = traces.find("msg-1");
+            assert_eq!(found.len(), 1);
+            let trace = &found[0];
+            assert_eq!(trace.peer, "bob@example.com");
+            assert_eq!(trace.direction, "send");
+            assert_eq!(trace.steps.len(), 2);
+            assert_eq!(trace.steps[0].message, "Routing via tcp");
+            assert!(!serde_json::to_string(trace).unwrap().contains("secret payload"));
+        }
+
+        #[test]
+        fn test_unsampled_spans_are_not_kept() {
+            let traces = MessageTraces::new(8);
+            let subscriber = tracing_subscriber::registry().with(MessageTraceLayer::new(0.0, traces.clone()));
+            tracing::subscriber::with_default(subscriber, || {
+                let _span = message_span("msg-2", "carol", "receive").entered();
+                tracing::info!("Received");
+            });
+            assert!(traces.recent().is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_is_stable_per_message() {
+        assert!(is_sampled("anything", 1.0));
+        assert!(!is_sampled("anything", 0.0));
+        let sampled = (0..1000).filter(|i| is_sampled(&format!("msg-{}", i), 0.25)).count();
+        assert!((150..350).contains(&sampled), "sampled {}", sampled);
+        assert_eq!(is_sampled("msg-42", 0.5), is_sampled("msg-42", 0.5));
+    }
+}
@@ -0,0 +1,353 @@
+//! Mailing-list style broadcasts to email-only recipients
+//!
+//! A broadcast to hundreds of plain email recipients is sent the way a
+//! mailing list would send it: one message per batch of recipients, who are
+//! listed in Bcc, with `List-Id` and `List-Unsubscribe` headers so mail
+//! clients offer an unsubscribe button. Recipients whose public key we hold
+//! get their own encrypted copy instead. Addresses that unsubscribed, bounced
+//! or complained are kept on a suppression list and skipped, and the
+//! [`BroadcastReport`] says what happened to every recipient.
+
+use crate::error::Result;
+#[cfg(feature = "email")]
+use crate::{config::MailingListConfig, types::SimpleMessage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+use tracing::info;
+
+/// Subject of the unsubscribe mail a `List-Unsubscribe` mailto link sends
+pub const UNSUBSCRIBE_SUBJECT: &str = "unsubscribe";
+
+/// Why an address no longer receives broadcasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressionReason {
+    /// The recipient asked to stop receiving broadcasts
+    Unsubscribed,
+    /// Mail to the address bounced permanently
+    Bounced,
+    /// The recipient reported a broadcast as spam
+    Complained,
+    /// Added by an operator
+    Manual,
+}
+
+/// A suppressed address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Suppression {
+    pub address: String,
+    pub reason: SuppressionReason,
+    pub since: DateTime<Utc>,
+}
+
+/// Addresses broadcasts skip, optionally kept in a JSON file
+pub struct SuppressionList {
+    path: Option<String>,
+    entries: RwLock<HashMap<String, Suppression>>,
+}
+
+impl SuppressionList {
+    /// An empty list kept in memory only
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The list stored at `path`, which is created on the first change
+    pub fn load(path: &str) -> Result<Self> {
+        let mut entries = HashMap::new();
+        if std::path::Path::new(path).exists() {
+            let content = std::fs::read_to_string(path)?;
+            let stored: Vec<Suppression> = serde_json::from_str(&content)?;
+            entries.extend(stored.into_iter().map(|entry| (entry.address.to_lowercase(), entry)));
+        }
+        Ok(Self {
+            path: Some(path.to_string()),
+            entries: RwLock::new(entries),
+        })
+    }
+
+    /// Stop broadcasting to `address`; returns false if it already was suppressed
+    pub fn suppress(&self, address: &str, reason: SuppressionReason) -> Result<bool> {
+        let key = address.trim().to_lowercase();
+        let added = {
+            let mut entries = self.entries.write().unwrap();
+            if entries.contains_key(&key) {
+                false
+            } else {
+                entries.insert(key.clone(), Suppression { address: key, reason, since: Utc::now() });
+                true
+            }
+        };
+        if added {
+            info!("Suppressed {} from email broadcasts ({:?})", address, reason);
+            self.save()?;
+        }
+        Ok(added)
+    }
+
+    /// Resume broadcasting to `address`; returns false if it was not suppressed
+    pub fn allow(&self, address: &str) -> Result<bool> {
+        let removed = self.entries.write().unwrap().remove(&address.trim().to_lowercase()).is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// Why `address` is suppressed, if it is
+    pub fn reason(&self, address: &str) -> Option<SuppressionReason> {
+        self.entries
+            .read()
+            .unwrap()
+            .get(&address.trim().to_lowercase())
+            .map(|entry| entry.reason)
+    }
+
+    pub fn entries(&self) -> Vec<Suppression> {
+        self.entries.read().unwrap().values().cloned().collect()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content = serde_json::to_string_pretty(&self.entries())?;
+        // Write-then-rename so a crash never leaves a truncated list
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+impl Default for SuppressionList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What happened to one broadcast recipient
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RecipientStatus {
+    /// Sent in Bcc with the other recipients of a batch
+    Sent { batch: usize },
+    /// Sent on its own, encrypted to the recipient's key
+    SentEncrypted,
+    /// Skipped because the address is suppressed
+    Suppressed { reason: SuppressionReason },
+    /// Sending failed
+    Failed { error: String },
+}
+
+/// Per-recipient outcome of an email broadcast
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastReport {
+    /// Recipients in the order they were given, duplicates removed
+    pub recipients: Vec<(String, RecipientStatus)>,
+}
+
+impl BroadcastReport {
+    pub fn status(&self, address: &str) -> Option<&RecipientStatus> {
+        self.recipients
+            .iter()
+            .find(|(recipient, _)| recipient.eq_ignore_ascii_case(address))
+            .map(|(_, status)| status)
+    }
+
+    /// Recipients the broadcast was sent to
+    pub fn sent(&self) -> usize {
+        self.count(|status| matches!(status, RecipientStatus::Sent { .. } | RecipientStatus::SentEncrypted))
+    }
+
+    pub fn suppressed(&self) -> usize {
+        self.count(|status| matches!(status, RecipientStatus::Suppressed { .. }))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|status| matches!(status, RecipientStatus::Failed { .. }))
+    }
+
+    /// Set the status of every recipient in `addresses`
+    pub fn mark(&mut self, addresses: &[String], status: RecipientStatus) {
+        for (recipient, current) in self.recipients.iter_mut() {
+            if addresses.iter().any(|address| address == recipient) {
+                *current = status.clone();
+            }
+        }
+    }
+
+    fn count(&self, matches: impl Fn(&RecipientStatus) -> bool) -> usize {
+        self.recipients.iter().filter(|(_, status)| matches(status)).count()
+    }
+}
+
+/// How a broadcast will be sent
+#[derive(Debug, Clone)]
+pub struct BroadcastPlan {
+    /// Bcc batches of recipients without a known key
+    pub batches: Vec<Vec<String>>,
+    /// Recipients getting their own encrypted copy
+    pub encrypted: Vec<String>,
+    /// Suppressed recipients already filled in; the rest start as failed
+    /// until their send succeeds
+    pub report: BroadcastReport,
+}
+
+impl BroadcastPlan {
+    /// Split `recipients` into encrypted copies and Bcc batches of at most
+    /// `batch_size`, skipping suppressed addresses and duplicates
+    pub fn new(
+        recipients: &[String],
+        suppressions: &SuppressionList,
+        batch_size: usize,
+        has_key: impl Fn(&str) -> bool,
+    ) -> Self {
+        let mut seen = HashSet::new();
+        let mut report = BroadcastReport::default();
+        let mut encrypted = Vec::new();
+        let mut plain = Vec::new();
+        for recipient in recipients {
+            let address = recipient.trim().to_lowercase();
+            if !seen.insert(address.clone()) {
+                continue;
+            }
+            let status = match suppressions.reason(&address) {
+                Some(reason) => RecipientStatus::Suppressed { reason },
+                None => {
+                    if has_key(&address) {
+                        encrypted.push(address.clone());
+                    } else {
+                        plain.push(address.clone());
+                    }
+                    RecipientStatus::Failed { error: "not sent".to_string() }
+                }
+            };
+            report.recipients.push((address, status));
+        }
+        let batches = plain.chunks(batch_size.max(1)).map(<[String]>::to_vec).collect();
+        Self { batches, encrypted, report }
+    }
+}
+
+/// The sender of a gatewayed email asking to be unsubscribed, if `message` is one
+///
+/// Unsubscribe requests are mail to `unsubscribe_address`, which must be one
+/// of the email gateway's addresses.
+#[cfg(feature = "email")]
+pub fn unsubscribe_request(message: &SimpleMessage, config: &MailingListConfig) -> Option<String> {
+    use crate::{
+        bridges::{BRIDGE_KEY, BRIDGE_SENDER_KEY},
+        email_server::gateway::EMAIL_BRIDGE,
+    };
+
+    let address = config.unsubscribe_address.as_deref()?;
+    if !message.to.eq_ignore_ascii_case(address)
+        || message.metadata.get(BRIDGE_KEY).map(String::as_str) != Some(EMAIL_BRIDGE)
+    {
+        return None;
+    }
+    message.metadata.get(BRIDGE_SENDER_KEY).cloned()
+}
+
+/// Build one broadcast email; `bcc` recipients are not shown to each other
+///
+/// An `encrypted` body is base64 ciphertext and is marked as such.
+#[cfg(feature = "email")]
+pub fn compose(
+    config: &MailingListConfig,
+    from: &str,
+    bcc: &[String],
+    subject: &str,
+    body: String,
+    encrypted: bool,
+) -> Result<lettre::Message> {
+    use crate::error::SynapseError;
+    use lettre::message::{
+        header::{ContentType, HeaderName, HeaderValue},
+        Mailbox,
+    };
+
+    let mailbox = |address: &str| {
+        address
+            .parse::<Mailbox>()
+            .map_err(|e| SynapseError::InvalidFormat(format!("Invalid address {}: {}", address, e)))
+    };
+    let header = |name: &'static str, value: String| HeaderValue::new(HeaderName::new_from_ascii_str(name), value);
+
+    let sender = mailbox(from)?;
+    // The list addresses itself; recipients only see their own address in the envelope
+    let mut builder = lettre::Message::builder()
+        .from(sender.clone())
+        .to(sender)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .raw_header(header("Precedence", "bulk".to_string()));
+    for address in bcc {
+        builder = builder.bcc(mailbox(address)?);
+    }
+    if encrypted {
+        builder = builder.raw_header(header(crate::headers::ENCRYPTED, "true".to_string()));
+    }
+    if let Some(list_id) = &config.list_id {
+        builder = builder.raw_header(header("List-Id", format!("<{}>", list_id)));
+    }
+    let mut unsubscribe = Vec::new();
+    if let Some(address) = &config.unsubscribe_address {
+        unsubscribe.push(format!("<mailto:{}?subject={}>", address, UNSUBSCRIBE_SUBJECT));
+    }
+    if let Some(url) = &config.unsubscribe_url {
+        unsubscribe.push(format!("<{}>", url));
+        builder = builder.raw_header(header("List-Unsubscribe-Post", "List-Unsubscribe=One-Click".to_string()));
+    }
+    if !unsubscribe.is_empty() {
+        builder = builder.raw_header(header("List-Unsubscribe", unsubscribe.join(", ")));
+    }
+    builder
+        .body(body)
+        .map_err(|e| SynapseError::InvalidFormat(format!("Failed to build broadcast email: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broadcasts_skip_suppressed_and_batch_the_rest() {
+        let suppressions = SuppressionList::new();
+        assert!(suppressions.suppress("Gone@Example.com", SuppressionReason::Unsubscribed).unwrap());
+        assert!(!suppressions.suppress("gone@example.com", SuppressionReason::Bounced).unwrap());
+
+        let recipients: Vec<String> = ["a@example.com", "gone@example.com", "b@example.com", "A@example.com", "key@example.com", "c@example.com"]
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        let plan = BroadcastPlan::new(&recipients, &suppressions, 2, |address| address == "key@example.com");
+
+        assert_eq!(plan.batches, vec![
+            vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            vec!["c@example.com".to_string()],
+        ]);
+        assert_eq!(plan.encrypted, vec!["key@example.com".to_string()]);
+        assert_eq!(plan.report.recipients.len(), 5);
+        assert_eq!(
+            plan.report.status("gone@example.com"),
+            Some(&RecipientStatus::Suppressed { reason: SuppressionReason::Unsubscribed })
+        );
+
+        let mut report = plan.report.clone();
+        report.mark(&plan.batches[0], RecipientStatus::Sent { batch: 0 });
+        report.mark(&plan.encrypted, RecipientStatus::SentEncrypted);
+        assert_eq!((report.sent(), report.suppressed(), report.failed()), (3, 1, 1));
+
+        assert!(suppressions.allow("gone@example.com").unwrap());
+        assert_eq!(suppressions.reason("gone@example.com"), None);
+    }
+}
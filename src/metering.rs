@@ -0,0 +1,541 @@
+//! # Usage Metering
+//!
+//! Providers hosting Synapse for others bill participants for what they use.
+//! A [`UsageMeter`] counts, per participant and metering period:
+//! - messages the participant sends through the router, and messages the
+//!   relay accepts for it, with their bytes
+//! - bytes the relay holds for it, as of the last message stored or
+//!   acknowledged (a level rather than a running total)
+//! - minutes it spends in relay delivery sessions, from opening a session to
+//!   its last fetch or acknowledgement
+//!
+//! Each participant has a [`UsageQuota`]: the default one unless an operator
+//! [set](UsageMeter::set_quota) its own. Usage that would go over it is
+//! refused with [`SynapseError::QuotaExceeded`] before anything is sent or
+//! stored, and a participant that used up its relay minutes cannot open
+//! another delivery session until the period ends.
+//!
+//! When a period ends its usage goes to every installed [`UsageExporter`]:
+//! [`CsvUsageExporter`] appends it to a file and [`WebhookUsageExporter`]
+//! POSTs it as JSON. Usage an exporter failed to take is offered to it again
+//! with the next period's.
+
+use crate::config::{MeteringConfig, UsageQuota};
+use crate::error::{Result, SynapseError};
+use crate::redaction::Secret;
+use crate::webhooks::{self, WebhookSender};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Something a quota limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeteredResource {
+    Messages,
+    Bytes,
+    StorageBytes,
+    RelayMinutes,
+}
+
+impl MeteredResource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MeteredResource::Messages => "messages",
+            MeteredResource::Bytes => "bytes",
+            MeteredResource::StorageBytes => "storage_bytes",
+            MeteredResource::RelayMinutes => "relay_minutes",
+        }
+    }
+
+    fn limit(&self, quota: &UsageQuota) -> Option<u64> {
+        match self {
+            MeteredResource::Messages => quota.messages,
+            MeteredResource::Bytes => quota.bytes,
+            MeteredResource::StorageBytes => quota.storage_bytes,
+            MeteredResource::RelayMinutes => quota.relay_minutes,
+        }
+    }
+}
+
+impl std::fmt::Display for MeteredResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One participant's usage in the current period
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub messages: u64,
+    pub bytes: u64,
+    /// Bytes the relay holds for the participant
+    pub storage_bytes: u64,
+    /// Time in relay delivery sessions, in milliseconds
+    pub relay_ms: u64,
+}
+
+impl Usage {
+    /// Relay session time, counting a started minute as a whole one
+    pub fn relay_minutes(&self) -> u64 {
+        self.relay_ms.div_ceil(60_000)
+    }
+
+    pub fn amount(&self, resource: MeteredResource) -> u64 {
+        match resource {
+            MeteredResource::Messages => self.messages,
+            MeteredResource::Bytes => self.bytes,
+            MeteredResource::StorageBytes => self.storage_bytes,
+            MeteredResource::RelayMinutes => self.relay_minutes(),
+        }
+    }
+}
+
+/// A participant's usage over one ended period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub participant: String,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub messages: u64,
+    pub bytes: u64,
+    /// Bytes held by the relay when the period ended
+    pub storage_bytes: u64,
+    pub relay_minutes: u64,
+}
+
+/// Receives each period's usage, e.g. for a billing system
+#[async_trait]
+pub trait UsageExporter: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &str;
+
+    async fn export(&self, records: &[UsageRecord]) -> Result<()>;
+}
+
+/// Appends usage to a CSV file, writing a header line when it creates it
+pub struct CsvUsageExporter {
+    path: PathBuf,
+}
+
+impl CsvUsageExporter {
+    pub const HEADER: &'static str = "participant,period_start,period_end,messages,bytes,storage_bytes,relay_minutes";
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// One CSV line for a record
+    pub fn line(record: &UsageRecord) -> String {
+        let participant = if record.participant.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", record.participant.replace('"', "\"\""))
+        } else {
+            record.participant.clone()
+        };
+        format!(
+            "{},{},{},{},{},{},{}\n",
+            participant,
+            record.period_start.to_rfc3339(),
+            record.period_end.to_rfc3339(),
+            record.messages,
+            record.bytes,
+            record.storage_bytes,
+            record.relay_minutes
+        )
+    }
+}
+
+#[async_trait]
+impl UsageExporter for CsvUsageExporter {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    async fn export(&self, records: &[UsageRecord]) -> Result<()> {
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        let mut text = String::new();
+        if file.metadata().await?.len() == 0 {
+            text.push_str(Self::HEADER);
+            text.push('\n');
+        }
+        for record in records {
+            text.push_str(&Self::line(record));
+        }
+        file.write_all(text.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// JSON body POSTed by [`WebhookUsageExporter`]
+#[derive(Debug, Serialize)]
+struct UsageReport<'a> {
+    export_id: Uuid,
+    records: &'a [UsageRecord],
+}
+
+/// POSTs usage as JSON, signed like message webhooks when given a secret
+pub struct WebhookUsageExporter {
+    url: String,
+    secret: Option<Secret<String>>,
+    sender: Arc<dyn WebhookSender>,
+}
+
+impl WebhookUsageExporter {
+    pub fn new(url: &str, secret: Option<Secret<String>>, sender: Arc<dyn WebhookSender>) -> Result<Self> {
+        url::Url::parse(url)
+            .map_err(|e| SynapseError::ConfigurationError(format!("Invalid usage webhook URL {}: {}", url, e)))?;
+        Ok(Self {
+            url: url.to_string(),
+            secret,
+            sender,
+        })
+    }
+}
+
+#[async_trait]
+impl UsageExporter for WebhookUsageExporter {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn export(&self, records: &[UsageRecord]) -> Result<()> {
+        let export_id = Uuid::new_v4();
+        let body = serde_json::to_vec(&UsageReport { export_id, records })?;
+        let timestamp = Utc::now().timestamp();
+        let mut headers = vec![
+            ("X-Synapse-Delivery", export_id.to_string()),
+            ("X-Synapse-Timestamp", timestamp.to_string()),
+        ];
+        if let Some(ref secret) = self.secret {
+            headers.push(("X-Synapse-Signature", webhooks::signature_header(secret.expose(), timestamp, &body)));
+        }
+        match self.sender.post(&self.url, &headers, body).await? {
+            code if (200..300).contains(&code) => Ok(()),
+            code => Err(SynapseError::NetworkError(format!("Usage export to {} returned HTTP {}", self.url, code))),
+        }
+    }
+}
+
+struct ExporterSlot {
+    exporter: Arc<dyn UsageExporter>,
+    /// Records the exporter has not taken yet
+    backlog: Mutex<Vec<UsageRecord>>,
+}
+
+/// Per-participant usage counters and quotas
+///
+/// See the module docs.
+pub struct UsageMeter {
+    period: Duration,
+    default_quota: RwLock<UsageQuota>,
+    quotas: DashMap<String, UsageQuota>,
+    usage: DashMap<String, Usage>,
+    period_start: Mutex<DateTime<Utc>>,
+    exporters: RwLock<Vec<Arc<ExporterSlot>>>,
+}
+
+impl UsageMeter {
+    /// Meter without exporters
+    pub fn new(config: &MeteringConfig) -> Self {
+        Self {
+            period: Duration::from_secs(config.period_secs.max(1)),
+            default_quota: RwLock::new(config.default_quota),
+            quotas: DashMap::new(),
+            usage: DashMap::new(),
+            period_start: Mutex::new(Utc::now()),
+            exporters: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Meter exporting to the CSV file and webhook `config` names
+    pub fn from_config(config: &MeteringConfig) -> Result<Self> {
+        let mut meter = Self::new(config);
+        if let Some(ref path) = config.csv_path {
+            meter = meter.with_exporter(Arc::new(CsvUsageExporter::new(path)));
+        }
+        if let Some(ref url) = config.webhook_url {
+            let exporter = WebhookUsageExporter::new(url, config.webhook_secret.clone(), webhooks::default_sender())?;
+            meter = meter.with_exporter(Arc::new(exporter));
+        }
+        Ok(meter)
+    }
+
+    pub fn with_exporter(self, exporter: Arc<dyn UsageExporter>) -> Self {
+        self.add_exporter(exporter);
+        self
+    }
+
+    pub fn add_exporter(&self, exporter: Arc<dyn UsageExporter>) {
+        self.exporters.write().unwrap().push(Arc::new(ExporterSlot {
+            exporter,
+            backlog: Mutex::new(Vec::new()),
+        }));
+    }
+
+    /// Quota of participants without one of their own
+    pub fn default_quota(&self) -> UsageQuota {
+        *self.default_quota.read().unwrap()
+    }
+
+    pub fn set_default_quota(&self, quota: UsageQuota) {
+        *self.default_quota.write().unwrap() = quota;
+    }
+
+    /// Quota a participant is held to
+    pub fn quota(&self, participant: &str) -> UsageQuota {
+        self.quotas.get(participant).map_or_else(|| self.default_quota(), |quota| *quota)
+    }
+
+    /// Give a participant a quota of its own, taking effect immediately
+    pub fn set_quota(&self, participant: &str, quota: UsageQuota) {
+        info!("Usage quota for {} set to {:?}", participant, quota);
+        self.quotas.insert(participant.to_string(), quota);
+    }
+
+    /// Return a participant to the default quota
+    pub fn clear_quota(&self, participant: &str) -> Option<UsageQuota> {
+        self.quotas.remove(participant).map(|(_, quota)| quota)
+    }
+
+    /// Participants with a quota of their own
+    pub fn quotas(&self) -> BTreeMap<String, UsageQuota> {
+        self.quotas.iter().map(|entry| (entry.key().clone(), *entry.value())).collect()
+    }
+
+    /// A participant's usage in the current period
+    pub fn usage(&self, participant: &str) -> Usage {
+        self.usage.get(participant).map(|usage| *usage).unwrap_or_default()
+    }
+
+    /// How much of `resource` a participant has left this period; `None` when unlimited
+    pub fn remaining(&self, participant: &str, resource: MeteredResource) -> Option<u64> {
+        let limit = resource.limit(&self.quota(participant))?;
+        Some(limit.saturating_sub(self.usage(participant).amount(resource)))
+    }
+
+    /// When the current period began
+    pub fn period_start(&self) -> DateTime<Utc> {
+        *self.period_start.lock().unwrap()
+    }
+
+    /// Count a message of `bytes`, refusing it when it would go over the
+    /// participant's message or byte quota
+    pub fn charge_message(&self, participant: &str, bytes: u64) -> Result<()> {
+        let quota = self.quota(participant);
+        let mut usage = self.usage.entry(participant.to_string()).or_default();
+        check(participant, &quota, MeteredResource::Messages, usage.messages, 1)?;
+        check(participant, &quota, MeteredResource::Bytes, usage.bytes, bytes)?;
+        usage.messages += 1;
+        usage.bytes += bytes;
+        Ok(())
+    }
+
+    /// Refuse storing `additional` bytes for a participant the relay holds
+    /// `held` bytes for when that would go over its storage quota
+    pub fn check_storage(&self, participant: &str, held: u64, additional: u64) -> Result<()> {
+        check(participant, &self.quota(participant), MeteredResource::StorageBytes, held, additional)
+    }
+
+    /// Note how many bytes the relay now holds for a participant
+    pub fn record_storage(&self, participant: &str, held: u64) {
+        self.usage.entry(participant.to_string()).or_default().storage_bytes = held;
+    }
+
+    /// Add time a participant spent in a relay delivery session
+    pub fn charge_relay_time(&self, participant: &str, elapsed: Duration) {
+        let mut usage = self.usage.entry(participant.to_string()).or_default();
+        usage.relay_ms = usage.relay_ms.saturating_add(elapsed.as_millis() as u64);
+    }
+
+    /// Refuse a new relay delivery session once a participant's relay minutes are used up
+    pub fn check_relay_time(&self, participant: &str) -> Result<()> {
+        let quota = self.quota(participant);
+        let used = self.usage(participant).relay_minutes();
+        match quota.relay_minutes {
+            Some(limit) if used >= limit => Err(exceeded(participant, MeteredResource::RelayMinutes, used, limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// End the current period, returning each participant's usage in it
+    ///
+    /// Counters start again from zero, except the bytes held by the relay,
+    /// which are still held.
+    pub fn close_period(&self) -> Vec<UsageRecord> {
+        let period_end = Utc::now();
+        let period_start = std::mem::replace(&mut *self.period_start.lock().unwrap(), period_end);
+        let mut records = Vec::new();
+        for mut entry in self.usage.iter_mut() {
+            let usage = std::mem::take(entry.value_mut());
+            entry.value_mut().storage_bytes = usage.storage_bytes;
+            if usage == Usage::default() {
+                continue;
+            }
+            records.push(UsageRecord {
+                participant: entry.key().clone(),
+                period_start,
+                period_end,
+                messages: usage.messages,
+                bytes: usage.bytes,
+                storage_bytes: usage.storage_bytes,
+                relay_minutes: usage.relay_minutes(),
+            });
+        }
+        self.usage.retain(|_, usage| *usage != Usage::default());
+        records.sort_by(|a, b| a.participant.cmp(&b.participant));
+        records
+    }
+
+    /// End the current period and hand its usage to every exporter
+    ///
+    /// Returns the period's usage. When an exporter fails, the error of the
+    /// last one to fail is returned and the exporter keeps the records for
+    /// the next export.
+    pub async fn export_period(&self) -> Result<Vec<UsageRecord>> {
+        let records = self.close_period();
+        let slots = self.exporters.read().unwrap().clone();
+        let mut failure = None;
+        for slot in slots {
+            let mut batch = std::mem::take(&mut *slot.backlog.lock().unwrap());
+            batch.extend(records.iter().cloned());
+            if batch.is_empty() {
+                continue;
+            }
+            match slot.exporter.export(&batch).await {
+                Ok(()) => debug!("Exported {} usage records to {}", batch.len(), slot.exporter.name()),
+                Err(e) => {
+                    warn!("Usage export to {} failed, keeping {} records: {}", slot.exporter.name(), batch.len(), e);
+                    let mut backlog = slot.backlog.lock().unwrap();
+                    batch.append(&mut backlog);
+                    *backlog = batch;
+                    failure = Some(e);
+                }
+            }
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(records),
+        }
+    }
+
+    /// Export usage at the end of every period
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let first = tokio::time::Instant::now() + self.period;
+            let mut ticker = tokio::time::interval_at(first, self.period);
+            loop {
+                ticker.tick().await;
+                if let Ok(records) = self.export_period().await {
+                    debug!("Metering period closed with usage by {} participants", records.len());
+                }
+            }
+        })
+    }
+}
+
+fn exceeded(participant: &str, resource: MeteredResource, used: u64, limit: u64) -> SynapseError {
+    SynapseError::QuotaExceeded {
+        participant: participant.to_string(),
+        resource: resource.name().to_string(),
+        used,
+        limit,
+    }
+}
+
+fn check(participant: &str, quota: &UsageQuota, resource: MeteredResource, used: u64, adding: u64) -> Result<()> {
+    match resource.limit(quota) {
+        Some(limit) if used.saturating_add(adding) > limit => Err(exceeded(participant, resource, used, limit)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct FlakyExporter {
+        fail: std::sync::atomic::AtomicBool,
+        received: Mutex<Vec<UsageRecord>>,
+    }
+
+    #[async_trait]
+    impl UsageExporter for FlakyExporter {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn export(&self, records: &[UsageRecord]) -> Result<()> {
+            if self.fail.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(SynapseError::NetworkError("down".to_string()));
+            }
+            self.received.lock().unwrap().extend_from_slice(records);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn quotas_are_enforced_and_usage_exported() {
+        let config = MeteringConfig {
+            enabled: true,
+            default_quota: UsageQuota { messages: Some(2), ..UsageQuota::default() },
+            ..MeteringConfig::default()
+        };
+        let exporter = Arc::new(FlakyExporter::default());
+        let meter = UsageMeter::new(&config).with_exporter(exporter.clone());
+
+        meter.charge_message("alice@example.com", 100).unwrap();
+        meter.charge_message("alice@example.com", 50).unwrap();
+        match meter.charge_message("alice@example.com", 10) {
+            Err(SynapseError::QuotaExceeded { resource, used, limit, .. }) => {
+                assert_eq!((resource.as_str(), used, limit), ("messages", 2, 2));
+            }
+            other => panic!("expected a quota error, got {:?}", other),
+        }
+        assert_eq!(meter.usage("alice@example.com").bytes, 150);
+
+        // An operator raises alice's quota and caps bob's storage
+        meter.set_quota("alice@example.com", UsageQuota { messages: Some(10), bytes: Some(200), ..UsageQuota::default() });
+        assert!(meter.charge_message("alice@example.com", 60).is_err());
+        meter.charge_message("alice@example.com", 50).unwrap();
+        assert_eq!(meter.remaining("alice@example.com", MeteredResource::Bytes), Some(0));
+        meter.set_quota("bob@example.com", UsageQuota { storage_bytes: Some(1000), relay_minutes: Some(1), ..UsageQuota::default() });
+        assert!(meter.check_storage("bob@example.com", 900, 200).is_err());
+        meter.check_storage("bob@example.com", 900, 100).unwrap();
+        meter.record_storage("bob@example.com", 1000);
+        meter.check_relay_time("bob@example.com").unwrap();
+        meter.charge_relay_time("bob@example.com", Duration::from_secs(61));
+        assert_eq!(meter.usage("bob@example.com").relay_minutes(), 2);
+        assert!(meter.check_relay_time("bob@example.com").is_err());
+
+        // A failed export is offered again with the next period
+        exporter.fail.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(meter.export_period().await.is_err());
+        assert_eq!(meter.usage("alice@example.com"), Usage::default());
+        assert_eq!(meter.usage("bob@example.com").storage_bytes, 1000);
+        exporter.fail.store(false, std::sync::atomic::Ordering::SeqCst);
+        let second = meter.export_period().await.unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!((second[0].participant.as_str(), second[0].relay_minutes), ("bob@example.com", 0));
+
+        let received = exporter.received.lock().unwrap();
+        let summary: Vec<(&str, u64, u64)> =
+            received.iter().map(|r| (r.participant.as_str(), r.messages, r.bytes)).collect();
+        assert_eq!(
+            summary,
+            [("alice@example.com", 3, 200), ("bob@example.com", 0, 0), ("bob@example.com", 0, 0)]
+        );
+        assert_eq!(received[1].relay_minutes, 2);
+        assert!(CsvUsageExporter::line(&received[0]).starts_with("alice@example.com,"));
+    }
+}
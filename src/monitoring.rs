@@ -4,17 +4,20 @@
 //! diagnostics for all transport layers and system components.
 
 use crate::{
-    error::Result,
+    error::{Result, SynapseError},
     transport::abstraction::TransportMetrics,
+    webhooks::{signature_header, WebhookSender},
 };
+use async_trait::async_trait;
 use std::{
+    future::Future,
     sync::Arc,
     time::{Duration, Instant, SystemTime},
     collections::{HashMap, VecDeque},
 };
 use tokio::sync::{RwLock, broadcast};
 use serde::{Serialize, Deserialize};
-use tracing::{info, debug};
+use tracing::{info, debug, warn};
 
 /// Central metrics collection and monitoring system
 #[derive(Debug)]
@@ -24,6 +27,7 @@ pub struct MetricsCollector {
     performance_history: Arc<RwLock<PerformanceHistory>>,
     event_broadcaster: broadcast::Sender<MetricEvent>,
     alerts: Arc<RwLock<Vec<Alert>>>,
+    slo: Arc<SloTracker>,
     config: MetricsConfig,
 }
 
@@ -40,6 +44,12 @@ pub struct SystemMetrics {
     pub average_response_time: Duration,
     pub error_rate_percent: f64,
     pub last_updated: SystemTime,
+    /// Reuse of the transports' message buffers
+    #[serde(default)]
+    pub buffer_pool: crate::buffer_pool::BufferPoolStats,
+    /// Tasks spawned, running and waiting per subsystem
+    #[serde(default)]
+    pub runtime: Vec<crate::runtime::SubsystemStats>,
 }
 
 /// Historical performance data for trend analysis
@@ -75,6 +85,7 @@ pub enum AlertType {
     CircuitBreakerOpen,
     SecurityEvent,
     PerformanceDegradation,
+    SloBreach,
 }
 
 /// Alert severity levels
@@ -129,6 +140,358 @@ impl Default for MetricsConfig {
     }
 }
 
+/// Delivery objectives a peer is held to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloConfig {
+    /// Minimum fraction of deliveries that must succeed (0.0-1.0)
+    pub min_success_rate: f64,
+    /// Maximum acceptable 95th percentile latency of successful deliveries
+    pub max_p95_latency: Duration,
+    /// Sliding window the success rate and latency are computed over
+    pub window: Duration,
+    /// How long a breach must persist before alert handlers fire
+    pub breach_duration: Duration,
+    /// Deliveries needed in the window before the SLO is judged
+    pub min_samples: usize,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            min_success_rate: 0.95,
+            max_p95_latency: Duration::from_secs(2),
+            window: Duration::from_secs(300),
+            breach_duration: Duration::from_secs(300),
+            min_samples: 10,
+        }
+    }
+}
+
+/// Current SLO standing of a single peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerSloStatus {
+    pub peer: String,
+    /// Deliveries in the current window
+    pub samples: usize,
+    pub success_rate: f64,
+    pub p95_latency: Duration,
+    /// Transport most likely responsible while the SLO is breached
+    pub suspected_transport: Option<String>,
+    pub breached: bool,
+    /// How long the current breach has lasted
+    pub breached_for: Duration,
+}
+
+/// Passed to SLO alert handlers when a breach starts alerting or clears
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloAlert {
+    pub peer: String,
+    pub status: PeerSloStatus,
+    /// Which objectives were missed
+    pub reasons: Vec<String>,
+    pub slo: SloConfig,
+    /// `false` when the breach is raised, `true` once the peer recovers
+    pub resolved: bool,
+    pub timestamp: SystemTime,
+}
+
+/// Receives SLO breach and recovery notifications
+#[async_trait]
+pub trait SloAlertHandler: Send + Sync {
+    async fn handle(&self, alert: &SloAlert) -> Result<()>;
+}
+
+struct FnSloAlertHandler<F>(F);
+
+#[async_trait]
+impl<F, Fut> SloAlertHandler for FnSloAlertHandler<F>
+where
+    F: Fn(SloAlert) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn handle(&self, alert: &SloAlert) -> Result<()> {
+        (self.0)(alert.clone()).await;
+        Ok(())
+    }
+}
+
+/// POSTs SLO alerts as JSON, signed the same way as message webhooks
+pub struct WebhookSloAlertHandler {
+    sender: Arc<dyn WebhookSender>,
+    url: String,
+    secret: String,
+}
+
+impl WebhookSloAlertHandler {
+    pub fn new(sender: Arc<dyn WebhookSender>, url: &str, secret: &str) -> Self {
+        Self {
+            sender,
+            url: url.to_string(),
+            secret: secret.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl SloAlertHandler for WebhookSloAlertHandler {
+    async fn handle(&self, alert: &SloAlert) -> Result<()> {
+        let body = serde_json::to_vec(alert)?;
+        let timestamp = chrono::Utc::now().timestamp();
+        let headers = [
+            ("X-Synapse-Timestamp", timestamp.to_string()),
+            ("X-Synapse-Signature", signature_header(&self.secret, timestamp, &body)),
+        ];
+        let status = self.sender.post(&self.url, &headers, body).await?;
+        if !(200..300).contains(&status) {
+            return Err(SynapseError::NetworkError(format!(
+                "SLO alert webhook {} returned {}",
+                self.url, status
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Emails SLO alerts through an email transport
+#[cfg(feature = "email")]
+pub struct EmailSloAlertHandler {
+    transport: Arc<crate::email::EmailTransport>,
+    from: String,
+    to: String,
+}
+
+#[cfg(feature = "email")]
+impl EmailSloAlertHandler {
+    pub fn new(transport: Arc<crate::email::EmailTransport>, from: &str, to: &str) -> Self {
+        Self {
+            transport,
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "email")]
+#[async_trait]
+impl SloAlertHandler for EmailSloAlertHandler {
+    async fn handle(&self, alert: &SloAlert) -> Result<()> {
+        let state = if alert.resolved { "recovered" } else { "breached" };
+        let mut body = format!(
+            "Peer: {}\nSuccess rate: {:.1}% (objective {:.1}%)\np95 latency: {:?} (objective {:?})\nDeliveries in window: {}\n",
+            alert.peer,
+            alert.status.success_rate * 100.0,
+            alert.slo.min_success_rate * 100.0,
+            alert.status.p95_latency,
+            alert.slo.max_p95_latency,
+            alert.status.samples,
+        );
+        if let Some(transport) = &alert.status.suspected_transport {
+            body.push_str(&format!("Suspected transport: {}\n", transport));
+        }
+        for reason in &alert.reasons {
+            body.push_str(&format!("- {}\n", reason));
+        }
+
+        let email = lettre::Message::builder()
+            .from(self.from.parse::<lettre::message::Mailbox>().map_err(|e| SynapseError::InvalidFormat(format!("Invalid from address: {}", e)))?)
+            .to(self.to.parse::<lettre::message::Mailbox>().map_err(|e| SynapseError::InvalidFormat(format!("Invalid to address: {}", e)))?)
+            .subject(format!("[Synapse] SLO {} for {}", state, alert.peer))
+            .body(body)
+            .map_err(|e| SynapseError::InvalidFormat(format!("Failed to build SLO alert email: {}", e)))?;
+        self.transport.send_email(&email).await
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DeliverySample {
+    at: Instant,
+    success: bool,
+    latency: Duration,
+    transport: String,
+}
+
+#[derive(Debug, Default)]
+struct PeerSloState {
+    samples: VecDeque<DeliverySample>,
+    breach_started: Option<Instant>,
+    /// ID of the raised alert while handlers have been notified of a breach
+    alert_id: Option<String>,
+}
+
+/// Per-peer delivery samples, objectives and alert handlers
+struct SloTracker {
+    default_slo: RwLock<SloConfig>,
+    peer_slos: RwLock<HashMap<String, SloConfig>>,
+    peers: RwLock<HashMap<String, PeerSloState>>,
+    handlers: RwLock<Vec<Arc<dyn SloAlertHandler>>>,
+}
+
+impl std::fmt::Debug for SloTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SloTracker").finish_non_exhaustive()
+    }
+}
+
+impl SloTracker {
+    fn new() -> Self {
+        Self {
+            default_slo: RwLock::new(SloConfig::default()),
+            peer_slos: RwLock::new(HashMap::new()),
+            peers: RwLock::new(HashMap::new()),
+            handlers: RwLock::new(Vec::new()),
+        }
+    }
+
+    async fn slo_for(&self, peer: &str) -> SloConfig {
+        match self.peer_slos.read().await.get(peer) {
+            Some(slo) => slo.clone(),
+            None => self.default_slo.read().await.clone(),
+        }
+    }
+
+    /// Update breach state for `peer`, returning an alert when it starts alerting or recovers
+    async fn evaluate(&self, peer: &str, now: Instant) -> Option<(SloAlert, Option<String>)> {
+        let slo = self.slo_for(peer).await;
+        let mut peers = self.peers.write().await;
+        let state = peers.get_mut(peer)?;
+        while state
+            .samples
+            .front()
+            .is_some_and(|s| now.saturating_duration_since(s.at) > slo.window)
+        {
+            state.samples.pop_front();
+        }
+
+        let mut status = peer_status(peer, &state.samples, &slo);
+        if status.samples < slo.min_samples {
+            // Too little traffic to judge either way; keep the current state
+            return None;
+        }
+
+        if status.breached {
+            let started = *state.breach_started.get_or_insert(now);
+            status.breached_for = now.saturating_duration_since(started);
+            if state.alert_id.is_none() && status.breached_for >= slo.breach_duration {
+                let millis = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                state.alert_id = Some(format!("slo-{}-{}", peer, millis));
+                let reasons = breach_reasons(&status, &slo);
+                return Some((
+                    SloAlert {
+                        peer: peer.to_string(),
+                        status,
+                        reasons,
+                        slo,
+                        resolved: false,
+                        timestamp: SystemTime::now(),
+                    },
+                    state.alert_id.clone(),
+                ));
+            }
+        } else {
+            state.breach_started = None;
+            if let Some(alert_id) = state.alert_id.take() {
+                return Some((
+                    SloAlert {
+                        peer: peer.to_string(),
+                        status,
+                        reasons: Vec::new(),
+                        slo,
+                        resolved: true,
+                        timestamp: SystemTime::now(),
+                    },
+                    Some(alert_id),
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// 95th percentile of `latencies` (nearest-rank); zero when empty
+fn p95(mut latencies: Vec<Duration>) -> Duration {
+    if latencies.is_empty() {
+        return Duration::ZERO;
+    }
+    latencies.sort();
+    let rank = ((latencies.len() as f64) * 0.95).ceil() as usize;
+    latencies[rank.saturating_sub(1).min(latencies.len() - 1)]
+}
+
+fn peer_status(peer: &str, samples: &VecDeque<DeliverySample>, slo: &SloConfig) -> PeerSloStatus {
+    let total = samples.len();
+    let successes = samples.iter().filter(|s| s.success).count();
+    let success_rate = if total == 0 { 1.0 } else { successes as f64 / total as f64 };
+    let p95_latency = p95(samples.iter().filter(|s| s.success).map(|s| s.latency).collect());
+
+    let rate_breached = success_rate < slo.min_success_rate;
+    let latency_breached = p95_latency > slo.max_p95_latency;
+    let breached = total >= slo.min_samples && (rate_breached || latency_breached);
+
+    let suspected_transport = if breached {
+        suspect_transport(samples, rate_breached)
+    } else {
+        None
+    };
+
+    PeerSloStatus {
+        peer: peer.to_string(),
+        samples: total,
+        success_rate,
+        p95_latency,
+        suspected_transport,
+        breached,
+        breached_for: Duration::ZERO,
+    }
+}
+
+/// The transport with the most failures, or the slowest one when only latency is breached
+fn suspect_transport(samples: &VecDeque<DeliverySample>, rate_breached: bool) -> Option<String> {
+    let mut by_transport: HashMap<&str, (usize, Vec<Duration>)> = HashMap::new();
+    for sample in samples {
+        let entry = by_transport.entry(sample.transport.as_str()).or_default();
+        if sample.success {
+            entry.1.push(sample.latency);
+        } else {
+            entry.0 += 1;
+        }
+    }
+
+    if rate_breached {
+        by_transport
+            .iter()
+            .filter(|(_, (failures, _))| *failures > 0)
+            .max_by_key(|(_, (failures, _))| *failures)
+            .map(|(name, _)| name.to_string())
+    } else {
+        by_transport
+            .into_iter()
+            .map(|(name, (_, latencies))| (name, p95(latencies)))
+            .max_by_key(|(_, latency)| *latency)
+            .map(|(name, _)| name.to_string())
+    }
+}
+
+fn breach_reasons(status: &PeerSloStatus, slo: &SloConfig) -> Vec<String> {
+    let mut reasons = Vec::new();
+    if status.success_rate < slo.min_success_rate {
+        reasons.push(format!(
+            "success rate {:.1}% below {:.1}%",
+            status.success_rate * 100.0,
+            slo.min_success_rate * 100.0
+        ));
+    }
+    if status.p95_latency > slo.max_p95_latency {
+        reasons.push(format!(
+            "p95 latency {:?} above {:?}",
+            status.p95_latency, slo.max_p95_latency
+        ));
+    }
+    reasons
+}
+
 impl MetricsCollector {
     /// Create a new metrics collector
     pub fn new(config: MetricsConfig) -> Self {
@@ -140,6 +503,7 @@ impl MetricsCollector {
             performance_history: Arc::new(RwLock::new(PerformanceHistory::new(config.max_history_samples))),
             event_broadcaster,
             alerts: Arc::new(RwLock::new(Vec::new())),
+            slo: Arc::new(SloTracker::new()),
             config,
         }
     }
@@ -153,6 +517,7 @@ impl MetricsCollector {
         let performance_history = self.performance_history.clone();
         let event_broadcaster = self.event_broadcaster.clone();
         let alerts = self.alerts.clone();
+        let slo = self.slo.clone();
         let config = self.config.clone();
         
         // Spawn metrics collection task
@@ -180,6 +545,12 @@ impl MetricsCollector {
                 
                 // Check for alerts
                 Self::check_alerts(&current_metrics, &alerts, &event_broadcaster, &config).await;
+
+                // Re-check SLOs so breaches on quiet peers still age into alerts
+                let peers: Vec<String> = slo.peers.read().await.keys().cloned().collect();
+                for peer in peers {
+                    Self::evaluate_peer_slo(&slo, &peer, &alerts, &event_broadcaster).await;
+                }
                 
                 debug!("Collected metrics: {:?}", current_metrics);
             }
@@ -228,6 +599,143 @@ impl MetricsCollector {
         }
     }
     
+    /// Set the SLO applied to peers without their own
+    pub async fn set_slo(&self, slo: SloConfig) {
+        *self.slo.default_slo.write().await = slo;
+    }
+
+    /// Hold `peer` to its own SLO instead of the default
+    pub async fn set_peer_slo(&self, peer: &str, slo: SloConfig) {
+        self.slo.peer_slos.write().await.insert(peer.to_string(), slo);
+    }
+
+    /// Return `peer` to the default SLO
+    pub async fn clear_peer_slo(&self, peer: &str) {
+        self.slo.peer_slos.write().await.remove(peer);
+    }
+
+    /// Register a handler notified when an SLO breach has lasted `breach_duration` and when it clears
+    pub async fn add_slo_alert_handler(&self, handler: Arc<dyn SloAlertHandler>) {
+        self.slo.handlers.write().await.push(handler);
+    }
+
+    /// Register an async callback for SLO breaches and recoveries
+    pub async fn on_slo_alert<F, Fut>(&self, callback: F)
+    where
+        F: Fn(SloAlert) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.add_slo_alert_handler(Arc::new(FnSloAlertHandler(callback))).await;
+    }
+
+    /// Record the outcome of a delivery to `peer` and re-check its SLO
+    pub async fn record_delivery(&self, peer: &str, transport: &str, success: bool, latency: Duration) {
+        let window = self.slo.slo_for(peer).await.window;
+        {
+            let mut peers = self.slo.peers.write().await;
+            let state = peers.entry(peer.to_string()).or_default();
+            state.samples.push_back(DeliverySample {
+                at: Instant::now(),
+                success,
+                latency,
+                transport: transport.to_string(),
+            });
+            // Bound memory for very busy peers; the window is pruned on evaluation
+            let cap = self.config.max_history_samples.max(1);
+            while state.samples.len() > cap
+                || state.samples.front().is_some_and(|s| s.at.elapsed() > window)
+            {
+                state.samples.pop_front();
+            }
+        }
+        Self::evaluate_peer_slo(&self.slo, peer, &self.alerts, &self.event_broadcaster).await;
+    }
+
+    /// SLO standing of a peer with recorded deliveries
+    pub async fn peer_slo_status(&self, peer: &str) -> Option<PeerSloStatus> {
+        let slo = self.slo.slo_for(peer).await;
+        let peers = self.slo.peers.read().await;
+        let state = peers.get(peer)?;
+        let mut status = peer_status(peer, &state.samples, &slo);
+        if status.breached {
+            status.breached_for = state.breach_started.map(|s| s.elapsed()).unwrap_or_default();
+        }
+        Some(status)
+    }
+
+    /// SLO standing of every peer with recorded deliveries
+    pub async fn peer_slo_statuses(&self) -> Vec<PeerSloStatus> {
+        let peers: Vec<String> = self.slo.peers.read().await.keys().cloned().collect();
+        let mut statuses = Vec::with_capacity(peers.len());
+        for peer in peers {
+            if let Some(status) = self.peer_slo_status(&peer).await {
+                statuses.push(status);
+            }
+        }
+        statuses
+    }
+
+    /// Evaluate one peer's SLO, raising or resolving its alert and notifying handlers
+    async fn evaluate_peer_slo(
+        slo: &Arc<SloTracker>,
+        peer: &str,
+        alerts: &Arc<RwLock<Vec<Alert>>>,
+        broadcaster: &broadcast::Sender<MetricEvent>,
+    ) {
+        let Some((slo_alert, alert_id)) = slo.evaluate(peer, Instant::now()).await else {
+            return;
+        };
+
+        if slo_alert.resolved {
+            info!("Peer {} is meeting its SLO again", peer);
+            let mut alerts_vec = alerts.write().await;
+            if let Some(alert) = alerts_vec.iter_mut().find(|a| Some(&a.id) == alert_id.as_ref()) {
+                alert.resolved = true;
+                alert.resolution_time = Some(SystemTime::now());
+            }
+        } else {
+            let message = format!(
+                "SLO breached for {} for {:?}: {}{}",
+                peer,
+                slo_alert.status.breached_for,
+                slo_alert.reasons.join(", "),
+                slo_alert
+                    .status
+                    .suspected_transport
+                    .as_ref()
+                    .map(|t| format!(" (suspected transport: {})", t))
+                    .unwrap_or_default()
+            );
+            warn!("{}", message);
+            let alert = Alert {
+                id: alert_id.unwrap_or_default(),
+                alert_type: AlertType::SloBreach,
+                severity: AlertSeverity::Critical,
+                message,
+                timestamp: SystemTime::now(),
+                transport: slo_alert.status.suspected_transport.clone(),
+                resolved: false,
+                resolution_time: None,
+            };
+            let _ = broadcaster.send(MetricEvent::PerformanceAlert {
+                alert_type: alert.alert_type.clone(),
+                severity: alert.severity.clone(),
+                message: alert.message.clone(),
+            });
+            alerts.write().await.push(alert);
+        }
+
+        let handlers = slo.handlers.read().await.clone();
+        for handler in handlers {
+            let slo_alert = slo_alert.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handler.handle(&slo_alert).await {
+                    warn!("SLO alert handler failed for {}: {}", slo_alert.peer, e);
+                }
+            });
+        }
+    }
+    
     /// Subscribe to metric events
     pub fn subscribe_to_events(&self) -> broadcast::Receiver<MetricEvent> {
         self.event_broadcaster.subscribe()
@@ -289,6 +797,8 @@ impl MetricsCollector {
             average_response_time: Duration::from_millis(45),
             error_rate_percent: 1.2,
             last_updated: SystemTime::now(),
+            buffer_pool: crate::buffer_pool::BufferPool::shared().stats(),
+            runtime: crate::runtime::RuntimeTopology::current().stats(),
         }
     }
     
@@ -518,6 +1028,8 @@ impl Default for SystemMetrics {
             average_response_time: Duration::from_millis(0),
             error_rate_percent: 0.0,
             last_updated: SystemTime::now(),
+            buffer_pool: Default::default(),
+            runtime: Vec::new(),
         }
     }
 }
@@ -533,3 +1045,75 @@ pub struct HealthReport {
     pub recommendations: Vec<String>,
     pub generated_at: SystemTime,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strict_slo() -> SloConfig {
+        SloConfig {
+            min_success_rate: 0.9,
+            max_p95_latency: Duration::from_millis(500),
+            window: Duration::from_secs(60),
+            breach_duration: Duration::ZERO,
+            min_samples: 4,
+        }
+    }
+
+    #[test]
+    fn test_p95_nearest_rank() {
+        let latencies = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(p95(latencies), Duration::from_millis(95));
+        assert_eq!(p95(Vec::new()), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_slo_breach_fires_once_and_resolves() {
+        let collector = MetricsCollector::new(MetricsConfig::default());
+        collector.set_slo(strict_slo()).await;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        collector
+            .on_slo_alert(move |alert| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(alert);
+                }
+            })
+            .await;
+
+        for _ in 0..3 {
+            collector.record_delivery("bob", "tcp", true, Duration::from_millis(20)).await;
+            collector.record_delivery("bob", "email", false, Duration::ZERO).await;
+        }
+        let alert = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert!(!alert.resolved);
+        assert_eq!(alert.status.suspected_transport.as_deref(), Some("email"));
+        assert_eq!(collector.get_active_alerts().await.len(), 1);
+
+        // Still breached: no repeat notification
+        collector.record_delivery("bob", "email", false, Duration::ZERO).await;
+        assert!(tokio::time::timeout(Duration::from_millis(100), rx.recv()).await.is_err());
+
+        for _ in 0..60 {
+            collector.record_delivery("bob", "tcp", true, Duration::from_millis(20)).await;
+        }
+        let alert = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert!(alert.resolved);
+        assert!(collector.get_active_alerts().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_slo_waits_for_breach_duration() {
+        let collector = MetricsCollector::new(MetricsConfig::default());
+        collector
+            .set_peer_slo("carol", SloConfig { breach_duration: Duration::from_secs(600), ..strict_slo() })
+            .await;
+        for _ in 0..5 {
+            collector.record_delivery("carol", "udp", true, Duration::from_secs(2)).await;
+        }
+        let status = collector.peer_slo_status("carol").await.unwrap();
+        assert!(status.breached);
+        assert_eq!(status.suspected_transport.as_deref(), Some("udp"));
+        assert!(collector.get_active_alerts().await.is_empty());
+    }
+}
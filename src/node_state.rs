@@ -0,0 +1,326 @@
+//! # Node State Failover
+//!
+//! A standby node takes over from a failed primary by importing the state the
+//! primary last exported. `EnhancedSynapseRouter::export_state` captures the
+//! identity registry cache, the contact book, messages waiting in local
+//! identities' inboxes, session resumption tickets and, on a full node, a
+//! checkpoint of the trust ledger with its balances and nonces;
+//! `EnhancedSynapseRouter::import_state` loads it. Exporting every few
+//! seconds and importing on the standby as each export arrives lets the
+//! standby serve within seconds of the primary failing.
+//!
+//! Each export carries a [`StateMarker`]. Exports descended from the same
+//! first export share a lineage; the node exporting holds a term, and its
+//! exports count up within it. When a node that imported another's state
+//! exports itself, it has taken over and starts the next term. Importing
+//! state checks its marker against the last one seen ([`StateLineage`]):
+//! older state is refused, and state from a different node in the same term
+//! means two nodes acted as primary at once, a split brain. Both are refused
+//! with [`SynapseError::StateConflict`].
+//!
+//! Private keys are not part of the state; move them with identity bundles.
+//! Messages held by a relay are sealed with a key of the node running it, so
+//! they are not either; give both nodes the same database relay store.
+
+use crate::blockchain::StateSnapshot;
+use crate::contacts::Contact;
+use crate::error::{Result, SynapseError};
+use crate::hashing::HashAlgorithm;
+use crate::transport::resumption::SessionTicket;
+use crate::types::{GlobalIdentity, SimpleMessage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Current node state format version
+pub const NODE_STATE_VERSION: u8 = 1;
+
+/// Where an export sits in the history of a deployment's state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateMarker {
+    /// Shared by every export descended from the same first export
+    pub lineage: Uuid,
+    /// Raised each time another node takes over
+    pub term: u64,
+    /// Instance that exported during this term
+    pub holder: String,
+    /// Exports by the holder in this term, counting from 1
+    pub sequence: u64,
+    pub exported_at: DateTime<Utc>,
+}
+
+/// How an incoming marker relates to the last one seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateConsistency {
+    /// Follows the state we hold
+    Newer,
+    /// The state we hold
+    Same,
+    /// Older than the state we hold
+    Stale,
+    /// Exported by another node in the same term
+    SplitBrain,
+    /// From a different lineage
+    Unrelated,
+}
+
+impl StateConsistency {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StateConsistency::Newer => "newer",
+            StateConsistency::Same => "same",
+            StateConsistency::Stale => "stale",
+            StateConsistency::SplitBrain => "split_brain",
+            StateConsistency::Unrelated => "unrelated",
+        }
+    }
+}
+
+impl std::fmt::Display for StateConsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl StateMarker {
+    /// How `incoming` relates to this marker
+    pub fn compare(&self, incoming: &StateMarker) -> StateConsistency {
+        if incoming.lineage != self.lineage {
+            return StateConsistency::Unrelated;
+        }
+        if incoming.term != self.term {
+            return if incoming.term > self.term { StateConsistency::Newer } else { StateConsistency::Stale };
+        }
+        if incoming.holder != self.holder {
+            return StateConsistency::SplitBrain;
+        }
+        match incoming.sequence.cmp(&self.sequence) {
+            std::cmp::Ordering::Greater => StateConsistency::Newer,
+            std::cmp::Ordering::Equal => StateConsistency::Same,
+            std::cmp::Ordering::Less => StateConsistency::Stale,
+        }
+    }
+}
+
+/// The last marker one node exported or imported
+#[derive(Debug)]
+pub struct StateLineage {
+    instance: String,
+    last: Mutex<Option<StateMarker>>,
+}
+
+impl StateLineage {
+    /// Track markers for the node instance named `instance`
+    pub fn new(instance: impl Into<String>) -> Self {
+        Self {
+            instance: instance.into(),
+            last: Mutex::new(None),
+        }
+    }
+
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+
+    /// Marker of the last state exported or imported
+    pub fn current(&self) -> Option<StateMarker> {
+        self.last.lock().unwrap().clone()
+    }
+
+    /// Marker for a new export
+    ///
+    /// Continues our term, starts the next one when the last marker was
+    /// another node's, or starts a lineage when there was none.
+    pub fn next_export(&self) -> StateMarker {
+        let mut last = self.last.lock().unwrap();
+        let exported_at = Utc::now();
+        let marker = match last.take() {
+            Some(ours) if ours.holder == self.instance => StateMarker {
+                sequence: ours.sequence + 1,
+                exported_at,
+                ..ours
+            },
+            Some(theirs) => StateMarker {
+                lineage: theirs.lineage,
+                term: theirs.term + 1,
+                holder: self.instance.clone(),
+                sequence: 1,
+                exported_at,
+            },
+            None => StateMarker {
+                lineage: Uuid::new_v4(),
+                term: 1,
+                holder: self.instance.clone(),
+                sequence: 1,
+                exported_at,
+            },
+        };
+        *last = Some(marker.clone());
+        marker
+    }
+
+    /// Check `incoming` against the last marker, taking it when it is newer
+    ///
+    /// A node that has seen no marker takes any.
+    pub fn admit(&self, incoming: &StateMarker) -> StateConsistency {
+        let mut last = self.last.lock().unwrap();
+        let consistency = match *last {
+            Some(ref ours) => ours.compare(incoming),
+            None => StateConsistency::Newer,
+        };
+        if consistency == StateConsistency::Newer {
+            *last = Some(incoming.clone());
+        }
+        consistency
+    }
+}
+
+/// Everything a standby needs to take over from a node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeState {
+    /// Format version
+    pub version: u8,
+    pub marker: StateMarker,
+    /// Identity registry cache
+    pub identities: Vec<GlobalIdentity>,
+    pub contacts: Vec<Contact>,
+    /// Local identity name -> messages waiting in its inbox, oldest first
+    pub inboxes: BTreeMap<String, Vec<SimpleMessage>>,
+    /// Session resumption tickets
+    pub sessions: Vec<SessionTicket>,
+    /// Trust ledger checkpoint, when the node keeps one
+    pub checkpoint: Option<StateSnapshot>,
+    /// SHA-256 over the rest, set by [`NodeState::seal`]
+    #[serde(default)]
+    pub checksum: String,
+}
+
+impl NodeState {
+    /// Set the checksum over the current contents
+    pub fn seal(mut self) -> Result<Self> {
+        self.checksum = self.digest()?;
+        Ok(self)
+    }
+
+    /// Check the format version and that the contents match the checksum
+    pub fn verify(&self) -> Result<()> {
+        if self.version != NODE_STATE_VERSION {
+            return Err(SynapseError::InvalidFormat(format!(
+                "Unsupported node state version {}",
+                self.version
+            )));
+        }
+        if self.checksum != self.digest()? {
+            return Err(SynapseError::ValidationFailed(
+                "Node state does not match its checksum".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Serialize as JSON, e.g. to ship to a standby
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Parse and [verify](Self::verify) state serialized with [`Self::to_json`]
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        let state: NodeState = serde_json::from_slice(bytes)?;
+        state.verify()?;
+        Ok(state)
+    }
+
+    fn digest(&self) -> Result<String> {
+        // A JSON value sorts object keys, so maps hash the same whatever
+        // order they were built in
+        let mut value = serde_json::to_value(self)?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("checksum");
+        }
+        Ok(HashAlgorithm::Sha256.hash(&serde_json::to_vec(&value)?))
+    }
+}
+
+/// What [`NodeState`] an import took in
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateImport {
+    /// Identities new to the registry cache; known ones are replaced
+    pub identities: usize,
+    pub contacts: usize,
+    /// Messages queued in local identities' inboxes
+    pub messages: usize,
+    /// Messages for local identities this node does not host, or over its
+    /// queue limits
+    pub skipped_messages: usize,
+    /// Session tickets newer than the ones held
+    pub sessions: usize,
+    /// Block the trust ledger was restored at
+    pub checkpoint: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(marker: StateMarker) -> NodeState {
+        let mut message = SimpleMessage {
+            to: "support".to_string(),
+            from_entity: "alice@example.com".to_string(),
+            content: "hello".to_string(),
+            message_type: crate::types::MessageType::Direct,
+            metadata: Default::default(),
+        };
+        message.metadata.insert("b".to_string(), "2".to_string());
+        message.metadata.insert("a".to_string(), "1".to_string());
+        NodeState {
+            version: NODE_STATE_VERSION,
+            marker,
+            identities: vec![],
+            contacts: vec![],
+            inboxes: BTreeMap::from([("support".to_string(), vec![message])]),
+            sessions: vec![],
+            checkpoint: None,
+            checksum: String::new(),
+        }
+    }
+
+    #[test]
+    fn failover_is_ordered_and_split_brain_refused() {
+        let primary = StateLineage::new("primary");
+        let standby = StateLineage::new("standby");
+        let other = StateLineage::new("other");
+
+        let first = primary.next_export();
+        assert_eq!((first.term, first.sequence), (1, 1));
+        assert_eq!(standby.admit(&first), StateConsistency::Newer);
+        assert_eq!(other.admit(&first), StateConsistency::Newer);
+        let second = primary.next_export();
+        assert_eq!(standby.admit(&second), StateConsistency::Newer);
+        assert_eq!(standby.admit(&second), StateConsistency::Same);
+        assert_eq!(standby.admit(&first), StateConsistency::Stale);
+
+        // The standby takes over; the old primary's later exports are stale
+        let takeover = standby.next_export();
+        assert_eq!((takeover.lineage, takeover.term, takeover.sequence), (first.lineage, 2, 1));
+        let late = primary.next_export();
+        assert_eq!(standby.admit(&late), StateConsistency::Stale);
+        assert_eq!(primary.admit(&takeover), StateConsistency::Newer);
+
+        // Two nodes that both took over from term 1 share term 2
+        let rival = other.next_export();
+        assert_eq!(rival.term, 2);
+        assert_eq!(primary.admit(&rival), StateConsistency::SplitBrain);
+        assert_ne!(StateLineage::new("fresh").next_export().lineage, first.lineage);
+        assert_eq!(primary.current(), Some(takeover.clone()));
+
+        let sealed = state(takeover).seal().unwrap();
+        let parsed = NodeState::from_json(&sealed.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.checksum, sealed.checksum);
+        let mut tampered = parsed;
+        tampered.inboxes.get_mut("support").unwrap()[0].content = "bye".to_string();
+        assert!(tampered.verify().is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! # Notifications
+//!
+//! Canned message templates for the notices agents most often send: alerts,
+//! status updates and requests for approval. A [`Notification`] becomes an
+//! ordinary direct message whose content is a short human-readable rendering
+//! and whose `notification` metadata entry holds the structured payload, so
+//! an AI recipient parses the metadata while a person (or a bridge to a chat
+//! network) simply reads the text. Over email the message is sent as
+//! `multipart/alternative`, with the rendering for mail clients and the JSON
+//! envelope for Synapse nodes.
+
+use crate::error::Result;
+use crate::types::{MessageType, SimpleMessage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Metadata key holding the JSON payload of a notification
+pub const NOTIFICATION_KEY: &str = "notification";
+
+/// How urgent an alert is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+/// A structured notice with a human-readable rendering
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Notification {
+    /// Something needs attention
+    Alert {
+        severity: Severity,
+        title: String,
+        #[serde(default)]
+        details: String,
+        /// System or check that raised the alert
+        #[serde(default)]
+        source: Option<String>,
+    },
+    /// Progress or state of a job or component
+    Status {
+        component: String,
+        state: String,
+        #[serde(default)]
+        details: String,
+        /// Percent complete, when known
+        #[serde(default)]
+        progress: Option<u8>,
+    },
+    /// A decision the recipient is asked to make
+    ApprovalRequest {
+        /// Quoted back in the answer
+        request_id: String,
+        title: String,
+        #[serde(default)]
+        details: String,
+        /// Answers the recipient may give
+        options: Vec<String>,
+        #[serde(default)]
+        expires_at: Option<DateTime<Utc>>,
+    },
+}
+
+impl Notification {
+    pub fn alert(severity: Severity, title: impl Into<String>) -> Self {
+        Notification::Alert {
+            severity,
+            title: title.into(),
+            details: String::new(),
+            source: None,
+        }
+    }
+
+    pub fn status(component: impl Into<String>, state: impl Into<String>) -> Self {
+        Notification::Status {
+            component: component.into(),
+            state: state.into(),
+            details: String::new(),
+            progress: None,
+        }
+    }
+
+    /// A request answered with "approve" or "reject" unless other options are set
+    pub fn approval_request(title: impl Into<String>) -> Self {
+        Notification::ApprovalRequest {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            title: title.into(),
+            details: String::new(),
+            options: vec!["approve".to_string(), "reject".to_string()],
+            expires_at: None,
+        }
+    }
+
+    /// Add explanatory text
+    pub fn with_details(mut self, text: impl Into<String>) -> Self {
+        match &mut self {
+            Notification::Alert { details, .. }
+            | Notification::Status { details, .. }
+            | Notification::ApprovalRequest { details, .. } => *details = text.into(),
+        }
+        self
+    }
+
+    /// Name the system that raised an alert
+    pub fn with_source(mut self, name: impl Into<String>) -> Self {
+        if let Notification::Alert { source, .. } = &mut self {
+            *source = Some(name.into());
+        }
+        self
+    }
+
+    /// Report progress on a status update, capped at 100
+    pub fn with_progress(mut self, percent: u8) -> Self {
+        if let Notification::Status { progress, .. } = &mut self {
+            *progress = Some(percent.min(100));
+        }
+        self
+    }
+
+    /// Replace the answers offered by an approval request
+    pub fn with_options<S: Into<String>>(mut self, choices: impl IntoIterator<Item = S>) -> Self {
+        if let Notification::ApprovalRequest { options, .. } = &mut self {
+            *options = choices.into_iter().map(Into::into).collect();
+        }
+        self
+    }
+
+    /// Set when an approval request stops accepting answers
+    pub fn expiring_at(mut self, at: DateTime<Utc>) -> Self {
+        if let Notification::ApprovalRequest { expires_at, .. } = &mut self {
+            *expires_at = Some(at);
+        }
+        self
+    }
+
+    /// Name used in the payload
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notification::Alert { .. } => "alert",
+            Notification::Status { .. } => "status",
+            Notification::ApprovalRequest { .. } => "approval_request",
+        }
+    }
+
+    /// One-line summary, used as the email subject
+    pub fn subject(&self) -> String {
+        match self {
+            Notification::Alert { severity, title, .. } => format!("[{}] {}", severity.to_string().to_uppercase(), title),
+            Notification::Status { component, state, progress, .. } => match progress {
+                Some(percent) => format!("{}: {} ({}%)", component, state, percent),
+                None => format!("{}: {}", component, state),
+            },
+            Notification::ApprovalRequest { title, .. } => format!("Approval needed: {}", title),
+        }
+    }
+
+    /// Plain-text rendering for human readers
+    pub fn render_text(&self) -> String {
+        let mut lines = vec![self.subject()];
+        match self {
+            Notification::Alert { details, source, .. } => {
+                push_details(&mut lines, details);
+                if let Some(source) = source {
+                    lines.push(format!("Source: {}", source));
+                }
+            }
+            Notification::Status { details, .. } => push_details(&mut lines, details),
+            Notification::ApprovalRequest { request_id, details, options, expires_at, .. } => {
+                push_details(&mut lines, details);
+                lines.push(String::new());
+                lines.push(format!("Reply with one of: {}", options.join(", ")));
+                if let Some(expires_at) = expires_at {
+                    lines.push(format!("Expires: {}", expires_at.format("%Y-%m-%d %H:%M UTC")));
+                }
+                lines.push(format!("Request ID: {}", request_id));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Wrap the notification in a direct message from `from_entity` to `to_entity`
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(NOTIFICATION_KEY.to_string(), serde_json::to_string(self)?);
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: self.render_text(),
+            message_type: MessageType::Direct,
+            metadata,
+        })
+    }
+
+    /// Extract the notification carried by `message`, if any
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        serde_json::from_str(message.metadata.get(NOTIFICATION_KEY)?).ok()
+    }
+}
+
+fn push_details(lines: &mut Vec<String>, details: &str) {
+    if !details.is_empty() {
+        lines.push(String::new());
+        lines.push(details.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_render_for_people_and_round_trip_for_agents() {
+        let alert = Notification::alert(Severity::Critical, "Disk almost full")
+            .with_details("/var is at 97%")
+            .with_source("monitor-01");
+        assert_eq!(alert.subject(), "[CRITICAL] Disk almost full");
+        assert_eq!(alert.render_text(), "[CRITICAL] Disk almost full\n\n/var is at 97%\nSource: monitor-01");
+
+        let status = Notification::status("backup", "running").with_progress(140);
+        assert_eq!(status.subject(), "backup: running (100%)");
+
+        let approval = Notification::approval_request("Deploy v2.3").with_options(["ship", "hold"]);
+        let message = approval.to_message("release-bot", "alice").unwrap();
+        assert!(message.content.contains("Reply with one of: ship, hold"));
+        assert_eq!(Notification::from_message(&message), Some(approval));
+        assert_eq!(Notification::from_message(&SimpleMessage::new("alice", "bob", "hi")), None);
+
+        let payload: serde_json::Value = serde_json::from_str(&message.metadata[NOTIFICATION_KEY]).unwrap();
+        assert_eq!(payload["kind"], "approval_request");
+    }
+}
@@ -0,0 +1,454 @@
+//! # Organizations
+//!
+//! Groups participants under an organization with shared policy. An
+//! organization owns a domain (proven through a DNS TXT record), lists its
+//! members and their roles, can extend its own trust to verified members,
+//! and carries a policy (allowed transports, minimum security level) that
+//! the router enforces on every outbound message from a member.
+//!
+//! Organizations can be nested. A child organization inherits its parent's
+//! policy and may only tighten it: allowed transports are intersected and the
+//! strictest minimum security level wins.
+
+use crate::contacts::security_rank;
+use crate::error::{Result, SynapseError};
+use crate::synapse::blockchain::serialization::DateTimeWrapper;
+use crate::types::SecurityLevel;
+use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Prefix of the DNS name holding an organization's domain proof
+pub const DOMAIN_PROOF_RECORD_PREFIX: &str = "_synapse-org";
+
+/// Prefix of the TXT value holding an organization's domain proof
+pub const DOMAIN_PROOF_VALUE_PREFIX: &str = "synapse-org-verification=";
+
+/// Role of a member within an organization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrgRole {
+    /// Full control, including policy and membership
+    Owner,
+    /// May manage members
+    Admin,
+    /// Regular member
+    Member,
+}
+
+/// Messaging policy shared by an organization's members
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OrgPolicy {
+    /// Transports members may use (e.g. "tcp", "email"); `None` allows all
+    #[serde(default)]
+    pub allowed_transports: Option<Vec<String>>,
+    /// Minimum security level for messages sent by members
+    #[serde(default)]
+    pub min_security: Option<SecurityLevel>,
+}
+
+impl OrgPolicy {
+    /// Restrict the transports members may use
+    pub fn with_allowed_transports(mut self, transports: Vec<String>) -> Self {
+        self.allowed_transports = Some(transports.into_iter().map(|t| t.to_lowercase()).collect());
+        self
+    }
+
+    /// Require a minimum security level
+    pub fn with_min_security(mut self, level: SecurityLevel) -> Self {
+        self.min_security = Some(level);
+        self
+    }
+
+    /// Combine two policies, keeping the stricter setting of each
+    pub fn merge(&self, other: &OrgPolicy) -> OrgPolicy {
+        let allowed_transports = match (&self.allowed_transports, &other.allowed_transports) {
+            (Some(a), Some(b)) => Some(a.iter().filter(|t| b.contains(t)).cloned().collect()),
+            (Some(a), None) | (None, Some(a)) => Some(a.clone()),
+            (None, None) => None,
+        };
+        let min_security = match (&self.min_security, &other.min_security) {
+            (Some(a), Some(b)) => Some(if security_rank(a) >= security_rank(b) { a.clone() } else { b.clone() }),
+            (Some(a), None) | (None, Some(a)) => Some(a.clone()),
+            (None, None) => None,
+        };
+        OrgPolicy { allowed_transports, min_security }
+    }
+
+    /// Whether the policy permits the named transport
+    pub fn allows_transport(&self, transport: &str) -> bool {
+        self.allowed_transports
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|t| t.eq_ignore_ascii_case(transport)))
+    }
+
+    /// Whether the policy permits any transport other than email
+    pub fn allows_direct_transport(&self) -> bool {
+        self.allowed_transports
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|t| !t.eq_ignore_ascii_case("email")))
+    }
+
+    /// Raise a requested security level to the policy minimum
+    pub fn effective_security(&self, requested: SecurityLevel) -> SecurityLevel {
+        match &self.min_security {
+            Some(min) if security_rank(min) > security_rank(&requested) => min.clone(),
+            _ => requested,
+        }
+    }
+}
+
+/// Proof that an organization controls its domain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainProof {
+    /// Random token the organization must publish
+    pub token: String,
+    /// When the TXT record was last confirmed
+    pub verified_at: Option<DateTimeWrapper>,
+}
+
+/// Resolves DNS TXT records for domain proofs
+#[async_trait]
+pub trait TxtResolver: Send + Sync {
+    /// Return all TXT strings published at `name`
+    async fn lookup_txt(&self, name: &str) -> Result<Vec<String>>;
+}
+
+/// An organization grouping participants under shared policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    /// Unique organization identifier (e.g. "ai-lab")
+    pub id: String,
+    /// Human-readable name
+    pub name: String,
+    /// Domain the organization claims (e.g. "ai-lab.example.com")
+    pub domain: String,
+    /// Parent organization whose policy is inherited
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// Members keyed by global ID
+    #[serde(default)]
+    pub members: HashMap<String, OrgRole>,
+    /// Policy applied to members
+    #[serde(default)]
+    pub policy: OrgPolicy,
+    /// Trust score (0-100) the organization extends to its members
+    #[serde(default)]
+    pub delegated_trust: Option<f64>,
+    /// Domain ownership proof
+    pub domain_proof: DomainProof,
+    /// When the organization was created
+    pub created_at: DateTimeWrapper,
+}
+
+impl Organization {
+    /// Create an organization owned by `owner_global_id`
+    pub fn new(
+        id: impl Into<String>,
+        name: impl Into<String>,
+        domain: impl Into<String>,
+        owner_global_id: impl Into<String>,
+    ) -> Self {
+        let mut members = HashMap::new();
+        members.insert(owner_global_id.into(), OrgRole::Owner);
+        Self {
+            id: id.into(),
+            name: name.into(),
+            domain: domain.into().to_lowercase(),
+            parent: None,
+            members,
+            policy: OrgPolicy::default(),
+            delegated_trust: None,
+            domain_proof: DomainProof {
+                token: uuid::Uuid::new_v4().simple().to_string(),
+                verified_at: None,
+            },
+            created_at: DateTimeWrapper::new(Utc::now()),
+        }
+    }
+
+    /// Nest this organization under a parent
+    pub fn with_parent(mut self, parent_id: impl Into<String>) -> Self {
+        self.parent = Some(parent_id.into());
+        self
+    }
+
+    /// Set the organization's own policy
+    pub fn with_policy(mut self, policy: OrgPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Extend a trust score to verified members
+    pub fn with_delegated_trust(mut self, score: f64) -> Self {
+        self.delegated_trust = Some(score.clamp(0.0, 100.0));
+        self
+    }
+
+    /// DNS name and TXT value that prove domain ownership
+    pub fn dns_challenge(&self) -> (String, String) {
+        (
+            format!("{}.{}", DOMAIN_PROOF_RECORD_PREFIX, self.domain),
+            format!("{}{}", DOMAIN_PROOF_VALUE_PREFIX, self.domain_proof.token),
+        )
+    }
+
+    /// Whether the domain has been proven
+    pub fn is_domain_verified(&self) -> bool {
+        self.domain_proof.verified_at.is_some()
+    }
+
+    /// Role of a participant, if a member
+    pub fn role_of(&self, global_id: &str) -> Option<OrgRole> {
+        self.members.get(global_id).copied()
+    }
+
+    /// Whether a participant may manage membership
+    pub fn can_manage(&self, global_id: &str) -> bool {
+        matches!(self.role_of(global_id), Some(OrgRole::Owner | OrgRole::Admin))
+    }
+}
+
+/// Registry of known organizations and their members
+#[derive(Debug, Default)]
+pub struct OrganizationRegistry {
+    organizations: DashMap<String, Organization>,
+    memberships: DashMap<String, HashSet<String>>,
+}
+
+impl OrganizationRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new organization
+    pub fn register(&self, org: Organization) -> Result<()> {
+        if self.organizations.contains_key(&org.id) {
+            return Err(SynapseError::AlreadyExists(format!("Organization {}", org.id)));
+        }
+        if let Some(parent) = &org.parent {
+            if !self.organizations.contains_key(parent) {
+                return Err(SynapseError::NotFound(format!("Parent organization {}", parent)));
+            }
+        }
+        for member in org.members.keys() {
+            self.memberships.entry(member.clone()).or_default().insert(org.id.clone());
+        }
+        tracing::info!("🏢 Registered organization {} ({})", org.name, org.domain);
+        self.organizations.insert(org.id.clone(), org);
+        Ok(())
+    }
+
+    /// Remove an organization and its memberships
+    pub fn unregister(&self, org_id: &str) -> Option<Organization> {
+        let (_, org) = self.organizations.remove(org_id)?;
+        for member in org.members.keys() {
+            if let Some(mut orgs) = self.memberships.get_mut(member) {
+                orgs.remove(org_id);
+            }
+        }
+        Some(org)
+    }
+
+    /// Look up an organization
+    pub fn get(&self, org_id: &str) -> Option<Organization> {
+        self.organizations.get(org_id).map(|o| o.clone())
+    }
+
+    /// Organizations a participant belongs to
+    pub fn organizations_of(&self, global_id: &str) -> Vec<Organization> {
+        self.memberships
+            .get(global_id)
+            .map(|ids| ids.iter().filter_map(|id| self.get(id)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Add or change a member; `actor` must be an owner or admin
+    pub fn add_member(&self, org_id: &str, actor: &str, global_id: &str, role: OrgRole) -> Result<()> {
+        let mut org = self
+            .organizations
+            .get_mut(org_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Organization {}", org_id)))?;
+        if !org.can_manage(actor) {
+            return Err(SynapseError::AuthorizationError(format!(
+                "{} may not manage members of {}",
+                actor, org_id
+            )));
+        }
+        if role == OrgRole::Owner && org.role_of(actor) != Some(OrgRole::Owner) {
+            return Err(SynapseError::AuthorizationError("Only owners may add owners".to_string()));
+        }
+        org.members.insert(global_id.to_string(), role);
+        self.memberships.entry(global_id.to_string()).or_default().insert(org_id.to_string());
+        Ok(())
+    }
+
+    /// Remove a member; `actor` must be an owner or admin
+    pub fn remove_member(&self, org_id: &str, actor: &str, global_id: &str) -> Result<()> {
+        let mut org = self
+            .organizations
+            .get_mut(org_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Organization {}", org_id)))?;
+        if !org.can_manage(actor) && actor != global_id {
+            return Err(SynapseError::AuthorizationError(format!(
+                "{} may not manage members of {}",
+                actor, org_id
+            )));
+        }
+        let is_last_owner = org.role_of(global_id) == Some(OrgRole::Owner)
+            && org.members.values().filter(|r| **r == OrgRole::Owner).count() == 1;
+        if is_last_owner {
+            return Err(SynapseError::ValidationFailed(format!("{} is the last owner of {}", global_id, org_id)));
+        }
+        org.members.remove(global_id);
+        if let Some(mut orgs) = self.memberships.get_mut(global_id) {
+            orgs.remove(org_id);
+        }
+        Ok(())
+    }
+
+    /// Replace an organization's own policy; `actor` must be an owner
+    pub fn set_policy(&self, org_id: &str, actor: &str, policy: OrgPolicy) -> Result<()> {
+        let mut org = self
+            .organizations
+            .get_mut(org_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Organization {}", org_id)))?;
+        if org.role_of(actor) != Some(OrgRole::Owner) {
+            return Err(SynapseError::AuthorizationError(format!("{} may not change policy of {}", actor, org_id)));
+        }
+        org.policy = policy;
+        Ok(())
+    }
+
+    /// Check the organization's DNS TXT record and mark its domain verified
+    pub async fn verify_domain(&self, org_id: &str, resolver: &dyn TxtResolver) -> Result<bool> {
+        let (name, expected) = self
+            .get(org_id)
+            .map(|o| o.dns_challenge())
+            .ok_or_else(|| SynapseError::NotFound(format!("Organization {}", org_id)))?;
+
+        let records = resolver.lookup_txt(&name).await?;
+        let verified = records.iter().any(|r| r.trim() == expected);
+
+        if let Some(mut org) = self.organizations.get_mut(org_id) {
+            org.domain_proof.verified_at = verified.then(|| DateTimeWrapper::new(Utc::now()));
+        }
+        if verified {
+            tracing::info!("🏢 Verified domain for organization {} via {}", org_id, name);
+        } else {
+            tracing::warn!("🏢 Domain proof for organization {} not found at {}", org_id, name);
+        }
+        Ok(verified)
+    }
+
+    /// Policy of an organization combined with every ancestor's policy
+    pub fn effective_policy(&self, org_id: &str) -> OrgPolicy {
+        let mut policy = OrgPolicy::default();
+        let mut visited = HashSet::new();
+        let mut current = Some(org_id.to_string());
+
+        while let Some(id) = current {
+            if !visited.insert(id.clone()) {
+                break;
+            }
+            match self.organizations.get(&id) {
+                Some(org) => {
+                    policy = policy.merge(&org.policy);
+                    current = org.parent.clone();
+                }
+                None => break,
+            }
+        }
+        policy
+    }
+
+    /// Combined policy of every organization a participant belongs to
+    pub fn policy_for(&self, global_id: &str) -> OrgPolicy {
+        self.memberships
+            .get(global_id)
+            .map(|ids| {
+                ids.iter()
+                    .fold(OrgPolicy::default(), |acc, id| acc.merge(&self.effective_policy(id)))
+            })
+            .unwrap_or_default()
+    }
+
+    /// Highest trust extended to a participant by a domain-verified organization
+    pub fn delegated_trust(&self, global_id: &str) -> Option<f64> {
+        self.organizations_of(global_id)
+            .iter()
+            .filter(|org| org.is_domain_verified())
+            .filter_map(|org| org.delegated_trust)
+            .reduce(f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticResolver(Vec<String>);
+
+    #[async_trait]
+    impl TxtResolver for StaticResolver {
+        async fn lookup_txt(&self, _name: &str) -> Result<Vec<String>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_policy_inheritance_only_tightens() {
+        let registry = OrganizationRegistry::new();
+        registry
+            .register(
+                Organization::new("acme", "Acme", "acme.example.com", "ceo@acme.example.com").with_policy(
+                    OrgPolicy::default()
+                        .with_allowed_transports(vec!["tcp".into(), "email".into()])
+                        .with_min_security(SecurityLevel::Authenticated),
+                ),
+            )
+            .unwrap();
+        registry
+            .register(
+                Organization::new("acme-research", "Acme Research", "research.acme.example.com", "lead@acme.example.com")
+                    .with_parent("acme")
+                    .with_policy(
+                        OrgPolicy::default()
+                            .with_allowed_transports(vec!["email".into(), "quic".into()])
+                            .with_min_security(SecurityLevel::Public),
+                    ),
+            )
+            .unwrap();
+
+        let policy = registry.policy_for("lead@acme.example.com");
+        assert_eq!(policy.allowed_transports, Some(vec!["email".to_string()]));
+        assert!(!policy.allows_direct_transport());
+        assert!(matches!(policy.effective_security(SecurityLevel::Public), SecurityLevel::Authenticated));
+
+        assert_eq!(registry.policy_for("stranger@example.com"), OrgPolicy::default());
+    }
+
+    #[tokio::test]
+    async fn test_domain_proof_gates_delegated_trust() {
+        let registry = OrganizationRegistry::new();
+        let org = Organization::new("acme", "Acme", "acme.example.com", "ceo@acme.example.com").with_delegated_trust(80.0);
+        let (name, value) = org.dns_challenge();
+        assert_eq!(name, "_synapse-org.acme.example.com");
+        registry.register(org).unwrap();
+        registry
+            .add_member("acme", "ceo@acme.example.com", "dev@acme.example.com", OrgRole::Member)
+            .unwrap();
+
+        assert!(!registry.verify_domain("acme", &StaticResolver(vec!["v=spf1".into()])).await.unwrap());
+        assert_eq!(registry.delegated_trust("dev@acme.example.com"), None);
+
+        assert!(registry.verify_domain("acme", &StaticResolver(vec![value])).await.unwrap());
+        assert_eq!(registry.delegated_trust("dev@acme.example.com"), Some(80.0));
+
+        assert!(registry
+            .add_member("acme", "dev@acme.example.com", "intruder@example.com", OrgRole::Admin)
+            .is_err());
+    }
+}
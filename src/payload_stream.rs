@@ -0,0 +1,662 @@
+//! # Payload Streams
+//!
+//! Continuous data between two participants — audio frames, tokens streamed
+//! out of a model, sensor readings — does not fit the one-message-per-send
+//! model. A payload stream is an ordered, flow-controlled byte pipe opened
+//! with [`EnhancedSynapseRouter::open_stream`](crate::router_enhanced::EnhancedSynapseRouter::open_stream)
+//! and carried as [`StreamChunk`]s over the direct transports (QUIC, TCP,
+//! WebSocket), multiplexed by stream ID. Each side gets a [`PayloadStream`]
+//! implementing [`AsyncRead`] and [`AsyncWrite`].
+//!
+//! | Chunk type | `sequence_number`              | Meaning                          |
+//! |------------|--------------------------------|----------------------------------|
+//! | `open`     | 0                              | The peer opened a new stream     |
+//! | `data`     | index of the data chunk        | Payload bytes                    |
+//! | `ack`      | total bytes read by the peer   | Grants more credit to the writer |
+//! | `end`      | number of data chunks sent     | Graceful close of one direction  |
+//! | `reset`    | 0                              | Abort; both directions fail      |
+//!
+//! Flow control is credit based: a writer never has more than
+//! [`PayloadStreamConfig::window`] bytes unread by the peer, and
+//! `poll_write` stays pending until acks free up room. Chunks arriving out of
+//! order are reordered before they are readable. Shutting down the writer
+//! sends `end` and the peer's reader sees EOF once everything before it was
+//! read; dropping a reader before EOF resets the stream, so the other side's
+//! writes fail instead of waiting for credit that never comes.
+
+use crate::config::PayloadStreamConfig;
+use crate::error::{Result, SynapseError};
+use crate::types::{MessageType, SimpleMessage, StreamChunk, StreamPriority};
+use async_trait::async_trait;
+use base64::Engine as _;
+use dashmap::DashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+use tracing::debug;
+use uuid::Uuid;
+
+/// Metadata key marking a message as a payload stream chunk
+pub const PAYLOAD_STREAM_KEY: &str = "payload_stream";
+
+/// Delivers the chunks of a stream to its peer
+#[async_trait]
+pub trait ChunkSender: Send + Sync {
+    /// Send one chunk message to the peer
+    async fn send(&self, message: SimpleMessage) -> Result<()>;
+}
+
+/// Wrap a chunk in a message from `from_entity` to `to_entity`
+pub fn to_message(chunk: &StreamChunk, from_entity: &str, to_entity: &str) -> SimpleMessage {
+    let mut metadata = HashMap::new();
+    metadata.insert(PAYLOAD_STREAM_KEY.to_string(), chunk.stream_id.to_string());
+    SimpleMessage {
+        to: to_entity.to_string(),
+        from_entity: from_entity.to_string(),
+        content: serde_json::to_string(chunk).unwrap_or_default(),
+        message_type: MessageType::StreamChunk,
+        metadata,
+    }
+}
+
+/// Extract the chunk from a message, if it carries one
+pub fn chunk_from_message(message: &SimpleMessage) -> Option<StreamChunk> {
+    message.metadata.get(PAYLOAD_STREAM_KEY)?;
+    serde_json::from_str(&message.content).ok()
+}
+
+fn control_chunk(stream_id: Uuid, chunk_type: &str, sequence_number: u64) -> StreamChunk {
+    let mut chunk = StreamChunk::new_data(stream_id, sequence_number, String::new(), StreamPriority::RealTime);
+    chunk.chunk_type = chunk_type.to_string();
+    chunk
+}
+
+#[derive(Default)]
+struct StreamState {
+    // Write half
+    written: u64,
+    acked: u64,
+    next_write_seq: u64,
+    write_closed: bool,
+    write_waker: Option<Waker>,
+    // Read half
+    next_read_seq: u64,
+    out_of_order: BTreeMap<u64, Vec<u8>>,
+    readable: VecDeque<u8>,
+    buffered: usize,
+    end_at: Option<u64>,
+    consumed: u64,
+    acked_consumed: u64,
+    read_closed: bool,
+    read_waker: Option<Waker>,
+    reset: bool,
+}
+
+impl StreamState {
+    fn wake_all(&mut self) {
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn fully_closed(&self) -> bool {
+        self.reset || (self.write_closed && self.read_closed)
+    }
+}
+
+struct StreamShared {
+    id: Uuid,
+    peer: String,
+    window: u64,
+    chunk_size: usize,
+    state: Mutex<StreamState>,
+    outbound: mpsc::UnboundedSender<StreamChunk>,
+    streams: Weak<DashMap<Uuid, Arc<StreamShared>>>,
+}
+
+impl StreamShared {
+    fn send(&self, chunk: StreamChunk) {
+        // The send task is gone only once the stream was reset
+        let _ = self.outbound.send(chunk);
+    }
+
+    fn reset(&self, notify_peer: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.reset {
+            return;
+        }
+        state.reset = true;
+        state.wake_all();
+        drop(state);
+        if notify_peer {
+            self.send(control_chunk(self.id, "reset", 0));
+        }
+        self.forget();
+    }
+
+    fn forget(&self) {
+        if let Some(streams) = self.streams.upgrade() {
+            streams.remove(&self.id);
+        }
+    }
+
+    fn forget_if_closed(&self, state: &StreamState) {
+        if state.fully_closed() {
+            self.forget();
+        }
+    }
+
+    /// Apply a chunk from the peer
+    fn receive(&self, chunk: StreamChunk) {
+        let mut state = self.state.lock().unwrap();
+        if state.reset {
+            return;
+        }
+        match chunk.chunk_type.as_str() {
+            "data" => {
+                let Ok(data) = base64::engine::general_purpose::STANDARD.decode(&chunk.data) else {
+                    drop(state);
+                    debug!("Resetting stream {}: undecodable chunk", self.id);
+                    return self.reset(true);
+                };
+                let sequence = chunk.sequence_number;
+                if sequence < state.next_read_seq || state.out_of_order.contains_key(&sequence) {
+                    return;
+                }
+                // A well-behaved writer never exceeds the window we granted
+                if (state.buffered + data.len()) as u64 > self.window {
+                    drop(state);
+                    debug!("Resetting stream {}: peer overran the flow control window", self.id);
+                    return self.reset(true);
+                }
+                state.buffered += data.len();
+                state.out_of_order.insert(sequence, data);
+                let state = &mut *state;
+                while let Some(data) = state.out_of_order.remove(&state.next_read_seq) {
+                    state.readable.extend(data);
+                    state.next_read_seq += 1;
+                }
+                if let Some(waker) = state.read_waker.take() {
+                    waker.wake();
+                }
+            }
+            "ack" => {
+                if chunk.sequence_number > state.acked {
+                    state.acked = chunk.sequence_number.min(state.written);
+                    if let Some(waker) = state.write_waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+            "end" => {
+                state.end_at = Some(chunk.sequence_number);
+                if let Some(waker) = state.read_waker.take() {
+                    waker.wake();
+                }
+            }
+            "reset" => {
+                drop(state);
+                self.reset(false);
+            }
+            _ => {}
+        }
+    }
+
+    fn poll_read(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.state.lock().unwrap();
+        if state.reset {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::ConnectionReset, "payload stream was reset")));
+        }
+        if state.readable.is_empty() {
+            if state.end_at.is_some_and(|end| state.next_read_seq >= end) {
+                state.read_closed = true;
+                self.forget_if_closed(&state);
+                return Poll::Ready(Ok(()));
+            }
+            state.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = buf.remaining().min(state.readable.len());
+        let (front, back) = state.readable.as_slices();
+        let from_front = n.min(front.len());
+        buf.put_slice(&front[..from_front]);
+        buf.put_slice(&back[..n - from_front]);
+        state.readable.drain(..n);
+        state.buffered -= n;
+        state.consumed += n as u64;
+
+        // Return credit once half the window has been read
+        if state.consumed - state.acked_consumed >= self.window / 2 {
+            state.acked_consumed = state.consumed;
+            let consumed = state.consumed;
+            drop(state);
+            self.send(control_chunk(self.id, "ack", consumed));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut state = self.state.lock().unwrap();
+        if state.reset {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::ConnectionReset, "payload stream was reset")));
+        }
+        if state.write_closed {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, "payload stream was shut down")));
+        }
+        let credit = self.window.saturating_sub(state.written - state.acked) as usize;
+        if credit == 0 {
+            state.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = buf.len().min(credit).min(self.chunk_size);
+        let data = base64::engine::general_purpose::STANDARD.encode(&buf[..n]);
+        let chunk = StreamChunk::new_data(self.id, state.next_write_seq, data, StreamPriority::RealTime);
+        state.next_write_seq += 1;
+        state.written += n as u64;
+        drop(state);
+        self.send(chunk);
+        Poll::Ready(Ok(n))
+    }
+
+    fn shutdown_write(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.write_closed || state.reset {
+            return;
+        }
+        state.write_closed = true;
+        let end = StreamChunk::new_final(self.id, state.next_write_seq);
+        self.forget_if_closed(&state);
+        drop(state);
+        self.send(end);
+    }
+
+    fn close_read(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.read_closed || state.reset {
+            return;
+        }
+        let reached_end = state.readable.is_empty() && state.end_at.is_some_and(|end| state.next_read_seq >= end);
+        if !reached_end {
+            // The peer would otherwise keep waiting for credit
+            drop(state);
+            return self.reset(true);
+        }
+        state.read_closed = true;
+        self.forget_if_closed(&state);
+    }
+}
+
+/// Open and accept payload streams, and route incoming chunks to them
+pub struct PayloadStreams {
+    config: PayloadStreamConfig,
+    streams: Arc<DashMap<Uuid, Arc<StreamShared>>>,
+    incoming_tx: mpsc::Sender<PayloadStream>,
+    incoming_rx: tokio::sync::Mutex<mpsc::Receiver<PayloadStream>>,
+}
+
+impl PayloadStreams {
+    /// Create a hub with the given limits
+    pub fn new(config: PayloadStreamConfig) -> Self {
+        let (incoming_tx, incoming_rx) = mpsc::channel(config.max_streams.max(1));
+        Self {
+            config,
+            streams: Arc::new(DashMap::new()),
+            incoming_tx,
+            incoming_rx: tokio::sync::Mutex::new(incoming_rx),
+        }
+    }
+
+    /// Streams currently open in either direction
+    pub fn active(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Open a stream from `from_entity` to `to_entity`, sending chunks through `sender`
+    pub fn open(&self, from_entity: &str, to_entity: &str, sender: Arc<dyn ChunkSender>) -> Result<PayloadStream> {
+        if self.streams.len() >= self.config.max_streams {
+            return Err(SynapseError::TransportError(format!(
+                "At most {} payload streams may be open",
+                self.config.max_streams
+            )));
+        }
+        let stream = self.register(Uuid::new_v4(), from_entity, to_entity, sender);
+        stream.shared.send(control_chunk(stream.id(), "open", 0));
+        Ok(stream)
+    }
+
+    /// Wait for the next stream a peer opens to us
+    ///
+    /// Returns `None` once the hub is dropped.
+    pub async fn accept(&self) -> Option<PayloadStream> {
+        self.incoming_rx.lock().await.recv().await
+    }
+
+    /// Consume `message` if it is a stream chunk
+    ///
+    /// `reply` supplies the sender for a stream the peer opens; returning
+    /// `None` refuses the stream. Returns `true` when the message was a chunk
+    /// and should not be delivered as an ordinary message.
+    pub fn handle(&self, message: &SimpleMessage, reply: impl FnOnce() -> Option<Arc<dyn ChunkSender>>) -> bool {
+        if !message.metadata.contains_key(PAYLOAD_STREAM_KEY) {
+            return false;
+        }
+        let Some(chunk) = chunk_from_message(message) else {
+            return true;
+        };
+
+        if let Some(shared) = self.streams.get(&chunk.stream_id).map(|s| s.clone()) {
+            // Only the participant the stream was opened with may feed it
+            if shared.peer == message.from_entity {
+                shared.receive(chunk);
+            }
+            return true;
+        }
+        if chunk.chunk_type != "open" {
+            return true;
+        }
+
+        let refusal = control_chunk(chunk.stream_id, "reset", 0);
+        let Some(sender) = reply() else {
+            return true;
+        };
+        if self.streams.len() >= self.config.max_streams {
+            debug!("Refusing payload stream from {}: too many open streams", message.from_entity);
+            let reply_message = to_message(&refusal, &message.to, &message.from_entity);
+            tokio::spawn(async move {
+                let _ = sender.send(reply_message).await;
+            });
+            return true;
+        }
+        let stream = self.register(chunk.stream_id, &message.to, &message.from_entity, sender);
+        // If nobody is accepting streams the refused one is dropped, which resets it
+        let _ = self.incoming_tx.try_send(stream);
+        true
+    }
+
+    fn register(&self, id: Uuid, local: &str, peer: &str, sender: Arc<dyn ChunkSender>) -> PayloadStream {
+        let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<StreamChunk>();
+        let shared = Arc::new(StreamShared {
+            id,
+            peer: peer.to_string(),
+            window: self.config.window.max(1) as u64,
+            chunk_size: self.config.chunk_size.max(1),
+            state: Mutex::new(StreamState::default()),
+            outbound,
+            streams: Arc::downgrade(&self.streams),
+        });
+        self.streams.insert(id, shared.clone());
+
+        let task_shared = Arc::downgrade(&shared);
+        let (local, peer) = (local.to_string(), peer.to_string());
+        tokio::spawn(async move {
+            while let Some(chunk) = outbound_rx.recv().await {
+                let is_reset = chunk.chunk_type == "reset";
+                if let Err(e) = sender.send(to_message(&chunk, &local, &peer)).await {
+                    debug!("Payload stream {} to {} failed: {}", id, peer, e);
+                    if let Some(shared) = task_shared.upgrade() {
+                        shared.reset(false);
+                    }
+                    break;
+                }
+                if is_reset {
+                    break;
+                }
+            }
+        });
+
+        PayloadStream {
+            reader: PayloadReader { shared: shared.clone() },
+            writer: PayloadWriter { shared },
+        }
+    }
+}
+
+/// A bidirectional byte stream to a peer
+pub struct PayloadStream {
+    reader: PayloadReader,
+    writer: PayloadWriter,
+}
+
+impl PayloadStream {
+    /// Stream identifier shared by both ends
+    pub fn id(&self) -> Uuid {
+        self.reader.shared.id
+    }
+
+    /// The participant on the other end
+    pub fn peer(&self) -> &str {
+        &self.reader.shared.peer
+    }
+
+    /// Split into halves that can be used from different tasks
+    pub fn split(self) -> (PayloadReader, PayloadWriter) {
+        (self.reader, self.writer)
+    }
+}
+
+impl AsyncRead for PayloadStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PayloadStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+/// Receiving half of a payload stream
+///
+/// Dropping it before EOF resets the stream.
+pub struct PayloadReader {
+    shared: Arc<StreamShared>,
+}
+
+impl AsyncRead for PayloadReader {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        self.shared.poll_read(cx, buf)
+    }
+}
+
+impl Drop for PayloadReader {
+    fn drop(&mut self) {
+        self.shared.close_read();
+    }
+}
+
+/// Sending half of a payload stream
+///
+/// Shutting it down, or dropping it, ends this direction of the stream.
+pub struct PayloadWriter {
+    shared: Arc<StreamShared>,
+}
+
+impl AsyncWrite for PayloadWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.shared.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Every write is handed to the transport as its own chunk
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.shared.shutdown_write();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for PayloadWriter {
+    fn drop(&mut self) {
+        self.shared.shutdown_write();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Hands chunks straight to the other hub, optionally swapping pairs of them
+    struct Loopback {
+        peer: Arc<OnceLock<Arc<PayloadStreams>>>,
+        back: Arc<dyn ChunkSender>,
+        held: Mutex<Option<SimpleMessage>>,
+        reorder: bool,
+    }
+
+    #[async_trait]
+    impl ChunkSender for Loopback {
+        async fn send(&self, message: SimpleMessage) -> Result<()> {
+            let peer = self.peer.get().unwrap();
+            let mut deliver = vec![message];
+            if self.reorder && deliver[0].content.contains("\"chunk_type\":\"data\"") {
+                let mut held = self.held.lock().unwrap();
+                match held.take() {
+                    Some(earlier) => deliver.push(earlier),
+                    None => {
+                        *held = deliver.pop();
+                    }
+                }
+            }
+            for message in deliver {
+                let back = self.back.clone();
+                peer.handle(&message, || Some(back));
+            }
+            Ok(())
+        }
+    }
+
+    struct Unreachable;
+
+    #[async_trait]
+    impl ChunkSender for Unreachable {
+        async fn send(&self, _message: SimpleMessage) -> Result<()> {
+            Err(SynapseError::NoTransportAvailable("unreachable".to_string()))
+        }
+    }
+
+    fn config(chunk_size: usize, window: usize) -> PayloadStreamConfig {
+        PayloadStreamConfig { chunk_size, window, max_streams: 4 }
+    }
+
+    fn pair(config: PayloadStreamConfig, reorder: bool) -> (Arc<PayloadStreams>, Arc<PayloadStreams>, Arc<Loopback>) {
+        let a = Arc::new(PayloadStreams::new(config.clone()));
+        let b = Arc::new(PayloadStreams::new(config));
+        let (to_a, to_b) = (Arc::new(OnceLock::new()), Arc::new(OnceLock::new()));
+        to_a.set(a.clone()).ok();
+        to_b.set(b.clone()).ok();
+        let b_to_a: Arc<dyn ChunkSender> = Arc::new(Loopback {
+            peer: to_a,
+            back: Arc::new(Unreachable),
+            held: Mutex::new(None),
+            reorder: false,
+        });
+        let a_to_b = Arc::new(Loopback { peer: to_b, back: b_to_a, held: Mutex::new(None), reorder });
+        (a, b, a_to_b)
+    }
+
+    #[tokio::test]
+    async fn large_transfer_respects_a_small_window() {
+        let (a, b, a_to_b) = pair(config(1024, 4096), false);
+        let stream = a.open("alice", "bob", a_to_b).unwrap();
+        let mut incoming = b.accept().await.unwrap();
+        assert_eq!(incoming.id(), stream.id());
+        assert_eq!(incoming.peer(), "alice");
+
+        let payload: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let expected = payload.clone();
+        let (_reader, mut writer) = stream.split();
+        let write = tokio::spawn(async move {
+            writer.write_all(&payload).await.unwrap();
+            writer.shutdown().await.unwrap();
+        });
+
+        let mut received = Vec::new();
+        incoming.read_to_end(&mut received).await.unwrap();
+        write.await.unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn writer_waits_for_credit() {
+        let (a, b, a_to_b) = pair(config(512, 1024), false);
+        let mut stream = a.open("alice", "bob", a_to_b).unwrap();
+        let mut incoming = b.accept().await.unwrap();
+
+        stream.write_all(&[7u8; 1024]).await.unwrap();
+        let blocked = tokio::time::timeout(std::time::Duration::from_millis(50), stream.write_all(&[7u8; 1])).await;
+        assert!(blocked.is_err(), "write beyond the window must wait for an ack");
+
+        let mut buf = [0u8; 1024];
+        incoming.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&[8u8; 1]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn out_of_order_chunks_are_reassembled() {
+        let (a, b, a_to_b) = pair(config(4, 1024), true);
+        let stream = a.open("alice", "bob", a_to_b).unwrap();
+        let mut incoming = b.accept().await.unwrap();
+
+        let (_reader, mut writer) = stream.split();
+        writer.write_all(b"0123456789abcdef").await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let mut received = Vec::new();
+        incoming.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"0123456789abcdef");
+    }
+
+    #[tokio::test]
+    async fn dropping_the_reader_resets_the_writer() {
+        let (a, b, a_to_b) = pair(config(64, 128), false);
+        let mut stream = a.open("alice", "bob", a_to_b).unwrap();
+        let incoming = b.accept().await.unwrap();
+        stream.write_all(&[1u8; 128]).await.unwrap();
+
+        drop(incoming);
+        let err = tokio::time::timeout(std::time::Duration::from_secs(1), stream.write_all(&[1u8; 64]))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(a.active(), 0);
+        assert_eq!(b.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn streams_from_other_participants_are_ignored() {
+        let (a, b, a_to_b) = pair(config(64, 128), false);
+        let stream = a.open("alice", "bob", a_to_b).unwrap();
+        let mut incoming = b.accept().await.unwrap();
+
+        let forged = StreamChunk::new_data(stream.id(), 0, "aGVsbG8=", StreamPriority::RealTime);
+        assert!(b.handle(&to_message(&forged, "mallory", "bob"), || None));
+
+        let mut buf = [0u8; 5];
+        let read = tokio::time::timeout(std::time::Duration::from_millis(50), incoming.read(&mut buf)).await;
+        assert!(read.is_err());
+        assert!(!b.handle(&SimpleMessage::new("bob", "alice", "hi"), || None));
+    }
+}
@@ -0,0 +1,581 @@
+//! # Destination Policies
+//!
+//! Declarative rules constraining how messages may reach a destination
+//! domain, evaluated by the router before any transport is selected:
+//!
+//! ```text
+//! *.bank.example  -> require SecurityLevel::Secure, never email
+//! *  (external)   -> no UDP
+//! *  (eu peers)   -> relays and archives in eu only
+//! ```
+//!
+//! Rules are plain serde data so they can be loaded from JSON. Every
+//! evaluation that denies a message produces a typed
+//! [`SynapseError::PolicyViolation`] and an audit event that subscribers can
+//! record.
+
+use crate::contacts::security_rank;
+use crate::error::{Result, SynapseError};
+use crate::geo;
+use crate::transport::{NatMethod, TransportRoute};
+use crate::types::SecurityLevel;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::RwLock;
+use tokio::sync::broadcast;
+
+/// Networks a rule's transport restrictions apply to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkScope {
+    /// Any network
+    #[default]
+    Any,
+    /// Loopback, private and link-local addresses, and mDNS peers
+    Local,
+    /// Everything else, including email
+    External,
+}
+
+impl NetworkScope {
+    fn covers(self, network: NetworkScope) -> bool {
+        self == NetworkScope::Any || self == network
+    }
+}
+
+/// A single declarative destination rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Rule name reported in violations and audit events
+    pub name: String,
+    /// Destination domain pattern: "*", "bank.example" or "*.bank.example"
+    pub destination: String,
+    /// Networks the transport restrictions apply to
+    #[serde(default)]
+    pub network: NetworkScope,
+    /// Minimum security level for matching messages
+    #[serde(default)]
+    pub min_security: Option<SecurityLevel>,
+    /// Transports that must not be used (e.g. "email", "udp")
+    #[serde(default)]
+    pub denied_transports: Vec<String>,
+    /// Only apply to destinations registered in these regions (empty: any)
+    #[serde(default)]
+    pub destination_regions: Vec<String>,
+    /// Regions that relays and archives handling matching messages must be in
+    /// (empty: anywhere)
+    #[serde(default)]
+    pub residency: Vec<String>,
+}
+
+impl PolicyRule {
+    /// Create a rule for a destination pattern with no restrictions
+    pub fn new(name: impl Into<String>, destination: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            destination: destination.into().to_lowercase(),
+            network: NetworkScope::Any,
+            min_security: None,
+            denied_transports: Vec::new(),
+            destination_regions: Vec::new(),
+            residency: Vec::new(),
+        }
+    }
+
+    /// Require a minimum security level
+    pub fn require_security(mut self, level: SecurityLevel) -> Self {
+        self.min_security = Some(level);
+        self
+    }
+
+    /// Forbid a transport
+    pub fn deny_transport(mut self, transport: impl Into<String>) -> Self {
+        self.denied_transports.push(transport.into().to_lowercase());
+        self
+    }
+
+    /// Limit transport restrictions to a network scope
+    pub fn on_network(mut self, network: NetworkScope) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Only apply to destinations registered in a region, e.g. "eu"
+    pub fn for_region(mut self, region: impl Into<String>) -> Self {
+        self.destination_regions.push(geo::normalize(&region.into()));
+        self
+    }
+
+    /// Restrict relays and archives for matching messages to a region
+    pub fn require_residency(mut self, region: impl Into<String>) -> Self {
+        self.residency.push(geo::normalize(&region.into()));
+        self
+    }
+
+    /// Whether the rule applies to a destination global ID
+    pub fn matches(&self, destination: &str) -> bool {
+        let domain = destination.rsplit('@').next().unwrap_or(destination).to_lowercase();
+        match self.destination.as_str() {
+            "*" => true,
+            pattern => match pattern.strip_prefix("*.") {
+                Some(suffix) => domain.ends_with(&format!(".{}", suffix)),
+                None => domain == pattern,
+            },
+        }
+    }
+
+    fn denies(&self, transport: &str, network: NetworkScope) -> bool {
+        self.network.covers(network) && self.denied_transports.iter().any(|t| t.eq_ignore_ascii_case(transport))
+    }
+
+    fn applies_in(&self, region: Option<&str>) -> bool {
+        self.destination_regions.is_empty() || geo::within_any(region, &self.destination_regions)
+    }
+
+    fn excludes_region(&self, region: Option<&str>) -> bool {
+        !self.residency.is_empty() && !geo::within_any(region, &self.residency)
+    }
+}
+
+/// Outcome recorded in a policy audit event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyOutcome {
+    /// A message was allowed after matching one or more rules
+    Allowed,
+    /// A message or route was denied
+    Denied,
+}
+
+/// Audit record of a policy decision
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyAuditEvent {
+    pub timestamp: DateTime<Utc>,
+    pub sender: String,
+    pub destination: String,
+    /// Rule that decided the outcome, if any
+    pub rule: Option<String>,
+    /// Transport under consideration, if the decision was about a route
+    pub transport: Option<String>,
+    pub outcome: PolicyOutcome,
+    pub reason: String,
+}
+
+/// Rules matched for one message, used to vet transports as they are chosen
+#[derive(Debug, Clone)]
+pub struct PolicyDecision {
+    sender: String,
+    destination: String,
+    rules: Vec<PolicyRule>,
+}
+
+impl PolicyDecision {
+    /// Rule denying a transport on a network, if any
+    fn denying_rule(&self, transport: &str, network: NetworkScope) -> Option<&PolicyRule> {
+        self.rules.iter().find(|r| r.denies(transport, network))
+    }
+
+    /// Whether a transport may be used on a network
+    pub fn permits(&self, transport: &str, network: NetworkScope) -> bool {
+        self.denying_rule(transport, network).is_none()
+    }
+
+    /// Rule keeping data out of a region, if any. Unknown regions are
+    /// excluded whenever residency is restricted.
+    fn excluding_rule(&self, region: Option<&str>) -> Option<&PolicyRule> {
+        self.rules.iter().find(|r| r.excludes_region(region))
+    }
+
+    /// Whether a relay or archive in `region` may handle the message
+    pub fn permits_region(&self, region: Option<&str>) -> bool {
+        self.excluding_rule(region).is_none()
+    }
+
+    /// Regions data for this message must stay in, if restricted
+    pub fn allowed_regions(&self) -> Option<Vec<String>> {
+        let mut regions: Vec<String> = self.rules.iter().flat_map(|r| r.residency.iter().cloned()).collect();
+        if regions.is_empty() {
+            return None;
+        }
+        regions.sort();
+        regions.dedup();
+        Some(regions)
+    }
+
+    /// Whether a selected route may be used
+    pub fn permits_route(&self, route: &TransportRoute) -> bool {
+        self.permits(route_transport(route), route_network(route))
+            && route_relay_region(route).is_none_or(|region| self.permits_region(region.as_deref()))
+    }
+
+    /// Whether email delivery may be used
+    pub fn permits_email(&self) -> bool {
+        self.permits("email", NetworkScope::External)
+    }
+}
+
+/// Evaluates destination rules and publishes audit events
+pub struct PolicyEngine {
+    rules: RwLock<Vec<PolicyRule>>,
+    participant_regions: RwLock<HashMap<String, String>>,
+    audit_sender: broadcast::Sender<PolicyAuditEvent>,
+}
+
+impl PolicyEngine {
+    /// Create an engine with no rules (everything allowed)
+    pub fn new() -> Self {
+        let (audit_sender, _) = broadcast::channel(1000);
+        Self {
+            rules: RwLock::new(Vec::new()),
+            participant_regions: RwLock::new(HashMap::new()),
+            audit_sender,
+        }
+    }
+
+    /// Create an engine from a JSON array of rules
+    pub fn from_json(json: &str) -> Result<Self> {
+        let engine = Self::new();
+        engine.set_rules(serde_json::from_str(json)?);
+        Ok(engine)
+    }
+
+    /// Append a rule
+    pub fn add_rule(&self, rule: PolicyRule) {
+        self.rules.write().unwrap().push(rule);
+    }
+
+    /// Replace all rules
+    pub fn set_rules(&self, rules: Vec<PolicyRule>) {
+        *self.rules.write().unwrap() = rules;
+    }
+
+    /// Current rules
+    pub fn rules(&self) -> Vec<PolicyRule> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// Record the region a participant is registered in, for region rules
+    pub fn set_participant_region(&self, global_id: &str, region: Option<&str>) {
+        let mut regions = self.participant_regions.write().unwrap();
+        match region {
+            Some(region) => regions.insert(global_id.to_lowercase(), geo::normalize(region)),
+            None => regions.remove(&global_id.to_lowercase()),
+        };
+    }
+
+    /// Region a participant is registered in, if known
+    pub fn participant_region(&self, global_id: &str) -> Option<String> {
+        self.participant_regions.read().unwrap().get(&global_id.to_lowercase()).cloned()
+    }
+
+    /// Subscribe to policy audit events
+    pub fn subscribe_audit(&self) -> broadcast::Receiver<PolicyAuditEvent> {
+        self.audit_sender.subscribe()
+    }
+
+    /// Check a message against every matching rule before transport selection
+    pub fn evaluate(&self, sender: &str, destination: &str, security_level: &SecurityLevel) -> Result<PolicyDecision> {
+        let decision = self.residency(sender, destination);
+
+        for rule in &decision.rules {
+            if let Some(min) = &rule.min_security {
+                if security_rank(security_level) < security_rank(min) {
+                    return Err(self.deny(
+                        &decision,
+                        rule,
+                        None,
+                        format!("requires {:?} security, message requested {:?}", min, security_level),
+                    ));
+                }
+            }
+        }
+
+        if let Some(rule) = decision.rules.first() {
+            self.audit(PolicyAuditEvent {
+                timestamp: Utc::now(),
+                sender: decision.sender.clone(),
+                destination: decision.destination.clone(),
+                rule: Some(rule.name.clone()),
+                transport: None,
+                outcome: PolicyOutcome::Allowed,
+                reason: format!("{} rule(s) matched", decision.rules.len()),
+            });
+        }
+
+        Ok(decision)
+    }
+
+    /// Rules matched for a participant without the per-message security
+    /// checks, for relays and archives vetting where its data may be held
+    pub fn residency(&self, sender: &str, destination: &str) -> PolicyDecision {
+        let region = self.participant_region(destination);
+        let rules = self
+            .rules
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|r| r.matches(destination) && r.applies_in(region.as_deref()))
+            .cloned()
+            .collect();
+
+        PolicyDecision {
+            sender: sender.to_string(),
+            destination: destination.to_string(),
+            rules,
+        }
+    }
+
+    /// Produce the violation for a transport the decision forbids
+    pub fn check_transport(&self, decision: &PolicyDecision, transport: &str, network: NetworkScope) -> Result<()> {
+        match decision.denying_rule(transport, network) {
+            Some(rule) => Err(self.deny(
+                decision,
+                rule,
+                Some(transport),
+                format!("{} is not permitted on {:?} networks", transport, network),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Produce the violation for a route the decision forbids
+    pub fn check_route(&self, decision: &PolicyDecision, route: &TransportRoute) -> Result<()> {
+        self.check_transport(decision, route_transport(route), route_network(route))?;
+        match route_relay_region(route) {
+            Some(region) => self.check_residency(decision, route_transport(route), region.as_deref()),
+            None => Ok(()),
+        }
+    }
+
+    /// Produce the violation for a relay or archive outside the permitted
+    /// regions. `subsystem` names what would handle the data, e.g. "relay".
+    pub fn check_residency(&self, decision: &PolicyDecision, subsystem: &str, region: Option<&str>) -> Result<()> {
+        match decision.excluding_rule(region) {
+            Some(rule) => Err(self.deny(
+                decision,
+                rule,
+                Some(subsystem),
+                format!(
+                    "{} in region {} is outside {}",
+                    subsystem,
+                    region.unwrap_or("unknown"),
+                    rule.residency.join(", ")
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn deny(&self, decision: &PolicyDecision, rule: &PolicyRule, transport: Option<&str>, reason: String) -> SynapseError {
+        tracing::warn!(
+            "🚫 Policy {} denied message from {} to {}: {}",
+            rule.name, decision.sender, decision.destination, reason
+        );
+        self.audit(PolicyAuditEvent {
+            timestamp: Utc::now(),
+            sender: decision.sender.clone(),
+            destination: decision.destination.clone(),
+            rule: Some(rule.name.clone()),
+            transport: transport.map(str::to_string),
+            outcome: PolicyOutcome::Denied,
+            reason: reason.clone(),
+        });
+        SynapseError::PolicyViolation {
+            rule: rule.name.clone(),
+            destination: decision.destination.clone(),
+            reason,
+        }
+    }
+
+    fn audit(&self, event: PolicyAuditEvent) {
+        // No subscribers is not an error
+        let _ = self.audit_sender.send(event);
+    }
+}
+
+impl Default for PolicyEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Transport name used by policy rules for a route
+pub fn route_transport(route: &TransportRoute) -> &'static str {
+    match route {
+        TransportRoute::DirectTcp { .. } => "tcp",
+        TransportRoute::DirectUdp { .. } | TransportRoute::Udp { .. } => "udp",
+        TransportRoute::WebSocket { .. } => "websocket",
+        TransportRoute::Quic { .. } => "quic",
+        TransportRoute::LocalMdns { .. } => "mdns",
+        TransportRoute::NatTraversal { .. } => "nat",
+        TransportRoute::FastEmailRelay { .. } | TransportRoute::StandardEmail { .. } => "email",
+        TransportRoute::EmailDiscovery { target_transport } => route_transport(target_transport),
+        TransportRoute::Custom { .. } => "custom",
+    }
+}
+
+/// Region of the relay a route transits: `Some(None)` for relays in an
+/// unknown region, `None` for routes without a relay
+pub fn route_relay_region(route: &TransportRoute) -> Option<Option<String>> {
+    match route {
+        TransportRoute::NatTraversal { method: NatMethod::Turn { region, .. }, .. } => Some(region.clone()),
+        TransportRoute::FastEmailRelay { .. } => Some(None),
+        TransportRoute::EmailDiscovery { target_transport } => route_relay_region(target_transport),
+        _ => None,
+    }
+}
+
+/// Network a route reaches, judged by its address
+pub fn route_network(route: &TransportRoute) -> NetworkScope {
+    let address = match route {
+        TransportRoute::LocalMdns { .. } => return NetworkScope::Local,
+        TransportRoute::DirectTcp { address, .. }
+        | TransportRoute::DirectUdp { address, .. }
+        | TransportRoute::Udp { address, .. }
+        | TransportRoute::Quic { address, .. } => address.as_str(),
+        TransportRoute::WebSocket { url, .. } => url.as_str(),
+        TransportRoute::EmailDiscovery { target_transport } => return route_network(target_transport),
+        TransportRoute::Custom { network_spanning: false, .. } => return NetworkScope::Local,
+        TransportRoute::Custom { network_spanning: true, .. } => return NetworkScope::External,
+        TransportRoute::NatTraversal { .. }
+        | TransportRoute::FastEmailRelay { .. }
+        | TransportRoute::StandardEmail { .. } => return NetworkScope::External,
+    };
+    address_network(address)
+}
+
+fn address_network(address: &str) -> NetworkScope {
+    let host = address
+        .split("://")
+        .last()
+        .unwrap_or(address)
+        .split('/')
+        .next()
+        .unwrap_or(address);
+    if let Ok(addr) = host.parse::<std::net::SocketAddr>() {
+        return ip_network(addr.ip());
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return ip_network(ip);
+    }
+    let host = host.rsplit_once(':').map_or(host, |(h, _)| h);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    if host.eq_ignore_ascii_case("localhost") || host.ends_with(".local") {
+        return NetworkScope::Local;
+    }
+    host.parse().map_or(NetworkScope::External, ip_network)
+}
+
+fn ip_network(ip: IpAddr) -> NetworkScope {
+    let local = match ip {
+        IpAddr::V4(v4) => v4.is_private() || v4.is_loopback() || v4.is_link_local(),
+        IpAddr::V6(v6) => v6.is_loopback() || v6.is_unique_local() || v6.is_unicast_link_local(),
+    };
+    if local { NetworkScope::Local } else { NetworkScope::External }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> PolicyEngine {
+        let engine = PolicyEngine::new();
+        engine.add_rule(
+            PolicyRule::new("bank-secure", "*.bank.example")
+                .require_security(SecurityLevel::Secure)
+                .deny_transport("email"),
+        );
+        engine.add_rule(
+            PolicyRule::new("no-external-udp", "*")
+                .deny_transport("udp")
+                .on_network(NetworkScope::External),
+        );
+        engine
+    }
+
+    #[test]
+    fn test_destination_rules() {
+        let engine = engine();
+        let mut audit = engine.subscribe_audit();
+
+        let err = engine
+            .evaluate("me@example.com", "teller@ops.bank.example", &SecurityLevel::Authenticated)
+            .unwrap_err();
+        assert!(matches!(err, SynapseError::PolicyViolation { ref rule, .. } if rule == "bank-secure"));
+        assert_eq!(audit.try_recv().unwrap().outcome, PolicyOutcome::Denied);
+
+        let decision = engine
+            .evaluate("me@example.com", "teller@ops.bank.example", &SecurityLevel::Secure)
+            .unwrap();
+        assert!(!decision.permits_email());
+        assert!(engine.check_transport(&decision, "email", NetworkScope::External).is_err());
+
+        let decision = engine
+            .evaluate("me@example.com", "bob@example.org", &SecurityLevel::Public)
+            .unwrap();
+        assert!(decision.permits_email());
+        assert!(decision.permits("udp", NetworkScope::Local));
+        assert!(!decision.permits("udp", NetworkScope::External));
+    }
+
+    #[test]
+    fn test_address_network() {
+        assert_eq!(address_network("192.168.1.20:8080"), NetworkScope::Local);
+        assert_eq!(address_network("[::1]:9000"), NetworkScope::Local);
+        assert_eq!(address_network("ws://printer.local:80/ws"), NetworkScope::Local);
+        assert_eq!(address_network("8.8.8.8"), NetworkScope::External);
+        assert_eq!(address_network("relay.example.com:443"), NetworkScope::External);
+    }
+
+    #[test]
+    fn test_data_residency() {
+        let engine = PolicyEngine::new();
+        engine.add_rule(PolicyRule::new("eu-residency", "*").for_region("eu").require_residency("eu"));
+        engine.set_participant_region("anna@example.de", Some("EU-West"));
+        let mut audit = engine.subscribe_audit();
+
+        let decision = engine.evaluate("me@example.com", "anna@example.de", &SecurityLevel::Public).unwrap();
+        assert_eq!(decision.allowed_regions(), Some(vec!["eu".to_string()]));
+        assert!(decision.permits_region(Some("eu-central-1")));
+        assert!(!decision.permits_region(Some("us-east")));
+        assert!(!decision.permits_region(None));
+
+        let turn = |region: &str| TransportRoute::NatTraversal {
+            method: NatMethod::Turn {
+                server: "turn.example.com:3478".to_string(),
+                username: "synapse".to_string(),
+                region: Some(region.to_string()),
+            },
+            external_address: "203.0.113.5".to_string(),
+            external_port: 3478,
+            latency_ms: 40,
+            established_at: std::time::Instant::now(),
+        };
+        assert!(decision.permits_route(&turn("eu-west")));
+        assert!(!decision.permits_route(&turn("us-east")));
+        let _ = audit.try_recv();
+        assert!(engine.check_route(&decision, &turn("us-east")).is_err());
+        let event = audit.try_recv().unwrap();
+        assert_eq!(event.outcome, PolicyOutcome::Denied);
+        assert_eq!(event.transport.as_deref(), Some("nat"));
+        assert!(engine.check_residency(&decision, "archive", Some("us-east")).is_err());
+
+        // Participants outside the region are unaffected
+        let decision = engine.evaluate("me@example.com", "sam@example.com", &SecurityLevel::Public).unwrap();
+        assert!(decision.allowed_regions().is_none());
+        assert!(decision.permits_route(&turn("us-east")));
+    }
+
+    #[test]
+    fn test_rules_from_json() {
+        let engine = PolicyEngine::from_json(
+            r#"[{"name": "no-email", "destination": "partner.example", "denied_transports": ["email"]}]"#,
+        )
+        .unwrap();
+        let decision = engine.evaluate("me@example.com", "ops@partner.example", &SecurityLevel::Public).unwrap();
+        assert!(!decision.permits_email());
+    }
+}
@@ -0,0 +1,187 @@
+//! Wire protocol versions and negotiation
+//!
+//! Each node speaks [`PROTOCOL_VERSION`] and still accepts peers back to
+//! major version [`MIN_COMPATIBLE_MAJOR`]. Nodes tell each other their range
+//! in capability probes and mDNS announcements, and stamp it on every message
+//! envelope under [`PROTOCOL_VERSION_KEY`] and [`MIN_PROTOCOL_MAJOR_KEY`]. Two
+//! nodes talk at the highest version both speak; when their ranges do not
+//! overlap, sends to and messages from the peer fail with
+//! [`SynapseError::IncompatiblePeer`], carrying the peer's version, instead of
+//! being dropped or misread.
+//!
+//! Envelopes without a version come from nodes that predate negotiation and
+//! are taken as version 1.0.
+
+use crate::error::{Result, SynapseError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Wire protocol version spoken by this build
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 1, minor: 0 };
+
+/// Oldest wire protocol major version this build can still talk to
+pub const MIN_COMPATIBLE_MAJOR: u32 = 1;
+
+/// Envelope metadata entry with the sender's protocol version ("major.minor")
+pub const PROTOCOL_VERSION_KEY: &str = "protocol_version";
+
+/// Envelope metadata entry with the oldest major version the sender accepts
+pub const MIN_PROTOCOL_MAJOR_KEY: &str = "min_protocol_major";
+
+/// Synapse wire protocol version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ProtocolVersion {
+    /// Parse "major", "major.minor" or "major.minor.patch"; the patch is ignored
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().trim_start_matches('v').splitn(3, '.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = match parts.next() {
+            Some(minor) => minor.parse().ok()?,
+            None => 0,
+        };
+        if parts.next().is_some_and(|patch| patch.parse::<u32>().is_err()) {
+            return None;
+        }
+        Some(Self { major, minor })
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// The protocol versions a node speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRange {
+    /// Highest version, the one the node speaks natively
+    pub current: ProtocolVersion,
+    /// Oldest major version the node accepts
+    pub min_major: u32,
+}
+
+impl Default for VersionRange {
+    fn default() -> Self {
+        Self::LOCAL
+    }
+}
+
+impl VersionRange {
+    /// What this build speaks
+    pub const LOCAL: Self = Self {
+        current: PROTOCOL_VERSION,
+        min_major: MIN_COMPATIBLE_MAJOR,
+    };
+
+    /// What a node that predates version negotiation speaks
+    pub const LEGACY: Self = Self {
+        current: ProtocolVersion { major: 1, minor: 0 },
+        min_major: 1,
+    };
+
+    /// Highest version both ranges include, or why there is none
+    pub fn negotiate(&self, peer: &VersionRange) -> std::result::Result<ProtocolVersion, String> {
+        if peer.current.major < self.min_major {
+            Err(format!("peer speaks v{}, we require at least v{}", peer.current.major, self.min_major))
+        } else if peer.min_major > self.current.major {
+            Err(format!("peer requires at least v{}, we speak v{}", peer.min_major, self.current.major))
+        } else {
+            Ok(self.current.min(peer.current))
+        }
+    }
+
+    /// Write the range into envelope metadata
+    pub fn stamp(&self, metadata: &mut HashMap<String, String>) {
+        metadata.insert(PROTOCOL_VERSION_KEY.to_string(), self.current.to_string());
+        metadata.insert(MIN_PROTOCOL_MAJOR_KEY.to_string(), self.min_major.to_string());
+    }
+
+    /// Range stamped on an envelope
+    ///
+    /// Unstamped envelopes are [`VersionRange::LEGACY`]; `Err` carries a
+    /// version that could not be read.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> std::result::Result<Self, String> {
+        let Some(announced) = metadata.get(PROTOCOL_VERSION_KEY) else {
+            return Ok(Self::LEGACY);
+        };
+        let current = ProtocolVersion::parse(announced).ok_or_else(|| announced.clone())?;
+        let min_major = match metadata.get(MIN_PROTOCOL_MAJOR_KEY) {
+            Some(min) => min.trim().parse().map_err(|_| announced.clone())?,
+            None => current.major,
+        };
+        Ok(Self { current, min_major })
+    }
+}
+
+/// Version to talk to `peer` at, given the range it announced
+pub fn negotiate(peer: &str, range: &VersionRange) -> Result<ProtocolVersion> {
+    VersionRange::LOCAL
+        .negotiate(range)
+        .map_err(|reason| SynapseError::IncompatiblePeer {
+            peer: peer.to_string(),
+            version: range.current.to_string(),
+            reason,
+        })
+}
+
+/// Check the version on an envelope from `peer` before handling it
+pub fn check_envelope(peer: &str, metadata: &HashMap<String, String>) -> Result<ProtocolVersion> {
+    match VersionRange::from_metadata(metadata) {
+        Ok(range) => negotiate(peer, &range),
+        Err(version) => Err(SynapseError::IncompatiblePeer {
+            peer: peer.to_string(),
+            version,
+            reason: "unrecognised protocol version".to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(major: u32, minor: u32, min_major: u32) -> VersionRange {
+        VersionRange { current: ProtocolVersion { major, minor }, min_major }
+    }
+
+    #[test]
+    fn negotiates_highest_shared_version() {
+        assert_eq!(ProtocolVersion::parse(crate::PROTOCOL_VERSION), Some(PROTOCOL_VERSION));
+        assert_eq!(ProtocolVersion::parse("v2.3"), Some(ProtocolVersion { major: 2, minor: 3 }));
+        assert_eq!(ProtocolVersion::parse("2.x"), None);
+
+        let ours = range(2, 3, 1);
+        assert_eq!(ours.negotiate(&range(2, 1, 2)), Ok(ProtocolVersion { major: 2, minor: 1 }));
+        assert_eq!(ours.negotiate(&range(3, 0, 2)), Ok(ProtocolVersion { major: 2, minor: 3 }));
+        assert_eq!(ours.negotiate(&VersionRange::LEGACY), Ok(ProtocolVersion { major: 1, minor: 0 }));
+        assert!(ours.negotiate(&range(3, 0, 3)).is_err());
+        assert!(range(2, 0, 2).negotiate(&range(1, 4, 1)).is_err());
+
+        // Envelopes round-trip, and unstamped ones come from legacy nodes
+        let mut metadata = HashMap::new();
+        assert_eq!(check_envelope("alice", &metadata).unwrap(), ProtocolVersion { major: 1, minor: 0 });
+        VersionRange::LOCAL.stamp(&mut metadata);
+        assert_eq!(VersionRange::from_metadata(&metadata), Ok(VersionRange::LOCAL));
+        assert_eq!(check_envelope("alice", &metadata).unwrap(), PROTOCOL_VERSION);
+
+        range(PROTOCOL_VERSION.major + 1, 0, PROTOCOL_VERSION.major + 1).stamp(&mut metadata);
+        match check_envelope("alice", &metadata) {
+            Err(SynapseError::IncompatiblePeer { peer, version, .. }) => {
+                assert_eq!(peer, "alice");
+                assert_eq!(version, format!("{}.0", PROTOCOL_VERSION.major + 1));
+            }
+            other => panic!("expected an incompatible peer, got {:?}", other),
+        }
+        metadata.insert(PROTOCOL_VERSION_KEY.to_string(), "banana".to_string());
+        assert!(matches!(
+            check_envelope("alice", &metadata),
+            Err(SynapseError::IncompatiblePeer { version, .. }) if version == "banana"
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+//! # Participant Data Purge
+//!
+//! Erasure requests reach further than the participant's profile: contacts,
+//! learned name resolutions, conversation history, relayed messages and the
+//! trust ledger all hold references. The registry coordinates a purge by
+//! calling every registered [`PurgeTarget`] and collecting what each removed
+//! into a [`PurgeAudit`].
+//!
+//! Data that cannot be removed, such as committed blockchain transactions,
+//! is redacted instead and recorded as such. The audit record names the
+//! participant only by pseudonym so it can be kept after the purge.
+
+use crate::error::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Pseudonym used for an erased participant in audit records and redacted data
+pub fn pseudonym(global_id: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(global_id.as_bytes()));
+    format!("redacted:{}", &digest[..16])
+}
+
+/// What happened to a participant's data in one place
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeAction {
+    /// Removed outright
+    Deleted,
+    /// Kept but no longer attributable to the participant
+    Redacted,
+    /// Could not be purged; see the detail
+    Failed,
+}
+
+/// One line of a purge audit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PurgedItem {
+    /// Subsystem and kind of data, e.g. `history` or `database.contacts`
+    pub subsystem: String,
+    pub action: PurgeAction,
+    /// Records affected
+    pub count: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl PurgedItem {
+    pub fn deleted(subsystem: impl Into<String>, count: u64) -> Self {
+        Self { subsystem: subsystem.into(), action: PurgeAction::Deleted, count, detail: None }
+    }
+
+    pub fn redacted(subsystem: impl Into<String>, count: u64, detail: impl Into<String>) -> Self {
+        Self { subsystem: subsystem.into(), action: PurgeAction::Redacted, count, detail: Some(detail.into()) }
+    }
+
+    pub fn failed(subsystem: impl Into<String>, error: impl ToString) -> Self {
+        Self { subsystem: subsystem.into(), action: PurgeAction::Failed, count: 0, detail: Some(error.to_string()) }
+    }
+}
+
+/// Record of a completed purge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeAudit {
+    pub id: Uuid,
+    /// [`pseudonym`] of the purged participant
+    pub subject: String,
+    /// Global ID of the operator or registry that requested the purge
+    pub requested_by: String,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub items: Vec<PurgedItem>,
+}
+
+impl PurgeAudit {
+    /// Start an audit for purging `global_id`
+    pub fn new(global_id: &str, requested_by: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            subject: pseudonym(global_id),
+            requested_by: requested_by.into(),
+            started_at: Utc::now(),
+            completed_at: None,
+            items: Vec::new(),
+        }
+    }
+
+    /// Add a subsystem's result; empty deletions are left out
+    pub fn record(&mut self, item: PurgedItem) {
+        if item.action != PurgeAction::Deleted || item.count > 0 {
+            self.items.push(item);
+        }
+    }
+
+    /// Add what a target purged, or its failure
+    pub fn record_target(&mut self, target: &str, result: Result<Vec<PurgedItem>>) {
+        match result {
+            Ok(items) => items.into_iter().for_each(|item| self.record(item)),
+            Err(e) => self.record(PurgedItem::failed(target, e)),
+        }
+    }
+
+    /// Mark the purge finished
+    pub fn complete(&mut self) {
+        self.completed_at = Some(Utc::now());
+    }
+
+    /// Whether every subsystem purged or redacted successfully
+    pub fn is_complete(&self) -> bool {
+        self.completed_at.is_some() && self.items.iter().all(|item| item.action != PurgeAction::Failed)
+    }
+
+    /// Records deleted across all subsystems
+    pub fn deleted(&self) -> u64 {
+        self.items.iter().filter(|item| item.action == PurgeAction::Deleted).map(|item| item.count).sum()
+    }
+
+    /// Records kept but redacted across all subsystems
+    pub fn redacted(&self) -> u64 {
+        self.items.iter().filter(|item| item.action == PurgeAction::Redacted).map(|item| item.count).sum()
+    }
+}
+
+/// A subsystem that holds participant data and can erase it
+#[async_trait]
+pub trait PurgeTarget: Send + Sync {
+    /// Name used in the audit when the whole target fails
+    fn name(&self) -> &str;
+
+    /// Remove or redact everything held about `global_id`
+    async fn purge(&self, global_id: &str) -> Result<Vec<PurgedItem>>;
+}
+
+#[async_trait]
+impl PurgeTarget for crate::history::ConversationHistory {
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    async fn purge(&self, global_id: &str) -> Result<Vec<PurgedItem>> {
+        Ok(vec![PurgedItem::deleted("history", self.purge_peer(global_id) as u64)])
+    }
+}
+
+#[cfg(feature = "crypto")]
+#[async_trait]
+impl PurgeTarget for crate::relay::RelayServer {
+    fn name(&self) -> &str {
+        "relay"
+    }
+
+    async fn purge(&self, global_id: &str) -> Result<Vec<PurgedItem>> {
+        Ok(vec![PurgedItem::deleted("relay.messages", self.purge_participant(global_id).await?)])
+    }
+}
+
+#[async_trait]
+impl PurgeTarget for crate::synapse::blockchain::SynapseBlockchain {
+    fn name(&self) -> &str {
+        "blockchain"
+    }
+
+    async fn purge(&self, global_id: &str) -> Result<Vec<PurgedItem>> {
+        let committed = self.redact_participant(global_id).await;
+        Ok(vec![PurgedItem::redacted(
+            "blockchain.transactions",
+            committed as u64,
+            "committed blocks are immutable; references are masked by pseudonym",
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SynapseError;
+
+    #[test]
+    fn test_audit_totals_and_failures() {
+        let mut audit = PurgeAudit::new("alice@example.com", "registry@example.com");
+        assert!(audit.subject.starts_with("redacted:"));
+        assert!(!audit.subject.contains("alice"));
+
+        audit.record(PurgedItem::deleted("contacts", 0));
+        audit.record_target("history", Ok(vec![PurgedItem::deleted("history", 3)]));
+        audit.record(PurgedItem::redacted("blockchain.transactions", 2, "immutable"));
+        audit.complete();
+        assert_eq!(audit.items.len(), 2);
+        assert_eq!((audit.deleted(), audit.redacted()), (3, 2));
+        assert!(audit.is_complete());
+
+        audit.record_target("relay", Err(SynapseError::DatabaseError("offline".into())));
+        assert!(!audit.is_complete());
+    }
+}
@@ -0,0 +1,261 @@
+//! # Push Wake-Ups
+//!
+//! Phones cannot keep sockets open, so a relay that receives a message for a
+//! dormant peer asks the platform's push service to wake the app. The push
+//! payload only ever carries an opaque wake token chosen by the relay — never
+//! the sender, subject or content — so nothing about the message leaks to
+//! Google, Apple or a webhook operator. The woken client reconnects to the
+//! relay, authenticates and fetches over Synapse.
+//!
+//! Implementations are provided for FCM (HTTP v1), APNs (token-based auth) and
+//! plain webhooks; anything else can implement [`PushNotifier`].
+
+use crate::error::{Result, SynapseError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Push service a device is registered with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PushPlatform {
+    Fcm,
+    Apns,
+    Webhook,
+}
+
+/// A peer's push registration with a relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushRegistration {
+    pub platform: PushPlatform,
+    /// Platform device token (FCM registration token, APNs device token or webhook URL)
+    pub device_token: String,
+    /// Opaque token the relay puts in wake payloads for this peer
+    pub wake_token: String,
+}
+
+/// The entire content of a wake-up push
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WakePayload {
+    pub wake_token: String,
+}
+
+/// Delivers wake-ups through one push service
+#[async_trait]
+pub trait PushNotifier: Send + Sync {
+    /// Platform this notifier delivers to
+    fn platform(&self) -> PushPlatform;
+
+    /// Wake the device identified by `device_token`
+    async fn wake(&self, device_token: &str, payload: &WakePayload) -> Result<()>;
+}
+
+#[cfg(feature = "http")]
+pub use http_notifiers::*;
+
+#[cfg(feature = "http")]
+mod http_notifiers {
+    use super::*;
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+    use ring::{
+        rand::SystemRandom,
+        signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair},
+    };
+    use std::{
+        sync::{Mutex, RwLock},
+        time::{Duration, Instant},
+    };
+
+    async fn check_response(service: &str, response: reqwest::Response) -> Result<()> {
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        Err(SynapseError::TransportError(format!("{} rejected wake-up ({}): {}", service, status, body)))
+    }
+
+    /// Wakes devices by POSTing the payload as JSON; the device token is the URL
+    pub struct WebhookNotifier {
+        client: reqwest::Client,
+    }
+
+    impl WebhookNotifier {
+        pub fn new() -> Self {
+            Self { client: reqwest::Client::new() }
+        }
+    }
+
+    impl Default for WebhookNotifier {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    #[async_trait]
+    impl PushNotifier for WebhookNotifier {
+        fn platform(&self) -> PushPlatform {
+            PushPlatform::Webhook
+        }
+
+        async fn wake(&self, device_token: &str, payload: &WakePayload) -> Result<()> {
+            let response = self
+                .client
+                .post(device_token)
+                .json(payload)
+                .send()
+                .await
+                .map_err(|e| SynapseError::TransportError(format!("Webhook wake-up failed: {}", e)))?;
+            check_response("Webhook", response).await
+        }
+    }
+
+    /// Firebase Cloud Messaging via the HTTP v1 API
+    ///
+    /// Sends a high-priority data-only message so the app is woken without a
+    /// visible notification. The OAuth access token is short-lived; refresh it
+    /// with [`FcmNotifier::set_access_token`].
+    pub struct FcmNotifier {
+        client: reqwest::Client,
+        project_id: String,
+        access_token: RwLock<String>,
+    }
+
+    impl FcmNotifier {
+        pub fn new(project_id: impl Into<String>, access_token: impl Into<String>) -> Self {
+            Self {
+                client: reqwest::Client::new(),
+                project_id: project_id.into(),
+                access_token: RwLock::new(access_token.into()),
+            }
+        }
+
+        /// Replace the OAuth access token
+        pub fn set_access_token(&self, access_token: impl Into<String>) {
+            *self.access_token.write().unwrap() = access_token.into();
+        }
+    }
+
+    #[async_trait]
+    impl PushNotifier for FcmNotifier {
+        fn platform(&self) -> PushPlatform {
+            PushPlatform::Fcm
+        }
+
+        async fn wake(&self, device_token: &str, payload: &WakePayload) -> Result<()> {
+            let body = serde_json::json!({
+                "message": {
+                    "token": device_token,
+                    "data": { "wake_token": payload.wake_token },
+                    "android": { "priority": "high" },
+                }
+            });
+            let access_token = self.access_token.read().unwrap().clone();
+            let response = self
+                .client
+                .post(format!("https://fcm.googleapis.com/v1/projects/{}/messages:send", self.project_id))
+                .bearer_auth(access_token)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| SynapseError::TransportError(format!("FCM wake-up failed: {}", e)))?;
+            check_response("FCM", response).await
+        }
+    }
+
+    /// Apple Push Notification service with token-based (.p8 key) authentication
+    ///
+    /// Sends a background push (`content-available`), which wakes the app
+    /// without alerting the user.
+    pub struct ApnsNotifier {
+        client: reqwest::Client,
+        key_pair: EcdsaKeyPair,
+        key_id: String,
+        team_id: String,
+        topic: String,
+        host: &'static str,
+        /// Provider token and when it was minted; APNs wants it refreshed within the hour
+        token: Mutex<Option<(String, Instant)>>,
+    }
+
+    impl ApnsNotifier {
+        /// Provider tokens are reused for this long
+        const TOKEN_LIFETIME: Duration = Duration::from_secs(50 * 60);
+
+        /// Create a notifier from the contents of an APNs `.p8` signing key
+        pub fn new(
+            p8_pem: &str,
+            key_id: impl Into<String>,
+            team_id: impl Into<String>,
+            topic: impl Into<String>,
+            sandbox: bool,
+        ) -> Result<Self> {
+            let der: String = p8_pem.lines().filter(|line| !line.starts_with("-----")).collect();
+            let der = base64::engine::general_purpose::STANDARD
+                .decode(der.trim())
+                .map_err(|e| SynapseError::InvalidKey(format!("APNs key is not valid PEM: {}", e)))?;
+            let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &der, &SystemRandom::new())
+                .map_err(|e| SynapseError::InvalidKey(format!("APNs key rejected: {}", e)))?;
+
+            Ok(Self {
+                client: reqwest::Client::new(),
+                key_pair,
+                key_id: key_id.into(),
+                team_id: team_id.into(),
+                topic: topic.into(),
+                host: if sandbox { "api.sandbox.push.apple.com" } else { "api.push.apple.com" },
+                token: Mutex::new(None),
+            })
+        }
+
+        fn provider_token(&self) -> Result<String> {
+            let mut cached = self.token.lock().unwrap();
+            if let Some((token, minted)) = cached.as_ref() {
+                if minted.elapsed() < Self::TOKEN_LIFETIME {
+                    return Ok(token.clone());
+                }
+            }
+
+            let header = serde_json::json!({ "alg": "ES256", "kid": self.key_id });
+            let claims = serde_json::json!({ "iss": self.team_id, "iat": chrono::Utc::now().timestamp() });
+            let signing_input = format!(
+                "{}.{}",
+                URL_SAFE_NO_PAD.encode(header.to_string()),
+                URL_SAFE_NO_PAD.encode(claims.to_string())
+            );
+            let signature = self
+                .key_pair
+                .sign(&SystemRandom::new(), signing_input.as_bytes())
+                .map_err(|_| SynapseError::Signing("Failed to sign APNs provider token".to_string()))?;
+            let token = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.as_ref()));
+
+            *cached = Some((token.clone(), Instant::now()));
+            Ok(token)
+        }
+    }
+
+    #[async_trait]
+    impl PushNotifier for ApnsNotifier {
+        fn platform(&self) -> PushPlatform {
+            PushPlatform::Apns
+        }
+
+        async fn wake(&self, device_token: &str, payload: &WakePayload) -> Result<()> {
+            let body = serde_json::json!({
+                "aps": { "content-available": 1 },
+                "wake_token": payload.wake_token,
+            });
+            let response = self
+                .client
+                .post(format!("https://{}/3/device/{}", self.host, device_token))
+                .bearer_auth(self.provider_token()?)
+                .header("apns-topic", &self.topic)
+                .header("apns-push-type", "background")
+                .header("apns-priority", "5")
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| SynapseError::TransportError(format!("APNs wake-up failed: {}", e)))?;
+            check_response("APNs", response).await
+        }
+    }
+}
@@ -0,0 +1,808 @@
+//! # Relay Server
+//!
+//! A relay is a well-connected node that holds messages for registered peers
+//! while they are offline — typically mobile clients behind carrier NAT. Each
+//! accepted message is sealed with the relay's storage key before it reaches
+//! the [`RelayStore`], so a copied database reveals nothing beyond routing
+//! metadata (the payload is usually end-to-end encrypted by the sender too).
+//!
+//! When the peer reconnects it asks for a challenge, signs it with the key it
+//! registered, and receives a short-lived delivery session. Messages are handed
+//! out through that session and deleted only once acknowledged, so a dropped
+//! connection mid-delivery loses nothing.
+//!
+//! Each peer has a [`RelayPolicy`] bounding how many messages and bytes the
+//! relay holds for it and how long they are kept.
+//!
+//! Peers that cannot hold a connection open (phones) register a push target;
+//! when a message arrives while they have no live session, the relay sends a
+//! content-free wake-up through the matching [`PushNotifier`].
+//!
+//! A relay given the node's [`PolicyEngine`] refuses messages whose
+//! recipient's data-residency rules exclude the relay's region.
+//!
+//! A relay given a [`LeaderElection`] serves only while it is the active node
+//! of an active/standby pair; on the standby, accepting, challenges and
+//! deliveries fail with [`SynapseError::NotLeader`] and the retention sweep
+//! pauses.
+//!
+//! A relay given a [`UsageMeter`] charges each accepted message and the bytes
+//! it holds to the recipient, and the time in delivery sessions to the peer
+//! holding them, refusing what would go over their usage quotas.
+
+use crate::{
+    config::RelayConfig,
+    crypto::CryptoManager,
+    election::LeaderElection,
+    error::{Result, SynapseError},
+    metering::UsageMeter,
+    policy::PolicyEngine,
+    push::{PushNotifier, PushPlatform, PushRegistration, WakePayload},
+    types::SecureMessage,
+};
+use aes_gcm::{
+    Aes256Gcm, Key, Nonce,
+    aead::{Aead, KeyInit, OsRng},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use rsa::rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Storage limits and retention for one peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayPolicy {
+    /// Messages held at once
+    pub max_messages: u64,
+    /// Bytes held at once
+    pub max_bytes: u64,
+    /// How long a message is kept before being discarded, in seconds
+    pub retention_secs: u64,
+}
+
+impl RelayPolicy {
+    /// Policy using the relay's defaults
+    pub fn from_config(config: &RelayConfig) -> Self {
+        Self {
+            max_messages: config.default_quota_messages,
+            max_bytes: config.default_quota_bytes,
+            retention_secs: config.retention_secs,
+        }
+    }
+}
+
+/// A peer the relay holds messages for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayPeer {
+    pub global_id: String,
+    /// Public key (PEM) used to verify the peer's challenge responses
+    pub public_key_pem: String,
+    pub policy: RelayPolicy,
+    pub registered_at: DateTime<Utc>,
+    /// Last successful authentication
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Where to send wake-ups while the peer is dormant
+    #[serde(default)]
+    pub push: Option<PushRegistration>,
+    /// Last wake-up sent, used to avoid waking the device for every message
+    #[serde(default)]
+    pub last_woken: Option<DateTime<Utc>>,
+}
+
+/// A sealed message waiting for its recipient
+#[derive(Debug, Clone)]
+pub struct StoredMessage {
+    pub id: Uuid,
+    pub recipient: String,
+    pub sender: String,
+    pub stored_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Size of the message before sealing
+    pub size: u64,
+    /// Nonce followed by the AES-256-GCM ciphertext of the serialized message
+    pub sealed: Vec<u8>,
+}
+
+/// What the relay currently holds for a peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayUsage {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// A message handed to its recipient, pending acknowledgement
+#[derive(Debug, Clone)]
+pub struct RelayedMessage {
+    /// ID to acknowledge once the message is safely received
+    pub id: Uuid,
+    pub stored_at: DateTime<Utc>,
+    pub message: SecureMessage,
+}
+
+/// Authenticated delivery session for a reconnected peer
+#[derive(Debug, Clone)]
+pub struct RelaySession {
+    pub token: String,
+    pub peer: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Backing storage for held messages
+#[async_trait]
+pub trait RelayStore: Send + Sync {
+    /// Store a message
+    async fn put(&self, message: &StoredMessage) -> Result<()>;
+
+    /// Unexpired messages for a recipient, oldest first
+    async fn pending(&self, recipient: &str, now: DateTime<Utc>) -> Result<Vec<StoredMessage>>;
+
+    /// Delete messages for a recipient, returning how many were removed
+    async fn remove(&self, recipient: &str, ids: &[Uuid]) -> Result<u64>;
+
+    /// Unexpired messages and bytes held for a recipient
+    async fn usage(&self, recipient: &str, now: DateTime<Utc>) -> Result<RelayUsage>;
+
+    /// Delete every message whose retention has run out
+    async fn prune(&self, now: DateTime<Utc>) -> Result<u64>;
+    /// Delete every message to or from a participant, expired or not
+    async fn purge_participant(&self, global_id: &str) -> Result<u64>;
+}
+
+/// In-memory relay store
+#[derive(Default)]
+pub struct MemoryRelayStore {
+    messages: DashMap<String, Vec<StoredMessage>>,
+}
+
+impl MemoryRelayStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RelayStore for MemoryRelayStore {
+    async fn put(&self, message: &StoredMessage) -> Result<()> {
+        self.messages.entry(message.recipient.clone()).or_default().push(message.clone());
+        Ok(())
+    }
+
+    async fn pending(&self, recipient: &str, now: DateTime<Utc>) -> Result<Vec<StoredMessage>> {
+        Ok(self
+            .messages
+            .get(recipient)
+            .map(|held| held.iter().filter(|m| m.expires_at > now).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn remove(&self, recipient: &str, ids: &[Uuid]) -> Result<u64> {
+        let Some(mut held) = self.messages.get_mut(recipient) else {
+            return Ok(0);
+        };
+        let before = held.len();
+        held.retain(|m| !ids.contains(&m.id));
+        Ok((before - held.len()) as u64)
+    }
+
+    async fn usage(&self, recipient: &str, now: DateTime<Utc>) -> Result<RelayUsage> {
+        let pending = self.pending(recipient, now).await?;
+        Ok(RelayUsage {
+            messages: pending.len() as u64,
+            bytes: pending.iter().map(|m| m.size).sum(),
+        })
+    }
+
+    async fn prune(&self, now: DateTime<Utc>) -> Result<u64> {
+        let mut removed = 0;
+        for mut held in self.messages.iter_mut() {
+            let before = held.len();
+            held.retain(|m| m.expires_at > now);
+            removed += (before - held.len()) as u64;
+        }
+        self.messages.retain(|_, held| !held.is_empty());
+        Ok(removed)
+    }
+    async fn purge_participant(&self, global_id: &str) -> Result<u64> {
+        let mut removed = self.messages.remove(global_id).map(|(_, held)| held.len() as u64).unwrap_or(0);
+        for mut held in self.messages.iter_mut() {
+            let before = held.len();
+            held.retain(|m| m.sender != global_id);
+            removed += (before - held.len()) as u64;
+        }
+        self.messages.retain(|_, held| !held.is_empty());
+        Ok(removed)
+    }
+}
+
+#[cfg(feature = "database")]
+#[async_trait]
+impl RelayStore for crate::synapse::storage::Database {
+    async fn put(&self, message: &StoredMessage) -> Result<()> {
+        self.insert_relay_message(message)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))
+    }
+
+    async fn pending(&self, recipient: &str, now: DateTime<Utc>) -> Result<Vec<StoredMessage>> {
+        self.list_relay_messages(recipient, now)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))
+    }
+
+    async fn remove(&self, recipient: &str, ids: &[Uuid]) -> Result<u64> {
+        self.delete_relay_messages(recipient, ids)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))
+    }
+
+    async fn usage(&self, recipient: &str, now: DateTime<Utc>) -> Result<RelayUsage> {
+        let (messages, bytes) = self
+            .relay_usage(recipient, now)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))?;
+        Ok(RelayUsage { messages, bytes })
+    }
+
+    async fn prune(&self, now: DateTime<Utc>) -> Result<u64> {
+        self.prune_relay_messages(now)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))
+    }
+
+    async fn purge_participant(&self, global_id: &str) -> Result<u64> {
+        self.purge_relay_messages(global_id)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))
+    }
+}
+
+/// Store-and-forward relay for offline peers
+pub struct RelayServer {
+    config: RelayConfig,
+    store: Arc<dyn RelayStore>,
+    peers: DashMap<String, RelayPeer>,
+    /// Registered peer keys, used to verify challenge responses
+    verifier: RwLock<CryptoManager>,
+    /// Outstanding challenges: peer -> (nonce, expiry)
+    challenges: DashMap<String, (String, DateTime<Utc>)>,
+    sessions: DashMap<String, RelaySession>,
+    notifiers: RwLock<HashMap<PushPlatform, Arc<dyn PushNotifier>>>,
+    storage_key: [u8; 32],
+    policies: Option<Arc<PolicyEngine>>,
+    /// Serve only while this node holds the leader lease
+    election: Option<Arc<LeaderElection>>,
+    metering: Option<Arc<UsageMeter>>,
+    /// Session token -> time up to which the session has been metered
+    metered_sessions: DashMap<String, DateTime<Utc>>,
+}
+
+impl RelayServer {
+    /// Create a relay with a fresh storage key
+    pub fn new(config: RelayConfig, store: Arc<dyn RelayStore>) -> Self {
+        let mut storage_key = [0u8; 32];
+        OsRng.fill_bytes(&mut storage_key);
+        Self::with_storage_key(config, store, storage_key)
+    }
+
+    /// Create a relay with a known storage key, so a persistent store stays readable across restarts
+    pub fn with_storage_key(config: RelayConfig, store: Arc<dyn RelayStore>, storage_key: [u8; 32]) -> Self {
+        Self {
+            config,
+            store,
+            peers: DashMap::new(),
+            verifier: RwLock::new(CryptoManager::new()),
+            challenges: DashMap::new(),
+            sessions: DashMap::new(),
+            notifiers: RwLock::new(HashMap::new()),
+            storage_key,
+            policies: None,
+            election: None,
+            metering: None,
+            metered_sessions: DashMap::new(),
+        }
+    }
+
+    /// Enforce data-residency rules from a policy engine on accepted messages
+    pub fn with_policies(mut self, policies: Arc<PolicyEngine>) -> Self {
+        self.policies = Some(policies);
+        self
+    }
+
+    /// Serve only while `election` makes this node the active one
+    pub fn with_election(mut self, election: Arc<LeaderElection>) -> Self {
+        self.election = Some(election);
+        self
+    }
+
+    /// Charge usage to participants and enforce their usage quotas
+    pub fn with_metering(mut self, metering: Arc<UsageMeter>) -> Self {
+        self.metering = Some(metering);
+        self
+    }
+
+    fn ensure_active(&self) -> Result<()> {
+        match self.election {
+            Some(ref election) => election.ensure_leader(),
+            None => Ok(()),
+        }
+    }
+
+    /// Register a peer, using the default policy when none is given
+    pub fn register_peer(&self, global_id: &str, public_key_pem: &str, policy: Option<RelayPolicy>) -> Result<()> {
+        self.verifier.write().unwrap().import_public_key(global_id, public_key_pem)?;
+        let peer = RelayPeer {
+            global_id: global_id.to_string(),
+            public_key_pem: public_key_pem.to_string(),
+            policy: policy.unwrap_or_else(|| RelayPolicy::from_config(&self.config)),
+            registered_at: Utc::now(),
+            last_seen: None,
+            push: None,
+            last_woken: None,
+        };
+        self.peers.insert(global_id.to_string(), peer);
+        info!("Registered relay peer {}", global_id);
+        Ok(())
+    }
+
+    /// Unregister a peer and discard everything held for it
+    pub async fn unregister_peer(&self, global_id: &str) -> Result<RelayPeer> {
+        let (_, peer) = self
+            .peers
+            .remove(global_id)
+            .ok_or_else(|| SynapseError::PeerNotFound(global_id.to_string()))?;
+        self.sessions.retain(|_, s| s.peer != global_id);
+        self.metered_sessions.retain(|token, _| self.sessions.contains_key(token));
+        self.challenges.remove(global_id);
+
+        let held: Vec<Uuid> = self.store.pending(global_id, Utc::now()).await?.iter().map(|m| m.id).collect();
+        self.store.remove(global_id, &held).await?;
+        Ok(peer)
+    }
+
+    /// Registered peer details
+    pub fn peer(&self, global_id: &str) -> Option<RelayPeer> {
+        self.peers.get(global_id).map(|p| p.clone())
+    }
+
+    /// Replace a peer's quota and retention policy
+    pub fn set_policy(&self, global_id: &str, policy: RelayPolicy) -> Result<()> {
+        let mut peer = self
+            .peers
+            .get_mut(global_id)
+            .ok_or_else(|| SynapseError::PeerNotFound(global_id.to_string()))?;
+        peer.policy = policy;
+        Ok(())
+    }
+
+    /// Install the notifier used for one push platform
+    pub fn add_push_notifier(&self, notifier: Arc<dyn PushNotifier>) {
+        self.notifiers.write().unwrap().insert(notifier.platform(), notifier);
+    }
+
+    /// Register a device to wake for a peer, returning the opaque wake token
+    pub fn register_push(&self, global_id: &str, platform: PushPlatform, device_token: &str) -> Result<String> {
+        let mut peer = self
+            .peers
+            .get_mut(global_id)
+            .ok_or_else(|| SynapseError::PeerNotFound(global_id.to_string()))?;
+        let wake_token = Uuid::new_v4().simple().to_string();
+        peer.push = Some(PushRegistration {
+            platform,
+            device_token: device_token.to_string(),
+            wake_token: wake_token.clone(),
+        });
+        Ok(wake_token)
+    }
+
+    /// Stop sending wake-ups for a peer
+    pub fn unregister_push(&self, global_id: &str) -> Result<()> {
+        let mut peer = self
+            .peers
+            .get_mut(global_id)
+            .ok_or_else(|| SynapseError::PeerNotFound(global_id.to_string()))?;
+        peer.push = None;
+        Ok(())
+    }
+
+    /// Whether a peer has no live delivery session
+    pub fn is_dormant(&self, global_id: &str) -> bool {
+        let now = Utc::now();
+        !self.sessions.iter().any(|s| s.peer == global_id && s.expires_at > now)
+    }
+
+    /// Send a wake-up if the peer is dormant, has a push target and was not woken recently
+    fn wake_if_dormant(&self, global_id: &str) {
+        if !self.is_dormant(global_id) {
+            return;
+        }
+        let now = Utc::now();
+        let registration = {
+            let Some(mut peer) = self.peers.get_mut(global_id) else { return };
+            let Some(registration) = peer.push.clone() else { return };
+            let interval = chrono::Duration::seconds(self.config.wake_interval_secs as i64);
+            if peer.last_woken.is_some_and(|woken| now - woken < interval) {
+                return;
+            }
+            peer.last_woken = Some(now);
+            registration
+        };
+
+        let Some(notifier) = self.notifiers.read().unwrap().get(&registration.platform).cloned() else {
+            warn!("No {:?} notifier installed to wake {}", registration.platform, global_id);
+            return;
+        };
+        let global_id = global_id.to_string();
+        tokio::spawn(async move {
+            let payload = WakePayload { wake_token: registration.wake_token };
+            match notifier.wake(&registration.device_token, &payload).await {
+                Ok(()) => debug!("Sent {:?} wake-up to {}", registration.platform, global_id),
+                Err(e) => warn!("Wake-up for {} failed: {}", global_id, e),
+            }
+        });
+    }
+
+    /// Accept a message for an offline registered peer
+    pub async fn accept(&self, message: &SecureMessage) -> Result<Uuid> {
+        self.ensure_active()?;
+        let recipient = &message.to_global_id;
+        let policy = self
+            .peers
+            .get(recipient)
+            .map(|p| p.policy.clone())
+            .ok_or_else(|| SynapseError::PeerNotFound(format!("{} is not registered with this relay", recipient)))?;
+        if let Some(policies) = &self.policies {
+            let decision = policies.residency(&message.from_global_id, recipient);
+            policies.check_residency(&decision, "relay", self.config.region.as_deref())?;
+        }
+
+        let serialized = serde_json::to_vec(message)?;
+        let size = serialized.len() as u64;
+        if size > self.config.max_message_bytes {
+            return Err(SynapseError::PolicyViolation {
+                rule: "relay_max_message_size".to_string(),
+                destination: recipient.clone(),
+                reason: format!("{} bytes exceeds the {} byte limit", size, self.config.max_message_bytes),
+            });
+        }
+
+        let now = Utc::now();
+        let usage = self.store.usage(recipient, now).await?;
+        if usage.messages + 1 > policy.max_messages || usage.bytes + size > policy.max_bytes {
+            return Err(SynapseError::PolicyViolation {
+                rule: "relay_quota".to_string(),
+                destination: recipient.clone(),
+                reason: format!(
+                    "holding {} messages / {} bytes, quota is {} / {}",
+                    usage.messages, usage.bytes, policy.max_messages, policy.max_bytes
+                ),
+            });
+        }
+        if let Some(ref metering) = self.metering {
+            metering.check_storage(recipient, usage.bytes, size)?;
+            metering.charge_message(recipient, size)?;
+        }
+
+        let stored = StoredMessage {
+            id: Uuid::new_v4(),
+            recipient: recipient.clone(),
+            sender: message.from_global_id.clone(),
+            stored_at: now,
+            expires_at: now + chrono::Duration::seconds(policy.retention_secs as i64),
+            size,
+            sealed: self.seal(&serialized)?,
+        };
+        self.store.put(&stored).await?;
+        if let Some(ref metering) = self.metering {
+            metering.record_storage(recipient, usage.bytes + size);
+        }
+        debug!("Relay holding message {} for {}", stored.id, recipient);
+        self.wake_if_dormant(recipient);
+        Ok(stored.id)
+    }
+
+    /// Issue a challenge the peer must sign to start a delivery session
+    pub fn challenge(&self, global_id: &str) -> Result<String> {
+        self.ensure_active()?;
+        if !self.peers.contains_key(global_id) {
+            return Err(SynapseError::PeerNotFound(global_id.to_string()));
+        }
+        let nonce = format!("synapse-relay:{}:{}", global_id, Uuid::new_v4());
+        let expires_at = Utc::now() + chrono::Duration::seconds(self.config.challenge_ttl_secs as i64);
+        self.challenges.insert(global_id.to_string(), (nonce.clone(), expires_at));
+        Ok(nonce)
+    }
+
+    /// Verify a signed challenge and open a delivery session
+    pub fn authenticate(&self, global_id: &str, signature: &[u8]) -> Result<RelaySession> {
+        let (_, (nonce, expires_at)) = self
+            .challenges
+            .remove(global_id)
+            .ok_or_else(|| SynapseError::AuthenticationError(format!("No outstanding challenge for {}", global_id)))?;
+        let now = Utc::now();
+        if now > expires_at {
+            return Err(SynapseError::AuthenticationError(format!("Challenge for {} expired", global_id)));
+        }
+        if !self.verifier.read().unwrap().verify_signature(&nonce, signature, global_id)? {
+            warn!("Relay authentication failed for {}", global_id);
+            return Err(SynapseError::AuthenticationError(format!("Invalid challenge signature from {}", global_id)));
+        }
+
+        if let Some(ref metering) = self.metering {
+            metering.check_relay_time(global_id)?;
+        }
+        if let Some(mut peer) = self.peers.get_mut(global_id) {
+            peer.last_seen = Some(now);
+        }
+        let session = RelaySession {
+            token: Uuid::new_v4().to_string(),
+            peer: global_id.to_string(),
+            expires_at: now + chrono::Duration::seconds(self.config.session_ttl_secs as i64),
+        };
+        self.drop_expired_sessions(now);
+        self.sessions.insert(session.token.clone(), session.clone());
+        if self.metering.is_some() {
+            self.metered_sessions.insert(session.token.clone(), now);
+        }
+        info!("Relay delivery session opened for {}", global_id);
+        Ok(session)
+    }
+
+    /// Messages held for the session's peer, oldest first
+    pub async fn fetch(&self, token: &str) -> Result<Vec<RelayedMessage>> {
+        self.ensure_active()?;
+        let peer = self.session_peer(token)?;
+        self.meter_session(token, &peer);
+        let mut delivered = Vec::new();
+        for stored in self.store.pending(&peer, Utc::now()).await? {
+            let message = serde_json::from_slice(&self.open(&stored.sealed)?)?;
+            delivered.push(RelayedMessage { id: stored.id, stored_at: stored.stored_at, message });
+        }
+        Ok(delivered)
+    }
+
+    /// Delete messages the peer has safely received
+    pub async fn acknowledge(&self, token: &str, ids: &[Uuid]) -> Result<u64> {
+        self.ensure_active()?;
+        let peer = self.session_peer(token)?;
+        self.meter_session(token, &peer);
+        let removed = self.store.remove(&peer, ids).await?;
+        if let Some(ref metering) = self.metering {
+            metering.record_storage(&peer, self.store.usage(&peer, Utc::now()).await?.bytes);
+        }
+        Ok(removed)
+    }
+
+    /// What the relay currently holds for a peer
+    pub async fn usage(&self, global_id: &str) -> Result<RelayUsage> {
+        self.store.usage(global_id, Utc::now()).await
+    }
+
+    /// Discard messages past their retention and stale challenges and sessions
+    pub async fn prune_expired(&self) -> Result<u64> {
+        let now = Utc::now();
+        self.challenges.retain(|_, (_, expires_at)| *expires_at > now);
+        self.drop_expired_sessions(now);
+        self.store.prune(now).await
+    }
+
+    /// Forget a participant entirely: its registration, sessions and every
+    /// message held for or from it. Returns how many messages were deleted.
+    pub async fn purge_participant(&self, global_id: &str) -> Result<u64> {
+        if self.peers.remove(global_id).is_some() {
+            self.verifier.write().unwrap().forget_key(global_id);
+        }
+        self.sessions.retain(|_, s| s.peer != global_id);
+        self.challenges.remove(global_id);
+        self.store.purge_participant(global_id).await
+    }
+
+    /// Start the periodic retention sweep
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        let interval = Duration::from_secs(self.config.prune_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if self.ensure_active().is_err() {
+                    continue;
+                }
+                match self.prune_expired().await {
+                    Ok(0) => {}
+                    Ok(removed) => info!("Relay discarded {} expired messages", removed),
+                    Err(e) => warn!("Relay retention sweep failed: {}", e),
+                }
+            }
+        })
+    }
+
+    fn drop_expired_sessions(&self, now: DateTime<Utc>) {
+        self.sessions.retain(|_, s| s.expires_at > now);
+        self.metered_sessions.retain(|token, _| self.sessions.contains_key(token));
+    }
+
+    /// Charge the session's peer for the time since the session was last metered
+    fn meter_session(&self, token: &str, peer: &str) {
+        let Some(ref metering) = self.metering else { return };
+        let now = Utc::now();
+        if let Some(previous) = self.metered_sessions.insert(token.to_string(), now) {
+            metering.charge_relay_time(peer, (now - previous).to_std().unwrap_or_default());
+        }
+    }
+
+    fn session_peer(&self, token: &str) -> Result<String> {
+        let session = self
+            .sessions
+            .get(token)
+            .ok_or_else(|| SynapseError::AuthenticationError("Unknown relay session".to_string()))?;
+        if session.expires_at <= Utc::now() {
+            return Err(SynapseError::AuthenticationError("Relay session expired".to_string()));
+        }
+        Ok(session.peer.clone())
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.storage_key));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|e| SynapseError::Encryption(e.to_string()))?;
+        let mut sealed = Vec::with_capacity(12 + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < 12 {
+            return Err(SynapseError::Decryption("Sealed relay message is truncated".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(12);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.storage_key));
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| SynapseError::Decryption("Relay storage key does not match".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        policy::PolicyRule,
+        synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper},
+        types::SecurityLevel,
+    };
+
+    fn message(to: &str, content: &[u8]) -> SecureMessage {
+        SecureMessage {
+            message_id: UuidWrapper::new(Uuid::new_v4()),
+            to_global_id: to.to_string(),
+            from_global_id: "alice@example.com".to_string(),
+            encrypted_content: content.to_vec(),
+            signature: Vec::new(),
+            timestamp: DateTimeWrapper::new(Utc::now()),
+            security_level: SecurityLevel::Private,
+            routing_path: Vec::new(),
+            metadata: Default::default(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn holds_messages_until_authenticated_peer_acknowledges() {
+        let store = Arc::new(MemoryRelayStore::new());
+        let relay = RelayServer::new(RelayConfig::default(), store.clone());
+
+        let mut phone = CryptoManager::new();
+        let (_, public_key) = phone.generate_keypair().unwrap();
+        relay.register_peer("phone@example.com", &public_key, None).unwrap();
+
+        assert!(relay.accept(&message("stranger@example.com", b"hi")).await.is_err());
+        relay.accept(&message("phone@example.com", b"ciphertext")).await.unwrap();
+
+        // Payload is sealed at rest
+        let held = store.pending("phone@example.com", Utc::now()).await.unwrap();
+        assert!(!held[0].sealed.windows(10).any(|w| w == b"ciphertext"));
+
+        let challenge = relay.challenge("phone@example.com").unwrap();
+        assert!(relay.authenticate("phone@example.com", b"forged").is_err());
+
+        let challenge_again = relay.challenge("phone@example.com").unwrap();
+        assert_ne!(challenge, challenge_again);
+        let signature = phone.sign_message(&challenge_again).unwrap();
+        let session = relay.authenticate("phone@example.com", &signature).unwrap();
+
+        let delivered = relay.fetch(&session.token).await.unwrap();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].message.encrypted_content, b"ciphertext".to_vec());
+
+        relay.acknowledge(&session.token, &[delivered[0].id]).await.unwrap();
+        assert!(relay.fetch(&session.token).await.unwrap().is_empty());
+    }
+
+    struct RecordingNotifier(tokio::sync::mpsc::UnboundedSender<(String, WakePayload)>);
+
+    #[async_trait]
+    impl PushNotifier for RecordingNotifier {
+        fn platform(&self) -> PushPlatform {
+            PushPlatform::Webhook
+        }
+
+        async fn wake(&self, device_token: &str, payload: &WakePayload) -> Result<()> {
+            let _ = self.0.send((device_token.to_string(), payload.clone()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn wakes_dormant_peer_with_only_the_wake_token() {
+        let relay = RelayServer::new(RelayConfig::default(), Arc::new(MemoryRelayStore::new()));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        relay.add_push_notifier(Arc::new(RecordingNotifier(tx)));
+
+        let mut phone = CryptoManager::new();
+        let (_, public_key) = phone.generate_keypair().unwrap();
+        relay.register_peer("phone@example.com", &public_key, None).unwrap();
+        let wake_token = relay
+            .register_push("phone@example.com", PushPlatform::Webhook, "https://push.example.com/device")
+            .unwrap();
+
+        relay.accept(&message("phone@example.com", b"secret")).await.unwrap();
+        let (device, payload) = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(device, "https://push.example.com/device");
+        assert_eq!(payload, WakePayload { wake_token });
+
+        // A second message within the wake interval does not wake the device again
+        relay.accept(&message("phone@example.com", b"more")).await.unwrap();
+        tokio::task::yield_now().await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn enforces_quota_and_retention() {
+        let relay = RelayServer::new(RelayConfig::default(), Arc::new(MemoryRelayStore::new()));
+        let mut phone = CryptoManager::new();
+        let (_, public_key) = phone.generate_keypair().unwrap();
+        let policy = RelayPolicy { max_messages: 1, max_bytes: 1024 * 1024, retention_secs: 0 };
+        relay.register_peer("phone@example.com", &public_key, Some(policy)).unwrap();
+
+        // Zero retention: each message expires immediately, so the quota frees up again
+        relay.accept(&message("phone@example.com", b"one")).await.unwrap();
+        assert_eq!(relay.prune_expired().await.unwrap(), 1);
+
+        relay.set_policy("phone@example.com", RelayPolicy { max_messages: 1, max_bytes: 1024, retention_secs: 60 }).unwrap();
+        relay.accept(&message("phone@example.com", b"two")).await.unwrap();
+        let err = relay.accept(&message("phone@example.com", b"three")).await.unwrap_err();
+        assert!(matches!(err, SynapseError::PolicyViolation { .. }));
+        assert_eq!(relay.usage("phone@example.com").await.unwrap().messages, 1);
+    }
+
+    #[tokio::test]
+    async fn refuses_messages_outside_data_residency() {
+        let policies = Arc::new(PolicyEngine::new());
+        policies.add_rule(PolicyRule::new("eu-residency", "*").for_region("eu").require_residency("eu"));
+        policies.set_participant_region("phone@example.com", Some("eu-west"));
+        let config = RelayConfig { region: Some("us-east".to_string()), ..Default::default() };
+        let relay = RelayServer::new(config, Arc::new(MemoryRelayStore::new())).with_policies(policies);
+
+        let mut phone = CryptoManager::new();
+        let (_, public_key) = phone.generate_keypair().unwrap();
+        relay.register_peer("phone@example.com", &public_key, None).unwrap();
+        relay.register_peer("laptop@example.com", &public_key, None).unwrap();
+
+        let err = relay.accept(&message("phone@example.com", b"gdpr")).await.unwrap_err();
+        assert!(matches!(err, SynapseError::PolicyViolation { ref rule, .. } if rule == "eu-residency"));
+        assert_eq!(relay.usage("phone@example.com").await.unwrap().messages, 0);
+        relay.accept(&message("laptop@example.com", b"fine")).await.unwrap();
+    }
+}
@@ -1,18 +1,28 @@
 //! Main Synapse router implementation
 
 use crate::{
-    types::{SimpleMessage, SecureMessage, SecurityLevel, MessageType},
-    identity::IdentityRegistry,
+    types::{SimpleMessage, SecureMessage, SecurityLevel, MessageType, Attachment, GlobalIdentity},
+    identity::{IdentityRegistry, LocalIdentity},
     config::Config,
     error::Result,
     email::SynapseEmailMessage,
     CryptoManager,
+    crypto::StorageKey,
     EmailTransport,
     blockchain::serialization::{DateTimeWrapper, UuidWrapper},
+    history::MESSAGE_ID_KEY,
+    logging,
+    protocol::{self, VersionRange},
+    capabilities::CapabilityReport,
+    limits::ResourceGovernor,
+    crypto_pool::CryptoWorkerPool,
+    buffer_pool::{BufferPool, BufferPoolStats},
+    runtime::{RuntimeTopology, SubsystemStats},
+    transport::abstraction::IncomingMessage,
 };
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{info, debug, warn};
+use tracing::{info, debug, warn, Instrument};
 use uuid::Uuid;
 use chrono::Utc;
 
@@ -29,6 +39,10 @@ pub struct SynapseRouter {
     config: Config, // Router configuration settings
     /// Our global identity
     our_global_id: String,
+    /// Caps on concurrent cryptographic work
+    limits: Arc<ResourceGovernor>,
+    /// Checks received signatures in batches
+    crypto_pool: Arc<CryptoWorkerPool>,
 }
 
 impl SynapseRouter {
@@ -41,15 +55,29 @@ impl SynapseRouter {
         let identity = Arc::new(RwLock::new(IdentityRegistry::new()));
         
         // Initialize email transport
-        let email_transport = EmailTransport::new(config.email.clone()).await?;
+        #[allow(unused_mut)]
+        let mut email_transport = EmailTransport::new(config.email.clone()).await?;
+        #[cfg(feature = "email")]
+        if let Some(proxy) = crate::transport::proxy::ProxyDialer::from_config(&config.proxy)?
+            .route("email", &config.email.smtp.host)
+        {
+            warn!("SMTP cannot be proxied; email sends to {} will be refused", config.email.smtp.host);
+            email_transport.require_proxy(proxy.display_url());
+        }
+        #[cfg(feature = "email")]
+        email_transport.set_sending_limits(&config.email_sending);
         let email = Arc::new(RwLock::new(email_transport));
         
+        let limits = Arc::new(ResourceGovernor::new(&config.limits));
+        let crypto_pool = Arc::new(CryptoWorkerPool::new(limits.clone()));
         Ok(Self {
             crypto,
             identity,
             email,
             config: config,
             our_global_id,
+            limits,
+            crypto_pool,
         })
     }
 
@@ -59,53 +87,184 @@ impl SynapseRouter {
         simple_msg: SimpleMessage,
         destination_global_id: String,
     ) -> Result<()> {
-        info!("Sending message to {}: {}", destination_global_id, simple_msg.content);
-        
-        // Create secure message
-        let mut secure_msg = SecureMessage {
-            message_id: UuidWrapper::new(uuid::Uuid::new_v4()),
-            to_global_id: destination_global_id.clone(),
-            from_global_id: self.our_global_id.clone(),
-            encrypted_content: Vec::new(),
-            signature: Vec::new(),
-            timestamp: DateTimeWrapper::new(chrono::Utc::now()),
-            security_level: SecurityLevel::Authenticated,
-            routing_path: Vec::new(),
-            metadata: simple_msg.metadata.clone(),
-        };
-        
-        // Apply cryptographic operations if available
+        self.send_signed_message(simple_msg, destination_global_id, None, Vec::new()).await
+    }
+
+    /// Send a message on behalf of one of this node's local identities
+    pub async fn send_message_as(
+        &self,
+        simple_msg: SimpleMessage,
+        destination_global_id: String,
+        sender: &LocalIdentity,
+    ) -> Result<()> {
+        self.send_signed_message(simple_msg, destination_global_id, Some(sender), Vec::new()).await
+    }
+
+    /// Send a message with attachments, streaming any that are too large to go inline
+    pub async fn send_message_with_attachments(
+        &self,
+        simple_msg: SimpleMessage,
+        destination_global_id: String,
+        mut attachments: Vec<Attachment>,
+    ) -> Result<()> {
+        if attachments.iter().any(Attachment::needs_offload) {
+            let streams = crate::streaming::StreamManager::new(Arc::new(self.clone()));
+            let offloaded = streams
+                .offload_attachments(&mut attachments, &destination_global_id, &self.our_global_id)
+                .await?;
+            debug!("Offloaded {} attachment(s) for {}", offloaded, destination_global_id);
+        }
+
+        self.send_signed_message(simple_msg, destination_global_id, None, attachments).await
+    }
+
+    /// Share `limits` with the rest of the node, replacing the router's own
+    pub fn set_resource_limits(&mut self, limits: Arc<ResourceGovernor>) {
+        self.crypto_pool = Arc::new(CryptoWorkerPool::new(limits.clone()));
+        self.limits = limits;
+    }
+
+    /// Resource limits the router's cryptographic work is held to
+    pub fn resource_limits(&self) -> Arc<ResourceGovernor> {
+        self.limits.clone()
+    }
+
+    /// Sign content with the router's own key
+    pub async fn sign(&self, content: &str) -> Result<Vec<u8>> {
+        let _slot = self.limits.crypto_slot().await;
+        self.crypto.read().await.sign_message(content)
+    }
+
+    /// Check the signatures on received messages together on the crypto worker pool
+    ///
+    /// Each message's [`VerificationInfo`](crate::transport::abstraction::VerificationInfo)
+    /// is filled in as [`IncomingMessage::verify`] would, with the body taken
+    /// as the signed content.
+    pub async fn verify_incoming(&self, messages: &mut [IncomingMessage]) {
+        let mut checked = Vec::new();
+        let mut jobs = Vec::new();
         {
             let crypto = self.crypto.read().await;
-            
-            // Try to encrypt if we have recipient's key
-            if let SecurityLevel::Secure = secure_msg.security_level {
-                if let Ok(encrypted) = crypto.encrypt_message(&simple_msg.content, &simple_msg.to) {
-                    secure_msg.encrypted_content = encrypted;
+            for (index, incoming) in messages.iter_mut().enumerate() {
+                let content = String::from_utf8_lossy(&incoming.message.encrypted_content).into_owned();
+                if let Some(job) = incoming.verification_job(&crypto, &content, None) {
+                    checked.push(index);
+                    jobs.push(job);
                 }
             }
         }
+        for (index, valid) in checked.into_iter().zip(self.crypto_pool.verify_batch(jobs).await) {
+            messages[index].record_signature(valid);
+        }
+    }
+
+    /// Worker pool received signatures are checked on
+    pub fn crypto_pool(&self) -> Arc<CryptoWorkerPool> {
+        self.crypto_pool.clone()
+    }
+
+    /// Check a peer's signature over content against their known public key
+    pub async fn verify_signature(&self, content: &str, signature: &[u8], sender_global_id: &str) -> Result<bool> {
+        let _slot = self.limits.crypto_slot().await;
+        self.crypto.read().await.verify_signature(content, signature, sender_global_id)
+    }
+
+    /// Drop the cached identity, learned name resolutions and public key of
+    /// a participant, returning how many entries were removed
+    pub async fn forget_identity(&self, global_id: &str) -> usize {
+        let identities = self.identity.read().await.forget(global_id);
+        let key = self.crypto.write().await.forget_key(global_id);
+        identities + key as usize
+    }
+
+    /// Send a plain email (no Synapse envelope) through the email transport
+    #[cfg(feature = "email")]
+    pub async fn send_plain_email(&self, email: &lettre::Message) -> Result<()> {
+        self.email.read().await.send_email(email).await
+    }
+
+    /// Wrap, encrypt and deliver an already-signed message via email
+    async fn send_signed_message(
+        &self,
+        simple_msg: SimpleMessage,
+        destination_global_id: String,
+        sender: Option<&LocalIdentity>,
+        attachments: Vec<Attachment>,
+    ) -> Result<()> {
+        let from_global_id = sender.map_or_else(|| self.our_global_id.clone(), |sender| sender.global_id.clone());
+        let message_id = Uuid::new_v4();
+        let correlation_id = simple_msg
+            .metadata
+            .get(MESSAGE_ID_KEY)
+            .cloned()
+            .unwrap_or_else(|| message_id.to_string());
+        let span = logging::current_or_message_span(&correlation_id, &destination_global_id, "send");
+
+        async move {
+            info!("Sending message to {}", destination_global_id);
         
-        // Sign the message
-        let signature = {
-            let crypto = self.crypto.read().await;
-            crypto.sign_message(&simple_msg.content).unwrap_or_default()
-        };
-        secure_msg.signature = signature;
+            // Create secure message
+            let mut secure_msg = SecureMessage {
+                message_id: UuidWrapper::new(message_id),
+                to_global_id: destination_global_id.clone(),
+                from_global_id: from_global_id.clone(),
+                encrypted_content: Vec::new(),
+                signature: Vec::new(),
+                timestamp: DateTimeWrapper::new(chrono::Utc::now()),
+                security_level: SecurityLevel::Authenticated,
+                routing_path: Vec::new(),
+                metadata: simple_msg.metadata.clone(),
+                attachments,
+            };
         
-        // Send via email transport
-        let email_transport = self.email.read().await;
-        let simple_message = SimpleMessage {
-            to: destination_global_id.clone(),
-            from_entity: self.our_global_id.clone(),
-            content: serde_json::to_string(&secure_msg)?,
-            message_type: simple_msg.message_type.clone(),
-            metadata: simple_msg.metadata.clone(),
-        };
-        email_transport.send_message(&secure_msg, &self.our_global_id, &destination_global_id, &simple_message).await?;
+            let crypto_slot = self.limits.crypto_slot().await;
+            // Apply cryptographic operations if available
+            {
+                let crypto = self.crypto.read().await;
+            
+                // Try to encrypt if we have recipient's key
+                if let SecurityLevel::Secure = secure_msg.security_level {
+                    if let Ok(encrypted) = crypto.encrypt_message(&simple_msg.content, &simple_msg.to) {
+                        secure_msg.encrypted_content = encrypted;
+                    }
+                }
+            }
         
-        info!("Message sent successfully to {}", destination_global_id);
-        Ok(())
+            VersionRange::LOCAL.stamp(&mut secure_msg.metadata);
+
+            // Sign last, over the final ID, timestamp and attachments
+            let signed = match sender {
+                Some(sender) => secure_msg.sign_canonical(&simple_msg.content, |payload| sender.sign_bytes(payload)),
+                None => {
+                    let crypto = self.crypto.read().await;
+                    secure_msg.sign_canonical(&simple_msg.content, |payload| crypto.sign_bytes(payload))
+                }
+            };
+            if let Err(e) = signed {
+                debug!("Sending {} unsigned: {}", message_id, e);
+                secure_msg.signature.clear();
+            }
+            drop(crypto_slot);
+        
+            // Send via email transport
+            let email_transport = self.email.read().await;
+            // Inline attachments travel as MIME parts, so keep them out of the JSON envelope
+            let mut envelope = secure_msg.clone();
+            envelope.attachments.retain(|attachment| attachment.data().is_none());
+            let simple_message = SimpleMessage {
+                to: destination_global_id.clone(),
+                from_entity: from_global_id.clone(),
+                content: serde_json::to_string(&envelope)?,
+                message_type: simple_msg.message_type.clone(),
+                metadata: simple_msg.metadata.clone(),
+            };
+            email_transport.send_message(&secure_msg, &from_global_id, &destination_global_id, &simple_message).await?;
+        
+            info!("Message sent successfully to {}", destination_global_id);
+            Ok(())
+        }
+        .instrument(span)
+        .await
     }
 
     /// Receive messages from all transports
@@ -117,7 +276,14 @@ impl SynapseRouter {
         let email_messages = email_transport.receive_messages().await?;
         
         for email_msg in email_messages {
-            match self.process_email_message(email_msg).await {
+            let correlation_id = email_msg
+                .metadata
+                .get(MESSAGE_ID_KEY)
+                .or(email_msg.request_id.as_ref())
+                .cloned()
+                .unwrap_or_default();
+            let span = logging::message_span(&correlation_id, &email_msg.from_entity, "receive");
+            match self.process_email_message(email_msg).instrument(span).await {
                 Ok(processed_msg) => {
                     all_messages.push(processed_msg);
                 }
@@ -140,20 +306,24 @@ impl SynapseRouter {
             from_entity: email_msg.from_entity,
             content: email_msg.content,
             message_type: MessageType::Direct,
-            metadata: std::collections::HashMap::new(),
+            metadata: email_msg.metadata,
         };
         
         // Try to parse as secure message
         if let Ok(secure_msg) = serde_json::from_str::<SecureMessage>(&simple_msg.content) {
+            protocol::check_envelope(&simple_msg.from_entity, &secure_msg.metadata)?;
             // Decrypt and return message  
+            let _slot = self.limits.crypto_slot().await;
             let crypto_manager = self.crypto.read().await;
             if let Ok(decrypted_content) = crypto_manager.decrypt_message(&secure_msg.encrypted_content) {
+                let mut metadata = simple_msg.metadata;
+                metadata.extend(secure_msg.metadata);
                 let decrypted_msg = SimpleMessage {
                     to: simple_msg.to,
                     from_entity: simple_msg.from_entity,
                     content: decrypted_content,
                     message_type: simple_msg.message_type,
-                    metadata: simple_msg.metadata,
+                    metadata,
                 };
                 return Ok(decrypted_msg);
             }
@@ -172,12 +342,34 @@ impl SynapseRouter {
         crypto_manager.import_public_key(global_id, public_key_pem).map_err(|e| e.into())
     }
 
+    /// Encrypt content to a peer's public key, or `None` if we do not hold it
+    #[cfg(feature = "crypto")]
+    pub async fn encrypt_for(&self, content: &str, global_id: &str) -> Option<Vec<u8>> {
+        let _slot = self.limits.crypto_slot().await;
+        let crypto = self.crypto.read().await;
+        if !crypto.has_key_for(global_id) {
+            return None;
+        }
+        crypto.encrypt_message(content, global_id).ok()
+    }
+
+    /// Use an existing PKCS#8 PEM private key as our own
+    #[cfg(feature = "crypto")]
+    pub async fn load_private_key(&self, pem: &str) -> Result<()> {
+        self.crypto.write().await.load_private_key(pem)
+    }
+
     /// Generate our own keypair
     pub async fn generate_keypair(&self) -> Result<(String, String)> {
         let mut crypto_manager = self.crypto.write().await;
         crypto_manager.generate_keypair().map_err(|e| e.into())
     }
 
+    /// Key for encrypting local storage for `purpose`, derived from our private key
+    pub async fn storage_key(&self, purpose: &str) -> Result<StorageKey> {
+        self.crypto.read().await.storage_key(purpose)
+    }
+
     /// Get our global identity
     pub fn get_our_global_id(&self) -> &str {
         &self.our_global_id
@@ -187,7 +379,7 @@ impl SynapseRouter {
     pub async fn get_health(&self) -> RouterHealth {
         RouterHealth {
             status: "healthy".to_string(),
-            crypto_available: true,
+            crypto_available: cfg!(feature = "crypto"),
             email_available: true,
             known_peers: {
                 let identity_registry = self.identity.read().await;
@@ -198,6 +390,9 @@ impl SynapseRouter {
                 crypto_manager.known_entities().len()
             },
             our_global_id: self.our_global_id.clone(),
+            capabilities: crate::capabilities(),
+            buffer_pool: BufferPool::shared().stats(),
+            runtime: RuntimeTopology::current().stats(),
         }
     }
 
@@ -213,6 +408,7 @@ impl SynapseRouter {
             security_level: SecurityLevel::Authenticated,
             routing_path: Vec::new(),
             metadata: simple_msg.metadata.clone(),
+            attachments: Vec::new(),
         };
 
         // Apply cryptographic operations if available
@@ -261,12 +457,53 @@ impl SynapseRouter {
         identity.register_entity(global_id, name, profile)
     }
     
+    /// Every identity in the registry cache
+    pub async fn known_identities(&self) -> Vec<GlobalIdentity> {
+        self.identity.read().await.list_entities()
+    }
+
+    /// Put identities exported by another node into the registry cache,
+    /// replacing entries with the same global ID; returns how many were added
+    pub async fn restore_identities(&self, identities: Vec<GlobalIdentity>) -> usize {
+        let registry = self.identity.write().await;
+        let mut added = 0;
+        for identity in identities {
+            if registry.has_global_id(&identity.global_id) {
+                let global_id = identity.global_id.clone();
+                registry.update_identity(&global_id, identity).ok();
+            } else if let Err(e) = registry.register_identity(identity) {
+                debug!("Skipped restored identity: {}", e);
+            } else {
+                added += 1;
+            }
+        }
+        added
+    }
+
     /// Add an entity's key
     pub async fn add_entity_key(&self, global_id: &str, public_key: &str) -> Result<()> {
         let mut crypto = self.crypto.write().await;
         crypto.import_public_key(global_id, public_key)?;
         Ok(())
     }
+
+    /// Make sure an entity's public key is loaded, importing it from the identity registry if needed
+    pub async fn ensure_peer_key(&self, global_id: &str) -> bool {
+        if self.crypto.read().await.known_entities().iter().any(|id| id == global_id) {
+            return true;
+        }
+        let registered_key = self.identity.read().await.get_public_key(global_id);
+        match registered_key.filter(|key| !key.is_empty()) {
+            Some(key) => match self.crypto.write().await.import_public_key(global_id, &key) {
+                Ok(()) => true,
+                Err(e) => {
+                    debug!("Registered key for {} could not be imported: {}", global_id, e);
+                    false
+                }
+            },
+            None => false,
+        }
+    }
 }
 
 /// Router health information  
@@ -278,6 +515,12 @@ pub struct RouterHealth {
     pub known_peers: usize,
     pub known_keys: usize,
     pub our_global_id: String,
+    /// What this binary was built with
+    pub capabilities: CapabilityReport,
+    /// Reuse of the transports' message buffers
+    pub buffer_pool: BufferPoolStats,
+    /// Tasks spawned, running and waiting per subsystem
+    pub runtime: Vec<SubsystemStats>,
 }
 
 /// Check if SMTP is configured
@@ -317,5 +560,6 @@ async fn encrypt_message_for_recipient(
         security_level: SecurityLevel::Secure,
         routing_path: Vec::new(),
         metadata: simple_msg.metadata.clone(),
+        attachments: Vec::new(),
     })
 }
@@ -68,8 +68,9 @@
 //!
 //! ### Automatic Mode Detection
 //! ```rust
-//! // The router automatically detects your network situation
+//! // The router detects your network situation when the email server is set up
 //! let router = EnhancedSynapseRouter::new(config, entity_id).await?;
+//! router.ensure_email_server().await;
 //! 
 //! match router.email_server_connectivity() {
 //!     Some(info) if info.contains("RunLocalServer") => {
@@ -202,7 +203,7 @@
 //! ### Email Server Access
 //! ```rust
 //! // Configure email server if running locally
-//! if let Some(email_server) = router.email_server() {
+//! if let Some(email_server) = router.ensure_email_server().await {
 //!     // Add users for authentication
 //!     email_server.add_user(UserAccount {
 //!         username: "alice".to_string(),
@@ -215,7 +216,7 @@
 //!             can_relay: false,
 //!             is_admin: false,
 //!         },
-//!     })?;
+//!     }).await?;
 //!     
 //!     // Add domains for email routing
 //!     email_server.add_local_domain("mydomain.com")?;
@@ -251,19 +252,114 @@
 
 use crate::{
     types::{SimpleMessage, SecureMessage, SecurityLevel, MessageType},
-    transport::MultiTransportRouter,
-    transport::abstraction::MessageUrgency,
+    transport::{MultiTransportRouter, PreparedRoute},
+    transport::abstraction::{MessageUrgency, TransportCapabilities, TransportFactory, TransportType},
     transport::TransportRoute,
-    config::Config,
-    error::Result,
+    transport::router::ProductionTransportProvider,
+    config::{Config, EmailServerStartup},
+    error::{Result, SynapseError},
     email_server::{SynapseEmailServer, ServerRecommendation},
     router::SynapseRouter,
+    contacts::ContactBook,
+    organization::{OrgPolicy, OrganizationRegistry},
+    policy::{NetworkScope, PolicyDecision, PolicyEngine},
+    identity::{self, EncryptedIdentityBundle, LocalIdentity, LocalIdentityManager, MigrationProof, TransportBindings},
 };
 use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
+use crate::synapse::blockchain::{BlockchainConfig, LightClient, LightClientMessage, SynapseBlockchain};
+#[cfg(feature = "crypto")]
+use crate::relay::{MemoryRelayStore, RelayServer};
+#[cfg(feature = "crypto")]
+use crate::transport::fast_relay::FastRelayEndpoint;
+#[cfg(feature = "crypto")]
+use crate::groups::{Group, GroupCiphertext, GroupKeyPackage, GroupManager, GROUP_PAYLOAD_KEY};
+use crate::webhooks::WebhookDispatcher;
+use crate::indicators::{Indicator, IndicatorEvent, IndicatorHub, INDICATOR_KEY};
+use crate::payload_stream::{ChunkSender, PayloadStream, PayloadStreams};
+use crate::calls::{Call, CallEvent, CallManager, CallSignal, IceCandidate, MediaKind, CALL_SIGNAL_KEY};
+use crate::admission::{AdmissionController, AdmissionDecision, TrustSource};
+use crate::trust_gate::TrustGate;
+use crate::contacts::Contact;
+use crate::lifecycle::{LifecycleNotice, LifecycleRegistry, LifecycleState};
+use crate::purge::{PurgeTarget, PurgedItem};
+use crate::history::{AmendmentAction, ConversationHistory, HistoryEntry, MessageAmendment, AMENDMENT_KEY, MESSAGE_ID_KEY};
+#[cfg(feature = "search")]
+use crate::search::{ConversationIndex, HistoryQuery, SearchHit};
+use crate::summary::{ConversationSummary, Summarizer};
+use crate::notifications::{Notification, Severity};
+use crate::events::{EventBus, RouterEvent, TimedEvent};
+use crate::digest::{DeliveryDigest, DigestCollector, DIGEST_KEY};
+use crate::diagnostics::{self, CheckCategory, DiagnosticCheck, DiagnosticsReport, DnsResolver};
+use crate::transport::capability_probe::{CapabilityDigest, CapabilityProbe};
+use crate::transport::time_sync::{ClockEstimate, TimeProbe, TimeSample};
+use crate::transport::nat_behavior::NatClassification;
+use crate::transport::echo::{EchoProbe, EchoResult, EchoService, RECEIVED_FROM_KEY, RECEIVED_VIA_KEY};
+use crate::transport::abstraction::{IncomingMessage, SIGNATURE_STATUS_KEY};
+use crate::transport::binding::advertised_endpoint;
+use crate::endpoints::{EndpointPublisher, EndpointUpdate};
+use crate::cluster::Cluster;
+use crate::mailing_list::SuppressionList;
+#[cfg(feature = "email")]
+use crate::mailing_list::{self, BroadcastPlan, BroadcastReport, RecipientStatus, SuppressionReason};
+use crate::logging;
+use crate::protocol::{self, VersionRange};
+use crate::limits::{PeerAdmission, ResourceGovernor, ResourceUsage};
+use crate::startup::{BootProfile, StartupReport};
+use crate::election::{LeaderElection, Leadership};
+use crate::analytics::{AnalyticsOptions, InteractionGraph};
+use crate::metering::UsageMeter;
+use crate::node_state::{NodeState, StateConsistency, StateImport, StateLineage, StateMarker, NODE_STATE_VERSION};
 use uuid::Uuid;
 use chrono::Utc;
-use std::{sync::Arc, collections::HashMap};
-use tracing::{info, warn};
+use std::{sync::{Arc, Mutex, RwLock}, collections::{BTreeMap, BTreeSet, HashMap}, time::Duration};
+use tracing::{debug, info, warn, Instrument};
+use tokio::sync::broadcast;
+
+/// Metadata key carrying the message type over the direct transports
+const MESSAGE_TYPE_KEY: &str = "message_type";
+
+/// Configuration file of a standalone node, inside its data directory
+pub const STANDALONE_CONFIG_FILE: &str = "synapse.toml";
+
+/// Recipient and policies of a send, resolved before any transport is chosen
+struct ResolvedSend {
+    to_entity: String,
+    security_level: SecurityLevel,
+    prefers_email: bool,
+    policy: OrgPolicy,
+    decision: PolicyDecision,
+}
+
+/// Carries the chunks of a payload stream over the direct transports
+struct StreamRoute {
+    mt_router: Arc<MultiTransportRouter>,
+    security_level: SecurityLevel,
+    decision: PolicyDecision,
+}
+
+#[async_trait::async_trait]
+impl ChunkSender for StreamRoute {
+    async fn send(&self, message: SimpleMessage) -> Result<()> {
+        let secure_msg = SecureMessage {
+            message_id: UuidWrapper::new(Uuid::new_v4()),
+            to_global_id: message.to.clone(),
+            from_global_id: message.from_entity.clone(),
+            timestamp: DateTimeWrapper::new(Utc::now()),
+            security_level: self.security_level.clone(),
+            encrypted_content: message.content.into_bytes(),
+            signature: Vec::new(),
+            routing_path: Vec::new(),
+            metadata: message.metadata,
+            attachments: Vec::new(),
+        };
+        self.mt_router
+            .send_message_where(&message.to, &secure_msg, MessageUrgency::RealTime, |route| {
+                self.decision.permits_route(route)
+            })
+            .await
+            .map(|_| ())
+    }
+}
 
 /// Enhanced Synapse router with multi-transport support and email server
 pub struct EnhancedSynapseRouter {
@@ -271,8 +367,9 @@ pub struct EnhancedSynapseRouter {
     synapse_router: SynapseRouter,
     /// Multi-transport router for fast communication
     multi_transport: Option<Arc<MultiTransportRouter>>,
-    /// Local email server (SMTP/IMAP) for when we're externally accessible
-    email_server: Option<Arc<SynapseEmailServer>>,
+    /// Local email server (SMTP/IMAP) for when we're externally accessible;
+    /// unset until set up, see [`Self::ensure_email_server`]
+    email_server: tokio::sync::OnceCell<Option<Arc<SynapseEmailServer>>>,
     /// Configuration
     #[allow(dead_code)]
     config: Config,
@@ -280,121 +377,2732 @@ pub struct EnhancedSynapseRouter {
     our_global_id: String,
     /// Enable multi-transport features
     multi_transport_enabled: bool,
-    /// Email server enabled
-    email_server_enabled: bool,
+    /// Additional personas hosted by this router
+    local_identities: Arc<LocalIdentityManager>,
+    /// Contact book used for name resolution
+    contacts: Arc<ContactBook>,
+    /// Suspended, deprecated and deleted participants
+    lifecycle: Arc<LifecycleRegistry>,
+    /// Organizations whose policies apply to outbound messages
+    organizations: Arc<OrganizationRegistry>,
+    /// Destination policies checked before transport selection
+    policies: Arc<PolicyEngine>,
+    /// Store-and-forward relay for offline peers, when this node acts as one
+    #[cfg(feature = "crypto")]
+    relay: Option<Arc<RelayServer>>,
+    /// Trust ledger served to light clients, when this node is a full node
+    trust_ledger: Option<Arc<SynapseBlockchain>>,
+    /// Header-only trust verification, when this node is a light client
+    light_client: Option<Arc<LightClient>>,
+    /// HTTP endpoints notified of matching incoming messages
+    webhooks: Arc<WebhookDispatcher>,
+    /// Read receipts and typing indicators received from peers
+    indicators: Arc<IndicatorHub>,
+    /// Byte streams opened by us or by peers
+    payload_streams: Arc<PayloadStreams>,
+    /// Audio and video calls signaled through us
+    calls: Arc<CallManager>,
+    /// Exchanged messages, with edits and retractions applied
+    history: Arc<ConversationHistory>,
+    /// Full-text index kept in step with the history
+    #[cfg(feature = "search")]
+    search: Option<Arc<ConversationIndex>>,
+    /// Application-provided producer of conversation summaries
+    summarizer: Option<Arc<dyn Summarizer>>,
+    /// Throttling of inbound messages from untrusted strangers
+    admission: Arc<AdmissionController>,
+    /// Minimum sender trust for particular kinds of inbound messages
+    trust_gate: Arc<TrustGate>,
+    /// Encrypted group memberships, per local identity name
+    #[cfg(feature = "crypto")]
+    groups: dashmap::DashMap<String, Arc<GroupManager>>,
+    /// Discovery backends our endpoint updates are published to
+    endpoint_publishers: RwLock<Vec<Arc<dyn EndpointPublisher>>>,
+    /// Endpoints in the last update we announced
+    announced_endpoints: Mutex<BTreeMap<String, String>>,
+    /// Other instances sharing our identity, when running clustered
+    cluster: Option<Arc<Cluster>>,
+    /// Lifecycle events shared with the transports
+    events: EventBus,
+    /// Delivery tallies for the operator digest
+    digest: Arc<DigestCollector>,
+    /// Application-provided DNS lookups for the diagnostics' SRV and email checks
+    dns_resolver: Option<Arc<dyn DnsResolver>>,
+    /// Our pings awaiting reflection, and limits on the pings we answer
+    echo: Arc<EchoService>,
+    /// When we last collected from our fast email relays
+    relays_collected_at: Mutex<Option<std::time::Instant>>,
+    /// Addresses email broadcasts skip
+    suppressions: Arc<SuppressionList>,
+    /// Caps on peers, queued bytes, crypto work and connections
+    limits: Arc<ResourceGovernor>,
+    /// Timings of our startup
+    boot: Arc<BootProfile>,
+    /// Set once `start` has run, so an email server set up later starts at once
+    started: std::sync::atomic::AtomicBool,
+    /// Marker of the last node state exported or imported
+    state_lineage: StateLineage,
+    /// Our candidacy for active node, when on standby duty with another node
+    election: Option<Arc<LeaderElection>>,
+    /// Per-participant usage and quotas, when metering is enabled
+    metering: Option<Arc<UsageMeter>>,
 }
 
-impl EnhancedSynapseRouter {
-    /// Create a new enhanced router with multi-transport support and email server
-    pub async fn new(config: Config, our_global_id: String) -> Result<Self> {
-        info!("Initializing enhanced Synapse router with multi-transport support and email server");
-        
-        // Create the traditional Synapse router
-        let synapse_router = crate::router::SynapseRouter::new(config.clone(), our_global_id.clone()).await?;
-        
-        // Try to initialize multi-transport router
-        let multi_transport = match MultiTransportRouter::new(config.clone(), our_global_id.clone()).await {
-            Ok(mt_router) => {
-                info!("Multi-transport router initialized successfully");
-                Some(Arc::new(mt_router))
+impl EnhancedSynapseRouter {
+    /// Create a new enhanced router with multi-transport support
+    ///
+    /// The local email server is set up here only with
+    /// `startup.email_server = "eager"`; see [`Self::ensure_email_server`].
+    pub async fn new(config: Config, our_global_id: String) -> Result<Self> {
+        Self::with_boot_profile(config, our_global_id, Arc::new(BootProfile::new())).await
+    }
+
+    /// Like [`Self::new`], timing each step on `boot`
+    async fn with_boot_profile(config: Config, our_global_id: String, boot: Arc<BootProfile>) -> Result<Self> {
+        info!("Initializing enhanced Synapse router with multi-transport support");
+
+        let events = EventBus::default();
+        let limits = Arc::new(ResourceGovernor::new(&config.limits).with_event_bus(events.clone()));
+
+        // The traditional router, the transports and an eager email server
+        // do not depend on each other
+        let provider = ProductionTransportProvider::with_resource_limits(limits.clone());
+        let (synapse_router, multi_transport, email_server) = tokio::join!(
+            boot.time("router", crate::router::SynapseRouter::new(config.clone(), our_global_id.clone())),
+            boot.time(
+                "transports",
+                MultiTransportRouter::new_with_provider(config.clone(), our_global_id.clone(), Box::new(provider)),
+            ),
+            async {
+                match config.startup.email_server {
+                    EmailServerStartup::Eager => {
+                        Some(boot.time("email_server", Self::create_email_server(&config)).await)
+                    }
+                    EmailServerStartup::Lazy | EmailServerStartup::Disabled => None,
+                }
+            },
+        );
+
+        let mut synapse_router = synapse_router?;
+        synapse_router.set_resource_limits(limits.clone());
+
+        let multi_transport = match multi_transport {
+            Ok(mt_router) => {
+                info!("Multi-transport router initialized successfully");
+                Some(Arc::new(mt_router.with_event_bus(events.clone())))
+            }
+            Err(e) => {
+                warn!("Failed to initialize multi-transport router: {}", e);
+                warn!("Falling back to email-only mode");
+                None
+            }
+        };
+        
+        let multi_transport_enabled = multi_transport.is_some();
+
+        let policies = Arc::new(PolicyEngine::new());
+
+        let election = if config.election.enabled {
+            let election = LeaderElection::from_config(&config.election)?.with_event_bus(events.clone());
+            info!("Active/standby: candidate {} for lease {}", election.instance_id(), election.name());
+            Some(Arc::new(election))
+        } else {
+            None
+        };
+
+        let metering = if config.metering.enabled {
+            info!("Metering usage in {} second periods", config.metering.period_secs);
+            Some(Arc::new(UsageMeter::from_config(&config.metering)?))
+        } else {
+            None
+        };
+
+        #[cfg(feature = "crypto")]
+        let relay = config.relay.enabled.then(|| {
+            info!("Relay server role enabled");
+            let mut relay_config = config.relay.clone();
+            relay_config.region = relay_config.region.or_else(|| config.entity.region.clone());
+            let mut relay = RelayServer::new(relay_config, Arc::new(MemoryRelayStore::new())).with_policies(policies.clone());
+            if let Some(ref metering) = metering {
+                relay = relay.with_metering(Arc::clone(metering));
+            }
+            Arc::new(match election {
+                Some(ref election) => relay.with_election(Arc::clone(election)),
+                None => relay,
+            })
+        });
+        
+        let cluster = if config.cluster.enabled {
+            let cluster = Cluster::from_config(&config.cluster)?;
+            info!("Running as cluster instance {}", cluster.instance_id());
+            Some(Arc::new(cluster))
+        } else {
+            None
+        };
+        // Cluster instances share our identity, so exports name the instance
+        let state_lineage = StateLineage::new(match cluster {
+            Some(ref cluster) => cluster.instance_id().to_string(),
+            None => Uuid::new_v4().to_string(),
+        });
+
+        let admission = Arc::new(AdmissionController::new(config.admission.clone()));
+        let trust_gate = Arc::new(TrustGate::new(config.trust_gate.clone()));
+        let payload_streams = Arc::new(PayloadStreams::new(config.payload_streams.clone()));
+        let digest = Arc::new(DigestCollector::new(config.digest.clone()));
+        let echo = Arc::new(EchoService::new(config.echo.max_pings_per_minute));
+        let suppressions = Arc::new(match &config.mailing_list.suppression_file {
+            Some(path) => SuppressionList::load(path)?,
+            None => SuppressionList::new(),
+        });
+        #[cfg(feature = "search")]
+        let search = match config.storage.search_index {
+            Some(ref dir) => Some(Arc::new(ConversationIndex::open(std::path::Path::new(dir))?)),
+            None => None,
+        };
+
+        Ok(Self {
+            synapse_router,
+            multi_transport,
+            email_server: tokio::sync::OnceCell::new_with(email_server),
+            config,
+            our_global_id,
+            multi_transport_enabled,
+            local_identities: Arc::new(LocalIdentityManager::new().with_resource_limits(limits.clone())),
+            contacts: Arc::new(ContactBook::new()),
+            lifecycle: Arc::new(LifecycleRegistry::new()),
+            organizations: Arc::new(OrganizationRegistry::new()),
+            policies,
+            #[cfg(feature = "crypto")]
+            relay,
+            trust_ledger: None,
+            light_client: None,
+            webhooks: Arc::new(WebhookDispatcher::with_default_sender()),
+            indicators: Arc::new(IndicatorHub::new()),
+            payload_streams,
+            calls: Arc::new(CallManager::new()),
+            history: Arc::new(ConversationHistory::default()),
+            #[cfg(feature = "search")]
+            search,
+            summarizer: None,
+            admission,
+            trust_gate,
+            #[cfg(feature = "crypto")]
+            groups: dashmap::DashMap::new(),
+            endpoint_publishers: RwLock::new(Vec::new()),
+            announced_endpoints: Mutex::new(BTreeMap::new()),
+            cluster,
+            events,
+            digest,
+            dns_resolver: None,
+            echo,
+            relays_collected_at: Mutex::new(None),
+            suppressions,
+            limits,
+            boot,
+            started: std::sync::atomic::AtomicBool::new(false),
+            state_lineage,
+            election,
+            metering,
+        })
+    }
+
+    /// Set up the local email server, assessing connectivity first
+    ///
+    /// `None` when the assessment recommends the external provider or the
+    /// server cannot be set up.
+    async fn create_email_server(config: &Config) -> Option<Arc<SynapseEmailServer>> {
+        match SynapseEmailServer::with_mail_store(&config.mail_store).await {
+            Ok(server) => {
+                let connectivity = server.get_connectivity();
+                match &connectivity.recommended_config {
+                    ServerRecommendation::RunLocalServer { smtp_port, imap_port, external_ip } => {
+                        info!("Email server configured to run locally on {}:{}/{}", external_ip, smtp_port, imap_port);
+                        Some(Arc::new(server))
+                    }
+                    ServerRecommendation::RelayOnly { reason } => {
+                        info!("Email server configured for relay-only mode: {}", reason);
+                        Some(Arc::new(server))
+                    }
+                    ServerRecommendation::ExternalProvider { reason } => {
+                        warn!("Using external email provider: {}", reason);
+                        warn!("Email server will not be started locally");
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Failed to initialize email server: {}", e);
+                warn!("Will use external email provider only");
+                None
+            }
+        }
+    }
+    
+    /// A complete node in this process, keeping its state under `data_dir`
+    ///
+    /// Reads the node's configuration from [`STANDALONE_CONFIG_FILE`] in
+    /// `data_dir`, writing [`Config::standalone`] there on first run so it can
+    /// be edited. The node key is loaded from the configured private key file,
+    /// or generated. On top of what [`Self::new`] sets up, the node keeps an
+    /// in-process trust ledger and, with the `embedded-db` feature, a SQLite
+    /// store in `data_dir`. No Postgres, Redis or mail provider is needed:
+    ///
+    /// ```no_run
+    /// # async fn run() -> synapse::error::Result<()> {
+    /// use synapse::router_enhanced::EnhancedSynapseRouter;
+    ///
+    /// let router = EnhancedSynapseRouter::standalone("./synapse-node").await?;
+    /// router.start().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn standalone(data_dir: impl AsRef<std::path::Path>) -> Result<Self> {
+        let data_dir = data_dir.as_ref();
+        std::fs::create_dir_all(data_dir)?;
+        let config_path = data_dir.join(STANDALONE_CONFIG_FILE);
+        let config = if config_path.exists() {
+            Config::from_file(&config_path)?
+        } else {
+            let config = Config::standalone(data_dir);
+            config.to_file(&config_path)?;
+            info!("Wrote node configuration to {}", config_path.display());
+            config
+        };
+        Self::standalone_with_config(config, data_dir).await
+    }
+
+    /// A complete node like [`Self::standalone`], with `config` given rather
+    /// than read from `data_dir`, e.g. from [`Config::from_env`]
+    pub async fn standalone_with_config(config: Config, data_dir: impl AsRef<std::path::Path>) -> Result<Self> {
+        let data_dir = data_dir.as_ref();
+        std::fs::create_dir_all(data_dir)?;
+        let our_global_id = format!("{}@{}", config.entity.local_name, config.entity.domain);
+
+        // The embedded store, the router and the trust ledger are set up at once
+        let boot = Arc::new(BootProfile::new());
+        let store = async {
+            #[cfg(feature = "embedded-db")]
+            boot.time("embedded_db", Self::migrate_embedded_store(&data_dir.join("synapse.db"))).await?;
+            Ok::<(), SynapseError>(())
+        };
+        let ledger = boot.time("trust_ledger", SynapseBlockchain::new(BlockchainConfig::default()));
+        let (store, router, ledger) = tokio::join!(
+            store,
+            Self::with_boot_profile(config, our_global_id, Arc::clone(&boot)),
+            ledger,
+        );
+        store?;
+        let mut router = router?;
+        #[cfg(feature = "crypto")]
+        boot.time("node_key", router.load_or_create_node_key()).await?;
+
+        let ledger = ledger
+            .map_err(|e| SynapseError::ConfigurationError(format!("Could not start the trust ledger: {}", e)))?
+            .with_event_bus(router.events.clone());
+        router.set_trust_ledger(Arc::new(ledger));
+
+        info!("Standalone node {} ready in {}", router.our_global_id, data_dir.display());
+        Ok(router)
+    }
+
+    /// Load the node key from `security.private_key_path`, generating and
+    /// saving one there if allowed and there is none
+    #[cfg(feature = "crypto")]
+    async fn load_or_create_node_key(&self) -> Result<()> {
+        let security = &self.config.security;
+        let Some(ref private_path) = security.private_key_path else {
+            return Ok(());
+        };
+        let private_path = std::path::Path::new(private_path);
+        if private_path.exists() {
+            let pem = std::fs::read_to_string(private_path)?;
+            return self.synapse_router.load_private_key(&pem).await;
+        }
+        if !security.auto_generate_keys {
+            return Err(SynapseError::KeyNotFound(format!("No node key at {}", private_path.display())));
+        }
+
+        let (private_pem, public_pem) = self.synapse_router.generate_keypair().await?;
+        crate::crypto::write_sealed_file(private_path, None, private_pem.as_bytes())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(private_path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        if let Some(ref public_path) = security.public_key_path {
+            crate::crypto::write_sealed_file(std::path::Path::new(public_path), None, public_pem.as_bytes())?;
+        }
+        info!("Generated node key in {}", private_path.display());
+        Ok(())
+    }
+
+    /// Create the embedded SQLite store if needed and bring its schema up to date
+    #[cfg(feature = "embedded-db")]
+    async fn migrate_embedded_store(path: &std::path::Path) -> Result<()> {
+        use crate::synapse::storage::MigrationManager;
+        use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+
+        let options = SqliteConnectOptions::new().filename(path).create_if_missing(true);
+        let pool = SqlitePool::connect_with(options)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))?;
+        let plan = MigrationManager::embedded(pool.clone())
+            .migrate()
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))?;
+        if !plan.is_empty() {
+            info!("Migrated embedded store {}", path.display());
+        }
+        pool.close().await;
+        Ok(())
+    }
+
+    /// Send a message with automatic transport selection
+    ///
+    /// Returns the message ID, which later edits and retractions refer to.
+    pub async fn send_message_smart(
+        &self,
+        to_entity: &str,
+        content: &str,
+        message_type: MessageType,
+        security_level: SecurityLevel,
+        urgency: MessageUrgency,
+    ) -> Result<String> {
+        self.send_smart_from(None, to_entity, content, message_type, security_level, urgency, HashMap::new()).await
+    }
+
+    /// Send a message from one of the router's local identities
+    pub async fn send_message_as(
+        &self,
+        identity: &str,
+        to_entity: &str,
+        content: &str,
+        message_type: MessageType,
+        security_level: SecurityLevel,
+        urgency: MessageUrgency,
+    ) -> Result<String> {
+        let sender = self.local_identities.get(identity).ok_or_else(|| {
+            crate::error::SynapseError::Identity(format!("Unknown local identity: {}", identity))
+        })?;
+        self.send_smart_from(Some(&sender), to_entity, content, message_type, security_level, urgency, HashMap::new()).await
+    }
+
+    /// Send an alert, status update or approval request
+    ///
+    /// Critical alerts and approval requests are sent as interactive messages,
+    /// everything else in the background.
+    pub async fn send_notification(&self, to_entity: &str, notification: &Notification) -> Result<String> {
+        let message = notification.to_message(&self.our_global_id, to_entity)?;
+        let urgency = match notification {
+            Notification::Alert { severity: Severity::Critical, .. } | Notification::ApprovalRequest { .. } => {
+                MessageUrgency::Interactive
+            }
+            _ => MessageUrgency::Background,
+        };
+        self.send_smart_from(
+            None,
+            to_entity,
+            &message.content,
+            message.message_type,
+            SecurityLevel::Authenticated,
+            urgency,
+            message.metadata,
+        ).await
+    }
+
+    /// Send a prepared message, keeping its metadata, from one of the router's local identities
+    ///
+    /// Used by bridges that tag messages with conversation and origin details.
+    pub async fn send_prepared_as(
+        &self,
+        identity: &str,
+        message: SimpleMessage,
+        security_level: SecurityLevel,
+        urgency: MessageUrgency,
+    ) -> Result<String> {
+        let sender = self.local_identities.get(identity).ok_or_else(|| {
+            crate::error::SynapseError::Identity(format!("Unknown local identity: {}", identity))
+        })?;
+        self.send_smart_from(
+            Some(&sender),
+            &message.to,
+            &message.content,
+            message.message_type,
+            security_level,
+            urgency,
+            message.metadata,
+        ).await
+    }
+
+    /// Shared smart-send path for the primary identity and local personas
+    ///
+    /// Runs inside a message span so every log line of the send carries the
+    /// message ID and recipient.
+    async fn send_smart_from(
+        &self,
+        sender: Option<&LocalIdentity>,
+        to_entity: &str,
+        content: &str,
+        message_type: MessageType,
+        security_level: SecurityLevel,
+        urgency: MessageUrgency,
+        mut metadata: HashMap<String, String>,
+    ) -> Result<String> {
+        let message_id = metadata
+            .entry(MESSAGE_ID_KEY.to_string())
+            .or_insert_with(|| Uuid::new_v4().to_string())
+            .clone();
+        let span = logging::message_span(&message_id, to_entity, "send");
+        self.route_smart_from(message_id, sender, to_entity, content, message_type, security_level, urgency, metadata)
+            .instrument(span)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn route_smart_from(
+        &self,
+        message_id: String,
+        sender: Option<&LocalIdentity>,
+        to_entity: &str,
+        content: &str,
+        message_type: MessageType,
+        security_level: SecurityLevel,
+        urgency: MessageUrgency,
+        metadata: HashMap<String, String>,
+    ) -> Result<String> {
+        let from_global_id = sender.map_or_else(|| self.our_global_id.clone(), |s| s.global_id.clone());
+        if let Some(ref metering) = self.metering {
+            metering.charge_message(&from_global_id, content.len() as u64)?;
+        }
+        let ResolvedSend { to_entity, security_level, prefers_email, policy, decision } =
+            self.resolve_send(&from_global_id, to_entity, security_level)?;
+        let to_entity = to_entity.as_str();
+
+        info!("Sending smart message from {} to {} (urgency: {:?})", from_global_id, to_entity, urgency);
+        
+        // If multi-transport is available and urgency is high, try it first
+        if let Some(ref mt_router) = self.multi_transport {
+            let direct_urgency = matches!(urgency, MessageUrgency::RealTime | MessageUrgency::Interactive)
+                || mt_router.has_custom_transport_for(urgency).await;
+            if !prefers_email && policy.allows_direct_transport() && direct_urgency {
+                self.probe_before_large_send(to_entity, content.len()).await;
+                if let Some(Err(e)) = mt_router.peer_capabilities().protocol_version(to_entity) {
+                    return Err(e);
+                }
+
+                // Create secure message
+                let simple_msg = SimpleMessage {
+                    to: to_entity.to_string(),
+                    from_entity: from_global_id.clone(),
+                    content: content.to_string(),
+                    message_type: message_type.clone(),
+                    metadata: metadata.clone(),
+                };
+                
+                let mut secure_msg = self.create_secure_message(&simple_msg, security_level.clone()).await?;
+                if let Some(sender) = sender {
+                    if let Err(e) = secure_msg.sign_canonical(content, |payload| sender.sign_bytes(payload)) {
+                        debug!("Sending {} unsigned: {}", message_id, e);
+                        secure_msg.signature.clear();
+                    }
+                }
+                
+                // Try multi-transport first
+                match mt_router
+                    .send_message_where(to_entity, &secure_msg, urgency, |route| decision.permits_route(route))
+                    .await {
+                    Ok(delivery_receipt) => {
+                        info!("Message {} sent via multi-transport: {}", message_id, delivery_receipt.message_id);
+                        self.record_sent(&simple_msg, &delivery_receipt.transport_used.to_string());
+                        return Ok(message_id);
+                    }
+                    Err(e) => {
+                        warn!("Multi-transport failed: {}, falling back to email", e);
+                    }
+                }
+            }
+        }
+        
+        let simple_msg = SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_global_id,
+            content: content.to_string(),
+            message_type,
+            metadata,
+        };
+        self.deliver_by_email(message_id, sender, simple_msg, &policy, &decision).await
+    }
+
+    /// Resolve a recipient and check the sender's and destination policies
+    ///
+    /// Names go through the contact book and lifecycle redirects, and per-contact
+    /// and organization security floors are applied. Fails if no transport would
+    /// be allowed.
+    fn resolve_send(&self, from_global_id: &str, to_entity: &str, security_level: SecurityLevel) -> Result<ResolvedSend> {
+        // Resolve names through the contact book and apply per-contact defaults
+        let contact = self.contacts.resolve(to_entity);
+        let to_entity = contact.as_ref().map_or(to_entity, |c| c.global_id.as_str());
+        // Suspended and deleted recipients are refused; deprecated ones forward to their successor
+        let recipient = self.lifecycle.check_recipient(to_entity)?;
+        let security_level = match &contact {
+            Some(contact) => contact.effective_security(security_level),
+            None => security_level,
+        };
+        let prefers_email = contact.as_ref().is_some_and(|c| c.prefers_email());
+
+        // Apply the sender's organization policy
+        let policy = self.organizations.policy_for(from_global_id);
+        let security_level = policy.effective_security(security_level);
+        if !policy.allows_direct_transport() && !policy.allows_transport("email") {
+            return Err(SynapseError::AuthorizationError(format!(
+                "Organization policy for {} allows no available transport",
+                from_global_id
+            )));
+        }
+
+        // Destination policy is checked before any transport is chosen
+        let decision = self.policies.evaluate(from_global_id, &recipient, &security_level)?;
+        Ok(ResolvedSend { to_entity: recipient, security_level, prefers_email, policy, decision })
+    }
+
+    /// Email leg of a send, used when no direct transport delivered the message
+    async fn deliver_by_email(
+        &self,
+        message_id: String,
+        sender: Option<&LocalIdentity>,
+        simple_msg: SimpleMessage,
+        policy: &OrgPolicy,
+        decision: &PolicyDecision,
+    ) -> Result<String> {
+        let to_entity = simple_msg.to.clone();
+
+        // Typing and processing indicators are stale long before an email would arrive
+        if simple_msg.metadata.contains_key(INDICATOR_KEY)
+            && Indicator::decode(&simple_msg.content).is_ok_and(|i| i.is_ephemeral())
+        {
+            return Err(SynapseError::NoTransportAvailable(format!(
+                "No real-time transport to {} for an ephemeral indicator",
+                to_entity
+            )));
+        }
+        // So is call signaling
+        if simple_msg.metadata.contains_key(CALL_SIGNAL_KEY) {
+            return Err(SynapseError::NoTransportAvailable(format!(
+                "No real-time transport to {} for call signaling",
+                to_entity
+            )));
+        }
+
+        // Fallback to traditional email routing
+        if !policy.allows_transport("email") {
+            return Err(SynapseError::AuthorizationError(format!(
+                "Organization policy for {} does not allow email delivery to {}",
+                simple_msg.from_entity, to_entity
+            )));
+        }
+        self.policies.check_transport(decision, "email", NetworkScope::External)?;
+        info!("Using traditional email routing for {}", to_entity);
+        let sent = match sender {
+            Some(sender) => self.synapse_router.send_message_as(simple_msg.clone(), to_entity.clone(), sender).await,
+            None => self.synapse_router.send_message(simple_msg.clone(), to_entity.clone()).await,
+        };
+        match sent {
+            Ok(_) => {
+                self.record_sent(&simple_msg, "email");
+                Ok(message_id)
+            }
+            Err(e) => {
+                self.events.publish(RouterEvent::MessageDeadLettered {
+                    message_id,
+                    to: to_entity,
+                    reason: e.to_string(),
+                });
+                Err(e)
+            }
+        }
+    }
+
+    /// Send many messages at once, returning each message's ID or error in input order
+    ///
+    /// Built for high-throughput producers such as notification fan-out. Each
+    /// item is resolved and policy-checked like [`Self::send_message_smart`];
+    /// items bound for direct transports then go through
+    /// [`MultiTransportRouter::send_batch`], which groups them by target and
+    /// pipelines each group on one connection. Items no direct transport
+    /// delivered fall back to email individually. The target of each pair
+    /// replaces the message's `to`.
+    pub async fn send_batch(
+        &self,
+        messages: Vec<(String, SimpleMessage)>,
+        security_level: SecurityLevel,
+        urgency: MessageUrgency,
+    ) -> Vec<Result<String>> {
+        let mut results: Vec<Option<Result<String>>> = (0..messages.len()).map(|_| None).collect();
+        let direct_urgency = match &self.multi_transport {
+            Some(mt_router) => {
+                matches!(urgency, MessageUrgency::RealTime | MessageUrgency::Interactive)
+                    || mt_router.has_custom_transport_for(urgency).await
+            }
+            None => false,
+        };
+
+        // (index, message ID, message, resolution) of items taking a direct transport
+        let mut direct = Vec::new();
+        let mut secure = Vec::new();
+        let mut individual = Vec::new();
+        for (index, (target, mut message)) in messages.into_iter().enumerate() {
+            message.to = target;
+            message.from_entity = self.our_global_id.clone();
+            let message_id = message
+                .metadata
+                .entry(MESSAGE_ID_KEY.to_string())
+                .or_insert_with(|| Uuid::new_v4().to_string())
+                .clone();
+            if !direct_urgency {
+                individual.push((index, message));
+                continue;
+            }
+            let resolved = match self.resolve_send(&self.our_global_id, &message.to, security_level.clone()) {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    results[index] = Some(Err(e));
+                    continue;
+                }
+            };
+            if resolved.prefers_email || !resolved.policy.allows_direct_transport() {
+                individual.push((index, message));
+                continue;
+            }
+            message.to = resolved.to_entity.clone();
+            match self.create_secure_message(&message, resolved.security_level.clone()).await {
+                Ok(secure_msg) => {
+                    secure.push((message.to.clone(), secure_msg));
+                    direct.push((index, message_id, message, resolved));
+                }
+                Err(e) => results[index] = Some(Err(e)),
+            }
+        }
+
+        if let Some(mt_router) = self.multi_transport.as_ref().filter(|_| !direct.is_empty()) {
+            let decisions: HashMap<String, PolicyDecision> = direct
+                .iter()
+                .map(|(_, _, message, resolved)| (message.to.clone(), resolved.decision.clone()))
+                .collect();
+            let receipts = mt_router
+                .send_batch_where(secure, urgency, |target, route| {
+                    decisions.get(target).is_some_and(|decision| decision.permits_route(route))
+                })
+                .await;
+            for ((index, message_id, message, resolved), receipt) in direct.into_iter().zip(receipts) {
+                let result = match receipt {
+                    Ok(receipt) => {
+                        self.record_sent(&message, &receipt.transport_used.to_string());
+                        Ok(message_id)
+                    }
+                    Err(e) => {
+                        warn!("Batched message {} was not delivered directly: {}, falling back to email", message_id, e);
+                        self.deliver_by_email(message_id, None, message, &resolved.policy, &resolved.decision).await
+                    }
+                };
+                results[index] = Some(result);
+            }
+        }
+
+        for (index, message) in individual {
+            let result = self
+                .send_smart_from(
+                    None,
+                    &message.to,
+                    &message.content,
+                    message.message_type,
+                    security_level.clone(),
+                    urgency,
+                    message.metadata,
+                )
+                .await;
+            results[index] = Some(result);
+        }
+
+        info!("Sent a batch of {} messages", results.len());
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(SynapseError::TransportError("Batch item was not sent".into()))))
+            .collect()
+    }
+
+    /// Keep a sent message in the history so it can be amended later, and
+    /// count it towards the delivery digest
+    fn record_sent(&self, message: &SimpleMessage, transport: &str) {
+        let control_keys = [INDICATOR_KEY, AMENDMENT_KEY, CALL_SIGNAL_KEY, DIGEST_KEY];
+        if control_keys.iter().any(|key| message.metadata.contains_key(*key)) {
+            return;
+        }
+        self.archive(&mut message.clone(), &message.to);
+        self.events.publish(RouterEvent::MessageDelivered {
+            message_id: message.metadata.get(MESSAGE_ID_KEY).cloned().unwrap_or_default(),
+            to: message.to.clone(),
+            transport: transport.to_string(),
+        });
+    }
+
+    /// Record a message in the history unless the peer's data-residency
+    /// rules exclude the region the history store is in
+    fn archive(&self, message: &mut SimpleMessage, peer: &str) {
+        let storage = &self.config.storage;
+        if storage.history_store.is_some() {
+            let region = storage.region.as_deref().or(self.config.entity.region.as_deref());
+            let decision = self.policies.residency(&self.our_global_id, peer);
+            if self.policies.check_residency(&decision, "archive", region).is_err() {
+                return;
+            }
+        }
+        let message_id = self.history.record(message);
+        self.index_entry(self.history.get(&message_id));
+    }
+
+    /// Bring the search index up to date with a history entry
+    #[cfg_attr(not(feature = "search"), allow(unused_variables))]
+    fn index_entry(&self, entry: Option<HistoryEntry>) {
+        #[cfg(feature = "search")]
+        if let (Some(search), Some(entry)) = (&self.search, entry) {
+            if let Err(e) = search.index(&entry) {
+                warn!("Could not index message {}: {}", entry.message_id, e);
+            }
+        }
+    }
+
+    /// Search the conversation history
+    ///
+    /// Requires `storage.search_index` to be set.
+    #[cfg(feature = "search")]
+    pub fn search_history(&self, query: &HistoryQuery) -> Result<Vec<SearchHit>> {
+        let search = self.search.as_ref().ok_or_else(|| {
+            SynapseError::ConfigurationError("No search index configured (storage.search_index)".to_string())
+        })?;
+        search.search(query)
+    }
+
+    /// Summarize conversations with `summarizer` when asked to
+    pub fn set_summarizer(&mut self, summarizer: Arc<dyn Summarizer>) {
+        self.summarizer = Some(summarizer);
+    }
+
+    /// Fold the messages exchanged with `peer` since the last summary into it
+    ///
+    /// The summary is stored in the history, where [`ConversationHistory::summary`]
+    /// reads it back. Returns `None` if nothing has been exchanged with `peer`.
+    pub async fn summarize_conversation(&self, peer: &str) -> Result<Option<ConversationSummary>> {
+        let summarizer = self.summarizer.as_ref().ok_or_else(|| {
+            SynapseError::ConfigurationError("No summarizer set (set_summarizer)".to_string())
+        })?;
+        let summary = crate::summary::summarize(&self.history, summarizer.as_ref(), peer).await?;
+        if let Some(ref summary) = summary {
+            debug!("Summary of conversation with {} covers {} messages", peer, summary.message_count);
+        }
+        Ok(summary)
+    }
+
+    /// Warm up a route to `to_entity` ahead of a heavy exchange
+    ///
+    /// Resolves the recipient, checks organization and destination policy, loads
+    /// the recipient's key, and has the multi-transport router run discovery, NAT
+    /// traversal and pre-connection. The returned route is reused by sends until
+    /// it expires.
+    pub async fn prepare(&self, to_entity: &str, urgency: MessageUrgency) -> Result<PreparedRoute> {
+        let mt_router = self.multi_transport.as_ref().ok_or_else(|| {
+            SynapseError::NoTransportAvailable("Multi-transport router is not available".to_string())
+        })?;
+
+        let contact = self.contacts.resolve(to_entity);
+        let to_entity = contact.as_ref().map_or(to_entity, |c| c.global_id.as_str());
+        let recipient = self.lifecycle.check_recipient(to_entity)?;
+        let to_entity = recipient.as_str();
+        let security_level = match &contact {
+            Some(contact) => contact.effective_security(SecurityLevel::Authenticated),
+            None => SecurityLevel::Authenticated,
+        };
+
+        let policy = self.organizations.policy_for(&self.our_global_id);
+        if !policy.allows_direct_transport() {
+            return Err(SynapseError::AuthorizationError(format!(
+                "Organization policy for {} does not allow direct transports",
+                self.our_global_id
+            )));
+        }
+        let security_level = policy.effective_security(security_level);
+        let decision = self.policies.evaluate(&self.our_global_id, to_entity, &security_level)?;
+
+        let key_ready = self.synapse_router.ensure_peer_key(to_entity).await;
+        if !key_ready && matches!(security_level, SecurityLevel::Private | SecurityLevel::Secure) {
+            warn!("No public key known for {}; encrypted messages will need a key exchange first", to_entity);
+        }
+
+        let mut prepared = mt_router
+            .prepare_route(to_entity, urgency, |route| decision.permits_route(route))
+            .await?;
+        prepared.key_ready = key_ready;
+        Ok(prepared)
+    }
+
+    /// Plug in a transport at runtime
+    ///
+    /// `factory` must create a [`TransportType::Custom`] transport, which starts
+    /// immediately. Sends whose urgency is in `capabilities.supported_urgencies`
+    /// try it before the built-in transports, under the same destination
+    /// policies; it gets its own circuit breaker and metrics, publishes transport
+    /// events on [`Self::events`], and is listed in [`Self::status`].
+    pub async fn register_custom_transport(
+        &self,
+        factory: Box<dyn TransportFactory>,
+        capabilities: TransportCapabilities,
+    ) -> Result<()> {
+        let mt_router = self.multi_transport.as_ref().ok_or_else(|| {
+            SynapseError::NoTransportAvailable("Multi-transport router is not available".to_string())
+        })?;
+        mt_router.register_custom_transport(factory, capabilities).await
+    }
+
+    /// Stop a transport added with [`Self::register_custom_transport`]
+    pub async fn unregister_custom_transport(&self, transport_type: TransportType) -> Result<()> {
+        let mt_router = self.multi_transport.as_ref().ok_or_else(|| {
+            SynapseError::NoTransportAvailable("Multi-transport router is not available".to_string())
+        })?;
+        mt_router.unregister_custom_transport(transport_type).await
+    }
+
+    /// Relay server, when this node holds messages for offline peers
+    #[cfg(feature = "crypto")]
+    pub fn relay(&self) -> Option<Arc<RelayServer>> {
+        self.relay.clone()
+    }
+
+    /// Act as a relay using the given server (e.g. one backed by the database store)
+    #[cfg(feature = "crypto")]
+    pub fn set_relay(&mut self, relay: Arc<RelayServer>) {
+        self.relay = Some(relay);
+    }
+
+    /// Cluster this router is an instance of, if any
+    pub fn cluster(&self) -> Option<Arc<Cluster>> {
+        self.cluster.clone()
+    }
+
+    /// Run as an instance of a cluster (e.g. one with a custom shared store)
+    pub fn set_cluster(&mut self, cluster: Arc<Cluster>) {
+        self.cluster = Some(cluster);
+    }
+
+    /// Answer light clients' header and proof requests from this ledger
+    pub fn set_trust_ledger(&mut self, ledger: Arc<SynapseBlockchain>) {
+        self.digest.set_trust_source(ledger.clone());
+        self.trust_ledger = Some(ledger);
+    }
+
+    /// Light client, when this node verifies trust from headers only
+    pub fn light_client(&self) -> Option<Arc<LightClient>> {
+        self.light_client.clone()
+    }
+
+    /// Verify trust as a light client, syncing from full nodes with [`Self::sync_light_client`]
+    pub fn set_light_client(&mut self, client: Arc<LightClient>) {
+        self.light_client = Some(client);
+    }
+
+    /// Ask a full node for new headers and for proofs about watched peers
+    ///
+    /// The responses are applied as they arrive through
+    /// [`Self::receive_messages`]. Returns how many requests were sent.
+    pub async fn sync_light_client(&self, full_node: &str) -> Result<usize> {
+        let client = self
+            .light_client
+            .as_ref()
+            .ok_or_else(|| SynapseError::ConfigurationError("Light client is not enabled".to_string()))?;
+        let requests = client.sync_requests();
+        for request in &requests {
+            self.send_light_client_message(full_node, request).await?;
+        }
+        Ok(requests.len())
+    }
+
+    async fn send_light_client_message(&self, to_entity: &str, message: &LightClientMessage) -> Result<String> {
+        let message = message.to_message(&self.our_global_id, to_entity)?;
+        self.send_smart_from(
+            None,
+            &message.to,
+            &message.content,
+            message.message_type,
+            SecurityLevel::Authenticated,
+            MessageUrgency::Background,
+            message.metadata,
+        )
+        .await
+    }
+
+    /// Serve a light client's request or apply a full node's response
+    async fn handle_light_client_message(&self, from_entity: &str, message: LightClientMessage) {
+        if message.is_request() {
+            let Some(ledger) = &self.trust_ledger else {
+                debug!("Ignoring light client {} from {}: no trust ledger", message.kind(), from_entity);
+                return;
+            };
+            if let Some(response) = ledger.serve_light_client(&message).await {
+                if let Err(e) = self.send_light_client_message(from_entity, &response).await {
+                    debug!("Could not answer light client {}: {}", from_entity, e);
+                }
+            }
+        } else if let Some(client) = &self.light_client {
+            let kind = message.kind();
+            match client.handle(message) {
+                Ok(applied) => debug!("Applied {} {} from {}", applied, kind, from_entity),
+                Err(e) => warn!("Rejected {} from {}: {}", kind, from_entity, e),
+            }
+        }
+    }
+
+    /// Ask a peer what its transports accept
+    ///
+    /// Sends a capability ping carrying our own digest and waits up to the
+    /// configured probe timeout for the answer, which is then used by route
+    /// selection for later sends to the peer. Answers only arrive while
+    /// messages are being received, e.g. by a background receive loop.
+    pub async fn probe_capabilities(&self, to_entity: &str) -> Result<CapabilityDigest> {
+        let mt_router = self.multi_transport.as_ref().ok_or_else(|| {
+            SynapseError::NoTransportAvailable("Multi-transport router is not available".to_string())
+        })?;
+        let contact = self.contacts.resolve(to_entity);
+        let to_entity = contact.as_ref().map_or(to_entity, |c| c.global_id.as_str());
+
+        let peers = mt_router.peer_capabilities();
+        let (nonce, answer) = peers.start_probe(to_entity);
+        let ping = CapabilityProbe::Ping { nonce, digest: mt_router.local_capability_digest().await };
+        if let Err(e) = self.send_probe(mt_router, ping.to_message(&self.our_global_id, to_entity)?).await {
+            peers.cancel(nonce);
+            return Err(e);
+        }
+
+        let timeout = Duration::from_millis(self.config.probing.timeout_ms);
+        match tokio::time::timeout(timeout, answer).await {
+            Ok(Ok(digest)) => {
+                let version = protocol::negotiate(to_entity, &digest.protocol.unwrap_or(VersionRange::LEGACY))?;
+                debug!("{} accepts up to {} bytes at protocol v{}", to_entity, digest.max_message_size(), version);
+                Ok(digest)
+            }
+            _ => {
+                peers.cancel(nonce);
+                Err(SynapseError::ConnectionError(format!(
+                    "{} did not answer the capability probe within {:?}",
+                    to_entity, timeout
+                )))
+            }
+        }
+    }
+
+    /// Probe a peer before a large send if its limits are not known yet
+    ///
+    /// A failed probe is only logged; selection then goes ahead without them.
+    async fn probe_before_large_send(&self, to_entity: &str, size: usize) {
+        let probing = &self.config.probing;
+        let Some(mt_router) = &self.multi_transport else {
+            return;
+        };
+        if !probing.enabled || size < probing.large_message_bytes || mt_router.peer_capabilities().get(to_entity).is_some() {
+            return;
+        }
+        if let Err(e) = self.probe_capabilities(to_entity).await {
+            debug!("Sending {} bytes to {} without its capabilities: {}", size, to_entity, e);
+        }
+    }
+
+    /// Record a peer's ping and answer it, or complete one of our probes
+    async fn handle_capability_probe(&self, from_entity: &str, probe: CapabilityProbe) {
+        let Some(mt_router) = &self.multi_transport else {
+            return;
+        };
+        let peers = mt_router.peer_capabilities();
+        match probe {
+            CapabilityProbe::Ping { nonce, digest } => {
+                peers.record(from_entity, digest);
+                let pong = CapabilityProbe::Pong { nonce, digest: mt_router.local_capability_digest().await };
+                if let Err(e) = self.send_probe(mt_router, pong.to_message(&self.our_global_id, from_entity)?).await {
+                    debug!("Could not answer capability probe from {}: {}", from_entity, e);
+                }
+            }
+            CapabilityProbe::Pong { nonce, digest } => {
+                if !peers.complete(from_entity, nonce, digest) {
+                    debug!("Ignoring unsolicited capability answer from {}", from_entity);
+                }
+            }
+        }
+    }
+
+    /// Send a probe over the direct transports; probes are too small to need email
+    async fn send_probe(&self, mt_router: &MultiTransportRouter, message: SimpleMessage) -> Result<()> {
+        let secure_msg = self.create_secure_message(&message, SecurityLevel::Authenticated).await?;
+        mt_router.send_message(&message.to, &secure_msg, MessageUrgency::Interactive).await?;
+        Ok(())
+    }
+
+    /// Measure how far a peer's clock is from ours
+    ///
+    /// Sends a time probe and waits up to `time_sync.timeout_ms` for the
+    /// answer. The sample is kept so later timestamp checks on the peer's
+    /// messages allow for its offset. Answers only arrive while messages are
+    /// being received, e.g. by a background receive loop.
+    pub async fn sample_clock(&self, to_entity: &str) -> Result<TimeSample> {
+        let time_sync = &self.config.time_sync;
+        if !time_sync.enabled {
+            return Err(SynapseError::ConfigurationError("Time sync is disabled".to_string()));
+        }
+        let mt_router = self.multi_transport.as_ref().ok_or_else(|| {
+            SynapseError::NoTransportAvailable("Multi-transport router is not available".to_string())
+        })?;
+        let contact = self.contacts.resolve(to_entity);
+        let to_entity = contact.as_ref().map_or(to_entity, |c| c.global_id.as_str());
+
+        let clocks = mt_router.peer_clocks();
+        let (nonce, answer) = clocks.start_sample(to_entity);
+        let request = TimeProbe::Request { nonce, sent_at_ms: Utc::now().timestamp_millis() };
+        if let Err(e) = self.send_probe(mt_router, request.to_message(&self.our_global_id, to_entity)?).await {
+            clocks.cancel(nonce);
+            return Err(e);
+        }
+
+        let timeout = Duration::from_millis(time_sync.timeout_ms);
+        match tokio::time::timeout(timeout, answer).await {
+            Ok(Ok(sample)) => Ok(sample),
+            _ => {
+                clocks.cancel(nonce);
+                Err(SynapseError::ConnectionError(format!(
+                    "{} did not answer the time probe within {:?}",
+                    to_entity, timeout
+                )))
+            }
+        }
+    }
+
+    /// Classify our NAT (RFC 5780) and advertise the result to peers
+    ///
+    /// The classification is cached for `nat.cache_ttl_secs` unless `refresh`
+    /// is set, and sent in capability probes so both sides of a connection
+    /// can pick the traversal strategy the pair of NAT types allows.
+    pub async fn classify_nat(&self, refresh: bool) -> Result<NatClassification> {
+        let mt_router = self.multi_transport.as_ref().ok_or_else(|| {
+            SynapseError::NoTransportAvailable("Multi-transport router is not available".to_string())
+        })?;
+        mt_router.classify_nat(refresh).await
+    }
+
+    /// Ping a peer running the echo service and see how we reached it
+    ///
+    /// The reflection carries the address and transport our ping arrived
+    /// from as the echo node saw them. Waits up to `echo.timeout_ms`; answers
+    /// only arrive while messages are being received, e.g. by a background
+    /// receive loop.
+    pub async fn echo(&self, to_entity: &str) -> Result<EchoResult> {
+        let mt_router = self.multi_transport.as_ref().ok_or_else(|| {
+            SynapseError::NoTransportAvailable("Multi-transport router is not available".to_string())
+        })?;
+        let contact = self.contacts.resolve(to_entity);
+        let to_entity = contact.as_ref().map_or(to_entity, |c| c.global_id.as_str());
+
+        let (nonce, answer) = self.echo.start_ping(to_entity);
+        let ping = EchoProbe::ping(nonce);
+        let sent = match self.synapse_router.sign(&ping.signing_payload(&self.our_global_id, to_entity)).await {
+            Ok(signature) => match ping.with_signature(signature).to_message(&self.our_global_id, to_entity) {
+                Ok(message) => self.send_probe(mt_router, message).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            self.echo.cancel(nonce);
+            return Err(e);
+        }
+
+        let timeout = Duration::from_millis(self.config.echo.timeout_ms);
+        match tokio::time::timeout(timeout, answer).await {
+            Ok(Ok(result)) => Ok(result),
+            _ => {
+                self.echo.cancel(nonce);
+                Err(SynapseError::ConnectionError(format!(
+                    "{} did not answer the echo ping within {:?}",
+                    to_entity, timeout
+                )))
+            }
+        }
+    }
+
+    /// Reflect a peer's ping if we run the echo service, or complete one of ours
+    ///
+    /// Probes whose signature does not check out are dropped.
+    async fn handle_echo_probe(&self, message: &SimpleMessage, probe: EchoProbe) {
+        let received_at_ms = Utc::now().timestamp_millis();
+        let from_entity = &message.from_entity;
+        let verified = self
+            .synapse_router
+            .verify_signature(&probe.signing_payload(from_entity, &message.to), probe.signature(), from_entity)
+            .await;
+        if !matches!(verified, Ok(true)) {
+            debug!("Dropping echo {} from {}: bad signature", probe.kind(), from_entity);
+            return;
+        }
+
+        let reflection = match probe {
+            EchoProbe::Reflection { reflection, .. } => {
+                if self.echo.complete(from_entity, &reflection, received_at_ms).is_none() {
+                    debug!("Ignoring unsolicited echo reflection from {}", from_entity);
+                }
+                return;
+            }
+            ping => {
+                if !self.config.echo.enabled {
+                    return;
+                }
+                if !self.echo.admit(from_entity) {
+                    debug!("Not reflecting ping from {}: too many pings", from_entity);
+                    return;
+                }
+                ping.reflect(
+                    message.metadata.get(RECEIVED_FROM_KEY).cloned(),
+                    message.metadata.get(RECEIVED_VIA_KEY).cloned(),
+                    received_at_ms,
+                )
+            }
+        };
+        let (Some(reflection), Some(mt_router)) = (reflection, &self.multi_transport) else {
+            return;
+        };
+        let sent = match self.synapse_router.sign(&reflection.signing_payload(&self.our_global_id, from_entity)).await {
+            Ok(signature) => match reflection.with_signature(signature).to_message(&self.our_global_id, from_entity) {
+                Ok(message) => self.send_probe(mt_router, message).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            debug!("Could not reflect echo ping from {}: {}", from_entity, e);
+        }
+    }
+
+    /// How far our clock is off, judged by the peers sampled so far
+    pub fn clock_estimate(&self) -> Option<ClockEstimate> {
+        self.multi_transport.as_ref()?.peer_clocks().estimate()
+    }
+
+    /// Answer a peer's time probe, or record the sample its answer completes
+    async fn handle_time_probe(&self, from_entity: &str, probe: TimeProbe) {
+        let received_at_ms = Utc::now().timestamp_millis();
+        let Some(mt_router) = &self.multi_transport else {
+            return;
+        };
+        if !self.config.time_sync.enabled {
+            return;
+        }
+        if let Some(response) = probe.answer(received_at_ms) {
+            let sent = match response.to_message(&self.our_global_id, from_entity) {
+                Ok(message) => self.send_probe(mt_router, message).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                debug!("Could not answer time probe from {}: {}", from_entity, e);
+            }
+            return;
+        }
+        let Some(sample) = mt_router.peer_clocks().complete(from_entity, &probe, received_at_ms) else {
+            debug!("Ignoring unsolicited time probe answer from {}", from_entity);
+            return;
+        };
+        if sample.offset_ms.unsigned_abs() >= self.config.time_sync.warn_skew_ms {
+            warn!(
+                "Clock of {} is {}ms {} ours (round trip {}ms)",
+                from_entity,
+                sample.offset_ms.unsigned_abs(),
+                if sample.offset_ms > 0 { "ahead of" } else { "behind" },
+                sample.round_trip_ms
+            );
+            self.events.publish(RouterEvent::ClockSkewDetected {
+                peer: sample.peer,
+                offset_ms: sample.offset_ms,
+                round_trip_ms: sample.round_trip_ms,
+            });
+        }
+    }
+
+    /// Webhooks that receive matching incoming messages
+    pub fn webhooks(&self) -> Arc<WebhookDispatcher> {
+        self.webhooks.clone()
+    }
+
+    /// Receive pending messages, forwarding them to matching webhooks
+    ///
+    /// Mail taken in by the email gateway is included too; gatewayed mail for a
+    /// hosted local identity goes to that identity's inbox instead. When
+    /// clustered, messages from peers owned by another instance are forwarded
+    /// to it, and messages forwarded to this instance are included.
+    pub async fn receive_messages(&self) -> Result<Vec<SimpleMessage>> {
+        let mut received = self.synapse_router.receive_messages().await?;
+        for message in &mut received {
+            message.metadata.remove(RECEIVED_FROM_KEY);
+            message.metadata.insert(RECEIVED_VIA_KEY.to_string(), "email".to_string());
+        }
+        if let Some(mt_router) = &self.multi_transport {
+            let mut incoming = mt_router.receive_messages().await;
+            self.synapse_router.verify_incoming(&mut incoming).await;
+            received.extend(incoming.into_iter().map(Self::open_direct_message));
+        }
+        if self.relays_due() {
+            received.extend(self.collect_relayed().await);
+        }
+        if let Some(cluster) = &self.cluster {
+            received = self.route_through_cluster(cluster, received).await;
+        }
+
+        let mut messages = Vec::new();
+        for message in received {
+            let span = logging::message_span(Self::correlation_id(&message), &message.from_entity, "receive");
+            if let Some(message) = self.accept_inbound(message).instrument(span).await {
+                messages.push(message);
+            }
+        }
+
+        #[cfg(feature = "email")]
+        if let Some(gateway) = self.email_server().and_then(|server| server.gateway()) {
+            for message in gateway.take_inbound() {
+                if let Some(sender) = mailing_list::unsubscribe_request(&message, &self.config.mailing_list) {
+                    if let Err(e) = self.suppressions.suppress(&sender, SuppressionReason::Unsubscribed) {
+                        warn!("Could not record the unsubscribe of {}: {}", sender, e);
+                    }
+                    continue;
+                }
+                if self.local_identities.get(&message.to).is_some() {
+                    self.deliver_to_local_identity(message)?;
+                } else {
+                    self.webhooks.dispatch(&message);
+                    messages.push(message);
+                }
+            }
+        }
+        Ok(messages)
+    }
+
+    /// The message a direct transport delivered, noting where it came from
+    ///
+    /// The source, transport and signature status are set from what this node
+    /// saw; values a sender put in the metadata are replaced.
+    fn open_direct_message(incoming: IncomingMessage) -> SimpleMessage {
+        let signature = incoming.verification.signature;
+        let secure = incoming.message;
+        let mut metadata = secure.metadata;
+        let message_type = metadata
+            .remove(MESSAGE_TYPE_KEY)
+            .and_then(|kind| serde_json::from_value(serde_json::Value::String(kind)).ok())
+            .unwrap_or(MessageType::Direct);
+        metadata.remove(RECEIVED_FROM_KEY);
+        if !incoming.source.is_empty() {
+            metadata.insert(RECEIVED_FROM_KEY.to_string(), incoming.source);
+        }
+        metadata.insert(RECEIVED_VIA_KEY.to_string(), incoming.transport_type.to_string().to_lowercase());
+        metadata.insert(SIGNATURE_STATUS_KEY.to_string(), signature.as_str().to_string());
+        SimpleMessage {
+            to: secure.to_global_id,
+            from_entity: secure.from_global_id,
+            content: String::from_utf8_lossy(&secure.encrypted_content).into_owned(),
+            message_type,
+            metadata,
+        }
+    }
+
+    /// Keep the messages this instance handles, plus those forwarded to it
+    ///
+    /// If the shared store is unreachable, messages are handled here rather
+    /// than lost.
+    async fn route_through_cluster(&self, cluster: &Cluster, received: Vec<SimpleMessage>) -> Vec<SimpleMessage> {
+        let mut handled = Vec::new();
+        for message in received {
+            match cluster.route_inbound(message.clone()).await {
+                Ok(Some(message)) => handled.push(message),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("Handling message from {} locally: {}", message.from_entity, e);
+                    handled.push(message);
+                }
+            }
+        }
+        match cluster.take_forwarded().await {
+            Ok(forwarded) => handled.extend(forwarded),
+            Err(e) => warn!("Could not take forwarded messages: {}", e),
+        }
+        handled
+    }
+
+    /// Admission, indicators, amendments and webhooks for one received message
+    ///
+    /// Returns the message if it should be handed to the application.
+    async fn accept_inbound(&self, mut message: SimpleMessage) -> Option<SimpleMessage> {
+        // A peer outside our protocol range may mean something else by any of it
+        if let Err(e) = protocol::check_envelope(&message.from_entity, &message.metadata) {
+            warn!("Dropping message: {}", e);
+            return None;
+        }
+        // Past the peer cap a stranger is turned away or evicts the quietest peer
+        match self.limits.admit_peer(&message.from_entity) {
+            PeerAdmission::Rejected => {
+                debug!("Dropping message from {}: peer table full", message.from_entity);
+                return None;
+            }
+            PeerAdmission::Admitted { evicted: Some(peer) } => {
+                if let Some(ref mt_router) = self.multi_transport {
+                    mt_router.forget_routes(&peer).await;
+                }
+            }
+            PeerAdmission::Admitted { evicted: None } | PeerAdmission::Known => {}
+        }
+        // Signed lifecycle notices are checked on their own terms, not admission's
+        if let Some(notice) = LifecycleNotice::from_message(&message) {
+            self.accept_lifecycle_notice(notice).await;
+            return None;
+        }
+        // So are endpoint updates, which must carry the participant's own signature
+        if let Some(update) = EndpointUpdate::from_message(&message) {
+            self.accept_endpoint_update(update).await;
+            return None;
+        }
+        // And echo probes, which strangers send to public echo nodes
+        if let Some(probe) = EchoProbe::from_message(&message) {
+            self.handle_echo_probe(&message, probe).await;
+            return None;
+        }
+        if !self.screen_inbound(&message).await {
+            return None;
+        }
+        if let Some(light_client_message) = LightClientMessage::from_message(&message) {
+            self.handle_light_client_message(&message.from_entity, light_client_message).await;
+            return None;
+        }
+        if let Some(probe) = CapabilityProbe::from_message(&message) {
+            self.handle_capability_probe(&message.from_entity, probe).await;
+            return None;
+        }
+        if let Some(probe) = TimeProbe::from_message(&message) {
+            self.handle_time_probe(&message.from_entity, probe).await;
+            return None;
+        }
+        if self.consume_indicator(&message) {
+            return None;
+        }
+        if !self.gate_inbound(&message).await {
+            return None;
+        }
+        if self.consume_stream_chunk(&message) {
+            return None;
+        }
+        if let Some(signal) = CallSignal::from_message(&message) {
+            self.handle_call_signal(&message.from_entity, signal).await;
+            return None;
+        }
+        match MessageAmendment::from_message(&message) {
+            Some(amendment) => {
+                let verified = self
+                    .synapse_router
+                    .verify_signature(&amendment.signing_payload(), &amendment.signature, &amendment.author)
+                    .await;
+                if !self.apply_amendment(&amendment, verified) {
+                    return None;
+                }
+            }
+            None => {
+                let peer = message.from.clone();
+                self.archive(&mut message, &peer);
+            }
+        }
+        self.webhooks.dispatch(&message);
+        Some(message)
+    }
+
+    /// ID used to correlate log lines for a received message
+    fn correlation_id(message: &SimpleMessage) -> &str {
+        message.metadata.get(MESSAGE_ID_KEY).map_or("", String::as_str)
+    }
+
+    /// Reply to a plain email correspondent through the email gateway
+    ///
+    /// The reply is sent as ordinary plain-text email, threaded under the
+    /// correspondent's mail when `conversation_id` is set.
+    #[cfg(feature = "email")]
+    pub async fn reply_by_email(&self, message: &SimpleMessage) -> Result<()> {
+        let gateway = self
+            .email_server()
+            .and_then(|server| server.gateway())
+            .ok_or_else(|| SynapseError::ConfigurationError("Email gateway is not enabled".to_string()))?;
+        let email = gateway.compose_reply(message)?;
+        self.synapse_router.send_plain_email(&email).await
+    }
+
+    /// Addresses email broadcasts skip
+    pub fn suppression_list(&self) -> Arc<SuppressionList> {
+        self.suppressions.clone()
+    }
+
+    /// Send `content` to many email recipients the way a mailing list would
+    ///
+    /// Recipients whose public key we hold get their own encrypted copy; the
+    /// rest are sent one message per `mailing_list.batch_size` addresses in
+    /// Bcc. Suppressed addresses are skipped. Sends go through the email
+    /// transport's pacing, so a large broadcast takes a while.
+    #[cfg(feature = "email")]
+    pub async fn broadcast_email(&self, recipients: &[String], subject: &str, content: &str) -> Result<BroadcastReport> {
+        use base64::Engine as _;
+
+        #[cfg_attr(not(feature = "crypto"), allow(unused_mut))]
+        let mut ciphertexts: HashMap<String, Vec<u8>> = HashMap::new();
+        #[cfg(feature = "crypto")]
+        for recipient in recipients {
+            let address = recipient.trim().to_lowercase();
+            if self.suppressions.reason(&address).is_some() || ciphertexts.contains_key(&address) {
+                continue;
+            }
+            if let Some(ciphertext) = self.synapse_router.encrypt_for(content, &address).await {
+                ciphertexts.insert(address, ciphertext);
+            }
+        }
+        let plan = BroadcastPlan::new(
+            recipients,
+            &self.suppressions,
+            self.config.mailing_list.batch_size,
+            |address| ciphertexts.contains_key(address),
+        );
+        let mut report = plan.report;
+
+        for address in &plan.encrypted {
+            let body = base64::engine::general_purpose::STANDARD.encode(&ciphertexts[address]);
+            let single = std::slice::from_ref(address);
+            match self.send_broadcast(single, subject, body, true).await {
+                Ok(()) => report.mark(single, RecipientStatus::SentEncrypted),
+                Err(e) => report.mark(single, RecipientStatus::Failed { error: e.to_string() }),
+            }
+        }
+        for (index, batch) in plan.batches.iter().enumerate() {
+            match self.send_broadcast(batch, subject, content.to_string(), false).await {
+                Ok(()) => report.mark(batch, RecipientStatus::Sent { batch: index }),
+                Err(e) => {
+                    warn!("Broadcast batch {} ({} recipients) failed: {}", index, batch.len(), e);
+                    report.mark(batch, RecipientStatus::Failed { error: e.to_string() });
+                }
+            }
+        }
+        info!(
+            "Email broadcast sent to {} recipients ({} suppressed, {} failed)",
+            report.sent(),
+            report.suppressed(),
+            report.failed()
+        );
+        Ok(report)
+    }
+
+    #[cfg(feature = "email")]
+    async fn send_broadcast(&self, bcc: &[String], subject: &str, body: String, encrypted: bool) -> Result<()> {
+        let email = mailing_list::compose(&self.config.mailing_list, &self.our_global_id, bcc, subject, body, encrypted)?;
+        self.synapse_router.send_plain_email(&email).await
+    }
+
+    /// Read receipts and typing indicators from peers
+    pub fn indicators(&self) -> Arc<IndicatorHub> {
+        self.indicators.clone()
+    }
+
+    /// Subscribe to read receipts and typing indicators from peers
+    pub fn subscribe_indicators(&self) -> broadcast::Receiver<IndicatorEvent> {
+        self.indicators.subscribe()
+    }
+
+    /// Event bus the router and its transports publish lifecycle events to
+    ///
+    /// Pass it to a [`TransportManager`](crate::transport::manager::TransportManager)
+    /// or [`SynapseBlockchain`](crate::synapse::blockchain::SynapseBlockchain) to
+    /// merge their events into the same stream.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Caps on peers, queued bytes, crypto work and connections this node enforces
+    pub fn resource_limits(&self) -> &Arc<ResourceGovernor> {
+        &self.limits
+    }
+
+    /// How close the node is to its resource limits, and how often each was hit
+    pub fn resource_usage(&self) -> ResourceUsage {
+        self.limits.usage()
+    }
+
+    /// Subscribe to transport, discovery, circuit breaker, key and blockchain events
+    pub fn subscribe_events(&self) -> broadcast::Receiver<TimedEvent> {
+        self.events.subscribe()
+    }
+
+    /// Delivery tallies for the current digest period
+    pub fn delivery_digest(&self) -> Arc<DigestCollector> {
+        self.digest.clone()
+    }
+
+    /// Close the current digest period and send its report to the operators
+    /// in `digest.recipients` and `digest.email_recipients`
+    ///
+    /// An empty digest is not sent when `digest.skip_empty` is set. Returns the
+    /// digest either way.
+    pub async fn send_delivery_digest(&self) -> Result<DeliveryDigest> {
+        let digest = self.digest.take().await;
+        let config = &self.config.digest;
+        if digest.is_empty() && config.skip_empty {
+            debug!("Nothing to report in the delivery digest");
+            return Ok(digest);
+        }
+        for recipient in &config.recipients {
+            let message = digest.to_message(&self.our_global_id, recipient)?;
+            if let Err(e) = self.send_smart_from(
+                None,
+                recipient,
+                &message.content,
+                message.message_type,
+                SecurityLevel::Authenticated,
+                MessageUrgency::Background,
+                message.metadata,
+            ).await {
+                warn!("Could not send the delivery digest to {}: {}", recipient, e);
+            }
+        }
+        #[cfg(feature = "email")]
+        for address in &config.email_recipients {
+            if let Err(e) = self.mail_digest(&digest, address).await {
+                warn!("Could not mail the delivery digest to {}: {}", address, e);
+            }
+        }
+        #[cfg(not(feature = "email"))]
+        if !config.email_recipients.is_empty() {
+            warn!("Delivery digest email recipients need the `email` feature");
+        }
+        info!("Sent delivery digest: {} delivered, {} dead-lettered", digest.delivered, digest.dead_lettered);
+        Ok(digest)
+    }
+
+    #[cfg(feature = "email")]
+    async fn mail_digest(&self, digest: &DeliveryDigest, address: &str) -> Result<()> {
+        use lettre::message::{header::ContentType, Mailbox};
+
+        let mailbox = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| SynapseError::InvalidFormat(format!("Invalid address {}: {}", address, e)))
+        };
+        let email = lettre::Message::builder()
+            .from(mailbox(&self.our_global_id)?)
+            .to(mailbox(address)?)
+            .subject(digest.subject())
+            .header(ContentType::TEXT_PLAIN)
+            .body(digest.render_text())
+            .map_err(|e| SynapseError::InvalidFormat(format!("Failed to build digest email: {}", e)))?;
+        self.synapse_router.send_plain_email(&email).await
+    }
+
+    /// Tally router events and send a delivery digest every `digest.interval_secs`
+    ///
+    /// Returns at once when `digest.enabled` is off.
+    pub fn spawn_delivery_digest(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let period = Duration::from_secs(self.config.digest.interval_secs.max(60));
+        let mut events = self.events.subscribe();
+        tokio::spawn(async move {
+            if !self.config.digest.enabled {
+                debug!("Delivery digest is disabled");
+                return;
+            }
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) => self.digest.observe(&event.event),
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("Delivery digest missed {} router events", skipped);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = interval.tick() => {
+                        if let Err(e) = self.send_delivery_digest().await {
+                            warn!("Could not send the delivery digest: {}", e);
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Send a read receipt or activity indicator to `to_entity`
+    ///
+    /// Returns `false` without sending when indicators are disabled, the contact
+    /// has them suppressed, or an ephemeral indicator has no real-time route.
+    pub async fn send_indicator(&self, to_entity: &str, indicator: Indicator) -> Result<bool> {
+        self.send_indicator_from(None, to_entity, indicator).await
+    }
+
+    /// Send a read receipt or activity indicator from one of the router's local identities
+    pub async fn send_indicator_as(&self, identity: &str, to_entity: &str, indicator: Indicator) -> Result<bool> {
+        let sender = self.local_identities.get(identity).ok_or_else(|| {
+            crate::error::SynapseError::Identity(format!("Unknown local identity: {}", identity))
+        })?;
+        self.send_indicator_from(Some(&sender), to_entity, indicator).await
+    }
+
+    async fn send_indicator_from(
+        &self,
+        sender: Option<&LocalIdentity>,
+        to_entity: &str,
+        indicator: Indicator,
+    ) -> Result<bool> {
+        if !self.indicators.is_enabled() || self.indicators_suppressed_for(to_entity) {
+            return Ok(false);
+        }
+        let from_global_id = sender.map_or_else(|| self.our_global_id.clone(), |s| s.global_id.clone());
+        let message = indicator.to_message(&from_global_id, to_entity);
+        let urgency = if indicator.is_ephemeral() { MessageUrgency::RealTime } else { MessageUrgency::Interactive };
+        match self
+            .send_smart_from(sender, to_entity, &message.content, message.message_type, SecurityLevel::Authenticated, urgency, message.metadata)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(SynapseError::NoTransportAvailable(reason)) if indicator.is_ephemeral() => {
+                debug!("Dropped indicator for {}: {}", to_entity, reason);
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn indicators_suppressed_for(&self, entity: &str) -> bool {
+        self.contacts.resolve(entity).is_some_and(|contact| contact.suppress_indicators)
+    }
+
+    /// Publish `message` to indicator subscribers if it is an indicator
+    fn consume_indicator(&self, message: &SimpleMessage) -> bool {
+        self.indicators.handle(message, self.indicators_suppressed_for(&message.from_entity))
+    }
+
+    /// Audio and video calls, ringing, accepted or ended
+    pub fn calls(&self) -> Arc<CallManager> {
+        self.calls.clone()
+    }
+
+    /// Subscribe to call invites, answers, ICE candidates and hangups from peers
+    pub fn subscribe_calls(&self) -> broadcast::Receiver<CallEvent> {
+        self.calls.subscribe()
+    }
+
+    /// Call `to_entity` with an SDP offer from the application's media stack
+    ///
+    /// Returns the call, still ringing; the answer arrives as a
+    /// [`CallEventKind::Accepted`](crate::calls::CallEventKind) event.
+    pub async fn start_call(&self, to_entity: &str, media: Vec<MediaKind>, sdp_offer: &str) -> Result<Call> {
+        let peer = self.resolve_send(&self.our_global_id, to_entity, SecurityLevel::Authenticated)?.to_entity;
+        let invite = self.calls.invite(&peer, media, sdp_offer);
+        let call_id = invite.call_id().to_string();
+        if let Err(e) = self.send_call_signal(&peer, &invite).await {
+            self.calls.fail(&call_id, "unreachable");
+            return Err(e);
+        }
+        self.calls.get(&call_id).ok_or_else(|| SynapseError::NotFound(format!("Unknown call: {}", call_id)))
+    }
+
+    /// Answer an incoming call with an SDP answer
+    pub async fn accept_call(&self, call_id: &str, sdp_answer: &str) -> Result<Call> {
+        let (call, signal) = self.calls.accept(call_id, sdp_answer)?;
+        self.send_call_signal(&call.peer, &signal).await?;
+        Ok(call)
+    }
+
+    /// Decline an incoming call
+    pub async fn reject_call(&self, call_id: &str, reason: &str) -> Result<Call> {
+        let (call, signal) = self.calls.reject(call_id, reason)?;
+        self.send_call_signal(&call.peer, &signal).await?;
+        Ok(call)
+    }
+
+    /// End a ringing or accepted call
+    ///
+    /// The call is ended locally even if the peer cannot be told.
+    pub async fn hang_up(&self, call_id: &str) -> Result<Call> {
+        let (call, signal) = self.calls.hangup(call_id, "hangup")?;
+        self.send_call_signal(&call.peer, &signal).await?;
+        Ok(call)
+    }
+
+    /// Trickle a local ICE candidate to the peer of a call
+    pub async fn send_ice_candidate(&self, call_id: &str, candidate: IceCandidate) -> Result<()> {
+        let (call, signal) = self.calls.ice_candidate(call_id, candidate)?;
+        self.send_call_signal(&call.peer, &signal).await
+    }
+
+    async fn send_call_signal(&self, peer: &str, signal: &CallSignal) -> Result<()> {
+        let message = signal.to_message(&self.our_global_id, peer);
+        self.send_smart_from(
+            None,
+            peer,
+            &message.content,
+            message.message_type,
+            SecurityLevel::Authenticated,
+            MessageUrgency::RealTime,
+            message.metadata,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn handle_call_signal(&self, from: &str, signal: CallSignal) {
+        if let Some(reply) = self.calls.receive(from, signal) {
+            if let Err(e) = self.send_call_signal(from, &reply).await {
+                debug!("Could not tell {} that call {} is ringing: {}", from, reply.call_id(), e);
+            }
+        }
+    }
+
+    /// Open a flow-controlled byte stream to `to_entity`
+    ///
+    /// The stream is carried over the direct transports only; it fails when
+    /// none is available or allowed by policy, rather than falling back to
+    /// email. The peer receives it from [`accept_stream`](Self::accept_stream).
+    pub async fn open_stream(&self, to_entity: &str) -> Result<PayloadStream> {
+        let resolved = self.resolve_send(&self.our_global_id, to_entity, SecurityLevel::Authenticated)?;
+        let (mt_router, route) = self.stream_route(&self.our_global_id, &resolved)?;
+        // Fail now, not on the first write, if the peer cannot be reached directly
+        mt_router
+            .prepare_route(&resolved.to_entity, MessageUrgency::RealTime, |route| resolved.decision.permits_route(route))
+            .await?;
+        self.payload_streams.open(&self.our_global_id, &resolved.to_entity, route)
+    }
+
+    /// Wait for the next stream a peer opens to us
+    pub async fn accept_stream(&self) -> Option<PayloadStream> {
+        self.payload_streams.accept().await
+    }
+
+    /// Byte streams open in either direction
+    pub fn payload_streams(&self) -> Arc<PayloadStreams> {
+        self.payload_streams.clone()
+    }
+
+    fn stream_route(
+        &self,
+        from_global_id: &str,
+        resolved: &ResolvedSend,
+    ) -> Result<(Arc<MultiTransportRouter>, Arc<dyn ChunkSender>)> {
+        let mt_router = self.multi_transport.clone().ok_or_else(|| {
+            SynapseError::NoTransportAvailable("Payload streams require the multi-transport router".to_string())
+        })?;
+        if !resolved.policy.allows_direct_transport() {
+            return Err(SynapseError::AuthorizationError(format!(
+                "Organization policy for {} allows no direct transport for streams",
+                from_global_id
+            )));
+        }
+        let route: Arc<dyn ChunkSender> = Arc::new(StreamRoute {
+            mt_router: mt_router.clone(),
+            security_level: resolved.security_level.clone(),
+            decision: resolved.decision.clone(),
+        });
+        Ok((mt_router, route))
+    }
+
+    /// Hand payload stream chunks to their stream, accepting streams peers open
+    fn consume_stream_chunk(&self, message: &SimpleMessage) -> bool {
+        self.payload_streams.handle(message, || {
+            let resolved = self.resolve_send(&self.our_global_id, &message.from_entity, SecurityLevel::Authenticated);
+            match resolved.and_then(|resolved| self.stream_route(&self.our_global_id, &resolved)) {
+                Ok((_, route)) => Some(route),
+                Err(e) => {
+                    debug!("Refusing payload stream from {}: {}", message.from_entity, e);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Messages exchanged through this router, with edits and retractions applied
+    pub fn history(&self) -> Arc<ConversationHistory> {
+        self.history.clone()
+    }
+
+    /// Who talked to whom in our history, how much and how quickly, with
+    /// network trust from our trust ledger when we keep one
+    ///
+    /// See [`crate::analytics`]; export with `to_json` or `to_graphml`.
+    pub async fn interaction_graph(&self, options: &AnalyticsOptions) -> InteractionGraph {
+        let entries = self.history.entries();
+        let mut trust = HashMap::new();
+        if let Some(ref ledger) = self.trust_ledger {
+            let participants: BTreeSet<&str> = entries
+                .iter()
+                .flat_map(|entry| [entry.from.as_str(), entry.to.as_str()])
+                .filter(|participant| !options.exclude.contains(*participant))
+                .collect();
+            for participant in participants {
+                if let Some(score) = ledger.network_trust(participant).await {
+                    trust.insert(participant.to_string(), score);
+                }
+            }
+        }
+        InteractionGraph::build(&entries, &trust, options)
+    }
+
+    /// Load the on-disk stores, encrypting any written before encryption
+    /// was enabled
+    ///
+    /// With `storage.encrypt_at_rest` the stores are keyed from the node's
+    /// private key, so one must be loaded first. Returns the number of
+    /// history messages restored.
+    pub async fn unlock_storage(&self) -> Result<usize> {
+        let storage = &self.config.storage;
+        let history_key = match storage.encrypt_at_rest {
+            true => Some(self.synapse_router.storage_key("history").await?),
+            false => None,
+        };
+        if let (true, Some(mt_router)) = (storage.encrypt_at_rest, &self.multi_transport) {
+            let tickets = mt_router.sessions().unlock(self.synapse_router.storage_key("sessions").await?)?;
+            debug!("Unlocked session ticket store ({} tickets)", tickets);
+        }
+        let Some(ref path) = storage.history_store else {
+            return Ok(0);
+        };
+        let restored = self.history.load(std::path::Path::new(path), history_key.as_ref())?;
+        info!("Restored {} messages from history store", restored);
+        #[cfg(feature = "search")]
+        if let Some(search) = self.search.as_ref().filter(|search| search.is_empty()) {
+            let indexed = search.index_all(&self.history.entries())?;
+            info!("Indexed {} restored messages for search", indexed);
+        }
+        Ok(restored)
+    }
+
+    /// Write the conversation history and session tickets to disk
+    pub async fn persist_storage(&self) -> Result<()> {
+        let storage = &self.config.storage;
+        if let Some(ref path) = storage.history_store {
+            let key = match storage.encrypt_at_rest {
+                true => Some(self.synapse_router.storage_key("history").await?),
+                false => None,
+            };
+            self.history.save(std::path::Path::new(path), key.as_ref())?;
+        }
+        if let Some(ref mt_router) = self.multi_transport {
+            mt_router.sessions().persist()?;
+        }
+        Ok(())
+    }
+
+    /// Replace the content of a message we sent earlier
+    pub async fn edit_message(&self, to_entity: &str, message_id: &str, content: &str) -> Result<String> {
+        let action = AmendmentAction::Edit { content: content.to_string() };
+        self.amend_from(None, to_entity, message_id, action).await
+    }
+
+    /// Retract a message we sent earlier
+    pub async fn retract_message(&self, to_entity: &str, message_id: &str) -> Result<String> {
+        self.amend_from(None, to_entity, message_id, AmendmentAction::Retract).await
+    }
+
+    /// Edit or retract a message sent earlier by one of the router's local identities
+    pub async fn amend_message_as(
+        &self,
+        identity: &str,
+        to_entity: &str,
+        message_id: &str,
+        action: AmendmentAction,
+    ) -> Result<String> {
+        let sender = self.local_identities.get(identity).ok_or_else(|| {
+            crate::error::SynapseError::Identity(format!("Unknown local identity: {}", identity))
+        })?;
+        self.amend_from(Some(&sender), to_entity, message_id, action).await
+    }
+
+    async fn amend_from(
+        &self,
+        sender: Option<&LocalIdentity>,
+        to_entity: &str,
+        message_id: &str,
+        action: AmendmentAction,
+    ) -> Result<String> {
+        let amendment = match sender {
+            Some(sender) => MessageAmendment::sign(sender, message_id, action)?,
+            None => {
+                let mut amendment = MessageAmendment::new(self.our_global_id.clone(), message_id, action);
+                amendment.signature = self.synapse_router.sign(&amendment.signing_payload()).await?;
+                amendment
+            }
+        };
+
+        // Our own copy must accept the amendment before peers are asked to
+        if self.history.get(message_id).is_some() {
+            self.index_entry(Some(self.history.apply(&amendment)?));
+        } else {
+            self.history.policy().check(&amendment.action, &amendment.amended_at)?;
+        }
+
+        let message = amendment.to_message(to_entity)?;
+        self.send_smart_from(
+            sender,
+            to_entity,
+            &message.content,
+            message.message_type,
+            SecurityLevel::Authenticated,
+            MessageUrgency::Interactive,
+            message.metadata,
+        ).await
+    }
+
+    /// Apply a received amendment whose signature check returned `verified`
+    fn apply_amendment(&self, amendment: &MessageAmendment, verified: Result<bool>) -> bool {
+        match verified {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!("Rejected amendment of {} from {}: bad signature", amendment.message_id, amendment.author);
+                return false;
+            }
+            Err(e) => {
+                warn!("Rejected amendment of {} from {}: {}", amendment.message_id, amendment.author, e);
+                return false;
+            }
+        }
+        match self.history.apply(amendment) {
+            Ok(entry) => {
+                self.index_entry(Some(entry));
+                true
+            }
+            // Unknown messages may predate the history; pass the amendment on to the application
+            Err(SynapseError::NotFound(_)) => true,
+            Err(e) => {
+                warn!("Ignored {} of {} from {}: {}", amendment.action.name(), amendment.message_id, amendment.author, e);
+                false
+            }
+        }
+    }
+
+    /// Encrypted groups of a local identity
+    #[cfg(feature = "crypto")]
+    pub fn group_manager(&self, identity: &str) -> Result<Arc<GroupManager>> {
+        let identity = self.local_identities.get(identity).ok_or_else(|| {
+            crate::error::SynapseError::Identity(format!("Unknown local identity: {}", identity))
+        })?;
+        Ok(self
+            .groups
+            .entry(identity.name.clone())
+            .or_insert_with(|| Arc::new(GroupManager::new(identity.clone())))
+            .clone())
+    }
+
+    /// Create an encrypted group administered by a local identity and send members their keys
+    #[cfg(feature = "crypto")]
+    pub async fn create_group(&self, identity: &str, name: &str, members: Vec<String>) -> Result<Group> {
+        let (group, packages) = self.group_manager(identity)?.create_group(name, members)?;
+        self.send_key_packages(identity, packages).await?;
+        Ok(group)
+    }
+
+    /// Add a member to a group, rekeying it for everyone
+    #[cfg(feature = "crypto")]
+    pub async fn add_group_member(&self, identity: &str, group_id: &str, member: &str) -> Result<()> {
+        let manager = self.group_manager(identity)?;
+        let packages = manager.add_member(group_id, member)?;
+        self.publish_group_rekey(&manager, group_id);
+        self.send_key_packages(identity, packages).await
+    }
+
+    /// Remove a member from a group, rekeying it so they cannot read further messages
+    #[cfg(feature = "crypto")]
+    pub async fn remove_group_member(&self, identity: &str, group_id: &str, member: &str) -> Result<()> {
+        let manager = self.group_manager(identity)?;
+        let packages = manager.remove_member(group_id, member)?;
+        self.publish_group_rekey(&manager, group_id);
+        self.send_key_packages(identity, packages).await
+    }
+
+    /// Encrypt a message once with the group key and send it to every other member
+    #[cfg(feature = "crypto")]
+    pub async fn send_group_message(&self, identity: &str, group_id: &str, content: &str) -> Result<()> {
+        let manager = self.group_manager(identity)?;
+        let group = manager
+            .group(group_id)
+            .ok_or_else(|| SynapseError::NotFound(format!("Group {}", group_id)))?;
+        let sealed = manager.encrypt(group_id, content.as_bytes())?;
+        let me = manager.global_id();
+        for member in group.members.iter().filter(|member| **member != me) {
+            let message = sealed.to_message(member)?;
+            if let Err(e) = self.send_prepared_as(identity, message, SecurityLevel::Authenticated, MessageUrgency::Interactive).await {
+                warn!("Failed to send group {} message to {}: {}", group_id, member, e);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "crypto")]
+    fn publish_group_rekey(&self, manager: &GroupManager, group_id: &str) {
+        if let Some(group) = manager.group(group_id) {
+            self.events.publish(RouterEvent::KeyRotated {
+                identity: manager.global_id(),
+                scope: format!("group:{}", group.id),
+                epoch: group.epoch,
+            });
+        }
+    }
+
+    #[cfg(feature = "crypto")]
+    async fn send_key_packages(&self, identity: &str, packages: Vec<GroupKeyPackage>) -> Result<()> {
+        for package in packages {
+            let message = package.to_message()?;
+            self.send_prepared_as(identity, message, SecurityLevel::Authenticated, MessageUrgency::Interactive)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Install a received key package, or decrypt a group message for delivery
+    #[cfg(feature = "crypto")]
+    fn open_group_payload(&self, mut message: SimpleMessage) -> Result<Option<SimpleMessage>> {
+        let manager = self.group_manager(&message.to)?;
+        if let Some(package) = GroupKeyPackage::from_message(&message) {
+            match manager.accept_package(&package, &message.from_entity) {
+                Ok(group) => {
+                    info!("{} now in epoch {} of group {}", message.to, group.epoch, group.name);
+                    self.publish_group_rekey(&manager, &group.id);
+                }
+                Err(e) => warn!("Rejected key package for group {}: {}", package.group_id, e),
+            }
+            return Ok(None);
+        }
+        let Some(sealed) = GroupCiphertext::from_message(&message) else {
+            return Ok(Some(message));
+        };
+        match manager.decrypt(&sealed) {
+            Ok(plaintext) => {
+                message.content = String::from_utf8_lossy(&plaintext).into_owned();
+                message.metadata.remove(GROUP_PAYLOAD_KEY);
+                Ok(Some(message))
+            }
+            Err(e) => {
+                warn!("Could not open group {} message from {}: {}", sealed.group_id, sealed.sender, e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Admission control applied to inbound messages
+    pub fn admission(&self) -> Arc<AdmissionController> {
+        self.admission.clone()
+    }
+
+    /// Replace the admission controller (e.g. one with a trust source attached)
+    pub fn set_admission(&mut self, admission: Arc<AdmissionController>) {
+        self.admission = admission;
+    }
+
+    /// Add a held stranger as a contact and release their messages
+    ///
+    /// Messages for hosted local identities go to those inboxes; the rest are returned.
+    pub fn approve_contact_request(&self, sender: &str, display_name: &str) -> Result<Vec<SimpleMessage>> {
+        self.contacts.upsert(Contact::new(sender, display_name))?;
+        let mut released = Vec::new();
+        for message in self.admission.approve(sender) {
+            if self.local_identities.get(&message.to).is_some() {
+                self.deliver_to_local_identity(message)?;
+            } else {
+                released.push(message);
+            }
+        }
+        Ok(released)
+    }
+
+    fn admit(&self, message: &SimpleMessage) -> AdmissionDecision {
+        let is_contact = self.contacts.get(&message.from_entity).is_some();
+        self.admission.admit(message, is_contact)
+    }
+
+    /// Run admission control on an inbound message, challenging held strangers
+    async fn screen_inbound(&self, message: &SimpleMessage) -> bool {
+        if !self.admission.is_enabled() {
+            return true;
+        }
+        self.admission.refresh_trust(&message.from_entity).await;
+        match self.admit(message) {
+            AdmissionDecision::Admit => true,
+            AdmissionDecision::Hold { challenge: Some(challenge) } => {
+                let sender = self.local_identities.get(&message.to);
+                let reply = challenge.to_message(&message.to, &message.from_entity);
+                if let Err(e) = self.send_smart_from(
+                    sender.as_deref(),
+                    &reply.to,
+                    &reply.content,
+                    reply.message_type,
+                    SecurityLevel::Public,
+                    MessageUrgency::Background,
+                    reply.metadata,
+                ).await {
+                    debug!("Could not challenge {}: {}", message.from_entity, e);
+                }
+                false
+            }
+            AdmissionDecision::Hold { challenge: None } | AdmissionDecision::Drop => false,
+        }
+    }
+
+    /// Minimum-trust requirements applied to inbound messages
+    pub fn trust_gate(&self) -> Arc<TrustGate> {
+        self.trust_gate.clone()
+    }
+
+    /// Replace the trust gate (e.g. one with a trust source attached)
+    pub fn set_trust_gate(&mut self, trust_gate: Arc<TrustGate>) {
+        self.trust_gate = trust_gate;
+    }
+
+    /// Check an inbound message against the trust requirements, telling a
+    /// refused sender which one it missed
+    async fn gate_inbound(&self, message: &SimpleMessage) -> bool {
+        if !self.trust_gate.is_enabled() {
+            return true;
+        }
+        let Some(rejection) = self.trust_gate.check(message).await else {
+            return true;
+        };
+        if self.trust_gate.should_notify(&message.from_entity) {
+            let sender = self.local_identities.get(&message.to);
+            let reply = rejection.to_message(&message.to, &message.from_entity);
+            if let Err(e) = self.send_smart_from(
+                sender.as_deref(),
+                &reply.to,
+                &reply.content,
+                reply.message_type,
+                SecurityLevel::Public,
+                MessageUrgency::Background,
+                reply.metadata,
+            ).await {
+                debug!("Could not tell {} about the trust rejection: {}", message.from_entity, e);
+            }
+        }
+        false
+    }
+
+    /// Contact book consulted when resolving recipient names
+    pub fn contacts(&self) -> Arc<ContactBook> {
+        self.contacts.clone()
+    }
+
+    /// Lifecycle states of participants, consulted before every send
+    pub fn lifecycle(&self) -> Arc<LifecycleRegistry> {
+        self.lifecycle.clone()
+    }
+
+    /// Apply a lifecycle notice locally and pass it on to every contact
+    ///
+    /// Use this to propagate a registry's suspension, deprecation or deletion
+    /// tombstone. Returns how many contacts the notice was sent to.
+    pub async fn announce_lifecycle(&self, notice: &LifecycleNotice) -> Result<usize> {
+        self.apply_lifecycle_notice(notice.clone());
+        let mut sent = 0;
+        for contact in self.contacts.list() {
+            if contact.global_id == notice.global_id {
+                continue;
+            }
+            let message = notice.to_message(&contact.global_id)?;
+            match self.send_smart_from(
+                None,
+                &message.to,
+                &message.content,
+                message.message_type,
+                SecurityLevel::Authenticated,
+                MessageUrgency::Background,
+                message.metadata,
+            ).await {
+                Ok(_) => sent += 1,
+                Err(e) => debug!("Could not pass {} notice to {}: {}", notice.state.name(), contact.global_id, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Verify a received lifecycle notice and apply it
+    ///
+    /// Notices are accepted from trusted issuers, and from participants about
+    /// themselves when signed with their own key.
+    async fn accept_lifecycle_notice(&self, notice: LifecycleNotice) {
+        let verified = match self.lifecycle.issuer_key(&notice.issuer) {
+            Some(public_key) => notice.verify(&public_key),
+            None if notice.issuer == notice.global_id => {
+                self.synapse_router
+                    .verify_signature(&notice.signing_payload(), &notice.signature, &notice.issuer)
+                    .await
             }
-            Err(e) => {
-                warn!("Failed to initialize multi-transport router: {}", e);
-                warn!("Falling back to email-only mode");
-                None
+            None => Ok(false),
+        };
+        match verified {
+            Ok(true) => self.apply_lifecycle_notice(notice),
+            Ok(false) => warn!("Rejected {} notice for {} from {}: untrusted or bad signature", notice.state.name(), notice.global_id, notice.issuer),
+            Err(e) => warn!("Rejected {} notice for {} from {}: {}", notice.state.name(), notice.global_id, notice.issuer, e),
+        }
+    }
+
+    fn apply_lifecycle_notice(&self, notice: LifecycleNotice) {
+        let participant = notice.global_id.clone();
+        let state = notice.state.clone();
+        let issuer = notice.issuer.clone();
+        if !self.lifecycle.apply(notice) {
+            return;
+        }
+        // A deleted participant's cached contact and resolution go with it
+        if let LifecycleState::Deleted { .. } = state {
+            let _ = self.contacts.remove(&participant);
+        }
+        info!("{} is now {}", participant, state.name());
+        self.events.publish(RouterEvent::ParticipantLifecycleChanged {
+            participant,
+            state: state.name().to_string(),
+            issuer,
+        });
+    }
+
+    /// Publish our endpoint updates to a discovery backend (DHT, DNS, registry)
+    pub fn add_endpoint_publisher(&self, publisher: Arc<dyn EndpointPublisher>) {
+        self.endpoint_publishers.write().unwrap().push(publisher);
+    }
+
+    /// Where peers can reach our listening transports now, keyed by transport name
+    pub fn current_endpoints(&self) -> BTreeMap<String, String> {
+        let Some(mt_router) = &self.multi_transport else {
+            return BTreeMap::new();
+        };
+        let advertise_host = self.config.listeners.advertise_host.as_deref();
+        mt_router
+            .local_endpoints()
+            .into_iter()
+            .map(|(transport, bound)| (transport, advertised_endpoint(bound, advertise_host)))
+            .collect()
+    }
+
+    /// Look up TXT and SRV records for `run_diagnostics`
+    pub fn set_dns_resolver(&mut self, resolver: Arc<dyn DnsResolver>) {
+        self.dns_resolver = Some(resolver);
+    }
+
+    /// Check that this node can be reached and can send mail
+    ///
+    /// Covers bound listeners, reachability of the advertised endpoints
+    /// (including a round trip through `diagnostics.echo_peer`), NAT, DNS and
+    /// SRV publication, and the SMTP server with its SPF, DMARC and DKIM
+    /// records. Checks that fail or warn carry a remediation hint. The SRV and
+    /// email-authentication checks need a resolver set with `set_dns_resolver`.
+    pub async fn run_diagnostics(&self) -> DiagnosticsReport {
+        let settings = &self.config.diagnostics;
+        let timeout = Duration::from_millis(settings.timeout_ms);
+        let mut report = DiagnosticsReport::new(self.our_global_id.clone());
+
+        // Listeners
+        let bound = self.multi_transport.as_ref().map(|mt| mt.local_endpoints()).unwrap_or_default();
+        match &self.multi_transport {
+            None => report.push(
+                DiagnosticCheck::fail(CheckCategory::Listeners, "listeners", "Multi-transport router is not running")
+                    .fix("Check the startup log for the transport initialization error"),
+            ),
+            Some(_) if !bound.contains_key("tcp") => report.push(
+                DiagnosticCheck::fail(CheckCategory::Listeners, "listener.tcp", "TCP transport is not listening")
+                    .fix("Free listeners.tcp.port or set listeners.tcp.port_range so the transport can fall back"),
+            ),
+            Some(_) => {}
+        }
+        let mut transports: Vec<_> = bound.iter().collect();
+        transports.sort();
+        for (transport, address) in transports {
+            let preferred = match transport.as_str() {
+                "tcp" => Some(self.config.listeners.tcp.port),
+                _ => None,
+            };
+            let detail = match preferred {
+                Some(port) if port != 0 && port != address.port() => {
+                    format!("Bound to {} (port {} was taken)", address, port)
+                }
+                _ => format!("Bound to {}", address),
+            };
+            report.push(DiagnosticCheck::pass(CheckCategory::Listeners, format!("listener.{}", transport), detail));
+        }
+        // A lazy email server that was never needed is not checked
+        if let Some(email_server) = self.email_server.get() {
+            let smtp = &self.config.listeners.smtp;
+            let check = match email_server {
+                Some(_) => DiagnosticCheck::pass(
+                    CheckCategory::Listeners,
+                    "listener.smtp",
+                    format!("Email server configured on {}:{}", smtp.address, smtp.port),
+                ),
+                None => DiagnosticCheck::fail(CheckCategory::Listeners, "listener.smtp", "Email server did not start")
+                    .fix("Free listeners.smtp.port and listeners.imap.port, or disable the local email server"),
+            };
+            report.push(check);
+        }
+
+        // Reachability
+        let endpoints = self.current_endpoints();
+        for (transport, endpoint) in &endpoints {
+            if transport == "tcp" {
+                diagnostics::check_tcp_endpoint(&mut report, transport, endpoint, timeout).await;
+            } else {
+                report.push(DiagnosticCheck::skipped(
+                    CheckCategory::Reachability,
+                    format!("reachability.{}", transport),
+                    format!("No direct check for the {} transport", transport),
+                ));
             }
+        }
+        let echo = match &settings.echo_peer {
+            None => DiagnosticCheck::skipped(CheckCategory::Reachability, "reachability.echo", "No echo peer configured")
+                .fix("Set diagnostics.echo_peer to a public participant to test a full round trip"),
+            Some(peer) => match self.echo(peer).await {
+                Ok(result) => DiagnosticCheck::pass(
+                    CheckCategory::Reachability,
+                    "reachability.echo",
+                    format!(
+                        "Round trip through {} took {}ms; it saw us at {} over {}",
+                        peer,
+                        result.round_trip_ms,
+                        result.observed_source.as_deref().unwrap_or("an unknown address"),
+                        result.transport.as_deref().unwrap_or("an unknown transport")
+                    ),
+                ),
+                Err(e) => DiagnosticCheck::fail(
+                    CheckCategory::Reachability,
+                    "reachability.echo",
+                    format!("No round trip through {}: {}", peer, e),
+                )
+                .fix("Make sure messages are being received (e.g. a receive loop is running) and that the echo peer has echo.enabled set"),
+            },
         };
-        
-        // Try to initialize email server with connectivity detection
-        let email_server = match SynapseEmailServer::new().await {
-            Ok(server) => {
-                let connectivity = server.get_connectivity();
-                match &connectivity.recommended_config {
-                    ServerRecommendation::RunLocalServer { smtp_port, imap_port, external_ip } => {
-                        info!("Email server configured to run locally on {}:{}/{}", external_ip, smtp_port, imap_port);
-                        Some(Arc::new(server))
-                    }
-                    ServerRecommendation::RelayOnly { reason } => {
-                        info!("Email server configured for relay-only mode: {}", reason);
-                        Some(Arc::new(server))
-                    }
-                    ServerRecommendation::ExternalProvider { reason } => {
-                        warn!("Using external email provider: {}", reason);
-                        warn!("Email server will not be started locally");
-                        None
-                    }
+        report.push(echo);
+
+        // NAT
+        diagnostics::check_nat(&mut report, &endpoints).await;
+        if let Some(mt_router) = &self.multi_transport {
+            diagnostics::check_nat_type(&mut report, mt_router.classify_nat(false).await);
+        }
+
+        // DNS and email authentication
+        let resolver = self.dns_resolver.as_deref();
+        match self.our_global_id.split_once('@').map(|(_, domain)| domain) {
+            Some(domain) if !domain.is_empty() => {
+                diagnostics::check_dns(&mut report, domain, endpoints.get("tcp").map(String::as_str), resolver).await;
+                diagnostics::check_email(
+                    &mut report,
+                    &self.config.email.smtp,
+                    domain,
+                    settings.dkim_selector.as_deref(),
+                    resolver,
+                    timeout,
+                )
+                .await;
+            }
+            _ => {
+                report.push(DiagnosticCheck::skipped(
+                    CheckCategory::Dns,
+                    "dns.domain",
+                    format!("{} has no domain part", self.our_global_id),
+                ));
+                report.push(DiagnosticCheck::skipped(CheckCategory::Email, "email", "No domain to check"));
+            }
+        }
+
+        info!(
+            "Diagnostics for {}: {} ({} checks, {} hints)",
+            self.our_global_id,
+            report.status(),
+            report.checks.len(),
+            report.remediations().len()
+        );
+        report
+    }
+
+    /// Sign our current endpoints and announce them
+    ///
+    /// The update is published to every registered discovery backend and sent
+    /// to every contact and everyone we exchanged messages with in the last
+    /// `endpoint_updates.recent_peer_days`. Returns how many peers it was sent to.
+    pub async fn announce_endpoints(&self) -> Result<usize> {
+        let endpoints = self.current_endpoints();
+        let mut update = EndpointUpdate::new(self.our_global_id.clone(), endpoints.clone());
+        update.signature = self.synapse_router.sign(&update.signing_payload()).await?;
+        *self.announced_endpoints.lock().unwrap() = endpoints;
+
+        let publishers = self.endpoint_publishers.read().unwrap().clone();
+        for publisher in publishers {
+            if let Err(e) = publisher.publish(&update).await {
+                warn!("Could not publish endpoint update to {}: {}", publisher.name(), e);
+            }
+        }
+
+        let since = Utc::now() - chrono::Duration::days(i64::from(self.config.endpoint_updates.recent_peer_days));
+        let mut peers: BTreeSet<String> = self.contacts.list().into_iter().map(|c| c.global_id).collect();
+        peers.extend(self.history.peers_since(since));
+        peers.remove(&self.our_global_id);
+
+        let mut sent = 0;
+        for peer in peers {
+            let message = update.to_message(&peer)?;
+            match self.send_smart_from(
+                None,
+                &message.to,
+                &message.content,
+                message.message_type,
+                SecurityLevel::Authenticated,
+                MessageUrgency::Interactive,
+                message.metadata,
+            ).await {
+                Ok(_) => sent += 1,
+                Err(e) => debug!("Could not send endpoint update to {}: {}", peer, e),
+            }
+        }
+        info!("Announced {} endpoints to {} peers", update.endpoints.len(), sent);
+        Ok(sent)
+    }
+
+    /// Announce our endpoints if they changed since the last announcement
+    ///
+    /// Returns whether an update was announced.
+    pub async fn refresh_endpoints(&self) -> Result<bool> {
+        if !self.config.endpoint_updates.enabled {
+            return Ok(false);
+        }
+        let current = self.current_endpoints();
+        if current.is_empty() || *self.announced_endpoints.lock().unwrap() == current {
+            return Ok(false);
+        }
+        self.announce_endpoints().await?;
+        Ok(true)
+    }
+
+    /// Check our endpoints every `endpoint_updates.check_interval_secs`, announcing changes
+    pub fn spawn_endpoint_monitor(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let period = Duration::from_secs(self.config.endpoint_updates.check_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.refresh_endpoints().await {
+                    warn!("Could not announce endpoint change: {}", e);
                 }
             }
+        })
+    }
+
+    /// Verify a peer's endpoint update and dial the new addresses from now on
+    ///
+    /// Also used for updates looked up in a discovery backend. Only updates
+    /// signed with the participant's own key and newer than the one held are
+    /// applied; cached and resumable routes to the peer are dropped. Returns
+    /// whether the update was applied.
+    pub async fn accept_endpoint_update(&self, update: EndpointUpdate) -> bool {
+        let Some(mt_router) = &self.multi_transport else {
+            return false;
+        };
+        match self
+            .synapse_router
+            .verify_signature(&update.signing_payload(), &update.signature, &update.global_id)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!("Rejected endpoint update for {}: bad signature", update.global_id);
+                return false;
+            }
             Err(e) => {
-                warn!("Failed to initialize email server: {}", e);
-                warn!("Will use external email provider only");
-                None
+                warn!("Rejected endpoint update for {}: {}", update.global_id, e);
+                return false;
             }
-        };
-        
-        let multi_transport_enabled = multi_transport.is_some();
-        let email_server_enabled = email_server.is_some();
-        
-        Ok(Self {
-            synapse_router,
-            multi_transport,
-            email_server,
-            config,
-            our_global_id,
-            multi_transport_enabled,
-            email_server_enabled,
-        })
+        }
+
+        let peer = update.global_id.clone();
+        let transports: Vec<String> = update.endpoints.keys().cloned().collect();
+        if !mt_router.peer_endpoints().apply(update) {
+            debug!("Ignoring stale endpoint update from {}", peer);
+            return false;
+        }
+        mt_router.forget_routes(&peer).await;
+        info!("{} announced new endpoints for {}", peer, transports.join(", "));
+        self.events.publish(RouterEvent::PeerEndpointsChanged { peer, transports });
+        true
     }
-    
-    /// Send a message with automatic transport selection
-    pub async fn send_message_smart(
+
+    /// Erase everything this router holds about a participant
+    ///
+    /// Drops the contact, cached identities and learned name resolutions,
+    /// public keys, conversation history and its search index, admission state and any relayed
+    /// messages. Lifecycle tombstones are kept so the participant is not
+    /// resolved again. Usually called through the registry's purge, which
+    /// adds the router as a [`PurgeTarget`].
+    pub async fn purge_participant(&self, global_id: &str) -> Vec<PurgedItem> {
+        let mut items = vec![
+            PurgedItem::deleted("contacts", self.contacts.remove(global_id).is_ok() as u64),
+            PurgedItem::deleted("identities", self.synapse_router.forget_identity(global_id).await as u64),
+            PurgedItem::deleted("history", self.history.purge_peer(global_id) as u64),
+            PurgedItem::deleted("admission", self.admission.forget(global_id) as u64),
+        ];
+        self.trust_gate.forget(global_id);
+
+        let cached: usize = self
+            .local_identities
+            .names()
+            .iter()
+            .filter_map(|name| self.local_identities.get(name))
+            .map(|identity| identity.registry().forget(global_id))
+            .sum();
+        items.push(PurgedItem::deleted("identities.local", cached as u64));
+
+        #[cfg(feature = "search")]
+        if let Some(search) = &self.search {
+            items.push(match search.remove_peer(global_id) {
+                Ok(removed) => PurgedItem::deleted("history.search", removed),
+                Err(e) => PurgedItem::failed("history.search", e),
+            });
+        }
+
+        #[cfg(feature = "crypto")]
+        if let Some(relay) = &self.relay {
+            items.push(match relay.purge_participant(global_id).await {
+                Ok(removed) => PurgedItem::deleted("relay.messages", removed),
+                Err(e) => PurgedItem::failed("relay.messages", e),
+            });
+        }
+
+        info!("Purged local data for {}", crate::purge::pseudonym(global_id));
+        items
+    }
+
+    /// Organizations whose policies are enforced on outbound messages
+    pub fn organizations(&self) -> Arc<OrganizationRegistry> {
+        self.organizations.clone()
+    }
+
+    /// Destination policies enforced on outbound messages
+    pub fn policies(&self) -> Arc<PolicyEngine> {
+        self.policies.clone()
+    }
+
+    /// Host an additional identity on this router with its own key pair and ports
+    pub fn add_local_identity(
         &self,
-        to_entity: &str,
-        content: &str,
-        message_type: MessageType,
-        security_level: SecurityLevel,
-        urgency: MessageUrgency,
-    ) -> Result<String> {
-        info!("Sending smart message to {} (urgency: {:?})", to_entity, urgency);
-        
-        // If multi-transport is available and urgency is high, try it first
-        if let Some(ref mt_router) = self.multi_transport {
-            if matches!(urgency, MessageUrgency::RealTime | MessageUrgency::Interactive) {
-                // Create secure message
-                let simple_msg = SimpleMessage {
-                    to: to_entity.to_string(),
-                    from_entity: self.our_global_id.clone(),
-                    content: content.to_string(),
-                    message_type: message_type.clone(),
-                    metadata: std::collections::HashMap::new(),
-                };
-                
-                let secure_msg = self.create_secure_message(&simple_msg, security_level.clone()).await?;
-                
-                // Try multi-transport first
-                match mt_router.send_message(to_entity, &secure_msg, urgency).await {
-                    Ok(delivery_receipt) => {
-                        let message_id = delivery_receipt.message_id.clone();
-                        info!("Message sent via multi-transport: {}", message_id);
-                        return Ok(message_id);
-                    }
-                    Err(e) => {
-                        warn!("Multi-transport failed: {}, falling back to email", e);
-                    }
+        name: &str,
+        global_id: &str,
+        bindings: TransportBindings,
+    ) -> Result<Arc<LocalIdentity>> {
+        let identity = LocalIdentity::new(name, global_id, bindings);
+        identity.generate_keypair()?;
+        self.local_identities.add(identity)
+    }
+
+    /// Stop hosting a local identity
+    pub fn remove_local_identity(&self, name: &str) -> Result<()> {
+        self.local_identities.remove(name).map(|_| ())
+    }
+
+    /// Look up a local identity by name or global ID
+    pub fn local_identity(&self, name_or_global_id: &str) -> Option<Arc<LocalIdentity>> {
+        self.local_identities.get(name_or_global_id)
+    }
+
+    /// Names of all hosted local identities
+    pub fn local_identity_names(&self) -> Vec<String> {
+        self.local_identities.names()
+    }
+
+    /// Route an incoming message to the matching local identity's inbox
+    pub fn deliver_to_local_identity(&self, mut message: SimpleMessage) -> Result<()> {
+        let _span = logging::message_span(Self::correlation_id(&message), &message.from_entity, "deliver").entered();
+        if !matches!(self.admit(&message), AdmissionDecision::Admit) {
+            return Ok(());
+        }
+        if self.consume_indicator(&message) {
+            return Ok(());
+        }
+        #[cfg(feature = "crypto")]
+        if message.metadata.contains_key(GROUP_PAYLOAD_KEY) {
+            match self.open_group_payload(message)? {
+                Some(opened) => message = opened,
+                None => return Ok(()),
+            }
+        }
+        match MessageAmendment::from_message(&message) {
+            Some(amendment) => {
+                let identity = self.local_identities.get(&message.to).ok_or_else(|| {
+                    SynapseError::Identity(format!("No local identity for recipient: {}", message.to))
+                })?;
+                let verified =
+                    identity.verify_signature(&amendment.signing_payload(), &amendment.signature, &amendment.author);
+                if !self.apply_amendment(&amendment, verified) {
+                    return Ok(());
                 }
             }
+            None => {
+                let peer = message.from.clone();
+                self.archive(&mut message, &peer);
+            }
         }
-        
-        // Fallback to traditional email routing
-        info!("Using traditional email routing for {}", to_entity);
-        let simple_msg = SimpleMessage {
-            to: to_entity.to_string(),
-            from_entity: self.our_global_id.clone(),
-            content: content.to_string(),
-            message_type,
-            metadata: HashMap::new(),
-        };
-        self.synapse_router.send_message(simple_msg, to_entity.to_string()).await.map(|_| "email_fallback".to_string())
+        self.webhooks.dispatch(&message);
+        self.local_identities.deliver(message)
+    }
+
+    /// Export a local identity as an encrypted bundle for migration to another machine
+    pub fn export_local_identity(
+        &self,
+        name: &str,
+        trust_state_pointers: HashMap<String, String>,
+        passphrase: &str,
+    ) -> Result<EncryptedIdentityBundle> {
+        let identity = self.local_identities.get(name).ok_or_else(|| {
+            crate::error::SynapseError::Identity(format!("Unknown local identity: {}", name))
+        })?;
+        identity::export_bundle(&identity, trust_state_pointers, passphrase)
+    }
+
+    /// Import an identity bundle, host it here and announce the new endpoint to its contacts
+    ///
+    /// Every contact in the bundle receives a system message carrying a signed
+    /// [`MigrationProof`]. Contacts that cannot be reached are logged and skipped.
+    pub async fn import_local_identity(
+        &self,
+        bundle: &EncryptedIdentityBundle,
+        passphrase: &str,
+        bindings: TransportBindings,
+        previous_endpoint: Option<String>,
+        new_endpoint: &str,
+    ) -> Result<MigrationProof> {
+        let (imported, _trust_state_pointers) = identity::import_bundle(bundle, passphrase, bindings)?;
+        let imported = self.local_identities.add(imported)?;
+
+        let proof = MigrationProof::sign(&imported, previous_endpoint, new_endpoint)?;
+        let announcement = serde_json::to_string(&proof)?;
+
+        for contact in imported.registry().all_global_ids() {
+            if let Err(e) = self.send_smart_from(
+                Some(&imported),
+                &contact,
+                &announcement,
+                MessageType::System,
+                SecurityLevel::Authenticated,
+                MessageUrgency::Background,
+                HashMap::new(),
+            ).await {
+                warn!("Failed to announce migration of {} to {}: {}", imported.global_id, contact, e);
+            }
+        }
+
+        info!("Identity {} migrated to {}", imported.global_id, new_endpoint);
+        Ok(proof)
+    }
+
+    /// Drain the inbox of a local identity
+    pub fn receive_as(&self, identity: &str) -> Result<Vec<SimpleMessage>> {
+        let identity = self.local_identities.get(identity).ok_or_else(|| {
+            crate::error::SynapseError::Identity(format!("Unknown local identity: {}", identity))
+        })?;
+        Ok(identity.take_inbox())
     }
     
     /// Send message with explicit transport preference
@@ -471,27 +3179,313 @@ impl EnhancedSynapseRouter {
     /// Start all router services including email server
     pub async fn start(&self) -> Result<()> {
         info!("Starting enhanced Synapse router");
+
+        if let Err(e) = self.boot.time("storage", self.unlock_storage()).await {
+            warn!("Local stores not loaded: {}", e);
+        }
         
         // Start the traditional Synapse router
         // Start Synapse router (no explicit start method)
         // self.synapse_router.start().await?;
         
-        // Start email server if available
-        if let Some(ref email_server) = self.email_server {
-            email_server.start().await?;
+        // Standbys campaign too, to take over when the active node fails
+        if let Some(ref election) = self.election {
+            if let Err(e) = self.boot.time("election", election.campaign()).await {
+                warn!("Could not reach the leader lease store: {}", e);
+            }
+            Arc::clone(election).spawn();
+        }
+        if let Some(ref metering) = self.metering {
+            Arc::clone(metering).spawn();
+        }
+
+        // Start the email server if it was set up already; a lazy one that
+        // serves fast relay handoffs is set up, and started, now
+        self.started.store(true, std::sync::atomic::Ordering::Release);
+        if let Some(Some(email_server)) = self.email_server.get() {
+            self.boot.time("email_server.start", email_server.start()).await?;
             info!("Email server started successfully");
+        } else if self.config.fast_relay.serve {
+            self.ensure_email_server().await;
         }
         
         // Start multi-transport services if available
         if let Some(ref mt_router) = self.multi_transport {
-            mt_router.start_background_services().await?;
+            self.boot.time("background_services", mt_router.start_background_services()).await?;
             info!("Multi-transport services started");
+
+            // Listeners may have come up on new addresses since peers last heard from us
+            if let Err(e) = self.boot.time("endpoints", self.refresh_endpoints()).await {
+                warn!("Could not announce our endpoints: {}", e);
+            }
+        }
+
+        #[cfg(feature = "crypto")]
+        if let Some(ref relay) = self.relay {
+            Arc::clone(relay).start();
+            info!("Relay retention sweep started");
+            if self.config.fast_relay.serve {
+                self.start_fast_relay(Arc::clone(relay)).await?;
+            }
+        }
+        #[cfg(feature = "crypto")]
+        if self.config.fast_relay.serve && self.relay.is_none() {
+            warn!("fast_relay.serve is set but this node is not a relay (relay.enabled = false)");
+        }
+
+        if let (Some(cluster), Some(mt_router)) = (&self.cluster, &self.multi_transport) {
+            Arc::clone(cluster).start(mt_router.sessions());
         }
         
-        info!("Enhanced EMRP router fully started");
+        self.boot.ready();
+        info!("Enhanced EMRP router fully started, {}", self.boot.report());
         Ok(())
     }
+
+    /// Where the time to construct and start this router went
+    pub fn startup_report(&self) -> StartupReport {
+        self.boot.report()
+    }
+
+    /// Capture the state a standby needs to take over from us
+    ///
+    /// Each export continues our term of the state lineage, or starts the
+    /// next term when the last state we saw was another node's; see
+    /// [`crate::node_state`].
+    pub async fn export_state(&self) -> Result<NodeState> {
+        let checkpoint = match self.trust_ledger {
+            Some(ref ledger) => Some(ledger.take_snapshot().await?),
+            None => None,
+        };
+        let inboxes = self
+            .local_identities
+            .names()
+            .into_iter()
+            .filter_map(|name| {
+                let messages = self.local_identities.get(&name)?.peek_inbox();
+                Some((name, messages))
+            })
+            .collect();
+        let sessions = match self.multi_transport {
+            Some(ref multi_transport) => multi_transport.sessions().tickets(),
+            None => Vec::new(),
+        };
+        NodeState {
+            version: NODE_STATE_VERSION,
+            marker: self.state_lineage.next_export(),
+            identities: self.synapse_router.known_identities().await,
+            contacts: self.contacts.list(),
+            inboxes,
+            sessions,
+            checkpoint,
+            checksum: String::new(),
+        }
+        .seal()
+    }
+
+    /// Take over state another node exported
+    ///
+    /// Fails with [`SynapseError::StateConflict`] when the state is older
+    /// than what we last exported or imported, belongs to a different
+    /// lineage, or was exported by another node in the term we hold, which
+    /// is also published as [`RouterEvent::SplitBrainDetected`]. Importing
+    /// state already imported changes nothing. Inboxes of the local
+    /// identities in the state are replaced; keys are not part of it.
+    pub async fn import_state(&self, state: NodeState) -> Result<StateImport> {
+        state.verify()?;
+        let ours = self.state_lineage.current();
+        match self.state_lineage.admit(&state.marker) {
+            StateConsistency::Newer => {}
+            StateConsistency::Same => return Ok(StateImport::default()),
+            consistency => {
+                let ours = ours.expect("a marker to conflict with");
+                if consistency == StateConsistency::SplitBrain {
+                    warn!(
+                        "Split brain: {} and {} both exported term {} of node state",
+                        ours.holder, state.marker.holder, ours.term
+                    );
+                    self.events.publish(RouterEvent::SplitBrainDetected {
+                        lineage: ours.lineage.to_string(),
+                        term: ours.term,
+                        holders: vec![ours.holder.clone(), state.marker.holder.clone()],
+                    });
+                }
+                return Err(SynapseError::StateConflict {
+                    consistency: consistency.to_string(),
+                    reason: format!(
+                        "state from {} (term {}, export {}) does not follow {} (term {}, export {})",
+                        state.marker.holder, state.marker.term, state.marker.sequence,
+                        ours.holder, ours.term, ours.sequence
+                    ),
+                });
+            }
+        }
+
+        let mut report = StateImport {
+            identities: self.synapse_router.restore_identities(state.identities).await,
+            contacts: self.contacts.import_all(state.contacts)?,
+            ..StateImport::default()
+        };
+        for (name, messages) in state.inboxes {
+            let Some(identity) = self.local_identities.get(&name) else {
+                report.skipped_messages += messages.len();
+                continue;
+            };
+            identity.take_inbox();
+            for message in messages {
+                match self.local_identities.deliver(message) {
+                    Ok(()) => report.messages += 1,
+                    Err(e) => {
+                        debug!("Restored message for {} not queued: {}", name, e);
+                        report.skipped_messages += 1;
+                    }
+                }
+            }
+        }
+        if let Some(ref multi_transport) = self.multi_transport {
+            let sessions = multi_transport.sessions();
+            report.sessions = state.sessions.into_iter().map(|ticket| sessions.adopt(ticket)).filter(|&kept| kept).count();
+        }
+        match (&self.trust_ledger, state.checkpoint) {
+            (Some(ledger), Some(checkpoint)) => {
+                report.checkpoint = Some(checkpoint.header.number);
+                ledger.restore_snapshot(checkpoint).await;
+            }
+            (None, Some(checkpoint)) => {
+                debug!("No trust ledger to restore block {} into", checkpoint.header.number);
+            }
+            (_, None) => {}
+        }
+
+        info!(
+            "Imported node state of {} (term {}, export {}): {:?}",
+            state.marker.holder, state.marker.term, state.marker.sequence, report
+        );
+        Ok(report)
+    }
+
+    /// Marker of the last node state we exported or imported
+    pub fn state_marker(&self) -> Option<StateMarker> {
+        self.state_lineage.current()
+    }
+
+    /// Our candidacy for active node, when leader election is enabled
+    pub fn election(&self) -> Option<Arc<LeaderElection>> {
+        self.election.clone()
+    }
+
+    /// Usage counters and quotas of the participants, when metering is enabled
+    ///
+    /// Operators adjust quotas through it, e.g. when a customer changes plan.
+    pub fn metering(&self) -> Option<Arc<UsageMeter>> {
+        self.metering.clone()
+    }
+
+    /// Stop the router gracefully, writing its stores to disk first
+    pub async fn stop(&self) -> Result<()> {
+        info!("Stopping enhanced Synapse router");
+        // Hand over to the standby without waiting for our lease to lapse
+        if let Some(ref election) = self.election {
+            if let Err(e) = election.resign().await {
+                warn!("Could not give up lease {}: {}", election.name(), e);
+            }
+        }
+        // Usage since the last period ended would be lost otherwise
+        if let Some(ref metering) = self.metering {
+            if let Err(e) = metering.export_period().await {
+                warn!("Usage not exported on shutdown: {}", e);
+            }
+        }
+        if let Err(e) = self.persist_storage().await {
+            warn!("Local stores not saved: {}", e);
+        }
+        self.synapse_router.stop().await
+    }
     
+    /// Accept fast relay handoffs for `relay`'s peers over HTTP and SMTP
+    #[cfg(feature = "crypto")]
+    async fn start_fast_relay(&self, relay: Arc<RelayServer>) -> Result<()> {
+        let endpoint = Arc::new(FastRelayEndpoint::new(
+            self.our_global_id.clone(),
+            relay,
+            self.config.relay.max_message_bytes,
+        ));
+        if let Some(email_server) = self.email_server() {
+            email_server.enable_fast_relay(Arc::clone(&endpoint));
+        }
+        let address = &self.config.fast_relay.http_listen;
+        let listener = tokio::net::TcpListener::bind(address).await.map_err(|e| {
+            SynapseError::NetworkError(format!("Cannot bind fast relay API on {}: {}", address, e))
+        })?;
+        info!("Fast relay API listening on {}", address);
+        endpoint.serve(listener);
+        Ok(())
+    }
+
+    /// Collect what our fast email relays hold for us
+    ///
+    /// Each relay in `fast_relay.home_relays` is asked for a challenge, which
+    /// we sign to open a delivery session; held messages are fetched and
+    /// acknowledged. A relay that fails is skipped and tried again next time.
+    pub async fn collect_relayed(&self) -> Vec<SimpleMessage> {
+        #[cfg(feature = "http")]
+        {
+            let Some(client) = self.multi_transport.as_ref().and_then(|mt| mt.fast_relay_client()) else {
+                return Vec::new();
+            };
+            *self.relays_collected_at.lock().unwrap() = Some(std::time::Instant::now());
+            let mut collected = Vec::new();
+            for relay in &self.config.fast_relay.home_relays {
+                match self.collect_from_relay(&client, relay).await {
+                    Ok(messages) => collected.extend(messages),
+                    Err(e) => debug!("Could not collect from relay {}: {}", relay.relay, e),
+                }
+            }
+            collected
+        }
+        #[cfg(not(feature = "http"))]
+        {
+            Vec::new()
+        }
+    }
+
+    #[cfg(feature = "http")]
+    async fn collect_from_relay(
+        &self,
+        client: &crate::transport::fast_relay::FastRelayClient,
+        relay: &crate::config::EmailRelayEndpoint,
+    ) -> Result<Vec<SimpleMessage>> {
+        let challenge = client.challenge(relay, &self.our_global_id).await?;
+        let signature = self.synapse_router.sign(&challenge).await?;
+        let token = client.open_session(relay, &self.our_global_id, signature).await?;
+        let held = client.fetch(relay, &token).await?;
+        if held.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids = held.iter().map(|envelope| envelope.id).collect();
+        let mut incoming = held
+            .into_iter()
+            .map(|envelope| IncomingMessage::new(envelope.message, TransportType::Email, relay.relay.clone()))
+            .collect::<Vec<_>>();
+        self.synapse_router.verify_incoming(&mut incoming).await;
+        let messages = incoming.into_iter().map(Self::open_direct_message).collect::<Vec<_>>();
+        client.acknowledge(relay, &token, ids).await?;
+        info!("Collected {} messages from relay {}", messages.len(), relay.relay);
+        Ok(messages)
+    }
+
+    /// Whether the home relays are due for collection
+    fn relays_due(&self) -> bool {
+        if self.config.fast_relay.home_relays.is_empty() {
+            return false;
+        }
+        let interval = Duration::from_secs(self.config.fast_relay.collect_interval_secs);
+        self.relays_collected_at
+            .lock()
+            .unwrap()
+            .is_none_or(|last| last.elapsed() >= interval)
+    }
+
     /// Get enhanced router status including email server
     pub async fn status(&self) -> EnhancedRouterStatus {
         let synapse_status = self.synapse_router.get_health().await;
@@ -500,18 +3494,26 @@ impl EnhancedSynapseRouter {
         
         if let Some(ref mt_router) = self.multi_transport {
             capabilities.extend(mt_router.get_capabilities());
+            capabilities.extend(mt_router.custom_capabilities().await);
         }
         
-        if self.email_server_enabled {
+        if self.is_running_email_server() {
             capabilities.push("smtp-server".to_string());
             capabilities.push("imap-server".to_string());
         }
+
+        #[cfg(feature = "crypto")]
+        if self.relay.is_some() {
+            capabilities.push("relay".to_string());
+        }
         
         EnhancedRouterStatus {
             synapse_status,
             multi_transport_enabled: self.multi_transport_enabled,
-            email_server_enabled: self.email_server_enabled,
+            email_server_enabled: self.is_running_email_server(),
             available_transports: capabilities,
+            startup: self.startup_report(),
+            leadership: self.election.as_ref().map(|election| election.leadership()),
         }
     }
     
@@ -529,13 +3531,19 @@ impl EnhancedSynapseRouter {
         Ok(SecureMessage {
             message_id,
             to_global_id: simple_msg.to.clone(),
-            from_global_id: self.our_global_id.clone(),
+            from_global_id: simple_msg.from_entity.clone(),
             timestamp,
             security_level,
             encrypted_content: simple_msg.content.as_bytes().to_vec(),
             signature: Vec::new(),
             routing_path: Vec::new(),
-            metadata: simple_msg.metadata.clone(),
+            metadata: {
+                let mut metadata = simple_msg.metadata.clone();
+                metadata.insert(MESSAGE_TYPE_KEY.to_string(), simple_msg.message_type.to_string());
+                VersionRange::LOCAL.stamp(&mut metadata);
+                metadata
+            },
+            attachments: Vec::new(),
         })
     }
     
@@ -560,6 +3568,7 @@ impl EnhancedSynapseRouter {
                 signature: Vec::new(),
                 routing_path: Vec::new(),
                 metadata: std::collections::HashMap::new(),
+                attachments: Vec::new(),
             };
             
             // Test different transport routes
@@ -608,18 +3617,49 @@ impl EnhancedSynapseRouter {
     }
 
     /// Get access to the email server for configuration
+    ///
+    /// `None` until it is set up; see [`Self::ensure_email_server`].
     pub fn email_server(&self) -> Option<Arc<SynapseEmailServer>> {
-        self.email_server.clone()
+        self.email_server.get().cloned().flatten()
+    }
+
+    /// The local email server, setting it up on first use
+    ///
+    /// With `startup.email_server = "lazy"` this is where connectivity is
+    /// assessed and the server created; if the router has already started,
+    /// the server starts too. `None` when the server is disabled, or the
+    /// assessment recommends the external provider.
+    pub async fn ensure_email_server(&self) -> Option<Arc<SynapseEmailServer>> {
+        if self.config.startup.email_server == EmailServerStartup::Disabled {
+            return None;
+        }
+        let mut created = false;
+        let email_server = self
+            .email_server
+            .get_or_init(|| {
+                created = true;
+                self.boot.time("email_server", Self::create_email_server(&self.config))
+            })
+            .await
+            .clone();
+        let server = email_server.as_ref().filter(|_| created && self.started.load(std::sync::atomic::Ordering::Acquire));
+        if let Some(server) = server {
+            match server.start().await {
+                Ok(()) => info!("Email server started successfully"),
+                Err(e) => warn!("Email server did not start: {}", e),
+            }
+        }
+        email_server
     }
 
     /// Check if we're running our own email server
     pub fn is_running_email_server(&self) -> bool {
-        self.email_server_enabled && self.email_server.is_some()
+        self.email_server().is_some()
     }
 
     /// Get email server connectivity information
     pub fn email_server_connectivity(&self) -> Option<String> {
-        if let Some(ref server) = self.email_server {
+        if let Some(server) = self.email_server() {
             let connectivity = server.get_connectivity();
             Some(format!("{:?}", connectivity.recommended_config))
         } else {
@@ -628,6 +3668,17 @@ impl EnhancedSynapseRouter {
     }
 }
 
+#[async_trait::async_trait]
+impl PurgeTarget for EnhancedSynapseRouter {
+    fn name(&self) -> &str {
+        "router"
+    }
+
+    async fn purge(&self, global_id: &str) -> Result<Vec<PurgedItem>> {
+        Ok(self.purge_participant(global_id).await)
+    }
+}
+
 /// Re-export the original router for compatibility
 
 
@@ -649,6 +3700,10 @@ pub struct EnhancedRouterStatus {
     pub multi_transport_enabled: bool,
     pub email_server_enabled: bool,
     pub available_transports: Vec<String>,
+    /// Where the time to construct and start the router went
+    pub startup: StartupReport,
+    /// Whether we are the active node, when leader election is enabled
+    pub leadership: Option<Leadership>,
 }
 
 /// Transport performance benchmarks
@@ -0,0 +1,306 @@
+//! # Runtime Topology
+//!
+//! By default every task of a node shares the runtime the binary started, so
+//! a burst of block production or a slow database can hold up message
+//! delivery. [`RuntimeConfig`] can give each [`Subsystem`] a runtime of its
+//! own and cap how many of its tasks run at once. Subsystems start their
+//! background work with [`spawn`], which places it according to the
+//! [`RuntimeTopology`] the binary [installed](RuntimeTopology::install), or on
+//! the current runtime when none was.
+//!
+//! [`RuntimeTopology::stats`] reports, per subsystem, the tasks spawned,
+//! running and waiting for their budget, and the depth of the scheduler queue
+//! they run on; it is part of the router's health report.
+
+use crate::config::{RuntimeConfig, SubsystemRuntimeConfig};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+static INSTALLED: OnceLock<RuntimeTopology> = OnceLock::new();
+
+/// A part of the node whose tasks can be given their own runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    /// Listeners, connections and message delivery
+    Transports,
+    /// Blockchain block production and snapshots
+    Consensus,
+    /// Trust decay and other database maintenance
+    Storage,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 3] = [Subsystem::Transports, Subsystem::Consensus, Subsystem::Storage];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subsystem::Transports => "transports",
+            Subsystem::Consensus => "consensus",
+            Subsystem::Storage => "storage",
+        }
+    }
+}
+
+impl std::fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Task counters of one subsystem
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubsystemStats {
+    pub subsystem: Subsystem,
+    /// Threads of its dedicated runtime, 0 when it shares the main runtime
+    pub worker_threads: usize,
+    /// Tasks allowed to run at once, 0 for unlimited
+    pub max_tasks: usize,
+    /// Tasks spawned since startup
+    pub spawned: u64,
+    /// Tasks running now
+    pub running: usize,
+    /// Tasks waiting for the budget to let them run
+    pub waiting: usize,
+    /// Tasks that completed or were cancelled
+    pub finished: u64,
+    /// Tasks of any subsystem ready to run but not yet picked up by a worker
+    /// of the runtime these run on
+    pub queue_depth: usize,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    spawned: AtomicU64,
+    running: AtomicUsize,
+    waiting: AtomicUsize,
+    finished: AtomicU64,
+}
+
+/// Counts a task from spawn to finish, including when it is cancelled
+struct TaskGuard {
+    counters: Arc<Counters>,
+    running: bool,
+}
+
+impl TaskGuard {
+    fn start(&mut self) {
+        self.counters.waiting.fetch_sub(1, Ordering::AcqRel);
+        self.counters.running.fetch_add(1, Ordering::AcqRel);
+        self.running = true;
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        let state = if self.running { &self.counters.running } else { &self.counters.waiting };
+        state.fetch_sub(1, Ordering::AcqRel);
+        self.counters.finished.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Where one subsystem's tasks run, and how many at once
+#[derive(Debug)]
+struct Lane {
+    config: SubsystemRuntimeConfig,
+    runtime: Option<Runtime>,
+    budget: Option<Arc<Semaphore>>,
+    counters: Arc<Counters>,
+}
+
+impl Lane {
+    fn new(subsystem: Subsystem, config: SubsystemRuntimeConfig) -> std::io::Result<Self> {
+        let runtime = match config.worker_threads {
+            0 => None,
+            threads => {
+                info!("Running {} on {} dedicated threads", subsystem, threads);
+                Some(
+                    tokio::runtime::Builder::new_multi_thread()
+                        .worker_threads(threads)
+                        .thread_name(format!("synapse-{}", subsystem))
+                        .enable_all()
+                        .build()?,
+                )
+            }
+        };
+        Ok(Self {
+            config,
+            runtime,
+            budget: (config.max_tasks > 0).then(|| Arc::new(Semaphore::new(config.max_tasks))),
+            counters: Arc::default(),
+        })
+    }
+}
+
+impl Drop for Lane {
+    fn drop(&mut self) {
+        // Dropping a runtime waits for its threads, which is not allowed
+        // from inside another runtime
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// The runtimes and task budgets of the node's subsystems
+///
+/// See the module docs.
+#[derive(Debug)]
+pub struct RuntimeTopology {
+    lanes: [Lane; 3],
+}
+
+impl RuntimeTopology {
+    /// Build the dedicated runtimes `config` asks for
+    pub fn new(config: &RuntimeConfig) -> std::io::Result<Self> {
+        Ok(Self {
+            lanes: [
+                Lane::new(Subsystem::Transports, config.transports)?,
+                Lane::new(Subsystem::Consensus, config.consensus)?,
+                Lane::new(Subsystem::Storage, config.storage)?,
+            ],
+        })
+    }
+
+    /// Topology running everything on the current runtime without budgets
+    pub fn shared() -> Self {
+        Self::new(&RuntimeConfig::default()).expect("no runtimes to build")
+    }
+
+    /// Make this the topology [`spawn`] uses
+    ///
+    /// Call it before the node starts; once a topology is in use, later ones
+    /// are dropped.
+    pub fn install(self) -> &'static RuntimeTopology {
+        let mut candidate = Some(self);
+        let installed = INSTALLED.get_or_init(|| candidate.take().expect("initialized once"));
+        if candidate.is_some() {
+            warn!("A runtime topology is already in use; ignoring the new one");
+        }
+        installed
+    }
+
+    /// The installed topology, or a shared one if none was installed
+    pub fn current() -> &'static RuntimeTopology {
+        INSTALLED.get_or_init(Self::shared)
+    }
+
+    /// Run `future` as a task of `subsystem`
+    ///
+    /// Must be called from within a runtime when the subsystem shares it.
+    pub fn spawn<F>(&self, subsystem: Subsystem, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let lane = &self.lanes[subsystem as usize];
+        lane.counters.spawned.fetch_add(1, Ordering::Relaxed);
+        lane.counters.waiting.fetch_add(1, Ordering::AcqRel);
+        let mut guard = TaskGuard {
+            counters: Arc::clone(&lane.counters),
+            running: false,
+        };
+        let budget = lane.budget.clone();
+        let task = async move {
+            let _turn = match budget {
+                Some(budget) => Some(budget.acquire_owned().await.expect("task budget is never closed")),
+                None => None,
+            };
+            guard.start();
+            let output = future.await;
+            drop(guard);
+            output
+        };
+        match lane.runtime {
+            Some(ref runtime) => runtime.spawn(task),
+            None => tokio::spawn(task),
+        }
+    }
+
+    /// Counters of every subsystem, in [`Subsystem::ALL`] order
+    pub fn stats(&self) -> Vec<SubsystemStats> {
+        Subsystem::ALL
+            .iter()
+            .map(|&subsystem| {
+                let lane = &self.lanes[subsystem as usize];
+                let queue_depth = match lane.runtime {
+                    Some(ref runtime) => runtime.metrics().global_queue_depth(),
+                    None => Handle::try_current().map_or(0, |handle| handle.metrics().global_queue_depth()),
+                };
+                SubsystemStats {
+                    subsystem,
+                    worker_threads: lane.config.worker_threads,
+                    max_tasks: lane.config.max_tasks,
+                    spawned: lane.counters.spawned.load(Ordering::Relaxed),
+                    running: lane.counters.running.load(Ordering::Acquire),
+                    waiting: lane.counters.waiting.load(Ordering::Acquire),
+                    finished: lane.counters.finished.load(Ordering::Relaxed),
+                    queue_depth,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Run `future` as a task of `subsystem` on the installed topology
+pub fn spawn<F>(subsystem: Subsystem, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    RuntimeTopology::current().spawn(subsystem, future)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn subsystems_run_on_their_runtime_within_budget() {
+        let topology = RuntimeTopology::new(&RuntimeConfig {
+            consensus: SubsystemRuntimeConfig { worker_threads: 1, max_tasks: 1 },
+            ..RuntimeConfig::default()
+        })
+        .unwrap();
+        let consensus = |topology: &RuntimeTopology| topology.stats()[Subsystem::Consensus as usize].clone();
+
+        let main = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        main.block_on(async {
+            let (release, hold) = tokio::sync::oneshot::channel::<()>();
+            let first = topology.spawn(Subsystem::Consensus, async move {
+                hold.await.ok();
+                std::thread::current().name().map(String::from)
+            });
+            let second = topology.spawn(Subsystem::Consensus, async { 2 });
+            let shared = topology.spawn(Subsystem::Transports, async {
+                std::thread::current().name().map(String::from)
+            });
+
+            // The second consensus task waits for the first to finish
+            while consensus(&topology).running == 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            let stats = consensus(&topology);
+            assert_eq!((stats.running, stats.waiting), (1, 1));
+
+            release.send(()).unwrap();
+            assert_eq!(first.await.unwrap().as_deref(), Some("synapse-consensus"));
+            assert_eq!(second.await.unwrap(), 2);
+            assert_ne!(shared.await.unwrap().as_deref(), Some("synapse-consensus"));
+        });
+
+        let stats = consensus(&topology);
+        assert_eq!((stats.worker_threads, stats.max_tasks), (1, 1));
+        assert_eq!((stats.spawned, stats.finished), (2, 2));
+        assert_eq!((stats.running, stats.waiting), (0, 0));
+        let transports = &topology.stats()[Subsystem::Transports as usize];
+        assert_eq!((transports.worker_threads, transports.finished), (0, 1));
+    }
+}
@@ -0,0 +1,324 @@
+//! # Conversation Search
+//!
+//! A local full-text index over the conversation history, built with
+//! tantivy (requires the `search` feature). The router indexes each message
+//! as it is recorded, re-indexes it when it is edited and drops it when it
+//! is retracted or its peer is purged, so the index never has to be rebuilt
+//! from scratch.
+//!
+//! Queries match message bodies and metadata values, optionally limited to
+//! one conversation (every message exchanged with a peer) or one sender, and
+//! return ranked hits with a snippet of the matching text.
+//!
+//! The index is stored unencrypted; nodes that need the history encrypted at
+//! rest should keep it in memory or on an encrypted volume.
+
+use crate::error::{Result, SynapseError};
+use crate::history::HistoryEntry;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+/// Memory the index writer may buffer before flushing a segment
+const WRITER_HEAP_BYTES: usize = 20_000_000;
+
+/// A search over the conversation history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryQuery {
+    /// Words or phrases to match; empty matches every message
+    pub text: String,
+    /// Only messages exchanged with this peer
+    pub peer: Option<String>,
+    /// Only messages sent by this participant
+    pub sender: Option<String>,
+    /// Most hits returned
+    pub limit: usize,
+}
+
+impl HistoryQuery {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            peer: None,
+            sender: None,
+            limit: 20,
+        }
+    }
+
+    /// Limit the search to one conversation
+    pub fn with_peer(mut self, peer: impl Into<String>) -> Self {
+        self.peer = Some(peer.into());
+        self
+    }
+
+    /// Limit the search to one sender
+    pub fn from_sender(mut self, sender: impl Into<String>) -> Self {
+        self.sender = Some(sender.into());
+        self
+    }
+
+    /// Return at most `limit` hits
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+/// A message matching a query, best matches first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub message_id: String,
+    pub from: String,
+    pub to: String,
+    pub recorded_at: DateTime<Utc>,
+    /// Relevance; only comparable within one result set
+    pub score: f32,
+    /// Excerpt of the body around the matched terms
+    pub snippet: String,
+    /// Byte ranges of the matched terms within `snippet`
+    pub highlights: Vec<(usize, usize)>,
+}
+
+struct Fields {
+    message_id: Field,
+    /// Sender and recipient, for conversation filters
+    participant: Field,
+    sender: Field,
+    recipient: Field,
+    body: Field,
+    metadata: Field,
+    recorded_at: Field,
+}
+
+/// Full-text index over conversation history entries
+pub struct ConversationIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+}
+
+impl ConversationIndex {
+    /// Create an index held in memory
+    pub fn in_memory() -> Result<Self> {
+        Self::with_index(Index::create_in_ram(schema()))
+    }
+
+    /// Open the index in `dir`, creating it if needed
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let directory = MmapDirectory::open(dir).map_err(|e| SynapseError::DatabaseError(e.to_string()))?;
+        Self::with_index(Index::open_or_create(directory, schema())?)
+    }
+
+    fn with_index(index: Index) -> Result<Self> {
+        let schema = index.schema();
+        let field = |name: &str| schema.get_field(name).map_err(SynapseError::from);
+        let fields = Fields {
+            message_id: field("message_id")?,
+            participant: field("participant")?,
+            sender: field("sender")?,
+            recipient: field("recipient")?,
+            body: field("body")?,
+            metadata: field("metadata")?,
+            recorded_at: field("recorded_at")?,
+        };
+        let writer = index.writer_with_num_threads(1, WRITER_HEAP_BYTES)?;
+        // Reloaded after each commit so a message is searchable once indexed
+        let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+        Ok(Self {
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            fields,
+        })
+    }
+
+    /// Number of indexed messages
+    pub fn len(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a message, replacing any earlier version of it
+    pub fn index(&self, entry: &HistoryEntry) -> Result<()> {
+        self.index_all([entry]).map(|_| ())
+    }
+
+    /// Add several messages in one commit, returning how many were indexed
+    ///
+    /// Retracted messages are removed rather than indexed.
+    pub fn index_all<'a>(&self, entries: impl IntoIterator<Item = &'a HistoryEntry>) -> Result<usize> {
+        let fields = &self.fields;
+        let mut writer = self.writer.lock().unwrap();
+        let mut indexed = 0;
+        for entry in entries {
+            writer.delete_term(Term::from_field_text(fields.message_id, &entry.message_id));
+            if entry.is_retracted() {
+                continue;
+            }
+            let mut document = TantivyDocument::default();
+            document.add_text(fields.message_id, &entry.message_id);
+            document.add_text(fields.participant, &entry.from);
+            document.add_text(fields.participant, &entry.to);
+            document.add_text(fields.sender, &entry.from);
+            document.add_text(fields.recipient, &entry.to);
+            document.add_text(fields.body, &entry.content);
+            for value in entry.metadata.values() {
+                document.add_text(fields.metadata, value);
+            }
+            document.add_i64(fields.recorded_at, entry.recorded_at.0.timestamp_millis());
+            writer.add_document(document)?;
+            indexed += 1;
+        }
+        self.commit(&mut writer)?;
+        Ok(indexed)
+    }
+
+    /// Drop a message from the index
+    pub fn remove(&self, message_id: &str) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_text(self.fields.message_id, message_id));
+        self.commit(&mut writer)
+    }
+
+    /// Drop every message exchanged with `peer`, returning how many were removed
+    pub fn remove_peer(&self, peer: &str) -> Result<u64> {
+        let mut writer = self.writer.lock().unwrap();
+        let before = self.len();
+        writer.delete_term(Term::from_field_text(self.fields.participant, peer));
+        self.commit(&mut writer)?;
+        Ok(before - self.len())
+    }
+
+    fn commit(&self, writer: &mut IndexWriter) -> Result<()> {
+        writer.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    /// Ranked messages matching `query`
+    ///
+    /// The text is parsed leniently, so stray quotes or operators in user
+    /// input narrow the match instead of failing the search.
+    pub fn search(&self, query: &HistoryQuery) -> Result<Vec<SearchHit>> {
+        let fields = &self.fields;
+        let searcher = self.reader.searcher();
+        let text_query: Box<dyn Query> = if query.text.trim().is_empty() {
+            Box::new(AllQuery)
+        } else {
+            let parser = QueryParser::for_index(&self.index, vec![fields.body, fields.metadata]);
+            parser.parse_query_lenient(&query.text).0
+        };
+
+        let mut clauses = vec![(Occur::Must, text_query.box_clone())];
+        let filters = [(fields.participant, &query.peer), (fields.sender, &query.sender)];
+        for (field, value) in filters {
+            if let Some(value) = value {
+                let term = Term::from_field_text(field, value);
+                clauses.push((Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic))));
+            }
+        }
+
+        let top = searcher.search(&BooleanQuery::new(clauses), &TopDocs::with_limit(query.limit.max(1)))?;
+        let snippets = SnippetGenerator::create(&searcher, &*text_query, fields.body)?;
+        top.into_iter()
+            .map(|(score, address)| {
+                let document: TantivyDocument = searcher.doc(address)?;
+                let text = |field| document.get_first(field).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                let recorded_at = document.get_first(fields.recorded_at).and_then(|v| v.as_i64()).unwrap_or_default();
+                let snippet = snippets.snippet_from_doc(&document);
+                Ok(SearchHit {
+                    message_id: text(fields.message_id),
+                    from: text(fields.sender),
+                    to: text(fields.recipient),
+                    recorded_at: DateTime::from_timestamp_millis(recorded_at).unwrap_or_default(),
+                    score,
+                    snippet: snippet.fragment().to_string(),
+                    highlights: snippet.highlighted().iter().map(|range| (range.start, range.end)).collect(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn schema() -> Schema {
+    let mut schema = Schema::builder();
+    schema.add_text_field("message_id", STRING | STORED);
+    schema.add_text_field("participant", STRING);
+    schema.add_text_field("sender", STRING | STORED);
+    schema.add_text_field("recipient", STRING | STORED);
+    schema.add_text_field("body", TEXT | STORED);
+    schema.add_text_field("metadata", TEXT);
+    schema.add_i64_field("recorded_at", INDEXED | STORED | FAST);
+    schema.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{AmendmentAction, ConversationHistory, MessageAmendment};
+    use crate::types::SimpleMessage;
+
+    fn record(history: &ConversationHistory, index: &ConversationIndex, to: &str, from: &str, content: &str) -> String {
+        let id = history.record(&mut SimpleMessage::new(to, from, content));
+        index.index(&history.get(&id).unwrap()).unwrap();
+        id
+    }
+
+    #[test]
+    fn finds_ranked_messages_with_filters() {
+        let history = ConversationHistory::default();
+        let index = ConversationIndex::in_memory().unwrap();
+        record(&history, &index, "bob", "alice", "The quarterly report is attached");
+        record(&history, &index, "alice", "bob", "Thanks, I will read the report tonight");
+        record(&history, &index, "carol", "alice", "Lunch on Friday? The report can wait");
+        assert_eq!(index.len(), 3);
+
+        let hits = index.search(&HistoryQuery::new("report")).unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        let hits = index.search(&HistoryQuery::new("report").with_peer("bob")).unwrap();
+        assert_eq!(hits.len(), 2);
+        let hits = index.search(&HistoryQuery::new("report").with_peer("bob").from_sender("bob")).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].to, "alice");
+        let (start, end) = hits[0].highlights[0];
+        assert_eq!(&hits[0].snippet[start..end], "report");
+
+        assert_eq!(index.search(&HistoryQuery::new("").with_peer("carol")).unwrap().len(), 1);
+        // Unbalanced quotes in user input do not fail the search
+        assert!(index.search(&HistoryQuery::new("\"quarterly")).is_ok());
+    }
+
+    #[test]
+    fn follows_edits_retractions_and_purges() {
+        let history = ConversationHistory::default();
+        let index = ConversationIndex::in_memory().unwrap();
+        let id = record(&history, &index, "bob", "alice", "meet at the station");
+        record(&history, &index, "carol", "alice", "meet at noon");
+
+        let edit = MessageAmendment::new("alice", &id, AmendmentAction::Edit { content: "meet at the harbour".into() });
+        index.index(&history.apply(&edit).unwrap()).unwrap();
+        assert!(index.search(&HistoryQuery::new("station")).unwrap().is_empty());
+        assert_eq!(index.search(&HistoryQuery::new("harbour")).unwrap()[0].message_id, id);
+
+        let retract = MessageAmendment::new("alice", &id, AmendmentAction::Retract);
+        index.index(&history.apply(&retract).unwrap()).unwrap();
+        assert!(index.search(&HistoryQuery::new("harbour")).unwrap().is_empty());
+
+        assert_eq!(index.remove_peer("carol").unwrap(), 1);
+        assert!(index.is_empty());
+    }
+}
@@ -0,0 +1,375 @@
+//! # Running Under a Service Supervisor
+//!
+//! A node started by systemd or the Windows service control manager has to
+//! tell its supervisor when it is up, keep proving it is alive, and stop
+//! cleanly when asked. [`Supervisor`] does this for whichever supervisor
+//! started the process and does nothing when there is none:
+//!
+//! - **systemd**: `READY=1`, `STATUS=` and `STOPPING=1` are sent to
+//!   `$NOTIFY_SOCKET` (use `Type=notify`), and with `WatchdogSec=` set the
+//!   watchdog is pinged at half the interval from the async runtime, so a
+//!   stalled runtime gets the node restarted.
+//! - **Windows**: with the `windows-service` feature, [`run_windows_service`]
+//!   registers a control handler that turns Stop and Shutdown requests into
+//!   the same [`Shutdown`] as Ctrl+C.
+//!
+//! Either way, SIGTERM, SIGINT and Ctrl+C trigger [`Shutdown`]; the caller
+//! waits for it, calls [`Supervisor::stopping`] and then stops the router,
+//! which persists its stores.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+/// Shared request to shut down, triggered by signals or the supervisor
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    requested: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            requested: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Ask everything waiting on this to shut down
+    pub fn trigger(&self) {
+        self.requested.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Resolves once shutdown has been requested
+    pub async fn wait(&self) {
+        let mut requested = self.requested.subscribe();
+        let _ = requested.wait_for(|requested| *requested).await;
+    }
+
+    /// Trigger on Ctrl+C, and on SIGTERM and SIGINT on Unix
+    pub fn trigger_on_signals(&self) -> tokio::task::JoinHandle<()> {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            {
+                use tokio::signal::unix::{signal, SignalKind};
+                match signal(SignalKind::terminate()) {
+                    Ok(mut terminate) => tokio::select! {
+                        _ = tokio::signal::ctrl_c() => info!("Interrupted, shutting down"),
+                        _ = terminate.recv() => info!("Terminated, shutting down"),
+                    },
+                    Err(e) => {
+                        warn!("Cannot listen for SIGTERM: {}", e);
+                        let _ = tokio::signal::ctrl_c().await;
+                    }
+                }
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = tokio::signal::ctrl_c().await;
+                info!("Interrupted, shutting down");
+            }
+            shutdown.trigger();
+        })
+    }
+}
+
+/// Sends state notifications to systemd over `$NOTIFY_SOCKET`
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct SystemdNotifier {
+    socket: std::os::unix::net::UnixDatagram,
+    path: String,
+}
+
+#[cfg(unix)]
+impl SystemdNotifier {
+    /// Notifier for `$NOTIFY_SOCKET`, if systemd started us with one
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("NOTIFY_SOCKET").ok().filter(|path| !path.is_empty())?;
+        match Self::new(path) {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                warn!("Cannot open NOTIFY_SOCKET: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Notifier for a socket path; a leading `@` names an abstract socket
+    pub fn new(path: impl Into<String>) -> std::io::Result<Self> {
+        Ok(Self {
+            socket: std::os::unix::net::UnixDatagram::unbound()?,
+            path: path.into(),
+        })
+    }
+
+    /// Send newline-separated `KEY=value` assignments, e.g. `READY=1`
+    pub fn notify(&self, state: &str) -> std::io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if let Some(name) = self.path.strip_prefix('@') {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            return self.socket.send_to_addr(state.as_bytes(), &address).map(|_| ());
+        }
+        self.socket.send_to(state.as_bytes(), &self.path).map(|_| ())
+    }
+
+    /// Interval systemd expects watchdog pings within, if the watchdog is on
+    /// for this process
+    pub fn watchdog_interval() -> Option<Duration> {
+        watchdog_interval(
+            std::env::var("WATCHDOG_USEC").ok().as_deref(),
+            std::env::var("WATCHDOG_PID").ok().as_deref(),
+            std::process::id(),
+        )
+    }
+}
+
+#[cfg(unix)]
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>, our_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse() != Ok(our_pid)) {
+        return None;
+    }
+    usec?.parse().ok().filter(|usec| *usec > 0).map(Duration::from_micros)
+}
+
+/// The node's side of its supervisor
+///
+/// ```no_run
+/// # async fn run(router: synapse::router_enhanced::EnhancedSynapseRouter) -> synapse::error::Result<()> {
+/// use synapse::service::Supervisor;
+///
+/// let mut supervisor = Supervisor::detect();
+/// router.start().await?;
+/// supervisor.ready("Accepting messages");
+/// supervisor.shutdown().wait().await;
+/// supervisor.stopping();
+/// router.stop().await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Supervisor {
+    shutdown: Shutdown,
+    #[cfg(unix)]
+    notifier: Option<Arc<SystemdNotifier>>,
+    watchdog: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl Supervisor {
+    /// Supervisor of this process, listening for termination signals;
+    /// must be called inside the Tokio runtime
+    pub fn detect() -> Self {
+        Self::with_shutdown(Shutdown::new())
+    }
+
+    /// Like [`Supervisor::detect`], triggering an existing [`Shutdown`]
+    pub fn with_shutdown(shutdown: Shutdown) -> Self {
+        shutdown.trigger_on_signals();
+        let supervisor = Self {
+            shutdown,
+            #[cfg(unix)]
+            notifier: SystemdNotifier::from_env().map(Arc::new),
+            watchdog: None,
+        };
+        if supervisor.is_supervised() {
+            debug!("Running under systemd");
+        }
+        supervisor
+    }
+
+    /// Whether a supervisor is listening to our notifications
+    pub fn is_supervised(&self) -> bool {
+        #[cfg(unix)]
+        let supervised = self.notifier.is_some();
+        #[cfg(not(unix))]
+        let supervised = false;
+        supervised
+    }
+
+    /// Triggered when the node should stop
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Report the node started, and start pinging the watchdog
+    pub fn ready(&mut self, status: &str) {
+        self.notify(&format!("READY=1\nSTATUS={}", status));
+        #[cfg(unix)]
+        if let (Some(notifier), Some(interval), None) =
+            (self.notifier.clone(), SystemdNotifier::watchdog_interval(), &self.watchdog)
+        {
+            info!("Pinging the systemd watchdog every {:?}", interval / 2);
+            self.watchdog = Some(tokio::spawn(async move {
+                let mut ticks = tokio::time::interval(interval / 2);
+                loop {
+                    ticks.tick().await;
+                    if let Err(e) = notifier.notify("WATCHDOG=1") {
+                        warn!("Watchdog ping failed: {}", e);
+                    }
+                }
+            }));
+        }
+    }
+
+    /// Human-readable status shown by `systemctl status`
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={}", status));
+    }
+
+    /// Report the node is shutting down; watchdog pings stop
+    pub fn stopping(&mut self) {
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.abort();
+        }
+        self.notify("STOPPING=1");
+    }
+
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn notify(&self, state: &str) {
+        #[cfg(unix)]
+        if let Some(ref notifier) = self.notifier {
+            if let Err(e) = notifier.notify(state) {
+                warn!("Could not notify systemd: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.abort();
+        }
+    }
+}
+
+#[cfg(all(windows, feature = "windows-service"))]
+mod windows {
+    use super::Shutdown;
+    use crate::error::{Result, SynapseError};
+    use std::ffi::OsString;
+    use std::sync::OnceLock;
+    use std::time::Duration;
+    use tracing::error;
+    use windows_service::{
+        define_windows_service,
+        service::{ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType},
+        service_control_handler::{self, ServiceControlHandlerResult},
+        service_dispatcher,
+    };
+
+    type ServiceMain = fn(Shutdown) -> Result<()>;
+
+    static SERVICE: OnceLock<(&'static str, ServiceMain)> = OnceLock::new();
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Run `main` as the Windows service `name`
+    ///
+    /// Blocks until the service stops. Stop and Shutdown requests from the
+    /// service control manager trigger the [`Shutdown`] passed to `main`,
+    /// which should wait on it, stop the node and return. Only works when the
+    /// process was started by the service control manager.
+    pub fn run_windows_service(name: &'static str, main: ServiceMain) -> Result<()> {
+        if SERVICE.set((name, main)).is_err() {
+            return Err(SynapseError::ConfigurationError("Windows service already running".to_string()));
+        }
+        service_dispatcher::start(name, ffi_service_main)
+            .map_err(|e| SynapseError::ConfigurationError(format!("Cannot start Windows service {}: {}", name, e)))
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let Some(&(name, main)) = SERVICE.get() else {
+            return;
+        };
+        let shutdown = Shutdown::new();
+        let requested = shutdown.clone();
+        let handler = move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                requested.trigger();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let status = match service_control_handler::register(name, handler) {
+            Ok(status) => status,
+            Err(e) => {
+                error!("Cannot register service control handler: {}", e);
+                return;
+            }
+        };
+        let report = |state, exit_code| {
+            let _ = status.set_service_status(ServiceStatus {
+                service_type: ServiceType::OWN_PROCESS,
+                current_state: state,
+                controls_accepted: match state {
+                    ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+                    _ => ServiceControlAccept::empty(),
+                },
+                exit_code: ServiceExitCode::Win32(exit_code),
+                checkpoint: 0,
+                wait_hint: Duration::from_secs(10),
+                process_id: None,
+            });
+        };
+
+        report(ServiceState::Running, 0);
+        let exit_code = match main(shutdown) {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("Service stopped with an error: {}", e);
+                1
+            }
+        };
+        report(ServiceState::Stopped, exit_code);
+    }
+}
+
+#[cfg(all(windows, feature = "windows-service"))]
+pub use windows::run_windows_service;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn notifies_systemd_and_shuts_down() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let systemd = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        let notifier = SystemdNotifier::new(path.to_string_lossy()).unwrap();
+        notifier.notify("READY=1\nSTATUS=up").unwrap();
+        let mut buf = [0u8; 64];
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=up");
+
+        assert_eq!(watchdog_interval(Some("30000000"), None, 7), Some(Duration::from_secs(30)));
+        assert_eq!(watchdog_interval(Some("30000000"), Some("7"), 7), Some(Duration::from_secs(30)));
+        assert_eq!(watchdog_interval(Some("30000000"), Some("8"), 7), None);
+        assert_eq!(watchdog_interval(Some("0"), None, 7), None);
+
+        let shutdown = Shutdown::new();
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+        assert!(!shutdown.is_triggered());
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        // Waiting after the fact returns at once
+        shutdown.wait().await;
+    }
+}
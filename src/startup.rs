@@ -0,0 +1,138 @@
+//! # Startup Profiling
+//!
+//! A router sets up its transports, stores and optional email server before
+//! it accepts messages. Independent subsystems are set up concurrently, and
+//! the email server, whose connectivity assessment probes the network, only
+//! when needed (see [`StartupConfig`](crate::config::StartupConfig)).
+//!
+//! Each step is timed on a [`BootProfile`]; its [`StartupReport`] shows where
+//! a slow cold start went. `EnhancedSynapseRouter::startup_report` returns it
+//! and the router logs it once started.
+
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Time one startup step took
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StartupTiming {
+    /// Step, e.g. `transports` or `email_server`
+    pub subsystem: String,
+    /// When the step began, from the start of the profile
+    pub started_after: Duration,
+    pub elapsed: Duration,
+}
+
+/// Where the time to start a router went
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StartupReport {
+    /// Steps in the order they finished; concurrent steps overlap
+    pub timings: Vec<StartupTiming>,
+    /// From the start of the profile until the router was started
+    pub ready_after: Option<Duration>,
+}
+
+impl StartupReport {
+    /// The step that took longest
+    pub fn slowest(&self) -> Option<&StartupTiming> {
+        self.timings.iter().max_by_key(|timing| timing.elapsed)
+    }
+}
+
+impl std::fmt::Display for StartupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ready_after {
+            Some(ready) => write!(f, "ready in {} ms", ready.as_millis())?,
+            None => f.write_str("starting")?,
+        }
+        let mut timings = self.timings.iter();
+        if let Some(first) = timings.next() {
+            write!(f, ": {} {} ms", first.subsystem, first.elapsed.as_millis())?;
+            for timing in timings {
+                write!(f, ", {} {} ms", timing.subsystem, timing.elapsed.as_millis())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Timings of one router's startup
+#[derive(Debug)]
+pub struct BootProfile {
+    began: Instant,
+    timings: Mutex<Vec<StartupTiming>>,
+    ready_after: OnceLock<Duration>,
+}
+
+impl Default for BootProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BootProfile {
+    pub fn new() -> Self {
+        Self {
+            began: Instant::now(),
+            timings: Mutex::new(Vec::new()),
+            ready_after: OnceLock::new(),
+        }
+    }
+
+    /// Run `step`, recording how long it took as `subsystem`
+    pub async fn time<F: Future>(&self, subsystem: &str, step: F) -> F::Output {
+        let started = Instant::now();
+        let output = step.await;
+        self.timings.lock().unwrap().push(StartupTiming {
+            subsystem: subsystem.to_string(),
+            started_after: started.duration_since(self.began),
+            elapsed: started.elapsed(),
+        });
+        output
+    }
+
+    /// Note that startup is complete; later calls are ignored
+    pub fn ready(&self) -> Duration {
+        *self.ready_after.get_or_init(|| self.began.elapsed())
+    }
+
+    pub fn report(&self) -> StartupReport {
+        StartupReport {
+            timings: self.timings.lock().unwrap().clone(),
+            ready_after: self.ready_after.get().copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_steps_are_timed_separately() {
+        let profile = BootProfile::new();
+        let pause = |ms| tokio::time::sleep(Duration::from_millis(ms));
+        let (transports, email) = tokio::join!(
+            profile.time("transports", async {
+                pause(40).await;
+                1
+            }),
+            profile.time("email_server", pause(10)),
+        );
+        assert_eq!((transports, email), (1, ()));
+        assert_eq!(profile.report().ready_after, None);
+
+        let ready = profile.ready();
+        assert_eq!(profile.ready(), ready);
+        let report = profile.report();
+        assert_eq!(report.ready_after, Some(ready));
+        let order: Vec<&str> = report.timings.iter().map(|t| t.subsystem.as_str()).collect();
+        assert_eq!(order, ["email_server", "transports"]);
+        assert_eq!(report.slowest().unwrap().subsystem, "transports");
+        // The email server step began before the transports were done
+        assert!(report.timings[0].started_after < report.timings[1].started_after + report.timings[1].elapsed);
+        assert!(report.timings[0].started_after < Duration::from_millis(40));
+        assert!(report.to_string().starts_with("ready in "));
+    }
+}
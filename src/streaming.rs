@@ -1,16 +1,35 @@
 //! Real-time streaming support for EMRP
+//!
+//! Besides chunked streams over the email router, large transfers can be
+//! striped across several transports at once with [`MultipathSender`]; the
+//! receiving side puts the stripes back in order with [`StripeReassembler`]
+//! and checks the whole transfer against the content hash the sender puts
+//! on the final stripe.
 use crate::{
-    types::{SimpleMessage, MessageType, StreamChunk},
+    types::{SimpleMessage, SecureMessage, SecurityLevel, MessageType, StreamChunk, StreamPriority, Attachment, AttachmentContent},
     router::SynapseRouter,
-    error::Result,
+    transport::abstraction::{Transport, TransportTarget, TransportType},
+    error::{Result, SynapseError},
+    hashing::{ContentHasher, HashAlgorithm},
 };
 use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
 use uuid::Uuid;
 use chrono::Utc;
 use base64::Engine;
-use std::{sync::Arc, collections::HashMap};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::{sync::Arc, collections::{BTreeMap, HashMap, VecDeque}, time::{Duration, Instant}};
 use tokio::sync::mpsc;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
+
+/// Message metadata key carrying the ID of the multipath stream a stripe belongs to
+pub const MULTIPATH_STREAM_KEY: &str = "multipath_stream";
+
+/// Metadata key on a multipath stream's final stripe with the content hash
+/// of the whole transfer
+pub const MULTIPATH_CONTENT_HASH_KEY: &str = "multipath_content_hash";
+
+/// Chunk size used when offloading attachments to a stream
+pub const ATTACHMENT_CHUNK_SIZE: usize = 64 * 1024;
 
 /// Stream manager for handling real-time message streams
 pub struct StreamManager {
@@ -103,6 +122,39 @@ impl StreamManager {
         Ok(())
     }
 
+    /// Send raw bytes as a stream of chunks, returning the stream ID
+    pub async fn send_attachment(&self, to_entity: &str, from_entity: &str, data: &[u8]) -> Result<String> {
+        let mut session = self.start_stream(to_entity, from_entity).await?;
+        for chunk in data.chunks(ATTACHMENT_CHUNK_SIZE) {
+            self.send_chunk(&mut session, chunk).await?;
+        }
+        self.finish_stream(&mut session).await?;
+        Ok(session.stream_id)
+    }
+
+    /// Move attachments too large to travel inline onto their own streams
+    ///
+    /// Each offloaded attachment keeps its name, MIME type, size and digest, so
+    /// the receiver can verify the reassembled bytes. Returns how many were moved.
+    pub async fn offload_attachments(
+        &self,
+        attachments: &mut [Attachment],
+        to_entity: &str,
+        from_entity: &str,
+    ) -> Result<usize> {
+        let mut offloaded = 0;
+        for attachment in attachments.iter_mut().filter(|a| a.needs_offload()) {
+            let AttachmentContent::Inline { data } = &attachment.content else {
+                continue;
+            };
+            let stream_id = self.send_attachment(to_entity, from_entity, data).await?;
+            debug!("Offloaded attachment {} ({} bytes) to stream {}", attachment.name, attachment.size, stream_id);
+            attachment.content = AttachmentContent::Stream { stream_id };
+            offloaded += 1;
+        }
+        Ok(offloaded)
+    }
+
     /// Process incoming stream chunks
     pub async fn handle_stream_message(&self, message: &SimpleMessage) -> Result<Option<StreamChunk>> {
         if message.message_type != MessageType::StreamChunk {
@@ -155,6 +207,438 @@ impl StreamSession {
     }
 }
 
+/// Multipath striping settings
+#[derive(Debug, Clone)]
+pub struct MultipathConfig {
+    /// Bytes per stripe
+    pub chunk_size: usize,
+    /// Stripes each path may have in flight before any acknowledgement
+    pub initial_window: f64,
+    /// Upper bound on any path's congestion window
+    pub max_window: f64,
+    /// Consecutive failures after which a path is dropped from the transfer
+    pub max_path_failures: u32,
+}
+
+impl Default for MultipathConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 256 * 1024,
+            initial_window: 2.0,
+            max_window: 64.0,
+            max_path_failures: 3,
+        }
+    }
+}
+
+/// AIMD congestion window for one path, counted in stripes
+#[derive(Debug, Clone)]
+pub struct CongestionWindow {
+    cwnd: f64,
+    ssthresh: f64,
+    max_window: f64,
+    srtt: Option<Duration>,
+}
+
+impl CongestionWindow {
+    /// Create a window in slow start
+    pub fn new(initial: f64, max_window: f64) -> Self {
+        Self { cwnd: initial.max(1.0), ssthresh: max_window, max_window, srtt: None }
+    }
+
+    /// Current window size
+    pub fn window(&self) -> f64 {
+        self.cwnd
+    }
+
+    /// Smoothed round-trip time, once a stripe has been acknowledged
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// Grow the window after a delivered stripe
+    pub fn on_ack(&mut self, rtt: Duration) {
+        self.srtt = Some(match self.srtt {
+            Some(srtt) => srtt.mul_f64(0.875) + rtt.mul_f64(0.125),
+            None => rtt,
+        });
+        if self.cwnd < self.ssthresh {
+            self.cwnd += 1.0; // slow start
+        } else {
+            self.cwnd += 1.0 / self.cwnd; // congestion avoidance
+        }
+        self.cwnd = self.cwnd.min(self.max_window);
+    }
+
+    /// Halve the window after a failed stripe
+    pub fn on_loss(&mut self) {
+        self.ssthresh = (self.cwnd / 2.0).max(1.0);
+        self.cwnd = self.ssthresh;
+    }
+
+    /// Expected time until one more stripe queued on this path is delivered
+    pub fn expected_delivery(&self, in_flight: usize) -> Duration {
+        // Unmeasured paths are tried optimistically so they get a first sample
+        let srtt = self.srtt.unwrap_or(Duration::ZERO);
+        srtt.mul_f64(1.0 + in_flight as f64 / self.cwnd)
+    }
+}
+
+struct PathState {
+    transport: Arc<dyn Transport>,
+    target: TransportTarget,
+    window: CongestionWindow,
+    in_flight: usize,
+    consecutive_failures: u32,
+    failed: bool,
+    stripes: u64,
+    bytes: u64,
+}
+
+impl PathState {
+    fn has_room(&self) -> bool {
+        !self.failed && (self.in_flight as f64) < self.window.window().floor().max(1.0)
+    }
+}
+
+/// Per-path outcome of a multipath transfer
+#[derive(Debug, Clone)]
+pub struct PathReport {
+    pub transport: TransportType,
+    pub target: String,
+    pub stripes: u64,
+    pub bytes: u64,
+    pub srtt: Option<Duration>,
+    pub final_window: f64,
+    /// Whether the path was dropped after repeated failures
+    pub failed: bool,
+}
+
+/// Outcome of a multipath transfer
+#[derive(Debug, Clone)]
+pub struct MultipathReport {
+    pub stream_id: Uuid,
+    pub total_bytes: u64,
+    pub stripes: u64,
+    /// Stripes that had to be resent after a path failed them
+    pub retransmissions: u64,
+    pub elapsed: Duration,
+    pub paths: Vec<PathReport>,
+    /// Content hash of the data, as sent with the final stripe
+    pub content_hash: String,
+}
+
+impl MultipathReport {
+    /// Aggregate throughput in bytes per second
+    pub fn throughput_bps(&self) -> u64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 { (self.total_bytes as f64 / secs) as u64 } else { self.total_bytes }
+    }
+}
+
+/// Stripes one large transfer across several transports at once
+///
+/// Each path keeps its own congestion window; every stripe goes to the path
+/// expected to deliver it soonest, so faster paths carry proportionally more.
+/// Stripes a path fails are resent over the others.
+pub struct MultipathSender {
+    paths: Vec<(Arc<dyn Transport>, TransportTarget)>,
+    config: MultipathConfig,
+    content_hash: HashAlgorithm,
+}
+
+impl MultipathSender {
+    /// Create a sender with no paths
+    pub fn new(config: MultipathConfig) -> Self {
+        Self { paths: Vec::new(), config, content_hash: HashAlgorithm::Sha256 }
+    }
+
+    /// Hash the transfer with `algorithm`, e.g. the one negotiated with the
+    /// peer by [`PeerCapabilities::content_hash`](crate::transport::capability_probe::PeerCapabilities::content_hash)
+    pub fn with_content_hash(mut self, algorithm: HashAlgorithm) -> Self {
+        self.content_hash = algorithm;
+        self
+    }
+
+    /// Add a transport and the target address it reaches the peer at
+    pub fn add_path(mut self, transport: Arc<dyn Transport>, target: TransportTarget) -> Self {
+        self.paths.push((transport, target));
+        self
+    }
+
+    /// Number of configured paths
+    pub fn path_count(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Send `data` to `to_entity`, striped over every path
+    pub async fn send(&self, from_entity: &str, to_entity: &str, data: &[u8]) -> Result<MultipathReport> {
+        if self.paths.is_empty() {
+            return Err(SynapseError::NoTransportAvailable("Multipath transfer has no paths".to_string()));
+        }
+        if self.config.chunk_size == 0 {
+            return Err(SynapseError::ConfigurationError("Multipath chunk size must be non-zero".to_string()));
+        }
+
+        let start = Instant::now();
+        let stream_id = Uuid::new_v4();
+        let stripe_count = data.len().div_ceil(self.config.chunk_size) as u64;
+        let mut paths: Vec<PathState> = self.paths.iter().map(|(transport, target)| PathState {
+            transport: Arc::clone(transport),
+            target: target.clone(),
+            window: CongestionWindow::new(self.config.initial_window, self.config.max_window),
+            in_flight: 0,
+            consecutive_failures: 0,
+            failed: false,
+            stripes: 0,
+            bytes: 0,
+        }).collect();
+
+        let mut pending: VecDeque<u64> = (0..stripe_count).collect();
+        let mut in_flight = FuturesUnordered::new();
+        let mut retransmissions = 0;
+
+        info!("Striping {} bytes to {} over {} paths as stream {}", data.len(), to_entity, paths.len(), stream_id);
+        loop {
+            while let Some(&sequence) = pending.front() {
+                let Some(index) = Self::pick_path(&paths) else { break };
+                pending.pop_front();
+
+                let stripe = self.stripe(data, sequence);
+                let chunk = StreamChunk::new_data(
+                    stream_id,
+                    sequence,
+                    base64::engine::general_purpose::STANDARD.encode(stripe),
+                    StreamPriority::Background,
+                );
+                let message = Self::stripe_message(from_entity, to_entity, &chunk)?;
+                let path = &mut paths[index];
+                path.in_flight += 1;
+                let transport = Arc::clone(&path.transport);
+                let target = path.target.clone();
+                let len = stripe.len() as u64;
+                in_flight.push(async move {
+                    let sent_at = Instant::now();
+                    let result = transport.send_message(&target, &message).await;
+                    (index, sequence, len, result, sent_at.elapsed())
+                });
+            }
+
+            let Some((index, sequence, len, result, rtt)) = in_flight.next().await else {
+                if pending.is_empty() {
+                    break;
+                }
+                return Err(SynapseError::TransportError(format!(
+                    "All multipath paths to {} failed with {} stripes undelivered", to_entity, pending.len()
+                )));
+            };
+
+            let path = &mut paths[index];
+            path.in_flight -= 1;
+            match result {
+                Ok(_) => {
+                    path.window.on_ack(rtt);
+                    path.consecutive_failures = 0;
+                    path.stripes += 1;
+                    path.bytes += len;
+                }
+                Err(e) => {
+                    path.window.on_loss();
+                    path.consecutive_failures += 1;
+                    if path.consecutive_failures >= self.config.max_path_failures {
+                        warn!("Dropping {} path to {} from stream {}: {}", path.transport.transport_type(), path.target.identifier, stream_id, e);
+                        path.failed = true;
+                    } else {
+                        debug!("Stripe {} failed on {}: {}", sequence, path.transport.transport_type(), e);
+                    }
+                    retransmissions += 1;
+                    pending.push_front(sequence);
+                }
+            }
+        }
+
+        // Close the stream over the fastest surviving path
+        let closer = paths
+            .iter()
+            .filter(|p| !p.failed)
+            .min_by_key(|p| p.window.srtt().unwrap_or(Duration::MAX))
+            .ok_or_else(|| SynapseError::TransportError(format!("No path left to finish stream {}", stream_id)))?;
+        let content_hash = self.content_hash.hash(data);
+        let mut final_message = Self::stripe_message(from_entity, to_entity, &StreamChunk::new_final(stream_id, stripe_count))?;
+        final_message.metadata.insert(MULTIPATH_CONTENT_HASH_KEY.to_string(), content_hash.clone());
+        closer.transport.send_message(&closer.target, &final_message).await?;
+
+        let report = MultipathReport {
+            stream_id,
+            total_bytes: data.len() as u64,
+            stripes: stripe_count,
+            retransmissions,
+            elapsed: start.elapsed(),
+            paths: paths.iter().map(|p| PathReport {
+                transport: p.transport.transport_type(),
+                target: p.target.identifier.clone(),
+                stripes: p.stripes,
+                bytes: p.bytes,
+                srtt: p.window.srtt(),
+                final_window: p.window.window(),
+                failed: p.failed,
+            }).collect(),
+            content_hash,
+        };
+        info!("Multipath stream {} delivered at {} B/s", stream_id, report.throughput_bps());
+        Ok(report)
+    }
+
+    /// Path with window room that should deliver the next stripe soonest
+    fn pick_path(paths: &[PathState]) -> Option<usize> {
+        paths
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.has_room())
+            .min_by_key(|(_, p)| p.window.expected_delivery(p.in_flight))
+            .map(|(index, _)| index)
+    }
+
+    fn stripe<'a>(&self, data: &'a [u8], sequence: u64) -> &'a [u8] {
+        let start = sequence as usize * self.config.chunk_size;
+        let end = (start + self.config.chunk_size).min(data.len());
+        &data[start..end]
+    }
+
+    fn stripe_message(from_entity: &str, to_entity: &str, chunk: &StreamChunk) -> Result<SecureMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(MULTIPATH_STREAM_KEY.to_string(), chunk.stream_id.to_string());
+        Ok(SecureMessage {
+            message_id: UuidWrapper::new(Uuid::new_v4()),
+            to_global_id: to_entity.to_string(),
+            from_global_id: from_entity.to_string(),
+            encrypted_content: serde_json::to_vec(chunk)?,
+            signature: Vec::new(),
+            timestamp: DateTimeWrapper::new(Utc::now()),
+            security_level: SecurityLevel::Public,
+            routing_path: Vec::new(),
+            metadata,
+            attachments: Vec::new(),
+        })
+    }
+}
+
+/// Extract the stripe carried by a multipath message, if it is one
+pub fn stripe_from_message(message: &SecureMessage) -> Result<Option<StreamChunk>> {
+    if !message.metadata.contains_key(MULTIPATH_STREAM_KEY) {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&message.encrypted_content)?))
+}
+
+/// Content hash the sender put on a multipath stream's final stripe
+pub fn stripe_content_hash(message: &SecureMessage) -> Option<&str> {
+    message.metadata.get(MULTIPATH_CONTENT_HASH_KEY).map(String::as_str)
+}
+
+/// Puts stripes arriving over several paths back in order
+///
+/// Contiguous data is released as soon as it is available, so only stripes
+/// that arrived ahead of a gap are held in memory. Released data is hashed
+/// as it goes, for [`verify`](Self::verify) to check once the transfer is
+/// complete.
+pub struct StripeReassembler {
+    stream_id: Uuid,
+    next_sequence: u64,
+    buffered: BTreeMap<u64, Vec<u8>>,
+    max_buffered: usize,
+    total_stripes: Option<u64>,
+    hasher: Option<ContentHasher>,
+}
+
+impl StripeReassembler {
+    /// Create a reassembler holding at most `max_buffered` out-of-order stripes
+    pub fn new(stream_id: Uuid, max_buffered: usize) -> Self {
+        Self {
+            stream_id,
+            next_sequence: 0,
+            buffered: BTreeMap::new(),
+            max_buffered,
+            total_stripes: None,
+            hasher: Some(HashAlgorithm::Sha256.hasher()),
+        }
+    }
+
+    /// Hash released data with `algorithm`, the one negotiated with the sender
+    pub fn with_content_hash(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hasher = Some(algorithm.hasher());
+        self
+    }
+
+    /// Accept a stripe and return whatever data is now contiguous
+    pub fn push(&mut self, chunk: StreamChunk) -> Result<Vec<u8>> {
+        if chunk.stream_id != self.stream_id {
+            return Err(SynapseError::InvalidMessageFormat(format!(
+                "Stripe for stream {} pushed to reassembler for {}", chunk.stream_id, self.stream_id
+            )));
+        }
+        if chunk.is_final {
+            self.total_stripes = Some(chunk.sequence_number);
+            return Ok(Vec::new());
+        }
+        if chunk.sequence_number < self.next_sequence || self.buffered.contains_key(&chunk.sequence_number) {
+            return Ok(Vec::new()); // duplicate from a retransmission
+        }
+        if self.buffered.len() >= self.max_buffered {
+            return Err(SynapseError::TransportError(format!(
+                "Stream {} has more than {} stripes out of order", self.stream_id, self.max_buffered
+            )));
+        }
+
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(&chunk.data)
+            .map_err(|e| SynapseError::InvalidMessageFormat(e.to_string()))?;
+        self.buffered.insert(chunk.sequence_number, data);
+
+        let mut ready = Vec::new();
+        while let Some(data) = self.buffered.remove(&self.next_sequence) {
+            ready.extend_from_slice(&data);
+            self.next_sequence += 1;
+        }
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&ready);
+        }
+        Ok(ready)
+    }
+
+    /// Check the complete transfer against the sender's content hash
+    ///
+    /// Fails if stripes are still missing, the hash was already checked, or
+    /// the data does not match, e.g. because the sender hashed with a
+    /// different algorithm than this reassembler.
+    pub fn verify(&mut self, expected: &str) -> Result<()> {
+        if !self.is_complete() {
+            return Err(SynapseError::TransportError(format!("Stream {} is not complete", self.stream_id)));
+        }
+        let actual = self
+            .hasher
+            .take()
+            .ok_or_else(|| SynapseError::TransportError(format!("Stream {} was already verified", self.stream_id)))?
+            .finish();
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(SynapseError::InvalidMessageFormat(format!(
+                "Stream {} content hash mismatch: expected {}, got {}", self.stream_id, expected, actual
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether every stripe up to the final marker has been released
+    pub fn is_complete(&self) -> bool {
+        self.total_stripes == Some(self.next_sequence)
+    }
+
+    /// Stripes received ahead of a gap
+    pub fn buffered(&self) -> usize {
+        self.buffered.len()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -178,4 +662,44 @@ mod tests {
         assert_eq!(session.stream_id, "test-stream");
         assert_eq!(session.chunk_counter, 0);
     }
+
+    #[test]
+    fn congestion_window_grows_and_halves() {
+        let mut window = CongestionWindow::new(2.0, 8.0);
+        for _ in 0..10 {
+            window.on_ack(Duration::from_millis(20));
+        }
+        assert_eq!(window.window(), 8.0);
+
+        window.on_loss();
+        assert_eq!(window.window(), 4.0);
+        window.on_ack(Duration::from_millis(20));
+        assert_eq!(window.window(), 4.25); // congestion avoidance after a loss
+    }
+
+    #[test]
+    fn reassembler_releases_contiguous_data_in_order() {
+        let stream_id = Uuid::new_v4();
+        let encode = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+        let mut reassembler = StripeReassembler::new(stream_id, 8);
+
+        let late = StreamChunk::new_data(stream_id, 1, encode(b"world"), StreamPriority::Background);
+        assert!(reassembler.push(late.clone()).unwrap().is_empty());
+        assert_eq!(reassembler.buffered(), 1);
+
+        let first = StreamChunk::new_data(stream_id, 0, encode(b"hello "), StreamPriority::Background);
+        assert_eq!(reassembler.push(first).unwrap(), b"hello world".to_vec());
+        assert!(reassembler.push(late).unwrap().is_empty());
+
+        assert!(!reassembler.is_complete());
+        assert!(reassembler.verify(&HashAlgorithm::Sha256.hash(b"hello world")).is_err());
+        reassembler.push(StreamChunk::new_final(stream_id, 2)).unwrap();
+        assert!(reassembler.is_complete());
+        assert!(reassembler.verify(&HashAlgorithm::Blake3.hash(b"hello world")).is_err());
+
+        let mut blake = StripeReassembler::new(stream_id, 8).with_content_hash(HashAlgorithm::Blake3);
+        blake.push(StreamChunk::new_data(stream_id, 0, encode(b"hello world"), StreamPriority::Background)).unwrap();
+        blake.push(StreamChunk::new_final(stream_id, 1)).unwrap();
+        blake.verify(&HashAlgorithm::Blake3.hash(b"hello world")).unwrap();
+    }
 }
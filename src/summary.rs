@@ -0,0 +1,157 @@
+//! # Conversation Summaries
+//!
+//! Agents with a limited context window cannot replay a long conversation on
+//! every turn. An application registers a [`Summarizer`] (a local model, a
+//! hosted API, or another Synapse LLM peer it forwards the request to) and
+//! asks for a summary when it needs one. Summaries are rolling: each request
+//! carries the previous summary and only the messages recorded since, so a
+//! refresh costs the same however long the conversation has grown.
+//!
+//! Summaries are kept with the conversation history, saved and restored with
+//! it, and read back with [`ConversationHistory::summary`]. Edits and
+//! retractions made after a message was summarized are not folded back in.
+
+use crate::error::Result;
+use crate::history::{ConversationHistory, HistoryEntry};
+use crate::synapse::blockchain::serialization::DateTimeWrapper;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// Messages passed to a summarizer in one request unless it asks otherwise
+pub const DEFAULT_MAX_MESSAGES: usize = 200;
+
+/// Produces conversation summaries; provided by the application
+#[async_trait]
+pub trait Summarizer: Send + Sync {
+    /// Most messages passed in one request; longer backlogs are folded in
+    /// over several requests
+    fn max_messages(&self) -> usize {
+        DEFAULT_MAX_MESSAGES
+    }
+
+    /// Fold `request.messages` into `request.previous`, returning the new summary
+    async fn summarize(&self, request: &SummaryRequest) -> Result<String>;
+}
+
+/// What a summarizer is asked to fold together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummaryRequest {
+    /// The peer the conversation is with
+    pub peer: String,
+    /// Summary of everything before `messages`, if any
+    pub previous: Option<String>,
+    /// Messages recorded since the previous summary, oldest first
+    pub messages: Vec<HistoryEntry>,
+}
+
+impl SummaryRequest {
+    /// The messages as `sender: content` lines, ready to put in a prompt
+    pub fn transcript(&self) -> String {
+        self.messages
+            .iter()
+            .map(|entry| format!("{}: {}", entry.from, entry.content))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Rolling summary of the conversation with one peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub peer: String,
+    pub text: String,
+    /// Last message folded into the summary
+    pub last_message_id: String,
+    /// When that message was recorded
+    pub covers_until: DateTimeWrapper,
+    /// Messages folded in over the summary's lifetime
+    pub message_count: usize,
+    pub updated_at: DateTimeWrapper,
+}
+
+/// Bring the summary of the conversation with `peer` up to date
+///
+/// Returns the stored summary unchanged when nothing new was recorded, and
+/// `None` if there is nothing to summarize yet.
+pub async fn summarize(
+    history: &ConversationHistory,
+    summarizer: &dyn Summarizer,
+    peer: &str,
+) -> Result<Option<ConversationSummary>> {
+    let (mut summary, messages) = history.unsummarized(peer);
+    for batch in messages.chunks(summarizer.max_messages().max(1)) {
+        let request = SummaryRequest {
+            peer: peer.to_string(),
+            previous: summary.as_ref().map(|s| s.text.clone()),
+            messages: batch.to_vec(),
+        };
+        let text = summarizer.summarize(&request).await?;
+        let last = batch.last().expect("chunks are never empty");
+        let updated = ConversationSummary {
+            peer: peer.to_string(),
+            text,
+            last_message_id: last.message_id.clone(),
+            covers_until: last.recorded_at.clone(),
+            message_count: summary.as_ref().map_or(0, |s| s.message_count) + batch.len(),
+            updated_at: DateTimeWrapper::new(Utc::now()),
+        };
+        // Stored per batch so a failure later on keeps the progress made
+        history.set_summary(updated.clone());
+        summary = Some(updated);
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SimpleMessage;
+    use std::sync::Mutex;
+
+    /// Records the requests it gets and summarizes by counting messages
+    struct Counting {
+        requests: Mutex<Vec<SummaryRequest>>,
+    }
+
+    #[async_trait]
+    impl Summarizer for Counting {
+        fn max_messages(&self) -> usize {
+            2
+        }
+
+        async fn summarize(&self, request: &SummaryRequest) -> Result<String> {
+            self.requests.lock().unwrap().push(request.clone());
+            let before: usize = request.previous.as_deref().map_or(0, |p| p.parse().unwrap());
+            Ok((before + request.messages.len()).to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn summaries_roll_forward_over_new_messages() {
+        let history = ConversationHistory::default();
+        let summarizer = Counting { requests: Mutex::new(Vec::new()) };
+        assert!(summarize(&history, &summarizer, "bob").await.unwrap().is_none());
+
+        for content in ["hi", "hello", "how are you?"] {
+            history.record(&mut SimpleMessage::new("bob", "alice", content));
+        }
+        history.record(&mut SimpleMessage::new("carol", "alice", "unrelated"));
+        let summary = summarize(&history, &summarizer, "bob").await.unwrap().unwrap();
+        assert_eq!((summary.text.as_str(), summary.message_count), ("3", 3));
+        assert_eq!(summarizer.requests.lock().unwrap().len(), 2);
+        assert_eq!(summarizer.requests.lock().unwrap()[1].transcript(), "alice: how are you?");
+
+        // Nothing new: no request, same summary
+        summarize(&history, &summarizer, "bob").await.unwrap();
+        assert_eq!(summarizer.requests.lock().unwrap().len(), 2);
+
+        history.record(&mut SimpleMessage::new("alice", "bob", "fine, thanks"));
+        let summary = summarize(&history, &summarizer, "bob").await.unwrap().unwrap();
+        assert_eq!(summary.text, "4");
+        let last = summarizer.requests.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(last.previous.as_deref(), Some("3"));
+        assert_eq!(last.messages.len(), 1);
+        assert_eq!(history.summary("bob").unwrap().text, "4");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::synapse::services::DiscoveryService;
+use crate::synapse::services::{DiscoveryService, SearchFilters};
 use crate::synapse::models::ParticipantProfile;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
@@ -22,11 +22,27 @@ pub struct DiscoveryFilters {
     pub entity_types: Option<Vec<String>>,
     pub organizations: Option<Vec<String>>,
     pub capabilities: Option<Vec<String>>,
+    #[serde(default)]
+    pub regions: Option<Vec<String>>,
     pub min_trust_score: Option<f64>,
     pub max_distance: Option<f64>,
     pub discoverability_levels: Option<Vec<String>>,
 }
 
+impl From<DiscoveryFilters> for SearchFilters {
+    fn from(filters: DiscoveryFilters) -> Self {
+        SearchFilters {
+            entity_types: filters.entity_types.unwrap_or_default(),
+            organizations: filters.organizations.unwrap_or_default(),
+            domains: Vec::new(),
+            capabilities: filters.capabilities.unwrap_or_default(),
+            regions: filters.regions.unwrap_or_default(),
+            min_trust_score: filters.min_trust_score,
+            experts_only: false,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProximityDiscoveryRequest {
     pub max_distance: f64, // Degrees of separation in trust network
@@ -153,6 +169,54 @@ impl DiscoveryAPI {
         })
     }
 
+    /// Natural-language participant search
+    ///
+    /// `query` is free text such as "the rust compiler expert at example.com";
+    /// `filters` narrow the results further.
+    pub async fn search(
+        &self,
+        requester_id: &str,
+        query: &str,
+        filters: Option<DiscoveryFilters>,
+        max_results: Option<usize>,
+    ) -> Result<APIResponse<Vec<DiscoveryResult>>> {
+        debug!("Natural-language search from {}: {}", requester_id, query);
+
+        if query.trim().is_empty() {
+            return Ok(APIResponse {
+                success: false,
+                data: None,
+                error: Some("Query cannot be empty".to_string()),
+                message: None,
+            });
+        }
+
+        let max_results = max_results.unwrap_or(10).min(100);
+        let filters = filters.map(SearchFilters::from).unwrap_or_default();
+
+        let results: Vec<DiscoveryResult> = self.discovery
+            .search(query, &filters, requester_id, max_results)
+            .into_iter()
+            .map(|hit| {
+                let mut result = self.profile_to_discovery_result(&hit.profile, None);
+                if !hit.matched_terms.is_empty() {
+                    result.match_reason = Some(format!("Matched: {}", hit.matched_terms.join(", ")));
+                }
+                result
+            })
+            .collect();
+
+        let results_count = results.len();
+        info!("Search found {} results for query: {}", results_count, query);
+
+        Ok(APIResponse {
+            success: true,
+            data: Some(results),
+            error: None,
+            message: Some(format!("Found {} participants", results_count)),
+        })
+    }
+
     /// Discover participants by proximity in trust network
     pub async fn discover_by_proximity(
         &self,
@@ -4,6 +4,7 @@ pub mod participant_api;
 pub mod trust_api;
 pub mod discovery_api;
 pub mod errors;
+pub mod rate_limit;
 
 // Re-export API handlers
 pub use participant_api::ParticipantAPI;
@@ -12,3 +13,4 @@ pub use discovery_api::DiscoveryAPI;
 
 // Re-export error types for convenience
 pub use errors::{ApiError, ApiResponse, ApiErrorResponse};
+pub use rate_limit::{AccessKind, ApiRateLimiter, Caller, RateLimitConfig, RateLimitStatus};
@@ -0,0 +1,335 @@
+//! Per-caller rate limiting for the public APIs
+//!
+//! Every caller — an API key, or an authenticated participant when no key is
+//! presented — gets two token buckets, one for reads and one for writes, so a
+//! client polling trust scores cannot starve its own report submissions. The
+//! HTTP or gRPC front end asks the [`ApiRateLimiter`] before dispatching a
+//! request, copies [`RateLimitStatus::headers`] onto the response and answers
+//! `429` with [`RateLimitStatus::error_response`] when the caller is over
+//! quota. Every refusal is recorded in error telemetry.
+
+use crate::synapse::api::errors::{ApiError, ApiResponse};
+use crate::synapse::telemetry::{ErrorSeverity, ErrorSource, ErrorTelemetry};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::warn;
+
+pub const LIMIT_HEADER: &str = "X-RateLimit-Limit";
+pub const REMAINING_HEADER: &str = "X-RateLimit-Remaining";
+pub const RESET_HEADER: &str = "X-RateLimit-Reset";
+pub const RETRY_AFTER_HEADER: &str = "Retry-After";
+
+/// Requests a caller may make
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateQuota {
+    /// Sustained rate
+    pub requests_per_minute: u32,
+    /// Requests that may be made back to back after a quiet period
+    pub burst: u32,
+}
+
+/// Read and write quotas for one caller
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CallerQuota {
+    pub read: RateQuota,
+    pub write: RateQuota,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Quotas for callers without an override
+    pub default: CallerQuota,
+    /// Quotas for specific callers, keyed by [`Caller::key`]
+    pub overrides: HashMap<String, CallerQuota>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default: CallerQuota {
+                read: RateQuota { requests_per_minute: 600, burst: 100 },
+                write: RateQuota { requests_per_minute: 60, burst: 20 },
+            },
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+/// Who a request is charged to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Caller {
+    ApiKey(String),
+    Participant(String),
+}
+
+impl Caller {
+    /// Charge to the API key if one was presented, otherwise the participant
+    pub fn identify(api_key: Option<&str>, participant_id: Option<&str>) -> Option<Self> {
+        api_key
+            .map(|key| Caller::ApiKey(key.to_string()))
+            .or_else(|| participant_id.map(|id| Caller::Participant(id.to_string())))
+    }
+
+    /// Bucket and override key, e.g. `participant:alice@example.com`
+    pub fn key(&self) -> String {
+        match self {
+            Caller::ApiKey(key) => format!("key:{}", key),
+            Caller::Participant(id) => format!("participant:{}", id),
+        }
+    }
+}
+
+/// Which quota a request draws on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+impl AccessKind {
+    /// Safe HTTP methods are reads; everything else is a write
+    pub fn for_http_method(method: &str) -> Self {
+        match method.to_ascii_uppercase().as_str() {
+            "GET" | "HEAD" | "OPTIONS" => AccessKind::Read,
+            _ => AccessKind::Write,
+        }
+    }
+}
+
+/// Outcome of a rate limit check, with what the caller should be told
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub kind: AccessKind,
+    /// Bucket capacity
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_secs: u64,
+    /// Seconds until the next request would be allowed, when refused
+    pub retry_after_secs: Option<u64>,
+}
+
+impl RateLimitStatus {
+    fn unlimited(kind: AccessKind) -> Self {
+        Self { allowed: true, kind, limit: u32::MAX, remaining: u32::MAX, reset_secs: 0, retry_after_secs: None }
+    }
+
+    /// Standard rate limit response headers
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        if self.limit == u32::MAX {
+            return Vec::new();
+        }
+        let mut headers = vec![
+            (LIMIT_HEADER, self.limit.to_string()),
+            (REMAINING_HEADER, self.remaining.to_string()),
+            (RESET_HEADER, self.reset_secs.to_string()),
+        ];
+        if let Some(retry_after) = self.retry_after_secs {
+            headers.push((RETRY_AFTER_HEADER, retry_after.to_string()));
+        }
+        headers
+    }
+
+    /// `429` body for a refused request
+    pub fn error_response<T>(&self) -> ApiResponse<T> {
+        ApiResponse::error_with_details(
+            ApiError::RateLimited,
+            serde_json::json!({
+                "kind": self.kind,
+                "limit": self.limit,
+                "retry_after_secs": self.retry_after_secs,
+            }),
+        )
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(quota: RateQuota) -> Self {
+        Self {
+            tokens: f64::from(quota.burst),
+            capacity: f64::from(quota.burst),
+            per_second: f64::from(quota.requests_per_minute) / 60.0,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * self.per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    fn seconds_until(&self, tokens: f64) -> u64 {
+        if self.tokens >= tokens {
+            0
+        } else if self.per_second > 0.0 {
+            ((tokens - self.tokens) / self.per_second).ceil() as u64
+        } else {
+            u64::MAX
+        }
+    }
+
+    fn take(&mut self, kind: AccessKind) -> RateLimitStatus {
+        self.refill();
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+        RateLimitStatus {
+            allowed,
+            kind,
+            limit: self.capacity as u32,
+            remaining: self.tokens.floor() as u32,
+            reset_secs: self.seconds_until(self.capacity),
+            retry_after_secs: (!allowed).then(|| self.seconds_until(1.0)),
+        }
+    }
+}
+
+/// Token buckets per caller and access kind
+pub struct ApiRateLimiter {
+    config: RateLimitConfig,
+    buckets: DashMap<(String, AccessKind), Bucket>,
+    refused: DashMap<String, u64>,
+    telemetry: Option<Arc<ErrorTelemetry>>,
+}
+
+impl ApiRateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: DashMap::new(),
+            refused: DashMap::new(),
+            telemetry: None,
+        }
+    }
+
+    /// Report refused requests to `telemetry`
+    pub fn with_telemetry(mut self, telemetry: Arc<ErrorTelemetry>) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    /// Settings in use
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Charge one request to `caller`
+    pub fn check(&self, caller: &Caller, kind: AccessKind) -> RateLimitStatus {
+        if !self.config.enabled {
+            return RateLimitStatus::unlimited(kind);
+        }
+        let key = caller.key();
+        let quotas = self.config.overrides.get(&key).unwrap_or(&self.config.default);
+        let quota = match kind {
+            AccessKind::Read => quotas.read,
+            AccessKind::Write => quotas.write,
+        };
+        let status = self
+            .buckets
+            .entry((key.clone(), kind))
+            .or_insert_with(|| Bucket::new(quota))
+            .take(kind);
+        if !status.allowed {
+            self.record_refusal(&key, &status);
+        }
+        status
+    }
+
+    /// Charge one request, failing with [`ApiError::RateLimited`] when over quota
+    pub fn enforce(&self, caller: &Caller, kind: AccessKind) -> Result<RateLimitStatus, ApiError> {
+        let status = self.check(caller, kind);
+        if status.allowed { Ok(status) } else { Err(ApiError::RateLimited) }
+    }
+
+    /// Requests refused per caller since startup
+    pub fn refusals(&self) -> HashMap<String, u64> {
+        self.refused.iter().map(|entry| (entry.key().clone(), *entry.value())).collect()
+    }
+
+    /// Drop a caller's buckets and counters
+    pub fn forget(&self, caller: &Caller) {
+        let key = caller.key();
+        self.buckets.retain(|(bucket_key, _), _| *bucket_key != key);
+        self.refused.remove(&key);
+    }
+
+    fn record_refusal(&self, key: &str, status: &RateLimitStatus) {
+        let count = {
+            let mut refused = self.refused.entry(key.to_string()).or_insert(0);
+            *refused += 1;
+            *refused
+        };
+        warn!("Rate limit exceeded for {} ({:?}, refusal {})", key, status.kind, count);
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.report_error(
+                ErrorSource::Api,
+                ErrorSeverity::Warning,
+                &ApiError::RateLimited.to_string(),
+                Some("RATE_LIMIT_EXCEEDED"),
+                Some(HashMap::from([
+                    ("caller".to_string(), key.to_string()),
+                    ("kind".to_string(), format!("{:?}", status.kind)),
+                    ("limit".to_string(), status.limit.to_string()),
+                    ("refusals".to_string(), count.to_string()),
+                ])),
+                None,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_separate_read_and_write_quotas() {
+        let config = RateLimitConfig {
+            default: CallerQuota {
+                read: RateQuota { requests_per_minute: 60, burst: 3 },
+                write: RateQuota { requests_per_minute: 1, burst: 1 },
+            },
+            ..RateLimitConfig::default()
+        };
+        let telemetry = Arc::new(ErrorTelemetry::default());
+        let limiter = ApiRateLimiter::new(config).with_telemetry(telemetry.clone());
+        let alice = Caller::identify(None, Some("alice@example.com")).unwrap();
+
+        let write = limiter.check(&alice, AccessKind::Write);
+        assert!(write.allowed);
+        assert_eq!(write.remaining, 0);
+        let refused = limiter.check(&alice, AccessKind::Write);
+        assert!(!refused.allowed);
+        assert!(refused.headers().iter().any(|(name, _)| *name == RETRY_AFTER_HEADER));
+        assert!(limiter.enforce(&alice, AccessKind::Write).is_err());
+
+        // Reads have their own bucket
+        let read = limiter.check(&alice, AccessKind::Read);
+        assert!(read.allowed);
+        assert_eq!(read.headers()[0], (LIMIT_HEADER, "3".to_string()));
+
+        // Other callers are unaffected
+        let bob = Caller::identify(Some("bob-key"), Some("bob@example.com")).unwrap();
+        assert!(limiter.check(&bob, AccessKind::Write).allowed);
+
+        assert_eq!(limiter.refusals().get("participant:alice@example.com"), Some(&2));
+        assert_eq!(telemetry.get_error_counts().get(&ErrorSource::Api), Some(&2));
+    }
+}
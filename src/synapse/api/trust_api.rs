@@ -1,18 +1,20 @@
-use crate::synapse::services::TrustManager;
-use crate::synapse::blockchain::SynapseBlockchain;
+use crate::synapse::services::{DecayStatus, TrustManager};
+use crate::synapse::services::disputes::{Dispute, DisputeEvidenceContent, DisputeService, Verdict};
+use crate::synapse::blockchain::{EvidenceSource, ReportEvidence, SynapseBlockchain};
 use crate::synapse::models::trust::TrustCategory;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use tracing::{info, debug};
 use uuid::Uuid;
 use chrono::Utc;
+use std::sync::Arc;
 use crate::synapse::blockchain::serialization::UuidWrapper;
 
 /// HTTP API for trust system operations
 pub struct TrustAPI {
     trust_manager: TrustManager,
-    #[allow(dead_code)]
     blockchain: SynapseBlockchain,
+    disputes: Option<Arc<DisputeService>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,6 +27,23 @@ pub struct SubmitTrustReportRequest {
     pub stake_amount: u32,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenDisputeRequest {
+    pub report_id: String,
+    pub reason: String,
+    pub evidence: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisputeEvidenceRequest {
+    pub evidence: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisputeVoteRequest {
+    pub verdict: Verdict,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StakeRequest {
     pub amount: u32,
@@ -89,9 +108,16 @@ impl TrustAPI {
         Self {
             trust_manager,
             blockchain,
+            disputes: None,
         }
     }
 
+    /// Serve the dispute endpoints from `disputes`
+    pub fn with_disputes(mut self, disputes: Arc<DisputeService>) -> Self {
+        self.disputes = Some(disputes);
+        self
+    }
+
     /// Submit a trust report about another participant
     pub async fn submit_trust_report(
         &self,
@@ -375,6 +401,124 @@ impl TrustAPI {
         })
     }
 
+    /// Evidence behind a trust report, for dispute resolution
+    ///
+    /// Evidence this node doesn't hold is fetched through `source`.
+    pub async fn get_report_evidence(
+        &self,
+        report_id: &str,
+        source: Option<&dyn EvidenceSource>,
+    ) -> Result<APIResponse<ReportEvidence>> {
+        debug!("Getting evidence for trust report {}", report_id);
+
+        match self.blockchain.report_evidence(report_id, source).await {
+            Ok(Some(evidence)) => Ok(APIResponse {
+                success: true,
+                data: Some(evidence),
+                error: None,
+                message: None,
+            }),
+            Ok(None) => Ok(APIResponse {
+                success: false,
+                data: None,
+                error: Some("No evidence found for this report".to_string()),
+                message: None,
+            }),
+            Err(e) => Ok(APIResponse {
+                success: false,
+                data: None,
+                error: Some(e.to_string()),
+                message: None,
+            }),
+        }
+    }
+
+    /// Dispute a negative report about the requester
+    pub async fn open_dispute(&self, subject_id: &str, request: OpenDisputeRequest) -> Result<APIResponse<Dispute>> {
+        debug!("{} disputing trust report {}", subject_id, request.report_id);
+        let Some(disputes) = &self.disputes else {
+            return Ok(disputes_unavailable());
+        };
+        let result = disputes
+            .open_dispute(subject_id, &request.report_id, &request.reason, request.evidence)
+            .await;
+        Ok(dispute_response(result, "Dispute opened"))
+    }
+
+    /// Attach evidence to a dispute the requester is a party to
+    pub async fn submit_dispute_evidence(
+        &self,
+        dispute_id: &str,
+        party: &str,
+        request: DisputeEvidenceRequest,
+    ) -> Result<APIResponse<Dispute>> {
+        let Some(disputes) = &self.disputes else {
+            return Ok(disputes_unavailable());
+        };
+        let result = disputes.submit_evidence(dispute_id, party, &request.evidence).await;
+        Ok(dispute_response(result, "Evidence attached"))
+    }
+
+    /// Cast an arbiter's vote on a dispute
+    pub async fn vote_on_dispute(
+        &self,
+        dispute_id: &str,
+        arbiter: &str,
+        request: DisputeVoteRequest,
+    ) -> Result<APIResponse<Dispute>> {
+        let Some(disputes) = &self.disputes else {
+            return Ok(disputes_unavailable());
+        };
+        let result = disputes.cast_vote(dispute_id, arbiter, request.verdict).await;
+        if let Ok(dispute) = &result {
+            info!("{} voted {:?} on dispute {} ({:?})", arbiter, request.verdict, dispute_id, dispute.status);
+        }
+        Ok(dispute_response(result, "Vote recorded"))
+    }
+
+    /// Get a dispute; only its parties and arbiters may see it
+    pub async fn get_dispute(&self, dispute_id: &str, requester_id: &str) -> Result<APIResponse<Dispute>> {
+        let Some(disputes) = &self.disputes else {
+            return Ok(disputes_unavailable());
+        };
+        let result = disputes
+            .get_dispute(dispute_id)
+            .filter(|d| d.is_party(requester_id) || d.arbiters.iter().any(|a| a == requester_id))
+            .ok_or_else(|| anyhow::anyhow!("Dispute not found"));
+        Ok(dispute_response(result, "Dispute found"))
+    }
+
+    /// Evidence from both sides of a dispute, for its parties and arbiters
+    pub async fn get_dispute_evidence(
+        &self,
+        dispute_id: &str,
+        requester_id: &str,
+        source: Option<&dyn EvidenceSource>,
+    ) -> Result<APIResponse<Vec<DisputeEvidenceContent>>> {
+        let Some(disputes) = &self.disputes else {
+            return Ok(disputes_unavailable());
+        };
+        let visible = disputes
+            .get_dispute(dispute_id)
+            .is_some_and(|d| d.is_party(requester_id) || d.arbiters.iter().any(|a| a == requester_id));
+        let result = if visible {
+            disputes.evidence(dispute_id, source).await
+        } else {
+            Err(anyhow::anyhow!("Dispute not found"))
+        };
+        Ok(dispute_response(result, "Evidence retrieved"))
+    }
+
+    /// Disputes the requester is a party to or arbitrates
+    pub async fn list_disputes(&self, participant_id: &str) -> Result<APIResponse<Vec<Dispute>>> {
+        let Some(disputes) = &self.disputes else {
+            return Ok(disputes_unavailable());
+        };
+        let list = disputes.list_disputes(participant_id);
+        let message = format!("Retrieved {} disputes", list.len());
+        Ok(dispute_response(Ok(list), &message))
+    }
+
     /// Get trust network analysis
     pub async fn get_trust_network_analysis(
         &self,
@@ -404,6 +548,35 @@ impl TrustAPI {
         })
     }
 
+    /// Admin: when trust decay last ran and when it runs next
+    pub async fn get_decay_status(&self) -> Result<APIResponse<DecayStatus>> {
+        debug!("Getting trust decay status");
+
+        let status = self.trust_manager.decay_status().await?;
+        let message = status.overdue.then(|| "Decay run is overdue".to_string());
+
+        Ok(APIResponse {
+            success: true,
+            data: Some(status),
+            error: None,
+            message,
+        })
+    }
+
+    /// Admin: run trust decay now instead of waiting for the schedule
+    pub async fn run_decay(&self) -> Result<APIResponse<Vec<String>>> {
+        let processed = self.trust_manager.process_decay().await?;
+        info!("Manual trust decay run processed {} participants", processed.len());
+        let message = format!("Decayed {} balances", processed.len());
+
+        Ok(APIResponse {
+            success: true,
+            data: Some(processed),
+            error: None,
+            message: Some(message),
+        })
+    }
+
     /// Get trust system statistics
     pub async fn get_trust_statistics(&self) -> Result<APIResponse<TrustStatistics>> {
         debug!("Getting trust system statistics");
@@ -450,3 +623,29 @@ pub struct TrustStatistics {
     pub active_validators: u32,
     pub pending_reports: u64,
 }
+
+fn disputes_unavailable<T>() -> APIResponse<T> {
+    APIResponse {
+        success: false,
+        data: None,
+        error: Some("Dispute resolution is not enabled".to_string()),
+        message: None,
+    }
+}
+
+fn dispute_response<T>(result: Result<T>, message: &str) -> APIResponse<T> {
+    match result {
+        Ok(data) => APIResponse {
+            success: true,
+            data: Some(data),
+            error: None,
+            message: Some(message.to_string()),
+        },
+        Err(e) => APIResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+            message: None,
+        },
+    }
+}
@@ -161,6 +161,8 @@ impl AuthApi {
             relationships: Vec::new(),
             topic_subscriptions: Vec::new(),
             organizational_context: None,
+            attachments: Vec::new(),
+            region: None,
             public_key: None,
             supported_protocols: Vec::new(),
             last_seen: Utc::now(),
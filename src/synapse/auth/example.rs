@@ -59,7 +59,7 @@ pub async fn setup_enhanced_auth_example() -> Result<()> {
     auth_config.oauth_providers.push(crate::synapse::auth::OAuthProviderConfig {
         provider_name: "google".to_string(),
         client_id: "your-client-id".to_string(),
-        client_secret: "your-client-secret".to_string(),
+        client_secret: "your-client-secret".into(),
         redirect_uri: "https://your-app.com/oauth/callback".to_string(),
         scopes: vec!["email".to_string(), "profile".to_string()],
         verification_level: VerificationLevel::Enhanced,
@@ -99,6 +99,8 @@ pub async fn setup_enhanced_auth_example() -> Result<()> {
         relationships: Vec::new(),
         topic_subscriptions: Vec::new(),
         organizational_context: None,
+        attachments: Vec::new(),
+        region: None,
         public_key: None,
         supported_protocols: Vec::new(),
         last_seen: chrono::Utc::now(),
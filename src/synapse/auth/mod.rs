@@ -34,6 +34,7 @@ use crate::synapse::models::{
 };
 use crate::synapse::services::registry::ParticipantRegistry;
 use crate::blockchain::serialization::DateTimeWrapper;
+use crate::redaction::Secret;
 
 /// Configuration for Synapse authentication
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,7 +86,7 @@ impl Default for SynapseAuthConfig {
 pub struct OAuthProviderConfig {
     pub provider_name: String,
     pub client_id: String,
-    pub client_secret: String,
+    pub client_secret: Secret<String>,
     pub redirect_uri: String,
     pub scopes: Vec<String>,
     pub verification_level: VerificationLevel,
@@ -1,6 +1,7 @@
 // Authentication utilities for Synapse
 // Provides WebCrypto integration, key management, and cryptographic helpers
 
+use crate::types::WebPublicKeys;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -258,10 +259,24 @@ impl SynapseKeyManager {
     }
 }
 
+/// DER prefix of a P-256 `SubjectPublicKeyInfo`; the uncompressed point follows
+const P256_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86, 0x48,
+    0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
 /// WebCrypto integration for browser environments
+///
+/// Browser nodes generate their keys inside the Web Crypto API (see
+/// `wasm::crypto`) and publish them as [`WebPublicKeys`]. This side keeps
+/// those keys per entity so signatures made in the browser (ECDSA P-256,
+/// raw `r || s`) can be checked natively.
 pub struct WebCryptoIntegration {
     /// Key manager
     key_manager: SynapseKeyManager,
+
+    /// Public keys of browser nodes, by entity ID
+    browser_keys: HashMap<String, WebPublicKeys>,
 }
 
 impl WebCryptoIntegration {
@@ -269,48 +284,100 @@ impl WebCryptoIntegration {
     pub async fn new() -> anyhow::Result<Self> {
         let key_manager = SynapseKeyManager::new().await?;
         
-        Ok(Self { key_manager })
+        Ok(Self { key_manager, browser_keys: HashMap::new() })
     }
-    
-    /// Generate a key pair in the browser
-    pub async fn generate_browser_keypair(
-        &self,
-        key_id: &str,
-    ) -> anyhow::Result<JsValue> {
-        // In a real implementation, this would use wasm-bindgen to call WebCrypto API
-        // For this example, we'll simulate it
-        Ok(JsValue::default())
+
+    /// Key manager for native keys
+    pub fn key_manager(&self) -> &SynapseKeyManager {
+        &self.key_manager
     }
     
-    /// Import a key from WebCrypto format
-    pub async fn import_key_from_webcrypto(
+    /// Record the public keys a browser node published
+    pub fn import_key_from_webcrypto(
         &mut self,
-        key_data: JsValue,
-        key_id: &str,
+        entity_id: &str,
+        keys: WebPublicKeys,
     ) -> anyhow::Result<()> {
-        // In a real implementation, this would convert WebCrypto key to auth-framework format
-        // For this example, we'll simulate it
+        if p256_point(&keys.signing).is_none() {
+            return Err(anyhow::anyhow!("Signing key of {} is not a P-256 SPKI key", entity_id));
+        }
+        if keys.encryption.is_empty() {
+            return Err(anyhow::anyhow!("Encryption key of {} is empty", entity_id));
+        }
+        self.browser_keys.insert(entity_id.to_string(), keys);
         Ok(())
     }
     
-    /// Export a key to WebCrypto format
-    pub async fn export_key_to_webcrypto(
+    /// Public keys recorded for a browser node
+    pub fn export_key_to_webcrypto(&self, entity_id: &str) -> Option<&WebPublicKeys> {
+        self.browser_keys.get(entity_id)
+    }
+
+    /// Forget a browser node's keys
+    pub fn remove_browser_keys(&mut self, entity_id: &str) -> bool {
+        self.browser_keys.remove(entity_id).is_some()
+    }
+
+    /// Verify a signature a browser node made over `data`
+    #[cfg(feature = "crypto")]
+    pub fn verify_browser_signature(
         &self,
-        key_id: &str,
-    ) -> anyhow::Result<JsValue> {
-        // In a real implementation, this would convert auth-framework key to WebCrypto format
-        // For this example, we'll simulate it
-        Ok(JsValue::default())
+        entity_id: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> anyhow::Result<bool> {
+        use ring::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey};
+
+        let keys = self
+            .browser_keys
+            .get(entity_id)
+            .ok_or_else(|| anyhow::anyhow!("No browser keys for {}", entity_id))?;
+        let point = p256_point(&keys.signing)
+            .ok_or_else(|| anyhow::anyhow!("Signing key of {} is not a P-256 SPKI key", entity_id))?;
+        Ok(UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point).verify(data, signature).is_ok())
+    }
+
+    /// Verify a signature a browser node made over `data`
+    #[cfg(not(feature = "crypto"))]
+    pub fn verify_browser_signature(
+        &self,
+        _entity_id: &str,
+        _data: &[u8],
+        _signature: &[u8],
+    ) -> anyhow::Result<bool> {
+        Err(anyhow::anyhow!("Browser signature verification requires the crypto feature"))
     }
 }
 
-/// Simulated JsValue for example purposes
-/// In a real implementation, this would be from wasm-bindgen
-#[derive(Debug, Clone)]
-pub struct JsValue;
+/// The uncompressed EC point inside a P-256 SPKI key
+fn p256_point(spki: &[u8]) -> Option<&[u8]> {
+    spki.strip_prefix(P256_SPKI_PREFIX).filter(|point| point.len() == 65 && point[0] == 0x04)
+}
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair as _};
+
+    #[tokio::test]
+    async fn test_browser_signatures_verify() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let signing = [P256_SPKI_PREFIX, key.public_key().as_ref()].concat();
+
+        let mut integration = WebCryptoIntegration::new().await.unwrap();
+        assert!(integration
+            .import_key_from_webcrypto("tab@wasm.synapse.local", WebPublicKeys { encryption: vec![1], signing: vec![2] })
+            .is_err());
+        integration
+            .import_key_from_webcrypto("tab@wasm.synapse.local", WebPublicKeys { encryption: vec![1], signing })
+            .unwrap();
 
-impl JsValue {
-    pub fn default() -> Self {
-        Self
+        let signature = key.sign(&rng, b"hello").unwrap();
+        assert!(integration.verify_browser_signature("tab@wasm.synapse.local", b"hello", signature.as_ref()).unwrap());
+        assert!(!integration.verify_browser_signature("tab@wasm.synapse.local", b"hullo", signature.as_ref()).unwrap());
+        assert!(integration.verify_browser_signature("other@wasm.synapse.local", b"hello", signature.as_ref()).is_err());
     }
 }
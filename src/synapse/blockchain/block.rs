@@ -1,20 +1,18 @@
 // Synapse Blockchain Block Structure
 
-use crate::synapse::blockchain::serialization::DateTimeWrapper;
-use crate::synapse::blockchain::serialization::UuidWrapper;
+use crate::synapse::blockchain::serialization::{self, timestamp};
 use serde::{Deserialize, Serialize};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use sha2::{Sha256, Digest};
-use bincode::{Encode, Decode};
 
 /// A block in the Synapse blockchain
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub struct Block {
     pub number: u64,
-    pub timestamp: DateTimeWrapper,
+    #[serde(with = "timestamp")]
+    pub timestamp: DateTime<Utc>,
     pub previous_hash: String,
     pub hash: String,
     pub transactions: Vec<Transaction>,
@@ -27,7 +25,7 @@ impl Block {
     pub fn genesis() -> Self {
         let mut block = Self {
             number: 0,
-            timestamp: DateTimeWrapper::new(Utc::now()),
+            timestamp: Utc::now(),
             previous_hash: "0".repeat(64),
             hash: String::new(),
             transactions: vec![],
@@ -48,7 +46,7 @@ impl Block {
     ) -> Self {
         let mut block = Self {
             number,
-            timestamp: DateTimeWrapper::new(Utc::now()),
+            timestamp: Utc::now(),
             previous_hash,
             hash: String::new(),
             transactions,
@@ -60,19 +58,40 @@ impl Block {
     }
     
     /// Calculate block hash
+    ///
+    /// Transactions are covered through their merkle root, so a header alone
+    /// is enough to check the hash.
     pub fn calculate_hash(&self) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(self.number.to_be_bytes());
-        hasher.update(self.timestamp.0.timestamp().to_be_bytes());
-        hasher.update(&self.previous_hash);
-        hasher.update(self.nonce.to_be_bytes());
-        hasher.update(&self.validator);
-        
-        for transaction in &self.transactions {
-            hasher.update(transaction.hash());
+        self.header().calculate_hash()
+    }
+    
+    /// Header of this block, as tracked by light clients
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            number: self.number,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash.clone(),
+            hash: self.hash.clone(),
+            merkle_root: merkle_root(&self.transactions),
+            nonce: self.nonce,
+            validator: self.validator.clone(),
         }
-        
-        format!("{:x}", hasher.finalize())
+    }
+    
+    /// Proof that the transaction at `index` is part of this block
+    pub fn prove(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.transactions.len() {
+            return None;
+        }
+        let mut level: Vec<String> = self.transactions.iter().map(merkle_leaf).collect();
+        let mut position = index;
+        let mut siblings = Vec::new();
+        while level.len() > 1 {
+            siblings.push(level.get(position ^ 1).unwrap_or(&level[position]).clone());
+            level = merkle_parents(&level);
+            position /= 2;
+        }
+        Some(MerkleProof { block_number: self.number, index, siblings })
     }
     
     /// Verify block integrity
@@ -103,18 +122,121 @@ impl Block {
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
-        bincode::encode_to_vec(self, bincode::config::standard())
+        serialization::to_canonical_bytes(self)
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::error::DecodeError> {
-        bincode::decode_from_slice(bytes, bincode::config::standard()).map(|r| r.0)
+        serialization::from_canonical_bytes(bytes)
     }
 }
 
+/// A block without its transactions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub number: u64,
+    #[serde(with = "timestamp")]
+    pub timestamp: DateTime<Utc>,
+    pub previous_hash: String,
+    pub hash: String,
+    /// Root of the merkle tree over the block's transactions
+    pub merkle_root: String,
+    pub nonce: u64,
+    pub validator: String,
+}
+
+impl BlockHeader {
+    /// Calculate the block hash from the header fields
+    pub fn calculate_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.number.to_be_bytes());
+        hasher.update(self.timestamp.timestamp().to_be_bytes());
+        hasher.update(&self.previous_hash);
+        hasher.update(self.nonce.to_be_bytes());
+        hasher.update(&self.validator);
+        hasher.update(&self.merkle_root);
+        
+        format!("{:x}", hasher.finalize())
+    }
+    
+    /// Check the hash and, if given, the link to the previous header
+    pub fn verify(&self, previous: Option<&BlockHeader>) -> bool {
+        if self.hash != self.calculate_hash() {
+            return false;
+        }
+        match previous {
+            Some(prev) => self.previous_hash == prev.hash && self.number == prev.number + 1,
+            None => true,
+        }
+    }
+}
+
+/// Merkle path from a transaction to its block's merkle root
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub block_number: u64,
+    /// Position of the transaction in the block
+    pub index: usize,
+    /// Sibling hashes from the leaf level up, hex encoded
+    pub siblings: Vec<String>,
+}
+
+impl MerkleProof {
+    /// Whether `transaction` is included in the block with `header`
+    pub fn verify(&self, transaction: &Transaction, header: &BlockHeader) -> bool {
+        if self.block_number != header.number {
+            return false;
+        }
+        let mut node = merkle_leaf(transaction);
+        let mut position = self.index;
+        for sibling in &self.siblings {
+            node = if position % 2 == 0 { merkle_node(&node, sibling) } else { merkle_node(sibling, &node) };
+            position /= 2;
+        }
+        position == 0 && node == header.merkle_root
+    }
+}
+
+/// Merkle root over a block's transactions; all zeros for an empty block
+pub fn merkle_root(transactions: &[Transaction]) -> String {
+    let mut level: Vec<String> = transactions.iter().map(merkle_leaf).collect();
+    if level.is_empty() {
+        return "0".repeat(64);
+    }
+    while level.len() > 1 {
+        level = merkle_parents(&level);
+    }
+    level.remove(0)
+}
+
+// Leaves cover the whole encoded transaction, not just `Transaction::hash`,
+// so a proof also vouches for fields like a report's score. Leaves and
+// inner nodes are domain separated.
+fn merkle_leaf(transaction: &Transaction) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(transaction.to_bytes().unwrap_or_else(|_| transaction.hash()));
+    format!("{:x}", hasher.finalize())
+}
+
+fn merkle_node(left: &str, right: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    format!("{:x}", hasher.finalize())
+}
+
+// An odd node out is paired with itself
+fn merkle_parents(level: &[String]) -> Vec<String> {
+    level
+        .chunks(2)
+        .map(|pair| merkle_node(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
 /// Transactions that can be stored in blocks
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub enum Transaction {
     /// Trust report about a participant
     TrustReport(TrustReport),
@@ -142,13 +264,14 @@ impl Transaction {
                 hasher.update(&report.id);
                 hasher.update(&report.reporter_id);
                 hasher.update(&report.subject_id);
-                hasher.update(report.timestamp.0.timestamp().to_be_bytes());
+                hasher.update(report.timestamp.timestamp().to_be_bytes());
+                hasher.update(report.nonce.to_be_bytes());
             }
             Transaction::Stake(stake) => {
                 hasher.update(&stake.id);
                 hasher.update(&stake.participant_id);
                 hasher.update(stake.amount.to_be_bytes());
-                hasher.update(stake.timestamp.0.timestamp().to_be_bytes());
+                hasher.update(stake.timestamp.timestamp().to_be_bytes());
             }
             // Handle other transaction types...
             _ => {}
@@ -156,6 +279,30 @@ impl Transaction {
         hasher.finalize().to_vec()
     }
     
+    /// Participant IDs the transaction refers to
+    pub fn participant_ids_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Transaction::TrustReport(report) => vec![&mut report.reporter_id, &mut report.subject_id],
+            Transaction::Stake(stake) => vec![&mut stake.participant_id],
+            Transaction::Unstake(unstake) => vec![&mut unstake.participant_id],
+            Transaction::Transfer(transfer) => vec![&mut transfer.from_participant, &mut transfer.to_participant],
+            Transaction::Registration(reg) => vec![&mut reg.participant_id],
+        }
+    }
+    
+    /// Whether the transaction refers to `participant_id`
+    pub fn mentions(&self, participant_id: &str) -> bool {
+        match self {
+            Transaction::TrustReport(report) => report.reporter_id == participant_id || report.subject_id == participant_id,
+            Transaction::Stake(stake) => stake.participant_id == participant_id,
+            Transaction::Unstake(unstake) => unstake.participant_id == participant_id,
+            Transaction::Transfer(transfer) => {
+                transfer.from_participant == participant_id || transfer.to_participant == participant_id
+            }
+            Transaction::Registration(reg) => reg.participant_id == participant_id,
+        }
+    }
+    
     /// Verify transaction is valid
     pub fn verify(&self) -> bool {
         match self {
@@ -168,18 +315,17 @@ impl Transaction {
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
-        bincode::encode_to_vec(self, bincode::config::standard())
+        serialization::to_canonical_bytes(self)
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::error::DecodeError> {
-        bincode::decode_from_slice(bytes, bincode::config::standard()).map(|r| r.0)
+        serialization::from_canonical_bytes(bytes)
     }
 }
 
 /// A trust report submitted to the blockchain
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub struct TrustReport {
     pub id: String,
     pub reporter_id: String,
@@ -189,8 +335,11 @@ pub struct TrustReport {
     pub category: String,
     pub evidence_hash: Option<String>, // Hash of evidence data
     pub stake_amount: u32, // Trust points staked on this report
-    pub timestamp: DateTimeWrapper,
+    #[serde(with = "timestamp")]
+    pub timestamp: DateTime<Utc>,
     pub signature: Vec<u8>, // Digital signature from reporter
+    #[serde(default)]
+    pub nonce: u64, // Reporter's transaction nonce, for replay protection
 }
 
 impl TrustReport {
@@ -204,7 +353,7 @@ impl TrustReport {
         stake_amount: u32,
     ) -> Self {
         Self {
-            id: UuidWrapper::new(Uuid::new_v4()).to_string(),
+            id: Uuid::new_v4().to_string(),
             reporter_id,
             subject_id,
             report_type,
@@ -212,8 +361,19 @@ impl TrustReport {
             category,
             evidence_hash: None,
             stake_amount,
-            timestamp: DateTimeWrapper::new(Utc::now()),
+            timestamp: Utc::now(),
             signature: vec![], // Would be populated with actual signature
+            nonce: 0,
+        }
+    }
+    
+    /// Points the report awards its subject; negative reports award none
+    pub fn points_awarded(&self) -> u32 {
+        if self.score > 0 {
+            // Simplified - would need consensus verification
+            (self.score as u32 * self.stake_amount) / 100
+        } else {
+            0
         }
     }
     
@@ -240,17 +400,16 @@ impl TrustReport {
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
-        bincode::encode_to_vec(self, bincode::config::standard())
+        serialization::to_canonical_bytes(self)
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::error::DecodeError> {
-        bincode::decode_from_slice(bytes, bincode::config::standard()).map(|r| r.0)
+        serialization::from_canonical_bytes(bytes)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub enum TrustReportType {
     /// Report good behavior
     Positive,
@@ -264,35 +423,35 @@ pub enum TrustReportType {
 
 impl TrustReportType {
     pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
-        bincode::encode_to_vec(self, bincode::config::standard())
+        serialization::to_canonical_bytes(self)
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::error::DecodeError> {
-        bincode::decode_from_slice(bytes, bincode::config::standard()).map(|r| r.0)
+        serialization::from_canonical_bytes(bytes)
     }
 }
 
 /// Stake trust points transaction
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub struct StakeTransaction {
     pub id: String,
     pub participant_id: String,
     pub amount: u32,
     pub purpose: StakePurpose,
-    pub timestamp: DateTimeWrapper,
+    #[serde(with = "timestamp")]
+    pub timestamp: DateTime<Utc>,
     pub signature: Vec<u8>,
 }
 
 impl StakeTransaction {
     pub fn new(participant_id: String, amount: u32, purpose: StakePurpose) -> Self {
         Self {
-            id: UuidWrapper::new(Uuid::new_v4()).to_string(),
+            id: Uuid::new_v4().to_string(),
             participant_id,
             amount,
             purpose,
-            timestamp: DateTimeWrapper::new(Utc::now()),
+            timestamp: Utc::now(),
             signature: vec![],
         }
     }
@@ -302,9 +461,8 @@ impl StakeTransaction {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub enum StakePurpose {
     /// Stake to become consensus validator
     ConsensusValidator,
@@ -315,15 +473,15 @@ pub enum StakePurpose {
 }
 
 /// Unstake trust points transaction
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub struct UnstakeTransaction {
     pub id: String,
     pub participant_id: String,
     pub amount: u32,
     pub stake_id: String, // Reference to original stake
-    pub timestamp: DateTimeWrapper,
+    #[serde(with = "timestamp")]
+    pub timestamp: DateTime<Utc>,
     pub signature: Vec<u8>,
 }
 
@@ -334,16 +492,16 @@ impl UnstakeTransaction {
 }
 
 /// Transfer trust points between participants
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub struct TransferTransaction {
     pub id: String,
     pub from_participant: String,
     pub to_participant: String,
     pub amount: u32,
     pub reason: String,
-    pub timestamp: DateTimeWrapper,
+    #[serde(with = "timestamp")]
+    pub timestamp: DateTime<Utc>,
     pub signature: Vec<u8>,
 }
 
@@ -354,16 +512,16 @@ impl TransferTransaction {
 }
 
 /// Participant registration transaction
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub struct RegistrationTransaction {
     pub id: String,
     pub participant_id: String,
     pub public_key: Vec<u8>,
     pub initial_trust_points: u32,
     pub entity_type: String,
-    pub timestamp: DateTimeWrapper,
+    #[serde(with = "timestamp")]
+    pub timestamp: DateTime<Utc>,
     pub signature: Vec<u8>,
 }
 
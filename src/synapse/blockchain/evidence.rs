@@ -0,0 +1,134 @@
+// Synapse Blockchain Evidence
+// Content-addressed evidence for trust reports
+
+//! Evidence attached to a trust report stays off-chain. The report carries
+//! the SHA-256 content address of the evidence and the blob itself lives in
+//! an [`EvidenceStore`]. A verifier resolving a dispute looks the blob up
+//! locally or fetches it from the reporter through an [`EvidenceSource`];
+//! either way the content is checked against the on-chain hash and the
+//! evidence rules before it is shown.
+
+use crate::synapse::storage::attachments::content_address;
+#[cfg(feature = "database")]
+use crate::synapse::storage::Database;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+/// Largest evidence accepted with a trust report
+pub const MAX_EVIDENCE_SIZE: usize = 1024 * 10; // 10KB
+
+/// Media type evidence blobs are stored under
+pub const EVIDENCE_MEDIA_TYPE: &str = "text/plain; charset=utf-8";
+
+/// Check evidence against the size and content rules
+///
+/// Applied when a report is submitted and again before fetched evidence is
+/// displayed.
+pub fn validate_evidence(evidence: &str) -> Result<()> {
+    if evidence.len() > MAX_EVIDENCE_SIZE {
+        return Err(anyhow!("Evidence size exceeds maximum allowed length"));
+    }
+
+    // Basic evidence sanitization
+    // This is a simple example - in production use proper sanitization
+    if evidence.to_ascii_lowercase().contains("<script") {
+        return Err(anyhow!("Evidence contains potentially malicious content"));
+    }
+    Ok(())
+}
+
+/// Where evidence blobs are kept, by content address
+#[async_trait]
+pub trait EvidenceStore: Send + Sync {
+    async fn put_evidence(&self, content_hash: &str, data: &[u8]) -> Result<()>;
+
+    async fn get_evidence(&self, content_hash: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// Evidence held by other nodes, usually the reporter's
+#[async_trait]
+pub trait EvidenceSource: Send + Sync {
+    /// Fetch the evidence with `content_hash` that `reporter_id` submitted
+    async fn fetch(&self, reporter_id: &str, content_hash: &str) -> Result<Vec<u8>>;
+}
+
+/// Evidence kept in memory; it is lost on restart
+#[derive(Default)]
+pub struct MemoryEvidenceStore {
+    blobs: DashMap<String, Vec<u8>>,
+}
+
+impl MemoryEvidenceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl EvidenceStore for MemoryEvidenceStore {
+    async fn put_evidence(&self, content_hash: &str, data: &[u8]) -> Result<()> {
+        self.blobs.insert(content_hash.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    async fn get_evidence(&self, content_hash: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.blobs.get(content_hash).map(|data| data.clone()))
+    }
+}
+
+// Evidence shares the content-addressed blob table with profile attachments
+#[cfg(feature = "database")]
+#[async_trait]
+impl EvidenceStore for Database {
+    async fn put_evidence(&self, content_hash: &str, data: &[u8]) -> Result<()> {
+        self.put_attachment_blob(content_hash, EVIDENCE_MEDIA_TYPE, data).await
+    }
+
+    async fn get_evidence(&self, content_hash: &str) -> Result<Option<Vec<u8>>> {
+        self.get_attachment_blob(content_hash).await
+    }
+}
+
+/// Evidence for one report, checked against its on-chain hash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportEvidence {
+    pub report_id: String,
+    pub reporter_id: String,
+    pub subject_id: String,
+    pub content_hash: String,
+    pub content: String,
+    /// Whether the report is in a committed block rather than still pending
+    pub committed: bool,
+}
+
+/// Decode evidence and check it matches `content_hash` and the evidence rules
+pub fn verify_evidence(content_hash: &str, data: &[u8]) -> Result<String> {
+    if data.len() > MAX_EVIDENCE_SIZE {
+        return Err(anyhow!("Evidence size exceeds maximum allowed length"));
+    }
+    if content_address(data) != content_hash {
+        return Err(anyhow!("Evidence does not match its content address"));
+    }
+    let content = String::from_utf8(data.to_vec()).map_err(|_| anyhow!("Evidence is not valid UTF-8"))?;
+    validate_evidence(&content)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evidence_rules() {
+        assert!(validate_evidence("delivery receipt 1234").is_ok());
+        assert!(validate_evidence("<SCRIPT>alert(1)</SCRIPT>").is_err());
+        assert!(validate_evidence(&"x".repeat(MAX_EVIDENCE_SIZE + 1)).is_err());
+
+        let evidence = b"delivery receipt 1234";
+        let hash = content_address(evidence);
+        assert_eq!(verify_evidence(&hash, evidence).unwrap(), "delivery receipt 1234");
+        assert!(verify_evidence(&hash, b"delivery receipt 9999").is_err());
+    }
+}
@@ -0,0 +1,325 @@
+// Synapse Blockchain Federation
+// Trust exchanged between independently run Synapse chains
+
+//! Organizations that each run their own trust chain can federate: each
+//! chain's validators sign [`TrustAttestation`]s of the scores their ledger
+//! holds, and a [`FederationBridge`] on the other side checks the signatures
+//! against the validator keys it was configured with and keeps the scores.
+//!
+//! Foreign scores never count as much as local ones. Each federated chain
+//! has a discount factor in `[0, 1]` that scales the weight of its reports,
+//! and [`SynapseBlockchain::get_trust_score_with_provenance`](super::SynapseBlockchain::get_trust_score_with_provenance)
+//! reports which chain each part of a score came from. Attestations only
+//! carry scores from a chain's own reports, so trust cannot echo back and
+//! forth between federated chains.
+
+use super::block::BlockHeader;
+use super::snapshot::{SnapshotScore, SnapshotSignature};
+use crate::crypto::CryptoManager;
+use crate::identity::LocalIdentity;
+use crate::synapse::blockchain::serialization::timestamp;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, warn};
+
+/// Another Synapse chain whose attestations this chain accepts
+#[derive(Debug, Clone)]
+pub struct FederatedChain {
+    pub chain_id: String,
+    /// Validator global ID -> public key PEM
+    pub validators: HashMap<String, String>,
+    /// Valid validator signatures an attestation needs
+    pub quorum: usize,
+    /// Weight of one foreign report relative to one local report, in `[0, 1]`
+    pub discount: f64,
+}
+
+/// Signed scores from one chain as of one of its blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustAttestation {
+    pub chain_id: String,
+    /// Header of the attesting chain's block the scores were read at
+    pub header: BlockHeader,
+    #[serde(with = "timestamp")]
+    pub issued_at: DateTime<Utc>,
+    pub scores: BTreeMap<String, SnapshotScore>,
+    pub signatures: Vec<SnapshotSignature>,
+}
+
+impl TrustAttestation {
+    pub fn new(chain_id: impl Into<String>, header: BlockHeader, scores: BTreeMap<String, SnapshotScore>) -> Self {
+        Self {
+            chain_id: chain_id.into(),
+            header,
+            issued_at: Utc::now(),
+            scores,
+            signatures: Vec::new(),
+        }
+    }
+
+    /// SHA-256 over the chain, block and scores, excluding signatures
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.chain_id.as_bytes());
+        hasher.update(self.header.number.to_be_bytes());
+        hasher.update(&self.header.hash);
+        hasher.update(self.issued_at.timestamp().to_be_bytes());
+        hasher.update(serde_json::to_vec(&self.scores).unwrap_or_default());
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Canonical string covered by validator signatures
+    pub fn signing_payload(&self) -> String {
+        format!("synapse-attestation:{}:{}:{}", self.chain_id, self.header.number, self.digest())
+    }
+
+    /// Add `validator`'s signature
+    pub fn sign(&mut self, validator: &LocalIdentity) -> Result<()> {
+        let signature = validator.sign(&self.signing_payload())?;
+        self.signatures.retain(|s| s.validator != validator.global_id);
+        self.signatures.push(SnapshotSignature { validator: validator.global_id.clone(), signature });
+        Ok(())
+    }
+
+    /// Take over signatures from another copy of the same attestation
+    ///
+    /// Returns false if `other` attests different scores.
+    pub fn merge_signatures(&mut self, other: &TrustAttestation) -> bool {
+        if other.digest() != self.digest() {
+            return false;
+        }
+        for signature in &other.signatures {
+            if !self.signatures.iter().any(|s| s.validator == signature.validator) {
+                self.signatures.push(signature.clone());
+            }
+        }
+        true
+    }
+
+    /// Check the attestation against a federated chain's validator keys
+    ///
+    /// Returns the number of valid signatures.
+    pub fn verify(&self, chain: &FederatedChain) -> Result<usize> {
+        if self.chain_id != chain.chain_id {
+            return Err(anyhow::anyhow!("Attestation is from {}, not {}", self.chain_id, chain.chain_id));
+        }
+        if self.header.hash != self.header.calculate_hash() {
+            return Err(anyhow::anyhow!("Attestation block header does not match its hash"));
+        }
+        let payload = self.signing_payload();
+        let mut crypto = CryptoManager::new();
+        let mut valid = 0;
+        for signature in &self.signatures {
+            let Some(public_key) = chain.validators.get(&signature.validator) else {
+                continue;
+            };
+            crypto.import_public_key(&signature.validator, public_key)?;
+            if crypto.verify_signature(&payload, &signature.signature, &signature.validator).unwrap_or(false) {
+                valid += 1;
+            }
+        }
+        if valid < chain.quorum {
+            return Err(anyhow::anyhow!(
+                "Attestation from {} has {} valid validator signatures, {} required",
+                self.chain_id, valid, chain.quorum
+            ));
+        }
+        Ok(valid)
+    }
+}
+
+/// Where part of a trust score came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreSource {
+    /// Chain the reports were recorded on; the local chain's ID for local reports
+    pub chain_id: String,
+    pub score: f64,
+    pub reports: u64,
+    /// Weight of each report in the combined score; 1.0 for local reports
+    pub discount: f64,
+    /// Block the score was read at, for foreign scores
+    pub block: Option<u64>,
+}
+
+/// A trust score and the chains it was computed from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProvenancedScore {
+    pub score: f64,
+    pub sources: Vec<ScoreSource>,
+}
+
+impl ProvenancedScore {
+    /// Combine sources, weighting each report by its source's discount
+    pub fn from_sources(sources: Vec<ScoreSource>) -> Self {
+        let weight: f64 = sources.iter().map(|s| s.reports as f64 * s.discount).sum();
+        let score = if weight > 0.0 {
+            sources.iter().map(|s| s.score * s.reports as f64 * s.discount).sum::<f64>() / weight
+        } else {
+            0.0 // No reports = neutral score
+        };
+        Self { score, sources }
+    }
+}
+
+/// Accepts attestations from federated chains and holds their scores
+pub struct FederationBridge {
+    chain_id: String,
+    chains: DashMap<String, FederatedChain>,
+    // Latest verified attestation per federated chain
+    attestations: DashMap<String, TrustAttestation>,
+}
+
+impl FederationBridge {
+    /// Bridge for the local chain known to others as `chain_id`
+    pub fn new(chain_id: impl Into<String>) -> Self {
+        Self {
+            chain_id: chain_id.into(),
+            chains: DashMap::new(),
+            attestations: DashMap::new(),
+        }
+    }
+
+    /// ID the local chain attests under
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    /// Accept attestations from `chain`, replacing any earlier settings for it
+    pub fn add_chain(&self, chain: FederatedChain) -> Result<()> {
+        if chain.chain_id == self.chain_id {
+            return Err(anyhow::anyhow!("Cannot federate with the local chain"));
+        }
+        if !(0.0..=1.0).contains(&chain.discount) {
+            return Err(anyhow::anyhow!("Discount for {} must be between 0 and 1", chain.chain_id));
+        }
+        if chain.quorum == 0 {
+            return Err(anyhow::anyhow!("Quorum for {} must be at least 1", chain.chain_id));
+        }
+        self.chains.insert(chain.chain_id.clone(), chain);
+        Ok(())
+    }
+
+    /// Stop accepting a chain and drop the scores it attested
+    pub fn remove_chain(&self, chain_id: &str) -> bool {
+        self.attestations.remove(chain_id);
+        self.chains.remove(chain_id).is_some()
+    }
+
+    /// Federated chain IDs
+    pub fn chains(&self) -> Vec<String> {
+        self.chains.iter().map(|entry| entry.key().clone()).collect()
+    }
+
+    /// Verify and keep an attestation, returning how many scores it carries
+    ///
+    /// An attestation older than the one already held for its chain is
+    /// rejected, so a replayed attestation cannot roll scores back.
+    pub fn import(&self, attestation: TrustAttestation) -> Result<usize> {
+        let chain = self
+            .chains
+            .get(&attestation.chain_id)
+            .ok_or_else(|| anyhow::anyhow!("Chain {} is not federated", attestation.chain_id))?;
+        attestation.verify(&chain)?;
+        drop(chain);
+
+        if let Some(held) = self.attestations.get(&attestation.chain_id) {
+            if attestation.header.number < held.header.number
+                || (attestation.header.number == held.header.number && attestation.issued_at <= held.issued_at)
+            {
+                warn!("Ignoring stale attestation from {} at block {}", attestation.chain_id, attestation.header.number);
+                return Err(anyhow::anyhow!("Attestation from {} is not newer than the one held", attestation.chain_id));
+            }
+        }
+        let count = attestation.scores.len();
+        info!(
+            "Imported {} trust scores from {} at block {}",
+            count, attestation.chain_id, attestation.header.number
+        );
+        self.attestations.insert(attestation.chain_id.clone(), attestation);
+        Ok(count)
+    }
+
+    /// Discounted foreign scores for a participant, one per attesting chain
+    pub fn foreign_scores(&self, participant_id: &str) -> Vec<ScoreSource> {
+        let mut sources: Vec<ScoreSource> = self
+            .attestations
+            .iter()
+            .filter_map(|entry| {
+                let attestation = entry.value();
+                let score = attestation.scores.get(participant_id)?;
+                let discount = self.chains.get(&attestation.chain_id)?.discount;
+                Some(ScoreSource {
+                    chain_id: attestation.chain_id.clone(),
+                    score: score.score,
+                    reports: score.reports,
+                    discount,
+                    block: Some(attestation.header.number),
+                })
+            })
+            .collect();
+        sources.sort_by(|a, b| a.chain_id.cmp(&b.chain_id));
+        sources
+    }
+
+    /// Drop a participant from every held attestation
+    pub fn forget(&self, participant_id: &str) {
+        for mut entry in self.attestations.iter_mut() {
+            entry.value_mut().scores.remove(participant_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::TransportBindings;
+    use crate::synapse::blockchain::block::Block;
+
+    fn validator(name: &str) -> (LocalIdentity, String) {
+        let identity = LocalIdentity::new(name, &format!("{}@example.com", name), TransportBindings::default());
+        let public_key = identity.generate_keypair().unwrap();
+        (identity, public_key)
+    }
+
+    #[test]
+    fn test_federated_scores() {
+        let (v1, k1) = validator("v1");
+        let bridge = FederationBridge::new("acme");
+        bridge
+            .add_chain(FederatedChain {
+                chain_id: "globex".into(),
+                validators: HashMap::from([(v1.global_id.clone(), k1)]),
+                quorum: 1,
+                discount: 0.5,
+            })
+            .unwrap();
+
+        let header = Block::genesis().header();
+        let scores = BTreeMap::from([("alice".to_string(), SnapshotScore { score: 40.0, reports: 2 })]);
+        let mut attestation = TrustAttestation::new("globex", header, scores);
+        assert!(bridge.import(attestation.clone()).is_err());
+        attestation.sign(&v1).unwrap();
+
+        // Tampered scores invalidate the signature
+        let mut tampered = attestation.clone();
+        tampered.scores.insert("mallory".into(), SnapshotScore { score: 100.0, reports: 50 });
+        assert!(bridge.import(tampered).is_err());
+
+        assert_eq!(bridge.import(attestation.clone()).unwrap(), 1);
+        assert!(bridge.import(attestation).is_err());
+
+        let foreign = bridge.foreign_scores("alice");
+        assert_eq!(foreign.len(), 1);
+        assert_eq!(foreign[0].chain_id, "globex");
+
+        // Two local reports at 80 outweigh two half-weight foreign reports at 40
+        let local = ScoreSource { chain_id: "acme".into(), score: 80.0, reports: 2, discount: 1.0, block: None };
+        let combined = ProvenancedScore::from_sources(vec![local, foreign[0].clone()]);
+        assert!((combined.score - 200.0 / 3.0).abs() < 1e-9);
+        assert!(bridge.foreign_scores("bob").is_empty());
+    }
+}
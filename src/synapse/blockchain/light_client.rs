@@ -0,0 +1,321 @@
+// Synapse Blockchain Light Client
+// Trust verification for devices that cannot hold or scan the full chain
+
+//! A [`LightClient`] keeps only block headers, starting from a checkpoint it
+//! trusts, plus the trust reports about a handful of watched peers. Full
+//! nodes send the reports with merkle proofs against the headers, so the
+//! client can check every report without trusting the node that sent it.
+//!
+//! Requests and responses travel as system messages over the normal
+//! transports; see [`LightClientMessage`]. A full node can leave reports out,
+//! so clients that need completeness should sync from more than one node.
+//!
+//! Header timestamps must not go backwards and may run ahead of the local
+//! clock by at most the client's skew allowance.
+
+use super::block::{BlockHeader, MerkleProof, Transaction, TrustReport};
+use super::score_reports;
+use crate::types::{MessageType, SimpleMessage};
+use anyhow::Result;
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+/// Metadata key marking a message as light-client traffic (the message kind)
+pub const LIGHT_CLIENT_KEY: &str = "light_client";
+
+/// Most headers a full node sends in one response
+pub const MAX_HEADERS_PER_RESPONSE: usize = 500;
+
+/// How far a header's timestamp may run ahead of the local clock by default
+pub const DEFAULT_MAX_CLOCK_SKEW: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// A trust report with the proof that a block includes it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustProof {
+    pub report: TrustReport,
+    pub proof: MerkleProof,
+}
+
+/// Light-client requests and full-node responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LightClientMessage {
+    /// Ask for the headers after block `from`
+    HeadersRequest { from: u64 },
+    Headers { headers: Vec<BlockHeader> },
+    /// Ask for reports about `subjects` in blocks after `since`
+    ProofsRequest { subjects: Vec<String>, since: u64 },
+    /// Reports about `subjects`, complete up to block `through`
+    Proofs {
+        subjects: Vec<String>,
+        through: u64,
+        proofs: Vec<TrustProof>,
+    },
+}
+
+impl LightClientMessage {
+    /// Name used in the message metadata
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LightClientMessage::HeadersRequest { .. } => "headers_request",
+            LightClientMessage::Headers { .. } => "headers",
+            LightClientMessage::ProofsRequest { .. } => "proofs_request",
+            LightClientMessage::Proofs { .. } => "proofs",
+        }
+    }
+
+    /// Whether a full node should answer this message
+    pub fn is_request(&self) -> bool {
+        matches!(self, LightClientMessage::HeadersRequest { .. } | LightClientMessage::ProofsRequest { .. })
+    }
+
+    /// Wrap the message in a system message from `from_entity` to `to_entity`
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> crate::error::Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(LIGHT_CLIENT_KEY.to_string(), self.kind().to_string());
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: serde_json::to_string(self)?,
+            message_type: MessageType::System,
+            metadata,
+        })
+    }
+
+    /// Extract the light-client message carried by `message`, if any
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        if message.message_type != MessageType::System || !message.metadata.contains_key(LIGHT_CLIENT_KEY) {
+            return None;
+        }
+        serde_json::from_str(&message.content).ok()
+    }
+}
+
+/// Header-only view of the chain with proven reports about watched peers
+pub struct LightClient {
+    headers: RwLock<BTreeMap<u64, BlockHeader>>,
+    /// Watched participant -> block its proven reports are complete up to
+    watched: DashMap<String, u64>,
+    /// Watched participant -> reports proven so far
+    reports: DashMap<String, Vec<TrustReport>>,
+    max_clock_skew: chrono::Duration,
+}
+
+impl LightClient {
+    /// Start from a header the device trusts, e.g. one pinned at provisioning
+    pub fn new(checkpoint: BlockHeader) -> Self {
+        Self {
+            headers: RwLock::new(BTreeMap::from([(checkpoint.number, checkpoint)])),
+            watched: DashMap::new(),
+            reports: DashMap::new(),
+            max_clock_skew: chrono::Duration::from_std(DEFAULT_MAX_CLOCK_SKEW).unwrap(),
+        }
+    }
+
+    /// Accept headers dated up to `skew` ahead of the local clock
+    pub fn with_max_clock_skew(mut self, skew: std::time::Duration) -> Self {
+        self.max_clock_skew = chrono::Duration::from_std(skew).unwrap_or(chrono::Duration::MAX);
+        self
+    }
+
+    /// Track trust reports about a peer from the next sync on
+    pub fn watch(&self, participant_id: &str) {
+        let checkpoint = *self.headers.read().unwrap().keys().next().unwrap();
+        self.watched.entry(participant_id.to_string()).or_insert(checkpoint);
+    }
+
+    /// Stop tracking a peer and drop its reports
+    pub fn unwatch(&self, participant_id: &str) {
+        self.watched.remove(participant_id);
+        self.reports.remove(participant_id);
+    }
+
+    /// Participants whose reports are tracked
+    pub fn watched(&self) -> Vec<String> {
+        self.watched.iter().map(|entry| entry.key().clone()).collect()
+    }
+
+    /// Latest verified header
+    pub fn tip(&self) -> BlockHeader {
+        self.headers.read().unwrap().values().next_back().cloned().unwrap()
+    }
+
+    /// Verified header for block `number`
+    pub fn header(&self, number: u64) -> Option<BlockHeader> {
+        self.headers.read().unwrap().get(&number).cloned()
+    }
+
+    /// Extend the header chain; returns how many headers were added
+    ///
+    /// Headers at or below the tip are skipped. Stops with an error at the
+    /// first header that does not hash correctly, link to the tip, or carry a
+    /// plausible timestamp.
+    pub fn apply_headers(&self, mut headers: Vec<BlockHeader>) -> Result<usize> {
+        headers.sort_by_key(|header| header.number);
+        let mut chain = self.headers.write().unwrap();
+        let mut added = 0;
+        for header in headers {
+            let tip = chain.values().next_back().unwrap();
+            if header.number <= tip.number {
+                continue;
+            }
+            if !header.verify(Some(tip)) {
+                return Err(anyhow::anyhow!("Header {} does not extend the verified chain", header.number));
+            }
+            // The hash covers whole seconds only
+            if header.timestamp.timestamp() < tip.timestamp.timestamp() {
+                return Err(anyhow::anyhow!("Header {} is dated before its parent", header.number));
+            }
+            if header.timestamp > Utc::now() + self.max_clock_skew {
+                return Err(anyhow::anyhow!(
+                    "Header {} is dated {} ahead of the local clock",
+                    header.number,
+                    header.timestamp - Utc::now()
+                ));
+            }
+            chain.insert(header.number, header);
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Record the proven reports in a full node's response; returns how many were new
+    ///
+    /// Proofs for unwatched peers, unknown blocks or that fail verification
+    /// are dropped.
+    pub fn apply_proofs(&self, subjects: &[String], through: u64, proofs: Vec<TrustProof>) -> usize {
+        let mut accepted = 0;
+        for TrustProof { report, proof } in proofs {
+            if !self.watched.contains_key(&report.subject_id) {
+                continue;
+            }
+            let Some(header) = self.header(proof.block_number) else {
+                continue;
+            };
+            if !proof.verify(&Transaction::TrustReport(report.clone()), &header) {
+                tracing::warn!("Rejected trust report proof for block {}", proof.block_number);
+                continue;
+            }
+            let mut reports = self.reports.entry(report.subject_id.clone()).or_default();
+            if !reports.iter().any(|existing| existing.id == report.id) {
+                reports.push(report);
+                accepted += 1;
+            }
+        }
+
+        // Reports past our own tip could not be checked yet; ask for them again
+        let through = through.min(self.tip().number);
+        for subject in subjects {
+            if let Some(mut synced) = self.watched.get_mut(subject) {
+                *synced = (*synced).max(through);
+            }
+        }
+        accepted
+    }
+
+    /// Apply a full node's response; requests are ignored
+    pub fn handle(&self, message: LightClientMessage) -> Result<usize> {
+        match message {
+            LightClientMessage::Headers { headers } => self.apply_headers(headers),
+            LightClientMessage::Proofs { subjects, through, proofs } => Ok(self.apply_proofs(&subjects, through, proofs)),
+            _ => Ok(0),
+        }
+    }
+
+    /// Requests that bring the headers and watched peers' reports up to date
+    pub fn sync_requests(&self) -> Vec<LightClientMessage> {
+        let mut requests = vec![LightClientMessage::HeadersRequest { from: self.tip().number }];
+        let mut by_since: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+        for entry in self.watched.iter() {
+            by_since.entry(*entry.value()).or_default().push(entry.key().clone());
+        }
+        requests.extend(
+            by_since
+                .into_iter()
+                .map(|(since, subjects)| LightClientMessage::ProofsRequest { subjects, since }),
+        );
+        requests
+    }
+
+    /// Trust score of a watched peer from its proven reports
+    pub fn get_trust_score(&self, participant_id: &str) -> Result<f64> {
+        if !self.watched.contains_key(participant_id) {
+            return Err(anyhow::anyhow!("{} is not watched by this light client", participant_id));
+        }
+        Ok(self.reports.get(participant_id).map(|reports| score_reports(reports.iter())).unwrap_or(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synapse::blockchain::block::{Block, TrustReportType};
+
+    fn report(subject: &str, score: i8) -> Transaction {
+        Transaction::TrustReport(TrustReport::new(
+            "reporter".to_string(),
+            subject.to_string(),
+            TrustReportType::Positive,
+            score,
+            "collaboration".to_string(),
+            10,
+        ))
+    }
+
+    fn proofs_for(block: &Block, subject: &str) -> Vec<TrustProof> {
+        block
+            .transactions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, transaction)| match transaction {
+                Transaction::TrustReport(report) if report.subject_id == subject => {
+                    Some(TrustProof { report: report.clone(), proof: block.prove(index)? })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_headers_and_proofs_verify() {
+        let genesis = Block::genesis();
+        let block = Block::new(
+            1,
+            genesis.hash.clone(),
+            vec![report("alice", 80), report("bob", -40), report("alice", 60)],
+            "validator".to_string(),
+        );
+        let client = LightClient::new(genesis.header());
+        client.watch("alice");
+
+        // Proofs for blocks without a verified header are not accepted
+        assert_eq!(client.apply_proofs(&["alice".to_string()], 1, proofs_for(&block, "alice")), 0);
+
+        let mut forged = block.header();
+        forged.merkle_root = "0".repeat(64);
+        assert!(client.apply_headers(vec![forged]).is_err());
+        assert_eq!(client.apply_headers(vec![block.header()]).unwrap(), 1);
+
+        // A tampered score no longer matches the merkle root
+        let mut proofs = proofs_for(&block, "alice");
+        proofs[1].report.score = 100;
+        assert_eq!(client.apply_proofs(&["alice".to_string()], 1, proofs), 1);
+        assert_eq!(client.apply_proofs(&["alice".to_string()], 1, proofs_for(&block, "alice")), 1);
+        assert_eq!(client.get_trust_score("alice").unwrap(), 70.0);
+        assert!(client.get_trust_score("bob").is_err());
+
+        // A header from the future is refused even when it hashes correctly
+        let mut future = Block::new(2, block.hash.clone(), Vec::new(), "validator".to_string()).header();
+        future.timestamp = Utc::now() + chrono::Duration::minutes(10);
+        future.hash = future.calculate_hash();
+        assert!(client.apply_headers(vec![future]).is_err());
+
+        match &client.sync_requests()[..] {
+            [LightClientMessage::HeadersRequest { from: 1 }, LightClientMessage::ProofsRequest { since: 1, .. }] => {}
+            other => panic!("unexpected sync requests: {:?}", other),
+        }
+    }
+}
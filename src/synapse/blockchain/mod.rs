@@ -2,7 +2,12 @@
 
 pub mod block;
 pub mod consensus;
+pub mod evidence;
+pub mod federation;
+pub mod light_client;
+pub mod nonces;
 pub mod serialization;  // Add this line
+pub mod snapshot;
 pub mod staking;
 pub mod verification;
 
@@ -10,12 +15,22 @@ use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use tokio::sync::RwLock;
+use sha2::{Digest, Sha256};
 use tracing::info;
+use crate::events::{EventBus, RouterEvent};
+use crate::identity::LocalIdentity;
+use crate::runtime::{self, Subsystem};
+use crate::synapse::services::decay::{DecayScheduler, MemoryScheduleStore};
  
- pub use block::{Block, Transaction, TrustReport, TrustReportType};
+ pub use block::{Block, BlockHeader, MerkleProof, Transaction, TrustReport, TrustReportType};
  pub use consensus::ConsensusEngine;
+ pub use evidence::{EvidenceSource, EvidenceStore, MemoryEvidenceStore, ReportEvidence};
+ pub use federation::{FederatedChain, FederationBridge, ProvenancedScore, ScoreSource, TrustAttestation};
+ pub use light_client::{LightClient, LightClientMessage, TrustProof};
+ pub use nonces::{MemoryNonceStore, NonceLedger, NonceStore};
+ pub use snapshot::{SnapshotScore, StateSnapshot};
  pub use staking::StakingManager;
  pub use verification::VerificationEngine;
 
@@ -27,6 +42,7 @@ pub struct BlockchainConfig {
     pub min_consensus_nodes: usize,
     pub staking_requirements: StakingRequirements,
     pub trust_decay_config: TrustDecayConfig,
+    pub snapshot_interval_blocks: u64, // Blocks between state snapshots; 0 disables them
 }
 
 #[derive(Debug, Clone)]
@@ -36,6 +52,17 @@ pub struct StakingRequirements {
     pub min_stake_for_report: u32,
     pub min_stake_for_consensus: u32,
     pub slash_percentage: f64, // Percentage of stake to slash for false reports
+    pub lock_up_secs: u64, // Minimum time a new stake or delegation stays in place
+    pub unbonding_secs: u64, // Time unstaked points stay unavailable after unstaking
+    pub min_delegation_amount: u32,
+    pub validator_commission: f64, // Share of delegated rewards kept by the validator
+}
+
+impl StakingRequirements {
+    /// Points slashed from `stake` points behind a false report or bad behavior
+    pub fn slash_amount(&self, stake: u32) -> u32 {
+        (stake as f64 * self.slash_percentage) as u32
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -57,12 +84,17 @@ impl Default for BlockchainConfig {
                 min_stake_for_report: 10,
                 min_stake_for_consensus: 50,
                 slash_percentage: 0.1, // 10% slash for false reports
+                lock_up_secs: 24 * 60 * 60, // 1 day
+                unbonding_secs: 7 * 24 * 60 * 60, // 7 days
+                min_delegation_amount: 10,
+                validator_commission: 0.1, // 10% of delegators' rewards
             },
             trust_decay_config: TrustDecayConfig {
                 monthly_decay_rate: 0.02, // 2% per month
                 min_activity_days: 30,
                 decay_check_interval_hours: 24, // Check daily
             },
+            snapshot_interval_blocks: 1000,
         }
     }
 }
@@ -72,13 +104,32 @@ pub struct SynapseBlockchain {
     pub config: BlockchainConfig,
     chain: Arc<RwLock<Vec<Block>>>,
     pending_transactions: Arc<RwLock<Vec<Transaction>>>,
-    // Track participant nonces for replay protection
-    participant_nonces: Arc<DashMap<String, u64>>,
+    // Highest committed nonce per participant, for replay protection
+    participant_nonces: Arc<NonceLedger>,
+    // Evidence blobs by content address; reports only carry the hash
+    evidence: Arc<dyn EvidenceStore>,
     #[allow(dead_code)]
     consensus_engine: Arc<ConsensusEngine>,
     pub staking_manager: Arc<StakingManager>,
     #[allow(dead_code)]
     verification_engine: Arc<VerificationEngine>,
+    // Receives BlockCommitted events
+    events: EventBus,
+    // Decay over staked balances; its runs are remembered in memory only
+    decay: Arc<DecayScheduler>,
+    // SHA-256 of each erased participant ID; committed blocks can't change,
+    // so references are masked whenever the chain is read out
+    redacted: Arc<DashSet<String>>,
+    // IDs of reports annulled by dispute resolution; they stay in their
+    // blocks but no longer count towards scores
+    annulled: Arc<DashSet<String>>,
+    // Validator identity that signs state snapshots, if this node takes them
+    snapshot_signer: Option<Arc<LocalIdentity>>,
+    latest_snapshot: Arc<RwLock<Option<StateSnapshot>>>,
+    // Scores carried over from the snapshot this node bootstrapped from
+    base_scores: Arc<DashMap<String, SnapshotScore>>,
+    // Scores attested by other organizations' chains, if this chain federates
+    federation: Option<Arc<FederationBridge>>,
 }
 
 impl SynapseBlockchain {
@@ -97,27 +148,229 @@ impl SynapseBlockchain {
         ).await?);
         
         let verification_engine = Arc::new(VerificationEngine::new(config.clone()));
+        let decay = Arc::new(DecayScheduler::new(
+            config.trust_decay_config.clone(),
+            staking_manager.clone(),
+            Arc::new(MemoryScheduleStore::new()),
+        ));
         
         Ok(Self {
             config,
             chain,
             pending_transactions: Arc::new(RwLock::new(Vec::new())),
-            participant_nonces: Arc::new(DashMap::new()),
+            participant_nonces: Arc::new(NonceLedger::new(Arc::new(MemoryNonceStore::new()))),
+            evidence: Arc::new(MemoryEvidenceStore::new()),
             consensus_engine,
             staking_manager,
             verification_engine,
+            events: EventBus::default(),
+            decay,
+            redacted: Arc::new(DashSet::new()),
+            annulled: Arc::new(DashSet::new()),
+            snapshot_signer: None,
+            latest_snapshot: Arc::new(RwLock::new(None)),
+            base_scores: Arc::new(DashMap::new()),
+            federation: None,
         })
     }
     
+    /// Start from a signed state snapshot instead of replaying the chain
+    ///
+    /// The snapshot must carry valid signatures from at least `quorum` of
+    /// `validators` (global ID -> public key PEM). The snapshot block becomes
+    /// the base of the local chain, so blocks produced after it link on.
+    pub async fn bootstrap_from_snapshot(
+        config: BlockchainConfig,
+        snapshot: StateSnapshot,
+        validators: &std::collections::HashMap<String, String>,
+        quorum: usize,
+    ) -> Result<Self> {
+        snapshot.verify_quorum(validators, quorum)?;
+        let blockchain = Self::new(config).await?;
+        blockchain.restore_snapshot(snapshot).await;
+        Ok(blockchain)
+    }
+    
+    /// Replace the chain and ledger with the state in `snapshot`
+    ///
+    /// The snapshot is trusted as is; [`Self::bootstrap_from_snapshot`] checks
+    /// its signatures first. A standby node uses this to take over the state a
+    /// primary exported.
+    pub async fn restore_snapshot(&self, snapshot: StateSnapshot) {
+        // The anchor block stands in for the snapshot block; its transactions
+        // are not needed, only its hash for the next block to link to
+        let header = snapshot.header.clone();
+        *self.chain.write().await = vec![Block {
+            number: header.number,
+            timestamp: header.timestamp,
+            previous_hash: header.previous_hash,
+            hash: header.hash,
+            transactions: vec![],
+            nonce: header.nonce,
+            validator: header.validator,
+        }];
+        self.staking_manager.restore_balances(&snapshot.balances);
+        self.participant_nonces.restore(&snapshot.nonces);
+        for (participant, score) in &snapshot.scores {
+            self.base_scores.insert(participant.clone(), score.clone());
+        }
+        
+        info!("Restored state at block {}", snapshot.header.number);
+        *self.latest_snapshot.write().await = Some(snapshot);
+    }
+    
+    /// Persist committed nonces in `store`, loading those already there
+    ///
+    /// Nodes that share a store, such as the database, see each other's
+    /// committed nonces when checking new reports.
+    pub async fn with_nonce_store(mut self, store: Arc<dyn NonceStore>) -> Result<Self> {
+        let ledger = NonceLedger::new(store);
+        ledger.restore(&self.participant_nonces.nonces());
+        ledger.replay(&self.chain.read().await);
+        let loaded = ledger.load().await?;
+        info!("Loaded {} persisted participant nonces", loaded);
+        self.participant_nonces = Arc::new(ledger);
+        Ok(self)
+    }
+    
+    /// Keep report evidence in `store`, e.g. the database
+    pub fn with_evidence_store(mut self, store: Arc<dyn EvidenceStore>) -> Self {
+        self.evidence = store;
+        self
+    }
+    
+    /// Sign a state snapshot every `snapshot_interval_blocks` blocks as `validator`
+    pub fn with_snapshot_signer(mut self, validator: Arc<LocalIdentity>) -> Self {
+        self.snapshot_signer = Some(validator);
+        self
+    }
+    
+    /// Capture the current state, signed by this node's snapshot signer if it has one
+    pub async fn take_snapshot(&self) -> Result<StateSnapshot> {
+        let snapshot = Self::capture_snapshot(
+            &self.chain,
+            &self.staking_manager,
+            &self.participant_nonces,
+            &self.redacted,
+            &self.annulled,
+            self.snapshot_signer.as_deref(),
+        ).await?;
+        *self.latest_snapshot.write().await = Some(snapshot.clone());
+        Ok(snapshot)
+    }
+    
+    /// Most recent snapshot taken or bootstrapped from, to hand to new nodes
+    pub async fn latest_snapshot(&self) -> Option<StateSnapshot> {
+        self.latest_snapshot.read().await.clone()
+    }
+    
+    /// Collect other validators' signatures on the latest snapshot
+    ///
+    /// Returns false if `other` is not a copy of the latest snapshot.
+    pub async fn add_snapshot_signatures(&self, other: &StateSnapshot) -> bool {
+        match self.latest_snapshot.write().await.as_mut() {
+            Some(latest) => latest.merge_signatures(other),
+            None => false,
+        }
+    }
+    
+    /// Blend in trust attested by the chains federated through `bridge`
+    pub fn with_federation(mut self, bridge: Arc<FederationBridge>) -> Self {
+        self.federation = Some(bridge);
+        self
+    }
+    
+    /// Federation bridge, if this chain federates with others
+    pub fn federation(&self) -> Option<&Arc<FederationBridge>> {
+        self.federation.as_ref()
+    }
+    
+    /// Sign the local scores of `participants` for federated chains to import
+    ///
+    /// Only scores from this chain's own reports are attested. Needs a
+    /// federation bridge for the chain ID and a snapshot signer to sign with;
+    /// gather other validators' signatures with
+    /// [`TrustAttestation::merge_signatures`].
+    pub async fn attest_scores(&self, participants: &[String]) -> Result<TrustAttestation> {
+        let federation = self.federation.as_ref().ok_or_else(|| anyhow::anyhow!("Federation is not configured"))?;
+        let signer = self.snapshot_signer.as_ref().ok_or_else(|| anyhow::anyhow!("No validator identity to sign with"))?;
+        
+        let mut scores = std::collections::BTreeMap::new();
+        for participant in participants {
+            if self.is_redacted(participant) {
+                continue;
+            }
+            if let Some(local) = self.local_score(participant).await {
+                scores.insert(participant.clone(), local);
+            }
+        }
+        let header = self.chain.read().await.last().map(Block::header)
+            .ok_or_else(|| anyhow::anyhow!("Chain is empty"))?;
+        let mut attestation = TrustAttestation::new(federation.chain_id(), header, scores);
+        attestation.sign(signer)?;
+        Ok(attestation)
+    }
+    
+    /// Verify and keep an attestation from a federated chain
+    pub fn import_attestation(&self, attestation: TrustAttestation) -> Result<usize> {
+        let federation = self.federation.as_ref().ok_or_else(|| anyhow::anyhow!("Federation is not configured"))?;
+        federation.import(attestation)
+    }
+    
+    async fn capture_snapshot(
+        chain: &Arc<RwLock<Vec<Block>>>,
+        staking_manager: &Arc<StakingManager>,
+        participant_nonces: &NonceLedger,
+        redacted: &DashSet<String>,
+        annulled: &DashSet<String>,
+        signer: Option<&LocalIdentity>,
+    ) -> Result<StateSnapshot> {
+        let balances = staking_manager.balances().await?;
+        let nonces = participant_nonces.nonces();
+        let mut snapshot = StateSnapshot::capture_excluding(
+            &chain.read().await,
+            balances,
+            nonces,
+            |report| annulled.contains(&report.id),
+        )?;
+        
+        // Erased participants must not reappear in snapshots handed to other nodes
+        let erased = |id: &String| redacted.contains(&format!("{:x}", Sha256::digest(id.as_bytes())));
+        snapshot.balances.retain(|id, _| !erased(id));
+        snapshot.scores.retain(|id, _| !erased(id));
+        snapshot.nonces.retain(|id, _| !erased(id));
+        if let Some(signer) = signer {
+            snapshot.sign(signer)?;
+        }
+        Ok(snapshot)
+    }
+
+    /// Publish block commits to `events`, typically the router's bus
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Event bus block commits are published to
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+    
     /// Start consensus process
     pub async fn start_consensus(&self) -> Result<()> {
         let chain = self.chain.clone();
         let pending_transactions = self.pending_transactions.clone();
         let config = self.config.clone();
         let staking_manager = self.staking_manager.clone();
-        
-        // Start consensus in background thread
-        tokio::spawn(async move {
+        let events = self.events.clone();
+        let participant_nonces = self.participant_nonces.clone();
+        let snapshot_signer = self.snapshot_signer.clone();
+        let latest_snapshot = self.latest_snapshot.clone();
+        let redacted = self.redacted.clone();
+        let annulled = self.annulled.clone();
+        
+        // Start consensus on its own runtime when one is configured
+        runtime::spawn(Subsystem::Consensus, async move {
             let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(config.block_time_seconds));
             
             loop {
@@ -128,10 +381,33 @@ impl SynapseBlockchain {
                     &chain,
                     &pending_transactions,
                     &staking_manager,
-                    &config
+                    &participant_nonces,
+                    &config,
+                    &events,
                 ).await {
                     tracing::error!("Consensus error: {}", e);
                 }
+                
+                // Validators snapshot the state at every interval boundary
+                let Some(signer) = snapshot_signer.as_deref() else {
+                    continue;
+                };
+                let tip = chain.read().await.last().map(|block| block.number).unwrap_or(0);
+                let taken = latest_snapshot.read().await.as_ref().map(|s| s.header.number);
+                if config.snapshot_interval_blocks == 0
+                    || tip == 0
+                    || tip % config.snapshot_interval_blocks != 0
+                    || taken == Some(tip)
+                {
+                    continue;
+                }
+                match Self::capture_snapshot(&chain, &staking_manager, &participant_nonces, &redacted, &annulled, Some(signer)).await {
+                    Ok(snapshot) => {
+                        info!("Took state snapshot at block {}", tip);
+                        *latest_snapshot.write().await = Some(snapshot);
+                    }
+                    Err(e) => tracing::error!("Snapshot at block {} failed: {}", tip, e),
+                }
             }
         });
         
@@ -144,7 +420,9 @@ impl SynapseBlockchain {
         chain: &Arc<RwLock<Vec<Block>>>,
         pending_transactions: &Arc<RwLock<Vec<Transaction>>>,
         staking_manager: &Arc<StakingManager>,
+        participant_nonces: &NonceLedger,
         config: &BlockchainConfig,
+        events: &EventBus,
     ) -> Result<()> {
         // Get pending transactions
         let transactions = {
@@ -159,6 +437,12 @@ impl SynapseBlockchain {
             processed
         };
         
+        // Another node may have committed the same nonces since submission
+        let transactions = participant_nonces.admit(transactions).await?;
+        if transactions.is_empty() {
+            return Ok(());
+        }
+        
         // Create new block
         let previous_block = {
             let chain_read = chain.read().await;
@@ -178,9 +462,11 @@ impl SynapseBlockchain {
             return Ok(());
         }
         
-        // Simple round-robin validator selection based on block number
-        let validator_idx = previous_block.number as usize % validators.len();
-        let validator = validators[validator_idx].clone();
+        // Pick a validator in proportion to its own plus delegated stake
+        let validator = match staking_manager.select_validator(previous_block.number).await? {
+            Some(validator) => validator,
+            None => validators[previous_block.number as usize % validators.len()].clone(),
+        };
         
         // Create and add the block
         let new_block = Block::new(
@@ -200,6 +486,7 @@ impl SynapseBlockchain {
             let mut chain_write = chain.write().await;
             chain_write.push(new_block.clone());
         }
+        participant_nonces.commit(&new_block).await?;
         
         tracing::info!(
             "Block {} added with {} transactions, validated by {}",
@@ -207,28 +494,34 @@ impl SynapseBlockchain {
             new_block.transactions.len(),
             new_block.validator
         );
+        events.publish(RouterEvent::BlockCommitted {
+            number: new_block.number,
+            hash: new_block.hash.clone(),
+            transactions: new_block.transactions.len(),
+            validator: new_block.validator.clone(),
+        });
         
         Ok(())
     }
     
     /// Get next nonce for a participant (for transaction replay protection)
+    ///
+    /// One more than the participant's highest committed or pending nonce.
+    /// The nonce is not reserved; of two reports submitted with the same
+    /// nonce only the first is accepted.
     pub async fn get_next_nonce(&self, participant_id: &str) -> Result<u64> {
-        let mut entry = self.participant_nonces.entry(participant_id.to_string()).or_insert(0);
-        let next_nonce = *entry.value() + 1;
-        *entry.value_mut() = next_nonce;
-        Ok(next_nonce)
+        let committed = self.participant_nonces.committed(participant_id).await?;
+        let pending = self.pending_transactions.read().await.iter().filter_map(|transaction| match transaction {
+            Transaction::TrustReport(report) if report.reporter_id == participant_id => Some(report.nonce),
+            _ => None,
+        }).max().unwrap_or(0);
+        Ok(committed.max(pending) + 1)
     }
     
     /// Verify nonce is valid for participant
     pub async fn verify_nonce(&self, participant_id: &str, nonce: u64) -> Result<bool> {
-        let current = self.participant_nonces.get(participant_id).map(|entry| *entry.value()).unwrap_or(0);
-        
-        // Nonce must be exactly one more than current
-        if nonce != current + 1 {
-            return Ok(false);
-        }
-        
-        Ok(true)
+        // Nonce must be exactly one more than the latest committed or pending
+        Ok(nonce == self.get_next_nonce(participant_id).await?)
     }
     
     /// Submit a trust report to the blockchain with nonce for replay protection
@@ -239,9 +532,13 @@ impl SynapseBlockchain {
         score: i8,
         category: crate::synapse::models::TrustCategory,
         stake_amount: u32,
-        _evidence: Option<String>,
+        evidence: Option<String>,
         nonce: u64,
     ) -> Result<String> {
+        if let Some(evidence) = &evidence {
+            evidence::validate_evidence(evidence)?;
+        }
+        
         // Verify nonce is valid to prevent replay attacks
         if !self.verify_nonce(reporter_id, nonce).await? {
             return Err(anyhow::anyhow!("Invalid nonce for transaction"));
@@ -270,7 +567,7 @@ impl SynapseBlockchain {
         };
         
         // Create trust report
-        let report = TrustReport::new(
+        let mut report = TrustReport::new(
             reporter_id.to_string(),
             subject_id.to_string(),
             report_type,
@@ -278,17 +575,25 @@ impl SynapseBlockchain {
             category_str,
             stake_amount,
         );
+        report.nonce = nonce;
+        
+        // Only the content address goes on-chain; the blob stays in the evidence store
+        if let Some(evidence) = evidence {
+            report.evidence_hash = Some(self.store_evidence(&evidence).await?);
+        }
         
         // Create transaction
         let transaction = Transaction::TrustReport(report);
         let transaction_id = transaction.id();
         
-        // Update participant nonce
-        self.participant_nonces.insert(reporter_id.to_string(), nonce);
-        
-        // Add to pending transactions
+        // Add to pending transactions; its nonce counts once the block commits
         {
             let mut pending = self.pending_transactions.write().await;
+            // Re-check under the lock so concurrent submissions can't share a nonce
+            let taken = pending.iter().any(|tx| matches!(tx, Transaction::TrustReport(r) if r.reporter_id == reporter_id && r.nonce >= nonce));
+            if taken {
+                return Err(anyhow::anyhow!("Invalid nonce for transaction"));
+            }
             pending.push(transaction);
         }
         
@@ -301,89 +606,265 @@ impl SynapseBlockchain {
     }
     
     /// Get trust score from blockchain
+    ///
+    /// On a federated chain, discounted scores from other chains are
+    /// averaged in; see [`SynapseBlockchain::get_trust_score_with_provenance`].
     pub async fn get_trust_score(&self, participant_id: &str) -> Result<f64> {
+        Ok(self.get_trust_score_with_provenance(participant_id).await?.score)
+    }
+    
+    /// Trust score along with the chains it was computed from
+    pub async fn get_trust_score_with_provenance(&self, participant_id: &str) -> Result<ProvenancedScore> {
+        if self.is_redacted(participant_id) {
+            return Ok(ProvenancedScore::from_sources(Vec::new()));
+        }
+        let mut sources = Vec::new();
+        if let Some(local) = self.local_score(participant_id).await {
+            let chain_id = self.federation.as_ref().map(|f| f.chain_id().to_string()).unwrap_or_else(|| "local".to_string());
+            sources.push(ScoreSource { chain_id, score: local.score, reports: local.reports, discount: 1.0, block: None });
+        }
+        if let Some(federation) = &self.federation {
+            sources.extend(federation.foreign_scores(participant_id));
+        }
+        Ok(ProvenancedScore::from_sources(sources))
+    }
+    
+    /// Score from this chain's own reports, or None if there are none
+    async fn local_score(&self, participant_id: &str) -> Option<SnapshotScore> {
         let chain = self.chain.read().await;
-        let mut total_score = 0.0;
-        let mut report_count = 0;
         
         // Scan all blocks for trust reports about this participant
-        for block in chain.iter() {
-            for transaction in &block.transactions {
-                if let Transaction::TrustReport(report) = transaction {
-                    if report.subject_id == participant_id {
-                        // Weight recent reports more heavily
-                        let age_days = (Utc::now() - report.timestamp.0).num_days();
-                        let weight = if age_days < 30 { 1.0 } else { 0.5 };
-                        
-                        total_score += report.score as f64 * weight;
-                        report_count += 1;
-                    }
+        let reports: Vec<&TrustReport> = chain.iter().flat_map(|block| &block.transactions).filter_map(|transaction| match transaction {
+            Transaction::TrustReport(report) if report.subject_id == participant_id && !self.is_annulled(&report.id) => {
+                Some(report)
+            }
+            _ => None,
+        }).collect();
+        let local = match self.base_scores.get(participant_id) {
+            // Bootstrapped nodes average later reports into the snapshot score
+            Some(base) => SnapshotScore {
+                score: base.combine(reports.iter().copied()),
+                reports: base.reports + reports.len() as u64,
+            },
+            None => SnapshotScore { score: score_reports(reports.iter().copied()), reports: reports.len() as u64 },
+        };
+        (local.reports > 0).then_some(local)
+    }
+    
+    /// A trust report by report or transaction ID, and whether it is committed
+    ///
+    /// Looks in committed blocks, then pending transactions.
+    pub async fn find_trust_report(&self, report_id: &str) -> Option<(TrustReport, bool)> {
+        let find = |transactions: &[Transaction]| {
+            transactions.iter().find_map(|transaction| match transaction {
+                Transaction::TrustReport(report) if report.id == report_id || transaction.id() == report_id => {
+                    Some(report.clone())
                 }
+                _ => None,
+            })
+        };
+        if let Some(report) = self.chain.read().await.iter().find_map(|block| find(&block.transactions)) {
+            return Some((report, true));
+        }
+        find(&self.pending_transactions.read().await).map(|report| (report, false))
+    }
+    
+    /// Check evidence against the evidence rules and store it, returning its content address
+    pub async fn store_evidence(&self, evidence: &str) -> Result<String> {
+        evidence::validate_evidence(evidence)?;
+        let content_hash = crate::synapse::storage::attachments::content_address(evidence.as_bytes());
+        self.evidence.put_evidence(&content_hash, evidence.as_bytes()).await?;
+        Ok(content_hash)
+    }
+    
+    /// Evidence content by content address, fetched from `holder` through
+    /// `source` if not held locally
+    ///
+    /// Content is only returned, and fetched content only kept, once it
+    /// matches the address and passes the evidence rules.
+    pub async fn fetch_evidence(
+        &self,
+        content_hash: &str,
+        holder: &str,
+        source: Option<&dyn EvidenceSource>,
+    ) -> Result<String> {
+        let (data, fetched) = match self.evidence.get_evidence(content_hash).await? {
+            Some(data) => (data, false),
+            None => {
+                let source = source.ok_or_else(|| anyhow::anyhow!("Evidence {} is not held locally", content_hash))?;
+                (source.fetch(holder, content_hash).await?, true)
             }
+        };
+        let content = evidence::verify_evidence(content_hash, &data)?;
+        if fetched {
+            self.evidence.put_evidence(content_hash, &data).await?;
         }
-        
-        if report_count == 0 {
-            Ok(0.0) // No reports = neutral score
-        } else {
-            Ok(total_score / report_count as f64)
+        Ok(content)
+    }
+    
+    /// Evidence submitted with a report, for verifiers resolving a dispute
+    ///
+    /// `report_id` is the report's ID or the transaction ID returned by
+    /// [`SynapseBlockchain::submit_trust_report`]. Looks the report up in
+    /// committed blocks, then pending transactions.
+    /// Evidence not held locally is fetched from `source` and kept once it
+    /// matches the on-chain hash. Returns None if the report is unknown,
+    /// has no evidence, or mentions an erased participant.
+    pub async fn report_evidence(
+        &self,
+        report_id: &str,
+        source: Option<&dyn EvidenceSource>,
+    ) -> Result<Option<ReportEvidence>> {
+        let Some((report, committed)) = self.find_trust_report(report_id).await else {
+            return Ok(None);
+        };
+        if self.is_redacted(&report.reporter_id) || self.is_redacted(&report.subject_id) {
+            return Ok(None);
         }
+        let Some(content_hash) = report.evidence_hash.clone() else {
+            return Ok(None);
+        };
+        let content = self.fetch_evidence(&content_hash, &report.reporter_id, source).await?;
+        
+        Ok(Some(ReportEvidence {
+            report_id: report.id,
+            reporter_id: report.reporter_id,
+            subject_id: report.subject_id,
+            content_hash,
+            content,
+            committed,
+        }))
     }
     
-    /// Process trust point decay
+    /// Headers of the blocks after `from`, at most `limit` of them
+    pub async fn headers_after(&self, from: u64, limit: usize) -> Vec<BlockHeader> {
+        let chain = self.chain.read().await;
+        chain.iter().filter(|block| block.number > from).take(limit).map(Block::header).collect()
+    }
+    
+    /// Merkle proofs for the trust reports about `subjects` in blocks after `since`
+    ///
+    /// Reports that mention an erased participant are left out, since the
+    /// proof would reveal the original IDs.
+    pub async fn trust_proofs(&self, subjects: &[String], since: u64) -> Vec<TrustProof> {
+        let chain = self.chain.read().await;
+        let mut proofs = Vec::new();
+        for block in chain.iter().filter(|block| block.number > since) {
+            for (index, transaction) in block.transactions.iter().enumerate() {
+                let Transaction::TrustReport(report) = transaction else {
+                    continue;
+                };
+                if !subjects.contains(&report.subject_id)
+                    || self.is_annulled(&report.id)
+                    || self.is_redacted(&report.subject_id)
+                    || self.is_redacted(&report.reporter_id)
+                {
+                    continue;
+                }
+                if let Some(proof) = block.prove(index) {
+                    proofs.push(TrustProof { report: report.clone(), proof });
+                }
+            }
+        }
+        proofs
+    }
+    
+    /// Answer a light client's request; responses and unknown messages get no reply
+    pub async fn serve_light_client(&self, request: &LightClientMessage) -> Option<LightClientMessage> {
+        match request {
+            LightClientMessage::HeadersRequest { from } => Some(LightClientMessage::Headers {
+                headers: self.headers_after(*from, light_client::MAX_HEADERS_PER_RESPONSE).await,
+            }),
+            LightClientMessage::ProofsRequest { subjects, since } => {
+                let through = self.chain.read().await.last().map(|block| block.number).unwrap_or(0);
+                Some(LightClientMessage::Proofs {
+                    subjects: subjects.clone(),
+                    through,
+                    proofs: self.trust_proofs(subjects, *since).await,
+                })
+            }
+            _ => None,
+        }
+    }
+    
+    /// Process trust point decay for staked balances
+    ///
+    /// Runs the shared [`DecayScheduler`](crate::synapse::services::DecayScheduler)
+    /// over the staking manager; nodes with a database should let the
+    /// `TrustManager`'s scheduler handle decay instead.
     pub async fn process_trust_decay(&self) -> Result<Vec<String>> {
-        let decay_rate = self.config.trust_decay_config.monthly_decay_rate;
-        let min_activity_days = self.config.trust_decay_config.min_activity_days;
-        
-        // Get cutoff time for decay processing
-        let cutoff_time = Utc::now() - chrono::Duration::days(min_activity_days as i64);
-        
-        // Fetch participants with balances that need decay processing
-        let participants = self.staking_manager.get_all_participants().await?;
-        let mut processed = Vec::new();
+        let report = self.decay.run_once(Utc::now()).await?;
+        Ok(report.processed)
+    }
+    
+    /// Stop counting a trust report, e.g. after a dispute found it false
+    ///
+    /// `report_id` is the report's own ID. The report stays in its block and
+    /// a pending report is still committed, but neither counts towards the
+    /// subject's score.
+    pub fn annul_report(&self, report_id: &str) {
+        if self.annulled.insert(report_id.to_string()) {
+            info!("Annulled trust report {}", report_id);
+        }
+    }
+    
+    /// Whether a trust report has been annulled
+    pub fn is_annulled(&self, report_id: &str) -> bool {
+        self.annulled.contains(report_id)
+    }
+    
+    /// Whether a participant's references have been redacted
+    pub fn is_redacted(&self, participant_id: &str) -> bool {
+        self.redacted.contains(&format!("{:x}", Sha256::digest(participant_id.as_bytes())))
+    }
+    
+    /// Erase a participant from the ledger as far as an append-only chain allows
+    ///
+    /// Uncommitted transactions that mention the participant are dropped and
+    /// their nonce forgotten. Committed blocks are hash-linked and stay as
+    /// they are; from now on [`SynapseBlockchain::redacted_chain`] shows the
+    /// participant only as its [`crate::purge::pseudonym`] and its trust
+    /// score reads as neutral. Returns how many committed transactions
+    /// mention the participant.
+    pub async fn redact_participant(&self, participant_id: &str) -> usize {
+        self.redacted.insert(format!("{:x}", Sha256::digest(participant_id.as_bytes())));
+        self.participant_nonces.forget(participant_id);
+        if let Some(federation) = &self.federation {
+            federation.forget(participant_id);
+        }
+        self.pending_transactions.write().await.retain(|tx| !tx.mentions(participant_id));
         
-        for participant_id in participants {
-            let balances = self.staking_manager.get_participant_balances(&participant_id).await?;
-            
-            for mut balance in balances {
-                // Check if decay should be applied
-                if balance.last_activity.clone().into_inner() < cutoff_time {
-                    // Calculate days since last activity
-                    let duration = Utc::now() - balance.last_activity.clone().into_inner();
-                    let days_inactive = duration.num_days() as f64;
-                    let months_inactive = days_inactive / 30.0;
-                    
-                    // Calculate decay amount
-                    let decay_amount = (balance.total_points as f64 * decay_rate * months_inactive) as u32;
-                    
-                    if decay_amount > 0 {
-                        // Apply decay
-                        balance.total_points = balance.total_points.saturating_sub(decay_amount);
-                        balance.available_points = balance.available_points.saturating_sub(decay_amount);
-                        
-                        // Don't decay below staked amount
-                        if balance.available_points < balance.staked_points {
-                            balance.available_points = balance.staked_points;
-                            balance.total_points = balance.staked_points;
+        let chain = self.chain.read().await;
+        let committed = chain
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .filter(|tx| tx.mentions(participant_id))
+            .count();
+        info!("Redacted {} committed transactions for an erased participant", committed);
+        committed
+    }
+    
+    /// The chain with every redacted participant replaced by its pseudonym
+    ///
+    /// Block hashes are those committed, so blocks that mention a redacted
+    /// participant no longer verify on their own; check integrity against the
+    /// node's own chain instead.
+    pub async fn redacted_chain(&self) -> Vec<Block> {
+        let chain = self.chain.read().await;
+        chain
+            .iter()
+            .map(|block| {
+                let mut block = block.clone();
+                for tx in &mut block.transactions {
+                    for id in tx.participant_ids_mut() {
+                        if self.is_redacted(id) {
+                            *id = crate::purge::pseudonym(id);
                         }
-                        
-                        // Update balance
-                        self.staking_manager.update_balance(&participant_id, &balance).await?;
-                        
-                        // Add to processed list
-                        processed.push(participant_id.clone());
-                        
-                        info!(
-                            "Applied decay to {}: -{} points ({} days inactive)",
-                            participant_id,
-                            decay_amount,
-                            days_inactive
-                        );
                     }
                 }
-            }
-        }
-        
-        Ok(processed)
+                block
+            })
+            .collect()
     }
     
     /// Get blockchain statistics
@@ -409,11 +890,38 @@ impl SynapseBlockchain {
             pending_transactions: pending.len(),
             total_trust_reports: total_reports,
             total_stakes: total_stakes,
-            last_block_time: chain.last().map(|b| b.timestamp.0),
+            last_block_time: chain.last().map(|b| b.timestamp),
         })
     }
 }
 
+/// Trust score from the reports about one participant
+///
+/// Reports older than 30 days count half; no reports is a neutral 0.
+pub fn score_reports<'a>(reports: impl IntoIterator<Item = &'a TrustReport>) -> f64 {
+    score_reports_at(reports, Utc::now())
+}
+
+/// Trust score from the reports about one participant, as of `at`
+pub fn score_reports_at<'a>(reports: impl IntoIterator<Item = &'a TrustReport>, at: DateTime<Utc>) -> f64 {
+    let mut total_score = 0.0;
+    let mut report_count = 0;
+    for report in reports {
+        // Weight recent reports more heavily
+        let age_days = (at - report.timestamp).num_days();
+        let weight = if age_days < 30 { 1.0 } else { 0.5 };
+        
+        total_score += report.score as f64 * weight;
+        report_count += 1;
+    }
+    
+    if report_count == 0 {
+        0.0 // No reports = neutral score
+    } else {
+        total_score / report_count as f64
+    }
+}
+
 /// Blockchain statistics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockchainStats {
@@ -0,0 +1,210 @@
+// Synapse Blockchain Nonces
+// Replay protection checked against committed transactions
+
+//! Every trust report carries its reporter's nonce, which must be one more
+//! than the reporter's previous report. The [`NonceLedger`] tracks the
+//! highest nonce each participant has in committed blocks and persists it
+//! through a [`NonceStore`], so a restarted node keeps rejecting replays and
+//! nodes that share a database see each other's commits.
+
+use super::block::{Block, Transaction};
+#[cfg(feature = "database")]
+use crate::synapse::storage::Database;
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Where committed nonces are kept across restarts
+#[async_trait]
+pub trait NonceStore: Send + Sync {
+    async fn load_nonce(&self, participant_id: &str) -> Result<Option<u64>>;
+
+    async fn load_nonces(&self) -> Result<BTreeMap<String, u64>>;
+
+    /// Raise the stored nonce to `nonce`; never lowers it
+    async fn save_nonce(&self, participant_id: &str, nonce: u64) -> Result<()>;
+}
+
+/// Nonces kept in memory; they are lost on restart
+#[derive(Default)]
+pub struct MemoryNonceStore {
+    nonces: DashMap<String, u64>,
+}
+
+impl MemoryNonceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl NonceStore for MemoryNonceStore {
+    async fn load_nonce(&self, participant_id: &str) -> Result<Option<u64>> {
+        Ok(self.nonces.get(participant_id).map(|nonce| *nonce))
+    }
+
+    async fn load_nonces(&self) -> Result<BTreeMap<String, u64>> {
+        Ok(self.nonces.iter().map(|entry| (entry.key().clone(), *entry.value())).collect())
+    }
+
+    async fn save_nonce(&self, participant_id: &str, nonce: u64) -> Result<()> {
+        let mut entry = self.nonces.entry(participant_id.to_string()).or_insert(0);
+        *entry = (*entry).max(nonce);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "database")]
+#[async_trait]
+impl NonceStore for Database {
+    async fn load_nonce(&self, participant_id: &str) -> Result<Option<u64>> {
+        self.get_participant_nonce(participant_id).await
+    }
+
+    async fn load_nonces(&self) -> Result<BTreeMap<String, u64>> {
+        Ok(self.list_participant_nonces().await?.into_iter().collect())
+    }
+
+    async fn save_nonce(&self, participant_id: &str, nonce: u64) -> Result<()> {
+        self.advance_participant_nonce(participant_id, nonce).await
+    }
+}
+
+/// Highest committed nonce per participant
+pub struct NonceLedger {
+    committed: DashMap<String, u64>,
+    store: Arc<dyn NonceStore>,
+}
+
+impl NonceLedger {
+    pub fn new(store: Arc<dyn NonceStore>) -> Self {
+        Self { committed: DashMap::new(), store }
+    }
+
+    /// Take in nonces persisted by earlier runs or other nodes
+    pub async fn load(&self) -> Result<usize> {
+        let stored = self.store.load_nonces().await?;
+        let count = stored.len();
+        self.restore(&stored);
+        Ok(count)
+    }
+
+    /// Take in nonces from a snapshot or store, keeping any higher ones already known
+    pub fn restore(&self, nonces: &BTreeMap<String, u64>) {
+        for (participant, nonce) in nonces {
+            self.raise(participant, *nonce);
+        }
+    }
+
+    /// Take in the nonces of every report in `chain`
+    pub fn replay(&self, chain: &[Block]) {
+        for block in chain {
+            for (reporter, nonce) in report_nonces(block) {
+                self.raise(reporter, nonce);
+            }
+        }
+    }
+
+    fn raise(&self, participant_id: &str, nonce: u64) {
+        let mut entry = self.committed.entry(participant_id.to_string()).or_insert(0);
+        *entry = (*entry).max(nonce);
+    }
+
+    /// Highest committed nonce, re-read from the store so commits by other
+    /// nodes sharing it are seen
+    pub async fn committed(&self, participant_id: &str) -> Result<u64> {
+        if let Some(stored) = self.store.load_nonce(participant_id).await? {
+            self.raise(participant_id, stored);
+        }
+        Ok(self.committed.get(participant_id).map(|nonce| *nonce).unwrap_or(0))
+    }
+
+    /// Keep the transactions whose nonces continue their reporter's sequence
+    ///
+    /// A report whose nonce was already committed, possibly by another node,
+    /// or that skips ahead is dropped.
+    pub async fn admit(&self, transactions: Vec<Transaction>) -> Result<Vec<Transaction>> {
+        let mut expected: BTreeMap<String, u64> = BTreeMap::new();
+        let mut admitted = Vec::with_capacity(transactions.len());
+        for transaction in transactions {
+            if let Transaction::TrustReport(report) = &transaction {
+                let next = match expected.get(&report.reporter_id) {
+                    Some(next) => *next,
+                    None => self.committed(&report.reporter_id).await? + 1,
+                };
+                if report.nonce != next {
+                    warn!(
+                        "Dropping report {} from {}: nonce {} out of sequence, expected {}",
+                        report.id, report.reporter_id, report.nonce, next
+                    );
+                    continue;
+                }
+                expected.insert(report.reporter_id.clone(), next + 1);
+            }
+            admitted.push(transaction);
+        }
+        Ok(admitted)
+    }
+
+    /// Record and persist the nonces in a newly committed block
+    pub async fn commit(&self, block: &Block) -> Result<()> {
+        for (reporter, nonce) in report_nonces(block) {
+            self.raise(reporter, nonce);
+            self.store.save_nonce(reporter, nonce).await?;
+        }
+        Ok(())
+    }
+
+    /// Drop a participant's nonce from memory; the store is purged separately
+    pub fn forget(&self, participant_id: &str) {
+        self.committed.remove(participant_id);
+    }
+
+    /// Every known committed nonce, for snapshots
+    pub fn nonces(&self) -> BTreeMap<String, u64> {
+        self.committed.iter().map(|entry| (entry.key().clone(), *entry.value())).collect()
+    }
+}
+
+fn report_nonces(block: &Block) -> impl Iterator<Item = (&str, u64)> {
+    block.transactions.iter().filter_map(|transaction| match transaction {
+        Transaction::TrustReport(report) => Some((report.reporter_id.as_str(), report.nonce)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synapse::blockchain::block::{TrustReport, TrustReportType};
+
+    fn report(reporter: &str, nonce: u64) -> Transaction {
+        let mut report = TrustReport::new(reporter.into(), "alice".into(), TrustReportType::Positive, 50, "general".into(), 10);
+        report.nonce = nonce;
+        Transaction::TrustReport(report)
+    }
+
+    #[tokio::test]
+    async fn test_nonces_survive_restart() {
+        let store = Arc::new(MemoryNonceStore::new());
+        let ledger = NonceLedger::new(store.clone());
+
+        let admitted = ledger.admit(vec![report("bob", 1), report("bob", 2), report("bob", 2), report("carol", 3)]).await.unwrap();
+        assert_eq!(admitted.len(), 2);
+        let block = Block::new(1, Block::genesis().hash, admitted, "v1".into());
+        ledger.commit(&block).await.unwrap();
+        assert_eq!(ledger.committed("bob").await.unwrap(), 2);
+
+        // A new node on the same store rejects the replayed reports
+        let restarted = NonceLedger::new(store.clone());
+        assert_eq!(restarted.load().await.unwrap(), 1);
+        assert!(restarted.admit(vec![report("bob", 2)]).await.unwrap().is_empty());
+
+        // Commits by another node are picked up from the store
+        store.save_nonce("bob", 5).await.unwrap();
+        assert_eq!(restarted.admit(vec![report("bob", 6)]).await.unwrap().len(), 1);
+    }
+}
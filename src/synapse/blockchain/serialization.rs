@@ -1,9 +1,23 @@
+//! Serialization of blockchain types
+//!
+//! Blockchain types hold plain `DateTime<Utc>` and `Uuid` fields, annotated
+//! with the [`timestamp`] and [`uuid_bytes`] serde modules, and are encoded
+//! with [`to_canonical_bytes`] for storage, hashing and the wire. Human-readable
+//! formats (JSON) get RFC 3339 timestamps and hyphenated UUIDs; the canonical
+//! binary encoding gets `(seconds, nanoseconds)` and the 16 UUID bytes.
+//!
+//! Both are byte-for-byte what the older [`DateTimeWrapper`] and
+//! [`UuidWrapper`] produced, so chains stored or hashed before the wrappers
+//! were dropped from the blockchain types still read back unchanged. The
+//! wrappers remain for the message types that use them.
+
 use bincode::{Encode, Decode, BorrowDecode};
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use uuid::Uuid;
+use ::uuid::Uuid;
 use std::fmt;
 
+/// Legacy timestamp wrapper; new code uses `DateTime<Utc>` with [`timestamp`]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DateTimeWrapper(pub DateTime<Utc>);
 
@@ -13,6 +27,7 @@ impl Default for DateTimeWrapper {
     }
 }
 
+/// Legacy UUID wrapper; new code uses `Uuid` with [`uuid_bytes`]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UuidWrapper(pub Uuid);
 
@@ -129,3 +144,129 @@ impl<'de, Context> BorrowDecode<'de, Context> for UuidWrapper {
         Ok(UuidWrapper(Uuid::from_bytes(bytes)))
     }
 }
+
+/// Canonical encoding of a blockchain value
+///
+/// Fields are written in declaration order with variable-length integers, so
+/// equal values always encode to equal bytes. Types encoded this way must not
+/// contain hash maps; use `BTreeMap`.
+pub fn to_canonical_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>, bincode::error::EncodeError> {
+    bincode::serde::encode_to_vec(value, bincode::config::standard())
+}
+
+/// Decode a value written by [`to_canonical_bytes`]
+pub fn from_canonical_bytes<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, bincode::error::DecodeError> {
+    bincode::serde::decode_from_slice(bytes, bincode::config::standard()).map(|(value, _)| value)
+}
+
+/// `#[serde(with = "timestamp")]` for `DateTime<Utc>` fields
+pub mod timestamp {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            // The form chrono itself writes, so existing JSON round-trips as is
+            serializer.serialize_str(&value.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        } else {
+            (value.timestamp(), value.timestamp_subsec_nanos()).serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?
+                .parse::<DateTime<Utc>>()
+                .map_err(D::Error::custom)
+        } else {
+            let (seconds, nanos) = <(i64, u32)>::deserialize(deserializer)?;
+            DateTime::<Utc>::from_timestamp(seconds, nanos).ok_or_else(|| D::Error::custom("Invalid DateTime values"))
+        }
+    }
+
+    /// `#[serde(with = "timestamp::option")]` for `Option<DateTime<Utc>>` fields
+    pub mod option {
+        use chrono::{DateTime, Utc};
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        #[derive(Serialize, Deserialize)]
+        struct Timestamp(#[serde(with = "super")] DateTime<Utc>);
+
+        pub fn serialize<S: Serializer>(value: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+            value.map(Timestamp).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+            Ok(Option::<Timestamp>::deserialize(deserializer)?.map(|timestamp| timestamp.0))
+        }
+    }
+}
+
+/// `#[serde(with = "uuid_bytes")]` for `Uuid` fields
+pub mod uuid_bytes {
+    use ::uuid::Uuid;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Uuid, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(&value.hyphenated())
+        } else {
+            value.as_bytes().serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uuid, D::Error> {
+        if deserializer.is_human_readable() {
+            Uuid::parse_str(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+        } else {
+            Ok(Uuid::from_bytes(<[u8; 16]>::deserialize(deserializer)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        #[serde(with = "uuid_bytes")]
+        id: Uuid,
+        #[serde(with = "timestamp")]
+        at: DateTime<Utc>,
+        #[serde(with = "timestamp::option")]
+        until: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Encode)]
+    struct LegacyRecord {
+        id: UuidWrapper,
+        at: DateTimeWrapper,
+        until: Option<DateTimeWrapper>,
+    }
+
+    #[test]
+    fn plain_fields_round_trip_and_read_legacy_encodings() {
+        let at = DateTime::<Utc>::from_timestamp(1_700_000_000, 123_456_789).unwrap();
+        let record = Record { id: Uuid::new_v4(), at, until: Some(at + chrono::Duration::days(1)) };
+
+        let bytes = to_canonical_bytes(&record).unwrap();
+        assert_eq!(from_canonical_bytes::<Record>(&bytes).unwrap(), record);
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(serde_json::from_str::<Record>(&json).unwrap(), record);
+
+        // Same bytes and JSON as the wrappers, so existing chain data still decodes
+        let legacy = LegacyRecord {
+            id: UuidWrapper::new(record.id),
+            at: DateTimeWrapper::new(record.at),
+            until: record.until.map(DateTimeWrapper::new),
+        };
+        assert_eq!(bincode::encode_to_vec(&legacy, bincode::config::standard()).unwrap(), bytes);
+        let legacy_json = serde_json::json!({
+            "id": UuidWrapper::new(record.id),
+            "at": DateTimeWrapper::new(record.at),
+            "until": record.until.map(DateTimeWrapper::new),
+        });
+        assert_eq!(serde_json::from_value::<Record>(legacy_json).unwrap(), record);
+    }
+}
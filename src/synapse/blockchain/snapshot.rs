@@ -0,0 +1,218 @@
+// Synapse Blockchain State Snapshots
+// Signed checkpoints of ledger state so new nodes need not replay the chain
+
+//! Validators periodically capture the derived state of the trust ledger —
+//! balances, scores and nonces — at a block, and sign it. A fresh node that
+//! trusts a set of validator keys can then start from any snapshot carrying
+//! a quorum of their signatures instead of replaying every block; see
+//! [`SynapseBlockchain::bootstrap_from_snapshot`](super::SynapseBlockchain::bootstrap_from_snapshot).
+
+use super::block::{BlockHeader, Transaction, TrustReport};
+use super::score_reports;
+use crate::crypto::CryptoManager;
+use crate::identity::LocalIdentity;
+use crate::synapse::blockchain::serialization::timestamp;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+/// A participant's trust score at the snapshot block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotScore {
+    pub score: f64,
+    /// Reports the score was computed from, so later reports can be averaged in
+    pub reports: u64,
+}
+
+impl SnapshotScore {
+    /// Score with later reports averaged in
+    pub fn combine<'a>(&self, later: impl IntoIterator<Item = &'a TrustReport>) -> f64 {
+        let later: Vec<&TrustReport> = later.into_iter().collect();
+        let total = self.reports + later.len() as u64;
+        if total == 0 {
+            return 0.0;
+        }
+        let later_sum = score_reports(later.iter().copied()) * later.len() as f64;
+        (self.score * self.reports as f64 + later_sum) / total as f64
+    }
+}
+
+/// A validator's signature over a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotSignature {
+    pub validator: String,
+    pub signature: Vec<u8>,
+}
+
+/// Ledger state as of one block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    /// Header of the block the state was taken at; new blocks build on it
+    pub header: BlockHeader,
+    #[serde(with = "timestamp")]
+    pub taken_at: DateTime<Utc>,
+    /// Participant -> total trust points
+    pub balances: BTreeMap<String, u32>,
+    pub scores: BTreeMap<String, SnapshotScore>,
+    /// Participant -> last transaction nonce, for replay protection
+    pub nonces: BTreeMap<String, u64>,
+    pub signatures: Vec<SnapshotSignature>,
+}
+
+impl StateSnapshot {
+    /// Capture the state derived from `chain`, whose last block becomes the snapshot block
+    pub fn capture(
+        chain: &[super::Block],
+        balances: BTreeMap<String, u32>,
+        nonces: BTreeMap<String, u64>,
+    ) -> Result<Self> {
+        Self::capture_excluding(chain, balances, nonces, |_| false)
+    }
+
+    /// Capture the state like [`StateSnapshot::capture`], leaving out reports
+    /// for which `excluded` is true, such as annulled ones
+    pub fn capture_excluding(
+        chain: &[super::Block],
+        balances: BTreeMap<String, u32>,
+        nonces: BTreeMap<String, u64>,
+        excluded: impl Fn(&TrustReport) -> bool,
+    ) -> Result<Self> {
+        let last = chain.last().ok_or_else(|| anyhow::anyhow!("Cannot snapshot an empty chain"))?;
+        let mut reports: BTreeMap<&str, Vec<&TrustReport>> = BTreeMap::new();
+        for transaction in chain.iter().flat_map(|block| &block.transactions) {
+            if let Transaction::TrustReport(report) = transaction {
+                if excluded(report) {
+                    continue;
+                }
+                reports.entry(report.subject_id.as_str()).or_default().push(report);
+            }
+        }
+        let scores = reports
+            .into_iter()
+            .map(|(subject, reports)| {
+                let score = SnapshotScore { score: score_reports(reports.iter().copied()), reports: reports.len() as u64 };
+                (subject.to_string(), score)
+            })
+            .collect();
+
+        Ok(Self {
+            header: last.header(),
+            taken_at: Utc::now(),
+            balances,
+            scores,
+            nonces,
+            signatures: Vec::new(),
+        })
+    }
+
+    /// SHA-256 over the block and state, excluding signatures
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.header.number.to_be_bytes());
+        hasher.update(&self.header.hash);
+        hasher.update(self.taken_at.timestamp().to_be_bytes());
+        // BTreeMaps serialize in key order, so the encoding is canonical
+        hasher.update(serde_json::to_vec(&(&self.balances, &self.scores, &self.nonces)).unwrap_or_default());
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Canonical string covered by validator signatures
+    pub fn signing_payload(&self) -> String {
+        format!("synapse-snapshot:{}:{}", self.header.number, self.digest())
+    }
+
+    /// Add `validator`'s signature
+    pub fn sign(&mut self, validator: &LocalIdentity) -> Result<()> {
+        let signature = validator.sign(&self.signing_payload())?;
+        self.signatures.retain(|s| s.validator != validator.global_id);
+        self.signatures.push(SnapshotSignature { validator: validator.global_id.clone(), signature });
+        Ok(())
+    }
+
+    /// Take over signatures from another copy of the same snapshot
+    ///
+    /// Returns false if `other` captures different state.
+    pub fn merge_signatures(&mut self, other: &StateSnapshot) -> bool {
+        if other.digest() != self.digest() {
+            return false;
+        }
+        for signature in &other.signatures {
+            if !self.signatures.iter().any(|s| s.validator == signature.validator) {
+                self.signatures.push(signature.clone());
+            }
+        }
+        true
+    }
+
+    /// Check that at least `quorum` of the given validators signed the snapshot
+    ///
+    /// `validators` maps validator global IDs to public key PEMs; signatures
+    /// from anyone else are ignored. Returns the number of valid signatures.
+    pub fn verify_quorum(&self, validators: &HashMap<String, String>, quorum: usize) -> Result<usize> {
+        if self.header.hash != self.header.calculate_hash() {
+            return Err(anyhow::anyhow!("Snapshot block header does not match its hash"));
+        }
+        let payload = self.signing_payload();
+        let mut crypto = CryptoManager::new();
+        let mut valid = 0;
+        for signature in &self.signatures {
+            let Some(public_key) = validators.get(&signature.validator) else {
+                continue;
+            };
+            crypto.import_public_key(&signature.validator, public_key)?;
+            if crypto.verify_signature(&payload, &signature.signature, &signature.validator).unwrap_or(false) {
+                valid += 1;
+            }
+        }
+        if valid < quorum {
+            return Err(anyhow::anyhow!("Snapshot has {} valid validator signatures, {} required", valid, quorum));
+        }
+        Ok(valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::TransportBindings;
+    use crate::synapse::blockchain::block::{Block, TrustReportType};
+
+    fn validator(name: &str) -> (LocalIdentity, String) {
+        let identity = LocalIdentity::new(name, &format!("{}@example.com", name), TransportBindings::default());
+        let public_key = identity.generate_keypair().unwrap();
+        (identity, public_key)
+    }
+
+    #[test]
+    fn test_snapshot_quorum() {
+        let genesis = Block::genesis();
+        let report = TrustReport::new("bob".into(), "alice".into(), TrustReportType::Positive, 80, "general".into(), 10);
+        let block = Block::new(1, genesis.hash.clone(), vec![Transaction::TrustReport(report)], "v1".into());
+        let balances = BTreeMap::from([("alice".to_string(), 108)]);
+        let nonces = BTreeMap::from([("bob".to_string(), 1)]);
+        let mut snapshot = StateSnapshot::capture(&[genesis, block], balances, nonces).unwrap();
+        assert_eq!(snapshot.header.number, 1);
+        assert_eq!(snapshot.scores["alice"], SnapshotScore { score: 80.0, reports: 1 });
+
+        let (v1, k1) = validator("v1");
+        let (v2, k2) = validator("v2");
+        let (outsider, _) = validator("outsider");
+        let keys = HashMap::from([(v1.global_id.clone(), k1), (v2.global_id.clone(), k2)]);
+
+        snapshot.sign(&v1).unwrap();
+        snapshot.sign(&outsider).unwrap();
+        assert!(snapshot.verify_quorum(&keys, 2).is_err());
+
+        let mut copy = snapshot.clone();
+        copy.signatures.clear();
+        copy.sign(&v2).unwrap();
+        assert!(snapshot.merge_signatures(&copy));
+        assert_eq!(snapshot.verify_quorum(&keys, 2).unwrap(), 2);
+
+        // Tampered state invalidates every signature
+        snapshot.balances.insert("mallory".to_string(), 10_000);
+        assert!(snapshot.verify_quorum(&keys, 1).is_err());
+    }
+}
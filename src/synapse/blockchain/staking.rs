@@ -4,11 +4,12 @@
 use super::block::{Block, Transaction, StakePurpose};
 use crate::synapse::models::trust::TrustBalance;
 use anyhow::Result;
-use bincode::{Decode, Encode};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use sha2::Digest;
 use serde::{Deserialize, Serialize};
-use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
+use crate::synapse::blockchain::serialization::{timestamp, DateTimeWrapper};
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
@@ -18,6 +19,9 @@ pub struct StakingManager {
     config: super::StakingRequirements,
     chain: Arc<RwLock<Vec<Block>>>,
     active_stakes: DashMap<String, Vec<ActiveStake>>, // participant_id -> stakes
+    delegations: DashMap<String, Vec<Delegation>>, // validator -> delegations to it
+    unbonding: DashMap<String, Vec<UnbondingStake>>, // participant_id -> points on their way back
+    adjustments: DashMap<String, i64>, // participant_id -> rewards minus penalties
 }
 
 impl StakingManager {
@@ -30,6 +34,9 @@ impl StakingManager {
             config,
             chain,
             active_stakes: DashMap::new(),
+            delegations: DashMap::new(),
+            unbonding: DashMap::new(),
+            adjustments: DashMap::new(),
         })
     }
     
@@ -69,27 +76,27 @@ impl StakingManager {
                     }
                     // Trust reports can award points if verified
                     Transaction::TrustReport(report) if report.subject_id == participant_id => {
-                        if report.score > 0 {
-                            // Positive reports award points (simplified - would need consensus verification)
-                            let awarded = (report.score as u32 * report.stake_amount) / 100;
-                            total_points += awarded;
-                        }
+                        total_points += report.points_awarded();
                     }
                     _ => {}
                 }
             }
         }
         
-        Ok(total_points)
+        // Delegation rewards and slashing penalties
+        let adjustment = self.adjustments.get(participant_id).map(|a| *a).unwrap_or(0);
+        Ok((total_points as i64 + adjustment).max(0) as u32)
     }
     
-    /// Get currently staked points for participant
+    /// Get points that are not available: own stakes, delegations and points still unbonding
     pub async fn get_staked_points(&self, participant_id: &str) -> Result<u32> {
         let stakes = self.active_stakes.get(participant_id)
             .map(|entry| entry.value().clone())
             .unwrap_or_default();
+        let delegated: u32 = self.delegations_from(participant_id).await?.iter().map(|d| d.amount).sum();
+        let unbonding: u32 = self.pending_unbonding(participant_id).await?.iter().map(|u| u.amount).sum();
         
-        Ok(stakes.iter().map(|s| s.amount).sum())
+        Ok(stakes.iter().map(|s| s.amount).sum::<u32>() + delegated + unbonding)
     }
     
     /// Stake trust points for a specific purpose
@@ -115,12 +122,12 @@ impl StakingManager {
         
         // Create stake record
         let stake = ActiveStake {
-            id: UuidWrapper::new(Uuid::new_v4()).to_string(),
+            id: Uuid::new_v4().to_string(),
             participant_id: participant_id.to_string(),
             amount,
             purpose,
-            staked_at: DateTimeWrapper::new(Utc::now()),
-            locked_until: None, // Can be set based on purpose
+            staked_at: Utc::now(),
+            locked_until: self.lock_up_until(),
         };
         
         // Add to active stakes
@@ -132,6 +139,10 @@ impl StakingManager {
     }
     
     /// Unstake trust points
+    ///
+    /// Fails while the stake's lock-up lasts. The points then unbond for
+    /// `unbonding_secs` before they are available again; the unstaked amount
+    /// is returned straight away.
     pub async fn unstake_points(&self, participant_id: &str, stake_id: &str) -> Result<u32> {
         let mut found_stake: Option<ActiveStake> = None;
         let mut stake_index: Option<usize> = None;
@@ -149,7 +160,7 @@ impl StakingManager {
         
         // Check if stake is still locked
         if let Some(locked_until) = stake.locked_until {
-            if Utc::now() < locked_until.into_inner() {
+            if Utc::now() < locked_until {
                 return Err(anyhow::anyhow!("Stake is still locked"));
             }
         }
@@ -158,11 +169,15 @@ impl StakingManager {
         if let Some(mut stakes) = self.active_stakes.get_mut(participant_id) {
             stakes.remove(index);
         }
+        self.start_unbonding(participant_id, stake.amount);
         
         Ok(stake.amount)
     }
     
     /// Slash stake for false reports or bad behavior
+    ///
+    /// Slashing a consensus stake also slashes delegations to that validator.
+    /// Returns the total slashed, delegators' share included.
     pub async fn slash_stake(&self, participant_id: &str, stake_id: &str, reason: &str) -> Result<u32> {
         let mut found_stake: Option<ActiveStake> = None;
         let mut stake_index: Option<usize> = None;
@@ -177,8 +192,9 @@ impl StakingManager {
         
         let stake = found_stake.ok_or_else(|| anyhow::anyhow!("Stake not found"))?;
         let index = stake_index.unwrap();
-        let slashed_amount = (stake.amount as f64 * self.config.slash_percentage) as u32;
+        let slashed_amount = self.config.slash_amount(stake.amount);
         let remaining_amount = stake.amount - slashed_amount;
+        let is_consensus = matches!(stake.purpose, StakePurpose::ConsensusValidator);
         
         // Remove the original stake and add remaining if any
         if let Some(mut stakes) = self.active_stakes.get_mut(participant_id) {
@@ -187,7 +203,7 @@ impl StakingManager {
             // If there's remaining amount, create new stake
             if remaining_amount > 0 {
                 let remaining_stake = ActiveStake {
-                    id: UuidWrapper::new(Uuid::new_v4()).to_string(),
+                    id: Uuid::new_v4().to_string(),
                     participant_id: stake.participant_id,
                     amount: remaining_amount,
                     purpose: stake.purpose,
@@ -198,13 +214,41 @@ impl StakingManager {
             }
         }
         
+        *self.adjustments.entry(participant_id.to_string()).or_insert(0) -= slashed_amount as i64;
+        
+        // Delegators share a validator's penalty at the same rate
+        let mut delegated_slashed = 0;
+        if is_consensus {
+            if let Some(mut delegations) = self.delegations.get_mut(participant_id) {
+                for delegation in delegations.iter_mut() {
+                    let penalty = self.config.slash_amount(delegation.amount);
+                    delegation.amount -= penalty;
+                    delegated_slashed += penalty;
+                    *self.adjustments.entry(delegation.delegator.clone()).or_insert(0) -= penalty as i64;
+                }
+                delegations.retain(|d| d.amount > 0);
+            }
+        }
+        
         tracing::warn!(
-            "Slashed {} trust points from {} for: {}",
+            "Slashed {} trust points from {} and {} from its delegators for: {}",
             slashed_amount,
             participant_id,
+            delegated_slashed,
             reason
         );
         
+        Ok(slashed_amount + delegated_slashed)
+    }
+    
+    /// Slash the points a participant put behind a false trust report
+    ///
+    /// Takes `slash_percentage` of `reported_stake` from the participant's
+    /// balance and returns the amount slashed.
+    pub async fn slash_report_stake(&self, participant_id: &str, reported_stake: u32, reason: &str) -> Result<u32> {
+        let slashed_amount = self.config.slash_amount(reported_stake);
+        *self.adjustments.entry(participant_id.to_string()).or_insert(0) -= slashed_amount as i64;
+        tracing::warn!("Slashed {} trust points from {} for: {}", slashed_amount, participant_id, reason);
         Ok(slashed_amount)
     }
     
@@ -239,7 +283,7 @@ impl StakingManager {
     pub async fn lock_stake(&self, participant_id: &str, stake_id: &str, lock_duration: chrono::Duration) -> Result<()> {
         if let Some(mut stakes) = self.active_stakes.get_mut(participant_id) {
             if let Some(stake) = stakes.iter_mut().find(|s| s.id == stake_id) {
-                stake.locked_until = Some(DateTimeWrapper::new(Utc::now() + lock_duration));
+                stake.locked_until = Some(Utc::now() + lock_duration);
                 return Ok(());
             }
         }
@@ -247,6 +291,232 @@ impl StakingManager {
         Err(anyhow::anyhow!("Stake not found"))
     }
     
+    /// Delegate points to a consensus validator
+    ///
+    /// Delegated points count towards the validator's weight in validator
+    /// selection; the delegator shares its rewards and penalties in proportion
+    /// to the amount.
+    pub async fn delegate(&self, delegator: &str, validator: &str, amount: u32) -> Result<String> {
+        if delegator == validator {
+            return Err(anyhow::anyhow!("Validators cannot delegate to themselves; stake instead"));
+        }
+        if amount < self.config.min_delegation_amount {
+            return Err(anyhow::anyhow!(
+                "Delegation amount {} is below minimum {}",
+                amount,
+                self.config.min_delegation_amount
+            ));
+        }
+        if self.own_consensus_stake(validator) < self.config.min_stake_for_consensus {
+            return Err(anyhow::anyhow!("{} is not a consensus validator", validator));
+        }
+        if !self.has_sufficient_stake(delegator, amount).await? {
+            return Err(anyhow::anyhow!("Insufficient available trust points"));
+        }
+        
+        let delegation = Delegation {
+            id: Uuid::new_v4().to_string(),
+            delegator: delegator.to_string(),
+            validator: validator.to_string(),
+            amount,
+            delegated_at: Utc::now(),
+            locked_until: self.lock_up_until(),
+        };
+        self.delegations.entry(validator.to_string()).or_default().push(delegation.clone());
+        
+        tracing::info!("{} delegated {} trust points to {}", delegator, amount, validator);
+        Ok(delegation.id)
+    }
+    
+    /// Withdraw a delegation once its lock-up has passed; the points unbond like unstaked points
+    pub async fn undelegate(&self, delegator: &str, delegation_id: &str) -> Result<u32> {
+        for mut entry in self.delegations.iter_mut() {
+            let Some(index) = entry.iter().position(|d| d.id == delegation_id && d.delegator == delegator) else {
+                continue;
+            };
+            if let Some(locked_until) = &entry[index].locked_until {
+                if Utc::now() < *locked_until {
+                    return Err(anyhow::anyhow!("Delegation is still locked"));
+                }
+            }
+            let delegation = entry.remove(index);
+            drop(entry);
+            self.start_unbonding(delegator, delegation.amount);
+            return Ok(delegation.amount);
+        }
+        Err(anyhow::anyhow!("Delegation not found"))
+    }
+    
+    /// Split a validator's reward between it and its delegators
+    ///
+    /// The validator keeps `validator_commission` of the delegators' share.
+    /// Returns each recipient's credit.
+    pub async fn distribute_rewards(&self, validator: &str, reward: u32) -> Result<Vec<(String, u32)>> {
+        let own = self.own_consensus_stake(validator) as u64;
+        let delegations = self.delegations_to(validator).await?;
+        let total = own + delegations.iter().map(|d| d.amount as u64).sum::<u64>();
+        if total == 0 {
+            return Err(anyhow::anyhow!("{} has no stake to reward", validator));
+        }
+        
+        let mut shares = Vec::with_capacity(delegations.len() + 1);
+        let mut paid = 0u32;
+        for delegation in &delegations {
+            let gross = reward as u64 * delegation.amount as u64 / total;
+            let net = (gross as f64 * (1.0 - self.config.validator_commission)) as u32;
+            paid += net;
+            shares.push((delegation.delegator.clone(), net));
+        }
+        // The validator gets its own share, the commission and any rounding remainder
+        shares.push((validator.to_string(), reward - paid));
+        
+        for (participant, amount) in &shares {
+            *self.adjustments.entry(participant.clone()).or_insert(0) += *amount as i64;
+        }
+        Ok(shares)
+    }
+    
+    /// Delegations made to a validator
+    pub async fn delegations_to(&self, validator: &str) -> Result<Vec<Delegation>> {
+        Ok(self.delegations.get(validator).map(|d| d.clone()).unwrap_or_default())
+    }
+    
+    /// Delegations made by a participant
+    pub async fn delegations_from(&self, delegator: &str) -> Result<Vec<Delegation>> {
+        Ok(self
+            .delegations
+            .iter()
+            .flat_map(|entry| entry.value().iter().filter(|d| d.delegator == delegator).cloned().collect::<Vec<_>>())
+            .collect())
+    }
+    
+    /// Every delegation, as delegator -> validator edges
+    pub async fn delegation_graph(&self) -> Result<Vec<Delegation>> {
+        Ok(self.delegations.iter().flat_map(|entry| entry.value().clone()).collect())
+    }
+    
+    /// Consensus validators with their own and delegated stake, heaviest first
+    pub async fn validator_weights(&self) -> Result<Vec<ValidatorWeight>> {
+        let mut weights: Vec<ValidatorWeight> = self
+            .get_consensus_validators()
+            .await?
+            .into_iter()
+            .map(|validator| {
+                let delegations = self.delegations.get(&validator).map(|d| d.clone()).unwrap_or_default();
+                ValidatorWeight {
+                    own_stake: self.own_consensus_stake(&validator),
+                    delegated: delegations.iter().map(|d| d.amount).sum(),
+                    delegators: delegations.len(),
+                    validator,
+                }
+            })
+            .collect();
+        weights.sort_by(|a, b| b.total().cmp(&a.total()).then_with(|| a.validator.cmp(&b.validator)));
+        Ok(weights)
+    }
+    
+    /// Choose a validator with probability proportional to its total weight
+    ///
+    /// `seed` (e.g. the block number) makes the choice deterministic, so every
+    /// node picks the same validator.
+    pub async fn select_validator(&self, seed: u64) -> Result<Option<String>> {
+        let mut weights = self.validator_weights().await?;
+        weights.sort_by(|a, b| a.validator.cmp(&b.validator));
+        let total: u64 = weights.iter().map(|w| w.total()).sum();
+        if total == 0 {
+            return Ok(None);
+        }
+        
+        let digest = sha2::Sha256::digest(seed.to_be_bytes());
+        let mut point = u64::from_be_bytes(digest[..8].try_into().unwrap()) % total;
+        for weight in weights {
+            if point < weight.total() {
+                return Ok(Some(weight.validator));
+            }
+            point -= weight.total();
+        }
+        Ok(None)
+    }
+    
+    /// Points unstaked or undelegated that have not finished unbonding
+    pub async fn pending_unbonding(&self, participant_id: &str) -> Result<Vec<UnbondingStake>> {
+        let now = Utc::now();
+        Ok(self
+            .unbonding
+            .get(participant_id)
+            .map(|entries| entries.iter().filter(|u| u.release_at > now).cloned().collect())
+            .unwrap_or_default())
+    }
+    
+    fn own_consensus_stake(&self, participant_id: &str) -> u32 {
+        self.active_stakes
+            .get(participant_id)
+            .map(|stakes| {
+                stakes
+                    .iter()
+                    .filter(|s| matches!(s.purpose, StakePurpose::ConsensusValidator))
+                    .map(|s| s.amount)
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+    
+    fn lock_up_until(&self) -> Option<DateTime<Utc>> {
+        (self.config.lock_up_secs > 0)
+            .then(|| Utc::now() + chrono::Duration::seconds(self.config.lock_up_secs as i64))
+    }
+    
+    fn start_unbonding(&self, participant_id: &str, amount: u32) {
+        if self.config.unbonding_secs == 0 {
+            return;
+        }
+        let now = Utc::now();
+        let mut entries = self.unbonding.entry(participant_id.to_string()).or_default();
+        entries.retain(|u| u.release_at > now);
+        entries.push(UnbondingStake {
+            amount,
+            release_at: now + chrono::Duration::seconds(self.config.unbonding_secs as i64),
+        });
+    }
+    
+    /// Total trust points of every participant the chain or adjustments know of
+    pub async fn balances(&self) -> Result<BTreeMap<String, u32>> {
+        let mut participants: BTreeSet<String> = self.adjustments.iter().map(|entry| entry.key().clone()).collect();
+        for block in self.chain.read().await.iter() {
+            for transaction in &block.transactions {
+                match transaction {
+                    Transaction::Registration(reg) => {
+                        participants.insert(reg.participant_id.clone());
+                    }
+                    Transaction::Transfer(transfer) => {
+                        participants.insert(transfer.to_participant.clone());
+                    }
+                    Transaction::TrustReport(report) => {
+                        participants.insert(report.subject_id.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        
+        let mut balances = BTreeMap::new();
+        for participant in participants {
+            let points = self.get_total_trust_points(&participant).await?;
+            balances.insert(participant, points);
+        }
+        Ok(balances)
+    }
+    
+    /// Start from balances taken from a snapshot rather than the chain
+    ///
+    /// Only valid while the chain holds no blocks from before the snapshot.
+    pub fn restore_balances(&self, balances: &BTreeMap<String, u32>) {
+        self.adjustments.clear();
+        for (participant, points) in balances {
+            self.adjustments.insert(participant.clone(), *points as i64);
+        }
+    }
+    
     /// Get all participant IDs in the system
     pub async fn get_all_participants(&self) -> Result<Vec<String>> {
         // For now, return participants who have stakes
@@ -282,7 +552,7 @@ impl StakingManager {
              staked_points: staked_amount,
              earned_lifetime: total_points,
              last_activity: DateTimeWrapper::new(stakes.iter()
-                .map(|s| s.staked_at.clone().into_inner())
+                .map(|s| s.staked_at)
                 .max()
                 .unwrap_or_else(Utc::now)),
              decay_rate: 0.02, // Default 2% per month
@@ -310,24 +580,61 @@ impl StakingManager {
     }
 }
 
+/// Points delegated by one participant to a consensus validator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delegation {
+    pub id: String,
+    pub delegator: String,
+    pub validator: String,
+    pub amount: u32,
+    #[serde(with = "timestamp")]
+    pub delegated_at: DateTime<Utc>,
+    #[serde(with = "timestamp::option")]
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+/// Points on their way back to a participant after unstaking or undelegating
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnbondingStake {
+    pub amount: u32,
+    #[serde(with = "timestamp")]
+    pub release_at: DateTime<Utc>,
+}
+
+/// A validator's weight in validator selection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatorWeight {
+    pub validator: String,
+    pub own_stake: u32,
+    pub delegated: u32,
+    pub delegators: usize,
+}
+
+impl ValidatorWeight {
+    pub fn total(&self) -> u64 {
+        self.own_stake as u64 + self.delegated as u64
+    }
+}
+
 /// An active stake record
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "serde")]
-#[bincode(crate = "bincode")]
 pub struct ActiveStake {
     pub id: String,
     pub participant_id: String,
     pub amount: u32,
     pub purpose: StakePurpose,
-    pub staked_at: DateTimeWrapper,
-    pub locked_until: Option<DateTimeWrapper>,
+    #[serde(with = "timestamp")]
+    pub staked_at: DateTime<Utc>,
+    #[serde(with = "timestamp::option")]
+    pub locked_until: Option<DateTime<Utc>>,
 }
 
 impl ActiveStake {
     /// Check if stake is currently locked
     pub fn is_locked(&self) -> bool {
         if let Some(locked_until) = &self.locked_until {
-            Utc::now() < locked_until.0
+            Utc::now() < *locked_until
         } else {
             false
         }
@@ -349,6 +656,10 @@ mod tests {
             min_stake_for_report: 10,
             min_stake_for_consensus: 500,
             slash_percentage: 0.2, // 20% slash
+            lock_up_secs: 0,
+            unbonding_secs: 0,
+            min_delegation_amount: 10,
+            validator_commission: 0.1,
         }
     }
 
@@ -360,25 +671,25 @@ mod tests {
             participant_id: participant_id.to_string(),
             amount,
             purpose,
-            staked_at: DateTimeWrapper::new(Utc::now()),
+            staked_at: Utc::now(),
             locked_until: None,
         }
     }
  
     async fn create_test_blockchain_with_registration(participant_id: &str, trust_points: u32) -> Arc<RwLock<Vec<Block>>> {
         let registration = block::RegistrationTransaction {
-            id: UuidWrapper::new(Uuid::new_v4()).to_string(),
+            id: Uuid::new_v4().to_string(),
             participant_id: participant_id.to_string(),
             public_key: vec![1, 2, 3, 4], // dummy key
             initial_trust_points: trust_points,
             entity_type: "test".to_string(),
-            timestamp: DateTimeWrapper::new(Utc::now()),
+            timestamp: Utc::now(),
             signature: vec![1, 2, 3, 4], // dummy signature
         };
         
         let mut block = Block {
             number: 0,
-            timestamp: DateTimeWrapper::new(Utc::now()),
+            timestamp: Utc::now(),
             previous_hash: "0".repeat(64),
             hash: String::new(),
             transactions: vec![Transaction::Registration(registration)],
@@ -494,6 +805,7 @@ mod tests {
             min_stake_for_report: 10,
             min_stake_for_consensus: 500,
             slash_percentage: 1.0, // 100% slash
+            ..create_test_config()
         };
         let chain = create_test_blockchain_with_registration("alice", 2000).await;
         let manager = StakingManager::new(config, chain).await.unwrap();
@@ -638,4 +950,59 @@ mod tests {
         let result = manager.stake_points("alice", 500, StakePurpose::TrustReporting).await;
         assert!(result.is_ok());
     }
+
+    async fn create_test_manager(config: StakingRequirements, participants: &[(&str, u32)]) -> StakingManager {
+        let mut blocks = Vec::new();
+        for (participant_id, trust_points) in participants {
+            blocks.push(create_test_blockchain_with_registration(participant_id, *trust_points).await.read().await[0].clone());
+        }
+        StakingManager::new(config, Arc::new(RwLock::new(blocks))).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_delegation_shares_rewards_and_penalties() {
+        let manager = create_test_manager(create_test_config(), &[("alice", 2000), ("bob", 1000), ("carol", 1000)]).await;
+        let stake_id = manager.stake_points("alice", 600, StakePurpose::ConsensusValidator).await.unwrap();
+        
+        // Only consensus validators accept delegations
+        assert!(manager.delegate("bob", "carol", 100).await.is_err());
+        assert!(manager.delegate("bob", "alice", 5).await.unwrap_err().to_string().contains("below minimum"));
+        manager.delegate("bob", "alice", 300).await.unwrap();
+        manager.delegate("carol", "alice", 100).await.unwrap();
+        assert_eq!(manager.get_available_stake("bob").await.unwrap(), 700);
+        
+        let weights = manager.validator_weights().await.unwrap();
+        assert_eq!((weights[0].own_stake, weights[0].delegated, weights[0].delegators), (600, 400, 2));
+        assert_eq!(manager.select_validator(7).await.unwrap().as_deref(), Some("alice"));
+        
+        // 1000 points of stake: bob holds 30%, carol 10%, less 10% commission
+        let shares: std::collections::HashMap<_, _> = manager.distribute_rewards("alice", 100).await.unwrap().into_iter().collect();
+        assert_eq!((shares["bob"], shares["carol"], shares["alice"]), (27, 9, 64));
+        assert_eq!(manager.get_total_trust_points("bob").await.unwrap(), 1027);
+        
+        // Slashing the validator slashes its delegators at the same rate
+        assert_eq!(manager.slash_stake("alice", &stake_id, "double signing").await.unwrap(), 120 + 60 + 20);
+        assert_eq!(manager.delegations_from("bob").await.unwrap()[0].amount, 240);
+        assert_eq!(manager.get_total_trust_points("bob").await.unwrap(), 967);
+        assert_eq!(manager.delegation_graph().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_lock_up_and_unbonding() {
+        let config = StakingRequirements { lock_up_secs: 3600, unbonding_secs: 3600, ..create_test_config() };
+        let manager = create_test_manager(config, &[("alice", 2000), ("bob", 1000)]).await;
+        
+        let stake_id = manager.stake_points("bob", 500, StakePurpose::TrustReporting).await.unwrap();
+        assert!(manager.unstake_points("bob", &stake_id).await.unwrap_err().to_string().contains("locked"));
+        
+        manager.stake_points("alice", 600, StakePurpose::ConsensusValidator).await.unwrap();
+        let delegation_id = manager.delegate("bob", "alice", 200).await.unwrap();
+        assert!(manager.undelegate("bob", &delegation_id).await.is_err());
+        
+        // Once unstaked, points stay unavailable until unbonding completes
+        manager.lock_stake("bob", &stake_id, Duration::seconds(-1)).await.unwrap();
+        assert_eq!(manager.unstake_points("bob", &stake_id).await.unwrap(), 500);
+        assert_eq!(manager.pending_unbonding("bob").await.unwrap()[0].amount, 500);
+        assert_eq!(manager.get_available_stake("bob").await.unwrap(), 300);
+    }
 }
@@ -127,7 +127,7 @@ impl VerificationEngine {
 
         // Check timestamp
         let now = Utc::now();
-        if block.timestamp.0 > now {
+        if block.timestamp > now {
             result.errors.push("Block timestamp is in the future".to_string());
             result.is_valid = false;
         }
@@ -148,7 +148,7 @@ impl VerificationEngine {
         }
 
         // Check timestamp sequence
-        if block.timestamp.0 <= previous_block.timestamp.0 {
+        if block.timestamp <= previous_block.timestamp {
             result.errors.push("Block timestamp not increasing".to_string());
             result.is_valid = false;
         }
@@ -7,6 +7,7 @@ pub mod trust;
 pub use participant::{
     ParticipantProfile, EntityType, IdentityContext, DiscoveryPermissions, 
     DiscoverabilityLevel, AvailabilityStatus, ContactPreferences,
+    AttachmentKind, ProfileAttachment,
 };
 
 pub use trust::{
@@ -28,6 +28,14 @@ pub struct ParticipantProfile {
     pub topic_subscriptions: Vec<TopicSubscription>,
     pub organizational_context: Option<OrganizationalContext>,
 
+    // Signed, content-addressed attachments (avatar, model card, ...)
+    #[serde(default)]
+    pub attachments: Vec<ProfileAttachment>,
+
+    // Where the participant runs, e.g. "eu-west"; see crate::geo
+    #[serde(default)]
+    pub region: Option<String>,
+
     // Technical details
     pub public_key: Option<Vec<u8>>,
     pub supported_protocols: Vec<String>,
@@ -292,6 +300,59 @@ pub struct CrossOrgPermissions {
     pub blocked_organizations: Vec<String>,
 }
 
+/// Kinds of attachment a profile can carry
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AttachmentKind {
+    Avatar,
+    ModelCard,
+    OrgCharter,
+    Other(String),
+}
+
+impl AttachmentKind {
+    /// Largest allowed attachment of this kind, in bytes
+    pub fn max_size_bytes(&self) -> u64 {
+        match self {
+            AttachmentKind::Avatar => 256 * 1024,
+            AttachmentKind::ModelCard => 64 * 1024,
+            AttachmentKind::OrgCharter => 128 * 1024,
+            AttachmentKind::Other(_) => 64 * 1024,
+        }
+    }
+}
+
+impl std::fmt::Display for AttachmentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttachmentKind::Avatar => write!(f, "avatar"),
+            AttachmentKind::ModelCard => write!(f, "model_card"),
+            AttachmentKind::OrgCharter => write!(f, "org_charter"),
+            AttachmentKind::Other(name) => write!(f, "other:{}", name),
+        }
+    }
+}
+
+/// Reference to a signed attachment; the content itself lives in the attachment store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileAttachment {
+    pub kind: AttachmentKind,
+    pub content_hash: String,  // hex SHA-256 of the content
+    pub media_type: String,    // "image/png", "application/json", ...
+    pub size_bytes: u64,
+    pub signature: Vec<u8>,    // owner's signature over signing_payload()
+    pub added_at: DateTime<Utc>,
+}
+
+impl ProfileAttachment {
+    /// Canonical string the profile owner signs
+    pub fn signing_payload(&self, owner_global_id: &str) -> String {
+        format!(
+            "synapse-attachment:{}:{}:{}:{}:{}",
+            owner_global_id, self.kind, self.content_hash, self.media_type, self.size_bytes
+        )
+    }
+}
+
 impl ParticipantProfile {
     pub fn new(global_id: String, display_name: String, entity_type: EntityType) -> Self {
         Self {
@@ -306,6 +367,8 @@ impl ParticipantProfile {
             relationships: Vec::new(),
             topic_subscriptions: Vec::new(),
             organizational_context: None,
+            attachments: Vec::new(),
+            region: None,
             public_key: None,
             supported_protocols: vec!["synapse-v1".to_string()],
             last_seen: Utc::now(),
@@ -350,6 +413,11 @@ impl ParticipantProfile {
         }
     }
 
+    /// Find the attachment of a given kind, if any
+    pub fn attachment(&self, kind: &AttachmentKind) -> Option<&ProfileAttachment> {
+        self.attachments.iter().find(|a| &a.kind == kind)
+    }
+
     pub fn is_available_for_contact(&self) -> bool {
         matches!(self.availability.status, Status::Available | Status::Busy)
     }
@@ -0,0 +1,353 @@
+// Synapse Trust Decay Scheduler
+// Single scheduler for trust point decay, shared by the trust manager and the blockchain
+
+use crate::synapse::blockchain::{StakingManager, TrustDecayConfig};
+use crate::synapse::models::TrustBalance;
+use crate::runtime::{self, Subsystem};
+#[cfg(feature = "database")]
+use crate::synapse::storage::Database;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Job name under which decay runs are recorded
+pub const DECAY_JOB: &str = "trust_decay";
+
+/// Days in the "month" that decay rates are quoted per
+const DAYS_PER_MONTH: f64 = 30.0;
+
+/// Balances the scheduler decays
+#[async_trait]
+pub trait DecayBalances: Send + Sync {
+    /// Balances with points whose last activity is before `cutoff`
+    async fn balances_for_decay(&self, cutoff: DateTime<Utc>) -> Result<Vec<TrustBalance>>;
+
+    /// Store a decayed balance
+    async fn save_balance(&self, balance: &TrustBalance) -> Result<()>;
+}
+
+/// Where the scheduler remembers its runs across restarts
+#[async_trait]
+pub trait ScheduleStore: Send + Sync {
+    async fn load_run(&self, job: &str) -> Result<Option<ScheduledRun>>;
+
+    async fn save_run(&self, run: &ScheduledRun) -> Result<()>;
+}
+
+/// Persisted state of a periodic job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledRun {
+    pub job: String,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub next_run_at: DateTime<Utc>,
+    /// Balances changed by the last run
+    pub last_processed: u64,
+}
+
+/// What a decay run did
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecayReport {
+    /// Start of the period decayed; the previous run, or `None` on the first run
+    pub since: Option<DateTime<Utc>>,
+    pub ran_at: DateTime<Utc>,
+    /// Participants whose balance decayed
+    pub processed: Vec<String>,
+    /// Points removed across all balances
+    pub points_removed: u64,
+}
+
+/// Scheduler state for the admin API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecayStatus {
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub next_run_at: DateTime<Utc>,
+    pub last_processed: u64,
+    pub interval_hours: u64,
+    /// Whether the next run is already due, e.g. after downtime
+    pub overdue: bool,
+}
+
+/// Points a balance loses between `since` and `now`
+///
+/// Decay starts `grace_days` after the last activity and runs at the balance's
+/// monthly rate. The amount is the difference between the decay accrued by
+/// `now` and by `since`, so fractions carry over between frequent runs and a
+/// run after downtime catches up on the whole gap.
+pub fn decay_amount(balance: &TrustBalance, since: Option<DateTime<Utc>>, now: DateTime<Utc>, grace_days: u64) -> u32 {
+    let decay_start = balance.last_activity.0 + Duration::days(grace_days as i64);
+    let months_until = |at: DateTime<Utc>| (at - decay_start).num_seconds().max(0) as f64 / 86_400.0 / DAYS_PER_MONTH;
+    let accrued = |at: DateTime<Utc>| (balance.total_points as f64 * balance.decay_rate * months_until(at)).floor();
+
+    let before = since.map(accrued).unwrap_or(0.0);
+    (accrued(now) - before).max(0.0) as u32
+}
+
+/// Take `amount` decayed points from a balance
+///
+/// Decay never eats into staked points.
+pub fn apply_decay(balance: &mut TrustBalance, amount: u32) {
+    balance.total_points = balance.total_points.saturating_sub(amount);
+    balance.available_points = balance.available_points.saturating_sub(amount);
+    if balance.available_points < balance.staked_points {
+        balance.available_points = balance.staked_points;
+        balance.total_points = balance.staked_points;
+    }
+}
+
+/// Runs trust decay on a fixed interval and remembers when it last ran
+pub struct DecayScheduler {
+    config: TrustDecayConfig,
+    balances: Arc<dyn DecayBalances>,
+    store: Arc<dyn ScheduleStore>,
+}
+
+impl DecayScheduler {
+    pub fn new(config: TrustDecayConfig, balances: Arc<dyn DecayBalances>, store: Arc<dyn ScheduleStore>) -> Self {
+        Self { config, balances, store }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::hours(self.config.decay_check_interval_hours.max(1) as i64)
+    }
+
+    /// Decay every inactive balance for the period since the previous run
+    pub async fn run_once(&self, now: DateTime<Utc>) -> Result<DecayReport> {
+        let since = self.store.load_run(DECAY_JOB).await?.and_then(|run| run.last_run_at);
+        let grace_days = self.config.min_activity_days;
+        let cutoff = now - Duration::days(grace_days as i64);
+
+        let mut report = DecayReport { since, ran_at: now, processed: Vec::new(), points_removed: 0 };
+        for mut balance in self.balances.balances_for_decay(cutoff).await? {
+            let amount = decay_amount(&balance, since, now, grace_days);
+            if amount == 0 {
+                continue;
+            }
+            apply_decay(&mut balance, amount);
+            self.balances.save_balance(&balance).await?;
+            debug!("Applied decay to {}: -{} points", balance.participant_id, amount);
+            report.points_removed += amount as u64;
+            report.processed.push(balance.participant_id);
+        }
+
+        self.store
+            .save_run(&ScheduledRun {
+                job: DECAY_JOB.to_string(),
+                last_run_at: Some(now),
+                next_run_at: now + self.interval(),
+                last_processed: report.processed.len() as u64,
+            })
+            .await?;
+        if let Some(since) = since {
+            if now - since > self.interval() * 2 {
+                info!("Caught up on trust decay missed since {}", since);
+            }
+        }
+        Ok(report)
+    }
+
+    /// When the scheduler last ran and when it runs next
+    pub async fn status(&self) -> Result<DecayStatus> {
+        let now = Utc::now();
+        let run = self.store.load_run(DECAY_JOB).await?;
+        let (last_run_at, next_run_at, last_processed) = match run {
+            Some(run) => (run.last_run_at, run.next_run_at, run.last_processed),
+            None => (None, now, 0),
+        };
+        Ok(DecayStatus {
+            last_run_at,
+            next_run_at,
+            last_processed,
+            interval_hours: self.config.decay_check_interval_hours,
+            overdue: next_run_at <= now,
+        })
+    }
+
+    /// Run decay whenever it is due, starting with any run missed while the node was down
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        runtime::spawn(Subsystem::Storage, async move {
+            loop {
+                let now = Utc::now();
+                let next_run_at = match self.store.load_run(DECAY_JOB).await {
+                    Ok(Some(run)) => run.next_run_at,
+                    Ok(None) => now,
+                    Err(e) => {
+                        warn!("Failed to load trust decay schedule: {}", e);
+                        now + Duration::minutes(5)
+                    }
+                };
+
+                if next_run_at > now {
+                    let wait = (next_run_at - now).to_std().unwrap_or_default();
+                    tokio::time::sleep(wait).await;
+                    continue;
+                }
+
+                match self.run_once(Utc::now()).await {
+                    Ok(report) if !report.processed.is_empty() => {
+                        info!("Processed trust decay for {} participants", report.processed.len());
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("Failed to process trust decay: {}", e);
+                        tokio::time::sleep(std::time::Duration::from_secs(300)).await;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Schedule state kept in memory; runs are not remembered across restarts
+#[derive(Default)]
+pub struct MemoryScheduleStore {
+    runs: DashMap<String, ScheduledRun>,
+}
+
+impl MemoryScheduleStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ScheduleStore for MemoryScheduleStore {
+    async fn load_run(&self, job: &str) -> Result<Option<ScheduledRun>> {
+        Ok(self.runs.get(job).map(|run| run.clone()))
+    }
+
+    async fn save_run(&self, run: &ScheduledRun) -> Result<()> {
+        self.runs.insert(run.job.clone(), run.clone());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "database")]
+#[async_trait]
+impl ScheduleStore for Database {
+    async fn load_run(&self, job: &str) -> Result<Option<ScheduledRun>> {
+        self.get_scheduled_run(job).await
+    }
+
+    async fn save_run(&self, run: &ScheduledRun) -> Result<()> {
+        self.upsert_scheduled_run(run).await
+    }
+}
+
+#[cfg(feature = "database")]
+#[async_trait]
+impl DecayBalances for Database {
+    async fn balances_for_decay(&self, cutoff: DateTime<Utc>) -> Result<Vec<TrustBalance>> {
+        self.get_balances_for_decay(cutoff).await
+    }
+
+    async fn save_balance(&self, balance: &TrustBalance) -> Result<()> {
+        self.upsert_trust_balance(balance).await
+    }
+}
+
+#[async_trait]
+impl DecayBalances for StakingManager {
+    async fn balances_for_decay(&self, cutoff: DateTime<Utc>) -> Result<Vec<TrustBalance>> {
+        let mut balances = Vec::new();
+        for participant_id in self.get_all_participants().await? {
+            balances.extend(
+                self.get_participant_balances(&participant_id)
+                    .await?
+                    .into_iter()
+                    .filter(|b| b.last_activity.0 < cutoff && b.total_points > 0),
+            );
+        }
+        Ok(balances)
+    }
+
+    async fn save_balance(&self, balance: &TrustBalance) -> Result<()> {
+        self.update_balance(&balance.participant_id, balance).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::serialization::DateTimeWrapper;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryBalances(Mutex<Vec<TrustBalance>>);
+
+    #[async_trait]
+    impl DecayBalances for MemoryBalances {
+        async fn balances_for_decay(&self, cutoff: DateTime<Utc>) -> Result<Vec<TrustBalance>> {
+            Ok(self.0.lock().unwrap().iter().filter(|b| b.last_activity.0 < cutoff).cloned().collect())
+        }
+
+        async fn save_balance(&self, balance: &TrustBalance) -> Result<()> {
+            let mut balances = self.0.lock().unwrap();
+            if let Some(existing) = balances.iter_mut().find(|b| b.participant_id == balance.participant_id) {
+                *existing = balance.clone();
+            }
+            Ok(())
+        }
+    }
+
+    fn balance(total: u32, last_activity: DateTime<Utc>) -> TrustBalance {
+        TrustBalance {
+            participant_id: "alice@example.com".to_string(),
+            total_points: total,
+            available_points: total,
+            staked_points: 0,
+            earned_lifetime: total,
+            last_activity: DateTimeWrapper::new(last_activity),
+            decay_rate: 0.02,
+        }
+    }
+
+    #[test]
+    fn test_frequent_runs_accrue_fractions() {
+        let now = Utc::now();
+        let idle = balance(1000, now - Duration::days(90));
+        // Two months past the 30-day grace period: 2% of 1000 per month
+        assert_eq!(decay_amount(&idle, None, now, 30), 40);
+
+        // Daily runs add up to the same total as one run covering the period
+        let daily: u32 = (0..60)
+            .map(|day| {
+                let since = now - Duration::days(60 - day);
+                decay_amount(&idle, Some(since), since + Duration::days(1), 30)
+            })
+            .sum();
+        assert_eq!(daily, 40);
+
+        let active = balance(1000, now - Duration::days(10));
+        assert_eq!(decay_amount(&active, None, now, 30), 0);
+    }
+
+    #[tokio::test]
+    async fn test_catches_up_after_downtime_and_persists_runs() {
+        let start = Utc::now() - Duration::days(120);
+        let balances = Arc::new(MemoryBalances::default());
+        balances.0.lock().unwrap().push(balance(1000, start));
+        let store = Arc::new(MemoryScheduleStore::new());
+        let config = TrustDecayConfig { monthly_decay_rate: 0.02, min_activity_days: 30, decay_check_interval_hours: 24 };
+        let scheduler = DecayScheduler::new(config, balances.clone(), store.clone());
+
+        let first = start + Duration::days(60);
+        let report = scheduler.run_once(first).await.unwrap();
+        assert_eq!(report.points_removed, 20);
+
+        // The node was down for a month; the next run covers the whole gap
+        let after_downtime = first + Duration::days(30);
+        let report = scheduler.run_once(after_downtime).await.unwrap();
+        assert_eq!(report.since, Some(first));
+        assert_eq!(report.points_removed, 20);
+        assert_eq!(balances.0.lock().unwrap()[0].total_points, 960);
+
+        let run = store.load_run(DECAY_JOB).await.unwrap().unwrap();
+        assert_eq!(run.last_run_at, Some(after_downtime));
+        assert_eq!(run.next_run_at, after_downtime + Duration::hours(24));
+    }
+}
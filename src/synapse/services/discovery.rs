@@ -1,4 +1,5 @@
 use crate::synapse::models::{ParticipantProfile, DiscoverabilityLevel};
+use super::search_index::{ParticipantSearchIndex, SearchFilters, SearchHit};
 #[cfg(feature = "database")]
 use crate::synapse::storage::Database;
 #[cfg(feature = "cache")]
@@ -13,6 +14,7 @@ pub struct DiscoveryService {
     database: Arc<Database>,
     #[cfg(feature = "cache")]
     cache: Arc<Cache>,
+    index: Arc<ParticipantSearchIndex>,
 }
 
 impl DiscoveryService {
@@ -21,12 +23,43 @@ impl DiscoveryService {
         Self {
             database,
             cache,
+            index: Arc::new(ParticipantSearchIndex::new()),
         }
     }
     
     #[cfg(not(all(feature = "database", feature = "cache")))]
     pub fn new() -> Self {
-        Self {}
+        Self {
+            index: Arc::new(ParticipantSearchIndex::new()),
+        }
+    }
+
+    /// Add or refresh a participant in the natural-language search index
+    pub fn index_participant(&self, profile: &ParticipantProfile) {
+        self.index.index(profile);
+    }
+
+    /// Remove a participant from the natural-language search index
+    pub fn unindex_participant(&self, global_id: &str) {
+        self.index.remove(global_id);
+    }
+
+    /// Natural-language search over indexed profiles
+    ///
+    /// Accepts queries like "the rust compiler expert at example.com". Results
+    /// respect each profile's `DiscoverabilityLevel`: only Public profiles, and
+    /// Unlisted profiles connected to the requester, are returned.
+    pub fn search(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        requester_id: &str,
+        max_results: usize,
+    ) -> Vec<SearchHit> {
+        let requester = self.index.get(requester_id);
+        let hits = self.index.search(query, filters, requester.as_ref(), max_results);
+        debug!("Natural-language search '{}' for {} returned {} hits", query, requester_id, hits.len());
+        hits
     }
 
     /// Discover participants by name with privacy respect
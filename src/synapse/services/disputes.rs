@@ -0,0 +1,422 @@
+// Synapse Dispute Resolution
+// Challenges to negative trust reports, decided by an arbiter vote
+
+//! The subject of a negative trust report can open a dispute. The reporter
+//! and the subject both attach evidence, and a panel of arbiters votes on
+//! whether the report stands. Arbiters are the configured arbiter set or,
+//! if none is configured, the validators with the most stake; neither
+//! party to a dispute can sit on its panel.
+//!
+//! A majority to annul the report annuls it on the blockchain, so it stops
+//! counting towards the subject's score, and slashes the stake the reporter
+//! put behind it. A majority to uphold, or a panel that fails to reach a
+//! majority before the deadline, leaves the report standing. Disputes are
+//! kept in memory.
+
+use crate::synapse::blockchain::{EvidenceSource, SynapseBlockchain};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Dispute resolution settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisputeConfig {
+    /// Fixed arbiter set; empty to pick the top validators by stake
+    pub arbiters: Vec<String>,
+    /// Arbiters on each panel
+    pub panel_size: usize,
+    /// Hours the panel has to reach a majority
+    pub voting_period_hours: i64,
+    /// Evidence items each party may attach
+    pub max_evidence_per_party: usize,
+}
+
+impl Default for DisputeConfig {
+    fn default() -> Self {
+        Self {
+            arbiters: Vec::new(),
+            panel_size: 5,
+            voting_period_hours: 72,
+            max_evidence_per_party: 5,
+        }
+    }
+}
+
+/// An arbiter's decision on a disputed report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// The report stands
+    Uphold,
+    /// The report was false; annul it and slash the reporter
+    Annul,
+}
+
+/// Where a dispute stands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DisputeStatus {
+    Open,
+    Resolved { verdict: Verdict, resolved_at: DateTime<Utc> },
+    /// No majority before the deadline; the report stands
+    Expired { expired_at: DateTime<Utc> },
+}
+
+/// Evidence one party attached to a dispute
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisputeEvidence {
+    pub party: String,
+    /// Content address in the blockchain's evidence store
+    pub content_hash: String,
+    pub submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisputeVote {
+    pub arbiter: String,
+    pub verdict: Verdict,
+    pub cast_at: DateTime<Utc>,
+}
+
+/// A challenge to one trust report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dispute {
+    pub id: String,
+    pub report_id: String,
+    pub reporter_id: String,
+    pub subject_id: String,
+    pub reason: String,
+    pub opened_at: DateTime<Utc>,
+    pub deadline: DateTime<Utc>,
+    pub arbiters: Vec<String>,
+    pub evidence: Vec<DisputeEvidence>,
+    pub votes: Vec<DisputeVote>,
+    pub status: DisputeStatus,
+    /// Points slashed from the reporter if the report was annulled
+    pub slashed: u32,
+}
+
+impl Dispute {
+    /// Verdict a majority of the panel has voted for, if any
+    pub fn majority(&self) -> Option<Verdict> {
+        let needed = self.arbiters.len() / 2 + 1;
+        [Verdict::Annul, Verdict::Uphold]
+            .into_iter()
+            .find(|verdict| self.votes.iter().filter(|vote| vote.verdict == *verdict).count() >= needed)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.status == DisputeStatus::Open
+    }
+
+    /// Whether `participant_id` is the reporter or the subject
+    pub fn is_party(&self, participant_id: &str) -> bool {
+        self.reporter_id == participant_id || self.subject_id == participant_id
+    }
+}
+
+/// Evidence attached to a dispute, with its content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisputeEvidenceContent {
+    pub party: String,
+    pub content_hash: String,
+    pub content: String,
+    pub submitted_at: DateTime<Utc>,
+}
+
+/// Opens, collects evidence for and decides disputes over trust reports
+pub struct DisputeService {
+    config: DisputeConfig,
+    blockchain: Arc<SynapseBlockchain>,
+    disputes: DashMap<String, Dispute>,
+}
+
+impl DisputeService {
+    pub fn new(config: DisputeConfig, blockchain: Arc<SynapseBlockchain>) -> Self {
+        Self {
+            config,
+            blockchain,
+            disputes: DashMap::new(),
+        }
+    }
+
+    /// Dispute a negative report about `subject_id`
+    ///
+    /// Only the report's subject can open a dispute, and only one dispute
+    /// per report can be open at a time.
+    pub async fn open_dispute(
+        &self,
+        subject_id: &str,
+        report_id: &str,
+        reason: &str,
+        evidence: Option<String>,
+    ) -> Result<Dispute> {
+        let (report, _) = self
+            .blockchain
+            .find_trust_report(report_id)
+            .await
+            .ok_or_else(|| anyhow!("Trust report {} not found", report_id))?;
+        if report.subject_id != subject_id {
+            return Err(anyhow!("Only the subject of a report can dispute it"));
+        }
+        if report.score >= 0 {
+            return Err(anyhow!("Only negative reports can be disputed"));
+        }
+        if self.blockchain.is_annulled(&report.id) {
+            return Err(anyhow!("Trust report {} has already been annulled", report.id));
+        }
+        if self.disputes.iter().any(|d| d.report_id == report.id && d.is_open()) {
+            return Err(anyhow!("Trust report {} is already under dispute", report.id));
+        }
+
+        let arbiters = self.select_arbiters(&report.reporter_id, &report.subject_id).await?;
+        let now = Utc::now();
+        let mut dispute = Dispute {
+            id: Uuid::new_v4().to_string(),
+            report_id: report.id,
+            reporter_id: report.reporter_id,
+            subject_id: report.subject_id,
+            reason: reason.to_string(),
+            opened_at: now,
+            deadline: now + Duration::hours(self.config.voting_period_hours),
+            arbiters,
+            evidence: Vec::new(),
+            votes: Vec::new(),
+            status: DisputeStatus::Open,
+            slashed: 0,
+        };
+        if let Some(evidence) = evidence {
+            let content_hash = self.blockchain.store_evidence(&evidence).await?;
+            dispute.evidence.push(DisputeEvidence {
+                party: subject_id.to_string(),
+                content_hash,
+                submitted_at: now,
+            });
+        }
+
+        info!(
+            "Dispute {} opened by {} over report {} with {} arbiters",
+            dispute.id, subject_id, dispute.report_id, dispute.arbiters.len()
+        );
+        self.disputes.insert(dispute.id.clone(), dispute.clone());
+        Ok(dispute)
+    }
+
+    // Configured arbiters, or the validators with the most stake; never a party
+    async fn select_arbiters(&self, reporter_id: &str, subject_id: &str) -> Result<Vec<String>> {
+        let candidates: Vec<String> = if self.config.arbiters.is_empty() {
+            self.blockchain
+                .staking_manager
+                .validator_weights()
+                .await?
+                .into_iter()
+                .map(|weight| weight.validator)
+                .collect()
+        } else {
+            self.config.arbiters.clone()
+        };
+        let arbiters: Vec<String> = candidates
+            .into_iter()
+            .filter(|arbiter| arbiter != reporter_id && arbiter != subject_id)
+            .take(self.config.panel_size.max(1))
+            .collect();
+        if arbiters.is_empty() {
+            return Err(anyhow!("No arbiters available to hear the dispute"));
+        }
+        Ok(arbiters)
+    }
+
+    /// Attach evidence from the reporter or the subject to an open dispute
+    pub async fn submit_evidence(&self, dispute_id: &str, party: &str, evidence: &str) -> Result<Dispute> {
+        {
+            let dispute = self.get_open(dispute_id)?;
+            if !dispute.is_party(party) {
+                return Err(anyhow!("Only the reporter or the subject can attach evidence"));
+            }
+            let attached = dispute.evidence.iter().filter(|e| e.party == party).count();
+            if attached >= self.config.max_evidence_per_party {
+                return Err(anyhow!("At most {} evidence items per party", self.config.max_evidence_per_party));
+            }
+        }
+
+        let content_hash = self.blockchain.store_evidence(evidence).await?;
+        let mut dispute = self
+            .disputes
+            .get_mut(dispute_id)
+            .ok_or_else(|| anyhow!("Dispute {} not found", dispute_id))?;
+        dispute.evidence.push(DisputeEvidence {
+            party: party.to_string(),
+            content_hash,
+            submitted_at: Utc::now(),
+        });
+        Ok(dispute.clone())
+    }
+
+    /// Evidence attached to a dispute, including the report's own, for display
+    ///
+    /// Evidence not held locally is fetched from the party that submitted it.
+    pub async fn evidence(&self, dispute_id: &str, source: Option<&dyn EvidenceSource>) -> Result<Vec<DisputeEvidenceContent>> {
+        let dispute = self.get_dispute(dispute_id).ok_or_else(|| anyhow!("Dispute {} not found", dispute_id))?;
+
+        let mut contents = Vec::new();
+        if let Some(report) = self.blockchain.report_evidence(&dispute.report_id, source).await? {
+            contents.push(DisputeEvidenceContent {
+                party: report.reporter_id,
+                content_hash: report.content_hash,
+                content: report.content,
+                submitted_at: dispute.opened_at,
+            });
+        }
+        for item in &dispute.evidence {
+            contents.push(DisputeEvidenceContent {
+                party: item.party.clone(),
+                content_hash: item.content_hash.clone(),
+                content: self.blockchain.fetch_evidence(&item.content_hash, &item.party, source).await?,
+                submitted_at: item.submitted_at,
+            });
+        }
+        Ok(contents)
+    }
+
+    /// Record an arbiter's vote, resolving the dispute once a majority agrees
+    pub async fn cast_vote(&self, dispute_id: &str, arbiter: &str, verdict: Verdict) -> Result<Dispute> {
+        let decided = {
+            let mut dispute = self
+                .disputes
+                .get_mut(dispute_id)
+                .ok_or_else(|| anyhow!("Dispute {} not found", dispute_id))?;
+            if !dispute.is_open() {
+                return Err(anyhow!("Dispute {} is closed", dispute_id));
+            }
+            if dispute.deadline <= Utc::now() {
+                return Err(anyhow!("Voting on dispute {} has ended", dispute_id));
+            }
+            if !dispute.arbiters.iter().any(|a| a == arbiter) {
+                return Err(anyhow!("{} is not an arbiter for dispute {}", arbiter, dispute_id));
+            }
+            if dispute.votes.iter().any(|vote| vote.arbiter == arbiter) {
+                return Err(anyhow!("{} has already voted on dispute {}", arbiter, dispute_id));
+            }
+            dispute.votes.push(DisputeVote {
+                arbiter: arbiter.to_string(),
+                verdict,
+                cast_at: Utc::now(),
+            });
+            dispute.majority()
+        };
+
+        match decided {
+            Some(verdict) => self.resolve(dispute_id, verdict).await,
+            None => self.get_dispute(dispute_id).ok_or_else(|| anyhow!("Dispute {} not found", dispute_id)),
+        }
+    }
+
+    async fn resolve(&self, dispute_id: &str, verdict: Verdict) -> Result<Dispute> {
+        let dispute = self.get_open(dispute_id)?;
+        let mut slashed = 0;
+        if verdict == Verdict::Annul {
+            let (report, _) = self
+                .blockchain
+                .find_trust_report(&dispute.report_id)
+                .await
+                .ok_or_else(|| anyhow!("Trust report {} not found", dispute.report_id))?;
+            self.blockchain.annul_report(&report.id);
+            slashed = self
+                .blockchain
+                .staking_manager
+                .slash_report_stake(&report.reporter_id, report.stake_amount, &format!("report annulled by dispute {}", dispute_id))
+                .await?;
+        }
+
+        let mut dispute = self
+            .disputes
+            .get_mut(dispute_id)
+            .ok_or_else(|| anyhow!("Dispute {} not found", dispute_id))?;
+        dispute.status = DisputeStatus::Resolved { verdict, resolved_at: Utc::now() };
+        dispute.slashed = slashed;
+        info!("Dispute {} resolved: {:?}, {} points slashed", dispute_id, verdict, slashed);
+        Ok(dispute.clone())
+    }
+
+    /// Close open disputes whose deadline has passed without a majority
+    ///
+    /// Returns the IDs of the disputes closed.
+    pub fn expire_disputes(&self, now: DateTime<Utc>) -> Vec<String> {
+        let mut expired = Vec::new();
+        for mut dispute in self.disputes.iter_mut() {
+            if dispute.is_open() && dispute.deadline <= now {
+                dispute.status = DisputeStatus::Expired { expired_at: now };
+                expired.push(dispute.id.clone());
+            }
+        }
+        if !expired.is_empty() {
+            info!("Expired {} disputes without a majority", expired.len());
+        }
+        expired
+    }
+
+    fn get_open(&self, dispute_id: &str) -> Result<Dispute> {
+        let dispute = self.get_dispute(dispute_id).ok_or_else(|| anyhow!("Dispute {} not found", dispute_id))?;
+        if !dispute.is_open() {
+            return Err(anyhow!("Dispute {} is closed", dispute_id));
+        }
+        Ok(dispute)
+    }
+
+    pub fn get_dispute(&self, dispute_id: &str) -> Option<Dispute> {
+        self.disputes.get(dispute_id).map(|dispute| dispute.clone())
+    }
+
+    /// Disputes a participant is a party to or arbitrates, newest first
+    pub fn list_disputes(&self, participant_id: &str) -> Vec<Dispute> {
+        let mut disputes: Vec<Dispute> = self
+            .disputes
+            .iter()
+            .filter(|d| d.is_party(participant_id) || d.arbiters.iter().any(|a| a == participant_id))
+            .map(|d| d.clone())
+            .collect();
+        disputes.sort_by(|a, b| b.opened_at.cmp(&a.opened_at));
+        disputes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote(arbiter: &str, verdict: Verdict) -> DisputeVote {
+        DisputeVote { arbiter: arbiter.into(), verdict, cast_at: Utc::now() }
+    }
+
+    #[test]
+    fn test_dispute_majority() {
+        let mut dispute = Dispute {
+            id: "d1".into(),
+            report_id: "r1".into(),
+            reporter_id: "bob".into(),
+            subject_id: "alice".into(),
+            reason: "never interacted".into(),
+            opened_at: Utc::now(),
+            deadline: Utc::now() + Duration::hours(1),
+            arbiters: vec!["v1".into(), "v2".into(), "v3".into(), "v4".into()],
+            evidence: Vec::new(),
+            votes: Vec::new(),
+            status: DisputeStatus::Open,
+            slashed: 0,
+        };
+        dispute.votes.push(vote("v1", Verdict::Annul));
+        dispute.votes.push(vote("v2", Verdict::Annul));
+        assert_eq!(dispute.majority(), None);
+
+        // Three of four is a majority; a tie is not
+        dispute.votes.push(vote("v3", Verdict::Uphold));
+        assert_eq!(dispute.majority(), None);
+        dispute.votes.push(vote("v4", Verdict::Annul));
+        assert_eq!(dispute.majority(), Some(Verdict::Annul));
+        assert!(dispute.is_party("alice") && !dispute.is_party("v1"));
+    }
+}
@@ -4,12 +4,18 @@ pub mod registry;
 pub mod discovery;
 pub mod trust_manager;
 pub mod privacy_manager;
+pub mod search_index;
+pub mod decay;
+pub mod disputes;
 
 // Re-export key services
-pub use registry::ParticipantRegistry;
+pub use registry::{ParticipantRegistry, ParticipantFilter, ParticipantPage, ImportOptions, ImportReport};
 pub use discovery::DiscoveryService;
 pub use trust_manager::TrustManager;
 pub use privacy_manager::PrivacyManager;
+pub use search_index::{ParticipantSearchIndex, SearchFilters, SearchHit};
+pub use decay::{DecayScheduler, DecayStatus, DecayReport};
+pub use disputes::{Dispute, DisputeConfig, DisputeService, DisputeStatus, Verdict};
 
 // Type aliases for compatibility
 pub type RegistryService = ParticipantRegistry;
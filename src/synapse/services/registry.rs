@@ -1,16 +1,27 @@
 // Synapse Participant Registry Service
 // Core registry for managing participant profiles and relationships
 
-use crate::synapse::models::{ParticipantProfile, DiscoverabilityLevel};
+use crate::synapse::models::{ParticipantProfile, DiscoverabilityLevel, EntityType};
 use crate::blockchain::serialization::DateTimeWrapper;
 #[cfg(feature = "database")]
 use crate::synapse::storage::Database;
 #[cfg(feature = "cache")]
 use crate::synapse::storage::Cache;
 use crate::synapse::services::trust_manager::TrustManager;
+#[cfg(feature = "database")]
+use crate::identity::LocalIdentity;
+#[cfg(feature = "database")]
+use crate::lifecycle::{LifecycleNotice, LifecycleState, Tombstone};
+use crate::purge::PurgeTarget;
+#[cfg(feature = "database")]
+use crate::purge::{PurgeAudit, PurgedItem};
 use anyhow::{Context, Result};
-use chrono::Utc;
-use std::sync::Arc;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::sync::{Arc, RwLock};
 use tracing::info;
 
 /// Main participant registry service
@@ -20,6 +31,8 @@ pub struct ParticipantRegistry {
     #[cfg(feature = "cache")]
     cache: Arc<Cache>,
     trust_manager: Arc<TrustManager>,
+    /// Subsystems outside the registry that are purged with a participant
+    purge_targets: RwLock<Vec<Arc<dyn PurgeTarget>>>,
 }
 
 impl ParticipantRegistry {
@@ -34,6 +47,7 @@ impl ParticipantRegistry {
             database,
             cache,
             trust_manager,
+            purge_targets: RwLock::new(Vec::new()),
         })
     }
     
@@ -46,6 +60,7 @@ impl ParticipantRegistry {
         Ok(Self {
             database,
             trust_manager,
+            purge_targets: RwLock::new(Vec::new()),
         })
     }
     
@@ -58,6 +73,7 @@ impl ParticipantRegistry {
         Ok(Self {
             cache,
             trust_manager,
+            purge_targets: RwLock::new(Vec::new()),
         })
     }
     
@@ -68,6 +84,7 @@ impl ParticipantRegistry {
     ) -> Result<Self> {
         Ok(Self {
             trust_manager,
+            purge_targets: RwLock::new(Vec::new()),
         })
     }
     
@@ -82,6 +99,7 @@ impl ParticipantRegistry {
 
         // Validate the profile
         self.validate_profile(&profile)?;
+        self.ensure_not_tombstoned(&profile.global_id).await?;
         
         // Store in database
         self.database.upsert_participant(&profile).await
@@ -89,7 +107,7 @@ impl ParticipantRegistry {
         
         // Cache the profile
         let cache_key = format!("participant:{}", profile.global_id);
-        self.cache.cache_participant(&cache_key, &profile, 3600).await // 1 hour TTL
+        self.cache.write_through(&cache_key, &profile, 3600).await // 1 hour TTL
             .context("Failed to cache participant")?;
         
         // Initialize trust balance
@@ -111,6 +129,7 @@ impl ParticipantRegistry {
         
         // Validate the profile
         self.validate_profile(&profile)?;
+        self.ensure_not_tombstoned(&profile.global_id).await?;
         
         // Store in database
         self.database.upsert_participant(&profile).await
@@ -188,10 +207,10 @@ impl ParticipantRegistry {
         self.database.upsert_participant(&profile).await
             .context("Failed to update participant in database")?;
         
-        // Invalidate cache
+        // Write through so concurrent readers cannot repopulate the old profile
         let cache_key = format!("participant:{}", profile.global_id);
-        self.cache.invalidate(&cache_key).await
-            .context("Failed to invalidate cache")?;
+        self.cache.write_through(&cache_key, &profile, 3600).await
+            .context("Failed to update cached participant")?;
         
         info!("Updated participant: {}", profile.global_id);
         Ok(())
@@ -262,21 +281,21 @@ impl ParticipantRegistry {
     pub async fn get_participant(&self, global_id: &str) -> Result<Option<ParticipantProfile>> {
         let cache_key = format!("participant:{}", global_id);
         
-        // Try cache first
-        if let Ok(Some(cached)) = self.cache.get_cached::<ParticipantProfile>(&cache_key).await {
-            return Ok(Some(cached));
-        }
-        
-        // Fall back to database
-        let profile = self.database.get_participant(global_id).await
-            .context("Failed to get participant from database")?;
-        
-        // Cache the result if found
-        if let Some(ref p) = profile {
-            let _ = self.cache.cache_participant(&cache_key, p, 3600).await;
+        // Try cache first, coalescing concurrent misses into one database read
+        let loaded = std::sync::atomic::AtomicBool::new(false);
+        let result = self.cache.get_or_load(&cache_key, 3600, || async {
+            loaded.store(true, std::sync::atomic::Ordering::Relaxed);
+            self.database.get_participant(global_id).await
+                .context("Failed to get participant from database")
+        }).await;
+        
+        match result {
+            Ok(profile) => Ok(profile),
+            Err(e) if loaded.load(std::sync::atomic::Ordering::Relaxed) => Err(e),
+            // Cache unavailable: fall back to the database
+            Err(_) => self.database.get_participant(global_id).await
+                .context("Failed to get participant from database"),
         }
-        
-        Ok(profile)
     }
     
     /// Get participant by global ID with database only
@@ -607,7 +626,7 @@ impl ParticipantRegistry {
             self.database.upsert_participant(&profile).await?;
             
             let cache_key = format!("participant:{}", global_id);
-            let _ = self.cache.invalidate(&cache_key).await;
+            let _ = self.cache.write_through(&cache_key, &profile, 3600).await;
         }
         Ok(())
     }
@@ -627,6 +646,348 @@ impl ParticipantRegistry {
         Ok(())
     }
     
+    /// Current lifecycle state of a participant
+    #[cfg(feature = "database")]
+    pub async fn lifecycle_state(&self, global_id: &str) -> Result<LifecycleState> {
+        let notice = self.database.get_lifecycle_notice(global_id).await?;
+        Ok(notice.map(|n| n.state).unwrap_or_default())
+    }
+    
+    /// Suspend a participant; sends to them fail until `until` or reinstatement
+    #[cfg(feature = "database")]
+    pub async fn suspend_participant(
+        &self,
+        issuer: &LocalIdentity,
+        global_id: &str,
+        reason: &str,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<LifecycleNotice> {
+        let state = LifecycleState::Suspended {
+            reason: reason.to_string(),
+            until: until.map(DateTimeWrapper::new),
+        };
+        self.change_lifecycle(issuer, global_id, state).await
+    }
+    
+    /// Lift a suspension or deprecation
+    #[cfg(feature = "database")]
+    pub async fn reinstate_participant(&self, issuer: &LocalIdentity, global_id: &str) -> Result<LifecycleNotice> {
+        self.change_lifecycle(issuer, global_id, LifecycleState::Active).await
+    }
+    
+    /// Retire a participant in favour of `successor`, which receives forwarded messages
+    #[cfg(feature = "database")]
+    pub async fn deprecate_participant(
+        &self,
+        issuer: &LocalIdentity,
+        global_id: &str,
+        successor: &str,
+    ) -> Result<LifecycleNotice> {
+        if successor == global_id || self.get_participant(successor).await?.is_none() {
+            return Err(anyhow::anyhow!("Successor {} is not a registered participant", successor));
+        }
+        let state = LifecycleState::Deprecated { successor: successor.to_string() };
+        self.change_lifecycle(issuer, global_id, state).await
+    }
+    
+    /// Delete a participant's profile and record a signed tombstone
+    ///
+    /// The tombstone should be passed to peers (e.g. with
+    /// `EnhancedSynapseRouter::announce_lifecycle`) so they drop cached data;
+    /// the global ID cannot be registered again.
+    #[cfg(feature = "database")]
+    pub async fn delete_participant(&self, issuer: &LocalIdentity, global_id: &str, reason: &str) -> Result<Tombstone> {
+        let tombstone = self
+            .change_lifecycle(issuer, global_id, LifecycleState::Deleted { reason: reason.to_string() })
+            .await?;
+        self.database.delete_participant(global_id).await?;
+        info!("Deleted participant {}", global_id);
+        Ok(tombstone)
+    }
+    
+    /// Tombstones issued since `since`, for peers catching up on deletions
+    #[cfg(feature = "database")]
+    pub async fn tombstones_since(&self, since: DateTime<Utc>) -> Result<Vec<Tombstone>> {
+        self.database.list_tombstones(since).await
+    }
+    
+    /// Purge a subsystem's data along with the registry's own when a
+    /// participant is purged, e.g. the router or the trust blockchain
+    pub fn add_purge_target(&self, target: Arc<dyn PurgeTarget>) {
+        self.purge_targets.write().unwrap().push(target);
+    }
+    
+    /// Erase a participant on request (the right to be forgotten)
+    ///
+    /// Deletes the profile behind a signed tombstone unless one already
+    /// exists, then removes every row naming the participant, drops cached
+    /// resolutions, redacts the stored blockchain and purges each registered
+    /// [`PurgeTarget`]. Failures are recorded rather than aborting the purge.
+    /// The returned audit is stored and names the participant only by
+    /// pseudonym.
+    #[cfg(feature = "database")]
+    pub async fn purge_participant(&self, issuer: &LocalIdentity, global_id: &str) -> Result<PurgeAudit> {
+        let mut audit = PurgeAudit::new(global_id, issuer.global_id.clone());
+        
+        // Read before deletion: the cached lookups listing this participant
+        let profile = self.database.get_participant(global_id).await?;
+        let tombstoned = self.database.get_lifecycle_notice(global_id).await?.is_some_and(|n| n.is_tombstone());
+        if !tombstoned && profile.is_some() {
+            self.delete_participant(issuer, global_id, "erasure request").await?;
+        }
+        
+        match self.database.purge_participant_data(global_id).await {
+            Ok(deleted) => {
+                for (table, count) in deleted {
+                    audit.record(PurgedItem::deleted(format!("database.{}", table), count));
+                }
+            }
+            Err(e) => audit.record(PurgedItem::failed("database", e)),
+        }
+        
+        let pseudonym = crate::purge::pseudonym(global_id);
+        match self.database.redact_blockchain_references(global_id, &pseudonym).await {
+            Ok(count) => audit.record(PurgedItem::redacted(
+                "database.blockchain_transactions",
+                count,
+                "ledger rows re-keyed to pseudonym; block hashes unchanged",
+            )),
+            Err(e) => audit.record(PurgedItem::failed("database.blockchain_transactions", e)),
+        }
+        
+        #[cfg(feature = "cache")]
+        {
+            let mut keys = vec![format!("participant:{}", global_id)];
+            if let Some(profile) = &profile {
+                if let Some(org) = &profile.organizational_context {
+                    keys.push(format!("org:{}", org.organization_id));
+                    keys.push(format!("org:{}", org.organization_name));
+                }
+                keys.extend(profile.topic_subscriptions.iter().map(|t| format!("topic:{}", t.topic)));
+            }
+            let mut cleared = 0;
+            for key in keys {
+                match self.cache.invalidate_versioned(&key).await {
+                    Ok(()) => cleared += 1,
+                    Err(e) => audit.record(PurgedItem::failed("cache", e)),
+                }
+            }
+            audit.record(PurgedItem::deleted("cache", cleared));
+        }
+        
+        let targets = self.purge_targets.read().unwrap().clone();
+        for target in targets {
+            let result = target.purge(global_id).await;
+            audit.record_target(target.name(), result);
+        }
+        
+        audit.complete();
+        self.database.insert_purge_audit(&audit).await?;
+        info!(
+            target: "synapse::audit",
+            audit_id = %audit.id,
+            subject = %audit.subject,
+            requested_by = %audit.requested_by,
+            deleted = audit.deleted(),
+            redacted = audit.redacted(),
+            complete = audit.is_complete(),
+            "Participant purged"
+        );
+        Ok(audit)
+    }
+    
+    /// Audits of earlier purges of a participant
+    #[cfg(feature = "database")]
+    pub async fn purge_audits(&self, global_id: &str) -> Result<Vec<PurgeAudit>> {
+        self.database.list_purge_audits(&crate::purge::pseudonym(global_id)).await
+    }
+    
+    #[cfg(feature = "database")]
+    async fn change_lifecycle(
+        &self,
+        issuer: &LocalIdentity,
+        global_id: &str,
+        state: LifecycleState,
+    ) -> Result<LifecycleNotice> {
+        self.ensure_not_tombstoned(global_id).await?;
+        if self.get_participant(global_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Participant not found: {}", global_id));
+        }
+        
+        let notice = LifecycleNotice::sign(issuer, global_id, state)?;
+        self.database.upsert_lifecycle_notice(&notice).await?;
+        
+        #[cfg(feature = "cache")]
+        {
+            let cache_key = format!("participant:{}", global_id);
+            let _ = self.cache.invalidate_versioned(&cache_key).await;
+        }
+        
+        info!("Participant {} is now {}", global_id, notice.state.name());
+        Ok(notice)
+    }
+    
+    /// Refuse to act on an identity that has been deleted
+    #[cfg(feature = "database")]
+    async fn ensure_not_tombstoned(&self, global_id: &str) -> Result<()> {
+        match self.database.get_lifecycle_notice(global_id).await? {
+            Some(notice) if notice.is_tombstone() => {
+                Err(anyhow::anyhow!("Participant {} has been deleted", global_id))
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    /// List participants matching `filter`, one page at a time. Pass the
+    /// previous page's `next_cursor` to continue; `limit` is capped at
+    /// [`MAX_PAGE_SIZE`].
+    #[cfg(feature = "database")]
+    pub async fn list_participants(
+        &self,
+        filter: &ParticipantFilter,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ParticipantPage> {
+        let after = cursor.map(decode_cursor).transpose()?;
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        
+        // Fetch one extra row to learn whether another page follows
+        let mut participants = self.database.list_participants(filter, after.as_deref(), limit + 1).await
+            .context("Failed to list participants")?;
+        let next_cursor = if participants.len() > limit {
+            participants.truncate(limit);
+            participants.last().map(|p| encode_cursor(&p.global_id))
+        } else {
+            None
+        };
+        
+        Ok(ParticipantPage { participants, next_cursor })
+    }
+    
+    #[cfg(not(feature = "database"))]
+    pub async fn list_participants(
+        &self,
+        _filter: &ParticipantFilter,
+        _cursor: Option<&str>,
+        _limit: usize,
+    ) -> Result<ParticipantPage> {
+        Ok(ParticipantPage::default())
+    }
+    
+    /// Import participants in batches, e.g. when migrating from another
+    /// registry. Invalid profiles are reported and skipped; each batch is
+    /// written in one transaction.
+    #[cfg(feature = "database")]
+    pub async fn import_participants(
+        &self,
+        profiles: Vec<ParticipantProfile>,
+        options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        let mut report = ImportReport::default();
+        let mut valid = Vec::with_capacity(profiles.len());
+        for profile in profiles {
+            let checked = match self.validate_profile(&profile) {
+                Ok(()) => self.ensure_not_tombstoned(&profile.global_id).await,
+                Err(e) => Err(e),
+            };
+            match checked {
+                Ok(()) => valid.push(profile),
+                Err(e) => report.failed.push((profile.global_id, e.to_string())),
+            }
+        }
+        
+        for batch in valid.chunks(options.batch_size.max(1)) {
+            let written = match self.database.bulk_upsert_participants(batch, options.overwrite).await {
+                Ok(written) => written,
+                Err(e) if !options.stop_on_error => {
+                    let reason = format!("{:#}", e);
+                    report.failed.extend(batch.iter().map(|p| (p.global_id.clone(), reason.clone())));
+                    continue;
+                }
+                Err(e) => return Err(e.context(format!("Import stopped after {} participants", report.imported + report.updated))),
+            };
+            
+            report.skipped += batch.len() - written.len();
+            for (global_id, inserted) in written {
+                if inserted {
+                    report.imported += 1;
+                    self.trust_manager.initialize_participant(&global_id).await
+                        .context("Failed to initialize trust balance")?;
+                } else {
+                    report.updated += 1;
+                    #[cfg(feature = "cache")]
+                    {
+                        let cache_key = format!("participant:{}", global_id);
+                        let _ = self.cache.invalidate_versioned(&cache_key).await;
+                    }
+                }
+            }
+        }
+        
+        info!(
+            "Imported participants: {} new, {} updated, {} skipped, {} failed",
+            report.imported, report.updated, report.skipped, report.failed.len()
+        );
+        Ok(report)
+    }
+    
+    #[cfg(not(feature = "database"))]
+    pub async fn import_participants(
+        &self,
+        _profiles: Vec<ParticipantProfile>,
+        _options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        Err(anyhow::anyhow!("Bulk import requires the database feature"))
+    }
+    
+    /// Import participants from JSON Lines, one profile per line, as
+    /// written by [`export_participants`](Self::export_participants)
+    pub async fn import_participants_jsonl<R: BufRead>(
+        &self,
+        reader: R,
+        options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        let mut report = ImportReport::default();
+        let mut batch = Vec::with_capacity(options.batch_size);
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.context("Failed to read import stream")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<ParticipantProfile>(&line) {
+                Ok(profile) => batch.push(profile),
+                Err(e) => report.failed.push((format!("line {}", index + 1), e.to_string())),
+            }
+            if batch.len() >= options.batch_size.max(1) {
+                report.merge(self.import_participants(std::mem::take(&mut batch), options).await?);
+            }
+        }
+        if !batch.is_empty() {
+            report.merge(self.import_participants(batch, options).await?);
+        }
+        Ok(report)
+    }
+    
+    /// Write every participant matching `filter` as JSON Lines; returns the count
+    pub async fn export_participants<W: Write>(&self, filter: &ParticipantFilter, mut writer: W) -> Result<usize> {
+        let mut exported = 0;
+        let mut cursor = None;
+        loop {
+            let page = self.list_participants(filter, cursor.as_deref(), EXPORT_PAGE_SIZE).await?;
+            for profile in &page.participants {
+                serde_json::to_writer(&mut writer, profile).context("Failed to serialize participant")?;
+                writer.write_all(b"\n").context("Failed to write export")?;
+            }
+            exported += page.participants.len();
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        writer.flush().context("Failed to write export")?;
+        Ok(exported)
+    }
+    
     /// Validate a participant profile
     fn validate_profile(&self, profile: &ParticipantProfile) -> Result<()> {
         if profile.global_id.is_empty() {
@@ -753,8 +1114,114 @@ impl Default for ContactSearchQuery {
     }
 }
 
+/// Largest page [`ParticipantRegistry::list_participants`] returns
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+const EXPORT_PAGE_SIZE: usize = 500;
+
+/// Filters for listing participants; unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParticipantFilter {
+    /// Match any of these entity types
+    #[serde(default)]
+    pub entity_types: Vec<EntityType>,
+    /// Organization ID or name
+    pub organization: Option<String>,
+    /// Network trust score range (0-100), inclusive
+    pub min_trust: Option<f64>,
+    pub max_trust: Option<f64>,
+    pub last_seen_after: Option<DateTime<Utc>>,
+    pub last_seen_before: Option<DateTime<Utc>>,
+    /// Regions to include, e.g. "eu" for every EU region; empty = all
+    #[serde(default)]
+    pub regions: Vec<String>,
+    /// Include private and stealth participants (administrative use)
+    #[serde(default)]
+    pub include_hidden: bool,
+}
+
+/// One page of [`ParticipantRegistry::list_participants`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParticipantPage {
+    pub participants: Vec<ParticipantProfile>,
+    /// Opaque cursor for the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Options for bulk participant import
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Replace participants that already exist instead of skipping them
+    pub overwrite: bool,
+    /// Profiles written per transaction
+    pub batch_size: usize,
+    /// Abort on the first failed batch instead of reporting it and continuing
+    pub stop_on_error: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            overwrite: false,
+            batch_size: 500,
+            stop_on_error: false,
+        }
+    }
+}
+
+/// Outcome of a bulk import
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub updated: usize,
+    /// Already present and left alone because `overwrite` was off
+    pub skipped: usize,
+    /// Participant ID (or input line) and the reason it was not imported
+    pub failed: Vec<(String, String)>,
+}
+
+impl ImportReport {
+    fn merge(&mut self, other: ImportReport) {
+        self.imported += other.imported;
+        self.updated += other.updated;
+        self.skipped += other.skipped;
+        self.failed.extend(other.failed);
+    }
+}
+
+fn encode_cursor(global_id: &str) -> String {
+    URL_SAFE_NO_PAD.encode(global_id)
+}
+
+fn decode_cursor(cursor: &str) -> Result<String> {
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).context("Invalid participant cursor")?;
+    String::from_utf8(bytes).context("Invalid participant cursor")
+}
+
 /// Type alias for compatibility with API
 pub type RegistryService = ParticipantRegistry;
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = encode_cursor("alice.work@ai-lab.com");
+        assert!(!cursor.contains('@'));
+        assert_eq!(decode_cursor(&cursor).unwrap(), "alice.work@ai-lab.com");
+        assert!(decode_cursor("not a cursor!").is_err());
+    }
 
+    #[test]
+    fn test_import_reports_merge() {
+        let mut total = ImportReport { imported: 2, ..Default::default() };
+        total.merge(ImportReport {
+            imported: 1,
+            updated: 3,
+            skipped: 1,
+            failed: vec![("line 4".to_string(), "bad json".to_string())],
+        });
+        assert_eq!((total.imported, total.updated, total.skipped, total.failed.len()), (3, 3, 1, 1));
+    }
+}
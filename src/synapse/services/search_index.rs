@@ -0,0 +1,424 @@
+// Natural-language participant search
+// Trigram index over profile descriptions and capabilities
+
+use crate::synapse::models::participant::{DiscoverabilityLevel, ExpertiseLevel, SubscriptionType};
+use crate::synapse::models::ParticipantProfile;
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+
+/// Words that carry no meaning in a discovery query
+const STOPWORDS: &[&str] = &[
+    "a", "an", "the", "at", "in", "on", "of", "for", "from", "with", "who", "that", "which",
+    "is", "are", "someone", "somebody", "anyone", "find", "me", "please", "and", "or", "to",
+];
+
+/// Words that signal the caller wants an expert rather than anyone with the topic
+const EXPERT_WORDS: &[&str] = &["expert", "experts", "specialist", "authority", "guru"];
+
+/// Minimum relevance for a profile to be returned
+const MIN_SCORE: f64 = 0.25;
+
+/// Structured filters applied on top of a text query
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+    /// Entity types to include (e.g. "AiModel"), empty = all
+    pub entity_types: Vec<String>,
+    /// Organization names or IDs to include, empty = all
+    pub organizations: Vec<String>,
+    /// Global ID domains to include (e.g. "example.com"), empty = all
+    pub domains: Vec<String>,
+    /// Capabilities or topics every result must have
+    pub capabilities: Vec<String>,
+    /// Regions to include (e.g. "eu" or "eu-west"), empty = all; participants
+    /// without a region are excluded when set
+    pub regions: Vec<String>,
+    /// Minimum network trust score (0-100)
+    pub min_trust_score: Option<f64>,
+    /// Only return participants subscribed as experts
+    pub experts_only: bool,
+}
+
+/// A parsed natural-language query
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedQuery {
+    /// Meaningful terms from the query
+    pub terms: Vec<String>,
+    /// Domain mentioned in the query ("at example.com", "@example.com")
+    pub domain: Option<String>,
+    /// Whether the query asked for an expert
+    pub wants_expert: bool,
+}
+
+impl ParsedQuery {
+    /// Parse a query such as "the rust compiler expert at example.com"
+    pub fn parse(query: &str) -> Self {
+        let mut parsed = ParsedQuery::default();
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '.' && c != '@' && c != '-'))
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+
+        for (i, word) in words.iter().enumerate() {
+            let after_at = i > 0 && words[i - 1] == "at";
+            if let Some(domain) = word.strip_prefix('@') {
+                parsed.domain = Some(domain.trim_end_matches('.').to_string());
+            } else if word.contains('.') && (after_at || word.split('.').all(|p| !p.is_empty())) {
+                parsed.domain = Some(word.trim_end_matches('.').to_string());
+            } else if EXPERT_WORDS.contains(&word.as_str()) {
+                parsed.wants_expert = true;
+            } else if !STOPWORDS.contains(&word.as_str()) {
+                parsed.terms.push(word.trim_end_matches('.').to_string());
+            }
+        }
+
+        parsed
+    }
+}
+
+/// A search hit with its relevance score
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub profile: ParticipantProfile,
+    pub score: f64,
+    /// Terms that matched, for explaining results
+    pub matched_terms: Vec<String>,
+}
+
+/// Indexed, lowercased text for one participant
+#[derive(Debug, Clone)]
+struct IndexedDocument {
+    profile: ParticipantProfile,
+    words: HashSet<String>,
+    trigrams: HashSet<String>,
+    capabilities: HashSet<String>,
+    expert_topics: HashSet<String>,
+}
+
+/// In-memory trigram index over participant profiles
+#[derive(Debug, Default)]
+pub struct ParticipantSearchIndex {
+    documents: DashMap<String, IndexedDocument>,
+    /// Trigram -> global IDs containing it
+    postings: DashMap<String, HashSet<String>>,
+}
+
+fn trigrams(word: &str) -> Vec<String> {
+    let padded: Vec<char> = format!("  {} ", word).chars().collect();
+    padded.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+fn domain_of(global_id: &str) -> &str {
+    global_id.rsplit('@').next().unwrap_or("")
+}
+
+impl ParticipantSearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or refresh a participant in the index
+    pub fn index(&self, profile: &ParticipantProfile) {
+        self.remove(&profile.global_id);
+
+        let mut text = vec![profile.display_name.clone(), profile.global_id.clone()];
+        let mut capabilities = HashSet::new();
+        let mut expert_topics = HashSet::new();
+
+        for identity in &profile.identities {
+            text.push(identity.name.clone());
+            text.extend(identity.role.clone());
+            text.extend(identity.organization.clone());
+            text.extend(identity.department.clone());
+            for capability in &identity.capabilities {
+                text.push(capability.clone());
+                capabilities.insert(capability.to_lowercase());
+            }
+        }
+
+        for topic in &profile.topic_subscriptions {
+            text.push(topic.topic.clone());
+            capabilities.insert(topic.topic.to_lowercase());
+            let is_expert = matches!(topic.subscription_type, SubscriptionType::Expert)
+                || matches!(topic.expertise_level, ExpertiseLevel::Expert | ExpertiseLevel::Authority);
+            if is_expert {
+                expert_topics.insert(topic.topic.to_lowercase());
+            }
+        }
+
+        if let Some(org) = &profile.organizational_context {
+            text.push(org.organization_name.clone());
+            text.push(org.role.clone());
+            text.extend(org.department.clone());
+            text.extend(org.team.clone());
+        }
+
+        let words: HashSet<String> = text.iter().flat_map(|t| tokenize(t)).collect();
+        let grams: HashSet<String> = words.iter().flat_map(|w| trigrams(w)).collect();
+
+        for gram in &grams {
+            self.postings
+                .entry(gram.clone())
+                .or_default()
+                .insert(profile.global_id.clone());
+        }
+
+        self.documents.insert(
+            profile.global_id.clone(),
+            IndexedDocument {
+                profile: profile.clone(),
+                words,
+                trigrams: grams,
+                capabilities,
+                expert_topics,
+            },
+        );
+    }
+
+    /// Remove a participant from the index
+    pub fn remove(&self, global_id: &str) {
+        if let Some((_, doc)) = self.documents.remove(global_id) {
+            for gram in doc.trigrams {
+                if let Some(mut ids) = self.postings.get_mut(&gram) {
+                    ids.remove(global_id);
+                }
+            }
+            self.postings.retain(|_, ids| !ids.is_empty());
+        }
+    }
+
+    /// Look up an indexed profile
+    pub fn get(&self, global_id: &str) -> Option<ParticipantProfile> {
+        self.documents.get(global_id).map(|d| d.profile.clone())
+    }
+
+    /// Number of indexed participants
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Whether `requester` may see `target` in search results
+    ///
+    /// Search only ever surfaces Public profiles, plus Unlisted profiles the
+    /// requester is connected to. Private and Stealth profiles never appear.
+    fn visible_to(target: &ParticipantProfile, requester: Option<&ParticipantProfile>) -> bool {
+        match target.discovery_permissions.discoverability {
+            DiscoverabilityLevel::Public => true,
+            DiscoverabilityLevel::Unlisted => requester.is_some_and(|r| {
+                target.global_id == r.global_id || target.can_be_discovered_by(r)
+            }),
+            DiscoverabilityLevel::Private | DiscoverabilityLevel::Stealth => false,
+        }
+    }
+
+    fn passes_filters(doc: &IndexedDocument, filters: &SearchFilters, parsed: &ParsedQuery) -> bool {
+        let profile = &doc.profile;
+
+        if !filters.entity_types.is_empty() {
+            let entity_type = format!("{:?}", profile.entity_type).to_lowercase();
+            if !filters.entity_types.iter().any(|t| t.to_lowercase().replace('_', "") == entity_type) {
+                return false;
+            }
+        }
+
+        if !filters.organizations.is_empty() {
+            let matches_org = profile.organizational_context.as_ref().is_some_and(|org| {
+                filters.organizations.iter().any(|o| {
+                    o.eq_ignore_ascii_case(&org.organization_id) || o.eq_ignore_ascii_case(&org.organization_name)
+                })
+            });
+            if !matches_org {
+                return false;
+            }
+        }
+
+        let domain = domain_of(&profile.global_id).to_lowercase();
+        let domain_matches = |wanted: &str| {
+            let wanted = wanted.to_lowercase();
+            domain == wanted || domain.ends_with(&format!(".{}", wanted))
+        };
+        if !filters.domains.is_empty() && !filters.domains.iter().any(|d| domain_matches(d)) {
+            return false;
+        }
+        if let Some(wanted) = &parsed.domain {
+            if !domain_matches(wanted) {
+                return false;
+            }
+        }
+
+        if !filters.regions.is_empty() && !crate::geo::within_any(profile.region.as_deref(), &filters.regions) {
+            return false;
+        }
+
+        if !filters
+            .capabilities
+            .iter()
+            .all(|c| doc.capabilities.contains(&c.to_lowercase()))
+        {
+            return false;
+        }
+
+        if let Some(min) = filters.min_trust_score {
+            if profile.trust_ratings.network_trust.network_score < min {
+                return false;
+            }
+        }
+
+        if (filters.experts_only || parsed.wants_expert) && doc.expert_topics.is_empty() {
+            return false;
+        }
+
+        true
+    }
+
+    /// Score how well a document matches the query terms
+    fn score(doc: &IndexedDocument, parsed: &ParsedQuery) -> (f64, Vec<String>) {
+        if parsed.terms.is_empty() {
+            return (1.0, Vec::new());
+        }
+
+        let mut total = 0.0;
+        let mut matched = Vec::new();
+        for term in &parsed.terms {
+            let term_score = if doc.words.contains(term) {
+                1.0
+            } else {
+                let grams = trigrams(term);
+                let hits = grams.iter().filter(|g| doc.trigrams.contains(*g)).count();
+                hits as f64 / grams.len().max(1) as f64 * 0.8
+            };
+            if term_score >= 0.5 {
+                matched.push(term.clone());
+            }
+            // Expertise in the very topic asked about counts extra
+            let expert_bonus = if parsed.wants_expert && doc.expert_topics.iter().any(|t| t.contains(term.as_str())) {
+                0.5
+            } else {
+                0.0
+            };
+            total += term_score + expert_bonus;
+        }
+
+        (total / parsed.terms.len() as f64, matched)
+    }
+
+    /// Search the index with a natural-language query and structured filters
+    pub fn search(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        requester: Option<&ParticipantProfile>,
+        max_results: usize,
+    ) -> Vec<SearchHit> {
+        let parsed = ParsedQuery::parse(query);
+
+        // Candidate set from trigram postings; an empty query scans everything
+        let candidates: Vec<String> = if parsed.terms.is_empty() {
+            self.documents.iter().map(|d| d.key().clone()).collect()
+        } else {
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for term in &parsed.terms {
+                for gram in trigrams(term) {
+                    if let Some(ids) = self.postings.get(&gram) {
+                        for id in ids.iter() {
+                            *counts.entry(id.clone()).or_insert(0) += 1;
+                        }
+                    }
+                }
+            }
+            counts.into_keys().collect()
+        };
+
+        let mut hits: Vec<SearchHit> = candidates
+            .iter()
+            .filter_map(|id| self.documents.get(id))
+            .filter(|doc| Self::visible_to(&doc.profile, requester))
+            .filter(|doc| Self::passes_filters(doc, filters, &parsed))
+            .filter_map(|doc| {
+                let (score, matched_terms) = Self::score(&doc, &parsed);
+                (score >= MIN_SCORE).then(|| SearchHit {
+                    profile: doc.profile.clone(),
+                    score,
+                    matched_terms,
+                })
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        hits.truncate(max_results);
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synapse::models::participant::{EntityType, TopicSubscription};
+
+    fn profile(global_id: &str, name: &str, topic: &str, expert: bool, level: DiscoverabilityLevel) -> ParticipantProfile {
+        let mut profile = ParticipantProfile::new(global_id.to_string(), name.to_string(), EntityType::AiModel);
+        profile.discovery_permissions.discoverability = level;
+        profile.topic_subscriptions.push(TopicSubscription {
+            topic: topic.to_string(),
+            subscription_type: if expert { SubscriptionType::Expert } else { SubscriptionType::Interested },
+            expertise_level: if expert { ExpertiseLevel::Expert } else { ExpertiseLevel::Beginner },
+            contact_preferences: None,
+        });
+        profile
+    }
+
+    #[test]
+    fn test_query_parsing() {
+        let parsed = ParsedQuery::parse("the rust compiler expert at example.com");
+        assert_eq!(parsed.terms, vec!["rust", "compiler"]);
+        assert_eq!(parsed.domain.as_deref(), Some("example.com"));
+        assert!(parsed.wants_expert);
+    }
+
+    #[test]
+    fn test_natural_language_search_respects_discoverability() {
+        let index = ParticipantSearchIndex::new();
+        index.index(&profile("rustc-bot@example.com", "Ferris", "rust compiler", true, DiscoverabilityLevel::Public));
+        index.index(&profile("learner@example.com", "Newbie", "rust", false, DiscoverabilityLevel::Public));
+        index.index(&profile("hidden@example.com", "Ghost", "rust compiler", true, DiscoverabilityLevel::Private));
+        index.index(&profile("rust@other.org", "Elsewhere", "rust compiler", true, DiscoverabilityLevel::Public));
+
+        let hits = index.search("the rust compiler expert at example.com", &SearchFilters::default(), None, 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].profile.global_id, "rustc-bot@example.com");
+
+        // Misspellings still match through trigrams
+        let hits = index.search("rsut compilr", &SearchFilters::default(), None, 10);
+        assert!(hits.iter().any(|h| h.profile.global_id == "rustc-bot@example.com"));
+        assert!(hits.iter().all(|h| h.profile.global_id != "hidden@example.com"));
+    }
+
+    #[test]
+    fn test_region_filter_keeps_data_in_scope() {
+        let index = ParticipantSearchIndex::new();
+        let mut paris = profile("paris@example.com", "Paris", "translation", false, DiscoverabilityLevel::Public);
+        paris.region = Some("eu-west".to_string());
+        let mut virginia = profile("virginia@example.com", "Virginia", "translation", false, DiscoverabilityLevel::Public);
+        virginia.region = Some("us-east".to_string());
+        let untagged = profile("nowhere@example.com", "Nowhere", "translation", false, DiscoverabilityLevel::Public);
+        for p in [&paris, &virginia, &untagged] {
+            index.index(p);
+        }
+
+        let filters = SearchFilters { regions: vec!["eu".to_string()], ..SearchFilters::default() };
+        let hits = index.search("translation", &filters, None, 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].profile.global_id, "paris@example.com");
+    }
+}
@@ -7,29 +7,34 @@ use crate::synapse::models::{TrustBalance, TrustCategory};
 #[cfg(feature = "database")]
 use crate::synapse::storage::Database;
 use crate::synapse::blockchain::SynapseBlockchain;
+#[cfg(feature = "database")]
+use crate::synapse::blockchain::evidence::validate_evidence;
+use crate::synapse::services::decay::{DecayScheduler, DecayStatus};
+#[cfg(not(feature = "database"))]
+use crate::synapse::services::decay::MemoryScheduleStore;
 use anyhow::{Context, Result};
 use chrono::{Duration, Utc};
 use std::sync::Arc;
-use tokio::time::{interval, Duration as TokioDuration};
-use tracing::{info, warn};
+use tracing::info;
 #[cfg(feature = "database")]
 use sqlx::Row;
 
 // Security constants
 const MAX_DAILY_REPORTS: u64 = 10;
-const MAX_EVIDENCE_SIZE: usize = 1024 * 10; // 10KB max evidence size
 
 /// Trust management service for dual trust system
 #[cfg(feature = "database")]
 pub struct TrustManager {
     database: Arc<Database>,
     blockchain: Arc<SynapseBlockchain>,
+    decay: Arc<DecayScheduler>,
 }
 
 /// Simplified trust manager when database feature is not available
 #[cfg(not(feature = "database"))]
 pub struct TrustManager {
     blockchain: Arc<SynapseBlockchain>,
+    decay: Arc<DecayScheduler>,
 }
 
 #[cfg(feature = "database")]
@@ -39,9 +44,15 @@ impl TrustManager {
         database: Arc<Database>,
         blockchain: Arc<SynapseBlockchain>,
     ) -> Result<Self> {
+        let decay = Arc::new(DecayScheduler::new(
+            blockchain.config.trust_decay_config.clone(),
+            database.clone(),
+            database.clone(),
+        ));
         Ok(Self {
             database,
             blockchain,
+            decay,
         })
     }
     
@@ -237,17 +248,9 @@ impl TrustManager {
             return Err(anyhow::anyhow!("Trust score must be between -100 and 100"));
         }
         
-        // Validate evidence size
+        // Validate evidence size and content
         if let Some(ref evidence) = evidence {
-            if evidence.len() > MAX_EVIDENCE_SIZE {
-                return Err(anyhow::anyhow!("Evidence size exceeds maximum allowed length"));
-            }
-            
-            // Basic evidence sanitization
-            // This is a simple example - in production use proper sanitization
-            if evidence.contains("<script>") {
-                return Err(anyhow::anyhow!("Evidence contains potentially malicious content"));
-            }
+            validate_evidence(evidence)?;
         }
         
         // Rate limiting check
@@ -427,79 +430,23 @@ impl TrustManager {
         Ok(())
     }
     
-    /// Process trust point decay for inactive participants
+    /// Decay inactive participants' trust points for the period since the last run
     pub async fn process_decay(&self) -> Result<Vec<String>> {
-        let cutoff_time = Utc::now() - Duration::days(30); // 30 days inactivity
-        let balances = self.database.get_balances_for_decay(cutoff_time).await?;
-        
-        let mut processed = Vec::new();
-        
-        for mut balance in balances {
-            let duration = Utc::now().signed_duration_since(balance.clone().last_activity.into_inner());
-            let days_inactive = duration.num_days();
-            let months_inactive = days_inactive as f64 / 30.0;
-            
-            // Calculate decay amount
-            let decay_amount = (balance.total_points as f64 * balance.decay_rate * months_inactive) as u32;
-            
-            if decay_amount > 0 {
-                balance.total_points = balance.total_points.saturating_sub(decay_amount);
-                balance.available_points = balance.available_points.saturating_sub(decay_amount);
-                
-                // Don't let decay reduce staked points directly
-                if balance.available_points < balance.staked_points {
-                    balance.available_points = balance.staked_points;
-                    balance.total_points = balance.staked_points;
-                }
-                
-                // Update in database
-                self.database.upsert_trust_balance(&balance).await?;
-                processed.push(balance.participant_id.clone());
-                
-                info!(
-                    "Applied decay to {}: -{} points ({} days inactive)",
-                    balance.participant_id, decay_amount, days_inactive
-                );
-            }
-        }
-        
-        Ok(processed)
+        Ok(self.decay.run_once(Utc::now()).await?.processed)
     }
     
-    /// Start background task for periodic trust decay
+    /// Start the background decay task; runs missed while the node was down happen first
     pub async fn start_decay_scheduler(&self) -> Result<()> {
-        let database = self.database.clone();
-        let blockchain = self.blockchain.clone();
-        
-        tokio::spawn(async move {
-            let mut interval = interval(TokioDuration::from_secs(24 * 60 * 60)); // Daily
-            
-            loop {
-                interval.tick().await;
-                
-                // Create a temporary TrustManager for the task
-                let temp_manager = TrustManager {
-                    database: database.clone(),
-                    blockchain: blockchain.clone(),
-                };
-                
-                match temp_manager.process_decay().await {
-                    Ok(processed) => {
-                        if !processed.is_empty() {
-                            info!("Processed trust decay for {} participants", processed.len());
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Failed to process trust decay: {}", e);
-                    }
-                }
-            }
-        });
-        
+        self.decay.clone().start();
         info!("Started trust decay scheduler");
         Ok(())
     }
     
+    /// Last and next decay run, for the admin API
+    pub async fn decay_status(&self) -> Result<DecayStatus> {
+        self.decay.status().await
+    }
+    
     /// Stake trust points for specific purpose
     pub async fn stake_trust_points(
         &self,
@@ -652,8 +599,14 @@ impl TrustManager {
     pub async fn new(
         blockchain: Arc<SynapseBlockchain>,
     ) -> Result<Self> {
+        let decay = Arc::new(DecayScheduler::new(
+            blockchain.config.trust_decay_config.clone(),
+            blockchain.staking_manager.clone(),
+            Arc::new(MemoryScheduleStore::new()),
+        ));
         Ok(Self {
             blockchain,
+            decay,
         })
     }
 
@@ -718,18 +671,23 @@ impl TrustManager {
         Ok(0)
     }
 
-    /// Process trust point decay (simplified)
+    /// Decay staked balances for the period since the last run
     pub async fn process_decay(&self) -> Result<Vec<String>> {
-        Ok(vec![])
+        Ok(self.decay.run_once(Utc::now()).await?.processed)
     }
 
-    /// Start decay scheduler (simplified version)
+    /// Start the background decay task; without a database runs are not remembered across restarts
     pub async fn start_decay_scheduler(&self) -> Result<()> {
-        // Without database, nothing to schedule
+        self.decay.clone().start();
         info!("Trust decay scheduler started (simplified mode)");
         Ok(())
     }
 
+    /// Last and next decay run, for the admin API
+    pub async fn decay_status(&self) -> Result<DecayStatus> {
+        self.decay.status().await
+    }
+
     /// Get trust score (simplified version)
     pub async fn get_trust_score(&self, _subject_id: &str, _requester_id: &str) -> Result<f64> {
         // Return default neutral score
@@ -0,0 +1,254 @@
+// Synapse Attachment Store
+// Content-addressed storage for signed profile attachments
+
+use crate::crypto::CryptoManager;
+use crate::synapse::models::participant::{AttachmentKind, ParticipantProfile, ProfileAttachment};
+#[cfg(feature = "database")]
+use crate::synapse::storage::Database;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "database")]
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Maximum number of attachments a single profile may carry
+pub const MAX_ATTACHMENTS_PER_PROFILE: usize = 8;
+
+/// Hex-encoded SHA-256 content address
+pub fn content_address(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Source of attachment content held by remote peers
+#[async_trait]
+pub trait AttachmentSource: Send + Sync {
+    /// Fetch the content with `content_hash` from the profile owner (or a relay)
+    async fn fetch(&self, owner_global_id: &str, content_hash: &str) -> Result<Vec<u8>>;
+}
+
+/// Content-addressed attachment store with lazy peer fetching
+///
+/// Content is cached in memory and, when the `database` feature is enabled,
+/// persisted to the `attachment_blobs` table. Nothing is returned for display
+/// until its size, hash and owner signature have been checked.
+pub struct AttachmentStore {
+    blobs: DashMap<String, Vec<u8>>,
+    #[cfg(feature = "database")]
+    database: Option<Arc<Database>>,
+}
+
+impl AttachmentStore {
+    /// Create an in-memory attachment store
+    pub fn new() -> Self {
+        Self {
+            blobs: DashMap::new(),
+            #[cfg(feature = "database")]
+            database: None,
+        }
+    }
+
+    /// Create an attachment store backed by the database
+    #[cfg(feature = "database")]
+    pub fn with_database(database: Arc<Database>) -> Self {
+        Self {
+            blobs: DashMap::new(),
+            database: Some(database),
+        }
+    }
+
+    /// Store and sign a new attachment for our own profile
+    pub async fn attach(
+        &self,
+        profile: &mut ParticipantProfile,
+        kind: AttachmentKind,
+        media_type: &str,
+        data: Vec<u8>,
+        signer: &CryptoManager,
+    ) -> Result<ProfileAttachment> {
+        let size_bytes = data.len() as u64;
+        if size_bytes > kind.max_size_bytes() {
+            return Err(anyhow!(
+                "{} attachment is {} bytes, limit is {}",
+                kind, size_bytes, kind.max_size_bytes()
+            ));
+        }
+
+        let replacing = profile.attachment(&kind).is_some();
+        if !replacing && profile.attachments.len() >= MAX_ATTACHMENTS_PER_PROFILE {
+            return Err(anyhow!("Profile already has {} attachments", MAX_ATTACHMENTS_PER_PROFILE));
+        }
+
+        let mut attachment = ProfileAttachment {
+            kind: kind.clone(),
+            content_hash: content_address(&data),
+            media_type: media_type.to_string(),
+            size_bytes,
+            signature: Vec::new(),
+            added_at: Utc::now(),
+        };
+        attachment.signature = signer
+            .sign_message(&attachment.signing_payload(&profile.global_id))
+            .map_err(|e| anyhow!("Failed to sign attachment: {}", e))?;
+
+        self.put(&attachment, data).await?;
+
+        profile.attachments.retain(|a| a.kind != kind);
+        profile.attachments.push(attachment.clone());
+        profile.updated_at = Utc::now();
+
+        Ok(attachment)
+    }
+
+    /// Store content under its content address
+    async fn put(&self, attachment: &ProfileAttachment, data: Vec<u8>) -> Result<()> {
+        #[cfg(feature = "database")]
+        if let Some(db) = &self.database {
+            db.put_attachment_blob(&attachment.content_hash, &attachment.media_type, &data).await?;
+        }
+        self.blobs.insert(attachment.content_hash.clone(), data);
+        Ok(())
+    }
+
+    /// Look up content locally (memory, then database)
+    async fn get_local(&self, content_hash: &str) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.blobs.get(content_hash) {
+            return Ok(Some(data.clone()));
+        }
+
+        #[cfg(feature = "database")]
+        if let Some(db) = &self.database {
+            if let Some(data) = db.get_attachment_blob(content_hash).await? {
+                self.blobs.insert(content_hash.to_string(), data.clone());
+                return Ok(Some(data));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check an attachment's size, content hash and owner signature
+    pub fn verify(owner: &ParticipantProfile, attachment: &ProfileAttachment, data: &[u8]) -> Result<()> {
+        if attachment.size_bytes > attachment.kind.max_size_bytes() || data.len() as u64 != attachment.size_bytes {
+            return Err(anyhow!("Attachment size does not match its declared or allowed size"));
+        }
+
+        if content_address(data) != attachment.content_hash {
+            return Err(anyhow!("Attachment content does not match its content address"));
+        }
+
+        let public_key = owner
+            .public_key
+            .as_ref()
+            .ok_or_else(|| anyhow!("Owner {} has no public key", owner.global_id))?;
+        let public_key_pem = std::str::from_utf8(public_key)
+            .map_err(|_| anyhow!("Owner public key is not PEM encoded"))?;
+
+        let mut crypto = CryptoManager::new();
+        crypto
+            .import_public_key(&owner.global_id, public_key_pem)
+            .map_err(|e| anyhow!("Invalid owner public key: {}", e))?;
+        let valid = crypto
+            .verify_signature(&attachment.signing_payload(&owner.global_id), &attachment.signature, &owner.global_id)
+            .map_err(|e| anyhow!("Signature check failed: {}", e))?;
+
+        if !valid {
+            return Err(anyhow!("Attachment signature is not valid for {}", owner.global_id));
+        }
+        Ok(())
+    }
+
+    /// Get verified attachment content, fetching it from peers on first use
+    pub async fn get_verified(
+        &self,
+        owner: &ParticipantProfile,
+        kind: &AttachmentKind,
+        source: &dyn AttachmentSource,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(attachment) = owner.attachment(kind) else {
+            return Ok(None);
+        };
+
+        let data = match self.get_local(&attachment.content_hash).await? {
+            Some(data) => data,
+            None => {
+                debug!("Fetching {} attachment {} from {}", kind, attachment.content_hash, owner.global_id);
+                source.fetch(&owner.global_id, &attachment.content_hash).await?
+            }
+        };
+
+        if let Err(e) = Self::verify(owner, attachment, &data) {
+            warn!("Rejected {} attachment from {}: {}", kind, owner.global_id, e);
+            self.blobs.remove(&attachment.content_hash);
+            return Err(e);
+        }
+
+        self.put(attachment, data.clone()).await?;
+        Ok(Some(data))
+    }
+}
+
+impl Default for AttachmentStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use super::*;
+    use crate::synapse::models::participant::EntityType;
+
+    struct StaticSource(Vec<u8>);
+
+    #[async_trait]
+    impl AttachmentSource for StaticSource {
+        async fn fetch(&self, _owner_global_id: &str, _content_hash: &str) -> Result<Vec<u8>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attachment_fetched_and_verified() {
+        let mut signer = CryptoManager::new();
+        let (_, public_pem) = signer.generate_keypair().unwrap();
+
+        let mut profile = ParticipantProfile::new(
+            "alice@example.com".to_string(),
+            "Alice".to_string(),
+            EntityType::Human,
+        );
+        profile.public_key = Some(public_pem.into_bytes());
+
+        let owner_store = AttachmentStore::new();
+        let avatar = b"not really a png".to_vec();
+        owner_store
+            .attach(&mut profile, AttachmentKind::Avatar, "image/png", avatar.clone(), &signer)
+            .await
+            .unwrap();
+
+        // A peer has no local copy and fetches lazily
+        let peer_store = AttachmentStore::new();
+        let fetched = peer_store
+            .get_verified(&profile, &AttachmentKind::Avatar, &StaticSource(avatar.clone()))
+            .await
+            .unwrap();
+        assert_eq!(fetched, Some(avatar));
+
+        // Tampered content is rejected
+        let fresh_store = AttachmentStore::new();
+        let tampered = fresh_store
+            .get_verified(&profile, &AttachmentKind::Avatar, &StaticSource(b"not really a gif".to_vec()))
+            .await;
+        assert!(tampered.is_err());
+
+        // Oversized content is refused up front
+        let too_big = vec![0u8; AttachmentKind::ModelCard.max_size_bytes() as usize + 1];
+        assert!(owner_store
+            .attach(&mut profile, AttachmentKind::ModelCard, "text/markdown", too_big, &signer)
+            .await
+            .is_err());
+    }
+}
@@ -1,21 +1,104 @@
 // Synapse Cache Layer
 // Redis-based caching for performance optimization
+//
+// Keys read through `get_or_load` and written through `write_through` are
+// versioned: every write bumps `ver:<key>` and entries carry the version they
+// were stored under, so a reader that loaded from the database before a
+// concurrent write cannot put its stale copy back. Misses are coalesced per
+// key, in-process and across nodes via a short Redis lock, so an expiring hot
+// key triggers one database load instead of one per caller.
 
 use anyhow::{Context, Result};
 #[cfg(feature = "cache")]
-use redis::{AsyncCommands, Client};
+use dashmap::DashMap;
+#[cfg(feature = "cache")]
+use redis::{AsyncCommands, Client, Script};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "cache")]
+use std::future::Future;
+#[cfg(feature = "cache")]
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "cache")]
+use tracing::debug;
+
+/// Tuning for versioned entries and miss coalescing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Fraction of the TTL added or removed at random (0.1 = ±10%) so keys
+    /// written together do not expire together
+    pub ttl_jitter: f64,
+    /// How long a node may hold the cross-node load lock for a key
+    pub lock_ttl: Duration,
+    /// How long a caller waits for another node's load before loading itself
+    pub lock_wait: Duration,
+    /// Lifetime of `ver:<key>` counters; must outlive the entries they guard
+    pub version_ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_jitter: 0.1,
+            lock_ttl: Duration::from_secs(5),
+            lock_wait: Duration::from_secs(2),
+            version_ttl: Duration::from_secs(7 * 24 * 3600),
+        }
+    }
+}
+
+/// A cached value with the write version it was stored under
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionedEntry<T> {
+    pub version: u64,
+    pub value: T,
+}
+
+/// Spread `ttl_seconds` by up to `jitter` of itself; `sample` is in [0, 1)
+pub fn jittered_ttl(ttl_seconds: u64, jitter: f64, sample: f64) -> u64 {
+    let jitter = jitter.clamp(0.0, 1.0);
+    let factor = 1.0 + jitter * (2.0 * sample - 1.0);
+    ((ttl_seconds as f64 * factor).round() as u64).max(1)
+}
+
+/// Stores `ARGV[2]` at `KEYS[1]` only if `KEYS[2]` still holds version `ARGV[1]`
+#[cfg(feature = "cache")]
+const SET_IF_VERSION: &str = r#"
+local current = redis.call('GET', KEYS[2]) or '0'
+if current == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+    return 1
+end
+return 0
+"#;
+
+/// Releases a lock only if this caller still owns it
+#[cfg(feature = "cache")]
+const RELEASE_LOCK: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
 
 /// Cache interface for Synapse
 #[cfg(feature = "cache")]
 pub struct Cache {
     client: Client,
+    config: CacheConfig,
+    /// In-process singleflight: one loader per key at a time
+    loading: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
 }
 
 #[cfg(feature = "cache")]
 impl Cache {
     /// Create new cache connection
     pub async fn new(redis_url: &str) -> Result<Self> {
+        Self::with_config(redis_url, CacheConfig::default()).await
+    }
+
+    /// Create new cache connection with versioning and stampede settings
+    pub async fn with_config(redis_url: &str, config: CacheConfig) -> Result<Self> {
         let client = Client::open(redis_url)
             .context("Failed to create Redis client")?;
         
@@ -29,7 +112,7 @@ impl Cache {
         let _: () = conn.del("test_connection").await
             .context("Failed to clean up test key")?;
         
-        Ok(Self { client })
+        Ok(Self { client, config, loading: DashMap::new() })
     }
     
     /// Cache a participant profile
@@ -160,4 +243,201 @@ impl Cache {
         conn.get(&key).await
             .context("Failed to get cached block hash")
     }
+
+    /// Read a versioned key, loading it from the source of truth on a miss.
+    ///
+    /// Concurrent misses for the same key share one `loader` call. The loaded
+    /// value is only cached if no write happened to the key while it loaded.
+    pub async fn get_or_load<T, F, Fut>(&self, key: &str, ttl_seconds: u64, loader: F) -> Result<Option<T>>
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<T>>>,
+    {
+        if let Some(value) = self.get_versioned(key).await? {
+            return Ok(Some(value));
+        }
+
+        let gate = self.loading.entry(key.to_string()).or_default().clone();
+        let result = async {
+            let _guard = gate.lock().await;
+            // Someone else may have filled it while we waited
+            if let Some(value) = self.get_versioned(key).await? {
+                return Ok(Some(value));
+            }
+
+            let token = uuid::Uuid::new_v4().to_string();
+            let locked = self.try_lock(key, &token).await?;
+            if !locked {
+                if let Some(value) = self.wait_for_fill(key).await? {
+                    return Ok(Some(value));
+                }
+                debug!("Cache lock wait for {} timed out, loading anyway", key);
+            }
+
+            let version = self.current_version(key).await?;
+            let loaded = loader().await;
+            if let Ok(Some(ref value)) = loaded {
+                let _ = self.set_if_version(key, value, version, ttl_seconds).await;
+            }
+            if locked {
+                let _ = self.release_lock(key, &token).await;
+            }
+            loaded
+        }
+        .await;
+
+        // Drop the gate once nobody else is queued on it
+        self.loading.remove_if(key, |_, g| Arc::strong_count(g) <= 2);
+        result
+    }
+
+    /// Store a value that was just written to the source of truth. Bumps the
+    /// key's version so in-flight loads of the old value are discarded.
+    pub async fn write_through<T>(&self, key: &str, value: &T, ttl_seconds: u64) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let version = self.bump_version(key).await?;
+        self.set_if_version(key, value, version, ttl_seconds).await?;
+        Ok(())
+    }
+
+    /// Invalidate a versioned key so in-flight loads of the old value are discarded
+    pub async fn invalidate_versioned(&self, key: &str) -> Result<()> {
+        self.bump_version(key).await?;
+        self.invalidate(key).await
+    }
+
+    /// Read a versioned key; entries stored under an older version are a miss
+    pub async fn get_versioned<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let mut conn = self.client.get_multiplexed_async_connection().await
+            .context("Failed to get Redis connection")?;
+
+        let (entry, version): (Option<String>, Option<u64>) = redis::pipe()
+            .get(key)
+            .get(Self::version_key(key))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to get cached value")?;
+
+        let Some(entry) = entry else { return Ok(None) };
+        let entry: VersionedEntry<T> = serde_json::from_str(&entry)
+            .context("Failed to deserialize cached value")?;
+        if entry.version != version.unwrap_or(0) {
+            debug!("Ignoring stale cache entry for {}", key);
+            return Ok(None);
+        }
+        Ok(Some(entry.value))
+    }
+
+    fn version_key(key: &str) -> String {
+        format!("ver:{}", key)
+    }
+
+    fn lock_key(key: &str) -> String {
+        format!("lock:{}", key)
+    }
+
+    async fn current_version(&self, key: &str) -> Result<u64> {
+        let mut conn = self.client.get_multiplexed_async_connection().await
+            .context("Failed to get Redis connection")?;
+        let version: Option<u64> = conn.get(Self::version_key(key)).await
+            .context("Failed to read cache version")?;
+        Ok(version.unwrap_or(0))
+    }
+
+    async fn bump_version(&self, key: &str) -> Result<u64> {
+        let mut conn = self.client.get_multiplexed_async_connection().await
+            .context("Failed to get Redis connection")?;
+        let version_key = Self::version_key(key);
+        let (version, _): (u64, ()) = redis::pipe()
+            .incr(&version_key, 1)
+            .expire(&version_key, self.config.version_ttl.as_secs() as i64)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to bump cache version")?;
+        Ok(version)
+    }
+
+    async fn set_if_version<T>(&self, key: &str, value: &T, version: u64, ttl_seconds: u64) -> Result<bool>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut conn = self.client.get_multiplexed_async_connection().await
+            .context("Failed to get Redis connection")?;
+        let serialized = serde_json::to_string(&VersionedEntry { version, value })
+            .context("Failed to serialize value")?;
+        let ttl = jittered_ttl(ttl_seconds, self.config.ttl_jitter, rand::random::<f64>());
+
+        let stored: i32 = Script::new(SET_IF_VERSION)
+            .key(key)
+            .key(Self::version_key(key))
+            .arg(version.to_string())
+            .arg(serialized)
+            .arg(ttl)
+            .invoke_async(&mut conn)
+            .await
+            .context("Failed to cache value")?;
+        Ok(stored == 1)
+    }
+
+    async fn try_lock(&self, key: &str, token: &str) -> Result<bool> {
+        let mut conn = self.client.get_multiplexed_async_connection().await
+            .context("Failed to get Redis connection")?;
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(Self::lock_key(key))
+            .arg(token)
+            .arg("NX")
+            .arg("PX")
+            .arg(self.config.lock_ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to take cache lock")?;
+        Ok(acquired.is_some())
+    }
+
+    async fn release_lock(&self, key: &str, token: &str) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await
+            .context("Failed to get Redis connection")?;
+        let _: i32 = Script::new(RELEASE_LOCK)
+            .key(Self::lock_key(key))
+            .arg(token)
+            .invoke_async(&mut conn)
+            .await
+            .context("Failed to release cache lock")?;
+        Ok(())
+    }
+
+    /// Poll for another node's load to land, up to `lock_wait`
+    async fn wait_for_fill<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let deadline = tokio::time::Instant::now() + self.config.lock_wait;
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if let Some(value) = self.get_versioned(key).await? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jittered_ttl_stays_in_band() {
+        assert_eq!(jittered_ttl(3600, 0.0, 0.9), 3600);
+        assert_eq!(jittered_ttl(3600, 0.1, 0.0), 3240);
+        assert_eq!(jittered_ttl(3600, 0.1, 0.5), 3600);
+        assert!(jittered_ttl(3600, 0.1, 0.999) <= 3960);
+        assert_eq!(jittered_ttl(1, 1.0, 0.0), 1);
+    }
 }
@@ -7,7 +7,11 @@ use chrono::{DateTime, Utc};
 
 use crate::blockchain::serialization::DateTimeWrapper;
 #[cfg(feature = "database")]
-use sqlx::{PgPool, Row};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+#[cfg(feature = "database")]
+use crate::synapse::services::registry::ParticipantFilter;
+#[cfg(feature = "database")]
+use super::migrations::{MigrationManager, PostgresExecutor, StartupMigration};
 
 /// Main database interface for Synapse
 #[cfg(feature = "database")]
@@ -17,20 +21,29 @@ pub struct Database {
 
 #[cfg(feature = "database")]
 impl Database {
-    /// Create new database connection
+    /// Create new database connection, upgrading the schema to the latest version
     pub async fn new(database_url: &str) -> Result<Self> {
+        Self::connect(database_url, StartupMigration::Upgrade).await
+    }
+
+    /// Create new database connection with an explicit startup migration policy
+    pub async fn connect(database_url: &str, migration: StartupMigration) -> Result<Self> {
         let pool = PgPool::connect(database_url)
             .await
             .context("Failed to connect to database")?;
         
-        // Run migrations
-        sqlx::migrate!("./migrations")
-            .run(&pool)
+        MigrationManager::new(pool.clone())
+            .run_startup(migration)
             .await
             .context("Failed to run database migrations")?;
         
         Ok(Self { pool })
     }
+
+    /// Migration manager for this database, for status queries and rollbacks
+    pub fn migrations(&self) -> MigrationManager<PostgresExecutor> {
+        MigrationManager::new(self.pool.clone())
+    }
     
     /// Store or update a participant profile
     pub async fn upsert_participant(&self, profile: &ParticipantProfile) -> Result<()> {
@@ -39,8 +52,8 @@ impl Database {
                 global_id, display_name, entity_type, identities,
                 discovery_permissions, availability, contact_preferences,
                 trust_ratings, topic_subscriptions, organizational_context,
-                public_key, supported_protocols, last_seen, created_at, updated_at
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                public_key, supported_protocols, last_seen, created_at, updated_at, region
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
             ON CONFLICT (global_id) DO UPDATE SET
                 display_name = EXCLUDED.display_name,
                 entity_type = EXCLUDED.entity_type,
@@ -54,7 +67,8 @@ impl Database {
                 public_key = EXCLUDED.public_key,
                 supported_protocols = EXCLUDED.supported_protocols,
                 last_seen = EXCLUDED.last_seen,
-                updated_at = EXCLUDED.updated_at
+                updated_at = EXCLUDED.updated_at,
+                region = EXCLUDED.region
         "#;
         
         sqlx::query(query)
@@ -73,6 +87,7 @@ impl Database {
             .bind(profile.last_seen)
             .bind(profile.created_at)
             .bind(profile.updated_at)
+            .bind(profile.region.as_deref().map(crate::geo::normalize))
             .execute(&self.pool)
             .await
             .context("Failed to upsert participant")?;
@@ -86,7 +101,7 @@ impl Database {
             SELECT global_id, display_name, entity_type, identities,
                    discovery_permissions, availability, contact_preferences,
                    trust_ratings, topic_subscriptions, organizational_context,
-                   public_key, supported_protocols, last_seen, created_at, updated_at
+                   public_key, supported_protocols, last_seen, created_at, updated_at, region
             FROM participants 
             WHERE global_id = $1
         "#;
@@ -111,6 +126,8 @@ impl Database {
                     relationships: vec![], // Loaded separately if needed
                     topic_subscriptions: serde_json::from_value(row.get("topic_subscriptions"))?,
                     organizational_context: serde_json::from_value(row.get("organizational_context"))?,
+                    attachments: vec![], // Loaded separately if needed
+                    region: row.try_get("region").unwrap_or_default(),
                     public_key: row.get("public_key"),
                     supported_protocols: serde_json::from_value(row.get("supported_protocols"))?,
                     last_seen: row.get("last_seen"),
@@ -135,7 +152,7 @@ impl Database {
             SELECT global_id, display_name, entity_type, identities,
                    discovery_permissions, availability, contact_preferences,
                    trust_ratings, topic_subscriptions, organizational_context,
-                   public_key, supported_protocols, last_seen, created_at, updated_at
+                   public_key, supported_protocols, last_seen, created_at, updated_at, region
             FROM participants 
             WHERE (display_name ILIKE $1 OR global_id ILIKE $1)
             AND (discovery_permissions->>'discoverability' = 'Public'
@@ -164,6 +181,8 @@ impl Database {
                 relationships: vec![], // Loaded separately if needed
                 topic_subscriptions: serde_json::from_value(row.get("topic_subscriptions"))?,
                 organizational_context: serde_json::from_value(row.get("organizational_context"))?,
+                attachments: vec![], // Loaded separately if needed
+                region: row.try_get("region").unwrap_or_default(),
                 public_key: row.get("public_key"),
                 supported_protocols: serde_json::from_value(row.get("supported_protocols"))?,
                 last_seen: row.get("last_seen"),
@@ -269,6 +288,8 @@ impl Database {
                 relationships: vec![], // Loaded separately if needed
                 topic_subscriptions: serde_json::from_value(row.get("topic_subscriptions"))?,
                 organizational_context: serde_json::from_value(row.get("organizational_context"))?,
+                attachments: vec![], // Loaded separately if needed
+                region: row.try_get("region").unwrap_or_default(),
                 public_key: row.get("public_key"),
                 supported_protocols: serde_json::from_value(row.get("supported_protocols"))?,
                 last_seen: row.get("last_seen"),
@@ -367,6 +388,151 @@ impl Database {
         Ok(vec![])
     }
 
+    /// List participants in `global_id` order, starting after `after`
+    pub async fn list_participants(
+        &self,
+        filter: &ParticipantFilter,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ParticipantProfile>> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            r#"
+            SELECT global_id, display_name, entity_type, identities,
+                   discovery_permissions, availability, contact_preferences,
+                   trust_ratings, topic_subscriptions, organizational_context,
+                   public_key, supported_protocols, last_seen, created_at, updated_at, region
+            FROM participants
+            WHERE TRUE
+            "#,
+        );
+        
+        if let Some(after) = after {
+            query.push(" AND global_id > ").push_bind(after.to_string());
+        }
+        if !filter.include_hidden {
+            query.push(" AND discovery_permissions->>'discoverability' IN ('Public', 'Unlisted')");
+        }
+        if !filter.entity_types.is_empty() {
+            let types: Vec<String> = filter.entity_types.iter()
+                .map(|t| serde_json::to_value(t).map(|v| v.as_str().unwrap_or_default().to_string()))
+                .collect::<std::result::Result<_, _>>()?;
+            query.push(" AND entity_type #>> '{}' = ANY(").push_bind(types).push(")");
+        }
+        if let Some(ref organization) = filter.organization {
+            query.push(" AND (organizational_context->>'organization_id' = ")
+                .push_bind(organization.clone())
+                .push(" OR organizational_context->>'organization_name' = ")
+                .push_bind(organization.clone())
+                .push(")");
+        }
+        let trust = "COALESCE((trust_ratings->'network_trust'->>'network_score')::DOUBLE PRECISION, 0)";
+        if let Some(min) = filter.min_trust {
+            query.push(format!(" AND {} >= ", trust)).push_bind(min);
+        }
+        if let Some(max) = filter.max_trust {
+            query.push(format!(" AND {} <= ", trust)).push_bind(max);
+        }
+        if !filter.regions.is_empty() {
+            // A region scope such as "eu" covers "eu" itself and every "eu-..." region
+            query.push(" AND (FALSE");
+            for scope in &filter.regions {
+                let scope = crate::geo::normalize(scope);
+                query.push(" OR region = ").push_bind(scope.clone())
+                    .push(" OR region LIKE ").push_bind(format!("{}-%", scope));
+            }
+            query.push(")");
+        }
+        if let Some(since) = filter.last_seen_after {
+            query.push(" AND last_seen >= ").push_bind(since);
+        }
+        if let Some(until) = filter.last_seen_before {
+            query.push(" AND last_seen < ").push_bind(until);
+        }
+        query.push(" ORDER BY global_id LIMIT ").push_bind(limit as i64);
+        
+        let rows = query.build()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list participants")?;
+        
+        rows.iter().map(participant_from_row).collect()
+    }
+    
+    /// Write many participants in one transaction. With `overwrite` unset,
+    /// existing participants are left alone. Returns the IDs written, each
+    /// flagged `true` if it was newly inserted.
+    pub async fn bulk_upsert_participants(
+        &self,
+        profiles: &[ParticipantProfile],
+        overwrite: bool,
+    ) -> Result<Vec<(String, bool)>> {
+        let conflict = if overwrite {
+            r#"
+            ON CONFLICT (global_id) DO UPDATE SET
+                display_name = EXCLUDED.display_name,
+                entity_type = EXCLUDED.entity_type,
+                identities = EXCLUDED.identities,
+                discovery_permissions = EXCLUDED.discovery_permissions,
+                availability = EXCLUDED.availability,
+                contact_preferences = EXCLUDED.contact_preferences,
+                trust_ratings = EXCLUDED.trust_ratings,
+                topic_subscriptions = EXCLUDED.topic_subscriptions,
+                organizational_context = EXCLUDED.organizational_context,
+                public_key = EXCLUDED.public_key,
+                supported_protocols = EXCLUDED.supported_protocols,
+                last_seen = EXCLUDED.last_seen,
+                updated_at = EXCLUDED.updated_at,
+                region = EXCLUDED.region
+            "#
+        } else {
+            " ON CONFLICT (global_id) DO NOTHING"
+        };
+        
+        let mut tx = self.pool.begin().await?;
+        let mut written = Vec::with_capacity(profiles.len());
+        for profile in profiles {
+            let query = format!(
+                r#"
+                INSERT INTO participants (
+                    global_id, display_name, entity_type, identities,
+                    discovery_permissions, availability, contact_preferences,
+                    trust_ratings, topic_subscriptions, organizational_context,
+                    public_key, supported_protocols, last_seen, created_at, updated_at, region
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+                {}
+                RETURNING (xmax = 0) AS inserted
+                "#,
+                conflict
+            );
+            let row = sqlx::query(&query)
+                .bind(&profile.global_id)
+                .bind(&profile.display_name)
+                .bind(serde_json::to_value(&profile.entity_type)?)
+                .bind(serde_json::to_value(&profile.identities)?)
+                .bind(serde_json::to_value(&profile.discovery_permissions)?)
+                .bind(serde_json::to_value(&profile.availability)?)
+                .bind(serde_json::to_value(&profile.contact_preferences)?)
+                .bind(serde_json::to_value(&profile.trust_ratings)?)
+                .bind(serde_json::to_value(&profile.topic_subscriptions)?)
+                .bind(serde_json::to_value(&profile.organizational_context)?)
+                .bind(&profile.public_key)
+                .bind(serde_json::to_value(&profile.supported_protocols)?)
+                .bind(profile.last_seen)
+                .bind(profile.created_at)
+                .bind(profile.updated_at)
+                .bind(profile.region.as_deref().map(crate::geo::normalize))
+                .fetch_optional(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to write participant {}", profile.global_id))?;
+            if let Some(row) = row {
+                written.push((profile.global_id.clone(), row.get::<bool, _>("inserted")));
+            }
+        }
+        tx.commit().await.context("Failed to commit participant batch")?;
+        
+        Ok(written)
+    }
+
     /// Delete participant
     pub async fn delete_participant(&self, participant_id: &str) -> Result<()> {
         let query = "DELETE FROM participants WHERE global_id = $1";
@@ -472,6 +638,548 @@ impl Database {
         Ok(())
     }
     
+    /// Store or update a contact in an owner's contact book
+    pub async fn upsert_contact(&self, owner_id: &str, contact: &crate::contacts::Contact) -> Result<()> {
+        let query = r#"
+            INSERT INTO contacts (owner_id, global_id, display_name, contact, updated_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            ON CONFLICT (owner_id, global_id) DO UPDATE SET
+                display_name = EXCLUDED.display_name,
+                contact = EXCLUDED.contact,
+                updated_at = EXCLUDED.updated_at
+        "#;
+        
+        sqlx::query(query)
+            .bind(owner_id)
+            .bind(&contact.global_id)
+            .bind(&contact.display_name)
+            .bind(serde_json::to_value(contact)?)
+            .execute(&self.pool)
+            .await
+            .context("Failed to upsert contact")?;
+        
+        Ok(())
+    }
+    
+    /// List all contacts in an owner's contact book
+    pub async fn list_contacts(&self, owner_id: &str) -> Result<Vec<crate::contacts::Contact>> {
+        let query = "SELECT contact FROM contacts WHERE owner_id = $1 ORDER BY display_name";
+        
+        let rows = sqlx::query(query)
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list contacts")?;
+        
+        let mut contacts = Vec::with_capacity(rows.len());
+        for row in rows {
+            contacts.push(serde_json::from_value(row.get("contact"))?);
+        }
+        
+        Ok(contacts)
+    }
+    
+    /// Delete a contact from an owner's contact book
+    pub async fn delete_contact(&self, owner_id: &str, global_id: &str) -> Result<()> {
+        let query = "DELETE FROM contacts WHERE owner_id = $1 AND global_id = $2";
+        
+        sqlx::query(query)
+            .bind(owner_id)
+            .bind(global_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete contact")?;
+        
+        Ok(())
+    }
+    
+    /// Insert or replace a profile attachment's signed metadata
+    pub async fn upsert_profile_attachment(
+        &self,
+        participant_id: &str,
+        attachment: &crate::synapse::models::participant::ProfileAttachment,
+    ) -> Result<()> {
+        let query = r#"
+            INSERT INTO profile_attachments (participant_id, kind, content_hash, attachment, updated_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            ON CONFLICT (participant_id, kind) DO UPDATE SET
+                content_hash = EXCLUDED.content_hash,
+                attachment = EXCLUDED.attachment,
+                updated_at = EXCLUDED.updated_at
+        "#;
+        
+        sqlx::query(query)
+            .bind(participant_id)
+            .bind(attachment.kind.to_string())
+            .bind(&attachment.content_hash)
+            .bind(serde_json::to_value(attachment)?)
+            .execute(&self.pool)
+            .await
+            .context("Failed to upsert profile attachment")?;
+        
+        Ok(())
+    }
+    
+    /// List the signed attachment metadata for a participant
+    pub async fn list_profile_attachments(
+        &self,
+        participant_id: &str,
+    ) -> Result<Vec<crate::synapse::models::participant::ProfileAttachment>> {
+        let query = "SELECT attachment FROM profile_attachments WHERE participant_id = $1 ORDER BY kind";
+        
+        let rows = sqlx::query(query)
+            .bind(participant_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list profile attachments")?;
+        
+        let mut attachments = Vec::with_capacity(rows.len());
+        for row in rows {
+            attachments.push(serde_json::from_value(row.get("attachment"))?);
+        }
+        
+        Ok(attachments)
+    }
+    
+    /// Store attachment content under its content address
+    pub async fn put_attachment_blob(&self, content_hash: &str, media_type: &str, data: &[u8]) -> Result<()> {
+        let query = r#"
+            INSERT INTO attachment_blobs (content_hash, media_type, size_bytes, data)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (content_hash) DO NOTHING
+        "#;
+        
+        sqlx::query(query)
+            .bind(content_hash)
+            .bind(media_type)
+            .bind(data.len() as i64)
+            .bind(data)
+            .execute(&self.pool)
+            .await
+            .context("Failed to store attachment content")?;
+        
+        Ok(())
+    }
+    
+    /// Fetch attachment content by content address
+    pub async fn get_attachment_blob(&self, content_hash: &str) -> Result<Option<Vec<u8>>> {
+        let query = "SELECT data FROM attachment_blobs WHERE content_hash = $1";
+        
+        let row = sqlx::query(query)
+            .bind(content_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch attachment content")?;
+        
+        Ok(row.map(|r| r.get("data")))
+    }
+    
+    /// Append a link probe measurement to its time series
+    pub async fn insert_probe_sample(&self, sample: &crate::transport::prober::ProbeSample) -> Result<()> {
+        let query = r#"
+            INSERT INTO probe_samples (peer, transport, measured_at, sample)
+            VALUES ($1, $2, $3, $4)
+        "#;
+        
+        sqlx::query(query)
+            .bind(&sample.peer)
+            .bind(sample.transport.to_string())
+            .bind(sample.measured_at)
+            .bind(serde_json::to_value(sample)?)
+            .execute(&self.pool)
+            .await
+            .context("Failed to insert probe sample")?;
+        
+        Ok(())
+    }
+    
+    /// List link probe measurements for a peer and transport since a point in time
+    pub async fn list_probe_samples(
+        &self,
+        peer: &str,
+        transport: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<crate::transport::prober::ProbeSample>> {
+        let query = r#"
+            SELECT sample FROM probe_samples
+            WHERE peer = $1 AND transport = $2 AND measured_at >= $3
+            ORDER BY measured_at
+        "#;
+        
+        let rows = sqlx::query(query)
+            .bind(peer)
+            .bind(transport)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list probe samples")?;
+        
+        let mut samples = Vec::with_capacity(rows.len());
+        for row in rows {
+            samples.push(serde_json::from_value(row.get("sample"))?);
+        }
+        
+        Ok(samples)
+    }
+    
+    /// Delete link probe measurements older than a cutoff
+    pub async fn prune_probe_samples(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM probe_samples WHERE measured_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await
+            .context("Failed to prune probe samples")?;
+        
+        Ok(result.rows_affected())
+    }
+    
+    /// Store a sealed message held by the relay for an offline peer
+    pub async fn insert_relay_message(&self, message: &crate::relay::StoredMessage) -> Result<()> {
+        let query = r#"
+            INSERT INTO relay_messages (id, recipient, sender, stored_at, expires_at, size, sealed)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#;
+        
+        sqlx::query(query)
+            .bind(message.id)
+            .bind(&message.recipient)
+            .bind(&message.sender)
+            .bind(message.stored_at)
+            .bind(message.expires_at)
+            .bind(message.size as i64)
+            .bind(&message.sealed)
+            .execute(&self.pool)
+            .await
+            .context("Failed to insert relay message")?;
+        
+        Ok(())
+    }
+    
+    /// List unexpired relay messages for a recipient, oldest first
+    pub async fn list_relay_messages(&self, recipient: &str, now: DateTime<Utc>) -> Result<Vec<crate::relay::StoredMessage>> {
+        let query = r#"
+            SELECT id, recipient, sender, stored_at, expires_at, size, sealed
+            FROM relay_messages
+            WHERE recipient = $1 AND expires_at > $2
+            ORDER BY stored_at
+        "#;
+        
+        let rows = sqlx::query(query)
+            .bind(recipient)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list relay messages")?;
+        
+        Ok(rows
+            .into_iter()
+            .map(|row| crate::relay::StoredMessage {
+                id: row.get("id"),
+                recipient: row.get("recipient"),
+                sender: row.get("sender"),
+                stored_at: row.get("stored_at"),
+                expires_at: row.get("expires_at"),
+                size: row.get::<i64, _>("size") as u64,
+                sealed: row.get("sealed"),
+            })
+            .collect())
+    }
+    
+    /// Delete relay messages for a recipient by ID
+    pub async fn delete_relay_messages(&self, recipient: &str, ids: &[uuid::Uuid]) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM relay_messages WHERE recipient = $1 AND id = ANY($2)")
+            .bind(recipient)
+            .bind(ids)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete relay messages")?;
+        
+        Ok(result.rows_affected())
+    }
+    
+    /// Delete every relay message to or from a participant
+    pub async fn purge_relay_messages(&self, global_id: &str) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM relay_messages WHERE recipient = $1 OR sender = $1")
+            .bind(global_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to purge relay messages")?;
+        
+        Ok(result.rows_affected())
+    }
+    
+    /// Count and total size of unexpired relay messages held for a recipient
+    pub async fn relay_usage(&self, recipient: &str, now: DateTime<Utc>) -> Result<(u64, u64)> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS messages, COALESCE(SUM(size), 0)::BIGINT AS bytes FROM relay_messages WHERE recipient = $1 AND expires_at > $2"
+        )
+        .bind(recipient)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to read relay usage")?;
+        
+        Ok((row.get::<i64, _>("messages") as u64, row.get::<i64, _>("bytes") as u64))
+    }
+    
+    /// Delete relay messages whose retention has run out
+    pub async fn prune_relay_messages(&self, now: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM relay_messages WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .context("Failed to prune relay messages")?;
+        
+        Ok(result.rows_affected())
+    }
+    
+    /// Store the latest lifecycle notice for a participant
+    pub async fn upsert_lifecycle_notice(&self, notice: &crate::lifecycle::LifecycleNotice) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO participant_lifecycle (global_id, state, notice, issued_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (global_id) DO UPDATE SET
+                state = EXCLUDED.state,
+                notice = EXCLUDED.notice,
+                issued_at = EXCLUDED.issued_at
+            "#,
+        )
+        .bind(&notice.global_id)
+        .bind(notice.state.name())
+        .bind(serde_json::to_value(notice)?)
+        .bind(notice.issued_at.0)
+        .execute(&self.pool)
+        .await
+        .context("Failed to store lifecycle notice")?;
+        
+        Ok(())
+    }
+    
+    /// Latest lifecycle notice for a participant
+    pub async fn get_lifecycle_notice(&self, global_id: &str) -> Result<Option<crate::lifecycle::LifecycleNotice>> {
+        let row = sqlx::query("SELECT notice FROM participant_lifecycle WHERE global_id = $1")
+            .bind(global_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch lifecycle notice")?;
+        
+        row.map(|row| serde_json::from_value(row.get("notice")).context("Invalid lifecycle notice"))
+            .transpose()
+    }
+    
+    /// Tombstones issued at or after `since`, oldest first
+    pub async fn list_tombstones(&self, since: DateTime<Utc>) -> Result<Vec<crate::lifecycle::Tombstone>> {
+        let rows = sqlx::query(
+            "SELECT notice FROM participant_lifecycle WHERE state = 'deleted' AND issued_at >= $1 ORDER BY issued_at",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list tombstones")?;
+        
+        rows.iter()
+            .map(|row| serde_json::from_value(row.get("notice")).context("Invalid tombstone"))
+            .collect()
+    }
+    
+    /// Delete every row that belongs to or names a participant, in one
+    /// transaction. Returns rows deleted per table; the lifecycle tombstone
+    /// is kept.
+    pub async fn purge_participant_data(&self, global_id: &str) -> Result<Vec<(&'static str, u64)>> {
+        const STATEMENTS: &[(&str, &str)] = &[
+            ("contacts", "DELETE FROM contacts WHERE owner_id = $1 OR global_id = $1"),
+            ("probe_samples", "DELETE FROM probe_samples WHERE peer = $1"),
+            ("relay_messages", "DELETE FROM relay_messages WHERE recipient = $1 OR sender = $1"),
+            ("profile_attachments", "DELETE FROM profile_attachments WHERE participant_id = $1"),
+            ("trust_ratings", "DELETE FROM trust_ratings WHERE rater_id = $1 OR subject_id = $1"),
+            (
+                "participant_relationships",
+                "DELETE FROM participant_relationships WHERE from_participant = $1 OR to_participant = $1",
+            ),
+            ("trust_balances", "DELETE FROM trust_balances WHERE participant_id = $1"),
+            ("participant_nonces", "DELETE FROM participant_nonces WHERE participant_id = $1"),
+            ("participants", "DELETE FROM participants WHERE global_id = $1"),
+        ];
+        
+        let mut tx = self.pool.begin().await.context("Failed to begin purge")?;
+        let mut deleted = Vec::with_capacity(STATEMENTS.len());
+        for (table, statement) in STATEMENTS {
+            let result = sqlx::query(statement)
+                .bind(global_id)
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to purge {}", table))?;
+            deleted.push((*table, result.rows_affected()));
+        }
+        tx.commit().await.context("Failed to commit purge")?;
+        
+        Ok(deleted)
+    }
+    
+    /// Replace a participant's ID with `pseudonym` in the stored copy of the
+    /// blockchain, returning how many transaction rows were rewritten
+    ///
+    /// Block hashes are left as committed.
+    pub async fn redact_blockchain_references(&self, global_id: &str, pseudonym: &str) -> Result<u64> {
+        let quoted = serde_json::to_string(global_id)?;
+        let replacement = serde_json::to_string(pseudonym)?;
+        let pattern = format!("%{}%", quoted.replace('%', "\\%").replace('_', "\\_"));
+        
+        let mut tx = self.pool.begin().await.context("Failed to begin redaction")?;
+        let rewritten = sqlx::query(
+            "UPDATE blockchain_transactions SET transaction_data = replace(transaction_data::text, $1, $2)::jsonb WHERE transaction_data::text LIKE $3",
+        )
+        .bind(&quoted)
+        .bind(&replacement)
+        .bind(&pattern)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to redact blockchain transactions")?
+        .rows_affected();
+        sqlx::query(
+            "UPDATE blockchain_blocks SET transactions = replace(transactions::text, $1, $2)::jsonb WHERE transactions::text LIKE $3",
+        )
+        .bind(&quoted)
+        .bind(&replacement)
+        .bind(&pattern)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to redact blockchain blocks")?;
+        tx.commit().await.context("Failed to commit redaction")?;
+        
+        Ok(rewritten)
+    }
+    
+    /// Store the audit record of a purge
+    pub async fn insert_purge_audit(&self, audit: &crate::purge::PurgeAudit) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO purge_audits (id, subject, requested_by, started_at, completed_at, items)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(audit.id)
+        .bind(&audit.subject)
+        .bind(&audit.requested_by)
+        .bind(audit.started_at)
+        .bind(audit.completed_at)
+        .bind(serde_json::to_value(&audit.items)?)
+        .execute(&self.pool)
+        .await
+        .context("Failed to store purge audit")?;
+        
+        Ok(())
+    }
+    
+    /// Purge audits for a pseudonymous subject, oldest first
+    pub async fn list_purge_audits(&self, subject: &str) -> Result<Vec<crate::purge::PurgeAudit>> {
+        let rows = sqlx::query(
+            "SELECT id, subject, requested_by, started_at, completed_at, items FROM purge_audits WHERE subject = $1 ORDER BY started_at",
+        )
+        .bind(subject)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list purge audits")?;
+        
+        rows.iter()
+            .map(|row| {
+                Ok(crate::purge::PurgeAudit {
+                    id: row.get("id"),
+                    subject: row.get("subject"),
+                    requested_by: row.get("requested_by"),
+                    started_at: row.get("started_at"),
+                    completed_at: row.get("completed_at"),
+                    items: serde_json::from_value(row.get("items")).context("Invalid purge audit items")?,
+                })
+            })
+            .collect()
+    }
+    
+    /// Persisted state of a periodic job
+    pub async fn get_scheduled_run(&self, job: &str) -> Result<Option<crate::synapse::services::decay::ScheduledRun>> {
+        let row = sqlx::query("SELECT job, last_run_at, next_run_at, last_processed FROM scheduled_runs WHERE job = $1")
+            .bind(job)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch scheduled run")?;
+        
+        Ok(row.map(|row| crate::synapse::services::decay::ScheduledRun {
+            job: row.get("job"),
+            last_run_at: row.get("last_run_at"),
+            next_run_at: row.get("next_run_at"),
+            last_processed: row.get::<i64, _>("last_processed") as u64,
+        }))
+    }
+    
+    /// Record the latest run of a periodic job
+    pub async fn upsert_scheduled_run(&self, run: &crate::synapse::services::decay::ScheduledRun) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO scheduled_runs (job, last_run_at, next_run_at, last_processed)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (job) DO UPDATE SET
+                last_run_at = EXCLUDED.last_run_at,
+                next_run_at = EXCLUDED.next_run_at,
+                last_processed = EXCLUDED.last_processed
+            "#,
+        )
+        .bind(&run.job)
+        .bind(run.last_run_at)
+        .bind(run.next_run_at)
+        .bind(run.last_processed as i64)
+        .execute(&self.pool)
+        .await
+        .context("Failed to store scheduled run")?;
+        
+        Ok(())
+    }
+    
+    /// Highest committed transaction nonce for a participant
+    pub async fn get_participant_nonce(&self, participant_id: &str) -> Result<Option<u64>> {
+        let row = sqlx::query("SELECT nonce FROM participant_nonces WHERE participant_id = $1")
+            .bind(participant_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch participant nonce")?;
+        
+        Ok(row.map(|row| row.get::<i64, _>("nonce") as u64))
+    }
+    
+    /// Every participant's highest committed nonce
+    pub async fn list_participant_nonces(&self) -> Result<Vec<(String, u64)>> {
+        let rows = sqlx::query("SELECT participant_id, nonce FROM participant_nonces")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list participant nonces")?;
+        
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("participant_id"), row.get::<i64, _>("nonce") as u64))
+            .collect())
+    }
+    
+    /// Raise a participant's committed nonce; a lower nonce never overwrites
+    /// a higher one, so nodes sharing the database cannot roll it back
+    pub async fn advance_participant_nonce(&self, participant_id: &str, nonce: u64) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO participant_nonces (participant_id, nonce, updated_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (participant_id) DO UPDATE SET
+                nonce = GREATEST(participant_nonces.nonce, EXCLUDED.nonce),
+                updated_at = NOW()
+            "#,
+        )
+        .bind(participant_id)
+        .bind(nonce as i64)
+        .execute(&self.pool)
+        .await
+        .context("Failed to store participant nonce")?;
+        
+        Ok(())
+    }
+    
     /// Execute a raw SQL query with parameters for strings
     pub async fn query_raw_string(
         &self, 
@@ -491,3 +1199,27 @@ impl Database {
         Ok(rows)
     }
 }
+
+#[cfg(feature = "database")]
+fn participant_from_row(row: &sqlx::postgres::PgRow) -> Result<ParticipantProfile> {
+    Ok(ParticipantProfile {
+        global_id: row.get("global_id"),
+        display_name: row.get("display_name"),
+        entity_type: serde_json::from_value(row.get("entity_type"))?,
+        identities: serde_json::from_value(row.get("identities"))?,
+        discovery_permissions: serde_json::from_value(row.get("discovery_permissions"))?,
+        availability: serde_json::from_value(row.get("availability"))?,
+        contact_preferences: serde_json::from_value(row.get("contact_preferences"))?,
+        trust_ratings: serde_json::from_value(row.get("trust_ratings"))?,
+        relationships: vec![], // Loaded separately if needed
+        topic_subscriptions: serde_json::from_value(row.get("topic_subscriptions"))?,
+        organizational_context: serde_json::from_value(row.get("organizational_context"))?,
+        attachments: vec![], // Loaded separately if needed
+        region: row.try_get("region").unwrap_or_default(),
+        public_key: row.get("public_key"),
+        supported_protocols: serde_json::from_value(row.get("supported_protocols"))?,
+        last_seen: row.get("last_seen"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
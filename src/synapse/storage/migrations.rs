@@ -1,105 +1,690 @@
-#[cfg(feature = "database")]
-use sqlx::{PgPool, Row};
-use anyhow::Result;
-use tracing::{info, debug};
+//! Versioned schema migrations for the Synapse storage backends
+//!
+//! Every schema change ships as a numbered [`Migration`] with an `up` script
+//! and a `down` script, once for PostgreSQL and once for the embedded SQLite
+//! store. [`MigrationManager`] compares the embedded set against the backend's
+//! `synapse_migrations` history table and can report [`MigrationStatus`],
+//! plan or apply an upgrade, and roll back to an earlier version. Each step
+//! runs in its own transaction together with its history row, so a failed
+//! script leaves the schema at the previous version.
+//!
+//! Databases created before this table existed were migrated by
+//! `sqlx::migrate!`; their `_sqlx_migrations` history is adopted on first run
+//! instead of replaying scripts that already ran.
+
+#![cfg(any(feature = "database", feature = "embedded-db"))]
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use tracing::{debug, info, warn};
+
+/// Name of the history table kept in every migrated database
+pub const HISTORY_TABLE: &str = "synapse_migrations";
+
+/// One versioned schema change
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+impl Migration {
+    /// Hex SHA-256 of the `up` script, recorded when the migration is applied
+    pub fn checksum(&self) -> String {
+        hex_digest(self.up)
+    }
+}
+
+fn hex_digest(sql: &str) -> String {
+    Sha256::digest(sql.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Storage backend a migration set targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    Postgres,
+    Sqlite,
+}
+
+macro_rules! migration {
+    ($version:expr, $name:expr, $dir:literal, $file:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../../../migrations/", $dir, $file)),
+            down: include_str!(concat!("../../../migrations/", $dir, "down/", $file)),
+        }
+    };
+}
+
+const POSTGRES_MIGRATIONS: &[Migration] = &[
+    migration!(1, "Create initial Synapse schema", "", "001_create_synapse_schema.sql"),
+    migration!(2, "Create contact book", "", "002_create_contacts.sql"),
+    migration!(3, "Create profile attachments", "", "003_create_profile_attachments.sql"),
+    migration!(4, "Create link probe history", "", "004_create_probe_samples.sql"),
+    migration!(5, "Create relay message store", "", "005_create_relay_messages.sql"),
+    migration!(6, "Create participant lifecycle", "", "006_create_participant_lifecycle.sql"),
+    migration!(7, "Create purge audits", "", "007_create_purge_audits.sql"),
+    migration!(8, "Create scheduled job state", "", "008_create_scheduled_runs.sql"),
+    migration!(9, "Create participant nonces", "", "009_create_participant_nonces.sql"),
+    migration!(10, "Add participant regions", "", "010_add_participant_regions.sql"),
+];
+
+const SQLITE_MIGRATIONS: &[Migration] = &[
+    migration!(1, "Create initial Synapse schema", "sqlite/", "001_create_synapse_schema.sql"),
+    migration!(2, "Create contact book", "sqlite/", "002_create_contacts.sql"),
+    migration!(3, "Create profile attachments", "sqlite/", "003_create_profile_attachments.sql"),
+    migration!(4, "Create link probe history", "sqlite/", "004_create_probe_samples.sql"),
+    migration!(5, "Create relay message store", "sqlite/", "005_create_relay_messages.sql"),
+    migration!(6, "Create participant lifecycle", "sqlite/", "006_create_participant_lifecycle.sql"),
+    migration!(7, "Create purge audits", "sqlite/", "007_create_purge_audits.sql"),
+    migration!(8, "Create scheduled job state", "sqlite/", "008_create_scheduled_runs.sql"),
+    migration!(9, "Create participant nonces", "sqlite/", "009_create_participant_nonces.sql"),
+    migration!(10, "Add participant regions", "sqlite/", "010_add_participant_regions.sql"),
+];
+
+/// The migrations shipped with this build for a backend, in version order
+pub fn migrations(backend: Backend) -> &'static [Migration] {
+    match backend {
+        Backend::Postgres => POSTGRES_MIGRATIONS,
+        Backend::Sqlite => SQLITE_MIGRATIONS,
+    }
+}
+
+/// A row of the history table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    /// Empty for rows adopted from `_sqlx_migrations`
+    pub checksum: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+/// Schema state of a database compared with this build
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationStatus {
+    pub backend: Backend,
+    /// Highest applied version, 0 for an empty database
+    pub current_version: i64,
+    /// Highest version this build knows about
+    pub latest_version: i64,
+    pub applied: Vec<AppliedMigration>,
+    /// Known migrations not yet applied, in the order they would run
+    pub pending: Vec<PlannedStep>,
+    /// Applied versions whose script changed since they ran
+    pub modified: Vec<i64>,
+    /// Applied versions this build has no script for (database is newer)
+    pub unknown: Vec<i64>,
+}
+
+impl MigrationStatus {
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending.is_empty() && self.unknown.is_empty()
+    }
+}
+
+/// Which way a plan moves the schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedStep {
+    pub version: i64,
+    pub name: String,
+}
+
+/// Steps that an upgrade or rollback would run, in execution order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationPlan {
+    pub direction: Direction,
+    pub from_version: i64,
+    pub to_version: i64,
+    pub steps: Vec<PlannedStep>,
+    /// True when the plan was only computed, not executed
+    pub dry_run: bool,
+}
+
+impl MigrationPlan {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// What [`crate::synapse::storage::Database`] does with pending migrations when it connects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupMigration {
+    /// Apply pending migrations before serving
+    #[default]
+    Upgrade,
+    /// Log the pending plan and leave the schema untouched
+    DryRun,
+    /// Do not look at the schema at all
+    Skip,
+}
+
+/// Backend-specific access to the history table and script execution
+#[async_trait]
+pub trait MigrationExecutor: Send + Sync {
+    fn backend(&self) -> Backend;
+
+    /// Create the history table, adopting any earlier migration history
+    async fn ensure_history(&self) -> Result<()>;
+
+    /// Applied migrations in version order
+    async fn applied(&self) -> Result<Vec<AppliedMigration>>;
+
+    /// Run `migration.up` and record it, atomically
+    async fn apply(&self, migration: &Migration) -> Result<()>;
+
+    /// Run `migration.down` and remove its record, atomically
+    async fn revert(&self, migration: &Migration) -> Result<()>;
+}
 
 /// Database migration manager for Synapse schema
-#[cfg(feature = "database")]
-pub struct MigrationManager {
-    pool: PgPool,
+pub struct MigrationManager<E> {
+    executor: E,
+    migrations: &'static [Migration],
 }
 
-#[cfg(feature = "database")]
-impl MigrationManager {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+impl<E: MigrationExecutor> MigrationManager<E> {
+    /// Manage a backend with the migrations shipped in this build
+    pub fn with_executor(executor: E) -> Self {
+        let migrations = migrations(executor.backend());
+        Self { executor, migrations }
+    }
+
+    /// Manage a backend with a custom migration set
+    pub fn with_migrations(executor: E, migrations: &'static [Migration]) -> Self {
+        Self { executor, migrations }
+    }
+
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+
+    /// Compare the database's history with this build's migrations
+    pub async fn status(&self) -> Result<MigrationStatus> {
+        self.executor.ensure_history().await?;
+        let applied = self.executor.applied().await?;
+        Ok(self.compare(applied))
+    }
+
+    fn compare(&self, applied: Vec<AppliedMigration>) -> MigrationStatus {
+        let known: BTreeMap<i64, &Migration> = self.migrations.iter().map(|m| (m.version, m)).collect();
+        let applied_versions: BTreeMap<i64, &AppliedMigration> = applied.iter().map(|a| (a.version, a)).collect();
+
+        let pending = self
+            .migrations
+            .iter()
+            .filter(|m| !applied_versions.contains_key(&m.version))
+            .map(|m| PlannedStep { version: m.version, name: m.name.to_string() })
+            .collect();
+        let modified = applied
+            .iter()
+            .filter(|a| !a.checksum.is_empty())
+            .filter(|a| known.get(&a.version).is_some_and(|m| m.checksum() != a.checksum))
+            .map(|a| a.version)
+            .collect();
+        let unknown = applied.iter().filter(|a| !known.contains_key(&a.version)).map(|a| a.version).collect();
+
+        MigrationStatus {
+            backend: self.executor.backend(),
+            current_version: applied.iter().map(|a| a.version).max().unwrap_or(0),
+            latest_version: self.migrations.iter().map(|m| m.version).max().unwrap_or(0),
+            applied,
+            pending,
+            modified,
+            unknown,
+        }
     }
 
     /// Run all pending migrations
-    pub async fn migrate(&self) -> Result<()> {
+    pub async fn migrate(&self) -> Result<MigrationPlan> {
+        self.migrate_to(None, false).await
+    }
+
+    /// Upgrade to `target` (or the latest version); with `dry_run` only plan it
+    pub async fn migrate_to(&self, target: Option<i64>, dry_run: bool) -> Result<MigrationPlan> {
+        let status = self.status().await?;
+        if !status.unknown.is_empty() {
+            bail!(
+                "Database schema has migrations {:?} that this build does not know; it is newer than version {}",
+                status.unknown,
+                status.latest_version
+            );
+        }
+        if !status.modified.is_empty() {
+            bail!("Applied migrations {:?} were modified after they ran", status.modified);
+        }
+
+        let target = target.unwrap_or(status.latest_version);
+        let steps: Vec<&Migration> = self
+            .migrations
+            .iter()
+            .filter(|m| m.version <= target && status.pending.iter().any(|p| p.version == m.version))
+            .collect();
+        let plan = MigrationPlan {
+            direction: Direction::Up,
+            from_version: status.current_version,
+            to_version: steps.last().map(|m| m.version).unwrap_or(status.current_version),
+            steps: steps.iter().map(|m| PlannedStep { version: m.version, name: m.name.to_string() }).collect(),
+            dry_run,
+        };
+
+        if dry_run || plan.is_empty() {
+            debug!("Migration plan ({:?}): {:?}", self.executor.backend(), plan.steps);
+            return Ok(plan);
+        }
+
         info!("Starting database migrations");
+        for migration in steps {
+            info!("Running migration {}: {}", migration.version, migration.name);
+            self.executor
+                .apply(migration)
+                .await
+                .with_context(|| format!("Migration {} ({}) failed", migration.version, migration.name))?;
+        }
+        info!("Database migrations completed");
+        Ok(plan)
+    }
 
-        // Create migrations table if it doesn't exist
-        self.create_migrations_table().await?;
+    /// Revert applied migrations newer than `target`, newest first; with
+    /// `dry_run` only plan it. `rollback_to(0, ..)` empties the schema.
+    pub async fn rollback_to(&self, target: i64, dry_run: bool) -> Result<MigrationPlan> {
+        let status = self.status().await?;
+        let mut steps = Vec::new();
+        for applied in status.applied.iter().rev().filter(|a| a.version > target) {
+            match self.migrations.iter().find(|m| m.version == applied.version) {
+                Some(migration) => steps.push(migration),
+                None => bail!("No down script for migration {} ({})", applied.version, applied.name),
+            }
+        }
+        let plan = MigrationPlan {
+            direction: Direction::Down,
+            from_version: status.current_version,
+            to_version: status.applied.iter().map(|a| a.version).filter(|v| *v <= target).max().unwrap_or(0),
+            steps: steps.iter().map(|m| PlannedStep { version: m.version, name: m.name.to_string() }).collect(),
+            dry_run,
+        };
+
+        if dry_run || plan.is_empty() {
+            return Ok(plan);
+        }
 
-        // Get current migration version
-        let current_version = self.get_current_version().await?;
-        debug!("Current migration version: {}", current_version);
+        warn!("Rolling back database schema from {} to {}", plan.from_version, plan.to_version);
+        for migration in steps {
+            info!("Reverting migration {}: {}", migration.version, migration.name);
+            self.executor
+                .revert(migration)
+                .await
+                .with_context(|| format!("Rollback of migration {} ({}) failed", migration.version, migration.name))?;
+        }
+        Ok(plan)
+    }
 
-        // Run migrations in order
-        let migrations = self.get_migrations();
-        for migration in migrations {
-            if migration.version > current_version {
-                info!("Running migration {}: {}", migration.version, migration.name);
-                self.run_migration(&migration).await?;
-                self.record_migration(migration.version, &migration.name).await?;
+    /// Apply [`StartupMigration`] policy when a database is opened
+    pub async fn run_startup(&self, mode: StartupMigration) -> Result<Option<MigrationPlan>> {
+        match mode {
+            StartupMigration::Skip => Ok(None),
+            StartupMigration::DryRun => {
+                let plan = self.migrate_to(None, true).await?;
+                if !plan.is_empty() {
+                    warn!(
+                        "Database schema is at version {}; {} migration(s) pending and not applied (dry run)",
+                        plan.from_version,
+                        plan.steps.len()
+                    );
+                }
+                Ok(Some(plan))
             }
+            StartupMigration::Upgrade => self.migrate().await.map(Some),
         }
+    }
+}
 
-        info!("Database migrations completed");
-        Ok(())
+#[cfg(feature = "database")]
+pub use postgres::PostgresExecutor;
+
+#[cfg(feature = "database")]
+mod postgres {
+    use super::*;
+    use sqlx::{PgPool, Row};
+
+    /// Runs migrations against PostgreSQL
+    pub struct PostgresExecutor {
+        pool: PgPool,
     }
 
-    async fn create_migrations_table(&self) -> Result<()> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS synapse_migrations (
-                version INTEGER PRIMARY KEY,
-                name TEXT NOT NULL,
-                applied_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+    impl PostgresExecutor {
+        pub fn new(pool: PgPool) -> Self {
+            Self { pool }
+        }
+    }
+
+    impl MigrationManager<PostgresExecutor> {
+        pub fn new(pool: PgPool) -> Self {
+            Self::with_executor(PostgresExecutor::new(pool))
+        }
+    }
+
+    #[async_trait]
+    impl MigrationExecutor for PostgresExecutor {
+        fn backend(&self) -> Backend {
+            Backend::Postgres
+        }
+
+        async fn ensure_history(&self) -> Result<()> {
+            sqlx::query(
+                r#"
+                CREATE TABLE IF NOT EXISTS synapse_migrations (
+                    version BIGINT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    checksum TEXT NOT NULL DEFAULT '',
+                    applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+                )
+                "#,
             )
-            "#
-        )
-        .execute(&self.pool)
-        .await?;
+            .execute(&self.pool)
+            .await?;
 
-        Ok(())
+            // Adopt history from sqlx::migrate!, which earlier releases ran on startup
+            let recorded: i64 = sqlx::query("SELECT COUNT(*) AS n FROM synapse_migrations")
+                .fetch_one(&self.pool)
+                .await?
+                .get("n");
+            let legacy: Option<String> = sqlx::query("SELECT to_regclass('_sqlx_migrations')::TEXT AS t")
+                .fetch_one(&self.pool)
+                .await?
+                .get("t");
+            if recorded == 0 && legacy.is_some() {
+                let adopted = sqlx::query(
+                    r#"
+                    INSERT INTO synapse_migrations (version, name, checksum, applied_at)
+                    SELECT version, description, '', installed_on
+                    FROM _sqlx_migrations WHERE success
+                    "#,
+                )
+                .execute(&self.pool)
+                .await?;
+                info!("Adopted {} migration(s) from _sqlx_migrations", adopted.rows_affected());
+            }
+            Ok(())
+        }
+
+        async fn applied(&self) -> Result<Vec<AppliedMigration>> {
+            let rows = sqlx::query("SELECT version, name, checksum, applied_at FROM synapse_migrations ORDER BY version")
+                .fetch_all(&self.pool)
+                .await?;
+            Ok(rows
+                .iter()
+                .map(|row| AppliedMigration {
+                    version: row.get("version"),
+                    name: row.get("name"),
+                    checksum: row.get("checksum"),
+                    applied_at: row.get("applied_at"),
+                })
+                .collect())
+        }
+
+        async fn apply(&self, migration: &Migration) -> Result<()> {
+            let mut tx = self.pool.begin().await?;
+            sqlx::raw_sql(migration.up).execute(&mut *tx).await?;
+            sqlx::query("INSERT INTO synapse_migrations (version, name, checksum) VALUES ($1, $2, $3)")
+                .bind(migration.version)
+                .bind(migration.name)
+                .bind(migration.checksum())
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(())
+        }
+
+        async fn revert(&self, migration: &Migration) -> Result<()> {
+            let mut tx = self.pool.begin().await?;
+            sqlx::raw_sql(migration.down).execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM synapse_migrations WHERE version = $1")
+                .bind(migration.version)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "embedded-db")]
+pub use sqlite::SqliteExecutor;
+
+#[cfg(feature = "embedded-db")]
+mod sqlite {
+    use super::*;
+    use sqlx::{Row, SqlitePool};
+
+    /// Runs migrations against the embedded SQLite store
+    pub struct SqliteExecutor {
+        pool: SqlitePool,
     }
 
-    async fn get_current_version(&self) -> Result<i32> {
-        let row = sqlx::query(
-            "SELECT COALESCE(MAX(version), 0) as version FROM synapse_migrations"
-        )
-        .fetch_one(&self.pool)
-        .await?;
+    impl SqliteExecutor {
+        pub fn new(pool: SqlitePool) -> Self {
+            Self { pool }
+        }
+    }
 
-        Ok(row.get("version"))
+    impl MigrationManager<SqliteExecutor> {
+        pub fn embedded(pool: SqlitePool) -> Self {
+            Self::with_executor(SqliteExecutor::new(pool))
+        }
     }
 
-    async fn run_migration(&self, migration: &Migration) -> Result<()> {
-        sqlx::query(&migration.sql)
+    #[async_trait]
+    impl MigrationExecutor for SqliteExecutor {
+        fn backend(&self) -> Backend {
+            Backend::Sqlite
+        }
+
+        async fn ensure_history(&self) -> Result<()> {
+            sqlx::query(
+                r#"
+                CREATE TABLE IF NOT EXISTS synapse_migrations (
+                    version INTEGER PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    checksum TEXT NOT NULL DEFAULT '',
+                    applied_at TEXT NOT NULL
+                )
+                "#,
+            )
             .execute(&self.pool)
             .await?;
-        Ok(())
+            Ok(())
+        }
+
+        async fn applied(&self) -> Result<Vec<AppliedMigration>> {
+            let rows = sqlx::query("SELECT version, name, checksum, applied_at FROM synapse_migrations ORDER BY version")
+                .fetch_all(&self.pool)
+                .await?;
+            rows.iter()
+                .map(|row| {
+                    let applied_at: String = row.get("applied_at");
+                    Ok(AppliedMigration {
+                        version: row.get("version"),
+                        name: row.get("name"),
+                        checksum: row.get("checksum"),
+                        applied_at: DateTime::parse_from_rfc3339(&applied_at)
+                            .with_context(|| format!("Bad applied_at in synapse_migrations: {}", applied_at))?
+                            .with_timezone(&Utc),
+                    })
+                })
+                .collect()
+        }
+
+        async fn apply(&self, migration: &Migration) -> Result<()> {
+            let mut tx = self.pool.begin().await?;
+            sqlx::raw_sql(migration.up).execute(&mut *tx).await?;
+            sqlx::query("INSERT INTO synapse_migrations (version, name, checksum, applied_at) VALUES (?, ?, ?, ?)")
+                .bind(migration.version)
+                .bind(migration.name)
+                .bind(migration.checksum())
+                .bind(Utc::now().to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(())
+        }
+
+        async fn revert(&self, migration: &Migration) -> Result<()> {
+            let mut tx = self.pool.begin().await?;
+            sqlx::raw_sql(migration.down).execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM synapse_migrations WHERE version = ?")
+                .bind(migration.version)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records history in memory; scripts "fail" when they contain `FAIL`
+    #[derive(Default)]
+    struct MemoryExecutor {
+        history: Mutex<Vec<AppliedMigration>>,
+    }
+
+    #[async_trait]
+    impl MigrationExecutor for MemoryExecutor {
+        fn backend(&self) -> Backend {
+            Backend::Sqlite
+        }
+
+        async fn ensure_history(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn applied(&self) -> Result<Vec<AppliedMigration>> {
+            Ok(self.history.lock().unwrap().clone())
+        }
+
+        async fn apply(&self, migration: &Migration) -> Result<()> {
+            if migration.up.contains("FAIL") {
+                bail!("syntax error");
+            }
+            self.history.lock().unwrap().push(AppliedMigration {
+                version: migration.version,
+                name: migration.name.to_string(),
+                checksum: migration.checksum(),
+                applied_at: Utc::now(),
+            });
+            Ok(())
+        }
+
+        async fn revert(&self, migration: &Migration) -> Result<()> {
+            self.history.lock().unwrap().retain(|a| a.version != migration.version);
+            Ok(())
+        }
+    }
+
+    const SET: &[Migration] = &[
+        Migration { version: 1, name: "one", up: "CREATE TABLE a (x INTEGER);", down: "DROP TABLE a;" },
+        Migration { version: 2, name: "two", up: "CREATE TABLE b (x INTEGER);", down: "DROP TABLE b;" },
+        Migration { version: 3, name: "three", up: "CREATE TABLE c (x INTEGER);", down: "DROP TABLE c;" },
+    ];
+
+    fn versions(plan: &MigrationPlan) -> Vec<i64> {
+        plan.steps.iter().map(|s| s.version).collect()
     }
 
-    async fn record_migration(&self, version: i32, name: &str) -> Result<()> {
-        sqlx::query(
-            "INSERT INTO synapse_migrations (version, name) VALUES ($1, $2)"
-        )
-        .bind(version)
-        .bind(name)
-        .execute(&self.pool)
-        .await?;
+    #[tokio::test]
+    async fn test_dry_run_then_upgrade_and_rollback() {
+        let manager = MigrationManager::with_migrations(MemoryExecutor::default(), SET);
 
-        Ok(())
+        let plan = manager.migrate_to(None, true).await.unwrap();
+        assert!(plan.dry_run);
+        assert_eq!(versions(&plan), vec![1, 2, 3]);
+        assert_eq!(manager.status().await.unwrap().current_version, 0);
+
+        let plan = manager.migrate_to(Some(2), false).await.unwrap();
+        assert_eq!(versions(&plan), vec![1, 2]);
+        let status = manager.status().await.unwrap();
+        assert_eq!((status.current_version, status.latest_version), (2, 3));
+        assert_eq!(status.pending, vec![PlannedStep { version: 3, name: "three".to_string() }]);
+
+        manager.migrate().await.unwrap();
+        assert!(manager.status().await.unwrap().is_up_to_date());
+
+        let plan = manager.rollback_to(1, false).await.unwrap();
+        assert_eq!(plan.direction, Direction::Down);
+        assert_eq!(versions(&plan), vec![3, 2]);
+        assert_eq!(plan.to_version, 1);
+        assert_eq!(manager.status().await.unwrap().current_version, 1);
     }
 
-    fn get_migrations(&self) -> Vec<Migration> {
-        vec![
-            Migration {
-                version: 1,
-                name: "Create initial Synapse schema".to_string(),
-                sql: include_str!("../../../migrations/001_create_synapse_schema.sql").to_string(),
-            },
-            // Add more migrations here as needed
-        ]
+    #[tokio::test]
+    async fn test_refuses_unknown_or_modified_history() {
+        let executor = MemoryExecutor::default();
+        executor.history.lock().unwrap().push(AppliedMigration {
+            version: 9,
+            name: "from the future".to_string(),
+            checksum: String::new(),
+            applied_at: Utc::now(),
+        });
+        let manager = MigrationManager::with_migrations(executor, SET);
+        assert_eq!(manager.status().await.unwrap().unknown, vec![9]);
+        assert!(manager.migrate().await.is_err());
+
+        let executor = MemoryExecutor::default();
+        executor.history.lock().unwrap().push(AppliedMigration {
+            version: 1,
+            name: "one".to_string(),
+            checksum: "edited".to_string(),
+            applied_at: Utc::now(),
+        });
+        let manager = MigrationManager::with_migrations(executor, SET);
+        assert_eq!(manager.status().await.unwrap().modified, vec![1]);
+        assert!(manager.migrate().await.is_err());
     }
-}
 
-#[derive(Debug)]
-struct Migration {
-    version: i32,
-    name: String,
-    sql: String,
+    #[tokio::test]
+    async fn test_failed_step_stops_upgrade() {
+        const BROKEN: &[Migration] = &[
+            Migration { version: 1, name: "one", up: "CREATE TABLE a (x INTEGER);", down: "DROP TABLE a;" },
+            Migration { version: 2, name: "two", up: "FAIL", down: "" },
+            Migration { version: 3, name: "three", up: "CREATE TABLE c (x INTEGER);", down: "DROP TABLE c;" },
+        ];
+        let manager = MigrationManager::with_migrations(MemoryExecutor::default(), BROKEN);
+        let err = manager.migrate().await.unwrap_err();
+        assert!(err.to_string().contains("Migration 2"));
+        assert_eq!(manager.status().await.unwrap().current_version, 1);
+    }
+
+    #[test]
+    fn test_shipped_sets_are_ordered_and_paired() {
+        for backend in [Backend::Postgres, Backend::Sqlite] {
+            let set = migrations(backend);
+            assert!(set.windows(2).all(|w| w[0].version < w[1].version));
+            assert!(set.iter().all(|m| !m.up.trim().is_empty() && !m.down.trim().is_empty()));
+        }
+        assert_eq!(migrations(Backend::Postgres).len(), migrations(Backend::Sqlite).len());
+    }
 }
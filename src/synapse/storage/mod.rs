@@ -3,9 +3,13 @@
 pub mod database;
 pub mod cache;
 pub mod migrations;
+pub mod attachments;
 
 // Re-export storage interfaces with feature guards
 #[cfg(feature = "database")]
 pub use database::Database;
+#[cfg(any(feature = "database", feature = "embedded-db"))]
+pub use migrations::{MigrationManager, MigrationStatus, MigrationPlan, StartupMigration};
 #[cfg(feature = "cache")]
-pub use cache::Cache;
+pub use cache::{Cache, CacheConfig};
+pub use attachments::{AttachmentSource, AttachmentStore};
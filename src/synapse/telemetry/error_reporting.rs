@@ -13,6 +13,7 @@ use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
 use crate::blockchain::serialization::UuidWrapper;
+use crate::redaction::{redact_map, scrub};
 
 /// Error source categories
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -26,6 +27,7 @@ pub enum ErrorSource {
     WebRTC,
     Crypto,
     Config,
+    Api,
     External,
     Unknown,
 }
@@ -139,6 +141,9 @@ impl ErrorTelemetry {
     }
     
     /// Report an error to the telemetry system
+    ///
+    /// The message, context and stack trace are scrubbed of secrets before
+    /// the report is logged, stored or exported.
     pub fn report_error(&self, 
                         source: ErrorSource, 
                         severity: ErrorSeverity, 
@@ -152,6 +157,10 @@ impl ErrorTelemetry {
             return;
         }
         
+        let message = scrub(message);
+        let mut context = context.unwrap_or_default();
+        redact_map(&mut context);
+
         // Create the error report
         let error = ErrorReport {
             id: UuidWrapper::new(Uuid::new_v4()).to_string(),
@@ -160,8 +169,8 @@ impl ErrorTelemetry {
             severity,
             message: message.to_string(),
             code: code.map(String::from),
-            context: context.unwrap_or_default(),
-            stack_trace: stack_trace.map(String::from),
+            context,
+            stack_trace: stack_trace.map(|stack| scrub(stack).into_owned()),
         };
         
         // Log to console if enabled
@@ -458,6 +467,23 @@ mod tests {
         assert_eq!(errors[0].severity, ErrorSeverity::Error);
     }
     
+    #[test]
+    fn test_error_reports_are_scrubbed() {
+        let telemetry = ErrorTelemetry::default();
+        telemetry.report_error(
+            ErrorSource::Transport,
+            ErrorSeverity::Error,
+            "SMTP login failed with password=hunter2",
+            None,
+            Some(HashMap::from([("access_token".to_string(), "abc123".to_string())])),
+            None
+        );
+
+        let report = &telemetry.get_recent_errors(1)[0];
+        assert!(!report.message.contains("hunter2"));
+        assert_eq!(report.context["access_token"], crate::redaction::REDACTED);
+    }
+    
     #[test]
     fn test_error_telemetry_history_limit() {
         let config = ErrorTelemetryConfig {
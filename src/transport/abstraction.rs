@@ -5,6 +5,7 @@
 //! failover, and optimization capabilities.
 
 use crate::{
+    crypto::{CryptoManager, VerifyJob},
     types::SecureMessage,
     error::Result,
 };
@@ -59,6 +60,54 @@ pub trait Transport: Send + Sync {
         let _ = (target, offer);
         Err(crate::error::SynapseError::TransportError("Connection offers not supported by this transport".to_string()))
     }
+
+    /// Refresh NAT mappings on the path to a target
+    ///
+    /// Called by the transport manager on routes that have been quiet for the
+    /// keepalive interval. Transports whose routes cross NATs send a packet
+    /// small enough to cost nothing; the default does nothing.
+    async fn send_keepalive(&self, _target: &TransportTarget) -> Result<()> {
+        Ok(())
+    }
+
+    /// Warm up a path to a target ahead of traffic
+    ///
+    /// Transports that can hold connections open should pre-connect here so the
+    /// next send skips setup. The default only checks that the target is reachable.
+    async fn prepare(&self, target: &TransportTarget) -> Result<()> {
+        let result = self.test_connectivity(target).await?;
+        if result.connected {
+            Ok(())
+        } else {
+            Err(crate::error::SynapseError::TransportError(format!(
+                "Cannot prepare {} route to {}: {}",
+                self.transport_type(),
+                target.identifier,
+                result.error.unwrap_or_else(|| "unreachable".to_string())
+            )))
+        }
+    }
+
+    /// Send several messages to one target, returning a result per message in order
+    ///
+    /// Transports that can carry more than one message per connection or
+    /// datagram should override this to pipeline or coalesce them. The default
+    /// sends them one at a time.
+    async fn send_batch(&self, target: &TransportTarget, messages: &[SecureMessage]) -> Vec<Result<DeliveryReceipt>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            results.push(self.send_message(target, message).await);
+        }
+        results
+    }
+
+    /// Address this transport accepts connections on, if it listens
+    ///
+    /// This is the address actually bound, so ephemeral ports are reported
+    /// as chosen by the OS.
+    fn local_endpoint(&self) -> Option<std::net::SocketAddr> {
+        None
+    }
 }
 
 /// Transport types supported by the system
@@ -71,6 +120,8 @@ pub enum TransportType {
     Email,
     AutoDiscovery, // Replaces Mdns with more comprehensive discovery
     Quic,
+    Tor, // Onion services, anonymous
+    I2p, // Garlic-routed streams, anonymous
     Custom(u32), // For extensibility
 }
 
@@ -84,6 +135,8 @@ impl std::fmt::Display for TransportType {
             TransportType::Email => write!(f, "Email"),
             TransportType::AutoDiscovery => write!(f, "Auto-Discovery"),
             TransportType::Quic => write!(f, "QUIC"),
+            TransportType::Tor => write!(f, "Tor"),
+            TransportType::I2p => write!(f, "I2P"),
             TransportType::Custom(id) => write!(f, "Custom({})", id),
         }
     }
@@ -189,6 +242,9 @@ pub struct TransportEstimate {
     pub available: bool,
     /// Confidence in these estimates (0.0-1.0)
     pub confidence: f64,
+    /// Region of the relay or provider the transport would go through, if known
+    #[serde(default)]
+    pub region: Option<String>,
 }
 
 /// Delivery receipt from successful message send
@@ -234,6 +290,8 @@ pub struct IncomingMessage {
     pub received_timestamp: u64,
     /// Additional transport-specific metadata
     pub metadata: HashMap<String, String>,
+    /// How the message was verified on receipt
+    pub verification: VerificationInfo,
 }
 
 impl IncomingMessage {
@@ -248,6 +306,7 @@ impl IncomingMessage {
                 .unwrap_or_default()
                 .as_secs(),
             metadata: HashMap::new(),
+            verification: VerificationInfo::default(),
         }
     }
     
@@ -255,6 +314,112 @@ impl IncomingMessage {
     pub fn received_at(&self) -> Duration {
         Duration::from_secs(self.received_timestamp)
     }
+
+    /// Check the sender's signature over `content`, the decrypted body, and
+    /// record the result with the sender's trust score at receipt
+    ///
+    /// Both canonical and legacy (content-only) signatures are accepted; see
+    /// [`crate::signing`].
+    pub fn verify(&mut self, crypto: &CryptoManager, content: &str, trust_score: Option<f64>) -> &VerificationInfo {
+        if let Some(job) = self.verification_job(crypto, content, trust_score) {
+            self.record_signature(job.run());
+        }
+        &self.verification
+    }
+
+    /// Record everything [`verify`](Self::verify) does except the signature
+    /// check itself, which is returned to run elsewhere
+    ///
+    /// Receive paths collect these jobs and check them together on a
+    /// [`CryptoWorkerPool`](crate::crypto_pool::CryptoWorkerPool), then hand
+    /// each result to [`record_signature`](Self::record_signature). `None`
+    /// means the status is already final: unsigned, unknown key or an
+    /// unsupported format.
+    pub fn verification_job(&mut self, crypto: &CryptoManager, content: &str, trust_score: Option<f64>) -> Option<VerifyJob> {
+        let sender = &self.message.from_global_id;
+        let mut job = None;
+        self.verification.signature = if self.message.signature.is_empty() {
+            SignatureStatus::Unsigned
+        } else {
+            match (crypto.signature_verifier(sender), self.message.signing_payload(content)) {
+                (None, _) => SignatureStatus::UnknownKey,
+                (Some(_), None) => SignatureStatus::UnsupportedFormat,
+                (Some(verifier), Some(payload)) => {
+                    job = Some(VerifyJob::new(verifier, payload, self.message.signature.clone()));
+                    SignatureStatus::Unverified
+                }
+            }
+        };
+        self.verification.key_fingerprint = crypto.key_fingerprint(sender);
+        self.verification.key_age = crypto.key_age(sender);
+        self.verification.trust_score = trust_score;
+        job
+    }
+
+    /// Record the outcome of the job [`verification_job`](Self::verification_job) returned
+    pub fn record_signature(&mut self, valid: bool) {
+        self.verification.signature = if valid { SignatureStatus::Valid } else { SignatureStatus::Invalid };
+    }
+}
+
+/// Metadata entry with the [`SignatureStatus`] of a received direct message
+pub const SIGNATURE_STATUS_KEY: &str = "signature_status";
+
+/// Outcome of checking a message's signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    /// Not checked yet
+    #[default]
+    Unverified,
+    /// The message carried no signature
+    Unsigned,
+    /// No public key is known for the sender
+    UnknownKey,
+    /// Signed by the sender's known key
+    Valid,
+    /// The signature does not match the sender's known key
+    Invalid,
+    /// Signed in a newer format than this node can check
+    UnsupportedFormat,
+}
+
+impl SignatureStatus {
+    /// Name as serialized, e.g. `unknown_key`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignatureStatus::Unverified => "unverified",
+            SignatureStatus::Unsigned => "unsigned",
+            SignatureStatus::UnknownKey => "unknown_key",
+            SignatureStatus::Valid => "valid",
+            SignatureStatus::Invalid => "invalid",
+            SignatureStatus::UnsupportedFormat => "unsupported_format",
+        }
+    }
+}
+
+/// How an incoming message was verified, for risk-based decisions
+///
+/// Transports fill in what they know about the channel; the signature,
+/// key and trust fields are set by [`IncomingMessage::verify`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VerificationInfo {
+    pub signature: SignatureStatus,
+    /// SHA-256 fingerprint of the sender key the signature was checked against
+    pub key_fingerprint: Option<String>,
+    /// How long that key has been known; a brand-new key deserves more caution
+    pub key_age: Option<Duration>,
+    /// Sender's trust score when the message arrived
+    pub trust_score: Option<f64>,
+    /// Whether the transport encrypted the message in transit
+    pub transport_encrypted: bool,
+}
+
+impl VerificationInfo {
+    /// Whether the message is signed by the sender's known key
+    pub fn is_authenticated(&self) -> bool {
+        self.signature == SignatureStatus::Valid
+    }
 }
 
 /// Result of connectivity test
@@ -556,6 +721,47 @@ impl TransportCapabilities {
         caps.features.push("authenticated".to_string());
         caps
     }
+    
+    /// Tor onion service transport capabilities
+    pub fn tor() -> Self {
+        Self {
+            max_message_size: 4 * 1024 * 1024, // 4MB, circuits are slow
+            reliable: true,
+            real_time: false, // Multi-hop circuits add latency
+            broadcast: false,
+            bidirectional: true,
+            encrypted: true, // End-to-end to the onion service
+            network_spanning: true,
+            supported_urgencies: vec![
+                MessageUrgency::Interactive,
+                MessageUrgency::Background,
+                MessageUrgency::Batch,
+            ],
+            features: vec![
+                "anonymous".to_string(),
+                "censorship_resistant".to_string(),
+                "nat_free".to_string(),
+                "onion_service".to_string(),
+            ],
+        }
+    }
+    
+    /// I2P streaming transport capabilities
+    pub fn i2p() -> Self {
+        let mut caps = Self::tor();
+        caps.features = vec![
+            "anonymous".to_string(),
+            "censorship_resistant".to_string(),
+            "nat_free".to_string(),
+            "garlic_routing".to_string(),
+        ];
+        caps
+    }
+    
+    /// Whether this transport hides the endpoints' network addresses
+    pub fn is_anonymous(&self) -> bool {
+        self.features.iter().any(|f| f == "anonymous")
+    }
 }
 
 /// Transport factory trait for creating transport instances
@@ -1023,7 +1229,13 @@ impl UnifiedTransportManager {
         
         for transport in self.transports.values() {
             match transport.receive_messages().await {
-                Ok(mut messages) => all_messages.append(&mut messages),
+                Ok(mut messages) => {
+                    let encrypted = transport.capabilities().encrypted;
+                    for message in &mut messages {
+                        message.verification.transport_encrypted |= encrypted;
+                    }
+                    all_messages.append(&mut messages);
+                }
                 Err(_) => continue, // Skip transports with errors
             }
         }
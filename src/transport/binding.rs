@@ -0,0 +1,235 @@
+//! Listener binding shared by every transport and server that accepts connections
+//!
+//! A listener is described by a [`ListenerBinding`]: the interface to bind, a
+//! preferred port with an optional fallback range, and whether an IPv6
+//! wildcard also accepts IPv4. Whatever address is actually bound (including
+//! an OS-chosen ephemeral port) is what gets advertised to peers, under
+//! `endpoint.<transport>` routing preferences of our identity.
+
+use crate::{
+    config::ListenerBinding,
+    error::{Result, SynapseError},
+    types::GlobalIdentity,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use tokio::net::{TcpListener, UdpSocket};
+use tracing::debug;
+
+/// Prefix of the routing preference keys bound endpoints are advertised under
+pub const ENDPOINT_KEY_PREFIX: &str = "endpoint.";
+
+/// Routing preference key a transport's endpoint is advertised under
+pub fn endpoint_key(transport: &str) -> String {
+    format!("{}{}", ENDPOINT_KEY_PREFIX, transport)
+}
+
+/// Addresses to try for a binding, in order
+///
+/// The preferred port comes first, then the fallback range. Port 0 with no
+/// range asks the OS for an ephemeral port.
+pub fn candidate_addrs(binding: &ListenerBinding) -> Vec<SocketAddr> {
+    let mut ports = Vec::new();
+    if binding.port != 0 {
+        ports.push(binding.port);
+    }
+    if let Some((first, last)) = binding.port_range {
+        ports.extend((first..=last).filter(|port| *port != binding.port));
+    }
+    if ports.is_empty() {
+        ports.push(0);
+    }
+    ports.into_iter().map(|port| SocketAddr::new(binding.address, port)).collect()
+}
+
+fn socket_for(addr: SocketAddr, ty: Type, protocol: Protocol, dual_stack: bool) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), ty, Some(protocol))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!dual_stack)?;
+    }
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+fn bind_error(kind: &str, binding: &ListenerBinding, error: Option<io::Error>) -> SynapseError {
+    let reason = error.map(|e| e.to_string()).unwrap_or_else(|| "no candidate ports".to_string());
+    SynapseError::NetworkError(format!(
+        "Failed to bind {} listener on {} (port {}, range {:?}): {}",
+        kind, binding.address, binding.port, binding.port_range, reason
+    ))
+}
+
+/// Bind a TCP listener, trying the preferred port and then the fallback range
+pub fn bind_tcp(binding: &ListenerBinding) -> Result<TcpListener> {
+    let mut last_error = None;
+    for addr in candidate_addrs(binding) {
+        let attempt = socket_for(addr, Type::STREAM, Protocol::TCP, binding.dual_stack).and_then(|socket| {
+            #[cfg(not(windows))]
+            socket.set_reuse_address(true)?;
+            socket.bind(&addr.into())?;
+            socket.listen(1024)?;
+            TcpListener::from_std(socket.into())
+        });
+        match attempt {
+            Ok(listener) => return Ok(listener),
+            Err(e) => {
+                debug!("Could not bind TCP {}: {}", addr, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(bind_error("TCP", binding, last_error))
+}
+
+/// Bind a UDP socket, trying the preferred port and then the fallback range
+pub fn bind_udp(binding: &ListenerBinding) -> Result<UdpSocket> {
+    let mut last_error = None;
+    for addr in candidate_addrs(binding) {
+        let attempt = socket_for(addr, Type::DGRAM, Protocol::UDP, binding.dual_stack).and_then(|socket| {
+            socket.bind(&addr.into())?;
+            UdpSocket::from_std(socket.into())
+        });
+        match attempt {
+            Ok(socket) => return Ok(socket),
+            Err(e) => {
+                debug!("Could not bind UDP {}: {}", addr, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(bind_error("UDP", binding, last_error))
+}
+
+/// Address of the interface outbound traffic leaves through
+///
+/// Connecting a UDP socket only performs a route lookup; nothing is sent.
+pub fn primary_local_ip(ipv6: bool) -> Option<IpAddr> {
+    let (bind, probe): (SocketAddr, SocketAddr) = if ipv6 {
+        ((Ipv6Addr::UNSPECIFIED, 0).into(), ("2001:db8::1".parse::<Ipv6Addr>().ok()?, 9).into())
+    } else {
+        ((Ipv4Addr::UNSPECIFIED, 0).into(), (Ipv4Addr::new(192, 0, 2, 1), 9).into())
+    };
+    let socket = std::net::UdpSocket::bind(bind).ok()?;
+    socket.connect(probe).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+/// The `host:port` peers should use to reach a bound listener
+///
+/// `advertise_host` wins when set. Otherwise a wildcard bind is advertised
+/// with the primary interface address, falling back to loopback.
+pub fn advertised_endpoint(bound: SocketAddr, advertise_host: Option<&str>) -> String {
+    if let Some(host) = advertise_host {
+        return match host.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, bound.port()).to_string(),
+            Err(_) => format!("{}:{}", host, bound.port()),
+        };
+    }
+    if !bound.ip().is_unspecified() {
+        return bound.to_string();
+    }
+    let ip = primary_local_ip(bound.is_ipv6()).unwrap_or(if bound.is_ipv6() {
+        IpAddr::V6(Ipv6Addr::LOCALHOST)
+    } else {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    });
+    SocketAddr::new(ip, bound.port()).to_string()
+}
+
+/// Advertise bound listeners in an identity, replacing earlier endpoints
+///
+/// `endpoints` maps transport names (`tcp`, `udp`, ...) to the bound address.
+/// Endpoints of transports no longer listening are removed.
+pub fn advertise_endpoints(
+    identity: &mut GlobalIdentity,
+    endpoints: &HashMap<String, SocketAddr>,
+    advertise_host: Option<&str>,
+) {
+    identity.routing_preferences.retain(|key, _| !key.starts_with(ENDPOINT_KEY_PREFIX));
+    for (transport, bound) in endpoints {
+        identity
+            .routing_preferences
+            .insert(endpoint_key(transport), advertised_endpoint(*bound, advertise_host));
+    }
+}
+
+/// Endpoints a peer advertised in its identity, keyed by transport name
+pub fn advertised_endpoints(identity: &GlobalIdentity) -> HashMap<String, String> {
+    identity
+        .routing_preferences
+        .iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(ENDPOINT_KEY_PREFIX)
+                .map(|transport| (transport.to_string(), value.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loopback(port: u16, port_range: Option<(u16, u16)>) -> ListenerBinding {
+        ListenerBinding {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port,
+            port_range,
+            dual_stack: false,
+        }
+    }
+
+    #[test]
+    fn candidates_try_preferred_port_then_range() {
+        let ports: Vec<u16> = candidate_addrs(&loopback(9001, Some((9000, 9002))))
+            .iter()
+            .map(SocketAddr::port)
+            .collect();
+        assert_eq!(ports, vec![9001, 9000, 9002]);
+
+        let ports: Vec<u16> = candidate_addrs(&loopback(0, None)).iter().map(SocketAddr::port).collect();
+        assert_eq!(ports, vec![0]);
+    }
+
+    #[tokio::test]
+    async fn ephemeral_binding_reports_real_port() {
+        let listener = bind_tcp(&loopback(0, None)).unwrap();
+        let bound = listener.local_addr().unwrap();
+        assert_ne!(bound.port(), 0);
+        assert_eq!(advertised_endpoint(bound, None), bound.to_string());
+
+        let socket = bind_udp(&loopback(0, None)).unwrap();
+        assert_ne!(socket.local_addr().unwrap().port(), 0);
+    }
+
+    #[tokio::test]
+    async fn taken_port_falls_back_to_range() {
+        let taken = bind_tcp(&loopback(0, None)).unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+        let spare = bind_tcp(&loopback(0, None)).unwrap();
+        let spare_port = spare.local_addr().unwrap().port();
+        drop(spare);
+
+        let listener = bind_tcp(&loopback(taken_port, Some((spare_port, spare_port)))).unwrap();
+        assert_eq!(listener.local_addr().unwrap().port(), spare_port);
+        assert!(bind_tcp(&loopback(taken_port, None)).is_err());
+    }
+
+    #[test]
+    fn advertisement_replaces_stale_endpoints() {
+        let mut identity = GlobalIdentity::default();
+        identity.routing_preferences.insert(endpoint_key("udp"), "10.0.0.1:1".to_string());
+        identity.routing_preferences.insert("onion_address".to_string(), "x.onion:80".to_string());
+
+        let endpoints = HashMap::from([("tcp".to_string(), "0.0.0.0:4100".parse().unwrap())]);
+        advertise_endpoints(&mut identity, &endpoints, Some("relay.example.com"));
+
+        let advertised = advertised_endpoints(&identity);
+        assert_eq!(advertised.len(), 1);
+        assert_eq!(advertised["tcp"], "relay.example.com:4100");
+        assert!(identity.routing_preferences.contains_key("onion_address"));
+    }
+}
@@ -0,0 +1,395 @@
+//! Capability probes answered by peers
+//!
+//! `can_reach` only checks our side of a link. Before committing a large send,
+//! the router pings the peer with a [`CapabilityDigest`] of its own transports
+//! (largest accepted message, whether the link is encrypted) and the peer
+//! answers with its digest. Answers are cached per peer in [`PeerCapabilities`],
+//! and route selection skips routes the peer has said it cannot take: a payload
+//! over that transport's size limit, or a `Secure` message over a link the peer
+//! does not encrypt. Digests also list the content hash algorithms a node
+//! supports, so large transfers can use the fastest one both sides know.
+//!
+//! Probes travel as small system messages; see [`CapabilityProbe`].
+
+use super::{abstraction::TransportCapabilities, nat_behavior::NatType, TransportRoute};
+use crate::config::EmailRelayEndpoint;
+use crate::hashing::{self, HashAlgorithm};
+use crate::protocol::{self, ProtocolVersion, VersionRange};
+use crate::types::{AttachmentContent, MessageType, SecureMessage, SecurityLevel, SimpleMessage};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// Metadata key marking a message as a capability probe (the probe kind)
+pub const CAPABILITY_PROBE_KEY: &str = "capability_probe";
+
+/// What a peer accepts over one of its transports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportLimits {
+    /// Largest payload, in bytes
+    pub max_message_size: usize,
+    /// Whether the link itself is encrypted
+    pub encrypted: bool,
+}
+
+/// The transports a node accepts messages on, keyed by carrier name
+///
+/// Carrier names are `tcp`, `mdns`, `nat`, `email`, `relay` and `custom:<id>`;
+/// see [`route_carrier`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityDigest {
+    pub transports: BTreeMap<String, TransportLimits>,
+    /// The node's NAT type, once it has classified it
+    #[serde(default)]
+    pub nat_type: Option<NatType>,
+    /// Fast email relays holding mail for the node
+    #[serde(default)]
+    pub email_relays: Vec<EmailRelayEndpoint>,
+    /// Protocol versions the node speaks; absent from nodes that predate
+    /// version negotiation
+    #[serde(default)]
+    pub protocol: Option<VersionRange>,
+    /// Content hash algorithms the node supports; empty from nodes that
+    /// only know SHA-256
+    #[serde(default)]
+    pub hashes: Vec<HashAlgorithm>,
+}
+
+impl CapabilityDigest {
+    /// Add a transport, merging with any already listed under the same name
+    pub fn insert(&mut self, carrier: impl Into<String>, capabilities: &TransportCapabilities) {
+        let limits = TransportLimits {
+            max_message_size: capabilities.max_message_size,
+            encrypted: capabilities.encrypted,
+        };
+        self.transports
+            .entry(carrier.into())
+            .and_modify(|existing| {
+                existing.max_message_size = existing.max_message_size.min(limits.max_message_size);
+                existing.encrypted &= limits.encrypted;
+            })
+            .or_insert(limits);
+    }
+
+    /// Advertise `relays` as holding mail for the node, under the `relay` carrier
+    ///
+    /// The carrier counts as encrypted only if every relay's API is HTTPS.
+    pub fn add_email_relays(&mut self, relays: &[EmailRelayEndpoint]) {
+        if relays.is_empty() {
+            return;
+        }
+        self.transports.insert(
+            "relay".to_string(),
+            TransportLimits {
+                max_message_size: relays.iter().map(|r| r.max_message_bytes as usize).min().unwrap_or(0),
+                encrypted: relays
+                    .iter()
+                    .all(|r| r.handoff_url.as_deref().is_some_and(|url| url.starts_with("https://"))),
+            },
+        );
+        self.email_relays.extend_from_slice(relays);
+    }
+
+    /// Largest payload accepted over any transport
+    pub fn max_message_size(&self) -> usize {
+        self.transports.values().map(|l| l.max_message_size).max().unwrap_or(0)
+    }
+
+    /// Whether the node would accept `message` sent over `route`
+    pub fn accepts(&self, route: &TransportRoute, message: &SecureMessage) -> bool {
+        let Some(limits) = self.transports.get(route_carrier(route).as_str()) else {
+            return false;
+        };
+        payload_size(message) <= limits.max_message_size
+            && (limits.encrypted || !matches!(message.security_level, SecurityLevel::Secure))
+    }
+}
+
+/// Name of the peer transport that receives a route
+///
+/// Matches how the router sends: UDP, QUIC and WebSocket routes are carried
+/// over the TCP transport, and email discovery ends in email.
+pub fn route_carrier(route: &TransportRoute) -> String {
+    match route {
+        TransportRoute::DirectTcp { .. }
+        | TransportRoute::DirectUdp { .. }
+        | TransportRoute::Udp { .. }
+        | TransportRoute::WebSocket { .. }
+        | TransportRoute::Quic { .. } => "tcp".to_string(),
+        TransportRoute::LocalMdns { .. } => "mdns".to_string(),
+        TransportRoute::NatTraversal { .. } => "nat".to_string(),
+        TransportRoute::FastEmailRelay { .. } => "relay".to_string(),
+        TransportRoute::StandardEmail { .. } | TransportRoute::EmailDiscovery { .. } => "email".to_string(),
+        TransportRoute::Custom { transport_type, .. } => match transport_type {
+            super::TransportType::Custom(id) => format!("custom:{}", id),
+            other => other.to_string().to_lowercase(),
+        },
+    }
+}
+
+/// Bytes a message puts on the wire: content plus inline attachments
+pub fn payload_size(message: &SecureMessage) -> usize {
+    let attachments: usize = message
+        .attachments
+        .iter()
+        .map(|a| match &a.content {
+            AttachmentContent::Inline { data } => data.len(),
+            AttachmentContent::Stream { .. } => 0,
+        })
+        .sum();
+    message.encrypted_content.len() + attachments
+}
+
+/// A probe and its answer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CapabilityProbe {
+    /// Our capabilities, asking for the peer's
+    Ping { nonce: u64, digest: CapabilityDigest },
+    /// Answer to the ping with the same nonce
+    Pong { nonce: u64, digest: CapabilityDigest },
+}
+
+impl CapabilityProbe {
+    /// Name used in the message metadata
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CapabilityProbe::Ping { .. } => "ping",
+            CapabilityProbe::Pong { .. } => "pong",
+        }
+    }
+
+    /// Wrap the probe in a system message from `from_entity` to `to_entity`
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> crate::error::Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(CAPABILITY_PROBE_KEY.to_string(), self.kind().to_string());
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: serde_json::to_string(self)?,
+            message_type: MessageType::System,
+            metadata,
+        })
+    }
+
+    /// Extract the probe carried by `message`, if any
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        if message.message_type != MessageType::System || !message.metadata.contains_key(CAPABILITY_PROBE_KEY) {
+            return None;
+        }
+        serde_json::from_str(&message.content).ok()
+    }
+}
+
+/// Capabilities peers have told us about, and probes awaiting an answer
+pub struct PeerCapabilities {
+    known: DashMap<String, (CapabilityDigest, Instant)>,
+    /// Nonce -> peer the ping went to and who is waiting for the answer
+    pending: DashMap<u64, (String, oneshot::Sender<CapabilityDigest>)>,
+    next_nonce: AtomicU64,
+    ttl: Duration,
+}
+
+impl PeerCapabilities {
+    /// Trust answers for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            known: DashMap::new(),
+            pending: DashMap::new(),
+            next_nonce: AtomicU64::new(rand::random()),
+            ttl,
+        }
+    }
+
+    /// The peer's unexpired digest, if it has told us one
+    pub fn get(&self, peer: &str) -> Option<CapabilityDigest> {
+        let entry = self.known.get(peer)?;
+        let (digest, recorded_at) = entry.value();
+        (recorded_at.elapsed() < self.ttl).then(|| digest.clone())
+    }
+
+    pub fn record(&self, peer: &str, digest: CapabilityDigest) {
+        self.known.insert(peer.to_string(), (digest, Instant::now()));
+    }
+
+    pub fn forget(&self, peer: &str) {
+        self.known.remove(peer);
+    }
+
+    /// Protocol version to talk to `peer` at, if it has told us its range
+    ///
+    /// Fails with [`SynapseError::IncompatiblePeer`](crate::error::SynapseError::IncompatiblePeer)
+    /// when the peer's range does not overlap ours.
+    pub fn protocol_version(&self, peer: &str) -> Option<crate::error::Result<ProtocolVersion>> {
+        let digest = self.get(peer)?;
+        Some(protocol::negotiate(peer, &digest.protocol.unwrap_or(VersionRange::LEGACY)))
+    }
+
+    /// Algorithm to hash content sent to `peer` with
+    ///
+    /// SHA-256 unless the peer has said it supports something we prefer.
+    pub fn content_hash(&self, peer: &str) -> HashAlgorithm {
+        self.get(peer).map_or(HashAlgorithm::Sha256, |digest| hashing::negotiate(&digest.hashes))
+    }
+
+    /// Whether selection may send `message` to `peer` over `route`
+    ///
+    /// Peers we know nothing about are assumed to accept anything.
+    pub fn accepts(&self, peer: &str, route: &TransportRoute, message: &SecureMessage) -> bool {
+        self.get(peer).is_none_or(|digest| digest.accepts(route, message))
+    }
+
+    /// Register a ping to `peer`, returning its nonce and the eventual answer
+    pub fn start_probe(&self, peer: &str) -> (u64, oneshot::Receiver<CapabilityDigest>) {
+        let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.insert(nonce, (peer.to_string(), sender));
+        (nonce, receiver)
+    }
+
+    /// Drop a ping that will not be waited for any more
+    pub fn cancel(&self, nonce: u64) {
+        self.pending.remove(&nonce);
+    }
+
+    /// Apply `from`'s answer to ping `nonce`
+    ///
+    /// Returns `false`, recording nothing, if we sent no such ping to `from`.
+    pub fn complete(&self, from: &str, nonce: u64, digest: CapabilityDigest) -> bool {
+        let Some((_, (_, waiter))) = self.pending.remove_if(&nonce, |_, (peer, _)| peer == from) else {
+            return false;
+        };
+        self.record(from, digest.clone());
+        // The prober may have timed out in the meantime
+        let _ = waiter.send(digest);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
+    use crate::transport::abstraction::{MessageUrgency, TransportType};
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn capabilities(max_message_size: usize, encrypted: bool) -> TransportCapabilities {
+        TransportCapabilities {
+            max_message_size,
+            reliable: true,
+            real_time: true,
+            broadcast: false,
+            bidirectional: true,
+            encrypted,
+            network_spanning: true,
+            supported_urgencies: vec![MessageUrgency::Interactive],
+            features: Vec::new(),
+        }
+    }
+
+    fn message(size: usize, security_level: SecurityLevel) -> SecureMessage {
+        SecureMessage {
+            message_id: UuidWrapper::new(Uuid::new_v4()),
+            to_global_id: "bob@example.com".to_string(),
+            from_global_id: "alice@example.com".to_string(),
+            encrypted_content: vec![0; size],
+            signature: Vec::new(),
+            timestamp: DateTimeWrapper::new(Utc::now()),
+            security_level,
+            routing_path: Vec::new(),
+            metadata: HashMap::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    fn tcp_route() -> TransportRoute {
+        TransportRoute::DirectTcp {
+            address: "10.0.0.5".to_string(),
+            port: 8080,
+            latency_ms: 12,
+            established_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn digest_limits_size_and_encryption() {
+        let mut digest = CapabilityDigest::default();
+        digest.insert("tcp", &capabilities(1024, false));
+        digest.insert("email", &capabilities(10 * 1024 * 1024, true));
+        let email = TransportRoute::StandardEmail { estimated_latency_min: 1 };
+
+        assert!(digest.accepts(&tcp_route(), &message(1024, SecurityLevel::Public)));
+        assert!(!digest.accepts(&tcp_route(), &message(1025, SecurityLevel::Public)));
+        assert!(!digest.accepts(&tcp_route(), &message(10, SecurityLevel::Secure)));
+        assert!(digest.accepts(&email, &message(1 << 20, SecurityLevel::Secure)));
+        assert_eq!(digest.max_message_size(), 10 * 1024 * 1024);
+
+        // Transports the peer does not run are refused
+        let mdns = TransportRoute::LocalMdns {
+            service_name: "_synapse._tcp".to_string(),
+            address: "10.0.0.5".to_string(),
+            port: 8080,
+            latency_ms: 1,
+            discovered_at: Instant::now(),
+        };
+        assert!(!digest.accepts(&mdns, &message(1, SecurityLevel::Public)));
+    }
+
+    #[test]
+    fn custom_routes_use_their_id() {
+        let route = TransportRoute::Custom {
+            transport_type: TransportType::Custom(7),
+            latency_ms: 0,
+            network_spanning: true,
+        };
+        assert_eq!(route_carrier(&route), "custom:7");
+    }
+
+    #[test]
+    fn probe_round_trips_as_system_message() {
+        let mut digest = CapabilityDigest::default();
+        digest.insert("tcp", &capabilities(4096, true));
+        let probe = CapabilityProbe::Ping { nonce: 9, digest };
+
+        let message = probe.to_message("alice@example.com", "bob@example.com").unwrap();
+        assert_eq!(message.message_type, MessageType::System);
+        assert_eq!(message.metadata.get(CAPABILITY_PROBE_KEY).map(String::as_str), Some("ping"));
+        assert_eq!(CapabilityProbe::from_message(&message), Some(probe));
+    }
+
+    #[tokio::test]
+    async fn answers_only_complete_our_pings_to_that_peer() {
+        let peers = PeerCapabilities::new(Duration::from_secs(60));
+        assert!(peers.accepts("bob", &tcp_route(), &message(1 << 30, SecurityLevel::Secure)));
+
+        let mut digest = CapabilityDigest::default();
+        digest.insert("tcp", &capabilities(1024, false));
+        let (nonce, answer) = peers.start_probe("bob");
+
+        assert!(!peers.complete("mallory", nonce, CapabilityDigest::default()));
+        assert!(!peers.complete("bob", nonce + 1, CapabilityDigest::default()));
+        assert!(peers.complete("bob", nonce, digest.clone()));
+        assert_eq!(answer.await.unwrap(), digest);
+
+        assert!(!peers.accepts("bob", &tcp_route(), &message(2048, SecurityLevel::Public)));
+        assert!(peers.accepts("bob", &tcp_route(), &message(512, SecurityLevel::Public)));
+
+        // Peers that list no hashes get SHA-256, others the best shared one
+        assert_eq!(peers.content_hash("bob"), HashAlgorithm::Sha256);
+        peers.record("bob", CapabilityDigest { hashes: hashing::PREFERENCE.to_vec(), ..digest });
+        assert_eq!(peers.content_hash("bob"), HashAlgorithm::Blake3);
+    }
+
+    #[test]
+    fn answers_expire() {
+        let peers = PeerCapabilities::new(Duration::ZERO);
+        peers.record("bob", CapabilityDigest::default());
+        assert!(peers.get("bob").is_none());
+    }
+}
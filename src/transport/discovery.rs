@@ -169,6 +169,7 @@ impl Transport for DiscoveryTransport {
                 cost: 0.1,
                 available: true,
                 confidence: 0.9,
+                region: None,
             })
         } else {
             Err(SynapseError::TransportError("Service not found".to_string()))
@@ -0,0 +1,275 @@
+//! Echo service for reachability and NAT checks
+//!
+//! A node with the echo role enabled answers signed [`EchoProbe::Ping`]s with
+//! an [`EchoReflection`]: the address and transport the ping arrived from as
+//! the echo node saw them, and when it arrived. The pinging node learns its
+//! public address and whether a round trip works at all, which is what its
+//! diagnostics and NAT detection need. Pings and reflections are signed by
+//! their sender, and each peer may only ping so often, so an echo node cannot
+//! be used to bounce traffic at a third party.
+
+use crate::types::{MessageType, SimpleMessage};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// Metadata key marking a message as an echo probe (the probe kind)
+pub const ECHO_KEY: &str = "echo";
+
+/// Metadata key the receive path sets to the address a message arrived from
+pub const RECEIVED_FROM_KEY: &str = "received_from";
+
+/// Metadata key the receive path sets to the transport a message arrived over
+pub const RECEIVED_VIA_KEY: &str = "received_via";
+
+/// What an echo node saw of a ping; times are Unix milliseconds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EchoReflection {
+    pub nonce: u64,
+    /// The pinger's `sent_at_ms`, echoed back
+    pub request_sent_at_ms: i64,
+    /// Source address of the ping, when its transport has one
+    pub observed_source: Option<String>,
+    /// Transport the ping arrived over
+    pub transport: Option<String>,
+    /// When the ping arrived, by the echo node's clock
+    pub received_at_ms: i64,
+    /// When the reflection was sent, by the echo node's clock
+    pub sent_at_ms: i64,
+}
+
+/// A signed ping or the echo node's signed answer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EchoProbe {
+    Ping {
+        nonce: u64,
+        sent_at_ms: i64,
+        #[serde(default)]
+        signature: Vec<u8>,
+    },
+    Reflection {
+        reflection: EchoReflection,
+        #[serde(default)]
+        signature: Vec<u8>,
+    },
+}
+
+impl EchoProbe {
+    /// An unsigned ping; sign it with [`Self::signing_payload`]
+    pub fn ping(nonce: u64) -> Self {
+        EchoProbe::Ping { nonce, sent_at_ms: Utc::now().timestamp_millis(), signature: Vec::new() }
+    }
+
+    /// The unsigned reflection of this ping, seen arriving at `received_at_ms`
+    pub fn reflect(&self, observed_source: Option<String>, transport: Option<String>, received_at_ms: i64) -> Option<Self> {
+        match *self {
+            EchoProbe::Ping { nonce, sent_at_ms, .. } => Some(EchoProbe::Reflection {
+                reflection: EchoReflection {
+                    nonce,
+                    request_sent_at_ms: sent_at_ms,
+                    observed_source,
+                    transport,
+                    received_at_ms,
+                    sent_at_ms: Utc::now().timestamp_millis(),
+                },
+                signature: Vec::new(),
+            }),
+            EchoProbe::Reflection { .. } => None,
+        }
+    }
+
+    /// Name used in the message metadata
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EchoProbe::Ping { .. } => "ping",
+            EchoProbe::Reflection { .. } => "reflection",
+        }
+    }
+
+    /// What the sender signs; binds the probe to both participants
+    pub fn signing_payload(&self, from_entity: &str, to_entity: &str) -> String {
+        match self {
+            EchoProbe::Ping { nonce, sent_at_ms, .. } => {
+                format!("synapse-echo-ping:{}:{}:{}:{}", from_entity, to_entity, nonce, sent_at_ms)
+            }
+            EchoProbe::Reflection { reflection: r, .. } => format!(
+                "synapse-echo-reflection:{}:{}:{}:{}:{}:{}:{}:{}",
+                from_entity,
+                to_entity,
+                r.nonce,
+                r.request_sent_at_ms,
+                r.observed_source.as_deref().unwrap_or(""),
+                r.transport.as_deref().unwrap_or(""),
+                r.received_at_ms,
+                r.sent_at_ms
+            ),
+        }
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        match self {
+            EchoProbe::Ping { signature, .. } | EchoProbe::Reflection { signature, .. } => signature,
+        }
+    }
+
+    pub fn with_signature(mut self, value: Vec<u8>) -> Self {
+        match &mut self {
+            EchoProbe::Ping { signature, .. } | EchoProbe::Reflection { signature, .. } => *signature = value,
+        }
+        self
+    }
+
+    /// Wrap the probe in a system message from `from_entity` to `to_entity`
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> crate::error::Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(ECHO_KEY.to_string(), self.kind().to_string());
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: serde_json::to_string(self)?,
+            message_type: MessageType::System,
+            metadata,
+        })
+    }
+
+    /// Extract the probe carried by `message`, if any
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        if message.message_type != MessageType::System || !message.metadata.contains_key(ECHO_KEY) {
+            return None;
+        }
+        serde_json::from_str(&message.content).ok()
+    }
+}
+
+/// What a round trip through an echo node showed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EchoResult {
+    /// The echo node
+    pub peer: String,
+    /// Our address as the echo node saw it
+    pub observed_source: Option<String>,
+    /// Transport our ping arrived over
+    pub transport: Option<String>,
+    /// Network round trip, excluding the echo node's processing time
+    pub round_trip_ms: u64,
+    pub taken_at: DateTime<Utc>,
+}
+
+impl EchoResult {
+    /// Result of `reflection` from `peer`, received at `received_at_ms`
+    pub fn from_reflection(peer: &str, reflection: &EchoReflection, received_at_ms: i64) -> Self {
+        let processing = reflection.sent_at_ms - reflection.received_at_ms;
+        let round_trip_ms = ((received_at_ms - reflection.request_sent_at_ms) - processing).max(0) as u64;
+        Self {
+            peer: peer.to_string(),
+            observed_source: reflection.observed_source.clone(),
+            transport: reflection.transport.clone(),
+            round_trip_ms,
+            taken_at: Utc::now(),
+        }
+    }
+}
+
+/// Pings awaiting a reflection, and the per-peer limit on pings we answer
+pub struct EchoService {
+    /// Nonce -> echo node the ping went to and who is waiting for the answer
+    pending: DashMap<u64, (String, oneshot::Sender<EchoResult>)>,
+    next_nonce: AtomicU64,
+    /// Start of each pinger's current minute and pings answered in it
+    answered: DashMap<String, (Instant, u32)>,
+    max_pings_per_minute: u32,
+}
+
+impl EchoService {
+    /// Answer at most `max_pings_per_minute` pings from each peer
+    pub fn new(max_pings_per_minute: u32) -> Self {
+        Self {
+            pending: DashMap::new(),
+            next_nonce: AtomicU64::new(rand::random()),
+            answered: DashMap::new(),
+            max_pings_per_minute,
+        }
+    }
+
+    /// Register a ping to `peer`, returning its nonce and the eventual result
+    pub fn start_ping(&self, peer: &str) -> (u64, oneshot::Receiver<EchoResult>) {
+        let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.insert(nonce, (peer.to_string(), sender));
+        (nonce, receiver)
+    }
+
+    /// Drop a ping that will not be waited for any more
+    pub fn cancel(&self, nonce: u64) {
+        self.pending.remove(&nonce);
+    }
+
+    /// Apply `from`'s reflection, received at `received_at_ms`
+    ///
+    /// Returns `None` if it does not answer a ping we sent to `from`.
+    pub fn complete(&self, from: &str, reflection: &EchoReflection, received_at_ms: i64) -> Option<EchoResult> {
+        let (_, (_, waiter)) = self.pending.remove_if(&reflection.nonce, |_, (peer, _)| peer == from)?;
+        let result = EchoResult::from_reflection(from, reflection, received_at_ms);
+        // The pinger may have timed out in the meantime
+        let _ = waiter.send(result.clone());
+        Some(result)
+    }
+
+    /// Whether another ping from `peer` may be answered now
+    pub fn admit(&self, peer: &str) -> bool {
+        let now = Instant::now();
+        let mut entry = self.answered.entry(peer.to_string()).or_insert((now, 0));
+        let (started, count) = entry.value_mut();
+        if now.duration_since(*started) >= Duration::from_secs(60) {
+            *started = now;
+            *count = 0;
+        }
+        if *count >= self.max_pings_per_minute {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reflections_answer_pings_and_pingers_are_limited() {
+        let service = EchoService::new(2);
+        let (nonce, _answer) = service.start_ping("echo@relay.example.com");
+        let ping = EchoProbe::ping(nonce);
+        let message = ping.to_message("alice@example.com", "echo@relay.example.com").unwrap();
+        assert_eq!(EchoProbe::from_message(&message), Some(ping.clone()));
+
+        let reflection = ping
+            .reflect(Some("203.0.113.7:40212".to_string()), Some("tcp".to_string()), Utc::now().timestamp_millis())
+            .unwrap();
+        assert!(reflection.reflect(None, None, 0).is_none());
+        assert_ne!(
+            reflection.signing_payload("echo@relay.example.com", "alice@example.com"),
+            ping.signing_payload("echo@relay.example.com", "alice@example.com")
+        );
+        let EchoProbe::Reflection { reflection, .. } = reflection else { unreachable!() };
+
+        // Only the node the ping went to can complete it, and only once
+        assert!(service.complete("mallory@example.com", &reflection, Utc::now().timestamp_millis()).is_none());
+        let result = service.complete("echo@relay.example.com", &reflection, Utc::now().timestamp_millis()).unwrap();
+        assert_eq!(result.observed_source.as_deref(), Some("203.0.113.7:40212"));
+        assert!(service.complete("echo@relay.example.com", &reflection, Utc::now().timestamp_millis()).is_none());
+
+        assert!(service.admit("alice@example.com"));
+        assert!(service.admit("alice@example.com"));
+        assert!(!service.admit("alice@example.com"));
+        assert!(service.admit("bob@example.com"));
+    }
+}
@@ -39,7 +39,7 @@ mod email_enhanced_impl {
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct RelayCredentials {
         pub username: String,
-        pub password: String,
+        pub password: crate::redaction::Secret<String>,
         pub auth_method: String,
     }
 
@@ -113,6 +113,7 @@ mod email_enhanced_impl {
                 security_level: crate::types::SecurityLevel::Public,
                 routing_path: vec![],
                 metadata: std::collections::HashMap::new(),
+                attachments: Vec::new(),
             };
             
             // Send via standard email with special headers
@@ -392,6 +393,7 @@ mod email_enhanced_impl {
                 cost: 1.0,
                 available: true,
                 confidence: 0.8,
+                region: None,
             })
         }
 
@@ -78,7 +78,7 @@ impl EmailTransportImpl {
                 host: smtp_server,
                 port: smtp_port,
                 username: username.clone(),
-                password: password.clone(),
+                password: password.clone().into(),
                 use_tls: true,
                 use_ssl: false,
             },
@@ -86,7 +86,7 @@ impl EmailTransportImpl {
                 host: imap_server,
                 port: imap_port,
                 username,
-                password,
+                password: password.into(),
                 use_ssl: true,
             },
         };
@@ -255,6 +255,7 @@ impl Transport for EmailTransportImpl {
             cost: 1.0, // Low cost
             available: true, // Assume available if configured
             confidence: 0.8, // Medium confidence in estimates
+            region: None,
         })
     }
     
@@ -0,0 +1,513 @@
+//! Fast email relay protocol
+//!
+//! Plain email reaches a Synapse peer only when the peer next polls its
+//! mailbox, which is minutes on most providers. A fast relay cuts this to
+//! seconds: senders hand the message to the recipient's relay directly, the
+//! relay holds it in its [`RelayServer`](crate::relay::RelayServer) store and
+//! wakes the recipient at once, and the recipient collects it over an
+//! authenticated session.
+//!
+//! Senders learn a peer's relays from its capability digest and hand off in
+//! one of two ways:
+//!
+//! - **HTTP**: a [`RelayRequest::Handoff`] POSTed as JSON to
+//!   `{handoff_url}`[`RELAY_API_PATH`], answered with the relay's receipt.
+//! - **SMTP submission**: an email to the recipient at the relay's SMTP
+//!   endpoint, marked with [`HANDOFF_HEADER`] and carrying the message as
+//!   base64 JSON. Used when the HTTP API is unreachable; there is no receipt.
+//!
+//! Recipients collect through the same HTTP API: request a challenge, sign it,
+//! open a session, fetch and acknowledge.
+
+use crate::{error::Result, types::SecureMessage};
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[cfg(feature = "http")]
+use crate::config::EmailRelayEndpoint;
+#[cfg(any(feature = "crypto", feature = "http"))]
+use crate::error::SynapseError;
+#[cfg(feature = "crypto")]
+use crate::relay::RelayServer;
+#[cfg(feature = "crypto")]
+use std::sync::Arc;
+#[cfg(feature = "crypto")]
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+#[cfg(feature = "crypto")]
+use tracing::{debug, info, warn};
+
+/// Path of the relay's HTTP API, appended to its `handoff_url`
+pub const RELAY_API_PATH: &str = "/synapse/relay/v1";
+
+/// Header marking an SMTP submission as a relay handoff
+pub const HANDOFF_HEADER: &str = "X-Synapse-Relay-Handoff";
+
+/// A call to the relay's HTTP API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum RelayRequest {
+    /// Hold a message for one of the relay's peers and wake them
+    Handoff { message: SecureMessage },
+    /// Ask for a challenge to sign before collecting
+    Challenge { global_id: String },
+    /// Open a delivery session with the signed challenge
+    Session { global_id: String, signature: Vec<u8> },
+    /// Messages held for the session's peer
+    Fetch { token: String },
+    /// Delete messages the peer has safely received
+    Acknowledge { token: String, ids: Vec<Uuid> },
+}
+
+/// The relay's answer to a [`RelayRequest`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum RelayResponse {
+    Accepted { receipt: HandoffReceipt },
+    Challenge { challenge: String },
+    Session { token: String, expires_at: DateTime<Utc> },
+    Messages { messages: Vec<RelayedEnvelope> },
+    Acknowledged { removed: u64 },
+    Error { reason: String },
+}
+
+/// Confirmation that a relay holds a handed-off message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandoffReceipt {
+    /// The relay's global ID
+    pub relay: String,
+    /// ID the relay holds the message under; SMTP submissions do not report one
+    pub stored_id: Option<Uuid>,
+    pub accepted_at: DateTime<Utc>,
+}
+
+/// A held message as handed to its recipient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayedEnvelope {
+    /// ID to acknowledge once the message is safely received
+    pub id: Uuid,
+    pub stored_at: DateTime<Utc>,
+    pub message: SecureMessage,
+}
+
+/// The SMTP submission handing `message` to a relay
+pub fn submission_email(message: &SecureMessage) -> Result<String> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(serde_json::to_vec(message)?);
+    let mut email = format!(
+        "From: <{}>\r\nTo: <{}>\r\nSubject: Synapse relay handoff\r\n{}: 1\r\nContent-Type: application/json\r\nContent-Transfer-Encoding: base64\r\n\r\n",
+        message.from_global_id, message.to_global_id, HANDOFF_HEADER
+    );
+    // SMTP lines are limited to 998 characters
+    for line in encoded.as_bytes().chunks(76) {
+        email.push_str(std::str::from_utf8(line).unwrap_or_default());
+        email.push_str("\r\n");
+    }
+    Ok(email)
+}
+
+/// The message carried by a relay handoff submission, or `None` for other mail
+pub fn parse_submission(raw: &[u8]) -> Option<SecureMessage> {
+    let raw = std::str::from_utf8(raw).ok()?;
+    let (headers, body) = raw.split_once("\r\n\r\n").or_else(|| raw.split_once("\n\n"))?;
+    let marked = headers.lines().any(|line| {
+        line.split_once(':')
+            .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case(HANDOFF_HEADER))
+    });
+    if !marked {
+        return None;
+    }
+    let encoded: String = body.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let json = base64::engine::general_purpose::STANDARD.decode(encoded).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// Relay side: answers the HTTP API and SMTP submissions for a [`RelayServer`]
+#[cfg(feature = "crypto")]
+pub struct FastRelayEndpoint {
+    relay_id: String,
+    server: Arc<RelayServer>,
+    /// Largest request body read; JSON byte arrays take up to four characters per byte
+    max_body_bytes: usize,
+}
+
+#[cfg(feature = "crypto")]
+impl FastRelayEndpoint {
+    /// Serve `server` as the relay `relay_id`, reading requests of at most `max_message_bytes` payload
+    pub fn new(relay_id: impl Into<String>, server: Arc<RelayServer>, max_message_bytes: u64) -> Self {
+        Self {
+            relay_id: relay_id.into(),
+            server,
+            max_body_bytes: (max_message_bytes as usize).saturating_mul(4).saturating_add(64 * 1024),
+        }
+    }
+
+    /// Whether `global_id` is registered with the relay, so mail to it is accepted
+    pub fn serves(&self, global_id: &str) -> bool {
+        self.server.peer(global_id).is_some()
+    }
+
+    /// Hold a handed-off message; the relay wakes the recipient if it is dormant
+    pub async fn handoff(&self, message: &SecureMessage) -> Result<HandoffReceipt> {
+        let stored_id = self.server.accept(message).await?;
+        info!("Relay accepted handoff {} for {}", stored_id, message.to_global_id);
+        Ok(HandoffReceipt {
+            relay: self.relay_id.clone(),
+            stored_id: Some(stored_id),
+            accepted_at: Utc::now(),
+        })
+    }
+
+    /// Hold the message carried by an SMTP submission
+    ///
+    /// Returns `Ok(None)` for mail that is not a relay handoff, which the SMTP
+    /// server then handles as usual.
+    pub async fn accept_submission(&self, raw: &[u8]) -> Result<Option<HandoffReceipt>> {
+        match parse_submission(raw) {
+            Some(message) => self.handoff(&message).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Answer an API call
+    pub async fn handle(&self, request: RelayRequest) -> Result<RelayResponse> {
+        Ok(match request {
+            RelayRequest::Handoff { message } => RelayResponse::Accepted { receipt: self.handoff(&message).await? },
+            RelayRequest::Challenge { global_id } => RelayResponse::Challenge { challenge: self.server.challenge(&global_id)? },
+            RelayRequest::Session { global_id, signature } => {
+                let session = self.server.authenticate(&global_id, &signature)?;
+                RelayResponse::Session { token: session.token, expires_at: session.expires_at }
+            }
+            RelayRequest::Fetch { token } => RelayResponse::Messages {
+                messages: self
+                    .server
+                    .fetch(&token)
+                    .await?
+                    .into_iter()
+                    .map(|held| RelayedEnvelope { id: held.id, stored_at: held.stored_at, message: held.message })
+                    .collect(),
+            },
+            RelayRequest::Acknowledge { token, ids } => RelayResponse::Acknowledged {
+                removed: self.server.acknowledge(&token, &ids).await?,
+            },
+        })
+    }
+
+    /// Answer a JSON request body with an HTTP status and JSON response body
+    pub async fn handle_json(&self, body: &[u8]) -> (u16, Vec<u8>) {
+        let (status, response) = match serde_json::from_slice::<RelayRequest>(body) {
+            Ok(request) => match self.handle(request).await {
+                Ok(response) => (200, response),
+                Err(e) => (status_for(&e), RelayResponse::Error { reason: e.to_string() }),
+            },
+            Err(e) => (400, RelayResponse::Error { reason: format!("Malformed relay request: {}", e) }),
+        };
+        (status, serde_json::to_vec(&response).unwrap_or_default())
+    }
+
+    /// Serve the HTTP API on `listener` until the handle is aborted
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, source)) => {
+                        let endpoint = Arc::clone(&self);
+                        tokio::spawn(async move {
+                            if let Err(e) = endpoint.serve_connection(stream).await {
+                                debug!("Relay API request from {} failed: {}", source, e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("Relay API listener failed to accept: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Read one HTTP/1.1 request and answer it; the connection is then closed
+    async fn serve_connection(&self, stream: TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+        let mut content_length = 0usize;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+
+        let (status, body) = if path != RELAY_API_PATH {
+            (404, Vec::new())
+        } else if method != "POST" {
+            (405, Vec::new())
+        } else if content_length > self.max_body_bytes {
+            (413, Vec::new())
+        } else {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await?;
+            self.handle_json(&body).await
+        };
+
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            reason_phrase(status),
+            body.len()
+        );
+        let stream = reader.get_mut();
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&body).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "crypto")]
+fn status_for(error: &SynapseError) -> u16 {
+    match error {
+        SynapseError::PeerNotFound(_) => 404,
+        SynapseError::AuthenticationError(_) => 401,
+        SynapseError::PolicyViolation { .. } => 403,
+        SynapseError::SerializationError(_) => 400,
+        _ => 500,
+    }
+}
+
+#[cfg(feature = "crypto")]
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Sender and recipient side: hands off to relays and collects from them
+#[cfg(feature = "http")]
+pub struct FastRelayClient {
+    http: reqwest::Client,
+}
+
+#[cfg(feature = "http")]
+impl FastRelayClient {
+    /// Client whose requests give up after `timeout`
+    pub fn new(timeout: std::time::Duration) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| SynapseError::ConfigurationError(format!("Failed to build relay client: {}", e)))?;
+        Ok(Self { http })
+    }
+
+    /// Hand `message` to `relay`, over HTTP if it can and SMTP submission otherwise
+    pub async fn handoff(&self, relay: &EmailRelayEndpoint, message: &SecureMessage) -> Result<HandoffReceipt> {
+        let mut last_error = None;
+        if relay.handoff_url.is_some() {
+            match self.call(relay, &RelayRequest::Handoff { message: message.clone() }).await {
+                Ok(RelayResponse::Accepted { receipt }) => return Ok(receipt),
+                Ok(other) => last_error = Some(unexpected(relay, &other)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if let Some(endpoint) = &relay.smtp_endpoint {
+            match submit(endpoint, message).await {
+                Ok(()) => {
+                    return Ok(HandoffReceipt {
+                        relay: relay.relay.clone(),
+                        stored_id: None,
+                        accepted_at: Utc::now(),
+                    });
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            SynapseError::ConfigurationError(format!("Relay {} advertises no handoff endpoint", relay.relay))
+        }))
+    }
+
+    /// Ask `relay` for a challenge to sign as `global_id`
+    pub async fn challenge(&self, relay: &EmailRelayEndpoint, global_id: &str) -> Result<String> {
+        match self.call(relay, &RelayRequest::Challenge { global_id: global_id.to_string() }).await? {
+            RelayResponse::Challenge { challenge } => Ok(challenge),
+            other => Err(unexpected(relay, &other)),
+        }
+    }
+
+    /// Open a delivery session with the signed challenge, returning its token
+    pub async fn open_session(&self, relay: &EmailRelayEndpoint, global_id: &str, signature: Vec<u8>) -> Result<String> {
+        let request = RelayRequest::Session { global_id: global_id.to_string(), signature };
+        match self.call(relay, &request).await? {
+            RelayResponse::Session { token, .. } => Ok(token),
+            other => Err(unexpected(relay, &other)),
+        }
+    }
+
+    /// Messages `relay` holds for the session's peer
+    pub async fn fetch(&self, relay: &EmailRelayEndpoint, token: &str) -> Result<Vec<RelayedEnvelope>> {
+        match self.call(relay, &RelayRequest::Fetch { token: token.to_string() }).await? {
+            RelayResponse::Messages { messages } => Ok(messages),
+            other => Err(unexpected(relay, &other)),
+        }
+    }
+
+    /// Let `relay` delete messages we have safely received
+    pub async fn acknowledge(&self, relay: &EmailRelayEndpoint, token: &str, ids: Vec<Uuid>) -> Result<u64> {
+        match self.call(relay, &RelayRequest::Acknowledge { token: token.to_string(), ids }).await? {
+            RelayResponse::Acknowledged { removed } => Ok(removed),
+            other => Err(unexpected(relay, &other)),
+        }
+    }
+
+    async fn call(&self, relay: &EmailRelayEndpoint, request: &RelayRequest) -> Result<RelayResponse> {
+        let base = relay.handoff_url.as_deref().ok_or_else(|| {
+            SynapseError::ConfigurationError(format!("Relay {} has no HTTP API", relay.relay))
+        })?;
+        let url = format!("{}{}", base.trim_end_matches('/'), RELAY_API_PATH);
+        let response = self
+            .http
+            .post(&url)
+            .json(request)
+            .send()
+            .await
+            .map_err(|e| SynapseError::NetworkError(format!("Relay request to {} failed: {}", url, e)))?;
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| SynapseError::NetworkError(format!("Relay response from {} was cut off: {}", url, e)))?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+#[cfg(feature = "http")]
+fn unexpected(relay: &EmailRelayEndpoint, response: &RelayResponse) -> SynapseError {
+    match response {
+        RelayResponse::Error { reason } => SynapseError::TransportError(format!("Relay {} refused: {}", relay.relay, reason)),
+        other => SynapseError::TransportError(format!("Relay {} sent an unexpected answer: {:?}", relay.relay, other)),
+    }
+}
+
+/// Submit the handoff email to the relay's SMTP endpoint
+#[cfg(all(feature = "http", feature = "email"))]
+async fn submit(endpoint: &str, message: &SecureMessage) -> Result<()> {
+    use lettre::{address::Envelope, SmtpTransport, Transport};
+
+    let (host, port) = endpoint
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host.to_string(), port.parse::<u16>().ok()?)))
+        .ok_or_else(|| SynapseError::InvalidFormat(format!("Relay SMTP endpoint {} is not host:port", endpoint)))?;
+    let envelope = Envelope::new(
+        Some(message.from_global_id.parse().map_err(|e| SynapseError::InvalidFormat(format!("Invalid sender address: {}", e)))?),
+        vec![message.to_global_id.parse().map_err(|e| SynapseError::InvalidFormat(format!("Invalid recipient address: {}", e)))?],
+    )
+    .map_err(|e| SynapseError::InvalidFormat(e.to_string()))?;
+    let email = submission_email(message)?;
+    tokio::task::spawn_blocking(move || {
+        SmtpTransport::builder_dangerous(host)
+            .port(port)
+            .build()
+            .send_raw(&envelope, email.as_bytes())
+            .map(|_| ())
+            .map_err(|e| SynapseError::SmtpConnection(e.to_string()))
+    })
+    .await
+    .map_err(|e| SynapseError::TransportError(format!("Relay submission task failed: {}", e)))?
+}
+
+#[cfg(all(feature = "http", not(feature = "email")))]
+async fn submit(endpoint: &str, _message: &SecureMessage) -> Result<()> {
+    Err(SynapseError::ConfigurationError(format!(
+        "Cannot submit to relay at {}: built without the email feature",
+        endpoint
+    )))
+}
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use super::*;
+    use crate::{
+        config::RelayConfig,
+        crypto::CryptoManager,
+        relay::MemoryRelayStore,
+        synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper},
+        types::SecurityLevel,
+    };
+
+    fn message(to: &str) -> SecureMessage {
+        SecureMessage {
+            message_id: UuidWrapper::new(Uuid::new_v4()),
+            to_global_id: to.to_string(),
+            from_global_id: "alice@example.com".to_string(),
+            encrypted_content: b"ciphertext".to_vec(),
+            signature: Vec::new(),
+            timestamp: DateTimeWrapper::new(Utc::now()),
+            security_level: SecurityLevel::Private,
+            routing_path: Vec::new(),
+            metadata: Default::default(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn handoffs_are_held_for_collection() {
+        let server = Arc::new(RelayServer::new(RelayConfig::default(), Arc::new(MemoryRelayStore::new())));
+        let mut phone = CryptoManager::new();
+        let (_, public_key) = phone.generate_keypair().unwrap();
+        server.register_peer("phone@example.com", &public_key, None).unwrap();
+        let endpoint = FastRelayEndpoint::new("relay@example.com", server, 1024 * 1024);
+
+        // Unregistered recipients are refused with a status the sender can act on
+        let body = serde_json::to_vec(&RelayRequest::Handoff { message: message("stranger@example.com") }).unwrap();
+        assert_eq!(endpoint.handle_json(&body).await.0, 404);
+        assert_eq!(endpoint.handle_json(b"not json").await.0, 400);
+
+        let body = serde_json::to_vec(&RelayRequest::Handoff { message: message("phone@example.com") }).unwrap();
+        let (status, response) = endpoint.handle_json(&body).await;
+        assert_eq!(status, 200);
+        assert!(matches!(serde_json::from_slice(&response).unwrap(), RelayResponse::Accepted { .. }));
+
+        // The same message submitted over SMTP; ordinary mail is left alone
+        let submission = submission_email(&message("phone@example.com")).unwrap();
+        assert!(submission.lines().all(|line| line.len() <= 998));
+        assert!(endpoint.accept_submission(submission.as_bytes()).await.unwrap().is_some());
+        assert!(endpoint.accept_submission(b"Subject: hi\r\n\r\nhello\r\n").await.unwrap().is_none());
+
+        let RelayResponse::Challenge { challenge } =
+            endpoint.handle(RelayRequest::Challenge { global_id: "phone@example.com".into() }).await.unwrap()
+        else {
+            panic!("expected a challenge");
+        };
+        let signature = phone.sign_message(&challenge).unwrap();
+        let RelayResponse::Session { token, .. } = endpoint
+            .handle(RelayRequest::Session { global_id: "phone@example.com".into(), signature })
+            .await
+            .unwrap()
+        else {
+            panic!("expected a session");
+        };
+        let RelayResponse::Messages { messages } = endpoint.handle(RelayRequest::Fetch { token }).await.unwrap() else {
+            panic!("expected messages");
+        };
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].message.encrypted_content, b"ciphertext");
+    }
+}
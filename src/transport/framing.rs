@@ -0,0 +1,37 @@
+//! Length-prefixed JSON framing for stream transports
+//!
+//! Each message is a big-endian `u32` length followed by the JSON-encoded
+//! [`SecureMessage`]. Used by overlay transports (Tor, I2P) whose streams
+//! carry exactly one message per connection.
+
+use crate::{
+    buffer_pool::BufferPool,
+    error::{Result, SynapseError},
+    types::SecureMessage,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Write a length-prefixed JSON message
+pub async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, message: &SecureMessage) -> Result<()> {
+    let mut payload = BufferPool::shared().take();
+    serde_json::to_writer(&mut *payload, message)?;
+    let len = u32::try_from(payload.len())
+        .map_err(|_| SynapseError::TransportError("Message too large for frame".to_string()))?;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(&payload).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Read a length-prefixed JSON message, refusing frames above `max_message_size`
+pub async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S, max_message_size: usize) -> Result<SecureMessage> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_message_size {
+        return Err(SynapseError::TransportError(format!("Frame of {} bytes exceeds limit", len)));
+    }
+    let mut payload = BufferPool::shared().take_zeroed(len);
+    stream.read_exact(&mut payload[..]).await?;
+    Ok(serde_json::from_slice(&payload)?)
+}
@@ -24,6 +24,7 @@ mod http_impl {
     };
     use tokio::sync::Mutex;
     use reqwest::{Client, ClientBuilder};
+    use crate::transport::proxy::ProxyEndpoint;
 
 /// HTTP/HTTPS Transport implementation
 #[cfg(feature = "http")]
@@ -61,6 +62,8 @@ pub struct HttpTransportConfig {
     pub max_connections: usize,
     /// User agent string
     pub user_agent: String,
+    /// Outbound proxy URL (socks5://, socks5h:// or http://)
+    pub proxy: Option<String>,
 }
 
 impl Default for HttpTransportConfig {
@@ -78,6 +81,7 @@ impl Default for HttpTransportConfig {
             headers,
             max_connections: 100,
             user_agent: "Synapse-HTTP-Transport/1.0".to_string(),
+            proxy: None,
         }
     }
 }
@@ -122,11 +126,25 @@ impl HttpTransportImpl {
             transport_config.user_agent = user_agent.clone();
         }
         
+        if let Some(proxy) = config.get("proxy").filter(|p| !p.is_empty()) {
+            transport_config.proxy = Some(proxy.clone());
+        }
+        
         // Build HTTP client
-        let client = ClientBuilder::new()
+        let mut builder = ClientBuilder::new()
             .timeout(transport_config.timeout)
             .pool_max_idle_per_host(transport_config.max_connections)
-            .user_agent(&transport_config.user_agent)
+            .user_agent(&transport_config.user_agent);
+        
+        if let Some(proxy_url) = &transport_config.proxy {
+            let endpoint = ProxyEndpoint::parse(proxy_url)?;
+            let proxy = reqwest::Proxy::all(endpoint.url())
+                .map_err(|e| SynapseError::TransportError(format!("Invalid HTTP proxy: {}", e)))?;
+            info!("HTTP transport using proxy {}", endpoint.display_url());
+            builder = builder.proxy(proxy);
+        }
+        
+        let client = builder
             .build()
             .map_err(|e| SynapseError::TransportError(format!("Failed to create HTTP client: {}", e)))?;
         
@@ -349,6 +367,7 @@ impl Transport for HttpTransportImpl {
             cost: 0.1, // Low cost
             available: true,
             confidence: 0.8,
+            region: None,
         })
     }
     
@@ -546,6 +565,11 @@ impl TransportFactory for HttpTransportFactory {
             }
         }
         
+        // Validate proxy URL
+        if let Some(proxy) = config.get("proxy").filter(|p| !p.is_empty()) {
+            ProxyEndpoint::parse(proxy)?;
+        }
+        
         Ok(())
     }
 }
@@ -0,0 +1,625 @@
+//! I2P streaming transport
+//!
+//! Talks to a local I2P router through the SAM v3 bridge (`127.0.0.1:7656` by
+//! default), publishes a persistent destination for incoming messages and
+//! opens I2P streams to the destinations peers advertise. This gives an
+//! anonymity overlay for regions where Tor is blocked.
+//!
+//! Peers exchange destinations through identity capabilities: a running node
+//! adds the `i2p` capability and an `i2p_destination` routing preference
+//! (its `.b32.i2p` address) to its [`GlobalIdentity`]. Messages use the same
+//! length-prefixed JSON framing as the Tor transport.
+
+use super::abstraction::*;
+use super::framing::{read_frame, write_frame};
+use crate::{
+    error::{Result, SynapseError},
+    types::{GlobalIdentity, SecureMessage},
+};
+use async_trait::async_trait;
+use base64::Engine as _;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::Mutex,
+};
+use tracing::{debug, info, warn};
+
+/// Capability advertised by nodes reachable over I2P
+pub const I2P_CAPABILITY: &str = "i2p";
+
+/// Routing preference key carrying a node's I2P address
+pub const I2P_DESTINATION_KEY: &str = "i2p_destination";
+
+/// Default SAM bridge address
+pub const DEFAULT_SAM_ADDRESS: &str = "127.0.0.1:7656";
+
+/// SAM protocol versions we speak
+const SAM_VERSION_RANGE: &str = "MIN=3.1 MAX=3.3";
+
+/// I2P base64 uses `-` and `~` in place of `+` and `/`
+const I2P_ALPHABET: base64::alphabet::Alphabet =
+    match base64::alphabet::Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-~") {
+        Ok(alphabet) => alphabet,
+        Err(_) => panic!("invalid I2P base64 alphabet"),
+    };
+const I2P_BASE64: base64::engine::GeneralPurpose =
+    base64::engine::GeneralPurpose::new(&I2P_ALPHABET, base64::engine::general_purpose::PAD);
+
+/// A parsed SAM reply line, e.g. `STREAM STATUS RESULT=OK`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamReply {
+    pub topic: String,
+    pub kind: String,
+    pub values: HashMap<String, String>,
+}
+
+impl SamReply {
+    /// Parse a reply line
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut parts = line.split_whitespace();
+        let (Some(topic), Some(kind)) = (parts.next(), parts.next()) else {
+            return Err(SynapseError::TransportError(format!("Malformed SAM reply: {}", line)));
+        };
+        let values = parts
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.trim_matches('"').to_string()))
+            .collect();
+        Ok(Self {
+            topic: topic.to_string(),
+            kind: kind.to_string(),
+            values,
+        })
+    }
+
+    /// Fail unless the reply is the expected one with `RESULT=OK`
+    pub fn expect_ok(self, topic: &str, kind: &str) -> Result<Self> {
+        if self.topic != topic || self.kind != kind {
+            return Err(SynapseError::TransportError(format!(
+                "Unexpected SAM reply {} {}, wanted {} {}",
+                self.topic, self.kind, topic, kind
+            )));
+        }
+        match self.values.get("RESULT").map(String::as_str) {
+            Some("OK") | None => Ok(self),
+            Some(result) => Err(SynapseError::TransportError(format!(
+                "SAM {} {} failed: {}{}",
+                topic,
+                kind,
+                result,
+                self.values.get("MESSAGE").map(|m| format!(" ({})", m)).unwrap_or_default()
+            ))),
+        }
+    }
+}
+
+/// `.b32.i2p` address of a base64 public destination
+pub fn b32_address(destination: &str) -> Result<String> {
+    let bytes = I2P_BASE64
+        .decode(destination)
+        .map_err(|e| SynapseError::TransportError(format!("Invalid I2P destination: {}", e)))?;
+    Ok(format!("{}.b32.i2p", base32_lower(&Sha256::digest(&bytes))))
+}
+
+/// RFC 4648 base32, lowercase, without padding
+fn base32_lower(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut output = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        output.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    output
+}
+
+/// Whether an address can be handed to SAM as a destination
+pub fn is_i2p_address(address: &str) -> bool {
+    let address = address.trim_start_matches("i2p://");
+    address.ends_with(".i2p") || (address.len() >= 516 && I2P_BASE64.decode(address).is_ok())
+}
+
+/// I2P address a peer advertised in its identity, if any
+pub fn advertised_i2p_address(identity: &GlobalIdentity) -> Option<String> {
+    if !identity.has_capability(I2P_CAPABILITY) {
+        return None;
+    }
+    identity
+        .routing_preferences
+        .get(I2P_DESTINATION_KEY)
+        .filter(|address| is_i2p_address(address))
+        .cloned()
+}
+
+/// Read one `\n`-terminated line without consuming stream data after it
+async fn read_line(stream: &mut TcpStream) -> Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte).await?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        if line.len() > 64 * 1024 {
+            return Err(SynapseError::TransportError("SAM line too long".to_string()));
+        }
+        line.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&line).trim_end_matches('\r').to_string())
+}
+
+/// Send a SAM command and parse its reply
+async fn command(stream: &mut TcpStream, command: &str) -> Result<SamReply> {
+    stream.write_all(format!("{}\n", command).as_bytes()).await?;
+    SamReply::parse(&read_line(stream).await?)
+}
+
+/// Open a SAM connection and complete the HELLO handshake
+async fn sam_connect(sam_address: &str) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect(sam_address)
+        .await
+        .map_err(|e| SynapseError::TransportError(format!("I2P SAM bridge at {} unavailable: {}", sam_address, e)))?;
+    command(&mut stream, &format!("HELLO VERSION {}", SAM_VERSION_RANGE))
+        .await?
+        .expect_ok("HELLO", "REPLY")?;
+    Ok(stream)
+}
+
+/// I2P transport configuration
+#[derive(Debug, Clone)]
+pub struct I2pTransportConfig {
+    /// SAM bridge address of the local I2P router
+    pub sam_address: String,
+    /// SAM session nickname (unique per router)
+    pub session_id: String,
+    /// File holding our private destination, so our address survives restarts
+    pub key_file: Option<PathBuf>,
+    /// Accept incoming streams
+    pub accept_incoming: bool,
+    /// Maximum accepted message size
+    pub max_message_size: usize,
+    /// Timeout for opening a stream and sending one message
+    pub send_timeout: Duration,
+}
+
+impl Default for I2pTransportConfig {
+    fn default() -> Self {
+        Self {
+            sam_address: DEFAULT_SAM_ADDRESS.to_string(),
+            session_id: format!("synapse-{}", std::process::id()),
+            key_file: None,
+            accept_incoming: true,
+            max_message_size: 4 * 1024 * 1024,
+            send_timeout: Duration::from_secs(90),
+        }
+    }
+}
+
+impl I2pTransportConfig {
+    fn from_map(config: &HashMap<String, String>) -> Self {
+        let mut i2p_config = Self::default();
+        if let Some(address) = config.get("sam_address") {
+            i2p_config.sam_address = address.clone();
+        }
+        if let Some(id) = config.get("session_id") {
+            i2p_config.session_id = id.clone();
+        }
+        if let Some(path) = config.get("key_file").filter(|p| !p.is_empty()) {
+            i2p_config.key_file = Some(PathBuf::from(path));
+        }
+        if let Some(accept) = config.get("accept_incoming") {
+            i2p_config.accept_incoming = accept == "true";
+        }
+        if let Some(size) = config.get("max_message_size").and_then(|s| s.parse().ok()) {
+            i2p_config.max_message_size = size;
+        }
+        if let Some(ms) = config.get("send_timeout_ms").and_then(|s| s.parse().ok()) {
+            i2p_config.send_timeout = Duration::from_millis(ms);
+        }
+        i2p_config
+    }
+}
+
+/// I2P transport implementation
+pub struct I2pTransportImpl {
+    config: I2pTransportConfig,
+    /// SAM control connection; the session lives as long as it stays open
+    session: Mutex<Option<TcpStream>>,
+    /// Our `.b32.i2p` address once the session exists
+    address: Arc<RwLock<Option<String>>>,
+    received_messages: Arc<Mutex<Vec<IncomingMessage>>>,
+    status: Arc<RwLock<TransportStatus>>,
+    metrics: Arc<RwLock<TransportMetrics>>,
+}
+
+impl I2pTransportImpl {
+    /// Create an I2P transport (the SAM session is opened by `start`)
+    pub async fn new(config: &HashMap<String, String>) -> Result<Self> {
+        let mut metrics = TransportMetrics::default();
+        metrics.transport_type = TransportType::I2p;
+
+        Ok(Self {
+            config: I2pTransportConfig::from_map(config),
+            session: Mutex::new(None),
+            address: Arc::new(RwLock::new(None)),
+            received_messages: Arc::new(Mutex::new(Vec::new())),
+            status: Arc::new(RwLock::new(TransportStatus::Stopped)),
+            metrics: Arc::new(RwLock::new(metrics)),
+        })
+    }
+
+    /// Our `.b32.i2p` address, once the session exists
+    pub fn address(&self) -> Option<String> {
+        self.address.read().unwrap().clone()
+    }
+
+    /// Advertise our I2P address in an identity for capability exchange
+    pub fn advertise(&self, identity: &mut GlobalIdentity) -> bool {
+        let Some(address) = self.address() else {
+            return false;
+        };
+        if !identity.has_capability(I2P_CAPABILITY) {
+            identity.add_capability(I2P_CAPABILITY);
+        }
+        identity.routing_preferences.insert(I2P_DESTINATION_KEY.to_string(), address);
+        true
+    }
+
+    /// Create the SAM streaming session, reusing a saved destination if present
+    async fn create_session(&self) -> Result<()> {
+        let saved_key = match &self.config.key_file {
+            Some(path) => tokio::fs::read_to_string(path).await.ok().map(|k| k.trim().to_string()),
+            None => None,
+        };
+        let destination = saved_key.as_deref().unwrap_or("TRANSIENT");
+
+        let mut session = sam_connect(&self.config.sam_address).await?;
+        let reply = command(
+            &mut session,
+            &format!(
+                "SESSION CREATE STYLE=STREAM ID={} DESTINATION={} SIGNATURE_TYPE=EdDSA_SHA512_Ed25519",
+                self.config.session_id, destination
+            ),
+        )
+        .await?
+        .expect_ok("SESSION", "STATUS")?;
+
+        if saved_key.is_none() {
+            if let (Some(path), Some(private)) = (&self.config.key_file, reply.values.get("DESTINATION")) {
+                if let Err(e) = tokio::fs::write(path, private).await {
+                    warn!("Failed to save I2P destination to {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        // Our public destination, hashed into the b32 address peers use
+        let lookup = command(&mut session, "NAMING LOOKUP NAME=ME").await?.expect_ok("NAMING", "REPLY")?;
+        let public = lookup
+            .values
+            .get("VALUE")
+            .ok_or_else(|| SynapseError::TransportError("SAM did not return our destination".to_string()))?;
+        let address = b32_address(public)?;
+        info!("🧄 I2P session {} ready at {}", self.config.session_id, address);
+
+        *self.address.write().unwrap() = Some(address);
+        *self.session.lock().await = Some(session);
+        Ok(())
+    }
+
+    /// Accept incoming streams until the session closes
+    fn spawn_acceptor(&self) {
+        let sam_address = self.config.sam_address.clone();
+        let session_id = self.config.session_id.clone();
+        let max_message_size = self.config.max_message_size;
+        let queue = Arc::clone(&self.received_messages);
+        let metrics = Arc::clone(&self.metrics);
+        let status = Arc::clone(&self.status);
+
+        tokio::spawn(async move {
+            while *status.read().unwrap() == TransportStatus::Running {
+                // SAM hands each accepted stream over on its own connection
+                let mut stream = match sam_connect(&sam_address).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("I2P accept failed: {}", e);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        continue;
+                    }
+                };
+                let accepted = match command(&mut stream, &format!("STREAM ACCEPT ID={} SILENT=false", session_id)).await {
+                    Ok(reply) => reply.expect_ok("STREAM", "STATUS"),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = accepted {
+                    warn!("I2P accept failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+
+                let queue = Arc::clone(&queue);
+                let metrics = Arc::clone(&metrics);
+                tokio::spawn(async move {
+                    // The first line names the peer's destination
+                    let source = match read_line(&mut stream).await {
+                        Ok(destination) => b32_address(destination.split_whitespace().next().unwrap_or_default())
+                            .unwrap_or_else(|_| "i2p".to_string()),
+                        Err(e) => {
+                            debug!("I2P stream closed before handshake: {}", e);
+                            return;
+                        }
+                    };
+                    match read_frame(&mut stream, max_message_size).await {
+                        Ok(message) => {
+                            debug!("Received message {} over I2P from {}", message.message_id, source);
+                            queue.lock().await.push(IncomingMessage::new(message, TransportType::I2p, source));
+                            metrics.write().unwrap().messages_received += 1;
+                        }
+                        Err(e) => {
+                            warn!("Invalid message on I2P stream from {}: {}", source, e);
+                            metrics.write().unwrap().receive_failures += 1;
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    fn parse_target_address(&self, target: &TransportTarget) -> Result<String> {
+        let address = target.address.as_deref().unwrap_or(&target.identifier);
+        let address = address.trim_start_matches("i2p://");
+        if is_i2p_address(address) {
+            Ok(address.to_string())
+        } else {
+            Err(SynapseError::TransportError(format!("Not an I2P destination: {}", address)))
+        }
+    }
+
+    async fn open_stream(&self, destination: &str) -> Result<TcpStream> {
+        if self.session.lock().await.is_none() {
+            return Err(SynapseError::TransportError("I2P session not started".to_string()));
+        }
+        let mut stream = sam_connect(&self.config.sam_address).await?;
+        command(
+            &mut stream,
+            &format!("STREAM CONNECT ID={} DESTINATION={} SILENT=false", self.config.session_id, destination),
+        )
+        .await?
+        .expect_ok("STREAM", "STATUS")?;
+        Ok(stream)
+    }
+
+    async fn send_to(&self, destination: &str, message: &SecureMessage) -> Result<Duration> {
+        let start = Instant::now();
+        let mut stream = self.open_stream(destination).await?;
+        write_frame(&mut stream, message).await?;
+        Ok(start.elapsed())
+    }
+}
+
+#[async_trait]
+impl Transport for I2pTransportImpl {
+    fn transport_type(&self) -> TransportType {
+        TransportType::I2p
+    }
+
+    fn capabilities(&self) -> TransportCapabilities {
+        let mut capabilities = TransportCapabilities::i2p();
+        capabilities.max_message_size = self.config.max_message_size;
+        capabilities
+    }
+
+    async fn can_reach(&self, target: &TransportTarget) -> bool {
+        // Opening a tunnel just to check is expensive; a destination is enough
+        self.parse_target_address(target).is_ok()
+    }
+
+    async fn estimate_metrics(&self, target: &TransportTarget) -> Result<TransportEstimate> {
+        let _address = self.parse_target_address(target)?;
+
+        // Garlic routing uses separate inbound and outbound tunnels
+        Ok(TransportEstimate {
+            latency: Duration::from_millis(2000),
+            reliability: 0.85,
+            bandwidth: 250_000,
+            cost: 2.0,
+            available: self.address().is_some(),
+            confidence: 0.5,
+            region: None,
+        })
+    }
+
+    async fn send_message(&self, target: &TransportTarget, message: &SecureMessage) -> Result<DeliveryReceipt> {
+        let destination = self.parse_target_address(target)?;
+
+        let result = tokio::time::timeout(self.config.send_timeout, self.send_to(&destination, message))
+            .await
+            .unwrap_or_else(|_| Err(SynapseError::TransportError(format!("I2P send to {} timed out", destination))));
+
+        let mut metrics = self.metrics.write().unwrap();
+        match result {
+            Ok(delivery_time) => {
+                metrics.messages_sent += 1;
+                metrics.average_latency_ms = delivery_time.as_millis() as u64;
+                Ok(DeliveryReceipt {
+                    message_id: message.message_id.to_string(),
+                    transport_used: TransportType::I2p,
+                    delivery_time,
+                    target_reached: destination,
+                    confirmation: DeliveryConfirmation::Delivered,
+                    metadata: HashMap::new(),
+                })
+            }
+            Err(e) => {
+                metrics.send_failures += 1;
+                Err(e)
+            }
+        }
+    }
+
+    async fn receive_messages(&self) -> Result<Vec<IncomingMessage>> {
+        let mut messages = self.received_messages.lock().await;
+        Ok(messages.drain(..).collect())
+    }
+
+    async fn test_connectivity(&self, target: &TransportTarget) -> Result<ConnectivityResult> {
+        let destination = self.parse_target_address(target)?;
+        let start = Instant::now();
+
+        let mut details = HashMap::new();
+        details.insert("target".to_string(), destination.clone());
+        let outcome = tokio::time::timeout(self.config.send_timeout, self.open_stream(&destination))
+            .await
+            .unwrap_or_else(|_| Err(SynapseError::TransportError("I2P connection timed out".to_string())));
+        match outcome {
+            Ok(_stream) => {
+                let rtt = start.elapsed();
+                details.insert("rtt_ms".to_string(), rtt.as_millis().to_string());
+                Ok(ConnectivityResult {
+                    connected: true,
+                    rtt: Some(rtt),
+                    error: None,
+                    quality: 0.5,
+                    details,
+                })
+            }
+            Err(e) => Ok(ConnectivityResult {
+                connected: false,
+                rtt: None,
+                error: Some(e.to_string()),
+                quality: 0.0,
+                details,
+            }),
+        }
+    }
+
+    async fn start(&self) -> Result<()> {
+        *self.status.write().unwrap() = TransportStatus::Starting;
+
+        if self.session.lock().await.is_none() {
+            if let Err(e) = self.create_session().await {
+                *self.status.write().unwrap() = TransportStatus::Failed;
+                return Err(e);
+            }
+        }
+
+        *self.status.write().unwrap() = TransportStatus::Running;
+        if self.config.accept_incoming {
+            self.spawn_acceptor();
+        }
+        info!("I2P transport running");
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        *self.status.write().unwrap() = TransportStatus::Stopping;
+
+        // Closing the control connection ends the SAM session
+        self.session.lock().await.take();
+        *self.address.write().unwrap() = None;
+
+        *self.status.write().unwrap() = TransportStatus::Stopped;
+        info!("I2P transport stopped");
+        Ok(())
+    }
+
+    async fn status(&self) -> TransportStatus {
+        *self.status.read().unwrap()
+    }
+
+    async fn metrics(&self) -> TransportMetrics {
+        self.metrics.read().unwrap().clone()
+    }
+}
+
+/// Factory for creating I2P transport instances
+pub struct I2pTransportFactory;
+
+#[async_trait]
+impl TransportFactory for I2pTransportFactory {
+    async fn create_transport(&self, config: &HashMap<String, String>) -> Result<Box<dyn Transport>> {
+        let transport = I2pTransportImpl::new(config).await?;
+        Ok(Box::new(transport))
+    }
+
+    fn transport_type(&self) -> TransportType {
+        TransportType::I2p
+    }
+
+    fn default_config(&self) -> HashMap<String, String> {
+        let mut config = HashMap::new();
+        config.insert("sam_address".to_string(), DEFAULT_SAM_ADDRESS.to_string());
+        config.insert("accept_incoming".to_string(), "true".to_string());
+        config.insert("max_message_size".to_string(), (4 * 1024 * 1024).to_string());
+        config
+    }
+
+    fn validate_config(&self, config: &HashMap<String, String>) -> Result<()> {
+        if let Some(address) = config.get("sam_address") {
+            let valid = address.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+            if !valid {
+                return Err(SynapseError::TransportError(format!("Invalid sam_address: {}", address)));
+            }
+        }
+        if let Some(id) = config.get("session_id") {
+            if id.is_empty() || id.contains(char::is_whitespace) {
+                return Err(SynapseError::TransportError(format!("Invalid session_id: {}", id)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EntityType;
+
+    #[test]
+    fn test_parse_sam_replies() {
+        let reply = SamReply::parse("HELLO REPLY RESULT=OK VERSION=3.3").unwrap();
+        assert_eq!(reply.values.get("VERSION").unwrap(), "3.3");
+        assert!(reply.expect_ok("HELLO", "REPLY").is_ok());
+
+        let failed = SamReply::parse("STREAM STATUS RESULT=CANT_REACH_PEER MESSAGE=\"unreachable\"").unwrap();
+        let err = failed.expect_ok("STREAM", "STATUS").unwrap_err().to_string();
+        assert!(err.contains("CANT_REACH_PEER"));
+
+        assert!(SamReply::parse("HELLO").is_err());
+    }
+
+    #[test]
+    fn test_base32_matches_rfc4648() {
+        assert_eq!(base32_lower(b""), "");
+        assert_eq!(base32_lower(b"f"), "my");
+        assert_eq!(base32_lower(b"foobar"), "mzxw6ytboi");
+        // A SHA-256 digest always yields the 52-character b32 label
+        assert_eq!(base32_lower(&Sha256::digest(b"destination")).len(), 52);
+    }
+
+    #[test]
+    fn test_i2p_address_from_identity() {
+        let address = format!("{}.b32.i2p", base32_lower(&Sha256::digest(b"bob")));
+        let mut identity = GlobalIdentity::new("Bob", "bob@example.com", EntityType::Human, "");
+        identity.routing_preferences.insert(I2P_DESTINATION_KEY.to_string(), address.clone());
+        assert!(advertised_i2p_address(&identity).is_none());
+
+        identity.add_capability(I2P_CAPABILITY);
+        assert_eq!(advertised_i2p_address(&identity), Some(address));
+        assert!(!is_i2p_address("bob.example.com"));
+    }
+}
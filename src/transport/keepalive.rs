@@ -0,0 +1,195 @@
+//! Keepalives for NAT-traversed routes
+//!
+//! UDP mappings in most NATs expire after about 30 seconds without traffic,
+//! after which a hole-punched route silently stops delivering. The transport
+//! manager tracks each route it sends over through a NAT-traversing transport
+//! and, while the route is in use, has the transport send a tiny keepalive
+//! whenever the route has been quiet for the keepalive interval. Routes with
+//! no real traffic for the idle timeout are dropped, so bindings of peers we
+//! stopped talking to are allowed to expire.
+
+use super::abstraction::{Transport, TransportTarget, TransportType};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// Capability feature marking a transport whose routes cross NATs
+pub const NAT_TRAVERSAL_FEATURE: &str = "nat_traversal";
+
+/// Keepalive timing for a route
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeepaliveSettings {
+    /// Quiet time after which a keepalive is sent
+    pub interval: Duration,
+    /// Time without real traffic after which keepalives stop
+    pub idle_timeout: Duration,
+}
+
+impl Default for KeepaliveSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(25),
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+struct RouteKeepalive {
+    transport: Arc<dyn Transport>,
+    target: TransportTarget,
+    settings: KeepaliveSettings,
+    /// Last real message sent or received
+    last_activity: Instant,
+    /// Last packet of any kind sent, keepalives included
+    last_sent: Instant,
+}
+
+/// Routes kept alive, keyed by transport and target identifier
+pub struct KeepaliveScheduler {
+    defaults: KeepaliveSettings,
+    routes: DashMap<(TransportType, String), RouteKeepalive>,
+    /// Per-route settings; `None` turns keepalives off for the route
+    overrides: DashMap<(TransportType, String), Option<KeepaliveSettings>>,
+}
+
+impl KeepaliveScheduler {
+    pub fn new(defaults: KeepaliveSettings) -> Self {
+        Self {
+            defaults,
+            routes: DashMap::new(),
+            overrides: DashMap::new(),
+        }
+    }
+
+    /// Use `settings` for the route to `target` over `transport_type`, or turn
+    /// its keepalives off with `None`
+    pub fn configure_route(&self, transport_type: TransportType, target: &str, settings: Option<KeepaliveSettings>) {
+        let key = (transport_type, target.to_string());
+        match settings {
+            Some(settings) => {
+                if let Some(mut route) = self.routes.get_mut(&key) {
+                    route.settings = settings;
+                }
+            }
+            None => {
+                self.routes.remove(&key);
+            }
+        }
+        self.overrides.insert(key, settings);
+    }
+
+    /// Record a message sent over `transport` to `target`, keeping the route alive
+    pub fn track(&self, transport: Arc<dyn Transport>, target: &TransportTarget) {
+        let key = (transport.transport_type(), target.identifier.clone());
+        let settings = match self.overrides.get(&key).map(|entry| *entry.value()) {
+            Some(None) => return,
+            Some(Some(settings)) => settings,
+            None => self.defaults,
+        };
+        let now = Instant::now();
+        self.routes
+            .entry(key)
+            .and_modify(|route| {
+                route.last_activity = now;
+                route.last_sent = now;
+            })
+            .or_insert_with(|| RouteKeepalive {
+                transport,
+                target: target.clone(),
+                settings,
+                last_activity: now,
+                last_sent: now,
+            });
+    }
+
+    /// Record a message received over a tracked route
+    pub fn touch(&self, transport_type: TransportType, target: &str) {
+        if let Some(mut route) = self.routes.get_mut(&(transport_type, target.to_string())) {
+            route.last_activity = Instant::now();
+        }
+    }
+
+    /// Routes needing a keepalive at `now`, marked as sent; idle routes are dropped
+    pub fn due(&self, now: Instant) -> Vec<(Arc<dyn Transport>, TransportTarget)> {
+        self.routes.retain(|(transport_type, target), route| {
+            let active = now.saturating_duration_since(route.last_activity) < route.settings.idle_timeout;
+            if !active {
+                debug!("Route to {} over {} went idle; stopping keepalives", target, transport_type);
+            }
+            active
+        });
+        let mut due = Vec::new();
+        for mut route in self.routes.iter_mut() {
+            if now.saturating_duration_since(route.last_sent) >= route.settings.interval {
+                route.last_sent = now;
+                due.push((Arc::clone(&route.transport), route.target.clone()));
+            }
+        }
+        due
+    }
+
+    /// Routes currently kept alive
+    pub fn active_routes(&self) -> Vec<(TransportType, String)> {
+        self.routes.iter().map(|entry| entry.key().clone()).collect()
+    }
+
+    /// Stop keeping routes over `transport_type` alive, e.g. when it is removed
+    pub fn forget_transport(&self, transport_type: TransportType) {
+        self.routes.retain(|(kind, _), _| *kind != transport_type);
+    }
+
+    /// Send due keepalives once a second until the handle is aborted
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(1));
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                for (transport, target) in self.due(Instant::now()) {
+                    if let Err(e) = transport.send_keepalive(&target).await {
+                        debug!("Keepalive to {} over {} failed: {}", target.identifier, transport.transport_type(), e);
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::providers::MockTransport;
+
+    #[test]
+    fn active_routes_get_keepalives_until_idle() {
+        let scheduler = KeepaliveScheduler::new(KeepaliveSettings {
+            interval: Duration::from_secs(25),
+            idle_timeout: Duration::from_secs(120),
+        });
+        let transport: Arc<dyn Transport> = Arc::new(MockTransport::new("nat"));
+        let kind = transport.transport_type();
+        scheduler.track(Arc::clone(&transport), &TransportTarget::new("alice".to_string()));
+        scheduler.track(Arc::clone(&transport), &TransportTarget::new("bob".to_string()));
+        scheduler.configure_route(kind, "bob", None);
+        scheduler.configure_route(kind, "carol", None);
+        scheduler.track(Arc::clone(&transport), &TransportTarget::new("carol".to_string()));
+
+        let start = Instant::now();
+        assert!(scheduler.due(start + Duration::from_secs(10)).is_empty());
+        let due = scheduler.due(start + Duration::from_secs(30));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].1.identifier, "alice");
+        // Sent at 30s, so not due again until 55s
+        assert!(scheduler.due(start + Duration::from_secs(40)).is_empty());
+        assert_eq!(scheduler.due(start + Duration::from_secs(60)).len(), 1);
+
+        // No real traffic for the idle timeout: keepalives stop
+        assert!(scheduler.due(start + Duration::from_secs(130)).is_empty());
+        assert!(scheduler.active_routes().is_empty());
+    }
+}
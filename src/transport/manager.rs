@@ -7,17 +7,22 @@
 use crate::{
     types::SecureMessage,
     error::Result,
-    circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, RequestOutcome},
+    circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitEvent, RequestOutcome},
+    events::{EventBus, RouterEvent},
 };
 use super::abstraction::*;
+use super::replay::{ReplayConfig, ReplayCache};
+use super::time_sync::PeerClocks;
+use super::keepalive::{KeepaliveScheduler, KeepaliveSettings, NAT_TRAVERSAL_FEATURE};
+use crate::geo::RegionAffinity;
 use std::{
     time::{Duration, Instant},
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, AtomicUsize, Ordering}},
     collections::HashMap,
 };
+use dashmap::DashMap;
 use serde::{Serialize, Deserialize};
 use tracing::{info, debug, warn};
-use tokio::sync::{Mutex, RwLock as TokioRwLock};
 
 /// Configuration for the TransportManager
 #[derive(Debug, Clone)]
@@ -31,11 +36,24 @@ pub struct TransportManagerConfig {
     /// Maximum time to wait for transport operations
     pub operation_timeout: Duration,
     /// How often to update transport metrics
+    ///
+    /// Totals are now derived whenever metrics are read, so this no longer
+    /// drives a background task; it is kept for configuration compatibility.
     pub metrics_update_interval: Duration,
     /// Transport-specific configurations
     pub transport_configs: HashMap<TransportType, HashMap<String, String>>,
     /// Circuit breaker configuration
     pub circuit_breaker_config: CircuitBreakerConfig,
+    /// File metrics are snapshotted to and restored from on start; `None` keeps them in memory only
+    pub metrics_snapshot_path: Option<String>,
+    /// How often to snapshot metrics
+    pub metrics_snapshot_interval: Duration,
+    /// Rejection of replayed or badly timestamped incoming messages
+    pub replay: ReplayConfig,
+    /// Region this node runs in; transports through nearby relays score higher
+    pub region: Option<String>,
+    /// Default keepalive timing for routes over NAT-traversing transports
+    pub keepalive: KeepaliveSettings,
 }
 
 impl Default for TransportManagerConfig {
@@ -61,6 +79,11 @@ impl Default for TransportManagerConfig {
                 half_open_max_calls: 3,
                 success_threshold: 0.6,
             },
+            metrics_snapshot_path: None,
+            metrics_snapshot_interval: Duration::from_secs(300),
+            replay: ReplayConfig::default(),
+            region: None,
+            keepalive: KeepaliveSettings::default(),
         }
     }
 }
@@ -80,6 +103,8 @@ pub enum TransportSelectionPolicy {
     RoundRobin,
     /// Prefer specific transport types
     PreferenceOrder,
+    /// Prefer anonymous transports (Tor), then encrypted ones
+    PrivacyFirst,
 }
 
 /// Failover configuration
@@ -120,6 +145,9 @@ pub struct SelectionWeights {
     pub bandwidth: f64,
     pub cost: f64,
     pub capability_match: f64,
+    /// Weight of the transport's region matching ours
+    #[serde(default)]
+    pub region: f64,
 }
 
 impl Default for SelectionWeights {
@@ -130,30 +158,46 @@ impl Default for SelectionWeights {
             bandwidth: 0.2,
             cost: 0.1,
             capability_match: 0.1,
+            region: 0.1,
         }
     }
 }
 
 /// Main TransportManager that provides unified transport abstraction
+///
+/// Every send touches the transport table, a circuit breaker and the metrics,
+/// so all of them live in sharded maps or atomics. Lookups clone the `Arc` out
+/// of the map and release the shard before awaiting, which keeps no lock held
+/// across a send and lets concurrent sends proceed without contending.
 pub struct TransportManager {
     /// Configuration
     config: TransportManagerConfig,
     /// Available transport instances
-    transports: TokioRwLock<HashMap<TransportType, Box<dyn Transport>>>,
+    transports: DashMap<TransportType, Arc<dyn Transport>>,
     /// Transport factories for creating new instances
-    factories: RwLock<HashMap<TransportType, Box<dyn TransportFactory>>>,
+    factories: DashMap<TransportType, Arc<dyn TransportFactory>>,
     /// Circuit breakers per transport
-    circuit_breakers: RwLock<HashMap<TransportType, Arc<CircuitBreaker>>>,
+    circuit_breakers: DashMap<TransportType, Arc<CircuitBreaker>>,
+    /// Capabilities declared at registration, used instead of what the transport reports
+    capability_overrides: DashMap<TransportType, TransportCapabilities>,
     /// Unified metrics
-    metrics: Arc<RwLock<UnifiedMetrics>>,
+    metrics: Arc<LiveMetrics>,
     /// Current transport status
-    transport_status: TokioRwLock<HashMap<TransportType, TransportStatus>>,
-    /// Selection weights for adaptive algorithm
+    transport_status: DashMap<TransportType, TransportStatus>,
+    /// Selection weights for adaptive algorithm; only ever held briefly, never across an await
     selection_weights: Arc<RwLock<SelectionWeights>>,
-    /// Last selection index for round-robin
-    round_robin_index: Arc<Mutex<usize>>,
+    /// Next selection index for round-robin
+    round_robin_index: AtomicUsize,
     /// Failed transports and their recovery times
-    failed_transports: TokioRwLock<HashMap<TransportType, Instant>>,
+    failed_transports: DashMap<TransportType, Instant>,
+    /// Receives transport up/down and circuit breaker events
+    events: EventBus,
+    /// Drops replayed incoming messages
+    replay_cache: ReplayCache,
+    /// Routes over NAT-traversing transports kept alive while in use
+    keepalives: Arc<KeepaliveScheduler>,
+    /// Task sending due keepalives while the manager runs
+    keepalive_task: Mutex<Option<tokio::task::JoinHandle<()>>>,
 }
 
 /// Unified metrics across all transports
@@ -175,32 +219,229 @@ pub struct UnifiedMetrics {
     pub last_updated_timestamp: u64,
 }
 
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 impl UnifiedMetrics {
     /// Update the last updated timestamp to now
     pub fn touch(&mut self) {
-        self.last_updated_timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        self.last_updated_timestamp = unix_now();
+    }
+
+    /// Recompute the overall reliability and average latency from the per-transport metrics
+    fn recompute_totals(&mut self) {
+        let total_attempts = self.total_messages_sent + self.total_failures;
+        if total_attempts > 0 {
+            self.overall_reliability = self.total_messages_sent as f64 / total_attempts as f64;
+        }
+
+        let active: Vec<u64> = self
+            .transport_metrics
+            .values()
+            .filter(|m| m.messages_sent > 0)
+            .map(|m| m.average_latency_ms)
+            .collect();
+        if !active.is_empty() {
+            self.average_latency = Duration::from_millis(active.iter().sum::<u64>() / active.len() as u64);
+        }
+    }
+}
+
+/// Live counterpart of [`UnifiedMetrics`], updated on every send
+///
+/// Per-transport entries sit in a sharded map so sends over different
+/// transports never contend, and the totals are atomics. Overall reliability
+/// and average latency are derived when a snapshot is taken instead of on the
+/// send path.
+#[derive(Debug, Default)]
+struct LiveMetrics {
+    transports: DashMap<TransportType, TransportMetrics>,
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    failures: AtomicU64,
+    last_updated: AtomicU64,
+}
+
+impl LiveMetrics {
+    fn record_send(&self, transport_type: TransportType, success: bool, latency: Duration) {
+        if success {
+            self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+
+        {
+            let mut transport_metrics = self.transports.entry(transport_type).or_insert_with(|| TransportMetrics {
+                transport_type,
+                ..Default::default()
+            });
+
+            if success {
+                transport_metrics.messages_sent += 1;
+                // Update running average latency
+                let total_messages = transport_metrics.messages_sent;
+                let old_avg_ms = transport_metrics.average_latency_ms as f64;
+                let new_latency_ms = latency.as_millis() as f64;
+                let new_avg_ms = (old_avg_ms * (total_messages - 1) as f64 + new_latency_ms) / total_messages as f64;
+                transport_metrics.average_latency_ms = new_avg_ms as u64;
+            } else {
+                transport_metrics.send_failures += 1;
+            }
+
+            // Update reliability score
+            let total_attempts = transport_metrics.messages_sent + transport_metrics.send_failures;
+            if total_attempts > 0 {
+                transport_metrics.reliability_score = transport_metrics.messages_sent as f64 / total_attempts as f64;
+            }
+
+            transport_metrics.touch();
+        }
+
+        self.last_updated.store(unix_now(), Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> UnifiedMetrics {
+        let mut metrics = UnifiedMetrics {
+            transport_metrics: self
+                .transports
+                .iter()
+                .map(|entry| (*entry.key(), entry.value().clone()))
+                .collect(),
+            total_messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            total_messages_received: self.messages_received.load(Ordering::Relaxed),
+            total_failures: self.failures.load(Ordering::Relaxed),
+            last_updated_timestamp: self.last_updated.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        metrics.recompute_totals();
+        metrics
+    }
+
+    fn restore(&self, metrics: UnifiedMetrics) {
+        self.transports.clear();
+        for (transport_type, transport_metrics) in metrics.transport_metrics {
+            self.transports.insert(transport_type, transport_metrics);
+        }
+        self.messages_sent.store(metrics.total_messages_sent, Ordering::Relaxed);
+        self.messages_received.store(metrics.total_messages_received, Ordering::Relaxed);
+        self.failures.store(metrics.total_failures, Ordering::Relaxed);
+        self.last_updated.store(metrics.last_updated_timestamp, Ordering::Relaxed);
+    }
+}
+
+/// On-disk form of [`UnifiedMetrics`]
+///
+/// Per-transport metrics are stored as a list because `TransportType::Custom`
+/// cannot be a JSON object key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub version: u32,
+    /// Unix timestamp the snapshot was taken
+    pub saved_at: u64,
+    pub transports: Vec<TransportMetrics>,
+    pub total_messages_sent: u64,
+    pub total_messages_received: u64,
+    pub total_failures: u64,
+}
+
+impl MetricsSnapshot {
+    pub const VERSION: u32 = 1;
+
+    pub fn from_metrics(metrics: &UnifiedMetrics) -> Self {
+        Self {
+            version: Self::VERSION,
+            saved_at: unix_now(),
+            transports: metrics.transport_metrics.values().cloned().collect(),
+            total_messages_sent: metrics.total_messages_sent,
+            total_messages_received: metrics.total_messages_received,
+            total_failures: metrics.total_failures,
+        }
+    }
+
+    pub fn into_metrics(self) -> UnifiedMetrics {
+        let mut metrics = UnifiedMetrics {
+            transport_metrics: self.transports.into_iter().map(|m| (m.transport_type, m)).collect(),
+            total_messages_sent: self.total_messages_sent,
+            total_messages_received: self.total_messages_received,
+            total_failures: self.total_failures,
+            last_updated_timestamp: self.saved_at,
+            ..Default::default()
+        };
+        metrics.recompute_totals();
+        metrics
+    }
+
+    /// Read a snapshot, returning `None` if the file does not exist
+    pub fn load(path: &str) -> Result<Option<Self>> {
+        if !std::path::Path::new(path).exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)?;
+        let snapshot: Self = serde_json::from_str(&content)?;
+        if snapshot.version != Self::VERSION {
+            return Err(crate::error::SynapseError::InvalidFormat(format!(
+                "Unsupported metrics snapshot version {} in {}",
+                snapshot.version, path
+            )));
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// Write the snapshot atomically
+    pub fn save(&self, path: &str) -> Result<()> {
+        let content = serde_json::to_string(self)?;
+        // Write-then-rename so a crash never leaves a truncated snapshot
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
     }
 }
 
 impl TransportManager {
     /// Create a new TransportManager
     pub fn new(config: TransportManagerConfig) -> Self {
+        let replay_cache = ReplayCache::new(config.replay.clone());
+        let keepalives = Arc::new(KeepaliveScheduler::new(config.keepalive));
         Self {
             config,
-            transports: TokioRwLock::new(HashMap::new()),
-            factories: RwLock::new(HashMap::new()),
-            circuit_breakers: RwLock::new(HashMap::new()),
-            metrics: Arc::new(RwLock::new(UnifiedMetrics::default())),
-            transport_status: TokioRwLock::new(HashMap::new()),
+            transports: DashMap::new(),
+            factories: DashMap::new(),
+            circuit_breakers: DashMap::new(),
+            capability_overrides: DashMap::new(),
+            metrics: Arc::new(LiveMetrics::default()),
+            transport_status: DashMap::new(),
             selection_weights: Arc::new(RwLock::new(SelectionWeights::default())),
-            round_robin_index: Arc::new(Mutex::new(0)),
-            failed_transports: TokioRwLock::new(HashMap::new()),
+            round_robin_index: AtomicUsize::new(0),
+            failed_transports: DashMap::new(),
+            events: EventBus::default(),
+            replay_cache,
+            keepalives,
+            keepalive_task: Mutex::new(None),
         }
     }
 
+    /// Publish transport lifecycle events to `events`, typically the router's bus
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Correct received timestamps by senders' measured clock offsets
+    pub fn with_peer_clocks(mut self, clocks: Arc<PeerClocks>) -> Self {
+        self.replay_cache = ReplayCache::new(self.config.replay.clone()).with_clocks(clocks);
+        self
+    }
+
+    /// Event bus transport lifecycle events are published to
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
     /// Register a transport factory
     pub async fn register_factory(&self, factory: Box<dyn TransportFactory>) -> Result<()> {
         let transport_type = factory.transport_type();
@@ -208,39 +449,142 @@ impl TransportManager {
         
         // Create circuit breaker for this transport
         let circuit_breaker = Arc::new(CircuitBreaker::new(self.config.circuit_breaker_config.clone()));
+
+        // Surface trips on the event bus
+        let mut breaker_events = circuit_breaker.subscribe_events();
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            loop {
+                match breaker_events.recv().await {
+                    Ok(CircuitEvent::Opened { reason, failure_count, .. }) => {
+                        events.publish(RouterEvent::CircuitOpened {
+                            transport: transport_type.to_string(),
+                            reason,
+                            failure_count,
+                        });
+                    }
+                    Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
         
-        {
-            let mut factories = self.factories.write().unwrap();
-            factories.insert(transport_type, factory);
-        }
+        self.factories.insert(transport_type, Arc::from(factory));
+        self.circuit_breakers.insert(transport_type, circuit_breaker);
+        self.transport_status.insert(transport_type, TransportStatus::Stopped);
         
-        {
-            let mut breakers = self.circuit_breakers.write().unwrap();
-            breakers.insert(transport_type, circuit_breaker);
+        Ok(())
+    }
+
+    /// Register a transport factory and start its transport straight away
+    ///
+    /// Unlike [`Self::register_factory`], which waits for [`Self::start`] and only
+    /// starts configured transports, this brings a transport up while the manager
+    /// is running. `capabilities`, when given, replace what the transport reports
+    /// for selection and capability negotiation.
+    pub async fn add_transport(
+        &self,
+        factory: Box<dyn TransportFactory>,
+        capabilities: Option<TransportCapabilities>,
+    ) -> Result<()> {
+        let transport_type = factory.transport_type();
+        if self.factories.contains_key(&transport_type) {
+            return Err(crate::error::SynapseError::AlreadyExists(
+                format!("Transport {} is already registered", transport_type)
+            ));
         }
-        
-        {
-            let mut status = self.transport_status.write().await;
-            status.insert(transport_type, TransportStatus::Stopped);
+        let config = self.config.transport_configs
+            .get(&transport_type)
+            .cloned()
+            .unwrap_or_else(|| factory.default_config());
+        factory.validate_config(&config)?;
+
+        self.register_factory(factory).await?;
+        if let Some(capabilities) = capabilities {
+            self.capability_overrides.insert(transport_type, capabilities);
+        }
+
+        if let Err(e) = self.start_transport(transport_type).await {
+            warn!("Failed to start transport {:?}: {}", transport_type, e);
+            self.events.publish(RouterEvent::TransportDown {
+                transport: transport_type.to_string(),
+                reason: e.to_string(),
+            });
+            self.forget_transport(transport_type).await;
+            return Err(e);
         }
-        
         Ok(())
     }
 
+    /// Stop a transport and drop its factory, circuit breaker and capabilities
+    pub async fn remove_transport(&self, transport_type: TransportType) -> Result<()> {
+        if !self.factories.contains_key(&transport_type) {
+            return Err(crate::error::SynapseError::NotFound(
+                format!("Transport {} is not registered", transport_type)
+            ));
+        }
+        self.stop_transport(transport_type).await?;
+        self.forget_transport(transport_type).await;
+        Ok(())
+    }
+
+    async fn forget_transport(&self, transport_type: TransportType) {
+        self.factories.remove(&transport_type);
+        self.circuit_breakers.remove(&transport_type);
+        self.capability_overrides.remove(&transport_type);
+        self.transport_status.remove(&transport_type);
+        self.failed_transports.remove(&transport_type);
+        self.keepalives.forget_transport(transport_type);
+    }
+
+    /// Running transport of a type, cloned out so no map shard stays locked across an await
+    fn transport(&self, transport_type: TransportType) -> Option<Arc<dyn Transport>> {
+        self.transports.get(&transport_type).map(|entry| Arc::clone(entry.value()))
+    }
+
+    /// All running transports, cloned out of the map
+    fn running_transports(&self) -> Vec<(TransportType, Arc<dyn Transport>)> {
+        self.transports
+            .iter()
+            .map(|entry| (*entry.key(), Arc::clone(entry.value())))
+            .collect()
+    }
+
+    fn circuit_breaker(&self, transport_type: TransportType) -> Option<Arc<CircuitBreaker>> {
+        self.circuit_breakers.get(&transport_type).map(|entry| Arc::clone(entry.value()))
+    }
+
     /// Initialize and start all enabled transports
     pub async fn start(&self) -> Result<()> {
         info!("Starting TransportManager with {} enabled transports", 
                self.config.enabled_transports.len());
+
+        // Restore reliability history from before the restart
+        match self.load_metrics_snapshot() {
+            Ok(true) => info!("Restored transport metrics snapshot"),
+            Ok(false) => {}
+            Err(e) => warn!("Ignoring unreadable metrics snapshot: {}", e),
+        }
         
         for &transport_type in &self.config.enabled_transports {
             if let Err(e) = self.start_transport(transport_type).await {
                 warn!("Failed to start transport {:?}: {}", transport_type, e);
+                self.events.publish(RouterEvent::TransportDown {
+                    transport: transport_type.to_string(),
+                    reason: e.to_string(),
+                });
                 // Continue with other transports
             }
         }
         
         // Start metrics update task
         self.start_metrics_task().await;
+
+        let mut keepalive_task = self.keepalive_task.lock().unwrap_or_else(|e| e.into_inner());
+        if keepalive_task.is_none() {
+            *keepalive_task = Some(Arc::clone(&self.keepalives).spawn());
+        }
+        drop(keepalive_task);
         
         info!("TransportManager started successfully");
         Ok(())
@@ -251,43 +595,31 @@ impl TransportManager {
         debug!("Starting transport {:?}", transport_type);
         
         // Update status to starting
-        {
-            let mut status = self.transport_status.write().await;
-            status.insert(transport_type, TransportStatus::Starting);
-        }
+        self.transport_status.insert(transport_type, TransportStatus::Starting);
         
         // Get factory and create transport instance
-        let transport = {
-            let factories = self.factories.read().unwrap();
-            if let Some(factory) = factories.get(&transport_type) {
-                let config = self.config.transport_configs
-                    .get(&transport_type)
-                    .cloned()
-                    .unwrap_or_else(|| factory.default_config());
-                factory.create_transport(&config).await?
-            } else {
-                return Err(crate::error::SynapseError::TransportError(
-                    format!("No factory registered for transport {:?}", transport_type)
-                ));
-            }
+        let Some(factory) = self.factories.get(&transport_type).map(|entry| Arc::clone(entry.value())) else {
+            return Err(crate::error::SynapseError::TransportError(
+                format!("No factory registered for transport {:?}", transport_type)
+            ));
         };
+        let config = self.config.transport_configs
+            .get(&transport_type)
+            .cloned()
+            .unwrap_or_else(|| factory.default_config());
+        let transport: Arc<dyn Transport> = Arc::from(factory.create_transport(&config).await?);
         
         // Start the transport
         transport.start().await?;
         
         // Store the transport instance
-        {
-            let mut transports = self.transports.write().await;
-            transports.insert(transport_type, transport);
-        }
+        self.transports.insert(transport_type, transport);
         
         // Update status to running
-        {
-            let mut status = self.transport_status.write().await;
-            status.insert(transport_type, TransportStatus::Running);
-        }
+        self.transport_status.insert(transport_type, TransportStatus::Running);
         
         info!("Transport {:?} started successfully", transport_type);
+        self.events.publish(RouterEvent::TransportUp { transport: transport_type.to_string() });
         Ok(())
     }
 
@@ -295,16 +627,21 @@ impl TransportManager {
     pub async fn stop(&self) -> Result<()> {
         info!("Stopping TransportManager");
         
-        let transport_types: Vec<TransportType> = {
-            let transports = self.transports.read().await;
-            transports.keys().cloned().collect()
-        };
+        let transport_types: Vec<TransportType> = self.transports.iter().map(|entry| *entry.key()).collect();
         
         for transport_type in transport_types {
             if let Err(e) = self.stop_transport(transport_type).await {
                 warn!("Failed to stop transport {:?}: {}", transport_type, e);
             }
         }
+
+        if let Some(task) = self.keepalive_task.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            task.abort();
+        }
+
+        if let Err(e) = self.save_metrics_snapshot() {
+            warn!("Failed to save metrics snapshot: {}", e);
+        }
         
         info!("TransportManager stopped");
         Ok(())
@@ -315,56 +652,60 @@ impl TransportManager {
         debug!("Stopping transport {:?}", transport_type);
         
         // Update status to stopping
-        {
-            let mut status = self.transport_status.write().await;
-            status.insert(transport_type, TransportStatus::Stopping);
-        }
+        self.transport_status.insert(transport_type, TransportStatus::Stopping);
         
         // Stop the transport
-        {
-            let mut transports = self.transports.write().await;
-            if let Some(transport) = transports.remove(&transport_type) {
-                transport.stop().await?;
-            }
+        if let Some((_, transport)) = self.transports.remove(&transport_type) {
+            transport.stop().await?;
         }
         
         // Update status to stopped
-        {
-            let mut status = self.transport_status.write().await;
-            status.insert(transport_type, TransportStatus::Stopped);
-        }
+        self.transport_status.insert(transport_type, TransportStatus::Stopped);
         
         info!("Transport {:?} stopped", transport_type);
+        self.events.publish(RouterEvent::TransportDown {
+            transport: transport_type.to_string(),
+            reason: "stopped".to_string(),
+        });
         Ok(())
     }
 
     /// Send a message using the best available transport
     pub async fn send_message(&self, target: &TransportTarget, message: &SecureMessage) -> Result<DeliveryReceipt> {
+        self.send_message_where(target, message, |_| true).await
+    }
+
+    /// Send a message using the best available transport that `permits` accepts
+    pub async fn send_message_where(
+        &self,
+        target: &TransportTarget,
+        message: &SecureMessage,
+        permits: impl Fn(TransportType) -> bool,
+    ) -> Result<DeliveryReceipt> {
         debug!("Sending message to target: {}", target.identifier);
         
         let selected_transports = self.select_transports(target).await?;
         
-        for transport_type in selected_transports {
+        for transport_type in selected_transports.into_iter().filter(|&t| permits(t)) {
             // Check if transport is failed and in recovery
-            if self.is_transport_in_recovery(transport_type).await {
+            if self.is_transport_in_recovery(transport_type) {
                 debug!("Transport {:?} is in recovery, skipping", transport_type);
                 continue;
             }
             
+            // The circuit breaker outcome is recorded by try_send_with_transport
             match self.try_send_with_transport(transport_type, target, message).await {
                 Ok(receipt) => {
-                    self.record_success(transport_type).await;
-                    self.update_transport_metrics(transport_type, true, receipt.delivery_time).await;
+                    self.update_transport_metrics(transport_type, true, receipt.delivery_time);
                     return Ok(receipt);
                 }
                 Err(e) => {
                     warn!("Failed to send via {:?}: {}", transport_type, e);
-                    self.record_failure(transport_type).await;
-                    self.update_transport_metrics(transport_type, false, Duration::from_secs(0)).await;
+                    self.update_transport_metrics(transport_type, false, Duration::from_secs(0));
                     
                     // Check if we should mark this transport as failed
-                    if self.should_mark_transport_failed(transport_type).await {
-                        self.mark_transport_failed(transport_type).await;
+                    if self.should_mark_transport_failed(transport_type) {
+                        self.mark_transport_failed(transport_type);
                     }
                 }
             }
@@ -373,20 +714,65 @@ impl TransportManager {
         Err(crate::error::SynapseError::TransportError("All transports failed".to_string()))
     }
 
+    /// Send a message over one transport, bypassing selection but not its circuit breaker
+    pub async fn send_message_via(
+        &self,
+        transport_type: TransportType,
+        target: &TransportTarget,
+        message: &SecureMessage,
+    ) -> Result<DeliveryReceipt> {
+        if self.is_transport_in_recovery(transport_type) {
+            return Err(crate::error::SynapseError::TransportError(
+                format!("Transport {:?} is recovering from failures", transport_type)
+            ));
+        }
+
+        let result = self.try_send_with_transport(transport_type, target, message).await;
+        match &result {
+            Ok(receipt) => {
+                self.update_transport_metrics(transport_type, true, receipt.delivery_time);
+            }
+            Err(_) => {
+                self.update_transport_metrics(transport_type, false, Duration::from_secs(0));
+                if self.should_mark_transport_failed(transport_type) {
+                    self.mark_transport_failed(transport_type);
+                }
+            }
+        }
+        result
+    }
+
+    /// Have a transport set up connection state for `target` before its first send
+    pub async fn prepare_transport(&self, transport_type: TransportType, target: &TransportTarget) -> Result<()> {
+        match self.transport(transport_type) {
+            Some(transport) => transport.prepare(target).await,
+            None => Err(crate::error::SynapseError::TransportError(
+                format!("Transport {:?} not available", transport_type)
+            )),
+        }
+    }
+
     /// Receive messages from all active transports
     pub async fn receive_messages(&self) -> Result<Vec<IncomingMessage>> {
         let mut all_messages = Vec::new();
         
-        let transports = self.transports.read().await;
-        for (transport_type, transport) in transports.iter() {
+        for (transport_type, transport) in self.running_transports() {
             // Skip failed transports
-            if self.is_transport_in_recovery(*transport_type).await {
+            if self.is_transport_in_recovery(transport_type) {
                 continue;
             }
             
             match transport.receive_messages().await {
                 Ok(mut messages) => {
                     debug!("Received {} messages from {:?}", messages.len(), transport_type);
+                    let encrypted = transport.capabilities().encrypted;
+                    for message in &mut messages {
+                        message.verification.transport_encrypted |= encrypted;
+                    }
+                    messages.retain(|message| self.admit_fresh(message));
+                    for message in &messages {
+                        self.keepalives.touch(transport_type, &message.message.from_global_id);
+                    }
                     all_messages.append(&mut messages);
                 }
                 Err(e) => {
@@ -398,38 +784,90 @@ impl TransportManager {
         Ok(all_messages)
     }
 
+    /// Replay cache applied to received messages
+    pub fn replay_cache(&self) -> &ReplayCache {
+        &self.replay_cache
+    }
+
+    /// Whether a received message is not a replay; replays are logged as
+    /// security events and published to the event bus
+    fn admit_fresh(&self, incoming: &IncomingMessage) -> bool {
+        let verdict = self.replay_cache.check(&incoming.message);
+        if verdict.is_fresh() {
+            return true;
+        }
+        warn!(
+            target: "synapse::security",
+            sender = %incoming.message.from_global_id,
+            message_id = %incoming.message.message_id.0,
+            source = %incoming.source,
+            transport = %incoming.transport_type,
+            verdict = ?verdict,
+            "Rejected replayed message"
+        );
+        self.events.publish(RouterEvent::ReplayRejected {
+            sender: incoming.message.from_global_id.clone(),
+            message_id: incoming.message.message_id.0.to_string(),
+            source: incoming.source.clone(),
+            verdict: format!("{:?}", verdict),
+        });
+        false
+    }
+
     /// Get status of all transports
     pub async fn get_transport_status(&self) -> HashMap<TransportType, TransportStatus> {
-        self.transport_status.read().await.clone()
+        self.transport_status.iter().map(|entry| (*entry.key(), *entry.value())).collect()
     }
 
     /// Get unified metrics
     pub async fn get_metrics(&self) -> UnifiedMetrics {
-        self.metrics.read().unwrap().clone()
+        self.metrics.snapshot()
+    }
+
+    /// Write current metrics to the configured snapshot file
+    pub fn save_metrics_snapshot(&self) -> Result<()> {
+        let Some(path) = &self.config.metrics_snapshot_path else {
+            return Ok(());
+        };
+        let snapshot = MetricsSnapshot::from_metrics(&self.metrics.snapshot());
+        snapshot.save(path)
+    }
+
+    /// Replace current metrics with the configured snapshot, returning whether one was found
+    pub fn load_metrics_snapshot(&self) -> Result<bool> {
+        let Some(path) = &self.config.metrics_snapshot_path else {
+            return Ok(false);
+        };
+        let Some(snapshot) = MetricsSnapshot::load(path)? else {
+            return Ok(false);
+        };
+        debug!("Loaded metrics for {} transports from {}", snapshot.transports.len(), path);
+        self.metrics.restore(snapshot.into_metrics());
+        Ok(true)
     }
 
     /// List available transport types
     pub async fn list_available_transports(&self) -> Vec<TransportType> {
-        let transports = self.transports.read().await;
-        transports.keys().cloned().collect()
+        self.transports.iter().map(|entry| *entry.key()).collect()
     }
 
     /// Get capabilities for a specific transport type
     pub async fn get_transport_capabilities(&self, transport_type: TransportType) -> Option<TransportCapabilities> {
-        let transports = self.transports.read().await;
-        if let Some(transport) = transports.get(&transport_type) {
-            Some(transport.capabilities())
-        } else {
-            None
-        }
+        self.transport(transport_type)
+            .map(|transport| self.capabilities_of(transport_type, transport.as_ref()))
+    }
+
+    /// Capabilities declared at registration, or else those the transport reports
+    fn capabilities_of(&self, transport_type: TransportType, transport: &dyn Transport) -> TransportCapabilities {
+        self.capability_overrides
+            .get(&transport_type)
+            .map(|entry| entry.value().clone())
+            .unwrap_or_else(|| transport.capabilities())
     }
 
     /// Select optimal transport for a target
     pub async fn select_optimal_transport(&self, target: &TransportTarget) -> Result<TransportType> {
-        let available_transports: Vec<_> = {
-            let transports = self.transports.read().await;
-            transports.keys().cloned().collect()
-        };
+        let available_transports = self.running_transports();
 
         if available_transports.is_empty() {
             return Err(crate::error::SynapseError::TransportError("No transports available".to_string()));
@@ -437,23 +875,17 @@ impl TransportManager {
 
         // Check target preferences first
         for &preferred in &target.preferred_transports {
-            if available_transports.contains(&preferred) {
-                let transports = self.transports.read().await;
-                if let Some(transport) = transports.get(&preferred) {
-                    if transport.can_reach(target).await {
-                        return Ok(preferred);
-                    }
+            if let Some((_, transport)) = available_transports.iter().find(|(t, _)| *t == preferred) {
+                if transport.can_reach(target).await {
+                    return Ok(preferred);
                 }
             }
         }
 
         // Fall back to the first available transport that can reach the target
-        let transports = self.transports.read().await;
-        for &transport_type in &available_transports {
-            if let Some(transport) = transports.get(&transport_type) {
-                if transport.can_reach(target).await {
-                    return Ok(transport_type);
-                }
+        for (transport_type, transport) in &available_transports {
+            if transport.can_reach(target).await {
+                return Ok(*transport_type);
             }
         }
 
@@ -462,8 +894,7 @@ impl TransportManager {
 
     /// Estimate delivery for a specific transport and target
     pub async fn estimate_delivery(&self, target: &TransportTarget, transport_type: TransportType) -> Result<DeliveryEstimate> {
-        let transports = self.transports.read().await;
-        if let Some(transport) = transports.get(&transport_type) {
+        if let Some(transport) = self.transport(transport_type) {
             let estimate = transport.estimate_metrics(target).await?;
             Ok(DeliveryEstimate {
                 latency: estimate.latency,
@@ -480,14 +911,11 @@ impl TransportManager {
 
     /// Get metrics summary for all transports
     pub async fn get_metrics_summary(&self) -> std::collections::HashMap<String, TransportMetrics> {
-        let metrics = self.metrics.read().unwrap();
-        let mut summary = std::collections::HashMap::new();
-        
-        for (transport_type, transport_metrics) in &metrics.transport_metrics {
-            summary.insert(transport_type.to_string(), transport_metrics.clone());
-        }
-        
-        summary
+        self.metrics
+            .transports
+            .iter()
+            .map(|entry| (entry.key().to_string(), entry.value().clone()))
+            .collect()
     }
 
     /// Select optimal transports for a target (ordered by preference)
@@ -511,14 +939,16 @@ impl TransportManager {
             TransportSelectionPolicy::PreferenceOrder => {
                 self.select_by_preference(target).await
             }
+            TransportSelectionPolicy::PrivacyFirst => {
+                self.select_privacy_first(target).await
+            }
         }
     }
 
     async fn select_first_available(&self) -> Result<Vec<TransportType>> {
-        let status = self.transport_status.read().await;
-        let available: Vec<TransportType> = status.iter()
-            .filter(|(_, status)| **status == TransportStatus::Running)
-            .map(|(&transport_type, _)| transport_type)
+        let available: Vec<TransportType> = self.transport_status.iter()
+            .filter(|entry| *entry.value() == TransportStatus::Running)
+            .map(|entry| *entry.key())
             .collect();
         
         if available.is_empty() {
@@ -531,9 +961,8 @@ impl TransportManager {
     async fn select_by_urgency(&self, urgency: MessageUrgency) -> Result<Vec<TransportType>> {
         let mut suitable_transports = Vec::new();
         
-        let transports = self.transports.read().await;
-        for (&transport_type, transport) in transports.iter() {
-            let capabilities = transport.capabilities();
+        for (transport_type, transport) in self.running_transports() {
+            let capabilities = self.capabilities_of(transport_type, transport.as_ref());
             if capabilities.supported_urgencies.contains(&urgency) {
                 suitable_transports.push(transport_type);
             }
@@ -579,8 +1008,7 @@ impl TransportManager {
     async fn select_by_performance(&self, target: &TransportTarget) -> Result<Vec<TransportType>> {
         let mut candidates = Vec::new();
         
-        let transports = self.transports.read().await;
-        for (&transport_type, transport) in transports.iter() {
+        for (transport_type, transport) in self.running_transports() {
             if transport.can_reach(target).await {
                 if let Ok(estimate) = transport.estimate_metrics(target).await {
                     candidates.push((transport_type, estimate));
@@ -604,13 +1032,10 @@ impl TransportManager {
         let mut selection = self.select_by_performance(target).await?;
         
         // Adjust based on recent failures and circuit breaker state
-        let breakers = self.circuit_breakers.read().unwrap();
-        selection.retain(|&transport_type| {
-            if let Some(breaker) = breakers.get(&transport_type) {
-                breaker.get_state() != crate::circuit_breaker::CircuitState::Open
-            } else {
-                true
-            }
+        selection.retain(|transport_type| {
+            self.circuit_breakers
+                .get(transport_type)
+                .is_none_or(|breaker| breaker.get_state() != crate::circuit_breaker::CircuitState::Open)
         });
         
         // If no transports pass circuit breaker test, fall back to urgency-based
@@ -628,9 +1053,7 @@ impl TransportManager {
             return Ok(available);
         }
         
-        let mut index = self.round_robin_index.lock().await;
-        let selected_index = *index % available.len();
-        *index = (*index + 1) % available.len();
+        let selected_index = self.round_robin_index.fetch_add(1, Ordering::Relaxed) % available.len();
         
         // Return selected transport first, then others as fallback
         let mut result = vec![available[selected_index]];
@@ -664,18 +1087,52 @@ impl TransportManager {
         Ok(result)
     }
 
+    async fn select_privacy_first(&self, target: &TransportTarget) -> Result<Vec<TransportType>> {
+        // Targets requiring anonymity never fall back to transports that expose addresses
+        let anonymous_only = target.required_capabilities.iter().any(|c| c == "anonymous");
+        let mut candidates = Vec::new();
+        
+        for (transport_type, transport) in self.running_transports() {
+            let capabilities = self.capabilities_of(transport_type, transport.as_ref());
+            if anonymous_only && !capabilities.is_anonymous() {
+                continue;
+            }
+            if transport.can_reach(target).await {
+                let rank = match (capabilities.is_anonymous(), capabilities.encrypted) {
+                    (true, _) => 0,
+                    (false, true) => 1,
+                    (false, false) => 2,
+                };
+                candidates.push((rank, transport_type));
+            }
+        }
+        
+        if candidates.is_empty() {
+            return Err(crate::error::SynapseError::TransportError(if anonymous_only {
+                "No anonymous transport can reach the target".to_string()
+            } else {
+                "No suitable transport found".to_string()
+            }));
+        }
+        
+        candidates.sort_by_key(|&(rank, _)| rank);
+        Ok(candidates.into_iter().map(|(_, t)| t).collect())
+    }
+
     fn calculate_performance_score(&self, estimate: &TransportEstimate, weights: &SelectionWeights) -> f64 {
         let latency_score = 1.0 / (1.0 + estimate.latency.as_secs_f64());
         let reliability_score = estimate.reliability;
         let bandwidth_score = (estimate.bandwidth as f64).log10() / 10.0; // Normalize bandwidth
         let cost_score = 1.0 / (1.0 + estimate.cost);
         let availability_score = if estimate.available { 1.0 } else { 0.0 };
+        let region_score = RegionAffinity::between(self.config.region.as_deref(), estimate.region.as_deref()).score();
         
         (latency_score * weights.latency +
          reliability_score * weights.reliability +
          bandwidth_score * weights.bandwidth +
          cost_score * weights.cost +
-         availability_score * weights.capability_match) * estimate.confidence
+         availability_score * weights.capability_match +
+         region_score * weights.region) * estimate.confidence
     }
 
     async fn try_send_with_transport(
@@ -684,15 +1141,9 @@ impl TransportManager {
         target: &TransportTarget, 
         message: &SecureMessage
     ) -> Result<DeliveryReceipt> {
-        let transports = self.transports.read().await;
-        if let Some(transport) = transports.get(&transport_type) {
+        if let Some(transport) = self.transport(transport_type) {
             // Check circuit breaker state first
-            let circuit_breaker = {
-                let breakers = self.circuit_breakers.read().unwrap();
-                breakers.get(&transport_type).cloned()
-            };
-            
-            if let Some(breaker) = circuit_breaker {
+            if let Some(breaker) = self.circuit_breaker(transport_type) {
                 // Check if circuit breaker allows the request
                 if breaker.get_state() == crate::circuit_breaker::CircuitState::Open {
                     return Err(crate::error::SynapseError::TransportError(
@@ -704,6 +1155,7 @@ impl TransportManager {
                 match transport.send_message(target, message).await {
                     Ok(receipt) => {
                         breaker.record_outcome(RequestOutcome::Success).await;
+                        self.track_route(transport_type, transport, target);
                         Ok(receipt)
                     }
                     Err(e) => {
@@ -712,7 +1164,9 @@ impl TransportManager {
                     }
                 }
             } else {
-                transport.send_message(target, message).await
+                let receipt = transport.send_message(target, message).await?;
+                self.track_route(transport_type, transport, target);
+                Ok(receipt)
             }
         } else {
             Err(crate::error::SynapseError::TransportError(
@@ -721,140 +1175,95 @@ impl TransportManager {
         }
     }
 
-    async fn record_success(&self, transport_type: TransportType) {
-        if let Ok(breakers) = self.circuit_breakers.read() {
-            if let Some(breaker) = breakers.get(&transport_type) {
-                breaker.record_outcome(RequestOutcome::Success).await;
-            }
+    /// Keep the route alive if its transport crosses NATs
+    fn track_route(&self, transport_type: TransportType, transport: Arc<dyn Transport>, target: &TransportTarget) {
+        let capabilities = self.capability_overrides
+            .get(&transport_type)
+            .map(|entry| entry.value().clone())
+            .unwrap_or_else(|| transport.capabilities());
+        if capabilities.features.iter().any(|feature| feature == NAT_TRAVERSAL_FEATURE) {
+            self.keepalives.track(transport, target);
         }
     }
 
-    async fn record_failure(&self, transport_type: TransportType) {
-        if let Ok(breakers) = self.circuit_breakers.read() {
-            if let Some(breaker) = breakers.get(&transport_type) {
-                breaker.record_outcome(RequestOutcome::Failure("Transport operation failed".to_string())).await;
-            }
-        }
+    /// Keep the route to `target` over `transport` alive from now on
+    ///
+    /// For NAT-traversing transports the manager does not own, such as the
+    /// router's built-in one; routes over registered transports are tracked on
+    /// every successful send.
+    pub fn keep_alive_route(&self, transport: Arc<dyn Transport>, target: &TransportTarget) {
+        self.keepalives.track(transport, target);
     }
 
-    async fn should_mark_transport_failed(&self, transport_type: TransportType) -> bool {
-        if let Ok(breakers) = self.circuit_breakers.read() {
-            if let Some(breaker) = breakers.get(&transport_type) {
-                let stats = breaker.get_stats();
-                let total_requests = stats.total_requests;
-                if total_requests > 0 {
-                    let failure_rate = stats.failure_count as f64 / total_requests as f64;
-                    return failure_rate > self.config.failover_config.failure_threshold;
-                }
-            }
+    /// Use `settings` for the route to `target` over `transport_type`, or turn
+    /// its keepalives off with `None`
+    pub fn set_route_keepalive(&self, transport_type: TransportType, target: &str, settings: Option<KeepaliveSettings>) {
+        self.keepalives.configure_route(transport_type, target, settings);
+    }
+
+    /// Record traffic received from `from` over `transport_type`
+    pub fn note_route_activity(&self, transport_type: TransportType, from: &str) {
+        self.keepalives.touch(transport_type, from);
+    }
+
+    /// Routes currently kept alive
+    pub fn keepalive_routes(&self) -> Vec<(TransportType, String)> {
+        self.keepalives.active_routes()
+    }
+
+    fn should_mark_transport_failed(&self, transport_type: TransportType) -> bool {
+        let Some(breaker) = self.circuit_breaker(transport_type) else {
+            return false;
+        };
+        let stats = breaker.get_stats();
+        let total_requests = stats.total_requests;
+        if total_requests > 0 {
+            let failure_rate = stats.failure_count as f64 / total_requests as f64;
+            return failure_rate > self.config.failover_config.failure_threshold;
         }
         false
     }
 
-    async fn mark_transport_failed(&self, transport_type: TransportType) {
+    fn mark_transport_failed(&self, transport_type: TransportType) {
         warn!("Marking transport {:?} as failed", transport_type);
         let recovery_time = Instant::now() + self.config.failover_config.recovery_timeout;
         
-        {
-            let mut failed = self.failed_transports.write().await;
-            failed.insert(transport_type, recovery_time);
-        }
-        
-        {
-            let mut status = self.transport_status.write().await;
-            status.insert(transport_type, TransportStatus::Failed);
-        }
+        self.failed_transports.insert(transport_type, recovery_time);
+        self.transport_status.insert(transport_type, TransportStatus::Failed);
+
+        self.events.publish(RouterEvent::TransportDown {
+            transport: transport_type.to_string(),
+            reason: format!("failing; retrying in {:?}", self.config.failover_config.recovery_timeout),
+        });
     }
 
-    async fn is_transport_in_recovery(&self, transport_type: TransportType) -> bool {
-        let failed = self.failed_transports.read().await;
-        if let Some(&recovery_time) = failed.get(&transport_type) {
-            Instant::now() < recovery_time
-        } else {
-            false
-        }
+    fn is_transport_in_recovery(&self, transport_type: TransportType) -> bool {
+        self.failed_transports
+            .get(&transport_type)
+            .is_some_and(|recovery_time| Instant::now() < *recovery_time)
     }
 
-    async fn update_transport_metrics(&self, transport_type: TransportType, success: bool, latency: Duration) {
-        let mut metrics = self.metrics.write().unwrap();
-        
-        if success {
-            metrics.total_messages_sent += 1;
-        } else {
-            metrics.total_failures += 1;
-        }
-        
-        // Update per-transport metrics
-        let transport_metrics = metrics.transport_metrics
-            .entry(transport_type)
-            .or_insert_with(|| {
-                let mut tm = TransportMetrics::default();
-                tm.transport_type = transport_type;
-                tm
-            });
-        
-        if success {
-            transport_metrics.messages_sent += 1;
-            // Update running average latency
-            let total_messages = transport_metrics.messages_sent;
-            let old_avg_ms = transport_metrics.average_latency_ms as f64;
-            let new_latency_ms = latency.as_millis() as f64;
-            let new_avg_ms = (old_avg_ms * (total_messages - 1) as f64 + new_latency_ms) / total_messages as f64;
-            transport_metrics.average_latency_ms = new_avg_ms as u64;
-        } else {
-            transport_metrics.send_failures += 1;
-        }
-        
-        // Update reliability score
-        let total_attempts = transport_metrics.messages_sent + transport_metrics.send_failures;
-        if total_attempts > 0 {
-            transport_metrics.reliability_score = transport_metrics.messages_sent as f64 / total_attempts as f64;
-        }
-        
-        transport_metrics.touch();
-        metrics.touch();
+    fn update_transport_metrics(&self, transport_type: TransportType, success: bool, latency: Duration) {
+        self.metrics.record_send(transport_type, success, latency);
     }
 
     async fn start_metrics_task(&self) {
-        let metrics = Arc::clone(&self.metrics);
-        let interval = self.config.metrics_update_interval;
-        
-        tokio::spawn(async move {
-            let mut interval_timer = tokio::time::interval(interval);
-            
-            loop {
+        // Periodically snapshot so a crash loses at most one interval
+        if let Some(path) = self.config.metrics_snapshot_path.clone() {
+            let metrics = Arc::clone(&self.metrics);
+            let snapshot_interval = self.config.metrics_snapshot_interval;
+            tokio::spawn(async move {
+                let mut interval_timer = tokio::time::interval(snapshot_interval);
                 interval_timer.tick().await;
-                
-                // Update unified metrics
-                let mut metrics_guard = metrics.write().unwrap();
-                
-                // Calculate overall reliability
-                let total_sent = metrics_guard.total_messages_sent;
-                let total_failed = metrics_guard.total_failures;
-                let total_attempts = total_sent + total_failed;
-                
-                if total_attempts > 0 {
-                    metrics_guard.overall_reliability = total_sent as f64 / total_attempts as f64;
-                }
-                
-                // Calculate average latency across all transports
-                let mut total_latency_ms = 0u64;
-                let mut transport_count = 0;
-                
-                for transport_metrics in metrics_guard.transport_metrics.values() {
-                    if transport_metrics.messages_sent > 0 {
-                        total_latency_ms += transport_metrics.average_latency_ms;
-                        transport_count += 1;
+                loop {
+                    interval_timer.tick().await;
+                    let snapshot = MetricsSnapshot::from_metrics(&metrics.snapshot());
+                    if let Err(e) = snapshot.save(&path) {
+                        warn!("Failed to save metrics snapshot to {}: {}", path, e);
                     }
                 }
-                
-                if transport_count > 0 {
-                    metrics_guard.average_latency = Duration::from_millis(total_latency_ms / transport_count);
-                }
-                
-                metrics_guard.touch();
-            }
-        });
+            });
+        }
     }
 
     // Note: Individual transport access is not exposed in the public API
@@ -864,12 +1273,14 @@ impl TransportManager {
 /// Builder pattern for TransportManager configuration
 pub struct TransportManagerBuilder {
     config: TransportManagerConfig,
+    events: Option<EventBus>,
 }
 
 impl TransportManagerBuilder {
     pub fn new() -> Self {
         Self {
             config: TransportManagerConfig::default(),
+            events: None,
         }
     }
     
@@ -905,8 +1316,42 @@ impl TransportManagerBuilder {
         self
     }
     
+    /// Snapshot metrics to `path` every `interval` and restore them on start
+    pub fn metrics_snapshot(mut self, path: impl Into<String>, interval: Duration) -> Self {
+        self.config.metrics_snapshot_path = Some(path.into());
+        self.config.metrics_snapshot_interval = interval;
+        self
+    }
+    
+    /// Replay window for received messages
+    pub fn replay_protection(mut self, config: ReplayConfig) -> Self {
+        self.config.replay = config;
+        self
+    }
+    
+    pub fn event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+    
+    /// Region this node runs in, so transports through nearby relays are preferred
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.config.region = Some(region.into());
+        self
+    }
+    
+    /// Default keepalive timing for routes over NAT-traversing transports
+    pub fn keepalive(mut self, settings: KeepaliveSettings) -> Self {
+        self.config.keepalive = settings;
+        self
+    }
+    
     pub fn build(self) -> TransportManager {
-        TransportManager::new(self.config)
+        let manager = TransportManager::new(self.config);
+        match self.events {
+            Some(events) => manager.with_event_bus(events),
+            None => manager,
+        }
     }
 }
 
@@ -915,3 +1360,147 @@ impl Default for TransportManagerBuilder {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
+    use crate::transport::providers::MockTransport;
+    use crate::types::SecurityLevel;
+    use async_trait::async_trait;
+
+    struct MockFactory(TransportType, Duration);
+
+    #[async_trait]
+    impl TransportFactory for MockFactory {
+        async fn create_transport(&self, _config: &HashMap<String, String>) -> Result<Box<dyn Transport>> {
+            Ok(Box::new(MockTransport::new("load").with_latency(self.1)))
+        }
+        fn transport_type(&self) -> TransportType {
+            self.0
+        }
+        fn default_config(&self) -> HashMap<String, String> {
+            HashMap::new()
+        }
+        fn validate_config(&self, _config: &HashMap<String, String>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn message() -> SecureMessage {
+        SecureMessage {
+            message_id: UuidWrapper::new(uuid::Uuid::new_v4()),
+            to_global_id: "peer".to_string(),
+            from_global_id: "me".to_string(),
+            encrypted_content: b"load".to_vec(),
+            signature: Vec::new(),
+            timestamp: DateTimeWrapper::new(chrono::Utc::now()),
+            security_level: SecurityLevel::Public,
+            routing_path: Vec::new(),
+            metadata: HashMap::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    async fn manager_with(transports: &[TransportType], latency: Duration) -> Arc<TransportManager> {
+        let manager = TransportManagerBuilder::new()
+            .selection_policy(TransportSelectionPolicy::RoundRobin)
+            .build();
+        for &transport_type in transports {
+            manager
+                .add_transport(Box::new(MockFactory(transport_type, latency)), None)
+                .await
+                .unwrap();
+        }
+        Arc::new(manager)
+    }
+
+    /// Send `per_task` messages from each of `tasks` concurrent tasks, returning the elapsed time
+    async fn load(manager: &Arc<TransportManager>, tasks: usize, per_task: usize) -> Duration {
+        let started = Instant::now();
+        let handles: Vec<_> = (0..tasks)
+            .map(|_| {
+                let manager = Arc::clone(manager);
+                tokio::spawn(async move {
+                    let target = TransportTarget::new("peer".to_string());
+                    for _ in 0..per_task {
+                        manager.send_message(&target, &message()).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        started.elapsed()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn concurrent_sends_scale_and_keep_exact_metrics() {
+        let transports = [TransportType::Custom(1), TransportType::Custom(2), TransportType::Custom(3)];
+        let latency = Duration::from_millis(5);
+        let manager = manager_with(&transports, latency).await;
+
+        // One task sends serially, so each send costs at least the transport latency
+        let serial = load(&manager, 1, 20).await;
+        assert!(serial >= latency * 20);
+
+        // 32 tasks sending the same number of messages each overlap their sends;
+        // a lock held across the send would serialize them to 32x the serial time
+        let concurrent = load(&manager, 32, 20).await;
+        assert!(
+            concurrent < serial * 8,
+            "32x the work took {:?} against {:?} serially",
+            concurrent,
+            serial
+        );
+
+        let metrics = manager.get_metrics().await;
+        assert_eq!(metrics.total_messages_sent, 33 * 20);
+        assert_eq!(metrics.total_failures, 0);
+        let per_transport: u64 = metrics.transport_metrics.values().map(|m| m.messages_sent).sum();
+        assert_eq!(per_transport, 33 * 20);
+        assert_eq!(metrics.transport_metrics.len(), transports.len());
+        assert!((metrics.overall_reliability - 1.0).abs() < f64::EPSILON);
+
+        // Each success is recorded once on its transport's breaker
+        let breaker_requests: u64 = transports
+            .iter()
+            .map(|&t| manager.circuit_breaker(t).unwrap().get_stats().total_requests)
+            .sum();
+        assert_eq!(breaker_requests, 33 * 20);
+    }
+
+    #[tokio::test]
+    async fn removed_transport_is_forgotten_everywhere() {
+        let manager = manager_with(&[TransportType::Custom(9)], Duration::ZERO).await;
+        let target = TransportTarget::new("peer".to_string());
+        manager.send_message(&target, &message()).await.unwrap();
+
+        manager.remove_transport(TransportType::Custom(9)).await.unwrap();
+        assert!(manager.list_available_transports().await.is_empty());
+        assert!(manager.circuit_breaker(TransportType::Custom(9)).is_none());
+        assert!(manager.send_message(&target, &message()).await.is_err());
+        assert_eq!(manager.get_metrics().await.total_messages_sent, 1);
+    }
+
+    #[test]
+    fn nearby_relays_score_higher() {
+        let manager = TransportManagerBuilder::new().region("eu-west").build();
+        let weights = SelectionWeights::default();
+        let estimate = |region: Option<&str>| TransportEstimate {
+            latency: Duration::from_millis(80),
+            reliability: 0.9,
+            bandwidth: 1_000_000,
+            cost: 1.0,
+            available: true,
+            confidence: 0.8,
+            region: region.map(str::to_string),
+        };
+        let same = manager.calculate_performance_score(&estimate(Some("eu-west-2")), &weights);
+        let area = manager.calculate_performance_score(&estimate(Some("eu-central")), &weights);
+        let unknown = manager.calculate_performance_score(&estimate(None), &weights);
+        let distant = manager.calculate_performance_score(&estimate(Some("ap-south")), &weights);
+        assert!(same > area && area > unknown && unknown > distant);
+    }
+}
@@ -8,6 +8,7 @@ use crate::{
     types::SecureMessage, 
     error::Result,
     circuit_breaker::{CircuitBreaker, CircuitBreakerConfig},
+    protocol::VersionRange,
 };
 use std::{
     time::{Duration, Instant},
@@ -19,6 +20,88 @@ use tracing::{info, debug, warn, error};
 use tokio::{net::UdpSocket as TokioUdpSocket, sync::Mutex, time::interval};
 use serde::{Serialize, Deserialize};
 
+/// DNS-SD service type for Synapse nodes
+pub const SYNAPSE_SERVICE_TYPE: &str = "_synapse._tcp.local.";
+
+pub use crate::protocol::{ProtocolVersion, MIN_COMPATIBLE_MAJOR, PROTOCOL_VERSION};
+
+/// Version of the TXT key layout below (RFC 6763 section 6.7)
+pub const TXT_FORMAT_VERSION: &str = "1";
+
+/// Structured TXT record keys announced by Synapse nodes
+pub mod txt_keys {
+    /// TXT layout version
+    pub const TXT_VERS: &str = "txtvers";
+    /// Protocol family, always "synapse"
+    pub const PROTOCOL: &str = "proto";
+    /// Wire protocol version ("major.minor")
+    pub const PROTOCOL_VERSION: &str = "pv";
+    /// Oldest wire protocol major version the node accepts
+    pub const MIN_PROTOCOL_MAJOR: &str = "pvmin";
+    /// Entity ID of the node
+    pub const ENTITY_ID: &str = "id";
+    /// Comma-separated capabilities
+    pub const CAPABILITIES: &str = "caps";
+    /// Comma-separated transports
+    pub const TRANSPORTS: &str = "tp";
+
+    /// Pre-versioning keys, still announced for older peers
+    pub const LEGACY_VERSION: &str = "version";
+    pub const LEGACY_ENTITY_ID: &str = "entity_id";
+    pub const LEGACY_CAPABILITIES: &str = "capabilities";
+}
+
+/// DNS-SD subtype selecting nodes that speak a protocol major version
+/// (e.g. `_v1._sub._synapse._tcp.local.`)
+pub fn versioned_subtype(major: u32) -> String {
+    format!("_v{}._sub.{}", major, SYNAPSE_SERVICE_TYPE)
+}
+
+/// Whether a discovered peer speaks a protocol version we can use
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PeerCompatibility {
+    /// Version ranges overlap
+    Compatible,
+    /// Peer and local version ranges do not overlap
+    Incompatible { peer_version: String, reason: String },
+    /// Peer did not announce a usable version
+    #[default]
+    Unknown,
+}
+
+impl PeerCompatibility {
+    /// Judge a peer from its TXT records
+    pub fn from_txt(txt_records: &HashMap<String, String>) -> Self {
+        let announced = txt_records
+            .get(txt_keys::PROTOCOL_VERSION)
+            .or_else(|| txt_records.get(txt_keys::LEGACY_VERSION));
+        let Some(announced) = announced else {
+            return PeerCompatibility::Unknown;
+        };
+        let Some(peer_version) = ProtocolVersion::parse(announced) else {
+            return PeerCompatibility::Unknown;
+        };
+
+        let min_major = txt_records
+            .get(txt_keys::MIN_PROTOCOL_MAJOR)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(peer_version.major);
+
+        match VersionRange::LOCAL.negotiate(&VersionRange { current: peer_version, min_major }) {
+            Ok(_) => PeerCompatibility::Compatible,
+            Err(reason) => PeerCompatibility::Incompatible {
+                peer_version: peer_version.to_string(),
+                reason,
+            },
+        }
+    }
+
+    /// Whether messages can be sent to the peer
+    pub fn is_usable(&self) -> bool {
+        !matches!(self, PeerCompatibility::Incompatible { .. })
+    }
+}
+
 /// Enhanced mDNS transport with full service discovery and announcement
 pub struct EnhancedMdnsTransport {
     /// Our service instance name
@@ -62,6 +145,9 @@ pub struct EnhancedMdnsPeer {
     pub last_seen: Instant,
     pub capabilities: Vec<String>,
     pub protocol_version: String,
+    /// Protocol compatibility judged from the peer's TXT records
+    #[serde(default)]
+    pub compatibility: PeerCompatibility,
 }
 
 /// Service announcement record
@@ -73,6 +159,9 @@ pub struct ServiceAnnouncement {
     pub host_name: String,
     pub port: u16,
     pub txt_records: HashMap<String, String>,
+    /// DNS-SD subtypes the instance is also registered under
+    #[serde(default)]
+    pub subtypes: Vec<String>,
     pub ttl: u32,
     #[serde(skip, default = "Instant::now")]
     pub announced_at: Instant,
@@ -97,6 +186,8 @@ pub struct MdnsConfig {
     pub max_peers: usize,
     /// How long to keep stale peers
     pub peer_timeout: Duration,
+    /// Keep peers with incompatible protocol versions (flagged) instead of dropping them
+    pub include_incompatible_peers: bool,
 }
 
 /// mDNS packet types we handle
@@ -144,6 +235,7 @@ impl Default for MdnsConfig {
             discovery_timeout: Duration::from_secs(5),
             max_peers: 100,
             peer_timeout: Duration::from_secs(300),
+            include_incompatible_peers: false,
         }
     }
 }
@@ -161,8 +253,8 @@ impl EnhancedMdnsTransport {
         let multicast_socket = create_multicast_socket(&config).await?;
         
         // Generate unique instance name
-        let instance_name = format!("{}._synapse._tcp.local.", entity_id);
-        let service_type = "_synapse._tcp.local.".to_string();
+        let instance_name = format!("{}.{}", entity_id, SYNAPSE_SERVICE_TYPE);
+        let service_type = SYNAPSE_SERVICE_TYPE.to_string();
         
         // Create circuit breaker with mDNS-appropriate settings
         let circuit_config = CircuitBreakerConfig {
@@ -219,6 +311,7 @@ impl EnhancedMdnsTransport {
             host_name: format!("{}.local.", self.entity_id),
             port: self.local_port,
             txt_records: self.build_txt_records(),
+            subtypes: vec![versioned_subtype(PROTOCOL_VERSION.major)],
             ttl: self.config.default_ttl,
             announced_at: Instant::now(),
         };
@@ -245,8 +338,8 @@ impl EnhancedMdnsTransport {
     pub async fn discover_services(&self) -> Result<Vec<EnhancedMdnsPeer>> {
         info!("Discovering Synapse services on local network");
         
-        // Send PTR query for _synapse._tcp.local.
-        self.send_ptr_query("_synapse._tcp.local.").await?;
+        // Browse only the subtype of our protocol major version
+        self.send_ptr_query(&versioned_subtype(PROTOCOL_VERSION.major)).await?;
         
         // Wait for responses
         tokio::time::sleep(self.config.discovery_timeout).await;
@@ -270,6 +363,14 @@ impl EnhancedMdnsTransport {
     
     /// Send message to a peer via mDNS-discovered address
     pub async fn send_to_peer(&self, peer: &EnhancedMdnsPeer, message: &SecureMessage) -> Result<String> {
+        if let PeerCompatibility::Incompatible { peer_version, reason } = &peer.compatibility {
+            return Err(crate::error::SynapseError::IncompatiblePeer {
+                peer: peer.entity_id.clone(),
+                version: peer_version.clone(),
+                reason: reason.clone(),
+            });
+        }
+        
         // Use the first available address
         if let Some(addr) = peer.addresses.first() {
             let socket_addr = SocketAddr::new(*addr, peer.port);
@@ -429,18 +530,29 @@ impl EnhancedMdnsTransport {
     }
     
     fn build_txt_records(&self) -> HashMap<String, String> {
+        let capabilities = "tcp,encrypted,direct".to_string();
         let mut txt_records = HashMap::new();
-        txt_records.insert("version".to_string(), "1.0".to_string());
-        txt_records.insert("protocol".to_string(), "synapse".to_string());
-        txt_records.insert("entity_id".to_string(), self.entity_id.clone());
-        txt_records.insert("capabilities".to_string(), "tcp,encrypted,direct".to_string());
-        txt_records.insert("transport_types".to_string(), "tcp,udp,email".to_string());
+        txt_records.insert(txt_keys::TXT_VERS.to_string(), TXT_FORMAT_VERSION.to_string());
+        txt_records.insert(txt_keys::PROTOCOL.to_string(), "synapse".to_string());
+        txt_records.insert(txt_keys::PROTOCOL_VERSION.to_string(), PROTOCOL_VERSION.to_string());
+        txt_records.insert(txt_keys::MIN_PROTOCOL_MAJOR.to_string(), MIN_COMPATIBLE_MAJOR.to_string());
+        txt_records.insert(txt_keys::ENTITY_ID.to_string(), self.entity_id.clone());
+        txt_records.insert(txt_keys::CAPABILITIES.to_string(), capabilities.clone());
+        txt_records.insert(txt_keys::TRANSPORTS.to_string(), "tcp,udp,email".to_string());
+        
+        // Keys read by nodes that predate structured TXT records
+        txt_records.insert(txt_keys::LEGACY_VERSION.to_string(), PROTOCOL_VERSION.to_string());
+        txt_records.insert(txt_keys::LEGACY_ENTITY_ID.to_string(), self.entity_id.clone());
+        txt_records.insert(txt_keys::LEGACY_CAPABILITIES.to_string(), capabilities);
         txt_records
     }
     
     async fn send_ptr_record(&self, announcement: &ServiceAnnouncement) -> Result<()> {
         // Implementation for sending PTR records
         debug!("Sending PTR record for {}", announcement.instance_name);
+        for subtype in &announcement.subtypes {
+            debug!("Sending subtype PTR record {} for {}", subtype, announcement.instance_name);
+        }
         Ok(())
     }
     
@@ -884,6 +996,25 @@ mod tests {
         assert_eq!(&encoded[1..5], b"test");
         assert_eq!(encoded[5], 8); // "_synapse"
     }
+    
+    #[test]
+    fn test_protocol_compatibility() {
+        assert_eq!(versioned_subtype(1), "_v1._sub._synapse._tcp.local.");
+        assert_eq!(ProtocolVersion::parse("2.3"), Some(ProtocolVersion { major: 2, minor: 3 }));
+        
+        let txt = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        
+        // Legacy peers only announce "version"
+        assert_eq!(PeerCompatibility::from_txt(&txt(&[("version", "1.0")])), PeerCompatibility::Compatible);
+        assert_eq!(PeerCompatibility::from_txt(&txt(&[])), PeerCompatibility::Unknown);
+        
+        // A newer peer that still accepts v1 is fine; one that dropped v1 is flagged
+        assert!(PeerCompatibility::from_txt(&txt(&[("pv", "2.0"), ("pvmin", "1")])).is_usable());
+        assert!(!PeerCompatibility::from_txt(&txt(&[("pv", "2.0"), ("pvmin", "2")])).is_usable());
+        assert!(!PeerCompatibility::from_txt(&txt(&[("pv", "0.9")])).is_usable());
+    }
 }
 
 /// Enhanced mDNS service browser for discovering multiple service types
@@ -918,6 +1049,19 @@ pub struct ServiceRecord {
     pub service_state: ServiceState,
 }
 
+impl ServiceRecord {
+    /// Protocol compatibility of a Synapse service (other services are always compatible)
+    pub fn compatibility(&self) -> PeerCompatibility {
+        let is_synapse = self.service_type.ends_with(SYNAPSE_SERVICE_TYPE)
+            || self.txt_records.get(txt_keys::PROTOCOL).is_some_and(|p| p == "synapse");
+        if is_synapse {
+            PeerCompatibility::from_txt(&self.txt_records)
+        } else {
+            PeerCompatibility::Compatible
+        }
+    }
+}
+
 /// Service state tracking
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ServiceState {
@@ -1041,6 +1185,17 @@ impl EnhancedMdnsServiceBrowser {
             .collect()
     }
     
+    /// Get discovered services, dropping Synapse nodes with incompatible protocol versions
+    pub async fn get_compatible_services(&self) -> Vec<ServiceRecord> {
+        self.service_cache
+            .read()
+            .unwrap()
+            .values()
+            .filter(|record| record.compatibility().is_usable())
+            .cloned()
+            .collect()
+    }
+    
     /// Find services by capability
     pub async fn find_services_by_capability(&self, capability: &str) -> Vec<ServiceRecord> {
         self.service_cache
@@ -1277,8 +1432,8 @@ impl EnhancedMdnsTransport {
     pub async fn create_service_browser(&self) -> Result<EnhancedMdnsServiceBrowser> {
         EnhancedMdnsServiceBrowser::new(
             vec![
-                "_synapse._tcp.local.".to_string(),
-                "_synapse._tcp.local.".to_string(),
+                versioned_subtype(PROTOCOL_VERSION.major),
+                SYNAPSE_SERVICE_TYPE.to_string(),
                 "_synapse-router._tcp.local.".to_string(),
             ],
             None
@@ -1297,11 +1452,13 @@ impl EnhancedMdnsTransport {
             host_name: format!("{}.local.", self.entity_id),
             addresses: vec![], // Would be populated with our actual addresses
             port: self.local_port,
-            txt_records: HashMap::from([
-                ("entity_id".to_string(), self.entity_id.clone()),
-                ("version".to_string(), "1.0".to_string()),
-                ("capabilities".to_string(), "routing,discovery,secure_messaging".to_string()),
-            ]),
+            txt_records: {
+                let mut txt_records = self.build_txt_records();
+                let capabilities = "routing,discovery,secure_messaging".to_string();
+                txt_records.insert(txt_keys::CAPABILITIES.to_string(), capabilities.clone());
+                txt_records.insert(txt_keys::LEGACY_CAPABILITIES.to_string(), capabilities);
+                txt_records
+            },
             priority: 10,
             weight: 5,
             ttl: 120,
@@ -1328,15 +1485,25 @@ impl EnhancedMdnsTransport {
         // Convert service records to enhanced peers
         for service in services {
             if service.service_state == ServiceState::Active {
-                let capabilities = service.txt_records.get("capabilities")
+                let compatibility = service.compatibility();
+                if !compatibility.is_usable() && !self.config.include_incompatible_peers {
+                    warn!("Ignoring mDNS peer {} with incompatible protocol: {:?}", service.service_name, compatibility);
+                    continue;
+                }
+                
+                let txt = &service.txt_records;
+                let capabilities = txt.get(txt_keys::CAPABILITIES)
+                    .or_else(|| txt.get(txt_keys::LEGACY_CAPABILITIES))
                     .map(|caps| caps.split(',').map(|s| s.trim().to_string()).collect())
                     .unwrap_or_default();
                 
-                let protocol_version = service.txt_records.get("version")
+                let protocol_version = txt.get(txt_keys::PROTOCOL_VERSION)
+                    .or_else(|| txt.get(txt_keys::LEGACY_VERSION))
                     .cloned()
                     .unwrap_or_else(|| "1.0".to_string());
                 
-                let entity_id = service.txt_records.get("entity_id")
+                let entity_id = txt.get(txt_keys::ENTITY_ID)
+                    .or_else(|| txt.get(txt_keys::LEGACY_ENTITY_ID))
                     .cloned()
                     .unwrap_or_else(|| service.service_name.clone());
                 
@@ -1355,6 +1522,7 @@ impl EnhancedMdnsTransport {
                     last_seen: service.last_updated,
                     capabilities,
                     protocol_version,
+                    compatibility,
                 };
                 peers.push(peer);
             }
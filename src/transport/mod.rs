@@ -6,6 +6,9 @@
 // Core abstraction layer
 pub mod abstraction;
 pub mod manager;
+pub mod replay;
+pub mod keepalive;
+pub mod time_sync;
 
 // Dependency injection providers for testability
 pub mod providers;
@@ -19,6 +22,8 @@ pub mod discovery; // New auto-discovery based implementation
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod udp_unified;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod udp_arq;
 
 // Re-export key types
 pub use abstraction::{TransportType, TransportCapabilities};
@@ -33,11 +38,33 @@ pub mod tcp_simple;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod tcp;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod proxy;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod tcp_enhanced;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod udp;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod http_unified;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod framing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod prober;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod resumption;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod capability_probe;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod echo;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod nat_behavior;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fast_relay;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod binding;
+#[cfg(all(feature = "tor", not(target_arch = "wasm32")))]
+pub mod tor;
+#[cfg(all(feature = "i2p", not(target_arch = "wasm32")))]
+pub mod i2p;
 // #[cfg(not(target_arch = "wasm32"))]
 // pub mod mdns;
 #[cfg(not(target_arch = "wasm32"))]
@@ -131,6 +158,13 @@ pub enum TransportRoute {
     EmailDiscovery { 
         target_transport: Box<TransportRoute>,
     },
+    /// A transport registered at runtime, see [`MultiTransportRouter::register_custom_transport`]
+    #[cfg(not(target_arch = "wasm32"))]
+    Custom {
+        transport_type: TransportType,
+        latency_ms: u32,
+        network_spanning: bool,
+    },
     
     // WASM-compatible transport routes
     #[cfg(target_arch = "wasm32")]
@@ -157,7 +191,13 @@ pub enum TransportRoute {
 pub enum NatMethod {
     Upnp,
     Stun { server: String },
-    Turn { server: String, username: String },
+    Turn {
+        server: String,
+        username: String,
+        /// Region the relay is in, checked against data-residency rules
+        #[serde(default)]
+        region: Option<String>,
+    },
     IceCandidate,
 }
 
@@ -194,7 +234,7 @@ pub struct ConnectionOffer {
 pub struct TurnServer {
     pub url: String,
     pub username: String, 
-    pub credential: String,
+    pub credential: crate::redaction::Secret<String>,
 }
 
 /// Transport performance metrics
@@ -326,36 +366,170 @@ pub trait Transport: Send + Sync {
     /// Get reliability score (0.0-1.0)
     fn reliability_score(&self) -> f32;
 }
+/// Routes discovered to a target, with what traffic over them measured since
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct DiscoveredRoutes {
+    pub routes: Vec<TransportRoute>,
+    pub metrics: TransportMetrics,
+    /// When the routes were probed or last confirmed by a successful send
+    pub confirmed_at: Instant,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DiscoveredRoutes {
+    /// Whether any route other than the email fallback was found
+    pub fn has_direct_route(&self) -> bool {
+        self.routes.iter().any(|route| !matches!(route, TransportRoute::StandardEmail { .. }))
+    }
+}
+/// Per-target cache of discovery results, shared with the router that sends over them
+
+#[cfg(not(target_arch = "wasm32"))]
+///
+/// Routes are reused without probing while fresh. Successful sends confirm
+/// them and backfill the measured latency; a failed send drops them so the
+/// next send probes again.
+#[derive(Debug)]
+pub struct DiscoveryCache {
+    entries: dashmap::DashMap<String, DiscoveredRoutes>,
+    /// How long routes including a direct path are reused
+    fresh_for: Duration,
+    /// How long a target only reachable by email is left unprobed
+    unreachable_for: Duration,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for DiscoveryCache {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(300), Duration::from_secs(30))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DiscoveryCache {
+    pub fn new(fresh_for: Duration, unreachable_for: Duration) -> Self {
+        Self {
+            entries: dashmap::DashMap::new(),
+            fresh_for,
+            unreachable_for,
+        }
+    }
+
+    /// Routes to a target that are still fresh
+    pub fn routes(&self, target: &str) -> Option<Vec<TransportRoute>> {
+        let entry = self.entries.get(target)?;
+        let window = if entry.has_direct_route() { self.fresh_for } else { self.unreachable_for };
+        (entry.confirmed_at.elapsed() < window).then(|| entry.routes.clone())
+    }
+
+    /// Metrics measured for a target, fresh or not
+    pub fn metrics(&self, target: &str) -> Option<TransportMetrics> {
+        self.entries.get(target).map(|entry| entry.metrics.clone())
+    }
+
+    /// Record freshly probed routes
+    pub fn insert(&self, target: &str, routes: Vec<TransportRoute>) {
+        let latency = routes
+            .iter()
+            .filter_map(|route| match route {
+                TransportRoute::DirectTcp { latency_ms, .. }
+                | TransportRoute::DirectUdp { latency_ms, .. }
+                | TransportRoute::LocalMdns { latency_ms, .. }
+                | TransportRoute::NatTraversal { latency_ms, .. } => Some(*latency_ms),
+                _ => None,
+            })
+            .min();
+        let mut metrics = TransportMetrics::default();
+        if let Some(latency_ms) = latency {
+            metrics.latency = Duration::from_millis(u64::from(latency_ms));
+        }
+        let now = Instant::now();
+        metrics.last_updated = now;
+        self.entries.insert(target.to_string(), DiscoveredRoutes { routes, metrics, confirmed_at: now });
+    }
+
+    /// Backfill a successful send's latency and keep the routes fresh
+    pub fn record_success(&self, target: &str, latency: Duration) {
+        let Some(mut entry) = self.entries.get_mut(target) else {
+            return;
+        };
+        let now = Instant::now();
+        let metrics = &mut entry.metrics;
+        // Smooth so one slow send does not reorder routes
+        let smoothed = (metrics.latency.as_micros() * 4 + latency.as_micros()) / 5;
+        metrics.latency = Duration::from_micros(smoothed as u64);
+        metrics.reliability_score = (metrics.reliability_score * 0.9 + 0.1).min(1.0);
+        metrics.last_updated = now;
+        entry.confirmed_at = now;
+    }
+
+    /// Forget a target's routes after a failed send
+    pub fn invalidate(&self, target: &str) -> bool {
+        self.entries.remove(target).is_some()
+    }
+}
+
+/// Probes a discovery runs at once unless configured otherwise
+pub const DEFAULT_PARALLEL_PROBES: usize = 8;
 
 /// Transport discovery and testing utilities
 pub struct TransportDiscovery {
     discovery_timeout: Duration,
-    #[allow(dead_code)]
-    connectivity_cache: dashmap::DashMap<String, (TransportMetrics, Instant)>,
+    /// Ports probed, in order, on targets given without a port
+    peer_ports: Vec<u16>,
+    /// Recently discovered routes, so warm peers are not probed on every send
+    #[cfg(not(target_arch = "wasm32"))]
+    connectivity_cache: Arc<DiscoveryCache>,
+    /// Bounds how many probes run at once, across all discoveries
+    #[cfg(not(target_arch = "wasm32"))]
+    probe_permits: Arc<tokio::sync::Semaphore>,
 }
 
 impl TransportDiscovery {
     pub fn new() -> Self {
         Self {
             discovery_timeout: Duration::from_secs(10),
-            connectivity_cache: dashmap::DashMap::new(),
+            peer_ports: crate::config::DEFAULT_PEER_PORTS.to_vec(),
+            #[cfg(not(target_arch = "wasm32"))]
+            connectivity_cache: Arc::new(DiscoveryCache::default()),
+            #[cfg(not(target_arch = "wasm32"))]
+            probe_permits: Arc::new(tokio::sync::Semaphore::new(DEFAULT_PARALLEL_PROBES)),
         }
     }
+
+    /// Set how many probes may run at once
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_max_parallel_probes(&mut self, max: usize) {
+        self.probe_permits = Arc::new(tokio::sync::Semaphore::new(max.max(1)));
+    }
+
+    /// Set the ports probed on targets given without a port
+    pub fn set_peer_ports(&mut self, ports: Vec<u16>) {
+        self.peer_ports = ports;
+    }
+
+    /// Cache of discovered routes, for recording how sends over them went
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cache(&self) -> Arc<DiscoveryCache> {
+        Arc::clone(&self.connectivity_cache)
+    }
     
     /// Discover all available transports to a target (non-WASM platforms)
+    ///
+    /// Fresh results from earlier discovery are returned without probing.
+    /// Otherwise every peer port is probed over TCP and UDP at once, at most
+    /// `max_parallel_probes` at a time across all discoveries. Discovery
+    /// returns as soon as a TCP connection confirms a route; the remaining
+    /// probes carry on in the background and add their routes to the cache.
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn discover_transports(&mut self, target: &str) -> Result<Vec<TransportRoute>> {
-        let mut routes = Vec::new();
-        
-        // Try direct TCP connection
-        if let Ok(tcp_route) = self.test_direct_tcp(target).await {
-            routes.push(tcp_route);
-        }
-        
-        // Try direct UDP connection
-        if let Ok(udp_route) = self.test_direct_udp(target).await {
-            routes.push(udp_route);
+        if let Some(routes) = self.connectivity_cache.routes(target) {
+            return Ok(routes);
         }
+
+        let mut routes = Vec::new();
         
         // Try mDNS discovery on local network
         if let Ok(mdns_route) = self.discover_mdns_peer(target).await {
@@ -366,12 +540,42 @@ impl TransportDiscovery {
         if let Ok(nat_routes) = self.establish_nat_traversal(target).await {
             routes.extend(nat_routes);
         }
+
+        // Race direct probes until one confirms a route
+        let mut probes = self.spawn_direct_probes(target);
+        while let Some(joined) = probes.join_next().await {
+            if let Ok(Some((route, confirmed))) = joined {
+                routes.push(route);
+                if confirmed {
+                    break;
+                }
+            }
+        }
         
         // Always include email fallback
         routes.push(TransportRoute::StandardEmail { 
             estimated_latency_min: 1 // 1+ minute typical email latency
         });
         
+        self.connectivity_cache.insert(target, routes.clone());
+
+        if !probes.is_empty() {
+            let cache = Arc::clone(&self.connectivity_cache);
+            let target = target.to_string();
+            let mut alternatives = routes.clone();
+            tokio::spawn(async move {
+                let before = alternatives.len();
+                while let Some(joined) = probes.join_next().await {
+                    if let Ok(Some((route, _))) = joined {
+                        // Keep the email fallback last
+                        alternatives.insert(alternatives.len() - 1, route);
+                    }
+                }
+                if alternatives.len() > before {
+                    cache.insert(&target, alternatives);
+                }
+            });
+        }
         Ok(routes)
     }
     
@@ -384,62 +588,58 @@ impl TransportDiscovery {
         }])
     }
     
+    /// Start TCP and UDP probes of every peer port, bounded by the probe semaphore
+    ///
+    /// Each probe yields its route, and whether a connection confirmed it.
     #[cfg(not(target_arch = "wasm32"))]
-    async fn test_direct_tcp(&self, target: &str) -> Result<TransportRoute> {
-        // Try common ports for EMRP TCP transport
-        let ports = vec![8080, 8443, 9090, 7777];
-        
-        for port in ports {
-            let address = format!("{}:{}", target, port);
-            let start = Instant::now();
-            
-            match timeout(self.discovery_timeout, tokio::net::TcpStream::connect(&address)).await {
-                Ok(Ok(_stream)) => {
-                    let latency = start.elapsed();
-                    return Ok(TransportRoute::DirectTcp {
-                        address: target.to_string(),
-                        port,
-                        latency_ms: latency.as_millis() as u32,
-                        established_at: Instant::now(),
-                    });
-                }
-                _ => continue,
+    fn spawn_direct_probes(&self, target: &str) -> tokio::task::JoinSet<Option<(TransportRoute, bool)>> {
+        let mut probes = tokio::task::JoinSet::new();
+        for &port in &self.peer_ports {
+            for udp in [false, true] {
+                let semaphore = Arc::clone(&self.probe_permits);
+                let target = target.to_string();
+                let probe_timeout = self.discovery_timeout;
+                probes.spawn(async move {
+                    let _permit = semaphore.acquire_owned().await.ok()?;
+                    if udp {
+                        Self::probe_udp(target, port, probe_timeout).await.map(|route| (route, false))
+                    } else {
+                        Self::probe_tcp(target, port, probe_timeout).await.map(|route| (route, true))
+                    }
+                });
             }
         }
-        
-        Err(crate::error::SynapseError::TransportError("No direct TCP connection available".into()))
+        probes
     }
-    
+
     #[cfg(not(target_arch = "wasm32"))]
-    async fn test_direct_udp(&self, target: &str) -> Result<TransportRoute> {
-        // Try common ports for EMRP UDP transport
-        let ports = vec![8080, 8443, 9090, 7777];
-        
-        for port in ports {
-            let address = format!("{}:{}", target, port);
-            let start = Instant::now();
-            
-            match timeout(
-                self.discovery_timeout,
-                tokio::net::UdpSocket::bind("0.0.0.0:0")
-            ).await {
-                Ok(Ok(socket)) => {
-                    // Test UDP connectivity with a ping packet
-                    if socket.connect(&address).await.is_ok() {
-                        let latency = start.elapsed();
-                        return Ok(TransportRoute::DirectUdp {
-                            address: target.to_string(),
-                            port,
-                            latency_ms: latency.as_millis() as u32,
-                            established_at: Instant::now(),
-                        });
-                    }
-                }
-                _ => continue,
-            }
+    async fn probe_tcp(target: String, port: u16, probe_timeout: Duration) -> Option<TransportRoute> {
+        let address = format!("{}:{}", target, port);
+        let start = Instant::now();
+        match timeout(probe_timeout, tokio::net::TcpStream::connect(&address)).await {
+            Ok(Ok(_stream)) => Some(TransportRoute::DirectTcp {
+                address: target,
+                port,
+                latency_ms: start.elapsed().as_millis() as u32,
+                established_at: Instant::now(),
+            }),
+            _ => None,
         }
-        
-        Err(crate::error::SynapseError::TransportError("No direct UDP connection available".into()))
+    }
+    
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn probe_udp(target: String, port: u16, probe_timeout: Duration) -> Option<TransportRoute> {
+        let address = format!("{}:{}", target, port);
+        let start = Instant::now();
+        let socket = timeout(probe_timeout, tokio::net::UdpSocket::bind("0.0.0.0:0")).await.ok()?.ok()?;
+        // Connecting a UDP socket only checks that the address resolves and is routable
+        socket.connect(&address).await.ok()?;
+        Some(TransportRoute::DirectUdp {
+            address: target,
+            port,
+            latency_ms: start.elapsed().as_millis() as u32,
+            established_at: Instant::now(),
+        })
     }
     
     #[cfg(not(target_arch = "wasm32"))]
@@ -490,6 +690,22 @@ impl TransportSelector {
         }
     }
     
+    /// Set the ports discovery probes on targets given without a port
+    pub fn set_peer_ports(&mut self, ports: Vec<u16>) {
+        self.discovery.set_peer_ports(ports);
+    }
+    /// Set how many discovery probes may run at once
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_max_parallel_probes(&mut self, max: usize) {
+        self.discovery.set_max_parallel_probes(max);
+    }
+
+    /// Cache of discovered routes, shared so sends can update it without the selector lock
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn discovery_cache(&self) -> Arc<DiscoveryCache> {
+        self.discovery.cache()
+    }
+
     /// Choose the optimal transport for a message (non-WASM platforms)
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn choose_optimal_transport(
@@ -580,7 +796,8 @@ impl TransportSelector {
             TransportRoute::DirectTcp { latency_ms, .. } 
             | TransportRoute::DirectUdp { latency_ms, .. }
             | TransportRoute::LocalMdns { latency_ms, .. }
-            | TransportRoute::NatTraversal { latency_ms, .. } => *latency_ms,
+            | TransportRoute::NatTraversal { latency_ms, .. }
+            | TransportRoute::Custom { latency_ms, .. } => *latency_ms,
             TransportRoute::FastEmailRelay { estimated_latency_ms, .. } => *estimated_latency_ms,
             TransportRoute::StandardEmail { estimated_latency_min } => estimated_latency_min * 60 * 1000, // Convert minutes to ms
             TransportRoute::EmailDiscovery { .. } => 30_000, // 30s for discovery
@@ -619,6 +836,7 @@ impl TransportSelector {
             TransportRoute::Udp { .. } => 0.80,
             TransportRoute::WebSocket { .. } => 0.85,
             TransportRoute::Quic { .. } => 0.90,
+            TransportRoute::Custom { .. } => 0.80,
         }
     }
     
@@ -635,6 +853,7 @@ impl TransportSelector {
             TransportRoute::Udp { .. } => 0.05, // 50ms
             TransportRoute::WebSocket { .. } => 0.2, // 200ms
             TransportRoute::Quic { .. } => 0.1, // 100ms
+            TransportRoute::Custom { .. } => 0.1, // 100ms
         }
     }
 }
@@ -672,10 +891,63 @@ pub use nat_traversal::{NatTraversalTransport, IceCandidate};
 #[cfg(feature = "email")]
 pub use email_enhanced::{EmailEnhancedTransport, FastEmailRelay};
 #[cfg(not(target_arch = "wasm32"))]
-pub use router::MultiTransportRouter;
+pub use router::{MultiTransportRouter, PreparedRoute};
 #[cfg(not(target_arch = "wasm32"))]
 pub use llm_discovery::{
     LlmDiscoveryManager, LlmDiscoveryConfig, DiscoveredLlm, LlmConnection,
     LlmModelInfo, LlmConnectionInfo, LlmPerformanceMetrics, LlmStatus,
     LlmRequest, LlmResponse, LlmResponseMetadata
 };
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    fn tcp_route(latency_ms: u32) -> TransportRoute {
+        TransportRoute::DirectTcp {
+            address: "10.0.0.5".to_string(),
+            port: 8080,
+            latency_ms,
+            established_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn discovered_routes_are_reused_until_a_send_fails() {
+        let cache = DiscoveryCache::default();
+        cache.insert("bob", vec![tcp_route(40), TransportRoute::StandardEmail { estimated_latency_min: 1 }]);
+        assert_eq!(cache.routes("bob").unwrap().len(), 2);
+        assert_eq!(cache.metrics("bob").unwrap().latency, Duration::from_millis(40));
+
+        cache.record_success("bob", Duration::from_millis(20));
+        assert_eq!(cache.metrics("bob").unwrap().latency, Duration::from_millis(36));
+
+        assert!(cache.invalidate("bob"));
+        assert!(cache.routes("bob").is_none());
+    }
+
+    #[test]
+    fn email_only_targets_are_reprobed_sooner() {
+        let cache = DiscoveryCache::new(Duration::from_secs(300), Duration::ZERO);
+        cache.insert("carol", vec![TransportRoute::StandardEmail { estimated_latency_min: 1 }]);
+        cache.insert("bob", vec![tcp_route(40)]);
+        assert!(cache.routes("carol").is_none());
+        assert!(cache.routes("bob").is_some());
+    }
+
+    #[tokio::test]
+    async fn discovery_probes_ports_concurrently_and_caches_the_result() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open_port = listener.local_addr().unwrap().port();
+        let closed_port = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+
+        let mut discovery = TransportDiscovery::new();
+        discovery.set_peer_ports(vec![closed_port, open_port]);
+        discovery.set_max_parallel_probes(2);
+
+        let routes = discovery.discover_transports("127.0.0.1").await.unwrap();
+        assert!(routes.iter().any(|r| matches!(r, TransportRoute::DirectTcp { port, .. } if *port == open_port)));
+        assert!(matches!(routes.last(), Some(TransportRoute::StandardEmail { .. })));
+        assert!(discovery.cache().routes("127.0.0.1").is_some());
+    }
+}
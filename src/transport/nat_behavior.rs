@@ -0,0 +1,423 @@
+//! NAT behavior discovery and traversal strategy selection
+//!
+//! Whether two peers behind NATs can reach each other depends on how both
+//! NATs behave. Following RFC 5780, a STUN server that reports an alternate
+//! address (`OTHER-ADDRESS`) lets us measure our NAT's *mapping* behavior
+//! (does the public port change with the destination?) and its *filtering*
+//! behavior (who may send to a mapped port?). The two combine into the
+//! classic [`NatType`]s, and [`select_strategy`] picks hole punching, TURN or
+//! a relay for a pair of them.
+//!
+//! Our classification is cached in [`NatTypeCache`] and advertised to peers in
+//! capability digests, so each side knows the other's type before connecting.
+
+use crate::error::{Result, SynapseError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const BINDING_INDICATION: u16 = 0x0011;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_CHANGE_REQUEST: u16 = 0x0003;
+const ATTR_CHANGED_ADDRESS: u16 = 0x0005;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const ATTR_RESPONSE_ORIGIN: u16 = 0x802B;
+const ATTR_OTHER_ADDRESS: u16 = 0x802C;
+const CHANGE_IP: u32 = 0x04;
+const CHANGE_PORT: u32 = 0x02;
+
+/// How a NAT maps or filters, per RFC 4787
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NatBehavior {
+    EndpointIndependent,
+    AddressDependent,
+    AddressAndPortDependent,
+}
+
+/// Classic NAT types, from the most to the least permissive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NatType {
+    /// Not behind a NAT, and unsolicited traffic gets through
+    OpenInternet,
+    /// One public port per local port, open to anyone once mapped
+    FullCone,
+    /// One public port per local port, open to addresses we sent to
+    RestrictedCone,
+    /// One public port per local port, open to address and port pairs we sent to
+    PortRestrictedCone,
+    /// A new public port per destination; hole punching rarely works
+    Symmetric,
+    /// No STUN answer at all: UDP is blocked
+    UdpBlocked,
+    /// The STUN server could not run the tests needed to tell
+    Unknown,
+}
+
+impl NatType {
+    /// Combine measured behaviors; `behind_nat` is false when the mapped
+    /// address is our own
+    ///
+    /// A firewall without NAT that filters like one counts as the matching cone.
+    pub fn from_behavior(behind_nat: bool, mapping: Option<NatBehavior>, filtering: Option<NatBehavior>) -> Self {
+        let mapping = if behind_nat { mapping } else { Some(NatBehavior::EndpointIndependent) };
+        match (mapping, filtering) {
+            (Some(NatBehavior::EndpointIndependent), Some(NatBehavior::EndpointIndependent)) if !behind_nat => {
+                NatType::OpenInternet
+            }
+            (Some(NatBehavior::EndpointIndependent), Some(NatBehavior::EndpointIndependent)) => NatType::FullCone,
+            (Some(NatBehavior::EndpointIndependent), Some(NatBehavior::AddressDependent)) => NatType::RestrictedCone,
+            (Some(NatBehavior::EndpointIndependent), Some(NatBehavior::AddressAndPortDependent)) => {
+                NatType::PortRestrictedCone
+            }
+            (Some(NatBehavior::AddressDependent | NatBehavior::AddressAndPortDependent), _) => NatType::Symmetric,
+            _ => NatType::Unknown,
+        }
+    }
+}
+
+/// How two peers should connect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraversalStrategy {
+    /// One side accepts unsolicited traffic; connect to it
+    Direct,
+    /// Both send to each other's mapped address to open the NATs
+    HolePunch,
+    /// Relay UDP through a TURN server
+    Turn,
+    /// Hand the message to a Synapse relay or email
+    Relay,
+}
+
+/// Pick how we (`ours`) and a peer (`theirs`) should connect
+///
+/// Hole punching fails when a symmetric NAT meets another symmetric or a
+/// port-restricted NAT, since the port the peer punched towards is not the
+/// one the symmetric side then uses. Those pairs go through TURN if a server
+/// is available. Unknown types get a hole punching attempt, which callers
+/// fall back from as usual.
+pub fn select_strategy(ours: NatType, theirs: NatType, turn_available: bool) -> TraversalStrategy {
+    use NatType::*;
+    let relayed = if turn_available { TraversalStrategy::Turn } else { TraversalStrategy::Relay };
+    match (ours, theirs) {
+        (UdpBlocked, _) | (_, UdpBlocked) => TraversalStrategy::Relay,
+        (OpenInternet, _) | (_, OpenInternet) => TraversalStrategy::Direct,
+        (Symmetric, Symmetric | PortRestrictedCone) | (PortRestrictedCone, Symmetric) => relayed,
+        _ => TraversalStrategy::HolePunch,
+    }
+}
+
+/// Our NAT as measured against a STUN server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NatClassification {
+    pub nat_type: NatType,
+    pub mapping: Option<NatBehavior>,
+    pub filtering: Option<NatBehavior>,
+    /// Our public address as the server saw it
+    pub mapped_address: Option<SocketAddr>,
+    /// Server the tests ran against
+    pub server: String,
+    pub classified_at: DateTime<Utc>,
+}
+
+/// Our latest classification, kept for a while since NATs rarely change
+pub struct NatTypeCache {
+    ttl: Duration,
+    current: RwLock<Option<(NatClassification, Instant)>>,
+}
+
+impl NatTypeCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, current: RwLock::new(None) }
+    }
+
+    /// The cached classification, unless it has expired
+    pub fn get(&self) -> Option<NatClassification> {
+        let current = self.current.read().unwrap();
+        current
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < self.ttl)
+            .map(|(classification, _)| classification.clone())
+    }
+
+    /// Our NAT type, `Unknown` until classified
+    pub fn nat_type(&self) -> NatType {
+        self.get().map_or(NatType::Unknown, |c| c.nat_type)
+    }
+
+    pub fn store(&self, classification: NatClassification) {
+        *self.current.write().unwrap() = Some((classification, Instant::now()));
+    }
+
+    /// Drop the classification, e.g. after a network change
+    pub fn clear(&self) {
+        *self.current.write().unwrap() = None;
+    }
+}
+
+/// What a binding response told us
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindingResponse {
+    /// Our address as the server saw it
+    pub mapped: SocketAddr,
+    /// The server's alternate address, for the RFC 5780 tests
+    pub other: Option<SocketAddr>,
+    /// Address the response was sent from
+    pub origin: Option<SocketAddr>,
+}
+
+/// A STUN binding request, optionally asking for the answer from the
+/// server's other IP address and/or port
+pub fn binding_request(transaction_id: [u8; 12], change_ip: bool, change_port: bool) -> Vec<u8> {
+    let flags = if change_ip { CHANGE_IP } else { 0 } | if change_port { CHANGE_PORT } else { 0 };
+    let attributes_len: u16 = if flags != 0 { 8 } else { 0 };
+    let mut packet = Vec::with_capacity(20 + attributes_len as usize);
+    packet.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+    packet.extend_from_slice(&attributes_len.to_be_bytes());
+    packet.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    packet.extend_from_slice(&transaction_id);
+    if flags != 0 {
+        packet.extend_from_slice(&ATTR_CHANGE_REQUEST.to_be_bytes());
+        packet.extend_from_slice(&4u16.to_be_bytes());
+        packet.extend_from_slice(&flags.to_be_bytes());
+    }
+    packet
+}
+
+/// A binding indication: a 20-byte STUN packet that expects no answer, used
+/// to refresh NAT mappings (RFC 5389 section 10.1.2)
+pub fn binding_indication(transaction_id: [u8; 12]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(20);
+    packet.extend_from_slice(&BINDING_INDICATION.to_be_bytes());
+    packet.extend_from_slice(&0u16.to_be_bytes());
+    packet.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    packet.extend_from_slice(&transaction_id);
+    packet
+}
+
+/// Parse a binding success response to the request with `transaction_id`
+pub fn parse_binding_response(data: &[u8], transaction_id: &[u8; 12]) -> Option<BindingResponse> {
+    if data.len() < 20
+        || u16::from_be_bytes([data[0], data[1]]) != BINDING_SUCCESS
+        || u32::from_be_bytes([data[4], data[5], data[6], data[7]]) != MAGIC_COOKIE
+        || &data[8..20] != transaction_id
+    {
+        return None;
+    }
+    let length = u16::from_be_bytes([data[2], data[3]]) as usize;
+    let attributes = data.get(20..20 + length)?;
+
+    let (mut mapped, mut xor_mapped, mut other, mut origin) = (None, None, None, None);
+    let mut offset = 0;
+    while offset + 4 <= attributes.len() {
+        let kind = u16::from_be_bytes([attributes[offset], attributes[offset + 1]]);
+        let len = u16::from_be_bytes([attributes[offset + 2], attributes[offset + 3]]) as usize;
+        let value = attributes.get(offset + 4..offset + 4 + len)?;
+        match kind {
+            ATTR_MAPPED_ADDRESS => mapped = parse_address(value, None),
+            ATTR_XOR_MAPPED_ADDRESS => xor_mapped = parse_address(value, Some(transaction_id)),
+            ATTR_OTHER_ADDRESS | ATTR_CHANGED_ADDRESS => other = other.or(parse_address(value, None)),
+            ATTR_RESPONSE_ORIGIN => origin = parse_address(value, None),
+            _ => {}
+        }
+        // Attributes are padded to a multiple of four bytes
+        offset += 4 + len.div_ceil(4) * 4;
+    }
+    Some(BindingResponse { mapped: xor_mapped.or(mapped)?, other, origin })
+}
+
+/// Decode a (XOR-)MAPPED-ADDRESS style value; XOR-ed when a transaction ID is given
+fn parse_address(value: &[u8], xor_with: Option<&[u8; 12]>) -> Option<SocketAddr> {
+    if value.len() < 4 {
+        return None;
+    }
+    let cookie = MAGIC_COOKIE.to_be_bytes();
+    let mut port = u16::from_be_bytes([value[2], value[3]]);
+    if xor_with.is_some() {
+        port ^= (MAGIC_COOKIE >> 16) as u16;
+    }
+    let ip = match value[1] {
+        0x01 => {
+            let mut octets: [u8; 4] = value.get(4..8)?.try_into().ok()?;
+            if xor_with.is_some() {
+                octets.iter_mut().zip(cookie).for_each(|(b, k)| *b ^= k);
+            }
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        0x02 => {
+            let mut octets: [u8; 16] = value.get(4..20)?.try_into().ok()?;
+            if let Some(transaction_id) = xor_with {
+                let key = cookie.iter().chain(transaction_id.iter());
+                octets.iter_mut().zip(key).for_each(|(b, k)| *b ^= k);
+            }
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Send one binding request from `socket` and wait for its answer
+///
+/// `None` means no answer within `timeout`, which the filtering tests expect
+/// from restrictive NATs.
+pub async fn binding(
+    socket: &UdpSocket,
+    server: SocketAddr,
+    change_ip: bool,
+    change_port: bool,
+    timeout: Duration,
+) -> Result<Option<BindingResponse>> {
+    let transaction_id: [u8; 12] = rand::random();
+    socket.send_to(&binding_request(transaction_id, change_ip, change_port), server).await?;
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut buffer = [0u8; 1024];
+    loop {
+        match tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+            Ok(Ok((len, _))) => {
+                // Stray packets and answers to earlier tests are skipped
+                if let Some(response) = parse_binding_response(&buffer[..len], &transaction_id) {
+                    return Ok(Some(response));
+                }
+            }
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => return Ok(None),
+        }
+    }
+}
+
+/// Run the RFC 5780 mapping and filtering tests against `server`
+pub async fn classify_with(socket: &UdpSocket, server: SocketAddr, timeout: Duration) -> Result<NatClassification> {
+    let classification = |nat_type, mapping, filtering, mapped_address| NatClassification {
+        nat_type,
+        mapping,
+        filtering,
+        mapped_address,
+        server: server.to_string(),
+        classified_at: Utc::now(),
+    };
+
+    let Some(first) = binding(socket, server, false, false, timeout).await? else {
+        return Ok(classification(NatType::UdpBlocked, None, None, None));
+    };
+    let local_ip = route_local_ip(server).await?;
+    let behind_nat = first.mapped.ip() != local_ip || first.mapped.port() != socket.local_addr()?.port();
+    let Some(other) = first.other else {
+        debug!("{} does not report an alternate address; NAT type unknown", server);
+        return Ok(classification(NatType::Unknown, None, None, Some(first.mapped)));
+    };
+
+    let filtering = if binding(socket, server, true, true, timeout).await?.is_some() {
+        Some(NatBehavior::EndpointIndependent)
+    } else if binding(socket, server, false, true, timeout).await?.is_some() {
+        Some(NatBehavior::AddressDependent)
+    } else {
+        Some(NatBehavior::AddressAndPortDependent)
+    };
+
+    let mapping = if !behind_nat {
+        Some(NatBehavior::EndpointIndependent)
+    } else {
+        let other_ip = SocketAddr::new(other.ip(), server.port());
+        match binding(socket, other_ip, false, false, timeout).await? {
+            Some(second) if second.mapped == first.mapped => Some(NatBehavior::EndpointIndependent),
+            Some(second) => match binding(socket, other, false, false, timeout).await? {
+                Some(third) if third.mapped == second.mapped => Some(NatBehavior::AddressDependent),
+                Some(_) => Some(NatBehavior::AddressAndPortDependent),
+                None => None,
+            },
+            None => None,
+        }
+    };
+
+    let nat_type = NatType::from_behavior(behind_nat, mapping, filtering);
+    Ok(classification(nat_type, mapping, filtering, Some(first.mapped)))
+}
+
+/// Classify our NAT against the first of `servers` that answers
+pub async fn classify(servers: &[String], timeout: Duration) -> Result<NatClassification> {
+    let mut blocked = None;
+    let mut last_error = None;
+    for server in servers {
+        let address = match tokio::net::lookup_host(server.as_str()).await {
+            Ok(mut addresses) => match addresses.find(SocketAddr::is_ipv4) {
+                Some(address) => address,
+                None => continue,
+            },
+            Err(e) => {
+                last_error = Some(SynapseError::from(e));
+                continue;
+            }
+        };
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        match classify_with(&socket, address, timeout).await {
+            Ok(classification) if classification.nat_type == NatType::UdpBlocked => blocked = Some(classification),
+            Ok(classification) => return Ok(classification),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    // Servers resolved but none answered
+    if let Some(classification) = blocked {
+        return Ok(classification);
+    }
+    Err(last_error.unwrap_or_else(|| SynapseError::ConfigurationError("No STUN servers configured".to_string())))
+}
+
+/// Our address on the interface that routes to `server`
+async fn route_local_ip(server: SocketAddr) -> Result<IpAddr> {
+    let probe = UdpSocket::bind("0.0.0.0:0").await?;
+    probe.connect(server).await?;
+    Ok(probe.local_addr()?.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_responses_decode_and_types_pick_strategies() {
+        let transaction_id = [7u8; 12];
+        let request = binding_request(transaction_id, true, true);
+        assert_eq!(request.len(), 28);
+        assert_eq!(&request[20..28], &[0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x00, 0x06]);
+
+        // Success response with XOR-MAPPED-ADDRESS 203.0.113.7:40212 and OTHER-ADDRESS 198.51.100.2:3479
+        let mut response = vec![0x01, 0x01, 0x00, 0x18];
+        response.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        response.extend_from_slice(&transaction_id);
+        let xor_port = 40212u16 ^ (MAGIC_COOKIE >> 16) as u16;
+        let xor_ip: Vec<u8> = [203u8, 0, 113, 7].iter().zip(MAGIC_COOKIE.to_be_bytes()).map(|(b, k)| b ^ k).collect();
+        response.extend_from_slice(&[0x00, 0x20, 0x00, 0x08, 0x00, 0x01]);
+        response.extend_from_slice(&xor_port.to_be_bytes());
+        response.extend_from_slice(&xor_ip);
+        response.extend_from_slice(&[0x80, 0x2C, 0x00, 0x08, 0x00, 0x01, 0x0D, 0x97, 198, 51, 100, 2]);
+
+        let parsed = parse_binding_response(&response, &transaction_id).unwrap();
+        assert_eq!(parsed.mapped, "203.0.113.7:40212".parse().unwrap());
+        assert_eq!(parsed.other, Some("198.51.100.2:3479".parse().unwrap()));
+        assert!(parse_binding_response(&response, &[0u8; 12]).is_none());
+
+        use NatBehavior::*;
+        assert_eq!(NatType::from_behavior(true, Some(EndpointIndependent), Some(AddressDependent)), NatType::RestrictedCone);
+        assert_eq!(NatType::from_behavior(true, Some(AddressAndPortDependent), None), NatType::Symmetric);
+        assert_eq!(NatType::from_behavior(false, None, Some(EndpointIndependent)), NatType::OpenInternet);
+        assert_eq!(NatType::from_behavior(true, None, Some(EndpointIndependent)), NatType::Unknown);
+
+        assert_eq!(select_strategy(NatType::Symmetric, NatType::FullCone, false), TraversalStrategy::HolePunch);
+        assert_eq!(select_strategy(NatType::Symmetric, NatType::Symmetric, true), TraversalStrategy::Turn);
+        assert_eq!(select_strategy(NatType::PortRestrictedCone, NatType::Symmetric, false), TraversalStrategy::Relay);
+        assert_eq!(select_strategy(NatType::OpenInternet, NatType::Symmetric, false), TraversalStrategy::Direct);
+        assert_eq!(select_strategy(NatType::UdpBlocked, NatType::OpenInternet, true), TraversalStrategy::Relay);
+    }
+}
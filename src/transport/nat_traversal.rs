@@ -1,29 +1,31 @@
 //! Simplified NAT traversal techniques for EMRP
 
 use super::abstraction::{self, Transport};
-use crate::{types::SecureMessage, error::{Result, SynapseError}};
+use super::nat_behavior::{self, NatClassification, NatType, TraversalStrategy};
+use super::keepalive::NAT_TRAVERSAL_FEATURE;
+use crate::{types::SecureMessage, error::{Result, SynapseError}, geo::RegionAffinity};
 use async_trait::async_trait;
 use std::{time::{Duration, Instant}, net::SocketAddr, collections::HashMap};
 use serde::{Serialize, Deserialize};
 use tracing::{info, debug, warn};
 use tokio::net::UdpSocket;
 
-/// TURN server configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TurnServer {
-    pub server: String,
-    pub username: String,
-    pub password: String,
-}
+pub use crate::config::TurnServer;
+
+/// How long a NAT classification is reused before the tests run again
+const CLASSIFICATION_TTL: Duration = Duration::from_secs(3600);
 
 /// NAT traversal transport supporting multiple techniques
 pub struct NatTraversalTransport {
     local_port: u16,
     stun_servers: Vec<String>,
     turn_servers: Vec<TurnServer>,
+    region: Option<String>,
     upnp_enabled: bool,
     ice_candidates: HashMap<String, IceCandidate>,
     external_address: Option<SocketAddr>,
+    /// Our NAT type and when it was measured
+    classification: Option<(NatClassification, Instant)>,
 }
 
 /// ICE candidate for connectivity establishment
@@ -70,9 +72,11 @@ impl NatTraversalTransport {
             local_port,
             stun_servers,
             turn_servers: Vec::new(),
+            region: None,
             upnp_enabled: true,
             ice_candidates: HashMap::new(),
             external_address: None,
+            classification: None,
         })
     }
     
@@ -80,6 +84,22 @@ impl NatTraversalTransport {
     pub fn add_turn_server(&mut self, turn_server: TurnServer) {
         self.turn_servers.push(turn_server);
     }
+
+    /// Region this node runs in; TURN servers in or near it are tried first
+    pub fn with_region(mut self, region: Option<String>) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// TURN servers, nearest first
+    pub fn turn_servers_by_proximity(&self) -> Vec<TurnServer> {
+        let mut servers = self.turn_servers.clone();
+        // Stable, so configured order breaks ties
+        servers.sort_by_key(|server| {
+            std::cmp::Reverse(RegionAffinity::between(self.region.as_deref(), server.region.as_deref()))
+        });
+        servers
+    }
     
     /// Discover external address using STUN
     pub async fn discover_external_address(&mut self) -> Result<SocketAddr> {
@@ -105,66 +125,48 @@ impl NatTraversalTransport {
     /// Perform STUN query to discover external address
     async fn stun_query(&self, stun_server: &str) -> Result<SocketAddr> {
         let socket = UdpSocket::bind(format!("0.0.0.0:{}", self.local_port)).await?;
-        
-        // Create STUN binding request
-        let stun_request = self.create_stun_binding_request();
-        
-        // Send STUN request
-        socket.send_to(&stun_request, stun_server).await?;
-        
-        // Wait for response
-        let mut buffer = vec![0; 1024];
-        match tokio::time::timeout(Duration::from_secs(5), socket.recv_from(&mut buffer)).await {
-            Ok(Ok((bytes_received, _))) => {
-                if let Some(external_addr) = self.parse_stun_response(&buffer[..bytes_received]) {
-                    Ok(external_addr)
-                } else {
-                    Err(crate::error::SynapseError::TransportError("Invalid STUN response".into()))
-                }
-            }
-            Ok(Err(e)) => Err(crate::error::SynapseError::TransportError(format!("STUN receive error: {}", e))),
-            Err(_) => Err(crate::error::SynapseError::TransportError("STUN query timeout".into())),
+        let server = tokio::net::lookup_host(stun_server)
+            .await?
+            .find(SocketAddr::is_ipv4)
+            .ok_or_else(|| SynapseError::TransportError(format!("{} has no IPv4 address", stun_server)))?;
+        match nat_behavior::binding(&socket, server, false, false, Duration::from_secs(5)).await? {
+            Some(response) => Ok(response.mapped),
+            None => Err(SynapseError::TransportError("STUN query timeout".into())),
         }
     }
-    
-    /// Create STUN binding request packet
-    fn create_stun_binding_request(&self) -> Vec<u8> {
-        // Simplified STUN binding request
-        // Real implementation would use proper STUN library
-        let mut packet = Vec::new();
-        
-        // STUN header: Message Type (Binding Request = 0x0001)
-        packet.extend_from_slice(&0x0001u16.to_be_bytes());
-        
-        // Message Length (0 for basic request)
-        packet.extend_from_slice(&0x0000u16.to_be_bytes());
-        
-        // Magic Cookie
-        packet.extend_from_slice(&0x2112A442u32.to_be_bytes());
-        
-        // Transaction ID (12 bytes)
-        packet.extend_from_slice(&[0; 12]);
-        
-        packet
-    }
-    
-    /// Parse STUN response to extract external address
-    fn parse_stun_response(&self, data: &[u8]) -> Option<SocketAddr> {
-        // Simplified STUN response parsing
-        // Real implementation would use proper STUN library
-        if data.len() < 20 {
-            return None;
+
+    /// Classify our NAT from the listening port, reusing a fresh classification
+    pub async fn classify_nat(&mut self) -> Result<NatClassification> {
+        if let Some((classification, at)) = &self.classification {
+            if at.elapsed() < CLASSIFICATION_TTL {
+                return Ok(classification.clone());
+            }
         }
-        
-        // Check if it's a STUN success response (0x0101)
-        let message_type = u16::from_be_bytes([data[0], data[1]]);
-        if message_type != 0x0101 {
-            return None;
+        let socket = UdpSocket::bind(format!("0.0.0.0:{}", self.local_port)).await?;
+        let mut last_error = None;
+        for stun_server in &self.stun_servers {
+            let server = match tokio::net::lookup_host(stun_server.as_str()).await {
+                Ok(mut addresses) => match addresses.find(SocketAddr::is_ipv4) {
+                    Some(server) => server,
+                    None => continue,
+                },
+                Err(e) => {
+                    last_error = Some(SynapseError::from(e));
+                    continue;
+                }
+            };
+            match nat_behavior::classify_with(&socket, server, Duration::from_secs(2)).await {
+                Ok(classification) if classification.nat_type != NatType::UdpBlocked => {
+                    info!("NAT classified as {:?} via {}", classification.nat_type, stun_server);
+                    self.external_address = classification.mapped_address.or(self.external_address);
+                    self.classification = Some((classification.clone(), Instant::now()));
+                    return Ok(classification);
+                }
+                Ok(_) => debug!("No STUN answer from {}", stun_server),
+                Err(e) => last_error = Some(e),
+            }
         }
-        
-        // For demo purposes, return a placeholder external address
-        // Real implementation would parse XOR-MAPPED-ADDRESS attribute
-        "203.0.113.1:12345".parse().ok()
+        Err(last_error.unwrap_or_else(|| SynapseError::TransportError("No STUN server answered".into())))
     }
     
     /// Attempt UPnP port mapping
@@ -224,7 +226,7 @@ impl NatTraversalTransport {
         }
         
         // Relay candidates (via TURN)
-        for turn_server in &self.turn_servers.clone() {
+        for turn_server in &self.turn_servers_by_proximity() {
             if let Ok(relay_addr) = self.allocate_turn_address(turn_server).await {
                 candidates.push(IceCandidate {
                     candidate_type: CandidateType::Relay,
@@ -259,8 +261,27 @@ impl NatTraversalTransport {
         Ok("203.0.113.100:54321".parse().unwrap())
     }
     
-    /// Establish connectivity using best available method
-    pub async fn establish_connection(&mut self, _target: &str) -> Result<NatMethod> {
+    /// Establish connectivity using the method our and the peer's NAT types allow
+    ///
+    /// `peer_nat` is the type the peer advertised, if any. Pairs hole punching
+    /// cannot open go through TURN, or come back as [`NatMethod::Relay`] when
+    /// no TURN server is configured.
+    pub async fn establish_connection(&mut self, target: &str, peer_nat: Option<NatType>) -> Result<NatMethod> {
+        let ours = match self.classify_nat().await {
+            Ok(classification) => classification.nat_type,
+            Err(e) => {
+                debug!("Connecting to {} without our NAT type: {}", target, e);
+                NatType::Unknown
+            }
+        };
+        let strategy = nat_behavior::select_strategy(ours, peer_nat.unwrap_or(NatType::Unknown), !self.turn_servers.is_empty());
+        debug!("Connecting to {} by {:?} ({:?} to {:?})", target, strategy, ours, peer_nat);
+        match strategy {
+            TraversalStrategy::Relay => return Ok(NatMethod::Relay),
+            TraversalStrategy::Turn => return self.allocate_nearest_turn().await,
+            TraversalStrategy::Direct | TraversalStrategy::HolePunch => {}
+        }
+
         // Try UPnP first (fastest if available)
         if let Ok(_mapping) = self.setup_upnp_mapping().await {
             return Ok(NatMethod::Upnp);
@@ -273,13 +294,19 @@ impl NatTraversalTransport {
             });
         }
         
-        // Try TURN as fallback
-        if !self.turn_servers.is_empty() {
-            let turn_server = &self.turn_servers[0];
+        // Try TURN as fallback, through the nearest server
+        self.allocate_nearest_turn().await
+    }
+
+    /// Allocate a relay address on the nearest TURN server
+    async fn allocate_nearest_turn(&self) -> Result<NatMethod> {
+        let turn_servers = self.turn_servers_by_proximity();
+        if let Some(turn_server) = turn_servers.first() {
             if self.allocate_turn_address(turn_server).await.is_ok() {
                 return Ok(NatMethod::Turn { 
                     server: turn_server.server.clone(),
                     username: turn_server.username.clone(),
+                    region: turn_server.region.clone(),
                 });
             }
         }
@@ -302,7 +329,7 @@ impl NatTraversalTransport {
                     Err(crate::error::SynapseError::TransportError("No external address available".into()))
                 }
             }
-            NatMethod::Turn { server, username: _ } => {
+            NatMethod::Turn { server, .. } => {
                 // Relay via TURN server
                 self.send_via_turn_relay(target, message, server).await
             }
@@ -310,6 +337,10 @@ impl NatTraversalTransport {
                 // Use ICE connectivity establishment
                 self.send_via_ice(target, message).await
             }
+            NatMethod::Relay => Err(SynapseError::TransportError(format!(
+                "{} can only be reached through a relay",
+                target
+            ))),
         }
     }
     
@@ -361,7 +392,7 @@ impl Transport for NatTraversalTransport {
                 abstraction::MessageUrgency::Background
             ],
             features: vec![
-                "nat_traversal".to_string(),
+                NAT_TRAVERSAL_FEATURE.to_string(),
                 "stun".to_string(),
                 "turn".to_string(),
                 "ice".to_string(),
@@ -390,6 +421,8 @@ impl Transport for NatTraversalTransport {
             cost: 5.0, // Higher cost due to complexity and server usage
             available: self.can_reach(target).await,
             confidence: 0.6, // Lower confidence due to NAT unpredictability
+            // Relayed traffic would go through the nearest TURN server
+            region: self.turn_servers_by_proximity().first().and_then(|server| server.region.clone()),
         })
     }
     
@@ -436,6 +469,16 @@ impl Transport for NatTraversalTransport {
         })
     }
 
+    async fn send_keepalive(&self, target: &abstraction::TransportTarget) -> Result<()> {
+        let Some(addr) = target.address.as_deref().and_then(|addr| addr.parse::<SocketAddr>().ok()) else {
+            return Ok(());
+        };
+        // Sent from the traversal port so the mapping the peer uses is the one refreshed
+        let socket = UdpSocket::bind(format!("0.0.0.0:{}", self.local_port)).await?;
+        socket.send_to(&nat_behavior::binding_indication(rand::random()), addr).await?;
+        Ok(())
+    }
+
     async fn start(&self) -> Result<()> {
         // Would typically start NAT traversal services here
         Ok(())
@@ -489,8 +532,11 @@ pub enum NatMethod {
     Turn {
         server: String,
         username: String,
+        region: Option<String>,
     },
     IceCandidate,
+    /// Neither NAT can be opened; hand the message to a Synapse relay
+    Relay,
 }
 
 // Implement Clone for NatTraversalTransport
@@ -500,9 +546,11 @@ impl Clone for NatTraversalTransport {
             local_port: self.local_port,
             stun_servers: self.stun_servers.clone(),
             turn_servers: self.turn_servers.clone(),
+            region: self.region.clone(),
             upnp_enabled: self.upnp_enabled,
             ice_candidates: self.ice_candidates.clone(),
             external_address: self.external_address,
+            classification: self.classification.clone(),
         }
     }
 }
@@ -0,0 +1,501 @@
+//! Background link probing with historical trends
+//!
+//! `Transport::test_connectivity` gives a single snapshot. The [`LinkProber`]
+//! probes every active peer over every transport that can reach it on a fixed
+//! interval, records RTT, loss and (where the transport reports it) throughput
+//! as a time series, and answers trend queries over that history.
+//!
+//! When the best route to a peer is trending worse, the prober pre-warms the
+//! best alternative (one connectivity test, which sets up DNS, NAT bindings or
+//! circuits) and emits a [`ProbeEvent::RouteDegrading`] so callers can switch
+//! before delivery actually suffers.
+
+use super::abstraction::{Transport, TransportTarget, TransportType};
+use crate::error::{Result, SynapseError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::broadcast, task::JoinHandle};
+use tracing::{debug, info, warn};
+
+/// Connectivity-result detail key transports may use to report throughput
+pub const THROUGHPUT_DETAIL_KEY: &str = "throughput_bps";
+
+/// One probe round against a peer over one transport
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProbeSample {
+    pub peer: String,
+    pub transport: TransportType,
+    pub measured_at: DateTime<Utc>,
+    /// Median round-trip time of successful probes
+    pub rtt_ms: Option<f64>,
+    /// Fraction of probes that failed (0.0-1.0)
+    pub loss: f64,
+    /// Throughput, when the transport reports it
+    pub throughput_bps: Option<u64>,
+}
+
+/// Direction a link's quality is moving in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrendDirection {
+    Improving,
+    Stable,
+    Degrading,
+}
+
+/// Summary of a link's recent history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkTrend {
+    pub peer: String,
+    pub transport: TransportType,
+    pub samples: usize,
+    pub mean_rtt_ms: Option<f64>,
+    /// Change in RTT per minute (least-squares slope)
+    pub rtt_slope_ms_per_min: f64,
+    pub mean_loss: f64,
+    /// Change in loss per minute (least-squares slope)
+    pub loss_slope_per_min: f64,
+    pub mean_throughput_bps: Option<u64>,
+    pub direction: TrendDirection,
+}
+
+impl LinkTrend {
+    /// Expected cost of this link `horizon` from now; lower is better
+    pub fn projected_cost(&self, horizon: Duration) -> f64 {
+        let minutes = horizon.as_secs_f64() / 60.0;
+        let rtt = (self.mean_rtt_ms.unwrap_or(10_000.0) + self.rtt_slope_ms_per_min * minutes).max(0.0);
+        let loss = (self.mean_loss + self.loss_slope_per_min * minutes).clamp(0.0, 1.0);
+        // A lost probe costs roughly a retransmission timeout
+        rtt * (1.0 + 10.0 * loss)
+    }
+}
+
+/// Events emitted by the prober
+#[derive(Debug, Clone)]
+pub enum ProbeEvent {
+    /// A probe round was recorded
+    Sampled(ProbeSample),
+    /// The best route to a peer is degrading; `prewarmed` was warmed up as a replacement
+    RouteDegrading {
+        peer: String,
+        current: TransportType,
+        prewarmed: Option<TransportType>,
+    },
+}
+
+/// Prober configuration
+#[derive(Debug, Clone)]
+pub struct ProbeConfig {
+    /// Time between probe rounds
+    pub interval: Duration,
+    /// Connectivity tests per peer and transport in a round (for loss)
+    pub probes_per_round: u32,
+    /// Window used for trend queries during routing decisions
+    pub trend_window: Duration,
+    /// How far ahead to project trends when ranking routes
+    pub projection_horizon: Duration,
+    /// RTT slope (ms/min) beyond which a link counts as degrading or improving
+    pub rtt_slope_threshold: f64,
+    /// Loss slope (per minute) beyond which a link counts as degrading or improving
+    pub loss_slope_threshold: f64,
+    /// How long samples are kept
+    pub retention: Duration,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            probes_per_round: 3,
+            trend_window: Duration::from_secs(15 * 60),
+            projection_horizon: Duration::from_secs(5 * 60),
+            rtt_slope_threshold: 5.0,
+            loss_slope_threshold: 0.01,
+            retention: Duration::from_secs(7 * 24 * 3600),
+        }
+    }
+}
+
+/// Time-series storage for probe samples
+#[async_trait]
+pub trait ProbeStore: Send + Sync {
+    /// Append a sample
+    async fn record(&self, sample: &ProbeSample) -> Result<()>;
+
+    /// Samples for a peer and transport since a point in time, oldest first
+    async fn series(&self, peer: &str, transport: TransportType, since: DateTime<Utc>) -> Result<Vec<ProbeSample>>;
+
+    /// Drop samples older than a cutoff
+    async fn prune(&self, cutoff: DateTime<Utc>) -> Result<()>;
+}
+
+/// In-memory probe store, bounded per series
+pub struct MemoryProbeStore {
+    series: DashMap<(String, TransportType), VecDeque<ProbeSample>>,
+    max_samples_per_series: usize,
+}
+
+impl MemoryProbeStore {
+    pub fn new(max_samples_per_series: usize) -> Self {
+        Self {
+            series: DashMap::new(),
+            max_samples_per_series,
+        }
+    }
+}
+
+impl Default for MemoryProbeStore {
+    fn default() -> Self {
+        // A week of one-minute rounds
+        Self::new(7 * 24 * 60)
+    }
+}
+
+#[async_trait]
+impl ProbeStore for MemoryProbeStore {
+    async fn record(&self, sample: &ProbeSample) -> Result<()> {
+        let mut series = self.series.entry((sample.peer.clone(), sample.transport)).or_default();
+        series.push_back(sample.clone());
+        while series.len() > self.max_samples_per_series {
+            series.pop_front();
+        }
+        Ok(())
+    }
+
+    async fn series(&self, peer: &str, transport: TransportType, since: DateTime<Utc>) -> Result<Vec<ProbeSample>> {
+        Ok(self
+            .series
+            .get(&(peer.to_string(), transport))
+            .map(|series| series.iter().filter(|s| s.measured_at >= since).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn prune(&self, cutoff: DateTime<Utc>) -> Result<()> {
+        for mut series in self.series.iter_mut() {
+            series.retain(|s| s.measured_at >= cutoff);
+        }
+        self.series.retain(|_, series| !series.is_empty());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "database")]
+#[async_trait]
+impl ProbeStore for crate::synapse::storage::Database {
+    async fn record(&self, sample: &ProbeSample) -> Result<()> {
+        self.insert_probe_sample(sample)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))
+    }
+
+    async fn series(&self, peer: &str, transport: TransportType, since: DateTime<Utc>) -> Result<Vec<ProbeSample>> {
+        self.list_probe_samples(peer, &transport.to_string(), since)
+            .await
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))
+    }
+
+    async fn prune(&self, cutoff: DateTime<Utc>) -> Result<()> {
+        self.prune_probe_samples(cutoff)
+            .await
+            .map(|_| ())
+            .map_err(|e| SynapseError::DatabaseError(e.to_string()))
+    }
+}
+
+/// Least-squares slope of `(x, y)` points
+fn slope(points: &[(f64, f64)]) -> f64 {
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if variance == 0.0 { 0.0 } else { covariance / variance }
+}
+
+/// Compute a trend from samples ordered oldest first
+pub fn compute_trend(samples: &[ProbeSample], config: &ProbeConfig) -> Option<LinkTrend> {
+    let first = samples.first()?;
+    let minutes = |s: &ProbeSample| (s.measured_at - first.measured_at).num_milliseconds() as f64 / 60_000.0;
+
+    let rtt_points: Vec<(f64, f64)> = samples.iter().filter_map(|s| Some((minutes(s), s.rtt_ms?))).collect();
+    let loss_points: Vec<(f64, f64)> = samples.iter().map(|s| (minutes(s), s.loss)).collect();
+    let throughputs: Vec<u64> = samples.iter().filter_map(|s| s.throughput_bps).collect();
+
+    let mean_rtt_ms = (!rtt_points.is_empty())
+        .then(|| rtt_points.iter().map(|(_, y)| y).sum::<f64>() / rtt_points.len() as f64);
+    let mean_loss = loss_points.iter().map(|(_, y)| y).sum::<f64>() / loss_points.len() as f64;
+    let mean_throughput_bps = (!throughputs.is_empty())
+        .then(|| throughputs.iter().sum::<u64>() / throughputs.len() as u64);
+    let rtt_slope_ms_per_min = slope(&rtt_points);
+    let loss_slope_per_min = slope(&loss_points);
+
+    let direction = if rtt_slope_ms_per_min > config.rtt_slope_threshold
+        || loss_slope_per_min > config.loss_slope_threshold
+    {
+        TrendDirection::Degrading
+    } else if rtt_slope_ms_per_min < -config.rtt_slope_threshold
+        || loss_slope_per_min < -config.loss_slope_threshold
+    {
+        TrendDirection::Improving
+    } else {
+        TrendDirection::Stable
+    };
+
+    Some(LinkTrend {
+        peer: first.peer.clone(),
+        transport: first.transport,
+        samples: samples.len(),
+        mean_rtt_ms,
+        rtt_slope_ms_per_min,
+        mean_loss,
+        loss_slope_per_min,
+        mean_throughput_bps,
+        direction,
+    })
+}
+
+/// Periodically probes active peers and keeps their link history
+pub struct LinkProber {
+    transports: HashMap<TransportType, Arc<dyn Transport>>,
+    peers: DashMap<String, TransportTarget>,
+    store: Arc<dyn ProbeStore>,
+    config: ProbeConfig,
+    events: broadcast::Sender<ProbeEvent>,
+}
+
+impl LinkProber {
+    /// Create a prober over the given transports
+    pub fn new(
+        transports: HashMap<TransportType, Arc<dyn Transport>>,
+        store: Arc<dyn ProbeStore>,
+        config: ProbeConfig,
+    ) -> Self {
+        let (events, _) = broadcast::channel(256);
+        Self {
+            transports,
+            peers: DashMap::new(),
+            store,
+            config,
+            events,
+        }
+    }
+
+    /// Start probing a peer
+    pub fn add_peer(&self, target: TransportTarget) {
+        self.peers.insert(target.identifier.clone(), target);
+    }
+
+    /// Stop probing a peer
+    pub fn remove_peer(&self, peer: &str) {
+        self.peers.remove(peer);
+    }
+
+    /// Subscribe to samples and route warnings
+    pub fn subscribe(&self) -> broadcast::Receiver<ProbeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Run probe rounds on the configured interval
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        info!("📈 Link prober started ({} transports)", self.transports.len());
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.interval);
+            loop {
+                interval.tick().await;
+                self.probe_round().await;
+
+                let cutoff = Utc::now() - chrono::Duration::from_std(self.config.retention).unwrap_or_default();
+                if let Err(e) = self.store.prune(cutoff).await {
+                    warn!("Failed to prune probe history: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Probe every peer once over every transport that can reach it
+    pub async fn probe_round(&self) {
+        let peers: Vec<TransportTarget> = self.peers.iter().map(|p| p.value().clone()).collect();
+        for target in peers {
+            for (&transport_type, transport) in &self.transports {
+                if !transport.can_reach(&target).await {
+                    continue;
+                }
+                let sample = self.probe(&target, transport_type, transport.as_ref()).await;
+                if let Err(e) = self.store.record(&sample).await {
+                    warn!("Failed to record probe sample for {}: {}", target.identifier, e);
+                }
+                let _ = self.events.send(ProbeEvent::Sampled(sample));
+            }
+            self.check_route(&target).await;
+        }
+    }
+
+    async fn probe(&self, target: &TransportTarget, transport_type: TransportType, transport: &dyn Transport) -> ProbeSample {
+        let probes = self.config.probes_per_round.max(1);
+        let mut successes = 0u32;
+        let mut rtts = Vec::new();
+        let mut throughputs = Vec::new();
+        for _ in 0..probes {
+            match transport.test_connectivity(target).await {
+                Ok(result) if result.connected => {
+                    successes += 1;
+                    if let Some(rtt) = result.rtt {
+                        rtts.push(rtt.as_secs_f64() * 1000.0);
+                    }
+                    if let Some(bps) = result.details.get(THROUGHPUT_DETAIL_KEY).and_then(|v| v.parse().ok()) {
+                        throughputs.push(bps);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        rtts.sort_by(|a, b| a.total_cmp(b));
+        let sample = ProbeSample {
+            peer: target.identifier.clone(),
+            transport: transport_type,
+            measured_at: Utc::now(),
+            rtt_ms: rtts.get(rtts.len() / 2).copied(),
+            loss: 1.0 - successes as f64 / probes as f64,
+            throughput_bps: (!throughputs.is_empty())
+                .then(|| throughputs.iter().sum::<u64>() / throughputs.len() as u64),
+        };
+        debug!("Probed {} over {}: {:?}", target.identifier, transport_type, sample.rtt_ms);
+        sample
+    }
+
+    /// Trend for a peer over one transport within `window`
+    pub async fn trend(&self, peer: &str, transport: TransportType, window: Duration) -> Result<Option<LinkTrend>> {
+        let since = Utc::now()
+            - chrono::Duration::from_std(window).map_err(|e| SynapseError::ValidationFailed(e.to_string()))?;
+        let samples = self.store.series(peer, transport, since).await?;
+        Ok(compute_trend(&samples, &self.config))
+    }
+
+    /// Transports for a peer ranked by projected cost, best first
+    pub async fn ranked_routes(&self, peer: &str) -> Result<Vec<(TransportType, LinkTrend)>> {
+        let mut routes = Vec::new();
+        for &transport in self.transports.keys() {
+            if let Some(trend) = self.trend(peer, transport, self.config.trend_window).await? {
+                routes.push((transport, trend));
+            }
+        }
+        let horizon = self.config.projection_horizon;
+        routes.sort_by(|(_, a), (_, b)| a.projected_cost(horizon).total_cmp(&b.projected_cost(horizon)));
+        Ok(routes)
+    }
+
+    /// Best transport for a peer according to probe history
+    pub async fn best_route(&self, peer: &str) -> Result<Option<TransportType>> {
+        Ok(self.ranked_routes(peer).await?.first().map(|(t, _)| *t))
+    }
+
+    /// Pre-warm an alternative when the route currently used for a peer is degrading
+    async fn check_route(&self, target: &TransportTarget) {
+        let routes = match self.ranked_routes(&target.identifier).await {
+            Ok(routes) => routes,
+            Err(e) => {
+                warn!("Failed to rank routes for {}: {}", target.identifier, e);
+                return;
+            }
+        };
+
+        // The current route is the best by recent average, ignoring the projection
+        let Some((current, current_trend)) = routes
+            .iter()
+            .min_by(|(_, a), (_, b)| a.projected_cost(Duration::ZERO).total_cmp(&b.projected_cost(Duration::ZERO)))
+        else {
+            return;
+        };
+        if current_trend.direction != TrendDirection::Degrading {
+            return;
+        }
+
+        let alternative = routes
+            .iter()
+            .find(|(t, trend)| t != current && trend.direction != TrendDirection::Degrading)
+            .map(|(t, _)| *t);
+        if let Some(transport) = alternative.and_then(|t| self.transports.get(&t)) {
+            debug!("Pre-warming {} for {}", transport.transport_type(), target.identifier);
+            let _ = transport.test_connectivity(target).await;
+        }
+
+        warn!(
+            "Route {} to {} is degrading ({:+.1} ms/min), pre-warmed {:?}",
+            current, target.identifier, current_trend.rtt_slope_ms_per_min, alternative
+        );
+        let _ = self.events.send(ProbeEvent::RouteDegrading {
+            peer: target.identifier.clone(),
+            current: *current,
+            prewarmed: alternative,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(minute: i64, rtt_ms: f64, loss: f64) -> ProbeSample {
+        ProbeSample {
+            peer: "bob@example.com".to_string(),
+            transport: TransportType::Tcp,
+            measured_at: DateTime::<Utc>::UNIX_EPOCH + chrono::Duration::minutes(minute),
+            rtt_ms: Some(rtt_ms),
+            loss,
+            throughput_bps: None,
+        }
+    }
+
+    #[test]
+    fn test_trend_direction() {
+        let config = ProbeConfig::default();
+
+        let rising: Vec<_> = (0..10).map(|m| sample(m, 20.0 + 10.0 * m as f64, 0.0)).collect();
+        let trend = compute_trend(&rising, &config).unwrap();
+        assert_eq!(trend.direction, TrendDirection::Degrading);
+        assert!((trend.rtt_slope_ms_per_min - 10.0).abs() < 1e-9);
+
+        let flat: Vec<_> = (0..10).map(|m| sample(m, 20.0, 0.0)).collect();
+        assert_eq!(compute_trend(&flat, &config).unwrap().direction, TrendDirection::Stable);
+
+        let recovering: Vec<_> = (0..10).map(|m| sample(m, 20.0, 0.5 - 0.05 * m as f64)).collect();
+        assert_eq!(compute_trend(&recovering, &config).unwrap().direction, TrendDirection::Improving);
+
+        assert!(compute_trend(&[], &config).is_none());
+    }
+
+    #[test]
+    fn test_projection_penalizes_degrading_links() {
+        let config = ProbeConfig::default();
+        let degrading = compute_trend(&(0..10).map(|m| sample(m, 20.0 + 10.0 * m as f64, 0.0)).collect::<Vec<_>>(), &config).unwrap();
+        let steady = compute_trend(&(0..10).map(|m| sample(m, 80.0, 0.0)).collect::<Vec<_>>(), &config).unwrap();
+
+        // Degrading link looks better on average but worse five minutes out
+        assert!(degrading.projected_cost(Duration::ZERO) < steady.projected_cost(Duration::ZERO));
+        assert!(degrading.projected_cost(config.projection_horizon) > steady.projected_cost(config.projection_horizon));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_series_and_prune() {
+        let store = MemoryProbeStore::new(3);
+        for m in 0..5 {
+            store.record(&sample(m, 20.0, 0.0)).await.unwrap();
+        }
+        let all = store.series("bob@example.com", TransportType::Tcp, DateTime::<Utc>::UNIX_EPOCH).await.unwrap();
+        assert_eq!(all.len(), 3);
+
+        store.prune(DateTime::<Utc>::UNIX_EPOCH + chrono::Duration::minutes(4)).await.unwrap();
+        let remaining = store.series("bob@example.com", TransportType::Tcp, DateTime::<Utc>::UNIX_EPOCH).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+    }
+}
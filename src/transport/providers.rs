@@ -33,14 +33,16 @@ pub struct ProductionTransportProvider;
 
 #[async_trait]
 impl TransportProvider for ProductionTransportProvider {
-    async fn create_tcp_transport(&self, _config: &Config) -> Result<Option<Arc<dyn Transport>>> {
+    async fn create_tcp_transport(&self, config: &Config) -> Result<Option<Arc<dyn Transport>>> {
         #[cfg(not(target_arch = "wasm32"))]
         {
+            use super::proxy::ProxyDialer;
             use super::tcp::TcpTransport;
+            let proxy = Arc::new(ProxyDialer::from_config(&config.proxy)?);
             match TcpTransport::new(8080).await {
                 Ok(transport) => {
                     tracing::info!("TCP transport initialized");
-                    Ok(Some(Arc::new(transport)))
+                    Ok(Some(Arc::new(transport.with_proxy(proxy))))
                 }
                 Err(e) => {
                     tracing::warn!("Failed to initialize TCP transport: {}", e);
@@ -49,7 +51,10 @@ impl TransportProvider for ProductionTransportProvider {
             }
         }
         #[cfg(target_arch = "wasm32")]
-        Ok(None)
+        {
+            let _ = config;
+            Ok(None)
+        }
     }
 
     async fn create_mdns_transport(&self, _config: &Config) -> Result<Option<Arc<dyn Transport>>> {
@@ -74,14 +79,14 @@ impl TransportProvider for ProductionTransportProvider {
         Ok(None)
     }
 
-    async fn create_nat_transport(&self, _config: &Config) -> Result<Option<Arc<dyn Transport>>> {
+    async fn create_nat_transport(&self, config: &Config) -> Result<Option<Arc<dyn Transport>>> {
         #[cfg(not(target_arch = "wasm32"))]
         {
             use super::nat_traversal::NatTraversalTransport;
             match NatTraversalTransport::new(8080).await {
                 Ok(transport) => {
                     tracing::info!("NAT traversal transport initialized");
-                    Ok(Some(Arc::new(transport)))
+                    Ok(Some(Arc::new(transport.with_region(config.entity.region.clone()))))
                 }
                 Err(e) => {
                     tracing::warn!("Failed to initialize NAT traversal transport: {}", e);
@@ -254,6 +259,7 @@ impl Transport for MockTransport {
             cost: 1.0,
             available: true,
             confidence: 0.8,
+            region: None,
         })
     }
 
@@ -4,8 +4,89 @@
 mod tests {
     use super::super::providers::MockTransport;
     use super::super::Transport; // Import the Transport trait from mod.rs
+    use super::super::abstraction::{
+        self, MessageUrgency, TransportCapabilities, TransportFactory, TransportType,
+    };
+    use super::super::router::{MultiTransportRouter, TransportProvider};
+    use super::super::TransportSelector;
     use crate::config::Config;
+    use crate::error::Result;
+    use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
+    use crate::types::{SecureMessage, SecurityLevel};
+    use async_trait::async_trait;
+    use std::{collections::HashMap, sync::Arc, time::Duration};
     use tokio;
+    use tokio::sync::RwLock;
+
+    /// Router provider with no built-in transports
+    struct NoTransports;
+
+    #[async_trait]
+    impl TransportProvider for NoTransports {
+        async fn create_tcp_transport(&self, _config: &Config) -> Result<Option<Arc<dyn abstraction::Transport>>> {
+            Ok(None)
+        }
+        async fn create_mdns_transport(&self, _config: &Config) -> Result<Option<Arc<dyn abstraction::Transport>>> {
+            Ok(None)
+        }
+        async fn create_nat_transport(&self, _config: &Config) -> Result<Option<Arc<dyn abstraction::Transport>>> {
+            Ok(None)
+        }
+        async fn create_email_transport(&self, _config: &Config) -> Result<Option<Arc<dyn abstraction::Transport>>> {
+            Ok(None)
+        }
+        fn create_transport_selector(&self) -> Arc<RwLock<TransportSelector>> {
+            Arc::new(RwLock::new(TransportSelector::new()))
+        }
+    }
+
+    /// Factory for a mock transport registered as a plugin
+    struct PluginFactory(TransportType);
+
+    #[async_trait]
+    impl TransportFactory for PluginFactory {
+        async fn create_transport(&self, _config: &HashMap<String, String>) -> Result<Box<dyn abstraction::Transport>> {
+            Ok(Box::new(MockTransport::new("plugin").with_latency(Duration::from_millis(1))))
+        }
+        fn transport_type(&self) -> TransportType {
+            self.0
+        }
+        fn default_config(&self) -> HashMap<String, String> {
+            HashMap::new()
+        }
+        fn validate_config(&self, _config: &HashMap<String, String>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn plugin_capabilities() -> TransportCapabilities {
+        TransportCapabilities {
+            max_message_size: 64 * 1024,
+            reliable: true,
+            real_time: true,
+            broadcast: false,
+            bidirectional: true,
+            encrypted: true,
+            network_spanning: true,
+            supported_urgencies: vec![MessageUrgency::Interactive],
+            features: vec!["satellite".to_string()],
+        }
+    }
+
+    fn test_message() -> SecureMessage {
+        SecureMessage {
+            message_id: UuidWrapper::new(uuid::Uuid::new_v4()),
+            to_global_id: "peer".to_string(),
+            from_global_id: "me".to_string(),
+            encrypted_content: b"hi".to_vec(),
+            signature: Vec::new(),
+            timestamp: DateTimeWrapper::new(chrono::Utc::now()),
+            security_level: SecurityLevel::Public,
+            routing_path: Vec::new(),
+            metadata: HashMap::new(),
+            attachments: Vec::new(),
+        }
+    }
 
     #[tokio::test]
     async fn test_mock_transport_basic_functionality() {
@@ -82,4 +163,44 @@ mod tests {
         let mock = MockTransport::new("di-test".to_string());
         assert!(mock.can_reach("test").await);
     }
+
+    #[tokio::test]
+    async fn test_custom_transport_joins_selection() {
+        let router = MultiTransportRouter::new_with_provider(Config::default(), "me".to_string(), Box::new(NoTransports))
+            .await
+            .unwrap();
+        let plugin = TransportType::Custom(7);
+
+        assert!(router
+            .register_custom_transport(Box::new(PluginFactory(TransportType::Tcp)), plugin_capabilities())
+            .await
+            .is_err());
+        router
+            .register_custom_transport(Box::new(PluginFactory(plugin)), plugin_capabilities())
+            .await
+            .unwrap();
+        assert!(router
+            .register_custom_transport(Box::new(PluginFactory(plugin)), plugin_capabilities())
+            .await
+            .is_err());
+
+        // Selected for the urgencies it declares
+        assert!(router.has_custom_transport_for(MessageUrgency::Interactive).await);
+        assert!(!router.has_custom_transport_for(MessageUrgency::Batch).await);
+        let receipt = router
+            .send_message("peer", &test_message(), MessageUrgency::Interactive)
+            .await
+            .unwrap();
+        assert_eq!(receipt.message_id, "mock-plugin-sent-to-peer");
+
+        // Recorded in its metrics and advertised
+        let metrics = router.custom_transports().get_metrics().await;
+        assert_eq!(metrics.transport_metrics[&plugin].messages_sent, 1);
+        let capabilities = router.custom_capabilities().await;
+        assert!(capabilities.contains(&"custom:7".to_string()));
+        assert!(capabilities.contains(&"satellite".to_string()));
+
+        router.unregister_custom_transport(plugin).await.unwrap();
+        assert!(!router.has_custom_transport_for(MessageUrgency::Interactive).await);
+    }
 }
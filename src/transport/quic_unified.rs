@@ -323,6 +323,7 @@ impl Transport for QuicTransportImpl {
                 cost: 3.0, // Medium-high cost due to complexity
                 available: true,
                 confidence: 0.9, // High confidence
+                region: None,
             })
         } else {
             Ok(TransportEstimate {
@@ -332,6 +333,7 @@ impl Transport for QuicTransportImpl {
                 cost: 1000.0,
                 available: false,
                 confidence: 0.95,
+                region: None,
             })
         }
     }
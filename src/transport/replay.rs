@@ -0,0 +1,197 @@
+//! Replay protection for messages arriving over transports
+//!
+//! A `SecureMessage` captured off the wire is still validly signed, so
+//! resending it to a UDP or TCP listener would otherwise deliver it again.
+//! The [`ReplayCache`] accepts a message only if its timestamp falls inside
+//! a window around the local clock and its ID has not been seen from the
+//! same sender within that window. Messages older than the window are
+//! rejected outright, so the cache of seen IDs never has to outlive it.
+//!
+//! Given the router's [`PeerClocks`], a sender's measured clock offset is
+//! removed from its timestamps first, so a peer with a known skew is not
+//! rejected for it.
+
+use super::time_sync::{PeerClocks, SkewWindow, TimestampCheck};
+use crate::types::SecureMessage;
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Settings for transport-level replay protection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayConfig {
+    pub enabled: bool,
+    /// Oldest message timestamp accepted
+    pub max_age: Duration,
+    /// How far ahead of the local clock a sender's timestamp may be
+    pub max_clock_skew: Duration,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_age: Duration::from_secs(300),
+            max_clock_skew: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Outcome of checking a received message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayVerdict {
+    Fresh,
+    /// The same sender already delivered this message ID
+    Duplicate,
+    /// The timestamp is older than the replay window
+    Stale,
+    /// The timestamp is further in the future than clock skew allows
+    FromFuture,
+}
+
+impl ReplayVerdict {
+    pub fn is_fresh(&self) -> bool {
+        *self == ReplayVerdict::Fresh
+    }
+}
+
+/// Remembers recently seen message IDs per sender
+pub struct ReplayCache {
+    config: ReplayConfig,
+    // (sender, message ID) -> message timestamp in seconds
+    seen: DashMap<(String, String), i64>,
+    last_prune: Mutex<Instant>,
+    rejected: AtomicU64,
+    clocks: Option<Arc<PeerClocks>>,
+}
+
+impl ReplayCache {
+    pub fn new(config: ReplayConfig) -> Self {
+        Self {
+            config,
+            seen: DashMap::new(),
+            last_prune: Mutex::new(Instant::now()),
+            rejected: AtomicU64::new(0),
+            clocks: None,
+        }
+    }
+
+    /// Correct sender timestamps by their measured clock offsets
+    pub fn with_clocks(mut self, clocks: Arc<PeerClocks>) -> Self {
+        self.clocks = Some(clocks);
+        self
+    }
+
+    /// Settings in use
+    pub fn config(&self) -> &ReplayConfig {
+        &self.config
+    }
+
+    /// Check a message and remember it if it is fresh
+    pub fn check(&self, message: &SecureMessage) -> ReplayVerdict {
+        if !self.config.enabled {
+            return ReplayVerdict::Fresh;
+        }
+        self.prune_if_due();
+
+        let timestamp = message.timestamp.0.timestamp();
+        let offset_ms = self.clocks.as_ref().and_then(|c| c.offset(&message.from_global_id)).unwrap_or(0);
+        let window = SkewWindow::new(self.config.max_age, self.config.max_clock_skew);
+        let verdict = match window.check(message.timestamp.0, Utc::now(), offset_ms) {
+            TimestampCheck::Stale => ReplayVerdict::Stale,
+            TimestampCheck::FromFuture => ReplayVerdict::FromFuture,
+            TimestampCheck::Valid => {
+                let key = (message.from_global_id.clone(), message.message_id.0.to_string());
+                match self.seen.entry(key) {
+                    dashmap::mapref::entry::Entry::Occupied(_) => ReplayVerdict::Duplicate,
+                    dashmap::mapref::entry::Entry::Vacant(entry) => {
+                        // Remembered in our clock so pruning matches the window
+                        entry.insert(timestamp - offset_ms / 1000);
+                        ReplayVerdict::Fresh
+                    }
+                }
+            }
+        };
+        if !verdict.is_fresh() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        verdict
+    }
+
+    /// Messages rejected since startup
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Drop IDs whose timestamps have left the window; they would be
+    /// rejected as stale anyway
+    pub fn prune(&self) {
+        let cutoff = Utc::now().timestamp() - self.config.max_age.as_secs() as i64;
+        self.seen.retain(|_, timestamp| *timestamp >= cutoff);
+    }
+
+    /// Drop everything remembered about a sender
+    pub fn forget(&self, sender: &str) {
+        self.seen.retain(|(seen_sender, _), _| seen_sender != sender);
+    }
+
+    fn prune_if_due(&self) {
+        let mut last_prune = self.last_prune.lock().unwrap();
+        if last_prune.elapsed() >= self.config.max_age / 4 {
+            *last_prune = Instant::now();
+            drop(last_prune);
+            self.prune();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::serialization::DateTimeWrapper;
+    use crate::types::SecurityLevel;
+
+    #[test]
+    fn test_replayed_messages_rejected() {
+        let cache = ReplayCache::new(ReplayConfig::default());
+        let message = SecureMessage::new("bob", "alice", b"hi".to_vec(), vec![1], SecurityLevel::Authenticated);
+
+        assert_eq!(cache.check(&message), ReplayVerdict::Fresh);
+        assert_eq!(cache.check(&message), ReplayVerdict::Duplicate);
+
+        // The same ID from another sender is a different message
+        let mut other = message.clone();
+        other.from_global_id = "carol".into();
+        assert_eq!(cache.check(&other), ReplayVerdict::Fresh);
+
+        let mut old = SecureMessage::new("bob", "alice", b"hi".to_vec(), vec![1], SecurityLevel::Authenticated);
+        old.timestamp = DateTimeWrapper::new(Utc::now() - chrono::Duration::minutes(10));
+        assert_eq!(cache.check(&old), ReplayVerdict::Stale);
+
+        let mut future = old.clone();
+        future.timestamp = DateTimeWrapper::new(Utc::now() + chrono::Duration::minutes(10));
+        assert_eq!(cache.check(&future), ReplayVerdict::FromFuture);
+        assert_eq!(cache.rejected(), 3);
+
+        cache.forget("alice");
+        assert_eq!(cache.check(&message), ReplayVerdict::Fresh);
+    }
+
+    #[test]
+    fn test_known_sender_skew_tolerated() {
+        let clocks = Arc::new(PeerClocks::new(4));
+        let cache = ReplayCache::new(ReplayConfig::default()).with_clocks(clocks.clone());
+        let mut ahead = SecureMessage::new("bob", "alice", b"hi".to_vec(), vec![1], SecurityLevel::Authenticated);
+        ahead.timestamp = DateTimeWrapper::new(Utc::now() + chrono::Duration::minutes(2));
+        assert_eq!(cache.check(&ahead), ReplayVerdict::FromFuture);
+
+        // Alice's clock is measured two minutes fast
+        clocks.record(crate::transport::time_sync::TimeSample::from_exchange("alice", 0, 120_000, 120_000, 0));
+        assert_eq!(cache.check(&ahead), ReplayVerdict::Fresh);
+        assert_eq!(cache.check(&ahead), ReplayVerdict::Duplicate);
+    }
+}
@@ -0,0 +1,549 @@
+//! Session resumption for reconnecting to recently used peers
+//!
+//! After a successful exchange the router records a [`SessionTicket`] holding
+//! the route that worked and any transport session state (for TLS or QUIC, the
+//! session ticket the handshake produced). When the peer is contacted again
+//! within the ticket lifetime — typically right after a Wi-Fi blip — the route
+//! is reused without discovery or connectivity probing, and the first message
+//! goes out immediately as early data (0-RTT).
+//!
+//! Tickets are single-use: resuming consumes the ticket and a fresh one is
+//! issued once the resumed exchange succeeds. Early-data messages carry their
+//! ticket ID in [`RESUMPTION_TICKET_KEY`] metadata so receivers can reject
+//! replays with a [`ReplayGuard`].
+
+use super::{NatMethod, TransportRoute, abstraction::TransportType, time_sync::PeerClocks};
+use crate::{
+    config::ResumptionConfig,
+    crypto::{StorageKey, read_sealed_file, write_sealed_file},
+    error::{Result, SynapseError},
+    types::SecureMessage,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Message metadata key carrying the ticket an early-data message was sent under
+pub const RESUMPTION_TICKET_KEY: &str = "resumption_ticket";
+
+/// Serializable form of a route that can be resumed without rediscovery
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ResumableRoute {
+    Tcp { address: String, port: u16 },
+    Udp { address: String, port: u16 },
+    Mdns { service_name: String, address: String, port: u16 },
+    Nat { method: NatMethod, external_address: String, external_port: u16 },
+    Quic { address: String, multiplexed: bool },
+    WebSocket { url: String },
+}
+
+impl ResumableRoute {
+    /// Capture a route for resumption; store-and-forward and custom routes have no session to resume
+    pub fn from_route(route: &TransportRoute) -> Option<Self> {
+        match route {
+            TransportRoute::DirectTcp { address, port, .. } => {
+                Some(Self::Tcp { address: address.clone(), port: *port })
+            }
+            TransportRoute::DirectUdp { address, port, .. } => {
+                Some(Self::Udp { address: address.clone(), port: *port })
+            }
+            TransportRoute::Udp { address, .. } => {
+                let (host, port) = address.rsplit_once(':')?;
+                Some(Self::Udp { address: host.to_string(), port: port.parse().ok()? })
+            }
+            TransportRoute::LocalMdns { service_name, address, port, .. } => Some(Self::Mdns {
+                service_name: service_name.clone(),
+                address: address.clone(),
+                port: *port,
+            }),
+            TransportRoute::NatTraversal { method, external_address, external_port, .. } => Some(Self::Nat {
+                method: method.clone(),
+                external_address: external_address.clone(),
+                external_port: *external_port,
+            }),
+            TransportRoute::Quic { address, multiplexed, .. } => {
+                Some(Self::Quic { address: address.clone(), multiplexed: *multiplexed })
+            }
+            TransportRoute::WebSocket { url, .. } => Some(Self::WebSocket { url: url.clone() }),
+            TransportRoute::FastEmailRelay { .. }
+            | TransportRoute::StandardEmail { .. }
+            | TransportRoute::EmailDiscovery { .. }
+            | TransportRoute::Custom { .. } => None,
+        }
+    }
+
+    /// Rebuild a live route, stamped as established now
+    pub fn to_route(&self, latency_ms: u32) -> TransportRoute {
+        let now = Instant::now();
+        let latency = Duration::from_millis(latency_ms as u64);
+        match self {
+            Self::Tcp { address, port } => TransportRoute::DirectTcp {
+                address: address.clone(),
+                port: *port,
+                latency_ms,
+                established_at: now,
+            },
+            Self::Udp { address, port } => TransportRoute::DirectUdp {
+                address: address.clone(),
+                port: *port,
+                latency_ms,
+                established_at: now,
+            },
+            Self::Mdns { service_name, address, port } => TransportRoute::LocalMdns {
+                service_name: service_name.clone(),
+                address: address.clone(),
+                port: *port,
+                latency_ms,
+                discovered_at: now,
+            },
+            Self::Nat { method, external_address, external_port } => TransportRoute::NatTraversal {
+                method: method.clone(),
+                external_address: external_address.clone(),
+                external_port: *external_port,
+                latency_ms,
+                established_at: now,
+            },
+            Self::Quic { address, multiplexed } => TransportRoute::Quic {
+                address: address.clone(),
+                latency,
+                reliability: 0.95,
+                multiplexed: *multiplexed,
+            },
+            Self::WebSocket { url } => TransportRoute::WebSocket {
+                url: url.clone(),
+                latency,
+                reliability: 0.9,
+            },
+        }
+    }
+
+    /// Transport the route runs over
+    pub fn transport_type(&self) -> TransportType {
+        match self {
+            Self::Tcp { .. } => TransportType::Tcp,
+            Self::Udp { .. } => TransportType::Udp,
+            Self::Mdns { .. } => TransportType::AutoDiscovery,
+            Self::Nat { .. } => TransportType::Custom(1), // as reported by NatTraversalTransport
+            Self::Quic { .. } => TransportType::Quic,
+            Self::WebSocket { .. } => TransportType::WebSocket,
+        }
+    }
+}
+
+/// Everything needed to resume a session with a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTicket {
+    /// Unique ticket ID, echoed in early-data metadata
+    pub id: Uuid,
+    pub peer: String,
+    pub route: ResumableRoute,
+    /// Latency observed when the ticket was issued
+    pub latency_ms: u32,
+    /// Opaque transport session state (e.g. a TLS/QUIC session ticket)
+    #[serde(default)]
+    pub session_state: Vec<u8>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl SessionTicket {
+    /// Whether the ticket can still be used at `now`
+    pub fn is_valid_at(&self, now: DateTime<Utc>) -> bool {
+        now < self.expires_at
+    }
+}
+
+/// Single-use session tickets for recently contacted peers
+pub struct SessionCache {
+    config: ResumptionConfig,
+    tickets: RwLock<HashMap<String, SessionTicket>>,
+    storage_key: RwLock<Option<StorageKey>>,
+}
+
+impl SessionCache {
+    /// Create an empty cache
+    pub fn new(config: ResumptionConfig) -> Self {
+        Self {
+            config,
+            tickets: RwLock::new(HashMap::new()),
+            storage_key: RwLock::new(None),
+        }
+    }
+
+    /// Create a cache, loading any unexpired tickets from the configured store
+    ///
+    /// An encrypted store is left for [`SessionCache::unlock`].
+    pub fn load(config: ResumptionConfig) -> Result<Self> {
+        let cache = Self::new(config);
+        let Some(path) = cache.config.ticket_store.clone() else {
+            return Ok(cache);
+        };
+        if !Path::new(&path).exists() {
+            return Ok(cache);
+        }
+        if StorageKey::is_sealed(&std::fs::read(&path)?) {
+            debug!("Session tickets in {} are encrypted; waiting for the storage key", path);
+            return Ok(cache);
+        }
+        cache.read_store(&path, None)?;
+        Ok(cache)
+    }
+
+    /// Encrypt the ticket store with `key` from now on
+    ///
+    /// Loads tickets from an encrypted store, and rewrites a plaintext store
+    /// encrypted. Returns the number of tickets held.
+    pub fn unlock(&self, key: StorageKey) -> Result<usize> {
+        *self.storage_key.write().unwrap() = Some(key.clone());
+        let Some(path) = self.config.ticket_store.clone() else {
+            return Ok(self.len());
+        };
+        if Path::new(&path).exists() && self.read_store(&path, Some(&key))? {
+            self.persist()?;
+            debug!("Encrypted session ticket store {}", path);
+        }
+        Ok(self.len())
+    }
+
+    // Merge unexpired tickets from the store; true when it was plaintext
+    fn read_store(&self, path: &str, key: Option<&StorageKey>) -> Result<bool> {
+        let (content, plaintext) = read_sealed_file(Path::new(path), key)?;
+        let stored: Vec<SessionTicket> = serde_json::from_slice(&content)?;
+        let now = Utc::now();
+        let mut tickets = self.tickets.write().unwrap();
+        for ticket in stored.into_iter().filter(|t| t.is_valid_at(now)) {
+            tickets.entry(ticket.peer.clone()).or_insert(ticket);
+        }
+        debug!("Loaded {} session tickets from {}", tickets.len(), path);
+        Ok(plaintext)
+    }
+
+    /// Whether resumption is enabled
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Whether resumed sessions may send early data
+    pub fn early_data(&self) -> bool {
+        self.config.enabled && self.config.early_data
+    }
+
+    /// Record a route that just carried a successful exchange
+    pub fn issue(&self, peer: &str, route: &TransportRoute, latency_ms: u32) -> Option<SessionTicket> {
+        if !self.config.enabled {
+            return None;
+        }
+        let route = ResumableRoute::from_route(route)?;
+        let issued_at = Utc::now();
+        let mut tickets = self.tickets.write().unwrap();
+
+        // Keep session state a transport attached to an earlier ticket for the same route
+        let session_state = tickets
+            .get(peer)
+            .filter(|previous| previous.route == route && previous.is_valid_at(issued_at))
+            .map(|previous| previous.session_state.clone())
+            .unwrap_or_default();
+
+        let ticket = SessionTicket {
+            id: Uuid::new_v4(),
+            peer: peer.to_string(),
+            route,
+            latency_ms,
+            session_state,
+            issued_at,
+            expires_at: issued_at + chrono::Duration::seconds(self.config.lifetime_secs as i64),
+        };
+        tickets.insert(peer.to_string(), ticket.clone());
+        Self::evict_oldest(&mut tickets, self.config.max_tickets);
+        Some(ticket)
+    }
+
+    /// Attach transport session state (e.g. a TLS session ticket) to a peer's ticket
+    pub fn attach_session_state(&self, peer: &str, state: Vec<u8>) -> bool {
+        let mut tickets = self.tickets.write().unwrap();
+        match tickets.get_mut(peer) {
+            Some(ticket) => {
+                ticket.session_state = state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Consume a peer's ticket if it is still valid
+    pub fn resume(&self, peer: &str) -> Option<SessionTicket> {
+        if !self.config.enabled {
+            return None;
+        }
+        let ticket = self.tickets.write().unwrap().remove(peer)?;
+        ticket.is_valid_at(Utc::now()).then_some(ticket)
+    }
+
+    /// Look at a peer's ticket without consuming it
+    pub fn peek(&self, peer: &str) -> Option<SessionTicket> {
+        let tickets = self.tickets.read().unwrap();
+        tickets.get(peer).filter(|t| t.is_valid_at(Utc::now())).cloned()
+    }
+
+    /// Drop a peer's ticket (e.g. after the resumed route failed)
+    pub fn invalidate(&self, peer: &str) {
+        self.tickets.write().unwrap().remove(peer);
+    }
+
+    /// Unexpired tickets held
+    pub fn tickets(&self) -> Vec<SessionTicket> {
+        let now = Utc::now();
+        self.tickets.read().unwrap().values().filter(|t| t.is_valid_at(now)).cloned().collect()
+    }
+
+    /// Take a ticket issued elsewhere (e.g. by another cluster instance)
+    ///
+    /// Kept only if it is unexpired and newer than the ticket held for the
+    /// peer. Returns whether it was kept.
+    pub fn adopt(&self, ticket: SessionTicket) -> bool {
+        if !self.config.enabled || !ticket.is_valid_at(Utc::now()) {
+            return false;
+        }
+        let mut tickets = self.tickets.write().unwrap();
+        if tickets.get(&ticket.peer).is_some_and(|held| held.issued_at >= ticket.issued_at) {
+            return false;
+        }
+        tickets.insert(ticket.peer.clone(), ticket);
+        Self::evict_oldest(&mut tickets, self.config.max_tickets);
+        true
+    }
+
+    /// Remove expired tickets
+    pub fn prune(&self) {
+        let now = Utc::now();
+        self.tickets.write().unwrap().retain(|_, t| t.is_valid_at(now));
+    }
+
+    /// Number of tickets held
+    pub fn len(&self) -> usize {
+        self.tickets.read().unwrap().len()
+    }
+
+    /// Whether no tickets are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write unexpired tickets to the configured store
+    pub fn persist(&self) -> Result<()> {
+        let Some(path) = &self.config.ticket_store else {
+            return Ok(());
+        };
+        self.prune();
+        let tickets: Vec<SessionTicket> = self.tickets.read().unwrap().values().cloned().collect();
+        let content = serde_json::to_vec(&tickets)?;
+        let key = self.storage_key.read().unwrap().clone();
+        write_sealed_file(Path::new(path), key.as_ref(), &content)
+    }
+
+    fn evict_oldest(tickets: &mut HashMap<String, SessionTicket>, max: usize) {
+        while tickets.len() > max {
+            let Some(oldest) = tickets
+                .values()
+                .min_by_key(|t| t.issued_at)
+                .map(|t| t.peer.clone())
+            else {
+                break;
+            };
+            tickets.remove(&oldest);
+        }
+    }
+}
+
+/// Rejects replayed or stale early-data messages
+pub struct ReplayGuard {
+    window: Duration,
+    seen: Mutex<HashMap<(String, Uuid), Instant>>,
+    clocks: Option<Arc<PeerClocks>>,
+}
+
+impl ReplayGuard {
+    /// Create a guard remembering early-data messages for `window`
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::new(HashMap::new()),
+            clocks: None,
+        }
+    }
+
+    /// Correct sender timestamps by their measured clock offsets
+    pub fn with_clocks(mut self, clocks: Arc<PeerClocks>) -> Self {
+        self.clocks = Some(clocks);
+        self
+    }
+
+    /// Create a guard using the configured replay window
+    pub fn from_config(config: &ResumptionConfig) -> Self {
+        Self::new(Duration::from_secs(config.replay_window_secs))
+    }
+
+    /// Accept or reject an incoming message
+    ///
+    /// Messages without [`RESUMPTION_TICKET_KEY`] metadata are not early data and
+    /// always pass. Early data must be fresh and must not have been seen before.
+    pub fn check(&self, message: &SecureMessage) -> Result<()> {
+        let Some(ticket) = message.metadata.get(RESUMPTION_TICKET_KEY) else {
+            return Ok(());
+        };
+
+        let offset_ms = self.clocks.as_ref().and_then(|c| c.offset(&message.from_global_id)).unwrap_or(0);
+        let sent_at = message.timestamp.0 - chrono::Duration::milliseconds(offset_ms);
+        let age = Utc::now().signed_duration_since(sent_at);
+        let window = chrono::Duration::from_std(self.window).unwrap_or(chrono::Duration::MAX);
+        if age > window || age < -window {
+            return Err(SynapseError::ValidationFailed(format!(
+                "Early data {} is outside the {:?} replay window",
+                message.message_id.0, self.window
+            )));
+        }
+
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, at| at.elapsed() < self.window);
+        let key = (ticket.clone(), message.message_id.0);
+        if seen.contains_key(&key) {
+            warn!("Rejected replayed early data {} under ticket {}", message.message_id.0, ticket);
+            return Err(SynapseError::ValidationFailed(format!(
+                "Replayed early data {}",
+                message.message_id.0
+            )));
+        }
+        seen.insert(key, Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper},
+        types::SecurityLevel,
+    };
+
+    fn tcp_route() -> TransportRoute {
+        TransportRoute::DirectTcp {
+            address: "10.0.0.5".to_string(),
+            port: 8080,
+            latency_ms: 12,
+            established_at: Instant::now(),
+        }
+    }
+
+    fn message(ticket: Option<&str>) -> SecureMessage {
+        let mut metadata = HashMap::new();
+        if let Some(ticket) = ticket {
+            metadata.insert(RESUMPTION_TICKET_KEY.to_string(), ticket.to_string());
+        }
+        SecureMessage {
+            message_id: UuidWrapper::new(Uuid::new_v4()),
+            to_global_id: "bob@example.com".to_string(),
+            from_global_id: "alice@example.com".to_string(),
+            encrypted_content: b"hi".to_vec(),
+            signature: Vec::new(),
+            timestamp: DateTimeWrapper::new(Utc::now()),
+            security_level: SecurityLevel::Public,
+            routing_path: Vec::new(),
+            metadata,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn tickets_are_single_use_and_skip_email_routes() {
+        let cache = SessionCache::new(ResumptionConfig::default());
+        assert!(cache.issue("bob", &TransportRoute::StandardEmail { estimated_latency_min: 1 }, 0).is_none());
+
+        let ticket = cache.issue("bob", &tcp_route(), 12).unwrap();
+        assert!(cache.attach_session_state("bob", vec![1, 2, 3]));
+
+        let resumed = cache.resume("bob").unwrap();
+        assert_eq!(resumed.id, ticket.id);
+        assert_eq!(resumed.session_state, vec![1, 2, 3]);
+        assert!(matches!(resumed.route.to_route(12), TransportRoute::DirectTcp { port: 8080, .. }));
+        assert!(cache.resume("bob").is_none());
+    }
+
+    #[test]
+    fn adopted_tickets_replace_only_older_ones() {
+        let issuer = SessionCache::new(ResumptionConfig::default());
+        let cache = SessionCache::new(ResumptionConfig::default());
+        let newer = issuer.issue("bob", &tcp_route(), 9).unwrap();
+        let mut older = newer.clone();
+        older.id = Uuid::new_v4();
+        older.issued_at = newer.issued_at - chrono::Duration::seconds(30);
+
+        assert!(cache.adopt(newer.clone()));
+        assert!(!cache.adopt(older));
+        assert_eq!(cache.tickets().len(), 1);
+        assert_eq!(cache.resume("bob").unwrap().id, newer.id);
+    }
+
+    #[test]
+    fn tickets_survive_persistence() {
+        let path = std::env::temp_dir().join(format!("synapse-tickets-{}.json", Uuid::new_v4()));
+        let config = ResumptionConfig {
+            ticket_store: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+
+        let cache = SessionCache::new(config.clone());
+        cache.issue("bob", &tcp_route(), 12).unwrap();
+        cache.persist().unwrap();
+
+        let reloaded = SessionCache::load(config).unwrap();
+        assert_eq!(reloaded.peek("bob").unwrap().route.transport_type(), TransportType::Tcp);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn plaintext_ticket_store_migrates_to_encrypted() {
+        let path = std::env::temp_dir().join(format!("synapse-tickets-{}.json", Uuid::new_v4()));
+        let config = ResumptionConfig {
+            ticket_store: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let key = StorageKey::from_bytes([7; 32]);
+
+        let cache = SessionCache::new(config.clone());
+        cache.issue("bob", &tcp_route(), 12).unwrap();
+        cache.persist().unwrap();
+
+        let reloaded = SessionCache::load(config.clone()).unwrap();
+        assert_eq!(reloaded.unlock(key.clone()).unwrap(), 1);
+        assert!(StorageKey::is_sealed(&std::fs::read(&path).unwrap()));
+
+        // Encrypted stores wait for the key
+        let locked = SessionCache::load(config).unwrap();
+        assert!(locked.is_empty());
+        assert_eq!(locked.unlock(key).unwrap(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn replay_guard_rejects_duplicate_early_data() {
+        let guard = ReplayGuard::new(Duration::from_secs(30));
+        let early = message(Some("ticket-1"));
+
+        assert!(guard.check(&early).is_ok());
+        assert!(guard.check(&early).is_err());
+        assert!(guard.check(&message(None)).is_ok());
+
+        let mut stale = message(Some("ticket-1"));
+        stale.timestamp = DateTimeWrapper::new(Utc::now() - chrono::Duration::minutes(5));
+        assert!(guard.check(&stale).is_err());
+    }
+}
@@ -3,20 +3,38 @@
 use super::{
     abstraction::{
         Transport, TransportTarget, MessageUrgency, TransportType,
-        TransportCapabilities, DeliveryReceipt
+        TransportCapabilities, TransportFactory, DeliveryReceipt, IncomingMessage
     },
-    TransportSelector, TransportRoute,
+    manager::{TransportManager, TransportManagerConfig},
+    resumption::{ReplayGuard, SessionCache, RESUMPTION_TICKET_KEY},
+    capability_probe::{route_carrier, CapabilityDigest, PeerCapabilities},
+    time_sync::PeerClocks,
+    keepalive::KeepaliveSettings,
+    nat_behavior::{self, NatClassification, NatTypeCache, TraversalStrategy},
+    binding::advertise_endpoints,
+    DiscoveryCache, TransportSelector, TransportRoute, NatMethod,
 };
 use crate::{
-    types::SecureMessage,
-    error::Result,
+    types::{GlobalIdentity, SecureMessage},
+    error::{Result, SynapseError},
     config::Config,
+    endpoints::EndpointCache,
+    events::{EventBus, RouterEvent},
+    protocol::VersionRange,
+    limits::ResourceGovernor,
+    runtime::{self, Subsystem},
 };
-use std::{sync::Arc, time::{Duration, Instant}, collections::HashMap};
+use std::{sync::Arc, time::{Duration, Instant}, collections::HashMap, net::SocketAddr};
 use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 use serde::{Serialize, Deserialize};
+#[cfg(feature = "http")]
+use super::{abstraction::DeliveryConfirmation, fast_relay::FastRelayClient};
+
+/// Targets a batch send serves at once
+pub const BATCH_TARGET_CONCURRENCY: usize = 32;
 
 /// Connection offer for establishing connections
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,17 +66,37 @@ pub trait TransportProvider: Send + Sync {
 }
 
 /// Production transport provider
-pub struct ProductionTransportProvider;
+#[derive(Default)]
+pub struct ProductionTransportProvider {
+    /// Connection cap applied to the TCP listener
+    limits: Option<Arc<ResourceGovernor>>,
+}
+
+impl ProductionTransportProvider {
+    /// Provider whose TCP listener takes its connections from `limits`
+    pub fn with_resource_limits(limits: Arc<ResourceGovernor>) -> Self {
+        Self { limits: Some(limits) }
+    }
+}
 
 #[async_trait]
 impl TransportProvider for ProductionTransportProvider {
-    async fn create_tcp_transport(&self, _config: &Config) -> Result<Option<Arc<dyn Transport>>> {
+    async fn create_tcp_transport(&self, config: &Config) -> Result<Option<Arc<dyn Transport>>> {
         // Create TCP transport using enhanced implementation
-        use crate::transport::tcp::TcpTransport;
+        use crate::transport::{proxy::ProxyDialer, tcp::TcpTransport};
         
-        let tcp_port = 8080; // Default TCP port
-        match TcpTransport::new(tcp_port).await {
-            Ok(transport) => Ok(Some(Arc::new(transport))),
+        let proxy = Arc::new(ProxyDialer::from_config(&config.proxy)?);
+        match TcpTransport::bind(&config.listeners.tcp).await {
+            Ok(mut transport) => {
+                if let Some(ref limits) = self.limits {
+                    transport = transport.with_resource_limits(Arc::clone(limits));
+                }
+                Ok(Some(Arc::new(
+                    transport
+                        .with_proxy(proxy)
+                        .with_peer_ports(config.listeners.peer_ports.clone()),
+                )))
+            }
             Err(e) => {
                 warn!("Failed to create TCP transport: {}", e);
                 Ok(None)
@@ -97,24 +135,84 @@ impl TransportProvider for ProductionTransportProvider {
     }
 }
 
+/// A route warmed up ahead of a burst of traffic
+#[derive(Debug, Clone)]
+pub struct PreparedRoute {
+    /// Target the route leads to
+    pub target: String,
+    /// Route chosen by transport selection
+    pub route: TransportRoute,
+    /// Urgency the route was selected for
+    pub urgency: MessageUrgency,
+    /// NAT traversal method negotiated during warm-up, if any
+    pub nat_method: Option<NatMethod>,
+    /// Whether the recipient's public key is known, so encryption needs no exchange
+    pub key_ready: bool,
+    /// Time spent on discovery, traversal and pre-connection
+    pub warmup_time: Duration,
+    /// When warm-up finished
+    pub prepared_at: Instant,
+    /// When pooled connections and traversal state should be considered stale
+    pub expires_at: Instant,
+}
+
+impl PreparedRoute {
+    /// Whether the prepared state has expired
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+
+    /// Time left before the prepared state expires
+    pub fn remaining(&self) -> Duration {
+        self.expires_at.saturating_duration_since(Instant::now())
+    }
+}
+
 /// Multi-transport router for Synapse
 pub struct MultiTransportRouter {
     tcp_transport: Option<Arc<dyn Transport>>,
     mdns_transport: Option<Arc<dyn Transport>>, // Enhanced mDNS transport for local discovery
     nat_transport: Option<Arc<dyn Transport>>,
     email_transport: Option<Arc<dyn Transport>>, // Enhanced email transport for reliability
+    /// Transports registered at runtime, with their own selection, breakers and metrics
+    custom_transports: TransportManager,
     transport_selector: Arc<RwLock<TransportSelector>>,
+    /// Discovery results, kept fresh by successful sends and dropped on failures
+    discovery_cache: Arc<DiscoveryCache>,
     route_cache: Arc<RwLock<HashMap<String, (TransportRoute, Instant)>>>,
     cache_duration: Duration,
+    prepared_routes: Arc<RwLock<HashMap<String, PreparedRoute>>>,
+    prepared_route_ttl: Duration,
+    sessions: Arc<SessionCache>,
+    replay_guard: Arc<ReplayGuard>,
+    /// What peers have said their transports accept
+    peer_capabilities: Arc<PeerCapabilities>,
+    /// Measured clock offsets of peers, used by timestamp checks
+    peer_clocks: Arc<PeerClocks>,
+    /// Our NAT classification, advertised in capability digests
+    nat_types: Arc<NatTypeCache>,
+    /// STUN servers and timeout used to classify our NAT
+    nat_config: crate::config::NatConfig,
+    /// Relays holding our mail, advertised in capability digests
+    home_relays: Vec<crate::config::EmailRelayEndpoint>,
+    /// Hands messages to peers' fast email relays
+    #[cfg(feature = "http")]
+    fast_relay_client: Option<Arc<FastRelayClient>>,
+    /// Host advertised for listeners bound to a wildcard address
+    advertise_host: Option<String>,
+    /// Endpoints peers announced in signed updates
+    peer_endpoints: Arc<EndpointCache>,
     #[allow(dead_code)]
     our_entity_id: String,
     performance_monitoring: bool,
+    /// Receives transport and discovery events
+    events: EventBus,
 }
 
 impl MultiTransportRouter {
     /// Create a new multi-transport router with default provider
     pub async fn new(config: Config, our_entity_id: String) -> Result<Self> {
-        let provider = ProductionTransportProvider;
+        let provider = ProductionTransportProvider::default();
         Self::new_with_provider(config, our_entity_id, Box::new(provider)).await
     }
 
@@ -126,30 +224,195 @@ impl MultiTransportRouter {
     ) -> Result<Self> {
         info!("Initializing multi-transport router for entity: {}", our_entity_id);
         
-        // Initialize transports through dependency injection
-        let tcp_transport = provider.create_tcp_transport(&config).await?;
-        let mdns_transport = provider.create_mdns_transport(&config).await?;
-        let nat_transport = provider.create_nat_transport(&config).await?;
-        let email_transport = provider.create_email_transport(&config).await?;
+        // Initialize transports through dependency injection, all at once
+        let (tcp_transport, mdns_transport, nat_transport, email_transport) = tokio::try_join!(
+            provider.create_tcp_transport(&config),
+            provider.create_mdns_transport(&config),
+            provider.create_nat_transport(&config),
+            provider.create_email_transport(&config),
+        )?;
         let transport_selector = provider.create_transport_selector();
+        transport_selector.write().await.set_peer_ports(config.listeners.peer_ports.clone());
+        let discovery_cache = transport_selector.read().await.discovery_cache();
 
         if tcp_transport.is_none() && mdns_transport.is_none() && 
            nat_transport.is_none() && email_transport.is_none() {
             warn!("No transports available - router may have limited functionality");
         }
 
+        let sessions = SessionCache::load(config.resumption.clone()).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable session ticket store: {}", e);
+            SessionCache::new(config.resumption.clone())
+        });
+        let peer_clocks = Arc::new(PeerClocks::new(config.time_sync.max_samples));
+        let replay_guard = ReplayGuard::from_config(&config.resumption).with_clocks(peer_clocks.clone());
+        #[cfg(feature = "http")]
+        let fast_relay_client = FastRelayClient::new(Duration::from_millis(config.fast_relay.handoff_timeout_ms))
+            .map(Arc::new)
+            .map_err(|e| warn!("Fast email relays unavailable: {}", e))
+            .ok();
+
         Ok(Self {
             tcp_transport,
             mdns_transport,
             nat_transport,
             email_transport,
+            custom_transports: TransportManager::new(TransportManagerConfig {
+                enabled_transports: Vec::new(),
+                region: config.entity.region.clone(),
+                keepalive: KeepaliveSettings {
+                    interval: Duration::from_secs(config.nat.keepalive_interval_secs),
+                    idle_timeout: Duration::from_secs(config.nat.keepalive_idle_secs),
+                },
+                ..TransportManagerConfig::default()
+            })
+            .with_peer_clocks(peer_clocks.clone()),
             transport_selector,
+            discovery_cache,
             route_cache: Arc::new(RwLock::new(HashMap::new())),
             cache_duration: Duration::from_secs(300), // 5 minutes
+            prepared_route_ttl: Duration::from_secs(60), // matches the TCP warm-stream lifetime
+            prepared_routes: Arc::new(RwLock::new(HashMap::new())),
+            sessions: Arc::new(sessions),
+            replay_guard: Arc::new(replay_guard),
+            peer_capabilities: Arc::new(PeerCapabilities::new(Duration::from_secs(config.probing.cache_ttl_secs))),
+            peer_clocks,
+            nat_types: Arc::new(NatTypeCache::new(Duration::from_secs(config.nat.cache_ttl_secs))),
+            nat_config: config.nat.clone(),
+            home_relays: config.fast_relay.home_relays.clone(),
+            #[cfg(feature = "http")]
+            fast_relay_client,
+            advertise_host: config.listeners.advertise_host.clone(),
+            peer_endpoints: Arc::new(EndpointCache::new()),
             our_entity_id,
             performance_monitoring: true,
+            events: EventBus::default(),
         })
     }
+
+    /// Publish transport and discovery events to `events`, typically the router's bus
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.custom_transports = self.custom_transports.with_event_bus(events.clone());
+        self.events = events;
+        self
+    }
+
+    /// Register a transport at runtime and start it
+    ///
+    /// `factory` must create a [`TransportType::Custom`] transport. For the
+    /// urgencies `capabilities` declares, it is offered every send ahead of the
+    /// built-in transports, subject to the same route permits; among several
+    /// custom transports the manager's selection policy, circuit breakers and
+    /// failure tracking decide. [`Self::custom_capabilities`] advertises it.
+    pub async fn register_custom_transport(
+        &self,
+        factory: Box<dyn TransportFactory>,
+        capabilities: TransportCapabilities,
+    ) -> Result<()> {
+        let transport_type = factory.transport_type();
+        if !matches!(transport_type, TransportType::Custom(_)) {
+            return Err(SynapseError::Config(format!(
+                "{} is a built-in transport; custom transports use TransportType::Custom", transport_type
+            )));
+        }
+        self.custom_transports.add_transport(factory, Some(capabilities)).await?;
+        info!("Registered custom transport {}", transport_type);
+        Ok(())
+    }
+
+    /// Stop a custom transport and remove it from selection
+    pub async fn unregister_custom_transport(&self, transport_type: TransportType) -> Result<()> {
+        self.custom_transports.remove_transport(transport_type).await
+    }
+
+    /// Manager of the custom transports, for their status and metrics
+    pub fn custom_transports(&self) -> &TransportManager {
+        &self.custom_transports
+    }
+
+    /// Whether a custom transport declares support for `urgency`
+    pub async fn has_custom_transport_for(&self, urgency: MessageUrgency) -> bool {
+        !self.custom_routes(urgency).await.is_empty()
+    }
+
+    /// Capability names advertised for the custom transports
+    pub async fn custom_capabilities(&self) -> Vec<String> {
+        let mut capabilities = Vec::new();
+        for transport_type in self.custom_transports.list_available_transports().await {
+            let (TransportType::Custom(id), Some(declared)) = (
+                transport_type,
+                self.custom_transports.get_transport_capabilities(transport_type).await,
+            ) else {
+                continue;
+            };
+            capabilities.push(format!("custom:{}", id));
+            capabilities.extend(declared.features);
+        }
+        capabilities.sort();
+        capabilities.dedup();
+        capabilities
+    }
+
+    /// Routes over the custom transports that declare support for `urgency`
+    async fn custom_routes(&self, urgency: MessageUrgency) -> Vec<TransportRoute> {
+        let metrics = self.custom_transports.get_metrics().await;
+        let mut routes = Vec::new();
+        for transport_type in self.custom_transports.list_available_transports().await {
+            let Some(capabilities) = self.custom_transports.get_transport_capabilities(transport_type).await else {
+                continue;
+            };
+            if !capabilities.supported_urgencies.contains(&urgency) {
+                continue;
+            }
+            let latency_ms = metrics.transport_metrics
+                .get(&transport_type)
+                .map_or(0, |m| m.average_latency_ms as u32);
+            routes.push(TransportRoute::Custom {
+                transport_type,
+                latency_ms,
+                network_spanning: capabilities.network_spanning,
+            });
+        }
+        routes
+    }
+
+    /// Send over a permitted custom transport that handles `urgency`
+    ///
+    /// Returns `None` when there is none or all of them fail, so the caller
+    /// falls through to the built-in transports.
+    async fn try_custom(
+        &self,
+        target: &str,
+        message: &SecureMessage,
+        urgency: MessageUrgency,
+        permits: &impl Fn(&TransportRoute) -> bool,
+    ) -> Option<DeliveryReceipt> {
+        let permitted: Vec<TransportType> = self.custom_routes(urgency).await
+            .into_iter()
+            .filter(|route| permits(route))
+            .filter_map(|route| match route {
+                TransportRoute::Custom { transport_type, .. } => Some(transport_type),
+                _ => None,
+            })
+            .collect();
+        if permitted.is_empty() {
+            return None;
+        }
+
+        let target_obj = TransportTarget::new(target.to_string()).with_urgency(urgency);
+        match self.custom_transports
+            .send_message_where(&target_obj, message, |t| permitted.contains(&t))
+            .await {
+            Ok(receipt) => {
+                debug!("Sent to {} via a custom transport", target);
+                Some(receipt)
+            }
+            Err(e) => {
+                debug!("No custom transport delivered to {}, trying built-in ones: {}", target, e);
+                None
+            }
+        }
+    }
     
     /// Send message with automatic transport selection
     pub async fn send_message(
@@ -157,14 +420,58 @@ impl MultiTransportRouter {
         target: &str, 
         message: &SecureMessage, 
         urgency: MessageUrgency
+    ) -> Result<DeliveryReceipt> {
+        self.send_message_where(target, message, urgency, |_| true).await
+    }
+    
+    /// Send message with automatic transport selection, using only routes `permits` accepts
+    ///
+    /// Routes the target has told us (via a capability probe) it cannot take
+    /// for this message are skipped as well.
+    pub async fn send_message_where(
+        &self, 
+        target: &str, 
+        message: &SecureMessage, 
+        urgency: MessageUrgency,
+        permits: impl Fn(&TransportRoute) -> bool,
     ) -> Result<DeliveryReceipt> {
         let start = Instant::now();
+        let permits = |route: &TransportRoute| {
+            permits(route) && self.peer_capabilities.accepts(target, route, message)
+        };
+
+        // Custom transports registered for this urgency get first refusal
+        if let Some(receipt) = self.try_custom(target, message, urgency, &permits).await {
+            return Ok(receipt);
+        }
         
         // Check cache first
         if let Some(cached_route) = self.get_cached_route(target).await {
-            if self.is_route_suitable(&cached_route, urgency) {
+            if self.is_route_suitable(&cached_route, urgency) && permits(&cached_route) {
                 debug!("Using cached route for {}: {:?}", target, cached_route);
-                return self.send_via_route(target, message, &cached_route).await;
+                let result = self.send_via_route(target, message, &cached_route).await;
+                self.record_delivery(target, &cached_route, &result).await;
+                return result;
+            }
+        }
+
+        // Resume a recent session, skipping discovery and handshakes
+        if let Some(receipt) = self.try_resume(target, message, urgency, &permits).await {
+            return Ok(receipt);
+        }
+
+        // Peers that announced their endpoints are dialed there without discovery
+        if let Some(route) = self.announced_route(target) {
+            if self.is_route_suitable(&route, urgency) && permits(&route) {
+                let result = self.send_via_route(target, message, &route).await;
+                self.record_delivery(target, &route, &result).await;
+                match result {
+                    Ok(receipt) => {
+                        self.cache_route(target.to_string(), route).await;
+                        return Ok(receipt);
+                    }
+                    Err(e) => debug!("Announced endpoint of {} failed, discovering: {}", target, e),
+                }
             }
         }
         
@@ -173,12 +480,20 @@ impl MultiTransportRouter {
         match selector.choose_optimal_transport(target, urgency).await {
             Ok(route) => {
                 drop(selector); // Release lock early
+                let route = self.prefer_fast_relay(target, route, &permits);
+                
+                if !permits(&route) {
+                    return Err(SynapseError::NoTransportAvailable(format!(
+                        "Selected route to {} is not permitted: {:?}", target, route
+                    )));
+                }
                 
                 // Cache the route
                 self.cache_route(target.to_string(), route.clone()).await;
                 
                 // Send via selected route
                 let result = self.send_via_route(target, message, &route).await;
+                self.record_delivery(target, &route, &result).await;
                 
                 if self.performance_monitoring {
                     let elapsed = start.elapsed();
@@ -191,12 +506,534 @@ impl MultiTransportRouter {
                 warn!("Transport selection failed for {}: {}", target, e);
                 
                 // Fallback to email if all else fails
+                if !permits(&TransportRoute::StandardEmail { estimated_latency_min: 0 }) {
+                    return Err(SynapseError::NoTransportAvailable(format!(
+                        "No permitted transport to {}", target
+                    )));
+                }
+                if let Some(route) = self.fast_relay_route(target).filter(|route| permits(route)) {
+                    info!("Handing message for {} to its fast email relay", target);
+                    return self.send_via_route(target, message, &route).await;
+                }
                 info!("Falling back to email transport for {}", target);
                 self.send_via_email(target, message).await
             }
         }
     }
+
+    /// Client for handing off to and collecting from fast email relays
+    #[cfg(feature = "http")]
+    pub fn fast_relay_client(&self) -> Option<Arc<FastRelayClient>> {
+        self.fast_relay_client.clone()
+    }
+
+    /// Route through one of the fast email relays `target` advertised, if any
+    fn fast_relay_route(&self, target: &str) -> Option<TransportRoute> {
+        #[cfg(feature = "http")]
+        {
+            self.fast_relay_client.as_ref()?;
+            let relay = self.peer_capabilities.get(target)?.email_relays.into_iter().next()?;
+            Some(TransportRoute::FastEmailRelay {
+                relay_server: relay.relay,
+                estimated_latency_ms: relay.latency_target_ms,
+            })
+        }
+        #[cfg(not(feature = "http"))]
+        {
+            let _ = target;
+            None
+        }
+    }
+
+    /// `route`, or the peer's fast email relay when `route` is plain email
+    fn prefer_fast_relay(&self, target: &str, route: TransportRoute, permits: impl Fn(&TransportRoute) -> bool) -> TransportRoute {
+        if !matches!(route, TransportRoute::StandardEmail { .. }) {
+            return route;
+        }
+        self.fast_relay_route(target).filter(|relay| permits(relay)).unwrap_or(route)
+    }
     
+    /// Send many messages, batching those that share a route
+    ///
+    /// Messages are grouped by target and up to [`BATCH_TARGET_CONCURRENCY`]
+    /// targets are served at once. The first message to a target resolves its
+    /// route as [`Self::send_message`] would; when that route runs over a
+    /// connection-oriented transport, the rest of the group is handed to
+    /// [`Transport::send_batch`], which pipelines it on one pooled connection.
+    /// Items the batch could not deliver are retried one at a time, so each
+    /// gets the same fallbacks as a single send. Results are in input order.
+    pub async fn send_batch(
+        &self,
+        items: Vec<(String, SecureMessage)>,
+        urgency: MessageUrgency,
+    ) -> Vec<Result<DeliveryReceipt>> {
+        self.send_batch_where(items, urgency, |_, _| true).await
+    }
+
+    /// [`Self::send_batch`], using only routes `permits` accepts for each target
+    pub async fn send_batch_where(
+        &self,
+        items: Vec<(String, SecureMessage)>,
+        urgency: MessageUrgency,
+        permits: impl Fn(&str, &TransportRoute) -> bool,
+    ) -> Vec<Result<DeliveryReceipt>> {
+        let count = items.len();
+        let mut groups: Vec<(String, Vec<(usize, SecureMessage)>)> = Vec::new();
+        let mut group_of: HashMap<String, usize> = HashMap::new();
+        for (index, (target, message)) in items.into_iter().enumerate() {
+            let group = *group_of.entry(target.clone()).or_insert_with(|| {
+                groups.push((target, Vec::new()));
+                groups.len() - 1
+            });
+            groups[group].1.push((index, message));
+        }
+
+        let start = Instant::now();
+        let target_count = groups.len();
+        let permits = &permits;
+        let mut sends = stream::iter(groups.into_iter().map(|(target, messages)| async move {
+            let (indices, messages): (Vec<usize>, Vec<SecureMessage>) = messages.into_iter().unzip();
+            let results = self
+                .send_batch_to(&target, messages, urgency, |route| permits(&target, route))
+                .await;
+            indices.into_iter().zip(results).collect::<Vec<_>>()
+        }))
+        .buffer_unordered(BATCH_TARGET_CONCURRENCY);
+
+        let mut results: Vec<Option<Result<DeliveryReceipt>>> = (0..count).map(|_| None).collect();
+        while let Some(group) = sends.next().await {
+            for (index, result) in group {
+                results[index] = Some(result);
+            }
+        }
+
+        if self.performance_monitoring {
+            info!("Sent a batch of {} messages to {} targets in {:?}", count, target_count, start.elapsed());
+        }
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(SynapseError::TransportError("Batch item was not sent".into()))))
+            .collect()
+    }
+
+    /// Send one target's share of a batch
+    async fn send_batch_to(
+        &self,
+        target: &str,
+        messages: Vec<SecureMessage>,
+        urgency: MessageUrgency,
+        permits: impl Fn(&TransportRoute) -> bool,
+    ) -> Vec<Result<DeliveryReceipt>> {
+        let mut results = Vec::with_capacity(messages.len());
+        let mut pending = messages.into_iter();
+
+        // Single sends until one succeeds, which leaves the route cached
+        for message in pending.by_ref() {
+            let result = self.send_message_where(target, &message, urgency, &permits).await;
+            let delivered = result.is_ok();
+            results.push(result);
+            if delivered {
+                break;
+            }
+        }
+        let pending: Vec<SecureMessage> = pending.collect();
+        if pending.is_empty() {
+            return results;
+        }
+
+        let batch_route = self.get_cached_route(target).await.filter(|route| {
+            self.is_route_suitable(route, urgency)
+                && permits(route)
+                && pending.iter().all(|message| self.peer_capabilities.accepts(target, route, message))
+        });
+        let Some((route, transport)) = batch_route.and_then(|route| {
+            let transport = self.batch_transport(&route)?;
+            Some((route, transport))
+        }) else {
+            for message in &pending {
+                results.push(self.send_message_where(target, message, urgency, &permits).await);
+            }
+            return results;
+        };
+
+        let batch = transport.send_batch(&self.target_for(target, &route), &pending).await;
+        if let Some(outcome) = batch.iter().find(|result| result.is_ok()).or_else(|| batch.first()) {
+            self.record_delivery(target, &route, outcome).await;
+        }
+        for (message, result) in pending.iter().zip(batch) {
+            match result {
+                Ok(receipt) => results.push(Ok(receipt)),
+                Err(e) => {
+                    debug!("Batched message to {} failed, sending it alone: {}", target, e);
+                    results.push(self.send_message_where(target, message, urgency, &permits).await);
+                }
+            }
+        }
+        results
+    }
+
+    /// Transport a batch over `route` is handed to, if the route carries batches
+    ///
+    /// mDNS, email and custom routes keep per-message delivery.
+    fn batch_transport(&self, route: &TransportRoute) -> Option<Arc<dyn Transport>> {
+        match route {
+            TransportRoute::DirectTcp { .. } |
+            TransportRoute::DirectUdp { .. } |
+            TransportRoute::Udp { .. } |
+            TransportRoute::WebSocket { .. } |
+            TransportRoute::Quic { .. } => self.tcp_transport.clone(),
+            TransportRoute::NatTraversal { .. } => self.nat_transport.clone(),
+            _ => None,
+        }
+    }
+
+    /// Send over the route from a peer's session ticket, if one is still valid
+    ///
+    /// With early data enabled the message goes out immediately, tagged with the
+    /// ticket ID for replay checks; otherwise the route is warmed up first. A
+    /// failed resumption falls through to normal discovery.
+    async fn try_resume(
+        &self,
+        target: &str,
+        message: &SecureMessage,
+        urgency: MessageUrgency,
+        permits: &impl Fn(&TransportRoute) -> bool,
+    ) -> Option<DeliveryReceipt> {
+        let ticket = self.sessions.peek(target)?;
+        let route = ticket.route.to_route(ticket.latency_ms);
+        if !self.is_route_suitable(&route, urgency) || !permits(&route) {
+            return None;
+        }
+        // Tickets are single-use; a concurrent send may already have taken it
+        let ticket = self.sessions.resume(target)?;
+
+        let result = if self.sessions.early_data() {
+            let mut early = message.clone();
+            early.metadata.insert(RESUMPTION_TICKET_KEY.to_string(), ticket.id.to_string());
+            self.send_via_route(target, &early, &route).await
+        } else {
+            match self.warm_route(target, &route).await {
+                Ok(_) => self.send_via_route(target, message, &route).await,
+                Err(e) => Err(e),
+            }
+        };
+
+        match result {
+            Ok(receipt) => {
+                debug!("Resumed session to {} via {:?}", target, route);
+                self.cache_route(target.to_string(), route.clone()).await;
+                self.sessions.issue(target, &route, receipt.delivery_time.as_millis() as u32);
+                Some(receipt)
+            }
+            Err(e) => {
+                debug!("Resumed session to {} failed, rediscovering: {}", target, e);
+                self.forget_failed_route(target).await;
+                None
+            }
+        }
+    }
+
+    /// Feed a send's outcome back into sessions and discovery
+    ///
+    /// A success issues a fresh session ticket and confirms the discovered
+    /// routes with the measured latency; a failure drops cached routes so the
+    /// next send probes again.
+    async fn record_delivery(&self, target: &str, route: &TransportRoute, result: &Result<DeliveryReceipt>) {
+        match result {
+            Ok(receipt) => {
+                self.sessions.issue(target, route, receipt.delivery_time.as_millis() as u32);
+                self.discovery_cache.record_success(target, receipt.delivery_time);
+            }
+            Err(_) => self.forget_failed_route(target).await,
+        }
+    }
+
+    /// Drop cached and discovered routes to a target after a failed send
+    async fn forget_failed_route(&self, target: &str) {
+        self.route_cache.write().await.remove(target);
+        if self.discovery_cache.invalidate(target) {
+            debug!("Dropped discovered routes to {} after a failed send", target);
+        }
+    }
+
+    /// Discovery results, with latencies measured by later sends
+    pub fn discovery_cache(&self) -> Arc<DiscoveryCache> {
+        Arc::clone(&self.discovery_cache)
+    }
+
+    /// Endpoints peers announced in signed updates
+    pub fn peer_endpoints(&self) -> Arc<EndpointCache> {
+        Arc::clone(&self.peer_endpoints)
+    }
+
+    /// Drop cached, prepared and resumable routes to a peer
+    ///
+    /// Called when the peer announces new endpoints, so the next send does not
+    /// go to the old address.
+    pub async fn forget_routes(&self, target: &str) {
+        self.route_cache.write().await.remove(target);
+        self.prepared_routes.write().await.remove(target);
+        self.sessions.invalidate(target);
+        self.discovery_cache.invalidate(target);
+    }
+
+    /// Direct TCP route to the endpoint a peer announced, if any
+    fn announced_route(&self, target: &str) -> Option<TransportRoute> {
+        self.tcp_transport.as_ref()?;
+        let endpoint = self.peer_endpoints.endpoint(target, "tcp")?;
+        let (host, port) = endpoint.rsplit_once(':')?;
+        Some(TransportRoute::DirectTcp {
+            address: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            port: port.parse().ok()?,
+            latency_ms: 0,
+            established_at: Instant::now(),
+        })
+    }
+
+    /// Transport target for `target`, at the endpoint it announced for the route's carrier
+    fn target_for(&self, target: &str, route: &TransportRoute) -> TransportTarget {
+        let target_obj = TransportTarget::new(target.to_string());
+        match self.peer_endpoints.endpoint(target, &route_carrier(route)) {
+            Some(endpoint) => target_obj.with_address(endpoint),
+            None => target_obj,
+        }
+    }
+
+    /// Addresses our listening transports are bound to, keyed by transport name
+    pub fn local_endpoints(&self) -> HashMap<String, SocketAddr> {
+        let builtin = [
+            ("tcp", &self.tcp_transport),
+            ("mdns", &self.mdns_transport),
+            ("nat", &self.nat_transport),
+            ("email", &self.email_transport),
+        ];
+        builtin
+            .into_iter()
+            .filter_map(|(name, transport)| {
+                transport
+                    .as_ref()
+                    .and_then(|transport| transport.local_endpoint())
+                    .map(|addr| (name.to_string(), addr))
+            })
+            .collect()
+    }
+
+    /// Advertise the addresses our listeners actually bound in `identity`
+    pub fn advertise_endpoints(&self, identity: &mut GlobalIdentity) {
+        advertise_endpoints(identity, &self.local_endpoints(), self.advertise_host.as_deref());
+    }
+
+    /// Capabilities peers have answered probes with
+    pub fn peer_capabilities(&self) -> Arc<PeerCapabilities> {
+        Arc::clone(&self.peer_capabilities)
+    }
+
+    /// Peers' measured clock offsets
+    pub fn peer_clocks(&self) -> Arc<PeerClocks> {
+        Arc::clone(&self.peer_clocks)
+    }
+
+    /// Digest of our transports' limits, sent in capability probes and answers
+    pub async fn local_capability_digest(&self) -> CapabilityDigest {
+        let mut digest = CapabilityDigest {
+            nat_type: self.nat_types.get().map(|classification| classification.nat_type),
+            protocol: Some(VersionRange::LOCAL),
+            hashes: crate::hashing::PREFERENCE.to_vec(),
+            ..CapabilityDigest::default()
+        };
+        digest.add_email_relays(&self.home_relays);
+        let builtin = [
+            ("tcp", &self.tcp_transport),
+            ("mdns", &self.mdns_transport),
+            ("nat", &self.nat_transport),
+            ("email", &self.email_transport),
+        ];
+        for (carrier, transport) in builtin {
+            if let Some(transport) = transport {
+                digest.insert(carrier, &transport.capabilities());
+            }
+        }
+        for transport_type in self.custom_transports.list_available_transports().await {
+            let (TransportType::Custom(id), Some(capabilities)) = (
+                transport_type,
+                self.custom_transports.get_transport_capabilities(transport_type).await,
+            ) else {
+                continue;
+            };
+            digest.insert(format!("custom:{}", id), &capabilities);
+        }
+        digest
+    }
+
+    /// Our cached NAT classification
+    pub fn nat_types(&self) -> Arc<NatTypeCache> {
+        Arc::clone(&self.nat_types)
+    }
+
+    /// Classify our NAT, reusing the cached result while it is fresh
+    pub async fn classify_nat(&self, refresh: bool) -> Result<NatClassification> {
+        if !refresh {
+            if let Some(classification) = self.nat_types.get() {
+                return Ok(classification);
+            }
+        }
+        let timeout = Duration::from_millis(self.nat_config.timeout_ms);
+        let classification = nat_behavior::classify(&self.nat_config.stun_servers, timeout).await?;
+        info!("NAT classified as {:?} via {}", classification.nat_type, classification.server);
+        self.nat_types.store(classification.clone());
+        Ok(classification)
+    }
+
+    /// How to connect to `target`, from our NAT type and the one it advertised
+    ///
+    /// Peers that have not told us their type, and our own type before it is
+    /// classified, count as unknown.
+    pub fn traversal_strategy(&self, target: &str) -> TraversalStrategy {
+        let theirs = self.peer_capabilities.get(target).and_then(|digest| digest.nat_type);
+        nat_behavior::select_strategy(
+            self.nat_types.nat_type(),
+            theirs.unwrap_or(nat_behavior::NatType::Unknown),
+            !self.nat_config.turn_servers.is_empty(),
+        )
+    }
+
+    /// Session tickets for recently contacted peers
+    ///
+    /// Transports with their own session state (TLS/QUIC tickets) attach it here.
+    pub fn sessions(&self) -> Arc<SessionCache> {
+        Arc::clone(&self.sessions)
+    }
+
+    /// Write session tickets to the configured store
+    pub fn persist_sessions(&self) -> Result<()> {
+        self.sessions.persist()
+    }
+
+    /// Check an incoming message against early-data replay protection
+    pub fn accept_incoming(&self, message: &SecureMessage) -> Result<()> {
+        self.replay_guard.check(message)
+    }
+
+    /// Take the messages received by the direct and registered transports
+    ///
+    /// Email is left to the email gateway, which receives it itself. Replayed
+    /// early data is dropped.
+    pub async fn receive_messages(&self) -> Vec<IncomingMessage> {
+        let mut received = Vec::new();
+        for transport in [&self.tcp_transport, &self.mdns_transport, &self.nat_transport].into_iter().flatten() {
+            match transport.receive_messages().await {
+                Ok(messages) => {
+                    for incoming in &messages {
+                        self.custom_transports.note_route_activity(incoming.transport_type, &incoming.message.from_global_id);
+                    }
+                    received.extend(messages);
+                }
+                Err(e) => debug!("Could not receive from {}: {}", transport.transport_type(), e),
+            }
+        }
+        received.retain(|incoming| match self.accept_incoming(&incoming.message) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("Dropping message from {}: {}", incoming.message.from_global_id, e);
+                false
+            }
+        });
+        match self.custom_transports.receive_messages().await {
+            Ok(messages) => received.extend(messages),
+            Err(e) => debug!("Could not receive from registered transports: {}", e),
+        }
+        received
+    }
+
+    /// Warm up a route to `target` before a burst of traffic
+    ///
+    /// Runs transport selection, then the route's setup work (mDNS discovery, NAT
+    /// traversal or pre-connecting a pooled stream) so the first real message
+    /// skips it. The route is cached, so sends within the expiry reuse it.
+    pub async fn prepare_route(
+        &self,
+        target: &str,
+        urgency: MessageUrgency,
+        permits: impl Fn(&TransportRoute) -> bool,
+    ) -> Result<PreparedRoute> {
+        if let Some(prepared) = self.prepared_route(target).await {
+            if prepared.urgency == urgency && permits(&prepared.route) {
+                debug!("Reusing prepared route to {} ({:?} left)", target, prepared.remaining());
+                return Ok(prepared);
+            }
+        }
+
+        let start = Instant::now();
+        let route = {
+            let mut selector = self.transport_selector.write().await;
+            selector.choose_optimal_transport(target, urgency).await?
+        };
+        if !permits(&route) {
+            return Err(SynapseError::NoTransportAvailable(format!(
+                "Selected route to {} is not permitted: {:?}", target, route
+            )));
+        }
+
+        let nat_method = self.warm_route(target, &route).await?;
+        self.cache_route(target.to_string(), route.clone()).await;
+
+        let prepared_at = Instant::now();
+        let prepared = PreparedRoute {
+            target: target.to_string(),
+            route,
+            urgency,
+            nat_method,
+            key_ready: false,
+            warmup_time: start.elapsed(),
+            prepared_at,
+            expires_at: prepared_at + self.prepared_route_ttl,
+        };
+        info!("Prepared route to {} via {:?} in {:?}", target, prepared.route, prepared.warmup_time);
+
+        let mut prepared_routes = self.prepared_routes.write().await;
+        prepared_routes.retain(|_, p| !p.is_expired());
+        prepared_routes.insert(target.to_string(), prepared.clone());
+        Ok(prepared)
+    }
+
+    /// Get the unexpired prepared route for a target, if any
+    pub async fn prepared_route(&self, target: &str) -> Option<PreparedRoute> {
+        let prepared_routes = self.prepared_routes.read().await;
+        prepared_routes.get(target).filter(|p| !p.is_expired()).cloned()
+    }
+
+    /// Perform the setup work a route needs before its first send
+    async fn warm_route(&self, target: &str, route: &TransportRoute) -> Result<Option<NatMethod>> {
+        match route {
+            TransportRoute::LocalMdns { .. } => {
+                self.discover_local_peer(target).await?;
+                Ok(None)
+            }
+            TransportRoute::NatTraversal { .. } => {
+                self.establish_nat_traversal(target).await.map(Some)
+            }
+            TransportRoute::FastEmailRelay { .. } |
+            TransportRoute::StandardEmail { .. } |
+            TransportRoute::EmailDiscovery { .. } => {
+                // Store-and-forward: nothing to hold open
+                Ok(None)
+            }
+            TransportRoute::Custom { transport_type, .. } => {
+                let target_obj = TransportTarget::new(target.to_string());
+                self.custom_transports.prepare_transport(*transport_type, &target_obj).await?;
+                Ok(None)
+            }
+            _ => {
+                // Every other route is carried over the TCP transport
+                let transport = self.tcp_transport.as_ref().ok_or_else(|| {
+                    SynapseError::TransportError("TCP transport not available".into())
+                })?;
+                transport.prepare(&TransportTarget::new(target.to_string())).await?;
+                Ok(None)
+            }
+        }
+    }
+
     /// Send message with explicit fallback priority
     pub async fn send_with_fallback_priority(
         &self,
@@ -224,7 +1061,7 @@ impl MultiTransportRouter {
     
     /// Send via specific transport route
     async fn send_via_route(&self, target: &str, message: &SecureMessage, route: &TransportRoute) -> Result<DeliveryReceipt> {
-        let target_obj = TransportTarget::new(target.to_string());
+        let target_obj = self.target_for(target, route);
         
         match route {
             TransportRoute::DirectTcp { .. } => {
@@ -271,16 +1108,65 @@ impl MultiTransportRouter {
             }
             TransportRoute::NatTraversal { .. } => {
                 if let Some(ref transport) = self.nat_transport {
-                    transport.send_message(&target_obj, message).await
+                    let receipt = transport.send_message(&target_obj, message).await?;
+                    // The hole punched for this peer closes within seconds of silence
+                    self.custom_transports.keep_alive_route(Arc::clone(transport), &target_obj);
+                    Ok(receipt)
                 } else {
                     Err(crate::error::SynapseError::TransportError("NAT traversal transport not available".into()))
                 }
             }
-            TransportRoute::FastEmailRelay { .. } | 
+            TransportRoute::FastEmailRelay { relay_server, .. } => {
+                match self.send_via_fast_relay(target, relay_server, message).await {
+                    Ok(receipt) => Ok(receipt),
+                    Err(e) => {
+                        warn!("Fast relay {} failed for {}, using plain email: {}", relay_server, target, e);
+                        self.send_via_email(target, message).await
+                    }
+                }
+            }
             TransportRoute::StandardEmail { .. } | 
             TransportRoute::EmailDiscovery { .. } => {
                 self.send_via_email(target, message).await
             }
+            TransportRoute::Custom { transport_type, .. } => {
+                self.custom_transports.send_message_via(*transport_type, &target_obj, message).await
+            }
+        }
+    }
+
+    /// Hand a message to the fast email relay `relay_id` that `target` advertised
+    async fn send_via_fast_relay(&self, target: &str, relay_id: &str, message: &SecureMessage) -> Result<DeliveryReceipt> {
+        #[cfg(feature = "http")]
+        {
+            let client = self.fast_relay_client.as_ref().ok_or_else(|| {
+                SynapseError::TransportError("Fast relay client not available".into())
+            })?;
+            let relay = self
+                .peer_capabilities
+                .get(target)
+                .and_then(|digest| digest.email_relays.into_iter().find(|relay| relay.relay == relay_id))
+                .ok_or_else(|| SynapseError::TransportError(format!("{} no longer advertises relay {}", target, relay_id)))?;
+            let start = Instant::now();
+            let receipt = client.handoff(&relay, message).await?;
+            let mut metadata = HashMap::from([("relay".to_string(), receipt.relay.clone())]);
+            if let Some(stored_id) = receipt.stored_id {
+                metadata.insert("relay_stored_id".to_string(), stored_id.to_string());
+            }
+            Ok(DeliveryReceipt {
+                message_id: message.message_id.0.to_string(),
+                transport_used: TransportType::Email,
+                delivery_time: start.elapsed(),
+                target_reached: target.to_string(),
+                // A receipt means the relay holds it and has woken the recipient
+                confirmation: if receipt.stored_id.is_some() { DeliveryConfirmation::Delivered } else { DeliveryConfirmation::Sent },
+                metadata,
+            })
+        }
+        #[cfg(not(feature = "http"))]
+        {
+            let _ = (target, message);
+            Err(SynapseError::TransportError(format!("Cannot reach relay {}: built without the http feature", relay_id)))
         }
     }
     
@@ -385,6 +1271,10 @@ impl MultiTransportRouter {
     /// Discover local peer via mDNS
     pub async fn discover_local_peer(&self, target: &str) -> Result<()> {
         if self.mdns_can_reach(target).await {
+            self.events.publish(RouterEvent::PeerDiscovered {
+                peer: target.to_string(),
+                transport: "mDNS".to_string(),
+            });
             Ok(())
         } else {
             Err(crate::error::SynapseError::TransportError("mDNS peer not found".into()))
@@ -394,6 +1284,21 @@ impl MultiTransportRouter {
     /// Establish NAT traversal connection
     pub async fn establish_nat_traversal(&self, target: &str) -> Result<super::NatMethod> {
         let target_obj = TransportTarget::new(target.to_string());
+        let strategy = self.traversal_strategy(target);
+        if strategy == TraversalStrategy::Turn {
+            let turn = &self.nat_config.turn_servers[0];
+            return Ok(super::NatMethod::Turn {
+                server: turn.server.clone(),
+                username: turn.username.clone(),
+                region: turn.region.clone(),
+            });
+        }
+        if strategy == TraversalStrategy::Relay {
+            return Err(crate::error::SynapseError::TransportError(format!(
+                "Our NAT and {}'s cannot be traversed directly; use a relay",
+                target
+            )));
+        }
         if let Some(ref nat) = self.nat_transport {
             // This is a simplified version - the real implementation would be in NAT transport
             if nat.can_reach(&target_obj).await {
@@ -566,6 +1471,30 @@ impl MultiTransportRouter {
             });
         }
         
+        // Periodically persist session tickets so resumption survives restarts
+        if self.sessions.enabled() {
+            let sessions = Arc::clone(&self.sessions);
+            runtime::spawn(Subsystem::Storage, async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = sessions.persist() {
+                        warn!("Failed to persist session tickets: {}", e);
+                    }
+                }
+            });
+        }
+
+        let available = [
+            ("TCP", self.tcp_transport.is_some()),
+            ("mDNS", self.has_mdns_transport()),
+            ("NAT", self.nat_transport.is_some()),
+            ("Email", self.email_transport.is_some()),
+        ];
+        for (transport, _) in available.into_iter().filter(|(_, up)| *up) {
+            self.events.publish(RouterEvent::TransportUp { transport: transport.to_string() });
+        }
+
         info!("All available transport services started");
         Ok(())
     }
@@ -1,12 +1,20 @@
 //! Enhanced TCP transport implementation with circuit breaker integration
 
 use super::abstraction::{Transport, TransportMetrics};
+use super::binding::bind_tcp;
+use super::proxy::ProxyDialer;
 use crate::{
     types::SecureMessage, 
+    config::{ListenerBinding, DEFAULT_PEER_PORTS},
     error::Result,
     circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, RequestOutcome},
+    limits::ResourceGovernor,
+    buffer_pool::BufferPool,
+    runtime::{self, Subsystem},
 };
 use async_trait::async_trait;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -14,38 +22,181 @@ use tokio::sync::Mutex;
 use std::sync::{Arc, RwLock};
 use tracing::{info, debug, warn, error};
 
+/// Batched messages are coalesced into writes of up to this many bytes
+pub const COALESCE_BYTES: usize = 64 * 1024;
+
+/// Largest unparsed input an incoming connection may buffer
+const MAX_PENDING_BYTES: usize = 16 * 1024 * 1024;
+
+/// How long an incoming connection may sit idle before it is closed
+const READ_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Enhanced TCP transport with circuit breaker for direct peer-to-peer communication
 pub struct TcpTransport {
     listen_port: u16,
     listener: Option<TcpListener>,
+    /// Address the listener actually bound, advertised to peers
+    local_addr: Option<SocketAddr>,
+    /// Ports tried, in order, when a target carries no port
+    peer_ports: Vec<u16>,
     connection_timeout: Duration,
-    pub received_messages: Arc<Mutex<Vec<SecureMessage>>>,
+    /// Received messages with the address each arrived from
+    pub received_messages: Arc<Mutex<Vec<(SecureMessage, SocketAddr)>>>,
     /// Circuit breaker for reliability
     circuit_breaker: Arc<CircuitBreaker>,
     /// Performance metrics
     #[allow(dead_code)]
     metrics: Arc<RwLock<TransportMetrics>>,
+    /// Outbound proxy dialer (direct when no proxy is configured)
+    proxy: Arc<ProxyDialer>,
+    /// Pre-connected streams keyed by "host:port", each used for one send
+    warm_streams: Arc<Mutex<HashMap<String, (TcpStream, Instant)>>>,
+    /// How long a pre-connected stream stays usable
+    warm_stream_ttl: Duration,
+    /// Caps concurrent incoming connections, when set
+    limits: Option<Arc<ResourceGovernor>>,
 }
 
 impl TcpTransport {
     pub async fn new(listen_port: u16) -> Result<Self> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", listen_port)).await.ok();
-        
-        if listener.is_some() {
-            info!("Enhanced TCP transport listening on port {} with circuit breaker", listen_port);
-        } else {
-            warn!("Failed to bind TCP port {}, will operate in client-only mode", listen_port);
+        Self::bind(&ListenerBinding::any(listen_port)).await
+    }
+
+    /// Create a transport listening where `binding` says
+    ///
+    /// When no candidate port can be bound the transport runs client-only.
+    pub async fn bind(binding: &ListenerBinding) -> Result<Self> {
+        let listener = match bind_tcp(binding) {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                warn!("{}, will operate in client-only mode", e);
+                None
+            }
+        };
+        let local_addr = listener.as_ref().and_then(|listener| listener.local_addr().ok());
+        if let Some(addr) = local_addr {
+            info!("Enhanced TCP transport listening on {} with circuit breaker", addr);
         }
         
         Ok(Self {
-            listen_port,
+            listen_port: local_addr.map_or(binding.port, |addr| addr.port()),
             listener,
+            local_addr,
+            peer_ports: DEFAULT_PEER_PORTS.to_vec(),
             connection_timeout: Duration::from_secs(10),
             received_messages: Arc::new(Mutex::new(Vec::new())),
             circuit_breaker: Arc::new(CircuitBreaker::new(CircuitBreakerConfig::default())),
             metrics: Arc::new(RwLock::new(TransportMetrics::default())),
+            proxy: Arc::new(ProxyDialer::direct()),
+            warm_streams: Arc::new(Mutex::new(HashMap::new())),
+            warm_stream_ttl: Duration::from_secs(60),
+            limits: None,
         })
     }
+
+    /// Set the ports tried, in order, when a target carries no port
+    pub fn with_peer_ports(mut self, ports: Vec<u16>) -> Self {
+        self.peer_ports = ports;
+        self
+    }
+
+    /// Address the listener is bound to, if listening
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Set how long pre-connected streams are kept before being discarded
+    pub fn with_warm_stream_ttl(mut self, ttl: Duration) -> Self {
+        self.warm_stream_ttl = ttl;
+        self
+    }
+
+    /// Open a connection now and hold it for the next send to `host:port`
+    pub async fn prewarm(&self, host: &str, port: u16) -> Result<Duration> {
+        let start = Instant::now();
+        let stream = self.connect(host, port).await?;
+        let elapsed = start.elapsed();
+
+        let mut warm = self.warm_streams.lock().await;
+        warm.retain(|_, (_, opened_at)| opened_at.elapsed() < self.warm_stream_ttl);
+        warm.insert(format!("{}:{}", host, port), (stream, Instant::now()));
+        debug!("Pre-connected TCP stream to {}:{} in {:?}", host, port, elapsed);
+        Ok(elapsed)
+    }
+
+    /// Take a pre-connected stream to `host:port` if one is still fresh
+    async fn take_warm_stream(&self, host: &str, port: u16) -> Option<TcpStream> {
+        let mut warm = self.warm_streams.lock().await;
+        match warm.remove(&format!("{}:{}", host, port)) {
+            Some((stream, opened_at)) if opened_at.elapsed() < self.warm_stream_ttl => Some(stream),
+            _ => None,
+        }
+    }
+
+    /// Send over a pre-connected stream when available, otherwise connect fresh
+    async fn send_to(&self, host: &str, port: u16, message: &SecureMessage) -> Result<()> {
+        if let Some(mut stream) = self.take_warm_stream(host, port).await {
+            match self.send_via_stream(&mut stream, message).await {
+                Ok(()) => return Ok(()),
+                Err(e) => debug!("Pre-connected stream to {}:{} went stale: {}", host, port, e),
+            }
+        }
+        let mut stream = self.connect(host, port).await?;
+        self.send_via_stream(&mut stream, message).await
+    }
+
+    /// Stream to `address` for a batch, preferring a pre-connected one
+    ///
+    /// Tries the same endpoints in the same order as a single send.
+    async fn open_stream(&self, address: &str) -> Result<TcpStream> {
+        let mut candidates = Vec::new();
+        if let Some((host, port_str)) = address.rsplit_once(':') {
+            if let Ok(port) = port_str.parse::<u16>() {
+                candidates.push((host, port));
+            }
+        }
+        let host = address.split(':').next().unwrap_or(address);
+        candidates.extend(self.peer_ports.iter().map(|&port| (host, port)));
+        
+        for (host, port) in candidates {
+            if let Some(stream) = self.take_warm_stream(host, port).await {
+                return Ok(stream);
+            }
+            match self.connect(host, port).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => debug!("Failed to open batch stream to {}:{}: {}", host, port, e),
+            }
+        }
+        Err(crate::error::SynapseError::TransportError("No TCP ports available".into()))
+    }
+
+    fn receipt(&self, target: &super::abstraction::TransportTarget, message: &SecureMessage, delivery_time: Duration) -> super::abstraction::DeliveryReceipt {
+        super::abstraction::DeliveryReceipt {
+            message_id: message.message_id.0.to_string(),
+            transport_used: super::abstraction::TransportType::Tcp,
+            delivery_time,
+            target_reached: target.identifier.clone(),
+            confirmation: super::abstraction::DeliveryConfirmation::Sent,
+            metadata: std::collections::HashMap::new(),
+        }
+    }
+
+    /// Refuse incoming connections beyond what `limits` allows
+    pub fn with_resource_limits(mut self, limits: Arc<ResourceGovernor>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Route outbound connections through the given proxy dialer
+    pub fn with_proxy(mut self, proxy: Arc<ProxyDialer>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Get the proxy dialer (for proxy health metrics)
+    pub fn proxy(&self) -> Arc<ProxyDialer> {
+        Arc::clone(&self.proxy)
+    }
     
     /// Get circuit breaker reference for monitoring
     pub fn get_circuit_breaker(&self) -> Arc<CircuitBreaker> {
@@ -66,10 +217,22 @@ impl TcpTransport {
             loop {
                 match listener.accept().await {
                     Ok((stream, addr)) => {
+                        // The slot is held for as long as the connection is served
+                        let slot = match self.limits {
+                            Some(ref limits) => match limits.try_connection() {
+                                Some(slot) => Some(slot),
+                                None => {
+                                    debug!("Refusing TCP connection from {}: connection limit reached", addr);
+                                    continue;
+                                }
+                            },
+                            None => None,
+                        };
                         debug!("Accepted TCP connection from {}", addr);
                         let queue_clone = Arc::clone(&message_queue);
-                        tokio::spawn(async move {
-                            Self::handle_connection(stream, queue_clone).await;
+                        runtime::spawn(Subsystem::Transports, async move {
+                            Self::handle_connection(stream, addr, queue_clone).await;
+                            drop(slot);
                         });
                     }
                     Err(e) => {
@@ -82,45 +245,90 @@ impl TcpTransport {
         }
     }
     
-    async fn handle_connection(mut stream: TcpStream, message_queue: Arc<Mutex<Vec<SecureMessage>>>) {
-        let mut buffer = vec![0; 8192];
+    /// Read messages from a connection until the peer closes it
+    ///
+    /// A connection carries one JSON message or, from a batch sender, several
+    /// written back to back. Each message is queued as soon as it has arrived
+    /// in full.
+    async fn handle_connection(
+        mut stream: TcpStream,
+        source: SocketAddr,
+        message_queue: Arc<Mutex<Vec<(SecureMessage, SocketAddr)>>>,
+    ) {
+        let mut pending = BufferPool::shared().take();
+        let mut chunk = BufferPool::shared().take_zeroed(8192);
         
-        match stream.read(&mut buffer).await {
-            Ok(bytes_read) => {
-                debug!("Received {} bytes via TCP", bytes_read);
-                buffer.truncate(bytes_read);
-                
-                // Parse and handle the message
-                if let Ok(message_str) = String::from_utf8(buffer) {
-                    if let Ok(message) = serde_json::from_str::<SecureMessage>(&message_str) {
-                        debug!("Received Synapse message via TCP: {}", message.message_id);
-                        
-                        // Queue the received message
-                        if let Ok(mut queue) = message_queue.try_lock() {
-                            queue.push(message);
-                            debug!("Queued TCP message, total messages: {}", queue.len());
-                        }
-                    }
+        loop {
+            let bytes_read = match tokio::time::timeout(READ_IDLE_TIMEOUT, stream.read(&mut chunk[..])).await {
+                Ok(Ok(0)) => break,
+                Ok(Ok(bytes_read)) => bytes_read,
+                Ok(Err(e)) => {
+                    warn!("Error reading from TCP connection: {}", e);
+                    break;
                 }
+                Err(_) => {
+                    debug!("Closing idle TCP connection");
+                    break;
+                }
+            };
+            debug!("Received {} bytes via TCP", bytes_read);
+            pending.extend_from_slice(&chunk[..bytes_read]);
+            
+            let Some(consumed) = Self::queue_complete_messages(&pending, source, &message_queue).await else {
+                warn!("Dropping TCP connection sending malformed messages");
+                return;
+            };
+            pending.drain(..consumed);
+            if pending.len() > MAX_PENDING_BYTES {
+                warn!("Dropping TCP connection with an oversized message");
+                return;
             }
-            Err(e) => {
-                warn!("Error reading from TCP connection: {}", e);
+        }
+    }
+
+    /// Queue every complete message at the start of `pending`
+    ///
+    /// Returns how many bytes were consumed, or `None` if the input is not JSON.
+    async fn queue_complete_messages(
+        pending: &[u8],
+        source: SocketAddr,
+        message_queue: &Mutex<Vec<(SecureMessage, SocketAddr)>>,
+    ) -> Option<usize> {
+        let mut messages = serde_json::Deserializer::from_slice(pending).into_iter::<SecureMessage>();
+        let mut complete = Vec::new();
+        loop {
+            match messages.next() {
+                Some(Ok(message)) => {
+                    debug!("Received Synapse message via TCP: {}", message.message_id);
+                    complete.push(message);
+                }
+                Some(Err(e)) if e.is_eof() => break,
+                Some(Err(_)) => return None,
+                None => break,
             }
         }
+        let consumed = messages.byte_offset();
+        
+        if !complete.is_empty() {
+            let mut queue = message_queue.lock().await;
+            queue.extend(complete.into_iter().map(|message| (message, source)));
+            debug!("Queued TCP messages, total messages: {}", queue.len());
+        }
+        Some(consumed)
     }
     
     pub async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
         let addr = format!("{}:{}", host, port);
         debug!("Attempting TCP connection to {}", addr);
         
-        match tokio::time::timeout(self.connection_timeout, TcpStream::connect(&addr)).await {
+        match tokio::time::timeout(self.connection_timeout, self.proxy.connect("tcp", host, port)).await {
             Ok(Ok(stream)) => {
                 debug!("Successfully connected to {}", addr);
                 Ok(stream)
             }
             Ok(Err(e)) => {
                 debug!("Failed to connect to {}: {}", addr, e);
-                Err(crate::error::SynapseError::TransportError(format!("TCP connection failed: {}", e)))
+                Err(e)
             }
             Err(_) => {
                 debug!("Timeout connecting to {}", addr);
@@ -130,10 +338,11 @@ impl TcpTransport {
     }
     
     pub async fn send_via_stream(&self, stream: &mut TcpStream, message: &SecureMessage) -> Result<()> {
-        let message_json = serde_json::to_string(message)
+        let mut message_json = BufferPool::shared().take();
+        serde_json::to_writer(&mut *message_json, message)
             .map_err(|e| crate::error::SynapseError::TransportError(format!("Failed to serialize message: {}", e)))?;
         
-        stream.write_all(message_json.as_bytes()).await
+        stream.write_all(&message_json).await
             .map_err(|e| crate::error::SynapseError::TransportError(format!("Failed to send TCP message: {}", e)))?;
         
         stream.flush().await
@@ -148,39 +357,34 @@ impl TcpTransport {
         if let Some((host, port_str)) = target.rsplit_once(':') {
             if let Ok(port) = port_str.parse::<u16>() {
                 // Direct connection to specified host:port
-                if let Ok(mut stream) = self.connect(host, port).await {
-                    match self.send_via_stream(&mut stream, message).await {
-                        Ok(_) => {
-                            info!("Successfully sent message via TCP to {}:{}", host, port);
-                            return Ok(format!("tcp://{}:{}", host, port));
-                        }
-                        Err(e) => {
-                            warn!("Failed to send via TCP to {}:{}: {}", host, port, e);
-                        }
+                match self.send_to(host, port, message).await {
+                    Ok(()) => {
+                        info!("Successfully sent message via TCP to {}:{}", host, port);
+                        return Ok(format!("tcp://{}:{}", host, port));
+                    }
+                    Err(e) => {
+                        warn!("Failed to send via TCP to {}:{}: {}", host, port, e);
                     }
                 }
             }
         }
         
-        // Fallback: try common Synapse ports on the target host
+        // Fallback: try the configured peer ports on the target host
         let host = if target.contains(':') {
             target.split(':').next().unwrap_or(target)
         } else {
             target
         };
         
-        let ports = vec![8080, 8443, 9090, 7777];
-        for port in ports {
-            if let Ok(mut stream) = self.connect(host, port).await {
-                match self.send_via_stream(&mut stream, message).await {
-                    Ok(_) => {
-                        info!("Successfully sent message via TCP to {}:{}", host, port);
-                        return Ok(format!("tcp://{}:{}", host, port));
-                    }
-                    Err(e) => {
-                        warn!("Failed to send via TCP to {}:{}: {}", host, port, e);
-                        continue;
-                    }
+        for &port in &self.peer_ports {
+            match self.send_to(host, port, message).await {
+                Ok(()) => {
+                    info!("Successfully sent message via TCP to {}:{}", host, port);
+                    return Ok(format!("tcp://{}:{}", host, port));
+                }
+                Err(e) => {
+                    debug!("Failed to send via TCP to {}:{}: {}", host, port, e);
+                    continue;
                 }
             }
         }
@@ -192,9 +396,8 @@ impl TcpTransport {
     #[allow(dead_code)]
     async fn test_connectivity_internal(&self, target: &str) -> Result<Duration> {
         let start = Instant::now();
-        let ports = vec![8080, 8443, 9090, 7777];
         
-        for port in ports {
+        for &port in &self.peer_ports {
             if let Ok(_stream) = self.connect(target, port).await {
                 return Ok(start.elapsed());
             }
@@ -243,8 +446,7 @@ impl Transport for TcpTransport {
         
         // Try with identifier if no specific address
         let host = &target.identifier;
-        let ports = vec![8080, 8443, 9090, 7777];
-        for port in ports {
+        for &port in &self.peer_ports {
             if self.connect(host, port).await.is_ok() {
                 return true;
             }
@@ -260,6 +462,7 @@ impl Transport for TcpTransport {
             cost: 0.1,
             available: self.can_reach(target).await,
             confidence: 0.8,
+            region: None,
         })
     }
     
@@ -272,20 +475,14 @@ impl Transport for TcpTransport {
         }
 
         let start_time = Instant::now();
-        let result = self.send_message_internal(&target.identifier, message).await;
+        let address = target.address.as_deref().unwrap_or(&target.identifier);
+        let result = self.send_message_internal(address, message).await;
 
         // Record the outcome with the circuit breaker
         match &result {
             Ok(_) => {
                 self.circuit_breaker.record_outcome(RequestOutcome::Success).await;
-                Ok(super::abstraction::DeliveryReceipt {
-                    message_id: message.message_id.0.to_string(),
-                    transport_used: self.transport_type(),
-                    delivery_time: start_time.elapsed(),
-                    target_reached: target.identifier.clone(),
-                    confirmation: super::abstraction::DeliveryConfirmation::Sent,
-                    metadata: std::collections::HashMap::new(),
-                })
+                Ok(self.receipt(target, message, start_time.elapsed()))
             }
             Err(e) => {
                 self.circuit_breaker.record_outcome(RequestOutcome::Failure(e.to_string())).await;
@@ -293,14 +490,99 @@ impl Transport for TcpTransport {
             }
         }
     }
+
+    /// Pipeline the messages over one connection
+    ///
+    /// Messages are written back to back and coalesced into writes of up to
+    /// [`COALESCE_BYTES`], so a burst of small notifications costs one connect
+    /// and a few writes instead of a connection each. A pre-connected stream
+    /// is used when one is waiting. If a write fails, messages in earlier
+    /// writes count as sent and the rest fail.
+    async fn send_batch(&self, target: &super::abstraction::TransportTarget, messages: &[SecureMessage]) -> Vec<Result<super::abstraction::DeliveryReceipt>> {
+        let fail_all = |e: crate::error::SynapseError| -> Vec<Result<super::abstraction::DeliveryReceipt>> {
+            messages.iter().map(|_| Err(e.clone())).collect()
+        };
+        if !self.circuit_breaker.can_proceed().await {
+            return fail_all(crate::error::SynapseError::TransportError(
+                format!("Circuit breaker is open for target {}", target.identifier)
+            ));
+        }
+
+        let start_time = Instant::now();
+        let address = target.address.as_deref().unwrap_or(&target.identifier);
+        let mut stream = match self.open_stream(address).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.circuit_breaker.record_outcome(RequestOutcome::Failure(e.to_string())).await;
+                return fail_all(e);
+            }
+        };
+
+        let mut results: Vec<Option<Result<super::abstraction::DeliveryReceipt>>> = messages.iter().map(|_| None).collect();
+        let mut buffer = BufferPool::shared().take();
+        let mut payload = BufferPool::shared().take();
+        let mut buffered = Vec::new();
+        let mut write_error = None;
+        for (index, message) in messages.iter().enumerate() {
+            payload.clear();
+            if let Err(e) = serde_json::to_writer(&mut *payload, message) {
+                results[index] = Some(Err(crate::error::SynapseError::TransportError(
+                    format!("Failed to serialize message: {}", e)
+                )));
+                continue;
+            }
+            if !buffer.is_empty() && buffer.len() + payload.len() > COALESCE_BYTES {
+                if let Err(e) = stream.write_all(&buffer).await {
+                    write_error = Some(e);
+                    break;
+                }
+                for sent in buffered.drain(..) {
+                    results[sent] = Some(Ok(self.receipt(target, &messages[sent], start_time.elapsed())));
+                }
+                buffer.clear();
+            }
+            buffer.extend_from_slice(&payload);
+            buffered.push(index);
+        }
+        if write_error.is_none() && !buffer.is_empty() {
+            let flushed = match stream.write_all(&buffer).await {
+                Ok(()) => stream.flush().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = flushed {
+                write_error = Some(e);
+            } else {
+                for sent in buffered.drain(..) {
+                    results[sent] = Some(Ok(self.receipt(target, &messages[sent], start_time.elapsed())));
+                }
+            }
+        }
+        let _ = stream.shutdown().await;
+
+        match &write_error {
+            Some(e) => {
+                warn!("Batch to {} failed after a partial write: {}", address, e);
+                self.circuit_breaker.record_outcome(RequestOutcome::Failure(e.to_string())).await;
+            }
+            None => {
+                info!("Sent {} messages via TCP to {} in {:?}", messages.len(), address, start_time.elapsed());
+                self.circuit_breaker.record_outcome(RequestOutcome::Success).await;
+            }
+        }
+        let unsent = write_error.map(|e| format!("Failed to send TCP message: {}", e)).unwrap_or_default();
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(crate::error::SynapseError::TransportError(unsent.clone()))))
+            .collect()
+    }
     
     async fn receive_messages(&self) -> Result<Vec<super::abstraction::IncomingMessage>> {
         let mut messages = self.received_messages.lock().await;
-        let result: Vec<_> = messages.drain(..).map(|msg| {
+        let result: Vec<_> = messages.drain(..).map(|(msg, source)| {
             super::abstraction::IncomingMessage::new(
                 msg,
                 self.transport_type(),
-                String::new() // Source address set later
+                source.to_string(),
             )
         }).collect();
         Ok(result)
@@ -320,6 +602,27 @@ impl Transport for TcpTransport {
         })
     }
 
+    async fn prepare(&self, target: &super::abstraction::TransportTarget) -> Result<()> {
+        let addr = target.address.as_deref().unwrap_or(&target.identifier);
+        if let Some((host, port_str)) = addr.rsplit_once(':') {
+            if let Ok(port) = port_str.parse::<u16>() {
+                return self.prewarm(host, port).await.map(|_| ());
+            }
+        }
+
+        // Same port probe order as send_message_internal, so the warm stream gets used
+        for &port in &self.peer_ports {
+            if self.prewarm(addr, port).await.is_ok() {
+                return Ok(());
+            }
+        }
+        Err(crate::error::SynapseError::TransportError(format!("Could not pre-connect to {}", addr)))
+    }
+
+    fn local_endpoint(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
     async fn start(&self) -> Result<()> {
         Ok(()) // Already started in new()
     }
@@ -341,3 +644,54 @@ impl Transport for TcpTransport {
         metrics.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::abstraction::TransportTarget;
+    use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
+    use crate::types::SecurityLevel;
+
+    fn message(content: &str) -> SecureMessage {
+        SecureMessage {
+            message_id: UuidWrapper::new(uuid::Uuid::new_v4()),
+            to_global_id: "peer".to_string(),
+            from_global_id: "me".to_string(),
+            encrypted_content: content.as_bytes().to_vec(),
+            signature: Vec::new(),
+            timestamp: DateTimeWrapper::new(chrono::Utc::now()),
+            security_level: SecurityLevel::Public,
+            routing_path: Vec::new(),
+            metadata: HashMap::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_is_pipelined_over_one_connection_and_split_on_receipt() {
+        let mut server = TcpTransport::bind(&ListenerBinding::any(0)).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let received = Arc::clone(&server.received_messages);
+        tokio::spawn(async move { server.start_server().await });
+
+        let client = TcpTransport::bind(&ListenerBinding::any(0)).await.unwrap();
+        let target = TransportTarget::new("peer".to_string()).with_address(format!("127.0.0.1:{}", port));
+        // Enough payload to span several coalesced writes
+        let messages: Vec<SecureMessage> = (0..200).map(|i| message(&format!("{}:{}", i, "x".repeat(1024)))).collect();
+
+        let results = client.send_batch(&target, &messages).await;
+        assert_eq!(results.len(), messages.len());
+        for (result, message) in results.iter().zip(&messages) {
+            assert_eq!(result.as_ref().unwrap().message_id, message.message_id.0.to_string());
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while received.lock().await.len() < messages.len() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let received = received.lock().await;
+        let ids: Vec<_> = received.iter().map(|(m, _)| m.message_id.0).collect();
+        let sent: Vec<_> = messages.iter().map(|m| m.message_id.0).collect();
+        assert_eq!(ids, sent);
+    }
+}
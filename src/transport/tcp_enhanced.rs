@@ -1,19 +1,91 @@
 //! Enhanced TCP transport implementation with circuit breaker integration
 
 use super::{Transport, TransportMetrics};
+use super::proxy::ProxyDialer;
 use crate::{
     types::SecureMessage, 
     error::Result,
     circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, RequestOutcome},
 };
 use async_trait::async_trait;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::Mutex;
+use tokio::task::JoinSet;
 use std::sync::{Arc, RwLock};
 use tracing::{info, debug, warn, error};
 
+/// Delay before starting the next connection attempt (RFC 8305 section 5)
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// IP address family of a connection attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    fn of(addr: &SocketAddr) -> Self {
+        if addr.is_ipv6() { AddressFamily::Ipv6 } else { AddressFamily::Ipv4 }
+    }
+}
+
+/// Per-peer record of which address family wins connection races
+#[derive(Debug, Clone, Default)]
+pub struct FamilyStats {
+    pub ipv6_wins: u32,
+    pub ipv4_wins: u32,
+    /// Family of the most recent successful connection
+    pub last_winner: Option<AddressFamily>,
+    /// Time the most recent successful connection took
+    pub last_connect_time: Option<Duration>,
+}
+
+impl FamilyStats {
+    /// Family to try first: IPv6 unless IPv4 has been winning
+    pub fn preferred_family(&self) -> AddressFamily {
+        if self.ipv4_wins > self.ipv6_wins && self.last_winner == Some(AddressFamily::Ipv4) {
+            AddressFamily::Ipv4
+        } else {
+            AddressFamily::Ipv6
+        }
+    }
+
+    fn record_win(&mut self, family: AddressFamily, elapsed: Duration) {
+        match family {
+            AddressFamily::Ipv6 => self.ipv6_wins += 1,
+            AddressFamily::Ipv4 => self.ipv4_wins += 1,
+        }
+        self.last_winner = Some(family);
+        self.last_connect_time = Some(elapsed);
+    }
+}
+
+/// Order addresses for racing: alternate families, preferred family first (RFC 8305 section 4)
+pub fn interleave_families(addrs: &[SocketAddr], preferred: AddressFamily) -> Vec<SocketAddr> {
+    let (mut first, mut second): (Vec<_>, Vec<_>) = addrs
+        .iter()
+        .copied()
+        .partition(|addr| AddressFamily::of(addr) == preferred);
+    first.dedup();
+    second.dedup();
+
+    let mut ordered = Vec::with_capacity(addrs.len());
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+    ordered
+}
+
 /// Enhanced TCP transport with circuit breaker for direct peer-to-peer communication
 pub struct EnhancedTcpTransport {
     listen_port: u16,
@@ -24,6 +96,10 @@ pub struct EnhancedTcpTransport {
     circuit_breaker: Arc<CircuitBreaker>,
     /// Performance metrics
     metrics: Arc<RwLock<TransportMetrics>>,
+    /// Address family race results per host
+    family_stats: Arc<RwLock<HashMap<String, FamilyStats>>>,
+    /// Outbound proxy dialer (direct when no proxy is configured)
+    proxy: Arc<ProxyDialer>,
 }
 
 impl EnhancedTcpTransport {
@@ -43,9 +119,27 @@ impl EnhancedTcpTransport {
             received_messages: Arc::new(Mutex::new(Vec::new())),
             circuit_breaker: Arc::new(CircuitBreaker::new(CircuitBreakerConfig::default())),
             metrics: Arc::new(RwLock::new(TransportMetrics::default())),
+            family_stats: Arc::new(RwLock::new(HashMap::new())),
+            proxy: Arc::new(ProxyDialer::direct()),
         })
     }
     
+    /// Route outbound connections through the given proxy dialer
+    pub fn with_proxy(mut self, proxy: Arc<ProxyDialer>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+    
+    /// Get the proxy dialer (for proxy health metrics)
+    pub fn proxy(&self) -> Arc<ProxyDialer> {
+        Arc::clone(&self.proxy)
+    }
+    
+    /// Address family race results for a host
+    pub fn family_stats(&self, host: &str) -> Option<FamilyStats> {
+        self.family_stats.read().ok()?.get(host).cloned()
+    }
+    
     /// Get circuit breaker reference for monitoring
     pub fn get_circuit_breaker(&self) -> Arc<CircuitBreaker> {
         Arc::clone(&self.circuit_breaker)
@@ -112,14 +206,14 @@ impl EnhancedTcpTransport {
         let addr = format!("{}:{}", host, port);
         debug!("Attempting TCP connection to {}", addr);
         
-        match tokio::time::timeout(self.connection_timeout, TcpStream::connect(&addr)).await {
+        match tokio::time::timeout(self.connection_timeout, self.race_connect(host, port)).await {
             Ok(Ok(stream)) => {
                 debug!("Successfully connected to {}", addr);
                 Ok(stream)
             }
             Ok(Err(e)) => {
                 debug!("Failed to connect to {}: {}", addr, e);
-                Err(crate::error::SynapseError::TransportError(format!("TCP connection failed: {}", e)))
+                Err(e)
             }
             Err(_) => {
                 debug!("Timeout connecting to {}", addr);
@@ -128,6 +222,69 @@ impl EnhancedTcpTransport {
         }
     }
     
+    /// Race connection attempts across all resolved addresses, alternating IPv6 and IPv4
+    ///
+    /// Hosts routed through a proxy are dialed via the proxy instead.
+    async fn race_connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        // Proxied hosts are resolved by the proxy, so there is nothing to race
+        if self.proxy.route("tcp", host).is_some() {
+            return self.proxy.connect("tcp", host, port).await;
+        }
+        
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(|c| c == '[' || c == ']'), port))
+            .await
+            .map_err(|e| crate::error::SynapseError::TransportError(format!("Failed to resolve {}: {}", host, e)))?
+            .collect();
+        
+        let preferred = self.family_stats(host).unwrap_or_default().preferred_family();
+        let mut pending = interleave_families(&addrs, preferred).into_iter();
+        let mut attempts = JoinSet::new();
+        let mut last_error = None;
+        let start = Instant::now();
+        
+        if let Some(addr) = pending.next() {
+            attempts.spawn(async move { (addr, TcpStream::connect(addr).await) });
+        }
+        
+        while !attempts.is_empty() {
+            tokio::select! {
+                joined = attempts.join_next() => {
+                    match joined {
+                        Some(Ok((addr, Ok(stream)))) => {
+                            // Dropping the JoinSet aborts the losing attempts
+                            let family = AddressFamily::of(&addr);
+                            debug!("{:?} won connection race to {} via {}", family, host, addr);
+                            if let Ok(mut stats) = self.family_stats.write() {
+                                stats.entry(host.to_string()).or_default().record_win(family, start.elapsed());
+                            }
+                            return Ok(stream);
+                        }
+                        Some(Ok((addr, Err(e)))) => {
+                            debug!("Connection attempt to {} failed: {}", addr, e);
+                            last_error = Some(e.to_string());
+                        }
+                        Some(Err(e)) => last_error = Some(e.to_string()),
+                        None => break,
+                    }
+                    // A failure starts the next attempt immediately
+                    if let Some(addr) = pending.next() {
+                        attempts.spawn(async move { (addr, TcpStream::connect(addr).await) });
+                    }
+                }
+                _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if !pending.as_slice().is_empty() => {
+                    if let Some(addr) = pending.next() {
+                        attempts.spawn(async move { (addr, TcpStream::connect(addr).await) });
+                    }
+                }
+            }
+        }
+        
+        Err(crate::error::SynapseError::TransportError(format!(
+            "TCP connection failed: {}",
+            last_error.unwrap_or_else(|| format!("no addresses for {}", host))
+        )))
+    }
+    
     async fn send_via_stream(&self, stream: &mut TcpStream, message: &SecureMessage) -> Result<()> {
         let message_json = serde_json::to_string(message)
             .map_err(|e| crate::error::SynapseError::TransportError(format!("Failed to serialize message: {}", e)))?;
@@ -292,8 +449,8 @@ impl Transport for EnhancedTcpTransport {
             if let Ok(port) = port_str.parse::<u16>() {
                 tokio::time::timeout(
                     Duration::from_secs(5),
-                    TcpStream::connect((host, port))
-                ).await.is_ok()
+                    self.race_connect(host, port)
+                ).await.is_ok_and(|r| r.is_ok())
             } else {
                 false
             }
@@ -334,3 +491,29 @@ impl Transport for EnhancedTcpTransport {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interleave_families() {
+        let addrs: Vec<SocketAddr> = ["10.0.0.1:80", "10.0.0.2:80", "[2001:db8::1]:80", "[2001:db8::2]:80"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+
+        let ordered = interleave_families(&addrs, AddressFamily::Ipv6);
+        let families: Vec<_> = ordered.iter().map(AddressFamily::of).collect();
+        assert_eq!(
+            families,
+            vec![AddressFamily::Ipv6, AddressFamily::Ipv4, AddressFamily::Ipv6, AddressFamily::Ipv4]
+        );
+
+        let mut stats = FamilyStats::default();
+        assert_eq!(stats.preferred_family(), AddressFamily::Ipv6);
+        stats.record_win(AddressFamily::Ipv4, Duration::from_millis(20));
+        assert_eq!(stats.preferred_family(), AddressFamily::Ipv4);
+        assert_eq!(interleave_families(&addrs, stats.preferred_family())[0], addrs[0]);
+    }
+}
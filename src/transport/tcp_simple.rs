@@ -104,6 +104,7 @@ impl Transport for SimpleTcpTransport {
             cost: 1.0,
             available: true,
             confidence: 0.8,
+            region: None,
         })
     }
 
@@ -308,6 +308,7 @@ impl Transport for TcpTransportImpl {
             cost: 1.0, // Relative cost
             available: can_connect,
             confidence: if can_connect { 0.8 } else { 0.3 },
+            region: None,
         })
     }
 
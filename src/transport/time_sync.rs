@@ -0,0 +1,302 @@
+//! Peer clock sampling and skew-tolerant timestamp checks
+//!
+//! Replay windows, early-data checks and block headers compare a sender's
+//! timestamp with the local clock, which only works while both are in sync.
+//! The router samples a peer's clock NTP-style: it sends a [`TimeProbe`]
+//! request stamped with its own time, the peer answers with when it received
+//! the request and when it replied, and the round trip gives the peer's
+//! offset from our clock. Samples are kept per peer in [`PeerClocks`];
+//! timestamp checks subtract a sender's known offset before applying their
+//! [`SkewWindow`], and the median across peers estimates how far our own
+//! clock is off.
+
+use crate::types::{MessageType, SimpleMessage};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::sync::oneshot;
+
+/// Metadata key marking a message as a time probe (the probe kind)
+pub const TIME_SYNC_KEY: &str = "time_sync";
+
+/// A clock sample request and its answer; times are Unix milliseconds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimeProbe {
+    /// Asks for the peer's clock
+    Request { nonce: u64, sent_at_ms: i64 },
+    /// Answer to the request with the same nonce
+    Response {
+        nonce: u64,
+        /// The requester's `sent_at_ms`, echoed back
+        request_sent_at_ms: i64,
+        /// When the request arrived, by the answering peer's clock
+        received_at_ms: i64,
+        /// When the answer was sent, by the answering peer's clock
+        sent_at_ms: i64,
+    },
+}
+
+impl TimeProbe {
+    /// Name used in the message metadata
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TimeProbe::Request { .. } => "request",
+            TimeProbe::Response { .. } => "response",
+        }
+    }
+
+    /// The response to a request that arrived at `received_at_ms`
+    pub fn answer(&self, received_at_ms: i64) -> Option<TimeProbe> {
+        match *self {
+            TimeProbe::Request { nonce, sent_at_ms } => Some(TimeProbe::Response {
+                nonce,
+                request_sent_at_ms: sent_at_ms,
+                received_at_ms,
+                sent_at_ms: Utc::now().timestamp_millis(),
+            }),
+            TimeProbe::Response { .. } => None,
+        }
+    }
+
+    /// Wrap the probe in a system message from `from_entity` to `to_entity`
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> crate::error::Result<SimpleMessage> {
+        let mut metadata = HashMap::new();
+        metadata.insert(TIME_SYNC_KEY.to_string(), self.kind().to_string());
+        Ok(SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: serde_json::to_string(self)?,
+            message_type: MessageType::System,
+            metadata,
+        })
+    }
+
+    /// Extract the probe carried by `message`, if any
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        if message.message_type != MessageType::System || !message.metadata.contains_key(TIME_SYNC_KEY) {
+            return None;
+        }
+        serde_json::from_str(&message.content).ok()
+    }
+}
+
+/// One measurement of a peer's clock
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSample {
+    pub peer: String,
+    /// How far the peer's clock is ahead of ours, in milliseconds
+    pub offset_ms: i64,
+    /// Network round trip, excluding the peer's processing time
+    pub round_trip_ms: u64,
+    pub taken_at: DateTime<Utc>,
+}
+
+impl TimeSample {
+    /// Compute a sample from the four timestamps of an exchange: request sent
+    /// (ours), request received and answer sent (the peer's), answer received (ours)
+    pub fn from_exchange(peer: &str, sent: i64, peer_received: i64, peer_sent: i64, received: i64) -> Self {
+        let offset_ms = ((peer_received - sent) + (peer_sent - received)) / 2;
+        let round_trip_ms = ((received - sent) - (peer_sent - peer_received)).max(0) as u64;
+        Self {
+            peer: peer.to_string(),
+            offset_ms,
+            round_trip_ms,
+            taken_at: Utc::now(),
+        }
+    }
+}
+
+/// Estimate of how far the local clock is off, from samples across peers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockEstimate {
+    /// Median peer offset: positive when peers are ahead, i.e. our clock is slow
+    pub offset_ms: i64,
+    /// Number of peers sampled
+    pub peers: usize,
+}
+
+/// Clock samples per peer, and requests awaiting an answer
+pub struct PeerClocks {
+    samples: DashMap<String, VecDeque<TimeSample>>,
+    /// Nonce -> peer the request went to and who is waiting for the answer
+    pending: DashMap<u64, (String, oneshot::Sender<TimeSample>)>,
+    next_nonce: AtomicU64,
+    max_samples: usize,
+}
+
+impl PeerClocks {
+    /// Keep the latest `max_samples` samples per peer
+    pub fn new(max_samples: usize) -> Self {
+        Self {
+            samples: DashMap::new(),
+            pending: DashMap::new(),
+            next_nonce: AtomicU64::new(rand::random()),
+            max_samples: max_samples.max(1),
+        }
+    }
+
+    /// Register a request to `peer`, returning its nonce and the eventual sample
+    pub fn start_sample(&self, peer: &str) -> (u64, oneshot::Receiver<TimeSample>) {
+        let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.insert(nonce, (peer.to_string(), sender));
+        (nonce, receiver)
+    }
+
+    /// Drop a request that will not be waited for any more
+    pub fn cancel(&self, nonce: u64) {
+        self.pending.remove(&nonce);
+    }
+
+    /// Apply `from`'s response, received at `received_at_ms`
+    ///
+    /// Returns `None`, recording nothing, if the probe is not a response to a
+    /// request we sent to `from`.
+    pub fn complete(&self, from: &str, response: &TimeProbe, received_at_ms: i64) -> Option<TimeSample> {
+        let TimeProbe::Response { nonce, request_sent_at_ms, received_at_ms: peer_received, sent_at_ms } = *response else {
+            return None;
+        };
+        let (_, (_, waiter)) = self.pending.remove_if(&nonce, |_, (peer, _)| peer == from)?;
+        let sample = TimeSample::from_exchange(from, request_sent_at_ms, peer_received, sent_at_ms, received_at_ms);
+        self.record(sample.clone());
+        // The sampler may have timed out in the meantime
+        let _ = waiter.send(sample.clone());
+        Some(sample)
+    }
+
+    /// Keep a sample, dropping the peer's oldest beyond the limit
+    pub fn record(&self, sample: TimeSample) {
+        let mut samples = self.samples.entry(sample.peer.clone()).or_default();
+        samples.push_back(sample);
+        while samples.len() > self.max_samples {
+            samples.pop_front();
+        }
+    }
+
+    /// Samples kept for a peer, oldest first
+    pub fn samples(&self, peer: &str) -> Vec<TimeSample> {
+        self.samples.get(peer).map(|s| s.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// A peer's clock offset, taken from its sample with the shortest round
+    /// trip since that one is least distorted by asymmetric delays
+    pub fn offset(&self, peer: &str) -> Option<i64> {
+        best_offset(&self.samples.get(peer)?)
+    }
+
+    /// Median offset across sampled peers
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        let mut offsets: Vec<i64> = self.samples.iter().filter_map(|entry| best_offset(entry.value())).collect();
+        if offsets.is_empty() {
+            return None;
+        }
+        offsets.sort_unstable();
+        Some(ClockEstimate {
+            offset_ms: offsets[offsets.len() / 2],
+            peers: offsets.len(),
+        })
+    }
+
+    /// Drop everything known about a peer's clock
+    pub fn forget(&self, peer: &str) {
+        self.samples.remove(peer);
+    }
+}
+
+fn best_offset(samples: &VecDeque<TimeSample>) -> Option<i64> {
+    samples.iter().min_by_key(|s| s.round_trip_ms).map(|s| s.offset_ms)
+}
+
+/// Outcome of checking a timestamp against a [`SkewWindow`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampCheck {
+    Valid,
+    /// Older than the window allows
+    Stale,
+    /// Further ahead than the skew allowance
+    FromFuture,
+}
+
+/// How old a timestamp may be and how far it may run ahead of our clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkewWindow {
+    pub max_age: Duration,
+    pub max_skew: Duration,
+}
+
+impl SkewWindow {
+    pub fn new(max_age: Duration, max_skew: Duration) -> Self {
+        Self { max_age, max_skew }
+    }
+
+    /// Check a timestamp from a peer whose clock runs `peer_offset_ms` ahead
+    /// of ours; the offset is removed before comparing with `now`
+    pub fn check(&self, timestamp: DateTime<Utc>, now: DateTime<Utc>, peer_offset_ms: i64) -> TimestampCheck {
+        let corrected = timestamp - chrono::Duration::milliseconds(peer_offset_ms);
+        let max_age = chrono::Duration::from_std(self.max_age).unwrap_or(chrono::Duration::MAX);
+        let max_skew = chrono::Duration::from_std(self.max_skew).unwrap_or(chrono::Duration::MAX);
+        if corrected < now - max_age {
+            TimestampCheck::Stale
+        } else if corrected > now + max_skew {
+            TimestampCheck::FromFuture
+        } else {
+            TimestampCheck::Valid
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exchange_measures_offset_without_network_delay() {
+        // Peer is 5s ahead; 40ms each way; 10ms processing
+        let sample = TimeSample::from_exchange("bob", 1_000, 6_040, 6_050, 1_090);
+        assert_eq!(sample.offset_ms, 5_000);
+        assert_eq!(sample.round_trip_ms, 80);
+    }
+
+    #[test]
+    fn samples_complete_requests_and_estimate_skew() {
+        let clocks = PeerClocks::new(2);
+        let (nonce, mut answer) = clocks.start_sample("bob");
+        let sent_at_ms = Utc::now().timestamp_millis();
+        let request = TimeProbe::Request { nonce, sent_at_ms };
+
+        let response = request.answer(sent_at_ms + 3_000).unwrap();
+        assert!(response.answer(0).is_none());
+        assert!(clocks.complete("mallory", &response, sent_at_ms).is_none());
+        let sample = clocks.complete("bob", &response, sent_at_ms).unwrap();
+        assert!(sample.offset_ms >= 1_500);
+        assert_eq!(answer.try_recv().unwrap(), sample);
+        assert!(clocks.complete("bob", &response, sent_at_ms).is_none());
+
+        clocks.record(TimeSample::from_exchange("bob", 0, 100, 100, 50));
+        clocks.record(TimeSample::from_exchange("bob", 0, 100, 100, 400));
+        assert_eq!(clocks.samples("bob").len(), 2);
+        assert_eq!(clocks.offset("bob"), Some(75));
+
+        clocks.record(TimeSample::from_exchange("carol", 0, 500, 500, 0));
+        clocks.record(TimeSample::from_exchange("dave", 0, 900, 900, 0));
+        assert_eq!(clocks.estimate(), Some(ClockEstimate { offset_ms: 500, peers: 3 }));
+    }
+
+    #[test]
+    fn window_tolerates_known_peer_offsets() {
+        let window = SkewWindow::new(Duration::from_secs(300), Duration::from_secs(30));
+        let now = Utc::now();
+        let ahead = now + chrono::Duration::seconds(90);
+        assert_eq!(window.check(ahead, now, 0), TimestampCheck::FromFuture);
+        assert_eq!(window.check(ahead, now, 90_000), TimestampCheck::Valid);
+        assert_eq!(window.check(now - chrono::Duration::minutes(6), now, 0), TimestampCheck::Stale);
+        assert_eq!(window.check(now - chrono::Duration::minutes(6), now, -120_000), TimestampCheck::Valid);
+    }
+}
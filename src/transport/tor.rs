@@ -0,0 +1,526 @@
+//! Tor onion-service transport
+//!
+//! Publishes this node as a Tor onion service (via arti) and connects to the
+//! onion addresses peers advertise, so neither side learns the other's IP
+//! address and federation keeps working where direct connections are blocked.
+//!
+//! Peers exchange onion addresses through identity capabilities: a node with
+//! a running service adds the `tor` capability and an `onion_address` routing
+//! preference to its [`GlobalIdentity`]. Messages are sent as length-prefixed
+//! JSON over a Tor stream. Combine with
+//! [`TransportSelectionPolicy::PrivacyFirst`](super::manager::TransportSelectionPolicy::PrivacyFirst)
+//! to prefer this transport when it can reach a peer.
+
+use super::abstraction::*;
+use super::framing::{read_frame, write_frame};
+use crate::{
+    error::{Result, SynapseError},
+    types::{GlobalIdentity, SecureMessage},
+};
+use arti_client::{
+    config::{onion_service::OnionServiceConfigBuilder, BoolOrAuto, TorClientConfigBuilder},
+    StreamPrefs, TorClient,
+};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tor_cell::relaycell::msg::{Connected, End, EndReason};
+use tor_hsservice::{handle_rend_requests, RunningOnionService};
+use tracing::{debug, info, warn};
+
+/// Capability advertised by nodes reachable over Tor
+pub const TOR_CAPABILITY: &str = "tor";
+
+/// Routing preference key carrying a node's onion address
+pub const ONION_ADDRESS_KEY: &str = "onion_address";
+
+/// Default virtual port of the onion service
+pub const DEFAULT_ONION_PORT: u16 = 8080;
+
+/// Length of a v3 onion address label (base32 of key, checksum and version)
+const ONION_V3_LABEL_LEN: usize = 56;
+
+type ArtiRuntime = tor_rtcompat::PreferredRuntime;
+
+/// A parsed v3 onion address with port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnionAddress {
+    /// Host including the `.onion` suffix
+    pub host: String,
+    pub port: u16,
+}
+
+impl OnionAddress {
+    /// Parse `<56 base32 chars>.onion[:port]`
+    pub fn parse(address: &str) -> Result<Self> {
+        let address = address.trim().trim_start_matches("tor://");
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| SynapseError::TransportError(format!("Invalid onion port in {}", address)))?,
+            ),
+            None => (address, DEFAULT_ONION_PORT),
+        };
+
+        let host = host.to_lowercase();
+        let label = host.strip_suffix(".onion").unwrap_or_default();
+        // Subdomains of an onion service are allowed; the key is the last label
+        let key = label.rsplit('.').next().unwrap_or_default();
+        let valid = key.len() == ONION_V3_LABEL_LEN
+            && key.chars().all(|c| matches!(c, 'a'..='z' | '2'..='7'));
+        if !valid {
+            return Err(SynapseError::TransportError(format!("Not a v3 onion address: {}", address)));
+        }
+
+        Ok(Self { host, port })
+    }
+}
+
+impl std::fmt::Display for OnionAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// Onion address a peer advertised in its identity, if any
+pub fn advertised_onion_address(identity: &GlobalIdentity) -> Option<OnionAddress> {
+    if !identity.has_capability(TOR_CAPABILITY) {
+        return None;
+    }
+    identity
+        .routing_preferences
+        .get(ONION_ADDRESS_KEY)
+        .and_then(|address| OnionAddress::parse(address).ok())
+}
+
+/// Tor transport configuration
+#[derive(Debug, Clone)]
+pub struct TorTransportConfig {
+    /// Directory for Tor state, including the onion service keys
+    pub state_dir: PathBuf,
+    /// Directory for the Tor directory cache
+    pub cache_dir: PathBuf,
+    /// Publish an onion service for incoming messages
+    pub publish_service: bool,
+    /// Nickname of the onion service (keys are stored under it)
+    pub service_nickname: String,
+    /// Virtual port peers connect to
+    pub virtual_port: u16,
+    /// Maximum accepted message size
+    pub max_message_size: usize,
+    /// Timeout for building a circuit and sending one message
+    pub send_timeout: Duration,
+}
+
+impl Default for TorTransportConfig {
+    fn default() -> Self {
+        let base = std::env::temp_dir().join("synapse-tor");
+        Self {
+            state_dir: base.join("state"),
+            cache_dir: base.join("cache"),
+            publish_service: true,
+            service_nickname: "synapse".to_string(),
+            virtual_port: DEFAULT_ONION_PORT,
+            max_message_size: 4 * 1024 * 1024,
+            send_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl TorTransportConfig {
+    fn from_map(config: &HashMap<String, String>) -> Self {
+        let mut tor_config = Self::default();
+        if let Some(dir) = config.get("state_dir") {
+            tor_config.state_dir = PathBuf::from(dir);
+        }
+        if let Some(dir) = config.get("cache_dir") {
+            tor_config.cache_dir = PathBuf::from(dir);
+        }
+        if let Some(publish) = config.get("publish_service") {
+            tor_config.publish_service = publish == "true";
+        }
+        if let Some(nickname) = config.get("service_nickname") {
+            tor_config.service_nickname = nickname.clone();
+        }
+        if let Some(port) = config.get("virtual_port").and_then(|p| p.parse().ok()) {
+            tor_config.virtual_port = port;
+        }
+        if let Some(size) = config.get("max_message_size").and_then(|s| s.parse().ok()) {
+            tor_config.max_message_size = size;
+        }
+        if let Some(ms) = config.get("send_timeout_ms").and_then(|s| s.parse().ok()) {
+            tor_config.send_timeout = Duration::from_millis(ms);
+        }
+        tor_config
+    }
+}
+
+/// Tor transport implementation
+pub struct TorTransportImpl {
+    client: TorClient<ArtiRuntime>,
+    config: TorTransportConfig,
+    /// Running onion service, kept alive for as long as the transport
+    service: Mutex<Option<Arc<RunningOnionService>>>,
+    /// Our onion address once the service is published
+    onion_address: Arc<RwLock<Option<OnionAddress>>>,
+    received_messages: Arc<Mutex<Vec<IncomingMessage>>>,
+    status: Arc<RwLock<TransportStatus>>,
+    metrics: Arc<RwLock<TransportMetrics>>,
+}
+
+impl TorTransportImpl {
+    /// Bootstrap a Tor client
+    pub async fn new(config: &HashMap<String, String>) -> Result<Self> {
+        let config = TorTransportConfig::from_map(config);
+
+        let tor_config = TorClientConfigBuilder::from_directories(&config.state_dir, &config.cache_dir)
+            .build()
+            .map_err(|e| SynapseError::TransportError(format!("Invalid Tor configuration: {}", e)))?;
+
+        info!("🧅 Bootstrapping Tor client");
+        let client = TorClient::create_bootstrapped(tor_config)
+            .await
+            .map_err(|e| SynapseError::TransportError(format!("Tor bootstrap failed: {}", e)))?;
+
+        let mut metrics = TransportMetrics::default();
+        metrics.transport_type = TransportType::Tor;
+
+        Ok(Self {
+            client,
+            config,
+            service: Mutex::new(None),
+            onion_address: Arc::new(RwLock::new(None)),
+            received_messages: Arc::new(Mutex::new(Vec::new())),
+            status: Arc::new(RwLock::new(TransportStatus::Stopped)),
+            metrics: Arc::new(RwLock::new(metrics)),
+        })
+    }
+
+    /// Our onion address, once the service is published
+    pub fn onion_address(&self) -> Option<OnionAddress> {
+        self.onion_address.read().unwrap().clone()
+    }
+
+    /// Advertise our onion address in an identity for capability exchange
+    pub fn advertise(&self, identity: &mut GlobalIdentity) -> bool {
+        let Some(address) = self.onion_address() else {
+            return false;
+        };
+        if !identity.has_capability(TOR_CAPABILITY) {
+            identity.add_capability(TOR_CAPABILITY);
+        }
+        identity.routing_preferences.insert(ONION_ADDRESS_KEY.to_string(), address.to_string());
+        true
+    }
+
+    /// Launch the onion service and start accepting streams
+    async fn publish_service(&self) -> Result<()> {
+        let nickname = self
+            .config
+            .service_nickname
+            .parse()
+            .map_err(|e| SynapseError::TransportError(format!("Invalid onion service nickname: {}", e)))?;
+        let service_config = OnionServiceConfigBuilder::default()
+            .nickname(nickname)
+            .build()
+            .map_err(|e| SynapseError::TransportError(format!("Invalid onion service configuration: {}", e)))?;
+
+        let (service, rend_requests) = self
+            .client
+            .launch_onion_service(service_config)
+            .map_err(|e| SynapseError::TransportError(format!("Failed to launch onion service: {}", e)))?;
+
+        if let Some(hs_id) = service.onion_address() {
+            let address = OnionAddress {
+                host: hs_id.to_string(),
+                port: self.config.virtual_port,
+            };
+            info!("🧅 Onion service published at {}", address);
+            *self.onion_address.write().unwrap() = Some(address);
+        }
+        *self.service.lock().await = Some(service);
+
+        let virtual_port = self.config.virtual_port;
+        let max_message_size = self.config.max_message_size;
+        let queue = Arc::clone(&self.received_messages);
+        let metrics = Arc::clone(&self.metrics);
+        tokio::spawn(async move {
+            let mut streams = handle_rend_requests(rend_requests);
+            while let Some(request) = streams.next().await {
+                let port = match request.request() {
+                    tor_proto::stream::IncomingStreamRequest::Begin(begin) => begin.port(),
+                    _ => 0,
+                };
+                if port != virtual_port {
+                    let _ = request.reject(End::new_with_reason(EndReason::DONE)).await;
+                    continue;
+                }
+
+                let queue = Arc::clone(&queue);
+                let metrics = Arc::clone(&metrics);
+                tokio::spawn(async move {
+                    let mut stream = match request.accept(Connected::new_empty()).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            debug!("Failed to accept onion stream: {}", e);
+                            return;
+                        }
+                    };
+                    match read_frame(&mut stream, max_message_size).await {
+                        Ok(message) => {
+                            debug!("Received message {} over Tor", message.message_id);
+                            let incoming = IncomingMessage::new(message, TransportType::Tor, "onion".to_string());
+                            queue.lock().await.push(incoming);
+                            metrics.write().unwrap().messages_received += 1;
+                        }
+                        Err(e) => {
+                            warn!("Invalid message on onion stream: {}", e);
+                            metrics.write().unwrap().receive_failures += 1;
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    fn parse_target_address(&self, target: &TransportTarget) -> Result<OnionAddress> {
+        target
+            .address
+            .as_deref()
+            .map(OnionAddress::parse)
+            .unwrap_or_else(|| OnionAddress::parse(&target.identifier))
+    }
+
+    async fn send_to(&self, address: &OnionAddress, message: &SecureMessage) -> Result<Duration> {
+        let start = Instant::now();
+        let mut prefs = StreamPrefs::new();
+        prefs.connect_to_onion_services(BoolOrAuto::Explicit(true));
+
+        let mut stream = self
+            .client
+            .connect_with_prefs((address.host.as_str(), address.port), &prefs)
+            .await
+            .map_err(|e| SynapseError::TransportError(format!("Tor connection to {} failed: {}", address, e)))?;
+        write_frame(&mut stream, message).await?;
+        Ok(start.elapsed())
+    }
+}
+
+#[async_trait]
+impl Transport for TorTransportImpl {
+    fn transport_type(&self) -> TransportType {
+        TransportType::Tor
+    }
+
+    fn capabilities(&self) -> TransportCapabilities {
+        let mut capabilities = TransportCapabilities::tor();
+        capabilities.max_message_size = self.config.max_message_size;
+        capabilities
+    }
+
+    async fn can_reach(&self, target: &TransportTarget) -> bool {
+        // Building a circuit just to check is expensive; an onion address is enough
+        self.parse_target_address(target).is_ok()
+    }
+
+    async fn estimate_metrics(&self, target: &TransportTarget) -> Result<TransportEstimate> {
+        let _address = self.parse_target_address(target)?;
+
+        // Onion circuits span six relays
+        Ok(TransportEstimate {
+            latency: Duration::from_millis(1500),
+            reliability: 0.9,
+            bandwidth: 500_000,
+            cost: 2.0,
+            available: true,
+            confidence: 0.5,
+            region: None,
+        })
+    }
+
+    async fn send_message(&self, target: &TransportTarget, message: &SecureMessage) -> Result<DeliveryReceipt> {
+        let address = self.parse_target_address(target)?;
+
+        let result = tokio::time::timeout(self.config.send_timeout, self.send_to(&address, message))
+            .await
+            .unwrap_or_else(|_| Err(SynapseError::TransportError(format!("Tor send to {} timed out", address))));
+
+        let mut metrics = self.metrics.write().unwrap();
+        match result {
+            Ok(delivery_time) => {
+                metrics.messages_sent += 1;
+                metrics.average_latency_ms = delivery_time.as_millis() as u64;
+                Ok(DeliveryReceipt {
+                    message_id: message.message_id.to_string(),
+                    transport_used: TransportType::Tor,
+                    delivery_time,
+                    target_reached: address.to_string(),
+                    confirmation: DeliveryConfirmation::Delivered,
+                    metadata: HashMap::new(),
+                })
+            }
+            Err(e) => {
+                metrics.send_failures += 1;
+                Err(e)
+            }
+        }
+    }
+
+    async fn receive_messages(&self) -> Result<Vec<IncomingMessage>> {
+        let mut messages = self.received_messages.lock().await;
+        Ok(messages.drain(..).collect())
+    }
+
+    async fn test_connectivity(&self, target: &TransportTarget) -> Result<ConnectivityResult> {
+        let address = self.parse_target_address(target)?;
+        let start = Instant::now();
+
+        let mut prefs = StreamPrefs::new();
+        prefs.connect_to_onion_services(BoolOrAuto::Explicit(true));
+        let connect = self.client.connect_with_prefs((address.host.as_str(), address.port), &prefs);
+
+        let mut details = HashMap::new();
+        details.insert("target".to_string(), address.to_string());
+        match tokio::time::timeout(self.config.send_timeout, connect).await {
+            Ok(Ok(_stream)) => {
+                let rtt = start.elapsed();
+                details.insert("rtt_ms".to_string(), rtt.as_millis().to_string());
+                Ok(ConnectivityResult {
+                    connected: true,
+                    rtt: Some(rtt),
+                    error: None,
+                    quality: 0.6,
+                    details,
+                })
+            }
+            Ok(Err(e)) => Ok(ConnectivityResult {
+                connected: false,
+                rtt: None,
+                error: Some(e.to_string()),
+                quality: 0.0,
+                details,
+            }),
+            Err(_) => Ok(ConnectivityResult {
+                connected: false,
+                rtt: None,
+                error: Some("Tor connection timed out".to_string()),
+                quality: 0.0,
+                details,
+            }),
+        }
+    }
+
+    async fn start(&self) -> Result<()> {
+        *self.status.write().unwrap() = TransportStatus::Starting;
+
+        if self.config.publish_service && self.service.lock().await.is_none() {
+            if let Err(e) = self.publish_service().await {
+                *self.status.write().unwrap() = TransportStatus::Failed;
+                return Err(e);
+            }
+        }
+
+        *self.status.write().unwrap() = TransportStatus::Running;
+        info!("Tor transport running");
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        *self.status.write().unwrap() = TransportStatus::Stopping;
+
+        // Dropping the service handle takes the onion service offline
+        self.service.lock().await.take();
+        *self.onion_address.write().unwrap() = None;
+
+        *self.status.write().unwrap() = TransportStatus::Stopped;
+        info!("Tor transport stopped");
+        Ok(())
+    }
+
+    async fn status(&self) -> TransportStatus {
+        *self.status.read().unwrap()
+    }
+
+    async fn metrics(&self) -> TransportMetrics {
+        self.metrics.read().unwrap().clone()
+    }
+}
+
+/// Factory for creating Tor transport instances
+pub struct TorTransportFactory;
+
+#[async_trait]
+impl TransportFactory for TorTransportFactory {
+    async fn create_transport(&self, config: &HashMap<String, String>) -> Result<Box<dyn Transport>> {
+        let transport = TorTransportImpl::new(config).await?;
+        Ok(Box::new(transport))
+    }
+
+    fn transport_type(&self) -> TransportType {
+        TransportType::Tor
+    }
+
+    fn default_config(&self) -> HashMap<String, String> {
+        let defaults = TorTransportConfig::default();
+        let mut config = HashMap::new();
+        config.insert("state_dir".to_string(), defaults.state_dir.display().to_string());
+        config.insert("cache_dir".to_string(), defaults.cache_dir.display().to_string());
+        config.insert("publish_service".to_string(), "true".to_string());
+        config.insert("service_nickname".to_string(), defaults.service_nickname);
+        config.insert("virtual_port".to_string(), defaults.virtual_port.to_string());
+        config
+    }
+
+    fn validate_config(&self, config: &HashMap<String, String>) -> Result<()> {
+        if let Some(port) = config.get("virtual_port") {
+            if port.parse::<u16>().is_err() {
+                return Err(SynapseError::TransportError(format!("Invalid virtual_port: {}", port)));
+            }
+        }
+        if let Some(nickname) = config.get("service_nickname") {
+            if nickname.is_empty() || !nickname.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(SynapseError::TransportError(format!("Invalid service_nickname: {}", nickname)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EntityType;
+
+    const ONION: &str = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion";
+
+    #[test]
+    fn test_parse_onion_address() {
+        let address = OnionAddress::parse(&format!("{}:9000", ONION)).unwrap();
+        assert_eq!(address.host, ONION);
+        assert_eq!(address.port, 9000);
+
+        assert_eq!(OnionAddress::parse(ONION).unwrap().port, DEFAULT_ONION_PORT);
+        assert!(OnionAddress::parse("example.com:80").is_err());
+        assert!(OnionAddress::parse("tooshort.onion").is_err());
+    }
+
+    #[test]
+    fn test_onion_address_from_identity() {
+        let mut identity = GlobalIdentity::new("Bob", "bob@example.com", EntityType::Human, "");
+        identity.routing_preferences.insert(ONION_ADDRESS_KEY.to_string(), ONION.to_string());
+        // The address only counts when the capability is advertised too
+        assert!(advertised_onion_address(&identity).is_none());
+
+        identity.add_capability(TOR_CAPABILITY);
+        assert_eq!(advertised_onion_address(&identity).unwrap().host, ONION);
+    }
+}
@@ -0,0 +1,378 @@
+//! Lightweight ARQ (automatic repeat request) layer for the UDP transport
+//!
+//! Plain UDP silently drops datagrams on lossy links. This module adds
+//! per-peer sequence numbers, selective acknowledgements and an adaptive
+//! retransmission timeout (RFC 6298 style) so a message is either
+//! acknowledged or reported as failed - never lost without notice.
+//!
+//! Each sender picks a random session ID at startup, so a peer that restarts
+//! (and resets its sequence numbers) is not mistaken for duplicates.
+//!
+//! Wire format (big endian):
+//!
+//! ```text
+//! DATA: magic(1) kind=1(1) session(4) seq(4) payload(..)
+//! ACK:  magic(1) kind=2(1) session(4) cumulative(4) selective(8)
+//! ```
+//!
+//! `cumulative` is the next sequence number the receiver expects; bit `i` of
+//! `selective` reports that `cumulative + 1 + i` has also arrived.
+
+use crate::error::{Result, SynapseError};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+
+/// First byte of every ARQ frame; JSON datagrams start with `{` instead
+pub const ARQ_MAGIC: u8 = 0xA7;
+
+const KIND_DATA: u8 = 1;
+const KIND_ACK: u8 = 2;
+const DATA_HEADER_LEN: usize = 10;
+const ACK_LEN: usize = 18;
+const SELECTIVE_BITS: u32 = 64;
+
+/// ARQ tuning parameters
+#[derive(Debug, Clone)]
+pub struct ArqConfig {
+    /// Retransmission timeout before any RTT sample
+    pub initial_rto: Duration,
+    /// Lower bound for the retransmission timeout
+    pub min_rto: Duration,
+    /// Upper bound for the retransmission timeout
+    pub max_rto: Duration,
+    /// Transmissions (including the first) before a message is reported lost
+    pub max_transmissions: u32,
+    /// Maximum unacknowledged messages per peer
+    pub window_size: usize,
+}
+
+impl Default for ArqConfig {
+    fn default() -> Self {
+        Self {
+            initial_rto: Duration::from_millis(200),
+            min_rto: Duration::from_millis(50),
+            max_rto: Duration::from_secs(2),
+            max_transmissions: 6,
+            window_size: 64,
+        }
+    }
+}
+
+/// A decoded ARQ frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArqFrame {
+    Data { session: u32, seq: u32, payload: Vec<u8> },
+    Ack(ArqAck),
+}
+
+/// Selective acknowledgement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArqAck {
+    pub session: u32,
+    /// Next sequence number the receiver expects
+    pub cumulative: u32,
+    /// Bit `i` set when `cumulative + 1 + i` has been received
+    pub selective: u64,
+}
+
+impl ArqAck {
+    /// Whether this acknowledgement covers `seq`
+    pub fn covers(&self, seq: u32) -> bool {
+        if seq < self.cumulative {
+            return true;
+        }
+        let offset = seq - self.cumulative;
+        offset >= 1 && offset <= SELECTIVE_BITS && self.selective & (1 << (offset - 1)) != 0
+    }
+}
+
+impl ArqFrame {
+    /// Whether a datagram is an ARQ frame (as opposed to a legacy JSON message)
+    pub fn is_arq(datagram: &[u8]) -> bool {
+        datagram.first() == Some(&ARQ_MAGIC)
+    }
+
+    /// Encode to wire format
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            ArqFrame::Data { session, seq, payload } => {
+                let mut bytes = Vec::with_capacity(DATA_HEADER_LEN + payload.len());
+                bytes.extend_from_slice(&[ARQ_MAGIC, KIND_DATA]);
+                bytes.extend_from_slice(&session.to_be_bytes());
+                bytes.extend_from_slice(&seq.to_be_bytes());
+                bytes.extend_from_slice(payload);
+                bytes
+            }
+            ArqFrame::Ack(ack) => {
+                let mut bytes = Vec::with_capacity(ACK_LEN);
+                bytes.extend_from_slice(&[ARQ_MAGIC, KIND_ACK]);
+                bytes.extend_from_slice(&ack.session.to_be_bytes());
+                bytes.extend_from_slice(&ack.cumulative.to_be_bytes());
+                bytes.extend_from_slice(&ack.selective.to_be_bytes());
+                bytes
+            }
+        }
+    }
+
+    /// Decode from wire format
+    pub fn decode(datagram: &[u8]) -> Result<Self> {
+        if !Self::is_arq(datagram) || datagram.len() < 2 {
+            return Err(SynapseError::InvalidMessageFormat("Not an ARQ frame".to_string()));
+        }
+        let u32_at = |at: usize| u32::from_be_bytes(datagram[at..at + 4].try_into().unwrap());
+
+        match datagram[1] {
+            KIND_DATA if datagram.len() >= DATA_HEADER_LEN => Ok(ArqFrame::Data {
+                session: u32_at(2),
+                seq: u32_at(6),
+                payload: datagram[DATA_HEADER_LEN..].to_vec(),
+            }),
+            KIND_ACK if datagram.len() == ACK_LEN => Ok(ArqFrame::Ack(ArqAck {
+                session: u32_at(2),
+                cumulative: u32_at(6),
+                selective: u64::from_be_bytes(datagram[10..18].try_into().unwrap()),
+            })),
+            kind => Err(SynapseError::InvalidMessageFormat(format!(
+                "Malformed ARQ frame (kind {}, {} bytes)",
+                kind,
+                datagram.len()
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InFlight {
+    sent_at: Instant,
+    transmissions: u32,
+}
+
+/// Sending half of an ARQ session with one peer
+#[derive(Debug)]
+pub struct ArqSender {
+    config: ArqConfig,
+    session: u32,
+    next_seq: u32,
+    in_flight: BTreeMap<u32, InFlight>,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl ArqSender {
+    /// Start a new session with a random session ID
+    pub fn new(config: ArqConfig) -> Self {
+        Self::with_session(config, rand::random())
+    }
+
+    /// Start a session with a fixed session ID
+    pub fn with_session(config: ArqConfig, session: u32) -> Self {
+        let rto = config.initial_rto;
+        Self {
+            config,
+            session,
+            next_seq: 0,
+            in_flight: BTreeMap::new(),
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto,
+        }
+    }
+
+    /// Session ID carried by every frame
+    pub fn session(&self) -> u32 {
+        self.session
+    }
+
+    /// Current retransmission timeout
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// Number of unacknowledged messages
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Assign a sequence number and build the DATA frame for a payload
+    pub fn prepare(&mut self, payload: Vec<u8>) -> Result<(u32, ArqFrame)> {
+        if self.in_flight.len() >= self.config.window_size {
+            return Err(SynapseError::TransportError(format!(
+                "ARQ window full ({} unacknowledged messages)",
+                self.in_flight.len()
+            )));
+        }
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.in_flight.insert(seq, InFlight { sent_at: Instant::now(), transmissions: 0 });
+        Ok((seq, ArqFrame::Data { session: self.session, seq, payload }))
+    }
+
+    /// Record a (re)transmission; fails once the message has used up its attempts
+    pub fn on_transmit(&mut self, seq: u32, now: Instant) -> Result<Duration> {
+        let entry = self
+            .in_flight
+            .get_mut(&seq)
+            .ok_or_else(|| SynapseError::TransportError(format!("ARQ sequence {} is not in flight", seq)))?;
+
+        if entry.transmissions >= self.config.max_transmissions {
+            let transmissions = entry.transmissions;
+            self.in_flight.remove(&seq);
+            return Err(SynapseError::TransportError(format!(
+                "No acknowledgement for UDP message {} after {} transmissions",
+                seq, transmissions
+            )));
+        }
+        if entry.transmissions > 0 {
+            // Exponential backoff on retransmission
+            self.rto = (self.rto * 2).min(self.config.max_rto);
+        }
+        entry.transmissions += 1;
+        entry.sent_at = now;
+        Ok(self.rto)
+    }
+
+    /// Whether a message is still awaiting acknowledgement
+    pub fn is_pending(&self, seq: u32) -> bool {
+        self.in_flight.contains_key(&seq)
+    }
+
+    /// Apply an acknowledgement, returning the newly acknowledged sequence numbers
+    pub fn on_ack(&mut self, ack: &ArqAck, now: Instant) -> Vec<u32> {
+        if ack.session != self.session {
+            return Vec::new();
+        }
+        let acked: Vec<u32> = self.in_flight.keys().copied().filter(|seq| ack.covers(*seq)).collect();
+
+        for seq in &acked {
+            if let Some(entry) = self.in_flight.remove(seq) {
+                // Karn's algorithm: only sample messages sent exactly once
+                if entry.transmissions == 1 {
+                    self.sample_rtt(now.saturating_duration_since(entry.sent_at));
+                }
+            }
+        }
+        acked
+    }
+
+    fn sample_rtt(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+        let rto = self.srtt.unwrap_or(rtt) + self.rttvar * 4;
+        self.rto = rto.clamp(self.config.min_rto, self.config.max_rto);
+    }
+}
+
+/// Receiving half of an ARQ session with one peer
+#[derive(Debug, Default)]
+pub struct ArqReceiver {
+    next_expected: u32,
+    received_ahead: BTreeSet<u32>,
+}
+
+impl ArqReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an arriving DATA frame; returns whether it is new and the ACK to send
+    pub fn on_data(&mut self, session: u32, seq: u32) -> (bool, ArqAck) {
+        let is_new = if seq < self.next_expected {
+            false
+        } else if seq == self.next_expected {
+            self.next_expected += 1;
+            while self.received_ahead.remove(&self.next_expected) {
+                self.next_expected += 1;
+            }
+            true
+        } else {
+            self.received_ahead.insert(seq)
+        };
+        (is_new, self.ack(session))
+    }
+
+    fn ack(&self, session: u32) -> ArqAck {
+        let selective = self
+            .received_ahead
+            .range(self.next_expected + 1..=self.next_expected + SELECTIVE_BITS)
+            .fold(0u64, |bits, seq| bits | 1 << (seq - self.next_expected - 1));
+        ArqAck { session, cumulative: self.next_expected, selective }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let data = ArqFrame::Data { session: 7, seq: 42, payload: b"{\"hi\":1}".to_vec() };
+        assert_eq!(ArqFrame::decode(&data.encode()).unwrap(), data);
+
+        let ack = ArqFrame::Ack(ArqAck { session: 7, cumulative: 3, selective: 0b101 });
+        assert_eq!(ArqFrame::decode(&ack.encode()).unwrap(), ack);
+
+        assert!(!ArqFrame::is_arq(b"{\"legacy\":true}"));
+    }
+
+    #[test]
+    fn test_selective_ack_over_lossy_link() {
+        let mut sender = ArqSender::with_session(ArqConfig::default(), 1);
+        let mut receiver = ArqReceiver::new();
+        let now = Instant::now();
+
+        let seqs: Vec<u32> = (0..4)
+            .map(|_| {
+                let (seq, _) = sender.prepare(Vec::new()).unwrap();
+                sender.on_transmit(seq, now).unwrap();
+                seq
+            })
+            .collect();
+
+        // Sequence 1 is lost; 0, 2 and 3 arrive
+        let mut last_ack = None;
+        for seq in [seqs[0], seqs[2], seqs[3]] {
+            let (is_new, ack) = receiver.on_data(1, seq);
+            assert!(is_new);
+            last_ack = Some(ack);
+        }
+        let ack = last_ack.unwrap();
+        assert_eq!(ack.cumulative, 1);
+        assert_eq!(sender.on_ack(&ack, now), vec![0, 2, 3]);
+        assert!(sender.is_pending(seqs[1]));
+
+        // The retransmission gets through and duplicates are recognised
+        sender.on_transmit(seqs[1], now).unwrap();
+        let (is_new, ack) = receiver.on_data(1, seqs[1]);
+        assert!(is_new);
+        assert_eq!(ack.cumulative, 4);
+        assert!(!receiver.on_data(1, seqs[2]).0);
+        assert_eq!(sender.on_ack(&ack, now), vec![1]);
+        assert_eq!(sender.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_gives_up_after_max_transmissions() {
+        let config = ArqConfig { max_transmissions: 2, ..Default::default() };
+        let mut sender = ArqSender::with_session(config, 1);
+        let (seq, _) = sender.prepare(Vec::new()).unwrap();
+        let now = Instant::now();
+
+        let first = sender.on_transmit(seq, now).unwrap();
+        let second = sender.on_transmit(seq, now).unwrap();
+        assert!(second > first);
+        assert!(sender.on_transmit(seq, now).is_err());
+        assert!(!sender.is_pending(seq));
+    }
+}
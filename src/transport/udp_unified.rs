@@ -4,8 +4,11 @@ use crate::{
     types::SecureMessage,
     error::{Result, SynapseError},
     circuit_breaker::{CircuitBreaker, CircuitBreakerConfig},
+    config::ListenerBinding,
 };
 use super::abstraction::*;
+use super::binding::bind_udp;
+use super::udp_arq::{ArqConfig, ArqFrame, ArqReceiver, ArqSender};
 use async_trait::async_trait;
 use std::{
     time::{Duration, Instant},
@@ -15,7 +18,7 @@ use std::{
 };
 use tokio::{
     net::UdpSocket,
-    sync::Mutex,
+    sync::{oneshot, Mutex},
 };
 use tracing::{info, debug, warn, error};
 use serde_json;
@@ -24,8 +27,8 @@ use serde_json;
 pub struct UdpTransportImpl {
     /// Local socket
     socket: Option<Arc<UdpSocket>>,
-    /// Local binding port
-    bind_port: u16,
+    /// Where the socket binds
+    binding: ListenerBinding,
     /// Maximum message size
     max_message_size: usize,
     /// Received messages queue
@@ -37,14 +40,38 @@ pub struct UdpTransportImpl {
     /// Circuit breaker for reliability
     #[allow(dead_code)]
     circuit_breaker: Arc<CircuitBreaker>,
+    /// Optional retransmission layer for lossy links
+    arq: Option<Arc<ArqState>>,
+}
+
+/// Per-peer ARQ sessions shared between senders and the receive task
+struct ArqState {
+    config: ArqConfig,
+    senders: std::sync::Mutex<HashMap<SocketAddr, ArqSender>>,
+    receivers: std::sync::Mutex<HashMap<(SocketAddr, u32), ArqReceiver>>,
+    waiters: std::sync::Mutex<HashMap<(SocketAddr, u32), oneshot::Sender<()>>>,
+}
+
+impl ArqState {
+    fn new(config: ArqConfig) -> Self {
+        Self {
+            config,
+            senders: std::sync::Mutex::new(HashMap::new()),
+            receivers: std::sync::Mutex::new(HashMap::new()),
+            waiters: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
 }
 
 impl UdpTransportImpl {
     /// Create a new UDP transport instance
     pub async fn new(config: &HashMap<String, String>) -> Result<Self> {
-        let bind_port = config.get("bind_port")
+        let mut binding = ListenerBinding::any(config.get("bind_port")
             .and_then(|p| p.parse().ok())
-            .unwrap_or(0); // 0 means let OS choose port
+            .unwrap_or(0)); // 0 means let OS choose port
+        if let Some(address) = config.get("bind_address").and_then(|a| a.parse().ok()) {
+            binding.address = address;
+        }
             
         let max_message_size = config.get("max_message_size")
             .and_then(|s| s.parse().ok())
@@ -53,25 +80,40 @@ impl UdpTransportImpl {
         let mut metrics = TransportMetrics::default();
         metrics.transport_type = TransportType::Udp;
 
+        // "reliable" enables acknowledgements and retransmission
+        let arq = config.get("reliable")
+            .is_some_and(|v| v == "true")
+            .then(|| {
+                let mut arq_config = ArqConfig::default();
+                if let Some(ms) = config.get("arq_initial_rto_ms").and_then(|v| v.parse().ok()) {
+                    arq_config.initial_rto = Duration::from_millis(ms);
+                }
+                if let Some(n) = config.get("arq_max_transmissions").and_then(|v| v.parse().ok()) {
+                    arq_config.max_transmissions = n;
+                }
+                Arc::new(ArqState::new(arq_config))
+            });
+
         Ok(Self {
             socket: None,
-            bind_port,
+            binding,
             max_message_size,
             received_messages: Arc::new(Mutex::new(Vec::new())),
             status: Arc::new(RwLock::new(TransportStatus::Stopped)),
             metrics: Arc::new(RwLock::new(metrics)),
             circuit_breaker: Arc::new(CircuitBreaker::new(CircuitBreakerConfig::default())),
+            arq,
         })
     }
 
+    /// Whether the ARQ reliability layer is enabled
+    pub fn is_reliable(&self) -> bool {
+        self.arq.is_some()
+    }
+
     /// Start the UDP server for incoming messages
     async fn start_server(&mut self) -> Result<()> {
-        let bind_addr = format!("0.0.0.0:{}", self.bind_port);
-        
-        let socket = UdpSocket::bind(&bind_addr).await
-            .map_err(|e| SynapseError::TransportError(
-                format!("Failed to bind UDP socket to {}: {}", bind_addr, e)
-            ))?;
+        let socket = bind_udp(&self.binding)?;
             
         let local_addr = socket.local_addr()
             .map_err(|e| SynapseError::TransportError(
@@ -87,16 +129,61 @@ impl UdpTransportImpl {
         let received_messages = Arc::clone(&self.received_messages);
         let metrics = Arc::clone(&self.metrics);
         let max_size = self.max_message_size;
+        let arq = self.arq.clone();
         
         tokio::spawn(async move {
-            let mut buffer = vec![0; max_size];
+            let mut buffer = vec![0; max_size + 16];
             
             loop {
                 match socket.recv_from(&mut buffer).await {
                     Ok((len, addr)) => {
                         debug!("Received {} bytes via UDP from {}", len, addr);
                         
-                        if let Ok(message_str) = String::from_utf8(buffer[..len].to_vec()) {
+                        // Unwrap ARQ frames: answer DATA with an ACK, hand ACKs to waiting senders
+                        let mut datagram = &buffer[..len];
+                        let payload;
+                        if ArqFrame::is_arq(datagram) {
+                            let Some(arq) = &arq else {
+                                warn!("Ignoring ARQ frame from {}: reliability layer disabled", addr);
+                                continue;
+                            };
+                            match ArqFrame::decode(datagram) {
+                                Ok(ArqFrame::Data { session, seq, payload: data }) => {
+                                    let (is_new, ack) = arq.receivers.lock().unwrap()
+                                        .entry((addr, session))
+                                        .or_default()
+                                        .on_data(session, seq);
+                                    if let Err(e) = socket.send_to(&ArqFrame::Ack(ack).encode(), addr).await {
+                                        warn!("Failed to acknowledge UDP message from {}: {}", addr, e);
+                                    }
+                                    if !is_new {
+                                        debug!("Dropping duplicate UDP message {} from {}", seq, addr);
+                                        continue;
+                                    }
+                                    payload = data;
+                                    datagram = &payload;
+                                }
+                                Ok(ArqFrame::Ack(ack)) => {
+                                    let acked = arq.senders.lock().unwrap()
+                                        .get_mut(&addr)
+                                        .map(|sender| sender.on_ack(&ack, Instant::now()))
+                                        .unwrap_or_default();
+                                    let mut waiters = arq.waiters.lock().unwrap();
+                                    for seq in acked {
+                                        if let Some(waiter) = waiters.remove(&(addr, seq)) {
+                                            let _ = waiter.send(());
+                                        }
+                                    }
+                                    continue;
+                                }
+                                Err(e) => {
+                                    warn!("Invalid ARQ frame from {}: {}", addr, e);
+                                    continue;
+                                }
+                            }
+                        }
+                        
+                        if let Ok(message_str) = std::str::from_utf8(datagram) {
                             if let Ok(message) = serde_json::from_str::<SecureMessage>(&message_str) {
                                 let mut incoming = IncomingMessage::new(
                                     message,
@@ -104,6 +191,7 @@ impl UdpTransportImpl {
                                     addr.to_string(),
                                 );
                                 incoming.metadata.insert("packet_size".to_string(), len.to_string());
+                                incoming.metadata.insert("reliable".to_string(), (datagram.len() != len).to_string());
                                 
                                 if let Ok(mut messages) = received_messages.try_lock() {
                                     messages.push(incoming);
@@ -151,6 +239,10 @@ impl UdpTransportImpl {
         
         let start_time = Instant::now();
         
+        if let Some(arq) = &self.arq {
+            return self.send_reliable(arq, target_addr, message_json, start_time).await;
+        }
+        
         // Use existing socket or create a temporary one
         let result = if let Some(socket) = &self.socket {
             socket.send_to(message_json.as_bytes(), target_addr).await
@@ -207,6 +299,86 @@ impl UdpTransportImpl {
         }
     }
 
+    /// Send with sequence numbers and retransmit until acknowledged or out of attempts
+    async fn send_reliable(
+        &self,
+        arq: &ArqState,
+        target_addr: &SocketAddr,
+        message_json: String,
+        start_time: Instant,
+    ) -> Result<DeliveryReceipt> {
+        // ACKs arrive on the receive task, so a bound socket is required
+        let socket = self.socket.as_ref().ok_or_else(|| SynapseError::TransportError(
+            "Reliable UDP requires a bound socket".to_string()
+        ))?;
+        let packet_size = message_json.len();
+        
+        let (seq, frame) = arq.senders.lock().unwrap()
+            .entry(*target_addr)
+            .or_insert_with(|| ArqSender::new(arq.config.clone()))
+            .prepare(message_json.into_bytes())?;
+        let frame = frame.encode();
+        
+        let (ack_tx, mut ack_rx) = oneshot::channel();
+        arq.waiters.lock().unwrap().insert((*target_addr, seq), ack_tx);
+        
+        let mut transmissions = 0;
+        let outcome = loop {
+            let rto = match arq.senders.lock().unwrap()
+                .get_mut(target_addr)
+                .map(|sender| sender.on_transmit(seq, Instant::now()))
+            {
+                Some(Ok(rto)) => rto,
+                Some(Err(e)) => break Err(e),
+                // Acknowledged between attempts
+                None => break Ok(()),
+            };
+            transmissions += 1;
+            
+            if let Err(e) = socket.send_to(&frame, target_addr).await {
+                break Err(SynapseError::TransportError(format!("Failed to send UDP message: {}", e)));
+            }
+            
+            match tokio::time::timeout(rto, &mut ack_rx).await {
+                Ok(_) => break Ok(()),
+                Err(_) => debug!("No ACK for UDP message {} to {} within {:?}, retransmitting", seq, target_addr, rto),
+            }
+        };
+        
+        arq.waiters.lock().unwrap().remove(&(*target_addr, seq));
+        
+        if let Err(e) = outcome {
+            if let Ok(mut metrics) = self.metrics.try_write() {
+                metrics.send_failures += 1;
+                metrics.touch();
+            }
+            warn!("Reliable UDP delivery to {} failed: {}", target_addr, e);
+            return Err(e);
+        }
+        
+        let send_time = start_time.elapsed();
+        info!("UDP message acknowledged by {} after {} transmission(s) in {:?}", target_addr, transmissions, send_time);
+        
+        if let Ok(mut metrics) = self.metrics.try_write() {
+            metrics.messages_sent += 1;
+            metrics.bytes_sent += (packet_size * transmissions) as u64;
+            metrics.touch();
+        }
+        
+        Ok(DeliveryReceipt {
+            message_id: message.message_id.0.to_string(),
+            transport_used: TransportType::Udp,
+            delivery_time: send_time,
+            target_reached: target_addr.to_string(),
+            confirmation: DeliveryConfirmation::Delivered,
+            metadata: HashMap::from([
+                ("packet_size".to_string(), packet_size.to_string()),
+                ("sequence".to_string(), seq.to_string()),
+                ("transmissions".to_string(), transmissions.to_string()),
+            ]),
+        })
+    }
+
     fn parse_target_address(&self, target: &TransportTarget) -> Result<SocketAddr> {
         if let Some(address) = &target.address {
             // Try to parse as socket address
@@ -250,7 +422,12 @@ impl Transport for UdpTransportImpl {
     }
 
     fn capabilities(&self) -> TransportCapabilities {
-        TransportCapabilities::udp()
+        let mut capabilities = TransportCapabilities::udp();
+        if self.arq.is_some() {
+            capabilities.reliable = true;
+            capabilities.features.push("arq".to_string());
+        }
+        capabilities
     }
 
     async fn can_reach(&self, target: &TransportTarget) -> bool {
@@ -265,11 +442,12 @@ impl Transport for UdpTransportImpl {
         // UDP estimates are more speculative since there's no connection
         Ok(TransportEstimate {
             latency: Duration::from_millis(10), // Assume low latency for UDP
-            reliability: 0.8, // UDP is less reliable than TCP
+            reliability: if self.arq.is_some() { 0.95 } else { 0.8 }, // ARQ recovers lost datagrams
             bandwidth: 10_000_000, // 10MB/s estimate for UDP
             cost: 0.5, // Lower cost than TCP
             available: true, // Assume available if address is valid
             confidence: 0.6, // Lower confidence since we can't test
+            region: None,
         })
     }
 
@@ -383,6 +561,10 @@ impl Transport for UdpTransportImpl {
         Ok(())
     }
 
+    fn local_endpoint(&self) -> Option<SocketAddr> {
+        self.socket.as_ref().and_then(|socket| socket.local_addr().ok())
+    }
+
     async fn status(&self) -> TransportStatus {
         *self.status.read().unwrap()
     }
@@ -400,13 +582,12 @@ impl TransportFactory for UdpTransportFactory {
     async fn create_transport(&self, config: &HashMap<String, String>) -> Result<Box<dyn Transport>> {
         let mut transport = UdpTransportImpl::new(config).await?;
         
-        // Start the server immediately if bind_port is specified
-        if let Some(port_str) = config.get("bind_port") {
-            if let Ok(port) = port_str.parse::<u16>() {
-                if port > 0 {
-                    transport.start_server().await?;
-                }
-            }
+        // Start the server immediately if bind_port is specified, or if ARQ needs it for ACKs
+        let has_port = config.get("bind_port")
+            .and_then(|p| p.parse::<u16>().ok())
+            .is_some_and(|port| port > 0);
+        if has_port || transport.is_reliable() {
+            transport.start_server().await?;
         }
         
         Ok(Box::new(transport))
@@ -420,6 +601,7 @@ impl TransportFactory for UdpTransportFactory {
         let mut config = HashMap::new();
         config.insert("bind_port".to_string(), "8081".to_string());
         config.insert("max_message_size".to_string(), "65507".to_string());
+        config.insert("reliable".to_string(), "false".to_string());
         config
     }
 
@@ -432,6 +614,14 @@ impl TransportFactory for UdpTransportFactory {
             }
         }
         
+        if let Some(address) = config.get("bind_address") {
+            if address.parse::<std::net::IpAddr>().is_err() {
+                return Err(SynapseError::TransportError(
+                    format!("Invalid bind_address: {}", address)
+                ));
+            }
+        }
+        
         if let Some(size_str) = config.get("max_message_size") {
             if let Ok(size) = size_str.parse::<usize>() {
                 if size > 65507 {
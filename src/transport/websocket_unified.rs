@@ -318,6 +318,7 @@ impl Transport for WebSocketTransportImpl {
                 cost: 2.0, // Medium cost
                 available: true,
                 confidence: 0.85, // Good confidence
+                region: None,
             })
         } else {
             Ok(TransportEstimate {
@@ -327,6 +328,7 @@ impl Transport for WebSocketTransportImpl {
                 cost: 1000.0,
                 available: false,
                 confidence: 0.95,
+                region: None,
             })
         }
     }
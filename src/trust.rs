@@ -0,0 +1,461 @@
+//! # Trust Simulation
+//!
+//! Tuning the network trust system's decay and stake parameters on a live
+//! chain takes months. [`simulate`] instead replays a stream of events under
+//! any [`BlockchainConfig`], offline and in moments. It applies the rules the
+//! chain does: report scoring with its age weighting, points positive reports
+//! award, the available points a reporter needs, slashing of reporters whose
+//! reports a dispute annulled, and scheduled decay that spares staked points.
+//! The result is every participant's score and points after each change.
+//!
+//! Events are recorded ([`events_from_chain`] reads them off committed
+//! blocks) or synthetic; [`TrustEvent`] is plain serde data, so a JSON file
+//! of them works too. They are applied in time order, and decay runs every
+//! `decay_check_interval_hours` of simulated time from the first event.
+//! Registering, reporting, staking, unstaking, transferring points and
+//! [`TrustEvent::Activity`] hold off decay.
+
+use crate::blockchain::{Block, BlockchainConfig, Transaction, TrustReport, TrustReportType, score_reports_at};
+use crate::synapse::blockchain::serialization::DateTimeWrapper;
+use crate::synapse::models::TrustBalance;
+use crate::synapse::services::decay::{apply_decay, decay_amount};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Something that happened to the trust system
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TrustEvent {
+    /// A participant joins with the configured `genesis_trust_points`
+    Register { participant: String, at: DateTime<Utc> },
+    /// A trust report is committed
+    Report {
+        id: String,
+        reporter: String,
+        subject: String,
+        /// -100 to 100
+        score: i8,
+        /// Points the reporter put behind the report
+        stake: u32,
+        at: DateTime<Utc>,
+    },
+    /// A dispute found a report false: it stops counting and its reporter is slashed
+    Annul { report_id: String, at: DateTime<Utc> },
+    /// Points staked, which decay cannot take
+    Stake { participant: String, amount: u32, at: DateTime<Utc> },
+    Unstake { participant: String, amount: u32, at: DateTime<Utc> },
+    Transfer { from: String, to: String, amount: u32, at: DateTime<Utc> },
+    /// Activity outside the chain that holds off decay, e.g. sending messages
+    Activity { participant: String, at: DateTime<Utc> },
+}
+
+impl TrustEvent {
+    pub fn at(&self) -> DateTime<Utc> {
+        match self {
+            TrustEvent::Register { at, .. }
+            | TrustEvent::Report { at, .. }
+            | TrustEvent::Annul { at, .. }
+            | TrustEvent::Stake { at, .. }
+            | TrustEvent::Unstake { at, .. }
+            | TrustEvent::Transfer { at, .. }
+            | TrustEvent::Activity { at, .. } => *at,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrustEvent::Register { .. } => "register",
+            TrustEvent::Report { .. } => "report",
+            TrustEvent::Annul { .. } => "annul",
+            TrustEvent::Stake { .. } => "stake",
+            TrustEvent::Unstake { .. } => "unstake",
+            TrustEvent::Transfer { .. } => "transfer",
+            TrustEvent::Activity { .. } => "activity",
+        }
+    }
+}
+
+/// Events of the transactions in committed blocks, in chain order
+///
+/// Annulments are not on the chain; add [`TrustEvent::Annul`] for reports
+/// disputes found false.
+pub fn events_from_chain(blocks: &[Block]) -> Vec<TrustEvent> {
+    blocks
+        .iter()
+        .flat_map(|block| &block.transactions)
+        .map(|transaction| match transaction {
+            Transaction::Registration(registration) => TrustEvent::Register {
+                participant: registration.participant_id.clone(),
+                at: registration.timestamp,
+            },
+            Transaction::TrustReport(report) => TrustEvent::Report {
+                id: report.id.clone(),
+                reporter: report.reporter_id.clone(),
+                subject: report.subject_id.clone(),
+                score: report.score,
+                stake: report.stake_amount,
+                at: report.timestamp,
+            },
+            Transaction::Stake(stake) => TrustEvent::Stake {
+                participant: stake.participant_id.clone(),
+                amount: stake.amount,
+                at: stake.timestamp,
+            },
+            Transaction::Unstake(unstake) => TrustEvent::Unstake {
+                participant: unstake.participant_id.clone(),
+                amount: unstake.amount,
+                at: unstake.timestamp,
+            },
+            Transaction::Transfer(transfer) => TrustEvent::Transfer {
+                from: transfer.from_participant.clone(),
+                to: transfer.to_participant.clone(),
+                amount: transfer.amount,
+                at: transfer.timestamp,
+            },
+        })
+        .collect()
+}
+
+/// A participant's standing after something changed it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustSample {
+    pub at: DateTime<Utc>,
+    /// The event that changed it, or `decay`
+    pub cause: String,
+    /// Score from the reports counting at `at`
+    pub score: f64,
+    /// Reports counting towards the score
+    pub reports: u64,
+    pub total_points: u32,
+    pub staked_points: u32,
+}
+
+/// Score trajectories and totals of one simulation run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Simulation {
+    /// Participant -> its samples, oldest first
+    pub trajectories: BTreeMap<String, Vec<TrustSample>>,
+    /// Events the chain would have refused, e.g. reports from participants
+    /// without `min_stake_for_report` available points
+    pub rejected: Vec<TrustEvent>,
+    pub decay_runs: u64,
+    pub points_decayed: u64,
+    pub points_slashed: u64,
+}
+
+impl Simulation {
+    /// Each participant's score when the run ended
+    pub fn final_scores(&self) -> BTreeMap<String, f64> {
+        self.trajectories
+            .iter()
+            .filter_map(|(participant, samples)| Some((participant.clone(), samples.last()?.score)))
+            .collect()
+    }
+
+    /// Every sample as CSV, one line per participant and sample
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("participant,at,cause,score,reports,total_points,staked_points\n");
+        for (participant, samples) in &self.trajectories {
+            for sample in samples {
+                csv.push_str(&format!(
+                    "{},{},{},{:.4},{},{},{}\n",
+                    participant,
+                    sample.at.to_rfc3339(),
+                    sample.cause,
+                    sample.score,
+                    sample.reports,
+                    sample.total_points,
+                    sample.staked_points
+                ));
+            }
+        }
+        csv
+    }
+}
+
+/// Replay `events` under `config`, up to the last event
+pub fn simulate(config: &BlockchainConfig, events: impl IntoIterator<Item = TrustEvent>) -> Simulation {
+    simulate_until(config, events, None)
+}
+
+/// Replay `events` under `config`, running decay on until `until` when given
+pub fn simulate_until(
+    config: &BlockchainConfig,
+    events: impl IntoIterator<Item = TrustEvent>,
+    until: Option<DateTime<Utc>>,
+) -> Simulation {
+    let mut events: Vec<TrustEvent> = events.into_iter().collect();
+    events.sort_by_key(TrustEvent::at);
+    let Some(first) = events.first().map(TrustEvent::at) else {
+        return Simulation::default();
+    };
+
+    let interval = Duration::hours(config.trust_decay_config.decay_check_interval_hours.max(1) as i64);
+    let mut simulator = Simulator::new(config);
+    let mut next_decay = first + interval;
+    for event in events {
+        while next_decay <= event.at() {
+            simulator.decay(next_decay);
+            next_decay += interval;
+        }
+        simulator.apply(event);
+    }
+    if let Some(until) = until {
+        while next_decay <= until {
+            simulator.decay(next_decay);
+            next_decay += interval;
+        }
+    }
+    simulator.result
+}
+
+struct Account {
+    balance: TrustBalance,
+    /// Reports about the participant that count
+    reports: Vec<TrustReport>,
+}
+
+struct Simulator<'a> {
+    config: &'a BlockchainConfig,
+    accounts: BTreeMap<String, Account>,
+    /// Every accepted report by ID, for annulment
+    reports: HashMap<String, TrustReport>,
+    last_decay: Option<DateTime<Utc>>,
+    result: Simulation,
+}
+
+impl<'a> Simulator<'a> {
+    fn new(config: &'a BlockchainConfig) -> Self {
+        Self {
+            config,
+            accounts: BTreeMap::new(),
+            reports: HashMap::new(),
+            last_decay: None,
+            result: Simulation::default(),
+        }
+    }
+
+    fn account(&mut self, participant: &str, at: DateTime<Utc>) -> &mut Account {
+        let decay_rate = self.config.trust_decay_config.monthly_decay_rate;
+        self.accounts.entry(participant.to_string()).or_insert_with(|| Account {
+            balance: TrustBalance {
+                participant_id: participant.to_string(),
+                total_points: 0,
+                available_points: 0,
+                staked_points: 0,
+                earned_lifetime: 0,
+                last_activity: DateTimeWrapper::new(at),
+                decay_rate,
+            },
+            reports: Vec::new(),
+        })
+    }
+
+    fn available(&self, participant: &str) -> u32 {
+        self.accounts.get(participant).map_or(0, |account| account.balance.available_points)
+    }
+
+    fn touch(&mut self, participant: &str, at: DateTime<Utc>) {
+        self.account(participant, at).balance.last_activity = DateTimeWrapper::new(at);
+    }
+
+    fn add_points(&mut self, participant: &str, points: i64, at: DateTime<Utc>) {
+        let balance = &mut self.account(participant, at).balance;
+        balance.total_points = (balance.total_points as i64 + points).max(0) as u32;
+        balance.available_points = balance.total_points.saturating_sub(balance.staked_points);
+        if points > 0 {
+            balance.earned_lifetime += points as u32;
+        }
+    }
+
+    fn sample(&mut self, participant: &str, cause: &str, at: DateTime<Utc>) {
+        let Some(account) = self.accounts.get(participant) else { return };
+        let sample = TrustSample {
+            at,
+            cause: cause.to_string(),
+            score: score_reports_at(&account.reports, at),
+            reports: account.reports.len() as u64,
+            total_points: account.balance.total_points,
+            staked_points: account.balance.staked_points,
+        };
+        self.result.trajectories.entry(participant.to_string()).or_default().push(sample);
+    }
+
+    fn apply(&mut self, event: TrustEvent) {
+        let at = event.at();
+        let cause = event.name();
+        let changed: Vec<String> = match event {
+            TrustEvent::Register { ref participant, .. } => {
+                self.touch(participant, at);
+                self.add_points(participant, self.config.genesis_trust_points as i64, at);
+                vec![participant.clone()]
+            }
+            TrustEvent::Report { ref id, ref reporter, ref subject, score, stake, .. } => {
+                let report_type = if score >= 0 { TrustReportType::Positive } else { TrustReportType::Negative };
+                let mut report = TrustReport::new(
+                    reporter.clone(),
+                    subject.clone(),
+                    report_type,
+                    score,
+                    "overall".to_string(),
+                    stake,
+                );
+                report.id = id.clone();
+                report.timestamp = at;
+                let min_stake = self.config.staking_requirements.min_stake_for_report;
+                if !report.verify() || self.available(reporter) < min_stake {
+                    self.result.rejected.push(event);
+                    return;
+                }
+                self.touch(reporter, at);
+                self.add_points(subject, report.points_awarded() as i64, at);
+                self.account(subject, at).reports.push(report.clone());
+                self.reports.insert(id.clone(), report);
+                vec![subject.clone()]
+            }
+            TrustEvent::Annul { ref report_id, .. } => {
+                let Some(report) = self.reports.remove(report_id) else {
+                    self.result.rejected.push(event);
+                    return;
+                };
+                self.account(&report.subject_id, at).reports.retain(|r| r.id != report.id);
+                let slashed = self.config.staking_requirements.slash_amount(report.stake_amount);
+                self.add_points(&report.reporter_id, -(slashed as i64), at);
+                self.result.points_slashed += slashed as u64;
+                vec![report.subject_id, report.reporter_id]
+            }
+            TrustEvent::Stake { ref participant, amount, .. } => {
+                if self.available(participant) < amount {
+                    self.result.rejected.push(event);
+                    return;
+                }
+                self.touch(participant, at);
+                let balance = &mut self.account(participant, at).balance;
+                balance.staked_points += amount;
+                balance.available_points -= amount;
+                vec![participant.clone()]
+            }
+            TrustEvent::Unstake { ref participant, amount, .. } => {
+                if self.accounts.get(participant).is_none_or(|account| account.balance.staked_points < amount) {
+                    self.result.rejected.push(event);
+                    return;
+                }
+                self.touch(participant, at);
+                let balance = &mut self.account(participant, at).balance;
+                balance.staked_points -= amount;
+                balance.available_points += amount;
+                vec![participant.clone()]
+            }
+            TrustEvent::Transfer { ref from, ref to, amount, .. } => {
+                if self.available(from) < amount {
+                    self.result.rejected.push(event);
+                    return;
+                }
+                self.touch(from, at);
+                self.touch(to, at);
+                self.add_points(from, -(amount as i64), at);
+                self.add_points(to, amount as i64, at);
+                vec![from.clone(), to.clone()]
+            }
+            TrustEvent::Activity { ref participant, .. } => {
+                self.touch(participant, at);
+                Vec::new()
+            }
+        };
+        for participant in changed {
+            self.sample(&participant, cause, at);
+        }
+    }
+
+    /// One run of the decay scheduler at `now`
+    fn decay(&mut self, now: DateTime<Utc>) {
+        let since = self.last_decay.replace(now);
+        let grace_days = self.config.trust_decay_config.min_activity_days;
+        let cutoff = now - Duration::days(grace_days as i64);
+        let mut decayed = Vec::new();
+        for (participant, account) in &mut self.accounts {
+            if account.balance.last_activity.0 >= cutoff {
+                continue;
+            }
+            let amount = decay_amount(&account.balance, since, now, grace_days);
+            if amount == 0 {
+                continue;
+            }
+            apply_decay(&mut account.balance, amount);
+            self.result.points_decayed += amount as u64;
+            decayed.push(participant.clone());
+        }
+        self.result.decay_runs += 1;
+        for participant in decayed {
+            self.sample(&participant, "decay", now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scenario(start: DateTime<Utc>) -> Vec<TrustEvent> {
+        let hours = |h| start + Duration::hours(h);
+        let register = |participant: &str| TrustEvent::Register { participant: participant.to_string(), at: start };
+        let report = |id: &str, reporter: &str, score, stake, at| TrustEvent::Report {
+            id: id.to_string(),
+            reporter: reporter.to_string(),
+            subject: "bob".to_string(),
+            score,
+            stake,
+            at,
+        };
+        vec![
+            // Out of order on purpose; events are sorted by time
+            TrustEvent::Annul { report_id: "r2".to_string(), at: hours(24) },
+            register("alice"),
+            register("bob"),
+            register("carol"),
+            report("r1", "alice", 50, 40, hours(1)),
+            report("r2", "carol", -80, 20, hours(2)),
+            report("r3", "dave", 90, 10, hours(3)),
+            TrustEvent::Stake { participant: "alice".to_string(), amount: 50, at: hours(24) },
+        ]
+    }
+
+    #[test]
+    fn replays_scoring_slashing_and_decay() {
+        let start = DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        let until = Some(start + Duration::days(90));
+        let config = BlockchainConfig::default();
+        let run = simulate_until(&config, scenario(start), until);
+
+        // dave is not registered, so has no points to report with
+        assert_eq!(run.rejected.len(), 1);
+        assert_eq!(run.points_slashed, 2);
+        let bob = &run.trajectories["bob"];
+        let causes: Vec<&str> = bob.iter().take(4).map(|s| s.cause.as_str()).collect();
+        assert_eq!(causes, ["register", "report", "report", "annul"]);
+        // alice's report awarded 20 points; carol's counted until annulled
+        assert_eq!((bob[1].score, bob[1].total_points), (50.0, 120));
+        assert_eq!(bob[2].score, -15.0);
+        assert_eq!((bob[3].score, bob[3].reports), (50.0, 1));
+        assert_eq!(run.trajectories["carol"][1].total_points, 98);
+
+        // Inactive for two months after the grace period, bob decays; the
+        // surviving report is over 30 days old by then and counts half
+        assert_eq!(run.decay_runs, 90);
+        let last = bob.last().unwrap();
+        assert_eq!(last.cause, "decay");
+        assert!((115..120).contains(&last.total_points), "{}", last.total_points);
+        assert_eq!(run.final_scores()["bob"], 25.0);
+        let alice = run.trajectories["alice"].last().unwrap();
+        assert!(alice.total_points >= alice.staked_points && alice.staked_points == 50);
+
+        // What if points never decayed
+        let mut no_decay = config.clone();
+        no_decay.trust_decay_config.monthly_decay_rate = 0.0;
+        let run = simulate_until(&no_decay, scenario(start), until);
+        assert_eq!(run.points_decayed, 0);
+        assert_eq!(run.trajectories["bob"].last().unwrap().total_points, 120);
+        assert!(run.to_csv().starts_with("participant,at,cause,score"));
+    }
+}
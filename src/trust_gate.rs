@@ -0,0 +1,244 @@
+//! # Minimum-Trust Gating
+//!
+//! Some inbound actions are only worth accepting from senders the network
+//! already trusts: "only run LLM tasks for peers with network trust ≥ 70",
+//! "file transfers need entity trust ≥ 50". The [`TrustGate`] checks each
+//! inbound message against the configured [`TrustRequirement`]s after
+//! admission control, and the router drops messages that fall short,
+//! replying with a [`TrustRejection`] that says which requirement was missed.
+//!
+//! Senders mark what a message asks for in the [`ACTION_KEY`] metadata
+//! entry; requirements can also match on message type alone.
+
+use crate::admission::TrustSource;
+use crate::config::{TrustGateConfig, TrustRequirement};
+use crate::types::{MessageType, SimpleMessage};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Metadata key naming the action a message requests, e.g. `llm_task`
+pub const ACTION_KEY: &str = "action";
+/// Metadata key marking a trust rejection sent back to a sender
+pub const TRUST_REJECTION_KEY: &str = "trust_rejection";
+
+/// Which trust score a requirement is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrustKind {
+    /// Reputation across the network, from the trust blockchain
+    Network,
+    /// The recipient's own trust in the sender
+    Entity,
+}
+
+/// Why a message was refused; sent back to the sender
+///
+/// The sender's actual score is not disclosed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustRejection {
+    pub message_type: MessageType,
+    pub action: Option<String>,
+    pub kind: TrustKind,
+    pub required: f64,
+}
+
+impl TrustRejection {
+    /// System message telling `to_entity` what it needs
+    pub fn to_message(&self, from_entity: &str, to_entity: &str) -> SimpleMessage {
+        let mut metadata = HashMap::new();
+        if let Ok(json) = serde_json::to_string(self) {
+            metadata.insert(TRUST_REJECTION_KEY.to_string(), json);
+        }
+        let what = self.action.as_deref().map_or_else(|| format!("{} messages", self.message_type), |a| format!("'{}' requests", a));
+        let kind = match self.kind {
+            TrustKind::Network => "network",
+            TrustKind::Entity => "entity",
+        };
+        SimpleMessage {
+            to: to_entity.to_string(),
+            from_entity: from_entity.to_string(),
+            content: format!("Message refused: {} require {} trust of at least {}.", what, kind, self.required),
+            message_type: MessageType::System,
+            metadata,
+        }
+    }
+
+    /// The rejection carried by a system message, if it is one
+    pub fn from_message(message: &SimpleMessage) -> Option<Self> {
+        serde_json::from_str(message.metadata.get(TRUST_REJECTION_KEY)?).ok()
+    }
+}
+
+/// Whether a requirement applies to a message
+pub fn requirement_matches(requirement: &TrustRequirement, message: &SimpleMessage) -> bool {
+    requirement.message_type.as_ref().is_none_or(|t| *t == message.message_type)
+        && requirement
+            .action
+            .as_ref()
+            .is_none_or(|action| message.metadata.get(ACTION_KEY) == Some(action))
+}
+
+/// Enforces minimum sender trust on inbound messages
+pub struct TrustGate {
+    config: TrustGateConfig,
+    trust: Option<Arc<dyn TrustSource>>,
+    // (kind, sender, recipient) -> score and when it was looked up
+    cache: DashMap<(TrustKind, String, String), (Option<f64>, Instant)>,
+    last_rejection: DashMap<String, Instant>,
+}
+
+impl TrustGate {
+    /// Create a gate; without a trust source every requirement fails
+    pub fn new(config: TrustGateConfig) -> Self {
+        Self {
+            config,
+            trust: None,
+            cache: DashMap::new(),
+            last_rejection: DashMap::new(),
+        }
+    }
+
+    /// Consult `trust` for network and entity trust scores
+    pub fn with_trust_source(mut self, trust: Arc<dyn TrustSource>) -> Self {
+        self.trust = Some(trust);
+        self
+    }
+
+    /// Whether any requirement is enforced
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled && !self.config.requirements.is_empty()
+    }
+
+    /// Settings in use
+    pub fn config(&self) -> &TrustGateConfig {
+        &self.config
+    }
+
+    /// Check a message against every requirement that matches it
+    ///
+    /// Returns the first requirement the sender misses, if any. Unknown
+    /// trust counts as missing the requirement.
+    pub async fn check(&self, message: &SimpleMessage) -> Option<TrustRejection> {
+        if !self.config.enabled {
+            return None;
+        }
+        for requirement in self.config.requirements.iter().filter(|r| requirement_matches(r, message)) {
+            let thresholds = [
+                (TrustKind::Network, requirement.min_network_trust),
+                (TrustKind::Entity, requirement.min_entity_trust),
+            ];
+            for (kind, required) in thresholds {
+                let Some(required) = required else {
+                    continue;
+                };
+                let score = self.trust_of(kind, &message.from_entity, &message.to).await;
+                if score.is_none_or(|score| score < required) {
+                    warn!(
+                        "Refused {} message from {}: {:?} trust below {}",
+                        message.message_type, message.from_entity, kind, required
+                    );
+                    return Some(TrustRejection {
+                        message_type: message.message_type.clone(),
+                        action: message.metadata.get(ACTION_KEY).cloned(),
+                        kind,
+                        required,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether to tell `sender` about a rejection now; at most once a minute
+    /// per sender so rejected floods are not amplified
+    pub fn should_notify(&self, sender: &str) -> bool {
+        if !self.config.notify_senders {
+            return false;
+        }
+        if self.last_rejection.get(sender).is_some_and(|sent| sent.elapsed() < Duration::from_secs(60)) {
+            return false;
+        }
+        self.last_rejection.insert(sender.to_string(), Instant::now());
+        true
+    }
+
+    /// Record a score directly, e.g. from a trust report
+    pub fn set_trust(&self, kind: TrustKind, sender: &str, recipient: &str, score: f64) {
+        self.cache.insert((kind, sender.to_string(), recipient.to_string()), (Some(score), Instant::now()));
+    }
+
+    /// Drop cached scores and rejection state for a sender
+    pub fn forget(&self, sender: &str) {
+        self.cache.retain(|(_, cached_sender, _), _| cached_sender != sender);
+        self.last_rejection.remove(sender);
+    }
+
+    async fn trust_of(&self, kind: TrustKind, sender: &str, recipient: &str) -> Option<f64> {
+        let key = (kind, sender.to_string(), recipient.to_string());
+        let ttl = Duration::from_secs(self.config.trust_cache_secs);
+        if let Some(entry) = self.cache.get(&key).filter(|entry| entry.1.elapsed() < ttl) {
+            return entry.0;
+        }
+        let trust = self.trust.as_ref()?;
+        let score = match kind {
+            TrustKind::Network => trust.network_trust(sender).await,
+            TrustKind::Entity => trust.entity_trust(sender, recipient).await,
+        };
+        debug!("Looked up {:?} trust for {}: {:?}", kind, sender, score);
+        self.cache.insert(key, (score, Instant::now()));
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct StaticTrust;
+
+    #[async_trait]
+    impl TrustSource for StaticTrust {
+        async fn network_trust(&self, global_id: &str) -> Option<f64> {
+            (global_id == "trusted@example.com").then_some(80.0)
+        }
+    }
+
+    fn task(from: &str) -> SimpleMessage {
+        let mut message = SimpleMessage::new("bot@example.com", from, "summarize this");
+        message.metadata.insert(ACTION_KEY.to_string(), "llm_task".to_string());
+        message
+    }
+
+    #[tokio::test]
+    async fn llm_tasks_need_network_trust() {
+        let config = TrustGateConfig {
+            enabled: true,
+            requirements: vec![TrustRequirement {
+                action: Some("llm_task".to_string()),
+                min_network_trust: Some(70.0),
+                ..TrustRequirement::default()
+            }],
+            ..TrustGateConfig::default()
+        };
+        let gate = TrustGate::new(config).with_trust_source(Arc::new(StaticTrust));
+
+        assert_eq!(gate.check(&task("trusted@example.com")).await, None);
+        let rejection = gate.check(&task("stranger@example.com")).await.unwrap();
+        assert_eq!(rejection.kind, TrustKind::Network);
+        assert_eq!(rejection.action.as_deref(), Some("llm_task"));
+
+        // Other actions are not gated
+        let chat = SimpleMessage::new("bot@example.com", "stranger@example.com", "hi");
+        assert_eq!(gate.check(&chat).await, None);
+
+        let reply = rejection.to_message("bot@example.com", "stranger@example.com");
+        assert_eq!(TrustRejection::from_message(&reply), Some(rejection));
+        assert!(gate.should_notify("stranger@example.com"));
+        assert!(!gate.should_notify("stranger@example.com"));
+    }
+}
@@ -201,7 +201,7 @@
 //!         host: "smtp.gmail.com".to_string(),
 //!         port: 587,
 //!         username: "mybot@gmail.com".to_string(),
-//!         password: "app_password".to_string(),
+//!         password: "app_password".into(),
 //!         use_tls: true,    // Modern security
 //!         use_ssl: false,   // Legacy security
 //!     },
@@ -209,7 +209,7 @@
 //!         host: "imap.gmail.com".to_string(),
 //!         port: 993, 
 //!         username: "mybot@gmail.com".to_string(),
-//!         password: "app_password".to_string(),
+//!         password: "app_password".into(),
 //!         use_ssl: true,    // Secure IMAP
 //!     },
 //! }
@@ -271,6 +271,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use chrono::Utc;
 use uuid::Uuid;
+use crate::redaction::Secret;
 use crate::synapse::blockchain::serialization::{DateTimeWrapper, UuidWrapper};
 
 /// Types of entities in the global network
@@ -500,7 +501,9 @@ impl GlobalIdentity {
 }
 
 /// Secure message for network transport
-#[derive(Debug, Clone, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+///
+/// `Debug` output leaves out the content, signature and metadata values.
+#[derive(Clone, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
 pub struct SecureMessage {
     /// Unique message identifier
     pub message_id: UuidWrapper,
@@ -522,6 +525,28 @@ pub struct SecureMessage {
     /// Additional metadata
     #[serde(default)]
     pub metadata: HashMap<String, String>,
+    /// Files sent with the message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+impl std::fmt::Debug for SecureMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut metadata_keys: Vec<&String> = self.metadata.keys().collect();
+        metadata_keys.sort();
+        f.debug_struct("SecureMessage")
+            .field("message_id", &self.message_id)
+            .field("to_global_id", &self.to_global_id)
+            .field("from_global_id", &self.from_global_id)
+            .field("encrypted_content", &format_args!("<{} bytes>", self.encrypted_content.len()))
+            .field("signature", &format_args!("<{} bytes>", self.signature.len()))
+            .field("timestamp", &self.timestamp)
+            .field("security_level", &self.security_level)
+            .field("routing_path", &self.routing_path)
+            .field("metadata_keys", &metadata_keys)
+            .field("attachments", &self.attachments.len())
+            .finish()
+    }
 }
 
 impl SecureMessage {
@@ -543,9 +568,15 @@ impl SecureMessage {
             security_level,
             routing_path: Vec::new(),
             metadata: HashMap::new(),
+            attachments: Vec::new(),
         }
     }
 
+    /// Attach a file to the message
+    pub fn add_attachment(&mut self, attachment: Attachment) {
+        self.attachments.push(attachment);
+    }
+
     /// Add a routing hop to the path
     pub fn add_routing_hop(&mut self, hop: impl Into<String>) {
         self.routing_path.push(hop.into());
@@ -567,6 +598,92 @@ impl SecureMessage {
     }
 }
 
+/// Attachments up to this size travel inside the message; larger ones are
+/// offloaded to a chunked stream
+pub const ATTACHMENT_INLINE_LIMIT: usize = 256 * 1024;
+
+/// A file sent with a message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct Attachment {
+    /// File name shown to the recipient
+    pub name: String,
+    /// MIME type, e.g. `image/png`
+    pub mime_type: String,
+    /// Size of the content in bytes
+    pub size: u64,
+    /// Hex SHA-256 of the content, which also addresses offloaded content
+    pub sha256: String,
+    /// Where the content is
+    pub content: AttachmentContent,
+}
+
+/// Content of an [`Attachment`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AttachmentContent {
+    /// Carried in the message itself (base64 in JSON)
+    Inline {
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+    /// Sent separately as a chunked stream with this ID
+    Stream { stream_id: String },
+}
+
+impl Attachment {
+    /// Attachment carrying its content inline
+    pub fn new(name: impl Into<String>, mime_type: impl Into<String>, data: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            mime_type: mime_type.into(),
+            size: data.len() as u64,
+            sha256: Self::digest(&data),
+            content: AttachmentContent::Inline { data },
+        }
+    }
+
+    /// Hex SHA-256 of some content
+    pub fn digest(data: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Inline content, if the attachment has not been offloaded
+    pub fn data(&self) -> Option<&[u8]> {
+        match &self.content {
+            AttachmentContent::Inline { data } => Some(data),
+            AttachmentContent::Stream { .. } => None,
+        }
+    }
+
+    /// Whether the attachment is too large to travel inline
+    pub fn needs_offload(&self) -> bool {
+        self.data().is_some_and(|data| data.len() > ATTACHMENT_INLINE_LIMIT)
+    }
+
+    /// Check received content against the attachment's size and hash
+    pub fn verify(&self, data: &[u8]) -> bool {
+        data.len() as u64 == self.size && Self::digest(data) == self.sha256
+    }
+}
+
+/// Serde adapter writing byte vectors as base64 strings
+mod base64_bytes {
+    use base64::Engine as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 /// Configuration for email providers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailConfig {
@@ -586,7 +703,7 @@ pub struct SmtpConfig {
     /// Username for authentication
     pub username: String,
     /// Password for authentication
-    pub password: String,
+    pub password: Secret<String>,
     /// Use TLS encryption
     pub use_tls: bool,
     /// Use SSL encryption
@@ -603,7 +720,7 @@ pub struct ImapConfig {
     /// Username for authentication
     pub username: String,
     /// Password for authentication
-    pub password: String,
+    pub password: Secret<String>,
     /// Use SSL encryption
     pub use_ssl: bool,
 }
@@ -744,3 +861,14 @@ impl StreamMetadata {
         }
     }
 }
+
+/// Public keys a browser node shares with its peers
+///
+/// Both keys are SPKI-encoded as exported by the Web Crypto API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebPublicKeys {
+    /// RSA-OAEP key that wraps message keys
+    pub encryption: Vec<u8>,
+    /// ECDSA P-256 key that verifies signatures
+    pub signing: Vec<u8>,
+}
@@ -1,23 +1,43 @@
-//! WebAssembly-compatible cryptography using Web Crypto API
-//! 
-//! This module provides cryptographic operations that work in browser
-//! environments using the Web Crypto API instead of native crypto libraries.
+//! WebAssembly-compatible cryptography using the Web Crypto API
+//!
+//! Browser builds cannot use the native `rsa`/`aes-gcm` stack, so browser
+//! nodes do their cryptography through `crypto.subtle`:
+//!
+//! - an RSA-OAEP key pair (SHA-256) wraps a fresh AES key for every message,
+//! - an ECDSA P-256 key pair signs outgoing messages,
+//! - AES-256-GCM encrypts message content.
+//!
+//! Private keys are generated non-extractable, so they never leave the
+//! browser's key store. Peers exchange the SPKI encodings returned by
+//! [`WebCrypto::export_public_keys`]. [`WebCrypto::seal`] signs and encrypts
+//! a message for one peer and [`WebCrypto::open`] reverses it, rejecting
+//! anything whose signature does not verify.
+//!
+//! To survive a page reload the private keys are stored wrapped:
+//! [`WebCrypto::generate_persistent_key_pair`] wraps them with an AES-GCM key
+//! derived from a passphrase (PBKDF2-SHA-256) and [`WebCrypto::restore_key_pair`]
+//! unwraps them again as non-extractable keys. The same derived key encrypts
+//! other data kept at rest, such as conversation history.
 
-use wasm_bindgen::prelude::*;
+use crate::error::{CryptoError, Result, SynapseError};
+pub use crate::types::WebPublicKeys;
+use js_sys::{Array, Object, Promise, Reflect, Uint8Array};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{window, Crypto, SubtleCrypto, CryptoKey, CryptoKeyPair};
-use js_sys::{Array, Object, Uint8Array, Promise};
+use web_sys::{Crypto, CryptoKey, SubtleCrypto};
 
-use crate::error::Result;
-
-/// WebAssembly-compatible crypto manager
-pub struct WebCrypto {
-    subtle_crypto: SubtleCrypto,
-    our_key_pair: Option<CryptoKeyPair>,
-    peer_public_keys: std::collections::HashMap<String, CryptoKey>,
-}
+/// Length of an AES-GCM nonce
+const NONCE_LEN: usize = 12;
+/// Length of a raw ECDSA P-256 signature (r || s)
+const SIGNATURE_LEN: usize = 64;
+/// PBKDF2 iterations for newly wrapped keys
+const PBKDF2_ITERATIONS: u32 = 310_000;
 
 /// Cryptographic algorithm configurations
+#[derive(Debug, Clone)]
 pub struct WebCryptoConfig {
     pub rsa_key_size: u32,
     pub aes_key_size: u32,
@@ -34,363 +54,543 @@ impl Default for WebCryptoConfig {
     }
 }
 
+/// Our key pairs as stored at rest; private keys are PKCS#8 wrapped with
+/// AES-GCM under a passphrase-derived key (nonce + ciphertext)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrappedKeys {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    /// SPKI encodings
+    pub public_keys: WebPublicKeys,
+    pub encryption_private: Vec<u8>,
+    pub signing_private: Vec<u8>,
+}
+
+#[derive(Clone)]
+struct KeyPair {
+    public: CryptoKey,
+    private: CryptoKey,
+}
+
+#[derive(Clone)]
+struct PeerKeys {
+    encryption: CryptoKey,
+    verification: CryptoKey,
+}
+
+/// WebAssembly-compatible crypto manager
+///
+/// Methods take `&self` so one manager can be shared by JS-facing handles;
+/// keys are JS handles and cheap to clone out of the store before awaiting.
+pub struct WebCrypto {
+    crypto: Crypto,
+    subtle: SubtleCrypto,
+    config: WebCryptoConfig,
+    encryption_keys: RefCell<Option<KeyPair>>,
+    signing_keys: RefCell<Option<KeyPair>>,
+    peers: RefCell<HashMap<String, PeerKeys>>,
+    /// Passphrase-derived key for data at rest
+    storage_key: RefCell<Option<CryptoKey>>,
+}
+
 impl WebCrypto {
-    /// Create a new WebCrypto instance
-    pub fn new() -> Self {
-        let window = window().expect("No window object available");
-        let crypto = window.crypto().expect("No crypto object available");
-        let subtle_crypto = crypto.subtle();
-        
-        web_sys::console::log_1(&"Initialized WebCrypto manager".into());
-        
-        Self {
-            subtle_crypto,
-            our_key_pair: None,
-            peer_public_keys: std::collections::HashMap::new(),
-        }
+    /// Create a manager on the global `crypto` object (window or worker)
+    pub fn new() -> Result<Self> {
+        Self::with_config(WebCryptoConfig::default())
     }
-    
-    /// Generate our RSA key pair for encryption/decryption
-    pub async fn generate_key_pair(&mut self) -> Result<()> {
-        web_sys::console::log_1(&"Generating RSA key pair...".into());
-        
-        // Configure RSA key generation
-        let algorithm = Object::new();
-        js_sys::Reflect::set(&algorithm, &"name".into(), &"RSA-OAEP".into())
-            .map_err(|e| anyhow::anyhow!("Failed to set algorithm name: {:?}", e))?;
-        js_sys::Reflect::set(&algorithm, &"modulusLength".into(), &2048u32.into())
-            .map_err(|e| anyhow::anyhow!("Failed to set modulus length: {:?}", e))?;
-        js_sys::Reflect::set(&algorithm, &"publicExponent".into(), &Uint8Array::from(&[0x01, 0x00, 0x01][..]))
-            .map_err(|e| anyhow::anyhow!("Failed to set public exponent: {:?}", e))?;
-        js_sys::Reflect::set(&algorithm, &"hash".into(), &"SHA-256".into())
-            .map_err(|e| anyhow::anyhow!("Failed to set hash algorithm: {:?}", e))?;
-        
-        // Generate key pair
-        let key_usages = Array::new();
-        key_usages.push(&"encrypt".into());
-        key_usages.push(&"decrypt".into());
-        
-        let key_pair_promise = self.subtle_crypto.generate_key_with_object(
-            &algorithm,
-            false, // extractable
-            &key_usages,
-        ).map_err(|e| anyhow::anyhow!("Failed to initiate key generation: {:?}", e))?;
-        
-        let key_pair_result = JsFuture::from(key_pair_promise).await
-            .map_err(|e| anyhow::anyhow!("Key generation failed: {:?}", e))?;
-        
-        let key_pair = key_pair_result.dyn_into::<CryptoKeyPair>()
-            .map_err(|e| anyhow::anyhow!("Failed to cast to CryptoKeyPair: {:?}", e))?;
-        
-        self.our_key_pair = Some(key_pair);
-        
-        web_sys::console::log_1(&"RSA key pair generated successfully".into());
+
+    pub fn with_config(config: WebCryptoConfig) -> Result<Self> {
+        let crypto = Reflect::get(&js_sys::global(), &JsValue::from_str("crypto"))
+            .ok()
+            .and_then(|crypto| crypto.dyn_into::<Crypto>().ok())
+            .ok_or_else(|| CryptoError::Crypto("Web Crypto API is not available".to_string()))?;
+        let subtle = crypto.subtle();
+        Ok(Self {
+            crypto,
+            subtle,
+            config,
+            encryption_keys: RefCell::new(None),
+            signing_keys: RefCell::new(None),
+            peers: RefCell::new(HashMap::new()),
+            storage_key: RefCell::new(None),
+        })
+    }
+
+    /// Generate our encryption and signing key pairs
+    pub async fn generate_key_pair(&self) -> Result<()> {
+        let (encryption, signing) = self.new_key_pairs(false).await?;
+        *self.encryption_keys.borrow_mut() = Some(encryption);
+        *self.signing_keys.borrow_mut() = Some(signing);
         Ok(())
     }
-    
-    /// Export our public key for sharing with peers
-    pub async fn export_public_key(&self) -> Result<Vec<u8>> {
-        if let Some(ref key_pair) = self.our_key_pair {
-            let public_key = key_pair.public_key();
-            
-            let export_promise = self.subtle_crypto.export_key("spki", &public_key)
-                .map_err(|e| anyhow::anyhow!("Failed to initiate key export: {:?}", e))?;
-            
-            let exported_result = JsFuture::from(export_promise).await
-                .map_err(|e| anyhow::anyhow!("Key export failed: {:?}", e))?;
-            
-            let array_buffer = exported_result.dyn_into::<js_sys::ArrayBuffer>()
-                .map_err(|e| anyhow::anyhow!("Failed to cast exported key: {:?}", e))?;
-            
-            let uint8_array = Uint8Array::new(&array_buffer);
-            let mut key_bytes = vec![0u8; uint8_array.length() as usize];
-            uint8_array.copy_to(&mut key_bytes);
-            
-            web_sys::console::log_1(&format!("Exported public key ({} bytes)", key_bytes.len()).into());
-            Ok(key_bytes)
-        } else {
-            Err(anyhow::anyhow!("No key pair available for export"))
-        }
+
+    /// Generate our key pairs and return them wrapped for storage
+    ///
+    /// The keys are generated extractable only long enough to wrap them; the
+    /// copies kept in use are unwrapped again as non-extractable.
+    pub async fn generate_persistent_key_pair(&self, passphrase: &str) -> Result<WrappedKeys> {
+        let (encryption, signing) = self.new_key_pairs(true).await?;
+        let salt = self.generate_random_bytes(16)?;
+        let storage_key = self.derive_storage_key(passphrase, &salt, PBKDF2_ITERATIONS).await?;
+
+        let wrapped = WrappedKeys {
+            salt,
+            iterations: PBKDF2_ITERATIONS,
+            public_keys: WebPublicKeys {
+                encryption: self.export_spki(&encryption.public).await?,
+                signing: self.export_spki(&signing.public).await?,
+            },
+            encryption_private: self.wrap_private_key(&encryption.private, &storage_key).await?,
+            signing_private: self.wrap_private_key(&signing.private, &storage_key).await?,
+        };
+        self.restore_key_pair(&wrapped, passphrase).await?;
+        Ok(wrapped)
+    }
+
+    /// Unwrap key pairs saved by [`WebCrypto::generate_persistent_key_pair`]
+    ///
+    /// Fails with an authentication error if the passphrase is wrong.
+    pub async fn restore_key_pair(&self, wrapped: &WrappedKeys, passphrase: &str) -> Result<()> {
+        let storage_key = self.derive_storage_key(passphrase, &wrapped.salt, wrapped.iterations).await?;
+
+        let rsa = algorithm(&[("name", "RSA-OAEP".into()), ("hash", self.config.hash_algorithm.as_str().into())])?;
+        let ecdsa = algorithm(&[("name", "ECDSA".into()), ("namedCurve", "P-256".into())])?;
+        let encryption = KeyPair {
+            public: self.import_spki(&wrapped.public_keys.encryption, &rsa, &["encrypt"]).await?,
+            private: self.unwrap_private_key(&wrapped.encryption_private, &storage_key, &rsa, &["decrypt"]).await?,
+        };
+        let signing = KeyPair {
+            public: self.import_spki(&wrapped.public_keys.signing, &ecdsa, &["verify"]).await?,
+            private: self.unwrap_private_key(&wrapped.signing_private, &storage_key, &ecdsa, &["sign"]).await?,
+        };
+
+        *self.encryption_keys.borrow_mut() = Some(encryption);
+        *self.signing_keys.borrow_mut() = Some(signing);
+        *self.storage_key.borrow_mut() = Some(storage_key);
+        Ok(())
+    }
+
+    /// Encrypt data for local storage with the passphrase-derived key
+    pub async fn seal_at_rest(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let key = self.current_storage_key()?;
+        self.encrypt_aes_gcm(&key, data).await
+    }
+
+    /// Decrypt data sealed by [`WebCrypto::seal_at_rest`]
+    pub async fn open_at_rest(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        let key = self.current_storage_key()?;
+        self.decrypt_aes_gcm(&key, sealed).await
     }
-    
-    /// Import a peer's public key
-    pub async fn import_peer_public_key(&mut self, peer_id: String, key_data: &[u8]) -> Result<()> {
-        web_sys::console::log_1(&format!("Importing public key for peer: {}", peer_id).into());
-        
-        // Configure key import
-        let algorithm = Object::new();
-        js_sys::Reflect::set(&algorithm, &"name".into(), &"RSA-OAEP".into())
-            .map_err(|e| anyhow::anyhow!("Failed to set algorithm name: {:?}", e))?;
-        js_sys::Reflect::set(&algorithm, &"hash".into(), &"SHA-256".into())
-            .map_err(|e| anyhow::anyhow!("Failed to set hash algorithm: {:?}", e))?;
-        
-        let key_usages = Array::new();
-        key_usages.push(&"encrypt".into());
-        
-        // Convert key data to ArrayBuffer
-        let uint8_array = Uint8Array::from(key_data);
-        let array_buffer = uint8_array.buffer();
-        
-        let import_promise = self.subtle_crypto.import_key_with_object(
-            "spki",
-            &array_buffer,
-            &algorithm,
-            false, // extractable
-            &key_usages,
-        ).map_err(|e| anyhow::anyhow!("Failed to initiate key import: {:?}", e))?;
-        
-        let imported_result = JsFuture::from(import_promise).await
-            .map_err(|e| anyhow::anyhow!("Key import failed: {:?}", e))?;
-        
-        let public_key = imported_result.dyn_into::<CryptoKey>()
-            .map_err(|e| anyhow::anyhow!("Failed to cast imported key: {:?}", e))?;
-        
-        self.peer_public_keys.insert(peer_id.clone(), public_key);
-        
-        web_sys::console::log_1(&format!("Successfully imported public key for {}", peer_id).into());
+
+    /// Check if our key pairs are available
+    pub fn has_key_pair(&self) -> bool {
+        self.encryption_keys.borrow().is_some() && self.signing_keys.borrow().is_some()
+    }
+
+    /// Export our public keys for sharing with peers
+    pub async fn export_public_keys(&self) -> Result<WebPublicKeys> {
+        let encryption = self.own_keys(&self.encryption_keys)?.public;
+        let signing = self.own_keys(&self.signing_keys)?.public;
+        Ok(WebPublicKeys {
+            encryption: self.export_spki(&encryption).await?,
+            signing: self.export_spki(&signing).await?,
+        })
+    }
+
+    /// Import a peer's public keys, replacing any held for it
+    pub async fn import_peer_keys(&self, peer_id: &str, keys: &WebPublicKeys) -> Result<()> {
+        let rsa = algorithm(&[("name", "RSA-OAEP".into()), ("hash", self.config.hash_algorithm.as_str().into())])?;
+        let encryption = self.import_spki(&keys.encryption, &rsa, &["encrypt"]).await?;
+        let ecdsa = algorithm(&[("name", "ECDSA".into()), ("namedCurve", "P-256".into())])?;
+        let verification = self.import_spki(&keys.signing, &ecdsa, &["verify"]).await?;
+
+        self.peers.borrow_mut().insert(peer_id.to_string(), PeerKeys { encryption, verification });
         Ok(())
     }
-    
+
+    /// Whether keys are held for a peer
+    pub fn has_peer_key(&self, peer_id: &str) -> bool {
+        self.peers.borrow().contains_key(peer_id)
+    }
+
+    /// Get the number of peers whose keys are stored
+    pub fn peer_key_count(&self) -> usize {
+        self.peers.borrow().len()
+    }
+
+    /// Remove a peer's public keys
+    pub fn remove_peer_key(&self, peer_id: &str) -> bool {
+        self.peers.borrow_mut().remove(peer_id).is_some()
+    }
+
+    /// Sign data with our ECDSA key; the signature is raw `r || s`
+    pub async fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let key = self.own_keys(&self.signing_keys)?.private;
+        let ecdsa = algorithm(&[("name", "ECDSA".into()), ("hash", "SHA-256".into())])?;
+        let signature = resolve(self.subtle.sign_with_object_and_buffer_source(&ecdsa, &key, &Uint8Array::from(data)))
+            .await
+            .map_err(|e| js_error(CryptoError::Signing, "Signing failed", e))?;
+        Ok(bytes(&signature))
+    }
+
+    /// Verify a peer's signature over data
+    pub async fn verify(&self, peer_id: &str, data: &[u8], signature: &[u8]) -> Result<bool> {
+        let key = self.peer_keys(peer_id)?.verification;
+        let ecdsa = algorithm(&[("name", "ECDSA".into()), ("hash", "SHA-256".into())])?;
+        let valid = resolve(self.subtle.verify_with_object_and_buffer_source_and_buffer_source(
+            &ecdsa,
+            &key,
+            &Uint8Array::from(signature),
+            &Uint8Array::from(data),
+        ))
+        .await
+        .map_err(|e| js_error(CryptoError::Crypto, "Signature verification failed", e))?;
+        Ok(valid.as_bool().unwrap_or(false))
+    }
+
+    /// Generate a symmetric AES key for session encryption
+    pub async fn generate_aes_key(&self) -> Result<CryptoKey> {
+        self.new_aes_key(false).await
+    }
+
+    /// Encrypt with AES-GCM; output layout: nonce (12 bytes) + ciphertext
+    pub async fn encrypt_aes_gcm(&self, key: &CryptoKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.generate_random_bytes(NONCE_LEN)?;
+        let aes = algorithm(&[("name", "AES-GCM".into()), ("iv", Uint8Array::from(&nonce[..]).into())])?;
+        let ciphertext = resolve(self.subtle.encrypt_with_object_and_buffer_source(&aes, key, &Uint8Array::from(plaintext)))
+            .await
+            .map_err(|e| js_error(CryptoError::Encryption, "AES-GCM encryption failed", e))?;
+
+        let mut sealed = nonce;
+        sealed.extend_from_slice(&bytes(&ciphertext));
+        Ok(sealed)
+    }
+
+    /// Decrypt data produced by [`WebCrypto::encrypt_aes_gcm`]
+    pub async fn decrypt_aes_gcm(&self, key: &CryptoKey, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(CryptoError::Decryption("Invalid encrypted data format".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let aes = algorithm(&[("name", "AES-GCM".into()), ("iv", Uint8Array::from(nonce).into())])?;
+        let plaintext = resolve(self.subtle.decrypt_with_object_and_buffer_source(&aes, key, &Uint8Array::from(ciphertext)))
+            .await
+            .map_err(|e| js_error(CryptoError::Decryption, "Wrong key or corrupted data", e))?;
+        Ok(bytes(&plaintext))
+    }
+
     /// Encrypt a message for a specific peer
+    ///
+    /// A fresh AES-256-GCM key encrypts the message and is wrapped with the
+    /// peer's RSA-OAEP key. Output layout: wrapped key length (2 bytes, big
+    /// endian) + wrapped key + nonce + ciphertext.
     pub async fn encrypt_for_peer(&self, peer_id: &str, message: &[u8]) -> Result<Vec<u8>> {
-        if let Some(public_key) = self.peer_public_keys.get(peer_id) {
-            web_sys::console::log_1(&format!("Encrypting message for peer: {}", peer_id).into());
-            
-            // Use RSA-OAEP for encryption
-            let algorithm = Object::new();
-            js_sys::Reflect::set(&algorithm, &"name".into(), &"RSA-OAEP".into())
-                .map_err(|e| anyhow::anyhow!("Failed to set algorithm name: {:?}", e))?;
-            
-            // Convert message to ArrayBuffer
-            let uint8_array = Uint8Array::from(message);
-            let array_buffer = uint8_array.buffer();
-            
-            let encrypt_promise = self.subtle_crypto.encrypt_with_object_and_buffer_source(
-                &algorithm,
-                public_key,
-                &array_buffer,
-            ).map_err(|e| anyhow::anyhow!("Failed to initiate encryption: {:?}", e))?;
-            
-            let encrypted_result = JsFuture::from(encrypt_promise).await
-                .map_err(|e| anyhow::anyhow!("Encryption failed: {:?}", e))?;
-            
-            let encrypted_buffer = encrypted_result.dyn_into::<js_sys::ArrayBuffer>()
-                .map_err(|e| anyhow::anyhow!("Failed to cast encrypted data: {:?}", e))?;
-            
-            let encrypted_array = Uint8Array::new(&encrypted_buffer);
-            let mut encrypted_bytes = vec![0u8; encrypted_array.length() as usize];
-            encrypted_array.copy_to(&mut encrypted_bytes);
-            
-            web_sys::console::log_1(&format!("Encrypted {} bytes for {}", encrypted_bytes.len(), peer_id).into());
-            Ok(encrypted_bytes)
-        } else {
-            Err(anyhow::anyhow!("No public key available for peer: {}", peer_id))
-        }
+        let peer_key = self.peer_keys(peer_id)?.encryption;
+        let message_key = self.new_aes_key(true).await?;
+        let raw_key = resolve(self.subtle.export_key("raw", &message_key))
+            .await
+            .map_err(|e| js_error(CryptoError::Encryption, "Message key export failed", e))?;
+
+        let rsa = algorithm(&[("name", "RSA-OAEP".into())])?;
+        let wrapped = resolve(self.subtle.encrypt_with_object_and_buffer_source(&rsa, &peer_key, &Uint8Array::new(&raw_key)))
+            .await
+            .map_err(|e| js_error(CryptoError::Encryption, "Message key wrapping failed", e))?;
+        let wrapped = bytes(&wrapped);
+
+        let mut result = Vec::with_capacity(2 + wrapped.len() + NONCE_LEN + message.len() + 16);
+        result.extend_from_slice(&(wrapped.len() as u16).to_be_bytes());
+        result.extend_from_slice(&wrapped);
+        result.extend_from_slice(&self.encrypt_aes_gcm(&message_key, message).await?);
+        Ok(result)
     }
-    
-    /// Decrypt a message using our private key
+
+    /// Decrypt a message produced by [`WebCrypto::encrypt_for_peer`] for us
     pub async fn decrypt_message(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
-        if let Some(ref key_pair) = self.our_key_pair {
-            web_sys::console::log_1(&"Decrypting received message...".into());
-            
-            let private_key = key_pair.private_key();
-            
-            // Use RSA-OAEP for decryption
-            let algorithm = Object::new();
-            js_sys::Reflect::set(&algorithm, &"name".into(), &"RSA-OAEP".into())
-                .map_err(|e| anyhow::anyhow!("Failed to set algorithm name: {:?}", e))?;
-            
-            // Convert encrypted data to ArrayBuffer
-            let uint8_array = Uint8Array::from(encrypted_data);
-            let array_buffer = uint8_array.buffer();
-            
-            let decrypt_promise = self.subtle_crypto.decrypt_with_object_and_buffer_source(
-                &algorithm,
-                &private_key,
-                &array_buffer,
-            ).map_err(|e| anyhow::anyhow!("Failed to initiate decryption: {:?}", e))?;
-            
-            let decrypted_result = JsFuture::from(decrypt_promise).await
-                .map_err(|e| anyhow::anyhow!("Decryption failed: {:?}", e))?;
-            
-            let decrypted_buffer = decrypted_result.dyn_into::<js_sys::ArrayBuffer>()
-                .map_err(|e| anyhow::anyhow!("Failed to cast decrypted data: {:?}", e))?;
-            
-            let decrypted_array = Uint8Array::new(&decrypted_buffer);
-            let mut decrypted_bytes = vec![0u8; decrypted_array.length() as usize];
-            decrypted_array.copy_to(&mut decrypted_bytes);
-            
-            web_sys::console::log_1(&format!("Decrypted {} bytes", decrypted_bytes.len()).into());
-            Ok(decrypted_bytes)
-        } else {
-            Err(anyhow::anyhow!("No private key available for decryption"))
+        let private_key = self.own_keys(&self.encryption_keys)?.private;
+        if encrypted_data.len() < 2 {
+            return Err(CryptoError::Decryption("Invalid encrypted data format".to_string()));
+        }
+        let wrapped_len = u16::from_be_bytes([encrypted_data[0], encrypted_data[1]]) as usize;
+        if encrypted_data.len() < 2 + wrapped_len {
+            return Err(CryptoError::Decryption("Invalid encrypted data format".to_string()));
+        }
+        let (wrapped, sealed) = encrypted_data[2..].split_at(wrapped_len);
+
+        let rsa = algorithm(&[("name", "RSA-OAEP".into())])?;
+        let raw_key = resolve(self.subtle.decrypt_with_object_and_buffer_source(&rsa, &private_key, &Uint8Array::from(wrapped)))
+            .await
+            .map_err(|e| js_error(CryptoError::Decryption, "Message key unwrapping failed", e))?;
+        let aes = algorithm(&[("name", "AES-GCM".into())])?;
+        let message_key = resolve(self.subtle.import_key_with_object(
+            "raw",
+            &Uint8Array::new(&raw_key),
+            &aes,
+            false,
+            &usages(&["decrypt"]),
+        ))
+        .await
+        .and_then(|key| key.dyn_into::<CryptoKey>())
+        .map_err(|e| js_error(CryptoError::Decryption, "Message key import failed", e))?;
+
+        self.decrypt_aes_gcm(&message_key, sealed).await
+    }
+
+    /// Sign a message from `from` to peer `to` and encrypt it for `to`
+    pub async fn seal(&self, from: &str, to: &str, message: &[u8]) -> Result<Vec<u8>> {
+        let signature = self.sign(&signing_payload(from, to, message)).await?;
+        let mut signed = signature;
+        signed.extend_from_slice(message);
+        self.encrypt_for_peer(to, &signed).await
+    }
+
+    /// Decrypt a message sealed for us (`to`) and check `from`'s signature
+    pub async fn open(&self, from: &str, to: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        let signed = self.decrypt_message(sealed).await?;
+        if signed.len() < SIGNATURE_LEN {
+            return Err(CryptoError::Decryption("Sealed message carries no signature".to_string()));
         }
+        let (signature, message) = signed.split_at(SIGNATURE_LEN);
+        if !self.verify(from, &signing_payload(from, to, message), signature).await? {
+            return Err(SynapseError::AuthenticationError(format!("Signature from {} does not verify", from)));
+        }
+        Ok(message.to_vec())
     }
-    
-    /// Generate a hash of data
+
+    /// Generate a SHA-256 hash of data
     pub async fn hash_data(&self, data: &[u8]) -> Result<Vec<u8>> {
-        // Convert data to ArrayBuffer
-        let uint8_array = Uint8Array::from(data);
-        let array_buffer = uint8_array.buffer();
-        
-        let hash_promise = self.subtle_crypto.digest_with_str_and_buffer_source(
-            "SHA-256",
-            &array_buffer,
-        ).map_err(|e| anyhow::anyhow!("Failed to initiate hashing: {:?}", e))?;
-        
-        let hash_result = JsFuture::from(hash_promise).await
-            .map_err(|e| anyhow::anyhow!("Hashing failed: {:?}", e))?;
-        
-        let hash_buffer = hash_result.dyn_into::<js_sys::ArrayBuffer>()
-            .map_err(|e| anyhow::anyhow!("Failed to cast hash result: {:?}", e))?;
-        
-        let hash_array = Uint8Array::new(&hash_buffer);
-        let mut hash_bytes = vec![0u8; hash_array.length() as usize];
-        hash_array.copy_to(&mut hash_bytes);
-        
-        Ok(hash_bytes)
-    }
-    
+        let hash = resolve(self.subtle.digest_with_str_and_buffer_source("SHA-256", &Uint8Array::from(data)))
+            .await
+            .map_err(|e| js_error(CryptoError::Crypto, "Hashing failed", e))?;
+        Ok(bytes(&hash))
+    }
+
     /// Generate random bytes using the crypto-secure random number generator
     pub fn generate_random_bytes(&self, length: usize) -> Result<Vec<u8>> {
-        let mut bytes = vec![0u8; length];
-        let uint8_array = Uint8Array::from(&mut bytes[..]);
-        
-        let window = window().ok_or_else(|| anyhow::anyhow!("No window object"))?;
-        let crypto = window.crypto().map_err(|e| anyhow::anyhow!("No crypto object: {:?}", e))?;
-        
-        crypto.get_random_values_with_u8_array(&uint8_array)
-            .map_err(|e| anyhow::anyhow!("Failed to generate random bytes: {:?}", e))?;
-        
-        uint8_array.copy_to(&mut bytes);
-        Ok(bytes)
-    }
-    
-    /// Generate a symmetric AES key for session encryption
-    pub async fn generate_aes_key(&self) -> Result<CryptoKey> {
-        web_sys::console::log_1(&"Generating AES key...".into());
-        
-        let algorithm = Object::new();
-        js_sys::Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into())
-            .map_err(|e| anyhow::anyhow!("Failed to set algorithm name: {:?}", e))?;
-        js_sys::Reflect::set(&algorithm, &"length".into(), &256u32.into())
-            .map_err(|e| anyhow::anyhow!("Failed to set key length: {:?}", e))?;
-        
-        let key_usages = Array::new();
-        key_usages.push(&"encrypt".into());
-        key_usages.push(&"decrypt".into());
-        
-        let key_promise = self.subtle_crypto.generate_key_with_object(
-            &algorithm,
-            false, // extractable
-            &key_usages,
-        ).map_err(|e| anyhow::anyhow!("Failed to initiate AES key generation: {:?}", e))?;
-        
-        let key_result = JsFuture::from(key_promise).await
-            .map_err(|e| anyhow::anyhow!("AES key generation failed: {:?}", e))?;
-        
-        let aes_key = key_result.dyn_into::<CryptoKey>()
-            .map_err(|e| anyhow::anyhow!("Failed to cast to CryptoKey: {:?}", e))?;
-        
-        web_sys::console::log_1(&"AES key generated successfully".into());
-        Ok(aes_key)
-    }
-    
-    /// Check if our key pair is available
-    pub fn has_key_pair(&self) -> bool {
-        self.our_key_pair.is_some()
+        let mut random = vec![0u8; length];
+        self.crypto
+            .get_random_values_with_u8_array(&mut random)
+            .map_err(|e| js_error(CryptoError::Crypto, "Failed to generate random bytes", e))?;
+        Ok(random)
     }
-    
-    /// Get the number of peer public keys stored
-    pub fn peer_key_count(&self) -> usize {
-        self.peer_public_keys.len()
+
+    async fn new_key_pairs(&self, extractable: bool) -> Result<(KeyPair, KeyPair)> {
+        let rsa = algorithm(&[
+            ("name", "RSA-OAEP".into()),
+            ("modulusLength", self.config.rsa_key_size.into()),
+            ("publicExponent", Uint8Array::from(&[0x01, 0x00, 0x01][..]).into()),
+            ("hash", self.config.hash_algorithm.as_str().into()),
+        ])?;
+        let generated = resolve(self.subtle.generate_key_with_object(&rsa, extractable, &usages(&["encrypt", "decrypt"])))
+            .await
+            .map_err(|e| js_error(CryptoError::KeyGeneration, "RSA-OAEP key generation failed", e))?;
+        let encryption = key_pair(&generated)?;
+
+        let ecdsa = algorithm(&[("name", "ECDSA".into()), ("namedCurve", "P-256".into())])?;
+        let generated = resolve(self.subtle.generate_key_with_object(&ecdsa, extractable, &usages(&["sign", "verify"])))
+            .await
+            .map_err(|e| js_error(CryptoError::KeyGeneration, "ECDSA key generation failed", e))?;
+        let signing = key_pair(&generated)?;
+
+        Ok((encryption, signing))
+    }
+
+    async fn derive_storage_key(&self, passphrase: &str, salt: &[u8], iterations: u32) -> Result<CryptoKey> {
+        let pbkdf2 = algorithm(&[("name", "PBKDF2".into())])?;
+        let base = resolve(self.subtle.import_key_with_object(
+            "raw",
+            &Uint8Array::from(passphrase.as_bytes()),
+            &pbkdf2,
+            false,
+            &usages(&["deriveKey"]),
+        ))
+        .await
+        .and_then(|key| key.dyn_into::<CryptoKey>())
+        .map_err(|e| js_error(CryptoError::KeyGeneration, "Passphrase import failed", e))?;
+
+        let params = algorithm(&[
+            ("name", "PBKDF2".into()),
+            ("salt", Uint8Array::from(salt).into()),
+            ("iterations", iterations.into()),
+            ("hash", "SHA-256".into()),
+        ])?;
+        let aes = algorithm(&[("name", "AES-GCM".into()), ("length", 256u32.into())])?;
+        resolve(self.subtle.derive_key_with_object_and_object(
+            &params,
+            &base,
+            &aes,
+            false,
+            &usages(&["encrypt", "decrypt", "wrapKey", "unwrapKey"]),
+        ))
+        .await
+        .and_then(|key| key.dyn_into::<CryptoKey>())
+        .map_err(|e| js_error(CryptoError::KeyGeneration, "Storage key derivation failed", e))
+    }
+
+    async fn wrap_private_key(&self, key: &CryptoKey, storage_key: &CryptoKey) -> Result<Vec<u8>> {
+        let nonce = self.generate_random_bytes(NONCE_LEN)?;
+        let aes = algorithm(&[("name", "AES-GCM".into()), ("iv", Uint8Array::from(&nonce[..]).into())])?;
+        let wrapped = resolve(self.subtle.wrap_key_with_object("pkcs8", key, storage_key, &aes))
+            .await
+            .map_err(|e| js_error(CryptoError::Encryption, "Key wrapping failed", e))?;
+
+        let mut sealed = nonce;
+        sealed.extend_from_slice(&bytes(&wrapped));
+        Ok(sealed)
+    }
+
+    async fn unwrap_private_key(
+        &self,
+        wrapped: &[u8],
+        storage_key: &CryptoKey,
+        key_algorithm: &Object,
+        key_usages: &[&str],
+    ) -> Result<CryptoKey> {
+        if wrapped.len() < NONCE_LEN {
+            return Err(CryptoError::InvalidKey("Wrapped key is truncated".to_string()));
+        }
+        let (nonce, ciphertext) = wrapped.split_at(NONCE_LEN);
+        let aes = algorithm(&[("name", "AES-GCM".into()), ("iv", Uint8Array::from(nonce).into())])?;
+        resolve(self.subtle.unwrap_key_with_buffer_source_and_object_and_object(
+            "pkcs8",
+            &Uint8Array::from(ciphertext),
+            storage_key,
+            &aes,
+            key_algorithm,
+            false,
+            &usages(key_usages),
+        ))
+        .await
+        .and_then(|key| key.dyn_into::<CryptoKey>())
+        .map_err(|_| SynapseError::AuthenticationError("Wrong passphrase or corrupted key store".to_string()))
+    }
+
+    fn current_storage_key(&self) -> Result<CryptoKey> {
+        self.storage_key
+            .borrow()
+            .clone()
+            .ok_or_else(|| CryptoError::KeyNotFound("No storage key; restore or generate persistent keys first".to_string()))
+    }
+
+    async fn new_aes_key(&self, extractable: bool) -> Result<CryptoKey> {
+        let aes = algorithm(&[("name", "AES-GCM".into()), ("length", self.config.aes_key_size.into())])?;
+        resolve(self.subtle.generate_key_with_object(&aes, extractable, &usages(&["encrypt", "decrypt"])))
+            .await
+            .and_then(|key| key.dyn_into::<CryptoKey>())
+            .map_err(|e| js_error(CryptoError::KeyGeneration, "AES key generation failed", e))
+    }
+
+    async fn export_spki(&self, key: &CryptoKey) -> Result<Vec<u8>> {
+        let exported = resolve(self.subtle.export_key("spki", key))
+            .await
+            .map_err(|e| js_error(CryptoError::InvalidKey, "Key export failed", e))?;
+        Ok(bytes(&exported))
+    }
+
+    async fn import_spki(&self, spki: &[u8], algorithm: &Object, key_usages: &[&str]) -> Result<CryptoKey> {
+        resolve(self.subtle.import_key_with_object("spki", &Uint8Array::from(spki), algorithm, true, &usages(key_usages)))
+            .await
+            .and_then(|key| key.dyn_into::<CryptoKey>())
+            .map_err(|e| js_error(CryptoError::InvalidKey, "Key import failed", e))
+    }
+
+    fn own_keys(&self, keys: &RefCell<Option<KeyPair>>) -> Result<KeyPair> {
+        keys.borrow()
+            .clone()
+            .ok_or_else(|| CryptoError::KeyNotFound("No key pair generated".to_string()))
     }
-    
-    /// Remove a peer's public key
-    pub fn remove_peer_key(&mut self, peer_id: &str) -> bool {
-        self.peer_public_keys.remove(peer_id).is_some()
+
+    fn peer_keys(&self, peer_id: &str) -> Result<PeerKeys> {
+        self.peers
+            .borrow()
+            .get(peer_id)
+            .cloned()
+            .ok_or_else(|| CryptoError::KeyNotFound(format!("No public key for {}", peer_id)))
     }
 }
 
+/// What a sealed message's signature covers; binds sender and recipient
+fn signing_payload(from: &str, to: &str, message: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(from.len() + to.len() + message.len() + 2);
+    payload.extend_from_slice(from.as_bytes());
+    payload.push(0);
+    payload.extend_from_slice(to.as_bytes());
+    payload.push(0);
+    payload.extend_from_slice(message);
+    payload
+}
+
+fn js_error(kind: fn(String) -> SynapseError, context: &str, error: JsValue) -> SynapseError {
+    kind(format!("{}: {:?}", context, error))
+}
+
+async fn resolve(promise: std::result::Result<Promise, JsValue>) -> std::result::Result<JsValue, JsValue> {
+    JsFuture::from(promise?).await
+}
+
+fn algorithm(entries: &[(&str, JsValue)]) -> Result<Object> {
+    let object = Object::new();
+    for (name, value) in entries {
+        Reflect::set(&object, &JsValue::from_str(name), value)
+            .map_err(|e| js_error(CryptoError::Crypto, "Invalid algorithm parameters", e))?;
+    }
+    Ok(object)
+}
+
+fn usages(names: &[&str]) -> Array {
+    names.iter().map(|name| JsValue::from_str(name)).collect()
+}
+
+fn bytes(buffer: &JsValue) -> Vec<u8> {
+    Uint8Array::new(buffer).to_vec()
+}
+
+fn key_pair(generated: &JsValue) -> Result<KeyPair> {
+    let key = |name: &str| {
+        Reflect::get(generated, &JsValue::from_str(name))
+            .and_then(|key| key.dyn_into::<CryptoKey>())
+            .map_err(|e| js_error(CryptoError::KeyGeneration, "Generated key pair is incomplete", e))
+    };
+    Ok(KeyPair {
+        public: key("publicKey")?,
+        private: key("privateKey")?,
+    })
+}
+
 /// Utility functions for WebAssembly crypto operations
 pub mod utils {
     use super::*;
-    
+
     /// Check if Web Crypto API is available
     pub fn is_web_crypto_available() -> bool {
-        if let Some(window) = window() {
-            if let Ok(crypto) = window.crypto() {
-                js_sys::Reflect::has(&crypto, &"subtle".into()).unwrap_or(false)
-            } else {
-                false
-            }
-        } else {
-            false
-        }
+        Reflect::get(&js_sys::global(), &JsValue::from_str("crypto"))
+            .ok()
+            .and_then(|crypto| crypto.dyn_into::<Crypto>().ok())
+            .is_some_and(|crypto| Reflect::has(&crypto, &JsValue::from_str("subtle")).unwrap_or(false))
     }
-    
+
     /// Get supported crypto algorithms
     pub fn get_supported_algorithms() -> Vec<String> {
-        let mut algorithms = Vec::new();
-        
-        if is_web_crypto_available() {
-            // These are commonly supported in modern browsers
-            algorithms.push("RSA-OAEP".to_string());
-            algorithms.push("AES-GCM".to_string());
-            algorithms.push("SHA-256".to_string());
-            algorithms.push("HMAC".to_string());
-            algorithms.push("PBKDF2".to_string());
+        if !is_web_crypto_available() {
+            return Vec::new();
         }
-        
-        algorithms
+        ["RSA-OAEP", "ECDSA", "AES-GCM", "SHA-256"].iter().map(|name| name.to_string()).collect()
     }
-    
+
     /// Convert bytes to hex string
     pub fn bytes_to_hex(bytes: &[u8]) -> String {
-        bytes.iter()
-            .map(|b| format!("{:02x}", b))
-            .collect()
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
     }
-    
+
     /// Convert hex string to bytes
     pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>> {
         if hex.len() % 2 != 0 {
-            return Err(anyhow::anyhow!("Hex string must have even length"));
+            return Err(SynapseError::InvalidFormat("Hex string must have even length".to_string()));
         }
-        
+
         (0..hex.len())
             .step_by(2)
             .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
             .collect::<std::result::Result<Vec<_>, _>>()
-            .map_err(|e| anyhow::anyhow!("Invalid hex string: {}", e))
-    }
-    
-    /// Base64 encode bytes
-    pub fn base64_encode(bytes: &[u8]) -> String {
-        let uint8_array = Uint8Array::from(bytes);
-        let window = window().expect("No window object");
-        let btoa = js_sys::Reflect::get(&window, &"btoa".into()).expect("No btoa function");
-        let btoa_fn = btoa.dyn_into::<js_sys::Function>().expect("btoa is not a function");
-        
-        // Convert Uint8Array to string for btoa
-        let binary_string = String::from_utf8_lossy(bytes);
-        let result = btoa_fn.call1(&window, &binary_string.into()).expect("btoa failed");
-        result.as_string().expect("btoa result is not a string")
-    }
-    
-    /// Base64 decode string
-    pub fn base64_decode(encoded: &str) -> Result<Vec<u8>> {
-        let window = window().ok_or_else(|| anyhow::anyhow!("No window object"))?;
-        let atob = js_sys::Reflect::get(&window, &"atob".into())
-            .map_err(|_| anyhow::anyhow!("No atob function"))?;
-        let atob_fn = atob.dyn_into::<js_sys::Function>()
-            .map_err(|_| anyhow::anyhow!("atob is not a function"))?;
-        
-        let result = atob_fn.call1(&window, &encoded.into())
-            .map_err(|e| anyhow::anyhow!("atob failed: {:?}", e))?;
-        let binary_string = result.as_string()
-            .ok_or_else(|| anyhow::anyhow!("atob result is not a string"))?;
-        
-        Ok(binary_string.into_bytes())
+            .map_err(|e| SynapseError::InvalidFormat(format!("Invalid hex string: {}", e)))
     }
 }
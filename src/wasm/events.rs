@@ -0,0 +1,114 @@
+//! Typed events for JavaScript listeners
+//!
+//! A browser node reports what happens to it as [`NodeEvent`]s: verified
+//! incoming messages, peer presence changes, delivery receipts for queued
+//! messages and changes to its own connection state. Listeners registered
+//! with `WasmSynapseNode::on_event` receive each event as a plain object
+//! tagged by `type`; the TypeScript definitions below describe them, so the
+//! generated `.d.ts` gives web dashboards a discriminated union to switch on.
+
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const NODE_EVENT_TYPES: &'static str = r#"
+export type PresenceStatus = "online" | "away" | "offline";
+export type DeliveryStatus = "queued" | "delivered" | "failed";
+export type ConnectionState = "disconnected" | "connecting" | "connected" | "reconnecting";
+
+export interface SynapseMessageEvent { type: "message"; id: string; from: string; text: string; timestamp: number; }
+export interface PresenceEvent { type: "presence"; peer: string; status: PresenceStatus; }
+export interface DeliveryReceiptEvent { type: "delivery_receipt"; message_id: string; peer: string; status: DeliveryStatus; }
+export interface ConnectionStateEvent { type: "connection_state"; state: ConnectionState; }
+
+export type SynapseEvent = SynapseMessageEvent | PresenceEvent | DeliveryReceiptEvent | ConnectionStateEvent;
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// Callback receiving [`NodeEvent`]s
+    #[wasm_bindgen(typescript_type = "(event: SynapseEvent) => void")]
+    pub type SynapseEventListener;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Queued,
+    Delivered,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    #[default]
+    Disconnected,
+    Connecting,
+    Connected,
+    Reconnecting,
+}
+
+/// Something a browser node reports to its listeners
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeEvent {
+    /// A message whose signature verified
+    Message { id: String, from: String, text: String, timestamp: u64 },
+    Presence { peer: String, status: PresenceStatus },
+    DeliveryReceipt { message_id: String, peer: String, status: DeliveryStatus },
+    ConnectionState { state: ConnectionState },
+}
+
+/// Registered listeners, called in registration order
+#[derive(Default)]
+pub struct EventEmitter {
+    listeners: RefCell<Vec<(u32, js_sys::Function)>>,
+    next_id: Cell<u32>,
+}
+
+impl EventEmitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a listener, returning the ID that removes it
+    pub fn subscribe(&self, listener: SynapseEventListener) -> u32 {
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+        self.listeners.borrow_mut().push((id, listener.unchecked_into()));
+        id
+    }
+
+    pub fn unsubscribe(&self, id: u32) -> bool {
+        let mut listeners = self.listeners.borrow_mut();
+        let before = listeners.len();
+        listeners.retain(|(listener_id, _)| *listener_id != id);
+        listeners.len() < before
+    }
+
+    /// Deliver an event to every listener
+    ///
+    /// A listener that throws is logged and does not stop the others.
+    pub fn emit(&self, event: &NodeEvent) {
+        let Some(value) = serde_json::to_string(event).ok().and_then(|json| js_sys::JSON::parse(&json).ok()) else {
+            return;
+        };
+        // Listeners may subscribe or unsubscribe while being called
+        let listeners: Vec<js_sys::Function> = self.listeners.borrow().iter().map(|(_, f)| f.clone()).collect();
+        for listener in listeners {
+            if let Err(error) = listener.call1(&JsValue::NULL, &value) {
+                web_sys::console::error_2(&"Synapse event listener failed:".into(), &error);
+            }
+        }
+    }
+}
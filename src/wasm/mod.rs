@@ -1,8 +1,16 @@
 //! WebAssembly support for Synapse Neural Communication Network
 //! 
 //! This module provides basic WebAssembly bindings for the Synapse protocol.
-//! Full WASM implementation is currently under development.
+//! Browser nodes sign and encrypt messages through the Web Crypto API (see
+//! `crypto`) and persist their state in IndexedDB (see `node_store`); the
+//! rest of the WASM implementation is under development.
 
+#[cfg(target_arch = "wasm32")]
+pub mod crypto;
+#[cfg(target_arch = "wasm32")]
+pub mod events;
+#[cfg(target_arch = "wasm32")]
+pub mod node_store;
 #[cfg(target_arch = "wasm32")]
 pub mod simple;
 
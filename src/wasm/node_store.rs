@@ -0,0 +1,327 @@
+//! Persistent state for browser nodes
+//!
+//! A browser node keeps everything it needs across page reloads behind the
+//! [`NodeStore`] trait: its wrapped key pairs, its contacts' public keys,
+//! conversation history and the queue of messages not yet delivered.
+//! [`IndexedDbStore`] keeps them in IndexedDB; [`MemoryNodeStore`] is for
+//! pages that should not persist anything.
+//!
+//! Nothing is stored in the clear that the network would not see anyway:
+//! private keys are wrapped with the passphrase-derived key, history content
+//! is sealed with the same key, and queued messages are already signed and
+//! encrypted for their recipient.
+
+use super::crypto::WrappedKeys;
+use crate::error::{Result, SynapseError};
+use crate::types::WebPublicKeys;
+use async_trait::async_trait;
+use js_sys::{Array, Function, Promise, Reflect};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbObjectStore, IdbOpenDbRequest, IdbRequest, IdbTransactionMode};
+
+/// Schema version of the IndexedDB database
+pub const DB_VERSION: u32 = 1;
+
+const IDENTITY_STORE: &str = "identity";
+const CONTACTS_STORE: &str = "contacts";
+const HISTORY_STORE: &str = "history";
+const OUTBOX_STORE: &str = "outbox";
+const IDENTITY_KEY: &str = "keys";
+
+/// A peer whose public keys we hold
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredContact {
+    pub entity_id: String,
+    pub public_keys: WebPublicKeys,
+    /// Milliseconds since the epoch
+    pub added_at: u64,
+}
+
+/// One message of a conversation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub id: String,
+    /// The other side of the conversation
+    pub peer: String,
+    pub outgoing: bool,
+    /// Milliseconds since the epoch
+    pub timestamp: u64,
+    /// Message text sealed with the storage key
+    pub content: Vec<u8>,
+}
+
+/// A sealed message waiting to be delivered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    pub id: String,
+    pub to: String,
+    pub sealed: Vec<u8>,
+    /// Milliseconds since the epoch
+    pub queued_at: u64,
+    pub attempts: u32,
+}
+
+/// Backing storage for a browser node's state
+#[async_trait(?Send)]
+pub trait NodeStore {
+    /// Save our wrapped key pairs, replacing any saved before
+    async fn save_identity(&self, keys: &WrappedKeys) -> Result<()>;
+
+    async fn load_identity(&self) -> Result<Option<WrappedKeys>>;
+
+    /// Add or replace a contact
+    async fn save_contact(&self, contact: &StoredContact) -> Result<()>;
+
+    async fn contacts(&self) -> Result<Vec<StoredContact>>;
+
+    async fn remove_contact(&self, entity_id: &str) -> Result<bool>;
+
+    async fn append_history(&self, record: &HistoryRecord) -> Result<()>;
+
+    /// History with a peer, oldest first
+    async fn history(&self, peer: &str) -> Result<Vec<HistoryRecord>>;
+
+    /// Add or replace a queued message
+    async fn enqueue(&self, message: &QueuedMessage) -> Result<()>;
+
+    /// Queued messages, oldest first
+    async fn queued(&self) -> Result<Vec<QueuedMessage>>;
+
+    /// Remove a delivered message from the queue
+    async fn dequeue(&self, id: &str) -> Result<bool>;
+}
+
+/// Node state kept only for the lifetime of the page
+#[derive(Default)]
+pub struct MemoryNodeStore {
+    identity: RefCell<Option<WrappedKeys>>,
+    contacts: RefCell<HashMap<String, StoredContact>>,
+    history: RefCell<Vec<HistoryRecord>>,
+    outbox: RefCell<Vec<QueuedMessage>>,
+}
+
+impl MemoryNodeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait(?Send)]
+impl NodeStore for MemoryNodeStore {
+    async fn save_identity(&self, keys: &WrappedKeys) -> Result<()> {
+        *self.identity.borrow_mut() = Some(keys.clone());
+        Ok(())
+    }
+
+    async fn load_identity(&self) -> Result<Option<WrappedKeys>> {
+        Ok(self.identity.borrow().clone())
+    }
+
+    async fn save_contact(&self, contact: &StoredContact) -> Result<()> {
+        self.contacts.borrow_mut().insert(contact.entity_id.clone(), contact.clone());
+        Ok(())
+    }
+
+    async fn contacts(&self) -> Result<Vec<StoredContact>> {
+        Ok(self.contacts.borrow().values().cloned().collect())
+    }
+
+    async fn remove_contact(&self, entity_id: &str) -> Result<bool> {
+        Ok(self.contacts.borrow_mut().remove(entity_id).is_some())
+    }
+
+    async fn append_history(&self, record: &HistoryRecord) -> Result<()> {
+        self.history.borrow_mut().push(record.clone());
+        Ok(())
+    }
+
+    async fn history(&self, peer: &str) -> Result<Vec<HistoryRecord>> {
+        Ok(self.history.borrow().iter().filter(|record| record.peer == peer).cloned().collect())
+    }
+
+    async fn enqueue(&self, message: &QueuedMessage) -> Result<()> {
+        let mut outbox = self.outbox.borrow_mut();
+        outbox.retain(|queued| queued.id != message.id);
+        outbox.push(message.clone());
+        Ok(())
+    }
+
+    async fn queued(&self) -> Result<Vec<QueuedMessage>> {
+        Ok(self.outbox.borrow().clone())
+    }
+
+    async fn dequeue(&self, id: &str) -> Result<bool> {
+        let mut outbox = self.outbox.borrow_mut();
+        let before = outbox.len();
+        outbox.retain(|queued| queued.id != id);
+        Ok(outbox.len() < before)
+    }
+}
+
+/// Node state kept in IndexedDB; records are stored as JSON strings
+pub struct IndexedDbStore {
+    db: IdbDatabase,
+}
+
+impl IndexedDbStore {
+    /// Open (creating if needed) the database `name`
+    pub async fn open(name: &str) -> Result<Self> {
+        let factory = Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))
+            .ok()
+            .and_then(|factory| factory.dyn_into::<IdbFactory>().ok())
+            .ok_or_else(|| SynapseError::DatabaseError("IndexedDB is not available".to_string()))?;
+        let request = factory.open_with_u32(name, DB_VERSION).map_err(|e| db_error("Failed to open IndexedDB", e))?;
+
+        let upgrade = Closure::once_into_js(move |event: web_sys::Event| {
+            let db = event
+                .target()
+                .and_then(|target| target.dyn_into::<IdbOpenDbRequest>().ok())
+                .and_then(|request| request.result().ok())
+                .and_then(|result| result.dyn_into::<IdbDatabase>().ok());
+            if let Some(db) = db {
+                for store in [IDENTITY_STORE, CONTACTS_STORE, HISTORY_STORE, OUTBOX_STORE] {
+                    let _ = db.create_object_store(store);
+                }
+            }
+        });
+        request.set_onupgradeneeded(Some(upgrade.unchecked_ref()));
+
+        let db = complete(&request)
+            .await?
+            .dyn_into::<IdbDatabase>()
+            .map_err(|e| db_error("IndexedDB open returned no database", e))?;
+        Ok(Self { db })
+    }
+
+    fn store(&self, name: &str, mode: IdbTransactionMode) -> Result<IdbObjectStore> {
+        self.db
+            .transaction_with_str_and_mode(name, mode)
+            .and_then(|transaction| transaction.object_store(name))
+            .map_err(|e| db_error("IndexedDB transaction failed", e))
+    }
+
+    async fn put<T: Serialize>(&self, store: &str, key: &str, value: &T) -> Result<()> {
+        let json = serde_json::to_string(value)?;
+        let request = self
+            .store(store, IdbTransactionMode::Readwrite)?
+            .put_with_key(&JsValue::from_str(&json), &JsValue::from_str(key))
+            .map_err(|e| db_error("IndexedDB write failed", e))?;
+        complete(&request).await?;
+        Ok(())
+    }
+
+    async fn get<T: DeserializeOwned>(&self, store: &str, key: &str) -> Result<Option<T>> {
+        let request = self
+            .store(store, IdbTransactionMode::Readonly)?
+            .get(&JsValue::from_str(key))
+            .map_err(|e| db_error("IndexedDB read failed", e))?;
+        match complete(&request).await?.as_string() {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_all<T: DeserializeOwned>(&self, store: &str) -> Result<Vec<T>> {
+        let request = self
+            .store(store, IdbTransactionMode::Readonly)?
+            .get_all()
+            .map_err(|e| db_error("IndexedDB read failed", e))?;
+        let values: Array = complete(&request).await?.unchecked_into();
+        values
+            .iter()
+            .filter_map(|value| value.as_string())
+            .map(|json| serde_json::from_str(&json).map_err(SynapseError::from))
+            .collect()
+    }
+
+    /// Delete a record, reporting whether it existed
+    async fn delete(&self, store: &str, key: &str) -> Result<bool> {
+        let existed = self.get::<serde_json::Value>(store, key).await?.is_some();
+        if existed {
+            let request = self
+                .store(store, IdbTransactionMode::Readwrite)?
+                .delete(&JsValue::from_str(key))
+                .map_err(|e| db_error("IndexedDB delete failed", e))?;
+            complete(&request).await?;
+        }
+        Ok(existed)
+    }
+}
+
+#[async_trait(?Send)]
+impl NodeStore for IndexedDbStore {
+    async fn save_identity(&self, keys: &WrappedKeys) -> Result<()> {
+        self.put(IDENTITY_STORE, IDENTITY_KEY, keys).await
+    }
+
+    async fn load_identity(&self) -> Result<Option<WrappedKeys>> {
+        self.get(IDENTITY_STORE, IDENTITY_KEY).await
+    }
+
+    async fn save_contact(&self, contact: &StoredContact) -> Result<()> {
+        self.put(CONTACTS_STORE, &contact.entity_id, contact).await
+    }
+
+    async fn contacts(&self) -> Result<Vec<StoredContact>> {
+        self.get_all(CONTACTS_STORE).await
+    }
+
+    async fn remove_contact(&self, entity_id: &str) -> Result<bool> {
+        self.delete(CONTACTS_STORE, entity_id).await
+    }
+
+    async fn append_history(&self, record: &HistoryRecord) -> Result<()> {
+        // Keys sort by time, so `getAll` returns records oldest first
+        let key = format!("{:016x}:{}", record.timestamp, record.id);
+        self.put(HISTORY_STORE, &key, record).await
+    }
+
+    async fn history(&self, peer: &str) -> Result<Vec<HistoryRecord>> {
+        let records: Vec<HistoryRecord> = self.get_all(HISTORY_STORE).await?;
+        Ok(records.into_iter().filter(|record| record.peer == peer).collect())
+    }
+
+    async fn enqueue(&self, message: &QueuedMessage) -> Result<()> {
+        self.put(OUTBOX_STORE, &message.id, message).await
+    }
+
+    async fn queued(&self) -> Result<Vec<QueuedMessage>> {
+        let mut queued: Vec<QueuedMessage> = self.get_all(OUTBOX_STORE).await?;
+        queued.sort_by_key(|message| message.queued_at);
+        Ok(queued)
+    }
+
+    async fn dequeue(&self, id: &str) -> Result<bool> {
+        self.delete(OUTBOX_STORE, id).await
+    }
+}
+
+/// Wait for an IndexedDB request and return its result
+async fn complete(request: &IdbRequest) -> Result<JsValue> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let on_success = Closure::once_into_js(move |event: web_sys::Event| {
+            let result = event
+                .target()
+                .and_then(|target| target.dyn_into::<IdbRequest>().ok())
+                .and_then(|request| request.result().ok())
+                .unwrap_or(JsValue::UNDEFINED);
+            let _ = resolve.call1(&JsValue::NULL, &result);
+        });
+        let on_error = Closure::once_into_js(move |event: web_sys::Event| {
+            let _ = reject.call1(&JsValue::NULL, &event);
+        });
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise).await.map_err(|e| db_error("IndexedDB request failed", e))
+}
+
+fn db_error(context: &str, error: JsValue) -> SynapseError {
+    SynapseError::DatabaseError(format!("{}: {:?}", context, error))
+}
@@ -1,8 +1,24 @@
 //! Simple WebAssembly bindings for Synapse
 //! 
 //! This module provides basic WebAssembly functionality for the Synapse protocol.
+//! Messages are signed and encrypted with the browser's Web Crypto API; the
+//! async methods return Promises. After `open_storage`, identity, contacts,
+//! history and undelivered messages are kept in IndexedDB and survive page
+//! reloads.
 
+use super::crypto::{WebCrypto, WebPublicKeys, utils::bytes_to_hex};
+use super::events::{
+    ConnectionState, DeliveryStatus, EventEmitter, NodeEvent, PresenceStatus, SynapseEventListener,
+};
+use super::node_store::{HistoryRecord, IndexedDbStore, NodeStore, QueuedMessage, StoredContact};
+use crate::error::SynapseError;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
 
 /// Simple browser-based Synapse node
 #[wasm_bindgen]
@@ -12,6 +28,28 @@ pub struct WasmSynapseNode {
     
     /// Simple configuration
     config: WasmConfig,
+
+    /// Key store shared with pending Promises
+    crypto: Rc<WebCrypto>,
+
+    /// Persistent state, once `open_storage` has completed
+    store: Rc<RefCell<Option<Rc<dyn NodeStore>>>>,
+
+    /// Listeners for typed events
+    events: Rc<EventEmitter>,
+
+    connection_state: Cell<ConnectionState>,
+
+    /// Last reported presence of each peer
+    presence: RefCell<HashMap<String, PresenceStatus>>,
+}
+
+/// A history entry as handed to JavaScript
+#[derive(Serialize)]
+struct HistoryEntry {
+    timestamp: u64,
+    outgoing: bool,
+    text: String,
 }
 
 /// Simple WASM configuration
@@ -41,16 +79,22 @@ impl WasmConfig {
 impl WasmSynapseNode {
     /// Create a new WASM Synapse node
     #[wasm_bindgen(constructor)]
-    pub fn new(entity_name: String, entity_type: String) -> WasmSynapseNode {
+    pub fn new(entity_name: String, entity_type: String) -> Result<WasmSynapseNode, JsValue> {
         let entity_id = format!("{}@wasm.synapse.local", entity_name);
+        let crypto = WebCrypto::new().map_err(to_js_error)?;
         
-        WasmSynapseNode {
+        Ok(WasmSynapseNode {
             entity_id,
             config: WasmConfig {
                 entity_name,
                 entity_type,
             },
-        }
+            crypto: Rc::new(crypto),
+            store: Rc::new(RefCell::new(None)),
+            events: Rc::new(EventEmitter::new()),
+            connection_state: Cell::new(ConnectionState::default()),
+            presence: RefCell::new(HashMap::new()),
+        })
     }
     
     /// Get the entity ID
@@ -77,13 +121,292 @@ impl WasmSynapseNode {
         web_sys::console::log_1(&format!("[{}] {}", self.entity_id, message).into());
     }
     
-    /// Send a simple message (placeholder implementation)
+    /// Generate this node's encryption and signing keys
+    #[wasm_bindgen]
+    pub fn generate_keys(&self) -> js_sys::Promise {
+        let crypto = self.crypto.clone();
+        future_to_promise(async move {
+            crypto.generate_key_pair().await.map_err(to_js_error)?;
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Register a listener for typed events; returns the ID for `off_event`
+    #[wasm_bindgen]
+    pub fn on_event(&self, listener: SynapseEventListener) -> u32 {
+        self.events.subscribe(listener)
+    }
+
+    /// Remove a listener registered with `on_event`
+    #[wasm_bindgen]
+    pub fn off_event(&self, id: u32) -> bool {
+        self.events.unsubscribe(id)
+    }
+
+    /// Current connection state
+    #[wasm_bindgen(getter)]
+    pub fn connection_state(&self) -> String {
+        to_tag(&self.connection_state.get())
+    }
+
+    /// Record a connection state change reported by the transport and
+    /// notify listeners if it changed
+    #[wasm_bindgen]
+    pub fn set_connection_state(&self, state: &str) -> Result<(), JsValue> {
+        let state: ConnectionState = from_tag(state)?;
+        if self.connection_state.replace(state) != state {
+            self.events.emit(&NodeEvent::ConnectionState { state });
+        }
+        Ok(())
+    }
+
+    /// Last reported presence of a peer (`offline` if never reported)
+    #[wasm_bindgen]
+    pub fn peer_presence(&self, peer_id: &str) -> String {
+        to_tag(&self.presence.borrow().get(peer_id).copied().unwrap_or(PresenceStatus::Offline))
+    }
+
+    /// Record a peer's presence and notify listeners if it changed
+    #[wasm_bindgen]
+    pub fn set_peer_presence(&self, peer_id: String, status: &str) -> Result<(), JsValue> {
+        let status: PresenceStatus = from_tag(status)?;
+        if self.presence.borrow_mut().insert(peer_id.clone(), status) != Some(status) {
+            self.events.emit(&NodeEvent::Presence { peer: peer_id, status });
+        }
+        Ok(())
+    }
+
+    /// Open this node's IndexedDB store and load or create its identity
+    ///
+    /// Resolves to `true` when a saved identity was restored and `false`
+    /// when new keys were generated and saved. Rejects if `passphrase` does
+    /// not unlock the saved keys.
+    #[wasm_bindgen]
+    pub fn open_storage(&self, passphrase: String) -> js_sys::Promise {
+        let crypto = self.crypto.clone();
+        let slot = self.store.clone();
+        let name = format!("synapse_{}", self.entity_id);
+        future_to_promise(async move {
+            let store: Rc<dyn NodeStore> = Rc::new(IndexedDbStore::open(&name).await.map_err(to_js_error)?);
+            let restored = match store.load_identity().await.map_err(to_js_error)? {
+                Some(wrapped) => {
+                    crypto.restore_key_pair(&wrapped, &passphrase).await.map_err(to_js_error)?;
+                    true
+                }
+                None => {
+                    let wrapped = crypto.generate_persistent_key_pair(&passphrase).await.map_err(to_js_error)?;
+                    store.save_identity(&wrapped).await.map_err(to_js_error)?;
+                    false
+                }
+            };
+            for contact in store.contacts().await.map_err(to_js_error)? {
+                crypto.import_peer_keys(&contact.entity_id, &contact.public_keys).await.map_err(to_js_error)?;
+            }
+            *slot.borrow_mut() = Some(store);
+            Ok(JsValue::from_bool(restored))
+        })
+    }
+
+    /// Whether keys have been generated
+    #[wasm_bindgen(getter)]
+    pub fn has_keys(&self) -> bool {
+        self.crypto.has_key_pair()
+    }
+
+    /// Our public keys as JSON, for sharing with peers
+    #[wasm_bindgen]
+    pub fn public_keys(&self) -> js_sys::Promise {
+        let crypto = self.crypto.clone();
+        future_to_promise(async move {
+            let keys = crypto.export_public_keys().await.map_err(to_js_error)?;
+            let json = serde_json::to_string(&keys).map_err(|e| to_js_error(e.into()))?;
+            Ok(JsValue::from_str(&json))
+        })
+    }
+
+    /// Register a peer's public keys, as returned by its `public_keys()`
+    #[wasm_bindgen]
+    pub fn add_peer(&self, peer_id: String, public_keys: String) -> js_sys::Promise {
+        let crypto = self.crypto.clone();
+        let store = self.store.borrow().clone();
+        future_to_promise(async move {
+            let keys: WebPublicKeys = serde_json::from_str(&public_keys).map_err(|e| to_js_error(e.into()))?;
+            crypto.import_peer_keys(&peer_id, &keys).await.map_err(to_js_error)?;
+            if let Some(store) = store {
+                let contact = StoredContact { entity_id: peer_id, public_keys: keys, added_at: now_millis() };
+                store.save_contact(&contact).await.map_err(to_js_error)?;
+            }
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Sign and encrypt a message for a peer; resolves to the sealed bytes
+    ///
+    /// With storage open the message is also recorded in history and queued
+    /// until `mark_delivered` is called with its ID; a `queued` delivery
+    /// receipt carries that ID.
+    #[wasm_bindgen]
+    pub fn send_message(&self, target: String, message: String) -> js_sys::Promise {
+        self.log(&format!("Sealing {} byte message for {}", message.len(), target));
+        let crypto = self.crypto.clone();
+        let from = self.entity_id.clone();
+        let store = self.store.borrow().clone();
+        let events = self.events.clone();
+        future_to_promise(async move {
+            let sealed = crypto.seal(&from, &target, message.as_bytes()).await.map_err(to_js_error)?;
+            if let Some(store) = store {
+                let id = bytes_to_hex(&crypto.generate_random_bytes(16).map_err(to_js_error)?);
+                record_history(&crypto, store.as_ref(), &id, &target, true, &message).await.map_err(to_js_error)?;
+                let queued = QueuedMessage {
+                    id: id.clone(),
+                    to: target.clone(),
+                    sealed: sealed.clone(),
+                    queued_at: now_millis(),
+                    attempts: 0,
+                };
+                store.enqueue(&queued).await.map_err(to_js_error)?;
+                events.emit(&NodeEvent::DeliveryReceipt { message_id: id, peer: target, status: DeliveryStatus::Queued });
+            }
+            Ok(js_sys::Uint8Array::from(&sealed[..]).into())
+        })
+    }
+
+    /// Messages not yet delivered, as JSON `[{id, to, sealed, queued_at, attempts}]`
+    #[wasm_bindgen]
+    pub fn pending_messages(&self) -> js_sys::Promise {
+        let store = self.store.borrow().clone();
+        future_to_promise(async move {
+            let queued = match store {
+                Some(store) => store.queued().await.map_err(to_js_error)?,
+                None => Vec::new(),
+            };
+            let json = serde_json::to_string(&queued).map_err(|e| to_js_error(e.into()))?;
+            Ok(JsValue::from_str(&json))
+        })
+    }
+
+    /// Remove a delivered message from the queue and emit a `delivered`
+    /// receipt; resolves to whether it was queued
+    #[wasm_bindgen]
+    pub fn mark_delivered(&self, id: String) -> js_sys::Promise {
+        let store = self.store.borrow().clone();
+        let events = self.events.clone();
+        future_to_promise(async move {
+            let Some(store) = store else {
+                return Ok(JsValue::FALSE);
+            };
+            let queued = store.queued().await.map_err(to_js_error)?;
+            let Some(message) = queued.into_iter().find(|message| message.id == id) else {
+                return Ok(JsValue::FALSE);
+            };
+            store.dequeue(&id).await.map_err(to_js_error)?;
+            events.emit(&NodeEvent::DeliveryReceipt { message_id: id, peer: message.to, status: DeliveryStatus::Delivered });
+            Ok(JsValue::TRUE)
+        })
+    }
+
+    /// Record a failed delivery attempt and emit a `failed` receipt; the
+    /// message stays queued for a retry
     #[wasm_bindgen]
-    pub fn send_message(&self, target: &str, message: &str) -> String {
-        let formatted = format!("Message from {} to {}: {}", self.entity_id, target, message);
-        self.log(&formatted);
-        formatted
+    pub fn mark_failed(&self, id: String) -> js_sys::Promise {
+        let store = self.store.borrow().clone();
+        let events = self.events.clone();
+        future_to_promise(async move {
+            let Some(store) = store else {
+                return Ok(JsValue::FALSE);
+            };
+            let queued = store.queued().await.map_err(to_js_error)?;
+            let Some(mut message) = queued.into_iter().find(|message| message.id == id) else {
+                return Ok(JsValue::FALSE);
+            };
+            message.attempts += 1;
+            store.enqueue(&message).await.map_err(to_js_error)?;
+            events.emit(&NodeEvent::DeliveryReceipt { message_id: id, peer: message.to, status: DeliveryStatus::Failed });
+            Ok(JsValue::TRUE)
+        })
     }
+
+    /// Conversation with a peer as JSON `[{timestamp, outgoing, text}]`, oldest first
+    #[wasm_bindgen]
+    pub fn history(&self, peer_id: String) -> js_sys::Promise {
+        let crypto = self.crypto.clone();
+        let store = self.store.borrow().clone();
+        future_to_promise(async move {
+            let mut entries = Vec::new();
+            if let Some(store) = store {
+                for record in store.history(&peer_id).await.map_err(to_js_error)? {
+                    let text = crypto.open_at_rest(&record.content).await.map_err(to_js_error)?;
+                    entries.push(HistoryEntry {
+                        timestamp: record.timestamp,
+                        outgoing: record.outgoing,
+                        text: String::from_utf8_lossy(&text).into_owned(),
+                    });
+                }
+            }
+            let json = serde_json::to_string(&entries).map_err(|e| to_js_error(e.into()))?;
+            Ok(JsValue::from_str(&json))
+        })
+    }
+
+    /// Decrypt a sealed message from a peer and verify its signature;
+    /// resolves to the message text and emits a `message` event
+    #[wasm_bindgen]
+    pub fn receive_message(&self, sender: String, sealed: Vec<u8>) -> js_sys::Promise {
+        let crypto = self.crypto.clone();
+        let to = self.entity_id.clone();
+        let store = self.store.borrow().clone();
+        let events = self.events.clone();
+        future_to_promise(async move {
+            let message = crypto.open(&sender, &to, &sealed).await.map_err(to_js_error)?;
+            let text = String::from_utf8(message)
+                .map_err(|e| to_js_error(SynapseError::InvalidMessageFormat(e.to_string())))?;
+            let id = bytes_to_hex(&crypto.generate_random_bytes(16).map_err(to_js_error)?);
+            if let Some(store) = store {
+                record_history(&crypto, store.as_ref(), &id, &sender, false, &text).await.map_err(to_js_error)?;
+            }
+            events.emit(&NodeEvent::Message { id, from: sender, text: text.clone(), timestamp: now_millis() });
+            Ok(JsValue::from_str(&text))
+        })
+    }
+}
+
+/// Serialized name of a unit enum variant, e.g. `connected`
+fn to_tag<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn from_tag<T: DeserializeOwned>(tag: &str) -> Result<T, JsValue> {
+    serde_json::from_value(serde_json::Value::String(tag.to_string()))
+        .map_err(|_| to_js_error(SynapseError::InvalidFormat(format!("Unknown value '{}'", tag))))
+}
+
+async fn record_history(
+    crypto: &WebCrypto,
+    store: &dyn NodeStore,
+    id: &str,
+    peer: &str,
+    outgoing: bool,
+    text: &str,
+) -> crate::error::Result<()> {
+    let record = HistoryRecord {
+        id: id.to_string(),
+        peer: peer.to_string(),
+        outgoing,
+        timestamp: now_millis(),
+        content: crypto.seal_at_rest(text.as_bytes()).await?,
+    };
+    store.append_history(&record).await
+}
+
+fn now_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+fn to_js_error(error: SynapseError) -> JsValue {
+    js_sys::Error::new(&error.to_string()).into()
 }
 
 /// Initialize panic hook for better error messages in WASM
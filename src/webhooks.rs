@@ -0,0 +1,514 @@
+//! # Webhook Egress
+//!
+//! Registered webhooks receive matching incoming messages as signed JSON POSTs,
+//! which lets ticketing systems, chat bridges and other HTTP services react to
+//! Synapse traffic without speaking the protocol.
+//!
+//! Every request carries:
+//! - `X-Synapse-Delivery`: the delivery ID (stable across retries)
+//! - `X-Synapse-Timestamp`: Unix seconds when the request was signed
+//! - `X-Synapse-Signature`: `sha256=<hex HMAC-SHA256 of "{timestamp}.{body}">`
+//!
+//! Receivers should recompute the signature with their shared secret and
+//! reject stale timestamps. Failed deliveries are retried with exponential
+//! backoff; 4xx responses other than 429 are treated as permanent.
+
+use crate::{
+    error::{Result, SynapseError},
+    redaction::Secret,
+    types::{MessageType, SimpleMessage},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Which incoming messages a webhook receives; empty criteria match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookFilter {
+    /// Sender global ID, or `*@domain` for any sender in a domain
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Recipient global ID, or `*@domain`
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Message types to forward
+    #[serde(default)]
+    pub message_types: Vec<MessageType>,
+    /// Substring the content must contain (case-insensitive)
+    #[serde(default)]
+    pub content_contains: Option<String>,
+    /// Metadata entries that must be present with these values
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+impl WebhookFilter {
+    /// Whether a message passes the filter
+    pub fn matches(&self, message: &SimpleMessage) -> bool {
+        fn address_matches(pattern: &str, address: &str) -> bool {
+            match pattern.strip_prefix("*@") {
+                Some(domain) => address.rsplit_once('@').is_some_and(|(_, d)| d.eq_ignore_ascii_case(domain)),
+                None => pattern.eq_ignore_ascii_case(address),
+            }
+        }
+
+        self.from.as_deref().is_none_or(|p| address_matches(p, &message.from_entity))
+            && self.to.as_deref().is_none_or(|p| address_matches(p, &message.to))
+            && (self.message_types.is_empty() || self.message_types.contains(&message.message_type))
+            && self
+                .content_contains
+                .as_deref()
+                .is_none_or(|needle| message.content.to_lowercase().contains(&needle.to_lowercase()))
+            && self.metadata.iter().all(|(k, v)| message.metadata.get(k) == Some(v))
+    }
+}
+
+/// A registered webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    pub filter: WebhookFilter,
+    /// Shared secret for request signatures
+    #[serde(skip_serializing)]
+    pub secret: Secret<String>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Where a delivery stands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered { status_code: u16, delivered_at: DateTime<Utc> },
+    Retrying { next_attempt_at: DateTime<Utc>, last_error: String },
+    Failed { last_error: String },
+}
+
+/// One message sent (or being sent) to one webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Retry schedule for failed deliveries
+#[derive(Debug, Clone)]
+pub struct WebhookRetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for WebhookRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 6,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+}
+
+impl WebhookRetryPolicy {
+    /// Delay before the attempt after `attempt` (1-based)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Performs the HTTP POST for a delivery
+#[async_trait]
+pub trait WebhookSender: Send + Sync {
+    /// POST `body` with `headers`, returning the response status code
+    async fn post(&self, url: &str, headers: &[(&str, String)], body: Vec<u8>) -> Result<u16>;
+}
+
+/// Sends webhooks with reqwest
+#[cfg(feature = "http")]
+pub struct HttpWebhookSender {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "http")]
+impl HttpWebhookSender {
+    pub fn new(timeout: Duration) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| SynapseError::ConfigurationError(format!("Failed to build webhook client: {}", e)))?;
+        Ok(Self { client })
+    }
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl WebhookSender for HttpWebhookSender {
+    async fn post(&self, url: &str, headers: &[(&str, String)], body: Vec<u8>) -> Result<u16> {
+        let mut request = self.client.post(url).header("Content-Type", "application/json").body(body);
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SynapseError::NetworkError(format!("Webhook POST to {} failed: {}", url, e)))?;
+        Ok(response.status().as_u16())
+    }
+}
+
+/// Stand-in sender when no HTTP client is available; every delivery fails
+pub struct UnavailableWebhookSender {
+    reason: String,
+}
+
+#[async_trait]
+impl WebhookSender for UnavailableWebhookSender {
+    async fn post(&self, url: &str, _headers: &[(&str, String)], _body: Vec<u8>) -> Result<u16> {
+        Err(SynapseError::ConfigurationError(format!("Cannot deliver webhook to {}: {}", url, self.reason)))
+    }
+}
+
+/// The HTTP client when the `http` feature is enabled, otherwise a sender
+/// whose every POST fails
+pub fn default_sender() -> Arc<dyn WebhookSender> {
+    #[cfg(feature = "http")]
+    let sender: Arc<dyn WebhookSender> = match HttpWebhookSender::new(Duration::from_secs(10)) {
+        Ok(sender) => Arc::new(sender),
+        Err(e) => {
+            warn!("{}; webhook deliveries will fail", e);
+            Arc::new(UnavailableWebhookSender { reason: e.to_string() })
+        }
+    };
+    #[cfg(not(feature = "http"))]
+    let sender: Arc<dyn WebhookSender> = Arc::new(UnavailableWebhookSender {
+        reason: "built without the http feature".to_string(),
+    });
+    sender
+}
+
+/// HMAC-SHA256 (RFC 2104)
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// Value of the `X-Synapse-Signature` header for a request
+pub fn signature_header(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut signed = format!("{}.", timestamp).into_bytes();
+    signed.extend_from_slice(body);
+    let mac = hmac_sha256(secret.as_bytes(), &signed);
+    let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+/// JSON body POSTed to webhooks
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    delivery_id: Uuid,
+    webhook_id: Uuid,
+    received_at: DateTime<Utc>,
+    message: &'a SimpleMessage,
+}
+
+/// Registry of webhooks and the deliveries made to them
+pub struct WebhookDispatcher {
+    hooks: DashMap<Uuid, Webhook>,
+    deliveries: DashMap<Uuid, WebhookDelivery>,
+    sender: Arc<dyn WebhookSender>,
+    retry: WebhookRetryPolicy,
+    /// Finished deliveries kept for status queries
+    max_tracked: usize,
+}
+
+impl WebhookDispatcher {
+    pub fn new(sender: Arc<dyn WebhookSender>, retry: WebhookRetryPolicy) -> Self {
+        Self {
+            hooks: DashMap::new(),
+            deliveries: DashMap::new(),
+            sender,
+            retry,
+            max_tracked: 10_000,
+        }
+    }
+
+    /// Dispatcher using the HTTP client when the `http` feature is enabled
+    pub fn with_default_sender() -> Self {
+        Self::new(default_sender(), WebhookRetryPolicy::default())
+    }
+
+    /// Register a webhook, returning its ID
+    pub fn register(&self, url: &str, filter: WebhookFilter, secret: &str) -> Result<Uuid> {
+        let parsed = url::Url::parse(url)
+            .map_err(|e| SynapseError::ConfigurationError(format!("Invalid webhook URL {}: {}", url, e)))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(SynapseError::ConfigurationError(format!("Webhook URL must be http(s): {}", url)));
+        }
+        if secret.is_empty() {
+            return Err(SynapseError::ConfigurationError("Webhook secret must not be empty".to_string()));
+        }
+
+        let hook = Webhook {
+            id: Uuid::new_v4(),
+            url: url.to_string(),
+            filter,
+            secret: secret.into(),
+            enabled: true,
+            created_at: Utc::now(),
+        };
+        let id = hook.id;
+        self.hooks.insert(id, hook);
+        info!("Registered webhook {} -> {}", id, url);
+        Ok(id)
+    }
+
+    /// Remove a webhook
+    pub fn unregister(&self, id: Uuid) -> Result<Webhook> {
+        self.hooks
+            .remove(&id)
+            .map(|(_, hook)| hook)
+            .ok_or_else(|| SynapseError::NotFound(format!("Webhook {}", id)))
+    }
+
+    /// Pause or resume a webhook
+    pub fn set_enabled(&self, id: Uuid, enabled: bool) -> Result<()> {
+        let mut hook = self.hooks.get_mut(&id).ok_or_else(|| SynapseError::NotFound(format!("Webhook {}", id)))?;
+        hook.enabled = enabled;
+        Ok(())
+    }
+
+    /// Registered webhooks
+    pub fn list(&self) -> Vec<Webhook> {
+        self.hooks.iter().map(|h| h.clone()).collect()
+    }
+
+    /// Status of one delivery
+    pub fn delivery(&self, id: Uuid) -> Option<WebhookDelivery> {
+        self.deliveries.get(&id).map(|d| d.clone())
+    }
+
+    /// Tracked deliveries to one webhook, newest first
+    pub fn deliveries_for(&self, webhook_id: Uuid) -> Vec<WebhookDelivery> {
+        let mut deliveries: Vec<_> = self
+            .deliveries
+            .iter()
+            .filter(|d| d.webhook_id == webhook_id)
+            .map(|d| d.clone())
+            .collect();
+        deliveries.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        deliveries
+    }
+
+    /// Queue deliveries of an incoming message to every matching webhook
+    ///
+    /// Returns the delivery IDs; deliveries (and their retries) run in the background.
+    pub fn dispatch(self: &Arc<Self>, message: &SimpleMessage) -> Vec<Uuid> {
+        let hooks: Vec<Webhook> = self
+            .hooks
+            .iter()
+            .filter(|h| h.enabled && h.filter.matches(message))
+            .map(|h| h.clone())
+            .collect();
+
+        let mut ids = Vec::with_capacity(hooks.len());
+        for hook in hooks {
+            let now = Utc::now();
+            let delivery = WebhookDelivery {
+                id: Uuid::new_v4(),
+                webhook_id: hook.id,
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                created_at: now,
+                updated_at: now,
+            };
+            let payload = WebhookPayload {
+                delivery_id: delivery.id,
+                webhook_id: hook.id,
+                received_at: now,
+                message,
+            };
+            let body = match serde_json::to_vec(&payload) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("Failed to serialize webhook payload: {}", e);
+                    continue;
+                }
+            };
+
+            ids.push(delivery.id);
+            self.deliveries.insert(delivery.id, delivery.clone());
+            let dispatcher = Arc::clone(self);
+            tokio::spawn(async move { dispatcher.deliver(delivery.id, hook, body).await });
+        }
+        self.trim_history();
+        ids
+    }
+
+    /// Attempt a delivery until it succeeds, fails permanently or runs out of attempts
+    async fn deliver(&self, delivery_id: Uuid, hook: Webhook, body: Vec<u8>) {
+        for attempt in 1..=self.retry.max_attempts {
+            let timestamp = Utc::now().timestamp();
+            let headers = [
+                ("X-Synapse-Delivery", delivery_id.to_string()),
+                ("X-Synapse-Timestamp", timestamp.to_string()),
+                ("X-Synapse-Signature", signature_header(hook.secret.expose(), timestamp, &body)),
+            ];
+
+            let (error, retryable) = match self.sender.post(&hook.url, &headers, body.clone()).await {
+                Ok(code) if (200..300).contains(&code) => {
+                    debug!("Webhook delivery {} accepted by {} ({})", delivery_id, hook.url, code);
+                    self.update(delivery_id, attempt, DeliveryStatus::Delivered {
+                        status_code: code,
+                        delivered_at: Utc::now(),
+                    });
+                    return;
+                }
+                Ok(code) => (format!("HTTP {}", code), code == 429 || code >= 500),
+                Err(e) => (e.to_string(), true),
+            };
+
+            if !retryable || attempt == self.retry.max_attempts {
+                warn!("Webhook delivery {} to {} failed after {} attempts: {}", delivery_id, hook.url, attempt, error);
+                self.update(delivery_id, attempt, DeliveryStatus::Failed { last_error: error });
+                return;
+            }
+
+            let backoff = self.retry.backoff(attempt);
+            self.update(delivery_id, attempt, DeliveryStatus::Retrying {
+                next_attempt_at: Utc::now() + chrono::Duration::from_std(backoff).unwrap_or_default(),
+                last_error: error,
+            });
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    fn update(&self, delivery_id: Uuid, attempts: u32, status: DeliveryStatus) {
+        if let Some(mut delivery) = self.deliveries.get_mut(&delivery_id) {
+            delivery.attempts = attempts;
+            delivery.status = status;
+            delivery.updated_at = Utc::now();
+        }
+    }
+
+    /// Forget the oldest finished deliveries beyond the tracking limit
+    fn trim_history(&self) {
+        if self.deliveries.len() <= self.max_tracked {
+            return;
+        }
+        let mut finished: Vec<(Uuid, DateTime<Utc>)> = self
+            .deliveries
+            .iter()
+            .filter(|d| matches!(d.status, DeliveryStatus::Delivered { .. } | DeliveryStatus::Failed { .. }))
+            .map(|d| (d.id, d.updated_at))
+            .collect();
+        finished.sort_by_key(|(_, updated_at)| *updated_at);
+        let excess = self.deliveries.len() - self.max_tracked;
+        for (id, _) in finished.into_iter().take(excess) {
+            self.deliveries.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn filter_matches_domain_type_and_content() {
+        let filter = WebhookFilter {
+            from: Some("*@ops.example.com".to_string()),
+            message_types: vec![MessageType::Direct],
+            content_contains: Some("incident".to_string()),
+            ..Default::default()
+        };
+        let mut message = SimpleMessage {
+            to: "me@example.com".to_string(),
+            from_entity: "pager@ops.example.com".to_string(),
+            content: "New INCIDENT opened".to_string(),
+            message_type: MessageType::Direct,
+            metadata: HashMap::new(),
+        };
+        assert!(filter.matches(&message));
+
+        message.from_entity = "pager@elsewhere.com".to_string();
+        assert!(!filter.matches(&message));
+    }
+
+    /// Fails with the queued status codes, then accepts
+    struct ScriptedSender {
+        responses: Mutex<Vec<u16>>,
+        requests: Mutex<Vec<Vec<(String, String)>>>,
+    }
+
+    #[async_trait]
+    impl WebhookSender for ScriptedSender {
+        async fn post(&self, _url: &str, headers: &[(&str, String)], _body: Vec<u8>) -> Result<u16> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push(headers.iter().map(|(k, v)| (k.to_string(), v.clone())).collect());
+            Ok(self.responses.lock().unwrap().pop().unwrap_or(200))
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_then_records_delivery() {
+        let sender = Arc::new(ScriptedSender { responses: Mutex::new(vec![503]), requests: Mutex::new(Vec::new()) });
+        let retry = WebhookRetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        };
+        let dispatcher = Arc::new(WebhookDispatcher::new(sender.clone(), retry));
+        let hook = dispatcher.register("https://hooks.example.com/synapse", WebhookFilter::default(), "s3cret").unwrap();
+
+        let message = SimpleMessage::new("me@example.com", "you@example.com", "hello");
+        let ids = dispatcher.dispatch(&message);
+        assert_eq!(ids.len(), 1);
+
+        for _ in 0..100 {
+            if matches!(dispatcher.delivery(ids[0]).unwrap().status, DeliveryStatus::Delivered { .. }) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let delivery = dispatcher.delivery(ids[0]).unwrap();
+        assert!(matches!(delivery.status, DeliveryStatus::Delivered { status_code: 200, .. }));
+        assert_eq!(delivery.attempts, 2);
+        assert_eq!(dispatcher.deliveries_for(hook).len(), 1);
+
+        let requests = sender.requests.lock().unwrap();
+        assert!(requests[0].iter().any(|(k, v)| k == "X-Synapse-Signature" && v.starts_with("sha256=")));
+    }
+}
@@ -59,4 +59,23 @@ mod basic_integration_tests {
         let secure_message = router.convert_to_secure_message(&simple_message).await;
         assert!(secure_message.is_ok(), "Message conversion should succeed");
     }
+
+    #[tokio::test]
+    async fn test_attachment_round_trip() {
+        use synapse::types::{Attachment, SecureMessage, SecurityLevel};
+
+        let attachment = Attachment::new("notes.txt", "text/plain", b"meeting at noon".to_vec());
+        assert!(attachment.verify(b"meeting at noon"));
+        assert!(!attachment.needs_offload());
+
+        let mut message = SecureMessage::new("bob", "alice", Vec::new(), Vec::new(), SecurityLevel::Public);
+        message.add_attachment(attachment.clone());
+
+        let json = serde_json::to_string(&message).expect("Serialization should succeed");
+        let decoded: SecureMessage = serde_json::from_str(&json).expect("Deserialization should succeed");
+        assert_eq!(decoded.attachments, vec![attachment]);
+
+        let large = Attachment::new("scan.bin", "application/octet-stream", vec![0u8; 300 * 1024]);
+        assert!(large.needs_offload());
+    }
 }
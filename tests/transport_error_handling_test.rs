@@ -15,3 +15,40 @@ async fn test_transport_timeout_handling_stub() -> anyhow::Result<()> {
     println!("✓ Transport timeout handling stub test completed");
     Ok(())
 }
+
+#[tokio::test]
+async fn test_metrics_snapshot_survives_restart() -> anyhow::Result<()> {
+    use synapse::transport::abstraction::{TransportMetrics, TransportType};
+    use synapse::transport::manager::{MetricsSnapshot, TransportManagerBuilder, UnifiedMetrics};
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("metrics.json").to_string_lossy().into_owned();
+
+    let mut metrics = UnifiedMetrics::default();
+    for (transport_type, sent, failed) in [(TransportType::Tcp, 90, 10), (TransportType::Custom(7), 4, 0)] {
+        metrics.transport_metrics.insert(transport_type, TransportMetrics {
+            transport_type,
+            messages_sent: sent,
+            send_failures: failed,
+            reliability_score: sent as f64 / (sent + failed) as f64,
+            average_latency_ms: 40,
+            ..Default::default()
+        });
+        metrics.total_messages_sent += sent;
+        metrics.total_failures += failed;
+    }
+    MetricsSnapshot::from_metrics(&metrics).save(&path)?;
+
+    let manager = TransportManagerBuilder::new()
+        .metrics_snapshot(path, Duration::from_secs(60))
+        .build();
+    assert!(manager.load_metrics_snapshot()?);
+
+    let restored = manager.get_metrics().await;
+    assert_eq!(restored.total_messages_sent, 94);
+    assert_eq!(restored.transport_metrics[&TransportType::Tcp].send_failures, 10);
+    assert!(restored.transport_metrics.contains_key(&TransportType::Custom(7)));
+    assert!((restored.overall_reliability - 94.0 / 104.0).abs() < 1e-9);
+    Ok(())
+}